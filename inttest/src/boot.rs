@@ -21,13 +21,39 @@
 //! This file exists to run the tests as a second process in order to retrieve the exit code, then
 //! shutdown the machine.
 
-use std::{os::unix::process::ExitStatusExt, process::Command};
+use std::{fs, mem, os::unix::process::ExitStatusExt, process::Command, ptr::null_mut};
+
+/// The file written by a process when it is terminated at shutdown.
+///
+/// The test script checks it has reached the disk, meaning processes are terminated and data is
+/// written back before powering off.
+const SHUTDOWN_MARKER: &str = "/shutdown_marker";
+
+/// Spawns a process waiting to be terminated at shutdown, then writing [`SHUTDOWN_MARKER`].
+fn spawn_shutdown_waiter() {
+	let _ = fs::remove_file(SHUTDOWN_MARKER);
+	// Block the signal before forking, so that it remains pending if sent before the child waits
+	// for it
+	unsafe {
+		let mut set: libc::sigset_t = mem::zeroed();
+		libc::sigemptyset(&mut set);
+		libc::sigaddset(&mut set, libc::SIGTERM);
+		libc::sigprocmask(libc::SIG_BLOCK, &set, null_mut());
+		if libc::fork() == 0 {
+			let mut sig = 0;
+			libc::sigwait(&set, &mut sig);
+			let _ = fs::write(SHUTDOWN_MARKER, "SIGTERM");
+			libc::_exit(0);
+		}
+	}
+}
 
 pub fn main() {
 	let status = Command::new("/inttest").status().unwrap();
 	if let Some(sig) = status.signal() {
 		eprintln!("[KILLED] {sig}");
 	}
+	spawn_shutdown_waiter();
 	let cmd = if status.success() { -1 } else { -2 };
 	// Shutdown
 	unsafe {
@@ -0,0 +1,246 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Checks that failures are reported with the same errno as Linux, since ported software often
+//! relies on telling them apart.
+
+use crate::{
+	log, test_assert, util,
+	util::{expect_errno, TestResult},
+};
+use libc::{
+	EACCES, EAGAIN, EBADF, EINVAL, EISDIR, ELOOP, ENAMETOOLONG, ENODEV, ENOENT, ENOMEM, ENOTBLK,
+	ENOTDIR, ENXIO, EROFS, ESPIPE, MAP_ANONYMOUS, MAP_PRIVATE, MAP_SHARED, MS_RDONLY, PROT_READ,
+	PROT_WRITE, S_IFCHR,
+};
+use std::{
+	ffi::CString,
+	fs,
+	fs::{File, OpenOptions},
+	io::{Read, Seek, SeekFrom, Write},
+	os::{fd::AsRawFd, unix},
+	ptr::{null, null_mut},
+};
+
+pub fn paths() -> TestResult {
+	fs::write("errno_file", b"")?;
+	unix::fs::symlink("errno_file", "errno_link")?;
+
+	log!("Missing file");
+	expect_errno(File::open("errno_missing"), ENOENT)?;
+	expect_errno(File::open("errno_missing/file"), ENOENT)?;
+	log!("Regular file used as a directory");
+	expect_errno(File::open("errno_file/file"), ENOTDIR)?;
+	log!("Symbolic link to a regular file used as a directory");
+	expect_errno(File::open("errno_link/file"), ENOTDIR)?;
+	expect_errno(fs::read_dir("errno_file"), ENOTDIR)?;
+
+	log!("Cleanup");
+	fs::remove_file("errno_link")?;
+	fs::remove_file("errno_file")?;
+	Ok(())
+}
+
+pub fn path_limits() -> TestResult {
+	fs::create_dir("errno_dir")?;
+	fs::write("errno_dir/file", b"")?;
+	unix::fs::symlink(".", "errno_dir/l")?;
+	unix::fs::symlink("errno_loop", "errno_loop")?;
+
+	log!("Name too long");
+	expect_errno(File::open("a".repeat(256)), ENAMETOOLONG)?;
+	log!("Path too long");
+	expect_errno(File::open("./".repeat(2048) + "errno_dir"), ENAMETOOLONG)?;
+	log!("Symbolic link loop");
+	expect_errno(File::open("errno_loop"), ELOOP)?;
+	log!("Symbolic links in intermediate components");
+	// Links are not nested, but the total number of links followed is bounded
+	File::open(format!("errno_dir/{}file", "l/".repeat(40)))?;
+	expect_errno(
+		File::open(format!("errno_dir/{}file", "l/".repeat(41))),
+		ELOOP,
+	)?;
+
+	log!("Cleanup");
+	fs::remove_file("errno_loop")?;
+	fs::remove_dir_all("errno_dir")?;
+	Ok(())
+}
+
+pub fn open() -> TestResult {
+	log!("Write to a directory");
+	fs::create_dir("errno_dir")?;
+	expect_errno(OpenOptions::new().write(true).open("errno_dir"), EISDIR)?;
+	expect_errno(
+		OpenOptions::new().read(true).write(true).open("errno_dir"),
+		EISDIR,
+	)?;
+	fs::remove_dir("errno_dir")?;
+
+	log!("Read from a file open for writing");
+	let mut file = File::create("errno_file")?;
+	expect_errno(file.read(&mut [0u8; 16]), EBADF)?;
+	drop(file);
+	fs::remove_file("errno_file")?;
+
+	log!("Open a device that does not exist");
+	// A major number reserved for local use, which the kernel does not allocate
+	util::mknod("errno_dev", S_IFCHR | 0o600, 240, 255)?;
+	expect_errno(File::open("errno_dev"), ENXIO)?;
+	fs::remove_file("errno_dev")?;
+
+	Ok(())
+}
+
+pub fn pipes() -> TestResult {
+	let (read, write) = util::pipe()?;
+	log!("Seek on a pipe");
+	let mut read = File::from(read);
+	expect_errno(read.seek(SeekFrom::Start(0)), ESPIPE)?;
+	let mut write = File::from(write);
+	expect_errno(write.stream_position(), ESPIPE)?;
+	log!("Positional read on a pipe");
+	let res = unix::fs::FileExt::read_at(&read, &mut [0u8; 16], 0);
+	expect_errno(res, ESPIPE)?;
+	let res = unix::fs::FileExt::write_at(&write, &[0u8; 16], 0);
+	expect_errno(res, ESPIPE)?;
+	Ok(())
+}
+
+pub fn nonblock() -> TestResult {
+	let (read, write) = util::pipe()?;
+	let mut read = File::from(read);
+	let mut write = File::from(write);
+	log!("Non-blocking read on an empty pipe");
+	for fd in [read.as_raw_fd(), write.as_raw_fd()] {
+		test_assert!(unsafe { libc::fcntl(fd, libc::F_SETFL, libc::O_NONBLOCK) } == 0);
+	}
+	expect_errno(read.read(&mut [0u8; 16]), EAGAIN)?;
+	log!("Non-blocking write on a full pipe");
+	let buf = [0u8; 4096];
+	loop {
+		match write.write(&buf) {
+			Ok(len) => test_assert!(len > 0),
+			Err(e) if e.raw_os_error() == Some(EAGAIN) => break,
+			Err(e) => return Err(e.into()),
+		}
+	}
+	// A write of at most `PIPE_BUF` bytes is never partial
+	expect_errno(write.write(&buf[..libc::PIPE_BUF]), EAGAIN)?;
+	log!("Read back the data");
+	test_assert!(read.read(&mut [0u8; 16])? == 16);
+	Ok(())
+}
+
+pub fn memory() -> TestResult {
+	const LEN: usize = 4096;
+	let prot = PROT_READ | PROT_WRITE;
+	fs::write("errno_file", [0u8; LEN])?;
+	let file = File::open("errno_file")?;
+	let fd = file.as_raw_fd();
+
+	log!("Map without a mapping type");
+	expect_errno(
+		util::mmap(null_mut(), LEN, prot, MAP_ANONYMOUS, -1, 0),
+		EINVAL,
+	)?;
+	log!("Map a file without a file descriptor");
+	expect_errno(util::mmap(null_mut(), LEN, prot, MAP_PRIVATE, -1, 0), EBADF)?;
+	log!("Map a directory");
+	let dir = File::open(".")?;
+	expect_errno(
+		util::mmap(null_mut(), LEN, PROT_READ, MAP_PRIVATE, dir.as_raw_fd(), 0),
+		ENODEV,
+	)?;
+	log!("Shared writable mapping of a file open for reading");
+	expect_errno(util::mmap(null_mut(), LEN, prot, MAP_SHARED, fd, 0), EACCES)?;
+	log!("Private writable mapping of a file open for reading");
+	let ptr = util::mmap(null_mut(), LEN, prot, MAP_PRIVATE, fd, 0)?;
+	util::munmap(ptr, LEN)?;
+	log!("Map a file open for writing only");
+	let wr_file = OpenOptions::new().write(true).open("errno_file")?;
+	expect_errno(
+		util::mmap(
+			null_mut(),
+			LEN,
+			PROT_READ,
+			MAP_SHARED,
+			wr_file.as_raw_fd(),
+			0,
+		),
+		EACCES,
+	)?;
+
+	log!("Unaligned unmap");
+	let ptr = util::mmap(null_mut(), LEN, prot, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0)?;
+	expect_errno(util::munmap(ptr.wrapping_byte_add(1), LEN), EINVAL)?;
+	log!("Change the protection of unmapped memory");
+	util::munmap(ptr, LEN)?;
+	expect_errno(util::mprotect(ptr, LEN, PROT_READ), ENOMEM)?;
+
+	log!("Cleanup");
+	fs::remove_file("errno_file")?;
+	Ok(())
+}
+
+pub fn readonly() -> TestResult {
+	log!("Mount read-only tmpfs");
+	fs::create_dir("/errno_ro")?;
+	let src = CString::new("tmpfs")?;
+	let target = CString::new("/errno_ro")?;
+	util::mount(&src, &target, &src, MS_RDONLY, null())?;
+
+	log!("Create a file");
+	expect_errno(File::create("/errno_ro/file"), EROFS)?;
+	expect_errno(fs::create_dir("/errno_ro/dir"), EROFS)?;
+	log!("Write the root directory's attributes");
+	expect_errno(util::chmod("/errno_ro", 0o700), EROFS)?;
+	test_assert!(util::stat("/errno_ro")?.st_mode & 0o777 != 0o700);
+
+	log!("Cleanup");
+	util::umount(&target)?;
+	fs::remove_dir("/errno_ro")?;
+	Ok(())
+}
+
+pub fn mount() -> TestResult {
+	fs::create_dir("/errno_mnt")?;
+	fs::write("/errno_mnt_file", b"")?;
+	let tmpfs = CString::new("tmpfs")?;
+	let ext2 = CString::new("ext2")?;
+	let dir = CString::new("/errno_mnt")?;
+	let file = CString::new("/errno_mnt_file")?;
+	let missing = CString::new("/errno_mnt_missing")?;
+
+	log!("Mount on a file");
+	expect_errno(util::mount(&tmpfs, &file, &tmpfs, 0, null()), ENOTDIR)?;
+	log!("Mount on a missing directory");
+	expect_errno(util::mount(&tmpfs, &missing, &tmpfs, 0, null()), ENOENT)?;
+	log!("Mount from a missing device");
+	expect_errno(util::mount(&missing, &dir, &ext2, 0, null()), ENOENT)?;
+	log!("Mount from a file that is not a block device");
+	expect_errno(util::mount(&file, &dir, &ext2, 0, null()), ENOTBLK)?;
+	log!("The source of a filesystem without device is only a name");
+	util::mount(&file, &dir, &tmpfs, 0, null())?;
+	util::umount(&dir)?;
+
+	log!("Cleanup");
+	fs::remove_file("/errno_mnt_file")?;
+	fs::remove_dir("/errno_mnt")?;
+	Ok(())
+}
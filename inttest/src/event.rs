@@ -0,0 +1,732 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Event notification through file descriptors testing.
+
+use crate::{log, test_assert, test_assert_eq, util, util::TestResult};
+use std::{
+	ffi::CString,
+	fs, io,
+	io::{Read, Seek, SeekFrom, Write},
+	mem,
+	mem::size_of,
+	os::fd::{AsRawFd, FromRawFd, OwnedFd},
+	time::{Duration, Instant},
+};
+
+/// Reads a counter value from `fd`.
+fn read_u64(fd: &OwnedFd) -> io::Result<u64> {
+	let mut val = 0u64;
+	let res = unsafe { libc::read(fd.as_raw_fd(), &mut val as *mut u64 as *mut _, 8) };
+	if res < 0 {
+		return Err(io::Error::last_os_error());
+	}
+	Ok(val)
+}
+
+/// Writes a counter value to `fd`.
+fn write_u64(fd: &OwnedFd, val: u64) -> io::Result<()> {
+	let res = unsafe { libc::write(fd.as_raw_fd(), &val as *const u64 as *const _, 8) };
+	if res < 0 {
+		return Err(io::Error::last_os_error());
+	}
+	Ok(())
+}
+
+pub fn eventfd() -> TestResult {
+	log!("Create counter");
+	let fd = unsafe { libc::eventfd(3, libc::EFD_NONBLOCK) };
+	test_assert!(fd >= 0);
+	let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+	log!("Read and write");
+	write_u64(&fd, 4)?;
+	test_assert_eq!(read_u64(&fd)?, 7);
+	util::expect_errno(read_u64(&fd), libc::EAGAIN)?;
+	log!("Poll");
+	let mut pfd = libc::pollfd {
+		fd: fd.as_raw_fd(),
+		events: libc::POLLIN | libc::POLLOUT,
+		revents: 0,
+	};
+	test_assert_eq!(unsafe { libc::poll(&mut pfd, 1, 0) }, 1);
+	test_assert_eq!(pfd.revents, libc::POLLOUT);
+	write_u64(&fd, 1)?;
+	test_assert_eq!(unsafe { libc::poll(&mut pfd, 1, 0) }, 1);
+	test_assert_eq!(pfd.revents, libc::POLLIN | libc::POLLOUT);
+	log!("Overflow");
+	util::expect_errno(write_u64(&fd, u64::MAX), libc::EINVAL)?;
+	util::expect_errno(write_u64(&fd, u64::MAX - 1), libc::EAGAIN)?;
+	drop(fd);
+
+	log!("Semaphore");
+	let fd = unsafe { libc::eventfd(2, libc::EFD_NONBLOCK | libc::EFD_SEMAPHORE) };
+	test_assert!(fd >= 0);
+	let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+	test_assert_eq!(read_u64(&fd)?, 1);
+	test_assert_eq!(read_u64(&fd)?, 1);
+	util::expect_errno(read_u64(&fd), libc::EAGAIN)?;
+	Ok(())
+}
+
+pub fn signalfd() -> TestResult {
+	log!("Block signal");
+	let mut set: libc::sigset_t = unsafe { std::mem::zeroed() };
+	let mut old: libc::sigset_t = unsafe { std::mem::zeroed() };
+	unsafe {
+		libc::sigemptyset(&mut set);
+		libc::sigaddset(&mut set, libc::SIGUSR1);
+		test_assert_eq!(libc::sigprocmask(libc::SIG_BLOCK, &set, &mut old), 0);
+	}
+	log!("Create signalfd");
+	let fd = unsafe { libc::signalfd(-1, &set, libc::SFD_NONBLOCK | libc::SFD_CLOEXEC) };
+	test_assert!(fd >= 0);
+	let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+	let mut info: libc::signalfd_siginfo = unsafe { std::mem::zeroed() };
+	let size = std::mem::size_of::<libc::signalfd_siginfo>();
+	let read = |info: &mut libc::signalfd_siginfo| {
+		let res = unsafe { libc::read(fd.as_raw_fd(), info as *mut _ as *mut _, size) };
+		if res < 0 {
+			return Err(io::Error::last_os_error());
+		}
+		Ok(res as usize)
+	};
+	util::expect_errno(read(&mut info), libc::EAGAIN)?;
+	log!("Send signal");
+	unsafe {
+		libc::kill(libc::getpid(), libc::SIGUSR1);
+	}
+	let mut pfd = libc::pollfd {
+		fd: fd.as_raw_fd(),
+		events: libc::POLLIN,
+		revents: 0,
+	};
+	test_assert_eq!(unsafe { libc::poll(&mut pfd, 1, 0) }, 1);
+	test_assert_eq!(pfd.revents, libc::POLLIN);
+	log!("Read signal");
+	test_assert_eq!(read(&mut info)?, size);
+	test_assert_eq!(info.ssi_signo, libc::SIGUSR1 as u32);
+	util::expect_errno(read(&mut info), libc::EAGAIN)?;
+	unsafe {
+		libc::sigprocmask(libc::SIG_SETMASK, &old, std::ptr::null_mut());
+	}
+	Ok(())
+}
+
+/// Calls `select` on the file descriptor `fd` for reading and writing, returning the number of
+/// ready file descriptors along with whether `fd` is readable and writable.
+fn select_rw(fd: &OwnedFd, timeout: &mut libc::timeval) -> io::Result<(i32, bool, bool)> {
+	unsafe {
+		let mut readfds: libc::fd_set = std::mem::zeroed();
+		let mut writefds: libc::fd_set = std::mem::zeroed();
+		libc::FD_SET(fd.as_raw_fd(), &mut readfds);
+		libc::FD_SET(fd.as_raw_fd(), &mut writefds);
+		let res = libc::select(
+			fd.as_raw_fd() + 1,
+			&mut readfds,
+			&mut writefds,
+			std::ptr::null_mut(),
+			timeout,
+		);
+		if res < 0 {
+			return Err(io::Error::last_os_error());
+		}
+		Ok((
+			res,
+			libc::FD_ISSET(fd.as_raw_fd(), &readfds),
+			libc::FD_ISSET(fd.as_raw_fd(), &writefds),
+		))
+	}
+}
+
+pub fn select() -> TestResult {
+	let (rx, tx) = util::pipe()?;
+	log!("Select on an empty pipe");
+	let mut timeout = libc::timeval {
+		tv_sec: 0,
+		tv_usec: 10_000,
+	};
+	test_assert_eq!(select_rw(&rx, &mut timeout)?, (0, false, false));
+	test_assert_eq!((timeout.tv_sec, timeout.tv_usec), (0, 0));
+	test_assert_eq!(select_rw(&tx, &mut timeout)?, (1, false, true));
+	log!("Select on a non-empty pipe");
+	test_assert_eq!(
+		unsafe { libc::write(tx.as_raw_fd(), b"a".as_ptr() as *const _, 1) },
+		1
+	);
+	test_assert_eq!(select_rw(&rx, &mut timeout)?, (1, true, false));
+	log!("Select on the end of a pipe");
+	drop(tx);
+	let mut buf = 0u8;
+	test_assert_eq!(
+		unsafe { libc::read(rx.as_raw_fd(), &mut buf as *mut u8 as *mut _, 1) },
+		1
+	);
+	test_assert_eq!(select_rw(&rx, &mut timeout)?, (1, true, false));
+	log!("Select on a closed file descriptor");
+	let fd = rx.as_raw_fd();
+	drop(rx);
+	let res = unsafe {
+		let mut readfds: libc::fd_set = std::mem::zeroed();
+		libc::FD_SET(fd, &mut readfds);
+		libc::select(
+			fd + 1,
+			&mut readfds,
+			std::ptr::null_mut(),
+			std::ptr::null_mut(),
+			&mut timeout,
+		)
+	};
+	test_assert_eq!(res, -1);
+	test_assert_eq!(io::Error::last_os_error().raw_os_error(), Some(libc::EBADF));
+	log!("Wait for a signal with pselect");
+	let mut set: libc::sigset_t = unsafe { std::mem::zeroed() };
+	let mut old: libc::sigset_t = unsafe { std::mem::zeroed() };
+	unsafe {
+		libc::sigemptyset(&mut set);
+		libc::sigaddset(&mut set, libc::SIGUSR1);
+		test_assert_eq!(libc::sigprocmask(libc::SIG_BLOCK, &set, &mut old), 0);
+		test_assert_eq!(
+			libc::signal(libc::SIGUSR1, handle_usr1 as usize),
+			libc::SIG_DFL
+		);
+		libc::kill(libc::getpid(), libc::SIGUSR1);
+	}
+	// The signal is delivered only once unblocked by pselect
+	let unblocked: libc::sigset_t = unsafe { std::mem::zeroed() };
+	let res = unsafe {
+		libc::pselect(
+			0,
+			std::ptr::null_mut(),
+			std::ptr::null_mut(),
+			std::ptr::null_mut(),
+			std::ptr::null(),
+			&unblocked,
+		)
+	};
+	let errno = io::Error::last_os_error().raw_os_error();
+	unsafe {
+		libc::signal(libc::SIGUSR1, libc::SIG_DFL);
+		libc::sigprocmask(libc::SIG_SETMASK, &old, std::ptr::null_mut());
+	}
+	test_assert_eq!(res, -1);
+	test_assert_eq!(errno, Some(libc::EINTR));
+	Ok(())
+}
+
+pub fn poll() -> TestResult {
+	log!("Poll without file descriptors");
+	let start = Instant::now();
+	test_assert_eq!(unsafe { libc::poll(std::ptr::null_mut(), 0, 20) }, 0);
+	test_assert!(start.elapsed() >= Duration::from_millis(20));
+	log!("Poll ignored and invalid file descriptors");
+	let (rx, tx) = util::pipe()?;
+	let closed = {
+		let (rx, _) = util::pipe()?;
+		rx.as_raw_fd()
+	};
+	let mut pfds = [
+		libc::pollfd {
+			fd: -1,
+			events: libc::POLLIN,
+			revents: 0,
+		},
+		libc::pollfd {
+			fd: closed,
+			events: 0,
+			revents: 0,
+		},
+		libc::pollfd {
+			fd: rx.as_raw_fd(),
+			events: libc::POLLIN,
+			revents: 0,
+		},
+		libc::pollfd {
+			fd: tx.as_raw_fd(),
+			events: libc::POLLOUT,
+			revents: 0,
+		},
+	];
+	test_assert_eq!(unsafe { libc::poll(pfds.as_mut_ptr(), 4, 0) }, 2);
+	let revents: Vec<_> = pfds.iter().map(|p| p.revents).collect();
+	test_assert_eq!(revents, [0, libc::POLLNVAL, 0, libc::POLLOUT]);
+	log!("Hang up");
+	test_assert_eq!(
+		unsafe { libc::write(tx.as_raw_fd(), b"a".as_ptr() as *const _, 1) },
+		1
+	);
+	drop(tx);
+	// `POLLHUP` is reported even if not requested
+	test_assert_eq!(ppoll_fd(&rx, 0, None, None), (1, libc::POLLHUP));
+	test_assert_eq!(
+		ppoll_fd(&rx, libc::POLLIN, None, None),
+		(1, libc::POLLIN | libc::POLLHUP)
+	);
+	log!("Error on the write end");
+	let (rx, tx) = util::pipe()?;
+	drop(rx);
+	test_assert_eq!(
+		ppoll_fd(&tx, libc::POLLOUT, None, None),
+		(1, libc::POLLOUT | libc::POLLERR)
+	);
+	Ok(())
+}
+
+/// Calls `ppoll` on `fd`, looking for `events`, and returns the result along with the returned
+/// events.
+fn ppoll_fd(
+	fd: &OwnedFd,
+	events: libc::c_short,
+	timeout: Option<libc::timespec>,
+	sigmask: Option<&libc::sigset_t>,
+) -> (libc::c_int, libc::c_short) {
+	let mut pfd = libc::pollfd {
+		fd: fd.as_raw_fd(),
+		events,
+		revents: 0,
+	};
+	let res = unsafe {
+		libc::ppoll(
+			&mut pfd,
+			1,
+			timeout.as_ref().map_or(std::ptr::null(), |t| t as *const _),
+			sigmask.map_or(std::ptr::null(), |s| s as *const _),
+		)
+	};
+	(res, pfd.revents)
+}
+
+pub fn ppoll() -> TestResult {
+	let (rx, tx) = util::pipe()?;
+	log!("Poll an empty pipe with a timeout");
+	let timeout = libc::timespec {
+		tv_sec: 0,
+		tv_nsec: 20_000_000,
+	};
+	let start = Instant::now();
+	test_assert_eq!(ppoll_fd(&rx, libc::POLLIN, Some(timeout), None), (0, 0));
+	test_assert!(start.elapsed() >= Duration::from_millis(20));
+	log!("Invalid timeout");
+	let invalid = libc::timespec {
+		tv_sec: 0,
+		tv_nsec: 1_000_000_000,
+	};
+	test_assert_eq!(ppoll_fd(&rx, libc::POLLIN, Some(invalid), None).0, -1);
+	test_assert_eq!(
+		io::Error::last_os_error().raw_os_error(),
+		Some(libc::EINVAL)
+	);
+	log!("Wait for data written by another process");
+	let pid = unsafe { libc::fork() };
+	test_assert!(pid >= 0);
+	if pid == 0 {
+		unsafe {
+			libc::usleep(50_000);
+			libc::write(tx.as_raw_fd(), b"a".as_ptr() as *const _, 1);
+			libc::_exit(0);
+		}
+	}
+	let res = ppoll_fd(&rx, libc::POLLIN, None, None);
+	unsafe {
+		libc::waitpid(pid, std::ptr::null_mut(), 0);
+	}
+	test_assert_eq!(res, (1, libc::POLLIN));
+	log!("Wait for a signal with ppoll");
+	let mut set: libc::sigset_t = unsafe { std::mem::zeroed() };
+	let mut old: libc::sigset_t = unsafe { std::mem::zeroed() };
+	unsafe {
+		libc::sigemptyset(&mut set);
+		libc::sigaddset(&mut set, libc::SIGUSR1);
+		test_assert_eq!(libc::sigprocmask(libc::SIG_BLOCK, &set, &mut old), 0);
+		test_assert_eq!(
+			libc::signal(libc::SIGUSR1, handle_usr1 as usize),
+			libc::SIG_DFL
+		);
+	}
+	let pid = unsafe { libc::fork() };
+	test_assert!(pid >= 0);
+	if pid == 0 {
+		unsafe {
+			libc::usleep(50_000);
+			libc::kill(libc::getppid(), libc::SIGUSR1);
+			libc::_exit(0);
+		}
+	}
+	// The signal is delivered only while ppoll unblocks it
+	let unblocked: libc::sigset_t = unsafe { std::mem::zeroed() };
+	let (res, _) = ppoll_fd(&tx, 0, None, Some(&unblocked));
+	let errno = io::Error::last_os_error().raw_os_error();
+	unsafe {
+		libc::waitpid(pid, std::ptr::null_mut(), 0);
+		libc::signal(libc::SIGUSR1, libc::SIG_DFL);
+		libc::sigprocmask(libc::SIG_SETMASK, &old, std::ptr::null_mut());
+	}
+	test_assert_eq!(res, -1);
+	test_assert_eq!(errno, Some(libc::EINTR));
+	Ok(())
+}
+
+/// Creates a fanotify group of class `class`, then marks the file at `path` with the events
+/// `mask`.
+fn fanotify_group(class: libc::c_uint, mask: u64, path: &str) -> io::Result<OwnedFd> {
+	let fd = unsafe { libc::fanotify_init(class, libc::O_RDONLY as _) };
+	if fd < 0 {
+		return Err(io::Error::last_os_error());
+	}
+	let group = unsafe { OwnedFd::from_raw_fd(fd) };
+	fanotify_mark(&group, libc::FAN_MARK_ADD, mask, path)?;
+	Ok(group)
+}
+
+/// Adds or removes, according to `flags`, the events `mask` on the mark of `group` on the file at
+/// `path`.
+fn fanotify_mark(group: &OwnedFd, flags: libc::c_uint, mask: u64, path: &str) -> io::Result<()> {
+	let path = CString::new(path)?;
+	let res = unsafe {
+		libc::fanotify_mark(
+			group.as_raw_fd(),
+			flags,
+			mask,
+			libc::AT_FDCWD,
+			path.as_ptr(),
+		)
+	};
+	if res < 0 {
+		return Err(io::Error::last_os_error());
+	}
+	Ok(())
+}
+
+/// Reads an event from the fanotify `group`, blocking until one is available.
+///
+/// The file descriptor of the event is owned by the caller.
+fn fanotify_read(group: &OwnedFd) -> io::Result<libc::fanotify_event_metadata> {
+	let mut event: libc::fanotify_event_metadata = unsafe { mem::zeroed() };
+	let len = size_of::<libc::fanotify_event_metadata>();
+	let res = unsafe { libc::read(group.as_raw_fd(), &mut event as *mut _ as *mut _, len) };
+	if res < 0 {
+		return Err(io::Error::last_os_error());
+	}
+	if res as usize != len {
+		return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+	}
+	Ok(event)
+}
+
+pub fn fanotify() -> TestResult {
+	fs::write("fanotify", "content")?;
+	let res = (|| {
+		log!("Create group");
+		let group = fanotify_group(libc::FAN_CLASS_NOTIF, libc::FAN_OPEN, "fanotify")?;
+		log!("Poll without events");
+		let timeout = libc::timespec {
+			tv_sec: 0,
+			tv_nsec: 0,
+		};
+		test_assert_eq!(ppoll_fd(&group, libc::POLLIN, Some(timeout), None), (0, 0));
+		log!("Poll with an event");
+		drop(fs::File::open("fanotify")?);
+		test_assert_eq!(
+			ppoll_fd(&group, libc::POLLIN, Some(timeout), None),
+			(1, libc::POLLIN)
+		);
+		log!("Read event");
+		let event = fanotify_read(&group)?;
+		test_assert!(event.mask & libc::FAN_OPEN != 0);
+		unsafe {
+			libc::close(event.fd);
+		}
+		test_assert_eq!(ppoll_fd(&group, libc::POLLIN, Some(timeout), None), (0, 0));
+		Ok(())
+	})();
+	fs::remove_file("fanotify")?;
+	res
+}
+
+pub fn fanotify_events() -> TestResult {
+	fs::write("fanotify", "content")?;
+	let res = (|| {
+		let mask = libc::FAN_ACCESS | libc::FAN_MODIFY;
+		let group = fanotify_group(libc::FAN_CLASS_NOTIF, mask, "fanotify")?;
+		let mut file = fs::OpenOptions::new()
+			.read(true)
+			.write(true)
+			.open("fanotify")?;
+		log!("Modify");
+		file.write_all(b"abc")?;
+		let event = fanotify_read(&group)?;
+		let event_fd = unsafe { OwnedFd::from_raw_fd(event.fd) };
+		test_assert_eq!(event.mask, libc::FAN_MODIFY);
+		test_assert_eq!(event.pid, unsafe { libc::getpid() });
+		// The event refers to the modified file
+		let file_stat = util::fstat(file.as_raw_fd())?;
+		let event_stat = util::fstat(event_fd.as_raw_fd())?;
+		test_assert_eq!(event_stat.st_ino, file_stat.st_ino);
+		test_assert_eq!(event_stat.st_dev, file_stat.st_dev);
+		log!("Access");
+		file.seek(SeekFrom::Start(0))?;
+		file.read_exact(&mut [0u8; 3])?;
+		let event = fanotify_read(&group)?;
+		drop(unsafe { OwnedFd::from_raw_fd(event.fd) });
+		test_assert_eq!(event.mask, libc::FAN_ACCESS);
+		log!("Remove mark");
+		fanotify_mark(&group, libc::FAN_MARK_REMOVE, mask, "fanotify")?;
+		file.write_all(b"def")?;
+		let timeout = libc::timespec {
+			tv_sec: 0,
+			tv_nsec: 0,
+		};
+		test_assert_eq!(ppoll_fd(&group, libc::POLLIN, Some(timeout), None), (0, 0));
+		Ok(())
+	})();
+	fs::remove_file("fanotify")?;
+	res
+}
+
+pub fn fanotify_perm() -> TestResult {
+	fs::write("fanotify", "content")?;
+	let res = (|| {
+		let group = fanotify_group(libc::FAN_CLASS_CONTENT, libc::FAN_OPEN_PERM, "fanotify")?;
+		for (response, expected) in [(libc::FAN_DENY, Some(libc::EPERM)), (libc::FAN_ALLOW, None)]
+		{
+			log!("Open with response {response}");
+			// The file is opened by a child, since opening blocks until the response is written
+			let pid = unsafe { libc::fork() };
+			test_assert!(pid >= 0);
+			if pid == 0 {
+				let errno = fs::File::open("fanotify")
+					.err()
+					.and_then(|e| e.raw_os_error());
+				unsafe {
+					libc::_exit(errno.unwrap_or(0));
+				}
+			}
+			let event = fanotify_read(&group)?;
+			test_assert_eq!(event.mask, libc::FAN_OPEN_PERM);
+			test_assert_eq!(event.pid, pid);
+			let resp = libc::fanotify_response {
+				fd: event.fd,
+				response,
+			};
+			let len = size_of::<libc::fanotify_response>();
+			let res =
+				unsafe { libc::write(group.as_raw_fd(), &resp as *const _ as *const _, len) };
+			unsafe {
+				libc::close(event.fd);
+			}
+			test_assert_eq!(res, len as isize);
+			let mut status = 0;
+			unsafe {
+				libc::waitpid(pid, &mut status, 0);
+			}
+			test_assert!(libc::WIFEXITED(status));
+			test_assert_eq!(libc::WEXITSTATUS(status), expected.unwrap_or(0));
+		}
+		log!("Respond to an unknown event");
+		let resp = libc::fanotify_response {
+			fd: 1000,
+			response: libc::FAN_ALLOW,
+		};
+		let len = size_of::<libc::fanotify_response>();
+		let res = unsafe { libc::write(group.as_raw_fd(), &resp as *const _ as *const _, len) };
+		test_assert_eq!(res, -1);
+		test_assert_eq!(
+			io::Error::last_os_error().raw_os_error(),
+			Some(libc::ENOENT)
+		);
+		Ok(())
+	})();
+	fs::remove_file("fanotify")?;
+	res
+}
+
+/// An event read from an inotify instance.
+#[derive(Debug)]
+struct InotifyEvent {
+	wd: libc::c_int,
+	mask: u32,
+	cookie: u32,
+	name: String,
+}
+
+/// Adds a watch for the events `mask` on the file at `path` to the inotify instance `fd`.
+fn inotify_add_watch(fd: &OwnedFd, path: &str, mask: u32) -> io::Result<libc::c_int> {
+	let path = CString::new(path)?;
+	let wd = unsafe { libc::inotify_add_watch(fd.as_raw_fd(), path.as_ptr(), mask) };
+	if wd < 0 {
+		return Err(io::Error::last_os_error());
+	}
+	Ok(wd)
+}
+
+/// Reads the pending events of the non-blocking inotify instance `fd`.
+///
+/// If no event is pending, the function returns an empty list.
+fn inotify_read(fd: &OwnedFd) -> io::Result<Vec<InotifyEvent>> {
+	let mut buf = [0u8; 4096];
+	let res = unsafe { libc::read(fd.as_raw_fd(), buf.as_mut_ptr() as *mut _, buf.len()) };
+	if res < 0 {
+		let err = io::Error::last_os_error();
+		if err.raw_os_error() == Some(libc::EAGAIN) {
+			return Ok(vec![]);
+		}
+		return Err(err);
+	}
+	let mut events = vec![];
+	let mut off = 0;
+	while off < res as usize {
+		let hdr: libc::inotify_event =
+			unsafe { (buf.as_ptr().add(off) as *const libc::inotify_event).read_unaligned() };
+		off += size_of::<libc::inotify_event>();
+		let name = &buf[off..(off + hdr.len as usize)];
+		let name_len = name.iter().position(|b| *b == 0).unwrap_or(name.len());
+		events.push(InotifyEvent {
+			wd: hdr.wd,
+			mask: hdr.mask,
+			cookie: hdr.cookie,
+			name: String::from_utf8_lossy(&name[..name_len]).into_owned(),
+		});
+		off += hdr.len as usize;
+	}
+	Ok(events)
+}
+
+/// Creates a non-blocking inotify instance.
+fn inotify_init() -> io::Result<OwnedFd> {
+	let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+	if fd < 0 {
+		return Err(io::Error::last_os_error());
+	}
+	Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+/// Checks that `events` contains exactly one event, with the watch descriptor `wd`, the mask
+/// `mask` and the name `name`.
+fn check_inotify_event(
+	events: &[InotifyEvent],
+	wd: libc::c_int,
+	mask: u32,
+	name: &str,
+) -> TestResult {
+	test_assert_eq!(events.len(), 1);
+	test_assert_eq!(events[0].wd, wd);
+	test_assert_eq!(events[0].mask, mask);
+	test_assert_eq!(events[0].name.as_str(), name);
+	Ok(())
+}
+
+pub fn inotify() -> TestResult {
+	fs::create_dir("inotify")?;
+	let res = (|| {
+		let fd = inotify_init()?;
+		let mask = libc::IN_CREATE
+			| libc::IN_MODIFY
+			| libc::IN_DELETE
+			| libc::IN_MOVED_FROM
+			| libc::IN_MOVED_TO;
+		let wd = inotify_add_watch(&fd, "inotify", mask)?;
+		test_assert!(inotify_read(&fd)?.is_empty());
+		log!("Create");
+		let mut file = fs::OpenOptions::new()
+			.write(true)
+			.create_new(true)
+			.open("inotify/a")?;
+		check_inotify_event(&inotify_read(&fd)?, wd, libc::IN_CREATE, "a")?;
+		log!("Modify");
+		file.write_all(b"abc")?;
+		check_inotify_event(&inotify_read(&fd)?, wd, libc::IN_MODIFY, "a")?;
+		drop(file);
+		log!("Move");
+		fs::rename("inotify/a", "inotify/b")?;
+		let events = inotify_read(&fd)?;
+		test_assert_eq!(events.len(), 2);
+		check_inotify_event(&events[..1], wd, libc::IN_MOVED_FROM, "a")?;
+		check_inotify_event(&events[1..], wd, libc::IN_MOVED_TO, "b")?;
+		// Both events of the move are associated
+		test_assert!(events[0].cookie != 0);
+		test_assert_eq!(events[0].cookie, events[1].cookie);
+		log!("Directory");
+		fs::create_dir("inotify/dir")?;
+		check_inotify_event(
+			&inotify_read(&fd)?,
+			wd,
+			libc::IN_CREATE | libc::IN_ISDIR,
+			"dir",
+		)?;
+		fs::remove_dir("inotify/dir")?;
+		check_inotify_event(
+			&inotify_read(&fd)?,
+			wd,
+			libc::IN_DELETE | libc::IN_ISDIR,
+			"dir",
+		)?;
+		log!("Delete");
+		let file_wd = inotify_add_watch(&fd, "inotify/b", libc::IN_DELETE_SELF)?;
+		fs::remove_file("inotify/b")?;
+		let events = inotify_read(&fd)?;
+		test_assert_eq!(events.len(), 3);
+		check_inotify_event(&events[..1], wd, libc::IN_DELETE, "b")?;
+		check_inotify_event(&events[1..2], file_wd, libc::IN_DELETE_SELF, "")?;
+		// The watch on the removed file is removed along with it
+		check_inotify_event(&events[2..], file_wd, libc::IN_IGNORED, "")?;
+		Ok(())
+	})();
+	fs::remove_dir_all("inotify")?;
+	res
+}
+
+pub fn inotify_rm_watch() -> TestResult {
+	fs::create_dir("inotify")?;
+	let res = (|| {
+		let fd = inotify_init()?;
+		let wd = inotify_add_watch(&fd, "inotify", libc::IN_CREATE)?;
+		log!("Only directory");
+		fs::write("inotify/file", "")?;
+		check_inotify_event(&inotify_read(&fd)?, wd, libc::IN_CREATE, "file")?;
+		util::expect_errno(
+			inotify_add_watch(&fd, "inotify/file", libc::IN_ONLYDIR | libc::IN_ATTRIB),
+			libc::ENOTDIR,
+		)?;
+		log!("Remove watch");
+		test_assert_eq!(unsafe { libc::inotify_rm_watch(fd.as_raw_fd(), wd) }, 0);
+		check_inotify_event(&inotify_read(&fd)?, wd, libc::IN_IGNORED, "")?;
+		// No event is reported after removal
+		fs::write("inotify/file2", "")?;
+		test_assert!(inotify_read(&fd)?.is_empty());
+		log!("Remove twice");
+		test_assert_eq!(unsafe { libc::inotify_rm_watch(fd.as_raw_fd(), wd) }, -1);
+		test_assert_eq!(
+			io::Error::last_os_error().raw_os_error(),
+			Some(libc::EINVAL)
+		);
+		log!("Oneshot");
+		let wd = inotify_add_watch(&fd, "inotify", libc::IN_CREATE | libc::IN_ONESHOT)?;
+		fs::write("inotify/file3", "")?;
+		let events = inotify_read(&fd)?;
+		test_assert_eq!(events.len(), 2);
+		check_inotify_event(&events[..1], wd, libc::IN_CREATE, "file3")?;
+		check_inotify_event(&events[1..], wd, libc::IN_IGNORED, "")?;
+		fs::write("inotify/file4", "")?;
+		test_assert!(inotify_read(&fd)?.is_empty());
+		Ok(())
+	})();
+	fs::remove_dir_all("inotify")?;
+	res
+}
+
+/// Handler for `SIGUSR1`, which does nothing.
+extern "C" fn handle_usr1(_: libc::c_int) {}
@@ -22,13 +22,22 @@ use crate::{
 	log, test_assert, test_assert_eq, util,
 	util::{unprivileged, TestError, TestResult},
 };
+use libc::c_int;
 use std::{
+	ffi::CString,
 	fs,
 	fs::OpenOptions,
 	io,
 	io::{Read, Seek, SeekFrom, Write},
-	os::{fd::AsRawFd, unix, unix::fs::MetadataExt},
+	mem,
+	os::{
+		fd::{AsRawFd, FromRawFd},
+		unix,
+		unix::fs::{FileExt, MetadataExt, OpenOptionsExt},
+	},
 	path::Path,
+	thread,
+	time::{Duration, Instant},
 };
 
 pub fn basic() -> TestResult {
@@ -95,8 +104,240 @@ pub fn basic() -> TestResult {
 	Ok(())
 }
 
+pub fn past_eof() -> TestResult {
+	let path = Path::new("sparse");
+	let mut file = OpenOptions::new()
+		.create_new(true)
+		.read(true)
+		.write(true)
+		.open(path)?;
+	file.write_all(b"abc")?;
+
+	log!("Read past end of file");
+	let mut buf = [0xffu8; 16];
+	test_assert_eq!(file.read_at(&mut buf, 3)?, 0);
+	test_assert_eq!(file.read_at(&mut buf, 100_000)?, 0);
+	test_assert_eq!(buf, [0xff; 16]);
+	// Positional reads do not move the file's offset
+	test_assert_eq!(file.stream_position()?, 3);
+
+	log!("Write past end of file");
+	// Leave a gap spanning several pages
+	let off = 3 * 4096 + 10;
+	file.write_all_at(b"de", off)?;
+	test_assert_eq!(file.metadata()?.len(), off + 2);
+	test_assert_eq!(file.stream_position()?, 3);
+	let mut content = vec![];
+	file.seek(SeekFrom::Start(0))?;
+	file.read_to_end(&mut content)?;
+	test_assert_eq!(content.len() as u64, off + 2);
+	test_assert_eq!(&content[..3], b"abc");
+	test_assert!(content[3..(off as usize)].iter().all(|b| *b == 0));
+	test_assert_eq!(&content[(off as usize)..], b"de");
+
+	log!("Extend with truncate");
+	file.set_len(2)?;
+	file.set_len(8)?;
+	let mut buf = [0xffu8; 16];
+	test_assert_eq!(file.read_at(&mut buf, 0)?, 8);
+	test_assert_eq!(&buf[..8], b"ab\0\0\0\0\0\0");
+
+	log!("Cleanup");
+	fs::remove_file(path)?;
+	Ok(())
+}
+
+pub fn truncate() -> TestResult {
+	let path = Path::new("truncate");
+	fs::write(path, vec![b'a'; 256 * 1024])?;
+	let res = (|| {
+		let file = OpenOptions::new().read(true).write(true).open(path)?;
+		file.sync_all()?;
+		let blocks = util::fstat(file.as_raw_fd())?.st_blocks;
+		test_assert!(blocks >= 512);
+		let free = util::fstatvfs(file.as_raw_fd())?.f_bfree;
+		log!("Shrink");
+		file.set_len(4096)?;
+		let stat = util::fstat(file.as_raw_fd())?;
+		test_assert_eq!(stat.st_size, 4096);
+		test_assert!(stat.st_blocks > 0 && stat.st_blocks < blocks);
+		// Blocks past the end of the file are freed
+		test_assert!(util::fstatvfs(file.as_raw_fd())?.f_bfree > free);
+		log!("Truncate by path");
+		let c_path = CString::new("truncate")?;
+		test_assert_eq!(unsafe { libc::truncate(c_path.as_ptr(), 0) }, 0);
+		let stat = util::fstat(file.as_raw_fd())?;
+		test_assert_eq!((stat.st_size, stat.st_blocks), (0, 0));
+		log!("Invalid length");
+		test_assert_eq!(unsafe { libc::truncate(c_path.as_ptr(), -1) }, -1);
+		test_assert_eq!(
+			io::Error::last_os_error().raw_os_error(),
+			Some(libc::EINVAL)
+		);
+		log!("File not open for writing");
+		let read_only = fs::File::open(path)?;
+		test_assert_eq!(unsafe { libc::ftruncate(read_only.as_raw_fd(), 0) }, -1);
+		test_assert_eq!(
+			io::Error::last_os_error().raw_os_error(),
+			Some(libc::EINVAL)
+		);
+		Ok(())
+	})();
+	fs::remove_file(path)?;
+	res
+}
+
+pub fn large_files() -> TestResult {
+	fs::write("large_file", "abcdef")?;
+	unix::fs::symlink("large_file", "large_file_link")?;
+	let res = (|| {
+		let mut file = OpenOptions::new()
+			.read(true)
+			.write(true)
+			.open("large_file")?;
+		log!("Get the status of a file in several ways");
+		let fstat = util::fstat(file.as_raw_fd())?;
+		let stat = fs::metadata("large_file")?;
+		test_assert_eq!(stat.ino(), fstat.st_ino as u64);
+		test_assert_eq!(stat.dev(), fstat.st_dev as u64);
+		test_assert_eq!(stat.size(), 6);
+		let lstat = fs::symlink_metadata("large_file_link")?;
+		test_assert!(lstat.file_type().is_symlink());
+		test_assert!(lstat.ino() != stat.ino());
+		test_assert_eq!(fs::metadata("large_file_link")?.ino(), stat.ino());
+		log!("Sizes beyond 2 GiB");
+		let len: u64 = 3 << 30;
+		file.set_len(len)?;
+		test_assert_eq!(util::fstat(file.as_raw_fd())?.st_size as u64, len);
+		let c_path = CString::new("large_file")?;
+		test_assert_eq!(
+			unsafe { libc::truncate(c_path.as_ptr(), (len + 1) as _) },
+			0
+		);
+		test_assert_eq!(fs::metadata("large_file")?.len(), len + 1);
+		let mut buf = [1u8; 4];
+		test_assert_eq!(file.read_at(&mut buf, len - 2)?, 3);
+		test_assert_eq!(buf, [0, 0, 0, 1]);
+		file.set_len(6)?;
+		log!("Send file content at an offset");
+		let (rx, tx) = util::pipe()?;
+		let mut rx = fs::File::from(rx);
+		let mut off: libc::off_t = 2;
+		let res = unsafe { libc::sendfile(tx.as_raw_fd(), file.as_raw_fd(), &mut off, 3) };
+		test_assert_eq!(res, 3);
+		test_assert_eq!(off, 5);
+		test_assert_eq!(file.stream_position()?, 0);
+		let mut buf = [0u8; 3];
+		rx.read_exact(&mut buf)?;
+		test_assert_eq!(&buf, b"cde");
+		log!("Send file content at the current offset");
+		let res =
+			unsafe { libc::sendfile(tx.as_raw_fd(), file.as_raw_fd(), std::ptr::null_mut(), 16) };
+		test_assert_eq!(res, 6);
+		test_assert_eq!(file.stream_position()?, 6);
+		let mut buf = [0u8; 6];
+		rx.read_exact(&mut buf)?;
+		test_assert_eq!(&buf, b"abcdef");
+		Ok(())
+	})();
+	log!("Cleanup");
+	fs::remove_file("large_file_link")?;
+	fs::remove_file("large_file")?;
+	res
+}
+
+pub fn unlinked_open() -> TestResult {
+	let path = Path::new("unlinked");
+	let content = vec![b'a'; 256 * 1024];
+	let mut file = OpenOptions::new()
+		.create_new(true)
+		.read(true)
+		.write(true)
+		.open(path)?;
+	file.write_all(&content)?;
+	file.sync_all()?;
+
+	log!("Remove open file");
+	fs::remove_file(path)?;
+	test_assert!(!path.exists());
+	test_assert_eq!(util::fstat(file.as_raw_fd())?.st_nlink, 0);
+	let free_open = util::fstatvfs(file.as_raw_fd())?.f_bfree;
+
+	log!("Use removed file");
+	let mut buf = vec![];
+	file.seek(SeekFrom::Start(0))?;
+	file.read_to_end(&mut buf)?;
+	test_assert!(buf == content);
+	file.write_all(b"more")?;
+
+	log!("Close removed file");
+	drop(file);
+	let free_closed = util::fstatvfs(fs::File::open(".")?.as_raw_fd())?.f_bfree;
+	test_assert!(free_closed > free_open);
+	Ok(())
+}
+
 // TODO O_APPEND
 
+pub fn sparse() -> TestResult {
+	let path = Path::new("sparse");
+	let size = 1024 * 1024;
+	let file = OpenOptions::new()
+		.create_new(true)
+		.read(true)
+		.write(true)
+		.open(path)?;
+	let res = (|| {
+		log!("Extend file");
+		file.set_len(size)?;
+		let stat = util::fstat(file.as_raw_fd())?;
+		test_assert_eq!((stat.st_size as u64, stat.st_blocks), (size, 0));
+		log!("Write in the middle");
+		file.write_all_at(b"data", size / 2)?;
+		file.sync_all()?;
+		let blocks = util::fstat(file.as_raw_fd())?.st_blocks;
+		// At most one page is written back
+		test_assert!(blocks > 0 && blocks <= 64);
+		let mut buf = [0xff; 8];
+		file.read_exact_at(&mut buf, size / 2 - 4)?;
+		test_assert_eq!(&buf, b"\0\0\0\0data");
+		log!("Punch hole");
+		let res = unsafe {
+			libc::fallocate(
+				file.as_raw_fd(),
+				libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE,
+				0,
+				size as _,
+			)
+		};
+		test_assert_eq!(res, 0);
+		let stat = util::fstat(file.as_raw_fd())?;
+		test_assert_eq!((stat.st_size as u64, stat.st_blocks), (size, 0));
+		file.read_exact_at(&mut buf, size / 2 - 4)?;
+		test_assert_eq!(buf, [0; 8]);
+		log!("Punch hole over data not written back");
+		file.write_all_at(b"data", size / 2)?;
+		let res = unsafe {
+			libc::fallocate(
+				file.as_raw_fd(),
+				libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE,
+				0,
+				size as _,
+			)
+		};
+		test_assert_eq!(res, 0);
+		// Writing back must not allocate the punched blocks again
+		file.sync_all()?;
+		let stat = util::fstat(file.as_raw_fd())?;
+		test_assert_eq!((stat.st_size as u64, stat.st_blocks), (size, 0));
+		file.read_exact_at(&mut buf, size / 2 - 4)?;
+		test_assert_eq!(buf, [0; 8]);
+		Ok(())
+	})();
+	fs::remove_file(path)?;
+	res
+}
+
 pub fn directories() -> TestResult {
 	log!("Create directory at non-existent location (invalid)");
 	let res = fs::create_dir("/abc/def");
@@ -147,6 +388,72 @@ pub fn directories() -> TestResult {
 	Ok(())
 }
 
+/// Lists the entries of the directory at `path`, with their type as reported by `readdir`.
+fn dirent_types(path: &str) -> io::Result<Vec<(String, u8)>> {
+	let c_path = CString::new(path)?;
+	let dir = unsafe { libc::opendir(c_path.as_ptr()) };
+	if dir.is_null() {
+		return Err(io::Error::last_os_error());
+	}
+	let mut entries = vec![];
+	loop {
+		let ent = unsafe { libc::readdir(dir) };
+		if ent.is_null() {
+			break;
+		}
+		let ent = unsafe { &*ent };
+		let name = util::c_str_array(&ent.d_name);
+		entries.push((name, ent.d_type));
+	}
+	unsafe {
+		libc::closedir(dir);
+	}
+	Ok(entries)
+}
+
+/// Checks the types of the entries of the directory at `path`, which contains a file, a
+/// directory and a symbolic link.
+///
+/// If `exact` is `false`, the type may also be reported as unknown.
+fn check_dirent_types(path: &str, exact: bool) -> TestResult {
+	let entries = dirent_types(path)?;
+	test_assert_eq!(entries.len(), 5);
+	for (name, d_type) in entries {
+		let expected = match name.as_str() {
+			"." | ".." | "dir" => libc::DT_DIR,
+			"file" => libc::DT_REG,
+			"link" => libc::DT_LNK,
+			_ => return Err(TestError(format!("unexpected entry `{name}`"))),
+		};
+		test_assert!(d_type == expected || (!exact && d_type == libc::DT_UNKNOWN));
+	}
+	Ok(())
+}
+
+pub fn dirents() -> TestResult {
+	let src = CString::new("tmpfs")?;
+	let target = CString::new("dirents/tmp")?;
+	fs::create_dir_all("dirents/root")?;
+	fs::create_dir("dirents/tmp")?;
+	util::mount(&src, &target, &src, 0, std::ptr::null())?;
+	let res = (|| {
+		for dir in ["dirents/root", "dirents/tmp"] {
+			fs::create_dir(format!("{dir}/dir"))?;
+			fs::write(format!("{dir}/file"), b"")?;
+			unix::fs::symlink("file", format!("{dir}/link"))?;
+		}
+		log!("Types on the root filesystem");
+		// Depending on the filesystem's features, types may not be stored in directories
+		check_dirent_types("dirents/root", false)?;
+		log!("Types on tmpfs");
+		// tmpfs always knows the type of its files
+		check_dirent_types("dirents/tmp", true)
+	})();
+	util::umount(&target)?;
+	fs::remove_dir_all("dirents")?;
+	res
+}
+
 pub fn dir_perms() -> TestResult {
 	fs::create_dir_all("/foo/bar")?;
 	util::chown("/foo", 1000, 1000)?;
@@ -185,6 +492,132 @@ pub fn dir_perms() -> TestResult {
 	Ok(())
 }
 
+/// Returns the supplementary groups of the current process.
+fn getgroups() -> io::Result<Vec<libc::gid_t>> {
+	let count = unsafe { libc::getgroups(0, std::ptr::null_mut()) };
+	if count < 0 {
+		return Err(io::Error::last_os_error());
+	}
+	let mut groups = vec![0; count as usize];
+	let count = unsafe { libc::getgroups(count, groups.as_mut_ptr()) };
+	if count < 0 {
+		return Err(io::Error::last_os_error());
+	}
+	groups.truncate(count as usize);
+	Ok(groups)
+}
+
+pub fn groups() -> TestResult {
+	fs::create_dir("groups")?;
+	// Run in a child so that the groups of the test process are not changed
+	let res = util::in_child(|| {
+		log!("Set supplementary groups");
+		let groups = [2000, 2001];
+		test_assert_eq!(unsafe { libc::setgroups(2, groups.as_ptr()) }, 0);
+		test_assert_eq!(getgroups()?, groups);
+		let mut small = [0; 1];
+		test_assert_eq!(unsafe { libc::getgroups(1, small.as_mut_ptr()) }, -1);
+		test_assert_eq!(
+			io::Error::last_os_error().raw_os_error(),
+			Some(libc::EINVAL)
+		);
+		let status = fs::read_to_string("/proc/self/status")?;
+		let line = status
+			.lines()
+			.find_map(|l| l.strip_prefix("Groups:"))
+			.ok_or_else(|| TestError("missing groups".to_owned()))?;
+		test_assert_eq!(
+			line.split_whitespace().collect::<Vec<_>>(),
+			["2000", "2001"]
+		);
+		log!("Access files through a supplementary group");
+		fs::write("groups/member", "a")?;
+		util::chown("groups/member", 0, 2001)?;
+		util::chmod("groups/member", 0o040)?;
+		fs::write("groups/other", "a")?;
+		util::chown("groups/other", 0, 2002)?;
+		util::chmod("groups/other", 0o040)?;
+		fs::write("groups/owned", "a")?;
+		util::chown("groups/owned", 1000, 1000)?;
+		unprivileged(|| -> TestResult {
+			test_assert_eq!(getgroups()?, groups);
+			test_assert_eq!(fs::read_to_string("groups/member")?, "a");
+			util::expect_errno(fs::read("groups/other"), libc::EACCES)?;
+			log!("Change the group of a file to a supplementary group");
+			util::chown("groups/owned", u32::MAX, 2000)?;
+			util::expect_errno(util::chown("groups/owned", u32::MAX, 2002), libc::EPERM)?;
+			log!("Set supplementary groups without privileges");
+			test_assert_eq!(unsafe { libc::setgroups(0, std::ptr::null()) }, -1);
+			test_assert_eq!(io::Error::last_os_error().raw_os_error(), Some(libc::EPERM));
+			Ok(())
+		})??;
+		test_assert_eq!(fs::metadata("groups/owned")?.gid(), 2000);
+		Ok(())
+	});
+	log!("Cleanup");
+	fs::remove_dir_all("groups")?;
+	res
+}
+
+pub fn suid() -> TestResult {
+	fs::write("suid", b"")?;
+	unix::fs::symlink("suid", "suid_link")?;
+	let res = (|| {
+		let mode = || -> io::Result<u32> { Ok(util::stat("suid")?.st_mode & 0o7777) };
+		util::chown("suid", 1000, 1000)?;
+		log!("Write");
+		util::chmod("suid", 0o6777)?;
+		unprivileged(|| -> TestResult {
+			OpenOptions::new()
+				.write(true)
+				.open("suid")?
+				.write_all(b"a")?;
+			Ok(())
+		})??;
+		test_assert_eq!(mode()?, 0o777);
+		log!("Write without group execute permission");
+		// The SGID bit does not give privileges, so it is kept
+		util::chmod("suid", 0o6767)?;
+		unprivileged(|| -> TestResult {
+			OpenOptions::new()
+				.write(true)
+				.open("suid")?
+				.write_all(b"b")?;
+			Ok(())
+		})??;
+		test_assert_eq!(mode()?, 0o2767);
+		log!("Change owner");
+		util::chmod("suid", 0o6777)?;
+		let file = fs::File::open("suid")?;
+		unprivileged(|| -> TestResult {
+			// Unprivileged users cannot give their files away
+			test_assert_eq!(unsafe { libc::fchown(file.as_raw_fd(), 0, 1000) }, -1);
+			test_assert_eq!(io::Error::last_os_error().raw_os_error(), Some(libc::EPERM));
+			test_assert_eq!(unsafe { libc::fchown(file.as_raw_fd(), u32::MAX, 1000) }, 0);
+			Ok(())
+		})??;
+		test_assert_eq!(mode()?, 0o777);
+		log!("Change the owner of a symbolic link");
+		let link = CString::new("suid_link")?;
+		let res = unsafe {
+			libc::fchownat(
+				libc::AT_FDCWD,
+				link.as_ptr(),
+				2000,
+				2000,
+				libc::AT_SYMLINK_NOFOLLOW,
+			)
+		};
+		test_assert_eq!(res, 0);
+		test_assert_eq!(fs::symlink_metadata("suid_link")?.uid(), 2000);
+		test_assert_eq!(util::stat("suid")?.st_uid, 1000);
+		Ok(())
+	})();
+	fs::remove_file("suid_link")?;
+	fs::remove_file("suid")?;
+	res
+}
+
 pub fn hardlinks() -> TestResult {
 	log!("Create link to directory (invalid)");
 	fs::create_dir("test_dir")?;
@@ -247,6 +680,249 @@ pub fn symlinks() -> TestResult {
 	Ok(())
 }
 
+/// A file handle, as used by `name_to_handle_at` and `open_by_handle_at`.
+#[repr(C)]
+struct FileHandle {
+	handle_bytes: u32,
+	handle_type: c_int,
+	f_handle: [u8; 128],
+}
+
+/// Returns a handle to the file at `path`, along with the ID of its mountpoint.
+///
+/// `size` is the size of the buffer for the content of the handle.
+fn name_to_handle(path: &str, size: u32) -> Result<(FileHandle, c_int), (io::Error, u32)> {
+	let mut handle = FileHandle {
+		handle_bytes: size,
+		handle_type: 0,
+		f_handle: [0; 128],
+	};
+	let mut mount_id = 0;
+	let path = CString::new(path).unwrap();
+	let res = unsafe {
+		libc::syscall(
+			libc::SYS_name_to_handle_at,
+			libc::AT_FDCWD,
+			path.as_ptr(),
+			&mut handle,
+			&mut mount_id,
+			0,
+		)
+	};
+	if res < 0 {
+		return Err((io::Error::last_os_error(), handle.handle_bytes));
+	}
+	Ok((handle, mount_id))
+}
+
+/// Opens the file with the given `handle` on the mountpoint of the working directory.
+fn open_by_handle(handle: &FileHandle, flags: c_int) -> io::Result<fs::File> {
+	let fd = unsafe {
+		libc::syscall(
+			libc::SYS_open_by_handle_at,
+			libc::AT_FDCWD,
+			handle as *const FileHandle,
+			flags,
+		)
+	};
+	if fd < 0 {
+		return Err(io::Error::last_os_error());
+	}
+	Ok(unsafe { fs::File::from_raw_fd(fd as _) })
+}
+
+pub fn file_handles() -> TestResult {
+	fs::write("handle", b"abc")?;
+	let res = (|| {
+		log!("Buffer too small");
+		let Err((err, size)) = name_to_handle("handle", 0) else {
+			return Err(TestError("expected EOVERFLOW".to_owned()));
+		};
+		test_assert_eq!(err.raw_os_error(), Some(libc::EOVERFLOW));
+		test_assert!(size > 0 && size <= 128);
+		log!("Get handle");
+		let (handle, _) = name_to_handle("handle", size).map_err(|(e, _)| e)?;
+		test_assert_eq!(handle.handle_bytes, size);
+		log!("Open by handle");
+		let mut file = open_by_handle(&handle, libc::O_RDONLY)?;
+		let mut content = vec![];
+		file.read_to_end(&mut content)?;
+		test_assert_eq!(content, b"abc");
+		test_assert_eq!(file.metadata()?.ino(), fs::metadata("handle")?.ino());
+		drop(file);
+		let res = open_by_handle(&handle, libc::O_RDONLY | libc::O_CREAT);
+		util::expect_errno(res, libc::EINVAL)?;
+		let res = unprivileged(|| open_by_handle(&handle, libc::O_RDONLY))?;
+		util::expect_errno(res, libc::EPERM)?;
+		log!("Removed file");
+		fs::remove_file("handle")?;
+		util::expect_errno(open_by_handle(&handle, libc::O_RDONLY), libc::ESTALE)
+	})();
+	let _ = fs::remove_file("handle");
+	res
+}
+
+pub fn lookup_cache() -> TestResult {
+	let not_found =
+		|path: &str| matches!(util::stat(path), Err(e) if e.kind() == io::ErrorKind::NotFound);
+	log!("Lookup missing file");
+	fs::create_dir("lookup")?;
+	test_assert!(not_found("lookup/file"));
+	log!("Create file");
+	fs::write("lookup/file", b"abc")?;
+	test_assert_eq!(fs::read("lookup/file")?, b"abc");
+	log!("Remove file");
+	fs::remove_file("lookup/file")?;
+	test_assert!(not_found("lookup/file"));
+	log!("Link to missing file");
+	test_assert!(not_found("lookup/link"));
+	fs::hard_link("inttest", "lookup/link")?;
+	util::stat("lookup/link")?;
+	log!("Rename to missing file");
+	test_assert!(not_found("lookup/renamed"));
+	fs::rename("lookup/link", "lookup/renamed")?;
+	test_assert!(not_found("lookup/link"));
+	util::stat("lookup/renamed")?;
+	fs::remove_file("lookup/renamed")?;
+	log!("Lookup many missing files");
+	for i in 0..2048 {
+		test_assert!(not_found(&format!("lookup/{i}")));
+	}
+	util::stat("lookup")?;
+	log!("Cleanup");
+	fs::remove_dir("lookup")?;
+	test_assert!(not_found("lookup"));
+	Ok(())
+}
+
+/// Executes the file at `path`, which is expected to fail.
+fn execv_err(path: &str) -> io::Result<()> {
+	let path = CString::new(path)?;
+	let argv = [path.as_ptr(), std::ptr::null()];
+	unsafe {
+		libc::execv(path.as_ptr(), argv.as_ptr());
+	}
+	Err(io::Error::last_os_error())
+}
+
+pub fn exec_cache() -> TestResult {
+	const PROG: &str = "/exec_cache/bin/prog";
+	fs::create_dir_all("/exec_cache/bin")?;
+	fs::create_dir_all("/exec_cache/other")?;
+	let res = (|| {
+		// A file that is not executable fails with `EACCES`, a missing file with `ENOENT`
+		log!("Execute the same path twice");
+		fs::write(PROG, b"abc")?;
+		util::chmod(PROG, 0o644)?;
+		util::expect_errno(execv_err(PROG), libc::EACCES)?;
+		util::expect_errno(execv_err(PROG), libc::EACCES)?;
+		log!("Remove, then create the file");
+		fs::remove_file(PROG)?;
+		util::expect_errno(execv_err(PROG), libc::ENOENT)?;
+		fs::write(PROG, b"abc")?;
+		util::expect_errno(execv_err(PROG), libc::EACCES)?;
+		log!("Rename a directory on the path");
+		fs::rename("/exec_cache/bin", "/exec_cache/bin2")?;
+		util::expect_errno(execv_err(PROG), libc::ENOENT)?;
+		fs::rename("/exec_cache/bin2", "/exec_cache/bin")?;
+		util::expect_errno(execv_err(PROG), libc::EACCES)?;
+		log!("Replace a symbolic link on the path");
+		unix::fs::symlink("bin", "/exec_cache/link")?;
+		util::expect_errno(execv_err("/exec_cache/link/prog"), libc::EACCES)?;
+		fs::remove_file("/exec_cache/link")?;
+		unix::fs::symlink("other", "/exec_cache/link")?;
+		util::expect_errno(execv_err("/exec_cache/link/prog"), libc::ENOENT)?;
+		log!("Mount over a directory on the path");
+		let src = CString::new("tmpfs")?;
+		let target = CString::new("/exec_cache/bin")?;
+		util::mount(&src, &target, &src, 0, std::ptr::null())?;
+		let res = execv_err(PROG);
+		util::umount(&target)?;
+		util::expect_errno(res, libc::ENOENT)?;
+		util::expect_errno(execv_err(PROG), libc::EACCES)?;
+		log!("Missing interpreter");
+		fs::write(PROG, b"#!/exec_cache/interp\n")?;
+		util::chmod(PROG, 0o755)?;
+		util::expect_errno(execv_err(PROG), libc::ENOENT)?;
+		fs::write("/exec_cache/interp", b"abc")?;
+		util::expect_errno(execv_err(PROG), libc::EACCES)?;
+		log!("Remove search permission on the path");
+		fs::remove_file("/exec_cache/interp")?;
+		unprivileged(|| util::expect_errno(execv_err(PROG), libc::ENOENT))??;
+		util::chmod("/exec_cache/bin", 0o700)?;
+		unprivileged(|| util::expect_errno(execv_err(PROG), libc::EACCES))??;
+		Ok(())
+	})();
+	log!("Cleanup");
+	fs::remove_dir_all("/exec_cache")?;
+	res
+}
+
+pub fn chroot() -> TestResult {
+	log!("Setup");
+	fs::create_dir_all("jail/sub")?;
+	unix::fs::symlink("../../..", "jail/sub/up")?;
+	unix::fs::symlink("/..", "jail/abs")?;
+
+	util::in_child(|| {
+		log!("Enter chroot");
+		util::chroot("jail")?;
+		// The working directory is still outside of the root directory
+		let root = fs::metadata("/")?.ino();
+		log!("Parent of the working directory");
+		test_assert_eq!(fs::metadata("..")?.ino(), root);
+		std::env::set_current_dir("/sub")?;
+		log!("Parent of the root directory");
+		test_assert_eq!(fs::metadata("/..")?.ino(), root);
+		test_assert_eq!(fs::metadata("../../..")?.ino(), root);
+		log!("Escape through symbolic links");
+		test_assert_eq!(fs::metadata("up")?.ino(), root);
+		test_assert_eq!(fs::metadata("up/..")?.ino(), root);
+		test_assert_eq!(fs::metadata("/abs")?.ino(), root);
+		Ok(())
+	})?;
+
+	log!("Cleanup");
+	fs::remove_dir_all("jail")?;
+	Ok(())
+}
+
+pub fn cwd() -> TestResult {
+	log!("Setup");
+	fs::create_dir_all("cwd/sub")?;
+	fs::create_dir("cwd/search")?;
+	fs::create_dir("cwd/list")?;
+	util::chmod("cwd/search", 0o711)?;
+	util::chmod("cwd/list", 0o744)?;
+
+	let res = util::in_child(|| {
+		log!("Search permission");
+		// Entering a directory requires the permission to search it, not to list it
+		unprivileged(|| -> TestResult {
+			std::env::set_current_dir("cwd/search")?;
+			std::env::set_current_dir("../..")?;
+			util::expect_errno(std::env::set_current_dir("cwd/list"), libc::EACCES)
+		})??;
+		log!("Working directory in a chroot");
+		std::env::set_current_dir("cwd/sub")?;
+		util::chroot("..")?;
+		test_assert_eq!(std::env::current_dir()?, Path::new("/sub"));
+		log!("Buffer too small");
+		let mut buf = [0u8; 4];
+		let ptr = unsafe { libc::getcwd(buf.as_mut_ptr() as *mut _, buf.len()) };
+		test_assert!(ptr.is_null());
+		test_assert_eq!(
+			io::Error::last_os_error().raw_os_error(),
+			Some(libc::ERANGE)
+		);
+		Ok(())
+	});
+
+	log!("Cleanup");
+	fs::remove_dir_all("cwd")?;
+	res
+}
+
 pub fn rename() -> TestResult {
 	log!("Create file");
 	{
@@ -272,28 +948,214 @@ pub fn rename() -> TestResult {
 	log!("Cleanup");
 	fs::remove_file("new")?;
 
-	// FIXME: moving a directory is broken
-	/*log!("Create directories");
+	log!("Create directories");
 	fs::create_dir_all("old/foo/bar")?;
 	log!("Rename");
 	fs::rename("old", "new")?;
 	log!("Stat old directory");
 	test_assert!(matches!(fs::metadata("old"), Err(e) if e.kind() == io::ErrorKind::NotFound));
 	log!("Stat new directories");
-	for (path, links) in [("new", 3), ("foo", 3), ("bar", 2)] {
+	for (path, links) in [("new", 3), ("new/foo", 3), ("new/foo/bar", 2)] {
 		let metadata = fs::metadata(path)?;
 		test_assert!(metadata.is_dir());
 		test_assert_eq!(metadata.nlink(), links);
 	}
+	log!("Move into a descendant");
+	util::expect_errno(fs::rename("new", "new/foo/baz"), libc::EINVAL)?;
+	log!("Move to another directory");
+	fs::rename("new/foo/bar", "new/bar")?;
+	test_assert_eq!(fs::metadata("new")?.nlink(), 4);
+	test_assert_eq!(fs::metadata("new/foo")?.nlink(), 2);
 	log!("Cleanup");
 	fs::remove_dir_all("new")?;
-	test_assert!(matches!(fs::metadata("new"), Err(e) if e.kind() == io::ErrorKind::NotFound));*/
+	test_assert!(matches!(fs::metadata("new"), Err(e) if e.kind() == io::ErrorKind::NotFound));
+
+	log!("Replace file");
+	fs::write("a", b"a")?;
+	fs::write("b", b"b")?;
+	fs::rename("a", "b")?;
+	test_assert!(matches!(fs::metadata("a"), Err(e) if e.kind() == io::ErrorKind::NotFound));
+	test_assert_eq!(fs::read("b")?, b"a");
+	log!("Replace file with RENAME_NOREPLACE");
+	fs::write("a", b"a2")?;
+	util::expect_errno(
+		util::renameat2("a", "b", libc::RENAME_NOREPLACE),
+		libc::EEXIST,
+	)?;
+	log!("Exchange files");
+	util::renameat2("a", "b", libc::RENAME_EXCHANGE)?;
+	test_assert_eq!(fs::read("a")?, b"a");
+	test_assert_eq!(fs::read("b")?, b"a2");
+	log!("Exchange with missing file");
+	util::expect_errno(
+		util::renameat2("a", "c", libc::RENAME_EXCHANGE),
+		libc::ENOENT,
+	)?;
+	log!("Invalid flags");
+	util::expect_errno(
+		util::renameat2("a", "b", libc::RENAME_NOREPLACE | libc::RENAME_EXCHANGE),
+		libc::EINVAL,
+	)?;
+	log!("Replace directory with file");
+	fs::create_dir("dir")?;
+	util::expect_errno(fs::rename("a", "dir"), libc::EISDIR)?;
+	util::expect_errno(fs::rename("dir", "a"), libc::ENOTDIR)?;
+	log!("Replace non-empty directory");
+	fs::create_dir_all("dir2/sub")?;
+	util::expect_errno(fs::rename("dir", "dir2"), libc::ENOTEMPTY)?;
+	log!("Replace empty directory");
+	fs::rename("dir2/sub", "dir")?;
+	test_assert!(fs::metadata("dir")?.is_dir());
+	test_assert_eq!(fs::metadata("dir2")?.nlink(), 2);
+	log!("Cleanup");
+	fs::remove_file("a")?;
+	fs::remove_file("b")?;
+	fs::remove_dir("dir")?;
+	fs::remove_dir("dir2")?;
 
 	// TODO test moving across mountpoints
 
 	Ok(())
 }
 
+pub fn rmdir() -> TestResult {
+	fs::create_dir_all("rmdir/dir/sub")?;
+	fs::create_dir("rmdir/mnt")?;
+	let res = (|| {
+		log!("Dot entries");
+		util::expect_errno(fs::remove_dir("rmdir/dir/."), libc::EINVAL)?;
+		util::expect_errno(fs::remove_dir("rmdir/dir/.."), libc::ENOTEMPTY)?;
+		log!("Mountpoint");
+		let src = CString::new("tmpfs")?;
+		let target = CString::new("rmdir/mnt")?;
+		util::mount(&src, &target, &src, 0, std::ptr::null())?;
+		let res = util::expect_errno(fs::remove_dir("rmdir/mnt"), libc::EBUSY)
+			.and_then(|_| util::expect_errno(fs::rename("rmdir/mnt", "rmdir/mnt2"), libc::EBUSY));
+		util::umount(&target)?;
+		res?;
+		log!("Replace directories");
+		fs::create_dir("rmdir/empty")?;
+		fs::write("rmdir/file", b"")?;
+		util::expect_errno(fs::rename("rmdir/empty", "rmdir/dir"), libc::ENOTEMPTY)?;
+		util::expect_errno(fs::rename("rmdir/empty", "rmdir/file"), libc::ENOTDIR)?;
+		util::expect_errno(fs::rename("rmdir/file", "rmdir/empty"), libc::EISDIR)?;
+		fs::rename("rmdir/dir/sub", "rmdir/empty")?;
+		test_assert!(!Path::new("rmdir/dir/sub").exists());
+		test_assert!(fs::metadata("rmdir/empty")?.is_dir());
+		// `dir`, `mnt` and `empty`, which replaced a directory
+		test_assert_eq!(fs::metadata("rmdir")?.nlink(), 5);
+		test_assert_eq!(fs::metadata("rmdir/dir")?.nlink(), 2);
+		Ok(())
+	})();
+	fs::remove_dir_all("rmdir")?;
+	res
+}
+
+pub fn leases() -> TestResult {
+	let path = Path::new("lease");
+	fs::write(path, b"content")?;
+	// Breaking a lease sends `SIGIO` to its holder, which would terminate the process
+	unsafe {
+		libc::signal(libc::SIGIO, libc::SIG_IGN);
+	}
+	let res = (|| {
+		let file = fs::File::open(path)?;
+		let fd = file.as_raw_fd();
+		log!("Place read lease");
+		test_assert_eq!(
+			unsafe { libc::fcntl(fd, libc::F_SETLEASE, libc::F_RDLCK) },
+			0
+		);
+		test_assert_eq!(unsafe { libc::fcntl(fd, libc::F_GETLEASE) }, libc::F_RDLCK);
+		log!("Open for reading");
+		drop(fs::File::open(path)?);
+		test_assert_eq!(unsafe { libc::fcntl(fd, libc::F_GETLEASE) }, libc::F_RDLCK);
+		log!("Open for writing");
+		let res = OpenOptions::new()
+			.write(true)
+			.custom_flags(libc::O_NONBLOCK)
+			.open(path);
+		util::expect_errno(res, libc::EWOULDBLOCK)?;
+		// The lease is being broken
+		test_assert_eq!(unsafe { libc::fcntl(fd, libc::F_GETLEASE) }, libc::F_UNLCK);
+		log!("Release lease");
+		test_assert_eq!(
+			unsafe { libc::fcntl(fd, libc::F_SETLEASE, libc::F_UNLCK) },
+			0
+		);
+		let writer = OpenOptions::new().write(true).open(path)?;
+		log!("Place conflicting leases");
+		test_assert_eq!(
+			unsafe { libc::fcntl(fd, libc::F_SETLEASE, libc::F_RDLCK) },
+			-1
+		);
+		test_assert_eq!(
+			io::Error::last_os_error().raw_os_error(),
+			Some(libc::EAGAIN)
+		);
+		test_assert_eq!(
+			unsafe { libc::fcntl(fd, libc::F_SETLEASE, libc::F_WRLCK) },
+			-1
+		);
+		test_assert_eq!(
+			io::Error::last_os_error().raw_os_error(),
+			Some(libc::EAGAIN)
+		);
+		drop(writer);
+		log!("Place write lease");
+		test_assert_eq!(
+			unsafe { libc::fcntl(fd, libc::F_SETLEASE, libc::F_WRLCK) },
+			0
+		);
+		test_assert_eq!(unsafe { libc::fcntl(fd, libc::F_GETLEASE) }, libc::F_WRLCK);
+		log!("Open for reading with write lease");
+		let res = OpenOptions::new()
+			.read(true)
+			.custom_flags(libc::O_NONBLOCK)
+			.open(path);
+		util::expect_errno(res, libc::EWOULDBLOCK)?;
+		// The lease is being downgraded
+		test_assert_eq!(unsafe { libc::fcntl(fd, libc::F_GETLEASE) }, libc::F_RDLCK);
+		test_assert_eq!(
+			unsafe { libc::fcntl(fd, libc::F_SETLEASE, libc::F_RDLCK) },
+			0
+		);
+		drop(fs::File::open(path)?);
+		log!("Wait for the lease to be released");
+		let pid = unsafe { libc::fork() };
+		if pid < 0 {
+			return Err(io::Error::last_os_error().into());
+		}
+		if pid == 0 {
+			// The lease is released long before it would be broken forcibly
+			let start = Instant::now();
+			let res = OpenOptions::new().write(true).open(path);
+			let code = (res.is_err() || start.elapsed() >= Duration::from_secs(10)) as c_int;
+			unsafe {
+				libc::_exit(code);
+			}
+		}
+		while unsafe { libc::fcntl(fd, libc::F_GETLEASE) } != libc::F_UNLCK {
+			thread::sleep(Duration::from_millis(10));
+		}
+		thread::sleep(Duration::from_millis(100));
+		test_assert_eq!(
+			unsafe { libc::fcntl(fd, libc::F_SETLEASE, libc::F_UNLCK) },
+			0
+		);
+		let mut status = 0;
+		test_assert!(unsafe { libc::waitpid(pid, &mut status, 0) } == pid);
+		test_assert!(libc::WIFEXITED(status) && libc::WEXITSTATUS(status) == 0);
+		Ok(())
+	})();
+	unsafe {
+		libc::signal(libc::SIGIO, libc::SIG_DFL);
+	}
+	log!("Cleanup");
+	fs::remove_file(path)?;
+	res
+}
+
 pub fn fifo() -> TestResult {
 	log!("Create fifo");
 	util::mkfifo("fifo", 0o666)?;
@@ -305,3 +1167,529 @@ pub fn fifo() -> TestResult {
 
 	Ok(())
 }
+
+pub fn ioctl() -> TestResult {
+	let mut file = OpenOptions::new()
+		.create(true)
+		.truncate(true)
+		.read(true)
+		.write(true)
+		.open("ioctl")?;
+	let res = (|| {
+		let fd = file.as_raw_fd();
+		file.write_all(b"abcdef")?;
+		file.seek(SeekFrom::Start(2))?;
+		log!("Count readable bytes on a regular file");
+		let mut count: c_int = 0;
+		test_assert!(unsafe { libc::ioctl(fd, libc::FIONREAD, &mut count) } == 0);
+		test_assert_eq!(count, 4);
+		log!("Set and clear close-on-exec");
+		test_assert!(unsafe { libc::ioctl(fd, libc::FIOCLEX) } == 0);
+		test_assert_eq!(
+			unsafe { libc::fcntl(fd, libc::F_GETFD) } & libc::FD_CLOEXEC,
+			libc::FD_CLOEXEC
+		);
+		test_assert!(unsafe { libc::ioctl(fd, libc::FIONCLEX) } == 0);
+		test_assert_eq!(
+			unsafe { libc::fcntl(fd, libc::F_GETFD) } & libc::FD_CLOEXEC,
+			0
+		);
+		Ok(())
+	})();
+	log!("Cleanup");
+	fs::remove_file("ioctl")?;
+	res?;
+
+	let (read, write) = util::pipe()?;
+	let mut read = fs::File::from(read);
+	let mut write = fs::File::from(write);
+	let fd = read.as_raw_fd();
+	log!("Set non-blocking mode");
+	let on: c_int = 1;
+	test_assert!(unsafe { libc::ioctl(fd, libc::FIONBIO, &on) } == 0);
+	test_assert!(unsafe { libc::fcntl(fd, libc::F_GETFL) } & libc::O_NONBLOCK != 0);
+	util::expect_errno(read.read(&mut [0u8; 16]), libc::EAGAIN)?;
+	log!("Count readable bytes on a pipe");
+	write.write_all(b"abc")?;
+	let mut count: c_int = 0;
+	test_assert!(unsafe { libc::ioctl(fd, libc::FIONREAD, &mut count) } == 0);
+	test_assert_eq!(count, 3);
+	log!("Clear non-blocking mode");
+	let off: c_int = 0;
+	test_assert!(unsafe { libc::ioctl(fd, libc::FIONBIO, &off) } == 0);
+	test_assert_eq!(
+		unsafe { libc::fcntl(fd, libc::F_GETFL) } & libc::O_NONBLOCK,
+		0
+	);
+	Ok(())
+}
+
+pub fn mount_options() -> TestResult {
+	fs::create_dir("/mount_opts")?;
+	let src = CString::new("tmpfs")?;
+	let target = CString::new("/mount_opts")?;
+	log!("Mount with options");
+	let data = CString::new("size=64k,mode=700,uid=1000,gid=1000")?;
+	util::mount(&src, &target, &src, 0, data.as_ptr() as *const _)?;
+	let dir = fs::File::open("/mount_opts")?;
+	let stat = util::fstatvfs(dir.as_raw_fd())?;
+	test_assert_eq!(stat.f_blocks as u64 * stat.f_frsize as u64, 64 * 1024);
+	let stat = util::fstat(dir.as_raw_fd())?;
+	test_assert_eq!(stat.st_mode & 0o7777, 0o700);
+	test_assert_eq!((stat.st_uid, stat.st_gid), (1000, 1000));
+	drop(dir);
+	util::umount(&target)?;
+	log!("Mount with an unsupported option");
+	let data = CString::new("size=64k,foo")?;
+	let res = util::mount(&src, &target, &src, 0, data.as_ptr() as *const _);
+	util::expect_errno(res, libc::EINVAL)?;
+	log!("Cleanup");
+	fs::remove_dir("/mount_opts")?;
+	Ok(())
+}
+
+pub fn page_cache() -> TestResult {
+	fs::write("page_cache", b"aaaa")?;
+	let res = (|| {
+		let file = OpenOptions::new()
+			.read(true)
+			.write(true)
+			.open("page_cache")?;
+		let reader = fs::File::open("page_cache")?;
+		let mut buf = [0u8; 4];
+		log!("Read after write");
+		file.write_at(b"bb", 1)?;
+		reader.read_exact_at(&mut buf, 0)?;
+		test_assert_eq!(&buf, b"abba");
+		log!("Shared mapping");
+		let ptr = util::mmap(
+			std::ptr::null_mut(),
+			4096,
+			libc::PROT_READ | libc::PROT_WRITE,
+			libc::MAP_SHARED,
+			file.as_raw_fd(),
+			0,
+		)? as *mut u8;
+		// Modifications through the mapping are seen by reads, and conversely
+		unsafe {
+			*ptr = b'c';
+		}
+		reader.read_exact_at(&mut buf, 0)?;
+		test_assert_eq!(&buf, b"cbba");
+		file.write_at(b"d", 3)?;
+		test_assert_eq!(unsafe { *ptr.add(3) }, b'd');
+		util::munmap(ptr as _, 4096)?;
+		log!("Synchronize");
+		file.sync_all()?;
+		test_assert_eq!(unsafe { libc::syncfs(file.as_raw_fd()) }, 0);
+		unsafe {
+			libc::sync();
+		}
+		drop(file);
+		drop(reader);
+		test_assert_eq!(fs::read("page_cache")?, b"cbbd");
+		Ok(())
+	})();
+	fs::remove_file("page_cache")?;
+	res
+}
+
+pub fn file_mappings() -> TestResult {
+	let mut content = vec![b'a'; 4096];
+	content.extend_from_slice(&[b'b'; 4096]);
+	fs::write("file_mappings", &content)?;
+	let res = (|| {
+		let file = OpenOptions::new()
+			.read(true)
+			.write(true)
+			.open("file_mappings")?;
+		let prot = libc::PROT_READ | libc::PROT_WRITE;
+		log!("Private mapping at an offset");
+		let private = util::mmap(
+			std::ptr::null_mut(),
+			4096,
+			prot,
+			libc::MAP_PRIVATE,
+			file.as_raw_fd(),
+			4096,
+		)? as *mut u8;
+		test_assert_eq!(unsafe { *private.add(10) }, b'b');
+		unsafe {
+			*private = b'x';
+		}
+		let mut buf = [0u8; 1];
+		file.read_exact_at(&mut buf, 4096)?;
+		test_assert_eq!(&buf, b"b");
+		log!("Private mapping after fork");
+		util::in_child(|| {
+			test_assert_eq!(unsafe { *private }, b'x');
+			unsafe {
+				*private = b'y';
+			}
+			Ok(())
+		})?;
+		test_assert_eq!(unsafe { *private }, b'x');
+		util::munmap(private as _, 4096)?;
+		log!("Shared mapping written by another process");
+		let shared = util::mmap(
+			std::ptr::null_mut(),
+			8192,
+			prot,
+			libc::MAP_SHARED,
+			file.as_raw_fd(),
+			0,
+		)? as *mut u8;
+		util::in_child(|| {
+			unsafe {
+				*shared.add(4097) = b'z';
+			}
+			let res = unsafe { libc::msync(shared as _, 8192, libc::MS_SYNC) };
+			test_assert_eq!(res, 0);
+			Ok(())
+		})?;
+		test_assert_eq!(unsafe { *shared.add(4097) }, b'z');
+		file.read_exact_at(&mut buf, 4097)?;
+		test_assert_eq!(&buf, b"z");
+		log!("Shared mapping outliving its file descriptor");
+		drop(file);
+		unsafe {
+			*shared = b'w';
+		}
+		util::munmap(shared as _, 8192)?;
+		content[0] = b'w';
+		content[4097] = b'z';
+		test_assert!(fs::read("file_mappings")? == content);
+		Ok(())
+	})();
+	fs::remove_file("file_mappings")?;
+	res
+}
+
+pub fn reclaim() -> TestResult {
+	let content: Vec<u8> = (0..8192u32).map(|i| i as u8).collect();
+	fs::write("reclaim", &content)?;
+	let file = OpenOptions::new().read(true).write(true).open("reclaim")?;
+	let prot = libc::PROT_READ | libc::PROT_WRITE;
+	let shared = util::mmap(
+		std::ptr::null_mut(),
+		8192,
+		prot,
+		libc::MAP_SHARED,
+		file.as_raw_fd(),
+		0,
+	)? as *mut u8;
+	let private = util::mmap(
+		std::ptr::null_mut(),
+		4096,
+		prot,
+		libc::MAP_PRIVATE,
+		file.as_raw_fd(),
+		0,
+	)? as *mut u8;
+	let res = (|| {
+		log!("Page out modified pages");
+		unsafe {
+			*shared = b'a';
+			*shared.add(4096) = b'b';
+			*private.add(2) = b'p';
+		}
+		let res = unsafe { libc::madvise(shared as _, 8192, libc::MADV_PAGEOUT) };
+		test_assert_eq!(res, 0);
+		// Modifications are written back before the pages are evicted
+		let mut buf = [0u8; 1];
+		file.read_exact_at(&mut buf, 4096)?;
+		test_assert_eq!(&buf, b"b");
+		// Pages are reloaded on the next access
+		test_assert_eq!(
+			unsafe { (*shared, *shared.add(1), *shared.add(4096)) },
+			(b'a', 1, b'b')
+		);
+		log!("Page out a private copy");
+		let res = unsafe { libc::madvise(private as _, 4096, libc::MADV_PAGEOUT) };
+		test_assert_eq!(res, 0);
+		test_assert_eq!(unsafe { *private.add(2) }, b'p');
+		log!("Cold pages");
+		unsafe {
+			*shared.add(1) = b'c';
+		}
+		let res = unsafe { libc::madvise(shared as _, 8192, libc::MADV_COLD) };
+		test_assert_eq!(res, 0);
+		test_assert_eq!(unsafe { *shared.add(1) }, b'c');
+		file.read_exact_at(&mut buf, 1)?;
+		test_assert_eq!(&buf, b"c");
+		log!("Invalid ranges");
+		let res = unsafe { libc::madvise(shared.add(1) as _, 4096, libc::MADV_PAGEOUT) };
+		test_assert_eq!(res, -1);
+		test_assert_eq!(
+			io::Error::last_os_error().raw_os_error(),
+			Some(libc::EINVAL)
+		);
+		util::munmap(unsafe { shared.add(4096) } as _, 4096)?;
+		let res = unsafe { libc::madvise(shared as _, 8192, libc::MADV_COLD) };
+		test_assert_eq!(res, -1);
+		test_assert_eq!(
+			io::Error::last_os_error().raw_os_error(),
+			Some(libc::ENOMEM)
+		);
+		log!("Advise through a pidfd");
+		let pidfd =
+			unsafe { libc::syscall(libc::SYS_pidfd_open, libc::getpid(), 0) } as libc::c_int;
+		test_assert!(pidfd >= 0);
+		let pidfd = unsafe { std::os::fd::OwnedFd::from_raw_fd(pidfd) };
+		let iov = [libc::iovec {
+			iov_base: shared as _,
+			iov_len: 4096,
+		}];
+		let process_madvise = |advice: c_int, flags: libc::c_uint| unsafe {
+			libc::syscall(
+				libc::SYS_process_madvise,
+				pidfd.as_raw_fd(),
+				iov.as_ptr(),
+				iov.len(),
+				advice,
+				flags,
+			)
+		};
+		test_assert_eq!(process_madvise(libc::MADV_PAGEOUT, 0), 4096);
+		test_assert_eq!(unsafe { *shared }, b'a');
+		test_assert_eq!(process_madvise(libc::MADV_DONTNEED, 0), -1);
+		test_assert_eq!(
+			io::Error::last_os_error().raw_os_error(),
+			Some(libc::EINVAL)
+		);
+		test_assert_eq!(process_madvise(libc::MADV_COLD, 1), -1);
+		test_assert_eq!(
+			io::Error::last_os_error().raw_os_error(),
+			Some(libc::EINVAL)
+		);
+		Ok(())
+	})();
+	unsafe {
+		libc::munmap(shared as _, 8192);
+		libc::munmap(private as _, 4096);
+	}
+	fs::remove_file("reclaim")?;
+	res
+}
+
+/// Not defined by the `libc` crate for musl.
+const LOOP_SET_FD: libc::c_ulong = 0x4c00;
+/// Not defined by the `libc` crate for musl.
+const LOOP_CLR_FD: libc::c_ulong = 0x4c01;
+/// Not defined by the `libc` crate for musl.
+const LOOP_SET_STATUS64: libc::c_ulong = 0x4c04;
+/// Not defined by the `libc` crate for musl.
+const LOOP_GET_STATUS64: libc::c_ulong = 0x4c05;
+/// Not defined by the `libc` crate for musl.
+const BLKGETSIZE64: libc::c_ulong =
+	0x80001272 | ((mem::size_of::<usize>() as libc::c_ulong) << 16);
+
+/// The status of a loop device. Not defined by the `libc` crate for musl.
+#[repr(C)]
+struct LoopInfo64 {
+	lo_device: u64,
+	lo_inode: u64,
+	lo_rdevice: u64,
+	lo_offset: u64,
+	lo_sizelimit: u64,
+	lo_number: u32,
+	lo_encrypt_type: u32,
+	lo_encrypt_key_size: u32,
+	lo_flags: u32,
+	lo_file_name: [u8; 64],
+	lo_crypt_name: [u8; 64],
+	lo_encrypt_key: [u8; 32],
+	lo_init: [u64; 2],
+}
+
+pub fn loop_device() -> TestResult {
+	// One sector and a half past the end are not exposed by the device
+	let content: Vec<u8> = (0..(8192 + 768u32)).map(|i| (i / 512) as u8).collect();
+	fs::write("/loop_backing", &content)?;
+	util::mknod("/loop_dev", libc::S_IFBLK | 0o600, 7, 0)?;
+	let res = (|| {
+		let dev = OpenOptions::new()
+			.read(true)
+			.write(true)
+			.open("/loop_dev")?;
+		let ioctl = |req: libc::c_ulong, arg: usize| unsafe {
+			libc::ioctl(dev.as_raw_fd(), req as _, arg)
+		};
+		let mut info: LoopInfo64 = unsafe { mem::zeroed() };
+		log!("Unbound device");
+		test_assert_eq!(ioctl(LOOP_GET_STATUS64, &mut info as *mut _ as _), -1);
+		test_assert_eq!(io::Error::last_os_error().raw_os_error(), Some(libc::ENXIO));
+		log!("Bind");
+		let backing = OpenOptions::new()
+			.read(true)
+			.write(true)
+			.open("/loop_backing")?;
+		test_assert_eq!(ioctl(LOOP_SET_FD, backing.as_raw_fd() as _), 0);
+		test_assert_eq!(ioctl(LOOP_SET_FD, backing.as_raw_fd() as _), -1);
+		test_assert_eq!(io::Error::last_os_error().raw_os_error(), Some(libc::EBUSY));
+		let mut size = 0u64;
+		test_assert_eq!(ioctl(BLKGETSIZE64, &mut size as *mut _ as _), 0);
+		test_assert_eq!(size, 8192);
+		log!("Status");
+		test_assert_eq!(ioctl(LOOP_GET_STATUS64, &mut info as *mut _ as _), 0);
+		test_assert_eq!(info.lo_inode, backing.metadata()?.ino());
+		test_assert_eq!((info.lo_offset, info.lo_number, info.lo_flags), (0, 0, 0));
+		test_assert!(info.lo_file_name.starts_with(b"/loop_backing\0"));
+		log!("Read and write through the device");
+		let mut buf = [0u8; 512];
+		dev.read_exact_at(&mut buf, 1024)?;
+		test_assert!(buf.iter().all(|b| *b == 2));
+		dev.write_all_at(b"hello", 512)?;
+		log!("Offset");
+		info.lo_offset = 4096;
+		test_assert_eq!(ioctl(LOOP_SET_STATUS64, &info as *const _ as _), 0);
+		test_assert_eq!(ioctl(BLKGETSIZE64, &mut size as *mut _ as _), 0);
+		test_assert_eq!(size, 4096);
+		dev.read_exact_at(&mut buf, 0)?;
+		test_assert!(buf.iter().all(|b| *b == 8));
+		info.lo_offset = 100;
+		test_assert_eq!(ioctl(LOOP_SET_STATUS64, &info as *const _ as _), -1);
+		test_assert_eq!(
+			io::Error::last_os_error().raw_os_error(),
+			Some(libc::EINVAL)
+		);
+		log!("Unbind");
+		test_assert_eq!(ioctl(LOOP_CLR_FD, 0), 0);
+		test_assert_eq!(ioctl(LOOP_CLR_FD, 0), -1);
+		test_assert_eq!(io::Error::last_os_error().raw_os_error(), Some(libc::ENXIO));
+		let mut expected = content.clone();
+		expected[512..517].copy_from_slice(b"hello");
+		test_assert!(fs::read("/loop_backing")? == expected);
+		log!("Bind a read-only file");
+		let backing = fs::File::open("/loop_backing")?;
+		test_assert_eq!(ioctl(LOOP_SET_FD, backing.as_raw_fd() as _), 0);
+		let res = dev.write_all_at(b"hello", 0);
+		test_assert_eq!(ioctl(LOOP_CLR_FD, 0), 0);
+		util::expect_errno(res, libc::EROFS)?;
+		Ok(())
+	})();
+	fs::remove_file("/loop_dev")?;
+	fs::remove_file("/loop_backing")?;
+	res
+}
+
+pub fn page_cache_umount() -> TestResult {
+	log!("Mount the root filesystem a second time");
+	let dev = util::stat("/")?.st_dev;
+	let (major, minor) = (libc::major(dev), libc::minor(dev));
+	util::mknod("/page_cache_dev", libc::S_IFBLK | 0o600, major, minor)?;
+	fs::create_dir("/page_cache_mnt")?;
+	let src = CString::new("/page_cache_dev")?;
+	let target = CString::new("/page_cache_mnt")?;
+	let ext2 = CString::new("ext2")?;
+	util::mount(&src, &target, &ext2, 0, std::ptr::null())?;
+	let res = (|| {
+		log!("Modify a file through the second mountpoint");
+		fs::write("/page_cache_mnt/page_cache", b"aaaa")?;
+		let file = OpenOptions::new()
+			.write(true)
+			.open("/page_cache_mnt/page_cache")?;
+		// The file is not extended, so the modification remains in the cache
+		file.write_at(b"bbbb", 0)?;
+		drop(file);
+		log!("Unmount");
+		util::umount(&target)?;
+		// Pages are cached per mountpoint, so the content is read from the filesystem again
+		test_assert_eq!(fs::read("/page_cache")?, b"bbbb");
+		Ok(())
+	})();
+	log!("Cleanup");
+	if res.is_err() {
+		let _ = util::umount(&target);
+	}
+	let _ = fs::remove_file("/page_cache");
+	fs::remove_dir("/page_cache_mnt")?;
+	fs::remove_file("/page_cache_dev")?;
+	res
+}
+
+pub fn superblock_state() -> TestResult {
+	log!("Read the superblock of the root filesystem");
+	let dev = util::stat("/")?.st_dev;
+	let (major, minor) = (libc::major(dev), libc::minor(dev));
+	util::mknod("/superblock_dev", libc::S_IFBLK | 0o600, major, minor)?;
+	let res = (|| {
+		let mut sb = [0u8; 1024];
+		fs::File::open("/superblock_dev")?.read_exact_at(&mut sb, 1024)?;
+		let u16_at = |off: usize| u16::from_le_bytes([sb[off], sb[off + 1]]);
+		let u32_at = |off: usize| u32::from_le_bytes(sb[off..off + 4].try_into().unwrap());
+		test_assert_eq!(u16_at(56), 0xef53);
+		log!("Mount time and count");
+		test_assert!(u32_at(44) > 0);
+		test_assert!(u16_at(52) >= 1);
+		log!("State");
+		// The filesystem is mounted in read-write, so it is not clean until unmounted
+		let state = u16_at(58);
+		test_assert_eq!(state & 1, 0);
+		test_assert_eq!(state & 2, 0);
+		log!("Last mount path");
+		let last_mounted = &sb[136..200];
+		let len = last_mounted.iter().position(|b| *b == 0).unwrap_or(64);
+		test_assert_eq!(&last_mounted[..len], b"/");
+		Ok(())
+	})();
+	fs::remove_file("/superblock_dev")?;
+	res
+}
+
+/// Calls `pivot_root` with the given paths.
+fn pivot_root_at(new_root: &str, put_old: &str) -> io::Result<()> {
+	let new_root = CString::new(new_root)?;
+	let put_old = CString::new(put_old)?;
+	let res = unsafe { libc::syscall(libc::SYS_pivot_root, new_root.as_ptr(), put_old.as_ptr()) };
+	if res >= 0 {
+		Ok(())
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+pub fn pivot_root() -> TestResult {
+	log!("Setup");
+	let src = CString::new("tmpfs")?;
+	let old = CString::new("/pivot")?;
+	let new = CString::new("/pivot/new")?;
+	fs::create_dir("/pivot")?;
+	util::mount(&src, &old, &src, 0, std::ptr::null())?;
+	fs::write("/pivot/old_marker", b"old")?;
+	fs::create_dir("/pivot/new")?;
+	util::mount(&src, &new, &src, 0, std::ptr::null())?;
+	fs::write("/pivot/new/new_marker", b"new")?;
+	fs::create_dir("/pivot/new/old")?;
+	fs::create_dir("/pivot/new/dir")?;
+
+	// Pivot in a child process whose root directory is the root of the first mount, so that other
+	// processes are not affected
+	let res = util::in_child(|| {
+		util::chroot("/pivot")?;
+		std::env::set_current_dir("/")?;
+		log!("Unprivileged");
+		let res = unprivileged(|| pivot_root_at("/new", "/new/old"))?;
+		util::expect_errno(res, libc::EPERM)?;
+		log!("New root is not a mountpoint");
+		util::expect_errno(pivot_root_at("/new/dir", "/new/dir"), libc::EINVAL)?;
+		log!("Previous root put on itself");
+		util::expect_errno(pivot_root_at("/new", "/"), libc::EBUSY)?;
+		log!("Pivot");
+		pivot_root_at("/new", "/new/old")?;
+		test_assert_eq!(fs::read("/new_marker")?, b"new");
+		test_assert_eq!(fs::read("/old/old_marker")?, b"old");
+		// The working directory was the previous root, it is moved to the new one
+		test_assert_eq!(fs::read("new_marker")?, b"new");
+		log!("Detach the previous root");
+		util::umount(&CString::new("/old")?)?;
+		test_assert!(!Path::new("/old/old_marker").exists());
+		Ok(())
+	});
+
+	log!("Cleanup");
+	util::umount(&new)?;
+	util::umount(&old)?;
+	fs::remove_dir("/pivot")?;
+	res
+}
@@ -23,8 +23,13 @@
 use crate::util::TestResult;
 use std::process::exit;
 
+mod errno;
+mod event;
 mod filesystem;
+mod process;
 mod procfs;
+mod socket;
+mod time;
 mod util;
 
 /*
@@ -56,17 +61,37 @@ const TESTS: &[TestSuite] = &[
 				desc: "Create, remove and modify the properties of a single file",
 				start: filesystem::basic,
 			},
+			Test {
+				name: "past_eof",
+				desc: "Read and write past the end of a file",
+				start: filesystem::past_eof,
+			},
 			// TODO umask
 			Test {
 				name: "directories",
 				desc: "Create, remove and modify the properties directories",
 				start: filesystem::directories,
 			},
+			Test {
+				name: "dirents",
+				desc: "Read the types of directory entries",
+				start: filesystem::dirents,
+			},
 			Test {
 				name: "dir_perms",
 				desc: "Test directory permissions",
 				start: filesystem::dir_perms,
 			},
+			Test {
+				name: "groups",
+				desc: "Access files through supplementary groups",
+				start: filesystem::groups,
+			},
+			Test {
+				name: "suid",
+				desc: "Clear the SUID and SGID bits on write and change of owner",
+				start: filesystem::suid,
+			},
 			Test {
 				name: "hardlinks",
 				desc: "Test hard links",
@@ -82,21 +107,262 @@ const TESTS: &[TestSuite] = &[
 			// TODO try to fill the filesystem
 			// TODO mount/umount (procfs and tmpfs. check /proc/mounts too)
 			// TODO mount/umount another real filesystem
-			// FIXME
-			/*Test {
+			Test {
 				name: "rename",
 				desc: "Test renaming files",
 				start: filesystem::rename,
-			},*/
+			},
+			Test {
+				name: "rmdir",
+				desc: "Refuse to remove dot entries and mountpoints, replace directories",
+				start: filesystem::rmdir,
+			},
+			Test {
+				name: "file_handles",
+				desc: "Open files through handles",
+				start: filesystem::file_handles,
+			},
+			Test {
+				name: "lookup_cache",
+				desc: "Test lookups of files that are created and removed",
+				start: filesystem::lookup_cache,
+			},
+			Test {
+				name: "exec_cache",
+				desc: "Execute files whose path changes",
+				start: filesystem::exec_cache,
+			},
+			Test {
+				name: "truncate",
+				desc: "Shrink files and free their blocks",
+				start: filesystem::truncate,
+			},
+			Test {
+				name: "large_files",
+				desc: "Get the status of, truncate and send files with 64 bits sizes",
+				start: filesystem::large_files,
+			},
+			Test {
+				name: "unlinked_open",
+				desc: "Use a file after its last link has been removed",
+				start: filesystem::unlinked_open,
+			},
+			Test {
+				name: "sparse",
+				desc: "Allocate blocks only for written parts of files",
+				start: filesystem::sparse,
+			},
+			Test {
+				name: "chroot",
+				desc: "Test that paths cannot escape the root directory",
+				start: filesystem::chroot,
+			},
+			Test {
+				name: "cwd",
+				desc: "Change and get the working directory",
+				start: filesystem::cwd,
+			},
+			Test {
+				name: "leases",
+				desc: "Test file leases",
+				start: filesystem::leases,
+			},
 			Test {
 				name: "fifo",
 				desc: "Test FIFO files",
 				start: filesystem::fifo,
 			},
+			Test {
+				name: "ioctl",
+				desc: "Use the ioctl requests common to all files",
+				start: filesystem::ioctl,
+			},
+			Test {
+				name: "mount_options",
+				desc: "Mount a filesystem with specific options",
+				start: filesystem::mount_options,
+			},
+			Test {
+				name: "page_cache",
+				desc: "Read, map and synchronize cached file content",
+				start: filesystem::page_cache,
+			},
+			Test {
+				name: "file_mappings",
+				desc: "Map files privately and shared between processes",
+				start: filesystem::file_mappings,
+			},
+			Test {
+				name: "reclaim",
+				desc: "Reclaim pages of file mappings with madvise and process_madvise",
+				start: filesystem::reclaim,
+			},
+			Test {
+				name: "loop_device",
+				desc: "Expose a regular file as a block device",
+				start: filesystem::loop_device,
+			},
+			Test {
+				name: "page_cache_umount",
+				desc: "Write cached file content back when unmounting",
+				start: filesystem::page_cache_umount,
+			},
+			Test {
+				name: "superblock_state",
+				desc: "Check the state of the root filesystem is recorded in its superblock",
+				start: filesystem::superblock_state,
+			},
+			Test {
+				name: "pivot_root",
+				desc: "Change the root mount of a process",
+				start: filesystem::pivot_root,
+			},
 			// TODO file socket (including in tmpfs)
 			// TODO check /dev/* contents
 		],
 	},
+	TestSuite {
+		name: "errno",
+		desc: "Errors reported by system calls",
+		tests: &[
+			Test {
+				name: "paths",
+				desc: "Resolve paths through missing files and non-directories",
+				start: errno::paths,
+			},
+			Test {
+				name: "path_limits",
+				desc: "Resolve paths with long names and many symbolic links",
+				start: errno::path_limits,
+			},
+			Test {
+				name: "open",
+				desc: "Open files with an access incompatible with their type",
+				start: errno::open,
+			},
+			Test {
+				name: "pipes",
+				desc: "Seek on pipes",
+				start: errno::pipes,
+			},
+			Test {
+				name: "nonblock",
+				desc: "Non-blocking I/O on pipes",
+				start: errno::nonblock,
+			},
+			Test {
+				name: "memory",
+				desc: "Map files and memory with invalid arguments",
+				start: errno::memory,
+			},
+			Test {
+				name: "readonly",
+				desc: "Modify a read-only filesystem",
+				start: errno::readonly,
+			},
+			Test {
+				name: "mount",
+				desc: "Mount with an invalid source or target",
+				start: errno::mount,
+			},
+		],
+	},
+	TestSuite {
+		name: "process",
+		desc: "Process creation",
+		tests: &[
+			Test {
+				name: "clone3_args",
+				desc: "Pass invalid arguments to clone3",
+				start: process::clone3_args,
+			},
+			Test {
+				name: "clone3_pidfd",
+				desc: "Create a child with clone3 and get a PID file descriptor",
+				start: process::clone3_pidfd,
+			},
+			Test {
+				name: "getcpu",
+				desc: "Get the CPU and NUMA node of the current process",
+				start: process::getcpu,
+			},
+			Test {
+				name: "sched_yield",
+				desc: "Yield to processes with the same priority",
+				start: process::sched_yield,
+			},
+			Test {
+				name: "context_switch",
+				desc: "Measure the cost of switching between processes",
+				start: process::context_switch,
+			},
+			Test {
+				name: "personality",
+				desc: "Emulate older kernels and other architectures in uname",
+				start: process::personality_uname,
+			},
+			Test {
+				name: "fault_signals",
+				desc: "Receive the information of signals raised by CPU exceptions",
+				start: process::fault_signals,
+			},
+			Test {
+				name: "mprotect",
+				desc: "Change the protection of a part of a mapping",
+				start: process::mprotect_split,
+			},
+			Test {
+				name: "thread_group",
+				desc: "Terminate every thread of a thread group",
+				start: process::thread_group,
+			},
+			Test {
+				name: "clone_fs",
+				desc: "Share and unshare filesystem information and file descriptors",
+				start: process::clone_fs,
+			},
+			Test {
+				name: "waitpid",
+				desc: "Wait for children selected by PID, process group or kind",
+				start: process::waitpid,
+			},
+			Test {
+				name: "kill_group",
+				desc: "Send signals to process groups",
+				start: process::kill_group,
+			},
+			Test {
+				name: "pidfd",
+				desc: "Signal, poll and wait for a process through a pidfd",
+				start: process::pidfd,
+			},
+			Test {
+				name: "futex",
+				desc: "Wait on a futex with and without timeout, then wake it up",
+				start: process::futex_wait_wake,
+			},
+			Test {
+				name: "futex_requeue",
+				desc: "Requeue processes waiting on a futex to another, then wake them up",
+				start: process::futex_requeue_wake,
+			},
+			Test {
+				name: "clear_child_tid",
+				desc: "Clear the thread ID of a process and wake up waiters when it exits",
+				start: process::clear_child_tid,
+			},
+			Test {
+				name: "ksm",
+				desc: "Merge identical pages, then write to them",
+				start: process::ksm,
+			},
+			Test {
+				name: "kcmp",
+				desc: "Compare kernel resources of processes",
+				start: process::kcmp_order,
+			},
+		],
+	},
 	// TODO fork/clone (threads)
 	// TODO signals (handlers and masking)
 	// TODO ELF files (execve)
@@ -114,17 +380,21 @@ const TESTS: &[TestSuite] = &[
 				desc: "Mount the procfs",
 				start: procfs::mount,
 			},
+			Test {
+				name: "/proc/self/mounts",
+				desc: "Read the mount tables",
+				start: procfs::mounts,
+			},
 			Test {
 				name: "/proc/self/cwd",
 				desc: "/proc/self/cwd",
 				start: procfs::cwd,
 			},
-			// TODO: not yet implemented
-			/*Test {
+			Test {
 				name: "/proc/self/exe",
 				desc: "/proc/self/exe",
 				start: procfs::exe,
-			},*/
+			},
 			Test {
 				name: "/proc/self/cmdline",
 				desc: "/proc/self/cmdline",
@@ -135,9 +405,167 @@ const TESTS: &[TestSuite] = &[
 				desc: "/proc/self/environ",
 				start: procfs::environ,
 			},
+			Test {
+				name: "/proc/self/timens_offsets",
+				desc: "Set offsets of a time namespace",
+				start: procfs::timens_offsets,
+			},
+			Test {
+				name: "/proc/self/fd",
+				desc: "/proc/self/fd",
+				start: procfs::fd,
+			},
+			Test {
+				name: "/proc/self/map_files",
+				desc: "/proc/self/map_files",
+				start: procfs::map_files,
+			},
+			Test {
+				name: "/proc/self/maps",
+				desc: "List the memory mappings of processes",
+				start: procfs::maps,
+			},
+			Test {
+				name: "/proc/pressure/memory",
+				desc: "Read the memory pressure level",
+				start: procfs::pressure,
+			},
+			Test {
+				name: "/proc/consoles",
+				desc: "List and configure the active consoles",
+				start: procfs::consoles,
+			},
+			Test {
+				name: "kernel threads",
+				desc: "List kernel threads",
+				start: procfs::kthreads,
+			},
+			Test {
+				name: "kernel information",
+				desc: "Read the kernel version, command line and configuration",
+				start: procfs::kernel_info,
+			},
+			Test {
+				name: "/proc/iomem",
+				desc: "Read the physical memory map",
+				start: procfs::iomem,
+			},
+			Test {
+				name: "boot time",
+				desc: "Read the boot ID, the uptime and the start time of processes",
+				start: procfs::boot_time,
+			},
+			Test {
+				name: "/proc/self magic links",
+				desc: "Execute the same path through magic links from different processes",
+				start: procfs::exec_self,
+			},
 			// TODO /proc/self/stat
 		],
 	},
+	TestSuite {
+		name: "event",
+		desc: "Event notification through file descriptors",
+		tests: &[
+			Test {
+				name: "eventfd",
+				desc: "Read and write an event counter",
+				start: event::eventfd,
+			},
+			Test {
+				name: "signalfd",
+				desc: "Read blocked signals through a signalfd",
+				start: event::signalfd,
+			},
+			Test {
+				name: "select",
+				desc: "Wait for file descriptors with select and pselect",
+				start: event::select,
+			},
+			Test {
+				name: "poll",
+				desc: "Poll ignored, invalid and hung up file descriptors",
+				start: event::poll,
+			},
+			Test {
+				name: "ppoll",
+				desc: "Sleep on file descriptors with ppoll",
+				start: event::ppoll,
+			},
+			Test {
+				name: "fanotify",
+				desc: "Poll and read the events of a fanotify group",
+				start: event::fanotify,
+			},
+			Test {
+				name: "fanotify_events",
+				desc: "Receive access and modification events through fanotify",
+				start: event::fanotify_events,
+			},
+			Test {
+				name: "fanotify_perm",
+				desc: "Allow and deny opening a file through fanotify permission events",
+				start: event::fanotify_perm,
+			},
+			Test {
+				name: "inotify",
+				desc: "Receive creation, modification, move and deletion events through inotify",
+				start: event::inotify,
+			},
+			Test {
+				name: "inotify_rm_watch",
+				desc: "Remove inotify watches explicitly and after a oneshot event",
+				start: event::inotify_rm_watch,
+			},
+		],
+	},
+	TestSuite {
+		name: "socket",
+		desc: "Sockets",
+		tests: &[
+			Test {
+				name: "peercred",
+				desc: "Get the credentials of the peer with SO_PEERCRED",
+				start: socket::peercred,
+			},
+			Test {
+				name: "passcred",
+				desc: "Pass credentials with SCM_CREDENTIALS",
+				start: socket::passcred,
+			},
+			Test {
+				name: "tcp_accept",
+				desc: "Accept a TCP connection on the loopback",
+				start: socket::tcp_accept,
+			},
+		],
+	},
+	TestSuite {
+		name: "time",
+		desc: "Clocks and timers",
+		tests: &[
+			Test {
+				name: "coarse_clocks",
+				desc: "Read coarse clocks through the vDSO",
+				start: time::coarse_clocks,
+			},
+			Test {
+				name: "posix_timer",
+				desc: "Arm and disarm a per-process timer",
+				start: time::posix_timer,
+			},
+			Test {
+				name: "timerfd",
+				desc: "Wait for the expiration of a timer through a file descriptor",
+				start: time::timerfd,
+			},
+			Test {
+				name: "namespace_abstime",
+				desc: "Arm timers with an absolute deadline in a time namespace",
+				start: time::namespace_abstime,
+			},
+		],
+	},
 	// TODO install required commands
 	/*TestSuite {
 		name: "command",
@@ -0,0 +1,1254 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Process creation testing.
+
+use crate::{
+	log, test_assert, test_assert_eq, util,
+	util::{TestError, TestResult},
+};
+use std::{
+	env, fs, io,
+	mem::size_of,
+	os::fd::AsRawFd,
+	ptr::{null, null_mut},
+	sync::{
+		atomic::{AtomicI32, AtomicU32, AtomicUsize, Ordering::Relaxed},
+		mpsc,
+	},
+	thread,
+	time::{Duration, Instant},
+};
+
+/// The arguments of `clone3`.
+#[repr(C)]
+#[derive(Default)]
+struct CloneArgs {
+	flags: u64,
+	pidfd: u64,
+	child_tid: u64,
+	parent_tid: u64,
+	exit_signal: u64,
+	stack: u64,
+	stack_size: u64,
+	tls: u64,
+	set_tid: u64,
+	set_tid_size: u64,
+	cgroup: u64,
+}
+
+/// Calls `clone3` with the given arguments, of size `size`.
+///
+/// In the child process, the function returns `0`.
+fn clone3(args: *const u8, size: usize) -> io::Result<libc::pid_t> {
+	let res = unsafe { libc::syscall(libc::SYS_clone3, args, size) };
+	if res < 0 {
+		return Err(io::Error::last_os_error());
+	}
+	Ok(res as _)
+}
+
+pub fn clone3_args() -> TestResult {
+	log!("Invalid size");
+	let args = CloneArgs {
+		exit_signal: libc::SIGCHLD as _,
+		..Default::default()
+	};
+	util::expect_errno(clone3(&args as *const _ as _, 32), libc::EINVAL)?;
+	log!("Unknown fields");
+	let mut buf = [0u8; size_of::<CloneArgs>() + 8];
+	buf[size_of::<CloneArgs>()] = 1;
+	util::expect_errno(clone3(buf.as_ptr(), buf.len()), libc::E2BIG)?;
+	log!("Exit signal in flags");
+	let args = CloneArgs {
+		flags: libc::SIGCHLD as _,
+		..Default::default()
+	};
+	util::expect_errno(
+		clone3(&args as *const _ as _, size_of::<CloneArgs>()),
+		libc::EINVAL,
+	)?;
+	log!("Stack without size");
+	let args = CloneArgs {
+		stack: 0x1000,
+		..Default::default()
+	};
+	util::expect_errno(
+		clone3(&args as *const _ as _, size_of::<CloneArgs>()),
+		libc::EINVAL,
+	)?;
+	Ok(())
+}
+
+pub fn clone3_pidfd() -> TestResult {
+	log!("Create child");
+	let mut pidfd: libc::c_int = -1;
+	let mut parent_tid: libc::pid_t = 0;
+	let args = CloneArgs {
+		flags: (libc::CLONE_PIDFD | libc::CLONE_PARENT_SETTID) as _,
+		pidfd: &mut pidfd as *mut _ as usize as _,
+		parent_tid: &mut parent_tid as *mut _ as usize as _,
+		exit_signal: libc::SIGCHLD as _,
+		..Default::default()
+	};
+	// Use the size of the first version of the structure
+	let pid = clone3(&args as *const _ as _, 64)?;
+	if pid == 0 {
+		unsafe {
+			libc::_exit(42);
+		}
+	}
+	test_assert_eq!(parent_tid, pid);
+	test_assert!(pidfd >= 0);
+	log!("Wait for child");
+	let mut status = 0;
+	let res = unsafe { libc::waitpid(pid, &mut status, 0) };
+	test_assert_eq!(res, pid);
+	test_assert!(libc::WIFEXITED(status));
+	test_assert_eq!(libc::WEXITSTATUS(status), 42);
+	unsafe {
+		libc::close(pidfd);
+	}
+	Ok(())
+}
+
+/// Runs `f` in a child process created by `clone3` with the given `flags`, then waits for it.
+fn clone_run<F: FnOnce() -> TestResult>(flags: libc::c_int, f: F) -> TestResult {
+	let args = CloneArgs {
+		flags: flags as _,
+		exit_signal: libc::SIGCHLD as _,
+		..Default::default()
+	};
+	let pid = clone3(&args as *const _ as _, size_of::<CloneArgs>())?;
+	if pid == 0 {
+		let code = match f() {
+			Ok(()) => 0,
+			Err(TestError(e)) => {
+				eprintln!("{e}");
+				1
+			}
+		};
+		unsafe {
+			libc::_exit(code);
+		}
+	}
+	let mut status = 0;
+	test_assert_eq!(unsafe { libc::waitpid(pid, &mut status, 0) }, pid);
+	test_assert!(libc::WIFEXITED(status));
+	test_assert_eq!(libc::WEXITSTATUS(status), 0);
+	Ok(())
+}
+
+pub fn clone_fs() -> TestResult {
+	fs::create_dir_all("clone_fs/a")?;
+	fs::create_dir_all("clone_fs/b")?;
+	// Run in a child so that the working directory of the test process is not changed
+	let res = util::in_child(|| {
+		env::set_current_dir("clone_fs")?;
+		let dir = env::current_dir()?;
+		log!("Copy filesystem information");
+		clone_run(0, || {
+			env::set_current_dir("a")?;
+			unsafe {
+				libc::umask(0o077);
+			}
+			Ok(())
+		})?;
+		test_assert!(env::current_dir()? == dir);
+		test_assert!(unsafe { libc::umask(0o022) } != 0o077);
+		log!("Share filesystem information");
+		clone_run(libc::CLONE_FS, || {
+			env::set_current_dir("a")?;
+			unsafe {
+				libc::umask(0o077);
+			}
+			Ok(())
+		})?;
+		test_assert_eq!(env::current_dir()?, dir.join("a"));
+		test_assert_eq!(unsafe { libc::umask(0o022) }, 0o077);
+		log!("Unshare filesystem information");
+		clone_run(libc::CLONE_FS, || {
+			util::unshare(libc::CLONE_FS)?;
+			env::set_current_dir("../b")?;
+			Ok(())
+		})?;
+		test_assert_eq!(env::current_dir()?, dir.join("a"));
+		log!("Unshare file descriptors");
+		let file = fs::File::open(".")?;
+		let fd = file.as_raw_fd();
+		clone_run(libc::CLONE_FILES, || {
+			util::unshare(libc::CLONE_FILES)?;
+			test_assert_eq!(unsafe { libc::close(fd) }, 0);
+			Ok(())
+		})?;
+		test_assert!(unsafe { libc::fcntl(fd, libc::F_GETFD) } >= 0);
+		Ok(())
+	});
+	log!("Cleanup");
+	fs::remove_dir_all("clone_fs")?;
+	res
+}
+
+/// Waits for a child process, returning its PID along with its exit status.
+fn wait(pid: libc::pid_t, options: libc::c_int) -> io::Result<(libc::pid_t, libc::c_int)> {
+	let mut status = 0;
+	let res = unsafe { libc::waitpid(pid, &mut status, options) };
+	if res < 0 {
+		return Err(io::Error::last_os_error());
+	}
+	Ok((res, libc::WEXITSTATUS(status)))
+}
+
+/// Creates a child process which exits with `code`. If `clone` is set, the child does not notify
+/// its parent when terminating.
+fn spawn_exit(code: libc::c_int, clone: bool) -> io::Result<libc::pid_t> {
+	let args = CloneArgs {
+		exit_signal: if clone { 0 } else { libc::SIGCHLD as _ },
+		..Default::default()
+	};
+	let pid = clone3(&args as *const _ as _, size_of::<CloneArgs>())?;
+	if pid == 0 {
+		unsafe {
+			libc::_exit(code);
+		}
+	}
+	Ok(pid)
+}
+
+pub fn waitpid() -> TestResult {
+	// Run in a child so that the children of the test process are not waited for
+	util::in_child(|| {
+		log!("Wait without children");
+		util::expect_errno(wait(-1, 0), libc::ECHILD)?;
+		log!("Wait for a process that is not a child");
+		util::expect_errno(wait(unsafe { libc::getppid() }, 0), libc::ECHILD)?;
+		log!("Wait for a process group");
+		let pid = unsafe { libc::fork() };
+		test_assert!(pid >= 0);
+		if pid == 0 {
+			thread::sleep(Duration::from_millis(100));
+			unsafe {
+				libc::_exit(3);
+			}
+		}
+		test_assert_eq!(unsafe { libc::setpgid(pid, pid) }, 0);
+		// The child is not in the group of the current process anymore
+		util::expect_errno(wait(0, libc::WNOHANG), libc::ECHILD)?;
+		test_assert_eq!(wait(-pid, 0)?, (pid, 3));
+		log!("Wait for a clone child");
+		let pid = spawn_exit(4, true)?;
+		util::expect_errno(wait(pid, 0), libc::ECHILD)?;
+		util::expect_errno(wait(-1, libc::WNOHANG), libc::ECHILD)?;
+		test_assert_eq!(wait(-1, libc::__WCLONE)?, (pid, 4));
+		log!("Wait for all children");
+		let clone_pid = spawn_exit(5, true)?;
+		let pid = spawn_exit(6, false)?;
+		util::expect_errno(wait(pid, libc::__WCLONE), libc::ECHILD)?;
+		let mut reaped = [wait(-1, libc::__WALL)?, wait(-1, libc::__WALL)?];
+		reaped.sort();
+		let mut expected = [(clone_pid, 5), (pid, 6)];
+		expected.sort();
+		test_assert_eq!(reaped, expected);
+		util::expect_errno(wait(-1, libc::__WALL), libc::ECHILD)?;
+		log!("Wait for a child of another thread");
+		// The thread stays alive until the child has been waited for, since the children of a
+		// terminated thread are attached to the init process
+		let (pid_tx, pid_rx) = mpsc::channel();
+		let (done_tx, done_rx) = mpsc::channel::<()>();
+		let thread = thread::spawn(move || {
+			pid_tx.send(spawn_exit(7, false)).unwrap();
+			let _ = done_rx.recv();
+		});
+		let res = (|| {
+			let pid = pid_rx.recv().unwrap()?;
+			util::expect_errno(wait(pid, libc::__WNOTHREAD), libc::ECHILD)?;
+			test_assert_eq!(wait(pid, 0)?, (pid, 7));
+			Ok(())
+		})();
+		drop(done_tx);
+		thread.join().unwrap();
+		res
+	})
+}
+
+/// Runs `f` in a child process while another thread of the child is blocked, then returns the
+/// status of the child.
+fn with_blocked_thread<F: FnOnce() + Send + 'static>(
+	f: F,
+	block_leader: bool,
+) -> io::Result<libc::c_int> {
+	let pid = unsafe { libc::fork() };
+	if pid < 0 {
+		return Err(io::Error::last_os_error());
+	}
+	if pid == 0 {
+		let block = || loop {
+			unsafe {
+				libc::pause();
+			}
+		};
+		if block_leader {
+			thread::spawn(f);
+			block();
+		} else {
+			thread::spawn(block);
+			f();
+		}
+		unsafe {
+			libc::_exit(0);
+		}
+	}
+	let mut status = 0;
+	let res = unsafe { libc::waitpid(pid, &mut status, 0) };
+	if res < 0 {
+		return Err(io::Error::last_os_error());
+	}
+	Ok(status)
+}
+
+pub fn thread_group() -> TestResult {
+	log!("Thread IDs");
+	let pid = unsafe { libc::getpid() };
+	let tid = unsafe { libc::syscall(libc::SYS_gettid) } as libc::pid_t;
+	test_assert_eq!(tid, pid);
+	let (thread_pid, thread_tid) = thread::spawn(|| unsafe {
+		(
+			libc::getpid(),
+			libc::syscall(libc::SYS_gettid) as libc::pid_t,
+		)
+	})
+	.join()
+	.unwrap();
+	test_assert_eq!(thread_pid, pid);
+	test_assert!(thread_tid != tid);
+	log!("Exit the group from the leader");
+	let status = with_blocked_thread(
+		|| unsafe {
+			libc::syscall(libc::SYS_exit_group, 7);
+		},
+		false,
+	)?;
+	test_assert!(libc::WIFEXITED(status));
+	test_assert_eq!(libc::WEXITSTATUS(status), 7);
+	log!("Exit the group from another thread");
+	let status = with_blocked_thread(
+		|| unsafe {
+			libc::syscall(libc::SYS_exit_group, 8);
+		},
+		true,
+	)?;
+	test_assert!(libc::WIFEXITED(status));
+	test_assert_eq!(libc::WEXITSTATUS(status), 8);
+	log!("Fatal signal on another thread");
+	let status = with_blocked_thread(
+		|| unsafe {
+			let tid = libc::syscall(libc::SYS_gettid);
+			libc::syscall(libc::SYS_tkill, tid, libc::SIGUSR1);
+		},
+		true,
+	)?;
+	test_assert!(libc::WIFSIGNALED(status));
+	test_assert_eq!(libc::WTERMSIG(status), libc::SIGUSR1);
+	log!("Exit the leader before the other threads");
+	let pid = unsafe { libc::fork() };
+	test_assert!(pid >= 0);
+	if pid == 0 {
+		thread::spawn(|| {
+			thread::sleep(Duration::from_millis(500));
+			unsafe {
+				libc::syscall(libc::SYS_exit_group, 9);
+			}
+		});
+		unsafe {
+			libc::syscall(libc::SYS_exit, 3);
+		}
+	}
+	thread::sleep(Duration::from_millis(100));
+	let mut status = 0;
+	// The group cannot be waited for as long as one of its threads is running
+	test_assert_eq!(unsafe { libc::waitpid(pid, &mut status, libc::WNOHANG) }, 0);
+	test_assert_eq!(unsafe { libc::waitpid(pid, &mut status, 0) }, pid);
+	test_assert!(libc::WIFEXITED(status));
+	test_assert_eq!(libc::WEXITSTATUS(status), 9);
+	Ok(())
+}
+
+/// Creates a child blocked until it receives a signal, in the process group `pgid`.
+///
+/// If `pgid` is zero, the child creates its own group.
+fn blocked_child(pgid: libc::pid_t) -> io::Result<libc::pid_t> {
+	let pid = unsafe { libc::fork() };
+	if pid < 0 {
+		return Err(io::Error::last_os_error());
+	}
+	if pid == 0 {
+		loop {
+			unsafe {
+				libc::pause();
+			}
+		}
+	}
+	if unsafe { libc::setpgid(pid, pgid) } < 0 {
+		return Err(io::Error::last_os_error());
+	}
+	Ok(pid)
+}
+
+pub fn kill_group() -> TestResult {
+	log!("Create a process group");
+	let leader = blocked_child(0)?;
+	let member = blocked_child(leader)?;
+	let res = (|| {
+		log!("Signal a process that is not a group leader as a group");
+		let res = unsafe { libc::kill(-member, 0) };
+		util::expect_errno(
+			if res < 0 {
+				Err(io::Error::last_os_error())
+			} else {
+				Ok(())
+			},
+			libc::ESRCH,
+		)?;
+		log!("Signal the group after its leader has terminated");
+		unsafe {
+			libc::kill(leader, libc::SIGKILL);
+			libc::waitpid(leader, null_mut(), 0);
+		}
+		test_assert_eq!(unsafe { libc::kill(-leader, libc::SIGTERM) }, 0);
+		let mut status = 0;
+		test_assert_eq!(unsafe { libc::waitpid(member, &mut status, 0) }, member);
+		test_assert!(libc::WIFSIGNALED(status));
+		test_assert_eq!(libc::WTERMSIG(status), libc::SIGTERM);
+		Ok(())
+	})();
+	unsafe {
+		libc::kill(leader, libc::SIGKILL);
+		libc::kill(member, libc::SIGKILL);
+		libc::waitpid(leader, null_mut(), 0);
+		libc::waitpid(member, null_mut(), 0);
+	}
+	res
+}
+
+pub fn pidfd() -> TestResult {
+	log!("Create child");
+	let pid = unsafe { libc::fork() };
+	test_assert!(pid >= 0);
+	if pid == 0 {
+		loop {
+			unsafe {
+				libc::pause();
+			}
+		}
+	}
+	let fd = unsafe { libc::syscall(libc::SYS_pidfd_open, pid, 0) } as libc::c_int;
+	test_assert!(fd >= 0);
+	let mut pfd = libc::pollfd {
+		fd,
+		events: libc::POLLIN,
+		revents: 0,
+	};
+	test_assert_eq!(unsafe { libc::poll(&mut pfd, 1, 0) }, 0);
+	log!("Send signal");
+	let res = unsafe {
+		libc::syscall(
+			libc::SYS_pidfd_send_signal,
+			fd,
+			libc::SIGKILL,
+			std::ptr::null::<libc::siginfo_t>(),
+			0,
+		)
+	};
+	test_assert_eq!(res, 0);
+	log!("Poll for exit");
+	test_assert_eq!(unsafe { libc::poll(&mut pfd, 1, -1) }, 1);
+	test_assert_eq!(pfd.revents, libc::POLLIN);
+	log!("Wait through the pidfd");
+	let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+	// `P_PIDFD`
+	let res = unsafe { libc::waitid(3, fd as _, &mut info, libc::WEXITED) };
+	test_assert_eq!(res, 0);
+	test_assert_eq!(info.si_signo, libc::SIGCHLD);
+	test_assert_eq!(info.si_code, libc::CLD_KILLED);
+	test_assert_eq!(unsafe { info.si_pid() }, pid);
+	test_assert_eq!(unsafe { info.si_status() }, libc::SIGKILL);
+	log!("Send signal to waited process");
+	let res = unsafe { libc::syscall(libc::SYS_pidfd_send_signal, fd, 0, 0, 0) };
+	util::expect_errno(
+		if res < 0 {
+			Err(io::Error::last_os_error())
+		} else {
+			Ok(())
+		},
+		libc::ESRCH,
+	)?;
+	unsafe {
+		libc::close(fd);
+	}
+	Ok(())
+}
+
+pub fn getcpu() -> TestResult {
+	log!("Get CPU and node");
+	let mut cpu: libc::c_uint = libc::c_uint::MAX;
+	let mut node: libc::c_uint = libc::c_uint::MAX;
+	let res = unsafe {
+		libc::syscall(
+			libc::SYS_getcpu,
+			&mut cpu,
+			&mut node,
+			null_mut::<libc::c_void>(),
+		)
+	};
+	test_assert_eq!(res, 0);
+	// Only one CPU and one NUMA node are supported
+	test_assert_eq!((cpu, node), (0, 0));
+	log!("Get CPU through the libc");
+	test_assert_eq!(unsafe { libc::sched_getcpu() }, cpu as libc::c_int);
+	log!("Null pointers");
+	let res = unsafe {
+		libc::syscall(
+			libc::SYS_getcpu,
+			null_mut::<libc::c_uint>(),
+			null_mut::<libc::c_uint>(),
+			null_mut::<libc::c_void>(),
+		)
+	};
+	test_assert_eq!(res, 0);
+	Ok(())
+}
+
+/// Sets the personality of the current process, returning the previous one.
+fn personality(persona: libc::c_ulong) -> io::Result<libc::c_int> {
+	let res = unsafe { libc::personality(persona) };
+	if res < 0 {
+		return Err(io::Error::last_os_error());
+	}
+	Ok(res)
+}
+
+pub fn personality_uname() -> TestResult {
+	/// Value to query the current personality without changing it.
+	const QUERY: libc::c_ulong = 0xffffffff;
+	/// Not defined by the `libc` crate for musl.
+	const PER_LINUX: libc::c_int = 0;
+	/// Not defined by the `libc` crate for musl.
+	const PER_LINUX32: libc::c_ulong = 0x0008;
+	/// Not defined by the `libc` crate for musl.
+	const UNAME26: libc::c_int = 0x0020000;
+	let uts = util::uname()?;
+	let release = util::c_str_array(&uts.release);
+	let machine = util::c_str_array(&uts.machine);
+	// Run in a child so that the personality of the test process is not changed
+	util::in_child(|| {
+		log!("Query personality");
+		test_assert_eq!(personality(QUERY)?, PER_LINUX);
+		log!("Report a 2.6 release");
+		test_assert_eq!(personality(UNAME26 as _)?, PER_LINUX);
+		test_assert_eq!(personality(QUERY)?, UNAME26);
+		let minor: u32 = release
+			.split('.')
+			.nth(1)
+			.and_then(|n| n.parse().ok())
+			.unwrap_or(0);
+		let uts = util::uname()?;
+		let release26 = util::c_str_array(&uts.release);
+		test_assert!(release26.starts_with(&format!("2.6.{}", minor + 60)));
+		test_assert!(util::c_str_array(&uts.machine) == machine);
+		log!("Inherit personality");
+		util::in_child(|| {
+			test_assert_eq!(personality(QUERY)?, UNAME26);
+			Ok(())
+		})?;
+		log!("Report a 32 bits architecture");
+		personality(PER_LINUX32)?;
+		let uts = util::uname()?;
+		test_assert!(util::c_str_array(&uts.machine) == "i686");
+		test_assert!(util::c_str_array(&uts.release) == release);
+		Ok(())
+	})
+}
+
+/// The code expected in the information of the next fault signal.
+static FAULT_CODE: AtomicI32 = AtomicI32::new(0);
+/// The address expected in the information of the next fault signal. If zero, the address is not
+/// checked.
+static FAULT_ADDR: AtomicUsize = AtomicUsize::new(0);
+
+/// Handler for fault signals, exiting with `0` if the signal information matches the expected
+/// one.
+extern "C" fn handle_fault(_: libc::c_int, info: *mut libc::siginfo_t, _: *mut libc::c_void) {
+	let info = unsafe { &*info };
+	let addr = FAULT_ADDR.load(Relaxed);
+	let code_match = info.si_code == FAULT_CODE.load(Relaxed);
+	let addr_match = addr == 0 || unsafe { info.si_addr() } as usize == addr;
+	unsafe {
+		libc::_exit(if code_match && addr_match { 0 } else { 1 });
+	}
+}
+
+/// Runs `f` in a child process, expecting it to raise the signal `sig` with the code `code` and
+/// the fault address `addr`.
+fn expect_fault(sig: libc::c_int, code: libc::c_int, addr: usize, f: fn()) -> TestResult {
+	util::in_child(|| {
+		FAULT_CODE.store(code, Relaxed);
+		FAULT_ADDR.store(addr, Relaxed);
+		let mut act: libc::sigaction = unsafe { std::mem::zeroed() };
+		act.sa_sigaction = handle_fault as usize;
+		act.sa_flags = libc::SA_SIGINFO;
+		test_assert_eq!(unsafe { libc::sigaction(sig, &act, null_mut()) }, 0);
+		f();
+		Err(TestError("the fault did not raise a signal".to_owned()))
+	})
+}
+
+pub fn fault_signals() -> TestResult {
+	/// Not defined by the `libc` crate for Linux.
+	const SEGV_MAPERR: libc::c_int = 1;
+	/// Not defined by the `libc` crate for Linux.
+	const SEGV_ACCERR: libc::c_int = 2;
+	/// Not defined by the `libc` crate for Linux.
+	const ILL_ILLOPN: libc::c_int = 2;
+	/// Not defined by the `libc` crate for Linux.
+	const FPE_INTDIV: libc::c_int = 1;
+	let len = 4096;
+	let ptr = util::mmap(
+		null_mut(),
+		len,
+		libc::PROT_READ,
+		libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+		-1,
+		0,
+	)?;
+	let res = (|| {
+		log!("Write to a read-only page");
+		expect_fault(libc::SIGSEGV, SEGV_ACCERR, ptr as usize, || unsafe {
+			let ptr = FAULT_ADDR.load(Relaxed) as *mut u8;
+			// Map the page first, so that the fault is caused by the access type only
+			ptr.read_volatile();
+			ptr.write_volatile(1);
+		})?;
+		log!("Read an unmapped page");
+		util::in_child(|| {
+			util::munmap(ptr, len)?;
+			expect_fault(libc::SIGSEGV, SEGV_MAPERR, ptr as usize, || unsafe {
+				(FAULT_ADDR.load(Relaxed) as *const u8).read_volatile();
+			})
+		})?;
+		log!("Illegal instruction");
+		expect_fault(libc::SIGILL, ILL_ILLOPN, 0, || unsafe {
+			std::arch::asm!("ud2");
+		})?;
+		log!("Integer division by zero");
+		expect_fault(libc::SIGFPE, FPE_INTDIV, 0, || unsafe {
+			std::arch::asm!(
+				"div {0:e}",
+				in(reg) 0,
+				inout("eax") 1 => _,
+				inout("edx") 0 => _,
+			);
+		})?;
+		log!("Blocked fault signal");
+		let pid = unsafe { libc::fork() };
+		test_assert!(pid >= 0);
+		if pid == 0 {
+			unsafe {
+				let mut set: libc::sigset_t = std::mem::zeroed();
+				libc::sigemptyset(&mut set);
+				libc::sigaddset(&mut set, libc::SIGSEGV);
+				libc::sigprocmask(libc::SIG_BLOCK, &set, null_mut());
+				(ptr as *mut u8).write_volatile(1);
+				libc::_exit(0);
+			}
+		}
+		let mut status = 0;
+		test_assert_eq!(unsafe { libc::waitpid(pid, &mut status, 0) }, pid);
+		// The signal cannot be blocked since the process cannot continue
+		test_assert!(libc::WIFSIGNALED(status));
+		test_assert_eq!(libc::WTERMSIG(status), libc::SIGSEGV);
+		Ok(())
+	})();
+	util::munmap(ptr, len)?;
+	res
+}
+
+/// Changes the protection of the memory range `[addr, addr + len)`.
+fn mprotect(addr: *mut libc::c_void, len: usize, prot: libc::c_int) -> io::Result<()> {
+	let res = unsafe { libc::mprotect(addr, len, prot) };
+	if res < 0 {
+		return Err(io::Error::last_os_error());
+	}
+	Ok(())
+}
+
+pub fn mprotect_split() -> TestResult {
+	/// Not defined by the `libc` crate for Linux.
+	const SEGV_ACCERR: libc::c_int = 2;
+	let len = 3 * 4096;
+	let rw = libc::PROT_READ | libc::PROT_WRITE;
+	let ptr = util::mmap(
+		null_mut(),
+		len,
+		rw,
+		libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+		-1,
+		0,
+	)? as *mut u8;
+	let res = (|| {
+		for i in 0..3 {
+			unsafe {
+				*ptr.add(i * 4096) = i as u8 + 1;
+			}
+		}
+		log!("Protect the middle of a mapping");
+		let middle = unsafe { ptr.add(4096) };
+		mprotect(middle as _, 4096, libc::PROT_READ)?;
+		// The content is preserved
+		for i in 0..3 {
+			test_assert_eq!(unsafe { *ptr.add(i * 4096) }, i as u8 + 1);
+		}
+		// Pages around the range remain writable
+		unsafe {
+			*ptr = 4;
+			*ptr.add(2 * 4096) = 5;
+		}
+		expect_fault(libc::SIGSEGV, SEGV_ACCERR, middle as usize, || unsafe {
+			(FAULT_ADDR.load(Relaxed) as *mut u8).write_volatile(1);
+		})?;
+		log!("Restore the protection");
+		mprotect(middle as _, 4096, rw)?;
+		unsafe {
+			*middle = 6;
+		}
+		test_assert_eq!(unsafe { (*ptr, *middle, *ptr.add(2 * 4096)) }, (4, 6, 5));
+		log!("Range out of the mapping");
+		let res = mprotect(ptr as _, len + 4096, libc::PROT_READ);
+		util::expect_errno(res, libc::ENOMEM)?;
+		Ok(())
+	})();
+	util::munmap(ptr as _, len)?;
+	res?;
+	log!("Write protection on a read-only file");
+	let file = fs::File::open("/maestro-test")?;
+	util::unprivileged(|| -> TestResult {
+		let ptr = util::mmap(
+			null_mut(),
+			4096,
+			libc::PROT_READ,
+			libc::MAP_SHARED,
+			file.as_raw_fd(),
+			0,
+		)?;
+		let res = util::expect_errno(mprotect(ptr, 4096, rw), libc::EACCES);
+		util::munmap(ptr, 4096)?;
+		res
+	})??;
+	let ptr = util::mmap(
+		null_mut(),
+		4096,
+		libc::PROT_READ,
+		libc::MAP_PRIVATE,
+		file.as_raw_fd(),
+		0,
+	)?;
+	// Private mappings are copied on write, which does not require write access to the file
+	let res = mprotect(ptr, 4096, rw);
+	util::munmap(ptr, 4096)?;
+	res?;
+	Ok(())
+}
+
+/// Returns the number of voluntary context switches of the current process.
+fn voluntary_switches() -> io::Result<libc::c_long> {
+	let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+	let res = unsafe { libc::getrusage(libc::RUSAGE_SELF, &mut usage) };
+	if res < 0 {
+		return Err(io::Error::last_os_error());
+	}
+	Ok(usage.ru_nvcsw)
+}
+
+pub fn sched_yield() -> TestResult {
+	let len = 4096;
+	let ptr = util::mmap(
+		null_mut(),
+		len,
+		libc::PROT_READ | libc::PROT_WRITE,
+		libc::MAP_SHARED | libc::MAP_ANONYMOUS,
+		-1,
+		0,
+	)?;
+	let counter = unsafe { &*(ptr as *const AtomicU32) };
+	log!("Create child");
+	let pid = unsafe { libc::fork() };
+	test_assert!(pid >= 0);
+	if pid == 0 {
+		loop {
+			counter.fetch_add(1, Relaxed);
+			unsafe {
+				libc::sched_yield();
+			}
+		}
+	}
+	let res = (|| {
+		let start = Instant::now();
+		while counter.load(Relaxed) == 0 {
+			test_assert!(start.elapsed() < Duration::from_secs(5));
+			unsafe {
+				libc::sched_yield();
+			}
+		}
+		log!("Yield to the child");
+		let switches = voluntary_switches()?;
+		let before = counter.load(Relaxed);
+		test_assert_eq!(unsafe { libc::sched_yield() }, 0);
+		// The child has the same priority, so it runs before the current process resumes
+		test_assert!(counter.load(Relaxed) != before);
+		test_assert!(voluntary_switches()? > switches);
+		Ok(())
+	})();
+	unsafe {
+		libc::kill(pid, libc::SIGKILL);
+		libc::waitpid(pid, null_mut(), 0);
+	}
+	util::munmap(ptr, len)?;
+	res
+}
+
+/// The number of round trips between the processes of the context switch benchmark.
+const PING_PONG_ROUNDS: u32 = 1000;
+
+pub fn context_switch() -> TestResult {
+	let len = 4096;
+	let ptr = util::mmap(
+		null_mut(),
+		len,
+		libc::PROT_READ | libc::PROT_WRITE,
+		libc::MAP_SHARED | libc::MAP_ANONYMOUS,
+		-1,
+		0,
+	)?;
+	// The parent hands the turn over by setting an odd value, the child hands it back by
+	// incrementing it
+	let turn = unsafe { &*(ptr as *const AtomicU32) };
+	log!("Create child");
+	let pid = unsafe { libc::fork() };
+	test_assert!(pid >= 0);
+	if pid == 0 {
+		loop {
+			let cur = turn.load(Relaxed);
+			if cur % 2 == 1 {
+				turn.store(cur + 1, Relaxed);
+			}
+			unsafe {
+				libc::sched_yield();
+			}
+		}
+	}
+	let res = (|| {
+		log!("Ping-pong between the processes");
+		let switches = voluntary_switches()?;
+		let start = Instant::now();
+		for i in 0..PING_PONG_ROUNDS {
+			turn.store(i * 2 + 1, Relaxed);
+			// Yield until the child has taken its turn
+			while turn.load(Relaxed) != i * 2 + 2 {
+				test_assert!(start.elapsed() < Duration::from_secs(10));
+				unsafe {
+					libc::sched_yield();
+				}
+			}
+		}
+		let elapsed = start.elapsed();
+		let yields = voluntary_switches()? - switches;
+		test_assert!(yields >= PING_PONG_ROUNDS as libc::c_long);
+		// Each yield switches to the child, which switches back by yielding as well
+		let per_switch = elapsed / (yields as u32 * 2);
+		log!("{yields} round trips, {per_switch:?} per context switch");
+		Ok(())
+	})();
+	unsafe {
+		libc::kill(pid, libc::SIGKILL);
+		libc::waitpid(pid, null_mut(), 0);
+	}
+	util::munmap(ptr, len)?;
+	res
+}
+
+/// Performs a futex operation on `word`.
+fn futex(
+	word: &AtomicU32,
+	op: libc::c_int,
+	val: u32,
+	timeout: Option<&libc::timespec>,
+	bitset: u32,
+) -> io::Result<libc::c_long> {
+	let timeout = timeout.map_or(null(), |t| t as *const _);
+	let res = unsafe {
+		libc::syscall(
+			libc::SYS_futex,
+			word.as_ptr(),
+			op,
+			val,
+			timeout,
+			null::<u32>(),
+			bitset,
+		)
+	};
+	if res >= 0 {
+		Ok(res)
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+/// Wakes at most `count` processes waiting on `word`, then moves at most `requeue_count` of the
+/// remaining waiters to `word2`, if `word` contains `cmp`.
+fn futex_requeue(
+	word: &AtomicU32,
+	count: u32,
+	word2: &AtomicU32,
+	requeue_count: u32,
+	cmp: u32,
+) -> io::Result<libc::c_long> {
+	let res = unsafe {
+		libc::syscall(
+			libc::SYS_futex,
+			word.as_ptr(),
+			libc::FUTEX_CMP_REQUEUE,
+			count,
+			requeue_count as usize,
+			word2.as_ptr(),
+			cmp,
+		)
+	};
+	if res >= 0 {
+		Ok(res)
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+pub fn futex_wait_wake() -> TestResult {
+	let len = 4096;
+	let ptr = util::mmap(
+		std::ptr::null_mut(),
+		len,
+		libc::PROT_READ | libc::PROT_WRITE,
+		libc::MAP_SHARED | libc::MAP_ANONYMOUS,
+		-1,
+		0,
+	)?;
+	let word = unsafe { &*(ptr as *const AtomicU32) };
+	log!("Wait with another value");
+	util::expect_errno(futex(word, libc::FUTEX_WAIT, 1, None, 0), libc::EAGAIN)?;
+	log!("Relative timeout");
+	let timeout = libc::timespec {
+		tv_sec: 0,
+		tv_nsec: 100_000_000,
+	};
+	let start = Instant::now();
+	let res = futex(word, libc::FUTEX_WAIT, 0, Some(&timeout), 0);
+	util::expect_errno(res, libc::ETIMEDOUT)?;
+	let elapsed = start.elapsed();
+	test_assert!(elapsed >= Duration::from_millis(100));
+	test_assert!(elapsed < Duration::from_secs(5));
+	log!("Absolute timeout");
+	let mut deadline: libc::timespec = unsafe { std::mem::zeroed() };
+	unsafe {
+		libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut deadline);
+	}
+	deadline.tv_nsec += 100_000_000;
+	if deadline.tv_nsec >= 1_000_000_000 {
+		deadline.tv_sec += 1;
+		deadline.tv_nsec -= 1_000_000_000;
+	}
+	let start = Instant::now();
+	let res = futex(
+		word,
+		libc::FUTEX_WAIT_BITSET,
+		0,
+		Some(&deadline),
+		libc::FUTEX_BITSET_MATCH_ANY as _,
+	);
+	util::expect_errno(res, libc::ETIMEDOUT)?;
+	let elapsed = start.elapsed();
+	test_assert!(elapsed >= Duration::from_millis(90));
+	test_assert!(elapsed < Duration::from_secs(5));
+	log!("Wake a waiting process");
+	let pid = unsafe { libc::fork() };
+	test_assert!(pid >= 0);
+	if pid == 0 {
+		// If the parent changed the value before waiting, waiting fails
+		let code = match futex(word, libc::FUTEX_WAIT, 0, None, 0) {
+			Ok(_) => 0,
+			Err(e) if e.raw_os_error() == Some(libc::EAGAIN) => 0,
+			Err(_) => 1,
+		};
+		unsafe {
+			libc::_exit(code);
+		}
+	}
+	thread::sleep(Duration::from_millis(100));
+	word.store(1, Relaxed);
+	let start = Instant::now();
+	let mut status = 0;
+	loop {
+		futex(word, libc::FUTEX_WAKE, 1, None, 0)?;
+		let res = unsafe { libc::waitpid(pid, &mut status, libc::WNOHANG) };
+		test_assert!(res >= 0);
+		if res == pid {
+			break;
+		}
+		test_assert!(start.elapsed() < Duration::from_secs(5));
+		thread::sleep(Duration::from_millis(10));
+	}
+	test_assert!(libc::WIFEXITED(status));
+	test_assert_eq!(libc::WEXITSTATUS(status), 0);
+	log!("Wake without waiters");
+	test_assert_eq!(futex(word, libc::FUTEX_WAKE, 1, None, 0)?, 0);
+	util::munmap(ptr, len)?;
+	Ok(())
+}
+
+/// Waits until `word` is cleared, which happens when the process whose TID is stored in it
+/// exits.
+fn wait_cleared(word: &AtomicU32) -> TestResult {
+	let timeout = libc::timespec {
+		tv_sec: 0,
+		tv_nsec: 100_000_000,
+	};
+	let start = Instant::now();
+	loop {
+		let val = word.load(Relaxed);
+		if val == 0 {
+			break;
+		}
+		// Errors are ignored since the value may have changed in the meantime
+		let _ = futex(word, libc::FUTEX_WAIT, val, Some(&timeout), 0);
+		test_assert!(start.elapsed() < Duration::from_secs(5));
+	}
+	Ok(())
+}
+
+pub fn clear_child_tid() -> TestResult {
+	let len = 4096;
+	let ptr = util::mmap(
+		null_mut(),
+		len,
+		libc::PROT_READ | libc::PROT_WRITE,
+		libc::MAP_SHARED | libc::MAP_ANONYMOUS,
+		-1,
+		0,
+	)?;
+	let word = unsafe { &*(ptr as *const AtomicU32) };
+	let res = (|| {
+		log!("Clone with the child TID");
+		let args = CloneArgs {
+			flags: (libc::CLONE_CHILD_SETTID | libc::CLONE_CHILD_CLEARTID) as _,
+			child_tid: word.as_ptr() as usize as _,
+			exit_signal: libc::SIGCHLD as _,
+			..Default::default()
+		};
+		let pid = clone3(&args as *const _ as _, size_of::<CloneArgs>())?;
+		if pid == 0 {
+			thread::sleep(Duration::from_millis(100));
+			unsafe {
+				libc::_exit(0);
+			}
+		}
+		test_assert_eq!(word.load(Relaxed), pid as u32);
+		log!("Wait for the child TID to be cleared");
+		wait_cleared(word)?;
+		unsafe {
+			libc::waitpid(pid, null_mut(), 0);
+		}
+		log!("Set the address after creation");
+		word.store(1, Relaxed);
+		let pid = unsafe { libc::fork() };
+		test_assert!(pid >= 0);
+		if pid == 0 {
+			let tid = unsafe { libc::syscall(libc::SYS_set_tid_address, word.as_ptr()) };
+			let code = if tid == unsafe { libc::syscall(libc::SYS_gettid) } {
+				0
+			} else {
+				1
+			};
+			thread::sleep(Duration::from_millis(100));
+			unsafe {
+				libc::_exit(code);
+			}
+		}
+		wait_cleared(word)?;
+		let mut status = 0;
+		test_assert_eq!(unsafe { libc::waitpid(pid, &mut status, 0) }, pid);
+		test_assert!(libc::WIFEXITED(status));
+		test_assert_eq!(libc::WEXITSTATUS(status), 0);
+		Ok(())
+	})();
+	util::munmap(ptr, len)?;
+	res
+}
+
+pub fn futex_requeue_wake() -> TestResult {
+	let len = 4096;
+	let ptr = util::mmap(
+		std::ptr::null_mut(),
+		len,
+		libc::PROT_READ | libc::PROT_WRITE,
+		libc::MAP_SHARED | libc::MAP_ANONYMOUS,
+		-1,
+		0,
+	)?;
+	let words = unsafe { &*(ptr as *const [AtomicU32; 2]) };
+	let [word, word2] = words;
+	log!("Compare with another value");
+	util::expect_errno(futex_requeue(word, 0, word2, 1, 1), libc::EAGAIN)?;
+	log!("Start waiters");
+	let mut pids = [0; 2];
+	for pid in &mut pids {
+		*pid = unsafe { libc::fork() };
+		test_assert!(*pid >= 0);
+		if *pid == 0 {
+			let code = match futex(word, libc::FUTEX_WAIT, 0, None, 0) {
+				Ok(_) => 0,
+				Err(_) => 1,
+			};
+			unsafe {
+				libc::_exit(code);
+			}
+		}
+	}
+	log!("Requeue waiters");
+	// Waiters are requeued as soon as they are waiting
+	let start = Instant::now();
+	let mut requeued = 0;
+	while requeued < pids.len() {
+		requeued += futex_requeue(word, 0, word2, 2, 0)? as usize;
+		test_assert!(start.elapsed() < Duration::from_secs(5));
+		thread::sleep(Duration::from_millis(10));
+	}
+	test_assert_eq!(requeued, pids.len());
+	// No waiter is left on the first futex
+	test_assert_eq!(futex(word, libc::FUTEX_WAKE, 2, None, 0)?, 0);
+	log!("Wake requeued waiters");
+	for _ in &pids {
+		test_assert_eq!(futex(word2, libc::FUTEX_WAKE, 1, None, 0)?, 1);
+	}
+	for pid in pids {
+		let mut status = 0;
+		let res = unsafe { libc::waitpid(pid, &mut status, 0) };
+		test_assert_eq!(res, pid);
+		test_assert!(libc::WIFEXITED(status));
+		test_assert_eq!(libc::WEXITSTATUS(status), 0);
+	}
+	test_assert_eq!(futex(word2, libc::FUTEX_WAKE, 1, None, 0)?, 0);
+	util::munmap(ptr, len)?;
+	Ok(())
+}
+
+pub fn ksm() -> TestResult {
+	let len = 4 * 4096;
+	// Map one more page, then unmap it to have an unmapped page right after the range
+	let ptr = util::mmap(
+		std::ptr::null_mut(),
+		len + 4096,
+		libc::PROT_READ | libc::PROT_WRITE,
+		libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+		-1,
+		0,
+	)?;
+	util::munmap(unsafe { ptr.add(len) }, 4096)?;
+	let mem = unsafe { std::slice::from_raw_parts_mut(ptr as *mut u8, len) };
+	mem.fill(0x42);
+	log!("Unmapped range");
+	let res = util::madvise(ptr, len + 4096, libc::MADV_MERGEABLE);
+	util::expect_errno(res, libc::ENOMEM)?;
+	log!("Merge identical pages");
+	util::madvise(ptr, len, libc::MADV_MERGEABLE)?;
+	// Leave the daemon enough time to scan the pages
+	thread::sleep(Duration::from_secs(3));
+	test_assert!(mem.iter().all(|b| *b == 0x42));
+	log!("Write to merged pages");
+	mem[0] = 0;
+	mem[3 * 4096] = 1;
+	test_assert_eq!(mem[0], 0);
+	test_assert_eq!(mem[3 * 4096], 1);
+	test_assert!(mem[1..3 * 4096].iter().all(|b| *b == 0x42));
+	test_assert!(mem[3 * 4096 + 1..].iter().all(|b| *b == 0x42));
+	log!("Unmerge");
+	util::madvise(ptr, len, libc::MADV_UNMERGEABLE)?;
+	mem[4096] = 2;
+	test_assert_eq!(mem[4096], 2);
+	test_assert_eq!(mem[2 * 4096], 0x42);
+	util::munmap(ptr, len)?;
+	Ok(())
+}
+
+/// Calls `kcmp` on the given processes, returning the result.
+fn kcmp(
+	pid1: libc::pid_t,
+	pid2: libc::pid_t,
+	type_: libc::c_int,
+	idx1: libc::c_ulong,
+	idx2: libc::c_ulong,
+) -> io::Result<libc::c_long> {
+	let res = unsafe { libc::syscall(libc::SYS_kcmp, pid1, pid2, type_, idx1, idx2) };
+	if res < 0 {
+		return Err(io::Error::last_os_error());
+	}
+	Ok(res)
+}
+
+pub fn kcmp_order() -> TestResult {
+	const KCMP_FILE: libc::c_int = 0;
+	const KCMP_VM: libc::c_int = 1;
+	let pid = unsafe { libc::getpid() };
+	log!("Compare the same resources");
+	test_assert_eq!(kcmp(pid, pid, KCMP_VM, 0, 0)?, 0);
+	let pipe = util::pipe()?;
+	let rd = pipe.0.as_raw_fd() as _;
+	let wr = pipe.1.as_raw_fd() as _;
+	test_assert_eq!(kcmp(pid, pid, KCMP_FILE, rd, rd)?, 0);
+	log!("Compare different resources");
+	let ord = kcmp(pid, pid, KCMP_FILE, rd, wr)?;
+	test_assert!(ord == 1 || ord == 2);
+	test_assert_eq!(kcmp(pid, pid, KCMP_FILE, wr, rd)?, 3 - ord);
+	log!("Compare with a child process");
+	let child = unsafe { libc::fork() };
+	test_assert!(child >= 0);
+	if child == 0 {
+		thread::sleep(Duration::from_secs(5));
+		unsafe {
+			libc::_exit(0);
+		}
+	}
+	let res = (|| {
+		let ord = kcmp(pid, child, KCMP_VM, 0, 0)?;
+		test_assert!(ord == 1 || ord == 2);
+		test_assert_eq!(kcmp(child, pid, KCMP_VM, 0, 0)?, 3 - ord);
+		// File descriptions are shared after a fork
+		test_assert_eq!(kcmp(pid, child, KCMP_FILE, rd, rd)?, 0);
+		Ok(())
+	})();
+	unsafe {
+		libc::kill(child, libc::SIGKILL);
+		libc::waitpid(child, null_mut(), 0);
+	}
+	res
+}
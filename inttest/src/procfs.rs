@@ -19,12 +19,23 @@
 //! procfs filesystem testing.
 
 use crate::{
-	log, test_assert_eq, util,
+	log, test_assert, test_assert_eq, util,
 	util::{TestError, TestResult},
 };
 use std::{
-	collections::HashMap, env, env::current_dir, ffi::CString, fs, os::unix::ffi::OsStrExt,
-	ptr::null,
+	collections::HashMap,
+	env,
+	env::current_dir,
+	ffi::CString,
+	fs, io, mem,
+	os::{
+		fd::AsRawFd,
+		unix::{
+			ffi::OsStrExt,
+			fs::{MetadataExt, PermissionsExt},
+		},
+	},
+	ptr::{null, null_mut},
 };
 
 pub fn mount() -> TestResult {
@@ -44,18 +55,72 @@ pub fn mount() -> TestResult {
 	Ok(())
 }
 
+/// Returns the options of the mount at `target` from `/proc/self/mounts`, then the super options
+/// of the same mount from `/proc/self/mountinfo`.
+fn mount_options(target: &str) -> Result<(String, String), TestError> {
+	let mounts = fs::read_to_string("/proc/self/mounts")?;
+	let options = mounts
+		.lines()
+		.map(|line| line.split(' ').collect::<Vec<_>>())
+		.filter(|fields| fields[1] == target)
+		.last()
+		.map(|fields| fields[3].to_owned())
+		.ok_or_else(|| TestError(format!("missing mount {target}")))?;
+	let mountinfo = fs::read_to_string("/proc/self/mountinfo")?;
+	let super_options = mountinfo
+		.lines()
+		.filter(|line| line.split(' ').nth(4) == Some(target))
+		.last()
+		.and_then(|line| line.split(" - ").nth(1)?.split(' ').nth(2))
+		.map(str::to_owned)
+		.ok_or_else(|| TestError(format!("missing mountinfo for {target}")))?;
+	Ok((options, super_options))
+}
+
+pub fn mounts() -> TestResult {
+	log!("ext2 root");
+	let (options, super_options) = mount_options("/")?;
+	for opts in [&options, &super_options] {
+		let opts: Vec<_> = opts.split(',').collect();
+		test_assert!(opts.contains(&"rw"));
+		let errors = opts.iter().filter_map(|o| o.strip_prefix("errors="));
+		let errors: Vec<_> = errors.collect();
+		test_assert!(matches!(
+			errors.as_slice(),
+			["continue" | "remount-ro" | "panic"]
+		));
+	}
+	log!("Read-only tmpfs");
+	fs::create_dir("/mounts_tmp")?;
+	let src = CString::new("tmpfs")?;
+	let target = CString::new("/mounts_tmp")?;
+	util::mount(&src, &target, &src, libc::MS_RDONLY, null())?;
+	let res = (|| {
+		let (options, super_options) = mount_options("/mounts_tmp")?;
+		test_assert!(options.split(',').any(|o| o == "ro"));
+		test_assert!(!options.contains("errors="));
+		test_assert_eq!(super_options.as_str(), "ro");
+		Ok(())
+	})();
+	util::umount(&target)?;
+	fs::remove_dir("/mounts_tmp")?;
+	res
+}
+
 pub fn cwd() -> TestResult {
 	let cwd = fs::read_link("/proc/self/cwd")?;
 	test_assert_eq!(cwd, current_dir()?);
 	Ok(())
 }
 
-// TODO: not yet implemented
-/*pub fn exe() -> TestResult {
+pub fn exe() -> TestResult {
 	let exe = fs::read_link("/proc/self/exe")?;
 	test_assert_eq!(exe.as_os_str().as_bytes(), b"/maestro-test");
+	log!("Open through the link");
+	let content = fs::read("/proc/self/exe")?;
+	test_assert_eq!(content, fs::read("/maestro-test")?);
 	Ok(())
-}*/
+}
 
 pub fn cmdline() -> TestResult {
 	let args0 = fs::read("/proc/self/cmdline")?;
@@ -90,3 +155,482 @@ pub fn environ() -> TestResult {
 	test_assert_eq!(args0, args1);
 	Ok(())
 }
+
+pub fn timens_offsets() -> TestResult {
+	/// Not defined by the `libc` crate for musl.
+	const CLONE_NEWTIME: i32 = 0x80;
+	// Run in a child so that processes created by other tests remain in the root namespace
+	util::in_child(|| {
+		log!("Create namespace");
+		util::unshare(CLONE_NEWTIME)?;
+		log!("Set offsets");
+		fs::write(
+			"/proc/self/timens_offsets",
+			"monotonic 86400 0\nboottime 172800 500000000\n",
+		)?;
+		let offsets = fs::read_to_string("/proc/self/timens_offsets")?;
+		let offsets: Vec<Vec<&str>> = offsets
+			.lines()
+			.map(|l| l.split_whitespace().collect())
+			.collect();
+		test_assert_eq!(
+			offsets,
+			[
+				["monotonic", "86400", "0"],
+				["boottime", "172800", "500000000"]
+			]
+		);
+		log!("Check clocks in namespace");
+		util::in_child(|| {
+			let mut ts: libc::timespec = unsafe { mem::zeroed() };
+			let res = unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts) };
+			test_assert_eq!(res, 0);
+			test_assert!(ts.tv_sec >= 86400);
+			let uptime = fs::read_to_string("/proc/uptime")?;
+			let uptime: f64 = uptime
+				.split_whitespace()
+				.next()
+				.and_then(|s| s.parse().ok())
+				.ok_or_else(|| TestError("invalid uptime".to_owned()))?;
+			test_assert!(uptime >= 172800.5);
+			Ok(())
+		})?;
+		log!("Offsets are frozen once the namespace is entered");
+		util::expect_errno(
+			fs::write("/proc/self/timens_offsets", "monotonic 0 0\n"),
+			libc::EACCES,
+		)?;
+		Ok(())
+	})
+}
+
+pub fn fd() -> TestResult {
+	log!("Open file");
+	fs::write("proc_fd", "content")?;
+	let file = fs::File::open("proc_fd")?;
+	let path = format!("/proc/self/fd/{}", file.as_raw_fd());
+	let res = (|| {
+		log!("Read link");
+		let target = fs::read_link(&path)?;
+		test_assert_eq!(target, current_dir()?.join("proc_fd"));
+		log!("Open removed file through the link");
+		fs::remove_file("proc_fd")?;
+		test_assert_eq!(fs::read_to_string(&path)?, "content");
+		log!("Non-canonical names");
+		let name = format!("/proc/self/fd/0{}", file.as_raw_fd());
+		util::expect_errno(fs::read_link(name), libc::ENOENT)?;
+		let name = format!("/proc/self/fd/+{}", file.as_raw_fd());
+		util::expect_errno(fs::read_link(name), libc::ENOENT)?;
+		Ok(())
+	})();
+	let _ = fs::remove_file("proc_fd");
+	res
+}
+
+pub fn map_files() -> TestResult {
+	log!("Map file");
+	let file = fs::File::open("/maestro-test")?;
+	let len = 4096;
+	let addr = unsafe {
+		libc::mmap(
+			null_mut(),
+			len,
+			libc::PROT_READ,
+			libc::MAP_PRIVATE,
+			file.as_raw_fd(),
+			0,
+		)
+	};
+	test_assert!(addr != libc::MAP_FAILED);
+	let name = format!("{:x}-{:x}", addr as usize, addr as usize + len);
+	let res = (|| {
+		log!("List mappings");
+		let found = fs::read_dir("/proc/self/map_files")?
+			.map(|ent| ent.map(|ent| ent.file_name()))
+			.collect::<io::Result<Vec<_>>>()?
+			.iter()
+			.any(|n| n.as_bytes() == name.as_bytes());
+		test_assert!(found);
+		log!("Read link");
+		let path = format!("/proc/self/map_files/{name}");
+		let target = fs::read_link(&path)?;
+		test_assert_eq!(target.as_os_str().as_bytes(), b"/maestro-test");
+		log!("Open through the link");
+		test_assert_eq!(fs::read(&path)?, fs::read("/maestro-test")?);
+		Ok(())
+	})();
+	unsafe {
+		libc::munmap(addr, len);
+	}
+	res
+}
+
+/// A line of a `maps` file.
+#[derive(Debug)]
+struct MapsLine {
+	begin: usize,
+	end: usize,
+	perms: String,
+	off: u64,
+	inode: u64,
+	path: String,
+}
+
+/// Parses the `maps` file of the process `pid`.
+fn read_maps(pid: &str) -> Result<Vec<MapsLine>, TestError> {
+	let content = fs::read_to_string(format!("/proc/{pid}/maps"))?;
+	content
+		.lines()
+		.map(|line| {
+			let mut fields = line.splitn(6, ' ');
+			let mut next = || fields.next().unwrap_or_default();
+			let (begin, end) = next().split_once('-')?;
+			let perms = next().to_owned();
+			let off = u64::from_str_radix(next(), 16).ok()?;
+			let _dev = next();
+			let inode = next().parse().ok()?;
+			let path = next().trim_start().to_owned();
+			Some(MapsLine {
+				begin: usize::from_str_radix(begin, 16).ok()?,
+				end: usize::from_str_radix(end, 16).ok()?,
+				perms,
+				off,
+				inode,
+				path,
+			})
+		})
+		.collect::<Option<Vec<_>>>()
+		.ok_or_else(|| TestError(format!("invalid maps line in:\n{content}")))
+}
+
+pub fn maps() -> TestResult {
+	let file = fs::File::open("/maestro-test")?;
+	let inode = file.metadata()?.ino();
+	let ptr = util::mmap(
+		null_mut(),
+		8192,
+		libc::PROT_READ,
+		libc::MAP_PRIVATE,
+		file.as_raw_fd(),
+		4096,
+	)? as usize;
+	let anon = util::mmap(
+		null_mut(),
+		4096,
+		libc::PROT_READ | libc::PROT_WRITE,
+		libc::MAP_SHARED | libc::MAP_ANONYMOUS,
+		-1,
+		0,
+	)? as usize;
+	let res = (|| {
+		log!("File mapping");
+		let maps = read_maps("self")?;
+		let m = maps
+			.iter()
+			.find(|m| m.begin == ptr)
+			.ok_or_else(|| TestError("missing file mapping".to_owned()))?;
+		test_assert_eq!(m.end, ptr + 8192);
+		test_assert_eq!(m.perms.as_str(), "r--p");
+		test_assert_eq!((m.off, m.inode), (4096, inode));
+		test_assert_eq!(m.path.as_str(), "/maestro-test");
+		log!("Anonymous mapping");
+		let m = maps
+			.iter()
+			.find(|m| m.begin == anon)
+			.ok_or_else(|| TestError("missing anonymous mapping".to_owned()))?;
+		test_assert_eq!(m.perms.as_str(), "rw-s");
+		test_assert_eq!(m.inode, 0);
+		test_assert!(m.path.is_empty());
+		log!("Stack");
+		let local = 0u8;
+		let addr = &local as *const _ as usize;
+		let m = maps
+			.iter()
+			.find(|m| (m.begin..m.end).contains(&addr))
+			.ok_or_else(|| TestError("missing stack mapping".to_owned()))?;
+		test_assert_eq!(m.path.as_str(), "[stack]");
+		log!("Split mapping");
+		let res =
+			unsafe { libc::mprotect((ptr + 4096) as _, 4096, libc::PROT_READ | libc::PROT_EXEC) };
+		test_assert_eq!(res, 0);
+		let maps = read_maps("self")?;
+		let split: Vec<_> = maps
+			.iter()
+			.filter(|m| (ptr..ptr + 8192).contains(&m.begin))
+			.map(|m| (m.begin, m.end, m.perms.as_str(), m.off))
+			.collect();
+		test_assert_eq!(
+			split,
+			[
+				(ptr, ptr + 4096, "r--p", 4096),
+				(ptr + 4096, ptr + 8192, "r-xp", 8192)
+			]
+		);
+		log!("Mappings of another process");
+		let pid = unsafe { libc::getpid() }.to_string();
+		util::in_child(|| {
+			test_assert!(read_maps(&pid)?.iter().any(|m| m.begin == anon));
+			Ok(())
+		})?;
+		Ok(())
+	})();
+	unsafe {
+		libc::munmap(ptr as _, 8192);
+		libc::munmap(anon as _, 4096);
+	}
+	res
+}
+
+pub fn pressure() -> TestResult {
+	log!("Read");
+	let content = fs::read_to_string("/proc/pressure/memory")?;
+	let fields: HashMap<_, _> = content
+		.lines()
+		.filter_map(|line| line.split_once(' '))
+		.collect();
+	test_assert!(matches!(
+		fields.get("level"),
+		Some(&("none" | "low" | "medium" | "critical"))
+	));
+	let kb = |name: &str| -> Result<u64, TestError> {
+		fields
+			.get(name)
+			.and_then(|val| val.strip_suffix(" kB")?.parse().ok())
+			.ok_or_else(|| TestError(format!("invalid field {name}")))
+	};
+	let (free, total) = (kb("free")?, kb("total")?);
+	test_assert!(total > 0 && free <= total);
+	test_assert!(fields
+		.get("stalls")
+		.is_some_and(|s| s.parse::<u64>().is_ok()));
+	log!("Poll");
+	let file = fs::File::open("/proc/pressure/memory")?;
+	let mut pfd = libc::pollfd {
+		fd: file.as_raw_fd(),
+		events: libc::POLLIN | libc::POLLPRI,
+		revents: 0,
+	};
+	test_assert_eq!(unsafe { libc::poll(&mut pfd, 1, 0) }, 1);
+	test_assert!(pfd.revents & libc::POLLIN != 0);
+	// Urgent data is reported only under memory pressure
+	let level = fields["level"];
+	test_assert_eq!(pfd.revents & libc::POLLPRI != 0, level != "none");
+	Ok(())
+}
+
+/// Returns the flags of the console `name` listed in `/proc/consoles`, if active.
+fn console_flags(name: &str) -> Result<Option<String>, TestError> {
+	let content = fs::read_to_string("/proc/consoles")?;
+	Ok(content
+		.lines()
+		.filter_map(|line| line.split_once(' '))
+		.find(|(n, _)| *n == name)
+		.map(|(_, flags)| flags.to_owned()))
+}
+
+pub fn consoles() -> TestResult {
+	let write = |s: &str| fs::write("/proc/consoles", s);
+	log!("Default consoles");
+	test_assert!(console_flags("tty0")?.is_some());
+	log!("Activate a console");
+	// A console that is not used by the test runner is chosen, so that its output is not lost
+	write("ttyS1 -w\n")?;
+	test_assert!(console_flags("ttyS1")?.as_deref() == Some("-w"));
+	write("ttyS1 r-")?;
+	test_assert!(console_flags("ttyS1")?.as_deref() == Some("r-"));
+	log!("Invalid writes");
+	util::expect_errno(write("ttyS1 x"), libc::EINVAL)?;
+	util::expect_errno(write("ttyS1"), libc::EINVAL)?;
+	util::expect_errno(write("ttyS1 rw extra"), libc::EINVAL)?;
+	util::expect_errno(write("foo rw"), libc::ENOENT)?;
+	// Lines are validated before being applied
+	util::expect_errno(write("ttyS1 rw\nttyS1 x\n"), libc::EINVAL)?;
+	test_assert!(console_flags("ttyS1")?.as_deref() == Some("r-"));
+	log!("Deactivate");
+	write("ttyS1 --")?;
+	test_assert!(console_flags("ttyS1")?.as_deref() == Some("--"));
+	Ok(())
+}
+
+pub fn kthreads() -> TestResult {
+	log!("List processes");
+	let mut names = Vec::new();
+	for ent in fs::read_dir("/proc")? {
+		let ent = ent?;
+		if !ent.file_name().as_bytes().iter().all(u8::is_ascii_digit) {
+			continue;
+		}
+		// The process may have exited in the meantime
+		let Ok(cmdline) = fs::read(ent.path().join("cmdline")) else {
+			continue;
+		};
+		names.push(cmdline);
+	}
+	for name in [&b"events\0"[..], b"writeback\0", b"ksmd\0"] {
+		log!(
+			"Look for {}",
+			String::from_utf8_lossy(&name[..name.len() - 1])
+		);
+		test_assert!(names.iter().any(|n| n == name));
+	}
+	Ok(())
+}
+
+pub fn kernel_info() -> TestResult {
+	log!("Version");
+	let uts = util::uname()?;
+	let version = fs::read_to_string("/proc/version")?;
+	let prefix = format!(
+		"{} version {} ",
+		util::c_str_array(&uts.sysname),
+		util::c_str_array(&uts.release)
+	);
+	test_assert!(version.starts_with(&prefix));
+	test_assert_eq!(version.lines().count(), 1);
+	log!("Command line");
+	let cmdline = fs::read("/proc/cmdline")?;
+	test_assert_eq!(cmdline.last(), Some(&b'\n'));
+	test_assert_eq!(cmdline.iter().filter(|b| **b == b'\n').count(), 1);
+	log!("Configuration");
+	let config = fs::read("/proc/config")?;
+	test_assert!(!config.is_empty());
+	let metadata = fs::metadata("/proc/config")?;
+	test_assert_eq!(metadata.len(), config.len() as u64);
+	test_assert_eq!(metadata.permissions().mode() & 0o777, 0o444);
+	Ok(())
+}
+
+/// Returns the start time of the current process, in clock ticks since boot.
+fn start_time() -> Result<u64, TestError> {
+	let stat = fs::read_to_string("/proc/self/stat")?;
+	// Skip the name, which may contain spaces
+	stat.rsplit_once(") ")
+		.and_then(|(_, fields)| fields.split_whitespace().nth(19))
+		.and_then(|s| s.parse().ok())
+		.ok_or_else(|| TestError("invalid start time".to_owned()))
+}
+
+pub fn boot_time() -> TestResult {
+	log!("Boot ID");
+	let boot_id = fs::read_to_string("/proc/sys/kernel/random/boot_id")?;
+	test_assert_eq!(boot_id.len(), 37);
+	test_assert!(boot_id.ends_with('\n'));
+	let groups: Vec<&str> = boot_id.trim_end().split('-').collect();
+	test_assert_eq!(
+		groups.iter().map(|g| g.len()).collect::<Vec<_>>(),
+		[8, 4, 4, 4, 12]
+	);
+	test_assert!(groups.iter().all(|g| g
+		.bytes()
+		.all(|b| b.is_ascii_hexdigit() && !b.is_ascii_uppercase())));
+	// Version 4 UUID
+	test_assert!(groups[2].starts_with('4'));
+	test_assert_eq!(
+		fs::read_to_string("/proc/sys/kernel/random/boot_id")?,
+		boot_id
+	);
+	log!("Uptime");
+	let boottime = || {
+		let mut ts: libc::timespec = unsafe { mem::zeroed() };
+		let res = unsafe { libc::clock_gettime(libc::CLOCK_BOOTTIME, &mut ts) };
+		(res == 0).then(|| ts.tv_sec as f64 + ts.tv_nsec as f64 / 1e9)
+	};
+	let before = boottime().ok_or_else(io::Error::last_os_error)?;
+	let uptime = fs::read_to_string("/proc/uptime")?;
+	let after = boottime().ok_or_else(io::Error::last_os_error)?;
+	let uptime: f64 = uptime
+		.split_whitespace()
+		.next()
+		.and_then(|s| s.parse().ok())
+		.ok_or_else(|| TestError("invalid uptime".to_owned()))?;
+	// The uptime is truncated to hundredths of a second
+	test_assert!(uptime >= before - 0.01 && uptime <= after);
+	log!("Process start time");
+	let start = start_time()?;
+	test_assert!(start as f64 <= after * 100.0);
+	util::in_child(|| {
+		let child_start = start_time()?;
+		test_assert!(child_start >= start);
+		test_assert!(child_start as f64 >= before * 100.0 - 1.0);
+		Ok(())
+	})?;
+	Ok(())
+}
+
+pub fn iomem() -> TestResult {
+	let metadata = fs::metadata("/proc/iomem")?;
+	test_assert_eq!(metadata.permissions().mode() & 0o777, 0o400);
+	log!("Parse memory map");
+	let iomem = fs::read_to_string("/proc/iomem")?;
+	let regions = iomem
+		.lines()
+		.map(|line| {
+			let (range, name) = line.split_once(" : ")?;
+			let (start, end) = range.split_once('-')?;
+			let start = u64::from_str_radix(start, 16).ok()?;
+			let end = u64::from_str_radix(end, 16).ok()?;
+			Some((start, end, name))
+		})
+		.collect::<Option<Vec<_>>>()
+		.ok_or_else(|| TestError("invalid memory map".to_owned()))?;
+	test_assert!(regions.iter().all(|(start, end, _)| start <= end));
+	log!("Look for available memory");
+	// The kernel and its processes cannot run with less than that
+	let ram: u64 = regions
+		.iter()
+		.filter(|(_, _, name)| *name == "System RAM")
+		.map(|(start, end, _)| end - start + 1)
+		.sum();
+	test_assert!(ram >= 1024 * 1024);
+	Ok(())
+}
+
+pub fn exec_self() -> TestResult {
+	log!("Create files");
+	fs::create_dir_all("exec_a")?;
+	fs::create_dir_all("exec_b")?;
+	// The interpreter does not exist, so that a successful permission check is told apart
+	let script = "#!/nonexistent \n";
+	fs::write("exec_a/prog", script)?;
+	fs::set_permissions("exec_a/prog", fs::Permissions::from_mode(0o644))?;
+	fs::write("exec_b/prog", script)?;
+	fs::set_permissions("exec_b/prog", fs::Permissions::from_mode(0o755))?;
+	let exec = |path: &str| -> io::Result<()> {
+		let path = CString::new(path)?;
+		let argv = [path.as_ptr(), null()];
+		unsafe {
+			libc::execv(path.as_ptr(), argv.as_ptr());
+		}
+		Err(io::Error::last_os_error())
+	};
+	let res = (|| {
+		// The same path must resolve differently in each process
+		log!("Execute through the working directory of a first process");
+		util::in_child(|| {
+			env::set_current_dir("exec_a")?;
+			util::expect_errno(exec("/proc/self/cwd/prog"), libc::EACCES)
+		})?;
+		log!("Execute through the working directory of a second process");
+		util::in_child(|| {
+			env::set_current_dir("exec_b")?;
+			util::expect_errno(exec("/proc/self/cwd/prog"), libc::ENOENT)
+		})?;
+		log!("Execute through a file descriptor of a first process");
+		util::in_child(|| {
+			let file = fs::File::open("exec_a/prog")?;
+			util::dup2(file.as_raw_fd(), 100)?;
+			util::expect_errno(exec("/proc/self/fd/100"), libc::EACCES)
+		})?;
+		log!("Execute through a file descriptor of a second process");
+		util::in_child(|| {
+			let file = fs::File::open("exec_b/prog")?;
+			util::dup2(file.as_raw_fd(), 100)?;
+			util::expect_errno(exec("/proc/self/fd/100"), libc::ENOENT)
+		})?;
+		Ok(())
+	})();
+	log!("Cleanup");
+	fs::remove_dir_all("exec_a")?;
+	fs::remove_dir_all("exec_b")?;
+	res
+}
@@ -0,0 +1,227 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Sockets testing.
+
+use crate::{log, test_assert, test_assert_eq, util, util::TestResult};
+use std::{
+	io, mem,
+	mem::size_of,
+	net::{TcpListener, TcpStream},
+	os::{
+		fd::{AsRawFd, FromRawFd},
+		unix::net::UnixStream,
+	},
+	ptr,
+};
+
+/// Returns the credentials of the current process.
+fn current_cred() -> libc::ucred {
+	unsafe {
+		libc::ucred {
+			pid: libc::getpid(),
+			uid: libc::geteuid(),
+			gid: libc::getegid(),
+		}
+	}
+}
+
+/// Sends a byte on `sock`, along with the credentials `cred` if any.
+fn send_cred(sock: &UnixStream, cred: Option<libc::ucred>) -> io::Result<()> {
+	let mut data = 0u8;
+	let mut iov = libc::iovec {
+		iov_base: &mut data as *mut u8 as *mut _,
+		iov_len: 1,
+	};
+	let mut control = [0u8; 64];
+	let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+	msg.msg_iov = &mut iov;
+	msg.msg_iovlen = 1;
+	if let Some(cred) = cred {
+		msg.msg_control = control.as_mut_ptr() as *mut _;
+		msg.msg_controllen = unsafe { libc::CMSG_SPACE(size_of::<libc::ucred>() as _) } as _;
+		unsafe {
+			let hdr = libc::CMSG_FIRSTHDR(&msg);
+			(*hdr).cmsg_level = libc::SOL_SOCKET;
+			(*hdr).cmsg_type = libc::SCM_CREDENTIALS;
+			(*hdr).cmsg_len = libc::CMSG_LEN(size_of::<libc::ucred>() as _) as _;
+			ptr::write_unaligned(libc::CMSG_DATA(hdr) as *mut libc::ucred, cred);
+		}
+	}
+	let res = unsafe { libc::sendmsg(sock.as_raw_fd(), &msg, 0) };
+	if res < 0 {
+		return Err(io::Error::last_os_error());
+	}
+	Ok(())
+}
+
+/// Receives a byte from `sock`, returning the credentials received along with it, if any.
+fn recv_cred(sock: &UnixStream) -> io::Result<Option<libc::ucred>> {
+	let mut data = 0u8;
+	let mut iov = libc::iovec {
+		iov_base: &mut data as *mut u8 as *mut _,
+		iov_len: 1,
+	};
+	let mut control = [0u8; 64];
+	let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+	msg.msg_iov = &mut iov;
+	msg.msg_iovlen = 1;
+	msg.msg_control = control.as_mut_ptr() as *mut _;
+	msg.msg_controllen = control.len() as _;
+	let res = unsafe { libc::recvmsg(sock.as_raw_fd(), &mut msg, 0) };
+	if res < 0 {
+		return Err(io::Error::last_os_error());
+	}
+	unsafe {
+		let hdr = libc::CMSG_FIRSTHDR(&msg);
+		if hdr.is_null() {
+			return Ok(None);
+		}
+		if (*hdr).cmsg_level != libc::SOL_SOCKET || (*hdr).cmsg_type != libc::SCM_CREDENTIALS {
+			return Err(io::Error::from_raw_os_error(libc::EINVAL));
+		}
+		Ok(Some(ptr::read_unaligned(
+			libc::CMSG_DATA(hdr) as *const libc::ucred
+		)))
+	}
+}
+
+/// Compares credentials, since [`libc::ucred`] does not implement [`PartialEq`].
+fn cred_eq(a: &libc::ucred, b: &libc::ucred) -> bool {
+	a.pid == b.pid && a.uid == b.uid && a.gid == b.gid
+}
+
+pub fn peercred() -> TestResult {
+	log!("Create socket pair");
+	let (sock0, sock1) = UnixStream::pair()?;
+	log!("Get peer credentials");
+	for sock in [&sock0, &sock1] {
+		let mut cred: libc::ucred = unsafe { mem::zeroed() };
+		let mut len = size_of::<libc::ucred>() as libc::socklen_t;
+		let res = unsafe {
+			libc::getsockopt(
+				sock.as_raw_fd(),
+				libc::SOL_SOCKET,
+				libc::SO_PEERCRED,
+				&mut cred as *mut _ as *mut _,
+				&mut len,
+			)
+		};
+		test_assert_eq!(res, 0);
+		test_assert_eq!(len as usize, size_of::<libc::ucred>());
+		test_assert!(cred_eq(&cred, &current_cred()));
+	}
+	Ok(())
+}
+
+pub fn passcred() -> TestResult {
+	let (sock0, sock1) = UnixStream::pair()?;
+	log!("Receive without SO_PASSCRED");
+	send_cred(&sock0, None)?;
+	test_assert!(recv_cred(&sock1)?.is_none());
+	log!("Receive with SO_PASSCRED");
+	let enable: libc::c_int = 1;
+	let res = unsafe {
+		libc::setsockopt(
+			sock1.as_raw_fd(),
+			libc::SOL_SOCKET,
+			libc::SO_PASSCRED,
+			&enable as *const _ as *const _,
+			size_of::<libc::c_int>() as _,
+		)
+	};
+	test_assert_eq!(res, 0);
+	send_cred(&sock0, None)?;
+	let cred = recv_cred(&sock1)?;
+	test_assert!(cred.is_some_and(|cred| cred_eq(&cred, &current_cred())));
+	log!("Send explicit credentials");
+	send_cred(&sock0, Some(current_cred()))?;
+	let cred = recv_cred(&sock1)?;
+	test_assert!(cred.is_some_and(|cred| cred_eq(&cred, &current_cred())));
+	log!("Send an out of range PID");
+	for pid in [0, -1, current_cred().pid + 0x10000] {
+		let mut cred = current_cred();
+		cred.pid = pid;
+		util::expect_errno(send_cred(&sock0, Some(cred)), libc::ESRCH)?;
+	}
+	log!("Send forged credentials");
+	util::in_child(|| {
+		unsafe {
+			test_assert_eq!(libc::setgid(1000), 0);
+			test_assert_eq!(libc::setuid(1000), 0);
+		}
+		let mut cred = current_cred();
+		cred.pid = 1;
+		util::expect_errno(send_cred(&sock0, Some(cred)), libc::EPERM)?;
+		let mut cred = current_cred();
+		cred.uid = 0;
+		util::expect_errno(send_cred(&sock0, Some(cred)), libc::EPERM)?;
+		Ok(())
+	})
+}
+
+pub fn tcp_accept() -> TestResult {
+	log!("Listen");
+	let listener = TcpListener::bind("127.0.0.1:0")?;
+	let addr = listener.local_addr()?;
+	test_assert_eq!(unsafe { libc::listen(listener.as_raw_fd(), 1) }, 0);
+	let mut pfd = libc::pollfd {
+		fd: listener.as_raw_fd(),
+		events: libc::POLLIN,
+		revents: 0,
+	};
+	test_assert_eq!(unsafe { libc::poll(&mut pfd, 1, 0) }, 0);
+	log!("Connect");
+	let client = TcpStream::connect(addr)?;
+	test_assert_eq!(unsafe { libc::poll(&mut pfd, 1, 1000) }, 1);
+	test_assert_eq!(pfd.revents, libc::POLLIN);
+	log!("Accept");
+	let fd = unsafe {
+		libc::accept4(
+			listener.as_raw_fd(),
+			ptr::null_mut(),
+			ptr::null_mut(),
+			libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC,
+		)
+	};
+	test_assert!(fd >= 0);
+	let conn = unsafe { TcpStream::from_raw_fd(fd) };
+	test_assert!(unsafe { libc::fcntl(fd, libc::F_GETFL) } & libc::O_NONBLOCK != 0);
+	test_assert!(unsafe { libc::fcntl(fd, libc::F_GETFD) } & libc::FD_CLOEXEC != 0);
+	test_assert_eq!(conn.peer_addr()?, client.local_addr()?);
+	test_assert_eq!(client.peer_addr()?, conn.local_addr()?);
+	log!("Peer of a listening socket");
+	let mut addr: libc::sockaddr_in = unsafe { mem::zeroed() };
+	let mut len = size_of::<libc::sockaddr_in>() as libc::socklen_t;
+	let res = unsafe {
+		libc::getpeername(
+			listener.as_raw_fd(),
+			&mut addr as *mut _ as *mut libc::sockaddr,
+			&mut len,
+		)
+	};
+	test_assert_eq!(res, -1);
+	test_assert_eq!(
+		io::Error::last_os_error().raw_os_error(),
+		Some(libc::ENOTCONN)
+	);
+	log!("Accept without pending connection");
+	listener.set_nonblocking(true)?;
+	util::expect_errno(listener.accept(), libc::EAGAIN)?;
+	Ok(())
+}
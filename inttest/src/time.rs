@@ -0,0 +1,231 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Timers testing.
+
+use crate::{log, test_assert, test_assert_eq, util, util::TestResult};
+use std::{
+	fs, io, mem,
+	os::fd::{AsRawFd, FromRawFd, OwnedFd},
+	ptr::null_mut,
+	thread,
+	time::Duration,
+};
+
+/// Returns the specification of a timer firing every `ms` milliseconds.
+fn periodic(ms: i64) -> libc::itimerspec {
+	let ts = libc::timespec {
+		tv_sec: (ms / 1000) as _,
+		tv_nsec: ((ms % 1000) * 1_000_000) as _,
+	};
+	libc::itimerspec {
+		it_interval: ts,
+		it_value: ts,
+	}
+}
+
+/// Returns the current time of the clock `clk` in nanoseconds.
+fn now(clk: libc::clockid_t) -> io::Result<i64> {
+	let mut ts: libc::timespec = unsafe { mem::zeroed() };
+	let res = unsafe { libc::clock_gettime(clk, &mut ts) };
+	if res < 0 {
+		return Err(io::Error::last_os_error());
+	}
+	Ok(ts.tv_sec as i64 * 1_000_000_000 + ts.tv_nsec as i64)
+}
+
+pub fn coarse_clocks() -> TestResult {
+	log!("Resolution");
+	let mut res: libc::timespec = unsafe { mem::zeroed() };
+	let ret = unsafe { libc::clock_getres(libc::CLOCK_MONOTONIC_COARSE, &mut res) };
+	test_assert_eq!(ret, 0);
+	test_assert_eq!(res.tv_sec, 0);
+	test_assert!(res.tv_nsec > 0);
+	let ret = unsafe { libc::clock_getres(-1, &mut res) };
+	test_assert_eq!(ret, -1);
+	test_assert_eq!(
+		io::Error::last_os_error().raw_os_error(),
+		Some(libc::EINVAL)
+	);
+	// Clocks are updated on ticks, so coarse clocks match the precise ones read around them
+	for (coarse, precise) in [
+		(libc::CLOCK_MONOTONIC_COARSE, libc::CLOCK_MONOTONIC),
+		(libc::CLOCK_REALTIME_COARSE, libc::CLOCK_REALTIME),
+	] {
+		log!("Clock {coarse} against clock {precise}");
+		let mut last = now(coarse)?;
+		for _ in 0..1000 {
+			let before = now(precise)?;
+			let cur = now(coarse)?;
+			let after = now(precise)?;
+			test_assert!(before <= cur && cur <= after);
+			test_assert!(cur >= last);
+			last = cur;
+		}
+		log!("Clock {coarse} advances");
+		let start = now(coarse)?;
+		thread::sleep(Duration::from_millis(50));
+		test_assert!(now(coarse)? >= start + 40_000_000);
+	}
+	log!("Time of day");
+	let before = now(libc::CLOCK_REALTIME)? / 1_000_000_000;
+	let time = unsafe { libc::time(null_mut()) } as i64;
+	test_assert!(time >= before && time <= now(libc::CLOCK_REALTIME)? / 1_000_000_000);
+	Ok(())
+}
+
+pub fn posix_timer() -> TestResult {
+	log!("Create timer");
+	let mut sevp: libc::sigevent = unsafe { mem::zeroed() };
+	sevp.sigev_notify = libc::SIGEV_NONE;
+	let mut timer: libc::timer_t = null_mut();
+	let res = unsafe { libc::timer_create(libc::CLOCK_MONOTONIC, &mut sevp, &mut timer) };
+	test_assert_eq!(res, 0);
+	log!("Arm");
+	let spec = periodic(60_000);
+	let res = unsafe { libc::timer_settime(timer, 0, &spec, null_mut()) };
+	test_assert_eq!(res, 0);
+	let mut curr: libc::itimerspec = unsafe { mem::zeroed() };
+	let res = unsafe { libc::timer_gettime(timer, &mut curr) };
+	test_assert_eq!(res, 0);
+	test_assert_eq!(curr.it_interval.tv_sec, 60);
+	test_assert!(curr.it_value.tv_sec > 0 && curr.it_value.tv_sec <= 60);
+	log!("Disarm");
+	let spec: libc::itimerspec = unsafe { mem::zeroed() };
+	let res = unsafe { libc::timer_settime(timer, 0, &spec, null_mut()) };
+	test_assert_eq!(res, 0);
+	let res = unsafe { libc::timer_gettime(timer, &mut curr) };
+	test_assert_eq!(res, 0);
+	test_assert_eq!((curr.it_value.tv_sec, curr.it_value.tv_nsec), (0, 0));
+	log!("Delete");
+	let res = unsafe { libc::timer_delete(timer) };
+	test_assert_eq!(res, 0);
+	Ok(())
+}
+
+pub fn timerfd() -> TestResult {
+	log!("Invalid clock");
+	let res = unsafe { libc::timerfd_create(libc::CLOCK_PROCESS_CPUTIME_ID, 0) };
+	util::expect_errno(
+		if res < 0 {
+			Err(io::Error::last_os_error())
+		} else {
+			Ok(res)
+		},
+		libc::EINVAL,
+	)?;
+	log!("Create timer");
+	let fd = unsafe { libc::timerfd_create(libc::CLOCK_MONOTONIC, libc::TFD_NONBLOCK) };
+	test_assert!(fd >= 0);
+	let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+	let mut count = 0u64;
+	let read = |count: &mut u64| {
+		let res = unsafe { libc::read(fd.as_raw_fd(), count as *mut u64 as *mut _, 8) };
+		if res < 0 {
+			Err(io::Error::last_os_error())
+		} else {
+			Ok(res)
+		}
+	};
+	log!("Read disarmed timer");
+	util::expect_errno(read(&mut count), libc::EAGAIN)?;
+	log!("Arm");
+	let spec = periodic(10);
+	let res = unsafe { libc::timerfd_settime(fd.as_raw_fd(), 0, &spec, null_mut()) };
+	test_assert_eq!(res, 0);
+	let mut curr: libc::itimerspec = unsafe { mem::zeroed() };
+	let res = unsafe { libc::timerfd_gettime(fd.as_raw_fd(), &mut curr) };
+	test_assert_eq!(res, 0);
+	test_assert_eq!(curr.it_interval.tv_nsec, 10_000_000);
+	log!("Poll");
+	let mut pfd = libc::pollfd {
+		fd: fd.as_raw_fd(),
+		events: libc::POLLIN,
+		revents: 0,
+	};
+	let res = unsafe { libc::poll(&mut pfd, 1, 1000) };
+	test_assert_eq!(res, 1);
+	test_assert!(pfd.revents & libc::POLLIN != 0);
+	log!("Read expirations");
+	test_assert_eq!(read(&mut count)?, 8);
+	test_assert!(count >= 1);
+	log!("Disarm");
+	let spec: libc::itimerspec = unsafe { mem::zeroed() };
+	let res = unsafe { libc::timerfd_settime(fd.as_raw_fd(), 0, &spec, &mut curr) };
+	test_assert_eq!(res, 0);
+	test_assert_eq!(curr.it_interval.tv_nsec, 10_000_000);
+	let res = unsafe { libc::timerfd_gettime(fd.as_raw_fd(), &mut curr) };
+	test_assert_eq!(res, 0);
+	test_assert_eq!((curr.it_value.tv_sec, curr.it_value.tv_nsec), (0, 0));
+	util::expect_errno(read(&mut count), libc::EAGAIN)?;
+	Ok(())
+}
+
+pub fn namespace_abstime() -> TestResult {
+	/// Not defined by the `libc` crate for musl.
+	const CLONE_NEWTIME: i32 = 0x80;
+	// Run in a child so that processes created by other tests remain in the root namespace
+	util::in_child(|| {
+		log!("Create namespace");
+		util::unshare(CLONE_NEWTIME)?;
+		fs::write("/proc/self/timens_offsets", "monotonic 86400 0\n")?;
+		util::in_child(|| {
+			// A deadline shortly after the current time of the namespace
+			let mut spec: libc::itimerspec = unsafe { mem::zeroed() };
+			let res = unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut spec.it_value) };
+			test_assert_eq!(res, 0);
+			spec.it_value.tv_nsec += 10_000_000;
+			if spec.it_value.tv_nsec >= 1_000_000_000 {
+				spec.it_value.tv_sec += 1;
+				spec.it_value.tv_nsec -= 1_000_000_000;
+			}
+			log!("Arm per-process timer with an absolute deadline");
+			let mut sevp: libc::sigevent = unsafe { mem::zeroed() };
+			sevp.sigev_notify = libc::SIGEV_NONE;
+			let mut timer: libc::timer_t = null_mut();
+			let res = unsafe { libc::timer_create(libc::CLOCK_MONOTONIC, &mut sevp, &mut timer) };
+			test_assert_eq!(res, 0);
+			let res =
+				unsafe { libc::timer_settime(timer, libc::TIMER_ABSTIME, &spec, null_mut()) };
+			test_assert_eq!(res, 0);
+			let mut curr: libc::itimerspec = unsafe { mem::zeroed() };
+			let res = unsafe { libc::timer_gettime(timer, &mut curr) };
+			test_assert_eq!(res, 0);
+			// The deadline is not shifted by the offset of the namespace
+			test_assert_eq!(curr.it_value.tv_sec, 0);
+			let res = unsafe { libc::timer_delete(timer) };
+			test_assert_eq!(res, 0);
+			log!("Arm timerfd with an absolute deadline");
+			let fd = unsafe { libc::timerfd_create(libc::CLOCK_MONOTONIC, 0) };
+			test_assert!(fd >= 0);
+			let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+			let res = unsafe {
+				libc::timerfd_settime(fd.as_raw_fd(), libc::TFD_TIMER_ABSTIME, &spec, null_mut())
+			};
+			test_assert_eq!(res, 0);
+			let mut pfd = libc::pollfd {
+				fd: fd.as_raw_fd(),
+				events: libc::POLLIN,
+				revents: 0,
+			};
+			let res = unsafe { libc::poll(&mut pfd, 1, 1000) };
+			test_assert_eq!(res, 1);
+			Ok(())
+		})
+	})
+}
@@ -18,12 +18,16 @@
 
 //! Utility features.
 
-use libc::{gid_t, mode_t, uid_t};
+use libc::{gid_t, mode_t, off_t, uid_t};
 use std::{
 	error::Error,
-	ffi::{c_int, c_ulong, c_void, CStr, CString},
+	ffi::{c_int, c_uint, c_ulong, c_void, CStr, CString},
+	fmt::Debug,
 	io, mem,
-	os::unix::ffi::OsStrExt,
+	os::{
+		fd::{FromRawFd, OwnedFd},
+		unix::ffi::OsStrExt,
+	},
 	path::Path,
 	process::{Command, Stdio},
 };
@@ -68,6 +72,17 @@ macro_rules! test_assert_eq {
 	}};
 }
 
+/// Checks that `res` is an error with the given `errno`.
+pub fn expect_errno<T: Debug>(res: io::Result<T>, errno: c_int) -> TestResult {
+	match res {
+		Err(e) if e.raw_os_error() == Some(errno) => Ok(()),
+		res => Err(TestError(format!(
+			"Expected errno {errno} ({}), got `{res:?}`",
+			io::Error::from_raw_os_error(errno)
+		))),
+	}
+}
+
 /// Prints a log.
 #[macro_export]
 macro_rules! log {
@@ -130,6 +145,36 @@ pub fn fstat(fd: c_int) -> io::Result<libc::stat> {
 	}
 }
 
+pub fn fstatvfs(fd: c_int) -> io::Result<libc::statvfs> {
+	unsafe {
+		let mut stat: libc::statvfs = mem::zeroed();
+		let res = libc::fstatvfs(fd, &mut stat);
+		if res >= 0 {
+			Ok(stat)
+		} else {
+			Err(io::Error::last_os_error())
+		}
+	}
+}
+
+pub fn uname() -> io::Result<libc::utsname> {
+	unsafe {
+		let mut buf: libc::utsname = mem::zeroed();
+		let res = libc::uname(&mut buf);
+		if res >= 0 {
+			Ok(buf)
+		} else {
+			Err(io::Error::last_os_error())
+		}
+	}
+}
+
+/// Returns the content of the nul-terminated string stored in the array `field`.
+pub fn c_str_array(field: &[libc::c_char]) -> String {
+	let field = unsafe { CStr::from_ptr(field.as_ptr()) };
+	field.to_string_lossy().into_owned()
+}
+
 pub fn mkfifo<P: AsRef<Path>>(path: P, mode: mode_t) -> io::Result<()> {
 	let path = CString::new(path.as_ref().as_os_str().as_bytes())?;
 	let res = unsafe { libc::mkfifo(path.as_ptr(), mode) };
@@ -140,6 +185,70 @@ pub fn mkfifo<P: AsRef<Path>>(path: P, mode: mode_t) -> io::Result<()> {
 	}
 }
 
+pub fn mknod<P: AsRef<Path>>(path: P, mode: mode_t, major: u32, minor: u32) -> io::Result<()> {
+	let path = CString::new(path.as_ref().as_os_str().as_bytes())?;
+	let dev = libc::makedev(major, minor);
+	let res = unsafe { libc::mknod(path.as_ptr(), mode, dev) };
+	if res >= 0 {
+		Ok(())
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+pub fn pipe() -> io::Result<(OwnedFd, OwnedFd)> {
+	let mut fds: [c_int; 2] = [0; 2];
+	let res = unsafe { libc::pipe(fds.as_mut_ptr()) };
+	if res >= 0 {
+		unsafe { Ok((OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1]))) }
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+pub fn mmap(
+	addr: *mut c_void,
+	len: usize,
+	prot: c_int,
+	flags: c_int,
+	fd: c_int,
+	off: off_t,
+) -> io::Result<*mut c_void> {
+	let ptr = unsafe { libc::mmap(addr, len, prot, flags, fd, off) };
+	if ptr != libc::MAP_FAILED {
+		Ok(ptr)
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+pub fn munmap(addr: *mut c_void, len: usize) -> io::Result<()> {
+	let res = unsafe { libc::munmap(addr, len) };
+	if res >= 0 {
+		Ok(())
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+pub fn mprotect(addr: *mut c_void, len: usize, prot: c_int) -> io::Result<()> {
+	let res = unsafe { libc::mprotect(addr, len, prot) };
+	if res >= 0 {
+		Ok(())
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+pub fn madvise(addr: *mut c_void, len: usize, advice: c_int) -> io::Result<()> {
+	let res = unsafe { libc::madvise(addr, len, advice) };
+	if res >= 0 {
+		Ok(())
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
 pub fn mount(
 	src: &CStr,
 	target: &CStr,
@@ -155,6 +264,15 @@ pub fn mount(
 	}
 }
 
+pub fn umount(target: &CStr) -> io::Result<()> {
+	let res = unsafe { libc::umount(target.as_ptr()) };
+	if res >= 0 {
+		Ok(())
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
 pub fn seteuid(uid: uid_t) -> io::Result<()> {
 	let res = unsafe { libc::seteuid(uid) };
 	if res >= 0 {
@@ -183,6 +301,87 @@ pub fn unprivileged<F: FnOnce() -> R, R>(f: F) -> io::Result<R> {
 	Ok(res)
 }
 
+/// Executes the given code in a child process, which is useful for tests changing the state of
+/// the process (root directory, credentials, etc...).
+pub fn in_child<F: FnOnce() -> TestResult>(f: F) -> TestResult {
+	let pid = unsafe { libc::fork() };
+	if pid < 0 {
+		return Err(io::Error::last_os_error().into());
+	}
+	if pid == 0 {
+		let code = match f() {
+			Ok(()) => 0,
+			Err(TestError(e)) => {
+				eprintln!("{e}");
+				1
+			}
+		};
+		unsafe {
+			libc::_exit(code);
+		}
+	}
+	let mut status = 0;
+	let res = unsafe { libc::waitpid(pid, &mut status, 0) };
+	if res < 0 {
+		return Err(io::Error::last_os_error().into());
+	}
+	if libc::WIFEXITED(status) && libc::WEXITSTATUS(status) == 0 {
+		Ok(())
+	} else {
+		Err(TestError(format!(
+			"Child process failed (status: {status})"
+		)))
+	}
+}
+
+pub fn chroot<P: AsRef<Path>>(path: P) -> io::Result<()> {
+	let path = CString::new(path.as_ref().as_os_str().as_bytes())?;
+	let res = unsafe { libc::chroot(path.as_ptr()) };
+	if res >= 0 {
+		Ok(())
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+pub fn renameat2<P: AsRef<Path>, Q: AsRef<Path>>(old: P, new: Q, flags: c_uint) -> io::Result<()> {
+	let old = CString::new(old.as_ref().as_os_str().as_bytes())?;
+	let new = CString::new(new.as_ref().as_os_str().as_bytes())?;
+	let res = unsafe {
+		libc::syscall(
+			libc::SYS_renameat2,
+			libc::AT_FDCWD,
+			old.as_ptr(),
+			libc::AT_FDCWD,
+			new.as_ptr(),
+			flags,
+		)
+	};
+	if res >= 0 {
+		Ok(())
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+pub fn dup2(oldfd: c_int, newfd: c_int) -> io::Result<()> {
+	let res = unsafe { libc::dup2(oldfd, newfd) };
+	if res >= 0 {
+		Ok(())
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+pub fn unshare(flags: c_int) -> io::Result<()> {
+	let res = unsafe { libc::unshare(flags) };
+	if res >= 0 {
+		Ok(())
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
 /// Executes the given command and returns a [`Result`] corresponding to the exit status.
 pub fn exec(cmd: &mut Command) -> TestResult {
 	// TODO capture output and compare to expected output?
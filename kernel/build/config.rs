@@ -19,17 +19,11 @@
 //! This file implements the configuration file for compilation.
 
 use serde::Deserialize;
-use std::{fs, io};
+use std::{fs, io, path::Path};
 
 /// The debug section of the configuration file.
 #[derive(Deserialize)]
 struct ConfigDebug {
-	/// If enabled, the kernel tests storage.
-	///
-	/// **Warning**: this option is destructive for any data present on disks connected to the
-	/// host.
-	storage_test: bool,
-
 	/// If enabled, the kernel is compiled for QEMU. This feature is not *required* for QEMU but
 	/// it can provide additional features.
 	qemu: bool,
@@ -68,21 +62,45 @@ impl Config {
 			.map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))
 	}
 
+	/// Returns the list of cfg flags, along with whether they are enabled.
+	///
+	/// Debug options are enabled only when compiling in debug mode.
+	fn flags(&self, debug: bool) -> [(&'static str, bool); 3] {
+		let d = &self.debug;
+		[
+			("config_debug_qemu", debug && d.qemu),
+			("config_debug_malloc_magic", debug && d.malloc_magic),
+			("config_debug_malloc_check", debug && d.malloc_check),
+		]
+	}
+
 	/// Sets the crate's cfg flags according to the configuration.
 	pub fn set_cfg(&self, debug: bool) {
-		if debug {
-			if self.debug.storage_test {
-				println!("cargo:rustc-cfg=config_debug_storage_test");
-			}
-			if self.debug.qemu {
-				println!("cargo:rustc-cfg=config_debug_qemu");
+		for (name, enabled) in self.flags(debug) {
+			if enabled {
+				println!("cargo:rustc-cfg={name}");
 			}
-			if self.debug.malloc_magic {
-				println!("cargo:rustc-cfg=config_debug_malloc_magic");
-			}
-			if self.debug.malloc_check {
-				println!("cargo:rustc-cfg=config_debug_malloc_check");
+		}
+	}
+
+	/// Writes the effective configuration to a file in `out_dir`, so that it can be embedded in
+	/// the kernel and exposed to userspace.
+	///
+	/// The path to the file is passed to the rest of the codebase through the `CONFIG_PATH`
+	/// environment variable.
+	pub fn write(&self, debug: bool, out_dir: &Path) -> io::Result<()> {
+		let mut content = String::new();
+		for (name, enabled) in self.flags(debug) {
+			let name = name.to_uppercase();
+			if enabled {
+				content.push_str(&format!("{name}=y\n"));
+			} else {
+				content.push_str(&format!("# {name} is not set\n"));
 			}
 		}
+		let path = out_dir.join("config");
+		fs::write(&path, content)?;
+		println!("cargo:rustc-env=CONFIG_PATH={}", path.display());
+		Ok(())
 	}
 }
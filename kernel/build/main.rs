@@ -7,7 +7,11 @@ pub mod target;
 pub mod util;
 
 use crate::{config::Config, target::Target};
-use std::{env, path::PathBuf, process::exit};
+use std::{
+	env, io,
+	path::PathBuf,
+	process::{exit, Command},
+};
 
 /// The environment passed to the build script.
 pub struct Env {
@@ -21,6 +25,8 @@ pub struct Env {
 	pub arch: String,
 	/// The path to the target file.
 	pub target_path: PathBuf,
+	/// The directory in which generated files are placed.
+	pub out_dir: PathBuf,
 }
 
 impl Env {
@@ -32,12 +38,14 @@ impl Env {
 		// Unwrapping is safe because a default target is specified in `.cargo/config.toml`
 		let arch = env::var("CARGO_CFG_TARGET_ARCH").unwrap();
 		let target_path = manifest_dir.join(format!("arch/{arch}/{arch}.json"));
+		let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
 		Self {
 			manifest_dir,
 			profile,
 			opt_level,
 			arch,
 			target_path,
+			out_dir,
 		}
 	}
 
@@ -47,6 +55,16 @@ impl Env {
 	}
 }
 
+/// Passes the version of the compiler to the rest of the codebase, through the `RUSTC_VERSION`
+/// environment variable.
+fn pass_rustc_version() -> io::Result<()> {
+	let rustc = env::var("RUSTC").unwrap();
+	let output = Command::new(rustc).arg("--version").output()?;
+	let version = String::from_utf8_lossy(&output.stdout);
+	println!("cargo:rustc-env=RUSTC_VERSION={}", version.trim());
+	Ok(())
+}
+
 fn main() {
 	// Read config
 	let env = Env::get();
@@ -59,6 +77,16 @@ fn main() {
 		exit(1);
 	});
 	config.set_cfg(env.is_debug());
+	config
+		.write(env.is_debug(), &env.out_dir)
+		.unwrap_or_else(|e| {
+			eprintln!("Failed to write configuration file: {e}");
+			exit(1);
+		});
+	pass_rustc_version().unwrap_or_else(|e| {
+		eprintln!("Cannot retrieve compiler version: {e}");
+		exit(1);
+	});
 	// Compile
 	compile::compile_c(&env, &target).unwrap_or_else(|e| {
 		eprintln!("Compilation failed: {e}");
@@ -16,6 +16,11 @@ case $1 in
 		cargo run
 		STATUS=$?
 		set -e
+		# Check that processes have been terminated and their data written back before powering off
+		if [ "$(debugfs -R 'cat /shutdown_marker' qemu_disk 2>/dev/null)" != "SIGTERM" ]; then
+			>&2 echo "Data written on shutdown has been lost"
+			STATUS=1
+		fi
 		# FIXME: the clock currently starts at the timestamp zero, which causes fsck to detect errors due to the low value for dtime
 		#fsck.ext2 -fnv qemu_disk
 		exit $STATUS
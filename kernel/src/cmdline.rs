@@ -18,7 +18,7 @@
 
 //! Boot-time kernel command line arguments parsing.
 
-use crate::tty::vga;
+use crate::{device::console::MAX_CONSOLES, tty::vga};
 use core::{cmp::min, fmt, str};
 use utils::DisplayableStr;
 
@@ -121,6 +121,38 @@ impl<'s> Iterator for TokenIterator<'s> {
 	}
 }
 
+/// Parses a module parameter argument, in the form `<module>.<name>=<value>`.
+///
+/// Parameters of the kernel itself can also be passed without module, in the form
+/// `<name>=<value>`, in which case the module is `kernel`.
+///
+/// On success, the function returns the module, name and value. If the argument is not a module
+/// parameter, the function returns `None`.
+fn parse_param(s: &[u8]) -> Option<(&[u8], &[u8], &[u8])> {
+	let is_ident =
+		|s: &[u8]| !s.is_empty() && s.iter().all(|c| c.is_ascii_alphanumeric() || *c == b'_');
+	let eq = s.iter().position(|c| *c == b'=')?;
+	let (key, val) = (&s[..eq], &s[(eq + 1)..]);
+	let (module, name) = match key.iter().position(|c| *c == b'.') {
+		Some(dot) => (&key[..dot], &key[(dot + 1)..]),
+		None => (&b"kernel"[..], key),
+	};
+	(is_ident(module) && is_ident(name)).then_some((module, name, val))
+}
+
+/// Returns an iterator over the module parameters present on the given command line.
+///
+/// Each item is a tuple containing the module, name and value of the parameter.
+///
+/// For more information about parameters, see [`crate::module::param`].
+pub fn params(cmdline: &[u8]) -> impl Iterator<Item = (&[u8], &[u8], &[u8])> {
+	TokenIterator {
+		s: cmdline,
+		cursor: 0,
+	}
+	.filter_map(|tok| parse_param(tok.s))
+}
+
 /// Command line argument parser.
 ///
 /// Every bytes in the command line are interpreted as ASCII characters.
@@ -131,6 +163,10 @@ pub struct ArgsParser<'s> {
 	init: Option<&'s [u8]>,
 	/// Whether the kernel boots silently.
 	silent: bool,
+	/// The names of the consoles to use.
+	consoles: [&'s [u8]; MAX_CONSOLES],
+	/// The number of consoles specified.
+	consoles_count: usize,
 }
 
 impl<'s> ArgsParser<'s> {
@@ -140,6 +176,8 @@ impl<'s> ArgsParser<'s> {
 			root: None,
 			init: None,
 			silent: false,
+			consoles: [&[]; MAX_CONSOLES],
+			consoles_count: 0,
 		};
 
 		let mut iter = TokenIterator {
@@ -192,6 +230,28 @@ impl<'s> ArgsParser<'s> {
 
 				b"-silent" => s.silent = true,
 
+				b"-console" => {
+					let Some((_, console)) = iter.next() else {
+						return Err(ParseError {
+							cmdline,
+							err: "not enough arguments for `-console`",
+							token: Some((token.begin, token.s.len())),
+						});
+					};
+					if s.consoles_count >= MAX_CONSOLES {
+						return Err(ParseError {
+							cmdline,
+							err: "too many consoles",
+							token: Some((console.begin, console.s.len())),
+						});
+					}
+					s.consoles[s.consoles_count] = console.s;
+					s.consoles_count += 1;
+				}
+
+				// Module parameters are handled separately
+				tok if parse_param(tok).is_some() => {}
+
 				_ => {
 					return Err(ParseError {
 						cmdline,
@@ -219,6 +279,13 @@ impl<'s> ArgsParser<'s> {
 	pub fn is_silent(&self) -> bool {
 		self.silent
 	}
+
+	/// Returns the names of the consoles to use, in the order they have been specified.
+	///
+	/// If empty, the default consoles shall be used.
+	pub fn get_consoles(&self) -> &[&'s [u8]] {
+		&self.consoles[..self.consoles_count]
+	}
 }
 
 #[cfg(test)]
@@ -264,4 +331,48 @@ mod test {
 	fn cmdline7() {
 		assert!(ArgsParser::parse(b"-root 1 0 -init bleh -silent").is_ok());
 	}
+
+	#[test_case]
+	fn cmdline8() {
+		assert!(ArgsParser::parse(b"ide.=16").is_err());
+		assert!(ArgsParser::parse(b".queue_depth=16").is_err());
+		assert!(ArgsParser::parse(b"ide.queue_depth").is_err());
+	}
+
+	#[test_case]
+	fn cmdline9() {
+		let cmdline = b"-init /a.b=c ide.queue_depth=16 -silent net.mtu=";
+		assert!(ArgsParser::parse(cmdline).is_ok());
+		let mut iter = params(cmdline);
+		assert_eq!(
+			iter.next(),
+			Some((&b"ide"[..], &b"queue_depth"[..], &b"16"[..]))
+		);
+		assert_eq!(iter.next(), Some((&b"net"[..], &b"mtu"[..], &b""[..])));
+		assert_eq!(iter.next(), None);
+	}
+
+	#[test_case]
+	fn cmdline10() {
+		assert!(ArgsParser::parse(b"-console").is_err());
+		let args = ArgsParser::parse(b"-console ttyS0 -silent -console tty0").unwrap();
+		assert_eq!(args.get_consoles(), &[&b"ttyS0"[..], &b"tty0"[..]]);
+	}
+
+	#[test_case]
+	fn cmdline11() {
+		assert!(ArgsParser::parse(b"=5").is_err());
+		let cmdline = b"-silent panic=5 kernel.panic=-1";
+		assert!(ArgsParser::parse(cmdline).is_ok());
+		let mut iter = params(cmdline);
+		assert_eq!(
+			iter.next(),
+			Some((&b"kernel"[..], &b"panic"[..], &b"5"[..]))
+		);
+		assert_eq!(
+			iter.next(),
+			Some((&b"kernel"[..], &b"panic"[..], &b"-1"[..]))
+		);
+		assert_eq!(iter.next(), None);
+	}
 }
@@ -63,6 +63,17 @@ pub fn get_rflags() -> usize {
 	flags
 }
 
+/// Returns the value of the CPU's timestamp counter, which is incremented at each clock cycle.
+#[inline]
+pub fn rdtsc() -> u64 {
+	let lo: u32;
+	let hi: u32;
+	unsafe {
+		asm!("rdtsc", out("eax") lo, out("edx") hi, options(nomem, nostack));
+	}
+	((hi as u64) << 32) | lo as u64
+}
+
 /// Calls the CPUID instruction.
 #[inline]
 pub fn cpuid(mut eax: u32, mut ebx: u32, mut ecx: u32, mut edx: u32) -> (u32, u32, u32, u32) {
@@ -79,6 +90,16 @@ pub fn cpuid(mut eax: u32, mut ebx: u32, mut ecx: u32, mut edx: u32) -> (u32, u3
 	(eax, ebx, ecx, edx)
 }
 
+/// Returns the ID of the CPU executing the current code.
+///
+/// IDs are numbered contiguously from `0`. Since only one CPU is used for now, this function
+/// always returns `0`.
+#[inline]
+pub fn current_id() -> u32 {
+	// TODO read from per-CPU data when SMP is supported
+	0
+}
+
 /// Returns HWCAP bitmask for ELF.
 #[inline]
 pub fn get_hwcap() -> u32 {
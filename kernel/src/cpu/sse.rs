@@ -18,7 +18,11 @@
 
 //! SSE-related features.
 
-use crate::{cpu::get_hwcap, register_get, register_set};
+use crate::{
+	cpu::get_hwcap,
+	process::regs::{restore_fxstate, save_fxstate},
+	register_get, register_set,
+};
 
 /// Tells whether the CPU supports SSE.
 pub fn is_present() -> bool {
@@ -36,3 +40,15 @@ pub fn enable() {
 		register_set!("cr4", cr4);
 	}
 }
+
+/// Executes `f`, preserving the x87 FPU, MMX and SSE state around it.
+///
+/// The kernel does not use these registers, so they hold the state of the current process. Kernel
+/// code using SIMD instructions must run inside this function so that this state is not lost.
+pub fn preserve<T, F: FnOnce() -> T>(f: F) -> T {
+	let mut fxstate = [0u8; 512];
+	save_fxstate(&mut fxstate);
+	let res = f();
+	restore_fxstate(&fxstate);
+	res
+}
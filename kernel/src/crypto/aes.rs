@@ -0,0 +1,313 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Implementation of the AES block cipher, as specified by FIPS 197.
+//!
+//! The software implementation uses lookup tables, so its timing may depend on the data. When the
+//! CPU supports AES-NI, it is used instead.
+
+use crate::{cpu::sse, crypto::BlockCipher};
+use core::{
+	arch::x86::*,
+	sync::atomic::{AtomicBool, Ordering::Relaxed},
+};
+use utils::{errno, errno::EResult};
+
+/// Tells whether the CPU supports AES-NI.
+pub(super) static AES_NI: AtomicBool = AtomicBool::new(false);
+
+/// Multiplies `a` by `x` in GF(2^8).
+const fn xtime(a: u8) -> u8 {
+	(a << 1) ^ if a & 0x80 != 0 { 0x1b } else { 0 }
+}
+
+/// Multiplies `a` and `b` in GF(2^8).
+const fn gmul(mut a: u8, mut b: u8) -> u8 {
+	let mut res = 0;
+	while b != 0 {
+		if b & 1 != 0 {
+			res ^= a;
+		}
+		a = xtime(a);
+		b >>= 1;
+	}
+	res
+}
+
+/// Generates the S-box and its inverse.
+const fn gen_sbox() -> ([u8; 256], [u8; 256]) {
+	let mut sbox = [0; 256];
+	let mut inv = [0; 256];
+	// `p` goes through every non-zero element using the generator `3`, while `q` goes through
+	// their inverses
+	let mut p: u8 = 1;
+	let mut q: u8 = 1;
+	loop {
+		p ^= xtime(p);
+		q ^= q << 1;
+		q ^= q << 2;
+		q ^= q << 4;
+		if q & 0x80 != 0 {
+			q ^= 0x09;
+		}
+		let x = q ^ q.rotate_left(1) ^ q.rotate_left(2) ^ q.rotate_left(3) ^ q.rotate_left(4);
+		sbox[p as usize] = x ^ 0x63;
+		inv[(x ^ 0x63) as usize] = p;
+		if p == 1 {
+			break;
+		}
+	}
+	sbox[0] = 0x63;
+	inv[0x63] = 0;
+	(sbox, inv)
+}
+
+/// The S-box.
+const SBOX: [u8; 256] = gen_sbox().0;
+/// The inverse S-box.
+const INV_SBOX: [u8; 256] = gen_sbox().1;
+
+/// Applies MixColumns to `state`.
+fn mix_columns(state: &mut [u8; 16]) {
+	for col in state.chunks_exact_mut(4) {
+		let [a, b, c, d] = [col[0], col[1], col[2], col[3]];
+		let all = a ^ b ^ c ^ d;
+		col[0] ^= all ^ xtime(a ^ b);
+		col[1] ^= all ^ xtime(b ^ c);
+		col[2] ^= all ^ xtime(c ^ d);
+		col[3] ^= all ^ xtime(d ^ a);
+	}
+}
+
+/// Applies InvMixColumns to `state`.
+fn inv_mix_columns(state: &mut [u8; 16]) {
+	for col in state.chunks_exact_mut(4) {
+		let [a, b, c, d] = [col[0], col[1], col[2], col[3]];
+		col[0] = gmul(a, 14) ^ gmul(b, 11) ^ gmul(c, 13) ^ gmul(d, 9);
+		col[1] = gmul(a, 9) ^ gmul(b, 14) ^ gmul(c, 11) ^ gmul(d, 13);
+		col[2] = gmul(a, 13) ^ gmul(b, 9) ^ gmul(c, 14) ^ gmul(d, 11);
+		col[3] = gmul(a, 11) ^ gmul(b, 13) ^ gmul(c, 9) ^ gmul(d, 14);
+	}
+}
+
+/// Applies SubBytes and ShiftRows to `state`.
+fn sub_shift_rows(state: &mut [u8; 16]) {
+	let old = *state;
+	for (i, b) in state.iter_mut().enumerate() {
+		let (row, col) = (i % 4, i / 4);
+		*b = SBOX[old[row + 4 * ((col + row) % 4)] as usize];
+	}
+}
+
+/// Applies InvShiftRows and InvSubBytes to `state`.
+fn inv_sub_shift_rows(state: &mut [u8; 16]) {
+	let old = *state;
+	for (i, b) in state.iter_mut().enumerate() {
+		let (row, col) = (i % 4, i / 4);
+		*b = INV_SBOX[old[row + 4 * ((col + 4 - row) % 4)] as usize];
+	}
+}
+
+/// XORs `key` into `state`.
+fn add_round_key(state: &mut [u8; 16], key: &[u8; 16]) {
+	for (s, k) in state.iter_mut().zip(key) {
+		*s ^= k;
+	}
+}
+
+/// Encrypts `block` with AES-NI.
+///
+/// # Safety
+///
+/// The CPU must support AES-NI, and the SSE state must be preserved by the caller.
+#[target_feature(enable = "aes,sse2")]
+unsafe fn encrypt_ni(keys: &[[u8; 16]], block: &mut [u8; 16]) {
+	let last = keys.len() - 1;
+	let mut state = _mm_loadu_si128(block.as_ptr() as *const __m128i);
+	state = _mm_xor_si128(state, _mm_loadu_si128(keys[0].as_ptr() as *const __m128i));
+	for key in &keys[1..last] {
+		state = _mm_aesenc_si128(state, _mm_loadu_si128(key.as_ptr() as *const __m128i));
+	}
+	state = _mm_aesenclast_si128(
+		state,
+		_mm_loadu_si128(keys[last].as_ptr() as *const __m128i),
+	);
+	_mm_storeu_si128(block.as_mut_ptr() as *mut __m128i, state);
+}
+
+/// Decrypts `block` with AES-NI, using the keys of the equivalent inverse cipher.
+///
+/// # Safety
+///
+/// The CPU must support AES-NI, and the SSE state must be preserved by the caller.
+#[target_feature(enable = "aes,sse2")]
+unsafe fn decrypt_ni(keys: &[[u8; 16]], block: &mut [u8; 16]) {
+	let last = keys.len() - 1;
+	let mut state = _mm_loadu_si128(block.as_ptr() as *const __m128i);
+	state = _mm_xor_si128(state, _mm_loadu_si128(keys[0].as_ptr() as *const __m128i));
+	for key in &keys[1..last] {
+		state = _mm_aesdec_si128(state, _mm_loadu_si128(key.as_ptr() as *const __m128i));
+	}
+	state = _mm_aesdeclast_si128(
+		state,
+		_mm_loadu_si128(keys[last].as_ptr() as *const __m128i),
+	);
+	_mm_storeu_si128(block.as_mut_ptr() as *mut __m128i, state);
+}
+
+/// An AES key schedule, for 128, 192 or 256 bits keys.
+#[derive(Clone)]
+pub struct Aes {
+	/// The number of rounds.
+	rounds: usize,
+	/// The round keys.
+	enc_keys: [[u8; 16]; 15],
+	/// The round keys of the equivalent inverse cipher, in order of use.
+	dec_keys: [[u8; 16]; 15],
+}
+
+impl Aes {
+	/// Expands the given key.
+	///
+	/// If the key is not 16, 24 or 32 bytes long, the function returns [`errno::EINVAL`].
+	pub fn new(key: &[u8]) -> EResult<Self> {
+		let nk = match key.len() {
+			16 | 24 | 32 => key.len() / 4,
+			_ => return Err(errno!(EINVAL)),
+		};
+		let rounds = nk + 6;
+		// Expand the key into words
+		let mut words = [[0u8; 4]; 60];
+		for (w, k) in words.iter_mut().zip(key.chunks_exact(4)) {
+			w.copy_from_slice(k);
+		}
+		let mut rcon = 1;
+		for i in nk..(4 * (rounds + 1)) {
+			let mut tmp = words[i - 1];
+			if i % nk == 0 {
+				tmp.rotate_left(1);
+				tmp = tmp.map(|b| SBOX[b as usize]);
+				tmp[0] ^= rcon;
+				rcon = xtime(rcon);
+			} else if nk > 6 && i % nk == 4 {
+				tmp = tmp.map(|b| SBOX[b as usize]);
+			}
+			for (j, b) in tmp.iter().enumerate() {
+				words[i][j] = words[i - nk][j] ^ b;
+			}
+		}
+		let mut enc_keys = [[0; 16]; 15];
+		for (k, w) in enc_keys.iter_mut().zip(words.chunks_exact(4)) {
+			for (k, w) in k.chunks_exact_mut(4).zip(w) {
+				k.copy_from_slice(w);
+			}
+		}
+		let mut dec_keys = [[0; 16]; 15];
+		for (i, k) in dec_keys[..=rounds].iter_mut().enumerate() {
+			*k = enc_keys[rounds - i];
+			if i != 0 && i != rounds {
+				inv_mix_columns(k);
+			}
+		}
+		Ok(Self {
+			rounds,
+			enc_keys,
+			dec_keys,
+		})
+	}
+
+	/// Encrypts `block` in software.
+	fn encrypt_soft(&self, block: &mut [u8; 16]) {
+		add_round_key(block, &self.enc_keys[0]);
+		for key in &self.enc_keys[1..self.rounds] {
+			sub_shift_rows(block);
+			mix_columns(block);
+			add_round_key(block, key);
+		}
+		sub_shift_rows(block);
+		add_round_key(block, &self.enc_keys[self.rounds]);
+	}
+
+	/// Decrypts `block` in software.
+	fn decrypt_soft(&self, block: &mut [u8; 16]) {
+		add_round_key(block, &self.enc_keys[self.rounds]);
+		for key in self.enc_keys[1..self.rounds].iter().rev() {
+			inv_sub_shift_rows(block);
+			add_round_key(block, key);
+			inv_mix_columns(block);
+		}
+		inv_sub_shift_rows(block);
+		add_round_key(block, &self.enc_keys[0]);
+	}
+}
+
+impl BlockCipher for Aes {
+	const BLOCK_SIZE: usize = 16;
+
+	fn encrypt_block(&self, block: &mut [u8]) {
+		let block: &mut [u8; 16] = block.try_into().unwrap();
+		if AES_NI.load(Relaxed) {
+			// Safety: support for the instructions has been checked at boot
+			sse::preserve(|| unsafe { encrypt_ni(&self.enc_keys[..=self.rounds], block) });
+		} else {
+			self.encrypt_soft(block);
+		}
+	}
+
+	fn decrypt_block(&self, block: &mut [u8]) {
+		let block: &mut [u8; 16] = block.try_into().unwrap();
+		if AES_NI.load(Relaxed) {
+			// Safety: support for the instructions has been checked at boot
+			sse::preserve(|| unsafe { decrypt_ni(&self.dec_keys[..=self.rounds], block) });
+		} else {
+			self.decrypt_soft(block);
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::crypto::unhex;
+
+	#[test_case]
+	fn aes_vectors() {
+		let plain: [u8; 16] = unhex("00112233445566778899aabbccddeeff");
+		let key: [u8; 32] = core::array::from_fn(|i| i as u8);
+		let vectors = [
+			(16, "69c4e0d86a7b0430d8cdb78070b4c55a"),
+			(24, "dda97ca4864cdfe06eaf70a0ec0d7191"),
+			(32, "8ea2b7ca516745bfeafc49904b496089"),
+		];
+		for (len, cipher) in vectors {
+			let cipher: [u8; 16] = unhex(cipher);
+			let aes = Aes::new(&key[..len]).unwrap();
+			let mut block = plain;
+			aes.encrypt_block(&mut block);
+			assert_eq!(block, cipher);
+			aes.decrypt_block(&mut block);
+			assert_eq!(block, plain);
+			// Software implementation
+			aes.encrypt_soft(&mut block);
+			assert_eq!(block, cipher);
+			aes.decrypt_soft(&mut block);
+			assert_eq!(block, plain);
+		}
+		assert!(Aes::new(&key[..20]).is_err());
+	}
+}
@@ -18,6 +18,7 @@
 
 //! Implementation of the ChaCha20 algorithm.
 
+use crate::crypto::StreamCipher;
 use core::ptr;
 
 /// Performs a left rotation of `b` bits on the value `a`.
@@ -51,7 +52,26 @@ macro_rules! quarter_round {
 	};
 }
 
-/// Computes a ChaCha20 block.
+/// Applies the 20 rounds of the ChaCha20 permutation to `state`.
+fn permute(state: &mut [u32; 16]) {
+	for _ in (0..20).step_by(2) {
+		// Odd round
+		quarter_round!(state[0], state[4], state[8], state[12]);
+		quarter_round!(state[1], state[5], state[9], state[13]);
+		quarter_round!(state[2], state[6], state[10], state[14]);
+		quarter_round!(state[3], state[7], state[11], state[15]);
+
+		// Even round
+		quarter_round!(state[0], state[5], state[10], state[15]);
+		quarter_round!(state[1], state[6], state[11], state[12]);
+		quarter_round!(state[2], state[7], state[8], state[13]);
+		quarter_round!(state[3], state[4], state[9], state[14]);
+	}
+}
+
+/// Applies the ChaCha20 permutation to `inout`, without adding the input to the result.
+///
+/// This is not a ChaCha20 block: to encrypt data, use [`ChaCha20`].
 pub fn block(inout: &mut [u8; 64]) {
 	let mut buff: [u32; 16] = [0; 16];
 
@@ -59,23 +79,105 @@ pub fn block(inout: &mut [u8; 64]) {
 		ptr::copy_nonoverlapping(inout.as_ptr(), buff.as_mut_ptr() as *mut u8, 64);
 	}
 
-	for _ in (0..20).step_by(2) {
-		// Odd round
-		quarter_round!(buff[0], buff[4], buff[8], buff[12]);
-		quarter_round!(buff[1], buff[5], buff[9], buff[13]);
-		quarter_round!(buff[2], buff[6], buff[10], buff[14]);
-		quarter_round!(buff[3], buff[7], buff[11], buff[15]);
-
-		// Even round
-		quarter_round!(buff[0], buff[5], buff[10], buff[15]);
-		quarter_round!(buff[1], buff[6], buff[11], buff[12]);
-		quarter_round!(buff[2], buff[7], buff[8], buff[13]);
-		quarter_round!(buff[3], buff[4], buff[9], buff[14]);
-	}
+	permute(&mut buff);
 
 	unsafe {
 		ptr::copy_nonoverlapping(buff.as_ptr() as *mut u8, inout.as_mut_ptr(), 64);
 	}
 }
 
-// TODO unit tests
+/// The ChaCha20 stream cipher, as specified by RFC 8439.
+#[derive(Clone)]
+pub struct ChaCha20 {
+	/// The state of the next block.
+	state: [u32; 16],
+	/// The current block of keystream.
+	keystream: [u8; 64],
+	/// The offset of the next unused byte in `keystream`.
+	off: usize,
+}
+
+impl ChaCha20 {
+	/// Creates a new instance, with the given key, nonce and initial block counter.
+	pub fn new(key: &[u8; 32], nonce: &[u8; 12], counter: u32) -> Self {
+		let mut state = [0; 16];
+		// "expand 32-byte k"
+		state[..4].copy_from_slice(&[0x61707865, 0x3320646e, 0x79622d32, 0x6b206574]);
+		for (s, k) in state[4..12].iter_mut().zip(key.chunks_exact(4)) {
+			*s = u32::from_le_bytes([k[0], k[1], k[2], k[3]]);
+		}
+		state[12] = counter;
+		for (s, n) in state[13..].iter_mut().zip(nonce.chunks_exact(4)) {
+			*s = u32::from_le_bytes([n[0], n[1], n[2], n[3]]);
+		}
+		Self {
+			state,
+			keystream: [0; 64],
+			off: 64,
+		}
+	}
+
+	/// Computes the next block of keystream.
+	fn next_block(&mut self) {
+		let mut x = self.state;
+		permute(&mut x);
+		for ((k, x), s) in self.keystream.chunks_exact_mut(4).zip(x).zip(self.state) {
+			k.copy_from_slice(&x.wrapping_add(s).to_le_bytes());
+		}
+		self.state[12] = self.state[12].wrapping_add(1);
+		self.off = 0;
+	}
+}
+
+impl StreamCipher for ChaCha20 {
+	fn apply_keystream(&mut self, data: &mut [u8]) {
+		for b in data {
+			if self.off >= 64 {
+				self.next_block();
+			}
+			*b ^= self.keystream[self.off];
+			self.off += 1;
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::crypto::unhex;
+
+	/// Test vectors from RFC 8439.
+	#[test_case]
+	fn chacha20_vectors() {
+		let key: [u8; 32] = core::array::from_fn(|i| i as u8);
+		// Block function
+		let mut cipher = ChaCha20::new(&key, &unhex("000000090000004a00000000"), 1);
+		let mut block = [0; 64];
+		cipher.apply_keystream(&mut block);
+		assert_eq!(
+			block,
+			unhex(
+				"10f1e7e4d13b5915500fdd1fa32071c4c7d1f4c733c068030422aa9ac3d46c4e\
+				d2826446079faa0914c2d705d98b02a2b5129cd1de164eb9cbd083e8a2503c4e"
+			)
+		);
+		// Encryption, across several blocks
+		let plain =
+			b"Ladies and Gentlemen of the class of '99: If I could offer you only one tip \
+			for the future, sunscreen would be it.";
+		let expected: [u8; 114] = unhex(
+			"6e2e359a2568f98041ba0728dd0d6981e97e7aec1d4360c20a27afccfd9fae0b\
+			f91b65c5524733ab8f593dabcd62b3571639d624e65152ab8f530c359f0861d8\
+			07ca0dbf500d6a6156a38e088a22b65e52bc514d16ccf806818ce91ab7793736\
+			5af90bbf74a35be6b40b8eedf2785e42874d",
+		);
+		let nonce = unhex("000000000000004a00000000");
+		let mut data = *plain;
+		let mut cipher = ChaCha20::new(&key, &nonce, 1);
+		cipher.apply_keystream(&mut data[..50]);
+		cipher.apply_keystream(&mut data[50..]);
+		assert_eq!(data, expected);
+		ChaCha20::new(&key, &nonce, 1).apply_keystream(&mut data);
+		assert_eq!(&data, plain);
+	}
+}
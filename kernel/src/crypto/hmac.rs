@@ -0,0 +1,111 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Implementation of HMAC, as specified by RFC 2104.
+
+use crate::crypto::{constant_time_eq, Digest};
+
+/// The largest block size of a digest usable with [`Hmac`].
+const MAX_BLOCK_SIZE: usize = 128;
+
+/// An HMAC computation, using the hash function `D`.
+#[derive(Clone)]
+pub struct Hmac<D: Digest> {
+	/// The inner hash, fed with the message.
+	inner: D,
+	/// The outer hash, fed with the inner hash's output.
+	outer: D,
+}
+
+impl<D: Digest> Hmac<D> {
+	/// Creates a new instance with the given key.
+	pub fn new(key: &[u8]) -> Self {
+		let mut pad = [0u8; MAX_BLOCK_SIZE];
+		let pad = &mut pad[..D::BLOCK_SIZE];
+		// Keys longer than a block are hashed first
+		if key.len() > D::BLOCK_SIZE {
+			let hash = D::digest(key);
+			let hash = hash.as_ref();
+			pad[..hash.len()].copy_from_slice(hash);
+		} else {
+			pad[..key.len()].copy_from_slice(key);
+		}
+		pad.iter_mut().for_each(|b| *b ^= 0x36);
+		let mut inner = D::new();
+		inner.update(pad);
+		pad.iter_mut().for_each(|b| *b ^= 0x36 ^ 0x5c);
+		let mut outer = D::new();
+		outer.update(pad);
+		pad.fill(0);
+		Self {
+			inner,
+			outer,
+		}
+	}
+
+	/// Authenticates `data`, following the data authenticated previously.
+	pub fn update(&mut self, data: &[u8]) {
+		self.inner.update(data);
+	}
+
+	/// Returns the authentication code of the data.
+	pub fn finalize(mut self) -> D::Output {
+		let hash = self.inner.finalize();
+		self.outer.update(hash.as_ref());
+		self.outer.finalize()
+	}
+
+	/// Tells whether `tag` is the authentication code of the data.
+	///
+	/// The comparison is done in constant time.
+	pub fn verify(self, tag: &[u8]) -> bool {
+		constant_time_eq(self.finalize().as_ref(), tag)
+	}
+
+	/// Returns the authentication code of `data` with the given key.
+	pub fn mac(key: &[u8], data: &[u8]) -> D::Output {
+		let mut hmac = Self::new(key);
+		hmac.update(data);
+		hmac.finalize()
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::crypto::{sha256::Sha256, unhex};
+
+	/// Test vectors from RFC 4231.
+	#[test_case]
+	fn hmac_sha256() {
+		assert_eq!(
+			Hmac::<Sha256>::mac(&[0x0b; 20], b"Hi There"),
+			unhex("b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7")
+		);
+		assert_eq!(
+			Hmac::<Sha256>::mac(b"Jefe", b"what do ya want for nothing?"),
+			unhex("5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843")
+		);
+		let tag: [u8; 32] =
+			unhex("60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54");
+		let mut hmac = Hmac::<Sha256>::new(&[0xaa; 131]);
+		hmac.update(b"Test Using Larger Than Block-Size Key - Hash Key First");
+		assert!(hmac.clone().verify(&tag));
+		assert!(!hmac.verify(&tag[..31]));
+	}
+}
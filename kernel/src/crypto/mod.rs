@@ -17,14 +17,96 @@
  */
 
 //! Cryptographic algorithms and tools.
+//!
+//! Algorithms implement one of the [`Digest`], [`BlockCipher`] or [`StreamCipher`] traits, so
+//! that users can be written independently of the algorithm they use.
+//!
+//! When the CPU supports it, AES and SHA-256 use dedicated instructions.
 
+use crate::cpu;
+use core::sync::atomic::Ordering::Relaxed;
 use utils::errno::AllocResult;
 
+pub mod aes;
 pub mod chacha20;
 pub mod checksum;
+pub mod hmac;
 pub mod rand;
+pub mod sha256;
+
+/// A cryptographic hash function.
+pub trait Digest: Sized {
+	/// The size of a block of input, in bytes.
+	const BLOCK_SIZE: usize;
+	/// The output of the function.
+	type Output: AsRef<[u8]>;
+
+	/// Creates a new instance, with no data hashed.
+	fn new() -> Self;
+	/// Hashes `data`, following the data hashed previously.
+	fn update(&mut self, data: &[u8]);
+	/// Returns the hash of the data.
+	fn finalize(self) -> Self::Output;
+
+	/// Returns the hash of `data`.
+	fn digest(data: &[u8]) -> Self::Output {
+		let mut d = Self::new();
+		d.update(data);
+		d.finalize()
+	}
+}
+
+/// A block cipher, encrypting data by blocks of fixed size.
+pub trait BlockCipher {
+	/// The size of a block, in bytes.
+	const BLOCK_SIZE: usize;
+
+	/// Encrypts `block` in place.
+	///
+	/// If the length of `block` is not [`Self::BLOCK_SIZE`], the function panics.
+	fn encrypt_block(&self, block: &mut [u8]);
+	/// Decrypts `block` in place.
+	///
+	/// If the length of `block` is not [`Self::BLOCK_SIZE`], the function panics.
+	fn decrypt_block(&self, block: &mut [u8]);
+}
+
+/// A stream cipher, encrypting data by combining it with a keystream.
+pub trait StreamCipher {
+	/// Combines `data` with the next bytes of the keystream, encrypting or decrypting it in
+	/// place.
+	fn apply_keystream(&mut self, data: &mut [u8]);
+}
+
+/// Compares `a` and `b` in a time that does not depend on their content, to avoid leaking
+/// secrets through timing.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+	if a.len() != b.len() {
+		return false;
+	}
+	let diff = a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b));
+	diff == 0
+}
 
 /// Initializes cryptographic features.
 pub(crate) fn init() -> AllocResult<()> {
+	// Detect acceleration
+	let (_, _, ecx, _) = cpu::cpuid(1, 0, 0, 0);
+	let (_, ebx, ..) = cpu::cpuid(7, 0, 0, 0);
+	let ssse3 = ecx & (1 << 9) != 0;
+	let sse4_1 = ecx & (1 << 19) != 0;
+	aes::AES_NI.store(ecx & (1 << 25) != 0, Relaxed);
+	sha256::SHA_NI.store(ssse3 && sse4_1 && ebx & (1 << 29) != 0, Relaxed);
 	rand::init()
 }
+
+/// Decodes the hexadecimal string `s`, for test vectors.
+#[cfg(test)]
+fn unhex<const N: usize>(s: &str) -> [u8; N] {
+	let s = s.as_bytes();
+	assert_eq!(s.len(), N * 2);
+	core::array::from_fn(|i| {
+		let digit = |c: u8| (c as char).to_digit(16).unwrap() as u8;
+		(digit(s[i * 2]) << 4) | digit(s[i * 2 + 1])
+	})
+}
@@ -148,6 +148,27 @@ impl EntropyPool {
 /// The entropy pool.
 pub static ENTROPY_POOL: IntMutex<Option<EntropyPool>> = IntMutex::new(None);
 
+/// The random ID of the current boot, generated on first use.
+static BOOT_ID: IntMutex<Option<[u8; 16]>> = IntMutex::new(None);
+
+/// Returns the random ID of the current boot.
+///
+/// The ID is generated once and remains the same until the system is shut down. It is formatted
+/// as a version 4 UUID.
+pub fn boot_id() -> [u8; 16] {
+	let mut boot_id = BOOT_ID.lock();
+	*boot_id.get_or_insert_with(|| {
+		let mut id = [0; 16];
+		if let Some(pool) = &mut *ENTROPY_POOL.lock() {
+			pool.read(&mut id, true);
+		}
+		// Set UUID version and variant
+		id[6] = (id[6] & 0x0f) | 0x40;
+		id[8] = (id[8] & 0x3f) | 0x80;
+		id
+	})
+}
+
 /// Initializes randomness sources.
 pub(super) fn init() -> AllocResult<()> {
 	*ENTROPY_POOL.lock() = Some(EntropyPool::new()?);
@@ -0,0 +1,274 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Implementation of the SHA-256 hash function, as specified by FIPS 180-4.
+
+use crate::{cpu::sse, crypto::Digest};
+use core::{
+	arch::x86::*,
+	sync::atomic::{AtomicBool, Ordering::Relaxed},
+};
+
+/// Tells whether the CPU supports the SHA extensions.
+pub(super) static SHA_NI: AtomicBool = AtomicBool::new(false);
+
+/// The initial hash value.
+const H0: [u32; 8] = [
+	0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+/// The round constants.
+const K: [u32; 64] = [
+	0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4,
+	0xab1c5ed5, 0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe,
+	0x9bdc06a7, 0xc19bf174, 0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f,
+	0x4a7484aa, 0x5cb0a9dc, 0x76f988da, 0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7,
+	0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967, 0x27b70a85, 0x2e1b2138, 0x4d2c6dfc,
+	0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85, 0xa2bfe8a1, 0xa81a664b,
+	0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070, 0x19a4c116,
+	0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+	0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7,
+	0xc67178f2,
+];
+
+/// Processes `blocks` in software.
+fn compress_soft(state: &mut [u32; 8], blocks: &[u8]) {
+	for block in blocks.chunks_exact(64) {
+		// Message schedule
+		let mut w = [0u32; 64];
+		for (w, b) in w.iter_mut().zip(block.chunks_exact(4)) {
+			*w = u32::from_be_bytes([b[0], b[1], b[2], b[3]]);
+		}
+		for i in 16..64 {
+			let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+			let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+			w[i] = w[i - 16]
+				.wrapping_add(s0)
+				.wrapping_add(w[i - 7])
+				.wrapping_add(s1);
+		}
+		// Rounds
+		let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = *state;
+		for i in 0..64 {
+			let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+			let ch = (e & f) ^ (!e & g);
+			let t1 = h
+				.wrapping_add(s1)
+				.wrapping_add(ch)
+				.wrapping_add(K[i])
+				.wrapping_add(w[i]);
+			let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+			let maj = (a & b) ^ (a & c) ^ (b & c);
+			let t2 = s0.wrapping_add(maj);
+			h = g;
+			g = f;
+			f = e;
+			e = d.wrapping_add(t1);
+			d = c;
+			c = b;
+			b = a;
+			a = t1.wrapping_add(t2);
+		}
+		for (s, v) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+			*s = s.wrapping_add(v);
+		}
+	}
+}
+
+/// Processes `blocks` using the SHA extensions.
+///
+/// # Safety
+///
+/// The CPU must support the SHA, SSE2, SSSE3 and SSE4.1 instruction sets, and the SSE state must
+/// be preserved by the caller.
+#[target_feature(enable = "sha,sse2,ssse3,sse4.1")]
+unsafe fn compress_ni(state: &mut [u32; 8], blocks: &[u8]) {
+	// Performs four rounds with the message words `w`, starting at round `4 * i`
+	macro_rules! rounds4 {
+		($abef:ident, $cdgh:ident, $w:expr, $i:expr) => {{
+			let k = _mm_loadu_si128(K.as_ptr().add(4 * $i) as *const __m128i);
+			let t = _mm_add_epi32($w, k);
+			$cdgh = _mm_sha256rnds2_epu32($cdgh, $abef, t);
+			$abef = _mm_sha256rnds2_epu32($abef, $cdgh, _mm_shuffle_epi32(t, 0x0e));
+		}};
+	}
+
+	// Mask converting big-endian words to native order
+	let mask = _mm_set_epi64x(0x0c0d0e0f08090a0b, 0x0405060700010203);
+	// Rearrange the state in the order expected by the instructions
+	let state_ptr = state.as_mut_ptr() as *mut __m128i;
+	let dcba = _mm_loadu_si128(state_ptr);
+	let efgh = _mm_loadu_si128(state_ptr.add(1));
+	let cdab = _mm_shuffle_epi32(dcba, 0xb1);
+	let efgh = _mm_shuffle_epi32(efgh, 0x1b);
+	let mut abef = _mm_alignr_epi8(cdab, efgh, 8);
+	let mut cdgh = _mm_blend_epi16(efgh, cdab, 0xf0);
+	for block in blocks.chunks_exact(64) {
+		let abef_save = abef;
+		let cdgh_save = cdgh;
+		let block_ptr = block.as_ptr() as *const __m128i;
+		// The last four groups of message words
+		let mut w = [
+			_mm_shuffle_epi8(_mm_loadu_si128(block_ptr), mask),
+			_mm_shuffle_epi8(_mm_loadu_si128(block_ptr.add(1)), mask),
+			_mm_shuffle_epi8(_mm_loadu_si128(block_ptr.add(2)), mask),
+			_mm_shuffle_epi8(_mm_loadu_si128(block_ptr.add(3)), mask),
+		];
+		for (i, w) in w.iter().enumerate() {
+			rounds4!(abef, cdgh, *w, i);
+		}
+		for i in 4..16 {
+			let t = _mm_sha256msg1_epu32(w[i % 4], w[(i + 1) % 4]);
+			let t = _mm_add_epi32(t, _mm_alignr_epi8(w[(i + 3) % 4], w[(i + 2) % 4], 4));
+			w[i % 4] = _mm_sha256msg2_epu32(t, w[(i + 3) % 4]);
+			rounds4!(abef, cdgh, w[i % 4], i);
+		}
+		abef = _mm_add_epi32(abef, abef_save);
+		cdgh = _mm_add_epi32(cdgh, cdgh_save);
+	}
+	// Restore the order of the state
+	let feba = _mm_shuffle_epi32(abef, 0x1b);
+	let dchg = _mm_shuffle_epi32(cdgh, 0xb1);
+	_mm_storeu_si128(state_ptr, _mm_blend_epi16(feba, dchg, 0xf0));
+	_mm_storeu_si128(state_ptr.add(1), _mm_alignr_epi8(dchg, feba, 8));
+}
+
+/// Processes `blocks`, whose length must be a multiple of `64`.
+fn compress(state: &mut [u32; 8], blocks: &[u8]) {
+	if SHA_NI.load(Relaxed) {
+		// Safety: support for the instructions has been checked at boot
+		sse::preserve(|| unsafe { compress_ni(state, blocks) });
+	} else {
+		compress_soft(state, blocks);
+	}
+}
+
+/// A SHA-256 hash computation.
+#[derive(Clone)]
+pub struct Sha256 {
+	/// The current hash value.
+	state: [u32; 8],
+	/// Buffer for data that does not fill a whole block yet.
+	buf: [u8; 64],
+	/// The number of bytes in `buf`.
+	buf_len: usize,
+	/// The total number of bytes hashed.
+	len: u64,
+}
+
+impl Digest for Sha256 {
+	type Output = [u8; 32];
+
+	const BLOCK_SIZE: usize = 64;
+
+	fn new() -> Self {
+		Self {
+			state: H0,
+			buf: [0; 64],
+			buf_len: 0,
+			len: 0,
+		}
+	}
+
+	fn update(&mut self, mut data: &[u8]) {
+		self.len += data.len() as u64;
+		// Complete the pending block
+		if self.buf_len > 0 {
+			let len = data.len().min(64 - self.buf_len);
+			self.buf[self.buf_len..(self.buf_len + len)].copy_from_slice(&data[..len]);
+			self.buf_len += len;
+			data = &data[len..];
+			if self.buf_len < 64 {
+				return;
+			}
+			compress(&mut self.state, &self.buf);
+			self.buf_len = 0;
+		}
+		// Process whole blocks without copying them
+		let len = data.len() & !63;
+		compress(&mut self.state, &data[..len]);
+		let rest = &data[len..];
+		self.buf[..rest.len()].copy_from_slice(rest);
+		self.buf_len = rest.len();
+	}
+
+	fn finalize(mut self) -> Self::Output {
+		let bits = self.len.wrapping_mul(8);
+		// Padding, leaving room for the length
+		self.buf[self.buf_len] = 0x80;
+		self.buf[(self.buf_len + 1)..].fill(0);
+		if self.buf_len >= 56 {
+			compress(&mut self.state, &self.buf);
+			self.buf.fill(0);
+		}
+		self.buf[56..].copy_from_slice(&bits.to_be_bytes());
+		compress(&mut self.state, &self.buf);
+		let mut out = [0; 32];
+		for (o, s) in out.chunks_exact_mut(4).zip(self.state) {
+			o.copy_from_slice(&s.to_be_bytes());
+		}
+		out
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::crypto::unhex;
+
+	#[test_case]
+	fn sha256_vectors() {
+		assert_eq!(
+			Sha256::digest(b""),
+			unhex("e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855")
+		);
+		assert_eq!(
+			Sha256::digest(b"abc"),
+			unhex("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad")
+		);
+		assert_eq!(
+			Sha256::digest(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"),
+			unhex("248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1")
+		);
+	}
+
+	#[test_case]
+	fn sha256_incremental() {
+		let data: [u8; 200] = core::array::from_fn(|i| i as u8);
+		let expected = Sha256::digest(&data);
+		for split in [0, 1, 63, 64, 65, 130, 200] {
+			let mut d = Sha256::new();
+			d.update(&data[..split]);
+			d.update(&data[split..]);
+			assert_eq!(d.finalize(), expected);
+		}
+	}
+
+	#[test_case]
+	fn sha256_accelerated() {
+		if !SHA_NI.load(Relaxed) {
+			return;
+		}
+		let data: [u8; 256] = core::array::from_fn(|i| (i * 7) as u8);
+		let mut soft = H0;
+		compress_soft(&mut soft, &data);
+		let mut ni = H0;
+		sse::preserve(|| unsafe { compress_ni(&mut ni, &data) });
+		assert_eq!(soft, ni);
+	}
+}
@@ -427,6 +427,13 @@ impl PhysicalDevice for PCIDevice {
 		Some(self.status)
 	}
 
+	fn enable_bus_master(&self) {
+		let reg = read_long(self.bus, self.device, self.function, 1);
+		// Keep the status bits cleared, since writing a one to them resets them
+		let command = (reg & 0xffff) | 0b100;
+		write_long(self.bus, self.device, self.function, 1, command);
+	}
+
 	fn get_class(&self) -> u16 {
 		self.class as _
 	}
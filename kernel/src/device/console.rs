@@ -0,0 +1,447 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The system console is the output on which kernel logs are printed.
+//!
+//! Several consoles may be active at the same time (for example the VGA text TTY and a serial
+//! port). They are selected at boot using the `-console` command line argument, which may be
+//! repeated. If no console is specified, the default ones are used.
+//!
+//! Logs emitted before consoles are registered are kept in the logger's buffer, and are replayed
+//! on each console when it registers. This way, early boot messages are not lost.
+//!
+//! The `/dev/console` device writes to every active console and reads from the TTY. Input
+//! received on serial consoles is forwarded to the TTY, and the TTY echoes its input on them, so
+//! that a single program on `/dev/console` can be used from the screen and keyboard as well as
+//! from a serial line. Serial ports raise an interrupt when they receive input, which is then
+//! read from a work item.
+//!
+//! Each active console has flags ([`CON_INPUT`] and [`CON_OUTPUT`]) telling in which directions
+//! it is used. On the command line, they are specified after the name of the console, with the
+//! format `name[,flags]` where `flags` is a combination of `r` (input) and `w` (output). If no
+//! flags are specified, both are enabled.
+
+use crate::{
+	device::{serial, tty::TTYDeviceHandle, DeviceIO},
+	event,
+	event::CallbackResult,
+	logger::LOGGER,
+	syscall::ioctl,
+	tty::TTY,
+	workqueue,
+};
+use core::{ffi::c_void, mem::ManuallyDrop, num::NonZeroU64};
+use utils::{errno, errno::EResult, lock::IntMutex};
+
+/// The maximum number of consoles that can be active at the same time.
+pub const MAX_CONSOLES: usize = 8;
+
+/// Flag telling that input received on the console is forwarded to the TTY.
+///
+/// This has no effect on the VGA console, whose input comes from the keyboard.
+pub const CON_INPUT: u8 = 0b01;
+/// Flag telling that kernel logs and the output of `/dev/console` are printed on the console.
+pub const CON_OUTPUT: u8 = 0b10;
+
+/// The names of the consoles used when none is specified on the command line.
+const DEFAULT_CONSOLES: &[&[u8]] = &[b"tty0", b"ttyS0"];
+/// The name of the VGA console, which displays the TTY.
+const VGA_CONSOLE: &[u8] = b"tty0";
+
+/// The maximum number of bytes of input read from consoles at once.
+const INPUT_READ_SIZE: usize = 64;
+
+/// An output on which the kernel can print its logs.
+pub trait Console: Sync {
+	/// Returns the name of the console, as used on the command line.
+	fn name(&self) -> &'static [u8];
+
+	/// Writes the given buffer to the console.
+	fn write(&self, buf: &[u8]);
+
+	/// Reads the input received on the console into `buf`, without waiting.
+	///
+	/// The function returns the number of bytes read.
+	///
+	/// The default implementation returns `0`, for consoles that do not receive input.
+	fn read(&self, _buf: &mut [u8]) -> usize {
+		0
+	}
+
+	/// Returns the IRQ raised when input is received on the console.
+	///
+	/// The default implementation returns `None`, for consoles that do not receive input.
+	fn input_irq(&self) -> Option<u8> {
+		None
+	}
+
+	/// Enables or disables the interrupt raised when input is received on the console.
+	///
+	/// The default implementation does nothing, for consoles that do not receive input.
+	fn set_input_irq(&self, _enable: bool) {}
+}
+
+/// Console printing on the VGA text mode TTY.
+struct VgaConsole;
+
+impl Console for VgaConsole {
+	fn name(&self) -> &'static [u8] {
+		VGA_CONSOLE
+	}
+
+	fn write(&self, buf: &[u8]) {
+		TTY.display.lock().write(buf);
+	}
+}
+
+/// Console printing on a serial port.
+struct SerialConsole {
+	/// The console's name.
+	name: &'static [u8],
+	/// The index of the port in [`serial::PORTS`].
+	port: usize,
+}
+
+impl Console for SerialConsole {
+	fn name(&self) -> &'static [u8] {
+		self.name
+	}
+
+	fn write(&self, buf: &[u8]) {
+		serial::PORTS[self.port].lock().write(buf);
+	}
+
+	fn read(&self, buf: &mut [u8]) -> usize {
+		serial::PORTS[self.port].lock().read(buf)
+	}
+
+	fn input_irq(&self) -> Option<u8> {
+		Some(serial::PORTS[self.port].lock().get_irq())
+	}
+
+	fn set_input_irq(&self, enable: bool) {
+		serial::PORTS[self.port].lock().set_rx_interrupt(enable);
+	}
+}
+
+/// The list of consoles the kernel knows about.
+static AVAILABLE: &[&dyn Console] = &[
+	&VgaConsole,
+	&SerialConsole {
+		name: b"ttyS0",
+		port: 0,
+	},
+	&SerialConsole {
+		name: b"ttyS1",
+		port: 1,
+	},
+	&SerialConsole {
+		name: b"ttyS2",
+		port: 2,
+	},
+	&SerialConsole {
+		name: b"ttyS3",
+		port: 3,
+	},
+];
+
+/// An active console, with its flags.
+type ActiveConsole = (&'static dyn Console, u8);
+
+/// The list of active consoles.
+static CONSOLES: IntMutex<[Option<ActiveConsole>; MAX_CONSOLES]> =
+	IntMutex::new([None; MAX_CONSOLES]);
+
+/// Registers the given console with the given `flags`.
+///
+/// If [`CON_INPUT`] is set, the interrupt raised on input is enabled on the console. If
+/// [`CON_OUTPUT`] is set, logs emitted before registration are replayed on the console, unless
+/// the logger is silent.
+///
+/// If the console is already registered, the function does nothing. If too many consoles are
+/// registered, the function returns [`errno::ENOSPC`].
+pub fn register(console: &'static dyn Console, flags: u8) -> EResult<()> {
+	// Lock the logger first to keep the same locking order as when printing
+	let logger = LOGGER.lock();
+	let mut consoles = CONSOLES.lock();
+	let name = console.name();
+	if consoles.iter().flatten().any(|(c, _)| c.name() == name) {
+		return Ok(());
+	}
+	let slot = consoles
+		.iter_mut()
+		.find(|c| c.is_none())
+		.ok_or_else(|| errno!(ENOSPC))?;
+	*slot = Some((console, flags));
+	console.set_input_irq(flags & CON_INPUT != 0);
+	if flags & CON_OUTPUT != 0 && !logger.silent {
+		let (a, b) = logger.get_ordered_content();
+		console.write(a);
+		console.write(b);
+	}
+	Ok(())
+}
+
+/// Parses console flags, made of the characters `r` for [`CON_INPUT`] and `w` for
+/// [`CON_OUTPUT`]. The character `-` is ignored, to allow specifying no flag.
+///
+/// If an invalid character is present, the function returns `None`.
+pub fn parse_flags(s: &[u8]) -> Option<u8> {
+	s.iter().try_fold(0, |flags, c| match c {
+		b'r' => Some(flags | CON_INPUT),
+		b'w' => Some(flags | CON_OUTPUT),
+		b'-' => Some(flags),
+		_ => None,
+	})
+}
+
+/// Initializes the consoles with the given specifications, with the format `name[,flags]`.
+///
+/// If `specs` is empty, the default consoles are used. Unknown names and invalid flags are
+/// ignored.
+///
+/// If at least one console is already registered, the function does nothing.
+pub fn init(specs: &[&[u8]]) {
+	if CONSOLES.lock().iter().any(Option::is_some) {
+		return;
+	}
+	let specs = if specs.is_empty() {
+		DEFAULT_CONSOLES
+	} else {
+		specs
+	};
+	for spec in specs {
+		let (name, flags) = match spec.iter().position(|c| *c == b',') {
+			Some(i) => (&spec[..i], parse_flags(&spec[(i + 1)..])),
+			None => (*spec, Some(CON_INPUT | CON_OUTPUT)),
+		};
+		let Some(flags) = flags else {
+			continue;
+		};
+		if let Some(console) = AVAILABLE.iter().find(|c| c.name() == name) {
+			// Consoles beyond the limit are ignored
+			let _ = register(*console, flags);
+		}
+	}
+}
+
+/// Sets the flags of the console with the given name, registering it if it is not active.
+///
+/// If no console has this name, the function returns [`errno::ENOENT`].
+pub fn set_flags(name: &[u8], flags: u8) -> EResult<()> {
+	let console = AVAILABLE
+		.iter()
+		.find(|c| c.name() == name)
+		.ok_or_else(|| errno!(ENOENT))?;
+	{
+		let mut consoles = CONSOLES.lock();
+		let active = consoles
+			.iter_mut()
+			.flatten()
+			.find(|(c, _)| c.name() == name);
+		if let Some((c, f)) = active {
+			*f = flags;
+			c.set_input_irq(flags & CON_INPUT != 0);
+			return Ok(());
+		}
+	}
+	register(*console, flags)
+}
+
+/// Returns the names of the active consoles, along with their flags.
+pub fn list() -> [Option<(&'static [u8], u8)>; MAX_CONSOLES] {
+	CONSOLES
+		.lock()
+		.map(|c| c.map(|(c, flags)| (c.name(), flags)))
+}
+
+/// Writes the given buffer to every active console with [`CON_OUTPUT`] set.
+pub fn write(buf: &[u8]) {
+	for (console, flags) in CONSOLES.lock().iter().flatten() {
+		if flags & CON_OUTPUT != 0 {
+			console.write(buf);
+		}
+	}
+}
+
+/// Echoes the given input of the TTY on every active console, other than the VGA console, with
+/// both [`CON_INPUT`] and [`CON_OUTPUT`] set.
+///
+/// Since the TTY is displayed on the VGA console, this makes typed characters visible on consoles
+/// through which the input may have been received.
+pub fn echo(buf: &[u8]) {
+	const FLAGS: u8 = CON_INPUT | CON_OUTPUT;
+	for (console, flags) in CONSOLES.lock().iter().flatten() {
+		if flags & FLAGS == FLAGS && console.name() != VGA_CONSOLE {
+			console.write(buf);
+		}
+	}
+}
+
+/// Forwards the input received on consoles with [`CON_INPUT`] set to the TTY, until no input is
+/// left.
+///
+/// Reading the input of a serial port clears its interrupt, so that the next input raises a new
+/// one.
+fn read_input() {
+	let mut buf = [0; INPUT_READ_SIZE];
+	loop {
+		let len = CONSOLES
+			.lock()
+			.iter()
+			.flatten()
+			.filter(|(_, flags)| flags & CON_INPUT != 0)
+			.fold(0, |len, (console, _)| len + console.read(&mut buf[len..]));
+		if len == 0 {
+			break;
+		}
+		// The TTY must be fed without holding the lock, since it echoes its input on consoles
+		TTY.input(&buf[..len]);
+	}
+}
+
+/// Starts forwarding the input of consoles to the TTY, on the interrupts raised by consoles.
+///
+/// This function must be called only once, after workqueues are initialized.
+pub(crate) fn init_input() -> EResult<()> {
+	for (i, console) in AVAILABLE.iter().enumerate() {
+		let Some(irq) = console.input_irq() else {
+			continue;
+		};
+		// Several consoles may share the same IRQ
+		if AVAILABLE[..i].iter().any(|c| c.input_irq() == Some(irq)) {
+			continue;
+		}
+		let callback = |_, _, _: &_, _| {
+			// Ports cannot be read from the interrupt handler, since the interrupted code may hold
+			// their lock. On allocation failure, the input is read on the next work item
+			let _ = workqueue::queue_work(read_input);
+			CallbackResult::Continue
+		};
+		// The callback remains registered forever
+		let _ = ManuallyDrop::new(event::register_callback(0x20 + irq as u32, callback)?);
+	}
+	// Read the input received before interrupts were handled
+	workqueue::queue_work(read_input)?;
+	Ok(())
+}
+
+/// Handle for the `/dev/console` device.
+///
+/// Output is sent to every active console, while input and terminal settings are those of the
+/// TTY.
+pub struct ConsoleDeviceHandle;
+
+impl DeviceIO for ConsoleDeviceHandle {
+	fn block_size(&self) -> NonZeroU64 {
+		1.try_into().unwrap()
+	}
+
+	fn blocks_count(&self) -> u64 {
+		0
+	}
+
+	fn read(&self, off: u64, buff: &mut [u8]) -> EResult<usize> {
+		TTYDeviceHandle.read(off, buff)
+	}
+
+	fn write(&self, _off: u64, buff: &[u8]) -> EResult<usize> {
+		TTYDeviceHandle.check_sigttou(&TTY.display.lock())?;
+		write(buff);
+		Ok(buff.len())
+	}
+
+	fn read_bytes(&self, off: u64, buf: &mut [u8]) -> EResult<usize> {
+		self.read(off, buf)
+	}
+
+	fn read_bytes_nonblock(&self, off: u64, buf: &mut [u8]) -> EResult<usize> {
+		TTYDeviceHandle.read_bytes_nonblock(off, buf)
+	}
+
+	fn write_bytes(&self, off: u64, buf: &[u8]) -> EResult<usize> {
+		self.write(off, buf)
+	}
+
+	fn poll(&self, mask: u32) -> EResult<u32> {
+		TTYDeviceHandle.poll(mask)
+	}
+
+	fn poll_wait(&self) -> EResult<bool> {
+		TTYDeviceHandle.poll_wait()
+	}
+
+	fn ioctl(&self, request: ioctl::Request, argp: *const c_void) -> EResult<u32> {
+		TTYDeviceHandle.ioctl(request, argp)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::tty::termios::consts::ECHO;
+	use core::cmp::min;
+	use utils::collections::vec::Vec;
+
+	/// Console receiving canned input and recording its output.
+	struct TestConsole {
+		/// The input left to be read.
+		input: IntMutex<&'static [u8]>,
+		/// The data written on the console.
+		output: IntMutex<Vec<u8>>,
+	}
+
+	impl Console for TestConsole {
+		fn name(&self) -> &'static [u8] {
+			b"test"
+		}
+
+		fn write(&self, buf: &[u8]) {
+			let _ = self.output.lock().extend_from_slice(buf);
+		}
+
+		fn read(&self, buf: &mut [u8]) -> usize {
+			let mut input = self.input.lock();
+			let len = min(buf.len(), input.len());
+			buf[..len].copy_from_slice(&input[..len]);
+			*input = &input[len..];
+			len
+		}
+	}
+
+	static TEST_CONSOLE: TestConsole = TestConsole {
+		input: IntMutex::new(b"abc"),
+		output: IntMutex::new(Vec::new()),
+	};
+
+	#[test_case]
+	fn console_input_echo() {
+		register(&TEST_CONSOLE, CON_INPUT | CON_OUTPUT).unwrap();
+		read_input();
+		for slot in CONSOLES.lock().iter_mut() {
+			if matches!(slot, Some((c, _)) if c.name() == b"test") {
+				*slot = None;
+			}
+		}
+		assert!(TEST_CONSOLE.input.lock().is_empty());
+		// The input is echoed by the TTY
+		if TTY.display.lock().get_termios().c_lflag & ECHO != 0 {
+			assert!(TEST_CONSOLE.output.lock().ends_with(b"abc"));
+		}
+	}
+}
@@ -22,7 +22,7 @@ use super::{id, DeviceIO, DeviceType};
 use crate::{
 	crypto::rand,
 	device,
-	device::{tty::TTYDeviceHandle, Device, DeviceID},
+	device::{console::ConsoleDeviceHandle, tty::TTYDeviceHandle, Device, DeviceID},
 	logger::LOGGER,
 };
 use core::{cmp::min, mem::ManuallyDrop, num::NonZeroU64};
@@ -255,5 +255,18 @@ pub(super) fn create() -> EResult<()> {
 	)?;
 	device::register(current_tty_device)?;
 
+	let console_path = PathBuf::try_from(b"/dev/console")?;
+	let console_device = Device::new(
+		DeviceID {
+			dev_type: DeviceType::Char,
+			major: 5,
+			minor: 1,
+		},
+		console_path,
+		0o600,
+		ConsoleDeviceHandle,
+	)?;
+	device::register(console_device)?;
+
 	Ok(())
 }
@@ -34,6 +34,10 @@ pub trait PhysicalDevice {
 	fn get_command_reg(&self) -> Option<u16>;
 	/// Returns the status register if present.
 	fn get_status_reg(&self) -> Option<u16>;
+	/// Allows the device to initiate Direct Memory Access (DMA) transfers.
+	///
+	/// If not applicable, the function does nothing.
+	fn enable_bus_master(&self);
 
 	/// Returns the class of the device.
 	fn get_class(&self) -> u16;
@@ -33,10 +33,12 @@
 
 pub mod bar;
 pub mod bus;
+pub mod console;
 pub mod default;
 pub mod id;
 pub mod keyboard;
 pub mod manager;
+pub mod net;
 pub mod serial;
 pub mod storage;
 pub mod tty;
@@ -50,21 +52,26 @@ use crate::{
 		vfs::{ResolutionSettings, Resolved},
 		FileType, Mode, Stat,
 	},
-	syscall::ioctl,
+	syscall::{
+		ioctl,
+		poll::{POLLIN, POLLOUT},
+	},
 };
-use core::{ffi::c_void, fmt, num::NonZeroU64};
+use core::{cmp::min, ffi::c_void, fmt, num::NonZeroU64};
 use keyboard::KeyboardManager;
+use net::NetManager;
 use storage::StorageManager;
 use utils::{
 	collections::{
 		hashmap::HashMap,
 		path::{Path, PathBuf},
+		vec::Vec,
 	},
 	errno,
-	errno::EResult,
+	errno::{AllocResult, EResult},
 	lock::Mutex,
 	ptr::arc::Arc,
-	slice_copy, vec, TryClone,
+	vec, TryClone,
 };
 
 /// Enumeration representing the type of the device.
@@ -141,51 +148,133 @@ pub trait DeviceIO {
 	/// On success, the function returns the number of bytes written.
 	fn write(&self, off: u64, buf: &[u8]) -> EResult<usize>;
 
+	/// Reads contiguous sectors from the device into a list of buffers.
+	///
+	/// Arguments:
+	/// - `off` is the offset of the first sector on the device, in blocks
+	/// - `bufs` is the list of buffers to fill, in order
+	///
+	/// The size of each buffer has to be a multiple of the block size. Empty buffers are allowed.
+	///
+	/// Drivers should override this function to issue as few commands as possible to the
+	/// hardware. The default implementation calls [`Self::read`] for each buffer.
+	///
+	/// On success, the function returns the number of bytes read.
+	fn read_sectors(&self, off: u64, bufs: &mut [&mut [u8]]) -> EResult<usize> {
+		let blk_size = self.block_size().get();
+		let mut len = 0;
+		for buf in bufs {
+			len += self.read(off + len as u64 / blk_size, buf)?;
+		}
+		Ok(len)
+	}
+
+	/// Writes contiguous sectors to the device from a list of buffers.
+	///
+	/// Arguments:
+	/// - `off` is the offset of the first sector on the device, in blocks
+	/// - `bufs` is the list of buffers to write, in order
+	///
+	/// The size of each buffer has to be a multiple of the block size. Empty buffers are allowed.
+	///
+	/// Drivers should override this function to issue as few commands as possible to the
+	/// hardware. The default implementation calls [`Self::write`] for each buffer.
+	///
+	/// On success, the function returns the number of bytes written.
+	fn write_sectors(&self, off: u64, bufs: &[&[u8]]) -> EResult<usize> {
+		let blk_size = self.block_size().get();
+		let mut len = 0;
+		for buf in bufs {
+			len += self.write(off + len as u64 / blk_size, buf)?;
+		}
+		Ok(len)
+	}
+
 	/// Reads data from the device.
 	///
 	/// Contrary to [`Self::read`], `off` is in bytes and no block alignment is required.
+	///
+	/// The whole range is read with a single call to [`Self::read_sectors`].
 	fn read_bytes(&self, off: u64, buf: &mut [u8]) -> EResult<usize> {
-		let blk_size = self.block_size().get();
-		let mut blk = vec![0u8; blk_size as usize]?;
-		let start = off / blk_size;
-		let end = off
-			.checked_add(buf.len() as u64)
-			.ok_or_else(|| errno!(EOVERFLOW))?
-			.div_ceil(blk_size);
-		let mut buf_off = 0;
-		for i in start..end {
-			self.read(i, &mut blk)?;
-			let inner_off = (off % blk_size) as usize;
-			buf_off += slice_copy(&blk[inner_off..], &mut buf[buf_off..]);
+		if buf.is_empty() {
+			return Ok(0);
 		}
-		Ok(buf_off)
+		let blk_size = self.block_size().get() as usize;
+		off.checked_add(buf.len() as u64)
+			.ok_or_else(|| errno!(EOVERFLOW))?;
+		let inner_off = (off % blk_size as u64) as usize;
+		let (head_len, tail_len) = split_unaligned(off, buf.len(), blk_size);
+		// Unaligned parts go through bounce buffers
+		let mut head = bounce_buffer(head_len, blk_size)?;
+		let mut tail = bounce_buffer(tail_len, blk_size)?;
+		let (head_buf, body) = buf.split_at_mut(head_len);
+		let (body, tail_buf) = body.split_at_mut(body.len() - tail_len);
+		self.read_sectors(
+			off / blk_size as u64,
+			&mut [head.as_mut_slice(), body, tail.as_mut_slice()],
+		)?;
+		head_buf.copy_from_slice(&head[inner_off..(inner_off + head_len)]);
+		tail_buf.copy_from_slice(&tail[..tail_len]);
+		Ok(buf.len())
+	}
+
+	/// Reads data from the device like [`Self::read_bytes`], except that if no data is available,
+	/// the function returns [`errno::EAGAIN`] instead of waiting.
+	///
+	/// The default implementation calls [`Self::read_bytes`], for devices that never wait.
+	fn read_bytes_nonblock(&self, off: u64, buf: &mut [u8]) -> EResult<usize> {
+		self.read_bytes(off, buf)
 	}
 
 	/// Writes data to the device.
 	///
 	/// Contrary to [`Self::write`], `off` is in bytes and no block alignment is required.
+	///
+	/// Blocks that are only partially overwritten are read first. Then, the whole range is
+	/// written with a single call to [`Self::write_sectors`].
 	fn write_bytes(&self, off: u64, buf: &[u8]) -> EResult<usize> {
-		let blk_size = self.block_size().get();
-		let mut blk = vec![0u8; blk_size as usize]?;
-		let start = off / blk_size;
-		let end = off
-			.checked_add(buf.len() as u64)
-			.ok_or_else(|| errno!(EOVERFLOW))?
-			.div_ceil(blk_size);
-		let mut buf_off = 0;
-		for i in start..end {
-			self.read(i, &mut blk)?;
-			let inner_off = (off % blk_size) as usize;
-			buf_off += slice_copy(&buf[buf_off..], &mut blk[inner_off..]);
-			self.write(i, &blk)?;
+		if buf.is_empty() {
+			return Ok(0);
 		}
-		Ok(buf_off)
+		let blk_size = self.block_size().get() as usize;
+		off.checked_add(buf.len() as u64)
+			.ok_or_else(|| errno!(EOVERFLOW))?;
+		let start = off / blk_size as u64;
+		let inner_off = (off % blk_size as u64) as usize;
+		let (head_len, tail_len) = split_unaligned(off, buf.len(), blk_size);
+		let (head_buf, body) = buf.split_at(head_len);
+		let (body, tail_buf) = body.split_at(body.len() - tail_len);
+		// Read-modify-write unaligned parts
+		let mut head = bounce_buffer(head_len, blk_size)?;
+		if head_len > 0 {
+			self.read(start, &mut head)?;
+			head[inner_off..(inner_off + head_len)].copy_from_slice(head_buf);
+		}
+		let mut tail = bounce_buffer(tail_len, blk_size)?;
+		let tail_off = start + (head.len() + body.len()) as u64 / blk_size as u64;
+		if tail_len > 0 {
+			self.read(tail_off, &mut tail)?;
+			tail[..tail_len].copy_from_slice(tail_buf);
+		}
+		self.write_sectors(start, &[head.as_slice(), body, tail.as_slice()])?;
+		Ok(buf.len())
 	}
 
 	/// Polls the device with the given mask.
+	///
+	/// If the device is in an error state, the returned mask must contain `POLLERR`.
+	///
+	/// The default implementation reports the device as always ready for reading and writing.
 	fn poll(&self, mask: u32) -> EResult<u32> {
-		let _ = mask;
-		Err(errno!(EINVAL))
+		Ok(mask & (POLLIN | POLLOUT))
+	}
+
+	/// Registers the current process to be woken up on the next event on the device, for polling.
+	///
+	/// If the device cannot wake up processes, the function returns `false`, in which case it has
+	/// to be polled repeatedly. This is the default.
+	fn poll_wait(&self) -> EResult<bool> {
+		Ok(false)
 	}
 
 	/// Performs an ioctl operation on the device.
@@ -197,6 +286,40 @@ pub trait DeviceIO {
 		let _ = (request, argp);
 		Err(errno!(EINVAL))
 	}
+
+	/// Prepares the device for the system to be powered off, writing back volatile caches and
+	/// stopping the medium if relevant.
+	///
+	/// The default implementation does nothing.
+	fn shutdown(&self) -> EResult<()> {
+		Ok(())
+	}
+}
+
+/// Returns the lengths of the unaligned head and tail, in bytes, of a request of `len` bytes at
+/// offset `off` on a device with blocks of size `blk_size`.
+///
+/// If the whole request fits in a single unaligned block, it is considered as the head.
+fn split_unaligned(off: u64, len: usize, blk_size: usize) -> (usize, usize) {
+	let inner_off = (off % blk_size as u64) as usize;
+	let head = if inner_off != 0 {
+		min(blk_size - inner_off, len)
+	} else {
+		0
+	};
+	let tail = (len - head) % blk_size;
+	(head, tail)
+}
+
+/// Allocates a buffer of one block for an unaligned part of a request of size `len`.
+///
+/// If `len` is zero, the buffer is empty.
+fn bounce_buffer(len: usize, blk_size: usize) -> AllocResult<Vec<u8>> {
+	if len > 0 {
+		vec![0u8; blk_size]
+	} else {
+		Ok(Vec::new())
+	}
 }
 
 /// A device, either a block device or a char device.
@@ -364,6 +487,50 @@ pub fn unregister(id: &DeviceID) -> EResult<()> {
 	Ok(())
 }
 
+/// Replaces the devices with the IDs in `old` by the devices in `new`.
+///
+/// The replacement is atomic: a lookup either finds the old set of devices or the new one, never
+/// a mix of both. On failure, registered devices are left untouched.
+///
+/// If files management is initialized, the function then removes the files of the old devices and
+/// creates the files of the new ones. Files of devices present in both sets are kept in place.
+pub fn replace(old: &[DeviceID], new: Vec<Device>) -> EResult<()> {
+	// Allocate everything before modifying the list of devices
+	let mut new_devs = Vec::with_capacity(new.len())?;
+	for dev in new {
+		new_devs.push(Arc::new(dev)?)?;
+	}
+	let mut removed = Vec::with_capacity(old.len())?;
+	{
+		let mut devs = DEVICES.lock();
+		devs.reserve(new_devs.len())?;
+		for id in old {
+			if let Some(dev) = devs.remove(id) {
+				// Cannot fail since memory has been reserved
+				let _ = removed.push(dev);
+			}
+		}
+		for dev in &new_devs {
+			// Cannot fail since memory has been reserved
+			let _ = devs.insert(dev.id, dev.clone());
+		}
+	}
+	if file::is_init() {
+		for dev in removed {
+			let kept = new_devs
+				.iter()
+				.any(|new| new.id == dev.id && new.path == dev.path);
+			if !kept {
+				dev.remove_file()?;
+			}
+		}
+		for dev in new_devs {
+			Device::create_file(&dev.id, &dev.path, dev.mode)?;
+		}
+	}
+	Ok(())
+}
+
 /// Returns a mutable reference to the device with the given ID.
 ///
 /// If the device doesn't exist, the function returns `None`.
@@ -380,18 +547,14 @@ pub(crate) fn init() -> EResult<()> {
 	let storage_manager = StorageManager::new()?;
 	manager::register(storage_manager)?;
 
+	manager::register(NetManager::new())?;
+
+	storage::loop_device::create()?;
+
 	bus::detect()?;
 
-	// Testing disk I/O (if enabled)
-	#[cfg(config_debug_storage_test)]
-	{
-		let storage_manager_mutex = manager::get::<StorageManager>().unwrap();
-		let mut storage_manager = storage_manager_mutex.lock();
-		(&mut *storage_manager as &mut dyn core::any::Any)
-			.downcast_mut::<StorageManager>()
-			.unwrap()
-			.test();
-	}
+	// Run storage tests, if enabled
+	storage::integrity::init()?;
 
 	Ok(())
 }
@@ -409,3 +572,184 @@ pub(crate) fn stage2() -> EResult<()> {
 	}
 	Ok(())
 }
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use core::{
+		iter, mem,
+		sync::atomic::{AtomicUsize, Ordering::Relaxed},
+	};
+	use utils::{errno::CollectResult, format};
+
+	/// The size of a block of [`RamDisk`].
+	const BLK_SIZE: usize = 16;
+
+	/// A RAM disk counting the sector requests it receives.
+	struct RamDisk {
+		/// The content of the disk.
+		data: Mutex<Vec<u8>>,
+		/// The number of calls to `read_sectors`.
+		reads: AtomicUsize,
+		/// The number of calls to `write_sectors`.
+		writes: AtomicUsize,
+	}
+
+	impl RamDisk {
+		/// Creates a disk of `blocks` blocks, where each byte is initialized to its offset.
+		fn new(blocks: usize) -> Self {
+			let data = (0..(blocks * BLK_SIZE))
+				.map(|i| i as u8)
+				.collect::<CollectResult<Vec<_>>>()
+				.0
+				.unwrap();
+			Self {
+				data: Mutex::new(data),
+				reads: AtomicUsize::new(0),
+				writes: AtomicUsize::new(0),
+			}
+		}
+	}
+
+	impl DeviceIO for RamDisk {
+		fn block_size(&self) -> NonZeroU64 {
+			NonZeroU64::new(BLK_SIZE as _).unwrap()
+		}
+
+		fn blocks_count(&self) -> u64 {
+			(self.data.lock().len() / BLK_SIZE) as _
+		}
+
+		fn read(&self, off: u64, buf: &mut [u8]) -> EResult<usize> {
+			assert_eq!(buf.len() % BLK_SIZE, 0);
+			let start = off as usize * BLK_SIZE;
+			buf.copy_from_slice(&self.data.lock().as_slice()[start..(start + buf.len())]);
+			Ok(buf.len())
+		}
+
+		fn write(&self, off: u64, buf: &[u8]) -> EResult<usize> {
+			assert_eq!(buf.len() % BLK_SIZE, 0);
+			let start = off as usize * BLK_SIZE;
+			self.data.lock().as_mut_slice()[start..(start + buf.len())].copy_from_slice(buf);
+			Ok(buf.len())
+		}
+
+		fn read_sectors(&self, off: u64, bufs: &mut [&mut [u8]]) -> EResult<usize> {
+			self.reads.fetch_add(1, Relaxed);
+			let mut len = 0;
+			for buf in bufs {
+				len += self.read(off + (len / BLK_SIZE) as u64, buf)?;
+			}
+			Ok(len)
+		}
+
+		fn write_sectors(&self, off: u64, bufs: &[&[u8]]) -> EResult<usize> {
+			self.writes.fetch_add(1, Relaxed);
+			let mut len = 0;
+			for buf in bufs {
+				len += self.write(off + (len / BLK_SIZE) as u64, buf)?;
+			}
+			Ok(len)
+		}
+	}
+
+	/// Returns a buffer of `len` bytes set to `val`.
+	fn filled(len: usize, val: u8) -> Vec<u8> {
+		iter::repeat(val)
+			.take(len)
+			.collect::<CollectResult<Vec<_>>>()
+			.0
+			.unwrap()
+	}
+
+	/// Requests covering aligned, unaligned and single-block ranges, as `(offset, length)`.
+	const REQUESTS: &[(usize, usize)] =
+		&[(0, 0), (0, 32), (3, 5), (5, 11), (16, 20), (7, 50), (13, 3)];
+
+	#[test_case]
+	fn device_split_unaligned() {
+		assert_eq!(split_unaligned(0, 32, BLK_SIZE), (0, 0));
+		assert_eq!(split_unaligned(3, 5, BLK_SIZE), (5, 0));
+		assert_eq!(split_unaligned(5, 11, BLK_SIZE), (11, 0));
+		assert_eq!(split_unaligned(16, 20, BLK_SIZE), (0, 4));
+		assert_eq!(split_unaligned(7, 50, BLK_SIZE), (9, 9));
+	}
+
+	#[test_case]
+	fn device_read_bytes() {
+		let disk = RamDisk::new(8);
+		for &(off, len) in REQUESTS {
+			let mut buf = filled(len, 0);
+			assert_eq!(disk.read_bytes(off as _, buf.as_mut_slice()), Ok(len));
+			assert_eq!(
+				buf.as_slice(),
+				&disk.data.lock().as_slice()[off..(off + len)]
+			);
+		}
+		// Empty requests do not reach the device
+		assert_eq!(disk.reads.load(Relaxed), REQUESTS.len() - 1);
+	}
+
+	#[test_case]
+	fn device_write_bytes() {
+		for &(off, len) in REQUESTS {
+			let disk = RamDisk::new(8);
+			let mut expected = disk.data.lock().try_clone().unwrap();
+			let buf = filled(len, 0xff);
+			assert_eq!(disk.write_bytes(off as _, buf.as_slice()), Ok(len));
+			expected.as_mut_slice()[off..(off + len)].fill(0xff);
+			// Bytes around the request are preserved
+			assert_eq!(disk.data.lock().as_slice(), expected.as_slice());
+			assert_eq!(disk.writes.load(Relaxed), (len > 0) as usize);
+		}
+	}
+
+	/// Returns the ID of the test device with the given minor number.
+	fn test_id(minor: u32) -> DeviceID {
+		DeviceID {
+			dev_type: DeviceType::Block,
+			major: 0xfff,
+			minor,
+		}
+	}
+
+	/// Creates a test device with the given minor number, backed by a disk of `blocks` blocks.
+	fn test_device(minor: u32, blocks: usize) -> Device {
+		let path = PathBuf::try_from(format!("/dev/test{minor}").unwrap()).unwrap();
+		Device::new(test_id(minor), path, 0o600, RamDisk::new(blocks)).unwrap()
+	}
+
+	/// Returns the number of blocks of the registered device with the given minor number.
+	fn test_blocks(minor: u32) -> Option<u64> {
+		get(&test_id(minor)).map(|dev| dev.get_io().blocks_count())
+	}
+
+	#[test_case]
+	fn device_replace() {
+		// Removing a device file requires files management, which is not initialized during
+		// tests. Devices are thus kept alive by leaking a reference to them
+		let ids = [test_id(0), test_id(1), test_id(2)];
+		let leak = || ids.iter().filter_map(get).for_each(mem::forget);
+		let devs = [test_device(0, 1), test_device(1, 1)];
+		replace(
+			&[],
+			devs.into_iter().collect::<CollectResult<_>>().0.unwrap(),
+		)
+		.unwrap();
+		assert_eq!((test_blocks(0), test_blocks(1)), (Some(1), Some(1)));
+		leak();
+		let devs = [test_device(1, 2), test_device(2, 3)];
+		replace(
+			&ids,
+			devs.into_iter().collect::<CollectResult<_>>().0.unwrap(),
+		)
+		.unwrap();
+		assert_eq!(
+			(test_blocks(0), test_blocks(1), test_blocks(2)),
+			(None, Some(2), Some(3))
+		);
+		leak();
+		replace(&ids, Vec::new()).unwrap();
+		assert!(ids.iter().all(|id| get(id).is_none()));
+	}
+}
@@ -0,0 +1,73 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Network interface controllers.
+//!
+//! Detected controllers are registered as network interfaces named `ethN`.
+
+pub mod virtio;
+
+use crate::{
+	device::{bus::pci, manager::PhysicalDevice, DeviceManager},
+	net,
+};
+use utils::{errno::EResult, format, TryClone};
+
+/// Manages network interface controllers.
+#[derive(Default)]
+pub struct NetManager {
+	/// The number of registered interfaces, used to name the next one.
+	count: usize,
+}
+
+impl NetManager {
+	/// Creates a new instance.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Registers an interface for the controller `dev`, if it is supported.
+	fn add(&mut self, dev: &dyn PhysicalDevice) -> EResult<()> {
+		let name = format!("eth{}", self.count)?;
+		// TODO handle other controllers
+		let Some(iface) = virtio::VirtioNet::new(dev, name.try_clone()?)? else {
+			return Ok(());
+		};
+		net::register_iface(name, iface)?;
+		self.count += 1;
+		Ok(())
+	}
+}
+
+impl DeviceManager for NetManager {
+	fn on_plug(&mut self, dev: &dyn PhysicalDevice) -> EResult<()> {
+		// Ignore non-network devices
+		if dev.get_class() != pci::CLASS_NETWORK_CONTROLLER {
+			return Ok(());
+		}
+		if let Err(e) = self.add(dev) {
+			crate::log!(Net, Error, "Could not register network device: {e}");
+		}
+		Ok(())
+	}
+
+	fn on_unplug(&mut self, _dev: &dyn PhysicalDevice) -> EResult<()> {
+		// TODO remove interface
+		Ok(())
+	}
+}
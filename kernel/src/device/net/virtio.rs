@@ -0,0 +1,514 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Driver for virtio network devices, using the legacy PCI interface.
+//!
+//! Legacy and transitional devices are supported, which includes QEMU's default `virtio-net-pci`.
+//!
+//! When the device signals that frames have been received, they are read from a work item and
+//! passed to the network stack, which gives their buffers back to the device.
+
+use crate::{
+	crypto::rand,
+	device::{bar::BAR, manager::PhysicalDevice},
+	event,
+	event::{CallbackHook, CallbackResult},
+	memory::{buddy, buddy::FrameOrder, VirtAddr},
+	net,
+	net::{buff::BuffList, BindAddress, Interface, MAC},
+	workqueue,
+};
+use core::{
+	cmp::min,
+	ptr,
+	ptr::NonNull,
+	sync::atomic::{fence, Ordering::SeqCst},
+};
+use utils::{
+	collections::{string::String, vec::Vec},
+	errno,
+	errno::{EResult, Errno},
+	limits::PAGE_SIZE,
+	TryClone,
+};
+
+/// The vendor ID of virtio devices.
+const VENDOR_ID: u16 = 0x1af4;
+/// The device ID of legacy virtio network devices.
+const DEVICE_ID: u16 = 0x1000;
+
+/// Register: features supported by the device.
+const REG_DEVICE_FEATURES: usize = 0x00;
+/// Register: features used by the driver.
+const REG_GUEST_FEATURES: usize = 0x04;
+/// Register: physical page number of the selected queue.
+const REG_QUEUE_ADDRESS: usize = 0x08;
+/// Register: number of descriptors of the selected queue.
+const REG_QUEUE_SIZE: usize = 0x0c;
+/// Register: index of the selected queue.
+const REG_QUEUE_SELECT: usize = 0x0e;
+/// Register: notifies the device that buffers are available on a queue.
+const REG_QUEUE_NOTIFY: usize = 0x10;
+/// Register: status of the device.
+const REG_DEVICE_STATUS: usize = 0x12;
+/// Register: pending interrupts. Reading it acknowledges them.
+const REG_ISR_STATUS: usize = 0x13;
+/// Offset of the device-specific configuration, when MSI-X is disabled.
+const REG_CONFIG: usize = 0x14;
+
+/// Interrupt status: the device has used buffers of a queue.
+const ISR_QUEUE: u8 = 1;
+
+/// Device status: the guest has noticed the device.
+const STATUS_ACKNOWLEDGE: u8 = 1;
+/// Device status: the guest knows how to drive the device.
+const STATUS_DRIVER: u8 = 2;
+/// Device status: the driver is ready.
+const STATUS_DRIVER_OK: u8 = 4;
+/// Device status: the driver gave up on the device.
+const STATUS_FAILED: u8 = 128;
+
+/// Feature: the device has a MAC address in its configuration.
+const F_MAC: u32 = 1 << 5;
+/// Feature: the device reports its link status in its configuration.
+const F_STATUS: u32 = 1 << 16;
+/// Link status: the link is up.
+const NET_S_LINK_UP: u16 = 1;
+
+/// The index of the receive queue.
+const QUEUE_RX: u16 = 0;
+/// The index of the transmit queue.
+const QUEUE_TX: u16 = 1;
+
+/// Descriptor flag: the buffer is written by the device.
+const DESC_F_WRITE: u16 = 2;
+/// Available ring flag: the device should not interrupt when consuming buffers.
+const AVAIL_F_NO_INTERRUPT: u16 = 1;
+
+/// The size of the header preceding each frame.
+const HDR_SIZE: usize = 10;
+/// The size of a buffer, large enough for a header and an Ethernet frame.
+const BUFFER_SIZE: usize = 2048;
+/// The maximum number of buffers per queue.
+const MAX_BUFFERS: u16 = 64;
+
+/// A virtqueue descriptor.
+#[repr(C)]
+struct Desc {
+	/// The physical address of the buffer.
+	addr: u64,
+	/// The length of the buffer.
+	len: u32,
+	/// Descriptor flags.
+	flags: u16,
+	/// The next descriptor of the chain.
+	next: u16,
+}
+
+/// Allocates a zeroed, physically contiguous memory region of `size` bytes.
+fn alloc_dma(size: usize) -> EResult<(NonNull<u8>, FrameOrder)> {
+	let order = buddy::get_order(size.div_ceil(PAGE_SIZE));
+	let ptr = buddy::alloc_kernel(order)?;
+	unsafe {
+		ptr::write_bytes(ptr.as_ptr(), 0, buddy::get_frame_size(order));
+	}
+	Ok((ptr, order))
+}
+
+/// Returns the physical address of the kernel pointer `ptr`.
+fn phys_addr(ptr: *mut u8) -> usize {
+	VirtAddr::from(ptr).kernel_to_physical().unwrap().0
+}
+
+/// A queue of buffers shared with the device, each associated with one descriptor.
+struct Virtqueue {
+	/// The index of the queue on the device.
+	index: u16,
+	/// The number of descriptors.
+	size: u16,
+	/// The descriptor table and rings.
+	ring: NonNull<u8>,
+	/// The order of the allocation of `ring`.
+	ring_order: FrameOrder,
+	/// The offset of the used ring in `ring`.
+	used_off: usize,
+	/// The buffers, of [`BUFFER_SIZE`] bytes each.
+	buffers: NonNull<u8>,
+	/// The order of the allocation of `buffers`.
+	buffers_order: FrameOrder,
+	/// The number of buffers.
+	buffers_count: u16,
+	/// The index of the next entry to consume in the used ring.
+	last_used: u16,
+}
+
+impl Virtqueue {
+	/// Sets up the queue with index `index` of the device.
+	///
+	/// Descriptors point to their buffer, with the flags `flags`. `interrupt` tells whether the
+	/// device should interrupt the CPU when it has used buffers.
+	fn new(bar: &BAR, index: u16, flags: u16, interrupt: bool) -> EResult<Self> {
+		bar.write::<u16>(REG_QUEUE_SELECT, index as _);
+		let size = bar.read::<u16>(REG_QUEUE_SIZE) as u16;
+		if size == 0 {
+			return Err(errno!(ENODEV));
+		}
+		let queue = Self::alloc(index, size)?;
+		for id in 0..queue.buffers_count {
+			unsafe {
+				queue.desc(id).write(Desc {
+					addr: phys_addr(queue.buffer(id)) as _,
+					len: BUFFER_SIZE as _,
+					flags,
+					next: 0,
+				});
+			}
+		}
+		if !interrupt {
+			unsafe {
+				// Flags of the available ring
+				let avail = queue.ring.as_ptr().add(16 * size as usize) as *mut u16;
+				avail.write_volatile(AVAIL_F_NO_INTERRUPT);
+			}
+		}
+		bar.write::<u32>(
+			REG_QUEUE_ADDRESS,
+			(phys_addr(queue.ring.as_ptr()) / PAGE_SIZE) as _,
+		);
+		Ok(queue)
+	}
+
+	/// Allocates the memory for the queue with index `index` and `size` descriptors.
+	fn alloc(index: u16, size: u16) -> EResult<Self> {
+		// Layout mandated by the legacy interface
+		let size_usize = size as usize;
+		let used_off = (18 * size_usize + 6).next_multiple_of(PAGE_SIZE);
+		let ring_size = used_off + (8 * size_usize + 6).next_multiple_of(PAGE_SIZE);
+		let (ring, ring_order) = alloc_dma(ring_size)?;
+		let buffers_count = min(size, MAX_BUFFERS);
+		let (buffers, buffers_order) = alloc_dma(buffers_count as usize * BUFFER_SIZE)
+			.inspect_err(|_| unsafe { buddy::free_kernel(ring.as_ptr(), ring_order) })?;
+		Ok(Self {
+			index,
+			size,
+			ring,
+			ring_order,
+			used_off,
+			buffers,
+			buffers_order,
+			buffers_count,
+			last_used: 0,
+		})
+	}
+
+	/// Returns a pointer to the descriptor `id`.
+	fn desc(&self, id: u16) -> *mut Desc {
+		unsafe { self.ring.cast::<Desc>().as_ptr().add(id as _) }
+	}
+
+	/// Returns a pointer to the buffer of descriptor `id`.
+	fn buffer(&self, id: u16) -> *mut u8 {
+		unsafe { self.buffers.as_ptr().add(id as usize * BUFFER_SIZE) }
+	}
+
+	/// Makes the buffer of descriptor `id` available to the device, with a length of `len`
+	/// bytes.
+	fn push(&mut self, id: u16, len: usize) {
+		unsafe {
+			(*self.desc(id)).len = len as _;
+			let avail = self.ring.as_ptr().add(16 * self.size as usize) as *mut u16;
+			let idx = avail.add(1).read_volatile();
+			avail.add(2 + (idx % self.size) as usize).write_volatile(id);
+			// Publish the entry before the index
+			fence(SeqCst);
+			avail.add(1).write_volatile(idx.wrapping_add(1));
+		}
+	}
+
+	/// Returns the next buffer the device has finished with, along with the number of bytes it
+	/// wrote to it.
+	fn pop(&mut self) -> Option<(u16, usize)> {
+		unsafe {
+			let used = self.ring.as_ptr().add(self.used_off);
+			let idx = (used as *const u16).add(1).read_volatile();
+			if idx == self.last_used {
+				return None;
+			}
+			// Read the entry after the index
+			fence(SeqCst);
+			let elem = (used.add(4) as *const u32).add(2 * (self.last_used % self.size) as usize);
+			let id = elem.read_volatile();
+			let len = elem.add(1).read_volatile();
+			self.last_used = self.last_used.wrapping_add(1);
+			Some((id as _, len as _))
+		}
+	}
+
+	/// Notifies the device that new buffers are available.
+	fn notify(&self, bar: &BAR) {
+		fence(SeqCst);
+		bar.write::<u16>(REG_QUEUE_NOTIFY, self.index as _);
+	}
+}
+
+impl Drop for Virtqueue {
+	fn drop(&mut self) {
+		unsafe {
+			buddy::free_kernel(self.buffers.as_ptr(), self.buffers_order);
+			buddy::free_kernel(self.ring.as_ptr(), self.ring_order);
+		}
+	}
+}
+
+/// A virtio network device.
+pub struct VirtioNet {
+	/// The name of the interface.
+	name: String,
+	/// The I/O registers.
+	bar: BAR,
+	/// The MAC address.
+	mac: MAC,
+	/// Tells whether the device reports its link status.
+	has_status: bool,
+
+	/// The receive queue.
+	rx: Virtqueue,
+	/// The transmit queue.
+	tx: Virtqueue,
+	/// Descriptors of the transmit queue that are not in use.
+	tx_free: Vec<u16>,
+
+	/// The hook of the interrupt handler.
+	_hook: Option<CallbackHook>,
+}
+
+impl VirtioNet {
+	/// Initializes the given device as the interface `name`.
+	///
+	/// If the device is not a virtio network device, the function returns `None`.
+	pub fn new(dev: &dyn PhysicalDevice, name: String) -> EResult<Option<Self>> {
+		if dev.get_vendor_id() != VENDOR_ID || dev.get_device_id() != DEVICE_ID {
+			return Ok(None);
+		}
+		// The legacy interface is on the first BAR, in I/O space
+		let Some(Some(
+			bar @ BAR::IOSpace {
+				..
+			},
+		)) = dev.get_bars().first().cloned()
+		else {
+			return Err(errno!(ENODEV));
+		};
+		dev.enable_bus_master();
+		// Reset, then negotiate features
+		bar.write::<u8>(REG_DEVICE_STATUS, 0);
+		bar.write::<u8>(REG_DEVICE_STATUS, STATUS_ACKNOWLEDGE as _);
+		bar.write::<u8>(REG_DEVICE_STATUS, (STATUS_ACKNOWLEDGE | STATUS_DRIVER) as _);
+		let features = bar.read::<u32>(REG_DEVICE_FEATURES) as u32 & (F_MAC | F_STATUS);
+		bar.write::<u32>(REG_GUEST_FEATURES, features as _);
+		// On failure, reset the device so that it stops using the queues before they are freed,
+		// then tell it the driver gave up
+		let fail = |e: Errno| {
+			bar.write::<u8>(REG_DEVICE_STATUS, 0);
+			bar.write::<u8>(REG_DEVICE_STATUS, STATUS_FAILED as _);
+			e
+		};
+		let mut rx = Virtqueue::new(&bar, QUEUE_RX, DESC_F_WRITE, true).map_err(fail)?;
+		let tx = Virtqueue::new(&bar, QUEUE_TX, 0, false).map_err(fail)?;
+		let (tx_free, hook) = (|| -> EResult<_> {
+			let mut tx_free = Vec::with_capacity(tx.buffers_count as _)?;
+			for id in 0..tx.buffers_count {
+				tx_free.push(id)?;
+			}
+			// Received frames are processed out of the interrupt handler
+			let hook = match dev.get_interrupt_line() {
+				Some(line) => {
+					let bar = bar.clone();
+					let name = name.try_clone()?;
+					event::register_callback(0x20 + line as u32, move |_, _, _, _| {
+						// Reading the status acknowledges the interrupt
+						let isr = bar.read::<u8>(REG_ISR_STATUS) as u8;
+						if isr & ISR_QUEUE != 0 {
+							// On failure, frames are processed on the next interrupt
+							if let Ok(name) = name.try_clone() {
+								let _ = workqueue::queue_work(move || net::receive(&name));
+							}
+						}
+						CallbackResult::Continue
+					})?
+				}
+				None => None,
+			};
+			Ok((tx_free, hook))
+		})()
+		.map_err(fail)?;
+		let mut mac = [0; 6];
+		if features & F_MAC != 0 {
+			for (i, b) in mac.iter_mut().enumerate() {
+				*b = bar.read::<u8>(REG_CONFIG + i) as _;
+			}
+		} else {
+			// Random, locally administered unicast address
+			if let Some(pool) = &mut *rand::ENTROPY_POOL.lock() {
+				pool.read(&mut mac, true);
+			}
+			mac[0] = (mac[0] & !1) | 2;
+		}
+		// Give receive buffers to the device
+		for id in 0..rx.buffers_count {
+			rx.push(id, BUFFER_SIZE);
+		}
+		bar.write::<u8>(
+			REG_DEVICE_STATUS,
+			(STATUS_ACKNOWLEDGE | STATUS_DRIVER | STATUS_DRIVER_OK) as _,
+		);
+		rx.notify(&bar);
+		Ok(Some(Self {
+			name,
+			bar,
+			mac,
+			has_status: features & F_STATUS != 0,
+
+			rx,
+			tx,
+			tx_free,
+
+			_hook: hook,
+		}))
+	}
+}
+
+impl Interface for VirtioNet {
+	fn get_name(&self) -> &[u8] {
+		&self.name
+	}
+
+	fn is_up(&self) -> bool {
+		!self.has_status || self.bar.read::<u16>(REG_CONFIG + 6) as u16 & NET_S_LINK_UP != 0
+	}
+
+	fn get_mac(&self) -> &MAC {
+		&self.mac
+	}
+
+	fn get_addresses(&self) -> &[BindAddress] {
+		// TODO address configuration
+		&[]
+	}
+
+	fn get_mtu(&self) -> u32 {
+		(BUFFER_SIZE - HDR_SIZE) as _
+	}
+
+	fn read(&mut self, buff: &mut [u8]) -> EResult<u64> {
+		let Some((id, len)) = self.rx.pop() else {
+			return Err(errno!(EAGAIN));
+		};
+		// Skip the header
+		let frame_len = len.clamp(HDR_SIZE, BUFFER_SIZE) - HDR_SIZE;
+		let len = min(buff.len(), frame_len);
+		unsafe {
+			ptr::copy_nonoverlapping(self.rx.buffer(id).add(HDR_SIZE), buff.as_mut_ptr(), len);
+		}
+		// Give the buffer back to the device
+		self.rx.push(id, BUFFER_SIZE);
+		self.rx.notify(&self.bar);
+		Ok(len as _)
+	}
+
+	fn write(&mut self, buff: &BuffList<'_>) -> EResult<u64> {
+		let len = buff.len();
+		if len > BUFFER_SIZE - HDR_SIZE {
+			return Err(errno!(EMSGSIZE));
+		}
+		// Reclaim buffers the device has sent
+		while let Some((id, _)) = self.tx.pop() {
+			self.tx_free.push(id)?;
+		}
+		let Some(id) = self.tx_free.pop() else {
+			return Err(errno!(EAGAIN));
+		};
+		let buf = self.tx.buffer(id);
+		unsafe {
+			// No offloading
+			ptr::write_bytes(buf, 0, HDR_SIZE);
+			let mut off = HDR_SIZE;
+			for b in buff.iter() {
+				ptr::copy_nonoverlapping(b.as_ptr(), buf.add(off), b.len());
+				off += b.len();
+			}
+		}
+		self.tx.push(id, HDR_SIZE + len);
+		self.tx.notify(&self.bar);
+		Ok(len as _)
+	}
+}
+
+impl Drop for VirtioNet {
+	fn drop(&mut self) {
+		// Stop the device before freeing the queues
+		self.bar.write::<u8>(REG_DEVICE_STATUS, 0);
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	/// Writes the entry `(id, len)` at position `pos` of the used ring of `queue`, then sets the
+	/// ring's index to `idx`, as the device would.
+	fn device_use(queue: &Virtqueue, pos: u16, id: u16, len: u32, idx: u16) {
+		unsafe {
+			let used = queue.ring.as_ptr().add(queue.used_off);
+			let elem = (used.add(4) as *mut u32).add(2 * (pos % queue.size) as usize);
+			elem.write(id as _);
+			elem.add(1).write(len);
+			(used as *mut u16).add(1).write(idx);
+		}
+	}
+
+	#[test_case]
+	fn virtqueue_push() {
+		let mut queue = Virtqueue::alloc(0, 8).unwrap();
+		assert_eq!(queue.buffers_count, 8);
+		queue.push(3, 100);
+		queue.push(5, BUFFER_SIZE);
+		unsafe {
+			assert_eq!((*queue.desc(3)).len, 100);
+			assert_eq!((*queue.desc(5)).len, BUFFER_SIZE as u32);
+			let avail = queue.ring.as_ptr().add(16 * 8) as *const u16;
+			assert_eq!(avail.add(1).read(), 2);
+			assert_eq!((avail.add(2).read(), avail.add(3).read()), (3, 5));
+		}
+	}
+
+	#[test_case]
+	fn virtqueue_pop() {
+		let mut queue = Virtqueue::alloc(0, 8).unwrap();
+		assert_eq!(queue.pop(), None);
+		device_use(&queue, 0, 3, 42, 1);
+		assert_eq!(queue.pop(), Some((3, 42)));
+		assert_eq!(queue.pop(), None);
+		// The index wraps around
+		queue.last_used = u16::MAX;
+		device_use(&queue, u16::MAX, 5, 10, 0);
+		assert_eq!(queue.pop(), Some((5, 10)));
+		assert_eq!(queue.last_used, 0);
+		assert_eq!(queue.pop(), None);
+	}
+}
@@ -152,7 +152,55 @@ impl Serial {
 		}
 	}
 
-	// TODO read
+	/// Reads the data received on the port into `buf`, without waiting.
+	///
+	/// The function returns the number of bytes read. If the port does not exist, the function
+	/// returns `0`.
+	pub fn read(&mut self, buf: &mut [u8]) -> usize {
+		if !self.active {
+			self.active = self.probe();
+		}
+		if !self.active {
+			return 0;
+		}
+		let mut len = 0;
+		while len < buf.len() && self.is_data_ready() {
+			buf[len] = unsafe { io::inb(self.regs_off + DATA_REG_OFF) };
+			len += 1;
+		}
+		len
+	}
+
+	/// Returns the IRQ raised by the port.
+	pub fn get_irq(&self) -> u8 {
+		match self.regs_off {
+			COM1 | COM3 => 4,
+			_ => 3,
+		}
+	}
+
+	/// Enables or disables the interrupt raised when data is received on the port.
+	///
+	/// The interrupt remains raised until the received data is read.
+	///
+	/// If the port does not exist, the function does nothing.
+	pub fn set_rx_interrupt(&mut self, enable: bool) {
+		if !self.active {
+			self.active = self.probe();
+		}
+		if !self.active {
+			return;
+		}
+		let ier = if enable { INTERRUPT_DATA_AVAILABLE } else { 0 };
+		unsafe {
+			io::outb(self.regs_off + INTERRUPT_REG_OFF, ier);
+		}
+	}
+
+	/// Tells whether received data is available to be read.
+	fn is_data_ready(&self) -> bool {
+		(unsafe { io::inb(self.regs_off + LINE_STATUS_REG_OFF) } & LINE_STATUS_DR) != 0
+	}
 
 	/// Tells whether the transmission buffer is empty.
 	fn is_transmit_empty(&self) -> bool {
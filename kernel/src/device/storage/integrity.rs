@@ -0,0 +1,586 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Storage integrity testing.
+//!
+//! Workloads write pseudo-random data to storage devices, then read it back to check it. Results
+//! are printed in the kernel's log and are available in `/proc/storage_test`.
+//!
+//! Workloads run on a dedicated RAM-backed device, `/dev/stest`, which records a checksum for each
+//! sector it stores and verifies it on each read, so that memory corruptions are detected in
+//! addition to errors in the I/O path.
+//!
+//! If the `storage_test.disks` parameter is set, workloads also run on real disks at boot, before
+//! any filesystem is mounted. The original content of each tested sector is saved beforehand and
+//! restored afterwards. Data may still be lost if the system stops during the test.
+//!
+//! Tests are configured with the following parameters:
+//! - `storage_test.workload`: `none` (default), `seq`, `random` or `mixed`. Setting it at runtime
+//!   runs the tests again
+//! - `storage_test.sectors`: the number of sectors tested on each device
+//! - `storage_test.iterations`: the number of passes of the workload
+//! - `storage_test.seed`: the seed of the pseudo-random generator
+//! - `storage_test.disks`: whether real disks are tested too
+
+use crate::{
+	crypto::checksum,
+	device,
+	device::{id, manager, storage::StorageManager, Device, DeviceID, DeviceIO, DeviceType},
+	log,
+	module::{
+		param,
+		param::{Param, ParamType},
+	},
+};
+use core::{
+	cmp::{max, min},
+	fmt,
+	mem::ManuallyDrop,
+	num::NonZeroU64,
+	sync::atomic::{
+		AtomicBool,
+		Ordering::{Acquire, Relaxed, Release},
+	},
+};
+use utils::{
+	collections::{path::PathBuf, vec::Vec},
+	errno,
+	errno::{AllocResult, CollectResult, EResult},
+	lock::{atomic::AtomicU64, Mutex},
+	math,
+	ptr::arc::Arc,
+	vec,
+};
+
+/// The size of a tested sector, unless the device's blocks are larger.
+const SECTOR_SIZE: usize = 512;
+/// The maximum number of mismatches printed for each device.
+const MAX_PRINTED_MISMATCHES: u64 = 8;
+
+/// A workload, describing the order of operations on sectors.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Workload {
+	/// No test.
+	None,
+	/// Write every sector in order, then verify them in order.
+	Sequential,
+	/// Write every sector in a random order, then verify them in another random order.
+	Random,
+	/// Interleave writes and verifications of random sectors.
+	Mixed,
+}
+
+impl ParamType for Workload {
+	fn parse(s: &[u8]) -> Option<Self> {
+		match s {
+			b"none" => Some(Self::None),
+			b"seq" => Some(Self::Sequential),
+			b"random" => Some(Self::Random),
+			b"mixed" => Some(Self::Mixed),
+			_ => None,
+		}
+	}
+}
+
+impl fmt::Display for Workload {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let s = match self {
+			Self::None => "none",
+			Self::Sequential => "seq",
+			Self::Random => "random",
+			Self::Mixed => "mixed",
+		};
+		f.write_str(s)
+	}
+}
+
+/// The workload to run.
+static WORKLOAD: Param<Workload> = Param::new(
+	"storage_test",
+	"workload",
+	Workload::None,
+	true,
+	Some(on_workload_change),
+);
+/// The number of sectors tested on each device.
+static SECTORS: Param<u64> = Param::new("storage_test", "sectors", 2048, true, None);
+/// The number of passes of the workload.
+static ITERATIONS: Param<u32> = Param::new("storage_test", "iterations", 4, true, None);
+/// The seed of the pseudo-random generator.
+static SEED: Param<u32> = Param::new("storage_test", "seed", 42, true, None);
+/// Whether real disks are tested at boot.
+static DISKS: Param<bool> = Param::new("storage_test", "disks", false, false, None);
+
+/// Tells whether boot is complete. After that, real disks are never tested since they may be in
+/// use.
+static BOOTED: AtomicBool = AtomicBool::new(false);
+/// The number of checksum mismatches detected by the test device.
+static CHECKSUM_ERRORS: AtomicU64 = AtomicU64::new(0);
+/// The test device, created on the first run.
+static TEST_DISK: Mutex<Option<Arc<dyn DeviceIO>>> = Mutex::new(None);
+/// The reports of the last run.
+static REPORTS: Mutex<Vec<Report>> = Mutex::new(Vec::new());
+
+/// A RAM disk recording a CRC32 checksum for each sector, verified on each read.
+struct ChecksumDisk {
+	/// The lookup table for CRC32.
+	crc_table: [u32; 256],
+	/// The content of the disk.
+	data: Mutex<Vec<u8>>,
+	/// The checksum of each sector.
+	sums: Mutex<Vec<u32>>,
+}
+
+impl ChecksumDisk {
+	/// Creates a zeroed disk of `sectors` sectors.
+	fn new(sectors: usize) -> AllocResult<Self> {
+		let mut crc_table = [0; 256];
+		checksum::compute_crc32_lookuptable(&mut crc_table, 0xedb88320);
+		let empty = checksum::compute_crc32(&[0; SECTOR_SIZE], &crc_table);
+		Ok(Self {
+			crc_table,
+			data: Mutex::new(vec![0; sectors * SECTOR_SIZE]?),
+			sums: Mutex::new(vec![empty; sectors]?),
+		})
+	}
+
+	/// Checks the bounds of a request of `len` bytes at sector `off`, returning the range of
+	/// bytes.
+	fn range(&self, off: u64, len: usize) -> EResult<(usize, usize)> {
+		let size = self.data.lock().len();
+		if len % SECTOR_SIZE != 0 {
+			return Err(errno!(EINVAL));
+		}
+		let start = (off as usize)
+			.checked_mul(SECTOR_SIZE)
+			.ok_or_else(|| errno!(EINVAL))?;
+		let end = start.checked_add(len).ok_or_else(|| errno!(EINVAL))?;
+		if end > size {
+			return Err(errno!(EINVAL));
+		}
+		Ok((start, end))
+	}
+}
+
+impl DeviceIO for ChecksumDisk {
+	fn block_size(&self) -> NonZeroU64 {
+		(SECTOR_SIZE as u64).try_into().unwrap()
+	}
+
+	fn blocks_count(&self) -> u64 {
+		self.sums.lock().len() as _
+	}
+
+	fn read(&self, off: u64, buf: &mut [u8]) -> EResult<usize> {
+		let (start, end) = self.range(off, buf.len())?;
+		let data = self.data.lock();
+		let sums = self.sums.lock();
+		let mut corrupted = false;
+		for (i, sector) in data[start..end].chunks_exact(SECTOR_SIZE).enumerate() {
+			let sector_off = off as usize + i;
+			if checksum::compute_crc32(sector, &self.crc_table) != sums[sector_off] {
+				log!(
+					Storage,
+					Error,
+					"stest: checksum mismatch on sector {sector_off}"
+				);
+				CHECKSUM_ERRORS.fetch_add(1, Relaxed);
+				corrupted = true;
+			}
+		}
+		if corrupted {
+			return Err(errno!(EIO));
+		}
+		buf.copy_from_slice(&data[start..end]);
+		Ok(buf.len())
+	}
+
+	fn write(&self, off: u64, buf: &[u8]) -> EResult<usize> {
+		let (start, end) = self.range(off, buf.len())?;
+		let mut data = self.data.lock();
+		let mut sums = self.sums.lock();
+		data[start..end].copy_from_slice(buf);
+		for (i, sector) in buf.chunks_exact(SECTOR_SIZE).enumerate() {
+			sums[off as usize + i] = checksum::compute_crc32(sector, &self.crc_table);
+		}
+		Ok(buf.len())
+	}
+}
+
+/// The results of a test on a device.
+pub struct Report {
+	/// The index of the tested disk in the storage manager. If `None`, the test device was
+	/// tested.
+	disk: Option<usize>,
+	/// The workload.
+	workload: Workload,
+	/// The number of tested sectors.
+	sectors: u64,
+	/// The number of sectors written.
+	writes: u64,
+	/// The number of sectors read and verified.
+	reads: u64,
+	/// The number of sectors whose content did not match what was written.
+	mismatches: u64,
+	/// The number of failed I/O operations.
+	io_errors: u64,
+	/// The number of checksum mismatches detected by the device.
+	checksum_errors: u64,
+	/// Tells whether the test was aborted before running the workload.
+	aborted: bool,
+}
+
+impl Report {
+	/// Tells whether the test passed.
+	pub fn passed(&self) -> bool {
+		!self.aborted && self.mismatches == 0 && self.io_errors == 0 && self.checksum_errors == 0
+	}
+}
+
+impl fmt::Display for Report {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self.disk {
+			Some(i) => write!(f, "disk{i}")?,
+			None => write!(f, "stest")?,
+		}
+		write!(
+			f,
+			": workload={} sectors={} writes={} reads={} mismatches={} io_errors={} \
+			 checksum_errors={} {}",
+			self.workload,
+			self.sectors,
+			self.writes,
+			self.reads,
+			self.mismatches,
+			self.io_errors,
+			self.checksum_errors,
+			if self.aborted {
+				"ABORTED"
+			} else if self.passed() {
+				"PASS"
+			} else {
+				"FAIL"
+			}
+		)
+	}
+}
+
+/// Displays the reports of the last run, one per line.
+pub struct Reports;
+
+impl fmt::Display for Reports {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		for report in REPORTS.lock().iter() {
+			writeln!(f, "{report}")?;
+		}
+		Ok(())
+	}
+}
+
+/// A linear congruential pseudo-random generator.
+struct Rng(u32);
+
+impl Rng {
+	/// Returns the next value.
+	fn next(&mut self) -> u32 {
+		self.0 = self.0.wrapping_mul(1664525).wrapping_add(1013904223);
+		self.0
+	}
+
+	/// Returns a value in the range `0..n`.
+	fn below(&mut self, n: u64) -> u64 {
+		self.next() as u64 % n
+	}
+
+	/// Returns a random permutation of `0..n`, as a function of the index.
+	fn permutation(&mut self, n: u64) -> impl Fn(u64) -> u64 {
+		let start = self.below(n);
+		// Any step coprime with `n` visits each sector once
+		let mut step = self.below(n) | 1;
+		while math::gcd(step, n) != 1 {
+			step += 1;
+		}
+		move |i| (start + i * step) % n
+	}
+}
+
+/// Fills `buf` with pseudo-random data generated from `seed`.
+fn fill(buf: &mut [u8], seed: u32) {
+	let mut rng = Rng(seed);
+	for b in buf {
+		*b = (rng.next() >> 24) as u8;
+	}
+}
+
+/// A workload in progress on a device.
+struct Test<'d> {
+	/// The tested device.
+	io: &'d dyn DeviceIO,
+	/// The number of device blocks per tested sector.
+	blocks_per_sector: u64,
+	/// The seed of the data last written to each sector. `0` means unknown.
+	seeds: Vec<u32>,
+	/// Buffer for a sector's data.
+	buf: Vec<u8>,
+	/// Buffer for a sector's expected data.
+	expected: Vec<u8>,
+	/// The results.
+	report: Report,
+}
+
+impl Test<'_> {
+	/// Writes new data to sector `sector`.
+	fn write(&mut self, sector: u64, rng: &mut Rng) {
+		let seed = rng.next() | 1;
+		fill(&mut self.buf, seed);
+		self.report.writes += 1;
+		let res = self.io.write(sector * self.blocks_per_sector, &self.buf);
+		self.seeds[sector as usize] = match res {
+			Ok(_) => seed,
+			Err(_) => {
+				self.report.io_errors += 1;
+				0
+			}
+		};
+	}
+
+	/// Reads sector `sector` and compares it with the data last written to it.
+	fn verify(&mut self, sector: u64) {
+		let seed = self.seeds[sector as usize];
+		if seed == 0 {
+			return;
+		}
+		self.report.reads += 1;
+		if self
+			.io
+			.read(sector * self.blocks_per_sector, &mut self.buf)
+			.is_err()
+		{
+			self.report.io_errors += 1;
+			return;
+		}
+		fill(&mut self.expected, seed);
+		if self.buf != self.expected {
+			if self.report.mismatches < MAX_PRINTED_MISMATCHES {
+				log!(
+					Storage,
+					Error,
+					"storage test: data mismatch on sector {sector}"
+				);
+			}
+			self.report.mismatches += 1;
+		}
+	}
+
+	/// Runs the workload.
+	fn run(&mut self) {
+		let sectors = self.report.sectors;
+		if sectors == 0 {
+			return;
+		}
+		let mut rng = Rng(SEED.get());
+		for _ in 0..ITERATIONS.get() {
+			match self.report.workload {
+				Workload::None => {}
+				Workload::Sequential => {
+					(0..sectors).for_each(|s| self.write(s, &mut rng));
+					(0..sectors).for_each(|s| self.verify(s));
+				}
+				Workload::Random => {
+					let order = rng.permutation(sectors);
+					(0..sectors).for_each(|i| self.write(order(i), &mut rng));
+					let order = rng.permutation(sectors);
+					(0..sectors).for_each(|i| self.verify(order(i)));
+				}
+				Workload::Mixed => {
+					for _ in 0..(sectors * 2) {
+						let s = rng.below(sectors);
+						if self.seeds[s as usize] == 0 || rng.next() & 0x100 != 0 {
+							self.write(s, &mut rng);
+						} else {
+							self.verify(s);
+						}
+					}
+					(0..sectors).for_each(|s| self.verify(s));
+				}
+			}
+		}
+	}
+}
+
+/// Runs `workload` on `io`.
+///
+/// `disk` is the index of the disk in the storage manager, if any. In this case, the original
+/// content of tested sectors is restored afterwards.
+fn test_device(io: &dyn DeviceIO, disk: Option<usize>, workload: Workload) -> AllocResult<Report> {
+	let blk_size = io.block_size().get() as usize;
+	let sector_size = max(blk_size, SECTOR_SIZE);
+	let blocks_per_sector = (sector_size / blk_size) as u64;
+	let sectors = min(SECTORS.get(), io.blocks_count() / blocks_per_sector);
+	let mut test = Test {
+		io,
+		blocks_per_sector,
+		seeds: vec![0; sectors as usize]?,
+		buf: vec![0; sector_size]?,
+		expected: vec![0; sector_size]?,
+		report: Report {
+			disk,
+			workload,
+			sectors,
+			writes: 0,
+			reads: 0,
+			mismatches: 0,
+			io_errors: 0,
+			checksum_errors: 0,
+			aborted: false,
+		},
+	};
+	// Save the content of real disks
+	let mut backup = Vec::new();
+	if disk.is_some() {
+		backup = vec![0; sectors as usize * sector_size]?;
+		for (s, buf) in backup.chunks_exact_mut(sector_size).enumerate() {
+			if io.read(s as u64 * blocks_per_sector, buf).is_err() {
+				test.report.io_errors += 1;
+				test.report.aborted = true;
+				return Ok(test.report);
+			}
+		}
+	}
+	let checksum_errors = CHECKSUM_ERRORS.load(Relaxed);
+	test.run();
+	test.report.checksum_errors = CHECKSUM_ERRORS.load(Relaxed) - checksum_errors;
+	// Restore
+	for (s, buf) in backup.chunks_exact(sector_size).enumerate() {
+		if io.write(s as u64 * blocks_per_sector, buf).is_err() {
+			log!(
+				Storage,
+				Error,
+				"storage test: cannot restore sector {s} of disk{}",
+				disk.unwrap()
+			);
+			test.report.io_errors += 1;
+		}
+	}
+	Ok(test.report)
+}
+
+/// Returns the test device, creating and registering it if necessary.
+fn test_disk() -> EResult<Arc<dyn DeviceIO>> {
+	let mut test_disk = TEST_DISK.lock();
+	if let Some(disk) = &*test_disk {
+		return Ok(disk.clone());
+	}
+	let major = ManuallyDrop::new(id::alloc_major(DeviceType::Block, None)?);
+	let dev = Device::new(
+		DeviceID {
+			dev_type: DeviceType::Block,
+			major: major.get_major(),
+			minor: 0,
+		},
+		PathBuf::try_from(&b"/dev/stest"[..])?,
+		0o600,
+		ChecksumDisk::new(SECTORS.get() as usize)?,
+	)?;
+	let io = dev.get_io().clone();
+	device::register(dev)?;
+	*test_disk = Some(io.clone());
+	Ok(io)
+}
+
+/// Runs the configured workload on the test device and, if enabled at boot, on real disks.
+///
+/// Reports are printed and saved, replacing the ones of the previous run.
+pub fn run() -> EResult<()> {
+	let workload = WORKLOAD.get();
+	let mut reports = Vec::new();
+	let disk = test_disk()?;
+	reports.push(test_device(&*disk, None, workload)?)?;
+	if DISKS.get() && !BOOTED.load(Acquire) {
+		let interfaces = manager::get::<StorageManager>()
+			.map(|manager| {
+				let mut manager = manager.lock();
+				let manager = (&mut *manager as &mut dyn core::any::Any)
+					.downcast_mut::<StorageManager>()
+					.unwrap();
+				manager
+					.interfaces
+					.iter()
+					.cloned()
+					.collect::<CollectResult<Vec<_>>>()
+					.0
+			})
+			.transpose()?
+			.unwrap_or_default();
+		for (i, io) in interfaces.iter().enumerate() {
+			reports.push(test_device(&**io, Some(i), workload)?)?;
+		}
+	}
+	for report in &reports {
+		log!(Storage, Info, "storage test: {report}");
+	}
+	*REPORTS.lock() = reports;
+	Ok(())
+}
+
+/// Called when the workload parameter changes.
+fn on_workload_change(workload: Workload) {
+	if workload == Workload::None {
+		return;
+	}
+	if let Err(e) = run() {
+		log!(Storage, Error, "storage test: cannot run: {e}");
+	}
+}
+
+/// Registers the parameters, running the tests if a workload is set on the command line.
+///
+/// This function must be called once storage devices have been detected.
+pub(crate) fn init() -> EResult<()> {
+	param::register(&SECTORS)?;
+	param::register(&ITERATIONS)?;
+	param::register(&SEED)?;
+	param::register(&DISKS)?;
+	// Registered last since it starts the tests
+	param::register(&WORKLOAD)?;
+	BOOTED.store(true, Release);
+	Ok(())
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test_case]
+	fn storage_integrity() {
+		let disk = ChecksumDisk::new(64).unwrap();
+		for workload in [Workload::Sequential, Workload::Random, Workload::Mixed] {
+			let report = test_device(&disk, None, workload).unwrap();
+			assert_eq!(report.sectors, 64);
+			assert!(report.reads > 0);
+			assert!(report.passed());
+		}
+		// Corrupt a sector behind the device's back
+		disk.data.lock()[SECTOR_SIZE + 3] ^= 1;
+		let mut buf = [0; SECTOR_SIZE];
+		assert!(disk.read(0, &mut buf).is_ok());
+		assert_eq!(disk.read(1, &mut buf), Err(errno!(EIO)));
+		let report = test_device(&disk, None, Workload::Sequential).unwrap();
+		assert!(report.passed());
+	}
+}
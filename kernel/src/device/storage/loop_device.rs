@@ -0,0 +1,392 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! A loop device exposes a regular file as a block device, allowing to mount filesystem images
+//! without copying them to a storage device or a ramdisk first.
+//!
+//! A loop device is bound to a file with the `LOOP_SET_FD` ioctl, then released with
+//! `LOOP_CLR_FD`.
+
+use crate::{
+	device,
+	device::{id, Device, DeviceID, DeviceIO, DeviceType},
+	file::{
+		page_cache, vfs,
+		vfs::{mountpoint, mountpoint::MountSource},
+		File, FileType, Mode,
+	},
+	process::{mem_space::copy::SyscallPtr, Process},
+	syscall::{ioctl, FromSyscallArg},
+};
+use core::{
+	cmp::min,
+	ffi::{c_int, c_void},
+	mem::ManuallyDrop,
+	num::NonZeroU64,
+};
+use utils::{
+	collections::path::PathBuf, errno, errno::EResult, format, lock::Mutex, ptr::arc::Arc,
+};
+
+/// The loop devices' major number.
+const LOOP_MAJOR: u32 = 7;
+/// The number of loop devices on the system.
+const LOOP_COUNT: usize = 8;
+/// The mode of the device file for a loop device.
+const LOOP_MODE: Mode = 0o660;
+/// The size of a sector on a loop device, in bytes.
+const SECTOR_SIZE: u64 = 512;
+
+/// ioctl request: binds the loop device to a file descriptor.
+pub const LOOP_SET_FD: u32 = 0x4c00;
+/// ioctl request: unbinds the loop device from its file.
+pub const LOOP_CLR_FD: u32 = 0x4c01;
+/// ioctl request: sets the status of the loop device, with 64 bits offsets.
+pub const LOOP_SET_STATUS64: u32 = 0x4c04;
+/// ioctl request: returns the status of the loop device, with 64 bits offsets.
+pub const LOOP_GET_STATUS64: u32 = 0x4c05;
+
+/// Loop device flag: the device is read-only.
+pub const LO_FLAGS_READ_ONLY: u32 = 1;
+
+/// The size of name fields in [`LoopInfo64`].
+const LO_NAME_SIZE: usize = 64;
+/// The size of the encryption key field in [`LoopInfo64`].
+const LO_KEY_SIZE: usize = 32;
+
+/// The status of a loop device, as exchanged with the userspace.
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+struct LoopInfo64 {
+	/// The device number of the filesystem containing the backing file.
+	lo_device: u64,
+	/// The inode of the backing file.
+	lo_inode: u64,
+	/// The device number of the backing file, if it is a device file.
+	lo_rdevice: u64,
+	/// The offset of the data in the backing file, in bytes.
+	lo_offset: u64,
+	/// The maximum size of the device in bytes. If zero, the whole file is used.
+	lo_sizelimit: u64,
+	/// The minor number of the loop device.
+	lo_number: u32,
+	/// Encryption type. Unsupported.
+	lo_encrypt_type: u32,
+	/// Encryption key size. Unsupported.
+	lo_encrypt_key_size: u32,
+	/// Loop device flags.
+	lo_flags: u32,
+	/// The path to the backing file.
+	lo_file_name: [u8; LO_NAME_SIZE],
+	/// Encryption algorithm name. Unsupported.
+	lo_crypt_name: [u8; LO_NAME_SIZE],
+	/// Encryption key. Unsupported.
+	lo_encrypt_key: [u8; LO_KEY_SIZE],
+	/// Encryption initialization data. Unsupported.
+	lo_init: [u64; 2],
+}
+
+/// The file a loop device is bound to.
+struct Backing {
+	/// The backing file.
+	file: Arc<File>,
+	/// The offset of the data in the file, in bytes.
+	offset: u64,
+	/// The maximum size of the device in bytes. If zero, the whole file is used.
+	size_limit: u64,
+	/// Loop device flags.
+	flags: u32,
+	/// The name of the file, as given by the userspace.
+	file_name: [u8; LO_NAME_SIZE],
+}
+
+impl Backing {
+	/// Returns the size of the device in bytes.
+	fn size(&self) -> EResult<u64> {
+		let file_size = self.file.stat()?.size;
+		let mut size = file_size.saturating_sub(self.offset);
+		if self.size_limit > 0 {
+			size = min(size, self.size_limit);
+		}
+		// Only entire sectors are exposed
+		Ok(size - size % SECTOR_SIZE)
+	}
+
+	/// Checks that a request of `len` bytes at sector `off` remains in the bounds of the device,
+	/// then returns the offset of the request in the backing file, in bytes.
+	///
+	/// If out of bounds, the function returns [`errno::EINVAL`].
+	fn translate(&self, off: u64, len: usize) -> EResult<u64> {
+		let start = off.checked_mul(SECTOR_SIZE).ok_or_else(|| errno!(EINVAL))?;
+		let end = start
+			.checked_add(len as u64)
+			.ok_or_else(|| errno!(EINVAL))?;
+		if end > self.size()? {
+			return Err(errno!(EINVAL));
+		}
+		Ok(self.offset + start)
+	}
+}
+
+/// A loop device.
+struct LoopDevice {
+	/// The minor number of the device.
+	minor: u32,
+	/// The file the device is bound to, if any.
+	backing: Mutex<Option<Arc<Backing>>>,
+}
+
+impl LoopDevice {
+	/// Returns the ID of the device.
+	fn id(&self) -> DeviceID {
+		DeviceID {
+			dev_type: DeviceType::Block,
+			major: LOOP_MAJOR,
+			minor: self.minor,
+		}
+	}
+
+	/// Returns the file the device is bound to.
+	///
+	/// If the device is not bound, the function returns [`errno::ENXIO`].
+	fn backing(&self) -> EResult<Arc<Backing>> {
+		self.backing.lock().clone().ok_or_else(|| errno!(ENXIO))
+	}
+
+	/// Binds the device to the file at descriptor `fd` of the current process.
+	fn set_fd(&self, fd: c_int) -> EResult<()> {
+		let fds = Process::current()
+			.lock()
+			.file_descriptors
+			.clone()
+			.ok_or_else(|| errno!(EBADF))?;
+		let file = fds.lock().get_fd(fd)?.get_file().clone();
+		if !file.can_read() {
+			return Err(errno!(EBADF));
+		}
+		let Some(entry) = &file.vfs_entry else {
+			return Err(errno!(EINVAL));
+		};
+		let stat = file.stat()?;
+		match stat.get_type() {
+			Some(FileType::Regular) => {}
+			// Reject the device itself
+			Some(FileType::BlockDevice)
+				if (stat.dev_major, stat.dev_minor) != (LOOP_MAJOR, self.minor) => {}
+			_ => return Err(errno!(EINVAL)),
+		}
+		// Reject files stored on the device itself
+		let mp = entry.node().location.get_mountpoint();
+		if matches!(mp, Some(mp) if mp.source == MountSource::Device(self.id())) {
+			return Err(errno!(EINVAL));
+		}
+		let mut file_name = [0; LO_NAME_SIZE];
+		let path = format!("{}", vfs::Entry::get_path(entry)?)?;
+		let len = min(path.len(), LO_NAME_SIZE - 1);
+		file_name[..len].copy_from_slice(&path.as_bytes()[..len]);
+		let flags = if file.can_write() {
+			0
+		} else {
+			LO_FLAGS_READ_ONLY
+		};
+		let mut backing = self.backing.lock();
+		if backing.is_some() {
+			return Err(errno!(EBUSY));
+		}
+		*backing = Some(Arc::new(Backing {
+			file,
+			offset: 0,
+			size_limit: 0,
+			flags,
+			file_name,
+		})?);
+		Ok(())
+	}
+
+	/// Unbinds the device from its file.
+	///
+	/// If a filesystem is mounted from the device, the function returns [`errno::EBUSY`].
+	fn clear_fd(&self) -> EResult<()> {
+		if mountpoint::is_device_used(&self.id()) {
+			return Err(errno!(EBUSY));
+		}
+		let backing = self.backing.lock().take().ok_or_else(|| errno!(ENXIO))?;
+		// Write back data that has been written through the device
+		if let Some(entry) = &backing.file.vfs_entry {
+			let node = entry.node();
+			page_cache::sync_file(&node.location, &*node.ops)?;
+		}
+		Ok(())
+	}
+
+	/// Returns the status of the device.
+	fn get_status(&self) -> EResult<LoopInfo64> {
+		let backing = self.backing()?;
+		let stat = backing.file.stat()?;
+		let (lo_device, lo_inode) = match &backing.file.vfs_entry {
+			Some(entry) => {
+				let loc = &entry.node().location;
+				let mp = loc.get_mountpoint();
+				let dev = match mp.as_ref().map(|mp| &mp.source) {
+					Some(MountSource::Device(id)) => id.get_device_number(),
+					_ => 0,
+				};
+				(dev, loc.inode)
+			}
+			None => (0, 0),
+		};
+		Ok(LoopInfo64 {
+			lo_device,
+			lo_inode,
+			lo_rdevice: id::makedev(stat.dev_major, stat.dev_minor),
+			lo_offset: backing.offset,
+			lo_sizelimit: backing.size_limit,
+			lo_number: self.minor,
+			lo_encrypt_type: 0,
+			lo_encrypt_key_size: 0,
+			lo_flags: backing.flags,
+			lo_file_name: backing.file_name,
+			lo_crypt_name: [0; LO_NAME_SIZE],
+			lo_encrypt_key: [0; LO_KEY_SIZE],
+			lo_init: [0; 2],
+		})
+	}
+
+	/// Sets the offset, size limit and file name of the device from `info`.
+	///
+	/// If a filesystem is mounted from the device, the function returns [`errno::EBUSY`] since
+	/// its geometry cannot change.
+	fn set_status(&self, info: &LoopInfo64) -> EResult<()> {
+		if info.lo_encrypt_type != 0 {
+			return Err(errno!(EINVAL));
+		}
+		if info.lo_offset % SECTOR_SIZE != 0 {
+			return Err(errno!(EINVAL));
+		}
+		if mountpoint::is_device_used(&self.id()) {
+			return Err(errno!(EBUSY));
+		}
+		let mut guard = self.backing.lock();
+		let backing = guard.as_ref().ok_or_else(|| errno!(ENXIO))?;
+		let mut file_name = info.lo_file_name;
+		file_name[LO_NAME_SIZE - 1] = 0;
+		*guard = Some(Arc::new(Backing {
+			file: backing.file.clone(),
+			offset: info.lo_offset,
+			size_limit: info.lo_sizelimit,
+			flags: backing.flags,
+			file_name,
+		})?);
+		Ok(())
+	}
+}
+
+impl DeviceIO for LoopDevice {
+	fn block_size(&self) -> NonZeroU64 {
+		SECTOR_SIZE.try_into().unwrap()
+	}
+
+	fn blocks_count(&self) -> u64 {
+		self.backing()
+			.and_then(|b| b.size())
+			.map(|size| size / SECTOR_SIZE)
+			.unwrap_or(0)
+	}
+
+	fn read(&self, off: u64, buf: &mut [u8]) -> EResult<usize> {
+		// The lock is not held during I/O so that the device can be used concurrently
+		let backing = self.backing()?;
+		let off = backing.translate(off, buf.len())?;
+		let file = &backing.file;
+		let len = file.ops.read(file, off, buf)?;
+		// The file may have been truncated in the meantime
+		buf[len..].fill(0);
+		Ok(buf.len())
+	}
+
+	fn write(&self, off: u64, buf: &[u8]) -> EResult<usize> {
+		let backing = self.backing()?;
+		if backing.flags & LO_FLAGS_READ_ONLY != 0 {
+			return Err(errno!(EROFS));
+		}
+		let off = backing.translate(off, buf.len())?;
+		let file = &backing.file;
+		file.ops.write(file, off, buf)
+	}
+
+	fn ioctl(&self, request: ioctl::Request, argp: *const c_void) -> EResult<u32> {
+		match request.get_old_format() {
+			LOOP_SET_FD => {
+				self.set_fd(argp as usize as c_int)?;
+				Ok(0)
+			}
+			LOOP_CLR_FD => {
+				self.clear_fd()?;
+				Ok(0)
+			}
+			LOOP_SET_STATUS64 => {
+				let info_ptr = SyscallPtr::<LoopInfo64>::from_syscall_arg(argp as usize);
+				let info = info_ptr.copy_from_user()?.ok_or_else(|| errno!(EFAULT))?;
+				self.set_status(&info)?;
+				Ok(0)
+			}
+			LOOP_GET_STATUS64 => {
+				let info_ptr = SyscallPtr::<LoopInfo64>::from_syscall_arg(argp as usize);
+				info_ptr.copy_to_user(self.get_status()?)?;
+				Ok(0)
+			}
+			ioctl::BLKSSZGET => {
+				let size_ptr = SyscallPtr::<u32>::from_syscall_arg(argp as usize);
+				size_ptr.copy_to_user(SECTOR_SIZE as _)?;
+				Ok(0)
+			}
+			ioctl::BLKGETSIZE64 => {
+				let size = self.blocks_count() * SECTOR_SIZE;
+				let size_ptr = SyscallPtr::<u64>::from_syscall_arg(argp as usize);
+				size_ptr.copy_to_user(size)?;
+				Ok(0)
+			}
+			_ => Err(errno!(ENOTTY)),
+		}
+	}
+}
+
+/// Creates every loop device instances.
+pub(crate) fn create() -> EResult<()> {
+	let _major = ManuallyDrop::new(id::alloc_major(DeviceType::Block, Some(LOOP_MAJOR))?);
+
+	for i in 0..LOOP_COUNT {
+		let path = PathBuf::try_from(format!("/dev/loop{i}")?)?;
+		let dev = Device::new(
+			DeviceID {
+				dev_type: DeviceType::Block,
+				major: LOOP_MAJOR,
+				minor: i as _,
+			},
+			path,
+			LOOP_MODE,
+			LoopDevice {
+				minor: i as _,
+				backing: Mutex::new(None),
+			},
+		)?;
+		device::register(dev)?;
+	}
+
+	Ok(())
+}
@@ -19,6 +19,8 @@
 //! Storage management implementation.
 
 pub mod ide;
+pub mod integrity;
+pub mod loop_device;
 pub mod partition;
 pub mod pata;
 pub mod ramdisk;
@@ -29,14 +31,18 @@ use crate::{
 		bus::pci,
 		id,
 		id::MajorBlock,
+		manager,
 		manager::{DeviceManager, PhysicalDevice},
 		Device, DeviceID, DeviceIO, DeviceType,
 	},
 	file::Mode,
-	process::mem_space::copy::SyscallPtr,
+	module::param,
+	process::{mem_space::copy::SyscallPtr, rusage},
 	syscall::{ioctl, FromSyscallArg},
+	workqueue,
 };
 use core::{
+	any::Any,
 	ffi::{c_uchar, c_ulong, c_ushort, c_void},
 	num::NonZeroU64,
 };
@@ -49,6 +55,7 @@ use utils::{
 	errno,
 	errno::EResult,
 	format,
+	lock::Mutex,
 	ptr::arc::Arc,
 	TryClone,
 };
@@ -89,6 +96,25 @@ pub struct StorageDeviceHandle {
 	pub path_prefix: PathBuf,
 }
 
+impl StorageDeviceHandle {
+	/// Checks that a request of `len` bytes at offset `off` (in blocks) remains in the bounds of
+	/// the partition, then returns the offset of the request on the whole device.
+	///
+	/// If out of bounds, the function returns [`errno::EINVAL`].
+	fn translate(&self, off: u64, len: usize) -> EResult<u64> {
+		let (start, size) = match &self.partition {
+			Some(p) => (p.offset, p.size),
+			None => (0, self.io.blocks_count()),
+		};
+		let blk_size = self.io.block_size().get();
+		let blks = (len as u64).div_ceil(blk_size);
+		if off.saturating_add(blks) > size {
+			return Err(errno!(EINVAL));
+		}
+		Ok(start + off)
+	}
+}
+
 impl DeviceIO for StorageDeviceHandle {
 	fn block_size(&self) -> NonZeroU64 {
 		self.io.block_size()
@@ -99,31 +125,27 @@ impl DeviceIO for StorageDeviceHandle {
 	}
 
 	fn read(&self, off: u64, buf: &mut [u8]) -> EResult<usize> {
-		// Bound check
-		let (start, size) = match &self.partition {
-			Some(p) => (p.offset, p.size),
-			None => (0, self.io.blocks_count()),
-		};
-		let blk_size = self.io.block_size().get();
-		let buf_blks = (buf.len() as u64).div_ceil(blk_size);
-		if off.saturating_add(buf_blks) > size {
-			return Err(errno!(EINVAL));
-		}
-		self.io.read(start + off, buf)
+		self.read_sectors(off, &mut [buf])
 	}
 
 	fn write(&self, off: u64, buf: &[u8]) -> EResult<usize> {
-		// Bound check
-		let (start, size) = match &self.partition {
-			Some(p) => (p.offset, p.size),
-			None => (0, self.io.blocks_count()),
-		};
-		let blk_size = self.io.block_size().get();
-		let buf_blks = (buf.len() as u64).div_ceil(blk_size);
-		if off.saturating_add(buf_blks) > size {
-			return Err(errno!(EINVAL));
-		}
-		self.io.write(start + off, buf)
+		self.write_sectors(off, &[buf])
+	}
+
+	fn read_sectors(&self, off: u64, bufs: &mut [&mut [u8]]) -> EResult<usize> {
+		let len = bufs.iter().map(|b| b.len()).sum();
+		let off = self.translate(off, len)?;
+		let len = self.io.read_sectors(off, bufs)?;
+		rusage::account_block_read(len);
+		Ok(len)
+	}
+
+	fn write_sectors(&self, off: u64, bufs: &[&[u8]]) -> EResult<usize> {
+		let len = bufs.iter().map(|b| b.len()).sum();
+		let off = self.translate(off, len)?;
+		let len = self.io.write_sectors(off, bufs)?;
+		rusage::account_block_write(len);
+		Ok(len)
 	}
 
 	fn ioctl(&self, request: ioctl::Request, argp: *const c_void) -> EResult<u32> {
@@ -149,7 +171,6 @@ impl DeviceIO for StorageDeviceHandle {
 				Ok(0)
 			}
 			ioctl::BLKRRPART => {
-				StorageManager::clear_partitions(self.major)?;
 				StorageManager::read_partitions(
 					self.io.clone(),
 					self.major,
@@ -175,6 +196,90 @@ impl DeviceIO for StorageDeviceHandle {
 	}
 }
 
+/// Returns the ID of the device for the partition `part_nbr` of the storage device with ID
+/// `storage_id` in the manager.
+fn partition_id(storage_id: u32, part_nbr: u32) -> DeviceID {
+	DeviceID {
+		dev_type: DeviceType::Block,
+		// TODO use a different major for different storage device types
+		major: STORAGE_MAJOR,
+		minor: storage_id * MAX_PARTITIONS as u32 + part_nbr,
+	}
+}
+
+/// A scan of the partitions of a storage device, waiting to be executed.
+struct PartitionScan {
+	/// The I/O interface.
+	io: Arc<dyn DeviceIO>,
+	/// The major number of the device.
+	major: u32,
+	/// The ID of the storage device in the manager.
+	storage_id: u32,
+	/// The path to the file of the main device.
+	path_prefix: PathBuf,
+}
+
+/// Partition scans waiting to be executed, in order of submission.
+///
+/// Each scan has a matching work item on the system workqueue, which executes the first pending
+/// scan. This allows [`settle`] to execute scans before their work item runs.
+static PENDING_SCANS: Mutex<Vec<PartitionScan>> = Mutex::new(Vec::new());
+
+/// Executes the first pending partition scan.
+///
+/// If no scan is pending, the function returns `false`.
+fn run_pending_scan() -> bool {
+	let scan = {
+		let mut scans = PENDING_SCANS.lock();
+		if scans.is_empty() {
+			return false;
+		}
+		scans.remove(0)
+	};
+	let res =
+		StorageManager::read_partitions(scan.io, scan.major, scan.storage_id, &scan.path_prefix);
+	if let Err(e) = res {
+		crate::log!(
+			Storage,
+			Warn,
+			"Could not read partitions of {}: {e}",
+			scan.path_prefix
+		);
+	}
+	true
+}
+
+/// Executes pending partition scans in the current context, until the device with ID `id` is
+/// registered or no scan is pending.
+///
+/// Workqueues are not running at boot. This function allows to make sure the root device is
+/// available before mounting it, leaving the scans of other storage devices to the workqueue.
+pub fn settle(id: &DeviceID) {
+	while device::get(id).is_none() && run_pending_scan() {}
+}
+
+/// Prepares every storage device for the system to be powered off.
+///
+/// Errors are printed, but do not stop the shutdown of the other devices.
+pub fn shutdown() {
+	let Some(manager) = manager::get::<StorageManager>() else {
+		return;
+	};
+	let manager = manager.lock();
+	let manager = (&*manager as &dyn Any)
+		.downcast_ref::<StorageManager>()
+		.unwrap();
+	for (storage_id, io) in manager.interfaces.iter().enumerate() {
+		if let Err(e) = io.shutdown() {
+			crate::log!(
+				Storage,
+				Error,
+				"Could not shut storage device {storage_id} down: {e}"
+			);
+		}
+	}
+}
+
 /// An instance of StorageManager manages devices on a whole major number.
 ///
 /// The manager has name `storage`.
@@ -188,16 +293,19 @@ pub struct StorageManager {
 impl StorageManager {
 	/// Creates a new instance.
 	pub fn new() -> EResult<Self> {
+		param::register(&pata::QUEUE_DEPTH)?;
 		Ok(Self {
 			major_block: id::alloc_major(DeviceType::Block, Some(STORAGE_MAJOR))?,
 			interfaces: Vec::new(),
 		})
 	}
 
-	// TODO When failing, remove previously registered devices
 	/// Creates device files for every partitions on the storage device, within the limit of
 	/// `MAX_PARTITIONS`.
 	///
+	/// The partitions previously registered for the storage device are replaced at once, so that
+	/// a partial partition table is never visible. On failure, previous partitions are kept.
+	///
 	/// Arguments:
 	/// - `io` is the I/O interface.
 	/// - `major` is the major number of the device.
@@ -209,11 +317,12 @@ impl StorageManager {
 		storage_id: u32,
 		path_prefix: &Path,
 	) -> EResult<()> {
-		let Some(partitions_table) = partition::read(&*io)? else {
-			return Ok(());
+		let partitions = match partition::read(&*io)? {
+			Some(partitions_table) => partitions_table.get_partitions(&*io)?,
+			None => Vec::new(),
 		};
-		let partitions = partitions_table.get_partitions(&*io)?;
 
+		let mut devices = Vec::new();
 		let iter = partitions.into_iter().take(MAX_PARTITIONS - 1).enumerate();
 		for (i, partition) in iter {
 			let part_nbr = (i + 1) as u32;
@@ -229,34 +338,46 @@ impl StorageManager {
 				path_prefix: path_prefix.to_path_buf()?,
 			};
 			let device = Device::new(
-				DeviceID {
-					dev_type: DeviceType::Block,
-					// TODO use a different major for different storage device types
-					major: STORAGE_MAJOR,
-					minor: storage_id * MAX_PARTITIONS as u32 + part_nbr,
-				},
+				partition_id(storage_id, part_nbr),
 				path,
 				STORAGE_MODE,
 				handle,
 			)?;
-			device::register(device)?;
+			devices.push(device)?;
 		}
 
-		Ok(())
+		let old: [_; MAX_PARTITIONS - 1] =
+			core::array::from_fn(|i| partition_id(storage_id, (i + 1) as _));
+		device::replace(&old, devices)
 	}
 
-	/// Clears device files for every partition.
+	/// Schedules the scan of the partitions of a storage device on the system workqueue, so that
+	/// a slow device does not delay the probing of the other ones.
 	///
-	/// `major` is the major number of the devices to be removed.
-	pub fn clear_partitions(major: u32) -> EResult<()> {
-		for i in 1..MAX_PARTITIONS {
-			device::unregister(&DeviceID {
-				dev_type: DeviceType::Block,
-				major,
-				minor: i as _,
-			})?;
+	/// If the scan cannot be scheduled, it is executed immediately.
+	fn queue_scan(scan: PartitionScan) -> EResult<()> {
+		{
+			let mut scans = PENDING_SCANS.lock();
+			if scans.reserve(1).is_err() {
+				drop(scans);
+				return Self::read_partitions(
+					scan.io,
+					scan.major,
+					scan.storage_id,
+					&scan.path_prefix,
+				);
+			}
+			// Cannot fail since memory has been reserved
+			let _ = scans.push(scan);
+		}
+		// If the work item cannot be queued, execute the scan now
+		if workqueue::queue_work(|| {
+			run_pending_scan();
+		})
+		.is_err()
+		{
+			run_pending_scan();
 		}
-
 		Ok(())
 	}
 
@@ -296,120 +417,18 @@ impl StorageManager {
 		)?;
 		device::register(main_device)?;
 
-		Self::read_partitions(io.clone(), major, storage_id, &main_path)?;
+		Self::queue_scan(PartitionScan {
+			io: io.clone(),
+			major,
+			storage_id,
+			path_prefix: main_path,
+		})?;
 
 		self.interfaces.push(io)?;
 		Ok(())
 	}
 
 	// TODO Function to remove a device
-
-	/// Fills a random buffer `buff` of size `size` with seed `seed`.
-	///
-	/// The function returns the seed for the next block.
-	#[cfg(config_debug_storage_test)]
-	fn random_block(size: u64, buff: &mut [u8], seed: u32) -> u32 {
-		let mut s = seed;
-
-		for i in 0..size {
-			s = crate::util::math::pseudo_rand(s, 1664525, 1013904223, 0x100);
-			buff[i as usize] = (s & 0xff) as u8;
-		}
-
-		s
-	}
-
-	// TODO Test with several blocks at a time
-	/// Tests the given interface with the given interface `interface`.
-	///
-	/// `seed` is the seed for pseudo random generation. The function will set
-	/// this variable to another value for the next iteration.
-	#[cfg(config_debug_storage_test)]
-	fn test_interface(interface: &mut dyn StorageInterface, seed: u32) -> bool {
-		let block_size = interface.get_block_size();
-		let blocks_count = min(1024, interface.get_blocks_count());
-
-		let mut s = seed;
-		for i in 0..blocks_count {
-			let mut buff: [u8; 512] = [0; 512]; // TODO Set to block size
-			s = Self::random_block(block_size, &mut buff, s);
-			if interface.write(&buff, i, 1).is_err() {
-				crate::println!("\nCannot write to disk on block {}.", i);
-				return false;
-			}
-		}
-
-		s = seed;
-		for i in 0..blocks_count {
-			let mut buff: [u8; 512] = [0; 512]; // TODO Set to block size
-			s = Self::random_block(interface.get_block_size(), &mut buff, s);
-
-			let mut buf: [u8; 512] = [0; 512]; // TODO Set to block size
-			if interface.read(&mut buf, i, 1).is_err() {
-				crate::println!("\nCannot read from disk on block {}.", i);
-				return false;
-			}
-
-			if buf != buff {
-				return false;
-			}
-		}
-
-		true
-	}
-
-	/// Performs testing of storage devices and drivers.
-	///
-	/// If every tests pass, the function returns `true`. Else, it returns
-	/// `false`.
-	#[cfg(config_debug_storage_test)]
-	fn perform_test(&mut self) -> bool {
-		let mut seed = 42;
-		let iterations_count = 10;
-		for i in 0..iterations_count {
-			let interfaces_count = self.interfaces.len();
-
-			for j in 0..interfaces_count {
-				let mut interface = self.interfaces[j].lock();
-
-				crate::print!(
-					"Processing iteration: {}/{iterations_count}; device: {}/{iterations_count}...",
-					i + 1,
-					j + 1,
-				);
-
-				if !Self::test_interface(&mut *interface, seed) {
-					return false;
-				}
-
-				seed = crate::util::math::pseudo_rand(seed, 1103515245, 12345, 0x100);
-			}
-
-			if i < iterations_count - 1 {
-				crate::print!("\r");
-			} else {
-				crate::println!();
-			}
-		}
-
-		true
-	}
-
-	/// Tests every storage drivers on every storage devices.
-	///
-	/// The execution of this function removes all the data on every connected
-	/// writable disks, so it must be used carefully.
-	#[cfg(config_debug_storage_test)]
-	pub fn test(&mut self) {
-		crate::println!("Running disks tests... ({} devices)", self.interfaces.len());
-
-		if self.perform_test() {
-			crate::println!("Done!");
-		} else {
-			crate::println!("Storage test failed!");
-		}
-		crate::halt();
-	}
 }
 
 impl DeviceManager for StorageManager {
@@ -422,7 +441,7 @@ impl DeviceManager for StorageManager {
 		let mut register_iface = |res: EResult<_>| {
 			let res = res.and_then(|iface| self.add(iface));
 			if let Err(e) = res {
-				crate::println!("Could not register storage device: {e}");
+				crate::log!(Storage, Error, "Could not register storage device: {e}");
 			}
 		};
 
@@ -24,7 +24,7 @@ use crate::{
 	crypto::checksum::{compute_crc32, compute_crc32_lookuptable},
 	device::DeviceIO,
 };
-use core::mem::size_of;
+use core::mem::offset_of;
 use macros::AnyRepr;
 use utils::{
 	bytes::from_bytes,
@@ -38,12 +38,25 @@ use utils::{
 const GPT_SIGNATURE: &[u8] = b"EFI PART";
 /// The polynom used in the computation of the CRC32 checksum.
 const CHECKSUM_POLYNOM: u32 = 0xedb88320;
+/// The minimum size of the GPT header, in bytes.
+const HDR_MIN_SIZE: usize = 92;
+/// The minimum size of an entry, in bytes.
+const ENTRY_MIN_SIZE: u32 = 128;
+/// The maximum size of the entries array, in bytes.
+const ENTRIES_MAX_SIZE: u64 = 1 << 20;
 
 // TODO Add GPT restoring from alternate table (requires user confirmation)
 
 /// Type representing a Globally Unique IDentifier.
 type Guid = [u8; 16];
 
+/// Computes the CRC32 checksum of `data`, as used by GPT.
+fn checksum(data: &[u8]) -> u32 {
+	let mut lookup_table = [0; 256];
+	compute_crc32_lookuptable(&mut lookup_table, CHECKSUM_POLYNOM);
+	compute_crc32(data, &lookup_table)
+}
+
 /// Translates the given LBA value `lba` into a positive LBA value.
 ///
 /// `storage_size` is the number of blocks on the storage device.
@@ -85,61 +98,7 @@ struct GPTEntry {
 	name: [u16; 36],
 }
 
-impl Default for GPTEntry {
-	fn default() -> Self {
-		Self {
-			partition_type: [0; 16],
-			guid: [0; 16],
-			start: 0,
-			end: 0,
-			attributes: 0,
-			name: [0; 36],
-		}
-	}
-}
-
 impl GPTEntry {
-	/// Tells whether the given entry `other` equals the current entry.
-	///
-	/// Arguments:
-	/// - `entry_size` is the size of an entry.
-	/// - `blocks_count` is the number of blocks on the storage device.
-	fn eq(&self, other: &Self, entry_size: usize, blocks_count: u64) -> bool {
-		if self.partition_type != other.partition_type {
-			return false;
-		}
-
-		if self.guid != other.guid {
-			return false;
-		}
-
-		let start = translate_lba(self.start, blocks_count);
-		let other_start = translate_lba(other.start, blocks_count);
-		let end = translate_lba(self.end, blocks_count);
-		let other_end = translate_lba(other.end, blocks_count);
-
-		if start.is_none() || other_start.is_none() || end.is_none() || other_end.is_none() {
-			return false;
-		}
-		if start.unwrap() != other_start.unwrap() || end.unwrap() != other_end.unwrap() {
-			return false;
-		}
-
-		if self.attributes != other.attributes {
-			return false;
-		}
-
-		let name_offset = 56; // TODO Retrieve from struct's fields
-		let name_length = (entry_size - name_offset) / size_of::<u16>();
-		for i in 0..name_length {
-			if self.name[i] != other.name[i] {
-				return false;
-			}
-		}
-
-		true
-	}
-
 	/// Tells whether the entry is used.
 	fn is_used(&self) -> bool {
 		!self.partition_type.iter().all(|b| *b == 0)
@@ -149,7 +108,7 @@ impl GPTEntry {
 /// A GPT header.
 #[derive(AnyRepr, Clone)]
 #[repr(C)]
-pub struct Gpt {
+struct GptHeader {
 	/// The header's signature.
 	signature: [u8; 8],
 	/// The header's revision.
@@ -180,118 +139,121 @@ pub struct Gpt {
 	entries_checksum: u32,
 }
 
-impl Gpt {
+impl GptHeader {
 	/// Reads the header structure from the given storage interface `storage` at
 	/// the given LBA `lba`.
 	///
-	/// If the header is invalid, the function returns an error.
-	fn read_hdr(storage: &dyn DeviceIO, lba: i64) -> EResult<Self> {
-		let block_size = storage.block_size().get() as _;
+	/// If the header is invalid, the function returns `None`.
+	fn read(storage: &dyn DeviceIO, lba: i64) -> EResult<Option<Self>> {
+		let block_size = storage.block_size().get() as usize;
 		let blocks_count = storage.blocks_count();
-		if size_of::<Gpt>() > block_size {
-			return Err(errno!(EINVAL));
-		}
-		// Read the first block
+		let Some(lba) = translate_lba(lba, blocks_count).filter(|lba| *lba < blocks_count) else {
+			return Ok(None);
+		};
 		let mut buf = vec![0; block_size]?;
-		let lba = translate_lba(lba, blocks_count).ok_or_else(|| errno!(EINVAL))?;
 		storage.read(lba, &mut buf)?;
-		// Validate
-		let gpt_hdr = from_bytes::<Self>(&buf).unwrap().clone();
-		if !gpt_hdr.is_valid() {
-			return Err(errno!(EINVAL));
+		let Some(hdr) = from_bytes::<Self>(&buf).cloned() else {
+			return Ok(None);
+		};
+		if hdr.signature != GPT_SIGNATURE {
+			return Ok(None);
 		}
-		Ok(gpt_hdr)
-	}
-
-	/// Tells whether the header is valid.
-	fn is_valid(&self) -> bool {
-		if self.signature != GPT_SIGNATURE {
-			return false;
+		// Check checksum, which covers the header with the checksum field zeroed
+		let hdr_size = hdr.hdr_size as usize;
+		if !(HDR_MIN_SIZE..=block_size).contains(&hdr_size) {
+			return Ok(None);
 		}
-
-		// TODO Check current header LBA
-
-		if self.entry_size == 0 {
-			return false;
+		let checksum_off = offset_of!(Self, checksum);
+		buf[checksum_off..(checksum_off + 4)].fill(0);
+		if checksum(&buf[..hdr_size]) != hdr.checksum {
+			return Ok(None);
 		}
-
-		let mut lookup_table = [0; 256];
-		compute_crc32_lookuptable(&mut lookup_table, CHECKSUM_POLYNOM);
-
-		// Check checksum
-		let mut tmp = self.clone();
-		tmp.checksum = 0;
-		if compute_crc32(utils::bytes::as_bytes(&tmp), &lookup_table) != self.checksum {
-			return false;
+		// The header must be located where it claims to be
+		if translate_lba(hdr.hdr_lba, blocks_count) != Some(lba) {
+			return Ok(None);
 		}
-
-		// TODO check entries checksum
-
-		true
+		let entries_size = hdr.entries_number as u64 * hdr.entry_size as u64;
+		if hdr.entry_size < ENTRY_MIN_SIZE
+			|| hdr.entry_size % 8 != 0
+			|| entries_size > ENTRIES_MAX_SIZE
+		{
+			return Ok(None);
+		}
+		Ok(Some(hdr))
 	}
 
-	/// Returns the list of entries in the table.
+	/// Reads the list of used entries in the table.
 	///
 	/// `storage` is the storage device interface.
-	fn get_entries(&self, storage: &dyn DeviceIO) -> EResult<Vec<GPTEntry>> {
+	///
+	/// If the entries array does not match its checksum, the function returns `None`.
+	fn read_entries(&self, storage: &dyn DeviceIO) -> EResult<Option<Vec<GPTEntry>>> {
 		let block_size = storage.block_size().get();
 		let blocks_count = storage.blocks_count();
-		let entries_start =
-			translate_lba(self.entries_start, blocks_count).ok_or_else(|| errno!(EINVAL))?;
-		let mut buf = vec![0; block_size as usize]?;
-		let entries = (0..self.entries_number)
-			// Read entry
-			.map(|i| {
-				let off = entries_start + (i as u64 * self.entry_size as u64) / block_size;
-				let inner_off = ((i as u64 * self.entry_size as u64) % block_size) as usize;
-				storage.read(off, &mut buf)?;
-				let ent = from_bytes::<GPTEntry>(&buf[inner_off..]).unwrap().clone();
-				Ok(ent)
-			})
+		let Some(start) = translate_lba(self.entries_start, blocks_count) else {
+			return Ok(None);
+		};
+		let size = self.entries_number as u64 * self.entry_size as u64;
+		let blocks = size.div_ceil(block_size);
+		if start
+			.checked_add(blocks)
+			.map_or(true, |end| end > blocks_count)
+		{
+			return Ok(None);
+		}
+		// Read the whole array
+		let mut buf = vec![0; (blocks * block_size) as usize]?;
+		storage.read(start, &mut buf)?;
+		let array = &buf[..size as usize];
+		if checksum(array) != self.entries_checksum {
+			return Ok(None);
+		}
+		let entries = array
+			.chunks_exact(self.entry_size as usize)
+			.map(|ent| from_bytes::<GPTEntry>(ent).unwrap())
 			// Ignore empty entries
-			.filter_map(|entry: EResult<GPTEntry>| {
-				entry.map(|e| e.is_used().then_some(e)).transpose()
-			})
-			.map(|entry| {
-				let entry = entry?;
-				// Check entry correctness
-				let start =
-					translate_lba(entry.start, blocks_count).ok_or_else(|| errno!(EINVAL))?;
-				let end = translate_lba(entry.end, blocks_count).ok_or_else(|| errno!(EINVAL))?;
-				if start < end {
-					Ok(entry)
-				} else {
-					Err(errno!(EINVAL))
-				}
-			})
-			.collect::<EResult<CollectResult<_>>>()?
+			.filter(|ent| ent.is_used())
+			.cloned()
+			.collect::<CollectResult<_>>()
 			.0?;
-		Ok(entries)
+		Ok(Some(entries))
 	}
 }
 
+/// A GUID Partition Table.
+pub struct Gpt {
+	/// The list of used entries.
+	entries: Vec<GPTEntry>,
+}
+
 impl Table for Gpt {
 	fn read(storage: &dyn DeviceIO) -> EResult<Option<Self>> {
-		let blocks_count = storage.blocks_count();
-
-		let main_hdr = match Self::read_hdr(storage, 1) {
-			Ok(hdr) => hdr,
-			Err(e) if e == errno!(EINVAL) => return Ok(None),
-			Err(e) => return Err(e),
-		};
-		let alternate_hdr = Self::read_hdr(storage, main_hdr.alternate_hdr_lba)?;
-
-		let main_entries = main_hdr.get_entries(storage)?;
-		let alternate_entries = alternate_hdr.get_entries(storage)?;
-
-		// Check entries correctness
-		for (main_entry, alternate_entry) in main_entries.iter().zip(alternate_entries.iter()) {
-			if !main_entry.eq(alternate_entry, main_hdr.entry_size as _, blocks_count) {
-				return Err(errno!(EINVAL));
+		let primary = GptHeader::read(storage, 1)?;
+		if let Some(hdr) = &primary {
+			if let Some(entries) = hdr.read_entries(storage)? {
+				return Ok(Some(Self {
+					entries,
+				}));
 			}
 		}
-
-		Ok(Some(main_hdr))
+		// The primary table is corrupted, fallback to the alternate table
+		let alternate_lba = primary
+			.map(|hdr| hdr.alternate_hdr_lba)
+			// By default, the alternate header is on the last sector
+			.unwrap_or(-1);
+		if let Some(hdr) = GptHeader::read(storage, alternate_lba)? {
+			if let Some(entries) = hdr.read_entries(storage)? {
+				crate::log!(
+					Storage,
+					Warn,
+					"GPT: primary table is corrupted, using alternate table"
+				);
+				return Ok(Some(Self {
+					entries,
+				}));
+			}
+		}
+		Ok(None)
 	}
 
 	fn get_type(&self) -> &'static str {
@@ -302,11 +264,13 @@ impl Table for Gpt {
 		let blocks_count = storage.blocks_count();
 		let mut partitions = Vec::new();
 
-		for e in self.get_entries(storage)? {
+		for e in &self.entries {
 			let start = translate_lba(e.start, blocks_count).ok_or_else(|| errno!(EINVAL))?;
 			let end = translate_lba(e.end, blocks_count).ok_or_else(|| errno!(EINVAL))?;
-			// Doesn't overflow because the condition `end >= start` has already been
-			// checked + 1 is required because the ending LBA is included
+			if start > end {
+				return Err(errno!(EINVAL));
+			}
+			// + 1 is required because the ending LBA is included
 			let size = (end - start) + 1;
 
 			partitions.push(Partition {
@@ -318,3 +282,119 @@ impl Table for Gpt {
 		Ok(partitions)
 	}
 }
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use core::num::NonZeroU64;
+	use utils::bytes::as_bytes;
+
+	/// The number of sectors on the test disk.
+	const SECTORS: u64 = 64;
+	/// The size of a sector on the test disk.
+	const SECTOR_SIZE: usize = 512;
+
+	/// A disk backed by memory.
+	struct TestDisk(Vec<u8>);
+
+	impl DeviceIO for TestDisk {
+		fn block_size(&self) -> NonZeroU64 {
+			NonZeroU64::new(SECTOR_SIZE as _).unwrap()
+		}
+
+		fn blocks_count(&self) -> u64 {
+			SECTORS
+		}
+
+		fn read(&self, off: u64, buf: &mut [u8]) -> EResult<usize> {
+			let off = off as usize * SECTOR_SIZE;
+			buf.copy_from_slice(&self.0[off..(off + buf.len())]);
+			Ok(buf.len())
+		}
+
+		fn write(&self, _off: u64, _buf: &[u8]) -> EResult<usize> {
+			Err(errno!(EROFS))
+		}
+	}
+
+	/// Writes a table on `disk` with its header at `lba` and its entries array at `entries_lba`.
+	fn write_table(disk: &mut [u8], lba: i64, alternate_lba: i64, entries_lba: i64) {
+		let mut entries = [0; SECTOR_SIZE];
+		let entry = GPTEntry {
+			partition_type: [1; 16],
+			guid: [2; 16],
+			start: 10,
+			end: 19,
+			attributes: 0,
+			name: [0; 36],
+		};
+		entries[..128].copy_from_slice(as_bytes(&entry));
+		let mut hdr = GptHeader {
+			signature: *b"EFI PART",
+			revision: 0x10000,
+			hdr_size: HDR_MIN_SIZE as _,
+			checksum: 0,
+			reserved: 0,
+			hdr_lba: lba,
+			alternate_hdr_lba: alternate_lba,
+			first_usable: 3,
+			last_usable: 61,
+			disk_guid: [3; 16],
+			entries_start: entries_lba,
+			entries_number: 4,
+			entry_size: 128,
+			entries_checksum: checksum(&entries),
+		};
+		hdr.checksum = checksum(&as_bytes(&hdr)[..HDR_MIN_SIZE]);
+		let off = lba as usize * SECTOR_SIZE;
+		disk[off..(off + HDR_MIN_SIZE)].copy_from_slice(&as_bytes(&hdr)[..HDR_MIN_SIZE]);
+		let off = entries_lba as usize * SECTOR_SIZE;
+		disk[off..(off + SECTOR_SIZE)].copy_from_slice(&entries);
+	}
+
+	/// Creates a disk with a valid primary and alternate table.
+	fn test_disk() -> TestDisk {
+		let mut disk = Vec::new();
+		disk.resize(SECTORS as usize * SECTOR_SIZE, 0).unwrap();
+		write_table(&mut disk, 1, 63, 2);
+		write_table(&mut disk, 63, 1, 62);
+		TestDisk(disk)
+	}
+
+	/// Checks the partitions read from `disk`.
+	fn check_partitions(disk: &TestDisk) {
+		let gpt = Gpt::read(disk).unwrap().unwrap();
+		let partitions = gpt.get_partitions(disk).unwrap();
+		assert_eq!(partitions.len(), 1);
+		assert_eq!(partitions[0].offset, 10);
+		assert_eq!(partitions[0].size, 10);
+	}
+
+	#[test_case]
+	fn gpt_read() {
+		check_partitions(&test_disk());
+	}
+
+	#[test_case]
+	fn gpt_corrupted_primary_hdr() {
+		let mut disk = test_disk();
+		// Corrupt the revision
+		disk.0[SECTOR_SIZE + 8] ^= 1;
+		check_partitions(&disk);
+	}
+
+	#[test_case]
+	fn gpt_corrupted_primary_entries() {
+		let mut disk = test_disk();
+		disk.0[2 * SECTOR_SIZE + 32] ^= 1;
+		check_partitions(&disk);
+	}
+
+	#[test_case]
+	fn gpt_corrupted() {
+		let mut disk = test_disk();
+		disk.0[SECTOR_SIZE + 8] ^= 1;
+		disk.0[62 * SECTOR_SIZE + 32] ^= 1;
+		assert!(Gpt::read(&disk).unwrap().is_none());
+	}
+}
@@ -29,6 +29,8 @@ use utils::{bytes::from_bytes, collections::vec::Vec, errno::EResult, vec};
 
 /// The signature of the MBR partition table.
 const MBR_SIGNATURE: u16 = 0xaa55;
+/// The partition type indicating the disk uses GPT.
+const PROTECTIVE_PARTITION_TYPE: u8 = 0xee;
 
 /// A MBR partition.
 #[repr(C, packed)]
@@ -76,6 +78,15 @@ impl Clone for MbrTable {
 	}
 }
 
+impl MbrTable {
+	/// Tells whether the table is a protective MBR, meaning the disk uses GPT instead.
+	pub fn is_protective(&self) -> bool {
+		self.partitions
+			.iter()
+			.any(|p| p.partition_type == PROTECTIVE_PARTITION_TYPE)
+	}
+}
+
 impl Table for MbrTable {
 	fn read(storage: &dyn DeviceIO) -> EResult<Option<Self>> {
 		// Read first sector
@@ -59,13 +59,15 @@ pub trait Table {
 ///
 /// If no partitions table is present, the function returns `None`.
 pub fn read(storage: &dyn DeviceIO) -> EResult<Option<Box<dyn Table>>> {
-	// Try GPT
-	if let Some(table) = Gpt::read(storage)? {
-		return Ok(Some(Box::new(table)?));
+	let Some(mbr) = MbrTable::read(storage)? else {
+		return Ok(None);
+	};
+	if !mbr.is_protective() {
+		return Ok(Some(Box::new(mbr)?));
 	}
-	// Try MBR
-	if let Some(table) = MbrTable::read(storage)? {
-		return Ok(Some(Box::new(table)?));
+	// The disk uses GPT. If the table is corrupted, the protective partition must not be exposed
+	match Gpt::read(storage)? {
+		Some(table) => Ok(Some(Box::new(table)?)),
+		None => Ok(None),
 	}
-	Ok(None)
 }
@@ -40,6 +40,7 @@
 use crate::{
 	device::{storage::ide, DeviceIO},
 	io,
+	module::param::Param,
 };
 use core::{cmp::min, num::NonZeroU64};
 use utils::{errno, errno::EResult, lock::Mutex};
@@ -78,6 +79,8 @@ const COMMAND_READ_SECTORS_EXT: u8 = 0x24;
 const COMMAND_WRITE_SECTORS: u8 = 0x30;
 /// Writes sectors on the disk with LBA48.
 const COMMAND_WRITE_SECTORS_EXT: u8 = 0x34;
+/// Puts the drive in standby mode immediately, spinning it down.
+const COMMAND_STANDBY_IMMEDIATE: u8 = 0xe0;
 /// Flush cache command.
 const COMMAND_CACHE_FLUSH: u8 = 0xe7;
 /// Identifies the selected drive.
@@ -116,6 +119,25 @@ const STATUS_BSY: u8 = 0b10000000;
 /// The size of a sector in bytes.
 const SECTOR_SIZE: u64 = 512;
 
+/// The maximum number of sectors transferred by a single command.
+///
+/// PIO commands cannot be queued, so this bounds the amount of work queued on the device by a
+/// single request. Lower values reduce the latency of concurrent requests, at the cost of
+/// throughput. The value is capped to what the command set supports.
+pub static QUEUE_DEPTH: Param<u32> = Param::new("ide", "queue_depth", 65536, true, None);
+
+/// Returns the maximum number of sectors that can be transferred by a single command.
+///
+/// `lba48` tells whether the command uses LBA48, which allows larger transfers.
+fn max_sectors(lba48: bool) -> u64 {
+	let max = if lba48 {
+		(u16::MAX as u64) + 1
+	} else {
+		(u8::MAX as u64) + 1
+	};
+	(QUEUE_DEPTH.get() as u64).clamp(1, max)
+}
+
 // TODO Synchronize both master and slave disks so that another thread cannot
 // trigger a select while operating on a drive
 
@@ -383,236 +405,182 @@ impl PATAInterface {
 			}
 		}
 	}
-}
-
-impl DeviceIO for PATAInterface {
-	fn block_size(&self) -> NonZeroU64 {
-		SECTOR_SIZE.try_into().unwrap()
-	}
-
-	fn blocks_count(&self) -> u64 {
-		self.sectors_count
-	}
 
-	// TODO clean
-	fn read(&self, off: u64, buf: &mut [u8]) -> EResult<usize> {
-		let size = buf.len() as u64 / SECTOR_SIZE;
+	/// Checks a request of `size` sectors at offset `off`.
+	///
+	/// On success, the function returns whether LBA48 has to be used, and the maximum number of
+	/// sectors that can be transferred with a single command.
+	fn check_request(&self, off: u64, size: u64) -> EResult<(bool, u64)> {
 		// If the offset and size are out of bounds of the disk, return an error
 		if off >= self.sectors_count || off + size > self.sectors_count {
 			return Err(errno!(EINVAL));
 		}
-
 		// Tells whether to use LBA48
 		let lba48 = (off + size) >= ((1 << 28) - 1);
-
 		// If LBA48 is required but not supported, return an error
 		if lba48 && !self.lba48 {
 			return Err(errno!(EIO));
 		}
+		Ok((lba48, max_sectors(lba48)))
+	}
 
-		// The maximum number of sectors that can be handled at each iterations
-		let iter_max = if lba48 {
-			(u16::MAX as u64) + 1
+	/// Sends a command to read or write `count` sectors at offset `off`.
+	///
+	/// Arguments:
+	/// - `lba48` tells whether LBA48 is used
+	/// - `write` tells whether the command is a write
+	///
+	/// `count` must not exceed the maximum returned by [`Self::check_request`].
+	///
+	/// The device is assumed to be selected.
+	fn send_rw_command(&self, off: u64, count: u64, lba48: bool, write: bool) {
+		// The maximum value is encoded as zero
+		let count = count as u16;
+		let mut drive = if lba48 {
+			// LBA48
+			0x40
 		} else {
-			(u8::MAX as u64) + 1
+			// LBA28
+			0xe0
 		};
-
-		// Avoid data race
-		let _guard = self.lock.lock();
-		// Select disk
-		self.select(false);
-
-		let mut i = 0;
-		while i < size {
-			let off = off + i;
-
-			// The number of blocks for this iteration
-			let mut count = min(size - i, iter_max);
-			if count == iter_max {
-				count = 0;
-			}
-
-			let mut drive = if lba48 {
-				// LBA48
-				0x40
-			} else {
-				// LBA28
-				0xe0
-			};
-			if self.slave {
-				// Setting slave bit
-				drive |= 1 << 4;
-			}
-
-			// If LBA28, add the end of the sector offset
-			if !lba48 {
-				drive |= ((off >> 24) & 0x0f) as u8;
-			}
-
-			self.outb(PortOffset::Ata(DRIVE_REGISTER_OFFSET), drive);
-
-			// If LBA48, write high bytes first
-			if lba48 {
-				let count = ((count >> 8) & 0xff) as u8;
-				let lo_lba = ((off >> 24) & 0xff) as u8;
-				let mid_lba = ((off >> 32) & 0xff) as u8;
-				let hi_lba = ((off >> 40) & 0xff) as u8;
-
-				self.outb(PortOffset::Ata(SECTORS_COUNT_REGISTER_OFFSET), count);
-				self.outb(PortOffset::Ata(LBA_LO_REGISTER_OFFSET), lo_lba);
-				self.outb(PortOffset::Ata(LBA_MID_REGISTER_OFFSET), mid_lba);
-				self.outb(PortOffset::Ata(LBA_HI_REGISTER_OFFSET), hi_lba);
-			}
-
-			let lo_lba = (off & 0xff) as u8;
-			let mid_lba = ((off >> 8) & 0xff) as u8;
-			let hi_lba = ((off >> 16) & 0xff) as u8;
-
-			self.outb(
-				PortOffset::Ata(SECTORS_COUNT_REGISTER_OFFSET),
-				(count & 0xff) as u8,
-			);
+		if self.slave {
+			// Setting slave bit
+			drive |= 1 << 4;
+		}
+		// If LBA28, add the end of the sector offset
+		if !lba48 {
+			drive |= ((off >> 24) & 0x0f) as u8;
+		}
+		self.outb(PortOffset::Ata(DRIVE_REGISTER_OFFSET), drive);
+		// If LBA48, write high bytes first
+		if lba48 {
+			let count = (count >> 8) as u8;
+			let lo_lba = ((off >> 24) & 0xff) as u8;
+			let mid_lba = ((off >> 32) & 0xff) as u8;
+			let hi_lba = ((off >> 40) & 0xff) as u8;
+			self.outb(PortOffset::Ata(SECTORS_COUNT_REGISTER_OFFSET), count);
 			self.outb(PortOffset::Ata(LBA_LO_REGISTER_OFFSET), lo_lba);
 			self.outb(PortOffset::Ata(LBA_MID_REGISTER_OFFSET), mid_lba);
 			self.outb(PortOffset::Ata(LBA_HI_REGISTER_OFFSET), hi_lba);
+		}
+		let lo_lba = (off & 0xff) as u8;
+		let mid_lba = ((off >> 8) & 0xff) as u8;
+		let hi_lba = ((off >> 16) & 0xff) as u8;
+		self.outb(
+			PortOffset::Ata(SECTORS_COUNT_REGISTER_OFFSET),
+			(count & 0xff) as u8,
+		);
+		self.outb(PortOffset::Ata(LBA_LO_REGISTER_OFFSET), lo_lba);
+		self.outb(PortOffset::Ata(LBA_MID_REGISTER_OFFSET), mid_lba);
+		self.outb(PortOffset::Ata(LBA_HI_REGISTER_OFFSET), hi_lba);
+		let cmd = match (lba48, write) {
+			(false, false) => COMMAND_READ_SECTORS,
+			(true, false) => COMMAND_READ_SECTORS_EXT,
+			(false, true) => COMMAND_WRITE_SECTORS,
+			(true, true) => COMMAND_WRITE_SECTORS_EXT,
+		};
+		self.send_command(cmd);
+	}
+}
 
-			if lba48 {
-				self.send_command(COMMAND_READ_SECTORS_EXT);
-			} else {
-				self.send_command(COMMAND_READ_SECTORS);
-			}
+impl DeviceIO for PATAInterface {
+	fn block_size(&self) -> NonZeroU64 {
+		SECTOR_SIZE.try_into().unwrap()
+	}
 
-			if count == 0 {
-				count = iter_max;
-			}
+	fn blocks_count(&self) -> u64 {
+		self.sectors_count
+	}
 
-			for j in 0..count {
-				self.wait_io()?;
+	fn read(&self, off: u64, buf: &mut [u8]) -> EResult<usize> {
+		self.read_sectors(off, &mut [buf])
+	}
 
-				for k in 0..256 {
-					let index = (((i + j) * 256 + k) * 2) as usize;
-					debug_assert!(index + 1 < buf.len());
+	fn write(&self, off: u64, buf: &[u8]) -> EResult<usize> {
+		self.write_sectors(off, &[buf])
+	}
 
-					let word = self.inw(PortOffset::Ata(DATA_REGISTER_OFFSET));
-					buf[index] = (word & 0xff) as _;
-					buf[index + 1] = ((word >> 8) & 0xff) as _;
+	fn read_sectors(&self, off: u64, bufs: &mut [&mut [u8]]) -> EResult<usize> {
+		let size = bufs.iter().map(|b| b.len() as u64).sum::<u64>() / SECTOR_SIZE;
+		let (lba48, iter_max) = self.check_request(off, size)?;
+		let mut sectors = bufs
+			.iter_mut()
+			.flat_map(|b| b.chunks_exact_mut(SECTOR_SIZE as _));
+		// Avoid data race
+		let _guard = self.lock.lock();
+		// Select disk
+		self.select(false);
+		let mut i = 0;
+		while i < size {
+			let count = min(size - i, iter_max);
+			self.send_rw_command(off + i, count, lba48, false);
+			for sector in sectors.by_ref().take(count as _) {
+				self.wait_io()?;
+				for word in sector.chunks_exact_mut(2) {
+					let w = self.inw(PortOffset::Ata(DATA_REGISTER_OFFSET));
+					word.copy_from_slice(&w.to_le_bytes());
 				}
 			}
-
 			i += count;
 		}
-
 		Ok((size * SECTOR_SIZE) as _)
 	}
 
-	// TODO clean
-	fn write(&self, off: u64, buf: &[u8]) -> EResult<usize> {
-		let size = buf.len() as u64 / SECTOR_SIZE;
-		// If the offset and size are out of bounds of the disk, return an error
-		if off >= self.sectors_count || off + size > self.sectors_count {
-			return Err(errno!(EINVAL));
-		}
-
-		// Tells whether to use LBA48
-		let lba48 = (off + size) >= ((1 << 28) - 1);
-
-		// If LBA48 is required but not supported, return an error
-		if lba48 && !self.lba48 {
-			return Err(errno!(EIO));
-		}
-
-		// The maximum number of sectors that can be handled at each iterations
-		let iter_max = if lba48 {
-			(u16::MAX as u64) + 1
-		} else {
-			(u8::MAX as u64) + 1
-		};
-
+	fn write_sectors(&self, off: u64, bufs: &[&[u8]]) -> EResult<usize> {
+		let size = bufs.iter().map(|b| b.len() as u64).sum::<u64>() / SECTOR_SIZE;
+		let (lba48, iter_max) = self.check_request(off, size)?;
+		let mut sectors = bufs.iter().flat_map(|b| b.chunks_exact(SECTOR_SIZE as _));
 		// Avoid data race
 		let _guard = self.lock.lock();
 		// Select disk
 		self.select(false);
-
 		let mut i = 0;
 		while i < size {
-			let off = off + i;
-
-			// The number of blocks for this iteration
-			let mut count = min(size - i, iter_max);
-			if count == iter_max {
-				count = 0;
-			}
-
-			let mut drive = if lba48 {
-				// LBA48
-				0x40
-			} else {
-				// LBA28
-				0xe0
-			};
-			if self.slave {
-				// Setting slave bit
-				drive |= 1 << 4;
-			}
-
-			// If LBA28, add the end of the sector offset
-			if !lba48 {
-				drive |= ((off >> 24) & 0x0f) as u8;
-			}
-
-			self.outb(PortOffset::Ata(DRIVE_REGISTER_OFFSET), drive);
-
-			// If LBA48, write high bytes first
-			if lba48 {
-				let count = ((count >> 8) & 0xff) as u8;
-				let lo_lba = ((off >> 24) & 0xff) as u8;
-				let mid_lba = ((off >> 32) & 0xff) as u8;
-				let hi_lba = ((off >> 40) & 0xff) as u8;
-
-				self.outb(PortOffset::Ata(SECTORS_COUNT_REGISTER_OFFSET), count);
-				self.outb(PortOffset::Ata(LBA_LO_REGISTER_OFFSET), lo_lba);
-				self.outb(PortOffset::Ata(LBA_MID_REGISTER_OFFSET), mid_lba);
-				self.outb(PortOffset::Ata(LBA_HI_REGISTER_OFFSET), hi_lba);
-			}
-
-			let lo_lba = (off & 0xff) as u8;
-			let mid_lba = ((off >> 8) & 0xff) as u8;
-			let hi_lba = ((off >> 16) & 0xff) as u8;
-
-			self.outb(
-				PortOffset::Ata(SECTORS_COUNT_REGISTER_OFFSET),
-				(count & 0xff) as u8,
-			);
-			self.outb(PortOffset::Ata(LBA_LO_REGISTER_OFFSET), lo_lba);
-			self.outb(PortOffset::Ata(LBA_MID_REGISTER_OFFSET), mid_lba);
-			self.outb(PortOffset::Ata(LBA_HI_REGISTER_OFFSET), hi_lba);
-
-			if lba48 {
-				self.send_command(COMMAND_WRITE_SECTORS_EXT);
-			} else {
-				self.send_command(COMMAND_WRITE_SECTORS);
-			}
-
-			if count == 0 {
-				count = iter_max;
-			}
-
-			for j in 0..count {
+			let count = min(size - i, iter_max);
+			self.send_rw_command(off + i, count, lba48, true);
+			for sector in sectors.by_ref().take(count as _) {
 				self.wait_io()?;
-
-				for k in 0..256 {
-					let index = (((i + j) * 256 + k) * 2) as usize;
-					debug_assert!(index + 1 < buf.len());
-
-					let word = ((buf[index + 1] as u16) << 8) | (buf[index] as u16);
-					self.outw(PortOffset::Ata(DATA_REGISTER_OFFSET), word)
+				for word in sector.chunks_exact(2) {
+					let w = u16::from_le_bytes([word[0], word[1]]);
+					self.outw(PortOffset::Ata(DATA_REGISTER_OFFSET), w);
 				}
 			}
-
 			self.cache_flush();
 			i += count;
 		}
-
 		Ok((size * SECTOR_SIZE) as _)
 	}
+
+	fn shutdown(&self) -> EResult<()> {
+		// Avoid data race
+		let _guard = self.lock.lock();
+		self.select(true);
+		self.cache_flush();
+		self.send_command(COMMAND_STANDBY_IMMEDIATE);
+		self.wait_busy();
+		if self.get_status() & (STATUS_ERR | STATUS_DF) != 0 {
+			return Err(errno!(EIO));
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test_case]
+	fn queue_depth() {
+		let default = QUEUE_DEPTH.get();
+		// The default value is capped to what each command set supports
+		assert_eq!(max_sectors(false), 256);
+		assert_eq!(max_sectors(true), 65536);
+		QUEUE_DEPTH.set(16);
+		assert_eq!(max_sectors(false), 16);
+		assert_eq!(max_sectors(true), 16);
+		// At least one sector is transferred by each command
+		QUEUE_DEPTH.set(0);
+		assert_eq!(max_sectors(true), 1);
+		QUEUE_DEPTH.set(default);
+	}
 }
@@ -34,7 +34,10 @@ use crate::{
 	},
 	tty::{termios, termios::Termios, TTYDisplay, WinSize, TTY},
 };
-use core::{ffi::c_void, num::NonZeroU64};
+use core::{
+	ffi::{c_int, c_void},
+	num::NonZeroU64,
+};
 use utils::{errno, errno::EResult};
 
 /// A TTY device's handle.
@@ -72,7 +75,7 @@ impl TTYDeviceHandle {
 	/// If not, it is killed with a `SIGTTOU` signal.
 	///
 	/// This function must be called before performing the write operation.
-	fn check_sigttou(&self, tty: &TTYDisplay) -> EResult<()> {
+	pub(super) fn check_sigttou(&self, tty: &TTYDisplay) -> EResult<()> {
 		let proc_mutex = Process::current();
 		let mut proc = proc_mutex.lock();
 		if tty.get_termios().c_lflag & termios::consts::TOSTOP == 0 {
@@ -105,8 +108,7 @@ impl DeviceIO for TTYDeviceHandle {
 
 	fn read(&self, _off: u64, buff: &mut [u8]) -> EResult<usize> {
 		self.check_sigttin(&TTY.display.lock())?;
-		let len = TTY.read(buff)?;
-		Ok(len)
+		TTY.read(buff, false)
 	}
 
 	fn write(&self, _off: u64, buff: &[u8]) -> EResult<usize> {
@@ -119,6 +121,11 @@ impl DeviceIO for TTYDeviceHandle {
 		self.read(off, buf)
 	}
 
+	fn read_bytes_nonblock(&self, _off: u64, buf: &mut [u8]) -> EResult<usize> {
+		self.check_sigttin(&TTY.display.lock())?;
+		TTY.read(buf, true)
+	}
+
 	fn write_bytes(&self, off: u64, buf: &[u8]) -> EResult<usize> {
 		self.write(off, buf)
 	}
@@ -129,6 +136,11 @@ impl DeviceIO for TTYDeviceHandle {
 		Ok(res)
 	}
 
+	fn poll_wait(&self) -> EResult<bool> {
+		TTY.poll_wait()?;
+		Ok(true)
+	}
+
 	fn ioctl(&self, request: ioctl::Request, argp: *const c_void) -> EResult<u32> {
 		let mut tty = TTY.display.lock();
 		match request.get_old_format() {
@@ -172,6 +184,11 @@ impl DeviceIO for TTYDeviceHandle {
 				tty.set_winsize(winsize.clone());
 				Ok(0)
 			}
+			ioctl::FIONREAD => {
+				let count_ptr = SyscallPtr::<c_int>::from_syscall_arg(argp as usize);
+				count_ptr.copy_to_user(TTY.get_available_size() as _)?;
+				Ok(0)
+			}
 			_ => Err(errno!(EINVAL)),
 		}
 	}
@@ -22,7 +22,7 @@ use crate::{
 	crypto::{rand, rand::EntropyPool},
 	idt,
 	idt::pic,
-	process,
+	panic, process,
 	process::regs::Regs,
 };
 use core::{ffi::c_void, intrinsics::unlikely, ptr::NonNull};
@@ -208,7 +208,7 @@ extern "C" fn event_handler(id: u32, code: u32, ring: u32, regs: &mut Regs) {
 		let res = c(id, code, regs, ring);
 		match res {
 			CallbackResult::Continue => {}
-			CallbackResult::Panic => panic!("{}, code: {code:x}", get_error_message(id)),
+			CallbackResult::Panic => panic::exception(get_error_message(id), code, regs),
 		}
 	}
 	// Unlock to avoid deadlocks
@@ -0,0 +1,152 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Buffers attached to filesystem nodes.
+//!
+//! Named FIFOs and bound Unix sockets do not store their data on the filesystem. Instead, a
+//! buffer is attached to the [`FileLocation`] of the node, so that the same buffer is used
+//! regardless of the path or mountpoint through which the node is reached.
+//!
+//! A buffer remains attached as long as it is in use. It is detached when its last user
+//! releases it, or when the node is removed from the filesystem, since its location may then be
+//! reused by another node.
+
+use crate::file::{FileLocation, FileOps};
+use utils::{collections::hashmap::HashMap, errno, errno::EResult, lock::Mutex, ptr::arc::Arc};
+
+/// The buffers attached to filesystem nodes, by location.
+static BUFFERS: Mutex<HashMap<FileLocation, Arc<dyn FileOps>>> = Mutex::new(HashMap::new());
+
+/// Returns the buffer attached to the node at `loc`.
+///
+/// If no buffer is attached, `init` is called to create one, which is then attached.
+pub fn get_or_init<F: FnOnce() -> EResult<Arc<dyn FileOps>>>(
+	loc: &FileLocation,
+	init: F,
+) -> EResult<Arc<dyn FileOps>> {
+	let mut buffers = BUFFERS.lock();
+	if let Some(buf) = buffers.get(loc) {
+		return Ok(buf.clone());
+	}
+	let buf = init()?;
+	buffers.insert(loc.clone(), buf.clone())?;
+	Ok(buf)
+}
+
+/// Returns the buffer attached to the node at `loc`, if any.
+pub fn get(loc: &FileLocation) -> Option<Arc<dyn FileOps>> {
+	BUFFERS.lock().get(loc).cloned()
+}
+
+/// Attaches the buffer `buf` to the node at `loc`.
+///
+/// If a buffer is already attached to the node, the function returns [`errno::EADDRINUSE`].
+pub fn attach(loc: FileLocation, buf: Arc<dyn FileOps>) -> EResult<()> {
+	let mut buffers = BUFFERS.lock();
+	if buffers.get(&loc).is_some() {
+		return Err(errno!(EADDRINUSE));
+	}
+	buffers.insert(loc, buf)?;
+	Ok(())
+}
+
+/// Releases a reference to the buffer `buf`, attached to the node at `loc`.
+///
+/// If the caller's reference is the last one besides the attachment itself, the buffer is
+/// detached. If another buffer is attached to the node, the function does nothing.
+pub fn release(loc: &FileLocation, buf: &Arc<dyn FileOps>) {
+	// Lock to avoid a race condition with `strong_count`
+	let mut buffers = BUFFERS.lock();
+	let Some(attached) = buffers.get(loc) else {
+		return;
+	};
+	if attached.as_ptr() as *const () != buf.as_ptr() as *const () {
+		return;
+	}
+	// `buf` + the one in `BUFFERS` = `2`
+	if Arc::strong_count(buf) <= 2 {
+		buffers.remove(loc);
+	}
+}
+
+/// Detaches the buffer attached to the node at `loc`, if any.
+///
+/// Users of the buffer keep their reference to it.
+pub fn detach(loc: &FileLocation) {
+	BUFFERS.lock().remove(loc);
+}
+
+/// Detaches the buffer attached to the node at `loc` if it is the one located at `buf`.
+///
+/// This is used by a buffer to detach itself when it is no longer in use.
+pub fn detach_if(loc: &FileLocation, buf: *const ()) {
+	let mut buffers = BUFFERS.lock();
+	let Some(attached) = buffers.get(loc) else {
+		return;
+	};
+	if attached.as_ptr() as *const () != buf {
+		return;
+	}
+	let buf = buffers.remove(loc);
+	// Drop after unlocking, in case this is the last reference
+	drop(buffers);
+	drop(buf);
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::file::pipe::PipeBuffer;
+
+	/// Returns a location for testing purpose.
+	fn location(inode: u64) -> FileLocation {
+		FileLocation {
+			mountpoint_id: u32::MAX,
+			inode,
+		}
+	}
+
+	#[test_case]
+	fn buffer_shared() {
+		let loc = location(1);
+		let init = || Ok(Arc::new(PipeBuffer::new()?)? as _);
+		let buf0 = get_or_init(&loc, init).unwrap();
+		let buf1 = get_or_init(&loc, init).unwrap();
+		assert_eq!(buf0.as_ptr() as *const (), buf1.as_ptr() as *const ());
+		// The buffer remains attached while it is used
+		release(&loc, &buf0);
+		drop(buf0);
+		assert!(get(&loc).is_some());
+		release(&loc, &buf1);
+		drop(buf1);
+		assert!(get(&loc).is_none());
+	}
+
+	#[test_case]
+	fn buffer_attach() {
+		let loc = location(2);
+		let buf: Arc<dyn FileOps> = Arc::new(PipeBuffer::new().unwrap()).unwrap();
+		attach(loc.clone(), buf.clone()).unwrap();
+		assert_eq!(
+			attach(loc.clone(), buf.clone()).unwrap_err(),
+			errno!(EADDRINUSE)
+		);
+		detach(&loc);
+		assert!(get(&loc).is_none());
+	}
+}
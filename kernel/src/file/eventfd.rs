@@ -0,0 +1,160 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! An eventfd is a counter readable and writable through a file descriptor, which allows to wait
+//! for events, or to notify them, along with other files.
+//!
+//! Writing adds an 8 bytes value to the counter. Reading returns the counter and resets it to
+//! zero, or decrements it by one in semaphore mode.
+
+use crate::{
+	file::{wait_queue::WaitQueue, File, FileOps, FileType, Stat, O_CLOEXEC, O_NONBLOCK},
+	syscall::{
+		ioctl,
+		poll::{POLLIN, POLLOUT},
+	},
+};
+use core::{
+	ffi::{c_int, c_void},
+	mem,
+	mem::size_of,
+};
+use utils::{errno, errno::EResult, lock::Mutex};
+
+/// `eventfd2` flag: reading decrements the counter by one instead of resetting it.
+pub const EFD_SEMAPHORE: c_int = 1;
+/// `eventfd2` flag: the file descriptor is closed on `execve`.
+pub const EFD_CLOEXEC: c_int = O_CLOEXEC;
+/// `eventfd2` flag: the file descriptor is non-blocking.
+pub const EFD_NONBLOCK: c_int = O_NONBLOCK;
+
+/// The maximum value of the counter.
+const COUNTER_MAX: u64 = u64::MAX - 1;
+
+/// An event counter.
+#[derive(Debug)]
+pub struct EventFd {
+	/// The value of the counter.
+	counter: Mutex<u64>,
+	/// Tells whether the counter works in semaphore mode.
+	semaphore: bool,
+
+	/// The queue of processes waiting for the counter to be non-zero.
+	rd_queue: WaitQueue,
+	/// The queue of processes waiting for room in the counter.
+	wr_queue: WaitQueue,
+}
+
+impl EventFd {
+	/// Creates a counter with the initial value `initval`.
+	///
+	/// `semaphore` tells whether the counter works in semaphore mode.
+	pub fn new(initval: u64, semaphore: bool) -> Self {
+		Self {
+			counter: Mutex::new(initval),
+			semaphore,
+
+			rd_queue: WaitQueue::new(),
+			wr_queue: WaitQueue::new(),
+		}
+	}
+}
+
+impl FileOps for EventFd {
+	fn get_stat(&self, _file: &File) -> EResult<Stat> {
+		Ok(Stat {
+			mode: FileType::Regular.to_mode() | 0o600,
+			..Default::default()
+		})
+	}
+
+	fn acquire(&self, _file: &File) {}
+
+	fn release(&self, _file: &File) {}
+
+	fn poll(&self, _file: &File, mask: u32) -> EResult<u32> {
+		let counter = *self.counter.lock();
+		let mut events = 0;
+		if counter > 0 {
+			events |= POLLIN;
+		}
+		if counter < COUNTER_MAX {
+			events |= POLLOUT;
+		}
+		Ok(events & mask)
+	}
+
+	fn poll_wait(&self, _file: &File) -> EResult<bool> {
+		self.rd_queue.register()?;
+		self.wr_queue.register()?;
+		Ok(true)
+	}
+
+	fn ioctl(&self, _file: &File, _request: ioctl::Request, _argp: *const c_void) -> EResult<u32> {
+		Err(errno!(ENOTTY))
+	}
+
+	fn read(&self, file: &File, _off: u64, buf: &mut [u8]) -> EResult<usize> {
+		if buf.len() < size_of::<u64>() {
+			return Err(errno!(EINVAL));
+		}
+		let nonblock = file.get_flags() & O_NONBLOCK != 0;
+		let val = self.rd_queue.wait_until(|| {
+			let mut counter = self.counter.lock();
+			if *counter == 0 {
+				return nonblock.then_some(Err(errno!(EAGAIN)));
+			}
+			let val = if self.semaphore {
+				*counter -= 1;
+				1
+			} else {
+				mem::take(&mut *counter)
+			};
+			Some(Ok(val))
+		})??;
+		self.wr_queue.wake_all();
+		buf[..size_of::<u64>()].copy_from_slice(&val.to_ne_bytes());
+		Ok(size_of::<u64>())
+	}
+
+	fn write(&self, file: &File, _off: u64, buf: &[u8]) -> EResult<usize> {
+		let Some(val) = buf
+			.get(..size_of::<u64>())
+			.map(|b| u64::from_ne_bytes(b.try_into().unwrap()))
+		else {
+			return Err(errno!(EINVAL));
+		};
+		if val == u64::MAX {
+			return Err(errno!(EINVAL));
+		}
+		let nonblock = file.get_flags() & O_NONBLOCK != 0;
+		self.wr_queue.wait_until(|| {
+			let mut counter = self.counter.lock();
+			// Wait until the value fits
+			if val > COUNTER_MAX - *counter {
+				return nonblock.then_some(Err(errno!(EAGAIN)));
+			}
+			*counter += val;
+			Some(Ok(()))
+		})??;
+		if val > 0 {
+			self.rd_queue.wake_all();
+		}
+		Ok(size_of::<u64>())
+	}
+}
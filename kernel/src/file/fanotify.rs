@@ -0,0 +1,587 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! fanotify allows a privileged listener to be notified of accesses to files, and optionally to
+//! allow or deny them.
+//!
+//! A listener creates a notification group with the `fanotify_init` system call, which returns a
+//! file descriptor. Marks are then added to the group with `fanotify_mark`, selecting which files
+//! (a single inode, or a whole mountpoint) and which events are watched.
+//!
+//! Reading from the group's file descriptor returns events, each carrying a new file descriptor
+//! to the accessed file. For permission events, the process performing the access is blocked
+//! until the listener writes a response to the group's file descriptor.
+
+use crate::{
+	file::{
+		fd::{FileDescriptorTable, FD_CLOEXEC},
+		vfs,
+		wait_queue::WaitQueue,
+		File, FileLocation, FileOps, FileType, Stat, O_CLOEXEC, O_NONBLOCK,
+	},
+	process::{mem_space::copy::SyscallPtr, pid::Pid, Process},
+	syscall::{ioctl, poll::POLLIN, FromSyscallArg},
+};
+use core::{
+	ffi::{c_int, c_void},
+	intrinsics::unlikely,
+	mem,
+	mem::size_of,
+	ptr,
+};
+use utils::{
+	bytes::as_bytes,
+	collections::vec::Vec,
+	errno,
+	errno::{AllocResult, EResult},
+	lock::Mutex,
+	ptr::arc::Arc,
+};
+
+/// Init flag: Set the close-on-exec flag on the group's file descriptor.
+pub const FAN_CLOEXEC: u32 = 0x1;
+/// Init flag: Enable non-blocking reads on the group's file descriptor.
+pub const FAN_NONBLOCK: u32 = 0x2;
+/// Init flag: Group receiving notification events only.
+pub const FAN_CLASS_NOTIF: u32 = 0x0;
+/// Init flag: Group receiving permission events, after the file content is accessible.
+pub const FAN_CLASS_CONTENT: u32 = 0x4;
+/// Init flag: Group receiving permission events, before the file content is accessible.
+pub const FAN_CLASS_PRE_CONTENT: u32 = 0x8;
+
+/// Event: A file has been accessed (read).
+pub const FAN_ACCESS: u64 = 0x1;
+/// Event: A file has been modified (write).
+pub const FAN_MODIFY: u64 = 0x2;
+/// Event: A file has been opened.
+pub const FAN_OPEN: u64 = 0x20;
+/// Permission event: A file is about to be opened.
+pub const FAN_OPEN_PERM: u64 = 0x10000;
+/// Permission event: A file is about to be accessed (read).
+pub const FAN_ACCESS_PERM: u64 = 0x20000;
+
+/// The set of all notification events.
+const NOTIF_EVENTS: u64 = FAN_ACCESS | FAN_MODIFY | FAN_OPEN;
+/// The set of all permission events.
+const PERM_EVENTS: u64 = FAN_OPEN_PERM | FAN_ACCESS_PERM;
+
+/// Mark flag: Add the events in the mask to the mark.
+pub const FAN_MARK_ADD: u32 = 0x1;
+/// Mark flag: Remove the events in the mask from the mark.
+pub const FAN_MARK_REMOVE: u32 = 0x2;
+/// Mark flag: If the path is a symbolic link, mark the link itself.
+pub const FAN_MARK_DONT_FOLLOW: u32 = 0x4;
+/// Mark flag: If the path is not a directory, fail.
+pub const FAN_MARK_ONLYDIR: u32 = 0x8;
+/// Mark flag: Mark the mountpoint containing the path instead of the file itself.
+pub const FAN_MARK_MOUNT: u32 = 0x10;
+/// Mark flag: Remove all marks of the given kind (inode or mountpoint).
+pub const FAN_MARK_FLUSH: u32 = 0x80;
+/// Mark flag: Mark the filesystem containing the path.
+///
+/// Since a filesystem can be mounted only once, this is equivalent to [`FAN_MARK_MOUNT`].
+pub const FAN_MARK_FILESYSTEM: u32 = 0x100;
+
+/// Response: Allow the operation.
+pub const FAN_ALLOW: u32 = 0x1;
+/// Response: Deny the operation.
+pub const FAN_DENY: u32 = 0x2;
+
+/// Kernel-internal open file description flag: accesses through the file do not generate
+/// events.
+///
+/// This is set on file descriptions given to listeners, so that they do not deadlock waiting
+/// for their own response.
+pub const O_NONOTIFY: i32 = 0x4000000;
+
+/// The version of the events metadata structure.
+const FANOTIFY_METADATA_VERSION: u8 = 3;
+/// The maximum number of events in a group's queue. Notification events exceeding this limit
+/// are dropped and permission events are allowed.
+const QUEUE_MAX: usize = 16384;
+
+/// Metadata of an event, as read by userspace.
+#[repr(C)]
+#[derive(Debug)]
+struct EventMetadata {
+	/// The length of the event, including the metadata.
+	event_len: u32,
+	/// The version of the structure.
+	vers: u8,
+	/// Reserved.
+	reserved: u8,
+	/// The length of the metadata.
+	metadata_len: u16,
+	/// The mask of events.
+	mask: u64,
+	/// The file descriptor of the accessed file.
+	fd: i32,
+	/// The PID of the process that caused the event.
+	pid: i32,
+}
+
+/// A response to a permission event, as written by userspace.
+#[repr(C)]
+#[derive(Debug)]
+struct Response {
+	/// The file descriptor of the event.
+	fd: i32,
+	/// The response, either [`FAN_ALLOW`] or [`FAN_DENY`].
+	response: u32,
+}
+
+/// The target of a mark.
+#[derive(Debug, Eq, PartialEq)]
+pub enum MarkTarget {
+	/// A single file.
+	Inode(FileLocation),
+	/// Every file of a mountpoint.
+	Mount(u32),
+}
+
+impl MarkTarget {
+	/// Tells whether the file at the given location is covered by the target.
+	fn matches(&self, loc: &FileLocation) -> bool {
+		match self {
+			Self::Inode(l) => l == loc,
+			Self::Mount(id) => loc.mountpoint_id == *id,
+		}
+	}
+}
+
+/// A mark, selecting events to be reported for a target.
+#[derive(Debug)]
+struct Mark {
+	/// The target of the mark.
+	target: MarkTarget,
+	/// The mask of events to report.
+	mask: u64,
+}
+
+/// A pending permission request, on which the process performing the access waits.
+#[derive(Debug)]
+struct PermRequest {
+	/// The response. `0` as long as no response has been given.
+	response: Mutex<u32>,
+	/// The queue on which the process performing the access waits.
+	queue: WaitQueue,
+}
+
+impl PermRequest {
+	/// Sets the response to the request and wakes up the waiting process.
+	fn respond(&self, response: u32) {
+		*self.response.lock() = response;
+		self.queue.wake_all();
+	}
+}
+
+/// An event waiting to be read.
+#[derive(Debug)]
+struct Event {
+	/// The mask of events.
+	mask: u64,
+	/// The accessed file.
+	entry: Arc<vfs::Entry>,
+	/// The PID of the process that caused the event.
+	pid: Pid,
+	/// If this is a permission event, the associated request.
+	perm: Option<Arc<PermRequest>>,
+}
+
+#[derive(Debug, Default)]
+struct GroupInner {
+	/// The group's marks.
+	marks: Vec<Mark>,
+	/// Events waiting to be read.
+	events: Vec<Event>,
+	/// Permission events that have been read and are waiting for a response, along with the
+	/// file descriptor that has been given to the listener.
+	pending: Vec<(c_int, Arc<PermRequest>)>,
+}
+
+/// A notification group.
+#[derive(Debug)]
+pub struct Group {
+	/// Tells whether the group can receive permission events.
+	perm: bool,
+	/// The flags of open file descriptions created for events.
+	event_flags: i32,
+	/// Inner with locking.
+	inner: Mutex<GroupInner>,
+	/// The queue of processes waiting for events.
+	rd_queue: WaitQueue,
+}
+
+impl Group {
+	/// Creates a new group.
+	///
+	/// Arguments:
+	/// - `perm` tells whether the group can receive permission events
+	/// - `event_flags` is the set of flags of open file descriptions created for events
+	pub fn new(perm: bool, event_flags: i32) -> Self {
+		Self {
+			perm,
+			event_flags,
+			inner: Mutex::new(GroupInner::default()),
+			rd_queue: WaitQueue::new(),
+		}
+	}
+
+	/// Adds the events in `mask` to the mark on `target`, creating the mark if necessary.
+	///
+	/// If `mask` contains permission events and the group cannot receive them, the function
+	/// returns [`errno::EINVAL`].
+	pub fn add_mark(&self, target: MarkTarget, mask: u64) -> EResult<()> {
+		if unlikely(mask & !(NOTIF_EVENTS | PERM_EVENTS) != 0) {
+			return Err(errno!(EINVAL));
+		}
+		if unlikely(!self.perm && mask & PERM_EVENTS != 0) {
+			return Err(errno!(EINVAL));
+		}
+		let mut inner = self.inner.lock();
+		match inner.marks.iter_mut().find(|m| m.target == target) {
+			Some(mark) => mark.mask |= mask,
+			None => inner.marks.push(Mark {
+				target,
+				mask,
+			})?,
+		}
+		Ok(())
+	}
+
+	/// Removes the events in `mask` from the mark on `target`. If no event remain, the mark is
+	/// removed.
+	///
+	/// If no mark exists on `target`, the function returns [`errno::ENOENT`].
+	pub fn remove_mark(&self, target: MarkTarget, mask: u64) -> EResult<()> {
+		let mut inner = self.inner.lock();
+		let index = inner
+			.marks
+			.iter()
+			.position(|m| m.target == target)
+			.ok_or_else(|| errno!(ENOENT))?;
+		inner.marks[index].mask &= !mask;
+		if inner.marks[index].mask == 0 {
+			inner.marks.remove(index);
+		}
+		Ok(())
+	}
+
+	/// Removes all marks. If `mount` is set, mountpoint marks are removed, else inode marks are
+	/// removed.
+	pub fn flush_marks(&self, mount: bool) {
+		self.inner
+			.lock()
+			.marks
+			.retain(|m| matches!(m.target, MarkTarget::Mount(_)) != mount);
+	}
+
+	/// Returns the events of `mask` the group is interested in for the file at `loc`.
+	fn get_mask(&self, loc: &FileLocation, mask: u64) -> u64 {
+		self.inner
+			.lock()
+			.marks
+			.iter()
+			.filter(|m| m.target.matches(loc))
+			.fold(0, |acc, m| acc | m.mask)
+			& mask
+	}
+
+	/// Queues an event.
+	///
+	/// If the queue is full, the function returns `false`.
+	fn push_event(&self, event: Event) -> AllocResult<bool> {
+		{
+			let mut inner = self.inner.lock();
+			if inner.events.len() >= QUEUE_MAX {
+				return Ok(false);
+			}
+			inner.events.push(event)?;
+		}
+		self.rd_queue.wake_all();
+		Ok(true)
+	}
+
+	/// Gives the listener a file descriptor to the file accessed by `event`, then returns the
+	/// event's metadata.
+	///
+	/// Arguments:
+	/// - `fds` is the file descriptor table of the listener
+	/// - `fd_flags` is the set of flags of the file descriptor to create
+	///
+	/// If the event is a permission event, it is added to the requests waiting for a response. On
+	/// failure, the access is allowed.
+	fn report(
+		&self,
+		event: Event,
+		fds: &Mutex<FileDescriptorTable>,
+		fd_flags: i32,
+	) -> EResult<EventMetadata> {
+		let Event {
+			mask,
+			entry,
+			pid,
+			perm,
+		} = event;
+		let res = File::open_entry(entry, (self.event_flags & !O_CLOEXEC) | O_NONOTIFY)
+			.and_then(|file| Ok(fds.lock().create_fd(fd_flags, file)?.0 as c_int))
+			.and_then(|fd| {
+				let Some(perm) = &perm else {
+					return Ok(fd);
+				};
+				if let Err(e) = self.inner.lock().pending.push((fd, perm.clone())) {
+					// Cannot fail since the file descriptor has just been created
+					let _ = fds.lock().close_fd(fd);
+					return Err(e.into());
+				}
+				Ok(fd)
+			});
+		match res {
+			Ok(fd) => Ok(EventMetadata {
+				event_len: size_of::<EventMetadata>() as _,
+				vers: FANOTIFY_METADATA_VERSION,
+				reserved: 0,
+				metadata_len: size_of::<EventMetadata>() as _,
+				mask,
+				fd,
+				pid: pid as _,
+			}),
+			Err(e) => {
+				// Do not leave the process performing the access blocked
+				if let Some(perm) = perm {
+					perm.respond(FAN_ALLOW);
+				}
+				Err(e)
+			}
+		}
+	}
+}
+
+impl FileOps for Group {
+	fn get_stat(&self, _file: &File) -> EResult<Stat> {
+		Ok(Stat {
+			mode: FileType::Regular.to_mode() | 0o600,
+			..Default::default()
+		})
+	}
+
+	fn acquire(&self, _file: &File) {}
+
+	fn release(&self, _file: &File) {
+		// Unregister the group
+		GROUPS.lock().retain(|g| !ptr::eq(g.as_ptr(), self));
+		// Allow all permission requests, so that no process remains blocked
+		let (events, pending) = {
+			let mut inner = self.inner.lock();
+			inner.marks.clear();
+			(mem::take(&mut inner.events), mem::take(&mut inner.pending))
+		};
+		for event in events {
+			if let Some(perm) = event.perm {
+				perm.respond(FAN_ALLOW);
+			}
+		}
+		for (_, perm) in pending {
+			perm.respond(FAN_ALLOW);
+		}
+	}
+
+	fn poll(&self, _file: &File, mask: u32) -> EResult<u32> {
+		let readable = !self.inner.lock().events.is_empty();
+		Ok(if readable { mask & POLLIN } else { 0 })
+	}
+
+	fn poll_wait(&self, _file: &File) -> EResult<bool> {
+		self.rd_queue.register()?;
+		Ok(true)
+	}
+
+	fn ioctl(&self, _file: &File, request: ioctl::Request, argp: *const c_void) -> EResult<u32> {
+		match request.get_old_format() {
+			ioctl::FIONREAD => {
+				let len = self.inner.lock().events.len() * size_of::<EventMetadata>();
+				let count_ptr = SyscallPtr::<c_int>::from_syscall_arg(argp as usize);
+				count_ptr.copy_to_user(len as _)?;
+			}
+			_ => return Err(errno!(ENOTTY)),
+		}
+		Ok(0)
+	}
+
+	fn read(&self, file: &File, _off: u64, buf: &mut [u8]) -> EResult<usize> {
+		const EVENT_LEN: usize = size_of::<EventMetadata>();
+		if unlikely(buf.len() < EVENT_LEN) {
+			return Err(errno!(EINVAL));
+		}
+		let fds = Process::current()
+			.lock()
+			.file_descriptors
+			.clone()
+			.ok_or_else(|| errno!(EBADF))?;
+		let mut fd_flags = 0;
+		if self.event_flags & O_CLOEXEC != 0 {
+			fd_flags |= FD_CLOEXEC;
+		}
+		let nonblock = file.get_flags() & O_NONBLOCK != 0;
+		let events = self.rd_queue.wait_until(|| {
+			let mut inner = self.inner.lock();
+			if inner.events.is_empty() {
+				return nonblock.then_some(Err(errno!(EAGAIN)));
+			}
+			// Take as many events as fit in the buffer
+			let count = (buf.len() / EVENT_LEN).min(inner.events.len());
+			let mut events = Vec::new();
+			if let Err(e) = events.reserve(count) {
+				return Some(Err(e.into()));
+			}
+			for _ in 0..count {
+				// Cannot fail since memory has been reserved
+				let _ = events.push(inner.events.remove(0));
+			}
+			Some(Ok(events))
+		})??;
+		let mut events = events.into_iter();
+		let mut off = 0;
+		let mut res = Ok(());
+		for event in events.by_ref() {
+			match self.report(event, &fds, fd_flags) {
+				Ok(metadata) => {
+					buf[off..(off + EVENT_LEN)].copy_from_slice(as_bytes(&metadata));
+					off += EVENT_LEN;
+				}
+				Err(e) => {
+					res = Err(e);
+					break;
+				}
+			}
+		}
+		// Do not leave the processes performing the remaining accesses blocked
+		for event in events {
+			if let Some(perm) = event.perm {
+				perm.respond(FAN_ALLOW);
+			}
+		}
+		match res {
+			Err(e) if off == 0 => Err(e),
+			_ => Ok(off),
+		}
+	}
+
+	fn write(&self, _file: &File, _off: u64, buf: &[u8]) -> EResult<usize> {
+		const RESPONSE_LEN: usize = size_of::<Response>();
+		if unlikely(buf.len() < RESPONSE_LEN) {
+			return Err(errno!(EINVAL));
+		}
+		let response = Response {
+			fd: i32::from_ne_bytes(buf[0..4].try_into().unwrap()),
+			response: u32::from_ne_bytes(buf[4..8].try_into().unwrap()),
+		};
+		if unlikely(!matches!(response.response, FAN_ALLOW | FAN_DENY)) {
+			return Err(errno!(EINVAL));
+		}
+		let perm = {
+			let mut inner = self.inner.lock();
+			let index = inner
+				.pending
+				.iter()
+				.position(|(fd, _)| *fd == response.fd)
+				.ok_or_else(|| errno!(ENOENT))?;
+			inner.pending.remove(index).1
+		};
+		perm.respond(response.response);
+		Ok(RESPONSE_LEN)
+	}
+}
+
+/// The list of registered groups.
+static GROUPS: Mutex<Vec<Arc<Group>>> = Mutex::new(Vec::new());
+
+/// Registers the given group so that it receives events.
+pub fn register(group: Arc<Group>) -> AllocResult<()> {
+	GROUPS.lock().push(group)
+}
+
+/// Notifies listeners of the event `mask` on the file `entry`.
+///
+/// If `mask` is a permission event, the function blocks until every interested listener has
+/// responded. If any of them denied the operation, the function returns [`errno::EPERM`].
+///
+/// The function does nothing if no listener is registered.
+///
+/// Notification events are sent once the operation has been performed. Thus, callers should
+/// ignore errors for them, since the operation cannot be reverted.
+pub fn notify(entry: &Arc<vfs::Entry>, mask: u64) -> EResult<()> {
+	let loc = &entry.node().location;
+	// Collect interested groups, to avoid holding the lock while waiting for responses
+	let groups = {
+		let groups = GROUPS.lock();
+		if groups.is_empty() {
+			return Ok(());
+		}
+		let mut interested = Vec::new();
+		for group in groups.iter() {
+			if group.get_mask(loc, mask) != 0 {
+				interested.push(group.clone())?;
+			}
+		}
+		interested
+	};
+	if groups.is_empty() {
+		return Ok(());
+	}
+	let pid = Process::current().lock().get_pid();
+	let is_perm = mask & PERM_EVENTS != 0;
+	for group in groups {
+		let perm = is_perm
+			.then(|| {
+				Arc::new(PermRequest {
+					response: Mutex::new(0),
+					queue: WaitQueue::new(),
+				})
+			})
+			.transpose()?;
+		let queued = group.push_event(Event {
+			mask,
+			entry: entry.clone(),
+			pid,
+			perm: perm.clone(),
+		})?;
+		// If the event could not be queued, the operation is allowed
+		let Some(perm) = perm.filter(|_| queued) else {
+			continue;
+		};
+		let response = perm.queue.wait_until(|| {
+			let response = *perm.response.lock();
+			(response != 0).then_some(response)
+		})?;
+		if response == FAN_DENY {
+			return Err(errno!(EPERM));
+		}
+	}
+	Ok(())
+}
+
+/// Same as [`notify`], for an access through the open file description `file`.
+///
+/// Files with no VFS entry and files opened with [`O_NONOTIFY`] do not generate events.
+pub fn notify_file(file: &File, mask: u64) -> EResult<()> {
+	match &file.vfs_entry {
+		Some(entry) if file.get_flags() & O_NONOTIFY == 0 => notify(entry, mask),
+		_ => Ok(()),
+	}
+}
@@ -20,9 +20,16 @@
 //!
 //! A file descriptor is an ID held by a process pointing to an entry in the
 //! open file description table.
+//!
+//! Several file descriptors may point to the same open file description, in which case they
+//! share its offset and flags. This happens when duplicating a file descriptor (`dup`) or when
+//! forking a process: the child gets its own copy of the table, but each descriptor in it
+//! points to the same open file description as in the parent.
+//!
+//! The table itself is shared only when creating a process with `CLONE_FILES`.
 
 use crate::file::File;
-use core::{cmp::max, ffi::c_int, mem};
+use core::{cmp::max, ffi::c_int, intrinsics::unlikely, mem};
 use utils::{
 	collections::vec::Vec,
 	errno,
@@ -207,8 +214,24 @@ impl FileDescriptorTable {
 
 	/// Returns an immutable reference to the file descriptor with ID `id`.
 	///
-	/// If the file descriptor does not exist, the function returns [`errno::EBADF`].
+	/// If the file descriptor does not exist, or if it refers to a file opened with
+	/// [`O_PATH`](crate::file::O_PATH), the function returns [`errno::EBADF`]. To get such file
+	/// descriptors, use [`Self::get_fd_raw`].
 	pub fn get_fd(&self, id: c_int) -> EResult<&FileDescriptor> {
+		let fd = self.get_fd_raw(id)?;
+		if unlikely(fd.get_file().is_path()) {
+			return Err(errno!(EBADF));
+		}
+		Ok(fd)
+	}
+
+	/// Returns an immutable reference to the file descriptor with ID `id`, including file
+	/// descriptors referring to files opened with [`O_PATH`](crate::file::O_PATH).
+	///
+	/// This is meant for operations which do not access the content of the file.
+	///
+	/// If the file descriptor does not exist, the function returns [`errno::EBADF`].
+	pub fn get_fd_raw(&self, id: c_int) -> EResult<&FileDescriptor> {
 		let id: usize = id.try_into().map_err(|_| errno!(EBADF))?;
 		self.0
 			.get(id)
@@ -216,6 +239,17 @@ impl FileDescriptorTable {
 			.ok_or_else(|| errno!(EBADF))
 	}
 
+	/// Returns an iterator over the open file descriptors, with their IDs, by increasing ID.
+	///
+	/// File descriptors referring to files opened with [`O_PATH`](crate::file::O_PATH) are
+	/// included.
+	pub fn iter(&self) -> impl Iterator<Item = (u32, &FileDescriptor)> {
+		self.0
+			.iter()
+			.enumerate()
+			.filter_map(|(id, fd)| Some((id as u32, fd.as_ref()?)))
+	}
+
 	/// Returns a mutable reference to the file descriptor with ID `id`.
 	///
 	/// If the file descriptor does not exist, the function returns [`errno::EBADF`].
@@ -253,7 +287,7 @@ impl FileDescriptorTable {
 			NewFDConstraint::Min(min) => self.get_available_fd(Some(min))?,
 		};
 		// The old FD
-		let old_fd = self.get_fd(id)?;
+		let old_fd = self.get_fd_raw(id)?;
 		// Create the new FD
 		let mut new_fd = old_fd.clone();
 		let flags = if cloexec { FD_CLOEXEC } else { 0 };
@@ -272,6 +306,9 @@ impl FileDescriptorTable {
 
 	/// Duplicates the whole file descriptors table.
 	///
+	/// The new table's file descriptors point to the same open file descriptions as the current
+	/// table. The file descriptors' flags are copied.
+	///
 	/// `cloexec` specifies whether the cloexec flag must be taken into account. This is the case
 	/// when executing a program.
 	pub fn duplicate(&self, cloexec: bool) -> EResult<Self> {
@@ -327,10 +364,10 @@ impl Drop for FileDescriptorTable {
 mod test {
 	use super::*;
 	use crate::{
-		file::{File, FileOps, Stat},
+		file::{File, FileOps, Stat, O_PATH},
 		syscall::ioctl::Request,
 	};
-	use core::ffi::c_void;
+	use core::{ffi::c_void, sync::atomic};
 
 	/// Dummy node ops for testing purpose.
 	#[derive(Debug)]
@@ -400,4 +437,63 @@ mod test {
 		assert!(id3 >= 8);
 		assert_ne!(id3, id2);
 	}
+
+	#[test_case]
+	fn fd_iter() {
+		let mut fds = FileDescriptorTable::default();
+		fds.create_fd(0, dummy_file()).unwrap();
+		fds.create_fd(0, dummy_file()).unwrap();
+		fds.create_fd(0, dummy_file()).unwrap();
+		fds.close_fd(1).unwrap();
+		let mut iter = fds.iter().map(|(id, _)| id);
+		assert_eq!(iter.next(), Some(0));
+		assert_eq!(iter.next(), Some(2));
+		assert_eq!(iter.next(), None);
+	}
+
+	#[test_case]
+	fn fd_path() {
+		let mut fds = FileDescriptorTable::default();
+		let file = File::open_floating(Arc::new(Dummy).unwrap(), O_PATH).unwrap();
+		let (id, _) = fds.create_fd(0, file).unwrap();
+		// The content of the file cannot be accessed
+		assert_eq!(fds.get_fd(id as _).unwrap_err(), errno!(EBADF));
+		assert!(!fds.get_fd_raw(id as _).unwrap().get_file().can_read());
+		// The file descriptor can still be duplicated
+		let (new_id, _) = fds
+			.duplicate_fd(id as _, NewFDConstraint::None, false)
+			.unwrap();
+		assert!(fds.get_fd_raw(new_id as _).unwrap().get_file().is_path());
+	}
+
+	#[test_case]
+	fn fd_duplicate_table() {
+		let mut fds = FileDescriptorTable::default();
+		fds.create_fd(0, dummy_file()).unwrap();
+		fds.create_fd(FD_CLOEXEC, dummy_file()).unwrap();
+		let mut new_fds = fds.duplicate(false).unwrap();
+		// The open file description is shared
+		let file = fds.get_fd(0).unwrap().get_file();
+		let new_file = new_fds.get_fd(0).unwrap().get_file();
+		assert_eq!(file.as_ptr(), new_file.as_ptr());
+		new_file.off.store(42, atomic::Ordering::Release);
+		assert_eq!(file.off.load(atomic::Ordering::Acquire), 42);
+		// File descriptor flags are not shared
+		assert_eq!(new_fds.get_fd(1).unwrap().flags, FD_CLOEXEC);
+		new_fds.get_fd_mut(1).unwrap().flags = 0;
+		assert_eq!(fds.get_fd(1).unwrap().flags, FD_CLOEXEC);
+		// Closing a file descriptor does not affect the other table
+		new_fds.close_fd(0).unwrap();
+		assert!(fds.get_fd(0).is_ok());
+	}
+
+	#[test_case]
+	fn fd_duplicate_table_cloexec() {
+		let mut fds = FileDescriptorTable::default();
+		fds.create_fd(0, dummy_file()).unwrap();
+		fds.create_fd(FD_CLOEXEC, dummy_file()).unwrap();
+		let new_fds = fds.duplicate(true).unwrap();
+		assert!(new_fds.get_fd(0).is_ok());
+		assert!(new_fds.get_fd(1).is_err());
+	}
 }
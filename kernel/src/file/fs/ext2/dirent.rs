@@ -186,28 +186,32 @@ impl Dirent {
 		Ok(())
 	}
 
+	/// Returns the file type stored in the entry.
+	///
+	/// If the filesystem does not store types in directory entries, or if the type is unknown,
+	/// the function returns `None`.
+	pub fn get_type(&self, superblock: &Superblock) -> Option<FileType> {
+		if superblock.s_feature_incompat & super::REQUIRED_FEATURE_DIRECTORY_TYPE == 0 {
+			return None;
+		}
+		match self.file_type {
+			TYPE_INDICATOR_REGULAR => Some(FileType::Regular),
+			TYPE_INDICATOR_DIRECTORY => Some(FileType::Directory),
+			TYPE_INDICATOR_CHAR_DEVICE => Some(FileType::CharDevice),
+			TYPE_INDICATOR_BLOCK_DEVICE => Some(FileType::BlockDevice),
+			TYPE_INDICATOR_FIFO => Some(FileType::Fifo),
+			TYPE_INDICATOR_SOCKET => Some(FileType::Socket),
+			TYPE_INDICATOR_SYMLINK => Some(FileType::Link),
+			_ => None,
+		}
+	}
+
 	/// Returns the file type associated with the entry.
 	///
 	/// If the type cannot be retrieved from the entry directly, the function retrieves it from the
 	/// inode.
-	pub fn get_type(&self, superblock: &Superblock, io: &dyn DeviceIO) -> EResult<FileType> {
-		let ent_type =
-			if superblock.s_feature_incompat & super::REQUIRED_FEATURE_DIRECTORY_TYPE == 0 {
-				match self.file_type {
-					TYPE_INDICATOR_REGULAR => Some(FileType::Regular),
-					TYPE_INDICATOR_DIRECTORY => Some(FileType::Directory),
-					TYPE_INDICATOR_CHAR_DEVICE => Some(FileType::CharDevice),
-					TYPE_INDICATOR_BLOCK_DEVICE => Some(FileType::BlockDevice),
-					TYPE_INDICATOR_FIFO => Some(FileType::Fifo),
-					TYPE_INDICATOR_SOCKET => Some(FileType::Socket),
-					TYPE_INDICATOR_SYMLINK => Some(FileType::Link),
-					_ => None,
-				}
-			} else {
-				None
-			};
-		// If the type could not be retrieved from the entry itself, get it from the inode
-		match ent_type {
+	pub fn resolve_type(&self, superblock: &Superblock, io: &dyn DeviceIO) -> EResult<FileType> {
+		match self.get_type(superblock) {
 			Some(t) => Ok(t),
 			None => Ok(Ext2INode::read(self.inode as _, superblock, io)?.get_type()),
 		}
@@ -0,0 +1,979 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Directories with many entries are indexed with a hash tree, so that looking up an entry does
+//! not require scanning the whole directory.
+//!
+//! The leaves of the tree are regular blocks of directory entries, so that an indexed directory
+//! remains readable by implementations that do not support indexes. The root of the tree is
+//! stored in the first block, behind the `..` entry, and internal nodes are stored in blocks
+//! hidden behind a free entry covering them entirely.
+//!
+//! Entries are sorted across leaves according to the hash of their name: each entry of an index
+//! node refers to the block containing the entries whose hash is greater or equal to its own.
+
+use super::{
+	dirent::Dirent,
+	inode::{fill_free_entries, find_slot, insert_in_block, Ext2INode},
+	read_block, write_block, Superblock,
+};
+use crate::{device::DeviceIO, file::FileType};
+use core::{
+	cmp::min,
+	mem::{offset_of, size_of},
+	num::NonZeroU32,
+};
+use macros::AnyRepr;
+use utils::{bytes, collections::vec::Vec, errno, errno::EResult, vec};
+
+/// Hash algorithm: legacy
+const HASH_LEGACY: u8 = 0;
+/// Hash algorithm: half MD4
+const HASH_HALF_MD4: u8 = 1;
+/// Hash algorithm: TEA
+const HASH_TEA: u8 = 2;
+
+/// `s_flags`: Names are hashed as unsigned characters
+const FLAG_UNSIGNED_HASH: u32 = 0x2;
+
+/// The seed used for hashes if the superblock does not specify any.
+const DEFAULT_SEED: [u32; 4] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476];
+/// Hash value reserved to mark the end of a directory.
+const HASH_EOF: u32 = 0x7fffffff << 1;
+
+/// The offset of the index information in the root block.
+const ROOT_INFO_OFF: usize = 24;
+/// The offset of the entries in the root block.
+const ROOT_ENTRIES_OFF: usize = ROOT_INFO_OFF + size_of::<RootInfo>();
+/// The offset of the entries in an internal node's block.
+const NODE_ENTRIES_OFF: usize = 8;
+/// The maximum number of levels of internal nodes below the root.
+const MAX_INDIRECT_LEVELS: u8 = 1;
+
+/// Information about the index, stored in the root block.
+#[repr(C)]
+#[derive(AnyRepr, Clone)]
+struct RootInfo {
+	/// Reserved, must be zero.
+	reserved_zero: u32,
+	/// The hash algorithm.
+	hash_version: u8,
+	/// The size of this structure in bytes.
+	info_length: u8,
+	/// The number of levels of internal nodes below the root.
+	indirect_levels: u8,
+	/// Flags.
+	unused_flags: u8,
+}
+
+/// An entry of an index node.
+///
+/// The hash of the first entry of a node is implicit. Instead, the field holds the maximum
+/// number of entries in the node on its lower 16 bits, and the current number of entries on its
+/// higher 16 bits.
+#[repr(C)]
+#[derive(AnyRepr, Clone, Copy)]
+struct IndexEntry {
+	/// The lowest hash in the referenced block. If the lowest bit is set, the entries with the
+	/// same hash start at the end of the previous block.
+	hash: u32,
+	/// The referenced block, as an offset in the directory.
+	block: u32,
+}
+
+/// Converts a part of `name` into the input of a hash transform.
+///
+/// `name` is the remaining part of the name to hash. Its length is used as padding.
+fn str_to_hashbuf(name: &[u8], unsigned: bool, buf: &mut [u32]) {
+	let len = name.len() as u32;
+	let mut pad = len | (len << 8);
+	pad |= pad << 16;
+	let name = &name[..min(name.len(), buf.len() * 4)];
+	let mut words = buf.iter_mut();
+	let mut val = pad;
+	for (i, c) in name.iter().enumerate() {
+		let c = if unsigned { *c as u32 } else { *c as i8 as u32 };
+		val = c.wrapping_add(val << 8);
+		if i % 4 == 3 {
+			*words.next().unwrap() = val;
+			val = pad;
+		}
+	}
+	if let Some(w) = words.next() {
+		*w = val;
+	}
+	words.for_each(|w| *w = pad);
+}
+
+/// The legacy hash algorithm.
+fn legacy_hash(name: &[u8], unsigned: bool) -> u32 {
+	let mut hash0: u32 = 0x12a3fe2d;
+	let mut hash1: u32 = 0x37abe8f9;
+	for c in name {
+		let c = if unsigned { *c as i32 } else { *c as i8 as i32 };
+		let mut hash = hash1.wrapping_add(hash0 ^ c.wrapping_mul(7152373) as u32);
+		if hash & 0x80000000 != 0 {
+			hash = hash.wrapping_sub(0x7fffffff);
+		}
+		hash1 = hash0;
+		hash0 = hash;
+	}
+	hash0 << 1
+}
+
+/// Transforms `buf` with the half MD4 algorithm, using `input`.
+fn half_md4_transform(buf: &mut [u32; 4], input: &[u32; 8]) {
+	const K2: u32 = 0o13240474631;
+	const K3: u32 = 0o15666365641;
+	let f = |x: u32, y: u32, z: u32| z ^ (x & (y ^ z));
+	let g = |x: u32, y: u32, z: u32| (x & y).wrapping_add((x ^ y) & z);
+	let h = |x: u32, y: u32, z: u32| x ^ y ^ z;
+	let [mut a, mut b, mut c, mut d] = *buf;
+	macro_rules! round {
+		($f:ident, $a:ident, $b:ident, $c:ident, $d:ident, $x:expr, $s:expr) => {
+			$a = $a
+				.wrapping_add($f($b, $c, $d))
+				.wrapping_add($x)
+				.rotate_left($s);
+		};
+	}
+	// Round 1
+	round!(f, a, b, c, d, input[0], 3);
+	round!(f, d, a, b, c, input[1], 7);
+	round!(f, c, d, a, b, input[2], 11);
+	round!(f, b, c, d, a, input[3], 19);
+	round!(f, a, b, c, d, input[4], 3);
+	round!(f, d, a, b, c, input[5], 7);
+	round!(f, c, d, a, b, input[6], 11);
+	round!(f, b, c, d, a, input[7], 19);
+	// Round 2
+	round!(g, a, b, c, d, input[1].wrapping_add(K2), 3);
+	round!(g, d, a, b, c, input[3].wrapping_add(K2), 5);
+	round!(g, c, d, a, b, input[5].wrapping_add(K2), 9);
+	round!(g, b, c, d, a, input[7].wrapping_add(K2), 13);
+	round!(g, a, b, c, d, input[0].wrapping_add(K2), 3);
+	round!(g, d, a, b, c, input[2].wrapping_add(K2), 5);
+	round!(g, c, d, a, b, input[4].wrapping_add(K2), 9);
+	round!(g, b, c, d, a, input[6].wrapping_add(K2), 13);
+	// Round 3
+	round!(h, a, b, c, d, input[3].wrapping_add(K3), 3);
+	round!(h, d, a, b, c, input[7].wrapping_add(K3), 9);
+	round!(h, c, d, a, b, input[2].wrapping_add(K3), 11);
+	round!(h, b, c, d, a, input[6].wrapping_add(K3), 15);
+	round!(h, a, b, c, d, input[1].wrapping_add(K3), 3);
+	round!(h, d, a, b, c, input[5].wrapping_add(K3), 9);
+	round!(h, c, d, a, b, input[0].wrapping_add(K3), 11);
+	round!(h, b, c, d, a, input[4].wrapping_add(K3), 15);
+	buf[0] = buf[0].wrapping_add(a);
+	buf[1] = buf[1].wrapping_add(b);
+	buf[2] = buf[2].wrapping_add(c);
+	buf[3] = buf[3].wrapping_add(d);
+}
+
+/// Transforms `buf` with the TEA algorithm, using `input`.
+fn tea_transform(buf: &mut [u32; 4], input: &[u32; 4]) {
+	const DELTA: u32 = 0x9e3779b9;
+	let [mut b0, mut b1, ..] = *buf;
+	let [a, b, c, d] = *input;
+	let mut sum: u32 = 0;
+	for _ in 0..16 {
+		sum = sum.wrapping_add(DELTA);
+		b0 = b0.wrapping_add(
+			(b1 << 4).wrapping_add(a) ^ b1.wrapping_add(sum) ^ (b1 >> 5).wrapping_add(b),
+		);
+		b1 = b1.wrapping_add(
+			(b0 << 4).wrapping_add(c) ^ b0.wrapping_add(sum) ^ (b0 >> 5).wrapping_add(d),
+		);
+	}
+	buf[0] = buf[0].wrapping_add(b0);
+	buf[1] = buf[1].wrapping_add(b1);
+}
+
+/// Returns the entries of the index node stored in `buf` at the offset `off`.
+fn node_entries(buf: &mut [u8], off: usize) -> &mut [IndexEntry] {
+	bytes::slice_from_bytes_mut(&mut buf[off..]).unwrap()
+}
+
+/// Returns the number of entries and the maximum number of entries of the given node.
+fn count_limit(entries: &[IndexEntry]) -> (usize, usize) {
+	let val = entries[0].hash;
+	((val >> 16) as usize, (val & 0xffff) as usize)
+}
+
+/// Sets the number of entries and the maximum number of entries of the given node.
+fn set_count_limit(entries: &mut [IndexEntry], count: usize, limit: usize) {
+	entries[0].hash = ((count as u32) << 16) | (limit as u32 & 0xffff);
+}
+
+/// Returns the maximum number of entries of a node whose entries start at the offset `off` in a
+/// block of `blk_size` bytes.
+fn node_limit(blk_size: usize, off: usize) -> usize {
+	(blk_size - off) / size_of::<IndexEntry>()
+}
+
+/// Inserts an entry after the one at index `index` in the given node, which must not be full.
+fn insert_index_entry(entries: &mut [IndexEntry], index: usize, hash: u32, block: u32) {
+	let (count, limit) = count_limit(entries);
+	entries.copy_within((index + 1)..count, index + 2);
+	entries[index + 1] = IndexEntry {
+		hash,
+		block,
+	};
+	set_count_limit(entries, count + 1, limit);
+}
+
+/// Initializes `buf` as the block of an empty internal node.
+fn init_node_block(buf: &mut [u8], superblock: &Superblock) -> EResult<()> {
+	buf.fill(0);
+	// Hide the node behind a free entry
+	Dirent::write_new(buf, superblock, 0, buf.len() as _, None, b"")
+}
+
+/// Returns the number of blocks of the directory `inode`.
+fn dir_blocks(inode: &Ext2INode, superblock: &Superblock) -> u32 {
+	(inode.get_size(superblock) / superblock.get_block_size() as u64) as u32
+}
+
+/// Reads the block at offset `blk` in the directory `inode` into `buf`.
+///
+/// On success, the function returns the address of the block on the storage device.
+///
+/// If the block is outside the directory or not allocated, the function returns [`EUCLEAN`].
+fn read_dir_block(
+	inode: &Ext2INode,
+	blk: u32,
+	superblock: &Superblock,
+	io: &dyn DeviceIO,
+	buf: &mut [u8],
+) -> EResult<NonZeroU32> {
+	if blk >= dir_blocks(inode, superblock) {
+		return Err(errno!(EUCLEAN));
+	}
+	let disk_blk = inode
+		.translate_blk_off(blk, superblock, io)?
+		.ok_or_else(|| errno!(EUCLEAN))?;
+	read_block(disk_blk.get() as _, superblock.get_block_size(), io, buf)?;
+	Ok(disk_blk)
+}
+
+/// Appends a block to the directory `inode`.
+///
+/// The content of the block is **not** initialized.
+///
+/// On success, the function returns the offset of the block in the directory and its address on
+/// the storage device.
+fn append_block(
+	inode: &mut Ext2INode,
+	superblock: &mut Superblock,
+	io: &dyn DeviceIO,
+) -> EResult<(u32, NonZeroU32)> {
+	let blk_size = superblock.get_block_size();
+	let blk = dir_blocks(inode, superblock);
+	let disk_blk = inode.alloc_content_blk(blk, superblock, io)?;
+	inode.set_size(superblock, (blk as u64 + 1) * blk_size as u64);
+	Ok((blk, disk_blk))
+}
+
+/// Writes the entries of `src`, located at the offsets `ents`, contiguously into the block
+/// `dst`.
+///
+/// The last entry covers the remaining space in the block.
+fn write_entries(
+	dst: &mut [u8],
+	src: &mut [u8],
+	ents: &[(u32, usize)],
+	superblock: &Superblock,
+) -> EResult<()> {
+	if ents.is_empty() {
+		return fill_free_entries(dst, superblock);
+	}
+	let mut off = 0;
+	for (i, (_, src_off)) in ents.iter().enumerate() {
+		let ent = Dirent::from_slice(&mut src[*src_off..], superblock)?;
+		let rec_len = if i + 1 < ents.len() {
+			ent.used_space(superblock) as usize
+		} else {
+			dst.len() - off
+		};
+		Dirent::write_new(
+			&mut dst[off..],
+			superblock,
+			ent.inode,
+			rec_len as _,
+			ent.get_type(superblock),
+			ent.get_name(superblock),
+		)?;
+		off += rec_len;
+	}
+	Ok(())
+}
+
+/// A level of the path from the root of the index to a leaf.
+#[derive(Clone, Copy, Default)]
+struct Frame {
+	/// The offset of the node's block in the directory.
+	blk: u32,
+	/// The offset of the node's entries in its block.
+	entries_off: usize,
+	/// The index of the followed entry in the node.
+	index: usize,
+}
+
+/// The path from the root of the index to a leaf.
+#[derive(Default)]
+struct Path {
+	/// The nodes that have been traversed, starting from the root.
+	frames: [Frame; MAX_INDIRECT_LEVELS as usize + 1],
+	/// The number of nodes that have been traversed.
+	depth: usize,
+	/// The offset of the leaf in the directory.
+	leaf: u32,
+}
+
+impl Path {
+	/// Returns the deepest node of the path.
+	fn bottom(&self) -> &Frame {
+		&self.frames[self.depth - 1]
+	}
+}
+
+/// Checks the block `blk` referenced by an index node is valid for the directory `inode`.
+fn check_child(inode: &Ext2INode, blk: u32, superblock: &Superblock) -> EResult<u32> {
+	// The first block holds the root
+	if blk == 0 || blk >= dir_blocks(inode, superblock) {
+		return Err(errno!(EUCLEAN));
+	}
+	Ok(blk)
+}
+
+/// The hash tree index of a directory.
+pub struct Index {
+	/// The hash algorithm.
+	hash_version: u8,
+	/// Tells whether names are hashed as unsigned characters.
+	unsigned: bool,
+	/// The hash seed.
+	seed: [u32; 4],
+}
+
+impl Index {
+	/// Returns the index using the hash algorithm `hash_version`.
+	///
+	/// If the algorithm is not supported, the function returns `None`.
+	fn new(hash_version: u8, superblock: &Superblock) -> Option<Self> {
+		if hash_version > HASH_TEA {
+			return None;
+		}
+		let seed = superblock.s_hash_seed;
+		Some(Self {
+			hash_version,
+			unsigned: superblock.s_flags & FLAG_UNSIGNED_HASH != 0,
+			seed: if seed.iter().any(|s| *s != 0) {
+				seed
+			} else {
+				DEFAULT_SEED
+			},
+		})
+	}
+
+	/// Reads the index of the directory `inode`, which must be indexed.
+	///
+	/// On success, `buf` contains the root block of the index.
+	///
+	/// If the index uses features that are not supported, the function returns `None`.
+	pub fn read(
+		inode: &Ext2INode,
+		superblock: &Superblock,
+		io: &dyn DeviceIO,
+		buf: &mut [u8],
+	) -> EResult<Option<Self>> {
+		read_dir_block(inode, 0, superblock, io, buf)?;
+		let info: &RootInfo = bytes::from_bytes(&buf[ROOT_INFO_OFF..]).unwrap();
+		if info.info_length as usize != size_of::<RootInfo>()
+			|| info.indirect_levels > MAX_INDIRECT_LEVELS
+			|| info.unused_flags != 0
+		{
+			return Ok(None);
+		}
+		Ok(Self::new(info.hash_version, superblock))
+	}
+
+	/// Indexes the directory `inode`, which must be made of a single block.
+	///
+	/// The entries of the block are moved to a new leaf, and the block becomes the root of the
+	/// index. It is the caller's responsibility to flag the inode as indexed.
+	///
+	/// If the directory cannot be indexed, the function returns `None`.
+	pub fn create(
+		inode: &mut Ext2INode,
+		superblock: &mut Superblock,
+		io: &dyn DeviceIO,
+	) -> EResult<Option<Self>> {
+		let Some(index) = Self::new(superblock.s_def_hash_version, superblock) else {
+			return Ok(None);
+		};
+		let blk_size = superblock.get_block_size() as usize;
+		let mut buf = vec![0; blk_size]?;
+		let root_disk_blk = read_dir_block(inode, 0, superblock, io, &mut buf)?;
+		// The root must be hidden behind the `.` and `..` entries
+		let dot = Dirent::from_slice(&mut buf, superblock)?;
+		if dot.is_free() || dot.get_name(superblock) != b"." || dot.rec_len != 12 {
+			return Ok(None);
+		}
+		let dot_inode = dot.inode;
+		let dotdot = Dirent::from_slice(&mut buf[12..], superblock)?;
+		if dotdot.is_free() || dotdot.get_name(superblock) != b".." {
+			return Ok(None);
+		}
+		let dotdot_inode = dotdot.inode;
+		let mut off = 12 + dotdot.rec_len as usize;
+		// Move the other entries to a new leaf
+		let mut ents = Vec::new();
+		while off < blk_size {
+			let ent = Dirent::from_slice(&mut buf[off..], superblock)?;
+			if !ent.is_free() {
+				ents.push((0, off))?;
+			}
+			off += ent.rec_len as usize;
+		}
+		let mut leaf = vec![0; blk_size]?;
+		write_entries(&mut leaf, &mut buf, &ents, superblock)?;
+		let (leaf_blk, leaf_disk_blk) = append_block(inode, superblock, io)?;
+		write_block(leaf_disk_blk.get() as _, blk_size as _, io, &leaf)?;
+		// Write the root
+		buf.fill(0);
+		let dir = Some(FileType::Directory);
+		Dirent::write_new(&mut buf, superblock, dot_inode, 12, dir, b".")?;
+		let rec_len = (blk_size - 12) as _;
+		Dirent::write_new(
+			&mut buf[12..],
+			superblock,
+			dotdot_inode,
+			rec_len,
+			dir,
+			b"..",
+		)?;
+		let info = RootInfo {
+			reserved_zero: 0,
+			hash_version: index.hash_version,
+			info_length: size_of::<RootInfo>() as _,
+			indirect_levels: 0,
+			unused_flags: 0,
+		};
+		buf[ROOT_INFO_OFF..ROOT_ENTRIES_OFF].copy_from_slice(bytes::as_bytes(&info));
+		let entries = node_entries(&mut buf, ROOT_ENTRIES_OFF);
+		set_count_limit(entries, 1, node_limit(blk_size, ROOT_ENTRIES_OFF));
+		entries[0].block = leaf_blk;
+		write_block(root_disk_blk.get() as _, blk_size as _, io, &buf)?;
+		Ok(Some(index))
+	}
+
+	/// Returns the hash of the given name.
+	fn hash(&self, name: &[u8]) -> u32 {
+		let mut buf = self.seed;
+		let hash = match self.hash_version {
+			HASH_LEGACY => legacy_hash(name, self.unsigned),
+			HASH_HALF_MD4 => {
+				let mut input = [0; 8];
+				for off in (0..name.len()).step_by(32) {
+					str_to_hashbuf(&name[off..], self.unsigned, &mut input);
+					half_md4_transform(&mut buf, &input);
+				}
+				buf[1]
+			}
+			HASH_TEA => {
+				let mut input = [0; 4];
+				for off in (0..name.len()).step_by(16) {
+					str_to_hashbuf(&name[off..], self.unsigned, &mut input);
+					tea_transform(&mut buf, &input);
+				}
+				buf[0]
+			}
+			_ => unreachable!(),
+		};
+		// The lowest bit is reserved to mark collisions
+		let hash = hash & !1;
+		if hash == HASH_EOF {
+			HASH_EOF - 2
+		} else {
+			hash
+		}
+	}
+
+	/// Walks the index of the directory `inode` from the root, which is stored in `buf`, to the
+	/// leaf that may contain entries with the given hash.
+	fn probe(
+		&self,
+		inode: &Ext2INode,
+		hash: u32,
+		superblock: &Superblock,
+		io: &dyn DeviceIO,
+		buf: &mut [u8],
+	) -> EResult<Path> {
+		let info: &RootInfo = bytes::from_bytes(&buf[ROOT_INFO_OFF..]).unwrap();
+		let levels = info.indirect_levels as usize;
+		if levels > MAX_INDIRECT_LEVELS as usize {
+			return Err(errno!(EUCLEAN));
+		}
+		let blk_size = buf.len();
+		let mut path = Path::default();
+		let mut frame = Frame {
+			blk: 0,
+			entries_off: ROOT_ENTRIES_OFF,
+			index: 0,
+		};
+		loop {
+			let entries = node_entries(buf, frame.entries_off);
+			let (count, limit) = count_limit(entries);
+			if count == 0 || count > limit || limit != node_limit(blk_size, frame.entries_off) {
+				return Err(errno!(EUCLEAN));
+			}
+			// The hash of the first entry is implicit: it covers the lowest hashes
+			frame.index = entries[1..count].partition_point(|e| e.hash <= hash);
+			let child = check_child(inode, entries[frame.index].block, superblock)?;
+			path.frames[path.depth] = frame;
+			path.depth += 1;
+			if path.depth > levels {
+				path.leaf = child;
+				return Ok(path);
+			}
+			read_dir_block(inode, child, superblock, io, buf)?;
+			frame = Frame {
+				blk: child,
+				entries_off: NODE_ENTRIES_OFF,
+				index: 0,
+			};
+		}
+	}
+
+	/// Moves `path` to the next leaf, if it may contain entries with the given hash.
+	///
+	/// If no leaf remain or if the next one cannot contain the hash, the function returns
+	/// `false`.
+	fn next_leaf(
+		&self,
+		inode: &Ext2INode,
+		path: &mut Path,
+		hash: u32,
+		superblock: &Superblock,
+		io: &dyn DeviceIO,
+		buf: &mut [u8],
+	) -> EResult<bool> {
+		// Find the deepest node with an entry after the followed one
+		let mut depth = path.depth;
+		let mut child = loop {
+			let Some(d) = depth.checked_sub(1) else {
+				return Ok(false);
+			};
+			depth = d;
+			let frame = &mut path.frames[depth];
+			read_dir_block(inode, frame.blk, superblock, io, buf)?;
+			let entries = node_entries(buf, frame.entries_off);
+			let (count, _) = count_limit(entries);
+			if frame.index + 1 < count {
+				frame.index += 1;
+				let ent = entries[frame.index];
+				// Entries with the same hash may continue on the next block only if the next
+				// block starts with the same hash
+				if ent.hash & !1 != hash {
+					return Ok(false);
+				}
+				break ent.block;
+			}
+		};
+		// Walk down to the leaf
+		for frame in &mut path.frames[(depth + 1)..path.depth] {
+			let blk = check_child(inode, child, superblock)?;
+			read_dir_block(inode, blk, superblock, io, buf)?;
+			*frame = Frame {
+				blk,
+				entries_off: NODE_ENTRIES_OFF,
+				index: 0,
+			};
+			child = node_entries(buf, NODE_ENTRIES_OFF)[0].block;
+		}
+		path.leaf = check_child(inode, child, superblock)?;
+		Ok(true)
+	}
+
+	/// Looks for the entry with the given name in the directory `inode`.
+	///
+	/// `buf` must contain the root block of the index, as returned by [`Self::read`].
+	///
+	/// The function returns the same values as [`Ext2INode::get_dirent`].
+	pub fn lookup(
+		&self,
+		inode: &Ext2INode,
+		name: &[u8],
+		superblock: &Superblock,
+		io: &dyn DeviceIO,
+		buf: &mut [u8],
+	) -> EResult<Option<(u32, FileType, u64)>> {
+		let blk_size = buf.len() as u64;
+		let hash = self.hash(name);
+		let mut path = self.probe(inode, hash, superblock, io, buf)?;
+		loop {
+			read_dir_block(inode, path.leaf, superblock, io, buf)?;
+			let mut off = 0;
+			while off < buf.len() {
+				let ent = Dirent::from_slice(&mut buf[off..], superblock)?;
+				if !ent.is_free() && ent.get_name(superblock) == name {
+					let off = path.leaf as u64 * blk_size + off as u64;
+					return Ok(Some((ent.inode, ent.resolve_type(superblock, io)?, off)));
+				}
+				off += ent.rec_len as usize;
+			}
+			if !self.next_leaf(inode, &mut path, hash, superblock, io, buf)? {
+				return Ok(None);
+			}
+		}
+	}
+
+	/// Adds an entry to the directory `inode`.
+	///
+	/// Arguments are the same as [`Ext2INode::add_dirent`], with `rec_len` the size of the new
+	/// entry.
+	///
+	/// If the index is full, the function returns [`ENOSPC`].
+	#[allow(clippy::too_many_arguments)]
+	pub fn add(
+		&self,
+		inode: &mut Ext2INode,
+		superblock: &mut Superblock,
+		io: &dyn DeviceIO,
+		entry_inode: u32,
+		name: &[u8],
+		file_type: FileType,
+		rec_len: u16,
+	) -> EResult<()> {
+		let blk_size = superblock.get_block_size();
+		let hash = self.hash(name);
+		let mut buf = vec![0; blk_size as _]?;
+		// Each iteration either inserts the entry or splits a full block
+		loop {
+			read_dir_block(inode, 0, superblock, io, &mut buf)?;
+			let path = self.probe(inode, hash, superblock, io, &mut buf)?;
+			// If the leaf has room, insert
+			let leaf_disk_blk = read_dir_block(inode, path.leaf, superblock, io, &mut buf)?;
+			if let Some(slot) = find_slot(&mut buf, superblock, rec_len)? {
+				insert_in_block(
+					&mut buf,
+					superblock,
+					slot,
+					entry_inode,
+					rec_len,
+					name,
+					file_type,
+				)?;
+				return write_block(leaf_disk_blk.get() as _, blk_size, io, &buf);
+			}
+			// Make room in the index to reference a new leaf, or split the leaf
+			let bottom = *path.bottom();
+			read_dir_block(inode, bottom.blk, superblock, io, &mut buf)?;
+			let (count, limit) = count_limit(node_entries(&mut buf, bottom.entries_off));
+			if count < limit {
+				self.split_leaf(inode, &path, superblock, io)?;
+			} else {
+				self.split_node(inode, &path, superblock, io)?;
+			}
+		}
+	}
+
+	/// Splits the leaf of `path` in two, moving the entries with the highest hashes to a new
+	/// leaf.
+	///
+	/// The bottom node of `path` must not be full.
+	fn split_leaf(
+		&self,
+		inode: &mut Ext2INode,
+		path: &Path,
+		superblock: &mut Superblock,
+		io: &dyn DeviceIO,
+	) -> EResult<()> {
+		let blk_size = superblock.get_block_size() as usize;
+		let mut buf = vec![0; blk_size]?;
+		let leaf_disk_blk = read_dir_block(inode, path.leaf, superblock, io, &mut buf)?;
+		// Sort the entries by hash
+		let mut ents = Vec::new();
+		let mut off = 0;
+		while off < blk_size {
+			let ent = Dirent::from_slice(&mut buf[off..], superblock)?;
+			if !ent.is_free() {
+				ents.push((self.hash(ent.get_name(superblock)), off))?;
+			}
+			off += ent.rec_len as usize;
+		}
+		// A leaf with less than two entries cannot be full
+		if ents.len() < 2 {
+			return Err(errno!(EUCLEAN));
+		}
+		ents.sort_unstable_by_key(|(hash, _)| *hash);
+		// Move entries, starting from the highest hashes, until they fill half a block
+		let mut split = ents.len();
+		let mut moved_size = 0;
+		while split > 1 {
+			let ent = Dirent::from_slice(&mut buf[ents[split - 1].1..], superblock)?;
+			let size = ent.used_space(superblock) as usize;
+			if moved_size + size / 2 > blk_size / 2 {
+				break;
+			}
+			moved_size += size;
+			split -= 1;
+		}
+		let split_hash = ents[split].0;
+		// If entries with the same hash remain on the previous leaf, mark the collision
+		let continued = split_hash == ents[split - 1].0;
+		let mut low = vec![0; blk_size]?;
+		let mut high = vec![0; blk_size]?;
+		write_entries(&mut low, &mut buf, &ents[..split], superblock)?;
+		write_entries(&mut high, &mut buf, &ents[split..], superblock)?;
+		let (new_blk, new_disk_blk) = append_block(inode, superblock, io)?;
+		write_block(new_disk_blk.get() as _, blk_size as _, io, &high)?;
+		write_block(leaf_disk_blk.get() as _, blk_size as _, io, &low)?;
+		// Reference the new leaf
+		let bottom = path.bottom();
+		let node_disk_blk = read_dir_block(inode, bottom.blk, superblock, io, &mut buf)?;
+		let entries = node_entries(&mut buf, bottom.entries_off);
+		insert_index_entry(
+			entries,
+			bottom.index,
+			split_hash | continued as u32,
+			new_blk,
+		);
+		write_block(node_disk_blk.get() as _, blk_size as _, io, &buf)
+	}
+
+	/// Makes room in the bottom node of `path`, which is full.
+	///
+	/// If the bottom node is the root, its entries are moved to a new node below it. Else, the
+	/// node is split in two.
+	///
+	/// If the index cannot grow further, the function returns [`ENOSPC`].
+	fn split_node(
+		&self,
+		inode: &mut Ext2INode,
+		path: &Path,
+		superblock: &mut Superblock,
+		io: &dyn DeviceIO,
+	) -> EResult<()> {
+		let blk_size = superblock.get_block_size() as usize;
+		let mut root = vec![0; blk_size]?;
+		let root_disk_blk = read_dir_block(inode, 0, superblock, io, &mut root)?;
+		let root_entries = node_entries(&mut root, ROOT_ENTRIES_OFF);
+		let (root_count, root_limit) = count_limit(root_entries);
+		let mut node = vec![0; blk_size]?;
+		if path.depth == 1 {
+			// Move the entries of the root to a new node
+			init_node_block(&mut node, superblock)?;
+			let entries = node_entries(&mut node, NODE_ENTRIES_OFF);
+			entries[..root_count].copy_from_slice(&root_entries[..root_count]);
+			set_count_limit(entries, root_count, node_limit(blk_size, NODE_ENTRIES_OFF));
+			let (node_blk, node_disk_blk) = append_block(inode, superblock, io)?;
+			write_block(node_disk_blk.get() as _, blk_size as _, io, &node)?;
+			// Make the root point to the new node only
+			let root_entries = node_entries(&mut root, ROOT_ENTRIES_OFF);
+			set_count_limit(root_entries, 1, root_limit);
+			root_entries[0].block = node_blk;
+			root[ROOT_INFO_OFF + offset_of!(RootInfo, indirect_levels)] += 1;
+		} else {
+			if root_count >= root_limit {
+				return Err(errno!(ENOSPC));
+			}
+			// Move the upper half of the node's entries to a new node
+			let bottom = path.bottom();
+			let bottom_disk_blk = read_dir_block(inode, bottom.blk, superblock, io, &mut node)?;
+			let entries = node_entries(&mut node, NODE_ENTRIES_OFF);
+			let (count, limit) = count_limit(entries);
+			let split = count / 2;
+			let split_hash = entries[split].hash;
+			let mut new = vec![0; blk_size]?;
+			init_node_block(&mut new, superblock)?;
+			let new_entries = node_entries(&mut new, NODE_ENTRIES_OFF);
+			new_entries[..(count - split)].copy_from_slice(&entries[split..count]);
+			set_count_limit(new_entries, count - split, limit);
+			set_count_limit(entries, split, limit);
+			let (new_blk, new_disk_blk) = append_block(inode, superblock, io)?;
+			write_block(new_disk_blk.get() as _, blk_size as _, io, &new)?;
+			write_block(bottom_disk_blk.get() as _, blk_size as _, io, &node)?;
+			// Reference the new node
+			let root_entries = node_entries(&mut root, ROOT_ENTRIES_OFF);
+			insert_index_entry(root_entries, path.frames[0].index, split_hash, new_blk);
+		}
+		write_block(root_disk_blk.get() as _, blk_size as _, io, &root)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::{
+		super::test::{image, zeroed, TestDisk},
+		*,
+	};
+	use utils::format;
+
+	/// The inode of the test directory.
+	const DIR_INODE: u32 = 2;
+
+	/// Creates an empty directory on a test image, with the features required for indexing.
+	fn directory() -> (Superblock, TestDisk, Ext2INode) {
+		let (mut superblock, disk) = image();
+		superblock.s_feature_compat |= super::super::OPTIONAL_FEATURE_HASH_INDEX;
+		superblock.s_feature_incompat |= super::super::REQUIRED_FEATURE_DIRECTORY_TYPE;
+		superblock.s_def_hash_version = HASH_HALF_MD4;
+		let buf = zeroed(size_of::<Ext2INode>());
+		let mut dir = bytes::from_bytes::<Ext2INode>(&buf).cloned().unwrap();
+		dir.i_mode = FileType::Directory.to_mode() as u16 | 0o755;
+		let dir_type = FileType::Directory;
+		dir.add_dirent(&mut superblock, &disk, DIR_INODE, b".", dir_type)
+			.unwrap();
+		dir.add_dirent(&mut superblock, &disk, DIR_INODE, b"..", dir_type)
+			.unwrap();
+		(superblock, disk, dir)
+	}
+
+	/// Adds `count` regular files to the directory `dir`, starting from the `start`th.
+	fn add_files(
+		dir: &mut Ext2INode,
+		superblock: &mut Superblock,
+		disk: &TestDisk,
+		start: u32,
+		count: u32,
+	) {
+		for i in start..(start + count) {
+			let name = format!("file-with-a-long-name-{i:04}").unwrap();
+			dir.add_dirent(
+				superblock,
+				disk,
+				100 + i,
+				name.as_bytes(),
+				FileType::Regular,
+			)
+			.unwrap();
+		}
+	}
+
+	/// Returns the number of entries in the root of the index of the directory `dir`.
+	fn root_count(dir: &Ext2INode, superblock: &Superblock, disk: &TestDisk) -> usize {
+		let mut buf = zeroed(superblock.get_block_size() as _);
+		Index::read(dir, superblock, disk, &mut buf)
+			.unwrap()
+			.unwrap();
+		count_limit(node_entries(&mut buf, ROOT_ENTRIES_OFF)).0
+	}
+
+	fn index(hash_version: u8, unsigned: bool, seed: [u32; 4]) -> Index {
+		Index {
+			hash_version,
+			unsigned,
+			seed,
+		}
+	}
+
+	#[test_case]
+	fn hash_algorithms() {
+		let long = b"file-with-a-somewhat-longer-name-00000042";
+		let legacy = index(HASH_LEGACY, false, DEFAULT_SEED);
+		assert_eq!(legacy.hash(b"lost+found"), 0x5e2aba24);
+		assert_eq!(legacy.hash(long), 0xc724513e);
+		let half_md4 = index(HASH_HALF_MD4, false, DEFAULT_SEED);
+		assert_eq!(half_md4.hash(b"lost+found"), 0x591de422);
+		assert_eq!(half_md4.hash(long), 0x16f488da);
+		let tea = index(HASH_TEA, false, DEFAULT_SEED);
+		assert_eq!(tea.hash(b"lost+found"), 0x2dbf9e80);
+		assert_eq!(tea.hash(long), 0xcc34d072);
+	}
+
+	#[test_case]
+	fn hash_seed() {
+		let seed = [0xf43a1b60, 0xcd424b7e, 0x6667eeb3, 0x46f62d52];
+		assert_eq!(index(HASH_HALF_MD4, false, seed).hash(b"hello"), 0xb618f88e);
+	}
+
+	#[test_case]
+	fn hash_signedness() {
+		let name = "été".as_bytes();
+		assert_eq!(index(HASH_TEA, false, DEFAULT_SEED).hash(name), 0x04d337a6);
+		assert_ne!(index(HASH_TEA, true, DEFAULT_SEED).hash(name), 0x04d337a6);
+	}
+
+	#[test_case]
+	fn htree_create() {
+		let (mut superblock, disk, mut dir) = directory();
+		let blk_size = superblock.get_block_size() as u64;
+		// Fill the first block, the directory remains linear
+		let mut count = 0;
+		while dir.get_size(&superblock) == blk_size {
+			assert!(!dir.is_indexed(&superblock));
+			add_files(&mut dir, &mut superblock, &disk, count, 1);
+			count += 1;
+		}
+		// The entry which did not fit made the first block the root of an index with one leaf
+		assert!(dir.is_indexed(&superblock));
+		assert_eq!(dir.get_size(&superblock), 2 * blk_size);
+		assert_eq!(root_count(&dir, &superblock, &disk), 1);
+		let (inode, file_type, _) = dir.get_dirent(b"..", &superblock, &disk).unwrap().unwrap();
+		assert_eq!(inode, DIR_INODE);
+		assert_eq!(file_type, FileType::Directory);
+		for i in 0..count {
+			let name = format!("file-with-a-long-name-{i:04}").unwrap();
+			let ent = dir.get_dirent(name.as_bytes(), &superblock, &disk).unwrap();
+			assert_eq!(ent.map(|(inode, ..)| inode), Some(100 + i));
+		}
+	}
+
+	#[test_case]
+	fn htree_split_leaf() {
+		let (mut superblock, disk, mut dir) = directory();
+		add_files(&mut dir, &mut superblock, &disk, 0, 100);
+		assert!(dir.is_indexed(&superblock));
+		// The entries do not fit in a single leaf
+		let leaves = root_count(&dir, &superblock, &disk);
+		assert!(leaves > 1);
+		// Every block of the directory is either the root or a leaf
+		let blk_size = superblock.get_block_size() as u64;
+		assert_eq!(dir.get_size(&superblock), (1 + leaves as u64) * blk_size);
+	}
+
+	#[test_case]
+	fn htree_lookup_after_split() {
+		let (mut superblock, disk, mut dir) = directory();
+		add_files(&mut dir, &mut superblock, &disk, 0, 100);
+		let leaves = root_count(&dir, &superblock, &disk);
+		// Adding entries after splits goes to the leaf covering their hashes
+		add_files(&mut dir, &mut superblock, &disk, 100, 50);
+		assert!(root_count(&dir, &superblock, &disk) >= leaves);
+		for i in 0..150 {
+			let name = format!("file-with-a-long-name-{i:04}").unwrap();
+			let (inode, file_type, off) = dir
+				.get_dirent(name.as_bytes(), &superblock, &disk)
+				.unwrap()
+				.unwrap();
+			assert_eq!(inode, 100 + i);
+			assert_eq!(file_type, FileType::Regular);
+			// The offset points to the entry itself
+			let mut buf = zeroed(superblock.get_block_size() as _);
+			let blk_size = buf.len() as u64;
+			read_dir_block(&dir, (off / blk_size) as _, &superblock, &disk, &mut buf).unwrap();
+			let ent =
+				Dirent::from_slice(&mut buf[(off % blk_size) as usize..], &superblock).unwrap();
+			assert_eq!(ent.get_name(&superblock), name.as_bytes());
+		}
+		let missing = dir.get_dirent(b"missing", &superblock, &disk).unwrap();
+		assert!(missing.is_none());
+	}
+}
@@ -19,8 +19,8 @@
 //! An inode represents a file in the filesystem.
 
 use super::{
-	bgd::BlockGroupDescriptor, dirent, dirent::Dirent, read, read_block, write, write_block,
-	Superblock,
+	bgd::BlockGroupDescriptor, dirent, dirent::Dirent, htree, read, read_block, write,
+	write_block, Superblock,
 };
 use crate::{
 	device::DeviceIO,
@@ -31,6 +31,7 @@ use core::{
 	intrinsics::unlikely,
 	mem,
 	num::NonZeroU32,
+	ops::Range,
 };
 use macros::AnyRepr;
 use utils::{bytes, errno, errno::EResult, math, ptr::cow::Cow, vec};
@@ -101,7 +102,7 @@ const INODE_FLAG_NODUMP: u32 = 0x00040;
 /// `s_flags`: Last accessed time should not be updated
 const INODE_FLAG_ATIME_NOUPDATE: u32 = 0x00080;
 /// `s_flags`: Hash indexed directory
-const INODE_FLAG_HASH_INDEXED: u32 = 0x10000;
+const INODE_FLAG_HASH_INDEXED: u32 = 0x01000;
 /// `s_flags`: AFS directory
 const INODE_FLAG_AFS_DIRECTORY: u32 = 0x20000;
 /// `s_flags`: Journal file data
@@ -111,8 +112,8 @@ const INODE_FLAG_JOURNAL_FILE: u32 = 0x40000;
 const SECTOR_SIZE: u32 = 512;
 
 /// The maximum length for a symlink to be stored in the inode itself instead of a
-/// separate block.
-const SYMLINK_INLINE_LIMIT: u64 = 60;
+/// separate block, keeping room for a terminating nul byte as Linux does.
+const SYMLINK_INLINE_LIMIT: u64 = 59;
 
 /// The inode of the root directory.
 pub const ROOT_DIRECTORY_INODE: u32 = 2;
@@ -160,7 +161,7 @@ fn indirections_offsets(
 	if off < ent_per_blk * ent_per_blk * ent_per_blk {
 		offsets[0] = DIRECT_BLOCKS_COUNT + 2;
 		offsets[1] = (off >> (ent_per_blk_log * 2)) as _;
-		offsets[2] = ((off >> ent_per_blk_log) & (ent_per_blk_log - 1)) as _;
+		offsets[2] = ((off >> ent_per_blk_log) & (ent_per_blk - 1)) as _;
 		offsets[3] = (off & (ent_per_blk - 1)) as _;
 		return Ok(4);
 	}
@@ -170,7 +171,7 @@ fn indirections_offsets(
 /// Checks for an invalid block number.
 ///
 /// If the block number is zero, the function returns `None`.
-fn check_blk_off(blk: u32, superblock: &Superblock) -> EResult<Option<NonZeroU32>> {
+pub(super) fn check_blk_off(blk: u32, superblock: &Superblock) -> EResult<Option<NonZeroU32>> {
 	if unlikely(blk >= superblock.s_blocks_count) {
 		return Err(errno!(EUCLEAN));
 	}
@@ -179,18 +180,28 @@ fn check_blk_off(blk: u32, superblock: &Superblock) -> EResult<Option<NonZeroU32
 
 /// If no block is allocated at `blk`, allocate one.
 ///
-/// On success, the function returns `blk`.
+/// Arguments:
+/// - `blk` is the block number to allocate if zero
+/// - `sectors` is the number of sectors used by the inode, which is updated on allocation
+/// - `superblock` is the filesystem's superblock
+/// - `io` is the I/O interface
+///
+/// On success, the function returns `blk` along with a boolean telling whether it has just been
+/// allocated.
 fn ensure_allocated(
 	blk: &mut u32,
+	sectors: &mut u32,
 	superblock: &mut Superblock,
 	io: &dyn DeviceIO,
-) -> EResult<NonZeroU32> {
-	if *blk == 0 {
+) -> EResult<(NonZeroU32, bool)> {
+	let new = *blk == 0;
+	if new {
 		let new_blk = superblock.get_free_block(io)?;
 		superblock.mark_block_used(io, new_blk)?;
 		*blk = new_blk;
+		*sectors += superblock.get_block_size() / SECTOR_SIZE;
 	}
-	Ok(NonZeroU32::new(*blk).unwrap())
+	Ok((NonZeroU32::new(*blk).unwrap(), new))
 }
 
 /// Returns the next directory entry.
@@ -249,7 +260,7 @@ fn is_block_empty(buf: &mut [u8], superblock: &Superblock) -> EResult<bool> {
 /// [`dirent::ALIGN`].
 ///
 /// If an entry could not be created, the associated error is returned.
-fn fill_free_entries(buf: &mut [u8], superblock: &Superblock) -> EResult<()> {
+pub(super) fn fill_free_entries(buf: &mut [u8], superblock: &Superblock) -> EResult<()> {
 	const MIN: usize = dirent::NAME_OFF;
 	const MAX: usize = u16::MAX as usize;
 	const SPECIAL_CASE_END: usize = MAX + MIN;
@@ -274,6 +285,82 @@ fn fill_free_entries(buf: &mut [u8], superblock: &Superblock) -> EResult<()> {
 	Ok(())
 }
 
+/// Looks in the block `buf` for a slot large enough to fit an entry of `min_size` bytes.
+///
+/// A slot is either a used entry with enough unused space at its end, or a sequence of free
+/// entries.
+///
+/// On success, the function returns the range of the slot in the block.
+pub(super) fn find_slot(
+	buf: &mut [u8],
+	superblock: &Superblock,
+	min_size: u16,
+) -> EResult<Option<Range<usize>>> {
+	let mut off = 0;
+	let mut free_length = 0;
+	while off < buf.len() {
+		let ent = Dirent::from_slice(&mut buf[off..], superblock)?;
+		let rec_len = ent.rec_len as usize;
+		// If an entry is used but is able to fit the new entry, stop
+		if !ent.is_free() && ent.can_fit(min_size, superblock) {
+			return Ok(Some(off..(off + rec_len)));
+		}
+		if ent.is_free() {
+			free_length += rec_len;
+		} else {
+			free_length = 0;
+		}
+		off += rec_len;
+		// If a sequence large enough has been found, stop
+		if free_length >= min_size as usize {
+			return Ok(Some((off - free_length)..off));
+		}
+	}
+	Ok(None)
+}
+
+/// Writes a new entry in the block `buf`, in the slot returned by [`find_slot`].
+///
+/// Arguments:
+/// - `superblock` is the filesystem's superblock
+/// - `slot` is the range of the slot in the block
+/// - `entry_inode` is the inode of the entry
+/// - `rec_len` is the size of the entry
+/// - `name` is the name of the entry
+/// - `file_type` is the type of the entry
+pub(super) fn insert_in_block(
+	buf: &mut [u8],
+	superblock: &Superblock,
+	slot: Range<usize>,
+	entry_inode: u32,
+	mut rec_len: u16,
+	name: &[u8],
+	file_type: FileType,
+) -> EResult<()> {
+	let mut off = slot.start;
+	// If the entry is used, shrink it
+	let dirent = Dirent::from_slice(&mut buf[off..slot.end], superblock)?;
+	if !dirent.is_free() {
+		let used_space = dirent.used_space(superblock);
+		off += used_space as usize;
+		dirent.rec_len = used_space;
+	}
+	// If not enough space is left in the slot to fit another entry, use the remaining space
+	if off + rec_len as usize + dirent::NAME_OFF >= slot.end {
+		rec_len = (slot.end - off) as u16;
+	}
+	Dirent::write_new(
+		&mut buf[off..],
+		superblock,
+		entry_inode,
+		rec_len,
+		Some(file_type),
+		name,
+	)?;
+	// Create free entries to cover the remaining space in the slot
+	fill_free_entries(&mut buf[(off + rec_len as usize)..slot.end], superblock)
+}
+
 /// An inode represents a file in the filesystem.
 ///
 /// The name of the file is not included in the inode but in the directory entry associated with it
@@ -309,7 +396,7 @@ pub struct Ext2INode {
 	pub i_block: [u32; DIRECT_BLOCKS_COUNT + 3],
 	/// Generation number.
 	pub i_generation: u32,
-	/// The file's ACL.
+	/// The block containing the file's extended attributes.
 	pub i_file_acl: u32,
 	/// Higher 32 bits of size in bytes.
 	pub i_dir_acl: u32,
@@ -401,8 +488,9 @@ impl Ext2INode {
 	/// Arguments:
 	/// - `superblock` is the filesystem's superblock
 	/// - `size` is the file's size
-	/// - `inline` is `true` if the inode is a symlink storing the target inline
-	fn set_size(&mut self, superblock: &Superblock, size: u64, inline: bool) {
+	///
+	/// The number of used sectors is not updated, since it depends only on the allocated blocks.
+	pub(super) fn set_size(&mut self, superblock: &Superblock, size: u64) {
 		let has_version = superblock.s_rev_level >= 1;
 		let has_feature = superblock.s_feature_ro_compat & super::WRITE_REQUIRED_64_BITS != 0;
 		if has_version && has_feature {
@@ -411,19 +499,42 @@ impl Ext2INode {
 		} else {
 			self.i_size = size as u32;
 		}
-		if !inline {
-			let blk_size = superblock.get_block_size();
-			let sector_per_blk = blk_size / SECTOR_SIZE;
-			self.i_blocks = size.div_ceil(blk_size as _) as u32 * sector_per_blk;
-		} else {
-			self.i_blocks = 0;
-		}
 	}
 
-	/// Returns the number of content blocks.
+	/// Returns the number of blocks allocated for the content, including indirect blocks.
+	///
+	/// Since files may contain holes, this may be lower than the size of the file in blocks.
 	pub fn get_blocks(&self, superblock: &Superblock) -> u32 {
 		let sector_per_blk = superblock.get_block_size() / SECTOR_SIZE;
-		self.i_blocks.div_ceil(sector_per_blk)
+		let sectors = self.i_blocks.saturating_sub(self.xattr_sectors(superblock));
+		sectors.div_ceil(sector_per_blk)
+	}
+
+	/// Returns the number of sectors used by the extended attributes block, if any.
+	fn xattr_sectors(&self, superblock: &Superblock) -> u32 {
+		if self.i_file_acl != 0 {
+			superblock.get_block_size() / SECTOR_SIZE
+		} else {
+			0
+		}
+	}
+
+	/// Sets the extended attributes block to `blk`, updating the number of used sectors
+	/// accordingly.
+	///
+	/// If `blk` is zero, the inode has no extended attributes block.
+	pub(super) fn set_xattr_block(&mut self, superblock: &Superblock, blk: u32) {
+		self.i_blocks = self.i_blocks.saturating_sub(self.xattr_sectors(superblock));
+		self.i_file_acl = blk;
+		self.i_blocks += self.xattr_sectors(superblock);
+	}
+
+	/// Tells whether the inode is a symbolic link storing its target inline.
+	///
+	/// Like Linux, this is determined from the number of used sectors rather than the size, so
+	/// that links created by other implementations are read correctly.
+	fn is_fast_symlink(&self, superblock: &Superblock) -> bool {
+		self.get_type() == FileType::Link && self.get_blocks(superblock) == 0
 	}
 
 	/// Translates the given file block offset `off` to disk block offset.
@@ -434,7 +545,7 @@ impl Ext2INode {
 	/// - `io` is the I/O interface
 	///
 	/// If the block does not exist, the function returns `None`.
-	fn translate_blk_off(
+	pub(super) fn translate_blk_off(
 		&self,
 		off: u32,
 		superblock: &Superblock,
@@ -472,7 +583,7 @@ impl Ext2INode {
 	/// If a block is already allocated, the function does nothing.
 	///
 	/// On success, the function returns the allocated disk block offset.
-	fn alloc_content_blk(
+	pub(super) fn alloc_content_blk(
 		&mut self,
 		off: u32,
 		superblock: &mut Superblock,
@@ -481,17 +592,28 @@ impl Ext2INode {
 		let mut offsets: [usize; 4] = [0; 4];
 		let depth =
 			indirections_offsets(off, superblock.get_entries_per_block_log(), &mut offsets)?;
-		let mut blk = ensure_allocated(&mut self.i_block[offsets[0]], superblock, io)?;
+		let (mut blk, mut new) = ensure_allocated(
+			&mut self.i_block[offsets[0]],
+			&mut self.i_blocks,
+			superblock,
+			io,
+		)?;
 		// Perform indirections
 		let blk_size = superblock.get_block_size();
 		let mut buf = vec![0u8; blk_size as _]?;
 		for off in &offsets[1..depth] {
-			read_block(blk.get() as _, blk_size, io, &mut buf)?;
+			if new {
+				// The indirect block has just been allocated, so it may contain stale data
+				buf.fill(0);
+			} else {
+				read_block(blk.get() as _, blk_size, io, &mut buf)?;
+			}
 			let ents = bytes::slice_from_bytes_mut(&mut buf).unwrap();
-			let b = ensure_allocated(&mut ents[*off], superblock, io)?;
+			let (b, b_new) =
+				ensure_allocated(&mut ents[*off], &mut self.i_blocks, superblock, io)?;
 			// TODO avoided if unnecessary
 			write_block(blk.get() as _, blk_size, io, &buf)?;
-			blk = b;
+			(blk, new) = (b, b_new);
 		}
 		Ok(blk)
 	}
@@ -499,6 +621,7 @@ impl Ext2INode {
 	fn free_content_blk_impl(
 		blk: u32,
 		offsets: &[usize],
+		sectors: &mut u32,
 		superblock: &mut Superblock,
 		io: &dyn DeviceIO,
 	) -> EResult<bool> {
@@ -510,8 +633,12 @@ impl Ext2INode {
 		read_block(blk as _, blk_size, io, &mut buf)?;
 		let ents = bytes::slice_from_bytes_mut(&mut buf).unwrap();
 		let b = &mut ents[*off];
+		// If the child block is not allocated, there is nothing to free
+		if check_blk_off(*b, superblock)?.is_none() {
+			return Ok(false);
+		}
 		// Handle child block and determine whether the entry in the current block should be freed
-		let free = Self::free_content_blk_impl(*b, &offsets[1..], superblock, io)?;
+		let free = Self::free_content_blk_impl(*b, &offsets[1..], sectors, superblock, io)?;
 		if free {
 			let b = mem::take(b);
 			let empty = ents.iter().all(|b| *b == 0);
@@ -521,6 +648,7 @@ impl Ext2INode {
 			}
 			// If the block is empty, there is no point in saving it since it will be freed
 			superblock.free_block(io, b)?;
+			*sectors -= superblock.get_block_size() / SECTOR_SIZE;
 			Ok(empty)
 		} else {
 			Ok(false)
@@ -548,9 +676,11 @@ impl Ext2INode {
 		if check_blk_off(*blk, superblock)?.is_none() {
 			return Ok(());
 		}
-		if Self::free_content_blk_impl(*blk, &offsets[1..depth], superblock, io)? {
+		let sectors = &mut self.i_blocks;
+		if Self::free_content_blk_impl(*blk, &offsets[1..depth], sectors, superblock, io)? {
 			let blk = mem::take(blk);
 			superblock.free_block(io, blk)?;
+			*sectors -= superblock.get_block_size() / SECTOR_SIZE;
 		}
 		Ok(())
 	}
@@ -563,8 +693,8 @@ impl Ext2INode {
 	/// - `superblock` is the filesystem's superblock
 	/// - `io` is the I/O interface
 	///
-	/// The function returns the number of bytes that have been read and boolean
-	/// telling whether EOF is reached.
+	/// The function returns the number of bytes that have been read. If `off` is at or past the
+	/// end of the file, the function returns zero.
 	pub fn read_content(
 		&self,
 		off: u64,
@@ -573,8 +703,8 @@ impl Ext2INode {
 		io: &dyn DeviceIO,
 	) -> EResult<usize> {
 		let size = self.get_size(superblock);
-		if off > size {
-			return Err(errno!(EINVAL));
+		if off >= size {
+			return Ok(0);
 		}
 		let blk_size = superblock.get_block_size();
 		let mut blk_buff = vec![0u8; blk_size as _]?;
@@ -609,7 +739,11 @@ impl Ext2INode {
 	/// - `superblock` is the filesystem's superblock
 	/// - `io` is the I/O interface
 	///
-	/// The function returns the number of bytes that have been written.
+	/// If `off` is past the end of the file, no block is allocated for the gap, which creates a
+	/// hole reading as zeros. The end of the last block is always zeroed (see [`Self::truncate`]),
+	/// so that no previous data is exposed.
+	///
+	/// The new size is set only once the data is written.
 	pub fn write_content(
 		&mut self,
 		off: u64,
@@ -618,9 +752,9 @@ impl Ext2INode {
 		io: &dyn DeviceIO,
 	) -> EResult<()> {
 		let curr_size = self.get_size(superblock);
-		if off > curr_size {
-			return Err(errno!(EINVAL));
-		}
+		let end = off
+			.checked_add(buff.len() as u64)
+			.ok_or_else(|| errno!(EFBIG))?;
 		let blk_size = superblock.get_block_size();
 		let mut blk_buff = vec![0u8; blk_size as _]?;
 		let mut cur = 0;
@@ -648,8 +782,8 @@ impl Ext2INode {
 			cur += len;
 		}
 		// Update size
-		let new_size = max(off + buff.len() as u64, curr_size);
-		self.set_size(superblock, new_size, false);
+		let new_size = max(end, curr_size);
+		self.set_size(superblock, new_size);
 		Ok(())
 	}
 
@@ -660,8 +794,11 @@ impl Ext2INode {
 	/// - `io` is the I/O interface
 	/// - `size` is the new size of the inode's content
 	///
-	/// If `size` is greater than or equal to the previous size, the function
-	/// does nothing.
+	/// If `size` is smaller than the previous size, content blocks past the new size are freed,
+	/// including indirect blocks that become empty.
+	///
+	/// If `size` is greater than the previous size, the file is extended without allocating any
+	/// block, which creates a hole reading as zeros.
 	pub fn truncate(
 		&mut self,
 		superblock: &mut Superblock,
@@ -669,11 +806,13 @@ impl Ext2INode {
 		size: u64,
 	) -> EResult<()> {
 		let old_size = self.get_size(superblock);
-		if size >= old_size {
+		if size == old_size {
+			return Ok(());
+		}
+		if size > old_size {
+			self.set_size(superblock, size);
 			return Ok(());
 		}
-		// Change the size
-		self.set_size(superblock, size, false);
 		// The size of a block
 		let blk_size = superblock.get_block_size();
 		// The index of the beginning block to free
@@ -683,6 +822,90 @@ impl Ext2INode {
 		for i in begin..end {
 			self.free_content_blk(i, superblock, io)?;
 		}
+		// Zero the end of the last block so that extending the file again does not expose
+		// previous data
+		let inner_off = (size % blk_size as u64) as usize;
+		if inner_off > 0 {
+			let last = (size / blk_size as u64) as u32;
+			if let Some(blk) = self.translate_blk_off(last, superblock, io)? {
+				let mut buf = vec![0u8; blk_size as _]?;
+				read_block(blk.get() as _, blk_size, io, &mut buf)?;
+				buf[inner_off..].fill(0);
+				write_block(blk.get() as _, blk_size, io, &buf)?;
+			}
+		}
+		// Change the size
+		self.set_size(superblock, size);
+		Ok(())
+	}
+
+	/// Fills the content between the offsets `begin` and `end` with zeros.
+	///
+	/// Both offsets must be in the same block. If this block is not allocated, the function does
+	/// nothing.
+	fn zero_range(
+		&self,
+		begin: u64,
+		end: u64,
+		superblock: &Superblock,
+		io: &dyn DeviceIO,
+	) -> EResult<()> {
+		if begin >= end {
+			return Ok(());
+		}
+		let blk_size = superblock.get_block_size();
+		let Some(blk) = self.translate_blk_off((begin / blk_size as u64) as _, superblock, io)?
+		else {
+			return Ok(());
+		};
+		let mut buf = vec![0u8; blk_size as _]?;
+		read_block(blk.get() as _, blk_size, io, &mut buf)?;
+		let inner = (begin % blk_size as u64) as usize;
+		buf[inner..(inner + (end - begin) as usize)].fill(0);
+		write_block(blk.get() as _, blk_size, io, &buf)
+	}
+
+	/// Deallocates the content in the range of `len` bytes starting at offset `off`, which then
+	/// reads as zeros.
+	///
+	/// Arguments:
+	/// - `superblock` is the filesystem's superblock
+	/// - `io` is the I/O interface
+	/// - `off` is the beginning of the range
+	/// - `len` is the length of the range
+	///
+	/// Blocks entirely inside the range are freed, and the parts of the range in other blocks are
+	/// filled with zeros. The size of the file does not change.
+	pub fn punch_hole(
+		&mut self,
+		superblock: &mut Superblock,
+		io: &dyn DeviceIO,
+		off: u64,
+		len: u64,
+	) -> EResult<()> {
+		let size = self.get_size(superblock);
+		let end = min(off.saturating_add(len), size);
+		if off >= end {
+			return Ok(());
+		}
+		let blk_size = superblock.get_block_size() as u64;
+		// Free blocks covered entirely. The last block is covered if the range reaches the end of
+		// the file
+		let first = off.div_ceil(blk_size);
+		let last = if end == size {
+			end.div_ceil(blk_size)
+		} else {
+			end / blk_size
+		};
+		for blk in first..last {
+			self.free_content_blk(blk as _, superblock, io)?;
+		}
+		// Zero the parts of the range in the remaining blocks
+		let head_end = min(end, first * blk_size);
+		self.zero_range(off, head_end, superblock, io)?;
+		if head_end < end {
+			self.zero_range(max(head_end, last * blk_size), end, superblock, io)?;
+		}
 		Ok(())
 	}
 
@@ -722,12 +945,10 @@ impl Ext2INode {
 	/// - `io` is the I/O interface
 	pub fn free_content(&mut self, superblock: &mut Superblock, io: &dyn DeviceIO) -> EResult<()> {
 		// If the file is a link and its content is stored inline, there is nothing to do
-		if matches!(self.get_type(), FileType::Link)
-			&& self.get_size(superblock) <= SYMLINK_INLINE_LIMIT
-		{
+		if self.is_fast_symlink(superblock) {
 			return Ok(());
 		}
-		self.set_size(superblock, 0, false);
+		self.set_size(superblock, 0);
 		// TODO write inode
 		// Free blocks
 		for (off, blk) in self.i_block.iter().enumerate() {
@@ -741,6 +962,7 @@ impl Ext2INode {
 			superblock.free_block(io, blk.get())?;
 		}
 		self.i_block.fill(0);
+		self.i_blocks = self.xattr_sectors(superblock);
 		Ok(())
 	}
 
@@ -771,12 +993,17 @@ impl Ext2INode {
 		}
 		let blk_size = superblock.get_block_size();
 		let mut buf = vec![0; blk_size as _]?;
-		// TODO If the hash index is enabled, use it
+		// `.` and `..` are not indexed, but they are at the beginning of the first block
+		if self.is_indexed(superblock) && name != b"." && name != b".." {
+			if let Some(index) = htree::Index::read(self, superblock, io, &mut buf)? {
+				return index.lookup(self, name, superblock, io, &mut buf);
+			}
+		}
 		// Linear lookup
 		let mut off = 0;
 		while let Some(ent) = next_dirent(self, superblock, io, &mut buf, off)? {
 			if !ent.is_free() && ent.get_name(superblock) == name {
-				return Ok(Some((ent.inode, ent.get_type(superblock, io)?, off)));
+				return Ok(Some((ent.inode, ent.resolve_type(superblock, io)?, off)));
 			}
 			off += ent.rec_len as u64;
 		}
@@ -832,7 +1059,8 @@ impl Ext2INode {
 				break ent;
 			}
 		};
-		let entry_type = ent.get_type(superblock, io)?;
+		// Do not read the inode to get the type, as this would double the number of I/O operations
+		let entry_type = ent.get_type(superblock);
 		let name = ent.get_name(superblock).try_into()?;
 		let ent = DirEntry {
 			inode: ent.inode as _,
@@ -859,8 +1087,38 @@ impl Ext2INode {
 		Ok(true)
 	}
 
-	/// Looks for a sequence of free entries large enough to fit a chunk with at least `min_size`
-	/// bytes, and returns the offset to its beginning.
+	/// Returns the number of subdirectories in the directory, excluding `.` and `..`.
+	///
+	/// Arguments:
+	/// - `superblock` is the filesystem's superblock
+	/// - `io` is the I/O interface
+	#[cfg(debug_assertions)]
+	pub fn subdirectories_count(
+		&self,
+		superblock: &Superblock,
+		io: &dyn DeviceIO,
+	) -> EResult<usize> {
+		let blk_size = superblock.get_block_size() as u64;
+		let mut buf = vec![0; blk_size as _]?;
+		let mut off = 0;
+		let mut count = 0;
+		while let Some(ent) = next_dirent(self, superblock, io, &mut buf, off)? {
+			if !ent.is_free() {
+				let name = ent.get_name(superblock);
+				if name != b"."
+					&& name != b".."
+					&& ent.resolve_type(superblock, io)? == FileType::Directory
+				{
+					count += 1;
+				}
+			}
+			off += ent.rec_len as u64;
+		}
+		Ok(count)
+	}
+
+	/// Looks for a block with a slot large enough to fit an entry of `min_size` bytes (see
+	/// [`find_slot`]).
 	///
 	/// Arguments:
 	/// - `superblock` is the filesystem's superblock
@@ -868,41 +1126,38 @@ impl Ext2INode {
 	/// - `buf` is the block buffer
 	/// - `min_size` is the minimum size of the new entry in bytes
 	///
-	/// If no suitable sequence is found, the function returns `None`.
+	/// On success, the function returns the address of the block on the disk and the range of
+	/// the slot in the block. `buf` then contains the block.
+	///
+	/// If no suitable slot is found, the function returns `None`.
 	fn get_suitable_slot(
 		&self,
 		superblock: &Superblock,
 		io: &dyn DeviceIO,
 		buf: &mut [u8],
 		min_size: u16,
-	) -> EResult<Option<u64>> {
-		let blk_size = superblock.get_block_size() as u64;
-		let mut off = 0;
-		let mut free_length = 0;
-		while let Some(ent) = next_dirent(self, superblock, io, buf, off)? {
-			// If an entry is used but is able to fit the new entry, stop
-			if !ent.is_free() && ent.can_fit(min_size, superblock) {
-				return Ok(Some(off));
-			}
-			// If the entry is used or on the next block
-			let next = (off % blk_size + ent.rec_len as u64) > blk_size;
-			if !ent.is_free() || next {
-				// Reset counter
-				free_length = 0;
-			} else {
-				// Free entry, update counter
-				free_length += ent.rec_len as usize;
-			}
-			off += ent.rec_len as u64;
-			// If a sequence large enough has been found, stop
-			if free_length >= min_size as usize {
-				let begin = off - free_length as u64;
-				return Ok(Some(begin));
+	) -> EResult<Option<(NonZeroU32, Range<usize>)>> {
+		let blk_size = superblock.get_block_size();
+		let blocks = self.get_size(superblock).div_ceil(blk_size as _) as u32;
+		for blk in 0..blocks {
+			// If reaching a zero block, stop
+			let Some(disk_blk) = self.translate_blk_off(blk, superblock, io)? else {
+				break;
+			};
+			read_block(disk_blk.get() as _, blk_size, io, buf)?;
+			if let Some(slot) = find_slot(buf, superblock, min_size)? {
+				return Ok(Some((disk_blk, slot)));
 			}
 		}
 		Ok(None)
 	}
 
+	/// Tells whether the directory is indexed with a hash tree.
+	pub fn is_indexed(&self, superblock: &Superblock) -> bool {
+		superblock.s_feature_compat & super::OPTIONAL_FEATURE_HASH_INDEX != 0
+			&& self.i_flags & INODE_FLAG_HASH_INDEXED != 0
+	}
+
 	/// Adds a new entry to the current directory.
 	///
 	/// Arguments:
@@ -912,6 +1167,9 @@ impl Ext2INode {
 	/// - `name` is the name of the entry
 	/// - `file_type` is the type of the entry
 	///
+	/// When the directory grows past its first block, it gets indexed with a hash tree, if the
+	/// filesystem supports it.
+	///
 	/// If the block allocation fails or if the entry name is already used, the
 	/// function returns an error.
 	///
@@ -929,63 +1187,61 @@ impl Ext2INode {
 		if unlikely(name.len() > super::MAX_NAME_LEN) {
 			return Err(errno!(ENAMETOOLONG));
 		}
-		let mut rec_len = (dirent::NAME_OFF + name.len()).next_multiple_of(dirent::ALIGN) as u16;
+		let rec_len = (dirent::NAME_OFF + name.len()).next_multiple_of(dirent::ALIGN) as u16;
 		// If the entry is too large, error
 		let blk_size = superblock.get_block_size();
 		if unlikely(rec_len as u32 > blk_size) {
 			return Err(errno!(ENAMETOOLONG));
 		}
 		let mut buf = vec![0; blk_size as _]?;
-		if let Some(mut off) = self.get_suitable_slot(superblock, io, &mut buf, rec_len)? {
-			// If the entry is used, shrink it
-			let inner_off = (off % buf.len() as u64) as usize;
-			let dirent = Dirent::from_slice(&mut buf[inner_off..], superblock)?;
-			if !dirent.is_free() {
-				let used_space = dirent.used_space(superblock);
-				off += used_space as u64;
-				dirent.rec_len = used_space;
-			}
-			// Create used entry
-			let inner_off = (off % buf.len() as u64) as usize;
-			// If not enough space is left on the block to fit another entry, use the remaining
-			// space
-			if inner_off + rec_len as usize + dirent::NAME_OFF >= buf.len() {
-				rec_len = (buf.len() - inner_off) as u16;
+		if self.is_indexed(superblock) {
+			if let Some(index) = htree::Index::read(self, superblock, io, &mut buf)? {
+				return index.add(self, superblock, io, entry_inode, name, file_type, rec_len);
 			}
-			Dirent::write_new(
-				&mut buf[inner_off..],
-				superblock,
-				entry_inode,
-				rec_len,
-				Some(file_type),
-				name,
-			)?;
-			// Create free entries to cover remaining free space
-			fill_free_entries(&mut buf[(inner_off + rec_len as usize)..], superblock)?;
-			// Write block
-			let blk_off = (off / blk_size as u64) as u32;
-			let blk_off = self.translate_blk_off(blk_off, superblock, io)?.unwrap();
-			write_block(blk_off.get() as _, blk_size, io, &buf)?;
-		} else {
-			// No suitable free entry: Fill a new block
-			let blocks = self.get_blocks(superblock);
-			let blk = self.alloc_content_blk(blocks, superblock, io)?;
-			buf.fill(0);
-			// Create used entry
-			Dirent::write_new(
+			// The index cannot be used: drop it so that it does not become stale
+			self.i_flags &= !INODE_FLAG_HASH_INDEXED;
+		}
+		if let Some((disk_blk, slot)) =
+			self.get_suitable_slot(superblock, io, &mut buf, rec_len)?
+		{
+			insert_in_block(
 				&mut buf,
 				superblock,
+				slot,
 				entry_inode,
 				rec_len,
-				Some(file_type),
 				name,
+				file_type,
 			)?;
-			// Create free entries to cover remaining free space
-			fill_free_entries(&mut buf[rec_len as usize..], superblock)?;
-			// Write block
-			write_block(blk.get() as _, blk_size, io, &buf)?;
-			self.set_size(superblock, (blocks as u64 + 1) * blk_size as u64, false);
+			return write_block(disk_blk.get() as _, blk_size, io, &buf);
 		}
+		// If the first block is full, index the directory instead of growing it linearly
+		if superblock.s_feature_compat & super::OPTIONAL_FEATURE_HASH_INDEX != 0
+			&& self.get_size(superblock) == blk_size as u64
+		{
+			if let Some(index) = htree::Index::create(self, superblock, io)? {
+				self.i_flags |= INODE_FLAG_HASH_INDEXED;
+				return index.add(self, superblock, io, entry_inode, name, file_type, rec_len);
+			}
+		}
+		// No suitable free entry: Fill a new block
+		let blocks = (self.get_size(superblock) / blk_size as u64) as u32;
+		let blk = self.alloc_content_blk(blocks, superblock, io)?;
+		buf.fill(0);
+		// Create used entry
+		Dirent::write_new(
+			&mut buf,
+			superblock,
+			entry_inode,
+			rec_len,
+			Some(file_type),
+			name,
+		)?;
+		// Create free entries to cover remaining free space
+		fill_free_entries(&mut buf[rec_len as usize..], superblock)?;
+		// Write block
+		write_block(blk.get() as _, blk_size, io, &buf)?;
+		self.set_size(superblock, (blocks as u64 + 1) * blk_size as u64);
 		Ok(())
 	}
 
@@ -1018,11 +1274,12 @@ impl Ext2INode {
 		// Read and free entry
 		let ent = Dirent::from_slice(&mut buf[inner_off..], superblock)?;
 		ent.inode = 0;
-		// If the block is now empty, free it. Else, update it
-		if is_block_empty(&mut buf, superblock)? {
+		// If the block is now empty, free it. Else, update it. The blocks of an indexed directory
+		// are referenced by the index, so they are kept
+		if !self.is_indexed(superblock) && is_block_empty(&mut buf, superblock)? {
 			// If this is the last block, update the file's size
-			if file_blk_off as u32 + 1 >= self.get_blocks(superblock) {
-				self.set_size(superblock, file_blk_off * blk_size as u64, false);
+			if (file_blk_off + 1) * blk_size as u64 >= self.get_size(superblock) {
+				self.set_size(superblock, file_blk_off * blk_size as u64);
 			}
 			self.free_content_blk(file_blk_off as _, superblock, io)
 		} else {
@@ -1030,6 +1287,43 @@ impl Ext2INode {
 		}
 	}
 
+	/// Changes the inode the entry at the given offset points to.
+	///
+	/// Arguments:
+	/// - `off` is the offset of the entry to update
+	/// - `entry_inode` is the new inode of the entry
+	/// - `entry_type` is the type of the new inode
+	/// - `superblock` is the filesystem's superblock
+	/// - `io` is the I/O interface
+	///
+	/// If the entry does not exist, the function does nothing.
+	///
+	/// If the file is not a directory, the behaviour is undefined.
+	pub fn set_dirent_inode(
+		&self,
+		off: u64,
+		entry_inode: u32,
+		entry_type: FileType,
+		superblock: &Superblock,
+		io: &dyn DeviceIO,
+	) -> EResult<()> {
+		debug_assert_eq!(self.get_type(), FileType::Directory);
+		let blk_size = superblock.get_block_size();
+		let file_blk_off = off / blk_size as u64;
+		let inner_off = (off % blk_size as u64) as usize;
+		// Read entry's block
+		let mut buf = vec![0; blk_size as _]?;
+		let Some(disk_blk_off) = self.translate_blk_off(file_blk_off as _, superblock, io)? else {
+			return Ok(());
+		};
+		read_block(disk_blk_off.get() as _, blk_size, io, &mut buf)?;
+		// Update entry
+		let ent = Dirent::from_slice(&mut buf[inner_off..], superblock)?;
+		ent.inode = entry_inode;
+		ent.set_type(superblock, Some(entry_type));
+		write_block(disk_blk_off.get() as _, blk_size, io, &buf)
+	}
+
 	/// Reads the content symbolic link.
 	///
 	/// Arguments:
@@ -1049,14 +1343,17 @@ impl Ext2INode {
 		buf: &mut [u8],
 	) -> EResult<usize> {
 		let size = self.get_size(superblock);
-		if size <= SYMLINK_INLINE_LIMIT {
+		if self.is_fast_symlink(superblock) {
 			// The target is stored inline in the inode
-			let Some(len) = size.checked_sub(off) else {
-				return Err(errno!(EINVAL));
-			};
-			// Copy
-			let len = min(buf.len(), len as usize);
 			let src = bytes::as_bytes(&self.i_block);
+			if unlikely(size > src.len() as u64) {
+				return Err(errno!(EUCLEAN));
+			}
+			if off >= size {
+				return Ok(0);
+			}
+			// Copy
+			let len = min(buf.len(), (size - off) as usize);
 			let off = off as usize;
 			buf[..len].copy_from_slice(&src[off..(off + len)]);
 			Ok(len)
@@ -1081,20 +1378,21 @@ impl Ext2INode {
 		io: &dyn DeviceIO,
 		buf: &[u8],
 	) -> EResult<()> {
-		let old_size = self.get_size(superblock);
 		let new_size = buf.len() as u64;
 		// Erase previous
-		if old_size <= SYMLINK_INLINE_LIMIT {
+		if self.is_fast_symlink(superblock) {
 			self.i_block.fill(0);
+			self.set_size(superblock, 0);
+		} else {
+			self.truncate(superblock, io, 0)?;
 		}
 		// Write target
 		if new_size <= SYMLINK_INLINE_LIMIT {
 			// The target is stored inline in the inode
-			self.truncate(superblock, io, 0)?;
 			// Copy
 			let dst = bytes::as_bytes_mut(&mut self.i_block);
 			dst[..buf.len()].copy_from_slice(buf);
-			self.set_size(superblock, new_size, true);
+			self.set_size(superblock, new_size);
 		} else {
 			self.truncate(superblock, io, new_size)?;
 			self.write_content(0, buf, superblock, io)?;
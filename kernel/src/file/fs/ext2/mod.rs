@@ -45,36 +45,47 @@
 
 mod bgd;
 mod dirent;
+mod htree;
 mod inode;
+mod xattr;
 
 use crate::{
 	device::DeviceIO,
 	file::{
-		fs::{downcast_fs, Filesystem, FilesystemType, NodeOps, StatSet, Statfs},
+		fs::{
+			adjusted_nlink, downcast_fs, parse_options, ErrorPolicy, Filesystem, FilesystemType,
+			NodeOps, StatSet, Statfs, FILEID_INO32_GEN, RENAME_EXCHANGE, RENAME_NOREPLACE,
+		},
 		DirEntry, FileLocation, FileType, INode, Stat,
 	},
-	time::{clock, clock::CLOCK_MONOTONIC, unit::TimestampScale},
+	time::{
+		clock,
+		clock::{CLOCK_MONOTONIC, CLOCK_REALTIME},
+		unit::TimestampScale,
+	},
 };
 use bgd::BlockGroupDescriptor;
 use core::{
 	cmp::{max, min},
+	ffi::c_int,
 	fmt,
 	fmt::Formatter,
 	intrinsics::unlikely,
 	mem::size_of,
+	sync::atomic::{AtomicBool, Ordering::Relaxed},
 };
 use inode::Ext2INode;
 use macros::AnyRepr;
 use utils::{
 	boxed::Box,
 	bytes::{as_bytes, from_bytes, AnyRepr},
-	collections::path::PathBuf,
+	collections::{path::PathBuf, vec::Vec},
 	errno,
 	errno::EResult,
 	lock::Mutex,
 	math,
 	ptr::{arc::Arc, cow::Cow},
-	vec,
+	vec, DisplayableStr,
 };
 
 // TODO Take into account user's UID/GID when allocating block/inode to handle
@@ -103,10 +114,14 @@ const DEFAULT_BLOCKS_PER_GROUP: u32 = 1024;
 const DEFAULT_MOUNT_COUNT_BEFORE_FSCK: u16 = 1000;
 /// Default elapsed time in between each fsck in seconds.
 const DEFAULT_FSCK_INTERVAL: u32 = 16070400;
+/// The maximum number of links to an inode.
+const LINK_MAX: u16 = 32000;
 
-/// State telling that the filesystem is clean.
+/// State flag telling that the filesystem has been cleanly unmounted.
+///
+/// The flag is cleared while the filesystem is mounted in read-write.
 const FS_STATE_CLEAN: u16 = 1;
-/// State telling that the filesystem has errors.
+/// State flag telling that errors have been detected in the filesystem.
 const FS_STATE_ERROR: u16 = 2;
 
 /// Error handle action telling to ignore it.
@@ -230,6 +245,33 @@ fn write<T>(off: u64, blk_size: u32, io: &dyn DeviceIO, val: &T) -> EResult<()>
 	write_block(blk as _, blk_size, io, &buf)
 }
 
+/// Returns the path stored in the `s_last_mounted` field of the superblock, without the trailing
+/// NUL bytes.
+fn last_mounted(buf: &[u8]) -> &[u8] {
+	let len = buf.iter().position(|b| *b == 0).unwrap_or(buf.len());
+	&buf[..len]
+}
+
+/// Checks the file with the given `inode` can be replaced by a rename.
+///
+/// If the file is a non-empty directory, the function returns [`errno::ENOTEMPTY`].
+fn check_replaceable(inode: u32, superblock: &Superblock, io: &dyn DeviceIO) -> EResult<()> {
+	let inode_ = Ext2INode::read(inode as _, superblock, io)?;
+	if inode_.get_type() == FileType::Directory && !inode_.is_directory_empty(superblock, io)? {
+		return Err(errno!(ENOTEMPTY));
+	}
+	Ok(())
+}
+
+/// Makes the `..` entry of the directory with the given `inode` point to `parent`.
+fn set_parent(inode: u32, parent: u32, superblock: &Superblock, io: &dyn DeviceIO) -> EResult<()> {
+	let inode_ = Ext2INode::read(inode as _, superblock, io)?;
+	let (_, _, off) = inode_
+		.get_dirent(b"..", superblock, io)?
+		.ok_or_else(|| errno!(EUCLEAN))?;
+	inode_.set_dirent_inode(off, parent, FileType::Directory, superblock, io)
+}
+
 /// File operations.
 #[derive(Debug)]
 struct Ext2NodeOps;
@@ -259,6 +301,9 @@ impl NodeOps for Ext2NodeOps {
 	fn set_stat(&self, loc: &FileLocation, set: StatSet) -> EResult<()> {
 		let fs = loc.get_filesystem().unwrap();
 		let fs = downcast_fs::<Ext2Fs>(&*fs);
+		if unlikely(fs.is_readonly()) {
+			return Err(errno!(EROFS));
+		}
 		let superblock = fs.superblock.lock();
 		let mut inode_ = Ext2INode::read(loc.inode as _, &superblock, &*fs.io)?;
 		if let Some(mode) = set.mode {
@@ -300,7 +345,7 @@ impl NodeOps for Ext2NodeOps {
 	fn write_content(&self, loc: &FileLocation, off: u64, buf: &[u8]) -> EResult<usize> {
 		let fs = loc.get_filesystem().unwrap();
 		let fs = downcast_fs::<Ext2Fs>(&*fs);
-		if unlikely(fs.readonly) {
+		if unlikely(fs.is_readonly()) {
 			return Err(errno!(EROFS));
 		}
 		let mut superblock = fs.superblock.lock();
@@ -318,16 +363,41 @@ impl NodeOps for Ext2NodeOps {
 	fn truncate_content(&self, loc: &FileLocation, size: u64) -> EResult<()> {
 		let fs = loc.get_filesystem().unwrap();
 		let fs = downcast_fs::<Ext2Fs>(&*fs);
-		if unlikely(fs.readonly) {
+		if unlikely(fs.is_readonly()) {
 			return Err(errno!(EROFS));
 		}
-		let fs = downcast_fs::<Ext2Fs>(fs);
 		let mut superblock = fs.superblock.lock();
 		let mut inode_ = Ext2INode::read(loc.inode as _, &superblock, &*fs.io)?;
 		match inode_.get_type() {
 			FileType::Regular => inode_.truncate(&mut superblock, &*fs.io, size)?,
+			FileType::Directory => return Err(errno!(EISDIR)),
+			_ => return Err(errno!(EINVAL)),
+		}
+		// Update timestamps along with the size, in the same write
+		let timestamp = clock::current_time(CLOCK_REALTIME, TimestampScale::Second)?;
+		inode_.i_ctime = timestamp as _;
+		inode_.i_mtime = timestamp as _;
+		inode_.write(loc.inode as _, &superblock, &*fs.io)?;
+		superblock.write(&*fs.io)?;
+		Ok(())
+	}
+
+	fn punch_hole(&self, loc: &FileLocation, off: u64, len: u64) -> EResult<()> {
+		let fs = loc.get_filesystem().unwrap();
+		let fs = downcast_fs::<Ext2Fs>(&*fs);
+		if unlikely(fs.is_readonly()) {
+			return Err(errno!(EROFS));
+		}
+		let mut superblock = fs.superblock.lock();
+		let mut inode_ = Ext2INode::read(loc.inode as _, &superblock, &*fs.io)?;
+		match inode_.get_type() {
+			FileType::Regular => inode_.punch_hole(&mut superblock, &*fs.io, off, len)?,
+			FileType::Directory => return Err(errno!(EISDIR)),
 			_ => return Err(errno!(EINVAL)),
 		}
+		let timestamp = clock::current_time(CLOCK_REALTIME, TimestampScale::Second)?;
+		inode_.i_ctime = timestamp as _;
+		inode_.i_mtime = timestamp as _;
 		inode_.write(loc.inode as _, &superblock, &*fs.io)?;
 		superblock.write(&*fs.io)?;
 		Ok(())
@@ -347,10 +417,10 @@ impl NodeOps for Ext2NodeOps {
 		};
 		let ent = DirEntry {
 			inode: inode as _,
-			entry_type,
+			entry_type: Some(entry_type),
 			name: Cow::Borrowed(name),
 		};
-		Ok(Some((ent, Box::new(Ext2NodeOps)?)))
+		Ok(Some((ent, Box::new(CheckedNodeOps)?)))
 	}
 
 	fn next_entry(
@@ -373,12 +443,11 @@ impl NodeOps for Ext2NodeOps {
 	) -> EResult<(INode, Box<dyn NodeOps>)> {
 		let fs = parent.get_filesystem().unwrap();
 		let fs = downcast_fs::<Ext2Fs>(&*fs);
-		if unlikely(fs.readonly) {
+		if unlikely(fs.is_readonly()) {
 			return Err(errno!(EROFS));
 		}
 		let file_type = stat.get_type().ok_or_else(|| errno!(EINVAL))?;
-		let ops = Box::new(Ext2NodeOps)?;
-		let fs = downcast_fs::<Ext2Fs>(fs);
+		let ops = Box::new(CheckedNodeOps)?;
 		let mut superblock = fs.superblock.lock();
 		// Get parent directory
 		let mut parent_ = Ext2INode::read(parent.inode as _, &superblock, &*fs.io)?;
@@ -392,6 +461,11 @@ impl NodeOps for Ext2NodeOps {
 		}
 		// Get a free inode ID
 		let inode_index = superblock.get_free_inode(&*fs.io)?;
+		// Increment the generation of the inode, so that file handles referring to a previous
+		// use of it become stale
+		let generation = Ext2INode::read(inode_index as _, &superblock, &*fs.io)?
+			.i_generation
+			.wrapping_add(1);
 		// Create inode
 		let mut inode = Ext2INode {
 			i_mode: stat.mode as _,
@@ -402,12 +476,12 @@ impl NodeOps for Ext2NodeOps {
 			i_atime: stat.atime as _,
 			i_dtime: 0,
 			i_gid: stat.gid,
-			i_links_count: 1,
+			i_links_count: stat.nlink,
 			i_blocks: 0,
 			i_flags: 0,
 			i_osd1: 0,
 			i_block: [0; inode::DIRECT_BLOCKS_COUNT + 3],
-			i_generation: 0,
+			i_generation: generation,
 			i_file_acl: 0,
 			i_dir_acl: 0,
 			i_faddr: 0,
@@ -431,8 +505,6 @@ impl NodeOps for Ext2NodeOps {
 					b"..",
 					FileType::Directory,
 				)?;
-				inode.i_links_count += 1;
-				parent_.i_links_count += 1;
 			}
 			FileType::BlockDevice | FileType::CharDevice => {
 				if stat.dev_major > (u8::MAX as u32) || stat.dev_minor > (u8::MAX as u32) {
@@ -456,7 +528,7 @@ impl NodeOps for Ext2NodeOps {
 	fn link(&self, parent: &FileLocation, name: &[u8], target: INode) -> EResult<()> {
 		let fs = parent.get_filesystem().unwrap();
 		let fs = downcast_fs::<Ext2Fs>(&*fs);
-		if unlikely(fs.readonly) {
+		if unlikely(fs.is_readonly()) {
 			return Err(errno!(EROFS));
 		}
 		let mut superblock = fs.superblock.lock();
@@ -471,17 +543,16 @@ impl NodeOps for Ext2NodeOps {
 			return Err(errno!(EEXIST));
 		}
 		// The inode
-		let mut inode_ = Ext2INode::read(target as _, &superblock, &*fs.io)?;
-		// Check the maximum number of links is not exceeded
-		if inode_.i_links_count == u16::MAX {
-			return Err(errno!(EMFILE));
-		}
+		let inode_ = Ext2INode::read(target as _, &superblock, &*fs.io)?;
 		if inode_.get_type() == FileType::Directory {
 			// Cannot add hard links to directories
 			return Err(errno!(EISDIR));
 		}
-		// Update links count
-		inode_.i_links_count += 1;
+		// Check the maximum number of links is not exceeded, before adding the entry since
+		// the count is updated afterwards
+		if inode_.i_links_count >= LINK_MAX {
+			return Err(errno!(EMLINK));
+		}
 		// Write directory entry
 		parent_.add_dirent(
 			&mut superblock,
@@ -491,14 +562,13 @@ impl NodeOps for Ext2NodeOps {
 			inode_.get_type(),
 		)?;
 		parent_.write(parent.inode as _, &superblock, &*fs.io)?;
-		inode_.write(target as _, &superblock, &*fs.io)?;
 		Ok(())
 	}
 
 	fn unlink(&self, parent: &FileLocation, name: &[u8]) -> EResult<()> {
 		let fs = parent.get_filesystem().unwrap();
 		let fs = downcast_fs::<Ext2Fs>(&*fs);
-		if unlikely(fs.readonly) {
+		if unlikely(fs.is_readonly()) {
 			return Err(errno!(EROFS));
 		}
 		if name == b"." || name == b".." {
@@ -515,43 +585,356 @@ impl NodeOps for Ext2NodeOps {
 		let (remove_inode, _, remove_off) = parent_
 			.get_dirent(name, &superblock, &*fs.io)?
 			.ok_or_else(|| errno!(ENOENT))?;
-		let mut remove_inode_ = Ext2INode::read(remove_inode as _, &superblock, &*fs.io)?;
-		if remove_inode_.get_type() == FileType::Directory {
-			// If the directory is not empty, error
-			if !remove_inode_.is_directory_empty(&superblock, &*fs.io)? {
-				return Err(errno!(ENOTEMPTY));
-			}
-			// Decrement links because of the `..` entry being removed
-			parent_.i_links_count = parent_.i_links_count.saturating_sub(1);
+		let remove_inode_ = Ext2INode::read(remove_inode as _, &superblock, &*fs.io)?;
+		// If the directory is not empty, error
+		if remove_inode_.get_type() == FileType::Directory
+			&& !remove_inode_.is_directory_empty(&superblock, &*fs.io)?
+		{
+			return Err(errno!(ENOTEMPTY));
 		}
-		// Decrement the hard links count
-		remove_inode_.i_links_count = remove_inode_.i_links_count.saturating_sub(1);
-		remove_inode_.write(remove_inode as _, &superblock, &*fs.io)?;
 		// Remove the directory entry
 		parent_.remove_dirent(remove_off, &mut superblock, &*fs.io)?;
 		parent_.write(parent.inode as _, &superblock, &*fs.io)?;
 		Ok(())
 	}
 
+	fn rename(
+		&self,
+		old_parent: &FileLocation,
+		old_name: &[u8],
+		new_parent: &FileLocation,
+		new_name: &[u8],
+		flags: u32,
+	) -> EResult<()> {
+		let fs = old_parent.get_filesystem().unwrap();
+		let fs = downcast_fs::<Ext2Fs>(&*fs);
+		if unlikely(fs.is_readonly()) {
+			return Err(errno!(EROFS));
+		}
+		if old_name == b"." || old_name == b".." {
+			return Err(errno!(EINVAL));
+		}
+		let mut superblock = fs.superblock.lock();
+		// The source parent inode
+		let mut old_parent_ = Ext2INode::read(old_parent.inode as _, &superblock, &*fs.io)?;
+		if old_parent_.get_type() != FileType::Directory {
+			return Err(errno!(ENOTDIR));
+		}
+		let (inode, file_type, old_off) = old_parent_
+			.get_dirent(old_name, &superblock, &*fs.io)?
+			.ok_or_else(|| errno!(ENOENT))?;
+		// If both parents are the same, only the name changes
+		if old_parent.inode == new_parent.inode {
+			match old_parent_.get_dirent(new_name, &superblock, &*fs.io)? {
+				Some(_) if flags & RENAME_NOREPLACE != 0 => return Err(errno!(EEXIST)),
+				// Both names are links to the same file: nothing to do
+				Some((new_inode, ..)) if new_inode == inode => {}
+				Some((new_inode, new_type, new_off)) if flags & RENAME_EXCHANGE != 0 => {
+					old_parent_.set_dirent_inode(
+						old_off,
+						new_inode,
+						new_type,
+						&superblock,
+						&*fs.io,
+					)?;
+					old_parent_.set_dirent_inode(
+						new_off,
+						inode,
+						file_type,
+						&superblock,
+						&*fs.io,
+					)?;
+				}
+				Some((new_inode, _, new_off)) => {
+					check_replaceable(new_inode, &superblock, &*fs.io)?;
+					old_parent_.set_dirent_inode(
+						new_off,
+						inode,
+						file_type,
+						&superblock,
+						&*fs.io,
+					)?;
+					old_parent_.remove_dirent(old_off, &mut superblock, &*fs.io)?;
+					old_parent_.write(old_parent.inode as _, &superblock, &*fs.io)?;
+				}
+				None if flags & RENAME_EXCHANGE != 0 => return Err(errno!(ENOENT)),
+				None => {
+					old_parent_.add_dirent(
+						&mut superblock,
+						&*fs.io,
+						inode,
+						new_name,
+						file_type,
+					)?;
+					// Adding an entry to an indexed directory may move existing ones
+					let (_, _, old_off) = old_parent_
+						.get_dirent(old_name, &superblock, &*fs.io)?
+						.ok_or_else(|| errno!(EUCLEAN))?;
+					old_parent_.remove_dirent(old_off, &mut superblock, &*fs.io)?;
+					old_parent_.write(old_parent.inode as _, &superblock, &*fs.io)?;
+				}
+			}
+			return Ok(());
+		}
+		// The destination parent inode
+		let mut new_parent_ = Ext2INode::read(new_parent.inode as _, &superblock, &*fs.io)?;
+		if new_parent_.get_type() != FileType::Directory {
+			return Err(errno!(ENOTDIR));
+		}
+		match new_parent_.get_dirent(new_name, &superblock, &*fs.io)? {
+			Some(_) if flags & RENAME_NOREPLACE != 0 => return Err(errno!(EEXIST)),
+			// Both names are links to the same file: nothing to do
+			Some((new_inode, ..)) if new_inode == inode => return Ok(()),
+			Some((new_inode, new_type, new_off)) if flags & RENAME_EXCHANGE != 0 => {
+				new_parent_.set_dirent_inode(new_off, inode, file_type, &superblock, &*fs.io)?;
+				old_parent_.set_dirent_inode(
+					old_off,
+					new_inode,
+					new_type,
+					&superblock,
+					&*fs.io,
+				)?;
+				if new_type == FileType::Directory {
+					set_parent(new_inode, old_parent.inode as _, &superblock, &*fs.io)?;
+				}
+			}
+			Some((new_inode, _, new_off)) => {
+				check_replaceable(new_inode, &superblock, &*fs.io)?;
+				new_parent_.set_dirent_inode(new_off, inode, file_type, &superblock, &*fs.io)?;
+				old_parent_.remove_dirent(old_off, &mut superblock, &*fs.io)?;
+			}
+			None if flags & RENAME_EXCHANGE != 0 => return Err(errno!(ENOENT)),
+			None => {
+				new_parent_.add_dirent(&mut superblock, &*fs.io, inode, new_name, file_type)?;
+				old_parent_.remove_dirent(old_off, &mut superblock, &*fs.io)?;
+			}
+		}
+		if file_type == FileType::Directory {
+			set_parent(inode, new_parent.inode as _, &superblock, &*fs.io)?;
+		}
+		new_parent_.write(new_parent.inode as _, &superblock, &*fs.io)?;
+		old_parent_.write(old_parent.inode as _, &superblock, &*fs.io)?;
+		Ok(())
+	}
+
+	fn adjust_nlink(&self, loc: &FileLocation, delta: i16) -> EResult<u16> {
+		let fs = loc.get_filesystem().unwrap();
+		let fs = downcast_fs::<Ext2Fs>(&*fs);
+		if unlikely(fs.is_readonly()) {
+			return Err(errno!(EROFS));
+		}
+		let mut superblock = fs.superblock.lock();
+		let mut inode_ = Ext2INode::read(loc.inode as _, &superblock, &*fs.io)?;
+		let prev = inode_.i_links_count;
+		inode_.i_links_count = adjusted_nlink(prev, delta)?;
+		// The node may remain in use until it is removed. Record it as orphan so that it is
+		// reclaimed at the next mount if the filesystem is not cleanly unmounted before
+		let orphan = prev != 0 && inode_.i_links_count == 0;
+		if orphan {
+			inode_.i_dtime = superblock.s_last_orphan;
+		}
+		inode_.write(loc.inode as _, &superblock, &*fs.io)?;
+		if orphan {
+			superblock.s_last_orphan = loc.inode as _;
+			superblock.write(&*fs.io)?;
+		}
+		// Check the on-disk count matches the directory's entries: the entry in its parent,
+		// `.` and the `..` entry of each subdirectory
+		#[cfg(debug_assertions)]
+		if inode_.get_type() == FileType::Directory && inode_.i_links_count != 0 {
+			let subdirs = inode_.subdirectories_count(&superblock, &*fs.io)?;
+			debug_assert_eq!(inode_.i_links_count as usize, 2 + subdirs);
+		}
+		Ok(inode_.i_links_count)
+	}
+
 	fn remove_node(&self, loc: &FileLocation) -> EResult<()> {
 		let fs = loc.get_filesystem().unwrap();
 		let fs = downcast_fs::<Ext2Fs>(&*fs);
-		if unlikely(fs.readonly) {
+		if unlikely(fs.is_readonly()) {
 			return Err(errno!(EROFS));
 		}
 		let mut superblock = fs.superblock.lock();
 		let mut inode_ = Ext2INode::read(loc.inode, &superblock, &*fs.io)?;
+		// While orphan, the deletion time field holds the next inode in the orphan inodes list
+		superblock.remove_orphan(&*fs.io, loc.inode as _, inode_.i_dtime)?;
 		// Remove the inode
 		inode_.i_links_count = 0;
 		let timestamp = clock::current_time(CLOCK_MONOTONIC, TimestampScale::Second)?;
 		inode_.i_dtime = timestamp as _;
 		inode_.free_content(&mut superblock, &*fs.io)?;
+		xattr::release(&mut inode_, &mut superblock, &*fs.io)?;
 		inode_.write(loc.inode, &superblock, &*fs.io)?;
 		// Free inode
 		superblock.free_inode(&*fs.io, loc.inode, inode_.get_type() == FileType::Directory)?;
 		superblock.write(&*fs.io)?;
 		Ok(())
 	}
+
+	fn get_xattr(&self, loc: &FileLocation, name: &[u8]) -> EResult<Vec<u8>> {
+		let fs = loc.get_filesystem().unwrap();
+		let fs = downcast_fs::<Ext2Fs>(&*fs);
+		let superblock = fs.superblock.lock();
+		let inode_ = Ext2INode::read(loc.inode as _, &superblock, &*fs.io)?;
+		xattr::get(&inode_, &superblock, &*fs.io, name)
+	}
+
+	fn list_xattr(&self, loc: &FileLocation) -> EResult<Vec<u8>> {
+		let fs = loc.get_filesystem().unwrap();
+		let fs = downcast_fs::<Ext2Fs>(&*fs);
+		let superblock = fs.superblock.lock();
+		let inode_ = Ext2INode::read(loc.inode as _, &superblock, &*fs.io)?;
+		xattr::list(&inode_, &superblock, &*fs.io)
+	}
+
+	fn set_xattr(
+		&self,
+		loc: &FileLocation,
+		name: &[u8],
+		value: &[u8],
+		flags: c_int,
+	) -> EResult<()> {
+		let fs = loc.get_filesystem().unwrap();
+		let fs = downcast_fs::<Ext2Fs>(&*fs);
+		if unlikely(fs.is_readonly()) {
+			return Err(errno!(EROFS));
+		}
+		let mut superblock = fs.superblock.lock();
+		let mut inode_ = Ext2INode::read(loc.inode as _, &superblock, &*fs.io)?;
+		xattr::set(&mut inode_, &mut superblock, &*fs.io, name, value, flags)?;
+		inode_.write(loc.inode as _, &superblock, &*fs.io)?;
+		superblock.write(&*fs.io)?;
+		Ok(())
+	}
+
+	fn remove_xattr(&self, loc: &FileLocation, name: &[u8]) -> EResult<()> {
+		let fs = loc.get_filesystem().unwrap();
+		let fs = downcast_fs::<Ext2Fs>(&*fs);
+		if unlikely(fs.is_readonly()) {
+			return Err(errno!(EROFS));
+		}
+		let mut superblock = fs.superblock.lock();
+		let mut inode_ = Ext2INode::read(loc.inode as _, &superblock, &*fs.io)?;
+		xattr::remove(&mut inode_, &mut superblock, &*fs.io, name)?;
+		inode_.write(loc.inode as _, &superblock, &*fs.io)?;
+		superblock.write(&*fs.io)?;
+		Ok(())
+	}
+}
+
+/// Wrapper around [`Ext2NodeOps`] applying the error policy of the filesystem when an operation
+/// detects an inconsistency in its structures.
+#[derive(Debug)]
+struct CheckedNodeOps;
+
+/// Executes the operation `f` on the filesystem of `loc`, handling inconsistencies with
+/// [`Ext2Fs::check`].
+fn check<T, F: FnOnce(&Ext2NodeOps) -> EResult<T>>(loc: &FileLocation, f: F) -> EResult<T> {
+	let fs = loc.get_filesystem().unwrap();
+	let fs = downcast_fs::<Ext2Fs>(&*fs);
+	fs.check(|| f(&Ext2NodeOps))
+}
+
+impl NodeOps for CheckedNodeOps {
+	fn get_stat(&self, loc: &FileLocation) -> EResult<Stat> {
+		check(loc, |ops| ops.get_stat(loc))
+	}
+
+	fn set_stat(&self, loc: &FileLocation, set: StatSet) -> EResult<()> {
+		check(loc, |ops| ops.set_stat(loc, set))
+	}
+
+	fn read_content(&self, loc: &FileLocation, off: u64, buf: &mut [u8]) -> EResult<usize> {
+		check(loc, |ops| ops.read_content(loc, off, buf))
+	}
+
+	fn write_content(&self, loc: &FileLocation, off: u64, buf: &[u8]) -> EResult<usize> {
+		check(loc, |ops| ops.write_content(loc, off, buf))
+	}
+
+	fn truncate_content(&self, loc: &FileLocation, size: u64) -> EResult<()> {
+		check(loc, |ops| ops.truncate_content(loc, size))
+	}
+
+	fn punch_hole(&self, loc: &FileLocation, off: u64, len: u64) -> EResult<()> {
+		check(loc, |ops| ops.punch_hole(loc, off, len))
+	}
+
+	fn entry_by_name<'n>(
+		&self,
+		loc: &FileLocation,
+		name: &'n [u8],
+	) -> EResult<Option<(DirEntry<'n>, Box<dyn NodeOps>)>> {
+		check(loc, |ops| ops.entry_by_name(loc, name))
+	}
+
+	fn next_entry(
+		&self,
+		loc: &FileLocation,
+		off: u64,
+	) -> EResult<Option<(DirEntry<'static>, u64)>> {
+		check(loc, |ops| ops.next_entry(loc, off))
+	}
+
+	fn add_file(
+		&self,
+		parent: &FileLocation,
+		name: &[u8],
+		stat: Stat,
+	) -> EResult<(INode, Box<dyn NodeOps>)> {
+		check(parent, |ops| ops.add_file(parent, name, stat))
+	}
+
+	fn link(&self, parent: &FileLocation, name: &[u8], target: INode) -> EResult<()> {
+		check(parent, |ops| ops.link(parent, name, target))
+	}
+
+	fn unlink(&self, parent: &FileLocation, name: &[u8]) -> EResult<()> {
+		check(parent, |ops| ops.unlink(parent, name))
+	}
+
+	fn rename(
+		&self,
+		old_parent: &FileLocation,
+		old_name: &[u8],
+		new_parent: &FileLocation,
+		new_name: &[u8],
+		flags: u32,
+	) -> EResult<()> {
+		check(old_parent, |ops| {
+			ops.rename(old_parent, old_name, new_parent, new_name, flags)
+		})
+	}
+
+	fn adjust_nlink(&self, loc: &FileLocation, delta: i16) -> EResult<u16> {
+		check(loc, |ops| ops.adjust_nlink(loc, delta))
+	}
+
+	fn remove_node(&self, loc: &FileLocation) -> EResult<()> {
+		check(loc, |ops| ops.remove_node(loc))
+	}
+
+	fn get_xattr(&self, loc: &FileLocation, name: &[u8]) -> EResult<Vec<u8>> {
+		check(loc, |ops| ops.get_xattr(loc, name))
+	}
+
+	fn list_xattr(&self, loc: &FileLocation) -> EResult<Vec<u8>> {
+		check(loc, |ops| ops.list_xattr(loc))
+	}
+
+	fn set_xattr(
+		&self,
+		loc: &FileLocation,
+		name: &[u8],
+		value: &[u8],
+		flags: c_int,
+	) -> EResult<()> {
+		check(loc, |ops| ops.set_xattr(loc, name, value, flags))
+	}
+
+	fn remove_xattr(&self, loc: &FileLocation, name: &[u8]) -> EResult<()> {
+		check(loc, |ops| ops.remove_xattr(loc, name))
+	}
 }
 
 /// The ext2 superblock structure.
@@ -644,9 +1027,23 @@ pub struct Superblock {
 	s_journal_dev: u32,
 	/// The head of orphan inodes list.
 	s_last_orphan: u32,
+	/// The seed used by the hash algorithm of directory indexes.
+	s_hash_seed: [u32; 4],
+	/// The default hash algorithm to use for directory indexes.
+	s_def_hash_version: u8,
+	/// Unused.
+	_pad1: [u8; 3],
+	/// Default mount options.
+	s_default_mount_opts: u32,
+	/// The first meta block group.
+	s_first_meta_bg: u32,
+	/// Fields reserved for extensions of the format.
+	_reserved: [u8; 88],
+	/// Miscellaneous flags.
+	s_flags: u32,
 
 	/// Structure padding.
-	_padding: [u8; 788],
+	_padding: [u8; 668],
 }
 
 impl Superblock {
@@ -782,6 +1179,66 @@ impl Superblock {
 		Ok(prev)
 	}
 
+	/// Returns the number of entries that are not set in a bitmap starting at block `start`.
+	///
+	/// Arguments:
+	/// - `io` is the I/O interface.
+	/// - `start` is the starting block.
+	/// - `size` is the number of entries.
+	fn count_bitmap_free(&self, io: &dyn DeviceIO, start: u32, size: u32) -> EResult<u32> {
+		let blk_size = self.get_block_size();
+		let mut buff = vec![0; blk_size as _]?;
+		let mut count = 0;
+		let mut i = 0;
+
+		while (i * (blk_size * 8)) < size {
+			read_block(start + i, blk_size, io, buff.as_mut_slice())?;
+
+			let len = min(size - i * (blk_size * 8), blk_size * 8);
+			let (bytes, rem) = (len / 8, len % 8);
+			count += buff[..bytes as usize]
+				.iter()
+				.map(|b| b.count_zeros())
+				.sum::<u32>();
+			if rem > 0 {
+				count += (!buff[bytes as usize] & ((1 << rem) - 1)).count_ones();
+			}
+
+			i += 1;
+		}
+
+		Ok(count)
+	}
+
+	/// Checks the block group descriptors are consistent with the bitmaps they refer to.
+	///
+	/// `io` is the I/O interface.
+	///
+	/// The function returns the number of inconsistent block groups.
+	fn check_groups(&self, io: &dyn DeviceIO) -> EResult<u32> {
+		let blk_size = self.get_block_size();
+		// Tells whether a bitmap of `size` entries starting at block `start` is on the device
+		let in_range = |start: u32, size: u32| {
+			start
+				.checked_add(size.div_ceil(blk_size * 8))
+				.is_some_and(|end| start > 0 && end <= self.s_blocks_count)
+		};
+		let mut count = 0;
+		for i in 0..self.get_block_groups_count() {
+			let bgd = BlockGroupDescriptor::read(i, self, io)?;
+			let consistent = in_range(bgd.bg_block_bitmap, self.s_blocks_per_group)
+				&& in_range(bgd.bg_inode_bitmap, self.s_inodes_per_group)
+				&& self.count_bitmap_free(io, bgd.bg_block_bitmap, self.s_blocks_per_group)?
+					== bgd.bg_free_blocks_count as u32
+				&& self.count_bitmap_free(io, bgd.bg_inode_bitmap, self.s_inodes_per_group)?
+					== bgd.bg_free_inodes_count as u32;
+			if !consistent {
+				count += 1;
+			}
+		}
+		Ok(count)
+	}
+
 	/// Returns the id of a free inode in the filesystem.
 	///
 	/// `io` is the I/O interface.
@@ -824,13 +1281,20 @@ impl Superblock {
 		let bitfield_index = (inode - 1) % self.s_inodes_per_group;
 		let prev = self.set_bitmap(io, bgd.bg_inode_bitmap, bitfield_index, true)?;
 		if !prev {
-			bgd.bg_free_inodes_count -= 1;
+			// The counters cannot underflow unless they are inconsistent with the bitmap
+			bgd.bg_free_inodes_count = bgd
+				.bg_free_inodes_count
+				.checked_sub(1)
+				.ok_or_else(|| errno!(EUCLEAN))?;
 			if directory {
 				bgd.bg_used_dirs_count += 1;
 			}
 			bgd.write(group, self, io)?;
 
-			self.s_free_inodes_count -= 1;
+			self.s_free_inodes_count = self
+				.s_free_inodes_count
+				.checked_sub(1)
+				.ok_or_else(|| errno!(EUCLEAN))?;
 		}
 
 		Ok(())
@@ -860,7 +1324,10 @@ impl Superblock {
 		if prev {
 			bgd.bg_free_inodes_count += 1;
 			if directory {
-				bgd.bg_used_dirs_count -= 1;
+				bgd.bg_used_dirs_count = bgd
+					.bg_used_dirs_count
+					.checked_sub(1)
+					.ok_or_else(|| errno!(EUCLEAN))?;
 			}
 			bgd.write(group, self, io)?;
 
@@ -870,6 +1337,69 @@ impl Superblock {
 		Ok(())
 	}
 
+	/// Removes the inode `inode` from the orphan inodes list.
+	///
+	/// Arguments:
+	/// - `io` is the I/O interface.
+	/// - `inode` is the inode number.
+	/// - `next` is the inode following `inode` in the list.
+	///
+	/// If the inode is not in the list, the function does nothing.
+	fn remove_orphan(&mut self, io: &dyn DeviceIO, inode: u32, next: u32) -> EResult<()> {
+		if self.s_last_orphan == inode {
+			self.s_last_orphan = next;
+			return Ok(());
+		}
+		let mut cur = self.s_last_orphan;
+		// Bound the number of iterations in case the list is corrupted and contains a cycle
+		for _ in 0..self.s_inodes_count {
+			if cur == 0 {
+				break;
+			}
+			let mut cur_ = Ext2INode::read(cur as _, self, io)?;
+			if cur_.i_dtime == inode {
+				cur_.i_dtime = next;
+				cur_.write(cur as _, self, io)?;
+				break;
+			}
+			cur = cur_.i_dtime;
+		}
+		Ok(())
+	}
+
+	/// Frees the inodes in the orphan inodes list.
+	///
+	/// These inodes have no link left, but were still in use when the filesystem was last
+	/// unmounted.
+	///
+	/// `io` is the I/O interface.
+	///
+	/// The function returns the number of freed inodes.
+	fn reclaim_orphans(&mut self, io: &dyn DeviceIO) -> EResult<usize> {
+		let timestamp = clock::current_time(CLOCK_MONOTONIC, TimestampScale::Second)?;
+		let mut count = 0;
+		let mut cur = self.s_last_orphan;
+		// Bound the number of iterations in case the list is corrupted and contains a cycle
+		for _ in 0..self.s_inodes_count {
+			if cur == 0 {
+				break;
+			}
+			let mut inode_ = Ext2INode::read(cur as _, self, io)?;
+			let next = inode_.i_dtime;
+			if inode_.i_links_count == 0 {
+				inode_.i_dtime = timestamp as _;
+				inode_.free_content(self, io)?;
+				xattr::release(&mut inode_, self, io)?;
+				inode_.write(cur as _, self, io)?;
+				self.free_inode(io, cur as _, inode_.get_type() == FileType::Directory)?;
+				count += 1;
+			}
+			cur = next;
+		}
+		self.s_last_orphan = 0;
+		Ok(count)
+	}
+
 	/// Returns the id of a free block in the filesystem.
 	///
 	/// `io` is the I/O interface.
@@ -914,10 +1444,17 @@ impl Superblock {
 		let bitfield_index = blk % self.s_blocks_per_group;
 		let prev = self.set_bitmap(io, bgd.bg_block_bitmap, bitfield_index, true)?;
 		if !prev {
-			bgd.bg_free_blocks_count -= 1;
+			// The counters cannot underflow unless they are inconsistent with the bitmap
+			bgd.bg_free_blocks_count = bgd
+				.bg_free_blocks_count
+				.checked_sub(1)
+				.ok_or_else(|| errno!(EUCLEAN))?;
 			bgd.write(group, self, io)?;
 
-			self.s_free_blocks_count -= 1;
+			self.s_free_blocks_count = self
+				.s_free_blocks_count
+				.checked_sub(1)
+				.ok_or_else(|| errno!(EUCLEAN))?;
 		}
 
 		Ok(())
@@ -965,8 +1502,13 @@ struct Ext2Fs {
 	io: Arc<dyn DeviceIO>,
 	/// The filesystem's superblock.
 	superblock: Mutex<Superblock>,
-	/// Tells whether the filesystem is mounted in read-only.
-	readonly: bool,
+	/// Tells whether the filesystem is read-only, either because it has been mounted so or
+	/// because an error has been detected.
+	readonly: AtomicBool,
+	/// The action to take when an error is detected in the filesystem's structures.
+	error_policy: ErrorPolicy,
+	/// The state of the filesystem when it was mounted, restored when it is unmounted.
+	mount_state: u16,
 }
 
 impl Ext2Fs {
@@ -979,11 +1521,14 @@ impl Ext2Fs {
 	/// - `io` is the I/O interface.
 	/// - `mountpath` is the path on which the filesystem is mounted.
 	/// - `readonly` tells whether the filesystem is mounted in read-only.
+	/// - `error_policy` is the action to take on errors. If `None`, the one stored in the
+	///   superblock is used.
 	fn new(
 		mut superblock: Superblock,
 		io: Arc<dyn DeviceIO>,
 		mountpath: PathBuf,
 		readonly: bool,
+		error_policy: Option<ErrorPolicy>,
 	) -> EResult<Self> {
 		if !superblock.is_valid() {
 			return Err(errno!(EINVAL));
@@ -1006,28 +1551,157 @@ impl Ext2Fs {
 				return Err(errno!(EROFS));
 			}
 		}
-		let timestamp = clock::current_time(CLOCK_MONOTONIC, TimestampScale::Second)?;
-		if superblock.s_mnt_count >= superblock.s_max_mnt_count {
-			return Err(errno!(EINVAL));
-		}
-		// TODO
-		/*if timestamp >= superblock.last_fsck_timestamp + superblock.fsck_interval {
-			return Err(errno::EINVAL);
-		}*/
-		superblock.s_mnt_count += 1;
 		// Set the last mount path
 		let mountpath_bytes = mountpath.as_bytes();
 		let len = min(mountpath_bytes.len(), superblock.s_last_mounted.len());
 		superblock.s_last_mounted[..len].copy_from_slice(&mountpath_bytes[..len]);
 		superblock.s_last_mounted[len..].fill(0);
-		// Set the last mount timestamp
-		superblock.s_mtime = timestamp as _;
-		superblock.write(&*io)?;
-		Ok(Self {
+		let last_mounted_buf = superblock.s_last_mounted;
+		let path = DisplayableStr(last_mounted(&last_mounted_buf));
+		// Tell whether a consistency check is recommended
+		let timestamp = clock::current_time(CLOCK_REALTIME, TimestampScale::Second)?;
+		if superblock.s_state & FS_STATE_ERROR != 0 {
+			crate::log!(
+				Vfs,
+				Warn,
+				"ext2: mounting filesystem with errors, checking is recommended ({path})"
+			);
+		} else if !readonly && superblock.s_state & FS_STATE_CLEAN == 0 {
+			crate::log!(
+				Vfs,
+				Warn,
+				"ext2: mounting filesystem that was not cleanly unmounted, checking is recommended \
+				 ({path})"
+			);
+		} else if (superblock.s_max_mnt_count as i16) > 0
+			&& superblock.s_mnt_count >= superblock.s_max_mnt_count
+		{
+			crate::log!(
+				Vfs,
+				Warn,
+				"ext2: maximal mount count reached, checking is recommended ({path})"
+			);
+		} else if superblock.s_checkinterval != 0
+			&& timestamp >= superblock.s_lastcheck as u64 + superblock.s_checkinterval as u64
+		{
+			crate::log!(
+				Vfs,
+				Warn,
+				"ext2: checktime reached, checking is recommended ({path})"
+			);
+		}
+		let error_policy = error_policy.unwrap_or(match superblock.s_errors {
+			ERR_ACTION_READ_ONLY => ErrorPolicy::RemountRo,
+			ERR_ACTION_KERNEL_PANIC => ErrorPolicy::Panic,
+			ERR_ACTION_IGNORE => ErrorPolicy::Continue,
+			// Unknown policy
+			_ => ErrorPolicy::Continue,
+		});
+		let mount_state = superblock.s_state;
+		let inconsistent = superblock.check_groups(&*io)?;
+		let fs = Self {
 			io,
 			superblock: Mutex::new(superblock),
-			readonly,
-		})
+			readonly: AtomicBool::new(readonly),
+			error_policy,
+			mount_state,
+		};
+		if inconsistent > 0 {
+			crate::log!(
+				Vfs,
+				Error,
+				"ext2: {inconsistent} block group(s) inconsistent with their bitmaps ({path})"
+			);
+			fs.error();
+		}
+		// Nothing is written to a filesystem mounted in read-only
+		if !fs.is_readonly() {
+			let mut superblock = fs.superblock.lock();
+			let io = &*fs.io;
+			let count = superblock.reclaim_orphans(io)?;
+			if count > 0 {
+				crate::log!(
+					Vfs,
+					Info,
+					"ext2: reclaimed {count} orphan inode(s) ({path})"
+				);
+			}
+			superblock.s_mnt_count = superblock.s_mnt_count.saturating_add(1);
+			superblock.s_mtime = timestamp as _;
+			// Until unmounted, the filesystem is not clean
+			superblock.s_state &= !FS_STATE_CLEAN;
+			superblock.write(io)?;
+		}
+		Ok(fs)
+	}
+
+	/// Executes the operation `f` on the filesystem, then returns its result.
+	///
+	/// If the operation detected an inconsistency in the filesystem's structures, the error is
+	/// handled with [`Self::error`].
+	fn check<T, F: FnOnce() -> EResult<T>>(&self, f: F) -> EResult<T> {
+		let res = f();
+		if matches!(&res, Err(e) if e.as_int() == errno::EUCLEAN) {
+			self.error();
+		}
+		res
+	}
+
+	/// Records that an error has been detected in the filesystem's structures, then applies the
+	/// error policy.
+	fn error(&self) {
+		let mut superblock = self.superblock.lock();
+		let last_mounted_buf = superblock.s_last_mounted;
+		let path = DisplayableStr(last_mounted(&last_mounted_buf));
+		crate::log!(
+			Vfs,
+			Error,
+			"ext2: error detected in filesystem structures ({path})"
+		);
+		// Record the error on the storage device, so that the filesystem gets checked
+		if !self.is_readonly() && superblock.s_state & FS_STATE_ERROR == 0 {
+			superblock.s_state |= FS_STATE_ERROR;
+			if superblock.write(&*self.io).is_err() {
+				crate::log!(
+					Vfs,
+					Error,
+					"ext2: cannot record error in superblock ({path})"
+				);
+			}
+		}
+		match self.error_policy {
+			ErrorPolicy::Continue => {}
+			ErrorPolicy::RemountRo => {
+				if !self.readonly.swap(true, Relaxed) {
+					crate::log!(Vfs, Warn, "ext2: remounting filesystem read-only ({path})");
+				}
+			}
+			ErrorPolicy::Panic => panic!("ext2: error detected in filesystem structures ({path})"),
+		}
+	}
+}
+
+impl Drop for Ext2Fs {
+	fn drop(&mut self) {
+		// The filesystem may have been remounted in read-only after an error, in which case it
+		// must be checked anyway
+		if self.is_readonly() {
+			return;
+		}
+		let mut superblock = self.superblock.lock();
+		// Restore the state the filesystem had when mounted, keeping errors detected since
+		superblock.s_state = self.mount_state | (superblock.s_state & FS_STATE_ERROR);
+		if let Ok(timestamp) = clock::current_time(CLOCK_REALTIME, TimestampScale::Second) {
+			superblock.s_wtime = timestamp as _;
+		}
+		if superblock.write(&*self.io).is_err() {
+			let path = DisplayableStr(last_mounted(&superblock.s_last_mounted));
+			crate::log!(
+				Vfs,
+				Error,
+				"ext2: cannot write superblock on unmount ({path})"
+			);
+		}
 	}
 }
 
@@ -1046,6 +1720,14 @@ impl Filesystem for Ext2Fs {
 		inode::ROOT_DIRECTORY_INODE as _
 	}
 
+	fn is_readonly(&self) -> bool {
+		self.readonly.load(Relaxed)
+	}
+
+	fn get_error_policy(&self) -> Option<ErrorPolicy> {
+		Some(self.error_policy)
+	}
+
 	fn get_stat(&self) -> EResult<Statfs> {
 		let superblock = self.superblock.lock();
 		let fragment_size = math::pow2(superblock.s_log_frag_size + 10);
@@ -1054,14 +1736,16 @@ impl Filesystem for Ext2Fs {
 			f_bsize: superblock.get_block_size(),
 			f_blocks: superblock.s_blocks_count as _,
 			f_bfree: superblock.s_free_blocks_count as _,
-			// TODO Subtract blocks for superuser
-			f_bavail: superblock.s_free_blocks_count as _,
+			f_bavail: superblock
+				.s_free_blocks_count
+				.saturating_sub(superblock.s_r_blocks_count) as _,
 			f_files: superblock.s_inodes_count as _,
 			f_ffree: superblock.s_free_inodes_count as _,
 			f_fsid: Default::default(),
 			f_namelen: MAX_NAME_LEN as _,
 			f_frsize: fragment_size,
-			f_flags: 0, // TODO
+			f_flags: 0,
+			f_spare: [0; 4],
 		})
 	}
 
@@ -1069,7 +1753,34 @@ impl Filesystem for Ext2Fs {
 		let superblock = self.superblock.lock();
 		// Check the inode exists
 		Ext2INode::read(inode as _, &superblock, &*self.io)?;
-		Ok(Box::new(Ext2NodeOps)?)
+		Ok(Box::new(CheckedNodeOps)?)
+	}
+
+	fn encode_handle(&self, inode: INode) -> EResult<(c_int, Vec<u8>)> {
+		let superblock = self.superblock.lock();
+		let inode_ = Ext2INode::read(inode as _, &superblock, &*self.io)?;
+		let mut handle = Vec::with_capacity(8)?;
+		handle.extend_from_slice(&(inode as u32).to_le_bytes())?;
+		handle.extend_from_slice(&inode_.i_generation.to_le_bytes())?;
+		Ok((FILEID_INO32_GEN, handle))
+	}
+
+	fn decode_handle(&self, handle_type: c_int, handle: &[u8]) -> EResult<INode> {
+		if handle_type != FILEID_INO32_GEN || handle.len() != 8 {
+			return Err(errno!(EINVAL));
+		}
+		let inode = u32::from_le_bytes(handle[..4].try_into().unwrap());
+		let generation = u32::from_le_bytes(handle[4..].try_into().unwrap());
+		let superblock = self.superblock.lock();
+		if inode == 0 || inode > superblock.s_inodes_count {
+			return Err(errno!(ESTALE));
+		}
+		let inode_ = Ext2INode::read(inode as _, &superblock, &*self.io)?;
+		// If the inode has been freed or reused, the handle is stale
+		if inode_.i_links_count == 0 || inode_.i_generation != generation {
+			return Err(errno!(ESTALE));
+		}
+		Ok(inode as _)
 	}
 }
 
@@ -1078,6 +1789,7 @@ impl fmt::Debug for Ext2Fs {
 		f.debug_struct("Ext2Fs")
 			.field("superblock", &self.superblock)
 			.field("readonly", &self.readonly)
+			.field("error_policy", &self.error_policy)
 			.finish()
 	}
 }
@@ -1090,6 +1802,10 @@ impl FilesystemType for Ext2FsType {
 		b"ext2"
 	}
 
+	fn requires_device(&self) -> bool {
+		true
+	}
+
 	fn detect(&self, io: &dyn DeviceIO) -> EResult<bool> {
 		Ok(Superblock::read(io)?.is_valid())
 	}
@@ -1099,10 +1815,176 @@ impl FilesystemType for Ext2FsType {
 		io: Option<Arc<dyn DeviceIO>>,
 		mountpath: PathBuf,
 		readonly: bool,
+		data: &[u8],
 	) -> EResult<Arc<dyn Filesystem>> {
+		let mut error_policy = None;
+		parse_options(data, |name, value| {
+			match name {
+				b"errors" => {
+					let policy = value.and_then(ErrorPolicy::from_name);
+					error_policy = Some(policy.ok_or_else(|| errno!(EINVAL))?);
+				}
+				_ => return Ok(false),
+			}
+			Ok(true)
+		})?;
 		let io = io.ok_or_else(|| errno!(ENODEV))?;
 		let superblock = Superblock::read(&*io)?;
-		let fs = Ext2Fs::new(superblock, io, mountpath, readonly)?;
+		let fs = Ext2Fs::new(superblock, io, mountpath, readonly, error_policy)?;
 		Ok(Arc::new(fs)? as _)
 	}
 }
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use core::num::NonZeroU64;
+
+	/// The size of a block on the test image.
+	const BLK_SIZE: u32 = 1024;
+	/// The number of blocks on the test image.
+	const BLOCKS: u32 = 64;
+	/// The number of inodes on the test image.
+	const INODES: u32 = 32;
+
+	/// Returns a buffer of `len` zeroed bytes.
+	pub(super) fn zeroed(len: usize) -> Vec<u8> {
+		let mut buf = Vec::new();
+		buf.resize(len, 0).unwrap();
+		buf
+	}
+
+	/// A disk backed by memory.
+	pub(super) struct TestDisk(Mutex<Vec<u8>>);
+
+	impl DeviceIO for TestDisk {
+		fn block_size(&self) -> NonZeroU64 {
+			NonZeroU64::new(1).unwrap()
+		}
+
+		fn blocks_count(&self) -> u64 {
+			self.0.lock().len() as _
+		}
+
+		fn read(&self, off: u64, buf: &mut [u8]) -> EResult<usize> {
+			let off = off as usize;
+			buf.copy_from_slice(&self.0.lock()[off..(off + buf.len())]);
+			Ok(buf.len())
+		}
+
+		fn write(&self, off: u64, buf: &[u8]) -> EResult<usize> {
+			let off = off as usize;
+			self.0.lock()[off..(off + buf.len())].copy_from_slice(buf);
+			Ok(buf.len())
+		}
+	}
+
+	/// Creates an image with a single block group, in which the first 9 blocks and the first 11
+	/// inodes are used.
+	pub(super) fn image() -> (Superblock, TestDisk) {
+		let disk = TestDisk(Mutex::new(zeroed((BLK_SIZE * BLOCKS) as usize)));
+		let buf = zeroed(size_of::<Superblock>());
+		let mut superblock = from_bytes::<Superblock>(&buf).cloned().unwrap();
+		superblock.s_inodes_count = INODES;
+		superblock.s_blocks_count = BLOCKS;
+		superblock.s_free_blocks_count = BLOCKS - 9;
+		superblock.s_free_inodes_count = INODES - 11;
+		superblock.s_first_data_block = 1;
+		superblock.s_blocks_per_group = BLOCKS;
+		superblock.s_inodes_per_group = INODES;
+		superblock.s_magic = EXT2_MAGIC;
+		superblock.write(&disk).unwrap();
+		// The bitmaps are on blocks 3 and 4, followed by the inode table
+		let bgd = BlockGroupDescriptor {
+			bg_block_bitmap: 3,
+			bg_inode_bitmap: 4,
+			bg_inode_table: 5,
+			bg_free_blocks_count: (BLOCKS - 9) as _,
+			bg_free_inodes_count: (INODES - 11) as _,
+			bg_used_dirs_count: 0,
+			bg_pad: [0; 14],
+		};
+		bgd.write(0, &superblock, &disk).unwrap();
+		let mut bitmap = [0u8; BLK_SIZE as usize];
+		bitmap[..2].copy_from_slice(&[0xff, 0x01]);
+		write_block(3, BLK_SIZE, &disk, &bitmap).unwrap();
+		bitmap[..2].copy_from_slice(&[0xff, 0x07]);
+		write_block(4, BLK_SIZE, &disk, &bitmap).unwrap();
+		(superblock, disk)
+	}
+
+	#[test_case]
+	fn ext2_check_groups() {
+		let (superblock, disk) = image();
+		assert_eq!(superblock.check_groups(&disk).unwrap(), 0);
+		// The descriptor does not match the blocks bitmap
+		let mut bgd = BlockGroupDescriptor::read(0, &superblock, &disk).unwrap();
+		bgd.bg_free_blocks_count += 1;
+		bgd.write(0, &superblock, &disk).unwrap();
+		assert_eq!(superblock.check_groups(&disk).unwrap(), 1);
+		// The inodes bitmap is out of the device
+		bgd.bg_free_blocks_count -= 1;
+		bgd.bg_inode_bitmap = BLOCKS;
+		bgd.write(0, &superblock, &disk).unwrap();
+		assert_eq!(superblock.check_groups(&disk).unwrap(), 1);
+	}
+
+	#[test_case]
+	fn ext2_free_count_underflow() {
+		let (mut superblock, disk) = image();
+		// The descriptor claims no entry is free while the bitmaps have some
+		let mut bgd = BlockGroupDescriptor::read(0, &superblock, &disk).unwrap();
+		bgd.bg_free_blocks_count = 0;
+		bgd.bg_free_inodes_count = 0;
+		bgd.write(0, &superblock, &disk).unwrap();
+		assert_eq!(
+			superblock.mark_block_used(&disk, 9).unwrap_err(),
+			errno!(EUCLEAN)
+		);
+		assert_eq!(
+			superblock.mark_inode_used(&disk, 12, false).unwrap_err(),
+			errno!(EUCLEAN)
+		);
+	}
+
+	/// Allocates the regular file `inode`, then unlinks it while it is still in use, recording
+	/// it in the orphan inodes list the same way `adjust_nlink` does.
+	fn orphan(superblock: &mut Superblock, disk: &TestDisk, inode: u32) {
+		superblock.mark_inode_used(disk, inode, false).unwrap();
+		let mut inode_ = Ext2INode::read(inode as _, superblock, disk).unwrap();
+		inode_.i_mode = inode::INODE_TYPE_REGULAR | 0o644;
+		inode_.i_links_count = 0;
+		inode_.i_dtime = superblock.s_last_orphan;
+		inode_.write(inode as _, superblock, disk).unwrap();
+		superblock.s_last_orphan = inode;
+	}
+
+	#[test_case]
+	fn ext2_orphans() {
+		let (mut superblock, disk) = image();
+		let free = superblock.s_free_inodes_count;
+		for inode in 12..=14 {
+			orphan(&mut superblock, &disk, inode);
+		}
+		assert_eq!(superblock.s_free_inodes_count, free - 3);
+		// Closing the file in the middle of the list removes it
+		let next = Ext2INode::read(13, &superblock, &disk).unwrap().i_dtime;
+		assert_eq!(next, 12);
+		superblock.remove_orphan(&disk, 13, next).unwrap();
+		superblock.free_inode(&disk, 13, false).unwrap();
+		assert_eq!(Ext2INode::read(14, &superblock, &disk).unwrap().i_dtime, 12);
+		// Removing an inode that is not in the list does nothing
+		superblock.remove_orphan(&disk, 13, 0).unwrap();
+		assert_eq!(superblock.s_last_orphan, 14);
+		// The filesystem is not cleanly unmounted: the remaining orphans are reclaimed at the
+		// next mount
+		superblock.write(&disk).unwrap();
+		let mut superblock = Superblock::read(&disk).unwrap();
+		assert_eq!(superblock.reclaim_orphans(&disk).unwrap(), 2);
+		assert_eq!(superblock.s_last_orphan, 0);
+		assert_eq!(superblock.s_free_inodes_count, free);
+		assert_eq!(superblock.check_groups(&disk).unwrap(), 0);
+		// Nothing is left to reclaim
+		assert_eq!(superblock.reclaim_orphans(&disk).unwrap(), 0);
+	}
+}
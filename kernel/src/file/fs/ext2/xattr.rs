@@ -0,0 +1,432 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Extended attributes are name/value pairs attached to an inode, besides its content.
+//!
+//! The attributes of an inode are stored in a block referenced by the inode's `i_file_acl`
+//! field. The block begins with a header, followed by a list of entries sorted by name. Values
+//! are stored from the end of the block.
+//!
+//! Inodes having the same attributes may share the same block, in which case the block is copied
+//! before being modified.
+
+use super::{
+	inode::{check_blk_off, Ext2INode},
+	read_block, write_block, Superblock, OPTIONAL_FEATURE_INODE_EXTENDED,
+};
+use crate::{
+	device::DeviceIO,
+	file::fs::{XATTR_CREATE, XATTR_REPLACE},
+};
+use core::{cmp::Ordering, ffi::c_int, mem::size_of};
+use macros::AnyRepr;
+use utils::{bytes, collections::vec::Vec, errno, errno::EResult, vec};
+
+/// The magic number of an attributes block.
+const MAGIC: u32 = 0xea020000;
+/// The maximum length of an attribute's name, without the namespace prefix.
+const NAME_MAX: usize = 255;
+
+/// The supported namespaces, along with the index identifying them on disk.
+const NAMESPACES: [(u8, &[u8]); 3] = [(1, b"user."), (4, b"trusted."), (6, b"security.")];
+
+/// The header of an attributes block.
+#[repr(C)]
+#[derive(AnyRepr, Clone)]
+struct Header {
+	/// Magic number.
+	h_magic: u32,
+	/// The number of inodes referencing the block.
+	h_refcount: u32,
+	/// The number of blocks used to store the attributes. Always `1`.
+	h_blocks: u32,
+	/// The hash of all the entries of the block.
+	h_hash: u32,
+	/// Reserved.
+	_reserved: [u32; 4],
+}
+
+/// The header of an entry, followed by the attribute's name.
+#[repr(C)]
+#[derive(AnyRepr, Clone)]
+struct EntryHeader {
+	/// The length of the name.
+	e_name_len: u8,
+	/// The index of the namespace.
+	e_name_index: u8,
+	/// The offset of the value in the block.
+	e_value_offs: u16,
+	/// The block containing the value. Always zero.
+	e_value_block: u32,
+	/// The size of the value.
+	e_value_size: u32,
+	/// The hash of the name and value.
+	e_hash: u32,
+}
+
+/// Returns the size of an entry whose name has length `name_len`, including padding.
+fn entry_size(name_len: usize) -> usize {
+	(size_of::<EntryHeader>() + name_len).next_multiple_of(4)
+}
+
+/// Splits the full name of an attribute into the index of its namespace and its name without
+/// prefix.
+///
+/// If the namespace is not supported, the function returns [`errno::EOPNOTSUPP`].
+fn split_name(name: &[u8]) -> EResult<(u8, &[u8])> {
+	let (index, name) = NAMESPACES
+		.iter()
+		.find_map(|(index, prefix)| Some((*index, name.strip_prefix(*prefix)?)))
+		.ok_or_else(|| errno!(EOPNOTSUPP))?;
+	if name.is_empty() {
+		return Err(errno!(EINVAL));
+	}
+	if name.len() > NAME_MAX {
+		return Err(errno!(ERANGE));
+	}
+	Ok((index, name))
+}
+
+/// An extended attribute.
+struct Attr {
+	/// The index of the namespace.
+	index: u8,
+	/// The name, without the namespace prefix.
+	name: Vec<u8>,
+	/// The value.
+	value: Vec<u8>,
+}
+
+impl Attr {
+	/// Compares the attribute with the one named `name` in the namespace `index`, in the order in
+	/// which entries are sorted in a block.
+	fn cmp_name(&self, index: u8, name: &[u8]) -> Ordering {
+		(self.index, self.name.len(), self.name.as_slice()).cmp(&(index, name.len(), name))
+	}
+
+	/// Computes the hash of the entry.
+	fn hash(&self) -> u32 {
+		// Linux hashes names as signed characters
+		let hash = self
+			.name
+			.iter()
+			.fold(0u32, |hash, c| hash.rotate_left(5) ^ (*c as i8 as u32));
+		self.value.chunks(4).fold(hash, |hash, chunk| {
+			let mut word = [0; 4];
+			word[..chunk.len()].copy_from_slice(chunk);
+			hash.rotate_left(16) ^ u32::from_le_bytes(word)
+		})
+	}
+}
+
+/// Parses the attributes block `buf`.
+fn parse(buf: &[u8]) -> EResult<Vec<Attr>> {
+	let hdr: &Header = bytes::from_bytes(buf).unwrap();
+	if hdr.h_magic != MAGIC || hdr.h_blocks != 1 {
+		return Err(errno!(EUCLEAN));
+	}
+	let mut attrs = Vec::new();
+	let mut off = size_of::<Header>();
+	loop {
+		// The list of entries ends with four zero bytes
+		let end = buf.get(off..(off + 4)).ok_or_else(|| errno!(EUCLEAN))?;
+		if end == [0; 4] {
+			break;
+		}
+		let ent: &EntryHeader = bytes::from_bytes(&buf[off..]).ok_or_else(|| errno!(EUCLEAN))?;
+		if ent.e_value_block != 0 {
+			return Err(errno!(EUCLEAN));
+		}
+		let name = buf
+			.get((off + size_of::<EntryHeader>())..)
+			.and_then(|b| b.get(..ent.e_name_len as usize))
+			.ok_or_else(|| errno!(EUCLEAN))?;
+		let value = buf
+			.get(ent.e_value_offs as usize..)
+			.and_then(|b| b.get(..ent.e_value_size as usize))
+			.ok_or_else(|| errno!(EUCLEAN))?;
+		attrs.push(Attr {
+			index: ent.e_name_index,
+			name: Vec::try_from(name)?,
+			value: Vec::try_from(value)?,
+		})?;
+		off += entry_size(name.len());
+	}
+	Ok(attrs)
+}
+
+/// Writes the attributes `attrs` to the block `buf`, with a reference count of `1`.
+///
+/// If the attributes do not fit in the block, the function returns [`errno::ENOSPC`].
+fn serialize(attrs: &[Attr], buf: &mut [u8]) -> EResult<()> {
+	buf.fill(0);
+	let mut off = size_of::<Header>();
+	let mut value_off = buf.len();
+	let mut block_hash = 0u32;
+	for attr in attrs {
+		let size = entry_size(attr.name.len());
+		let value_size = attr.value.len().next_multiple_of(4);
+		// Keep room for the end of the list
+		if off + size + 4 + value_size > value_off {
+			return Err(errno!(ENOSPC));
+		}
+		value_off -= value_size;
+		let hash = attr.hash();
+		block_hash = block_hash.rotate_left(16) ^ hash;
+		let ent = EntryHeader {
+			e_name_len: attr.name.len() as _,
+			e_name_index: attr.index,
+			e_value_offs: if attr.value.is_empty() {
+				0
+			} else {
+				value_off as _
+			},
+			e_value_block: 0,
+			e_value_size: attr.value.len() as _,
+			e_hash: hash,
+		};
+		let name_off = off + size_of::<EntryHeader>();
+		buf[off..name_off].copy_from_slice(bytes::as_bytes(&ent));
+		buf[name_off..(name_off + attr.name.len())].copy_from_slice(&attr.name);
+		buf[value_off..(value_off + attr.value.len())].copy_from_slice(&attr.value);
+		off += size;
+	}
+	let hdr = Header {
+		h_magic: MAGIC,
+		h_refcount: 1,
+		h_blocks: 1,
+		h_hash: block_hash,
+		_reserved: [0; 4],
+	};
+	buf[..size_of::<Header>()].copy_from_slice(bytes::as_bytes(&hdr));
+	Ok(())
+}
+
+/// Reads the attributes of `inode`.
+///
+/// On success, the function returns the attributes along with the reference count of the block
+/// storing them. If the inode has no attribute, the reference count is zero.
+fn read_attrs(
+	inode: &Ext2INode,
+	superblock: &Superblock,
+	io: &dyn DeviceIO,
+) -> EResult<(Vec<Attr>, u32)> {
+	let Some(blk) = check_blk_off(inode.i_file_acl, superblock)? else {
+		return Ok((Vec::new(), 0));
+	};
+	let blk_size = superblock.get_block_size();
+	let mut buf = vec![0u8; blk_size as _]?;
+	read_block(blk.get(), blk_size, io, &mut buf)?;
+	let attrs = parse(&buf)?;
+	let hdr: &Header = bytes::from_bytes(&buf).unwrap();
+	Ok((attrs, hdr.h_refcount))
+}
+
+/// Replaces the attributes of `inode` with `attrs`.
+///
+/// `refcount` is the reference count of the block currently storing the attributes, as returned
+/// by [`read_attrs`].
+///
+/// The inode itself is not written to the device.
+fn write_attrs(
+	inode: &mut Ext2INode,
+	superblock: &mut Superblock,
+	io: &dyn DeviceIO,
+	attrs: &[Attr],
+	refcount: u32,
+) -> EResult<()> {
+	if attrs.is_empty() {
+		return release(inode, superblock, io);
+	}
+	let blk_size = superblock.get_block_size();
+	let mut buf = vec![0u8; blk_size as _]?;
+	serialize(attrs, &mut buf)?;
+	// A block that is not shared can be modified in place
+	if inode.i_file_acl != 0 && refcount == 1 {
+		return write_block(inode.i_file_acl, blk_size, io, &buf);
+	}
+	let blk = superblock.get_free_block(io)?;
+	superblock.mark_block_used(io, blk)?;
+	write_block(blk, blk_size, io, &buf)?;
+	release(inode, superblock, io)?;
+	inode.set_xattr_block(superblock, blk);
+	superblock.s_feature_compat |= OPTIONAL_FEATURE_INODE_EXTENDED;
+	Ok(())
+}
+
+/// Returns the value of the attribute `name` of `inode`.
+///
+/// If the attribute does not exist, the function returns [`errno::ENODATA`].
+pub fn get(
+	inode: &Ext2INode,
+	superblock: &Superblock,
+	io: &dyn DeviceIO,
+	name: &[u8],
+) -> EResult<Vec<u8>> {
+	let (index, name) = split_name(name)?;
+	let (attrs, _) = read_attrs(inode, superblock, io)?;
+	attrs
+		.into_iter()
+		.find(|attr| attr.cmp_name(index, name).is_eq())
+		.map(|attr| attr.value)
+		.ok_or_else(|| errno!(ENODATA))
+}
+
+/// Returns the names of the attributes of `inode`, each followed by a nul byte.
+///
+/// Attributes in namespaces that are not supported are omitted.
+pub fn list(inode: &Ext2INode, superblock: &Superblock, io: &dyn DeviceIO) -> EResult<Vec<u8>> {
+	let (attrs, _) = read_attrs(inode, superblock, io)?;
+	let mut list = Vec::new();
+	for attr in attrs {
+		let Some((_, prefix)) = NAMESPACES.iter().find(|(index, _)| *index == attr.index) else {
+			continue;
+		};
+		list.extend_from_slice(prefix)?;
+		list.extend_from_slice(&attr.name)?;
+		list.push(0)?;
+	}
+	Ok(list)
+}
+
+/// Sets the value of the attribute `name` of `inode`.
+///
+/// Arguments:
+/// - `superblock` is the filesystem's superblock
+/// - `io` is the I/O interface
+/// - `name` is the name of the attribute
+/// - `value` is the new value
+/// - `flags` is a combination of [`XATTR_CREATE`] and [`XATTR_REPLACE`]
+///
+/// The inode itself is not written to the device.
+pub fn set(
+	inode: &mut Ext2INode,
+	superblock: &mut Superblock,
+	io: &dyn DeviceIO,
+	name: &[u8],
+	value: &[u8],
+	flags: c_int,
+) -> EResult<()> {
+	let (index, name) = split_name(name)?;
+	if value.len() > superblock.get_block_size() as usize {
+		return Err(errno!(ERANGE));
+	}
+	let (mut attrs, refcount) = read_attrs(inode, superblock, io)?;
+	let attr = Attr {
+		index,
+		name: Vec::try_from(name)?,
+		value: Vec::try_from(value)?,
+	};
+	match attrs.binary_search_by(|attr| attr.cmp_name(index, name)) {
+		Ok(_) if flags & XATTR_CREATE != 0 => return Err(errno!(EEXIST)),
+		Ok(i) => attrs[i] = attr,
+		Err(_) if flags & XATTR_REPLACE != 0 => return Err(errno!(ENODATA)),
+		Err(i) => attrs.insert(i, attr)?,
+	}
+	write_attrs(inode, superblock, io, &attrs, refcount)
+}
+
+/// Removes the attribute `name` of `inode`.
+///
+/// If the attribute does not exist, the function returns [`errno::ENODATA`].
+///
+/// The inode itself is not written to the device.
+pub fn remove(
+	inode: &mut Ext2INode,
+	superblock: &mut Superblock,
+	io: &dyn DeviceIO,
+	name: &[u8],
+) -> EResult<()> {
+	let (index, name) = split_name(name)?;
+	let (mut attrs, refcount) = read_attrs(inode, superblock, io)?;
+	let i = attrs
+		.binary_search_by(|attr| attr.cmp_name(index, name))
+		.map_err(|_| errno!(ENODATA))?;
+	attrs.remove(i);
+	write_attrs(inode, superblock, io, &attrs, refcount)
+}
+
+/// Detaches the attributes block from `inode`, freeing it if no other inode references it.
+///
+/// The inode itself is not written to the device.
+pub fn release(
+	inode: &mut Ext2INode,
+	superblock: &mut Superblock,
+	io: &dyn DeviceIO,
+) -> EResult<()> {
+	let Some(blk) = check_blk_off(inode.i_file_acl, superblock)? else {
+		return Ok(());
+	};
+	let blk_size = superblock.get_block_size();
+	let mut buf = vec![0u8; blk_size as _]?;
+	read_block(blk.get(), blk_size, io, &mut buf)?;
+	let hdr: &Header = bytes::from_bytes(&buf).unwrap();
+	if hdr.h_magic == MAGIC && hdr.h_refcount > 1 {
+		let refcount = hdr.h_refcount - 1;
+		buf[4..8].copy_from_slice(&refcount.to_ne_bytes());
+		write_block(blk.get(), blk_size, io, &buf)?;
+	} else {
+		superblock.free_block(io, blk.get())?;
+	}
+	inode.set_xattr_block(superblock, 0);
+	Ok(())
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	/// Returns a buffer of `len` zeroed bytes.
+	fn zeroed(len: usize) -> Vec<u8> {
+		let mut buf = Vec::new();
+		buf.resize(len, 0).unwrap();
+		buf
+	}
+
+	#[test_case]
+	fn xattr_block() {
+		let attrs = [
+			Attr {
+				index: 1,
+				name: Vec::try_from(b"foo".as_slice()).unwrap(),
+				value: Vec::try_from(b"hello".as_slice()).unwrap(),
+			},
+			Attr {
+				index: 6,
+				name: Vec::try_from(b"selinux".as_slice()).unwrap(),
+				value: Vec::new(),
+			},
+		];
+		let mut buf = zeroed(1024);
+		serialize(&attrs, &mut buf).unwrap();
+		let parsed = parse(&buf).unwrap();
+		assert_eq!(parsed.len(), 2);
+		for (a, b) in attrs.iter().zip(parsed.iter()) {
+			assert_eq!(a.index, b.index);
+			assert_eq!(a.name, b.name);
+			assert_eq!(a.value, b.value);
+		}
+		// A value larger than the block does not fit
+		let big = [Attr {
+			index: 1,
+			name: Vec::try_from(b"big".as_slice()).unwrap(),
+			value: zeroed(1024),
+		}];
+		assert_eq!(serialize(&big, &mut buf).unwrap_err(), errno!(ENOSPC));
+	}
+}
@@ -0,0 +1,190 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! A directory is a sequence of records, each describing a file. A record cannot cross the
+//! boundary of a sector: the space left at the end of a sector is filled with zeros.
+//!
+//! The first two records of a directory describe the directory itself (`.`) and its parent
+//! (`..`).
+
+use super::{le32, Iso9660, SECTOR_SIZE};
+use utils::{collections::vec::Vec, errno, errno::EResult, vec};
+
+/// The size of a directory record, without its identifier.
+const RECORD_HDR_SIZE: usize = 33;
+
+/// File flag: the file is a directory.
+pub const FLAG_DIRECTORY: u8 = 0x02;
+/// File flag: the file is an associated file, which holds data related to another file.
+pub const FLAG_ASSOCIATED: u8 = 0x04;
+/// File flag: the content of the file continues in the extent described by the next record.
+pub const FLAG_MULTI_EXTENT: u8 = 0x80;
+
+/// A directory record.
+#[derive(Clone, Copy)]
+pub struct Record<'b>(&'b [u8]);
+
+impl<'b> Record<'b> {
+	/// Parses the record at the beginning of `buf`.
+	///
+	/// If `buf` starts with padding, the function returns `None`.
+	///
+	/// If the record is invalid, the function returns [`errno::EUCLEAN`].
+	pub fn parse(buf: &'b [u8]) -> EResult<Option<Self>> {
+		let len = buf.first().cloned().unwrap_or(0) as usize;
+		if len == 0 {
+			return Ok(None);
+		}
+		if len <= RECORD_HDR_SIZE || len > buf.len() {
+			return Err(errno!(EUCLEAN));
+		}
+		let rec = Self(&buf[..len]);
+		if RECORD_HDR_SIZE + rec.identifier_len() > len {
+			return Err(errno!(EUCLEAN));
+		}
+		Ok(Some(rec))
+	}
+
+	/// Returns the size of the record in bytes.
+	pub fn size(&self) -> usize {
+		self.0.len()
+	}
+
+	/// Returns the sector at which the file's content starts.
+	pub fn extent(&self) -> u32 {
+		le32(self.0, 2)
+	}
+
+	/// Returns the size of the file's content in bytes.
+	pub fn data_len(&self) -> u32 {
+		le32(self.0, 10)
+	}
+
+	/// Returns the date and time at which the file has been recorded.
+	pub fn recording_time(&self) -> &'b [u8] {
+		&self.0[18..25]
+	}
+
+	/// Returns the file's flags.
+	pub fn flags(&self) -> u8 {
+		self.0[25]
+	}
+
+	/// Returns the length of the file's identifier.
+	fn identifier_len(&self) -> usize {
+		self.0[32] as usize
+	}
+
+	/// Returns the file's identifier.
+	///
+	/// The identifiers of `.` and `..` are respectively `\0` and `\1`.
+	pub fn identifier(&self) -> &'b [u8] {
+		&self.0[RECORD_HDR_SIZE..(RECORD_HDR_SIZE + self.identifier_len())]
+	}
+
+	/// Returns the System Use area of the record, which stores extensions.
+	pub fn system_use(&self) -> &'b [u8] {
+		let len = self.identifier_len();
+		// Identifiers of even length are followed by a padding byte
+		let start = RECORD_HDR_SIZE + len + (len + 1) % 2;
+		self.0.get(start..).unwrap_or(&[])
+	}
+}
+
+/// Translates the identifier `id` of a record into a file name, for records with no Rock Ridge
+/// name.
+///
+/// The version number (`;1`) and the trailing dot of names without an extension are removed, and
+/// the name is converted to lowercase.
+pub fn translate_name(id: &[u8]) -> EResult<Vec<u8>> {
+	let id = match id {
+		b"\0" => b".".as_slice(),
+		b"\x01" => b"..",
+		_ => {
+			let end = id.iter().position(|c| *c == b';').unwrap_or(id.len());
+			let id = &id[..end];
+			id.strip_suffix(b".").unwrap_or(id)
+		}
+	};
+	let mut name = Vec::new();
+	name.extend_from_slice(id)?;
+	name.make_ascii_lowercase();
+	Ok(name)
+}
+
+/// Iterator over the records of a directory.
+pub struct DirIter<'f> {
+	/// The filesystem.
+	fs: &'f Iso9660,
+	/// The offset of the directory's content on the device, in bytes.
+	start: u64,
+	/// The size of the directory's content in bytes.
+	size: u64,
+	/// The offset of the next record in the directory's content.
+	off: u64,
+	/// The sector containing the next record, along with its offset in the directory's content.
+	sector: Option<(u64, Vec<u8>)>,
+}
+
+impl<'f> DirIter<'f> {
+	/// Creates an iterator over `size` bytes of records, starting at the offset `start` on the
+	/// device, in bytes.
+	///
+	/// `off` is the offset of the first record to return, from `start`.
+	pub fn new(fs: &'f Iso9660, start: u64, size: u64, off: u64) -> Self {
+		Self {
+			fs,
+			start,
+			size,
+			off,
+			sector: None,
+		}
+	}
+
+	/// Returns the offset of the next record in the directory's content.
+	pub fn offset(&self) -> u64 {
+		self.off
+	}
+
+	/// Returns the next record, along with its offset on the device in bytes.
+	pub fn next(&mut self) -> EResult<Option<(u64, Record<'_>)>> {
+		let inner_off = loop {
+			if self.off >= self.size {
+				return Ok(None);
+			}
+			let sector_off = self.off - self.off % SECTOR_SIZE;
+			if !matches!(self.sector, Some((off, _)) if off == sector_off) {
+				let mut buf = vec![0u8; SECTOR_SIZE as usize]?;
+				self.fs.io.read_bytes(self.start + sector_off, &mut buf)?;
+				self.sector = Some((sector_off, buf));
+			}
+			let (_, buf) = self.sector.as_ref().unwrap();
+			let inner_off = (self.off % SECTOR_SIZE) as usize;
+			if Record::parse(&buf[inner_off..])?.is_some() {
+				break inner_off;
+			}
+			// Padding up to the end of the sector
+			self.off = sector_off + SECTOR_SIZE;
+		};
+		let (_, buf) = self.sector.as_ref().unwrap();
+		let rec = Record::parse(&buf[inner_off..])?.unwrap();
+		let off = self.start + self.off;
+		self.off += rec.size() as u64;
+		Ok(Some((off, rec)))
+	}
+}
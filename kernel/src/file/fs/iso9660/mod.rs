@@ -0,0 +1,637 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! ISO 9660 is the read-only filesystem of optical discs, also used by the images of live and
+//! installation media.
+//!
+//! The device is divided into sectors of 2048 bytes. Volume descriptors start at sector 16, the
+//! primary volume descriptor locating the root directory.
+//!
+//! Each file is described by a record in its parent directory. The content of a file is stored in
+//! contiguous sectors, called an extent. Files larger than 4 GiB are split across several
+//! extents, each described by its own record.
+//!
+//! Without extensions, names are limited and files have no owner nor permissions. The Rock Ridge
+//! extensions, implemented in [`rock_ridge`], lift these limitations.
+//!
+//! Inode numbers are the offset in bytes of the record describing the file on the device. For
+//! directories, the `.` record is used, so that the inode number is the same whichever record
+//! leads to the directory.
+//!
+//! Joliet extensions and interleaved files are not supported.
+
+mod dir;
+mod rock_ridge;
+
+use crate::{
+	device::DeviceIO,
+	file::{
+		fs::{downcast_fs, parse_options, Filesystem, FilesystemType, NodeOps, StatSet, Statfs},
+		DirEntry, FileLocation, FileType, INode, Stat,
+	},
+	time::unit::Timestamp,
+};
+use core::{cmp::min, fmt, fmt::Formatter};
+use dir::{DirIter, Record, FLAG_ASSOCIATED, FLAG_DIRECTORY, FLAG_MULTI_EXTENT};
+use rock_ridge::RockRidge;
+use utils::{
+	boxed::Box,
+	collections::{path::PathBuf, vec::Vec},
+	errno,
+	errno::EResult,
+	ptr::{arc::Arc, cow::Cow},
+	vec,
+};
+
+/// The size of a sector, in bytes.
+const SECTOR_SIZE: u64 = 2048;
+/// The sector of the first volume descriptor.
+const DESCRIPTORS_START: u64 = 16;
+/// The maximum number of volume descriptors read to find the primary volume descriptor.
+const MAX_DESCRIPTORS: u64 = 64;
+/// The identifier present in every volume descriptor.
+const STANDARD_ID: &[u8] = b"CD001";
+
+/// Volume descriptor type: primary volume descriptor.
+const VD_PRIMARY: u8 = 1;
+/// Volume descriptor type: end of the list of volume descriptors.
+const VD_TERMINATOR: u8 = 255;
+
+/// Offset of the volume size, in sectors, in the primary volume descriptor.
+const PVD_VOLUME_SIZE_OFF: usize = 80;
+/// Offset of the logical block size in the primary volume descriptor.
+const PVD_BLOCK_SIZE_OFF: usize = 128;
+/// Offset of the root directory's record in the primary volume descriptor.
+const PVD_ROOT_OFF: usize = 156;
+
+/// The maximum number of extents of a file.
+const MAX_EXTENTS: usize = 64;
+/// The filesystem's magic number, as reported by `statfs`.
+const ISOFS_SUPER_MAGIC: u32 = 0x9660;
+/// The maximum length of a name in the filesystem.
+const MAX_NAME_LEN: usize = 255;
+
+/// Returns the 32 bits value at offset `off` in `buf`.
+///
+/// Numbers are stored in both little and big endian. Only the little endian value is used.
+fn le32(buf: &[u8], off: usize) -> u32 {
+	u32::from_le_bytes(buf[off..(off + 4)].try_into().unwrap())
+}
+
+/// Returns the number of days between the Unix epoch and the given date.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+	// Count years from March, so that the leap day is at the end of the year
+	let year = if month <= 2 { year - 1 } else { year };
+	let era = year.div_euclid(400);
+	let year_of_era = year - era * 400;
+	let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+	let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+	era * 146097 + day_of_era - 719468
+}
+
+/// Converts the given date and time into a timestamp.
+///
+/// `offset` is the offset from GMT, in intervals of 15 minutes.
+///
+/// If the date is invalid or not specified, the function returns `0`.
+fn make_timestamp(date: [i64; 6], offset: i8) -> Timestamp {
+	let [year, month, day, hour, min, sec] = date;
+	if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+		return 0;
+	}
+	let days = days_from_civil(year, month, day);
+	let secs = days * 86400 + hour * 3600 + min * 60 + sec - offset as i64 * 15 * 60;
+	secs.max(0) as _
+}
+
+/// Converts the 7 bytes date and time `buf`, used by directory records, into a timestamp.
+fn short_timestamp(buf: &[u8]) -> Timestamp {
+	let date = [
+		// Years since 1900
+		buf[0] as i64 + 1900,
+		buf[1] as _,
+		buf[2] as _,
+		buf[3] as _,
+		buf[4] as _,
+		buf[5] as _,
+	];
+	make_timestamp(date, buf[6] as _)
+}
+
+/// Converts the 17 bytes date and time `buf`, used by volume descriptors, into a timestamp.
+///
+/// The date and time are represented by ASCII digits, followed by the offset from GMT.
+fn long_timestamp(buf: &[u8]) -> Timestamp {
+	let digits = |range: core::ops::Range<usize>| {
+		buf[range].iter().try_fold(0i64, |n, c| {
+			c.is_ascii_digit().then(|| n * 10 + (c - b'0') as i64)
+		})
+	};
+	let mut date = [0; 6];
+	let fields = [0..4, 4..6, 6..8, 8..10, 10..12, 12..14];
+	for (field, range) in date.iter_mut().zip(fields) {
+		let Some(n) = digits(range) else {
+			return 0;
+		};
+		*field = n;
+	}
+	make_timestamp(date, buf[16] as _)
+}
+
+/// Reads the primary volume descriptor from the device.
+///
+/// If the device does not contain an ISO 9660 filesystem, the function returns `None`.
+fn read_primary_descriptor(io: &dyn DeviceIO) -> EResult<Option<Vec<u8>>> {
+	let dev_size = io.blocks_count().saturating_mul(io.block_size().get());
+	let mut buf = vec![0u8; SECTOR_SIZE as usize]?;
+	for i in DESCRIPTORS_START..(DESCRIPTORS_START + MAX_DESCRIPTORS) {
+		let off = i * SECTOR_SIZE;
+		if off + SECTOR_SIZE > dev_size {
+			break;
+		}
+		io.read_bytes(off, &mut buf)?;
+		if &buf[1..6] != STANDARD_ID {
+			break;
+		}
+		match buf[0] {
+			VD_PRIMARY => return Ok(Some(buf)),
+			VD_TERMINATOR => break,
+			_ => {}
+		}
+	}
+	Ok(None)
+}
+
+/// A file, as described by its directory records.
+struct Inode {
+	/// The file's flags.
+	flags: u8,
+	/// The extents storing the file's content, as pairs of sector and size in bytes.
+	extents: Vec<(u32, u32)>,
+	/// The timestamp of the recording of the file.
+	time: Timestamp,
+	/// Rock Ridge attributes.
+	rr: RockRidge,
+}
+
+impl Inode {
+	/// Returns the type of the file.
+	fn file_type(&self) -> FileType {
+		self.rr
+			.mode
+			.and_then(FileType::from_mode)
+			.unwrap_or(if self.flags & FLAG_DIRECTORY != 0 {
+				FileType::Directory
+			} else {
+				FileType::Regular
+			})
+	}
+
+	/// Returns the size of the file in bytes.
+	fn size(&self) -> u64 {
+		match &self.rr.link {
+			Some(link) => link.len() as _,
+			None => self.extents.iter().map(|(_, size)| *size as u64).sum(),
+		}
+	}
+
+	/// Returns the status of the file.
+	fn stat(&self) -> Stat {
+		let file_type = self.file_type();
+		// Without Rock Ridge, files can be read and executed by everyone
+		let perms = self.rr.mode.unwrap_or(0o555) & 0o7777;
+		let nlink = self.rr.nlink.unwrap_or(match file_type {
+			FileType::Directory => 2,
+			_ => 1,
+		});
+		let (dev_major, dev_minor) = self.rr.rdev.unwrap_or_default();
+		let size = self.size();
+		Stat {
+			mode: file_type.to_mode() | perms,
+			nlink: nlink.try_into().unwrap_or(u16::MAX),
+			uid: self.rr.uid.unwrap_or(0),
+			gid: self.rr.gid.unwrap_or(0),
+			size,
+			blocks: size.div_ceil(512),
+			dev_major,
+			dev_minor,
+			ctime: self.rr.ctime.unwrap_or(self.time),
+			mtime: self.rr.mtime.unwrap_or(self.time),
+			atime: self.rr.atime.unwrap_or(self.time),
+		}
+	}
+
+	/// Reads the content of the regular file or symbolic link from offset `off` into `buf`.
+	///
+	/// The function returns the number of bytes read.
+	fn read_content(&self, fs: &Iso9660, off: u64, buf: &mut [u8]) -> EResult<usize> {
+		let file_type = self.file_type();
+		if let (FileType::Link, Some(link)) = (file_type, &self.rr.link) {
+			let link = link.get((off as usize)..).unwrap_or_default();
+			let len = min(buf.len(), link.len());
+			buf[..len].copy_from_slice(&link[..len]);
+			return Ok(len);
+		}
+		if file_type != FileType::Regular {
+			return Err(errno!(EINVAL));
+		}
+		let mut len = 0;
+		let mut extent_start = 0;
+		for (sector, size) in self.extents.iter().cloned() {
+			let extent_end = extent_start + size as u64;
+			let cur = off + len as u64;
+			if len < buf.len() && cur < extent_end {
+				let inner_off = cur - extent_start;
+				let l = min((extent_end - cur) as usize, buf.len() - len);
+				let dev_off = sector as u64 * SECTOR_SIZE + inner_off;
+				fs.io.read_bytes(dev_off, &mut buf[len..(len + l)])?;
+				len += l;
+			}
+			extent_start = extent_end;
+		}
+		Ok(len)
+	}
+}
+
+/// Operations on ISO 9660 nodes.
+#[derive(Debug)]
+struct Iso9660NodeOps;
+
+impl NodeOps for Iso9660NodeOps {
+	fn get_stat(&self, loc: &FileLocation) -> EResult<Stat> {
+		let fs = loc.get_filesystem().unwrap();
+		let fs = downcast_fs::<Iso9660>(&*fs);
+		Ok(fs.read_inode(loc.inode)?.stat())
+	}
+
+	fn set_stat(&self, _loc: &FileLocation, _set: StatSet) -> EResult<()> {
+		Err(errno!(EROFS))
+	}
+
+	fn read_content(&self, loc: &FileLocation, off: u64, buf: &mut [u8]) -> EResult<usize> {
+		let fs = loc.get_filesystem().unwrap();
+		let fs = downcast_fs::<Iso9660>(&*fs);
+		fs.read_inode(loc.inode)?.read_content(fs, off, buf)
+	}
+
+	fn write_content(&self, _loc: &FileLocation, _off: u64, _buf: &[u8]) -> EResult<usize> {
+		Err(errno!(EROFS))
+	}
+
+	fn truncate_content(&self, _loc: &FileLocation, _size: u64) -> EResult<()> {
+		Err(errno!(EROFS))
+	}
+
+	fn entry_by_name<'n>(
+		&self,
+		loc: &FileLocation,
+		name: &'n [u8],
+	) -> EResult<Option<(DirEntry<'n>, Box<dyn NodeOps>)>> {
+		let fs = loc.get_filesystem().unwrap();
+		let fs = downcast_fs::<Iso9660>(&*fs);
+		let mut iter = fs.dir_iter(loc.inode, 0)?;
+		while let Some(ent) = fs.next_entry(&mut iter)? {
+			if ent.name.as_ref() == name {
+				let ent = DirEntry {
+					inode: ent.inode,
+					entry_type: ent.entry_type,
+					name: Cow::Borrowed(name),
+				};
+				return Ok(Some((ent, Box::new(Iso9660NodeOps)?)));
+			}
+		}
+		Ok(None)
+	}
+
+	fn next_entry(
+		&self,
+		loc: &FileLocation,
+		off: u64,
+	) -> EResult<Option<(DirEntry<'static>, u64)>> {
+		let fs = loc.get_filesystem().unwrap();
+		let fs = downcast_fs::<Iso9660>(&*fs);
+		let mut iter = fs.dir_iter(loc.inode, off)?;
+		let ent = fs.next_entry(&mut iter)?;
+		Ok(ent.map(|ent| (ent, iter.offset())))
+	}
+
+	fn add_file(
+		&self,
+		_parent: &FileLocation,
+		_name: &[u8],
+		_stat: Stat,
+	) -> EResult<(INode, Box<dyn NodeOps>)> {
+		Err(errno!(EROFS))
+	}
+
+	fn link(&self, _parent: &FileLocation, _name: &[u8], _target: INode) -> EResult<()> {
+		Err(errno!(EROFS))
+	}
+
+	fn unlink(&self, _parent: &FileLocation, _name: &[u8]) -> EResult<()> {
+		Err(errno!(EROFS))
+	}
+
+	fn rename(
+		&self,
+		_old_parent: &FileLocation,
+		_old_name: &[u8],
+		_new_parent: &FileLocation,
+		_new_name: &[u8],
+		_flags: u32,
+	) -> EResult<()> {
+		Err(errno!(EROFS))
+	}
+
+	fn remove_node(&self, _loc: &FileLocation) -> EResult<()> {
+		Err(errno!(EROFS))
+	}
+}
+
+/// An instance of the ISO 9660 filesystem.
+struct Iso9660 {
+	/// The device on which the filesystem is located.
+	io: Arc<dyn DeviceIO>,
+	/// The size of the volume, in sectors.
+	volume_size: u32,
+	/// The root directory's inode number.
+	root: INode,
+	/// If Rock Ridge extensions are in use, the number of bytes to skip at the beginning of
+	/// System Use areas.
+	rock_ridge: Option<usize>,
+}
+
+impl Iso9660 {
+	/// Creates a new instance from the primary volume descriptor `pvd`.
+	fn new(pvd: &[u8], io: Arc<dyn DeviceIO>) -> EResult<Self> {
+		let block_size =
+			u16::from_le_bytes([pvd[PVD_BLOCK_SIZE_OFF], pvd[PVD_BLOCK_SIZE_OFF + 1]]);
+		if block_size as u64 != SECTOR_SIZE {
+			return Err(errno!(EINVAL));
+		}
+		let root = Record::parse(&pvd[PVD_ROOT_OFF..])?.ok_or_else(|| errno!(EUCLEAN))?;
+		let mut fs = Self {
+			io,
+			volume_size: le32(pvd, PVD_VOLUME_SIZE_OFF),
+			root: root.extent() as INode * SECTOR_SIZE,
+			rock_ridge: None,
+		};
+		// The `System Use` area of the root directory's `.` record tells whether Rock Ridge
+		// extensions are in use
+		let mut iter = DirIter::new(&fs, fs.root, SECTOR_SIZE, 0);
+		let (_, dot) = iter.next()?.ok_or_else(|| errno!(EUCLEAN))?;
+		if dot.flags() & FLAG_DIRECTORY == 0 {
+			return Err(errno!(EUCLEAN));
+		}
+		let rr = rock_ridge::detect(dot.system_use());
+		fs.rock_ridge = rr;
+		Ok(fs)
+	}
+
+	/// Reads the Rock Ridge attributes of the given record.
+	fn read_rock_ridge(&self, rec: &Record) -> EResult<RockRidge> {
+		match self.rock_ridge {
+			Some(skip) => RockRidge::read(self, rec.system_use(), skip),
+			None => Ok(RockRidge::default()),
+		}
+	}
+
+	/// Reads the inode with number `inode`.
+	///
+	/// If the inode does not exist, the function returns [`errno::ENOENT`].
+	fn read_inode(&self, inode: INode) -> EResult<Inode> {
+		let end = self.volume_size as u64 * SECTOR_SIZE;
+		if inode >= end {
+			return Err(errno!(ENOENT));
+		}
+		// Records of a file split across several extents are consecutive
+		let start = inode - inode % SECTOR_SIZE;
+		let mut iter = DirIter::new(self, start, end - start, inode % SECTOR_SIZE);
+		let (_, rec) = iter.next()?.ok_or_else(|| errno!(ENOENT))?;
+		let mut flags = rec.flags();
+		let mut inode_ = Inode {
+			flags,
+			extents: Vec::new(),
+			time: short_timestamp(rec.recording_time()),
+			rr: self.read_rock_ridge(&rec)?,
+		};
+		inode_.extents.push((rec.extent(), rec.data_len()))?;
+		while flags & FLAG_MULTI_EXTENT != 0 {
+			if inode_.extents.len() >= MAX_EXTENTS {
+				return Err(errno!(EUCLEAN));
+			}
+			let (_, rec) = iter.next()?.ok_or_else(|| errno!(EUCLEAN))?;
+			flags = rec.flags();
+			inode_.extents.push((rec.extent(), rec.data_len()))?;
+		}
+		Ok(inode_)
+	}
+
+	/// Returns an iterator over the records of the directory with number `inode`, starting at
+	/// offset `off`.
+	///
+	/// If the inode is not a directory, the function returns [`errno::ENOTDIR`].
+	fn dir_iter(&self, inode: INode, off: u64) -> EResult<DirIter> {
+		let inode_ = self.read_inode(inode)?;
+		if inode_.flags & FLAG_DIRECTORY == 0 {
+			return Err(errno!(ENOTDIR));
+		}
+		let (sector, size) = inode_.extents[0];
+		Ok(DirIter::new(
+			self,
+			sector as u64 * SECTOR_SIZE,
+			size as _,
+			off,
+		))
+	}
+
+	/// Returns the next entry of the directory iterated on by `iter`.
+	///
+	/// Associated files and relocated directories are skipped.
+	fn next_entry(&self, iter: &mut DirIter) -> EResult<Option<DirEntry<'static>>> {
+		loop {
+			let Some((off, rec)) = iter.next()? else {
+				return Ok(None);
+			};
+			let flags = rec.flags();
+			let ent = self.record_entry(off, &rec)?;
+			// Skip the records of the following extents of the file
+			let mut last_flags = flags;
+			while last_flags & FLAG_MULTI_EXTENT != 0 {
+				let Some((_, rec)) = iter.next()? else {
+					break;
+				};
+				last_flags = rec.flags();
+			}
+			if let Some(ent) = ent {
+				return Ok(Some(ent));
+			}
+		}
+	}
+
+	/// Returns the directory entry for the record `rec`, located at offset `off` on the device.
+	///
+	/// If the record must not appear in the directory's listing, the function returns `None`.
+	fn record_entry(&self, off: u64, rec: &Record) -> EResult<Option<DirEntry<'static>>> {
+		let flags = rec.flags();
+		if flags & FLAG_ASSOCIATED != 0 {
+			return Ok(None);
+		}
+		let rr = self.read_rock_ridge(rec)?;
+		if rr.relocated {
+			return Ok(None);
+		}
+		let id = rec.identifier();
+		let dir_sector = match (id, rr.child_link, rr.parent_link) {
+			// The parent of a relocated directory
+			(b"\x01", _, Some(sector)) => Some(sector),
+			// The placeholder of a relocated directory
+			(_, Some(sector), _) => Some(sector),
+			_ if flags & FLAG_DIRECTORY != 0 => Some(rec.extent()),
+			_ => None,
+		};
+		let (inode, entry_type) = match dir_sector {
+			// Directories are identified by their `.` record
+			Some(sector) => (sector as INode * SECTOR_SIZE, FileType::Directory),
+			None => {
+				let file_type = rr.mode.and_then(FileType::from_mode);
+				(off, file_type.unwrap_or(FileType::Regular))
+			}
+		};
+		let name = match (id, rr.name) {
+			(b"\0" | b"\x01", _) | (_, None) => dir::translate_name(id)?,
+			(_, Some(name)) => name,
+		};
+		Ok(Some(DirEntry {
+			inode,
+			entry_type: Some(entry_type),
+			name: Cow::Owned(name.into()),
+		}))
+	}
+}
+
+impl Filesystem for Iso9660 {
+	fn get_name(&self) -> &[u8] {
+		b"iso9660"
+	}
+
+	fn use_cache(&self) -> bool {
+		true
+	}
+
+	fn get_root_inode(&self) -> INode {
+		self.root
+	}
+
+	fn is_readonly(&self) -> bool {
+		true
+	}
+
+	fn get_stat(&self) -> EResult<Statfs> {
+		Ok(Statfs {
+			f_type: ISOFS_SUPER_MAGIC,
+			f_bsize: SECTOR_SIZE as _,
+			f_blocks: self.volume_size as _,
+			f_bfree: 0,
+			f_bavail: 0,
+			f_files: 0,
+			f_ffree: 0,
+			f_fsid: Default::default(),
+			f_namelen: MAX_NAME_LEN as _,
+			f_frsize: SECTOR_SIZE as _,
+			f_flags: 0,
+			f_spare: [0; 4],
+		})
+	}
+
+	fn node_from_inode(&self, inode: INode) -> EResult<Box<dyn NodeOps>> {
+		// Check the inode exists
+		self.read_inode(inode)?;
+		Ok(Box::new(Iso9660NodeOps)?)
+	}
+}
+
+impl fmt::Debug for Iso9660 {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		f.debug_struct("Iso9660")
+			.field("volume_size", &self.volume_size)
+			.field("rock_ridge", &self.rock_ridge.is_some())
+			.finish()
+	}
+}
+
+/// The ISO 9660 filesystem type.
+pub struct Iso9660FsType;
+
+impl FilesystemType for Iso9660FsType {
+	fn get_name(&self) -> &'static [u8] {
+		b"iso9660"
+	}
+
+	fn requires_device(&self) -> bool {
+		true
+	}
+
+	fn detect(&self, io: &dyn DeviceIO) -> EResult<bool> {
+		Ok(read_primary_descriptor(io)?.is_some())
+	}
+
+	fn load_filesystem(
+		&self,
+		io: Option<Arc<dyn DeviceIO>>,
+		_mountpath: PathBuf,
+		_readonly: bool,
+		data: &[u8],
+	) -> EResult<Arc<dyn Filesystem>> {
+		// No option is supported
+		parse_options(data, |_, _| Ok(false))?;
+		let io = io.ok_or_else(|| errno!(ENODEV))?;
+		let pvd = read_primary_descriptor(&*io)?.ok_or_else(|| errno!(EINVAL))?;
+		// The filesystem is always read-only
+		let fs = Iso9660::new(&pvd, io)?;
+		Ok(Arc::new(fs)? as _)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test_case]
+	fn timestamps() {
+		// 2024-03-15 10:20:30, GMT+1
+		assert_eq!(short_timestamp(&[124, 3, 15, 10, 20, 30, 4]), 1710494430);
+		assert_eq!(short_timestamp(&[70, 1, 1, 0, 0, 0, 0]), 0);
+		// Not specified
+		assert_eq!(short_timestamp(&[0; 7]), 0);
+		assert_eq!(long_timestamp(b"2024031510203000\x04"), 1710494430);
+		assert_eq!(long_timestamp(b"0000000000000000\0"), 0);
+	}
+
+	#[test_case]
+	fn names() {
+		assert_eq!(dir::translate_name(b"\0").unwrap().as_slice(), b".");
+		assert_eq!(dir::translate_name(b"\x01").unwrap().as_slice(), b"..");
+		assert_eq!(
+			dir::translate_name(b"README.TXT;1").unwrap().as_slice(),
+			b"readme.txt"
+		);
+		assert_eq!(dir::translate_name(b"FILE.;1").unwrap().as_slice(), b"file");
+		assert_eq!(dir::translate_name(b"DIR").unwrap().as_slice(), b"dir");
+	}
+}
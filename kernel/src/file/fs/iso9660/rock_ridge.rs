@@ -0,0 +1,259 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Rock Ridge extensions store POSIX attributes (permissions, owners, symbolic links, long names,
+//! etc...) in the System Use area of directory records, as System Use Sharing Protocol (SUSP)
+//! entries.
+//!
+//! When the System Use area of a record is too small, a `CE` entry locates a continuation area,
+//! where the following entries are stored.
+//!
+//! Directories nested deeper than ISO 9660 allows are relocated: the relocated directory is
+//! marked with a `RE` entry, while a file placeholder with a `CL` entry is left at its original
+//! location. The `..` record of the relocated directory has a `PL` entry locating its original
+//! parent.
+
+use super::{le32, long_timestamp, short_timestamp, Iso9660, SECTOR_SIZE};
+use crate::{
+	file::{
+		perm::{Gid, Uid},
+		Mode,
+	},
+	time::unit::Timestamp,
+};
+use utils::{collections::vec::Vec, errno, errno::EResult, vec};
+
+/// The maximum number of continuation areas followed for a single record.
+const MAX_CONTINUATIONS: usize = 16;
+
+/// `NM` flag: the entry refers to the current directory.
+const NM_CURRENT: u8 = 0x02;
+/// `NM` flag: the entry refers to the parent directory.
+const NM_PARENT: u8 = 0x04;
+
+/// Symbolic link component flag: the component continues in the next component record.
+const SL_CONTINUE: u8 = 0x01;
+/// Symbolic link component flag: the component is the current directory.
+const SL_CURRENT: u8 = 0x02;
+/// Symbolic link component flag: the component is the parent directory.
+const SL_PARENT: u8 = 0x04;
+/// Symbolic link component flag: the component is the root directory.
+const SL_ROOT: u8 = 0x08;
+
+/// `TF` flag: the creation time is recorded.
+const TF_CREATION: u8 = 0x01;
+/// `TF` flag: the modification time is recorded.
+const TF_MODIFY: u8 = 0x02;
+/// `TF` flag: the last access time is recorded.
+const TF_ACCESS: u8 = 0x04;
+/// `TF` flag: the attributes change time is recorded.
+const TF_ATTRIBUTES: u8 = 0x08;
+/// `TF` flag: the backup time is recorded.
+const TF_BACKUP: u8 = 0x10;
+/// `TF` flag: the expiration time is recorded.
+const TF_EXPIRATION: u8 = 0x20;
+/// `TF` flag: the effective time is recorded.
+const TF_EFFECTIVE: u8 = 0x40;
+/// `TF` flag: timestamps use the 17 bytes format instead of the 7 bytes format.
+const TF_LONG_FORM: u8 = 0x80;
+
+/// Returns the number of bytes to skip at the beginning of System Use areas if the System Use
+/// area `area` of the root directory's `.` record indicates Rock Ridge extensions are in use.
+///
+/// Otherwise, the function returns `None`.
+pub fn detect(area: &[u8]) -> Option<usize> {
+	match area {
+		[b'S', b'P', len, 1, 0xbe, 0xef, skip, ..] if *len >= 7 => Some(*skip as usize),
+		_ => None,
+	}
+}
+
+/// Attributes read from the Rock Ridge entries of a record.
+#[derive(Default)]
+pub struct RockRidge {
+	/// The file's mode.
+	pub mode: Option<Mode>,
+	/// The number of links to the file.
+	pub nlink: Option<u32>,
+	/// The owner's user ID.
+	pub uid: Option<Uid>,
+	/// The owner's group ID.
+	pub gid: Option<Gid>,
+	/// The major and minor numbers of the device, if the file is a device file.
+	pub rdev: Option<(u32, u32)>,
+	/// The name of the file.
+	pub name: Option<Vec<u8>>,
+	/// The target of the symbolic link.
+	pub link: Option<Vec<u8>>,
+	/// The timestamp of the last modification of the metadata.
+	pub ctime: Option<Timestamp>,
+	/// The timestamp of the last modification of the content.
+	pub mtime: Option<Timestamp>,
+	/// The timestamp of the last access.
+	pub atime: Option<Timestamp>,
+	/// If the record is a placeholder for a relocated directory, the sector of the directory.
+	pub child_link: Option<u32>,
+	/// If the record is the `..` of a relocated directory, the sector of the original parent.
+	pub parent_link: Option<u32>,
+	/// Tells whether the record is a relocated directory, which must not appear in its parent.
+	pub relocated: bool,
+
+	/// Tells whether a separator must be inserted before the next component of the symbolic
+	/// link's target.
+	link_sep: bool,
+}
+
+impl RockRidge {
+	/// Reads the entries of the System Use area `area`, and of its continuation areas.
+	///
+	/// `skip` is the number of bytes to skip at the beginning of `area`.
+	pub fn read(fs: &Iso9660, area: &[u8], skip: usize) -> EResult<Self> {
+		let mut rr = Self::default();
+		let mut cont = rr.parse(area.get(skip..).unwrap_or(&[]))?;
+		let mut count = 0;
+		while let Some((sector, off, len)) = cont {
+			count += 1;
+			if count > MAX_CONTINUATIONS || off as u64 + len as u64 > SECTOR_SIZE {
+				return Err(errno!(EUCLEAN));
+			}
+			let mut buf = vec![0u8; len as usize]?;
+			fs.io
+				.read_bytes(sector as u64 * SECTOR_SIZE + off as u64, &mut buf)?;
+			cont = rr.parse(&buf)?;
+		}
+		Ok(rr)
+	}
+
+	/// Parses the entries in `area`.
+	///
+	/// If a continuation area is present, the function returns its sector, offset and size.
+	fn parse(&mut self, mut area: &[u8]) -> EResult<Option<(u32, u32, u32)>> {
+		let mut cont = None;
+		while area.len() >= 4 {
+			let len = area[2] as usize;
+			// Entries that do not fit are ignored, as trailing padding
+			if len < 4 || len > area.len() {
+				break;
+			}
+			let (ent, rest) = area.split_at(len);
+			area = rest;
+			let data = &ent[4..];
+			match &ent[..2] {
+				b"CE" if data.len() >= 24 => {
+					cont = Some((le32(data, 0), le32(data, 8), le32(data, 16)));
+				}
+				b"ST" => break,
+				b"PX" if data.len() >= 32 => {
+					self.mode = Some(le32(data, 0));
+					self.nlink = Some(le32(data, 8));
+					self.uid = Some(le32(data, 16) as _);
+					self.gid = Some(le32(data, 24) as _);
+				}
+				b"PN" if data.len() >= 16 => {
+					let high = le32(data, 0);
+					let low = le32(data, 8);
+					// Some implementations store the whole device number in the low part
+					self.rdev = if high == 0 && low & !0xff != 0 {
+						Some((low >> 8, low & 0xff))
+					} else {
+						Some((high, low))
+					};
+				}
+				// A name split across several entries is concatenated
+				b"NM" if !data.is_empty() => {
+					if data[0] & (NM_CURRENT | NM_PARENT) == 0 {
+						let name = self.name.get_or_insert_with(Vec::new);
+						name.extend_from_slice(&data[1..])?;
+					}
+				}
+				b"SL" if !data.is_empty() => self.parse_link(&data[1..])?,
+				b"TF" if !data.is_empty() => self.parse_timestamps(data[0], &data[1..]),
+				b"CL" if data.len() >= 8 => self.child_link = Some(le32(data, 0)),
+				b"PL" if data.len() >= 8 => self.parent_link = Some(le32(data, 0)),
+				b"RE" => self.relocated = true,
+				_ => {}
+			}
+		}
+		Ok(cont)
+	}
+
+	/// Parses the component records `comps` of a `SL` entry, appending them to the target of the
+	/// symbolic link.
+	fn parse_link(&mut self, mut comps: &[u8]) -> EResult<()> {
+		let link = self.link.get_or_insert_with(Vec::new);
+		while comps.len() >= 2 {
+			let flags = comps[0];
+			let len = comps[1] as usize;
+			let Some(content) = comps.get(2..(2 + len)) else {
+				return Err(errno!(EUCLEAN));
+			};
+			comps = &comps[(2 + len)..];
+			if flags & SL_ROOT != 0 {
+				link.push(b'/')?;
+				self.link_sep = false;
+				continue;
+			}
+			if self.link_sep {
+				link.push(b'/')?;
+			}
+			let content: &[u8] = match flags & (SL_CURRENT | SL_PARENT) {
+				SL_CURRENT => b".",
+				SL_PARENT => b"..",
+				_ => content,
+			};
+			link.extend_from_slice(content)?;
+			self.link_sep = flags & SL_CONTINUE == 0;
+		}
+		Ok(())
+	}
+
+	/// Parses the timestamps `stamps` of a `TF` entry with the given `flags`.
+	fn parse_timestamps(&mut self, flags: u8, stamps: &[u8]) {
+		let long = flags & TF_LONG_FORM != 0;
+		let mut stamps = stamps.chunks_exact(if long { 17 } else { 7 });
+		// Timestamps are stored in this order, if present
+		let kinds = [
+			TF_CREATION,
+			TF_MODIFY,
+			TF_ACCESS,
+			TF_ATTRIBUTES,
+			TF_BACKUP,
+			TF_EXPIRATION,
+			TF_EFFECTIVE,
+		];
+		for kind in kinds {
+			if flags & kind == 0 {
+				continue;
+			}
+			let Some(stamp) = stamps.next() else {
+				break;
+			};
+			let ts = if long {
+				long_timestamp(stamp)
+			} else {
+				short_timestamp(stamp)
+			};
+			match kind {
+				TF_MODIFY => self.mtime = Some(ts),
+				TF_ACCESS => self.atime = Some(ts),
+				TF_ATTRIBUTES => self.ctime = Some(ts),
+				_ => {}
+			}
+		}
+	}
+}
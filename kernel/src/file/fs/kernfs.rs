@@ -24,11 +24,17 @@
 //!
 //! This module implements utilities for kernfs.
 
-use crate::file::{fs::NodeOps, DirEntry, FileLocation, FileType, INode, Stat};
+use crate::file::{
+	fs::NodeOps,
+	perm::{Gid, Uid},
+	DirEntry, FileLocation, FileType, INode, Mode, Stat,
+};
 use core::{
 	cmp::min,
 	fmt,
 	fmt::{Debug, Write},
+	str,
+	str::FromStr,
 };
 use utils::{
 	boxed::Box,
@@ -103,6 +109,11 @@ impl<N: NodeOps> NodeStorage<N> {
 	pub fn remove_node(&mut self, inode: INode) -> Option<N> {
 		self.0.get_mut(inode as usize - 1).and_then(Option::take)
 	}
+
+	/// Returns an iterator over the stored nodes.
+	pub fn iter(&self) -> impl Iterator<Item = &N> {
+		self.0.iter().flatten()
+	}
 }
 
 /// Writer for [`format_content_args`].
@@ -186,6 +197,76 @@ impl NodeOps for StaticLink {
 	}
 }
 
+/// Parses a value written to a kernfs node.
+///
+/// Surrounding whitespaces, such as the newline appended by `echo`, are ignored.
+///
+/// If the value is invalid, the function returns [`errno::EINVAL`].
+pub fn parse_written<T: FromStr>(buf: &[u8]) -> EResult<T> {
+	str::from_utf8(buf)
+		.ok()
+		.and_then(|s| s.trim().parse().ok())
+		.ok_or_else(|| errno!(EINVAL))
+}
+
+/// Handler reading the content of a [`Tunable`].
+pub type TunableRead<T> = fn(&T, u64, &mut [u8]) -> EResult<usize>;
+/// Handler applying the content written to a [`Tunable`].
+pub type TunableWrite<T> = fn(&T, &[u8]) -> EResult<()>;
+
+/// A regular file exposing a value that can be read and written, such as a kernel tunable.
+///
+/// Permissions are checked against `mode` and `owner` when the file is opened. Handlers may
+/// perform additional checks.
+///
+/// `T` is the type of the data passed to the handlers.
+#[derive(Debug)]
+pub struct Tunable<T: 'static + Debug = ()> {
+	/// The file's permissions.
+	pub mode: Mode,
+	/// The user ID and group ID of the file's owner.
+	pub owner: (Uid, Gid),
+	/// Data passed to the handlers.
+	pub data: T,
+	/// The handler to read the file, taking the same arguments as [`NodeOps::read_content`].
+	///
+	/// If `None`, the file cannot be read.
+	pub read: Option<TunableRead<T>>,
+	/// The handler applying the content written to the file.
+	///
+	/// The whole buffer is passed at once, regardless of the offset.
+	///
+	/// If `None`, the file cannot be written.
+	pub write: Option<TunableWrite<T>>,
+}
+
+impl<T: 'static + Debug> NodeOps for Tunable<T> {
+	fn get_stat(&self, _loc: &FileLocation) -> EResult<Stat> {
+		Ok(Stat {
+			mode: FileType::Regular.to_mode() | self.mode,
+			uid: self.owner.0,
+			gid: self.owner.1,
+			..Default::default()
+		})
+	}
+
+	fn read_content(&self, _loc: &FileLocation, off: u64, buf: &mut [u8]) -> EResult<usize> {
+		let read = self.read.ok_or_else(|| errno!(EINVAL))?;
+		read(&self.data, off, buf)
+	}
+
+	fn write_content(&self, _loc: &FileLocation, _off: u64, buf: &[u8]) -> EResult<usize> {
+		let write = self.write.ok_or_else(|| errno!(EINVAL))?;
+		write(&self.data, buf)?;
+		Ok(buf.len())
+	}
+
+	fn truncate_content(&self, _loc: &FileLocation, _size: u64) -> EResult<()> {
+		// Ignored, so that the file can be opened with `O_TRUNC`
+		Ok(())
+	}
+}
+
 /// A builder for an entry of a [`StaticDir`].
 ///
 /// `T` is the type of the parameter passed to `init`.
@@ -246,7 +327,7 @@ impl<T: 'static + Clone + Debug> StaticDir<T> {
 		Ok(Some((
 			DirEntry {
 				inode: 0,
-				entry_type: e.entry_type,
+				entry_type: Some(e.entry_type),
 				name: Cow::Borrowed(name),
 			},
 			ops,
@@ -262,7 +343,7 @@ impl<T: 'static + Clone + Debug> StaticDir<T> {
 		Ok(Some((
 			DirEntry {
 				inode: 0,
-				entry_type: e.entry_type,
+				entry_type: Some(e.entry_type),
 				name: Cow::Borrowed(e.name),
 			},
 			(off + 1) as _,
@@ -297,6 +378,17 @@ impl<T: 'static + Clone + Debug> NodeOps for StaticDir<T> {
 
 #[cfg(test)]
 mod test {
+	use super::*;
+
+	#[test_case]
+	fn written_values() {
+		assert_eq!(parse_written::<i16>(b"-1000\n").unwrap(), -1000);
+		assert_eq!(parse_written::<u32>(b" 42 ").unwrap(), 42);
+		assert!(parse_written::<u32>(b"").is_err());
+		assert!(parse_written::<u32>(b"abc").is_err());
+		assert!(parse_written::<u32>(&[0xff, b'1']).is_err());
+	}
+
 	#[test_case]
 	fn content_chunks() {
 		let val = 123;
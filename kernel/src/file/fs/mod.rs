@@ -21,38 +21,77 @@
 
 pub mod ext2;
 pub mod initramfs;
+pub mod iso9660;
 pub mod kernfs;
 pub mod proc;
+pub mod squashfs;
+pub mod sys;
 pub mod tmp;
 
 use super::{
 	perm::{Gid, Uid},
+	vfs,
+	vfs::mountpoint,
 	DirEntry, FileLocation, INode, Mode, Stat,
 };
-use crate::{device::DeviceIO, time::unit::Timestamp};
+use crate::{
+	device::DeviceIO,
+	syscall::poll::{POLLIN, POLLOUT},
+	time::unit::Timestamp,
+};
 use core::{any::Any, ffi::c_int, fmt::Debug};
 use utils::{
 	boxed::Box,
-	collections::{hashmap::HashMap, path::PathBuf, string::String},
+	collections::{hashmap::HashMap, path::PathBuf, string::String, vec::Vec},
 	errno,
-	errno::{EResult, ENOTDIR},
+	errno::{EResult, Errno, ENOTDIR},
 	lock::Mutex,
 	ptr::arc::Arc,
+	DisplayableStr,
 };
 
+/// [`Statfs`] mount flag: the filesystem is mounted read-only.
+pub const ST_RDONLY: u32 = 0x0001;
+/// [`Statfs`] mount flag: setuid and setgid bits are ignored.
+pub const ST_NOSUID: u32 = 0x0002;
+/// [`Statfs`] mount flag: device files cannot be accessed.
+pub const ST_NODEV: u32 = 0x0004;
+/// [`Statfs`] mount flag: files cannot be executed.
+pub const ST_NOEXEC: u32 = 0x0008;
+/// [`Statfs`] mount flag: writes are synchronous.
+pub const ST_SYNCHRONOUS: u32 = 0x0010;
+/// [`Statfs`] mount flag: `f_flags` is filled in. Always set, as on Linux.
+pub const ST_VALID: u32 = 0x0020;
+/// [`Statfs`] mount flag: mandatory locking is permitted.
+pub const ST_MANDLOCK: u32 = 0x0040;
+/// [`Statfs`] mount flag: access timestamps are not updated.
+pub const ST_NOATIME: u32 = 0x0400;
+/// [`Statfs`] mount flag: directory access timestamps are not updated.
+pub const ST_NODIRATIME: u32 = 0x0800;
+/// [`Statfs`] mount flag: access timestamps are updated relative to mtime/ctime.
+pub const ST_RELATIME: u32 = 0x1000;
+
+/// [`NodeOps::rename`] flag: if the destination exists, fail instead of replacing it.
+pub const RENAME_NOREPLACE: u32 = 1;
+/// [`NodeOps::rename`] flag: exchange the source and the destination, which must both exist.
+pub const RENAME_EXCHANGE: u32 = 2;
+
 /// Used in the f_fsid field of [`Statfs`].
 ///
 /// It is currently unused.
 #[repr(C)]
-#[derive(Debug, Default)]
+#[derive(Clone, Debug, Default)]
 struct Fsid {
 	/// Unused.
 	_val: [c_int; 2],
 }
 
 /// Statistics about a filesystem.
+///
+/// This structure has the layout of `struct statfs64`, used by the `statfs64` and `fstatfs64`
+/// system calls.
 #[repr(C)]
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct Statfs {
 	/// Type of filesystem.
 	f_type: u32,
@@ -76,6 +115,91 @@ pub struct Statfs {
 	f_frsize: u32,
 	/// Mount flags of filesystem.
 	f_flags: u32,
+	/// Padding.
+	f_spare: [u32; 4],
+}
+
+/// Statistics about a filesystem, with the layout of `struct statfs`, used by the `statfs` and
+/// `fstatfs` system calls.
+#[repr(C)]
+#[derive(Debug)]
+pub struct Statfs32 {
+	/// Type of filesystem.
+	f_type: u32,
+	/// Optimal transfer block size.
+	f_bsize: u32,
+	/// Total data blocks in filesystem.
+	f_blocks: u32,
+	/// Free blocks in filesystem.
+	f_bfree: u32,
+	/// Free blocks available to unprivileged user.
+	f_bavail: u32,
+	/// Total inodes in filesystem.
+	f_files: u32,
+	/// Free inodes in filesystem.
+	f_ffree: u32,
+	/// Filesystem ID.
+	f_fsid: Fsid,
+	/// Maximum length of filenames.
+	f_namelen: u32,
+	/// Fragment size.
+	f_frsize: u32,
+	/// Mount flags of filesystem.
+	f_flags: u32,
+	/// Padding.
+	f_spare: [u32; 4],
+}
+
+impl Statfs {
+	/// Fills `f_flags` from the given mountpoint flags (see [`mountpoint::FLAG_RDONLY`] and
+	/// others).
+	///
+	/// Filesystems leave `f_flags` to zero since mount flags are a property of the mountpoint,
+	/// not of the filesystem.
+	pub fn set_mount_flags(&mut self, flags: u32) {
+		self.f_flags = statfs_flags(flags);
+	}
+}
+
+/// Converts mountpoint flags into the `ST_*` flags reported by `statfs`.
+fn statfs_flags(flags: u32) -> u32 {
+	const MAP: [(u32, u32); 9] = [
+		(mountpoint::FLAG_RDONLY, ST_RDONLY),
+		(mountpoint::FLAG_NOSUID, ST_NOSUID),
+		(mountpoint::FLAG_NODEV, ST_NODEV),
+		(mountpoint::FLAG_NOEXEC, ST_NOEXEC),
+		(mountpoint::FLAG_SYNCHRONOUS, ST_SYNCHRONOUS),
+		(mountpoint::FLAG_MANDLOCK, ST_MANDLOCK),
+		(mountpoint::FLAG_NOATIME, ST_NOATIME),
+		(mountpoint::FLAG_NODIRATIME, ST_NODIRATIME),
+		(mountpoint::FLAG_RELATIME, ST_RELATIME),
+	];
+	MAP.iter()
+		.filter(|(flag, _)| flags & flag != 0)
+		.fold(ST_VALID, |res, (_, st)| res | st)
+}
+
+impl TryFrom<&Statfs> for Statfs32 {
+	type Error = Errno;
+
+	/// Converts the statistics, failing with [`errno::EOVERFLOW`] if a value does not fit.
+	fn try_from(stat: &Statfs) -> EResult<Self> {
+		let conv = |val: i64| val.try_into().map_err(|_| errno!(EOVERFLOW));
+		Ok(Self {
+			f_type: stat.f_type,
+			f_bsize: stat.f_bsize,
+			f_blocks: conv(stat.f_blocks)?,
+			f_bfree: conv(stat.f_bfree)?,
+			f_bavail: conv(stat.f_bavail)?,
+			f_files: conv(stat.f_files)?,
+			f_ffree: conv(stat.f_ffree)?,
+			f_fsid: stat.f_fsid.clone(),
+			f_namelen: stat.f_namelen,
+			f_frsize: stat.f_frsize,
+			f_flags: stat.f_flags,
+			f_spare: [0; 4],
+		})
+	}
 }
 
 /// A set of attributes to modify on a file's status.
@@ -156,6 +280,20 @@ pub trait NodeOps: Debug {
 		Err(errno!(EINVAL))
 	}
 
+	/// If the node is a *magic* symbolic link, returns the entry it points to.
+	///
+	/// Path resolution follows magic links to their target entry directly instead of resolving
+	/// the path they contain, so that the target is reached even if it has been removed or is
+	/// outside of the root directory.
+	///
+	/// `loc` is the location of the file.
+	///
+	/// The default implementation of this function returns `None`.
+	fn magic_link(&self, loc: &FileLocation) -> EResult<Option<Arc<vfs::Entry>>> {
+		let _ = loc;
+		Ok(None)
+	}
+
 	/// Changes the size of the file, truncating its content if necessary.
 	///
 	/// If `size` is greater than or equals to the current size of the file, the function does
@@ -167,6 +305,26 @@ pub trait NodeOps: Debug {
 		Err(errno!(EINVAL))
 	}
 
+	/// Deallocates the content of the file in the range of `len` bytes starting at offset `off`,
+	/// which then reads as zeros. The size of the file does not change.
+	///
+	/// The default implementation of this function returns [`errno::EOPNOTSUPP`].
+	fn punch_hole(&self, loc: &FileLocation, off: u64, len: u64) -> EResult<()> {
+		let _ = (loc, off, len);
+		Err(errno!(EOPNOTSUPP))
+	}
+
+	/// Returns the events that occurred on the node, among `mask`.
+	///
+	/// `loc` is the location of the file.
+	///
+	/// The default implementation of this function reports the node as always ready for reading
+	/// and writing.
+	fn poll(&self, loc: &FileLocation, mask: u32) -> EResult<u32> {
+		let _ = loc;
+		Ok(mask & (POLLIN | POLLOUT))
+	}
+
 	/// Returns the directory entry with the given `name`, along with its offset and the handle of
 	/// the file.
 	///
@@ -189,6 +347,10 @@ pub trait NodeOps: Debug {
 	///
 	/// The second returned value is the offset to the next entry.
 	///
+	/// The type of the returned entry is to be filled only if it is cheaply available (for example
+	/// if it is stored in the directory itself). Otherwise, it must be `None` instead of being
+	/// retrieved from the file's status: the caller then decides whether it needs it.
+	///
 	/// If no entry is left, the function returns `None`.
 	///
 	/// If the node is not a directory, the function returns [`ENOTDIR`].
@@ -232,6 +394,9 @@ pub trait NodeOps: Debug {
 	/// - `name` is the name of the hard link to add.
 	/// - `stat` is the status of the file to add.
 	///
+	/// The links count of the new file is set to `stat.nlink`. The links count of `parent` must
+	/// not be updated, as this is done by the VFS.
+	///
 	/// On success, the function returns the allocated [`INode`] together with the new file's
 	/// handle.
 	///
@@ -253,6 +418,8 @@ pub trait NodeOps: Debug {
 	/// - `name` is the name of the hard link to add.
 	/// - `target` is the inode the link points to.
 	///
+	/// The links count of `target` must not be updated, as this is done by the VFS.
+	///
 	/// If this feature is not supported by the filesystem, the function returns
 	/// an error.
 	///
@@ -268,8 +435,10 @@ pub trait NodeOps: Debug {
 	/// - `parent` is the parent directory.
 	/// - `name` is the name of the hard link to remove.
 	///
-	/// On success, the function returns the number of links to the target node left, along with
-	/// the target inode.
+	/// If the target is a non-empty directory, the function returns [`errno::ENOTEMPTY`].
+	///
+	/// The links count of the target and `parent` must not be updated, as this is done by the
+	/// VFS. The target must not be removed either, even if no link to it is left.
 	///
 	/// If this feature is not supported by the filesystem, the function returns
 	/// an error.
@@ -280,6 +449,68 @@ pub trait NodeOps: Debug {
 		Err(errno!(ENOTDIR))
 	}
 
+	/// Moves a hard link from a directory to another, on the same filesystem.
+	///
+	/// Arguments:
+	/// - `old_parent` is the location of the directory containing the link to move.
+	/// - `old_name` is the name of the link to move.
+	/// - `new_parent` is the location of the destination directory.
+	/// - `new_name` is the name of the link in the destination directory.
+	/// - `flags` is a combination of [`RENAME_NOREPLACE`] and [`RENAME_EXCHANGE`].
+	///
+	/// If an entry named `new_name` already exists in `new_parent`, it is atomically replaced,
+	/// unless [`RENAME_NOREPLACE`] is set, in which case the function returns
+	/// [`errno::EEXIST`]. If the replaced entry is a non-empty directory, the function returns
+	/// [`errno::ENOTEMPTY`].
+	///
+	/// If [`RENAME_EXCHANGE`] is set, both entries are exchanged instead. If `new_name` does not
+	/// exist, the function returns [`errno::ENOENT`].
+	///
+	/// If a moved file is a directory, its `..` entry is updated to point to its new parent. The
+	/// links count of the parents and of the replaced file must not be updated, as this is done by
+	/// the VFS.
+	///
+	/// The caller is responsible for ensuring the operation does not create a cycle (moving a
+	/// directory into one of its descendants) and that a replaced file has the same type as the
+	/// moved one (directory or not).
+	///
+	/// The default implementation of this function returns an error.
+	fn rename(
+		&self,
+		old_parent: &FileLocation,
+		old_name: &[u8],
+		new_parent: &FileLocation,
+		new_name: &[u8],
+		flags: u32,
+	) -> EResult<()> {
+		let _ = (old_parent, old_name, new_parent, new_name, flags);
+		Err(errno!(ENOTDIR))
+	}
+
+	/// Adds `delta` to the number of hard links to the node, then returns the new count.
+	///
+	/// Links counts are maintained by the VFS, which calls this function after each operation
+	/// adding or removing a link. Filesystems must implement it atomically relative to other
+	/// operations on the node.
+	///
+	/// If the count would overflow, the function returns [`errno::EMLINK`]. If it would underflow,
+	/// it is set to zero.
+	///
+	/// The default implementation of this function uses [`Self::get_stat`] and
+	/// [`Self::set_stat`].
+	fn adjust_nlink(&self, loc: &FileLocation, delta: i16) -> EResult<u16> {
+		let stat = self.get_stat(loc)?;
+		let nlink = adjusted_nlink(stat.nlink, delta)?;
+		self.set_stat(
+			loc,
+			StatSet {
+				nlink: Some(nlink),
+				..Default::default()
+			},
+		)?;
+		Ok(nlink)
+	}
+
 	/// Removes a file from the filesystem.
 	///
 	/// If the file to be removed is a non-empty directory, the function returns
@@ -290,6 +521,148 @@ pub trait NodeOps: Debug {
 		let _ = loc;
 		Err(errno!(ENOTDIR))
 	}
+
+	/// Returns the value of the extended attribute `name` of the file.
+	///
+	/// `name` includes the namespace prefix (example: `user.foo`).
+	///
+	/// If the attribute does not exist, the function returns [`errno::ENODATA`].
+	///
+	/// The default implementation returns [`errno::EOPNOTSUPP`].
+	fn get_xattr(&self, loc: &FileLocation, name: &[u8]) -> EResult<Vec<u8>> {
+		let _ = (loc, name);
+		Err(errno!(EOPNOTSUPP))
+	}
+
+	/// Returns the names of the extended attributes of the file, each followed by a nul byte.
+	///
+	/// The default implementation returns [`errno::EOPNOTSUPP`].
+	fn list_xattr(&self, loc: &FileLocation) -> EResult<Vec<u8>> {
+		let _ = loc;
+		Err(errno!(EOPNOTSUPP))
+	}
+
+	/// Sets the value of the extended attribute `name` of the file.
+	///
+	/// Arguments:
+	/// - `loc` is the location of the file
+	/// - `name` is the name of the attribute, including the namespace prefix
+	/// - `value` is the new value of the attribute
+	/// - `flags` is a combination of [`XATTR_CREATE`] and [`XATTR_REPLACE`]
+	///
+	/// If [`XATTR_CREATE`] is set and the attribute already exists, the function returns
+	/// [`errno::EEXIST`]. If [`XATTR_REPLACE`] is set and the attribute does not exist, the
+	/// function returns [`errno::ENODATA`].
+	///
+	/// The default implementation returns [`errno::EOPNOTSUPP`].
+	fn set_xattr(
+		&self,
+		loc: &FileLocation,
+		name: &[u8],
+		value: &[u8],
+		flags: c_int,
+	) -> EResult<()> {
+		let _ = (loc, name, value, flags);
+		Err(errno!(EOPNOTSUPP))
+	}
+
+	/// Removes the extended attribute `name` of the file.
+	///
+	/// If the attribute does not exist, the function returns [`errno::ENODATA`].
+	///
+	/// The default implementation returns [`errno::EOPNOTSUPP`].
+	fn remove_xattr(&self, loc: &FileLocation, name: &[u8]) -> EResult<()> {
+		let _ = (loc, name);
+		Err(errno!(EOPNOTSUPP))
+	}
+}
+
+/// [`NodeOps::set_xattr`] flag: fail if the attribute already exists.
+pub const XATTR_CREATE: c_int = 1;
+/// [`NodeOps::set_xattr`] flag: fail if the attribute does not exist.
+pub const XATTR_REPLACE: c_int = 2;
+
+/// Returns the links count `nlink` with `delta` added.
+///
+/// This is a helper for implementations of [`NodeOps::adjust_nlink`]. It also allows checking a
+/// count can be increased before modifying a directory.
+pub fn adjusted_nlink(nlink: u16, delta: i16) -> EResult<u16> {
+	match nlink.checked_add_signed(delta) {
+		Some(nlink) => Ok(nlink),
+		None if delta > 0 => Err(errno!(EMLINK)),
+		None => Ok(0),
+	}
+}
+
+/// The action to take when a filesystem detects corruption in its structures.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ErrorPolicy {
+	/// Continue as if nothing happened.
+	Continue,
+	/// Switch the filesystem to read-only, to prevent further damage.
+	RemountRo,
+	/// Trigger a kernel panic.
+	Panic,
+}
+
+impl ErrorPolicy {
+	/// Returns the name of the policy, as used in mount options.
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			Self::Continue => "continue",
+			Self::RemountRo => "remount-ro",
+			Self::Panic => "panic",
+		}
+	}
+
+	/// Returns the policy with the given name, as used in mount options.
+	pub fn from_name(name: &[u8]) -> Option<Self> {
+		match name {
+			b"continue" => Some(Self::Continue),
+			b"remount-ro" => Some(Self::RemountRo),
+			b"panic" => Some(Self::Panic),
+			_ => None,
+		}
+	}
+}
+
+/// Parses the filesystem-specific options `data` passed to the `mount` system call.
+///
+/// Options are separated by commas. Each option is either a name alone, or a name and a value
+/// separated by `=`. For each option, `f` is called with its name and value, and returns whether
+/// the option is supported.
+///
+/// If an option is not supported, the function returns [`errno::EINVAL`].
+pub fn parse_options<F: FnMut(&[u8], Option<&[u8]>) -> EResult<bool>>(
+	data: &[u8],
+	mut f: F,
+) -> EResult<()> {
+	for opt in data.split(|b| *b == b',').filter(|opt| !opt.is_empty()) {
+		let (name, value) = match opt.iter().position(|b| *b == b'=') {
+			Some(i) => (&opt[..i], Some(&opt[(i + 1)..])),
+			None => (opt, None),
+		};
+		if !f(name, value)? {
+			crate::log!(
+				Vfs,
+				Warn,
+				"unsupported mount option `{}`",
+				DisplayableStr(name)
+			);
+			return Err(errno!(EINVAL));
+		}
+	}
+	Ok(())
+}
+
+/// Parses the numeric value of a mount option, written in the given `radix`.
+///
+/// If the value is missing or invalid, the function returns [`errno::EINVAL`].
+pub fn parse_option_num(value: Option<&[u8]>, radix: u32) -> EResult<u64> {
+	value
+		.and_then(|value| core::str::from_utf8(value).ok())
+		.and_then(|value| u64::from_str_radix(value, radix).ok())
+		.ok_or_else(|| errno!(EINVAL))
 }
 
 /// A filesystem.
@@ -306,12 +679,61 @@ pub trait Filesystem: Any + Debug {
 	/// Returns statistics about the filesystem.
 	fn get_stat(&self) -> EResult<Statfs>;
 
+	/// Tells whether the filesystem is read-only.
+	///
+	/// A filesystem mounted in read-write may become read-only after an error has been detected
+	/// in its structures.
+	///
+	/// The default implementation returns `false`.
+	fn is_readonly(&self) -> bool {
+		false
+	}
+
+	/// Returns the action taken by the filesystem when it detects an error in its structures, if
+	/// it checks for errors.
+	///
+	/// The default implementation returns `None`.
+	fn get_error_policy(&self) -> Option<ErrorPolicy> {
+		None
+	}
+
 	/// Returns the node handle for the given `inode`.
 	///
 	/// If the node does not exist, the function returns [`errno::ENOENT`].
 	fn node_from_inode(&self, inode: INode) -> EResult<Box<dyn NodeOps>>;
+
+	/// Encodes a handle allowing to find the node with the given `inode` again later, without
+	/// resolving its path.
+	///
+	/// The handle must remain valid across remounts of the filesystem.
+	///
+	/// On success, the function returns the type of the handle, along with its content.
+	///
+	/// The default implementation returns [`errno::EOPNOTSUPP`].
+	fn encode_handle(&self, inode: INode) -> EResult<(c_int, Vec<u8>)> {
+		let _ = inode;
+		Err(errno!(EOPNOTSUPP))
+	}
+
+	/// Decodes a handle returned by [`Self::encode_handle`], then returns the inode of the node
+	/// it refers to.
+	///
+	/// Arguments:
+	/// - `handle_type` is the type of the handle
+	/// - `handle` is the content of the handle
+	///
+	/// If the node does not exist anymore, the function returns [`errno::ESTALE`].
+	///
+	/// The default implementation returns [`errno::EOPNOTSUPP`].
+	fn decode_handle(&self, handle_type: c_int, handle: &[u8]) -> EResult<INode> {
+		let _ = (handle_type, handle);
+		Err(errno!(EOPNOTSUPP))
+	}
 }
 
+/// File handle type: 32 bits inode number followed by a 32 bits generation number.
+pub const FILEID_INO32_GEN: c_int = 1;
+
 /// Downcasts the given `fs` into `F`.
 ///
 /// If the filesystem type do not match, the function panics.
@@ -324,6 +746,11 @@ pub trait FilesystemType {
 	/// Returns the name of the filesystem.
 	fn get_name(&self) -> &'static [u8];
 
+	/// Tells whether the filesystem is loaded from a device.
+	///
+	/// If not, the source of the mount is only a name and does not need to exist.
+	fn requires_device(&self) -> bool;
+
 	/// Tells whether the given IO interface has the current filesystem.
 	///
 	/// `io` is the IO interface.
@@ -335,11 +762,14 @@ pub trait FilesystemType {
 	/// - `io` is the IO interface.
 	/// - `mountpath` is the path on which the filesystem is mounted.
 	/// - `readonly` tells whether the filesystem is mounted in read-only.
+	/// - `data` is the string of filesystem-specific options, to be parsed with [`parse_options`].
+	///   It is empty if no option is given.
 	fn load_filesystem(
 		&self,
 		io: Option<Arc<dyn DeviceIO>>,
 		mountpath: PathBuf,
 		readonly: bool,
+		data: &[u8],
 	) -> EResult<Arc<dyn Filesystem>>;
 }
 
@@ -386,6 +816,46 @@ pub fn register_defaults() -> EResult<()> {
 	register(ext2::Ext2FsType {})?;
 	register(tmp::TmpFsType {})?;
 	register(proc::ProcFsType {})?;
-	// TODO sysfs
+	register(squashfs::SquashFsType {})?;
+	register(iso9660::Iso9660FsType {})?;
+	register(sys::SysFsType {})?;
 	Ok(())
 }
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test_case]
+	fn statfs_mount_flags() {
+		assert_eq!(statfs_flags(0), ST_VALID);
+		assert_eq!(
+			statfs_flags(
+				mountpoint::FLAG_RDONLY | mountpoint::FLAG_NOEXEC | mountpoint::FLAG_NOATIME
+			),
+			ST_VALID | ST_RDONLY | ST_NOEXEC | ST_NOATIME
+		);
+		// Flags without a `statfs` equivalent are not reported
+		assert_eq!(statfs_flags(mountpoint::FLAG_SILENT), ST_VALID);
+	}
+
+	#[test_case]
+	fn mount_options() {
+		let mut opts = Vec::new();
+		parse_options(b"ro,,size=64k,mode=", |name, value| {
+			opts.push((name.len(), value.map(<[u8]>::len))).unwrap();
+			Ok(true)
+		})
+		.unwrap();
+		assert_eq!(opts.as_slice(), &[(2, None), (4, Some(3)), (4, Some(0))]);
+		// Unsupported options are rejected
+		let res = parse_options(b"size=1,foo", |name, _| Ok(name == b"size"));
+		assert_eq!(res.unwrap_err(), errno!(EINVAL));
+		assert_eq!(parse_option_num(Some(b"1777"), 8).unwrap(), 0o1777);
+		assert_eq!(
+			parse_option_num(Some(b"12a"), 10).unwrap_err(),
+			errno!(EINVAL)
+		);
+		assert_eq!(parse_option_num(None, 10).unwrap_err(), errno!(EINVAL));
+	}
+}
@@ -0,0 +1,109 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Implementation of the `consoles` file, which lists the active consoles along with their flags.
+//!
+//! Each line has the format `<name> <flags>`, where `flags` is made of `r` if the console's input
+//! is forwarded to the TTY and `w` if kernel logs are printed on it, each replaced by `-` if
+//! disabled.
+//!
+//! Writing lines with the same format sets the flags of consoles, activating them if necessary.
+
+use crate::{
+	device::{
+		console,
+		console::{CON_INPUT, CON_OUTPUT, MAX_CONSOLES},
+	},
+	file::{
+		fs::{
+			kernfs::{box_wrap, Tunable},
+			NodeOps,
+		},
+		perm::{ROOT_GID, ROOT_UID},
+	},
+	format_content,
+};
+use core::{fmt, fmt::Formatter};
+use utils::{
+	boxed::Box,
+	errno,
+	errno::{AllocResult, EResult},
+	DisplayableStr,
+};
+
+/// Creates the node.
+pub fn init(_: ()) -> AllocResult<Box<dyn NodeOps>> {
+	box_wrap(Tunable {
+		mode: 0o644,
+		owner: (ROOT_UID, ROOT_GID),
+		data: (),
+		read: Some(read),
+		write: Some(write),
+	})
+}
+
+/// The content of the file.
+struct ConsolesContent([Option<(&'static [u8], u8)>; MAX_CONSOLES]);
+
+impl fmt::Display for ConsolesContent {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		for (name, flags) in self.0.iter().flatten() {
+			let input = if flags & CON_INPUT != 0 { 'r' } else { '-' };
+			let output = if flags & CON_OUTPUT != 0 { 'w' } else { '-' };
+			writeln!(f, "{} {input}{output}", DisplayableStr(name))?;
+		}
+		Ok(())
+	}
+}
+
+fn read(_: &(), off: u64, buf: &mut [u8]) -> EResult<usize> {
+	format_content!(off, buf, "{}", ConsolesContent(console::list()))
+}
+
+/// Parses a line written to the file, returning the name of the console and its flags.
+///
+/// If the line is empty, the function returns `None`.
+fn parse_line(line: &[u8]) -> EResult<Option<(&[u8], u8)>> {
+	let mut fields = line
+		.split(u8::is_ascii_whitespace)
+		.filter(|s| !s.is_empty());
+	let Some(name) = fields.next() else {
+		return Ok(None);
+	};
+	let flags = fields
+		.next()
+		.and_then(console::parse_flags)
+		.ok_or_else(|| errno!(EINVAL))?;
+	if fields.next().is_some() {
+		return Err(errno!(EINVAL));
+	}
+	Ok(Some((name, flags)))
+}
+
+fn write(_: &(), buf: &[u8]) -> EResult<()> {
+	// Validate every line before applying them, so that an invalid write has no effect
+	for line in buf.split(|c| *c == b'\n') {
+		parse_line(line)?;
+	}
+	for line in buf.split(|c| *c == b'\n') {
+		if let Some((name, flags)) = parse_line(line)? {
+			console::set_flags(name, flags)?;
+		}
+	}
+	Ok(())
+}
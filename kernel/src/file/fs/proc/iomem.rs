@@ -0,0 +1,60 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+//! The `iomem` file returns the physical memory map given by the bootloader.
+
+use crate::{
+	file::{fs::NodeOps, FileLocation, FileType, Stat},
+	format_content,
+	memory::memmap,
+	multiboot::{MEMORY_ACPI_RECLAIMABLE, MEMORY_AVAILABLE, MEMORY_BADRAM, MEMORY_NVS},
+};
+use core::{fmt, fmt::Formatter};
+use utils::errno::EResult;
+
+/// The `iomem` file.
+#[derive(Debug, Default)]
+pub struct IoMem;
+
+impl NodeOps for IoMem {
+	fn get_stat(&self, _loc: &FileLocation) -> EResult<Stat> {
+		Ok(Stat {
+			mode: FileType::Regular.to_mode() | 0o400,
+			..Default::default()
+		})
+	}
+
+	fn read_content(&self, _loc: &FileLocation, off: u64, buf: &mut [u8]) -> EResult<usize> {
+		format_content!(off, buf, "{self}")
+	}
+}
+
+impl fmt::Display for IoMem {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		for entry in memmap::entries().filter(|e| e.len > 0) {
+			let name = match entry.type_ {
+				MEMORY_AVAILABLE => "System RAM",
+				MEMORY_ACPI_RECLAIMABLE => "ACPI Tables",
+				MEMORY_NVS => "ACPI Non-volatile Storage",
+				MEMORY_BADRAM => "Unusable memory",
+				_ => "Reserved",
+			};
+			writeln!(f, "{:08x}-{:08x} : {name}", entry.addr, entry.end() - 1)?;
+		}
+		Ok(())
+	}
+}
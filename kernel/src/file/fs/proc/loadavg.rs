@@ -0,0 +1,77 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The `loadavg` file returns the load average of the system, along with the number of processes.
+
+use crate::{
+	file::{fs::NodeOps, FileLocation, FileType, Stat},
+	format_content,
+	process::{
+		loadavg::{FIXED_1, FSHIFT},
+		scheduler::SCHEDULER,
+	},
+};
+use core::{fmt, fmt::Formatter};
+use utils::errno::EResult;
+
+/// Displays a fixed-point load average with two decimals.
+struct LoadDisp(u64);
+
+impl fmt::Display for LoadDisp {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		// Round to the nearest hundredth
+		let val = self.0 + FIXED_1 / 200;
+		let int = val >> FSHIFT;
+		let frac = ((val & (FIXED_1 - 1)) * 100) >> FSHIFT;
+		write!(f, "{int}.{frac:02}")
+	}
+}
+
+/// The `loadavg` file.
+#[derive(Debug, Default)]
+pub struct LoadAvg;
+
+impl NodeOps for LoadAvg {
+	fn get_stat(&self, _loc: &FileLocation) -> EResult<Stat> {
+		Ok(Stat {
+			mode: FileType::Regular.to_mode() | 0o444,
+			..Default::default()
+		})
+	}
+
+	fn read_content(&self, _loc: &FileLocation, off: u64, buf: &mut [u8]) -> EResult<usize> {
+		let (load, running, total, last_pid) = {
+			let mut sched = SCHEDULER.get().lock();
+			let load = sched.update_load_avg().get();
+			(
+				load,
+				sched.get_running_count(),
+				sched.get_process_count(),
+				sched.get_last_pid(),
+			)
+		};
+		format_content!(
+			off,
+			buf,
+			"{} {} {} {running}/{total} {last_pid}\n",
+			LoadDisp(load[0]),
+			LoadDisp(load[1]),
+			LoadDisp(load[2])
+		)
+	}
+}
@@ -19,14 +19,23 @@
 //! The `procfs` is a virtual filesystem which provides information about
 //! processes.
 
+mod cmdline;
+mod config;
+mod consoles;
+mod iomem;
+mod loadavg;
 mod mem_info;
+mod pressure;
 mod proc_dir;
 mod self_link;
+mod storage_test;
 mod sys_dir;
+mod sysrq_trigger;
+mod unimplemented_syscalls;
 mod uptime;
 mod version;
 
-use super::{kernfs, Filesystem, FilesystemType, NodeOps};
+use super::{kernfs, parse_options, Filesystem, FilesystemType, NodeOps};
 use crate::{
 	device::DeviceIO,
 	file::{
@@ -41,14 +50,24 @@ use crate::{
 		perm::{Gid, Uid},
 		DirEntry, FileLocation, FileType, INode, Stat,
 	},
-	process::{pid::Pid, scheduler::SCHEDULER, Process},
+	logger::Subsystem,
+	process::{pid, pid::Pid, scheduler::SCHEDULER, Process},
 };
+use cmdline::KernelCmdline;
+use config::KernelConfig;
+use iomem::IoMem;
+use loadavg::LoadAvg;
 use mem_info::MemInfo;
+use pressure::MemoryPressure;
 use proc_dir::{
-	cmdline::Cmdline, cwd::Cwd, exe::Exe, mounts::Mounts, stat::StatNode, status::Status,
+	cmdline::Cmdline, cwd::Cwd, exe::Exe, fd::FdDir, io::IoNode, map_files::MapFilesDir,
+	maps::Maps, mountinfo::MountInfo, mounts::Mounts, oom_score_adj, stat::StatNode,
+	status::Status, timens_offsets, unimplemented_syscalls::UnimplementedSyscallsNode,
 };
 use self_link::SelfNode;
-use sys_dir::OsRelease;
+use storage_test::StorageTest;
+use sys_dir::{BootId, OsRelease};
+use unimplemented_syscalls::UnimplementedSyscalls;
 use uptime::Uptime;
 use utils::{
 	boxed::Box,
@@ -56,10 +75,16 @@ use utils::{
 	errno,
 	errno::EResult,
 	format,
+	limits::PAGE_SIZE,
 	ptr::{arc::Arc, cow::Cow},
 };
 use version::Version;
 
+/// The magic number of the procfs.
+const PROC_SUPER_MAGIC: u32 = 0x9fa0;
+/// The maximum length of a name in the filesystem.
+const MAX_NAME_LEN: usize = 255;
+
 /// Returns the user ID and group ID of the process with the given PID.
 ///
 /// If the process does not exist, the function returns `(0, 0)`.
@@ -67,16 +92,54 @@ fn get_proc_owner(pid: Pid) -> (Uid, Gid) {
 	Process::get_by_pid(pid)
 		.map(|proc_mutex| {
 			let proc = proc_mutex.lock();
-			let uid = proc.access_profile.euid;
-			let gid = proc.access_profile.egid;
-			(uid, gid)
+			let ap = proc.cred.get();
+			(ap.euid, ap.egid)
 		})
 		.unwrap_or((0, 0))
 }
 
+/// The value of the `hidepid` mount option, restricting access to the directories of processes
+/// owned by other users.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+enum HidePid {
+	/// Every process directory is accessible.
+	#[default]
+	Off,
+	/// The directories of other users' processes are listed, but cannot be accessed.
+	NoAccess,
+	/// The directories of other users' processes are neither listed nor accessible.
+	Invisible,
+}
+
+impl HidePid {
+	/// Parses the value of the option, either as a number or a name.
+	fn from_value(value: &[u8]) -> Option<Self> {
+		match value {
+			b"0" | b"off" => Some(Self::Off),
+			b"1" | b"noaccess" => Some(Self::NoAccess),
+			b"2" | b"invisible" => Some(Self::Invisible),
+			_ => None,
+		}
+	}
+}
+
+/// Tells whether the directory of the process with the given `pid` is hidden from the current
+/// process by the `hidepid` option.
+fn is_pid_hidden(hidepid: HidePid, pid: Pid) -> bool {
+	if hidepid == HidePid::Off {
+		return false;
+	}
+	let (uid, _) = get_proc_owner(pid);
+	let ap = Process::current().lock().cred.get();
+	!ap.is_privileged() && ap.euid != uid
+}
+
 /// The root directory of the proc.
 #[derive(Clone, Debug)]
-struct RootDir;
+struct RootDir {
+	/// The value of the `hidepid` mount option.
+	hidepid: HidePid,
+}
 
 impl RootDir {
 	// Entries offsets: The first `Pid::MAX` offsets are reserved for processes. Static entries are
@@ -85,6 +148,31 @@ impl RootDir {
 	/// processes.
 	const STATIC: StaticDir = StaticDir {
 		entries: &[
+			StaticEntryBuilder {
+				name: b"cmdline",
+				entry_type: FileType::Regular,
+				init: entry_init_default::<KernelCmdline>,
+			},
+			StaticEntryBuilder {
+				name: b"config",
+				entry_type: FileType::Regular,
+				init: entry_init_default::<KernelConfig>,
+			},
+			StaticEntryBuilder {
+				name: b"consoles",
+				entry_type: FileType::Regular,
+				init: consoles::init,
+			},
+			StaticEntryBuilder {
+				name: b"iomem",
+				entry_type: FileType::Regular,
+				init: entry_init_default::<IoMem>,
+			},
+			StaticEntryBuilder {
+				name: b"loadavg",
+				entry_type: FileType::Regular,
+				init: entry_init_default::<LoadAvg>,
+			},
 			StaticEntryBuilder {
 				name: b"meminfo",
 				entry_type: FileType::Regular,
@@ -95,11 +183,30 @@ impl RootDir {
 				entry_type: FileType::Link,
 				init: |_| box_wrap(StaticLink(b"self/mounts")),
 			},
+			StaticEntryBuilder {
+				name: b"pressure",
+				entry_type: FileType::Directory,
+				init: |_| {
+					box_wrap(StaticDir {
+						entries: &[StaticEntryBuilder {
+							name: b"memory",
+							entry_type: FileType::Regular,
+							init: entry_init_default::<MemoryPressure>,
+						}],
+						data: (),
+					})
+				},
+			},
 			StaticEntryBuilder {
 				name: b"self",
 				entry_type: FileType::Link,
 				init: entry_init_default::<SelfNode>,
 			},
+			StaticEntryBuilder {
+				name: b"storage_test",
+				entry_type: FileType::Regular,
+				init: entry_init_default::<StorageTest>,
+			},
 			StaticEntryBuilder {
 				name: b"sys",
 				entry_type: FileType::Directory,
@@ -110,11 +217,75 @@ impl RootDir {
 							entry_type: FileType::Directory,
 							init: |_| {
 								box_wrap(StaticDir {
-									entries: &[StaticEntryBuilder {
-										name: b"osrelease",
-										entry_type: FileType::Regular,
-										init: entry_init_default::<OsRelease>,
-									}],
+									entries: &[
+										StaticEntryBuilder {
+											name: b"hostname",
+											entry_type: FileType::Regular,
+											init: sys_dir::hostname,
+										},
+										StaticEntryBuilder {
+											name: b"log",
+											entry_type: FileType::Directory,
+											init: |_| {
+												box_wrap(StaticDir {
+													entries: &[
+														StaticEntryBuilder {
+															name: b"net",
+															entry_type: FileType::Regular,
+															init: |_| {
+																sys_dir::log_level(Subsystem::Net)
+															},
+														},
+														StaticEntryBuilder {
+															name: b"sched",
+															entry_type: FileType::Regular,
+															init: |_| {
+																sys_dir::log_level(
+																	Subsystem::Sched,
+																)
+															},
+														},
+														StaticEntryBuilder {
+															name: b"storage",
+															entry_type: FileType::Regular,
+															init: |_| {
+																sys_dir::log_level(
+																	Subsystem::Storage,
+																)
+															},
+														},
+														StaticEntryBuilder {
+															name: b"vfs",
+															entry_type: FileType::Regular,
+															init: |_| {
+																sys_dir::log_level(Subsystem::Vfs)
+															},
+														},
+													],
+													data: (),
+												})
+											},
+										},
+										StaticEntryBuilder {
+											name: b"osrelease",
+											entry_type: FileType::Regular,
+											init: entry_init_default::<OsRelease>,
+										},
+										StaticEntryBuilder {
+											name: b"random",
+											entry_type: FileType::Directory,
+											init: |_| {
+												box_wrap(StaticDir {
+													entries: &[StaticEntryBuilder {
+														name: b"boot_id",
+														entry_type: FileType::Regular,
+														init: entry_init_default::<BootId>,
+													}],
+													data: (),
+												})
+											},
+										},
+									],
 									data: (),
 								})
 							},
@@ -123,6 +294,16 @@ impl RootDir {
 					})
 				},
 			},
+			StaticEntryBuilder {
+				name: b"sysrq-trigger",
+				entry_type: FileType::Regular,
+				init: sysrq_trigger::init,
+			},
+			StaticEntryBuilder {
+				name: b"unimplemented_syscalls",
+				entry_type: FileType::Regular,
+				init: entry_init_default::<UnimplementedSyscalls>,
+			},
 			StaticEntryBuilder {
 				name: b"uptime",
 				entry_type: FileType::Regular,
@@ -155,15 +336,24 @@ impl NodeOps for RootDir {
 		let Some(pid) = pid else {
 			return Self::STATIC.entry_by_name_inner(name);
 		};
-		// Check the process exists
+		// Check the process exists and is visible from the caller's namespace
+		let Some(pid) = Process::current().lock().pid_to_global(pid) else {
+			return Ok(None);
+		};
 		if Process::get_by_pid(pid).is_none() {
 			return Ok(None);
 		}
+		if is_pid_hidden(self.hidepid, pid) {
+			return match self.hidepid {
+				HidePid::NoAccess => Err(errno!(EACCES)),
+				_ => Ok(None),
+			};
+		}
 		// Return the entry for the process
 		Ok(Some((
 			DirEntry {
 				inode: 0,
-				entry_type: FileType::Directory,
+				entry_type: Some(FileType::Directory),
 				name: Cow::Borrowed(name),
 			},
 			Box::new(StaticDir {
@@ -188,11 +378,41 @@ impl NodeOps for RootDir {
 						entry_type: FileType::Regular,
 						init: entry_init_from::<Exe, Pid>,
 					},
+					StaticEntryBuilder {
+						name: b"fd",
+						entry_type: FileType::Directory,
+						init: entry_init_from::<FdDir, Pid>,
+					},
+					StaticEntryBuilder {
+						name: b"io",
+						entry_type: FileType::Regular,
+						init: entry_init_from::<IoNode, Pid>,
+					},
+					StaticEntryBuilder {
+						name: b"map_files",
+						entry_type: FileType::Directory,
+						init: entry_init_from::<MapFilesDir, Pid>,
+					},
+					StaticEntryBuilder {
+						name: b"maps",
+						entry_type: FileType::Regular,
+						init: entry_init_from::<Maps, Pid>,
+					},
+					StaticEntryBuilder {
+						name: b"mountinfo",
+						entry_type: FileType::Regular,
+						init: entry_init_from::<MountInfo, Pid>,
+					},
 					StaticEntryBuilder {
 						name: b"mounts",
 						entry_type: FileType::Regular,
 						init: entry_init_from::<Mounts, Pid>,
 					},
+					StaticEntryBuilder {
+						name: b"oom_score_adj",
+						entry_type: FileType::Regular,
+						init: oom_score_adj::init,
+					},
 					StaticEntryBuilder {
 						name: b"stat",
 						entry_type: FileType::Regular,
@@ -203,6 +423,16 @@ impl NodeOps for RootDir {
 						entry_type: FileType::Regular,
 						init: entry_init_from::<Status, Pid>,
 					},
+					StaticEntryBuilder {
+						name: b"timens_offsets",
+						entry_type: FileType::Regular,
+						init: timens_offsets::init,
+					},
+					StaticEntryBuilder {
+						name: b"unimplemented_syscalls",
+						entry_type: FileType::Regular,
+						init: entry_init_from::<UnimplementedSyscallsNode, Pid>,
+					},
 				],
 				data: pid,
 			})? as _,
@@ -214,24 +444,37 @@ impl NodeOps for RootDir {
 		_loc: &FileLocation,
 		off: u64,
 	) -> EResult<Option<(DirEntry<'static>, u64)>> {
-		let off: usize = off.try_into().map_err(|_| errno!(EINVAL))?;
-		// Iterate on processes
+		let mut off: usize = off.try_into().map_err(|_| errno!(EINVAL))?;
+		// Iterate on processes visible from the caller's namespace, by their PID in it
 		if off < Pid::MAX as usize {
-			// Find next process
-			let sched = SCHEDULER.get().lock();
-			// TODO start iterating at `off`
-			let pid = sched
-				.iter_process()
-				.map(|(pid, _)| pid)
-				.find(|pid| **pid >= off as Pid);
-			if let Some(pid) = pid {
+			let ns = Process::current().lock().get_pid_namespace().cloned();
+			loop {
+				// Find next process
+				let pid = SCHEDULER
+					.get()
+					.lock()
+					.iter_process()
+					.filter_map(|(pid, _)| {
+						let local = pid::to_local(ns.as_deref(), *pid)?;
+						Some((local, *pid))
+					})
+					.filter(|(pid, _)| *pid >= off as Pid)
+					.min();
+				let Some((pid, global)) = pid else {
+					break;
+				};
+				// Processes are checked without holding the scheduler's lock
+				if self.hidepid == HidePid::Invisible && is_pid_hidden(self.hidepid, global) {
+					off = pid as usize + 1;
+					continue;
+				}
 				return Ok(Some((
 					DirEntry {
 						inode: 0,
-						entry_type: FileType::Directory,
+						entry_type: Some(FileType::Directory),
 						name: Cow::Owned(format!("{pid}")?),
 					},
-					*pid as u64 + 1,
+					pid as u64 + 1,
 				)));
 			}
 		}
@@ -244,7 +487,10 @@ impl NodeOps for RootDir {
 
 /// A proc.
 #[derive(Debug)]
-pub struct ProcFS;
+pub struct ProcFS {
+	/// The value of the `hidepid` mount option.
+	hidepid: HidePid,
+}
 
 impl Filesystem for ProcFS {
 	fn get_name(&self) -> &[u8] {
@@ -261,23 +507,19 @@ impl Filesystem for ProcFS {
 
 	fn get_stat(&self) -> EResult<Statfs> {
 		Ok(Statfs {
-			f_type: 0,
-			f_bsize: 0,
-			f_blocks: 0,
-			f_bfree: 0,
-			f_bavail: 0,
-			f_files: 0,
-			f_ffree: 0,
-			f_fsid: Default::default(),
-			f_namelen: 0,
-			f_frsize: 0,
-			f_flags: 0,
+			f_type: PROC_SUPER_MAGIC,
+			f_bsize: PAGE_SIZE as _,
+			f_namelen: MAX_NAME_LEN as _,
+			f_frsize: PAGE_SIZE as _,
+			..Default::default()
 		})
 	}
 
 	fn node_from_inode(&self, inode: INode) -> EResult<Box<dyn NodeOps>> {
 		if inode == kernfs::ROOT_INODE {
-			Ok(Box::new(RootDir)? as _)
+			Ok(Box::new(RootDir {
+				hidepid: self.hidepid,
+			})? as _)
 		} else {
 			Err(errno!(ENOENT))
 		}
@@ -292,6 +534,10 @@ impl FilesystemType for ProcFsType {
 		b"procfs"
 	}
 
+	fn requires_device(&self) -> bool {
+		false
+	}
+
 	fn detect(&self, _io: &dyn DeviceIO) -> EResult<bool> {
 		Ok(false)
 	}
@@ -301,7 +547,22 @@ impl FilesystemType for ProcFsType {
 		_io: Option<Arc<dyn DeviceIO>>,
 		_mountpath: PathBuf,
 		_readonly: bool,
+		data: &[u8],
 	) -> EResult<Arc<dyn Filesystem>> {
-		Ok(Arc::new(ProcFS)?)
+		let mut hidepid = HidePid::Off;
+		parse_options(data, |name, value| {
+			match name {
+				b"hidepid" => {
+					hidepid = value
+						.and_then(HidePid::from_value)
+						.ok_or_else(|| errno!(EINVAL))?
+				}
+				_ => return Ok(false),
+			}
+			Ok(true)
+		})?;
+		Ok(Arc::new(ProcFS {
+			hidepid,
+		})?)
 	}
 }
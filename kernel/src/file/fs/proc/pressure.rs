@@ -0,0 +1,65 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The `pressure/memory` file reports the memory pressure level, allowing a userspace daemon to
+//! reclaim memory before the OOM killer has to act.
+//!
+//! The file is readable and reports [`POLLPRI`] while the system is under memory pressure.
+
+use crate::{
+	file::{fs::NodeOps, FileLocation, FileType, Stat},
+	format_content,
+	memory::{pressure, pressure::Level, stats::MEM_INFO},
+	syscall::poll::{POLLIN, POLLPRI},
+};
+use utils::errno::EResult;
+
+/// The `pressure/memory` file.
+#[derive(Debug, Default)]
+pub struct MemoryPressure;
+
+impl NodeOps for MemoryPressure {
+	fn get_stat(&self, _loc: &FileLocation) -> EResult<Stat> {
+		Ok(Stat {
+			mode: FileType::Regular.to_mode() | 0o444,
+			..Default::default()
+		})
+	}
+
+	fn read_content(&self, _loc: &FileLocation, off: u64, buf: &mut [u8]) -> EResult<usize> {
+		let (total, free) = {
+			let info = MEM_INFO.lock();
+			(info.mem_total, info.mem_free)
+		};
+		format_content!(
+			off,
+			buf,
+			"level {}\nfree {free} kB\ntotal {total} kB\nstalls {}\n",
+			pressure::level(),
+			pressure::stalls()
+		)
+	}
+
+	fn poll(&self, _loc: &FileLocation, mask: u32) -> EResult<u32> {
+		let mut events = POLLIN;
+		if pressure::level() > Level::None {
+			events |= POLLPRI;
+		}
+		Ok(mask & events)
+	}
+}
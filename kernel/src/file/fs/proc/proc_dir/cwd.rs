@@ -51,12 +51,12 @@ impl NodeOps for Cwd {
 	}
 
 	fn read_content(&self, _loc: &FileLocation, off: u64, buf: &mut [u8]) -> EResult<usize> {
-		let cwd = vfs::Entry::get_path(
-			&Process::get_by_pid(self.0)
-				.ok_or_else(|| errno!(ENOENT))?
-				.lock()
-				.cwd,
-		)?;
+		let fs = Process::get_by_pid(self.0)
+			.ok_or_else(|| errno!(ENOENT))?
+			.lock()
+			.fs
+			.clone();
+		let cwd = vfs::Entry::get_path(&fs.lock().cwd)?;
 		format_content!(off, buf, "{cwd}")
 	}
 }
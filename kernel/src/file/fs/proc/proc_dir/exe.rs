@@ -22,12 +22,12 @@
 use crate::{
 	file::{
 		fs::{proc::get_proc_owner, NodeOps},
-		FileLocation, FileType, Stat,
+		vfs, FileLocation, FileType, Stat,
 	},
 	format_content,
 	process::{pid::Pid, Process},
 };
-use utils::{errno, errno::EResult};
+use utils::{errno, errno::EResult, ptr::arc::Arc};
 
 /// The `exe` node.
 #[derive(Debug)]
@@ -39,6 +39,19 @@ impl From<Pid> for Exe {
 	}
 }
 
+impl Exe {
+	/// Returns the executable file of the process.
+	fn get_file(&self) -> EResult<Arc<vfs::Entry>> {
+		Process::get_by_pid(self.0)
+			.ok_or_else(|| errno!(ENOENT))?
+			.lock()
+			.exec_file
+			.clone()
+			// Kernel threads do not have an executable file
+			.ok_or_else(|| errno!(ENOENT))
+	}
+}
+
 impl NodeOps for Exe {
 	fn get_stat(&self, _loc: &FileLocation) -> EResult<Stat> {
 		let (uid, gid) = get_proc_owner(self.0);
@@ -50,9 +63,19 @@ impl NodeOps for Exe {
 		})
 	}
 
+	fn magic_link(&self, _loc: &FileLocation) -> EResult<Option<Arc<vfs::Entry>>> {
+		self.get_file().map(Some)
+	}
+
 	fn read_content(&self, _loc: &FileLocation, off: u64, buf: &mut [u8]) -> EResult<usize> {
-		let proc_mutex = Process::get_by_pid(self.0).ok_or_else(|| errno!(ENOENT))?;
-		let proc = proc_mutex.lock();
-		format_content!(off, buf, "{}", proc.exec_path)
+		let file = self.get_file()?;
+		let path = vfs::Entry::get_path(&file)?;
+		// Like Linux, tell when the file has been removed since the program was executed
+		let suffix = if file.stat()?.nlink == 0 {
+			" (deleted)"
+		} else {
+			""
+		};
+		format_content!(off, buf, "{path}{suffix}")
 	}
 }
@@ -0,0 +1,218 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Implementation of the `fd` directory, which contains a symbolic link for each open file
+//! descriptor of the process.
+//!
+//! Each link is named after the ID of the file descriptor and points to the path of the file.
+//! Path resolution follows a link to the open file itself, even if it has been removed or moved
+//! since it was opened.
+//! Files that are not on the VFS are represented by their type and an identifier, such as
+//! `pipe:[<id>]` or `socket:[<id>]`.
+
+use crate::{
+	file::{
+		fd::FileDescriptorTable,
+		fs::{proc::get_proc_owner, NodeOps},
+		vfs, DirEntry, File, FileLocation, FileType, Stat,
+	},
+	format_content,
+	process::{pid::Pid, Process},
+};
+use core::{ffi::c_int, ptr};
+use utils::{
+	boxed::Box,
+	errno,
+	errno::EResult,
+	format,
+	lock::Mutex,
+	ptr::{arc::Arc, cow::Cow},
+};
+
+/// Returns the file descriptors table of the process with the given PID.
+///
+/// If the process does not exist, the function returns [`errno::ENOENT`]. If the process has no
+/// file descriptors table (for example, if it is a zombie), the function returns `None`.
+fn get_fds(pid: Pid) -> EResult<Option<Arc<Mutex<FileDescriptorTable>>>> {
+	let proc_mutex = Process::get_by_pid(pid).ok_or_else(|| errno!(ENOENT))?;
+	let proc = proc_mutex.lock();
+	Ok(proc.file_descriptors.clone())
+}
+
+/// Parses the name of an entry of the `fd` directory.
+///
+/// Only the canonical decimal representation of a file descriptor ID is accepted, so that a file
+/// descriptor has a single entry (for example, `01` and `+1` are rejected).
+fn parse_name(name: &[u8]) -> Option<c_int> {
+	if !name.iter().all(u8::is_ascii_digit) || (name.len() > 1 && name[0] == b'0') {
+		return None;
+	}
+	core::str::from_utf8(name).ok()?.parse().ok()
+}
+
+/// The `fd` directory.
+#[derive(Debug)]
+pub struct FdDir(Pid);
+
+impl From<Pid> for FdDir {
+	fn from(pid: Pid) -> Self {
+		Self(pid)
+	}
+}
+
+impl NodeOps for FdDir {
+	fn get_stat(&self, _loc: &FileLocation) -> EResult<Stat> {
+		let (uid, gid) = get_proc_owner(self.0);
+		Ok(Stat {
+			mode: FileType::Directory.to_mode() | 0o500,
+			uid,
+			gid,
+			..Default::default()
+		})
+	}
+
+	fn entry_by_name<'n>(
+		&self,
+		_loc: &FileLocation,
+		name: &'n [u8],
+	) -> EResult<Option<(DirEntry<'n>, Box<dyn NodeOps>)>> {
+		let Some(fd) = parse_name(name) else {
+			return Ok(None);
+		};
+		let Some(fds) = get_fds(self.0)? else {
+			return Ok(None);
+		};
+		if fds.lock().get_fd_raw(fd).is_err() {
+			return Ok(None);
+		}
+		Ok(Some((
+			DirEntry {
+				inode: 0,
+				entry_type: Some(FileType::Link),
+				name: Cow::Borrowed(name),
+			},
+			Box::new(FdLink {
+				pid: self.0,
+				fd,
+			})? as _,
+		)))
+	}
+
+	fn next_entry(
+		&self,
+		_loc: &FileLocation,
+		off: u64,
+	) -> EResult<Option<(DirEntry<'static>, u64)>> {
+		let Some(fds) = get_fds(self.0)? else {
+			return Ok(None);
+		};
+		let fds = fds.lock();
+		let Some((id, _)) = fds.iter().find(|(id, _)| *id as u64 >= off) else {
+			return Ok(None);
+		};
+		Ok(Some((
+			DirEntry {
+				inode: 0,
+				entry_type: Some(FileType::Link),
+				name: Cow::Owned(format!("{id}")?),
+			},
+			id as u64 + 1,
+		)))
+	}
+}
+
+/// A link to the file of a file descriptor.
+#[derive(Debug)]
+struct FdLink {
+	/// The PID of the process owning the file descriptor.
+	pid: Pid,
+	/// The ID of the file descriptor.
+	fd: c_int,
+}
+
+impl FdLink {
+	/// Returns the open file description of the file descriptor.
+	fn get_file(&self) -> EResult<Arc<File>> {
+		let fds = get_fds(self.pid)?.ok_or_else(|| errno!(ENOENT))?;
+		let fds = fds.lock();
+		let fd = fds.get_fd_raw(self.fd).map_err(|_| errno!(ENOENT))?;
+		Ok(fd.get_file().clone())
+	}
+}
+
+impl NodeOps for FdLink {
+	fn get_stat(&self, _loc: &FileLocation) -> EResult<Stat> {
+		let (uid, gid) = get_proc_owner(self.pid);
+		// The permissions reflect the access mode of the file
+		let file = self.get_file()?;
+		let mut mode = 0o100;
+		if file.can_read() {
+			mode |= 0o400;
+		}
+		if file.can_write() {
+			mode |= 0o200;
+		}
+		Ok(Stat {
+			mode: FileType::Link.to_mode() | mode,
+			uid,
+			gid,
+			..Default::default()
+		})
+	}
+
+	fn magic_link(&self, _loc: &FileLocation) -> EResult<Option<Arc<vfs::Entry>>> {
+		// Files that are not on the VFS are reached through the path in the link, which does not
+		// exist
+		Ok(self.get_file()?.vfs_entry.clone())
+	}
+
+	fn read_content(&self, _loc: &FileLocation, off: u64, buf: &mut [u8]) -> EResult<usize> {
+		let file = self.get_file()?;
+		if let Some(ent) = &file.vfs_entry {
+			let path = vfs::Entry::get_path(ent)?;
+			return format_content!(off, buf, "{path}");
+		}
+		// The address of the file's handle identifies the underlying object
+		let id = ptr::from_ref(&*file.ops) as *const () as usize;
+		let prefix = match file.get_type()? {
+			FileType::Fifo => "pipe",
+			FileType::Socket => "socket",
+			_ => "anon_inode",
+		};
+		format_content!(off, buf, "{prefix}:[{id}]")
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test_case]
+	fn procfs_fd_name() {
+		assert_eq!(parse_name(b"0"), Some(0));
+		assert_eq!(parse_name(b"1"), Some(1));
+		assert_eq!(parse_name(b"1024"), Some(1024));
+		assert_eq!(parse_name(b""), None);
+		assert_eq!(parse_name(b"01"), None);
+		assert_eq!(parse_name(b"00"), None);
+		assert_eq!(parse_name(b"+1"), None);
+		assert_eq!(parse_name(b"-1"), None);
+		assert_eq!(parse_name(b" 1"), None);
+		assert_eq!(parse_name(b"99999999999"), None);
+	}
+}
@@ -0,0 +1,76 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+//! The `io` node returns the I/O accounting of the process.
+
+use crate::{
+	file::{
+		fs::{proc::get_proc_owner, NodeOps},
+		FileLocation, FileType, Stat,
+	},
+	format_content,
+	process::{pid::Pid, Process},
+};
+use core::sync::atomic::Ordering::Relaxed;
+use utils::{errno, errno::EResult};
+
+/// The `io` node of the proc.
+#[derive(Clone, Debug)]
+pub struct IoNode(Pid);
+
+impl From<Pid> for IoNode {
+	fn from(pid: Pid) -> Self {
+		Self(pid)
+	}
+}
+
+impl NodeOps for IoNode {
+	fn get_stat(&self, _loc: &FileLocation) -> EResult<Stat> {
+		let (uid, gid) = get_proc_owner(self.0);
+		Ok(Stat {
+			mode: FileType::Regular.to_mode() | 0o400,
+			uid,
+			gid,
+			..Default::default()
+		})
+	}
+
+	fn read_content(&self, _loc: &FileLocation, off: u64, buf: &mut [u8]) -> EResult<usize> {
+		let io = Process::get_by_pid(self.0)
+			.ok_or_else(|| errno!(ENOENT))?
+			.lock()
+			.io
+			.clone();
+		format_content!(
+			off,
+			buf,
+			"rchar: {}\n\
+			wchar: {}\n\
+			syscr: {}\n\
+			syscw: {}\n\
+			read_bytes: {}\n\
+			write_bytes: {}\n\
+			cancelled_write_bytes: 0\n",
+			io.rchar.load(Relaxed),
+			io.wchar.load(Relaxed),
+			io.syscr.load(Relaxed),
+			io.syscw.load(Relaxed),
+			io.read_bytes.load(Relaxed),
+			io.write_bytes.load(Relaxed),
+		)
+	}
+}
@@ -0,0 +1,215 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Implementation of the `map_files` directory, which contains a symbolic link for each memory
+//! mapping of the process that is backed by a file.
+//!
+//! Each link is named after the range of the mapping, with the format `<begin>-<end>` (in
+//! hexadecimal) and points to the mapped file. Following a link allows to open the mapped file
+//! even if it is not reachable from a path anymore, which is why it requires privileges.
+
+use crate::{
+	file::{
+		fs::{proc::get_proc_owner, NodeOps},
+		vfs, DirEntry, FileLocation, FileType, Stat,
+	},
+	format_content,
+	process::{
+		mem_space::{mapping::MemMapping, residence::MapResidence, MemSpace, MAPPING_FLAG_WRITE},
+		pid::Pid,
+		Process,
+	},
+};
+use utils::{
+	boxed::Box,
+	errno,
+	errno::EResult,
+	format,
+	limits::PAGE_SIZE,
+	lock::IntMutex,
+	ptr::{arc::Arc, cow::Cow},
+};
+
+/// Returns the memory space of the process with the given PID.
+///
+/// If the process does not exist, the function returns [`errno::ENOENT`]. If the process has no
+/// memory space (for example, if it is a kernel thread or a zombie), the function returns `None`.
+fn get_mem_space(pid: Pid) -> EResult<Option<Arc<IntMutex<MemSpace>>>> {
+	let proc_mutex = Process::get_by_pid(pid).ok_or_else(|| errno!(ENOENT))?;
+	let proc = proc_mutex.lock();
+	Ok(proc.get_mem_space().cloned())
+}
+
+/// Returns the range of addresses of the mapping `m`, along with its file, if the mapping is
+/// backed by a file that is present on the VFS.
+fn mapped_file(m: &MemMapping) -> Option<(usize, usize, Arc<vfs::Entry>)> {
+	let MapResidence::File {
+		file, ..
+	} = m.get_residence()
+	else {
+		return None;
+	};
+	let entry = file.vfs_entry.clone()?;
+	let begin = m.get_begin() as usize;
+	let end = begin + m.get_size().get() * PAGE_SIZE;
+	Some((begin, end, entry))
+}
+
+/// Parses the name of a link, returning the range of addresses it designates.
+fn parse_name(name: &[u8]) -> Option<(usize, usize)> {
+	let name = core::str::from_utf8(name).ok()?;
+	let (begin, end) = name.split_once('-')?;
+	let begin = usize::from_str_radix(begin, 16).ok()?;
+	let end = usize::from_str_radix(end, 16).ok()?;
+	Some((begin, end))
+}
+
+/// The `map_files` directory.
+#[derive(Debug)]
+pub struct MapFilesDir(Pid);
+
+impl From<Pid> for MapFilesDir {
+	fn from(pid: Pid) -> Self {
+		Self(pid)
+	}
+}
+
+impl NodeOps for MapFilesDir {
+	fn get_stat(&self, _loc: &FileLocation) -> EResult<Stat> {
+		let (uid, gid) = get_proc_owner(self.0);
+		Ok(Stat {
+			mode: FileType::Directory.to_mode() | 0o500,
+			uid,
+			gid,
+			..Default::default()
+		})
+	}
+
+	fn entry_by_name<'n>(
+		&self,
+		_loc: &FileLocation,
+		name: &'n [u8],
+	) -> EResult<Option<(DirEntry<'n>, Box<dyn NodeOps>)>> {
+		let Some((begin, end)) = parse_name(name) else {
+			return Ok(None);
+		};
+		let link = MapFileLink {
+			pid: self.0,
+			begin,
+			end,
+		};
+		if link.get_mapping()?.is_none() {
+			return Ok(None);
+		}
+		Ok(Some((
+			DirEntry {
+				inode: 0,
+				entry_type: Some(FileType::Link),
+				name: Cow::Borrowed(name),
+			},
+			Box::new(link)? as _,
+		)))
+	}
+
+	fn next_entry(
+		&self,
+		_loc: &FileLocation,
+		off: u64,
+	) -> EResult<Option<(DirEntry<'static>, u64)>> {
+		let Some(mem_space) = get_mem_space(self.0)? else {
+			return Ok(None);
+		};
+		let mem_space = mem_space.lock();
+		// The offset is the address from which to look for the next mapping
+		let mapping = mem_space
+			.iter_mappings()
+			.filter_map(mapped_file)
+			.find(|(begin, ..)| *begin as u64 >= off);
+		let Some((begin, end, _)) = mapping else {
+			return Ok(None);
+		};
+		Ok(Some((
+			DirEntry {
+				inode: 0,
+				entry_type: Some(FileType::Link),
+				name: Cow::Owned(format!("{begin:x}-{end:x}")?),
+			},
+			begin as u64 + 1,
+		)))
+	}
+}
+
+/// A link to the file of a memory mapping.
+#[derive(Debug)]
+struct MapFileLink {
+	/// The PID of the process owning the mapping.
+	pid: Pid,
+	/// The beginning address of the mapping.
+	begin: usize,
+	/// The end address of the mapping.
+	end: usize,
+}
+
+impl MapFileLink {
+	/// Returns the flags and file of the mapping.
+	///
+	/// If the mapping does not exist anymore, the function returns `None`.
+	fn get_mapping(&self) -> EResult<Option<(u8, Arc<vfs::Entry>)>> {
+		let Some(mem_space) = get_mem_space(self.pid)? else {
+			return Ok(None);
+		};
+		let mem_space = mem_space.lock();
+		let mapping = mem_space.iter_mappings().find_map(|m| {
+			let (begin, end, entry) = mapped_file(m)?;
+			(begin == self.begin && end == self.end).then(|| (m.get_flags(), entry))
+		});
+		Ok(mapping)
+	}
+}
+
+impl NodeOps for MapFileLink {
+	fn get_stat(&self, _loc: &FileLocation) -> EResult<Stat> {
+		let (uid, gid) = get_proc_owner(self.pid);
+		let (flags, _) = self.get_mapping()?.ok_or_else(|| errno!(ENOENT))?;
+		// The permissions reflect the access mode of the mapping
+		let mut mode = 0o400;
+		if flags & MAPPING_FLAG_WRITE != 0 {
+			mode |= 0o200;
+		}
+		Ok(Stat {
+			mode: FileType::Link.to_mode() | mode,
+			uid,
+			gid,
+			..Default::default()
+		})
+	}
+
+	fn magic_link(&self, _loc: &FileLocation) -> EResult<Option<Arc<vfs::Entry>>> {
+		if !Process::current().lock().cred.get().is_privileged() {
+			return Err(errno!(EPERM));
+		}
+		let (_, entry) = self.get_mapping()?.ok_or_else(|| errno!(ENOENT))?;
+		Ok(Some(entry))
+	}
+
+	fn read_content(&self, _loc: &FileLocation, off: u64, buf: &mut [u8]) -> EResult<usize> {
+		let (_, entry) = self.get_mapping()?.ok_or_else(|| errno!(ENOENT))?;
+		let path = vfs::Entry::get_path(&entry)?;
+		format_content!(off, buf, "{path}")
+	}
+}
@@ -0,0 +1,164 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Implementation of the `maps` node, which lists the memory mappings of the process.
+//!
+//! Each line has the following format:
+//!
+//! ```text
+//! <begin>-<end> <perms> <offset> <major>:<minor> <inode> <path>
+//! ```
+
+use crate::{
+	file::{
+		fs::{proc::get_proc_owner, NodeOps},
+		vfs,
+		vfs::mountpoint::MountSource,
+		FileLocation, FileType, Stat,
+	},
+	format_content,
+	memory::VirtAddr,
+	process::{
+		mem_space::{
+			residence::MapResidence, MemSpace, MAPPING_FLAG_EXEC, MAPPING_FLAG_SHARED,
+			MAPPING_FLAG_WRITE,
+		},
+		pid::Pid,
+		Process,
+	},
+};
+use core::{fmt, fmt::Formatter};
+use utils::{errno, errno::EResult, limits::PAGE_SIZE};
+
+/// The `maps` node.
+#[derive(Debug)]
+pub struct Maps(Pid);
+
+impl From<Pid> for Maps {
+	fn from(pid: Pid) -> Self {
+		Self(pid)
+	}
+}
+
+impl NodeOps for Maps {
+	fn get_stat(&self, _loc: &FileLocation) -> EResult<Stat> {
+		let (uid, gid) = get_proc_owner(self.0);
+		Ok(Stat {
+			mode: FileType::Regular.to_mode() | 0o444,
+			uid,
+			gid,
+			..Default::default()
+		})
+	}
+
+	fn read_content(&self, _loc: &FileLocation, off: u64, buf: &mut [u8]) -> EResult<usize> {
+		let (mem_space, esp) = {
+			let proc_mutex = Process::get_by_pid(self.0).ok_or_else(|| errno!(ENOENT))?;
+			let proc = proc_mutex.lock();
+			// Kernel threads and zombies have no memory space
+			let Some(mem_space) = proc.get_mem_space() else {
+				return Ok(0);
+			};
+			(mem_space.clone(), proc.regs.esp)
+		};
+		let mem_space = mem_space.lock();
+		format_content!(
+			off,
+			buf,
+			"{}",
+			MapsContent {
+				mem_space: &mem_space,
+				stack: VirtAddr(esp),
+			}
+		)
+	}
+}
+
+/// The content of the `maps` node.
+struct MapsContent<'m> {
+	/// The memory space to list mappings from.
+	mem_space: &'m MemSpace,
+	/// The process's stack pointer, used to find the mapping of the stack.
+	stack: VirtAddr,
+}
+
+impl fmt::Display for MapsContent<'_> {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		let brk_init = self.mem_space.get_brk_init();
+		let brk = self.mem_space.get_brk();
+		for m in self.mem_space.iter_mappings() {
+			let begin = VirtAddr::from(m.get_begin());
+			let end = begin + m.get_size().get() * PAGE_SIZE;
+			let flags = m.get_flags();
+			let write = if flags & MAPPING_FLAG_WRITE != 0 {
+				'w'
+			} else {
+				'-'
+			};
+			let exec = if flags & MAPPING_FLAG_EXEC != 0 {
+				'x'
+			} else {
+				'-'
+			};
+			let shared = if flags & MAPPING_FLAG_SHARED != 0 {
+				's'
+			} else {
+				'p'
+			};
+			let (off, major, minor, inode, entry) = match m.get_residence() {
+				MapResidence::File {
+					file,
+					off,
+				} => {
+					let entry = file.vfs_entry.as_ref();
+					let loc = entry.map(|e| &e.node().location);
+					// Filesystems without a device are reported as `0:0`, like in `mountinfo`
+					let (major, minor) = loc
+						.and_then(FileLocation::get_mountpoint)
+						.and_then(|mp| match &mp.source {
+							MountSource::Device(id) => Some((id.major, id.minor)),
+							MountSource::NoDev(_) => None,
+						})
+						.unwrap_or((0, 0));
+					(*off, major, minor, loc.map(|l| l.inode).unwrap_or(0), entry)
+				}
+				_ => (0, 0, 0, 0, None),
+			};
+			write!(
+				f,
+				"{begin:08x}-{end:08x} r{write}{exec}{shared} {off:08x} {major:02x}:{minor:02x} \
+				 {inode} ",
+				begin = begin.0,
+				end = end.0,
+			)?;
+			// Returning an error from a formatter is not allowed, so the path is omitted if it
+			// cannot be retrieved
+			if let Some(entry) = entry {
+				if let Ok(path) = vfs::Entry::get_path(entry) {
+					write!(f, "{path}")?;
+				}
+			} else if begin >= brk_init && begin < brk {
+				f.write_str("[heap]")?;
+			} else if self.stack >= begin && self.stack < end {
+				f.write_str("[stack]")?;
+			}
+			writeln!(f)?;
+		}
+		Ok(())
+	}
+}
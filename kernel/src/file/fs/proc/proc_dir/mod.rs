@@ -22,6 +22,14 @@ pub mod cmdline;
 pub mod cwd;
 pub mod environ;
 pub mod exe;
+pub mod fd;
+pub mod io;
+pub mod map_files;
+pub mod maps;
+pub mod mountinfo;
 pub mod mounts;
+pub mod oom_score_adj;
 pub mod stat;
 pub mod status;
+pub mod timens_offsets;
+pub mod unimplemented_syscalls;
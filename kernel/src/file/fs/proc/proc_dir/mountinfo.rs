@@ -0,0 +1,102 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Implementation of the `mountinfo` node, which gives detailed information about the
+//! mountpoints visible to the process.
+//!
+//! Each line has the following format:
+//!
+//! ```text
+//! <id> <parent id> <major>:<minor> <root> <mount point> <options> - <fs type> <source> <super options>
+//! ```
+
+use super::mounts::{
+	for_each_visible, get_proc_root, DisplaySource, Escaped, FsOptions, MountOptions,
+};
+use crate::{
+	file::{
+		fs::{proc::get_proc_owner, NodeOps},
+		vfs,
+		vfs::mountpoint::{MountSource, FLAG_RDONLY},
+		FileLocation, FileType, Stat,
+	},
+	format_content,
+	process::pid::Pid,
+};
+use core::{fmt, fmt::Formatter};
+use utils::{errno::EResult, ptr::arc::Arc, DisplayableStr};
+
+/// The `mountinfo` node.
+#[derive(Debug)]
+pub struct MountInfo(Pid);
+
+impl From<Pid> for MountInfo {
+	fn from(pid: Pid) -> Self {
+		Self(pid)
+	}
+}
+
+impl NodeOps for MountInfo {
+	fn get_stat(&self, _loc: &FileLocation) -> EResult<Stat> {
+		let (uid, gid) = get_proc_owner(self.0);
+		Ok(Stat {
+			mode: FileType::Regular.to_mode() | 0o444,
+			uid,
+			gid,
+			..Default::default()
+		})
+	}
+
+	fn read_content(&self, _loc: &FileLocation, off: u64, buf: &mut [u8]) -> EResult<usize> {
+		let root = get_proc_root(self.0)?;
+		format_content!(off, buf, "{}", MountInfoContent(&root))
+	}
+}
+
+/// The content of the `mountinfo` node, for a process with the given root directory.
+struct MountInfoContent<'r>(&'r Arc<vfs::Entry>);
+
+impl fmt::Display for MountInfoContent<'_> {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		for_each_visible(self.0, |m| {
+			// Filesystems without a device are reported as `0:0`, like `st_dev` in `stat`
+			let (major, minor) = match &m.mp.source {
+				MountSource::Device(id) => (id.major, id.minor),
+				MountSource::NoDev(_) => (0, 0),
+			};
+			let rw = if m.mp.get_flags() & FLAG_RDONLY != 0 {
+				"ro"
+			} else {
+				"rw"
+			};
+			writeln!(
+				f,
+				"{id} {parent_id} {major}:{minor} {root} {target} {options} - {fs_type} {source} \
+				 {rw}{fs_options}",
+				id = m.mp.id,
+				parent_id = m.mp.get_parent_id(),
+				root = Escaped(m.fs_root.as_bytes()),
+				target = Escaped(m.target.as_bytes()),
+				options = MountOptions(m.mp.flags),
+				fs_type = DisplayableStr(m.mp.fs.get_name()),
+				source = DisplaySource(&m.mp.source),
+				fs_options = FsOptions(&*m.mp.fs),
+			)
+		})
+	}
+}
@@ -19,17 +19,152 @@
 //! Implementation of the `mounts` node which allows to get the list of mountpoint.
 
 use crate::{
+	device,
 	file::{
-		fs::{proc::get_proc_owner, NodeOps},
+		fs::{proc::get_proc_owner, Filesystem, NodeOps},
 		vfs,
-		vfs::mountpoint,
+		vfs::{
+			mountpoint,
+			mountpoint::{MountPoint, MountSource},
+		},
 		FileLocation, FileType, Stat,
 	},
 	format_content,
-	process::pid::Pid,
+	process::{pid::Pid, Process},
 };
 use core::{fmt, fmt::Formatter};
-use utils::{errno::EResult, DisplayableStr};
+use utils::{collections::path::PathBuf, errno, errno::EResult, ptr::arc::Arc, DisplayableStr};
+
+/// Returns the root directory of the process with the given PID.
+pub(super) fn get_proc_root(pid: Pid) -> EResult<Arc<vfs::Entry>> {
+	let fs = Process::get_by_pid(pid)
+		.ok_or_else(|| errno!(ENOENT))?
+		.lock()
+		.fs
+		.clone();
+	let root = fs.lock().chroot.clone();
+	Ok(root)
+}
+
+/// A mountpoint, as seen from a process's root directory.
+pub(super) struct VisibleMount<'m> {
+	/// The mountpoint.
+	pub mp: &'m MountPoint,
+	/// The path, relative to the root of the mounted filesystem, of the directory that appears
+	/// as the root of the mountpoint.
+	///
+	/// This is `/` unless the process's root directory is located inside the mountpoint.
+	pub fs_root: PathBuf,
+	/// The path to the mountpoint, relative to the process's root directory.
+	pub target: PathBuf,
+}
+
+/// Calls `f` on each mountpoint that is visible from the directory `root`, by increasing ID.
+///
+/// A mountpoint is visible if it is mounted under `root`, or if `root` is located on it. This
+/// restricts the view of processes whose root directory has been changed with `chroot`.
+pub(super) fn for_each_visible<F: FnMut(VisibleMount) -> fmt::Result>(
+	root: &Arc<vfs::Entry>,
+	mut f: F,
+) -> fmt::Result {
+	let root_mp = root.node().location.mountpoint_id;
+	let mps = mountpoint::MOUNT_POINTS.lock();
+	let max = mps.iter().map(|(id, _)| *id).max().unwrap_or(0);
+	for mp in (0..=max).filter_map(|id| mps.get(&id)) {
+		let paths = if mp.id == root_mp {
+			vfs::Entry::get_path_from(root, &mp.root_entry)
+				.and_then(|fs_root| Ok((fs_root, PathBuf::root()?)))
+		} else {
+			vfs::Entry::get_path_from(&mp.root_entry, root)
+				.and_then(|target| Ok((PathBuf::root()?, target)))
+		};
+		match paths {
+			Ok((fs_root, target)) => f(VisibleMount {
+				mp,
+				fs_root,
+				target,
+			})?,
+			// The mountpoint is outside of `root`. Other errors are memory allocation failures, on
+			// which the mountpoint is skipped since a formatter is not allowed to fail
+			Err(_) => continue,
+		}
+	}
+	Ok(())
+}
+
+/// Displays a string, escaping the characters that would break the space-separated format of
+/// mount tables.
+pub(super) struct Escaped<'s>(pub &'s [u8]);
+
+impl fmt::Display for Escaped<'_> {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		let mut start = 0;
+		for (i, b) in self.0.iter().enumerate() {
+			if matches!(b, b' ' | b'\t' | b'\n' | b'\\') {
+				write!(f, "{}\\{b:03o}", DisplayableStr(&self.0[start..i]))?;
+				start = i + 1;
+			}
+		}
+		write!(f, "{}", DisplayableStr(&self.0[start..]))
+	}
+}
+
+/// Displays the source of a mountpoint, using the path to the device file when there is one.
+pub(super) struct DisplaySource<'s>(pub &'s MountSource);
+
+impl fmt::Display for DisplaySource<'_> {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		match self.0 {
+			MountSource::Device(id) => match device::get(id) {
+				Some(dev) => write!(f, "{}", Escaped(dev.get_path().as_bytes())),
+				None => write!(f, "{}", self.0),
+			},
+			MountSource::NoDev(name) => write!(f, "{}", Escaped(name.as_bytes())),
+		}
+	}
+}
+
+/// Displays the mount flags of a mountpoint as a comma-separated list of options.
+pub(super) struct MountOptions(pub u32);
+
+impl fmt::Display for MountOptions {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		let rw = if self.0 & mountpoint::FLAG_RDONLY != 0 {
+			"ro"
+		} else {
+			"rw"
+		};
+		f.write_str(rw)?;
+		let options = [
+			(mountpoint::FLAG_NOSUID, "nosuid"),
+			(mountpoint::FLAG_NODEV, "nodev"),
+			(mountpoint::FLAG_NOEXEC, "noexec"),
+			(mountpoint::FLAG_SYNCHRONOUS, "sync"),
+			(mountpoint::FLAG_MANDLOCK, "mand"),
+			(mountpoint::FLAG_NOATIME, "noatime"),
+			(mountpoint::FLAG_NODIRATIME, "nodiratime"),
+			(mountpoint::FLAG_RELATIME, "relatime"),
+		];
+		for (flag, name) in options {
+			if self.0 & flag != 0 {
+				write!(f, ",{name}")?;
+			}
+		}
+		Ok(())
+	}
+}
+
+/// Displays the options specific to a filesystem, each preceded by a comma.
+pub(super) struct FsOptions<'f>(pub &'f dyn Filesystem);
+
+impl fmt::Display for FsOptions<'_> {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		if let Some(policy) = self.0.get_error_policy() {
+			write!(f, ",errors={}", policy.as_str())?;
+		}
+		Ok(())
+	}
+}
 
 /// The `mounts` node.
 #[derive(Debug)]
@@ -53,27 +188,47 @@ impl NodeOps for Mounts {
 	}
 
 	fn read_content(&self, _loc: &FileLocation, off: u64, buf: &mut [u8]) -> EResult<usize> {
-		format_content!(off, buf, "{}", self)
+		let root = get_proc_root(self.0)?;
+		format_content!(off, buf, "{}", MountsContent(&root))
 	}
 }
 
-impl fmt::Display for Mounts {
+/// The content of the `mounts` node, for a process with the given root directory.
+struct MountsContent<'r>(&'r Arc<vfs::Entry>);
+
+impl fmt::Display for MountsContent<'_> {
 	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-		let mps = mountpoint::MOUNT_POINTS.lock();
-		for (_, mp) in mps.iter() {
-			let Ok(target) = vfs::Entry::get_path(&mp.root_entry) else {
-				continue;
-			};
-			let fs_type = mp.fs.get_name();
-			let flags = "TODO"; // TODO
+		for_each_visible(self.0, |m| {
 			writeln!(
 				f,
-				"{source} {target} {fs_type} {flags} 0 0",
-				source = mp.source,
-				target = target,
-				fs_type = DisplayableStr(fs_type)
-			)?;
-		}
-		Ok(())
+				"{source} {target} {fs_type} {options}{fs_options} 0 0",
+				source = DisplaySource(&m.mp.source),
+				target = Escaped(m.target.as_bytes()),
+				fs_type = DisplayableStr(m.mp.fs.get_name()),
+				options = MountOptions(m.mp.get_flags()),
+				fs_options = FsOptions(&*m.mp.fs),
+			)
+		})
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use utils::format;
+
+	#[test_case]
+	fn mount_options() {
+		let s = format!("{}", MountOptions(0)).unwrap();
+		assert_eq!(s.as_bytes(), b"rw");
+		let flags = mountpoint::FLAG_RDONLY | mountpoint::FLAG_NOSUID | mountpoint::FLAG_NOATIME;
+		let s = format!("{}", MountOptions(flags)).unwrap();
+		assert_eq!(s.as_bytes(), b"ro,nosuid,noatime");
+	}
+
+	#[test_case]
+	fn escaped() {
+		let s = format!("{}", Escaped(b"/mnt/a b\\c")).unwrap();
+		assert_eq!(s.as_bytes(), b"/mnt/a\\040b\\134c");
 	}
 }
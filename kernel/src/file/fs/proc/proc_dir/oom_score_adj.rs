@@ -0,0 +1,71 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+//! Implementation of the `oom_score_adj` file, which allows to adjust the score used by the OOM
+//! killer to select the process to terminate.
+
+use crate::{
+	file::fs::{
+		kernfs::{box_wrap, parse_written, Tunable},
+		proc::get_proc_owner,
+		NodeOps,
+	},
+	format_content,
+	process::{pid::Pid, Process},
+};
+use core::ops::RangeInclusive;
+use utils::{
+	boxed::Box,
+	errno,
+	errno::{AllocResult, EResult},
+};
+
+/// The range of valid values.
+const RANGE: RangeInclusive<i16> = -1000..=1000;
+
+/// Creates the node for the process with the given PID.
+pub fn init(pid: Pid) -> AllocResult<Box<dyn NodeOps>> {
+	box_wrap(Tunable {
+		mode: 0o644,
+		owner: get_proc_owner(pid),
+		data: pid,
+		read: Some(read),
+		write: Some(write),
+	})
+}
+
+fn read(pid: &Pid, off: u64, buf: &mut [u8]) -> EResult<usize> {
+	let proc = Process::get_by_pid(*pid).ok_or_else(|| errno!(ENOENT))?;
+	let oom_score_adj = proc.lock().oom_score_adj;
+	format_content!(off, buf, "{oom_score_adj}\n")
+}
+
+fn write(pid: &Pid, buf: &[u8]) -> EResult<()> {
+	let val: i16 = parse_written(buf)?;
+	if !RANGE.contains(&val) {
+		return Err(errno!(EINVAL));
+	}
+	let privileged = Process::current().lock().cred.get().is_privileged();
+	let proc = Process::get_by_pid(*pid).ok_or_else(|| errno!(ENOENT))?;
+	let mut proc = proc.lock();
+	// Only a privileged process may make another process less likely to be killed
+	if val < proc.oom_score_adj && !privileged {
+		return Err(errno!(EACCES));
+	}
+	proc.oom_score_adj = val;
+	Ok(())
+}
@@ -25,12 +25,21 @@ use crate::{
 		FileLocation, FileType, Stat,
 	},
 	format_content,
-	process::{pid::Pid, Process},
+	process::{
+		pid,
+		pid::{Pid, PidNamespace},
+		Process,
+	},
+	time::unit::TimeUnit,
 };
 use core::{fmt, fmt::Formatter};
 use utils::{collections::string::String, errno, errno::EResult, DisplayableStr};
 
-struct StatDisp<'p>(&'p Process);
+/// The number of clock ticks per second, in which times are expressed.
+const USER_HZ: u64 = 100;
+
+/// PIDs are displayed as seen from the PID namespace of the second field.
+struct StatDisp<'p>(&'p Process, Option<&'p PidNamespace>);
 
 impl<'p> fmt::Display for StatDisp<'p> {
 	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
@@ -40,24 +49,26 @@ impl<'p> fmt::Display for StatDisp<'p> {
 		let vmem_usage = 0;
 		let esp = self.0.regs.esp;
 		let eip = self.0.regs.eip;
+		let rusage = self.0.get_rusage();
 		// TODO Fill every fields with process's data
 		write!(
 			f,
 			"{pid} ({name}) {state_char} {ppid} {pgid} {sid} TODO TODO 0 \
-0 0 0 0 {user_jiffies} {kernel_jiffies} TODO TODO {priority} {nice} {num_threads} 0 {vmem_usage} \
-TODO TODO TODO TODO {esp} {eip} TODO TODO TODO TODO 0 0 0 TODO TODO TODO TODO TODO TODO TODO TODO \
-TODO TODO TODO TODO TODO TODO TODO TODO TODO",
-			pid = self.0.get_pid(),
+0 0 0 0 {user_jiffies} {kernel_jiffies} TODO TODO {priority} {nice} {num_threads} 0 {start_time} \
+{vmem_usage} TODO TODO TODO TODO TODO {esp} {eip} TODO TODO TODO TODO 0 0 0 TODO TODO TODO TODO TODO \
+TODO TODO TODO TODO TODO TODO TODO TODO TODO TODO",
+			pid = pid::to_local(self.1, self.0.get_pid()).unwrap_or(0),
 			name = DisplayableStr(name),
 			state_char = self.0.get_state().as_char(),
-			ppid = self.0.get_parent_pid(),
-			pgid = self.0.pgid,
+			ppid = pid::to_local(self.1, self.0.get_parent_pid()).unwrap_or(0),
+			pgid = pid::to_local(self.1, self.0.pgid).unwrap_or(0),
 			sid = 0,            // TODO
-			user_jiffies = 0,   // TODO
-			kernel_jiffies = 0, // TODO
+			user_jiffies = rusage.ru_utime.to_nano() / (1_000_000_000 / USER_HZ),
+			kernel_jiffies = rusage.ru_stime.to_nano() / (1_000_000_000 / USER_HZ),
 			priority = self.0.priority,
 			nice = self.0.nice,
 			num_threads = 1, // TODO
+			start_time = self.0.start_time / (1_000_000_000 / USER_HZ),
 		)
 	}
 }
@@ -84,8 +95,9 @@ impl NodeOps for StatNode {
 	}
 
 	fn read_content(&self, _loc: &FileLocation, off: u64, buf: &mut [u8]) -> EResult<usize> {
+		let ns = Process::current().lock().get_pid_namespace().cloned();
 		let proc_mutex = Process::get_by_pid(self.0).ok_or_else(|| errno!(ENOENT))?;
 		let proc = proc_mutex.lock();
-		format_content!(off, buf, "{}", StatDisp(&proc))
+		format_content!(off, buf, "{}", StatDisp(&proc, ns.as_deref()))
 	}
 }
@@ -22,20 +22,39 @@
 use crate::{
 	file::{
 		fs::{proc::get_proc_owner, NodeOps},
+		perm::Gid,
 		FileLocation, FileType, Stat,
 	},
 	format_content,
-	process::{pid::Pid, Process},
+	process::{
+		pid,
+		pid::{Pid, PidNamespace},
+		Process,
+	},
 };
 use core::{fmt, fmt::Formatter};
 use utils::{collections::string::String, errno, errno::EResult, DisplayableStr};
 
-struct StatusDisp<'p>(&'p Process);
+/// Displays a list of group IDs, each followed by a space.
+struct GroupsDisp<'g>(&'g [Gid]);
+
+impl fmt::Display for GroupsDisp<'_> {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		for gid in self.0 {
+			write!(f, "{gid} ")?;
+		}
+		Ok(())
+	}
+}
+
+/// PIDs are displayed as seen from the PID namespace of the second field.
+struct StatusDisp<'p>(&'p Process, Option<&'p PidNamespace>);
 
 impl<'p> fmt::Display for StatusDisp<'p> {
 	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
 		let name = self.0.argv.first().map(String::as_bytes).unwrap_or(b"?");
 		let state = self.0.get_state();
+		let ap = self.0.cred.get();
 		// TODO Fill every fields with process's data
 		writeln!(
 			f,
@@ -50,7 +69,7 @@ TracerPid: 0
 Uid: {uid} {euid} {suid} {ruid}
 Gid: {gid} {egid} {sgid} {rgid}
 FDSize: TODO
-Groups: TODO
+Groups: {groups}
 NStgid: TODO
 NSpid: TODO
 NSpgid: TODO
@@ -97,19 +116,20 @@ Mems_allowed_list: 0
 voluntary_ctxt_switches: 0
 nonvoluntary_ctxt_switches: 0",
 			name = DisplayableStr(name),
-			umask = self.0.umask,
+			umask = self.0.fs.lock().umask,
 			state_char = state.as_char(),
 			state_name = state.as_str(),
-			pid = self.0.get_pid(),
-			ppid = self.0.get_parent_pid(),
-			uid = self.0.access_profile.uid,
-			euid = self.0.access_profile.euid,
-			suid = self.0.access_profile.suid,
-			ruid = self.0.access_profile.uid,
-			gid = self.0.access_profile.gid,
-			egid = self.0.access_profile.egid,
-			sgid = self.0.access_profile.sgid,
-			rgid = self.0.access_profile.gid,
+			pid = pid::to_local(self.1, self.0.get_pid()).unwrap_or(0),
+			ppid = pid::to_local(self.1, self.0.get_parent_pid()).unwrap_or(0),
+			uid = ap.uid,
+			euid = ap.euid,
+			suid = ap.suid,
+			ruid = ap.uid,
+			gid = ap.gid,
+			egid = ap.egid,
+			sgid = ap.sgid,
+			rgid = ap.gid,
+			groups = GroupsDisp(ap.groups()),
 		)
 	}
 }
@@ -136,8 +156,9 @@ impl NodeOps for Status {
 	}
 
 	fn read_content(&self, _loc: &FileLocation, off: u64, buf: &mut [u8]) -> EResult<usize> {
+		let ns = Process::current().lock().get_pid_namespace().cloned();
 		let proc_mutex = Process::get_by_pid(self.0).ok_or_else(|| errno!(ENOENT))?;
 		let proc = proc_mutex.lock();
-		format_content!(off, buf, "{}", StatusDisp(&proc))
+		format_content!(off, buf, "{}", StatusDisp(&proc, ns.as_deref()))
 	}
 }
@@ -0,0 +1,115 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Implementation of the `timens_offsets` file, which allows to read and set the offsets of the
+//! time namespace for the children of a process.
+//!
+//! Each line contains the name of a clock, followed by its offset in seconds and nanoseconds.
+
+use crate::{
+	file::fs::{
+		kernfs::{box_wrap, Tunable},
+		proc::get_proc_owner,
+		NodeOps,
+	},
+	format_content,
+	process::{pid::Pid, Process},
+	time::{
+		clock::{CLOCK_BOOTTIME, CLOCK_MONOTONIC},
+		namespace,
+		unit::ClockIdT,
+	},
+};
+use core::str;
+use utils::{
+	boxed::Box,
+	errno,
+	errno::{AllocResult, EResult},
+};
+
+/// The clocks whose offset can be set, along with their name.
+const CLOCKS: [(ClockIdT, &str); 2] =
+	[(CLOCK_MONOTONIC, "monotonic"), (CLOCK_BOOTTIME, "boottime")];
+
+/// Creates the node for the process with the given PID.
+pub fn init(pid: Pid) -> AllocResult<Box<dyn NodeOps>> {
+	box_wrap(Tunable {
+		mode: 0o644,
+		owner: get_proc_owner(pid),
+		data: pid,
+		read: Some(read),
+		write: Some(write),
+	})
+}
+
+fn read(pid: &Pid, off: u64, buf: &mut [u8]) -> EResult<usize> {
+	let proc = Process::get_by_pid(*pid).ok_or_else(|| errno!(ENOENT))?;
+	let ns = proc.lock().time_ns_for_children.clone();
+	let offsets = namespace::offsets(ns.as_deref());
+	let [monotonic, boottime] = CLOCKS.map(|(clk, _)| offsets.get(clk));
+	format_content!(
+		off,
+		buf,
+		"monotonic  {:>10} {:>9}\nboottime   {:>10} {:>9}\n",
+		monotonic.div_euclid(1_000_000_000),
+		monotonic.rem_euclid(1_000_000_000),
+		boottime.div_euclid(1_000_000_000),
+		boottime.rem_euclid(1_000_000_000)
+	)
+}
+
+/// Parses a line written to the file, returning the clock and its offset in nanoseconds.
+fn parse_line(line: &str) -> Option<(ClockIdT, i64)> {
+	let mut words = line.split_whitespace();
+	let clock = words.next()?;
+	let clk = CLOCKS
+		.iter()
+		.find(|(clk, name)| *name == clock || clock.parse::<ClockIdT>() == Ok(*clk))?
+		.0;
+	let secs: i64 = words.next()?.parse().ok()?;
+	let nsecs: i64 = words.next()?.parse().ok()?;
+	if words.next().is_some() || !(0..1_000_000_000).contains(&nsecs) {
+		return None;
+	}
+	let offset = secs.checked_mul(1_000_000_000)?.checked_add(nsecs)?;
+	Some((clk, offset))
+}
+
+fn write(pid: &Pid, buf: &[u8]) -> EResult<()> {
+	let privileged = Process::current().lock().cred.get().is_privileged();
+	if !privileged {
+		return Err(errno!(EPERM));
+	}
+	let buf = str::from_utf8(buf).map_err(|_| errno!(EINVAL))?;
+	let lines = buf.lines().filter(|l| !l.trim().is_empty());
+	// Check every line before applying any of them
+	if lines.clone().any(|l| parse_line(l).is_none()) {
+		return Err(errno!(EINVAL));
+	}
+	let proc = Process::get_by_pid(*pid).ok_or_else(|| errno!(ENOENT))?;
+	// The root namespace cannot be modified, since processes are in it
+	let ns = proc
+		.lock()
+		.time_ns_for_children
+		.clone()
+		.ok_or_else(|| errno!(EACCES))?;
+	for (clk, offset) in lines.filter_map(parse_line) {
+		ns.set_offset(clk, offset)?;
+	}
+	Ok(())
+}
@@ -38,7 +38,7 @@ impl NodeOps for SelfNode {
 	}
 
 	fn read_content(&self, _loc: &FileLocation, off: u64, buf: &mut [u8]) -> EResult<usize> {
-		let pid = Process::current().lock().get_pid();
+		let pid = Process::current().lock().get_local_pid();
 		format_content!(off, buf, "{pid}")
 	}
 }
@@ -19,10 +19,78 @@
 //! TODO doc
 
 use crate::{
-	file::{fs::NodeOps, FileLocation, FileType, Stat},
-	format_content,
+	crypto::rand,
+	file::{
+		fs::{
+			kernfs::{box_wrap, Tunable},
+			NodeOps,
+		},
+		perm::{ROOT_GID, ROOT_UID},
+		FileLocation, FileType, Stat,
+	},
+	format_content, logger,
+	logger::{Level, Subsystem},
+	HOSTNAME,
 };
-use utils::errno::EResult;
+use core::{fmt, fmt::Formatter};
+use utils::{
+	boxed::Box,
+	collections::vec::Vec,
+	errno,
+	errno::{AllocResult, EResult},
+	limits::HOST_NAME_MAX,
+	DisplayableStr,
+};
+
+/// Creates the `hostname` file, which allows to read and set the hostname of the system.
+pub fn hostname(_: ()) -> AllocResult<Box<dyn NodeOps>> {
+	box_wrap(Tunable {
+		mode: 0o644,
+		owner: (ROOT_UID, ROOT_GID),
+		data: (),
+		read: Some(hostname_read),
+		write: Some(hostname_write),
+	})
+}
+
+fn hostname_read(_: &(), off: u64, buf: &mut [u8]) -> EResult<usize> {
+	let hostname = HOSTNAME.lock();
+	format_content!(off, buf, "{}\n", DisplayableStr(hostname.as_slice()))
+}
+
+fn hostname_write(_: &(), buf: &[u8]) -> EResult<()> {
+	// Remove the newline appended by `echo`
+	let buf = buf.strip_suffix(b"\n").unwrap_or(buf);
+	if buf.len() > HOST_NAME_MAX {
+		return Err(errno!(EINVAL));
+	}
+	*HOSTNAME.lock() = Vec::try_from(buf)?;
+	Ok(())
+}
+
+/// Creates a file in the `log` directory, which allows to read and set the log level of the
+/// subsystem `subsys`.
+pub fn log_level(subsys: Subsystem) -> AllocResult<Box<dyn NodeOps>> {
+	box_wrap(Tunable {
+		mode: 0o644,
+		owner: (ROOT_UID, ROOT_GID),
+		data: subsys,
+		read: Some(log_level_read),
+		write: Some(log_level_write),
+	})
+}
+
+fn log_level_read(subsys: &Subsystem, off: u64, buf: &mut [u8]) -> EResult<usize> {
+	format_content!(off, buf, "{}\n", logger::get_level(*subsys).name())
+}
+
+fn log_level_write(subsys: &Subsystem, buf: &[u8]) -> EResult<()> {
+	// Remove the newline appended by `echo`
+	let buf = buf.strip_suffix(b"\n").unwrap_or(buf);
+	let level = Level::parse(buf).ok_or_else(|| errno!(EINVAL))?;
+	logger::set_level(*subsys, level);
+	Ok(())
+}
 
 /// The `osrelease` file.
 #[derive(Debug, Default)]
@@ -40,3 +108,35 @@ impl NodeOps for OsRelease {
 		format_content!(off, buf, "{}\n", crate::VERSION)
 	}
 }
+
+/// Displays a UUID in its canonical textual form.
+struct UuidDisp([u8; 16]);
+
+impl fmt::Display for UuidDisp {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		for (i, b) in self.0.iter().enumerate() {
+			if matches!(i, 4 | 6 | 8 | 10) {
+				write!(f, "-")?;
+			}
+			write!(f, "{b:02x}")?;
+		}
+		Ok(())
+	}
+}
+
+/// The `random/boot_id` file, which contains an ID that is unique to the current boot.
+#[derive(Debug, Default)]
+pub struct BootId;
+
+impl NodeOps for BootId {
+	fn get_stat(&self, _loc: &FileLocation) -> EResult<Stat> {
+		Ok(Stat {
+			mode: FileType::Regular.to_mode() | 0o444,
+			..Default::default()
+		})
+	}
+
+	fn read_content(&self, _loc: &FileLocation, off: u64, buf: &mut [u8]) -> EResult<usize> {
+		format_content!(off, buf, "{}\n", UuidDisp(rand::boot_id()))
+	}
+}
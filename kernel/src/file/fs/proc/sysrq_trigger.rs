@@ -0,0 +1,66 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+//! Implementation of the `sysrq-trigger` file, which allows to trigger kernel actions by writing
+//! the character associated with them.
+//!
+//! Supported characters:
+//! - `b`: reboots the system immediately, without synchronizing filesystems
+//! - `s`: synchronizes all filesystems
+//!
+//! Other characters are ignored.
+
+use crate::{
+	file::{
+		fs::{
+			kernfs::{box_wrap, Tunable},
+			NodeOps,
+		},
+		page_cache,
+		perm::{ROOT_GID, ROOT_UID},
+	},
+	power,
+};
+use utils::{
+	boxed::Box,
+	errno::{AllocResult, EResult},
+};
+
+/// Creates the node.
+pub fn init(_: ()) -> AllocResult<Box<dyn NodeOps>> {
+	box_wrap(Tunable {
+		mode: 0o200,
+		owner: (ROOT_UID, ROOT_GID),
+		data: (),
+		read: None,
+		write: Some(write),
+	})
+}
+
+fn write(_: &(), buf: &[u8]) -> EResult<()> {
+	for c in buf {
+		match c {
+			b'b' => power::reboot(),
+			// Errors are ignored, as for the `sync` system call
+			b's' => {
+				let _ = page_cache::sync(None);
+			}
+			_ => {}
+		}
+	}
+	Ok(())
+}
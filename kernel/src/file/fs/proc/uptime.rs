@@ -17,10 +17,14 @@
  */
 
 //! The uptime file returns the amount of time elapsed since the system started up.
+//!
+//! The boot time offset of the reader's time namespace is applied.
 
 use crate::{
 	file::{fs::NodeOps, FileLocation, FileType, Stat},
 	format_content,
+	process::Process,
+	time::{clock, clock::CLOCK_BOOTTIME, namespace},
 };
 use utils::errno::EResult;
 
@@ -37,7 +41,10 @@ impl NodeOps for Uptime {
 	}
 
 	fn read_content(&self, _loc: &FileLocation, off: u64, buf: &mut [u8]) -> EResult<usize> {
-		// TODO
-		format_content!(off, buf, "0.00 0.00\n")
+		let ns = Process::current().lock().time_ns.clone();
+		let uptime = namespace::to_local(ns.as_deref(), CLOCK_BOOTTIME, clock::boottime());
+		let uptime = uptime / 10_000_000;
+		// TODO idle time
+		format_content!(off, buf, "{}.{:02} 0.00\n", uptime / 100, uptime % 100)
 	}
 }
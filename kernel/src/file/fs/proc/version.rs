@@ -16,7 +16,8 @@
  * Maestro. If not, see <https://www.gnu.org/licenses/>.
  */
 
-//! The `version` file returns the version of the kernel.
+//! The `version` file returns the version of the kernel, along with information identifying the
+//! build.
 
 use crate::{
 	file::{fs::NodeOps, FileLocation, FileType, Stat},
@@ -37,6 +38,19 @@ impl NodeOps for Version {
 	}
 
 	fn read_content(&self, _loc: &FileLocation, off: u64, buf: &mut [u8]) -> EResult<usize> {
-		format_content!(off, buf, "{} version {}\n", crate::NAME, crate::VERSION)
+		let profile = if cfg!(debug_assertions) {
+			"debug"
+		} else {
+			"release"
+		};
+		format_content!(
+			off,
+			buf,
+			"{} version {} ({}) {profile} {}\n",
+			crate::NAME,
+			crate::VERSION,
+			env!("RUSTC_VERSION"),
+			crate::ARCH
+		)
 	}
 }
@@ -0,0 +1,241 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+//! The listing of a directory is stored in the directory table.
+//!
+//! A listing is a sequence of runs, each made of a header followed by up to 256 entries whose
+//! inodes are stored in the same metadata block. Entries are sorted by name.
+//!
+//! Large directories have an index, stored after their inode, which locates the header of some
+//! runs along with the name of their first entry. It allows skipping the beginning of the
+//! listing.
+
+use super::{
+	inode::{file_type, Inode, InodeKind},
+	MetadataReader, SquashFs, MAX_NAME_LEN, METADATA_SIZE,
+};
+use crate::file::FileType;
+use core::mem::size_of;
+use macros::AnyRepr;
+use utils::{errno, errno::EResult};
+
+/// The maximum number of entries in a run.
+const MAX_RUN_ENTRIES: u32 = 256;
+
+/// The header of a run of directory entries.
+#[repr(C, packed)]
+#[derive(AnyRepr, Clone, Debug, Default)]
+struct DirHeader {
+	/// The number of entries in the run, minus one.
+	count: u32,
+	/// The offset of the metadata block storing the inodes from the start of the inode table.
+	start: u32,
+	/// The base inode number of the run's entries.
+	inode_number: u32,
+}
+
+/// A directory entry. The entry's name follows.
+#[repr(C, packed)]
+#[derive(AnyRepr, Clone, Debug, Default)]
+struct DirEntryHeader {
+	/// The offset of the inode in the uncompressed metadata block.
+	offset: u16,
+	/// The difference between the inode number and the base of the run.
+	inode_offset: i16,
+	/// The basic type of the inode.
+	entry_type: u16,
+	/// The length of the name, minus one.
+	name_size: u16,
+}
+
+/// An entry of the directory index. The name of the first entry of the run follows.
+#[repr(C, packed)]
+#[derive(AnyRepr, Clone, Debug, Default)]
+struct DirIndex {
+	/// The offset of the run's header in the listing.
+	index: u32,
+	/// The offset of the metadata block storing the run's header from the start of the directory
+	/// table.
+	start: u32,
+	/// The length of the name, minus one.
+	name_size: u32,
+}
+
+/// An entry read from a directory listing.
+pub struct RawEntry {
+	/// The inode number.
+	pub inode: u32,
+	/// The inode reference.
+	pub inode_ref: u64,
+	/// The type of the entry.
+	pub entry_type: FileType,
+	/// The offset of the next entry in the listing.
+	pub next_off: u64,
+	/// The buffer storing the entry's name.
+	name: [u8; MAX_NAME_LEN],
+	/// The length of the entry's name.
+	name_len: usize,
+}
+
+impl RawEntry {
+	/// Returns the name of the entry.
+	pub fn name(&self) -> &[u8] {
+		&self.name[..self.name_len]
+	}
+}
+
+/// Iterator over the entries of a directory listing.
+pub struct DirIter<'f> {
+	/// The reader for the listing.
+	reader: MetadataReader<'f>,
+	/// The current offset in the listing.
+	off: u64,
+	/// The size of the listing.
+	end: u64,
+	/// Entries whose offset is lower than this value are skipped.
+	min_off: u64,
+
+	/// The number of entries left in the current run.
+	remaining: u32,
+	/// The offset of the metadata block storing the inodes of the current run.
+	start: u32,
+	/// The base inode number of the current run.
+	inode_base: u32,
+}
+
+impl<'f> DirIter<'f> {
+	/// Creates an iterator over the listing of the directory `inode`, skipping entries before
+	/// the offset `min_off`.
+	///
+	/// `pred` is called with the offset and first name of each run in the directory index, in
+	/// order. The iterator starts at the last run for which it returns `true`.
+	///
+	/// If the inode is not a directory, the function returns [`errno::ENOTDIR`].
+	fn start<P: Fn(u64, &[u8]) -> bool>(
+		fs: &'f SquashFs,
+		inode: &Inode,
+		min_off: u64,
+		pred: P,
+	) -> EResult<Self> {
+		let InodeKind::Directory {
+			block,
+			offset,
+			index_count,
+			index,
+			..
+		} = inode.kind
+		else {
+			return Err(errno!(ENOTDIR));
+		};
+		// The size of directories accounts for `.` and `..`, which are not stored
+		let end = inode.size.checked_sub(3).ok_or_else(|| errno!(EUCLEAN))?;
+		let table = fs.superblock.directory_table_start;
+		let mut pos = (table + block as u64, offset as usize);
+		let mut off = 0;
+		if index_count > 0 {
+			let mut reader = MetadataReader::new(fs, index.0, index.1)?;
+			let mut name = [0u8; MAX_NAME_LEN];
+			for _ in 0..index_count {
+				let ent: DirIndex = reader.read_obj()?;
+				let name = name
+					.get_mut(..(ent.name_size as usize + 1))
+					.ok_or_else(|| errno!(EUCLEAN))?;
+				reader.read(name)?;
+				if !pred(ent.index as _, name) {
+					break;
+				}
+				off = ent.index as u64;
+				// Every metadata block but the last is full
+				let inner_off = (offset as usize + ent.index as usize) % METADATA_SIZE;
+				pos = (table + ent.start as u64, inner_off);
+			}
+		}
+		Ok(Self {
+			reader: MetadataReader::new(fs, pos.0, pos.1)?,
+			off,
+			end,
+			min_off,
+
+			remaining: 0,
+			start: 0,
+			inode_base: 0,
+		})
+	}
+
+	/// Creates an iterator over the listing of the directory `inode`, starting at the first
+	/// entry located at or after the offset `off`.
+	///
+	/// If the inode is not a directory, the function returns [`errno::ENOTDIR`].
+	pub fn seek(fs: &'f SquashFs, inode: &Inode, off: u64) -> EResult<Self> {
+		Self::start(fs, inode, off, |index, _| index <= off)
+	}
+
+	/// Returns the entry with the given `name` in the directory `inode`.
+	///
+	/// If the inode is not a directory, the function returns [`errno::ENOTDIR`].
+	pub fn lookup(fs: &'f SquashFs, inode: &Inode, name: &[u8]) -> EResult<Option<RawEntry>> {
+		let mut iter = Self::start(fs, inode, 0, |_, n| n <= name)?;
+		while let Some(ent) = iter.next()? {
+			if ent.name() == name {
+				return Ok(Some(ent));
+			}
+		}
+		Ok(None)
+	}
+
+	/// Returns the next entry.
+	///
+	/// If no entry is left, the function returns `None`.
+	pub fn next(&mut self) -> EResult<Option<RawEntry>> {
+		loop {
+			if self.off >= self.end {
+				return Ok(None);
+			}
+			// Read the header of the next run
+			if self.remaining == 0 {
+				let hdr: DirHeader = self.reader.read_obj()?;
+				if hdr.count >= MAX_RUN_ENTRIES {
+					return Err(errno!(EUCLEAN));
+				}
+				self.off += size_of::<DirHeader>() as u64;
+				self.remaining = hdr.count + 1;
+				self.start = hdr.start;
+				self.inode_base = hdr.inode_number;
+				continue;
+			}
+			let ent_off = self.off;
+			let ent: DirEntryHeader = self.reader.read_obj()?;
+			let name_len = ent.name_size as usize + 1;
+			let mut name = [0u8; MAX_NAME_LEN];
+			self.reader
+				.read(name.get_mut(..name_len).ok_or_else(|| errno!(EUCLEAN))?)?;
+			self.off += (size_of::<DirEntryHeader>() + name_len) as u64;
+			self.remaining -= 1;
+			if ent_off < self.min_off {
+				continue;
+			}
+			break Ok(Some(RawEntry {
+				inode: self.inode_base.wrapping_add_signed(ent.inode_offset as i32),
+				inode_ref: ((self.start as u64) << 16) | ent.offset as u64,
+				entry_type: file_type(ent.entry_type)?,
+				next_off: self.off,
+				name,
+				name_len,
+			}));
+		}
+	}
+}
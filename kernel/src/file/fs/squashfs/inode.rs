@@ -0,0 +1,444 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+//! Inodes store the metadata of files, in the inode table.
+//!
+//! An inode starts with a header common to every type, followed by fields depending on its type.
+//! Each type has a basic and an extended version, the latter supporting larger values and
+//! extended attributes.
+
+use super::{MetadataReader, SquashFs, DATA_UNCOMPRESSED};
+use crate::{
+	device::id,
+	file::{FileType, Mode, Stat},
+};
+use core::cmp::min;
+use macros::AnyRepr;
+use utils::{errno, errno::EResult};
+
+/// Inode type: basic directory
+const TYPE_DIR: u16 = 1;
+/// Inode type: basic regular file
+const TYPE_FILE: u16 = 2;
+/// Inode type: basic symbolic link
+const TYPE_SYMLINK: u16 = 3;
+/// Inode type: basic block device
+const TYPE_BLKDEV: u16 = 4;
+/// Inode type: basic character device
+const TYPE_CHRDEV: u16 = 5;
+/// Inode type: basic named pipe
+const TYPE_FIFO: u16 = 6;
+/// Inode type: basic socket
+const TYPE_SOCKET: u16 = 7;
+/// Inode type: extended directory
+const TYPE_LDIR: u16 = 8;
+/// Inode type: extended regular file
+const TYPE_LFILE: u16 = 9;
+/// Inode type: extended symbolic link
+const TYPE_LSYMLINK: u16 = 10;
+/// Inode type: extended block device
+const TYPE_LBLKDEV: u16 = 11;
+/// Inode type: extended character device
+const TYPE_LCHRDEV: u16 = 12;
+/// Inode type: extended named pipe
+const TYPE_LFIFO: u16 = 13;
+/// Inode type: extended socket
+const TYPE_LSOCKET: u16 = 14;
+
+/// Fragment index telling the file has no fragment.
+const NO_FRAGMENT: u32 = 0xffffffff;
+
+/// Returns the file type corresponding to the basic inode type `inode_type`.
+///
+/// If the type is invalid, the function returns [`errno::EUCLEAN`].
+pub fn file_type(inode_type: u16) -> EResult<FileType> {
+	match inode_type {
+		TYPE_DIR => Ok(FileType::Directory),
+		TYPE_FILE => Ok(FileType::Regular),
+		TYPE_SYMLINK => Ok(FileType::Link),
+		TYPE_BLKDEV => Ok(FileType::BlockDevice),
+		TYPE_CHRDEV => Ok(FileType::CharDevice),
+		TYPE_FIFO => Ok(FileType::Fifo),
+		TYPE_SOCKET => Ok(FileType::Socket),
+		_ => Err(errno!(EUCLEAN)),
+	}
+}
+
+/// The header common to all inodes.
+#[repr(C, packed)]
+#[derive(AnyRepr, Clone, Debug, Default)]
+struct InodeHeader {
+	/// The type of the inode.
+	inode_type: u16,
+	/// The file's permissions.
+	permissions: u16,
+	/// The index of the owner's UID in the ID table.
+	uid_idx: u16,
+	/// The index of the owner's GID in the ID table.
+	gid_idx: u16,
+	/// Timestamp of the last modification of the file.
+	mtime: u32,
+	/// The inode number.
+	inode_number: u32,
+}
+
+/// Basic directory inode.
+#[repr(C, packed)]
+#[derive(AnyRepr, Clone, Debug, Default)]
+struct DirInode {
+	/// The offset of the listing's first metadata block from the start of the directory table.
+	block_index: u32,
+	/// The number of hard links to the directory.
+	link_count: u32,
+	/// The size of the listing, plus `3`.
+	file_size: u16,
+	/// The offset of the listing in the uncompressed data of its first metadata block.
+	block_offset: u16,
+	/// The inode number of the parent directory.
+	parent_inode: u32,
+}
+
+/// Extended directory inode.
+#[repr(C, packed)]
+#[derive(AnyRepr, Clone, Debug, Default)]
+struct LDirInode {
+	/// The number of hard links to the directory.
+	link_count: u32,
+	/// The size of the listing, plus `3`.
+	file_size: u32,
+	/// The offset of the listing's first metadata block from the start of the directory table.
+	block_index: u32,
+	/// The inode number of the parent directory.
+	parent_inode: u32,
+	/// The number of entries in the directory index following the inode.
+	index_count: u16,
+	/// The offset of the listing in the uncompressed data of its first metadata block.
+	block_offset: u16,
+	/// The index of the extended attributes.
+	xattr_idx: u32,
+}
+
+/// Basic regular file inode.
+#[repr(C, packed)]
+#[derive(AnyRepr, Clone, Debug, Default)]
+struct FileInode {
+	/// The offset of the first data block on the device.
+	blocks_start: u32,
+	/// The index of the fragment storing the tail of the file.
+	frag_index: u32,
+	/// The offset of the tail in the uncompressed fragment block.
+	block_offset: u32,
+	/// The size of the file in bytes.
+	file_size: u32,
+}
+
+/// Extended regular file inode.
+#[repr(C, packed)]
+#[derive(AnyRepr, Clone, Debug, Default)]
+struct LFileInode {
+	/// The offset of the first data block on the device.
+	blocks_start: u64,
+	/// The size of the file in bytes.
+	file_size: u64,
+	/// The number of bytes saved by omitting zero blocks.
+	sparse: u64,
+	/// The number of hard links to the file.
+	link_count: u32,
+	/// The index of the fragment storing the tail of the file.
+	frag_index: u32,
+	/// The offset of the tail in the uncompressed fragment block.
+	block_offset: u32,
+	/// The index of the extended attributes.
+	xattr_idx: u32,
+}
+
+/// Symbolic link inode, basic or extended. The target's path follows.
+#[repr(C, packed)]
+#[derive(AnyRepr, Clone, Debug, Default)]
+struct SymlinkInode {
+	/// The number of hard links to the file.
+	link_count: u32,
+	/// The size of the target's path.
+	target_size: u32,
+}
+
+/// Device inode, basic or extended.
+#[repr(C, packed)]
+#[derive(AnyRepr, Clone, Debug, Default)]
+struct DevInode {
+	/// The number of hard links to the file.
+	link_count: u32,
+	/// The device number.
+	device: u32,
+}
+
+/// Location in the metadata, as the offset of a metadata block on the device and an offset in
+/// its uncompressed data.
+pub type MetadataPos = (u64, usize);
+
+/// The type-specific content of an inode.
+#[derive(Debug)]
+pub enum InodeKind {
+	/// Directory.
+	Directory {
+		/// The offset of the listing's first metadata block from the start of the directory
+		/// table.
+		block: u32,
+		/// The offset of the listing in its first metadata block.
+		offset: u16,
+		/// The inode number of the parent directory.
+		parent: u32,
+		/// The number of entries in the directory index.
+		index_count: u16,
+		/// The location of the directory index.
+		index: MetadataPos,
+	},
+	/// Regular file.
+	Regular {
+		/// The offset of the first data block on the device.
+		blocks_start: u64,
+		/// The index of the fragment storing the tail of the file.
+		fragment: u32,
+		/// The offset of the tail in the fragment.
+		fragment_off: u32,
+		/// The location of the list of data blocks sizes.
+		sizes: MetadataPos,
+	},
+	/// Symbolic link.
+	Link {
+		/// The location of the target's path.
+		target: MetadataPos,
+	},
+	/// Block or character device, with its device number.
+	Device(u32),
+	/// Named pipe or socket.
+	Ipc,
+}
+
+/// A squashfs inode.
+#[derive(Debug)]
+pub struct Inode {
+	/// The type of the file.
+	pub file_type: FileType,
+	/// The file's permissions.
+	pub permissions: u16,
+	/// The index of the owner's UID in the ID table.
+	pub uid_idx: u16,
+	/// The index of the owner's GID in the ID table.
+	pub gid_idx: u16,
+	/// Timestamp of the last modification of the file.
+	pub mtime: u32,
+	/// The inode number.
+	pub number: u32,
+	/// The number of hard links to the file.
+	pub nlink: u32,
+	/// The size of the file in bytes.
+	///
+	/// For directories, this is the size of the listing plus `3`, accounting for the `.` and `..`
+	/// entries which are not stored.
+	pub size: u64,
+	/// The type-specific content.
+	pub kind: InodeKind,
+}
+
+impl Inode {
+	/// Reads the inode at the current position of `reader`.
+	pub fn read(reader: &mut MetadataReader) -> EResult<Self> {
+		let hdr: InodeHeader = reader.read_obj()?;
+		let (nlink, size, kind) = match hdr.inode_type {
+			TYPE_DIR => {
+				let dir: DirInode = reader.read_obj()?;
+				let kind = InodeKind::Directory {
+					block: dir.block_index,
+					offset: dir.block_offset,
+					parent: dir.parent_inode,
+					index_count: 0,
+					index: reader.position(),
+				};
+				(dir.link_count, dir.file_size as u64, kind)
+			}
+			TYPE_LDIR => {
+				let dir: LDirInode = reader.read_obj()?;
+				let kind = InodeKind::Directory {
+					block: dir.block_index,
+					offset: dir.block_offset,
+					parent: dir.parent_inode,
+					index_count: dir.index_count,
+					index: reader.position(),
+				};
+				(dir.link_count, dir.file_size as u64, kind)
+			}
+			TYPE_FILE => {
+				let file: FileInode = reader.read_obj()?;
+				let kind = InodeKind::Regular {
+					blocks_start: file.blocks_start as _,
+					fragment: file.frag_index,
+					fragment_off: file.block_offset,
+					sizes: reader.position(),
+				};
+				(1, file.file_size as u64, kind)
+			}
+			TYPE_LFILE => {
+				let file: LFileInode = reader.read_obj()?;
+				let kind = InodeKind::Regular {
+					blocks_start: file.blocks_start,
+					fragment: file.frag_index,
+					fragment_off: file.block_offset,
+					sizes: reader.position(),
+				};
+				(file.link_count, file.file_size, kind)
+			}
+			TYPE_SYMLINK | TYPE_LSYMLINK => {
+				let link: SymlinkInode = reader.read_obj()?;
+				let kind = InodeKind::Link {
+					target: reader.position(),
+				};
+				(link.link_count, link.target_size as u64, kind)
+			}
+			TYPE_BLKDEV | TYPE_CHRDEV | TYPE_LBLKDEV | TYPE_LCHRDEV => {
+				let dev: DevInode = reader.read_obj()?;
+				(dev.link_count, 0, InodeKind::Device(dev.device))
+			}
+			TYPE_FIFO | TYPE_SOCKET | TYPE_LFIFO | TYPE_LSOCKET => {
+				let link_count: u32 = reader.read_obj()?;
+				(link_count, 0, InodeKind::Ipc)
+			}
+			_ => return Err(errno!(EUCLEAN)),
+		};
+		// Extended types follow basic types in the same order
+		let basic_type = if hdr.inode_type >= TYPE_LDIR {
+			hdr.inode_type - (TYPE_LDIR - TYPE_DIR)
+		} else {
+			hdr.inode_type
+		};
+		Ok(Self {
+			file_type: file_type(basic_type)?,
+			permissions: hdr.permissions,
+			uid_idx: hdr.uid_idx,
+			gid_idx: hdr.gid_idx,
+			mtime: hdr.mtime,
+			number: hdr.inode_number,
+			nlink,
+			size,
+			kind,
+		})
+	}
+
+	/// Returns the status of the file.
+	pub fn stat(&self, fs: &SquashFs) -> EResult<Stat> {
+		let (dev_major, dev_minor) = match self.kind {
+			InodeKind::Device(dev) => (id::major(dev as _), id::minor(dev as _)),
+			_ => (0, 0),
+		};
+		Ok(Stat {
+			mode: self.file_type.to_mode() | (self.permissions as Mode & 0o7777),
+			nlink: self.nlink.try_into().unwrap_or(u16::MAX),
+			uid: fs.get_id(self.uid_idx)? as _,
+			gid: fs.get_id(self.gid_idx)? as _,
+			size: self.size,
+			blocks: self.size.div_ceil(512),
+			dev_major,
+			dev_minor,
+			ctime: self.mtime as _,
+			mtime: self.mtime as _,
+			atime: self.mtime as _,
+		})
+	}
+
+	/// Reads the content of the regular file from offset `off` into `buf`.
+	///
+	/// The function returns the number of bytes read.
+	pub fn read_content(&self, fs: &SquashFs, off: u64, buf: &mut [u8]) -> EResult<usize> {
+		let InodeKind::Regular {
+			blocks_start,
+			fragment,
+			fragment_off,
+			sizes,
+		} = self.kind
+		else {
+			return Err(errno!(EINVAL));
+		};
+		if off >= self.size {
+			return Ok(0);
+		}
+		let len = min(buf.len() as u64, self.size - off) as usize;
+		let blk_size = fs.superblock.block_size as u64;
+		// If the file has a fragment, its tail is stored in it instead of a block
+		let blocks_count = if fragment == NO_FRAGMENT {
+			self.size.div_ceil(blk_size)
+		} else {
+			self.size / blk_size
+		};
+		// Skip the blocks before the one containing `off`
+		let mut sizes = MetadataReader::new(fs, sizes.0, sizes.1)?;
+		let mut blk_off = blocks_start;
+		for _ in 0..min(off / blk_size, blocks_count) {
+			let size: u32 = sizes.read_obj()?;
+			blk_off += (size & !DATA_UNCOMPRESSED) as u64;
+		}
+		let mut i = 0;
+		while i < len {
+			let cur = off + i as u64;
+			let blk = cur / blk_size;
+			let inner_off = (cur % blk_size) as usize;
+			let l = min(len - i, blk_size as usize - inner_off);
+			let (data, data_off) = if blk < blocks_count {
+				let size: u32 = sizes.read_obj()?;
+				let data = fs.data_block(blk_off, size)?;
+				blk_off += (size & !DATA_UNCOMPRESSED) as u64;
+				(data, inner_off)
+			} else {
+				let data = fs.fragment_block(fragment)?;
+				(Some(data), fragment_off as usize + inner_off)
+			};
+			let dst = &mut buf[i..(i + l)];
+			match data {
+				Some(data) => {
+					let src = data
+						.data
+						.get(data_off..(data_off + l))
+						.ok_or_else(|| errno!(EUCLEAN))?;
+					dst.copy_from_slice(src);
+				}
+				// Sparse block
+				None => dst.fill(0),
+			}
+			i += l;
+		}
+		Ok(len)
+	}
+
+	/// Reads the target of the symbolic link from offset `off` into `buf`.
+	///
+	/// The function returns the number of bytes read.
+	pub fn read_link(&self, fs: &SquashFs, off: u64, buf: &mut [u8]) -> EResult<usize> {
+		let InodeKind::Link {
+			target,
+		} = self.kind
+		else {
+			return Err(errno!(EINVAL));
+		};
+		let Some(len) = self.size.checked_sub(off) else {
+			return Err(errno!(EINVAL));
+		};
+		let len = min(buf.len() as u64, len) as usize;
+		let mut reader = MetadataReader::new(fs, target.0, target.1)?;
+		reader.skip(off as _)?;
+		reader.read(&mut buf[..len])?;
+		Ok(len)
+	}
+}
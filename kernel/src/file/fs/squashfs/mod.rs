@@ -0,0 +1,727 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+//! Squashfs is a compressed read-only filesystem, mostly used for live and embedded systems
+//! images.
+//!
+//! The filesystem is made of several tables, usually compressed:
+//! - Inode table: stores the inodes, in metadata blocks
+//! - Directory table: stores the directories' listings, in metadata blocks
+//! - Fragment table: locates fragment blocks, which store the tails of several files together
+//! - Export table: maps inode numbers to inode references
+//! - ID table: stores the UIDs and GIDs used by inodes, which refer to them by index
+//!
+//! Metadata blocks store up to 8 KiB of data each. A location in a table is represented by the
+//! offset of the metadata block from the start of the table, and the offset in its uncompressed
+//! data. An inode reference is such a location, encoded on 48 bits.
+//!
+//! Only zlib and LZ4 compressions are supported.
+//!
+//! For more information, see the [specifications](https://dr-emann.github.io/squashfs/).
+
+mod dir;
+mod inode;
+
+use crate::{
+	device::DeviceIO,
+	file::{
+		fs::{downcast_fs, parse_options, Filesystem, FilesystemType, NodeOps, StatSet, Statfs},
+		DirEntry, FileLocation, FileType, INode, Stat,
+	},
+};
+use core::{cmp::min, fmt, fmt::Formatter, mem::size_of};
+use dir::DirIter;
+use inode::{Inode, InodeKind};
+use macros::AnyRepr;
+use utils::{
+	boxed::Box,
+	bytes::{as_bytes_mut, AnyRepr},
+	collections::{hashmap::HashMap, path::PathBuf, vec::Vec},
+	compress::{lz4, zlib},
+	errno,
+	errno::EResult,
+	lock::Mutex,
+	ptr::{arc::Arc, cow::Cow},
+	vec,
+};
+
+/// The filesystem's magic number.
+const SQUASHFS_MAGIC: u32 = 0x73717368;
+/// The supported major version.
+const VERSION_MAJOR: u16 = 4;
+
+/// The minimum size of a data block.
+const MIN_BLOCK_SIZE: u32 = 4096;
+/// The maximum size of a data block.
+const MAX_BLOCK_SIZE: u32 = 1024 * 1024;
+/// The maximum size of the uncompressed data of a metadata block.
+const METADATA_SIZE: usize = 8192;
+/// Flag in the header of a metadata block telling its data is not compressed.
+const METADATA_UNCOMPRESSED: u16 = 1 << 15;
+/// Flag in the size of a data block telling its data is not compressed.
+const DATA_UNCOMPRESSED: u32 = 1 << 24;
+
+/// Compression: zlib
+const COMPRESSION_ZLIB: u16 = 1;
+/// Compression: LZ4
+const COMPRESSION_LZ4: u16 = 5;
+
+/// Superblock flag: the export table is present.
+const FLAG_EXPORTABLE: u16 = 0x80;
+
+/// The maximum length of a name in the filesystem.
+const MAX_NAME_LEN: usize = 256;
+
+/// Reads `buf.len()` bytes at the offset `off` in bytes on the device.
+fn read_bytes(io: &dyn DeviceIO, off: u64, buf: &mut [u8]) -> EResult<()> {
+	let blk_size = io.block_size().get();
+	let start = off / blk_size;
+	let end = (off + buf.len() as u64).div_ceil(blk_size);
+	let mut tmp = vec![0u8; ((end - start) * blk_size) as usize]?;
+	io.read(start, &mut tmp)?;
+	let inner_off = (off % blk_size) as usize;
+	buf.copy_from_slice(&tmp[inner_off..(inner_off + buf.len())]);
+	Ok(())
+}
+
+/// The squashfs superblock.
+#[repr(C, packed)]
+#[derive(AnyRepr, Clone, Debug, Default)]
+pub struct Superblock {
+	/// Magic number, to identify the filesystem.
+	s_magic: u32,
+	/// The number of inodes in the filesystem.
+	inodes: u32,
+	/// The timestamp of the creation of the filesystem.
+	mkfs_time: u32,
+	/// The size of a data block, in bytes.
+	block_size: u32,
+	/// The number of entries in the fragment table.
+	fragments: u32,
+	/// The compression algorithm used by the filesystem.
+	compression: u16,
+	/// The log2 of `block_size`.
+	block_log: u16,
+	/// Superblock flags.
+	flags: u16,
+	/// The number of entries in the ID table.
+	no_ids: u16,
+	/// Major version of the filesystem.
+	s_major: u16,
+	/// Minor version of the filesystem.
+	s_minor: u16,
+	/// The reference of the root directory's inode.
+	root_inode: u64,
+	/// The number of bytes used by the filesystem on the device.
+	bytes_used: u64,
+	/// The offset of the ID table.
+	id_table_start: u64,
+	/// The offset of the extended attributes ID table.
+	xattr_id_table_start: u64,
+	/// The offset of the inode table.
+	inode_table_start: u64,
+	/// The offset of the directory table.
+	directory_table_start: u64,
+	/// The offset of the fragment table.
+	fragment_table_start: u64,
+	/// The offset of the export table.
+	lookup_table_start: u64,
+}
+
+impl Superblock {
+	/// Creates a new instance by reading from the given device.
+	fn read(io: &dyn DeviceIO) -> EResult<Self> {
+		let mut sb = Self::default();
+		read_bytes(io, 0, as_bytes_mut(&mut sb))?;
+		Ok(sb)
+	}
+
+	/// Tells whether the superblock is valid.
+	pub fn is_valid(&self) -> bool {
+		self.s_magic == SQUASHFS_MAGIC && self.s_major == VERSION_MAJOR
+	}
+}
+
+/// A compression algorithm.
+#[derive(Clone, Copy, Debug)]
+enum Compression {
+	/// zlib (called `gzip` by squashfs tools).
+	Zlib,
+	/// LZ4, in block format.
+	Lz4,
+}
+
+impl Compression {
+	/// Returns the algorithm with the given ID in the superblock.
+	///
+	/// If the algorithm is not supported, the function returns `None`.
+	fn from_id(id: u16) -> Option<Self> {
+		match id {
+			COMPRESSION_ZLIB => Some(Self::Zlib),
+			COMPRESSION_LZ4 => Some(Self::Lz4),
+			_ => None,
+		}
+	}
+
+	/// Decompresses `src` into `dst`, returning the size of the decompressed data.
+	///
+	/// If the data is invalid, the function returns [`errno::EUCLEAN`].
+	fn decompress(self, src: &[u8], dst: &mut [u8]) -> EResult<usize> {
+		let len = match self {
+			Self::Zlib => zlib::decompress(src, dst),
+			Self::Lz4 => lz4::decompress(src, dst),
+		};
+		len.ok_or_else(|| errno!(EUCLEAN))
+	}
+}
+
+/// An uncompressed block read from the device.
+#[derive(Debug)]
+struct Block {
+	/// The data of the block.
+	data: Vec<u8>,
+	/// The offset of the following block on the device.
+	next: u64,
+}
+
+/// Cache for the last block read from the device.
+///
+/// Consecutive reads usually hit the same block, which saves decompressing it again.
+#[derive(Debug, Default)]
+struct BlockCache(Mutex<Option<(u64, Arc<Block>)>>);
+
+impl BlockCache {
+	/// Returns the block at the offset `off` on the device, reading it with `read` if it is not
+	/// in cache.
+	fn get<F: FnOnce() -> EResult<Block>>(&self, off: u64, read: F) -> EResult<Arc<Block>> {
+		if let Some((o, blk)) = &*self.0.lock() {
+			if *o == off {
+				return Ok(blk.clone());
+			}
+		}
+		let blk = Arc::new(read()?)?;
+		*self.0.lock() = Some((off, blk.clone()));
+		Ok(blk)
+	}
+}
+
+/// Reader for data stored in consecutive metadata blocks.
+struct MetadataReader<'f> {
+	/// The filesystem.
+	fs: &'f SquashFs,
+	/// The offset of the current block on the device.
+	pos: u64,
+	/// The current block.
+	blk: Arc<Block>,
+	/// The offset in the current block's data.
+	off: usize,
+}
+
+impl<'f> MetadataReader<'f> {
+	/// Creates a reader starting at offset `off` in the uncompressed data of the metadata block
+	/// located at `blk` on the device.
+	fn new(fs: &'f SquashFs, pos: u64, off: usize) -> EResult<Self> {
+		let blk = fs.metadata_block(pos)?;
+		if off > blk.data.len() {
+			return Err(errno!(EUCLEAN));
+		}
+		Ok(Self {
+			fs,
+			pos,
+			blk,
+			off,
+		})
+	}
+
+	/// Returns the current position, to create a new reader at the same location later.
+	fn position(&self) -> inode::MetadataPos {
+		(self.pos, self.off)
+	}
+
+	/// Moves to the next block if the current one is exhausted, then returns the data remaining
+	/// in it.
+	fn remaining(&mut self) -> EResult<&[u8]> {
+		if self.off >= self.blk.data.len() {
+			self.pos = self.blk.next;
+			self.blk = self.fs.metadata_block(self.pos)?;
+			self.off = 0;
+		}
+		Ok(&self.blk.data[self.off..])
+	}
+
+	/// Fills `buf` with the data at the current position, then moves forward.
+	fn read(&mut self, buf: &mut [u8]) -> EResult<()> {
+		let mut i = 0;
+		while i < buf.len() {
+			let data = self.remaining()?;
+			let len = min(buf.len() - i, data.len());
+			buf[i..(i + len)].copy_from_slice(&data[..len]);
+			self.off += len;
+			i += len;
+		}
+		Ok(())
+	}
+
+	/// Reads an object of the given type at the current position, then moves forward.
+	fn read_obj<T: AnyRepr + Default>(&mut self) -> EResult<T> {
+		let mut val = T::default();
+		self.read(as_bytes_mut(&mut val))?;
+		Ok(val)
+	}
+
+	/// Moves forward by `n` bytes.
+	fn skip(&mut self, mut n: usize) -> EResult<()> {
+		while n > 0 {
+			let len = min(n, self.remaining()?.len());
+			self.off += len;
+			n -= len;
+		}
+		Ok(())
+	}
+}
+
+/// Operations on squashfs nodes.
+#[derive(Debug)]
+struct SquashFsNodeOps;
+
+impl NodeOps for SquashFsNodeOps {
+	fn get_stat(&self, loc: &FileLocation) -> EResult<Stat> {
+		let fs = loc.get_filesystem().unwrap();
+		let fs = downcast_fs::<SquashFs>(&*fs);
+		let inode_ = fs.read_inode(loc.inode)?;
+		inode_.stat(fs)
+	}
+
+	fn set_stat(&self, _loc: &FileLocation, _set: StatSet) -> EResult<()> {
+		Err(errno!(EROFS))
+	}
+
+	fn read_content(&self, loc: &FileLocation, off: u64, buf: &mut [u8]) -> EResult<usize> {
+		let fs = loc.get_filesystem().unwrap();
+		let fs = downcast_fs::<SquashFs>(&*fs);
+		let inode_ = fs.read_inode(loc.inode)?;
+		match inode_.kind {
+			InodeKind::Regular {
+				..
+			} => inode_.read_content(fs, off, buf),
+			InodeKind::Link {
+				..
+			} => inode_.read_link(fs, off, buf),
+			_ => Err(errno!(EINVAL)),
+		}
+	}
+
+	fn write_content(&self, _loc: &FileLocation, _off: u64, _buf: &[u8]) -> EResult<usize> {
+		Err(errno!(EROFS))
+	}
+
+	fn truncate_content(&self, _loc: &FileLocation, _size: u64) -> EResult<()> {
+		Err(errno!(EROFS))
+	}
+
+	fn entry_by_name<'n>(
+		&self,
+		loc: &FileLocation,
+		name: &'n [u8],
+	) -> EResult<Option<(DirEntry<'n>, Box<dyn NodeOps>)>> {
+		let fs = loc.get_filesystem().unwrap();
+		let fs = downcast_fs::<SquashFs>(&*fs);
+		let inode_ = fs.read_inode(loc.inode)?;
+		let Some(ent) = DirIter::lookup(fs, &inode_, name)? else {
+			return Ok(None);
+		};
+		// Remember the location of the inode, to find it again later
+		fs.refs.lock().insert(ent.inode as _, ent.inode_ref)?;
+		let ent = DirEntry {
+			inode: ent.inode as _,
+			entry_type: Some(ent.entry_type),
+			name: Cow::Borrowed(name),
+		};
+		Ok(Some((ent, Box::new(SquashFsNodeOps)?)))
+	}
+
+	fn next_entry(
+		&self,
+		loc: &FileLocation,
+		off: u64,
+	) -> EResult<Option<(DirEntry<'static>, u64)>> {
+		let fs = loc.get_filesystem().unwrap();
+		let fs = downcast_fs::<SquashFs>(&*fs);
+		let inode_ = fs.read_inode(loc.inode)?;
+		let InodeKind::Directory {
+			parent, ..
+		} = inode_.kind
+		else {
+			return Err(errno!(ENOTDIR));
+		};
+		// The `.` and `..` entries are not stored, but they are accounted for in the size of the
+		// directory, at offsets `0` and `1`. Other entries start at offset `3`
+		let (inode, name, next_off): (INode, &'static [u8], u64) = match off {
+			0 => (loc.inode, b".", 1),
+			// The parent of the root directory is outside the filesystem
+			1 if loc.inode == fs.root => (loc.inode, b"..", 3),
+			1 => (parent as _, b"..", 3),
+			_ => {
+				let Some(ent) = DirIter::seek(fs, &inode_, off.saturating_sub(3))?.next()? else {
+					return Ok(None);
+				};
+				let next_off = ent.next_off + 3;
+				let ent = DirEntry {
+					inode: ent.inode as _,
+					entry_type: Some(ent.entry_type),
+					name: Cow::Owned(ent.name().try_into()?),
+				};
+				return Ok(Some((ent, next_off)));
+			}
+		};
+		let ent = DirEntry {
+			inode,
+			entry_type: Some(FileType::Directory),
+			name: Cow::Borrowed(name),
+		};
+		Ok(Some((ent, next_off)))
+	}
+
+	fn add_file(
+		&self,
+		_parent: &FileLocation,
+		_name: &[u8],
+		_stat: Stat,
+	) -> EResult<(INode, Box<dyn NodeOps>)> {
+		Err(errno!(EROFS))
+	}
+
+	fn link(&self, _parent: &FileLocation, _name: &[u8], _target: INode) -> EResult<()> {
+		Err(errno!(EROFS))
+	}
+
+	fn unlink(&self, _parent: &FileLocation, _name: &[u8]) -> EResult<()> {
+		Err(errno!(EROFS))
+	}
+
+	fn rename(
+		&self,
+		_old_parent: &FileLocation,
+		_old_name: &[u8],
+		_new_parent: &FileLocation,
+		_new_name: &[u8],
+		_flags: u32,
+	) -> EResult<()> {
+		Err(errno!(EROFS))
+	}
+
+	fn remove_node(&self, _loc: &FileLocation) -> EResult<()> {
+		Err(errno!(EROFS))
+	}
+}
+
+/// An instance of the squashfs filesystem.
+struct SquashFs {
+	/// The device on which the filesystem is located.
+	io: Arc<dyn DeviceIO>,
+	/// The filesystem's superblock.
+	superblock: Superblock,
+	/// The compression algorithm.
+	compression: Compression,
+	/// The root directory's inode number.
+	root: INode,
+	/// The content of the ID table.
+	ids: Vec<u32>,
+
+	/// The references of the inodes that have been found, by inode number.
+	///
+	/// This allows finding inodes from their number without the export table, which is optional.
+	refs: Mutex<HashMap<INode, u64>>,
+	/// Cache for metadata blocks.
+	metadata_cache: BlockCache,
+	/// Cache for data and fragment blocks.
+	data_cache: BlockCache,
+}
+
+impl SquashFs {
+	/// Creates a new instance.
+	///
+	/// Arguments:
+	/// - `superblock` is the filesystem's superblock
+	/// - `io` is the I/O interface
+	fn new(superblock: Superblock, io: Arc<dyn DeviceIO>) -> EResult<Self> {
+		if !superblock.is_valid() {
+			return Err(errno!(EINVAL));
+		}
+		let block_size = superblock.block_size;
+		let block_size_valid = block_size.is_power_of_two()
+			&& (MIN_BLOCK_SIZE..=MAX_BLOCK_SIZE).contains(&block_size)
+			&& block_size.trailing_zeros() == superblock.block_log as u32;
+		if !block_size_valid {
+			return Err(errno!(EINVAL));
+		}
+		let compression_id = superblock.compression;
+		let Some(compression) = Compression::from_id(compression_id) else {
+			crate::log!(
+				Vfs,
+				Error,
+				"squashfs: unsupported compression algorithm {compression_id}"
+			);
+			return Err(errno!(EINVAL));
+		};
+		let mut fs = Self {
+			io,
+			superblock,
+			compression,
+			root: 0,
+			ids: Vec::new(),
+
+			refs: Mutex::new(HashMap::new()),
+			metadata_cache: BlockCache::default(),
+			data_cache: BlockCache::default(),
+		};
+		fs.ids = fs.read_ids()?;
+		// Register the root directory
+		let root_ref = fs.superblock.root_inode;
+		let root = fs.read_inode_at(root_ref)?;
+		if !matches!(root.kind, InodeKind::Directory { .. }) {
+			return Err(errno!(EUCLEAN));
+		}
+		fs.root = root.number as _;
+		fs.refs.lock().insert(fs.root, root_ref)?;
+		Ok(fs)
+	}
+
+	/// Reads the block of `len` bytes located at `off` on the device, decompressing it if
+	/// `compressed` is set.
+	///
+	/// `max` is the maximum size of the uncompressed data.
+	fn read_block(&self, off: u64, len: usize, compressed: bool, max: usize) -> EResult<Block> {
+		if len > max {
+			return Err(errno!(EUCLEAN));
+		}
+		let mut raw = vec![0u8; len]?;
+		read_bytes(&*self.io, off, &mut raw)?;
+		let data = if compressed {
+			let mut data = vec![0u8; max]?;
+			let len = self.compression.decompress(&raw, &mut data)?;
+			data.truncate(len);
+			data
+		} else {
+			raw
+		};
+		Ok(Block {
+			data,
+			next: off + len as u64,
+		})
+	}
+
+	/// Returns the metadata block located at `off` on the device.
+	fn metadata_block(&self, off: u64) -> EResult<Arc<Block>> {
+		self.metadata_cache.get(off, || {
+			let mut hdr = [0u8; 2];
+			read_bytes(&*self.io, off, &mut hdr)?;
+			let hdr = u16::from_le_bytes(hdr);
+			let len = (hdr & !METADATA_UNCOMPRESSED) as usize;
+			if len == 0 {
+				return Err(errno!(EUCLEAN));
+			}
+			let compressed = hdr & METADATA_UNCOMPRESSED == 0;
+			self.read_block(off + 2, len, compressed, METADATA_SIZE)
+		})
+	}
+
+	/// Returns the data block located at `off` on the device, with the given size entry.
+	///
+	/// If the block is sparse, the function returns `None`.
+	fn data_block(&self, off: u64, size: u32) -> EResult<Option<Arc<Block>>> {
+		let len = (size & !DATA_UNCOMPRESSED) as usize;
+		if len == 0 {
+			return Ok(None);
+		}
+		let compressed = size & DATA_UNCOMPRESSED == 0;
+		let max = self.superblock.block_size as usize;
+		let blk = self
+			.data_cache
+			.get(off, || self.read_block(off, len, compressed, max))?;
+		Ok(Some(blk))
+	}
+
+	/// Reads the entry at `index` in the table located at `start` on the device, into `buf`.
+	///
+	/// Tables are stored in consecutive metadata blocks, which are located through an array of
+	/// pointers at `start`.
+	fn table_entry(&self, start: u64, index: u64, buf: &mut [u8]) -> EResult<()> {
+		let off = index * buf.len() as u64;
+		let blk = off / METADATA_SIZE as u64;
+		let mut ptr = [0u8; size_of::<u64>()];
+		read_bytes(&*self.io, start + blk * size_of::<u64>() as u64, &mut ptr)?;
+		let ptr = u64::from_le_bytes(ptr);
+		MetadataReader::new(self, ptr, (off % METADATA_SIZE as u64) as usize)?.read(buf)
+	}
+
+	/// Reads the content of the ID table.
+	fn read_ids(&self) -> EResult<Vec<u32>> {
+		let count = self.superblock.no_ids as usize;
+		let mut ids = vec![0u32; count]?;
+		if count > 0 {
+			let mut ptr = [0u8; size_of::<u64>()];
+			read_bytes(&*self.io, self.superblock.id_table_start, &mut ptr)?;
+			let ptr = u64::from_le_bytes(ptr);
+			// The metadata blocks of the table are consecutive
+			let mut reader = MetadataReader::new(self, ptr, 0)?;
+			for id in ids.iter_mut() {
+				*id = u32::from_le(reader.read_obj()?);
+			}
+		}
+		Ok(ids)
+	}
+
+	/// Returns the ID at `index` in the ID table.
+	fn get_id(&self, index: u16) -> EResult<u32> {
+		self.ids
+			.get(index as usize)
+			.cloned()
+			.ok_or_else(|| errno!(EUCLEAN))
+	}
+
+	/// Returns the data of the fragment block at `index` in the fragment table.
+	fn fragment_block(&self, index: u32) -> EResult<Arc<Block>> {
+		if index >= self.superblock.fragments {
+			return Err(errno!(EUCLEAN));
+		}
+		let mut ent = [0u8; 16];
+		self.table_entry(self.superblock.fragment_table_start, index as _, &mut ent)?;
+		let off = u64::from_le_bytes(ent[..8].try_into().unwrap());
+		let size = u32::from_le_bytes(ent[8..12].try_into().unwrap());
+		// A fragment block cannot be sparse
+		self.data_block(off, size)?.ok_or_else(|| errno!(EUCLEAN))
+	}
+
+	/// Returns the reference of the inode with number `inode`.
+	///
+	/// If the inode cannot be found, the function returns [`errno::ENOENT`].
+	fn inode_ref(&self, inode: INode) -> EResult<u64> {
+		if let Some(r) = self.refs.lock().get(&inode) {
+			return Ok(*r);
+		}
+		let exportable = self.superblock.flags & FLAG_EXPORTABLE != 0;
+		if !exportable || inode == 0 || inode > self.superblock.inodes as INode {
+			return Err(errno!(ENOENT));
+		}
+		let mut r = [0u8; size_of::<u64>()];
+		self.table_entry(self.superblock.lookup_table_start, inode - 1, &mut r)?;
+		let r = u64::from_le_bytes(r);
+		self.refs.lock().insert(inode, r)?;
+		Ok(r)
+	}
+
+	/// Reads the inode with the reference `inode_ref`.
+	fn read_inode_at(&self, inode_ref: u64) -> EResult<Inode> {
+		let blk = self.superblock.inode_table_start + (inode_ref >> 16);
+		let mut reader = MetadataReader::new(self, blk, (inode_ref & 0xffff) as usize)?;
+		Inode::read(&mut reader)
+	}
+
+	/// Reads the inode with number `inode`.
+	fn read_inode(&self, inode: INode) -> EResult<Inode> {
+		let inode_ = self.read_inode_at(self.inode_ref(inode)?)?;
+		if inode_.number as INode != inode {
+			return Err(errno!(EUCLEAN));
+		}
+		Ok(inode_)
+	}
+}
+
+impl Filesystem for SquashFs {
+	fn get_name(&self) -> &[u8] {
+		b"squashfs"
+	}
+
+	fn use_cache(&self) -> bool {
+		true
+	}
+
+	fn get_root_inode(&self) -> INode {
+		self.root
+	}
+
+	fn is_readonly(&self) -> bool {
+		true
+	}
+
+	fn get_stat(&self) -> EResult<Statfs> {
+		let block_size = self.superblock.block_size;
+		Ok(Statfs {
+			f_type: SQUASHFS_MAGIC as _,
+			f_bsize: block_size as _,
+			f_blocks: self.superblock.bytes_used.div_ceil(block_size as _) as _,
+			f_bfree: 0,
+			f_bavail: 0,
+			f_files: self.superblock.inodes as _,
+			f_ffree: 0,
+			f_fsid: Default::default(),
+			f_namelen: MAX_NAME_LEN as _,
+			f_frsize: block_size as _,
+			f_flags: 0,
+			f_spare: [0; 4],
+		})
+	}
+
+	fn node_from_inode(&self, inode: INode) -> EResult<Box<dyn NodeOps>> {
+		// Check the inode exists
+		self.read_inode(inode)?;
+		Ok(Box::new(SquashFsNodeOps)?)
+	}
+}
+
+impl fmt::Debug for SquashFs {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		f.debug_struct("SquashFs")
+			.field("superblock", &self.superblock)
+			.field("compression", &self.compression)
+			.finish()
+	}
+}
+
+/// The squashfs filesystem type.
+pub struct SquashFsType;
+
+impl FilesystemType for SquashFsType {
+	fn get_name(&self) -> &'static [u8] {
+		b"squashfs"
+	}
+
+	fn requires_device(&self) -> bool {
+		true
+	}
+
+	fn detect(&self, io: &dyn DeviceIO) -> EResult<bool> {
+		Ok(Superblock::read(io)?.is_valid())
+	}
+
+	fn load_filesystem(
+		&self,
+		io: Option<Arc<dyn DeviceIO>>,
+		_mountpath: PathBuf,
+		_readonly: bool,
+		data: &[u8],
+	) -> EResult<Arc<dyn Filesystem>> {
+		// No option is supported
+		parse_options(data, |_, _| Ok(false))?;
+		let io = io.ok_or_else(|| errno!(ENODEV))?;
+		let superblock = Superblock::read(&*io)?;
+		// The filesystem is always read-only
+		let fs = SquashFs::new(superblock, io)?;
+		Ok(Arc::new(fs)? as _)
+	}
+}
@@ -0,0 +1,117 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+//! The `sysfs` is a virtual filesystem which exposes kernel objects and allows to configure them.
+
+mod module;
+
+use super::{kernfs, parse_options, Filesystem, FilesystemType, NodeOps};
+use crate::{
+	device::DeviceIO,
+	file::{
+		fs::{
+			kernfs::{entry_init_default, StaticDir, StaticEntryBuilder},
+			Statfs,
+		},
+		FileType, INode,
+	},
+};
+use module::ModuleDir;
+use utils::{
+	boxed::Box, collections::path::PathBuf, errno, errno::EResult, limits::PAGE_SIZE,
+	ptr::arc::Arc,
+};
+
+/// The magic number of the sysfs.
+const SYSFS_MAGIC: u32 = 0x62656572;
+/// The maximum length of a name in the filesystem.
+const MAX_NAME_LEN: usize = 255;
+
+/// The root directory of the sysfs.
+const ROOT: StaticDir = StaticDir {
+	entries: &[StaticEntryBuilder {
+		name: b"module",
+		entry_type: FileType::Directory,
+		init: entry_init_default::<ModuleDir>,
+	}],
+	data: (),
+};
+
+/// A sysfs.
+#[derive(Debug)]
+pub struct SysFS;
+
+impl Filesystem for SysFS {
+	fn get_name(&self) -> &[u8] {
+		b"sysfs"
+	}
+
+	fn use_cache(&self) -> bool {
+		false
+	}
+
+	fn get_root_inode(&self) -> INode {
+		kernfs::ROOT_INODE
+	}
+
+	fn get_stat(&self) -> EResult<Statfs> {
+		Ok(Statfs {
+			f_type: SYSFS_MAGIC,
+			f_bsize: PAGE_SIZE as _,
+			f_namelen: MAX_NAME_LEN as _,
+			f_frsize: PAGE_SIZE as _,
+			..Default::default()
+		})
+	}
+
+	fn node_from_inode(&self, inode: INode) -> EResult<Box<dyn NodeOps>> {
+		if inode == kernfs::ROOT_INODE {
+			Ok(Box::new(ROOT)? as _)
+		} else {
+			Err(errno!(ENOENT))
+		}
+	}
+}
+
+/// The sysfs filesystem type.
+pub struct SysFsType;
+
+impl FilesystemType for SysFsType {
+	fn get_name(&self) -> &'static [u8] {
+		b"sysfs"
+	}
+
+	fn requires_device(&self) -> bool {
+		false
+	}
+
+	fn detect(&self, _io: &dyn DeviceIO) -> EResult<bool> {
+		Ok(false)
+	}
+
+	fn load_filesystem(
+		&self,
+		_io: Option<Arc<dyn DeviceIO>>,
+		_mountpath: PathBuf,
+		_readonly: bool,
+		data: &[u8],
+	) -> EResult<Arc<dyn Filesystem>> {
+		// No option is supported
+		parse_options(data, |_, _| Ok(false))?;
+		Ok(Arc::new(SysFS)?)
+	}
+}
@@ -0,0 +1,195 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+//! The `module` directory contains a directory for each module with parameters.
+//!
+//! Each module directory contains a `parameters` directory, with a file for each parameter.
+//! Reading a file returns the value of the parameter and writing to it sets the value.
+//!
+//! For more information about parameters, see [`crate::module::param`].
+
+use crate::{
+	file::{
+		fs::{
+			kernfs::{entry_init_from, StaticDir, StaticEntryBuilder},
+			NodeOps,
+		},
+		DirEntry, FileLocation, FileType, Stat,
+	},
+	format_content,
+	module::{param, param::ParamOps},
+};
+use core::{
+	fmt,
+	fmt::{Display, Formatter},
+};
+use utils::{boxed::Box, errno, errno::EResult, ptr::cow::Cow};
+
+/// Builds the directory of the module with the given name.
+fn module_dir(name: &'static str) -> StaticDir<&'static str> {
+	StaticDir {
+		entries: &[StaticEntryBuilder {
+			name: b"parameters",
+			entry_type: FileType::Directory,
+			init: entry_init_from::<ParamsDir, &'static str>,
+		}],
+		data: name,
+	}
+}
+
+/// The directory listing modules with parameters.
+#[derive(Debug, Default)]
+pub struct ModuleDir;
+
+impl NodeOps for ModuleDir {
+	fn get_stat(&self, _loc: &FileLocation) -> EResult<Stat> {
+		Ok(Stat {
+			mode: FileType::Directory.to_mode() | 0o555,
+			..Default::default()
+		})
+	}
+
+	fn entry_by_name<'n>(
+		&self,
+		_loc: &FileLocation,
+		name: &'n [u8],
+	) -> EResult<Option<(DirEntry<'n>, Box<dyn NodeOps>)>> {
+		let Some(param) = param::next_param(name, 0).map(|(p, _)| p) else {
+			return Ok(None);
+		};
+		Ok(Some((
+			DirEntry {
+				inode: 0,
+				entry_type: Some(FileType::Directory),
+				name: Cow::Borrowed(name),
+			},
+			Box::new(module_dir(param.module()))? as _,
+		)))
+	}
+
+	fn next_entry(
+		&self,
+		_loc: &FileLocation,
+		off: u64,
+	) -> EResult<Option<(DirEntry<'static>, u64)>> {
+		let off: usize = off.try_into().map_err(|_| errno!(EINVAL))?;
+		let ent = param::next_module(off).map(|(module, i)| {
+			(
+				DirEntry {
+					inode: 0,
+					entry_type: Some(FileType::Directory),
+					name: Cow::Borrowed(module.as_bytes()),
+				},
+				i as u64 + 1,
+			)
+		});
+		Ok(ent)
+	}
+}
+
+/// The `parameters` directory of a module.
+#[derive(Clone, Debug)]
+pub struct ParamsDir(&'static str);
+
+impl From<&'static str> for ParamsDir {
+	fn from(module: &'static str) -> Self {
+		Self(module)
+	}
+}
+
+impl NodeOps for ParamsDir {
+	fn get_stat(&self, _loc: &FileLocation) -> EResult<Stat> {
+		Ok(Stat {
+			mode: FileType::Directory.to_mode() | 0o555,
+			..Default::default()
+		})
+	}
+
+	fn entry_by_name<'n>(
+		&self,
+		_loc: &FileLocation,
+		name: &'n [u8],
+	) -> EResult<Option<(DirEntry<'n>, Box<dyn NodeOps>)>> {
+		let Some(param) = param::get(self.0.as_bytes(), name) else {
+			return Ok(None);
+		};
+		Ok(Some((
+			DirEntry {
+				inode: 0,
+				entry_type: Some(FileType::Regular),
+				name: Cow::Borrowed(name),
+			},
+			Box::new(ParamNode(param))? as _,
+		)))
+	}
+
+	fn next_entry(
+		&self,
+		_loc: &FileLocation,
+		off: u64,
+	) -> EResult<Option<(DirEntry<'static>, u64)>> {
+		let off: usize = off.try_into().map_err(|_| errno!(EINVAL))?;
+		let ent = param::next_param(self.0.as_bytes(), off).map(|(param, i)| {
+			(
+				DirEntry {
+					inode: 0,
+					entry_type: Some(FileType::Regular),
+					name: Cow::Borrowed(param.name().as_bytes()),
+				},
+				i as u64 + 1,
+			)
+		});
+		Ok(ent)
+	}
+}
+
+/// Displays the value of a parameter.
+struct ParamValueDisp(&'static dyn ParamOps);
+
+impl Display for ParamValueDisp {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		self.0.fmt_value(f)
+	}
+}
+
+/// The file of a parameter.
+#[derive(Debug)]
+pub struct ParamNode(&'static dyn ParamOps);
+
+impl NodeOps for ParamNode {
+	fn get_stat(&self, _loc: &FileLocation) -> EResult<Stat> {
+		let perms = if self.0.is_writable() { 0o644 } else { 0o444 };
+		Ok(Stat {
+			mode: FileType::Regular.to_mode() | perms,
+			..Default::default()
+		})
+	}
+
+	fn read_content(&self, _loc: &FileLocation, off: u64, buf: &mut [u8]) -> EResult<usize> {
+		format_content!(off, buf, "{}\n", ParamValueDisp(self.0))
+	}
+
+	fn write_content(&self, _loc: &FileLocation, _off: u64, buf: &[u8]) -> EResult<usize> {
+		if !self.0.is_writable() {
+			return Err(errno!(EACCES));
+		}
+		// Ignore the trailing newline, which is usually written by shells
+		let val = buf.strip_suffix(b"\n").unwrap_or(buf);
+		self.0.set_from_str(val)?;
+		Ok(buf.len())
+	}
+}
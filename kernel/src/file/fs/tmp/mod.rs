@@ -25,13 +25,18 @@ use crate::{
 	device::DeviceIO,
 	file::{
 		fs::{
-			downcast_fs, kernfs, kernfs::NodeStorage, Filesystem, FilesystemType, NodeOps,
-			StatSet, Statfs,
+			adjusted_nlink, downcast_fs, kernfs, kernfs::NodeStorage, parse_option_num,
+			parse_options, Filesystem, FilesystemType, NodeOps, StatSet, Statfs, RENAME_EXCHANGE,
+			RENAME_NOREPLACE,
 		},
 		perm::{Gid, Uid, ROOT_GID, ROOT_UID},
 		DirEntry, FileLocation, FileType, INode, Mode, Stat,
 	},
-	time::unit::Timestamp,
+	time::{
+		clock,
+		clock::CLOCK_REALTIME,
+		unit::{Timestamp, TimestampScale},
+	},
 };
 use core::{
 	cmp::{max, min},
@@ -40,7 +45,7 @@ use core::{
 };
 use utils::{
 	boxed::Box,
-	collections::{path::PathBuf, vec::Vec},
+	collections::{btreemap::BTreeMap, path::PathBuf, vec::Vec},
 	errno,
 	errno::EResult,
 	limits::PAGE_SIZE,
@@ -55,11 +60,133 @@ use utils::{
 const DEFAULT_MAX_SIZE: usize = 512 * 1024 * 1024;
 /// The maximum length of a name in the filesystem.
 const MAX_NAME_LEN: usize = 255;
+/// The magic number of the tmpfs.
+const TMPFS_MAGIC: u32 = 0x01021994;
+
+/// The content of a regular file.
+///
+/// The content is stored in pages which are allocated only when written, so that holes do not
+/// use memory.
+#[derive(Debug, Default)]
+struct RegularContent {
+	/// The allocated pages. The key is the offset of the page in the file, in pages.
+	pages: BTreeMap<u64, Box<[u8; PAGE_SIZE]>>,
+	/// The size of the file in bytes.
+	size: u64,
+}
+
+impl RegularContent {
+	/// Reads the content at offset `off` into `buf`, returning the number of bytes read.
+	///
+	/// Holes read as zeros.
+	fn read(&self, off: u64, buf: &mut [u8]) -> usize {
+		// Reading at or past the end of the file returns nothing
+		if off >= self.size {
+			return 0;
+		}
+		let len = min(buf.len() as u64, self.size - off) as usize;
+		let mut cur = 0;
+		while cur < len {
+			let pos = off + cur as u64;
+			let inner = (pos % PAGE_SIZE as u64) as usize;
+			let l = min(len - cur, PAGE_SIZE - inner);
+			let dst = &mut buf[cur..(cur + l)];
+			match self.pages.get(&(pos / PAGE_SIZE as u64)) {
+				Some(page) => dst.copy_from_slice(&page[inner..(inner + l)]),
+				None => dst.fill(0),
+			}
+			cur += l;
+		}
+		len
+	}
+
+	/// Writes `buf` at offset `off`, allocating pages as necessary.
+	///
+	/// Writing past the end of the file leaves a hole in the gap.
+	fn write(&mut self, off: u64, buf: &[u8]) -> EResult<()> {
+		let end = off
+			.checked_add(buf.len() as u64)
+			.ok_or_else(|| errno!(EFBIG))?;
+		let mut cur = 0;
+		while cur < buf.len() {
+			let pos = off + cur as u64;
+			let inner = (pos % PAGE_SIZE as u64) as usize;
+			let l = min(buf.len() - cur, PAGE_SIZE - inner);
+			let index = pos / PAGE_SIZE as u64;
+			if !self.pages.contains_key(&index) {
+				let res = Box::new([0; PAGE_SIZE]).and_then(|page| self.pages.insert(index, page));
+				if let Err(e) = res {
+					// Discard pages allocated past the end of the file
+					self.truncate(self.size);
+					return Err(e.into());
+				}
+			}
+			let page = self.pages.get_mut(&index).unwrap();
+			page[inner..(inner + l)].copy_from_slice(&buf[cur..(cur + l)]);
+			cur += l;
+		}
+		self.size = max(self.size, end);
+		Ok(())
+	}
+
+	/// Changes the size of the file to `size`, freeing pages past the end.
+	///
+	/// Extending the file leaves a hole at the end.
+	fn truncate(&mut self, size: u64) {
+		let pages_count = size.div_ceil(PAGE_SIZE as u64);
+		self.pages.retain(|index, _| *index < pages_count);
+		// Zero the end of the last page so that extending the file again does not expose previous
+		// data
+		let inner = (size % PAGE_SIZE as u64) as usize;
+		if let Some(page) = self.pages.get_mut(&(size / PAGE_SIZE as u64)) {
+			page[inner..].fill(0);
+		}
+		self.size = size;
+	}
+
+	/// Fills the content between the offsets `begin` and `end` with zeros.
+	///
+	/// Both offsets must be in the same page.
+	fn zero_range(&mut self, begin: u64, end: u64) {
+		if begin >= end {
+			return;
+		}
+		if let Some(page) = self.pages.get_mut(&(begin / PAGE_SIZE as u64)) {
+			let inner = (begin % PAGE_SIZE as u64) as usize;
+			page[inner..(inner + (end - begin) as usize)].fill(0);
+		}
+	}
+
+	/// Deallocates the content in the range of `len` bytes starting at offset `off`, which then
+	/// reads as zeros. The size of the file does not change.
+	fn punch_hole(&mut self, off: u64, len: u64) {
+		let end = min(off.saturating_add(len), self.size);
+		if off >= end {
+			return;
+		}
+		// Free pages covered entirely. The last page is covered if the range reaches the end of
+		// the file
+		let first = off.div_ceil(PAGE_SIZE as u64);
+		let last = if end == self.size {
+			end.div_ceil(PAGE_SIZE as u64)
+		} else {
+			end / PAGE_SIZE as u64
+		};
+		self.pages
+			.retain(|index, _| *index < first || *index >= last);
+		// Zero the parts of the range in the remaining pages
+		let head_end = min(end, first * PAGE_SIZE as u64);
+		self.zero_range(off, head_end);
+		if head_end < end {
+			self.zero_range(max(head_end, last * PAGE_SIZE as u64), end);
+		}
+	}
+}
 
 /// The content of a [`Node`].
 #[derive(Debug)]
 enum NodeContent {
-	Regular(Vec<u8>),
+	Regular(RegularContent),
 	Directory(Vec<DirEntry<'static>>),
 	Link(Vec<u8>),
 	Fifo,
@@ -108,20 +235,29 @@ impl NodeInner {
 
 	/// Returns the [`Stat`] associated with the content.
 	fn as_stat(&self) -> Stat {
-		let (file_type, size, dev_major, dev_minor) = match &self.content {
-			NodeContent::Regular(content) => (FileType::Regular, content.len() as _, 0, 0),
-			NodeContent::Directory(_) => (FileType::Directory, 0, 0, 0),
-			NodeContent::Link(target) => (FileType::Link, target.len() as _, 0, 0),
-			NodeContent::Fifo => (FileType::Fifo, 0, 0, 0),
-			NodeContent::Socket => (FileType::Socket, 0, 0, 0),
+		let (file_type, size, pages, dev_major, dev_minor) = match &self.content {
+			NodeContent::Regular(content) => (
+				FileType::Regular,
+				content.size,
+				content.pages.len() as u64,
+				0,
+				0,
+			),
+			NodeContent::Directory(_) => (FileType::Directory, 0, 0, 0, 0),
+			NodeContent::Link(target) => {
+				let size = target.len() as u64;
+				(FileType::Link, size, size.div_ceil(PAGE_SIZE as u64), 0, 0)
+			}
+			NodeContent::Fifo => (FileType::Fifo, 0, 0, 0, 0),
+			NodeContent::Socket => (FileType::Socket, 0, 0, 0, 0),
 			NodeContent::BlockDevice {
 				major,
 				minor,
-			} => (FileType::BlockDevice, 0, *major, *minor),
+			} => (FileType::BlockDevice, 0, 0, *major, *minor),
 			NodeContent::CharDevice {
 				major,
 				minor,
-			} => (FileType::CharDevice, 0, *major, *minor),
+			} => (FileType::CharDevice, 0, 0, *major, *minor),
 		};
 		Stat {
 			mode: file_type.to_mode() | self.mode,
@@ -129,7 +265,8 @@ impl NodeInner {
 			uid: self.uid,
 			gid: self.gid,
 			size,
-			blocks: size / PAGE_SIZE as u64,
+			// Counted in units of 512 bytes
+			blocks: pages * (PAGE_SIZE as u64 / 512),
 			dev_major,
 			dev_minor,
 			ctime: self.ctime,
@@ -156,20 +293,20 @@ impl Node {
 	pub fn new(stat: Stat, inode: Option<INode>, parent_inode: Option<INode>) -> EResult<Self> {
 		let file_type = stat.get_type().ok_or_else(|| errno!(EINVAL))?;
 		let content = match file_type {
-			FileType::Regular => NodeContent::Regular(Vec::new()),
+			FileType::Regular => NodeContent::Regular(RegularContent::default()),
 			FileType::Directory => {
 				let mut entries = Vec::new();
 				if let Some(inode) = inode {
 					entries.push(DirEntry {
 						inode,
-						entry_type: FileType::Directory,
+						entry_type: Some(FileType::Directory),
 						name: Cow::Borrowed(b"."),
 					})?;
 				}
 				if let Some(parent_inode) = parent_inode {
 					entries.push(DirEntry {
 						inode: parent_inode,
-						entry_type: FileType::Directory,
+						entry_type: Some(FileType::Directory),
 						name: Cow::Borrowed(b".."),
 					})?;
 				}
@@ -187,14 +324,9 @@ impl Node {
 				minor: stat.dev_minor,
 			},
 		};
-		let mut nlink = 1;
-		if file_type == FileType::Directory {
-			// Count the `.` entry
-			nlink += 1;
-		}
 		Ok(Self(Arc::new(Mutex::new(NodeInner {
 			mode: stat.mode,
-			nlink,
+			nlink: stat.nlink,
 			uid: stat.uid,
 			gid: stat.gid,
 			ctime: stat.ctime,
@@ -240,12 +372,14 @@ impl NodeOps for Node {
 	fn read_content(&self, _loc: &FileLocation, off: u64, buf: &mut [u8]) -> EResult<usize> {
 		let inner = self.0.lock();
 		let content = match &inner.content {
-			NodeContent::Regular(content) | NodeContent::Link(content) => content,
+			NodeContent::Regular(content) => return Ok(content.read(off, buf)),
+			NodeContent::Link(content) => content,
 			NodeContent::Directory(_) => return Err(errno!(EISDIR)),
 			_ => return Err(errno!(EINVAL)),
 		};
-		if off > content.len() as u64 {
-			return Err(errno!(EINVAL));
+		// Reading at or past the end of the file returns nothing
+		if off >= content.len() as u64 {
+			return Ok(0);
 		}
 		let off = off as usize;
 		let len = min(buf.len(), content.len() - off);
@@ -256,18 +390,8 @@ impl NodeOps for Node {
 	fn write_content(&self, _loc: &FileLocation, off: u64, buf: &[u8]) -> EResult<usize> {
 		let mut inner = self.0.lock();
 		match &mut inner.content {
-			NodeContent::Regular(content) => {
-				if off > content.len() as u64 {
-					return Err(errno!(EINVAL));
-				}
-				let off = off as usize;
-				let Some(end) = off.checked_add(buf.len()) else {
-					return Err(errno!(EOVERFLOW));
-				};
-				let new_len = max(content.len(), end);
-				content.resize(new_len, 0)?;
-				content[off..].copy_from_slice(buf);
-			}
+			// Since the node is locked, concurrent readers never see the new size before the data
+			NodeContent::Regular(content) => content.write(off, buf)?,
 			NodeContent::Link(content) => {
 				content.resize(buf.len(), 0)?;
 				content.copy_from_slice(buf);
@@ -279,13 +403,30 @@ impl NodeOps for Node {
 	}
 
 	fn truncate_content(&self, _loc: &FileLocation, size: u64) -> EResult<()> {
+		let ts = clock::current_time(CLOCK_REALTIME, TimestampScale::Second)?;
 		let mut inner = self.0.lock();
 		let content = match &mut inner.content {
 			NodeContent::Regular(content) => content,
 			NodeContent::Directory(_) => return Err(errno!(EISDIR)),
 			_ => return Err(errno!(EINVAL)),
 		};
-		content.truncate(size as _);
+		content.truncate(size);
+		inner.ctime = ts;
+		inner.mtime = ts;
+		Ok(())
+	}
+
+	fn punch_hole(&self, _loc: &FileLocation, off: u64, len: u64) -> EResult<()> {
+		let ts = clock::current_time(CLOCK_REALTIME, TimestampScale::Second)?;
+		let mut inner = self.0.lock();
+		let content = match &mut inner.content {
+			NodeContent::Regular(content) => content,
+			NodeContent::Directory(_) => return Err(errno!(EISDIR)),
+			_ => return Err(errno!(EINVAL)),
+		};
+		content.punch_hole(off, len);
+		inner.ctime = ts;
+		inner.mtime = ts;
 		Ok(())
 	}
 
@@ -358,7 +499,7 @@ impl NodeOps for Node {
 		// Add entry to parent
 		let ent = DirEntry {
 			inode,
-			entry_type,
+			entry_type: Some(entry_type),
 			name: Cow::Owned(name.try_into()?),
 		};
 		let res = parent_entries.binary_search_by(|ent| ent.name.as_ref().cmp(name));
@@ -368,10 +509,6 @@ impl NodeOps for Node {
 		parent_entries.insert(ent_index, ent)?;
 		// Insert node
 		*slot = Some(node.clone());
-		// Update links count
-		if entry_type == FileType::Directory {
-			parent_inner.nlink += 1;
-		}
 		Ok((inode, Box::new(node)?))
 	}
 
@@ -383,7 +520,7 @@ impl NodeOps for Node {
 		}
 		// Get node
 		let node = fs.nodes.lock().get_node(inode)?.clone();
-		let mut inner = node.0.lock();
+		let inner = node.0.lock();
 		let mut parent_inner = self.0.lock();
 		// Get parent entries
 		let NodeContent::Directory(parent_entries) = &mut parent_inner.content else {
@@ -392,7 +529,7 @@ impl NodeOps for Node {
 		// Insert the new entry
 		let ent = DirEntry {
 			inode,
-			entry_type: inner.get_type(),
+			entry_type: Some(inner.get_type()),
 			name: Cow::Owned(name.try_into()?),
 		};
 		let res = parent_entries.binary_search_by(|ent| ent.name.as_ref().cmp(name));
@@ -400,8 +537,6 @@ impl NodeOps for Node {
 			return Err(errno!(EEXIST));
 		};
 		parent_entries.insert(ent_index, ent)?;
-		// Update links count
-		inner.nlink += 1;
 		Ok(())
 	}
 
@@ -411,6 +546,9 @@ impl NodeOps for Node {
 		if unlikely(fs.readonly) {
 			return Err(errno!(EROFS));
 		}
+		if name == b"." || name == b".." {
+			return Err(errno!(EINVAL));
+		}
 		let mut parent_inner = self.0.lock();
 		// Get parent entries
 		let NodeContent::Directory(parent_entries) = &mut parent_inner.content else {
@@ -438,16 +576,132 @@ impl NodeOps for Node {
 		}
 		// Remove entry
 		parent_entries.remove(ent_index);
-		// If the node is a directory, decrement the number of hard links to the parent
-		// (because of the entry `..` in the removed node)
-		let mut inner = node.0.lock();
-		if matches!(inner.content, NodeContent::Directory(_)) {
-			parent_inner.nlink = parent_inner.nlink.saturating_sub(1);
+		Ok(())
+	}
+
+	fn rename(
+		&self,
+		old_parent: &FileLocation,
+		old_name: &[u8],
+		new_parent: &FileLocation,
+		new_name: &[u8],
+		flags: u32,
+	) -> EResult<()> {
+		let fs = old_parent.get_filesystem().unwrap();
+		let fs = downcast_fs::<TmpFS>(&*fs);
+		if unlikely(fs.readonly) {
+			return Err(errno!(EROFS));
+		}
+		if old_name == b"." || old_name == b".." {
+			return Err(errno!(EINVAL));
+		}
+		let name = Cow::Owned(new_name.try_into()?);
+		// If both parents are the same, only the name changes
+		if old_parent.inode == new_parent.inode {
+			let mut inner = self.0.lock();
+			let NodeContent::Directory(entries) = &mut inner.content else {
+				return Err(errno!(ENOTDIR));
+			};
+			let old_index = entries
+				.binary_search_by(|ent| ent.name.as_ref().cmp(old_name))
+				.map_err(|_| errno!(ENOENT))?;
+			match entries.binary_search_by(|ent| ent.name.as_ref().cmp(new_name)) {
+				Ok(_) if flags & RENAME_NOREPLACE != 0 => return Err(errno!(EEXIST)),
+				// Renaming a file to itself does nothing
+				Ok(new_index) if new_index == old_index => {}
+				Ok(new_index) if flags & RENAME_EXCHANGE != 0 => {
+					let (inode, entry_type) =
+						(entries[old_index].inode, entries[old_index].entry_type);
+					entries[old_index].inode = entries[new_index].inode;
+					entries[old_index].entry_type = entries[new_index].entry_type;
+					entries[new_index].inode = inode;
+					entries[new_index].entry_type = entry_type;
+				}
+				Ok(new_index) => {
+					fs.check_replaceable(new_parent.mountpoint_id, entries[new_index].inode)?;
+					entries[new_index].inode = entries[old_index].inode;
+					entries[new_index].entry_type = entries[old_index].entry_type;
+					entries.remove(old_index);
+				}
+				Err(_) if flags & RENAME_EXCHANGE != 0 => return Err(errno!(ENOENT)),
+				Err(mut new_index) => {
+					let mut ent = entries.remove(old_index);
+					ent.name = name;
+					if new_index > old_index {
+						new_index -= 1;
+					}
+					// Cannot fail since the entry has just been removed, leaving enough capacity
+					entries.insert(new_index, ent)?;
+				}
+			}
+			return Ok(());
+		}
+		let new_parent_node = fs.nodes.lock().get_node(new_parent.inode)?.clone();
+		// Lock in order of inodes to avoid deadlocks with a concurrent rename in the opposite
+		// direction
+		let (mut old_inner, mut new_inner) = if old_parent.inode < new_parent.inode {
+			let old_inner = self.0.lock();
+			(old_inner, new_parent_node.0.lock())
+		} else {
+			let new_inner = new_parent_node.0.lock();
+			(self.0.lock(), new_inner)
+		};
+		let NodeContent::Directory(old_entries) = &mut old_inner.content else {
+			return Err(errno!(ENOTDIR));
+		};
+		let NodeContent::Directory(new_entries) = &mut new_inner.content else {
+			return Err(errno!(ENOTDIR));
+		};
+		let old_index = old_entries
+			.binary_search_by(|ent| ent.name.as_ref().cmp(old_name))
+			.map_err(|_| errno!(ENOENT))?;
+		let inode = old_entries[old_index].inode;
+		let entry_type = old_entries[old_index].entry_type;
+		match new_entries.binary_search_by(|ent| ent.name.as_ref().cmp(new_name)) {
+			Ok(_) if flags & RENAME_NOREPLACE != 0 => return Err(errno!(EEXIST)),
+			Ok(new_index) if flags & RENAME_EXCHANGE != 0 => {
+				let new_ent = &mut new_entries[new_index];
+				let (new_inode, new_entry_type) = (new_ent.inode, new_ent.entry_type);
+				new_ent.inode = inode;
+				new_ent.entry_type = entry_type;
+				old_entries[old_index].inode = new_inode;
+				old_entries[old_index].entry_type = new_entry_type;
+				if new_entry_type == Some(FileType::Directory) {
+					fs.set_parent(new_inode, old_parent.inode)?;
+				}
+			}
+			Ok(new_index) => {
+				let new_ent = &mut new_entries[new_index];
+				fs.check_replaceable(new_parent.mountpoint_id, new_ent.inode)?;
+				new_ent.inode = inode;
+				new_ent.entry_type = entry_type;
+				old_entries.remove(old_index);
+			}
+			Err(_) if flags & RENAME_EXCHANGE != 0 => return Err(errno!(ENOENT)),
+			Err(new_index) => {
+				new_entries.insert(
+					new_index,
+					DirEntry {
+						inode,
+						entry_type,
+						name,
+					},
+				)?;
+				old_entries.remove(old_index);
+			}
+		}
+		if entry_type == Some(FileType::Directory) {
+			fs.set_parent(inode, new_parent.inode)?;
 		}
-		inner.nlink = inner.nlink.saturating_sub(1);
 		Ok(())
 	}
 
+	fn adjust_nlink(&self, _loc: &FileLocation, delta: i16) -> EResult<u16> {
+		let mut inner = self.0.lock();
+		inner.nlink = adjusted_nlink(inner.nlink, delta)?;
+		Ok(inner.nlink)
+	}
+
 	fn remove_node(&self, loc: &FileLocation) -> EResult<()> {
 		let fs = loc.get_filesystem().unwrap();
 		let fs = downcast_fs::<TmpFS>(&*fs);
@@ -485,7 +739,7 @@ impl TmpFS {
 		let root = Node::new(
 			Stat {
 				mode: FileType::Directory.to_mode() | 0o1777,
-				nlink: 0,
+				nlink: 2,
 				uid: ROOT_UID,
 				gid: ROOT_GID,
 				size: 0,
@@ -508,6 +762,35 @@ impl TmpFS {
 		};
 		Ok(fs)
 	}
+
+	/// Checks the node with the given `inode` can be replaced by a rename.
+	///
+	/// If the node is a non-empty directory, the function returns [`errno::ENOTEMPTY`].
+	fn check_replaceable(&self, mountpoint_id: u32, inode: INode) -> EResult<()> {
+		let node = self.nodes.lock().get_node(inode)?.clone();
+		let dir = node.0.lock().get_type() == FileType::Directory;
+		if dir
+			&& !node.is_empty_directory(&FileLocation {
+				mountpoint_id,
+				inode,
+			})? {
+			return Err(errno!(ENOTEMPTY));
+		}
+		Ok(())
+	}
+
+	/// Makes the `..` entry of the directory with the given `inode` point to `parent`.
+	fn set_parent(&self, inode: INode, parent: INode) -> EResult<()> {
+		let node = self.nodes.lock().get_node(inode)?.clone();
+		let mut inner = node.0.lock();
+		if let NodeContent::Directory(entries) = &mut inner.content {
+			let res = entries.binary_search_by(|ent| ent.name.as_ref().cmp(b".."));
+			if let Ok(i) = res {
+				entries[i].inode = parent;
+			}
+		}
+		Ok(())
+	}
 }
 
 impl Filesystem for TmpFS {
@@ -523,19 +806,34 @@ impl Filesystem for TmpFS {
 		kernfs::ROOT_INODE
 	}
 
+	fn is_readonly(&self) -> bool {
+		self.readonly
+	}
+
 	fn get_stat(&self) -> EResult<Statfs> {
+		let blocks = (self.max_size / PAGE_SIZE) as i64;
+		// Count the pages used by the content of files
+		let used: u64 = self
+			.nodes
+			.lock()
+			.iter()
+			.map(|node| node.0.lock().as_stat().blocks / (PAGE_SIZE as u64 / 512))
+			.sum();
+		let free = blocks.saturating_sub(used as _).max(0);
 		Ok(Statfs {
-			f_type: 0,
+			f_type: TMPFS_MAGIC,
 			f_bsize: PAGE_SIZE as _,
-			f_blocks: 0,
-			f_bfree: 0,
-			f_bavail: 0,
+			f_blocks: blocks,
+			f_bfree: free,
+			f_bavail: free,
+			// The number of inodes is not limited
 			f_files: 0,
 			f_ffree: 0,
 			f_fsid: Default::default(),
 			f_namelen: MAX_NAME_LEN as _,
-			f_frsize: 0,
+			f_frsize: PAGE_SIZE as _,
 			f_flags: 0,
+			f_spare: [0; 4],
 		})
 	}
 
@@ -544,6 +842,24 @@ impl Filesystem for TmpFS {
 	}
 }
 
+/// Parses the value of the `size` mount option, which is a number of bytes, optionally followed
+/// by a `k`, `m` or `g` suffix.
+///
+/// If the value is invalid, the function returns [`errno::EINVAL`].
+fn parse_size(value: Option<&[u8]>) -> EResult<usize> {
+	let value = value.ok_or_else(|| errno!(EINVAL))?;
+	let (num, unit) = match value.split_last() {
+		Some((b'k' | b'K', num)) => (num, 1 << 10),
+		Some((b'm' | b'M', num)) => (num, 1 << 20),
+		Some((b'g' | b'G', num)) => (num, 1 << 30),
+		_ => (value, 1),
+	};
+	parse_option_num(Some(num), 10)?
+		.checked_mul(unit)
+		.and_then(|size| size.try_into().ok())
+		.ok_or_else(|| errno!(EINVAL))
+}
+
 /// The tmpfs filesystem type.
 pub struct TmpFsType;
 
@@ -552,6 +868,10 @@ impl FilesystemType for TmpFsType {
 		b"tmpfs"
 	}
 
+	fn requires_device(&self) -> bool {
+		false
+	}
+
 	fn detect(&self, _io: &dyn DeviceIO) -> EResult<bool> {
 		Ok(false)
 	}
@@ -561,7 +881,211 @@ impl FilesystemType for TmpFsType {
 		_io: Option<Arc<dyn DeviceIO>>,
 		_mountpath: PathBuf,
 		readonly: bool,
+		data: &[u8],
 	) -> EResult<Arc<dyn Filesystem>> {
-		Ok(Arc::new(TmpFS::new(DEFAULT_MAX_SIZE, readonly)?)?)
+		let mut max_size = DEFAULT_MAX_SIZE;
+		let mut mode = 0o1777;
+		let mut uid = ROOT_UID;
+		let mut gid = ROOT_GID;
+		parse_options(data, |name, value| {
+			match name {
+				b"size" => max_size = parse_size(value)?,
+				b"mode" => mode = (parse_option_num(value, 8)? & 0o7777) as _,
+				b"uid" => {
+					uid = parse_option_num(value, 10)?
+						.try_into()
+						.map_err(|_| errno!(EINVAL))?
+				}
+				b"gid" => {
+					gid = parse_option_num(value, 10)?
+						.try_into()
+						.map_err(|_| errno!(EINVAL))?
+				}
+				_ => return Ok(false),
+			}
+			Ok(true)
+		})?;
+		let fs = TmpFS::new(max_size, readonly)?;
+		// Apply the attributes of the root directory
+		{
+			let root = fs.nodes.lock().get_node(kernfs::ROOT_INODE)?.clone();
+			let mut root = root.0.lock();
+			root.mode = mode;
+			root.uid = uid;
+			root.gid = gid;
+		}
+		Ok(Arc::new(fs)?)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	/// Creates a node with the given type and device number.
+	fn node(file_type: FileType, dev_major: u32, dev_minor: u32) -> Node {
+		Node::new(
+			Stat {
+				mode: file_type.to_mode() | 0o644,
+				nlink: 1,
+				dev_major,
+				dev_minor,
+				..Default::default()
+			},
+			None,
+			None,
+		)
+		.unwrap()
+	}
+
+	#[test_case]
+	fn node_types() {
+		let loc = FileLocation {
+			mountpoint_id: 0,
+			inode: 0,
+		};
+		for file_type in [
+			FileType::Regular,
+			FileType::Directory,
+			FileType::Link,
+			FileType::Fifo,
+			FileType::Socket,
+		] {
+			let stat = node(file_type, 0, 0).get_stat(&loc).unwrap();
+			assert_eq!(stat.get_type(), Some(file_type));
+			assert_eq!(stat.mode & 0o7777, 0o644);
+		}
+		for file_type in [FileType::BlockDevice, FileType::CharDevice] {
+			let node = node(file_type, 8, 1);
+			let stat = node.get_stat(&loc).unwrap();
+			assert_eq!(stat.get_type(), Some(file_type));
+			assert_eq!((stat.dev_major, stat.dev_minor), (8, 1));
+			assert!(node.read_content(&loc, 0, &mut [0; 1]).is_err());
+			assert!(node.write_content(&loc, 0, b"a").is_err());
+		}
+	}
+
+	#[test_case]
+	fn node_content() {
+		let loc = FileLocation {
+			mountpoint_id: 0,
+			inode: 0,
+		};
+		// Overwrite in the middle of a regular file
+		let file = node(FileType::Regular, 0, 0);
+		file.write_content(&loc, 0, b"abcdef").unwrap();
+		file.write_content(&loc, 2, b"XY").unwrap();
+		let mut buf = [0; 8];
+		let len = file.read_content(&loc, 0, &mut buf).unwrap();
+		assert_eq!(&buf[..len], b"abXYef");
+		assert_eq!(file.get_stat(&loc).unwrap().size, 6);
+		// Symbolic link target
+		let link = node(FileType::Link, 0, 0);
+		link.write_content(&loc, 0, b"/some/target").unwrap();
+		let mut buf = [0; 16];
+		let len = link.read_content(&loc, 0, &mut buf).unwrap();
+		assert_eq!(&buf[..len], b"/some/target");
+		assert_eq!(link.get_stat(&loc).unwrap().size, 12);
+	}
+
+	#[test_case]
+	fn node_past_eof() {
+		let loc = FileLocation {
+			mountpoint_id: 0,
+			inode: 0,
+		};
+		let file = node(FileType::Regular, 0, 0);
+		file.write_content(&loc, 0, b"abc").unwrap();
+		// Reading at or past the end of the file returns nothing
+		let mut buf = [0xff; 8];
+		assert_eq!(file.read_content(&loc, 3, &mut buf).unwrap(), 0);
+		assert_eq!(file.read_content(&loc, 100, &mut buf).unwrap(), 0);
+		// Writing past the end of the file fills the gap with zeros
+		file.write_content(&loc, 6, b"de").unwrap();
+		assert_eq!(file.get_stat(&loc).unwrap().size, 8);
+		let len = file.read_content(&loc, 0, &mut buf).unwrap();
+		assert_eq!(&buf[..len], b"abc\0\0\0de");
+		// Extending with a truncation also fills with zeros
+		file.truncate_content(&loc, 2).unwrap();
+		file.truncate_content(&loc, 4).unwrap();
+		let len = file.read_content(&loc, 0, &mut buf).unwrap();
+		assert_eq!(&buf[..len], b"ab\0\0");
+	}
+
+	#[test_case]
+	fn node_holes() {
+		let loc = FileLocation {
+			mountpoint_id: 0,
+			inode: 0,
+		};
+		let file = node(FileType::Regular, 0, 0);
+		// Extending the file does not allocate pages
+		file.truncate_content(&loc, 4 * PAGE_SIZE as u64).unwrap();
+		let stat = file.get_stat(&loc).unwrap();
+		assert_eq!((stat.size, stat.blocks), (4 * PAGE_SIZE as u64, 0));
+		// Writing allocates only the written pages
+		file.write_content(&loc, PAGE_SIZE as u64 - 1, b"ab")
+			.unwrap();
+		assert_eq!(
+			file.get_stat(&loc).unwrap().blocks,
+			2 * (PAGE_SIZE as u64 / 512)
+		);
+		// Punching a hole frees the pages it covers entirely, and zeros the rest
+		file.punch_hole(&loc, 0, PAGE_SIZE as u64 + 1).unwrap();
+		let stat = file.get_stat(&loc).unwrap();
+		assert_eq!(
+			(stat.size, stat.blocks),
+			(4 * PAGE_SIZE as u64, PAGE_SIZE as u64 / 512)
+		);
+		let mut buf = [0xff; 2];
+		file.read_content(&loc, PAGE_SIZE as u64 - 1, &mut buf)
+			.unwrap();
+		assert_eq!(&buf, b"\0\0");
+	}
+
+	#[test_case]
+	fn statfs_usage() {
+		let loc = FileLocation {
+			mountpoint_id: 0,
+			inode: 0,
+		};
+		let fs = TmpFS::new(16 * PAGE_SIZE, false).unwrap();
+		let stat = fs.get_stat().unwrap();
+		assert_eq!(stat.f_type, TMPFS_MAGIC);
+		assert_eq!((stat.f_blocks, stat.f_bfree), (16, 16));
+		// Add a file spanning two pages, the first one being a hole
+		let file = node(FileType::Regular, 0, 0);
+		*fs.nodes.lock().get_free_slot().unwrap().1 = Some(file.clone());
+		file.write_content(&loc, PAGE_SIZE as _, b"a").unwrap();
+		let stat = fs.get_stat().unwrap();
+		assert_eq!((stat.f_bfree, stat.f_bavail), (15, 15));
+		// Fill the hole
+		file.write_content(&loc, 0, b"b").unwrap();
+		let stat = fs.get_stat().unwrap();
+		assert_eq!((stat.f_bfree, stat.f_bavail), (14, 14));
+	}
+
+	#[test_case]
+	fn mount_options() {
+		let loc = FileLocation {
+			mountpoint_id: 0,
+			inode: kernfs::ROOT_INODE,
+		};
+		let load = |data: &[u8]| TmpFsType.load_filesystem(None, PathBuf::root()?, false, data);
+		let fs = load(b"size=64k,mode=755,uid=1000,gid=100").unwrap();
+		assert_eq!(fs.get_stat().unwrap().f_blocks, 16);
+		let stat = fs
+			.node_from_inode(kernfs::ROOT_INODE)
+			.unwrap()
+			.get_stat(&loc)
+			.unwrap();
+		assert_eq!(
+			(stat.mode, stat.uid, stat.gid),
+			(FileType::Directory.to_mode() | 0o755, 1000, 100)
+		);
+		// Invalid options
+		assert_eq!(load(b"size=1x").unwrap_err(), errno!(EINVAL));
+		assert_eq!(load(b"uid=65536").unwrap_err(), errno!(EINVAL));
+		assert_eq!(load(b"foo").unwrap_err(), errno!(EINVAL));
 	}
 }
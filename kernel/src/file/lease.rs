@@ -0,0 +1,332 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! File leases allow a process to be notified when another process accesses a file it has open,
+//! so that it can flush the state it has cached before the access proceeds.
+//!
+//! A lease is held by an open file description. A read lease is broken when the file is opened
+//! for writing or truncated, and a write lease is broken by any open. When a lease is broken, the
+//! process that placed it is sent [`Signal::SIGPOLL`] (also known as `SIGIO`), and the accessing
+//! process waits until the holder releases or downgrades the lease. If the holder does not do so
+//! within [`LEASE_BREAK_TIME`] seconds, the lease is broken forcibly.
+
+use crate::{
+	file::{perm::AccessProfile, wait_queue::WaitQueue, File, FileLocation, FileType},
+	process,
+	process::{pid::Pid, scheduler, signal::Signal, Process},
+	time::{
+		clock,
+		clock::CLOCK_MONOTONIC,
+		unit::{Timestamp, TimestampScale},
+	},
+	workqueue,
+};
+use core::{
+	ptr,
+	sync::atomic::{
+		AtomicBool,
+		Ordering::{Acquire, Relaxed},
+	},
+};
+use utils::{
+	collections::{hashmap::HashMap, vec::Vec},
+	errno,
+	errno::EResult,
+	lock::Mutex,
+	ptr::arc::Arc,
+};
+
+/// The time given to the holder of a lease to release it once it is broken, in seconds.
+const LEASE_BREAK_TIME: Timestamp = 45;
+
+/// The type of a lease.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LeaseType {
+	/// The holder is notified when the file is written.
+	Read,
+	/// The holder is notified when the file is opened.
+	Write,
+}
+
+/// A lease on a file.
+#[derive(Debug)]
+struct Lease {
+	/// The address of the open file description holding the lease, used as an identifier.
+	file: usize,
+	/// The PID of the process to notify when the lease is broken.
+	owner: Pid,
+	/// The type of the lease.
+	lease_type: LeaseType,
+	/// If the lease is being broken, the type it has to be downgraded to (`None` if it has to be
+	/// removed) and the time on [`CLOCK_MONOTONIC`], in nanoseconds, after which it is broken
+	/// forcibly.
+	breaking: Option<(Option<LeaseType>, Timestamp)>,
+}
+
+/// Leases, by location of the file they are placed on.
+static LEASES: Mutex<HashMap<FileLocation, Vec<Lease>>> = Mutex::new(HashMap::new());
+/// Processes waiting for a lease being broken to be released or downgraded.
+static BREAK_QUEUE: WaitQueue = WaitQueue::new();
+
+/// Returns the identifier of the open file description `file` in the leases list.
+fn file_id(file: &File) -> usize {
+	ptr::from_ref(file) as usize
+}
+
+/// Returns the type of the lease held by `file`, if any.
+///
+/// While the lease is being broken, the type it has to be downgraded to is returned.
+pub fn get(file: &File) -> Option<LeaseType> {
+	let ent = file.vfs_entry.as_ref()?;
+	let leases = LEASES.lock();
+	let lease = leases
+		.get(&ent.node().location)?
+		.iter()
+		.find(|l| l.file == file_id(file))?;
+	match lease.breaking {
+		Some((target, _)) => target,
+		None => Some(lease.lease_type),
+	}
+}
+
+/// Places a lease of type `lease_type` on `file`, replacing the one it already holds, if any. If
+/// `lease_type` is `None`, the lease is removed.
+///
+/// Arguments:
+/// - `owner` is the PID of the process to notify when the lease is broken
+/// - `ap` is the access profile of the process placing the lease
+///
+/// The following errors can be returned:
+/// - The file is not a regular file: [`errno::EINVAL`]
+/// - The agent does not own the file and is not privileged: [`errno::EACCES`]
+/// - The file is open in a way that conflicts with the lease, or another lease is being broken:
+///   [`errno::EAGAIN`]
+pub fn set(
+	file: &File,
+	lease_type: Option<LeaseType>,
+	owner: Pid,
+	ap: &AccessProfile,
+) -> EResult<()> {
+	let ent = file.vfs_entry.as_ref().ok_or_else(|| errno!(EINVAL))?;
+	let stat = ent.stat()?;
+	if stat.get_type() != Some(FileType::Regular) {
+		return Err(errno!(EINVAL));
+	}
+	if ap.euid != stat.uid && !ap.is_privileged() {
+		return Err(errno!(EACCES));
+	}
+	let node = ent.node();
+	let id = file_id(file);
+	let mut leases = LEASES.lock();
+	let Some(lease_type) = lease_type else {
+		let broken = remove(&mut leases, &node.location, id);
+		drop(leases);
+		if broken {
+			BREAK_QUEUE.wake_all();
+		}
+		return Ok(());
+	};
+	let conflict = match lease_type {
+		// A read lease can only be placed on a file that is not open for writing, including by
+		// the caller
+		LeaseType::Read => node.write_count.load(Acquire) > 0,
+		// A write lease can only be placed on a file that the caller is the only one to open
+		LeaseType::Write => node.open_count.load(Acquire) > 1,
+	};
+	if conflict {
+		return Err(errno!(EAGAIN));
+	}
+	let node_leases = leases.entry(node.location.clone()).or_insert(Vec::new())?;
+	match node_leases.iter_mut().find(|l| l.file == id) {
+		Some(lease) => {
+			// While the lease is being broken, it can only be downgraded to the requested type
+			if let Some((target, _)) = lease.breaking {
+				if target != Some(LeaseType::Read) || lease_type != LeaseType::Read {
+					return Err(errno!(EAGAIN));
+				}
+				lease.lease_type = lease_type;
+				lease.breaking = None;
+				drop(leases);
+				BREAK_QUEUE.wake_all();
+				return Ok(());
+			}
+			lease.lease_type = lease_type;
+		}
+		None => {
+			if node_leases.iter().any(|l| l.breaking.is_some()) {
+				return Err(errno!(EAGAIN));
+			}
+			node_leases.push(Lease {
+				file: id,
+				owner,
+				lease_type,
+				breaking: None,
+			})?;
+		}
+	}
+	Ok(())
+}
+
+/// Removes the lease held by the open file description with identifier `id` on the node at
+/// `loc`, if any.
+///
+/// The function returns `true` if the removed lease was being broken.
+fn remove(leases: &mut HashMap<FileLocation, Vec<Lease>>, loc: &FileLocation, id: usize) -> bool {
+	let Some(node_leases) = leases.get_mut(loc) else {
+		return false;
+	};
+	let mut broken = false;
+	node_leases.retain(|l| {
+		let keep = l.file != id;
+		broken |= !keep && l.breaking.is_some();
+		keep
+	});
+	if node_leases.is_empty() {
+		leases.remove(loc);
+	}
+	broken
+}
+
+/// Removes the lease held by `file`, if any.
+///
+/// This function is called when the open file description is closed.
+pub fn release(file: &File) {
+	if let Some(ent) = &file.vfs_entry {
+		let broken = remove(&mut LEASES.lock(), &ent.node().location, file_id(file));
+		if broken {
+			BREAK_QUEUE.wake_all();
+		}
+	}
+}
+
+/// Breaks the leases on the node at `loc` that conflict with an access, then waits for them to
+/// be released.
+///
+/// Arguments:
+/// - `write` tells whether the access modifies the file. If not, only write leases are broken
+/// - `except` is the open file description performing the access, if any. Its own lease is not
+///   broken
+/// - `nonblock` tells whether to fail with [`errno::EAGAIN`] instead of waiting if a lease has to
+///   be broken
+///
+/// If waiting is interrupted by a signal, the function returns [`errno::EINTR`].
+pub fn break_leases(
+	loc: &FileLocation,
+	write: bool,
+	except: Option<&File>,
+	nonblock: bool,
+) -> EResult<()> {
+	let except = except.map(file_id);
+	let target = (!write).then_some(LeaseType::Read);
+	// Tells whether a timer waking up the process at the next deadline is pending
+	let armed = Arc::new(AtomicBool::new(false))?;
+	loop {
+		// Register before checking so that a release occurring in between is not missed
+		if !nonblock {
+			BREAK_QUEUE.register()?;
+		}
+		let mut notify = Vec::new();
+		let next_deadline;
+		{
+			let mut leases = LEASES.lock();
+			let Some(node_leases) = leases.get_mut(loc) else {
+				return Ok(());
+			};
+			let now = clock::current_time(CLOCK_MONOTONIC, TimestampScale::Nanosecond)?;
+			let conflicts =
+				|l: &Lease| Some(l.file) != except && (write || l.lease_type == LeaseType::Write);
+			for lease in node_leases.iter_mut().filter(|l| conflicts(l)) {
+				match &mut lease.breaking {
+					Some((lease_target, deadline)) => {
+						// Forcibly downgrade the lease if the holder did not do it in time
+						if now >= *deadline {
+							match lease_target {
+								Some(t) if !write => {
+									lease.lease_type = *t;
+									lease.breaking = None;
+									continue;
+								}
+								// The lease is removed below
+								_ => *lease_target = None,
+							}
+						}
+						// A write access requires a removal, even if a downgrade was requested
+						if write {
+							*lease_target = None;
+						}
+					}
+					None => {
+						let deadline = now + LEASE_BREAK_TIME * 1_000_000_000;
+						lease.breaking = Some((target, deadline));
+						notify.push(lease.owner)?;
+					}
+				}
+			}
+			// Remove leases whose forced removal is due
+			node_leases
+				.retain(|l| !matches!(l.breaking, Some((None, deadline)) if now >= deadline));
+			if node_leases.is_empty() {
+				leases.remove(loc);
+				return Ok(());
+			}
+			if !node_leases.iter().any(conflicts) {
+				return Ok(());
+			}
+			next_deadline = node_leases
+				.iter()
+				.filter(|l| conflicts(l))
+				.filter_map(|l| l.breaking.map(|(_, deadline)| deadline))
+				.min()
+				.map(|deadline| deadline.saturating_sub(now));
+		}
+		// Notify holders without holding the lock, since locking a process while holding it may
+		// deadlock with a process closing a file
+		for pid in notify {
+			if let Some(proc) = Process::get_by_pid(pid) {
+				proc.lock().kill(Signal::SIGPOLL);
+			}
+		}
+		if nonblock {
+			return Err(errno!(EAGAIN));
+		}
+		let proc_mutex = Process::current();
+		let mut proc = proc_mutex.lock();
+		if proc.next_signal(true).is_some() {
+			return Err(errno!(EINTR));
+		}
+		// Make sure the process is woken up when the next lease has to be broken forcibly
+		if let Some(remaining) = next_deadline {
+			if !armed.swap(true, Relaxed) {
+				let armed = armed.clone();
+				let pid = proc.get_pid();
+				workqueue::queue_delayed_work(
+					move || {
+						armed.store(false, Relaxed);
+						if let Some(proc) = Process::get_by_pid(pid) {
+							proc.lock().wake();
+						}
+					},
+					remaining.div_ceil(1_000_000),
+				)?;
+			}
+		}
+		proc.set_state(process::State::Sleeping);
+		drop(proc);
+		scheduler::end_tick();
+	}
+}
@@ -24,11 +24,20 @@
 //! The root filesystem is passed to the kernel as an argument on boot.
 //! Other filesystems are mounted into subdirectories.
 
+pub mod buffer;
+pub mod eventfd;
+pub mod fanotify;
 pub mod fd;
 pub mod fs;
+pub mod lease;
+pub mod notify;
+pub mod page_cache;
 pub mod perm;
+pub mod pidfd;
 pub mod pipe;
+pub mod signalfd;
 pub mod socket;
+pub mod timerfd;
 pub mod util;
 pub mod vfs;
 pub mod wait_queue;
@@ -46,8 +55,12 @@ use crate::{
 		unit::{Timestamp, TimestampScale},
 	},
 };
-use core::{any::Any, ffi::c_void, fmt::Debug, intrinsics::unlikely, ops::Deref};
+use core::{
+	any::Any, ffi::c_void, fmt::Debug, intrinsics::unlikely, ops::Deref,
+	sync::atomic::Ordering::Release,
+};
 use perm::AccessProfile;
+use pipe::PipeBuffer;
 use utils::{
 	boxed::Box,
 	collections::string::String,
@@ -142,6 +155,9 @@ pub const O_NONBLOCK: i32 = 0b00000000000000000000100000000000;
 pub const O_SYNC: i32 = 0b00000000000100000001000000000000;
 /// If the file already exists, truncate it to length zero.
 pub const O_TRUNC: i32 = 0b00000000000000000000001000000000;
+/// Obtains a file descriptor that only indicates a location in the filesystem, without opening
+/// the file for reading or writing.
+pub const O_PATH: i32 = 0b00000000001000000000000000000000;
 
 /// Enumeration representing the different file types.
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
@@ -216,7 +232,7 @@ impl FileType {
 }
 
 /// The location of a file.
-#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub struct FileLocation {
 	/// The ID of the mountpoint of the file.
 	pub mountpoint_id: u32,
@@ -250,7 +266,10 @@ pub struct DirEntry<'name> {
 	/// The entry's inode.
 	pub inode: INode,
 	/// The entry's type.
-	pub entry_type: FileType,
+	///
+	/// If `None`, the type is unknown and has to be retrieved from the file's status. This
+	/// corresponds to [`DT_UNKNOWN`].
+	pub entry_type: Option<FileType>,
 	/// The name of the entry.
 	pub name: Cow<'name, [u8]>,
 }
@@ -362,6 +381,20 @@ pub trait FileOps: Any + Debug {
 	/// On success, the function returns the mask events that occurred.
 	fn poll(&self, file: &File, mask: u32) -> EResult<u32>;
 
+	/// Registers the current process to be woken up when the events returned by [`Self::poll`]
+	/// may have changed, so that it can sleep while waiting for them.
+	///
+	/// `file` is the file to perform the operation onto.
+	///
+	/// If the file cannot wake up processes, the function returns `false`, in which case waiters
+	/// have to keep polling the file.
+	///
+	/// The default implementation returns `false`.
+	fn poll_wait(&self, file: &File) -> EResult<bool> {
+		let _ = file;
+		Ok(false)
+	}
+
 	/// Performs an ioctl operation on the device file.
 	///
 	/// Arguments:
@@ -430,15 +463,39 @@ impl File {
 	/// Arguments:
 	/// - `entry` is the VFS entry of the file.
 	/// - `flags` is the open file description's flags.
+	///
+	/// If the file is a FIFO, the buffer attached to its node is used, or created if none is
+	/// attached. Then, the function waits for the other end of the FIFO to be open (see
+	/// [`PipeBuffer::wait_peer`]).
 	pub fn open_entry(entry: Arc<vfs::Entry>, flags: i32) -> EResult<Arc<Self>> {
+		let ops = match entry.get_type()? {
+			FileType::Fifo if flags & O_PATH == 0 => {
+				let buf = buffer::get_or_init(&entry.node().location, || {
+					Ok(Arc::new(PipeBuffer::new()?)? as _)
+				})?;
+				CounterOption::Some(buf)
+			}
+			_ => CounterOption::None(Box::new(vfs::FileOps)? as _),
+		};
+		if flags & O_PATH == 0 {
+			let node = entry.node();
+			node.open_count.fetch_add(1, Release);
+			if matches!(flags & 0b11, O_WRONLY | O_RDWR) {
+				node.write_count.fetch_add(1, Release);
+			}
+		}
 		let file = Self {
 			vfs_entry: Some(entry),
-			ops: CounterOption::None(Box::new(vfs::FileOps)?),
+			ops,
 			flags: Mutex::new(flags),
 			off: Default::default(),
 		};
 		file.ops.acquire(&file);
-		Ok(Arc::new(file)?)
+		let file = Arc::new(file)?;
+		if let Some(pipe) = file.get_buffer::<PipeBuffer>() {
+			pipe.wait_peer(&file)?;
+		}
+		Ok(file)
 	}
 
 	/// Open a file with no associated VFS entry.
@@ -477,14 +534,24 @@ impl File {
 		}
 	}
 
+	/// Tells whether the file has been opened with [`O_PATH`].
+	///
+	/// Such a file can only be used as a location in the filesystem: its content cannot be
+	/// accessed.
+	pub fn is_path(&self) -> bool {
+		self.get_flags() & O_PATH != 0
+	}
+
 	/// Tells whether the file is open for reading.
 	pub fn can_read(&self) -> bool {
-		matches!(self.get_flags() & 0b11, O_RDONLY | O_RDWR)
+		let flags = self.get_flags();
+		flags & O_PATH == 0 && matches!(flags & 0b11, O_RDONLY | O_RDWR)
 	}
 
 	/// Tells whether the file is open for writing.
 	pub fn can_write(&self) -> bool {
-		matches!(self.get_flags() & 0b11, O_WRONLY | O_RDWR)
+		let flags = self.get_flags();
+		flags & O_PATH == 0 && matches!(flags & 0b11, O_WRONLY | O_RDWR)
 	}
 
 	/// Returns the file's status.
@@ -498,35 +565,103 @@ impl File {
 		FileType::from_mode(stat.mode).ok_or_else(|| errno!(EUCLEAN))
 	}
 
+	/// Checks that the file has a position that can be changed, failing with [`errno::ESPIPE`]
+	/// for pipes and sockets.
+	pub fn check_seekable(&self) -> EResult<()> {
+		match self.get_type()? {
+			FileType::Fifo | FileType::Socket => Err(errno!(ESPIPE)),
+			_ => Ok(()),
+		}
+	}
+
 	/// Truncates the file to the given `size`.
 	///
-	/// If `size` is greater than or equals to the current size of the file, the function does
-	/// nothing.
+	/// If `size` is greater than the current size of the file, the file is extended with zeros.
 	pub fn truncate(&self, size: u64) -> EResult<()> {
 		if unlikely(!self.can_write()) {
-			return Err(errno!(EACCES));
+			return Err(errno!(EBADF));
 		}
 		let node = self
 			.vfs_entry
 			.as_ref()
 			.ok_or_else(|| errno!(EINVAL))?
 			.node();
-		node.ops.truncate_content(&node.location, size)
+		lease::break_leases(&node.location, true, Some(self), false)?;
+		page_cache::truncate(node, size)
+	}
+
+	/// Deallocates the range of `len` bytes starting at offset `off` in the file, which then
+	/// reads as zeros. The size of the file does not change.
+	pub fn punch_hole(&self, off: u64, len: u64) -> EResult<()> {
+		if unlikely(!self.can_write()) {
+			return Err(errno!(EBADF));
+		}
+		let node = self
+			.vfs_entry
+			.as_ref()
+			.ok_or_else(|| errno!(ESPIPE))?
+			.node();
+		lease::break_leases(&node.location, true, Some(self), false)?;
+		page_cache::punch_hole(node, off, len)
 	}
 
 	/// Closes the file, removing it the underlying node if no link remain and this was the last
 	/// use of it.
-	pub fn close(self) -> EResult<()> {
-		self.ops.release(&self);
-		if let Some(ent) = self.vfs_entry {
+	///
+	/// Dropping the file has the same effect, except errors are ignored.
+	pub fn close(mut self) -> EResult<()> {
+		self.release_node();
+		// Release the entry here instead of on drop, to report errors
+		let ent = self.vfs_entry.take();
+		drop(self);
+		if let Some(ent) = ent {
 			vfs::Entry::release(ent)?;
 		}
 		Ok(())
 	}
+
+	/// Releases the resources the file holds on its node: its lease, its use of the node, and
+	/// the buffer attached to the node, if any.
+	fn release_node(&self) {
+		let Some(ent) = &self.vfs_entry else {
+			return;
+		};
+		lease::release(self);
+		if !self.is_path() {
+			let node = ent.node();
+			node.open_count.fetch_sub(1, Release);
+			if self.can_write() {
+				node.write_count.fetch_sub(1, Release);
+			}
+		}
+		if let CounterOption::Some(buf) = &self.ops {
+			buffer::release(&ent.node().location, buf);
+		}
+	}
+}
+
+impl Drop for File {
+	fn drop(&mut self) {
+		self.ops.release(self);
+		self.release_node();
+		// The file may be dropped without being closed, for example when the last reference to
+		// it is held by a memory mapping
+		if let Some(ent) = self.vfs_entry.take() {
+			if let Err(e) = vfs::Entry::release(ent) {
+				crate::log!(Vfs, Error, "could not release file: {e}");
+			}
+		}
+	}
 }
 
 impl AccessProfile {
-	fn check_read_access_impl(uid: Uid, gid: Gid, stat: &Stat) -> bool {
+	/// Tells whether the group `file_gid` of a file matches the agent's group `gid` or one of its
+	/// supplementary groups.
+	fn has_group(&self, gid: Gid, file_gid: Gid) -> bool {
+		gid == file_gid || self.groups().contains(&file_gid)
+	}
+
+	fn check_read_access_impl(&self, uid: Uid, gid: Gid, stat: &Stat) -> bool {
 		// If root, bypass checks
 		if uid == perm::ROOT_UID || gid == perm::ROOT_GID {
 			return true;
@@ -535,7 +670,7 @@ impl AccessProfile {
 		if stat.mode & perm::S_IRUSR != 0 && stat.uid == uid {
 			return true;
 		}
-		if stat.mode & perm::S_IRGRP != 0 && stat.gid == gid {
+		if stat.mode & perm::S_IRGRP != 0 && self.has_group(gid, stat.gid) {
 			return true;
 		}
 		stat.mode & perm::S_IROTH != 0
@@ -550,7 +685,7 @@ impl AccessProfile {
 		} else {
 			(self.uid, self.gid)
 		};
-		Self::check_read_access_impl(uid, gid, stat)
+		self.check_read_access_impl(uid, gid, stat)
 	}
 
 	/// Tells whether the agent can read a file with the given status.
@@ -567,7 +702,7 @@ impl AccessProfile {
 		self.can_read_file(stat)
 	}
 
-	fn check_write_access_impl(uid: Uid, gid: Gid, stat: &Stat) -> bool {
+	fn check_write_access_impl(&self, uid: Uid, gid: Gid, stat: &Stat) -> bool {
 		// If root, bypass checks
 		if uid == perm::ROOT_UID || gid == perm::ROOT_GID {
 			return true;
@@ -576,7 +711,7 @@ impl AccessProfile {
 		if stat.mode & perm::S_IWUSR != 0 && stat.uid == uid {
 			return true;
 		}
-		if stat.mode & perm::S_IWGRP != 0 && stat.gid == gid {
+		if stat.mode & perm::S_IWGRP != 0 && self.has_group(gid, stat.gid) {
 			return true;
 		}
 		stat.mode & perm::S_IWOTH != 0
@@ -591,7 +726,7 @@ impl AccessProfile {
 		} else {
 			(self.uid, self.gid)
 		};
-		Self::check_write_access_impl(uid, gid, stat)
+		self.check_write_access_impl(uid, gid, stat)
 	}
 
 	/// Tells whether the agent can write a file with the given status.
@@ -606,7 +741,7 @@ impl AccessProfile {
 		self.can_write_file(stat) && self.can_execute_file(stat)
 	}
 
-	fn check_execute_access_impl(uid: Uid, gid: Gid, stat: &Stat) -> bool {
+	fn check_execute_access_impl(&self, uid: Uid, gid: Gid, stat: &Stat) -> bool {
 		// If root, bypass checks (unless the file is a regular file)
 		if stat.get_type() != Some(FileType::Regular)
 			&& (uid == perm::ROOT_UID || gid == perm::ROOT_GID)
@@ -617,7 +752,7 @@ impl AccessProfile {
 		if stat.mode & perm::S_IXUSR != 0 && stat.uid == uid {
 			return true;
 		}
-		if stat.mode & perm::S_IXGRP != 0 && stat.gid == gid {
+		if stat.mode & perm::S_IXGRP != 0 && self.has_group(gid, stat.gid) {
 			return true;
 		}
 		stat.mode & perm::S_IXOTH != 0
@@ -632,7 +767,7 @@ impl AccessProfile {
 		} else {
 			(self.uid, self.gid)
 		};
-		Self::check_execute_access_impl(uid, gid, stat)
+		self.check_execute_access_impl(uid, gid, stat)
 	}
 
 	/// Tells whether the agent can execute a file with the given status.
@@ -0,0 +1,469 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! inotify allows a process to be notified of changes to files and directories.
+//!
+//! A process creates an instance with the `inotify_init1` system call, which returns a file
+//! descriptor. Watches are then added to the instance with `inotify_add_watch`, each selecting a
+//! file and a set of events to report.
+//!
+//! Events are generated by the VFS when files are created, removed, moved or modified. An event
+//! on a file is reported both to watches on the file itself and, with the name of the file, to
+//! watches on its parent directory. Reading from the instance's file descriptor returns queued
+//! events.
+
+use crate::{
+	file::{wait_queue::WaitQueue, File, FileLocation, FileOps, FileType, Stat, O_NONBLOCK},
+	process::mem_space::copy::SyscallPtr,
+	syscall::{ioctl, poll::POLLIN, FromSyscallArg},
+};
+use core::{
+	ffi::{c_int, c_void},
+	intrinsics::unlikely,
+	mem::size_of,
+	ptr,
+	sync::atomic::{AtomicU32, Ordering::Relaxed},
+};
+use utils::{
+	bytes::as_bytes,
+	collections::{string::String, vec::Vec},
+	errno,
+	errno::{AllocResult, EResult},
+	lock::Mutex,
+	ptr::arc::Arc,
+};
+
+/// Event: The file has been accessed (read).
+pub const IN_ACCESS: u32 = 0x1;
+/// Event: The file has been modified (write, truncate).
+pub const IN_MODIFY: u32 = 0x2;
+/// Event: The file's metadata have changed (permissions, owner, timestamps, links count).
+pub const IN_ATTRIB: u32 = 0x4;
+/// Event: A file opened for writing has been closed.
+pub const IN_CLOSE_WRITE: u32 = 0x8;
+/// Event: A file not opened for writing has been closed.
+pub const IN_CLOSE_NOWRITE: u32 = 0x10;
+/// Event: The file has been opened.
+pub const IN_OPEN: u32 = 0x20;
+/// Event: A file has been moved out of the watched directory.
+pub const IN_MOVED_FROM: u32 = 0x40;
+/// Event: A file has been moved into the watched directory.
+pub const IN_MOVED_TO: u32 = 0x80;
+/// Event: A file has been created in the watched directory.
+pub const IN_CREATE: u32 = 0x100;
+/// Event: A file has been removed from the watched directory.
+pub const IN_DELETE: u32 = 0x200;
+/// Event: The watched file has been removed.
+pub const IN_DELETE_SELF: u32 = 0x400;
+/// Event: The watched file has been moved.
+pub const IN_MOVE_SELF: u32 = 0x800;
+
+/// The set of all events that can be watched.
+pub const IN_ALL_EVENTS: u32 = 0xfff;
+
+/// Event flag: The filesystem containing the watched file has been unmounted.
+pub const IN_UNMOUNT: u32 = 0x2000;
+/// Event flag: The queue of events overflowed.
+pub const IN_Q_OVERFLOW: u32 = 0x4000;
+/// Event flag: The watch has been removed.
+pub const IN_IGNORED: u32 = 0x8000;
+/// Event flag: The subject of the event is a directory.
+pub const IN_ISDIR: u32 = 0x40000000;
+
+/// Watch flag: If the path is not a directory, fail.
+pub const IN_ONLYDIR: u32 = 0x1000000;
+/// Watch flag: If the path is a symbolic link, watch the link itself.
+pub const IN_DONT_FOLLOW: u32 = 0x2000000;
+/// Watch flag: Do not report events on children after they have been unlinked.
+pub const IN_EXCL_UNLINK: u32 = 0x4000000;
+/// Watch flag: If a watch already exists on the file, fail.
+pub const IN_MASK_CREATE: u32 = 0x10000000;
+/// Watch flag: Add the events to the mask of the existing watch, instead of replacing it.
+pub const IN_MASK_ADD: u32 = 0x20000000;
+/// Watch flag: Remove the watch after the first event.
+pub const IN_ONESHOT: u32 = 0x80000000;
+
+/// Init flag: Set the close-on-exec flag on the instance's file descriptor.
+pub const IN_CLOEXEC: c_int = 0o2000000;
+/// Init flag: Enable non-blocking reads on the instance's file descriptor.
+pub const IN_NONBLOCK: c_int = 0o4000;
+
+/// The maximum number of events in an instance's queue. When reached, further events are
+/// dropped and an [`IN_Q_OVERFLOW`] event is queued.
+const QUEUE_MAX: usize = 16384;
+
+/// Header of an event, as read by userspace. It is followed by the null-padded name of the
+/// file, if any.
+#[repr(C)]
+#[derive(Debug)]
+struct EventHeader {
+	/// The watch descriptor.
+	wd: c_int,
+	/// The mask of events.
+	mask: u32,
+	/// Cookie associating the two events of a move.
+	cookie: u32,
+	/// The length of the name, including padding.
+	len: u32,
+}
+
+/// A watch, selecting events to be reported for a file.
+#[derive(Debug)]
+struct Watch {
+	/// The watch descriptor.
+	wd: c_int,
+	/// The watched file.
+	loc: FileLocation,
+	/// The mask of events to report, along with watch flags.
+	mask: u32,
+}
+
+/// An event waiting to be read.
+#[derive(Debug, PartialEq)]
+struct Event {
+	/// The watch descriptor.
+	wd: c_int,
+	/// The mask of events.
+	mask: u32,
+	/// Cookie associating the two events of a move.
+	cookie: u32,
+	/// The name of the file in the watched directory. Empty if the event concerns the watched
+	/// file itself.
+	name: String,
+}
+
+impl Event {
+	/// Returns the length of the name as written to userspace, including padding.
+	fn name_len(&self) -> usize {
+		if self.name.is_empty() {
+			0
+		} else {
+			// Include the terminating null byte and align on the size of the header
+			(self.name.len() + 1).next_multiple_of(size_of::<EventHeader>())
+		}
+	}
+
+	/// Returns the total length of the event as written to userspace.
+	fn len(&self) -> usize {
+		size_of::<EventHeader>() + self.name_len()
+	}
+}
+
+#[derive(Debug)]
+struct InstanceInner {
+	/// The instance's watches.
+	watches: Vec<Watch>,
+	/// The next watch descriptor to be allocated.
+	next_wd: c_int,
+	/// Events waiting to be read.
+	events: Vec<Event>,
+}
+
+/// An inotify instance.
+#[derive(Debug)]
+pub struct Instance {
+	/// Inner with locking.
+	inner: Mutex<InstanceInner>,
+	/// The queue of processes waiting for events.
+	rd_queue: WaitQueue,
+}
+
+impl Default for Instance {
+	fn default() -> Self {
+		Self {
+			inner: Mutex::new(InstanceInner {
+				watches: Vec::new(),
+				next_wd: 1,
+				events: Vec::new(),
+			}),
+			rd_queue: WaitQueue::new(),
+		}
+	}
+}
+
+impl Instance {
+	/// Adds a watch on the file at `loc`, or modifies the existing one.
+	///
+	/// `mask` is the set of events to report, along with watch flags.
+	///
+	/// On success, the function returns the watch descriptor.
+	///
+	/// If a watch already exists and [`IN_MASK_CREATE`] is set, the function returns
+	/// [`errno::EEXIST`].
+	pub fn add_watch(&self, loc: FileLocation, mask: u32) -> EResult<c_int> {
+		let mut inner = self.inner.lock();
+		if let Some(watch) = inner.watches.iter_mut().find(|w| w.loc == loc) {
+			if mask & IN_MASK_CREATE != 0 {
+				return Err(errno!(EEXIST));
+			}
+			if mask & IN_MASK_ADD != 0 {
+				watch.mask |= mask & !IN_MASK_ADD;
+			} else {
+				watch.mask = mask;
+			}
+			return Ok(watch.wd);
+		}
+		let wd = inner.next_wd;
+		let next_wd = wd.checked_add(1).ok_or_else(|| errno!(ENOSPC))?;
+		inner.watches.push(Watch {
+			wd,
+			loc,
+			mask: mask & !(IN_MASK_ADD | IN_MASK_CREATE),
+		})?;
+		inner.next_wd = next_wd;
+		Ok(wd)
+	}
+
+	/// Removes the watch with the descriptor `wd`, then queues an [`IN_IGNORED`] event for it.
+	///
+	/// If the watch does not exist, the function returns [`errno::EINVAL`].
+	pub fn rm_watch(&self, wd: c_int) -> EResult<()> {
+		{
+			let mut inner = self.inner.lock();
+			let index = inner
+				.watches
+				.iter()
+				.position(|w| w.wd == wd)
+				.ok_or_else(|| errno!(EINVAL))?;
+			inner.watches.remove(index);
+			push_event(&mut inner, wd, IN_IGNORED, 0, b"")?;
+		}
+		self.rd_queue.wake_all();
+		Ok(())
+	}
+
+	/// Reports the event `mask` on the file at `loc` to the matching watches.
+	///
+	/// Arguments:
+	/// - `loc` is the location of the watched file
+	/// - `mask` is the event, along with event flags
+	/// - `cookie` associates the two events of a move, or is zero
+	/// - `name` is the name of the file the event is about in the watched directory, or empty
+	/// - `unlinked` tells whether the file `name` has been unlinked from the watched directory, in
+	///   which case watches with the [`IN_EXCL_UNLINK`] flag ignore the event
+	/// - `remove` tells whether watches on `loc` must be removed after the event
+	///
+	/// Events that cannot be queued due to a lack of memory are lost.
+	fn notify(
+		&self,
+		loc: &FileLocation,
+		mask: u32,
+		cookie: u32,
+		name: &[u8],
+		unlinked: bool,
+		remove: bool,
+	) {
+		let mut queued = false;
+		{
+			let mut inner = self.inner.lock();
+			let inner = &mut *inner;
+			let mut i = 0;
+			while i < inner.watches.len() {
+				let watch = &inner.watches[i];
+				if watch.loc != *loc || (unlinked && watch.mask & IN_EXCL_UNLINK != 0) {
+					i += 1;
+					continue;
+				}
+				let (wd, watch_mask) = (watch.wd, watch.mask);
+				let matching = watch_mask & mask & IN_ALL_EVENTS != 0;
+				if matching {
+					let _ = push_event(inner, wd, mask, cookie, name);
+					queued = true;
+				}
+				if remove || (matching && watch_mask & IN_ONESHOT != 0) {
+					inner.watches.remove(i);
+					let _ = push_event(inner, wd, IN_IGNORED, 0, b"");
+					queued = true;
+				} else {
+					i += 1;
+				}
+			}
+		}
+		if queued {
+			self.rd_queue.wake_all();
+		}
+	}
+}
+
+/// Queues an event on the instance with the given `inner`.
+///
+/// If the last queued event is identical, the new one is merged into it. If the queue is full,
+/// the event is dropped and replaced with an [`IN_Q_OVERFLOW`] event.
+fn push_event(
+	inner: &mut InstanceInner,
+	wd: c_int,
+	mask: u32,
+	cookie: u32,
+	name: &[u8],
+) -> AllocResult<()> {
+	let event = if inner.events.len() < QUEUE_MAX - 1 {
+		Event {
+			wd,
+			mask,
+			cookie,
+			name: String::try_from(name)?,
+		}
+	} else {
+		Event {
+			wd: -1,
+			mask: IN_Q_OVERFLOW,
+			cookie: 0,
+			name: String::new(),
+		}
+	};
+	if inner.events.last() == Some(&event) {
+		return Ok(());
+	}
+	if inner.events.len() >= QUEUE_MAX {
+		return Ok(());
+	}
+	inner.events.push(event)
+}
+
+impl FileOps for Instance {
+	fn get_stat(&self, _file: &File) -> EResult<Stat> {
+		Ok(Stat {
+			mode: FileType::Regular.to_mode() | 0o600,
+			..Default::default()
+		})
+	}
+
+	fn acquire(&self, _file: &File) {}
+
+	fn release(&self, _file: &File) {
+		// Unregister the instance
+		INSTANCES.lock().retain(|i| !ptr::eq(i.as_ptr(), self));
+		let mut inner = self.inner.lock();
+		inner.watches.clear();
+		inner.events.clear();
+	}
+
+	fn poll(&self, _file: &File, mask: u32) -> EResult<u32> {
+		let readable = !self.inner.lock().events.is_empty();
+		Ok(if readable { mask & POLLIN } else { 0 })
+	}
+
+	fn poll_wait(&self, _file: &File) -> EResult<bool> {
+		self.rd_queue.register()?;
+		Ok(true)
+	}
+
+	fn ioctl(&self, _file: &File, request: ioctl::Request, argp: *const c_void) -> EResult<u32> {
+		match request.get_old_format() {
+			ioctl::FIONREAD => {
+				let len: usize = self.inner.lock().events.iter().map(Event::len).sum();
+				let count_ptr = SyscallPtr::<c_int>::from_syscall_arg(argp as usize);
+				count_ptr.copy_to_user(len as _)?;
+			}
+			_ => return Err(errno!(ENOTTY)),
+		}
+		Ok(0)
+	}
+
+	fn read(&self, file: &File, _off: u64, buf: &mut [u8]) -> EResult<usize> {
+		let nonblock = file.get_flags() & O_NONBLOCK != 0;
+		self.rd_queue.wait_until(|| {
+			let mut inner = self.inner.lock();
+			let Some(first) = inner.events.first() else {
+				return nonblock.then_some(Err(errno!(EAGAIN)));
+			};
+			if unlikely(buf.len() < first.len()) {
+				return Some(Err(errno!(EINVAL)));
+			}
+			// Write as many events as fit in the buffer
+			let mut off = 0;
+			let mut count = 0;
+			for event in inner.events.iter() {
+				let len = event.len();
+				if off + len > buf.len() {
+					break;
+				}
+				let name_len = event.name_len();
+				let header = EventHeader {
+					wd: event.wd,
+					mask: event.mask,
+					cookie: event.cookie,
+					len: name_len as _,
+				};
+				let (header_buf, name_buf) =
+					buf[off..(off + len)].split_at_mut(size_of::<EventHeader>());
+				header_buf.copy_from_slice(as_bytes(&header));
+				name_buf[..event.name.len()].copy_from_slice(&event.name);
+				name_buf[event.name.len()..].fill(0);
+				off += len;
+				count += 1;
+			}
+			for _ in 0..count {
+				inner.events.remove(0);
+			}
+			Some(Ok(off))
+		})?
+	}
+
+	fn write(&self, _file: &File, _off: u64, _buf: &[u8]) -> EResult<usize> {
+		Err(errno!(EINVAL))
+	}
+}
+
+/// The list of registered instances.
+static INSTANCES: Mutex<Vec<Arc<Instance>>> = Mutex::new(Vec::new());
+/// The next cookie to be used to associate the two events of a move.
+static NEXT_COOKIE: AtomicU32 = AtomicU32::new(1);
+
+/// Registers the given instance so that it receives events.
+pub fn register(instance: Arc<Instance>) -> AllocResult<()> {
+	INSTANCES.lock().push(instance)
+}
+
+/// Returns a new cookie, to associate the two events of a move.
+pub fn next_cookie() -> u32 {
+	NEXT_COOKIE.fetch_add(1, Relaxed)
+}
+
+/// Notifies instances of the event `mask` on the file at `loc`.
+///
+/// Arguments:
+/// - `loc` is the location of the watched file
+/// - `mask` is the event, along with event flags
+/// - `cookie` associates the two events of a move, or is zero
+/// - `name` is the name of the file the event is about in the watched directory, or empty if the
+///   event concerns the watched file itself
+///
+/// If the event is [`IN_DELETE_SELF`], watches on the file are removed afterward.
+///
+/// The function does nothing if no instance is registered.
+pub fn notify(loc: &FileLocation, mask: u32, cookie: u32, name: &[u8]) {
+	let instances = INSTANCES.lock();
+	let remove = mask & IN_DELETE_SELF != 0;
+	for instance in instances.iter() {
+		instance.notify(loc, mask, cookie, name, false, remove);
+	}
+}
+
+/// Notifies instances of the event `mask` on the file `name`, after it has been unlinked from the
+/// directory at `loc`, for example when the file is still open.
+///
+/// Watches with the [`IN_EXCL_UNLINK`] flag ignore the event.
+///
+/// The function does nothing if no instance is registered.
+pub fn notify_unlinked(loc: &FileLocation, mask: u32, name: &[u8]) {
+	let instances = INSTANCES.lock();
+	for instance in instances.iter() {
+		instance.notify(loc, mask, 0, name, true, false);
+	}
+}
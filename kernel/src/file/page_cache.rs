@@ -0,0 +1,795 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+//! The page cache keeps the content of files in memory, to avoid accessing the underlying storage
+//! on each read or write.
+//!
+//! Pages are identified by the location of the file and their offset in it, in pages. Modified
+//! pages are marked as dirty and written back to the filesystem when synchronizing the file or
+//! its mountpoint, when unmounting, or when the cache is full. The writeback daemon also writes
+//! them back periodically, so that modifications reach the storage even if files are never
+//! synchronized.
+//!
+//! Writes that extend a file are passed directly to the filesystem, so that it keeps track of the
+//! file's size. Thus, a cached page never contains data past the end of its file. Reads past the
+//! end of a file return nothing, and gaps left by writes past the end read as zeros.
+//!
+//! The cache is never locked while accessing the filesystem, since filesystems may access the
+//! cache themselves (for example, a filesystem on a loop device whose backing file is cached).
+//! Instead, each page has its own lock, serializing accesses to its content. Dirty pages are
+//! collected with the cache locked, then written back once it is unlocked. A page is marked clean
+//! right before being written back, so that modifications made in the meantime mark it dirty
+//! again. Pages removed from the cache are marked clean, so that they are not written back.
+//!
+//! Only files on filesystems for which [`super::fs::Filesystem::use_cache`] returns `true` are
+//! cached, unless they are mapped in memory, since mappings always use the cache. For other files,
+//! operations are passed directly to the filesystem.
+
+use crate::{
+	file::{fs::NodeOps, vfs::node::Node, FileLocation},
+	memory::buddy,
+	process::{kthread, mem_space::residence::ResidencePage},
+	time::unit::Timestamp,
+};
+use core::{
+	cmp::min,
+	ops::{Bound, Range, RangeInclusive},
+	ptr, slice,
+	sync::atomic::{AtomicBool, Ordering::Relaxed},
+};
+use utils::{
+	boxed::Box,
+	collections::{btreemap::BTreeMap, vec::Vec},
+	errno,
+	errno::{AllocResult, EResult},
+	limits::PAGE_SIZE,
+	lock::{Mutex, MutexGuard},
+	ptr::arc::Arc,
+};
+
+/// The maximum number of pages in the cache. When reached, unused pages are evicted.
+///
+/// Dirty pages can be evicted only once written back, which happens after the cache is unlocked.
+/// Thus, the cache may temporarily exceed this limit.
+const MAX_PAGES: usize = 4096;
+/// The interval between two rounds of the writeback daemon, in milliseconds.
+const WRITEBACK_INTERVAL: Timestamp = 5000;
+
+/// A page of a file's content, in the cache.
+#[derive(Debug)]
+pub struct CachedPage {
+	/// The physical page.
+	page: Arc<ResidencePage>,
+	/// Tells whether the page has been modified since it was last written back.
+	dirty: AtomicBool,
+	/// The number of bytes at the beginning of the page that are up to date with the file.
+	///
+	/// The rest of the page has been loaded while it was past the end of the file. If the file
+	/// has been extended since, the missing part must be read before accessing it.
+	///
+	/// Locking it serializes accesses to the page's content.
+	loaded: Mutex<usize>,
+}
+
+impl CachedPage {
+	/// Allocates a new zeroed page.
+	fn new() -> AllocResult<Self> {
+		let page = buddy::alloc(0, buddy::FLAG_ZONE_TYPE_KERNEL)?;
+		let page = Self {
+			page: Arc::new(ResidencePage::new(page))?,
+			dirty: AtomicBool::new(false),
+			loaded: Mutex::new(0),
+		};
+		page.content().fill(0);
+		Ok(page)
+	}
+
+	/// Returns the physical page, which may be mapped in memory spaces.
+	pub fn get_page(&self) -> &Arc<ResidencePage> {
+		&self.page
+	}
+
+	/// Returns the page's content.
+	#[allow(clippy::mut_from_ref)]
+	fn content(&self) -> &mut [u8] {
+		let ptr = self.page.get().kernel_to_virtual().unwrap().as_ptr::<u8>();
+		// Safety: the page is allocated in the kernel zone and accesses are serialized by the
+		// lock on `loaded`
+		unsafe { slice::from_raw_parts_mut(ptr, PAGE_SIZE) }
+	}
+
+	/// Tells whether the page is mapped in memory.
+	fn is_mapped(&self) -> bool {
+		Arc::strong_count(&self.page) > 1
+	}
+
+	/// Marks the page as modified, so that it gets written back.
+	pub fn mark_dirty(&self) {
+		self.dirty.store(true, Relaxed);
+	}
+
+	/// Locks the page, then reads the part of it that is inside the file but has not been loaded
+	/// yet.
+	///
+	/// The page's content may be accessed as long as the returned guard is held.
+	///
+	/// Arguments:
+	/// - `loc` and `ops` are the file's location and operations
+	/// - `index` is the offset of the page in the file, in pages
+	/// - `size` is the size of the file, in bytes
+	fn load(
+		&self,
+		loc: &FileLocation,
+		ops: &dyn NodeOps,
+		index: u64,
+		size: u64,
+	) -> EResult<MutexGuard<usize, true>> {
+		let mut loaded = self.loaded.lock();
+		let off = index * PAGE_SIZE as u64;
+		let len = min(size.saturating_sub(off), PAGE_SIZE as u64) as usize;
+		if *loaded < len {
+			let buf = &mut self.content()[*loaded..len];
+			let mut i = 0;
+			while i < buf.len() {
+				let l = ops.read_content(loc, off + (*loaded + i) as u64, &mut buf[i..])?;
+				if l == 0 {
+					break;
+				}
+				i += l;
+			}
+			buf[i..].fill(0);
+			*loaded = len;
+		}
+		Ok(loaded)
+	}
+
+	/// Writes the page back to the filesystem if it is dirty.
+	///
+	/// The cache must not be locked when calling this function.
+	///
+	/// Arguments:
+	/// - `loc` and `ops` are the file's location and operations
+	/// - `index` is the offset of the page in the file, in pages
+	/// - `size` is the size of the file, in bytes
+	fn writeback(
+		&self,
+		loc: &FileLocation,
+		ops: &dyn NodeOps,
+		index: u64,
+		size: u64,
+	) -> EResult<()> {
+		let loaded = self.loaded.lock();
+		// The page may have been written back or removed since it was collected
+		if !self.dirty.swap(false, Relaxed) {
+			return Ok(());
+		}
+		let off = index * PAGE_SIZE as u64;
+		// Do not overwrite the part of the file that has not been loaded
+		let len = min(size.saturating_sub(off), PAGE_SIZE as u64) as usize;
+		let len = min(len, *loaded);
+		let res = write_all(loc, ops, off, &self.content()[..len]);
+		// On failure, keep the page dirty to retry later
+		if res.is_err() {
+			self.mark_dirty();
+		}
+		res
+	}
+}
+
+/// A page of the cache, with its key.
+type CacheEntry = ((FileLocation, u64), Arc<CachedPage>);
+
+/// The page cache, associating a file location and an offset in pages with the cached page.
+static CACHE: Mutex<BTreeMap<(FileLocation, u64), Arc<CachedPage>>> = Mutex::new(BTreeMap::new());
+
+/// Writes the whole `buf` at offset `off` of the file.
+fn write_all(loc: &FileLocation, ops: &dyn NodeOps, off: u64, buf: &[u8]) -> EResult<()> {
+	let mut i = 0;
+	while i < buf.len() {
+		let len = ops.write_content(loc, off + i as u64, &buf[i..])?;
+		if len == 0 {
+			return Err(errno!(EIO));
+		}
+		i += len;
+	}
+	Ok(())
+}
+
+/// Tells whether the file at `loc` goes through the cache.
+fn is_cached(loc: &FileLocation) -> bool {
+	if loc.get_filesystem().is_some_and(|fs| fs.use_cache()) {
+		return true;
+	}
+	// The file is mapped in memory
+	CACHE.lock().range(file_range(loc)).next().is_some()
+}
+
+/// Returns the range of keys covering the pages of the file at `loc`.
+fn file_range(loc: &FileLocation) -> RangeInclusive<(FileLocation, u64)> {
+	(loc.clone(), 0)..=(loc.clone(), u64::MAX)
+}
+
+/// Returns the range of keys covering the pages of the file at `loc` that overlap the range of
+/// bytes from `off` to `end`.
+fn pages_range(loc: &FileLocation, off: u64, end: u64) -> Range<(FileLocation, u64)> {
+	(loc.clone(), off / PAGE_SIZE as u64)..(loc.clone(), end.div_ceil(PAGE_SIZE as u64))
+}
+
+/// Tells whether `page` is in use outside of the cache, either because it is mapped in memory or
+/// because it is being accessed.
+fn is_busy(page: &Arc<CachedPage>) -> bool {
+	Arc::strong_count(page) > 1 || page.is_mapped()
+}
+
+/// Returns the pages among `pages` for which `f` returns `true`, to be accessed once the cache is
+/// unlocked.
+fn collect<'c, I: Iterator<Item = (&'c (FileLocation, u64), &'c Arc<CachedPage>)>>(
+	pages: I,
+	f: impl Fn(&CachedPage) -> bool,
+) -> AllocResult<Vec<CacheEntry>> {
+	let mut res = Vec::new();
+	for (key, page) in pages {
+		if f(page) {
+			res.push((key.clone(), page.clone()))?;
+		}
+	}
+	Ok(res)
+}
+
+/// Writes back the given `pages`, which are sorted by key.
+///
+/// The cache must not be locked when calling this function.
+fn writeback_pages(pages: &[CacheEntry]) -> EResult<()> {
+	// The operations and size of the file currently being written back
+	let mut cur: Option<(&FileLocation, Box<dyn NodeOps>, u64)> = None;
+	for ((loc, index), page) in pages {
+		if !cur.as_ref().is_some_and(|(l, ..)| *l == loc) {
+			let fs = loc.get_filesystem().ok_or_else(|| errno!(ENOENT))?;
+			let ops = fs.node_from_inode(loc.inode)?;
+			let size = ops.get_stat(loc)?.size;
+			cur = Some((loc, ops, size));
+		}
+		let (_, ops, size) = cur.as_ref().unwrap();
+		page.writeback(loc, &**ops, *index, *size)?;
+	}
+	Ok(())
+}
+
+/// Evicts clean pages that are not in use, until the cache has room for a new page.
+fn shrink(cache: &mut BTreeMap<(FileLocation, u64), Arc<CachedPage>>) {
+	let mut excess = (cache.len() + 1).saturating_sub(MAX_PAGES);
+	cache.retain(|_, page| {
+		// Dirty pages have to be written back first
+		if excess == 0 || is_busy(page) || page.dirty.load(Relaxed) {
+			return true;
+		}
+		excess -= 1;
+		false
+	});
+}
+
+/// If the cache is full, writes its dirty pages back so that they can be evicted.
+///
+/// The cache must not be locked when calling this function.
+fn balance() -> EResult<()> {
+	let dirty = {
+		let cache = CACHE.lock();
+		if cache.len() < MAX_PAGES {
+			return Ok(());
+		}
+		collect(cache.iter(), |page| {
+			page.dirty.load(Relaxed) && !page.is_mapped()
+		})?
+	};
+	writeback_pages(&dirty)?;
+	// Collected pages are in use until dropped
+	drop(dirty);
+	shrink(&mut CACHE.lock());
+	Ok(())
+}
+
+/// Returns the cached page at `index` for the file at `loc`, inserting it if not present.
+///
+/// The page may not be loaded yet. If the cache is full, clean pages are evicted. Dirty pages are
+/// written back later by [`balance`].
+fn get_or_insert(loc: &FileLocation, index: u64) -> AllocResult<Arc<CachedPage>> {
+	let mut cache = CACHE.lock();
+	let key = (loc.clone(), index);
+	if let Some(page) = cache.get(&key) {
+		return Ok(page.clone());
+	}
+	if cache.len() >= MAX_PAGES {
+		shrink(&mut cache);
+	}
+	let page = Arc::new(CachedPage::new()?)?;
+	cache.insert(key, page.clone())?;
+	Ok(page)
+}
+
+/// Returns the page at offset `index` (in pages) of the file `node`, loading it into the cache if
+/// necessary.
+///
+/// The returned page may be mapped in memory. If it is modified, [`mark_dirty`] must
+/// be called so that it gets written back.
+pub fn get_page(node: &Node, index: u64) -> EResult<Arc<ResidencePage>> {
+	let size = node.ops.get_stat(&node.location)?.size;
+	let page = get_or_insert(&node.location, index)?;
+	page.load(&node.location, &*node.ops, index, size)?;
+	let page = page.page.clone();
+	balance()?;
+	Ok(page)
+}
+
+/// Tells whether `page` is the cached page at offset `index` (in pages) of the file at `loc`.
+pub fn contains_page(loc: &FileLocation, index: u64, page: &ResidencePage) -> bool {
+	CACHE
+		.lock()
+		.get(&(loc.clone(), index))
+		.is_some_and(|p| p.page.get() == page.get())
+}
+
+/// Evicts the page at offset `index` (in pages) of the file at `loc` from the cache, writing it
+/// back first if necessary.
+///
+/// If the page is mapped in memory, it is not evicted.
+pub fn evict(loc: &FileLocation, index: u64) -> EResult<()> {
+	let key = (loc.clone(), index);
+	let page = {
+		let cache = CACHE.lock();
+		let Some(page) = cache.get(&key) else {
+			return Ok(());
+		};
+		if page.is_mapped() {
+			return Ok(());
+		}
+		page.clone()
+	};
+	let entry = (key, page);
+	writeback_pages(slice::from_ref(&entry))?;
+	let (key, page) = entry;
+	let ptr = page.as_ptr();
+	drop(page);
+	// The page may have been mapped, modified or replaced in the meantime
+	let mut cache = CACHE.lock();
+	let unchanged = cache
+		.get(&key)
+		.is_some_and(|p| ptr::eq(p.as_ptr(), ptr) && !is_busy(p) && !p.dirty.load(Relaxed));
+	if unchanged {
+		cache.remove(&key);
+	}
+	Ok(())
+}
+
+/// Marks the page at offset `index` (in pages) of the file at `loc` as dirty, if present in the
+/// cache.
+pub fn mark_dirty(loc: &FileLocation, index: u64) {
+	if let Some(page) = CACHE.lock().get(&(loc.clone(), index)) {
+		page.mark_dirty();
+	}
+}
+
+/// Reads the content of the file `node` at offset `off` into `buf`, through the cache.
+///
+/// On success, the function returns the number of bytes read.
+pub fn read(node: &Node, off: u64, buf: &mut [u8]) -> EResult<usize> {
+	if !is_cached(&node.location) {
+		return node.ops.read_content(&node.location, off, buf);
+	}
+	let size = node.ops.get_stat(&node.location)?.size;
+	// Reading at or past the end of the file returns nothing
+	if off >= size {
+		return Ok(0);
+	}
+	let len = min(size - off, buf.len() as u64) as usize;
+	let mut i = 0;
+	while i < len {
+		let cur = off + i as u64;
+		let index = cur / PAGE_SIZE as u64;
+		let inner = (cur % PAGE_SIZE as u64) as usize;
+		let page = get_or_insert(&node.location, index)?;
+		let _loaded = page.load(&node.location, &*node.ops, index, size)?;
+		let l = min(len - i, PAGE_SIZE - inner);
+		buf[i..(i + l)].copy_from_slice(&page.content()[inner..(inner + l)]);
+		i += l;
+	}
+	balance()?;
+	Ok(len)
+}
+
+/// Writes `buf` to the file `node` at offset `off`, through the cache.
+///
+/// On success, the function returns the number of bytes written.
+pub fn write(node: &Node, off: u64, buf: &[u8]) -> EResult<usize> {
+	let loc = &node.location;
+	if !is_cached(loc) {
+		return node.ops.write_content(loc, off, buf);
+	}
+	let end = off
+		.checked_add(buf.len() as u64)
+		.ok_or_else(|| errno!(EFBIG))?;
+	let size = node.ops.get_stat(loc)?.size;
+	// The file is extended: update the pages already present in the cache, then write through
+	// so that the filesystem updates the size. Pages loaded before the filesystem updates the size
+	// are completed by the next access, since they are only partially loaded
+	if end > size {
+		let first = min(off, size);
+		let pages = collect(CACHE.lock().range(pages_range(loc, first, end)), |_| true)?;
+		for ((_, index), page) in pages {
+			let mut loaded = page.load(loc, &*node.ops, index, size)?;
+			let page_off = index * PAGE_SIZE as u64;
+			let page_end = page_off + PAGE_SIZE as u64;
+			// Writing past the end of the file leaves a gap reading as zeros. A page mapped past
+			// the end of the file may have been modified, so clear it
+			if off > size {
+				let begin = size.max(page_off);
+				let gap_end = off.min(page_end);
+				if begin < gap_end {
+					let inner = (begin - page_off) as usize;
+					let len = (gap_end - begin) as usize;
+					page.content()[inner..(inner + len)].fill(0);
+				}
+			}
+			if off >= page_end {
+				continue;
+			}
+			let begin = off.max(page_off);
+			let len = (end.min(page_end) - begin) as usize;
+			let inner = (begin - page_off) as usize;
+			let src = (begin - off) as usize;
+			page.content()[inner..(inner + len)].copy_from_slice(&buf[src..(src + len)]);
+			*loaded = (*loaded).max(inner + len);
+			// A writeback running concurrently may overwrite the data written through with the
+			// previous content, so write the page back again later
+			page.mark_dirty();
+		}
+		write_all(loc, &*node.ops, off, buf)?;
+		return Ok(buf.len());
+	}
+	let mut i = 0;
+	while i < buf.len() {
+		let cur = off + i as u64;
+		let index = cur / PAGE_SIZE as u64;
+		let inner = (cur % PAGE_SIZE as u64) as usize;
+		let page = get_or_insert(loc, index)?;
+		let _loaded = page.load(loc, &*node.ops, index, size)?;
+		let l = min(buf.len() - i, PAGE_SIZE - inner);
+		page.content()[inner..(inner + l)].copy_from_slice(&buf[i..(i + l)]);
+		page.mark_dirty();
+		i += l;
+	}
+	balance()?;
+	Ok(buf.len())
+}
+
+/// Truncates the file `node` to `size` bytes, discarding cached pages past the end of the file.
+///
+/// If `size` is greater than the current size of the file, the file is extended with zeros.
+pub fn truncate(node: &Node, size: u64) -> EResult<()> {
+	let loc = &node.location;
+	if !is_cached(loc) {
+		return node.ops.truncate_content(loc, size);
+	}
+	let last = size / PAGE_SIZE as u64;
+	let (removed, last_page) = {
+		let mut cache = CACHE.lock();
+		let removed = collect(
+			cache.range((
+				Bound::Excluded((loc.clone(), last)),
+				Bound::Included((loc.clone(), u64::MAX)),
+			)),
+			|_| true,
+		)?;
+		for (key, _) in &removed {
+			cache.remove(key);
+		}
+		(removed, cache.get(&(loc.clone(), last)).cloned())
+	};
+	// Wait for writebacks in progress, so that they do not extend the file again
+	for (_, page) in &removed {
+		let _loaded = page.loaded.lock();
+		page.dirty.store(false, Relaxed);
+	}
+	node.ops.truncate_content(loc, size)?;
+	// Clear the end of the last page, in case the file is extended again later
+	if let Some(page) = last_page {
+		let mut loaded = page.loaded.lock();
+		let len = (size % PAGE_SIZE as u64) as usize;
+		page.content()[len..].fill(0);
+		*loaded = (*loaded).min(len);
+	}
+	Ok(())
+}
+
+/// Deallocates the range of `len` bytes starting at offset `off` in the file `node`, then clears
+/// the corresponding parts of the cached pages so that they read as zeros.
+///
+/// Cached pages are kept since they may be mapped in memory. Since their content matches the
+/// filesystem's afterwards, they are clean, so that writing them back does not allocate the
+/// deallocated blocks again.
+pub fn punch_hole(node: &Node, off: u64, len: u64) -> EResult<()> {
+	let loc = &node.location;
+	if !is_cached(loc) {
+		return node.ops.punch_hole(loc, off, len);
+	}
+	let size = node.ops.get_stat(loc)?.size;
+	let end = off.saturating_add(len);
+	let pages = collect(CACHE.lock().range(pages_range(loc, off, end)), |_| true)?;
+	// The content of pages entirely inside the hole is discarded. Other pages are written back
+	// first, to keep the modifications made outside the hole
+	for ((_, index), page) in &pages {
+		let page_off = index * PAGE_SIZE as u64;
+		if off <= page_off && page_off + PAGE_SIZE as u64 <= end {
+			let _loaded = page.loaded.lock();
+			page.dirty.store(false, Relaxed);
+		} else {
+			page.writeback(loc, &*node.ops, *index, size)?;
+		}
+	}
+	node.ops.punch_hole(loc, off, len)?;
+	for ((_, index), page) in &pages {
+		let _loaded = page.loaded.lock();
+		let page_off = index * PAGE_SIZE as u64;
+		let begin = (off.max(page_off) - page_off) as usize;
+		let end = (end.min(page_off + PAGE_SIZE as u64) - page_off) as usize;
+		page.content()[begin..end].fill(0);
+	}
+	Ok(())
+}
+
+/// Writes back the dirty pages of the file at `loc`.
+pub fn sync_file(loc: &FileLocation, ops: &dyn NodeOps) -> EResult<()> {
+	let size = ops.get_stat(loc)?.size;
+	let dirty = collect(CACHE.lock().range(file_range(loc)), |page| {
+		page.dirty.load(Relaxed)
+	})?;
+	for ((_, index), page) in &dirty {
+		page.writeback(loc, ops, *index, size)?;
+	}
+	Ok(())
+}
+
+/// Writes back the dirty pages of all the files on the mountpoint with the given ID.
+///
+/// If `None`, the dirty pages of all mountpoints are written back.
+pub fn sync(mountpoint_id: Option<u32>) -> EResult<()> {
+	let dirty = collect(
+		CACHE
+			.lock()
+			.iter()
+			.filter(|((loc, _), _)| !mountpoint_id.is_some_and(|id| id != loc.mountpoint_id)),
+		|page| page.dirty.load(Relaxed),
+	)?;
+	writeback_pages(&dirty)
+}
+
+/// Discards the pages of the file at `loc` without writing them back.
+///
+/// This function is meant to be used when the file is removed.
+pub fn invalidate(loc: &FileLocation) {
+	CACHE.lock().retain(|(l, _), page| {
+		let keep = l != loc;
+		if !keep {
+			page.dirty.store(false, Relaxed);
+		}
+		keep
+	});
+}
+
+/// Discards the pages of all the files on the mountpoint with the given ID, without writing them
+/// back.
+pub fn invalidate_mountpoint(mountpoint_id: u32) {
+	CACHE.lock().retain(|(l, _), page| {
+		let keep = l.mountpoint_id != mountpoint_id;
+		if !keep {
+			page.dirty.store(false, Relaxed);
+		}
+		keep
+	});
+}
+
+/// Writes dirty pages back periodically, until asked to stop. This function is the body of the
+/// writeback daemon.
+fn writeback_loop() {
+	while !kthread::should_stop() {
+		kthread::park();
+		// On failure, the remaining pages stay dirty and are retried on the next round
+		let _ = sync(None);
+		kthread::sleep(WRITEBACK_INTERVAL);
+	}
+}
+
+/// Starts the writeback daemon.
+///
+/// This function must be called only once, after the workqueues have been started.
+pub(crate) fn init() -> EResult<()> {
+	kthread::spawn("writeback", writeback_loop)?;
+	Ok(())
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::file::{FileType, INode, Stat};
+
+	/// A regular file whose content is stored in memory.
+	#[derive(Debug)]
+	struct TestNode(Mutex<Vec<u8>>);
+
+	impl NodeOps for TestNode {
+		fn get_stat(&self, _loc: &FileLocation) -> EResult<Stat> {
+			Ok(Stat {
+				mode: FileType::Regular.to_mode() | 0o644,
+				size: self.0.lock().len() as _,
+				..Default::default()
+			})
+		}
+
+		fn read_content(&self, _loc: &FileLocation, off: u64, buf: &mut [u8]) -> EResult<usize> {
+			let content = self.0.lock();
+			let off = min(off as usize, content.len());
+			let len = min(buf.len(), content.len() - off);
+			buf[..len].copy_from_slice(&content[off..(off + len)]);
+			Ok(len)
+		}
+
+		fn write_content(&self, _loc: &FileLocation, off: u64, buf: &[u8]) -> EResult<usize> {
+			let mut content = self.0.lock();
+			let off = off as usize;
+			let end = off + buf.len();
+			if end > content.len() {
+				content.resize(end, 0)?;
+			}
+			content[off..end].copy_from_slice(buf);
+			Ok(buf.len())
+		}
+
+		fn truncate_content(&self, _loc: &FileLocation, size: u64) -> EResult<()> {
+			self.0.lock().resize(size as _, 0)?;
+			Ok(())
+		}
+	}
+
+	/// A regular file whose content is stored in another file through the cache, like a
+	/// filesystem on a loop device.
+	#[derive(Debug)]
+	struct StackedNode(Arc<Node>);
+
+	impl NodeOps for StackedNode {
+		fn get_stat(&self, _loc: &FileLocation) -> EResult<Stat> {
+			self.0.ops.get_stat(&self.0.location)
+		}
+
+		fn read_content(&self, _loc: &FileLocation, off: u64, buf: &mut [u8]) -> EResult<usize> {
+			read(&self.0, off, buf)
+		}
+
+		fn write_content(&self, _loc: &FileLocation, off: u64, buf: &[u8]) -> EResult<usize> {
+			write(&self.0, off, buf)
+		}
+
+		fn truncate_content(&self, _loc: &FileLocation, size: u64) -> EResult<()> {
+			truncate(&self.0, size)
+		}
+	}
+
+	/// Returns the node of a file of `len` bytes set to `a`. The file is located on no
+	/// mountpoint, so that its pages do not collide with those of other files.
+	fn node(inode: INode, len: usize) -> Node {
+		let mut content = Vec::new();
+		content.resize(len, b'a').unwrap();
+		let loc = FileLocation {
+			mountpoint_id: u32::MAX,
+			inode,
+		};
+		Node::new(loc, Box::new(TestNode(Mutex::new(content))).unwrap())
+	}
+
+	/// Reads `len` bytes at offset `off` of the file as stored by its filesystem, bypassing the
+	/// cache.
+	fn stored(node: &Node, off: u64, len: usize) -> Vec<u8> {
+		let mut buf = Vec::new();
+		buf.resize(len, 0).unwrap();
+		let len = node
+			.ops
+			.read_content(&node.location, off, &mut buf)
+			.unwrap();
+		buf.truncate(len);
+		buf
+	}
+
+	#[test_case]
+	fn page_cache_coherence() {
+		let node = node(1, PAGE_SIZE + 16);
+		let loc = &node.location;
+		// Mapping the first page brings the file into the cache
+		let page = get_page(&node, 0).unwrap();
+		assert!(contains_page(loc, 0, &page));
+		let mut buf = [0u8; 4];
+		// Read after write, the modification is not written back yet
+		assert_eq!(write(&node, 1, b"bb").unwrap(), 2);
+		assert_eq!(read(&node, 0, &mut buf).unwrap(), 4);
+		assert_eq!(&buf, b"abba");
+		assert_eq!(stored(&node, 0, 4).as_slice(), b"aaaa");
+		// Write across the boundary between two pages
+		write(&node, PAGE_SIZE as u64 - 1, b"cc").unwrap();
+		read(&node, PAGE_SIZE as u64 - 2, &mut buf).unwrap();
+		assert_eq!(&buf, b"acca");
+		// Synchronizing writes dirty pages back
+		sync_file(loc, &*node.ops).unwrap();
+		assert_eq!(stored(&node, 0, 4).as_slice(), b"abba");
+		assert_eq!(stored(&node, PAGE_SIZE as u64 - 2, 4).as_slice(), b"acca");
+		// Extending the file writes through, leaving a gap of zeros
+		let size = (PAGE_SIZE + 16) as u64;
+		write(&node, size + 2, b"dd").unwrap();
+		assert_eq!(stored(&node, size, 4).as_slice(), b"\0\0dd");
+		assert_eq!(read(&node, size, &mut buf).unwrap(), 4);
+		assert_eq!(&buf, b"\0\0dd");
+		// Reading past the end returns nothing
+		assert_eq!(read(&node, size + 4, &mut buf).unwrap(), 0);
+		// Truncating then extending again reads as zeros
+		truncate(&node, 2).unwrap();
+		truncate(&node, 4).unwrap();
+		read(&node, 0, &mut buf).unwrap();
+		assert_eq!(&buf, b"ab\0\0");
+		drop(page);
+		invalidate(loc);
+	}
+
+	#[test_case]
+	fn page_cache_invalidate() {
+		let node = node(2, 16);
+		let loc = &node.location;
+		let page = get_page(&node, 0).unwrap();
+		write(&node, 0, b"b").unwrap();
+		// Synchronizing another mountpoint leaves the page dirty
+		sync(Some(u32::MAX - 1)).unwrap();
+		assert_eq!(stored(&node, 0, 1).as_slice(), b"a");
+		// Discarding the pages of the mountpoint loses the modification
+		drop(page);
+		invalidate_mountpoint(loc.mountpoint_id);
+		assert!(CACHE.lock().range(file_range(loc)).next().is_none());
+		let mut buf = [0u8; 1];
+		read(&node, 0, &mut buf).unwrap();
+		assert_eq!(&buf, b"a");
+	}
+
+	#[test_case]
+	fn page_cache_stacked() {
+		let backing = Arc::new(node(3, 16)).unwrap();
+		// Mapping the backing file brings it into the cache
+		let backing_page = get_page(&backing, 0).unwrap();
+		let loc = FileLocation {
+			mountpoint_id: u32::MAX,
+			inode: 4,
+		};
+		let node = Node::new(loc, Box::new(StackedNode(backing.clone())).unwrap());
+		let page = get_page(&node, 0).unwrap();
+		// Loading and writing back the pages of the file accesses the cached backing file
+		let mut buf = [0u8; 2];
+		read(&node, 0, &mut buf).unwrap();
+		assert_eq!(&buf, b"aa");
+		write(&node, 0, b"b").unwrap();
+		sync_file(&node.location, &*node.ops).unwrap();
+		read(&backing, 0, &mut buf).unwrap();
+		assert_eq!(&buf, b"ba");
+		assert_eq!(stored(&backing, 0, 2).as_slice(), b"aa");
+		drop(page);
+		drop(backing_page);
+		invalidate(&node.location);
+		invalidate(&backing.location);
+	}
+}
@@ -33,6 +33,9 @@ pub const ROOT_UID: Uid = 0;
 /// The root group ID.
 pub const ROOT_GID: Gid = 0;
 
+/// The maximum number of supplementary groups of an agent.
+pub const NGROUPS_MAX: usize = 32;
+
 /// User: Read, Write and Execute.
 pub const S_IRWXU: Mode = 0o0700;
 /// User: Read.
@@ -98,6 +101,11 @@ pub struct AccessProfile {
 	pub suid: Uid,
 	/// The saved group ID.
 	pub sgid: Gid,
+
+	/// The supplementary group IDs. Only the first `groups_count` elements are used.
+	groups: [Gid; NGROUPS_MAX],
+	/// The number of supplementary groups.
+	groups_count: u8,
 }
 
 impl AccessProfile {
@@ -111,6 +119,9 @@ impl AccessProfile {
 
 		suid: 0,
 		sgid: 0,
+
+		groups: [0; NGROUPS_MAX],
+		groups_count: 0,
 	};
 
 	/// Creates a profile from the given IDs.
@@ -124,9 +135,23 @@ impl AccessProfile {
 
 			suid: uid,
 			sgid: gid,
+
+			groups: [0; NGROUPS_MAX],
+			groups_count: 0,
 		}
 	}
 
+	/// Returns the supplementary group IDs.
+	pub fn groups(&self) -> &[Gid] {
+		&self.groups[..self.groups_count as usize]
+	}
+
+	/// Tells whether the agent is a member of the group `gid`, either as its effective group or as
+	/// one of its supplementary groups.
+	pub fn is_in_group(&self, gid: Gid) -> bool {
+		self.egid == gid || self.groups().contains(&gid)
+	}
+
 	/// Tells whether the agent is privileged (root).
 	pub fn is_privileged(&self) -> bool {
 		self.euid == ROOT_UID || self.egid == ROOT_GID
@@ -191,4 +216,22 @@ impl AccessProfile {
 			Err(errno!(EPERM))
 		}
 	}
+
+	/// Sets the supplementary group IDs.
+	///
+	/// If the agent is not privileged enough to make the change, the function returns
+	/// [`errno::EPERM`].
+	///
+	/// If there are more than [`NGROUPS_MAX`] groups, the function returns [`errno::EINVAL`].
+	pub fn set_groups(&mut self, groups: &[Gid]) -> EResult<()> {
+		if !self.is_privileged() {
+			return Err(errno!(EPERM));
+		}
+		if groups.len() > NGROUPS_MAX {
+			return Err(errno!(EINVAL));
+		}
+		self.groups[..groups.len()].copy_from_slice(groups);
+		self.groups_count = groups.len() as _;
+		Ok(())
+	}
 }
@@ -0,0 +1,109 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! A pidfd is a file descriptor referring to a process, allowing to act on it with system calls
+//! such as `process_madvise`.
+//!
+//! Unlike a PID, a pidfd cannot refer to another process after the PID has been reused. It
+//! becomes readable for `poll` when the process terminates.
+
+use crate::{
+	file::{wait_queue::WaitQueue, File, FileOps, FileType, Stat, O_NONBLOCK},
+	process::{pid::Pid, Process, State},
+	syscall::{ioctl, poll::POLLIN},
+	time::unit::Timestamp,
+};
+use core::ffi::{c_int, c_void};
+use utils::{errno, errno::EResult, lock::IntMutex, ptr::arc::Arc};
+
+/// `pidfd_open` flag: the file descriptor is non-blocking.
+pub const PIDFD_NONBLOCK: c_int = O_NONBLOCK;
+
+/// Queue of processes polling a pidfd, woken up when any process terminates.
+pub static EXIT_QUEUE: WaitQueue = WaitQueue::new();
+
+/// A file referring to a process.
+#[derive(Debug)]
+pub struct PidFd {
+	/// The PID of the process.
+	pid: Pid,
+	/// The start time of the process, distinguishing it from a later process with the same PID.
+	start_time: Timestamp,
+}
+
+impl PidFd {
+	/// Creates a new instance referring to the process `proc`.
+	pub fn new(proc: &Process) -> Self {
+		Self {
+			pid: proc.get_pid(),
+			start_time: proc.start_time,
+		}
+	}
+
+	/// Returns the PID of the process the file refers to.
+	pub fn get_pid(&self) -> Pid {
+		self.pid
+	}
+
+	/// Returns the process the file refers to.
+	///
+	/// If the process does not exist anymore, the function returns [`errno::ESRCH`].
+	pub fn get_process(&self) -> EResult<Arc<IntMutex<Process>>> {
+		Process::get_by_pid(self.pid)
+			.filter(|proc| proc.lock().start_time == self.start_time)
+			.ok_or_else(|| errno!(ESRCH))
+	}
+}
+
+impl FileOps for PidFd {
+	fn get_stat(&self, _file: &File) -> EResult<Stat> {
+		Ok(Stat {
+			mode: FileType::Regular.to_mode() | 0o600,
+			..Default::default()
+		})
+	}
+
+	fn acquire(&self, _file: &File) {}
+
+	fn release(&self, _file: &File) {}
+
+	fn poll(&self, _file: &File, mask: u32) -> EResult<u32> {
+		let exited = self
+			.get_process()
+			.map_or(true, |proc| proc.lock().get_state() == State::Zombie);
+		let events = if exited { POLLIN } else { 0 };
+		Ok(events & mask)
+	}
+
+	fn poll_wait(&self, _file: &File) -> EResult<bool> {
+		EXIT_QUEUE.register()?;
+		Ok(true)
+	}
+
+	fn ioctl(&self, _file: &File, _request: ioctl::Request, _argp: *const c_void) -> EResult<u32> {
+		Err(errno!(ENOTTY))
+	}
+
+	fn read(&self, _file: &File, _off: u64, _buf: &mut [u8]) -> EResult<usize> {
+		Err(errno!(EINVAL))
+	}
+
+	fn write(&self, _file: &File, _off: u64, _buf: &[u8]) -> EResult<usize> {
+		Err(errno!(EINVAL))
+	}
+}
@@ -20,9 +20,13 @@
 //! and another writing, with a buffer in between.
 
 use crate::{
-	file::{wait_queue::WaitQueue, File, FileOps, FileType, Stat},
+	file::{wait_queue::WaitQueue, File, FileOps, FileType, Stat, O_NONBLOCK},
 	process::{mem_space::copy::SyscallPtr, signal::Signal, Process},
-	syscall::{ioctl, FromSyscallArg},
+	syscall::{
+		ioctl,
+		poll::{POLLERR, POLLHUP, POLLIN, POLLOUT},
+		FromSyscallArg,
+	},
 };
 use core::{
 	ffi::{c_int, c_void},
@@ -37,6 +41,13 @@ use utils::{
 	vec,
 };
 
+/// The capacity of a pipe's buffer, in bytes.
+///
+/// It must be at least [`PIPE_BUF`] so that atomic writes can always complete. The ring buffer
+/// keeps one unused slot, so its allocation is one byte larger, which still fits in the same
+/// block as a `PIPE_BUF`-sized buffer.
+const PIPE_SIZE: usize = PIPE_BUF;
+
 #[derive(Debug)]
 struct PipeInner {
 	/// The pipe's buffer.
@@ -45,6 +56,12 @@ struct PipeInner {
 	readers: usize,
 	/// The number of writers on the pipe.
 	writers: usize,
+	/// The number of times the pipe has been opened for reading.
+	///
+	/// This allows a writer waiting for a reader to notice one that came and left.
+	readers_count: usize,
+	/// The number of times the pipe has been opened for writing.
+	writers_count: usize,
 }
 
 /// Representing a FIFO buffer.
@@ -63,9 +80,12 @@ impl PipeBuffer {
 	pub fn new() -> AllocResult<Self> {
 		Ok(Self {
 			inner: Mutex::new(PipeInner {
-				buffer: RingBuffer::new(vec![0; PIPE_BUF]?),
+				// The ring buffer keeps one byte unused
+				buffer: RingBuffer::new(vec![0; PIPE_SIZE + 1]?),
 				readers: 0,
 				writers: 0,
+				readers_count: 0,
+				writers_count: 0,
 			}),
 			rd_queue: WaitQueue::default(),
 			wr_queue: WaitQueue::default(),
@@ -74,12 +94,51 @@ impl PipeBuffer {
 
 	/// Returns the capacity of the pipe in bytes.
 	pub fn get_capacity(&self) -> usize {
-		self.inner.lock().buffer.get_size()
+		self.inner.lock().buffer.get_size() - 1
+	}
+
+	/// Waits until the other end of the pipe is open, as required when opening the named FIFO
+	/// `file`.
+	///
+	/// A file open for both reading and writing does not wait. With [`O_NONBLOCK`], opening for
+	/// reading does not wait either, and opening for writing fails with [`errno::ENXIO`] if no
+	/// reader is present.
+	///
+	/// If waiting is interrupted by a signal, the function returns [`errno::EINTR`].
+	pub fn wait_peer(&self, file: &File) -> EResult<()> {
+		let nonblock = file.get_flags() & O_NONBLOCK != 0;
+		match (file.can_read(), file.can_write()) {
+			(true, false) if !nonblock => {
+				let count = self.inner.lock().writers_count;
+				self.rd_queue.wait_until(|| {
+					let inner = self.inner.lock();
+					(inner.writers > 0 || inner.writers_count != count).then_some(())
+				})
+			}
+			(false, true) => {
+				let count = {
+					let inner = self.inner.lock();
+					if nonblock && inner.readers == 0 {
+						return Err(errno!(ENXIO));
+					}
+					inner.readers_count
+				};
+				self.wr_queue.wait_until(|| {
+					let inner = self.inner.lock();
+					(inner.readers > 0 || inner.readers_count != count).then_some(())
+				})
+			}
+			_ => Ok(()),
+		}
 	}
 }
 
 impl FileOps for PipeBuffer {
-	fn get_stat(&self, _file: &File) -> EResult<Stat> {
+	fn get_stat(&self, file: &File) -> EResult<Stat> {
+		// Named FIFO
+		if let Some(ent) = &file.vfs_entry {
+			return ent.stat();
+		}
 		Ok(Stat {
 			mode: FileType::Fifo.to_mode() | 0o666,
 			..Default::default()
@@ -88,11 +147,16 @@ impl FileOps for PipeBuffer {
 
 	fn acquire(&self, file: &File) {
 		let mut inner = self.inner.lock();
+		// Wake processes waiting for the other end to be open
 		if file.can_read() {
 			inner.readers += 1;
+			inner.readers_count = inner.readers_count.wrapping_add(1);
+			self.wr_queue.wake_all();
 		}
 		if file.can_write() {
 			inner.writers += 1;
+			inner.writers_count = inner.writers_count.wrapping_add(1);
+			self.rd_queue.wake_all();
 		}
 	}
 
@@ -110,8 +174,38 @@ impl FileOps for PipeBuffer {
 		}
 	}
 
-	fn poll(&self, _file: &File, _mask: u32) -> EResult<u32> {
-		todo!()
+	fn poll(&self, file: &File, mask: u32) -> EResult<u32> {
+		let inner = self.inner.lock();
+		let mut res = 0;
+		if file.can_read() {
+			if !inner.buffer.is_empty() {
+				res |= POLLIN;
+			}
+			// No writer is left: reading returns end-of-file
+			if inner.writers == 0 {
+				res |= POLLHUP;
+			}
+		}
+		if file.can_write() {
+			if !inner.buffer.is_full() {
+				res |= POLLOUT;
+			}
+			// No reader is left: writing fails with `EPIPE`
+			if inner.readers == 0 {
+				res |= POLLERR;
+			}
+		}
+		Ok(res & mask)
+	}
+
+	fn poll_wait(&self, file: &File) -> EResult<bool> {
+		if file.can_read() {
+			self.rd_queue.register()?;
+		}
+		if file.can_write() {
+			self.wr_queue.register()?;
+		}
+		Ok(true)
 	}
 
 	fn ioctl(&self, _file: &File, request: ioctl::Request, argp: *const c_void) -> EResult<u32> {
@@ -126,46 +220,113 @@ impl FileOps for PipeBuffer {
 		Ok(0)
 	}
 
-	fn read(&self, _file: &File, _off: u64, buf: &mut [u8]) -> EResult<usize> {
+	fn read(&self, file: &File, _off: u64, buf: &mut [u8]) -> EResult<usize> {
 		if unlikely(buf.is_empty()) {
 			return Ok(0);
 		}
-		let len = self.rd_queue.wait_until(|| {
+		let nonblock = file.get_flags() & O_NONBLOCK != 0;
+		self.rd_queue.wait_until(|| {
 			let mut inner = self.inner.lock();
 			let len = inner.buffer.read(buf);
 			if len > 0 {
-				self.wr_queue.wake_next();
-				Some(len)
+				// Several writers may be waiting for different amounts of space
+				self.wr_queue.wake_all();
+				Some(Ok(len))
+			} else if inner.writers == 0 {
+				Some(Ok(0))
+			} else if nonblock {
+				Some(Err(errno!(EAGAIN)))
 			} else {
-				if inner.writers == 0 {
-					return Some(0);
-				}
-				// TODO if O_NONBLOCK, return `EAGAIN`
 				None
 			}
-		})?;
-		Ok(len)
+		})?
 	}
 
-	fn write(&self, _file: &File, _off: u64, buf: &[u8]) -> EResult<usize> {
+	fn write(&self, file: &File, _off: u64, buf: &[u8]) -> EResult<usize> {
 		if unlikely(buf.is_empty()) {
 			return Ok(0);
 		}
-		let len = self.wr_queue.wait_until(|| {
+		let nonblock = file.get_flags() & O_NONBLOCK != 0;
+		let mut total = 0;
+		let res = self.wr_queue.wait_until(|| {
 			let mut inner = self.inner.lock();
 			if inner.readers == 0 {
 				Process::current().lock().kill(Signal::SIGPIPE);
-				return Some(Err(errno!(EPIPE)));
+				return Some(if total > 0 {
+					Ok(total)
+				} else {
+					Err(errno!(EPIPE))
+				});
 			}
-			let len = inner.buffer.write(buf);
-			if len > 0 {
-				self.rd_queue.wake_next();
-				Some(Ok(len))
+			// Writes of at most `PIPE_BUF` bytes must not be interleaved with other writes
+			let avail = inner.buffer.get_available_len();
+			let writable = if buf.len() <= PIPE_BUF {
+				avail >= buf.len()
 			} else {
-				// TODO if O_NONBLOCK, return `EAGAIN`
-				None
+				avail > 0
+			};
+			if writable {
+				total += inner.buffer.write(&buf[total..]);
+				self.rd_queue.wake_next();
+				if total == buf.len() {
+					return Some(Ok(total));
+				}
+			}
+			if nonblock {
+				return Some(if total > 0 {
+					Ok(total)
+				} else {
+					Err(errno!(EAGAIN))
+				});
 			}
-		})??;
-		Ok(len)
+			None
+		});
+		match res {
+			// If interrupted after writing some data, return the length written so far
+			Err(e) if e.as_int() == errno::EINTR && total > 0 => Ok(total),
+			res => res?,
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::file::{O_RDONLY, O_RDWR, O_WRONLY};
+	use utils::ptr::arc::Arc;
+
+	#[test_case]
+	fn fifo_open_nonblock() {
+		let pipe = Arc::new(PipeBuffer::new().unwrap()).unwrap();
+		// Opening for writing without a reader fails
+		let writer = File::open_floating(pipe.clone(), O_WRONLY | O_NONBLOCK).unwrap();
+		assert_eq!(pipe.wait_peer(&writer).unwrap_err(), errno!(ENXIO));
+		drop(writer);
+		// Opening for reading or for both does not wait
+		let reader = File::open_floating(pipe.clone(), O_RDONLY | O_NONBLOCK).unwrap();
+		pipe.wait_peer(&reader).unwrap();
+		let rw = File::open_floating(pipe.clone(), O_RDWR).unwrap();
+		pipe.wait_peer(&rw).unwrap();
+		// A reader is present
+		let writer = File::open_floating(pipe.clone(), O_WRONLY | O_NONBLOCK).unwrap();
+		pipe.wait_peer(&writer).unwrap();
+	}
+
+	#[test_case]
+	fn pipe_nonblock() {
+		let pipe = Arc::new(PipeBuffer::new().unwrap()).unwrap();
+		let file = File::open_floating(pipe.clone(), O_RDWR | O_NONBLOCK).unwrap();
+		let mut buf = [0u8; PIPE_BUF];
+		assert_eq!(pipe.read(&file, 0, &mut buf).unwrap_err(), errno!(EAGAIN));
+		// Fill the pipe, leaving less than `PIPE_BUF` bytes
+		let mut data = Vec::new();
+		data.resize(PIPE_SIZE - 1, 0u8).unwrap();
+		assert_eq!(pipe.write(&file, 0, &data).unwrap(), PIPE_SIZE - 1);
+		// Small writes are atomic
+		assert_eq!(pipe.write(&file, 0, &buf).unwrap_err(), errno!(EAGAIN));
+		assert_eq!(pipe.write(&file, 0, b"a").unwrap(), 1);
+		assert_eq!(pipe.write(&file, 0, b"a").unwrap_err(), errno!(EAGAIN));
+		assert_eq!(pipe.read(&file, 0, &mut buf).unwrap(), PIPE_BUF);
+		assert_eq!(pipe.write(&file, 0, &buf).unwrap(), PIPE_BUF);
 	}
 }
@@ -0,0 +1,203 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+//! A signalfd is a file from which pending signals of the reading thread are dequeued, allowing
+//! to wait for signals along with other files.
+//!
+//! Signals to be read are usually blocked, so that they are not delivered to a handler first.
+
+use crate::{
+	file::{File, FileOps, FileType, Stat, O_CLOEXEC, O_NONBLOCK},
+	process,
+	process::{
+		scheduler,
+		signal::{SigSet, Signal, SignalInfo},
+		Process,
+	},
+	syscall::{ioctl, poll::POLLIN},
+};
+use core::{
+	ffi::{c_int, c_void},
+	mem::size_of,
+};
+use utils::{bytes, errno, errno::EResult, lock::Mutex};
+
+/// `signalfd4` flag: the file descriptor is closed on `execve`.
+pub const SFD_CLOEXEC: c_int = O_CLOEXEC;
+/// `signalfd4` flag: the file descriptor is non-blocking.
+pub const SFD_NONBLOCK: c_int = O_NONBLOCK;
+
+/// The structure returned when reading a signal.
+#[repr(C)]
+#[derive(Debug, Default)]
+struct SignalfdSiginfo {
+	/// The signal number.
+	ssi_signo: u32,
+	/// An errno value. Unused.
+	ssi_errno: i32,
+	/// The signal code.
+	ssi_code: i32,
+	/// The PID of the sender.
+	ssi_pid: u32,
+	/// The real user ID of the sender.
+	ssi_uid: u32,
+	/// The file descriptor, for `SIGIO`.
+	ssi_fd: i32,
+	/// The kernel timer ID, for POSIX timers.
+	ssi_tid: u32,
+	/// The band event, for `SIGIO`.
+	ssi_band: u32,
+	/// The number of overruns, for POSIX timers.
+	ssi_overrun: u32,
+	/// The trap number that caused the signal.
+	ssi_trapno: u32,
+	/// The exit status or signal, for `SIGCHLD`.
+	ssi_status: i32,
+	/// The integer sent by `sigqueue`.
+	ssi_int: i32,
+	/// The pointer sent by `sigqueue`.
+	ssi_ptr: u64,
+	/// The user CPU time consumed, for `SIGCHLD`.
+	ssi_utime: u64,
+	/// The system CPU time consumed, for `SIGCHLD`.
+	ssi_stime: u64,
+	/// The address that caused the signal, for hardware-generated signals.
+	ssi_addr: u64,
+	/// The least significant bit of the address, for `SIGBUS`.
+	ssi_addr_lsb: u16,
+	/// Padding.
+	__pad2: u16,
+	/// The system call number, for `SIGSYS`.
+	ssi_syscall: i32,
+	/// The address of the system call instruction, for `SIGSYS`.
+	ssi_call_addr: u64,
+	/// The architecture of the system call, for `SIGSYS`.
+	ssi_arch: u32,
+	/// Padding, for future fields.
+	__pad: [u8; 28],
+}
+
+impl SignalfdSiginfo {
+	/// Creates the structure for the signal `sig` with the given information.
+	fn new(sig: Signal, info: &SignalInfo) -> Self {
+		Self {
+			ssi_signo: sig.get_id() as _,
+			ssi_code: info.code,
+			ssi_pid: info.pid as _,
+			ssi_uid: info.uid as _,
+			ssi_addr: info.addr as _,
+			..Default::default()
+		}
+	}
+}
+
+/// A file reading the pending signals of a set.
+#[derive(Debug)]
+pub struct SignalFd {
+	/// The set of signals to read.
+	mask: Mutex<SigSet>,
+}
+
+impl SignalFd {
+	/// Creates a new instance, reading the signals of `mask`.
+	pub fn new(mask: SigSet) -> Self {
+		let s = Self {
+			mask: Mutex::new(SigSet::default()),
+		};
+		s.set_mask(mask);
+		s
+	}
+
+	/// Sets the set of signals to read.
+	///
+	/// Signals that cannot be caught are ignored.
+	pub fn set_mask(&self, mut mask: SigSet) {
+		mask.clear(Signal::SIGKILL.get_id() as _);
+		mask.clear(Signal::SIGSTOP.get_id() as _);
+		*self.mask.lock() = mask;
+	}
+}
+
+impl FileOps for SignalFd {
+	fn get_stat(&self, _file: &File) -> EResult<Stat> {
+		Ok(Stat {
+			mode: FileType::Regular.to_mode() | 0o600,
+			..Default::default()
+		})
+	}
+
+	fn acquire(&self, _file: &File) {}
+
+	fn release(&self, _file: &File) {}
+
+	fn poll(&self, _file: &File, mask: u32) -> EResult<u32> {
+		// Signals are those of the polling thread
+		let set = *self.mask.lock();
+		let pending = Process::current().lock().get_pending_signals();
+		let events = if pending.0 & set.0 != 0 { POLLIN } else { 0 };
+		Ok(events & mask)
+	}
+
+	fn poll_wait(&self, _file: &File) -> EResult<bool> {
+		// Sending a signal wakes up the process, even if the signal is blocked
+		Ok(true)
+	}
+
+	fn ioctl(&self, _file: &File, _request: ioctl::Request, _argp: *const c_void) -> EResult<u32> {
+		Err(errno!(ENOTTY))
+	}
+
+	fn read(&self, file: &File, _off: u64, buf: &mut [u8]) -> EResult<usize> {
+		const SIZE: usize = size_of::<SignalfdSiginfo>();
+		if buf.len() < SIZE {
+			return Err(errno!(EINVAL));
+		}
+		let nonblock = file.get_flags() & O_NONBLOCK != 0;
+		loop {
+			{
+				let set = *self.mask.lock();
+				let proc_mutex = Process::current();
+				let mut proc = proc_mutex.lock();
+				let mut len = 0;
+				for chunk in buf.chunks_exact_mut(SIZE) {
+					let Some((sig, info)) = proc.dequeue_signal(set) else {
+						break;
+					};
+					let info = SignalfdSiginfo::new(sig, &info);
+					chunk.copy_from_slice(bytes::as_bytes(&info));
+					len += SIZE;
+				}
+				if len > 0 {
+					return Ok(len);
+				}
+				if nonblock {
+					return Err(errno!(EAGAIN));
+				}
+				if proc.next_signal(true).is_some() {
+					return Err(errno!(EINTR));
+				}
+				// The process is woken up when a signal is sent to it, even if blocked
+				proc.set_state(process::State::Sleeping);
+			}
+			scheduler::end_tick();
+		}
+	}
+
+	fn write(&self, _file: &File, _off: u64, _buf: &[u8]) -> EResult<usize> {
+		Err(errno!(EINVAL))
+	}
+}
@@ -19,29 +19,89 @@
 //! This file implements sockets.
 
 use crate::{
-	file::{wait_queue::WaitQueue, File, FileOps, FileType, Stat},
-	net::{osi, SocketDesc},
-	syscall::ioctl::Request,
+	file::{
+		buffer, vfs, vfs::ResolutionSettings, wait_queue::WaitQueue, CounterOption, File,
+		FileLocation, FileOps, FileType, Stat, O_CLOEXEC, O_NONBLOCK,
+	},
+	net::{
+		osi, sockaddr,
+		tcp::{Endpoint, Tcb},
+		unix::{Channel, Received, Ucred},
+		SocketDesc, SocketDomain, SocketType,
+	},
+	process::{mem_space::copy::SyscallPtr, signal::Signal, Process},
+	syscall::{
+		ioctl,
+		ioctl::Request,
+		poll::{POLLHUP, POLLIN, POLLOUT, POLLRDHUP},
+		FromSyscallArg,
+	},
 };
 use core::{
-	ffi::{c_int, c_void},
-	sync::{atomic, atomic::AtomicUsize},
+	any::Any,
+	cmp::min,
+	ffi::{c_int, c_short, c_void},
+	mem::size_of,
+	sync::{
+		atomic,
+		atomic::{AtomicBool, AtomicUsize},
+	},
 };
 use utils::{
-	collections::{ring_buffer::RingBuffer, vec::Vec},
+	bytes,
+	collections::{path::Path, vec::Vec},
 	errno,
 	errno::{AllocResult, EResult},
 	lock::Mutex,
-	vec,
+	ptr::arc::Arc,
 };
 
-/// The maximum size of a socket's buffers.
-const BUFFER_SIZE: usize = 65536;
+/// Socket type flag: set [`O_NONBLOCK`] on the open file description.
+pub const SOCK_NONBLOCK: c_int = O_NONBLOCK;
+/// Socket type flag: set the close-on-exec flag on the file descriptor.
+pub const SOCK_CLOEXEC: c_int = O_CLOEXEC;
 
 /// Socket option level: Socket
-const SOL_SOCKET: c_int = 1;
+pub const SOL_SOCKET: c_int = 1;
 
-/// A UNIX socket.
+/// Socket option: enable the reception of [`SCM_CREDENTIALS`] messages.
+pub const SO_PASSCRED: c_int = 16;
+/// Socket option: the credentials of the peer.
+pub const SO_PEERCRED: c_int = 17;
+
+/// Ancillary data type: passing file descriptors.
+pub const SCM_RIGHTS: c_int = 1;
+/// Ancillary data type: passing the credentials of the sender.
+pub const SCM_CREDENTIALS: c_int = 2;
+
+/// Message flag: peek at incoming data without consuming it.
+pub const MSG_PEEK: c_int = 0x2;
+/// Message flag: control data was discarded due to a lack of space.
+pub const MSG_CTRUNC: c_int = 0x8;
+/// Message flag: the datagram was larger than the buffer.
+pub const MSG_TRUNC: c_int = 0x20;
+/// Message flag: do not block.
+pub const MSG_DONTWAIT: c_int = 0x40;
+/// Message flag: do not send `SIGPIPE` when the connection is closed.
+pub const MSG_NOSIGNAL: c_int = 0x4000;
+/// Message flag: set the close-on-exec flag on file descriptors received with [`SCM_RIGHTS`].
+pub const MSG_CMSG_CLOEXEC: c_int = 0x40000000;
+
+/// The maximum length of the queue of pending connections on a listening socket.
+const SOMAXCONN: usize = 4096;
+
+/// The queue of connections waiting to be accepted on a listening socket.
+#[derive(Debug)]
+struct Backlog {
+	/// The maximum number of pending connections.
+	max: usize,
+	/// The sockets of the pending connections, on the listening side.
+	pending: Vec<Arc<Socket>>,
+	/// The credentials of the process that started listening, given to connecting sockets.
+	cred: Ucred,
+}
+
+/// A socket.
 #[derive(Debug)]
 pub struct Socket {
 	/// The socket's stack descriptor.
@@ -54,36 +114,100 @@ pub struct Socket {
 
 	/// The address the socket is bound to.
 	sockname: Mutex<Vec<u8>>,
+	/// The location of the filesystem node the socket is bound to, if any.
+	node: Mutex<Option<FileLocation>>,
 
-	/// The buffer containing received data. If `None`, reception has been shutdown.
-	rx_buff: Mutex<Option<RingBuffer<u8, Vec<u8>>>>,
-	/// The buffer containing data to be transmitted. If `None`, transmission has been shutdown.
-	tx_buff: Mutex<Option<RingBuffer<u8, Vec<u8>>>>,
+	/// The channel on which the socket receives data.
+	rx: Arc<Channel>,
+	/// The channel on which the socket transmits data, which is the receiving channel of the
+	/// peer. If `None`, the socket is not connected.
+	peer: Mutex<Option<Arc<Channel>>>,
+	/// The credentials of the peer at the time the connection was established.
+	peer_cred: Mutex<Option<Ucred>>,
+	/// Tells whether the credentials of the sender are received along with data.
+	passcred: AtomicBool,
 
-	/// Receive wait queue.
-	rx_queue: WaitQueue,
-	/// Transmit wait queue.
-	tx_queue: WaitQueue,
+	/// If the socket is listening, the queue of pending connections.
+	backlog: Mutex<Option<Backlog>>,
+	/// The queue of processes waiting for a connection to accept.
+	accept_queue: WaitQueue,
+
+	/// For TCP sockets, the control block of the connection.
+	tcp: Option<Arc<Tcb>>,
 }
 
 impl Socket {
 	/// Creates a new instance.
 	pub fn new(desc: SocketDesc) -> AllocResult<Self> {
+		let tcp = if desc.domain == SocketDomain::AfInet && desc.type_ == SocketType::SockStream {
+			Some(Tcb::new()?)
+		} else {
+			None
+		};
+		Self::with_tcp(desc, tcp)
+	}
+
+	/// Creates a new instance with the given TCP control block.
+	fn with_tcp(desc: SocketDesc, tcp: Option<Arc<Tcb>>) -> AllocResult<Self> {
 		Ok(Self {
 			desc,
 			stack: None,
 			open_count: AtomicUsize::new(0),
 
 			sockname: Default::default(),
+			node: Default::default(),
 
-			rx_buff: Mutex::new(Some(RingBuffer::new(vec![0; BUFFER_SIZE]?))),
-			tx_buff: Mutex::new(Some(RingBuffer::new(vec![0; BUFFER_SIZE]?))),
+			rx: Arc::new(Channel::default())?,
+			peer: Default::default(),
+			peer_cred: Default::default(),
+			passcred: AtomicBool::new(false),
 
-			rx_queue: WaitQueue::new(),
-			tx_queue: WaitQueue::new(),
+			backlog: Default::default(),
+			accept_queue: WaitQueue::new(),
+
+			tcp,
 		})
 	}
 
+	/// Creates a pair of connected sockets with the given descriptor.
+	///
+	/// The credentials of the peer of both sockets are the ones of the current process.
+	pub fn new_pair(desc: SocketDesc) -> AllocResult<(Self, Self)> {
+		let cred = Ucred::current();
+		let sock0 = Self::new(desc)?;
+		let sock1 = Self::new(desc)?;
+		*sock0.peer.lock() = Some(sock1.rx.clone());
+		*sock1.peer.lock() = Some(sock0.rx.clone());
+		*sock0.peer_cred.lock() = Some(cred);
+		*sock1.peer_cred.lock() = Some(cred);
+		Ok((sock0, sock1))
+	}
+
+	/// Calls `f` with the Unix socket bound to the filesystem node at `path`.
+	///
+	/// Connecting to the socket requires the permission to write to the node.
+	///
+	/// If no socket is bound to the node, the function returns [`errno::ECONNREFUSED`].
+	pub fn with_bound<T, F: FnOnce(&Socket) -> EResult<T>>(
+		path: &[u8],
+		rs: &ResolutionSettings,
+		f: F,
+	) -> EResult<T> {
+		let ent = vfs::get_file_from_path(Path::new(path)?, rs)?;
+		let stat = ent.stat()?;
+		if stat.get_type() != Some(FileType::Socket) {
+			return Err(errno!(ECONNREFUSED));
+		}
+		if !rs.access_profile.can_write_file(&stat) {
+			return Err(errno!(EACCES));
+		}
+		let buf = buffer::get(&ent.node().location).ok_or_else(|| errno!(ECONNREFUSED))?;
+		let sock = (&*buf as &dyn Any)
+			.downcast_ref::<Socket>()
+			.ok_or_else(|| errno!(ECONNREFUSED))?;
+		f(sock)
+	}
+
 	/// Returns the socket's descriptor.
 	#[inline(always)]
 	pub fn desc(&self) -> &SocketDesc {
@@ -96,14 +220,32 @@ impl Socket {
 		self.stack.as_ref()
 	}
 
+	/// Tells whether the credentials of the sender are received along with data.
+	pub fn is_passcred(&self) -> bool {
+		self.passcred.load(atomic::Ordering::Relaxed)
+	}
+
 	/// Reads the given socket option.
 	///
 	/// Arguments:
 	/// - `level` is the level (protocol) at which the option is located.
 	/// - `optname` is the name of the option.
-	pub fn get_opt(&self, _level: c_int, _optname: c_int) -> EResult<&[u8]> {
-		// TODO
-		todo!()
+	///
+	/// If the option is not supported, the function returns [`errno::ENOPROTOOPT`].
+	pub fn get_opt(&self, level: c_int, optname: c_int) -> EResult<Vec<u8>> {
+		match (level, optname) {
+			(SOL_SOCKET, SO_PASSCRED) => {
+				let val = self.is_passcred() as c_int;
+				Ok(Vec::try_from(&val.to_ne_bytes()[..])?)
+			}
+			(SOL_SOCKET, SO_PEERCRED) => {
+				let cred = self.peer_cred.lock().ok_or_else(|| errno!(ENOTCONN))?;
+				let cred = cred.to_local(&Process::current().lock());
+				Ok(Vec::try_from(bytes::as_bytes(&cred))?)
+			}
+			// TODO support other options
+			_ => Err(errno!(ENOPROTOOPT)),
+		}
 	}
 
 	/// Writes the given socket option.
@@ -114,8 +256,15 @@ impl Socket {
 	/// - `optval` is the value of the option.
 	///
 	/// The function returns a value to be returned by the syscall on success.
-	pub fn set_opt(&self, _level: c_int, _optname: c_int, _optval: &[u8]) -> EResult<c_int> {
-		// TODO
+	pub fn set_opt(&self, level: c_int, optname: c_int, optval: &[u8]) -> EResult<c_int> {
+		// TODO support other options
+		if level == SOL_SOCKET && optname == SO_PASSCRED {
+			let val = optval
+				.get(..size_of::<c_int>())
+				.ok_or_else(|| errno!(EINVAL))?;
+			let val = c_int::from_ne_bytes(val.try_into().unwrap());
+			self.passcred.store(val != 0, atomic::Ordering::Relaxed);
+		}
 		Ok(0)
 	}
 
@@ -124,6 +273,38 @@ impl Socket {
 		&self.sockname
 	}
 
+	/// Tells whether the socket is connected to a peer.
+	pub fn is_connected(&self) -> bool {
+		match &self.tcp {
+			Some(tcb) => tcb.is_connected(),
+			None => self.peer.lock().is_some(),
+		}
+	}
+
+	/// Returns the address of the peer of the socket.
+	///
+	/// Unix sockets connect to unnamed sockets, in which case only the family of the address is
+	/// returned.
+	pub fn get_peername(&self) -> AllocResult<Vec<u8>> {
+		match &self.tcp {
+			Some(tcb) => {
+				let remote = tcb.remote();
+				Vec::try_from(&sockaddr::inet_sockaddr(remote.addr, remote.port)[..])
+			}
+			None => Vec::try_from(&(self.desc.domain.get_id() as c_short).to_ne_bytes()[..]),
+		}
+	}
+
+	/// Sets the name of the socket to the local end of the TCP connection `tcb`, if bound.
+	fn set_inet_name(&self, tcb: &Tcb) -> AllocResult<()> {
+		let local = tcb.local();
+		if local.port != 0 {
+			*self.sockname.lock() =
+				Vec::try_from(&sockaddr::inet_sockaddr(local.addr, local.port)[..])?;
+		}
+		Ok(())
+	}
+
 	/// Binds the socket to the given address.
 	///
 	/// `sockaddr` is the new socket name.
@@ -135,6 +316,15 @@ impl Socket {
 		if !sockname.is_empty() {
 			return Err(errno!(EINVAL));
 		}
+		if let Some(tcb) = &self.tcp {
+			let (addr, port) = sockaddr::inet_addr(sockaddr)?;
+			let local = tcb.bind(Endpoint {
+				addr,
+				port,
+			})?;
+			*sockname = Vec::try_from(&sockaddr::inet_sockaddr(local.addr, local.port)[..])?;
+			return Ok(());
+		}
 		// TODO check if address is already in used (EADDRINUSE)
 		// TODO check the requested network interface exists (EADDRNOTAVAIL)
 		// TODO check address against stack's domain
@@ -143,14 +333,329 @@ impl Socket {
 		Ok(())
 	}
 
+	/// Binds the socket to a filesystem node, as Unix sockets do.
+	///
+	/// Arguments:
+	/// - `file` is the open file description of the socket.
+	/// - `sockaddr` is the new socket name.
+	/// - `create` creates the node and returns its location.
+	///
+	/// The socket is attached to the node, so that connecting to the node reaches the socket,
+	/// until the socket is closed or the node removed.
+	///
+	/// If the socket is already bound, the function returns [`errno::EINVAL`].
+	pub fn bind_node<F: FnOnce() -> EResult<FileLocation>>(
+		&self,
+		file: &File,
+		sockaddr: &[u8],
+		create: F,
+	) -> EResult<()> {
+		let CounterOption::Some(ops) = &file.ops else {
+			return Err(errno!(ENOTSOCK));
+		};
+		let mut sockname = self.sockname.lock();
+		if !sockname.is_empty() {
+			return Err(errno!(EINVAL));
+		}
+		let name = Vec::try_from(sockaddr)?;
+		let loc = create()?;
+		buffer::attach(loc.clone(), ops.clone())?;
+		*self.node.lock() = Some(loc);
+		*sockname = name;
+		Ok(())
+	}
+
+	/// Starts listening for connections, with a queue of at most `backlog` pending connections.
+	///
+	/// The credentials of the current process are given to the sockets that connect to it.
+	///
+	/// If the socket is not connection-oriented, the function returns [`errno::EOPNOTSUPP`]. If
+	/// the socket is connected, or is a Unix socket that is not bound, the function returns
+	/// [`errno::EINVAL`].
+	pub fn listen(&self, backlog: c_int) -> EResult<()> {
+		if !self.desc.type_.is_stream() {
+			return Err(errno!(EOPNOTSUPP));
+		}
+		let max = (backlog.max(1) as usize).min(SOMAXCONN);
+		if let Some(tcb) = &self.tcp {
+			Tcb::listen(tcb, max)?;
+			// The socket is bound by listening
+			self.set_inet_name(tcb)?;
+			return Ok(());
+		}
+		let unbound = self.desc.domain == SocketDomain::AfUnix && self.sockname.lock().is_empty();
+		if self.peer.lock().is_some() || unbound {
+			return Err(errno!(EINVAL));
+		}
+		let cred = Ucred::current();
+		let mut guard = self.backlog.lock();
+		match &mut *guard {
+			// Already listening: only update the length of the queue and the credentials
+			Some(backlog) => {
+				backlog.max = max;
+				backlog.cred = cred;
+			}
+			None => {
+				*guard = Some(Backlog {
+					max,
+					pending: Vec::new(),
+					cred,
+				})
+			}
+		}
+		Ok(())
+	}
+
+	/// Connects the socket to the bound socket `target`.
+	///
+	/// A connection-oriented socket is queued on `target`, which must be listening, until it is
+	/// accepted. Other sockets only record `target` as their default destination.
+	///
+	/// On connection, each side records the credentials of the other: the ones of the current
+	/// process for the listening side, and the ones given when `target` started listening for the
+	/// connecting side.
+	///
+	/// Errors:
+	/// - [`errno::EPROTOTYPE`]: the sockets are of different types
+	/// - [`errno::EISCONN`]: the socket is already connected
+	/// - [`errno::ECONNREFUSED`]: `target` is not listening
+	/// - [`errno::EAGAIN`]: the queue of pending connections of `target` is full
+	pub fn connect(&self, target: &Socket) -> EResult<()> {
+		if self.desc.type_ != target.desc.type_ {
+			return Err(errno!(EPROTOTYPE));
+		}
+		let mut peer = self.peer.lock();
+		if !self.desc.type_.is_stream() {
+			*peer = Some(target.rx.clone());
+			return Ok(());
+		}
+		if peer.is_some() {
+			return Err(errno!(EISCONN));
+		}
+		if self.backlog.lock().is_some() {
+			return Err(errno!(EINVAL));
+		}
+		let cred = Ucred::current();
+		// Create the socket of the connection on the listening side
+		let server = Arc::new(Socket::new(target.desc)?)?;
+		*server.sockname.lock() = Vec::try_from(target.sockname.lock().as_slice())?;
+		*server.peer.lock() = Some(self.rx.clone());
+		*server.peer_cred.lock() = Some(cred);
+		let mut backlog = target.backlog.lock();
+		let Some(backlog) = &mut *backlog else {
+			return Err(errno!(ECONNREFUSED));
+		};
+		if backlog.pending.len() >= backlog.max {
+			return Err(errno!(EAGAIN));
+		}
+		*peer = Some(server.rx.clone());
+		*self.peer_cred.lock() = Some(backlog.cred);
+		backlog.pending.push(server)?;
+		target.accept_queue.wake_next();
+		Ok(())
+	}
+
+	/// Connects the socket to the Internet socket address `sockaddr`.
+	///
+	/// If `nonblock` is set and the connection cannot be established immediately, the function
+	/// returns [`errno::EINPROGRESS`] and the connection is established in the background.
+	///
+	/// If the socket's protocol does not support connections, the function returns
+	/// [`errno::EOPNOTSUPP`].
+	pub fn connect_inet(&self, sockaddr: &[u8], nonblock: bool) -> EResult<()> {
+		// TODO support UDP and IPv6
+		let Some(tcb) = &self.tcp else {
+			return Err(errno!(EOPNOTSUPP));
+		};
+		let (addr, port) = sockaddr::inet_addr(sockaddr)?;
+		let res = Tcb::connect(
+			tcb,
+			Endpoint {
+				addr,
+				port,
+			},
+			nonblock,
+		);
+		// The socket is bound by connecting
+		self.set_inet_name(tcb)?;
+		res
+	}
+
+	/// Accepts a pending connection on the listening socket, returning the socket of the
+	/// connection.
+	///
+	/// If no connection is pending and `nonblock` is set, the function returns
+	/// [`errno::EAGAIN`]. Else, it waits for a connection.
+	///
+	/// If the socket is not listening, the function returns [`errno::EINVAL`].
+	pub fn accept(&self, nonblock: bool) -> EResult<Arc<Socket>> {
+		if let Some(tcb) = &self.tcp {
+			let conn = tcb.accept(nonblock)?;
+			let sock = Self::with_tcp(self.desc, Some(conn.clone()))
+				.and_then(Arc::new)
+				.inspect_err(|_| Tcb::close(&conn))?;
+			sock.set_inet_name(&conn)?;
+			return Ok(sock);
+		}
+		self.accept_queue.wait_until(|| {
+			let mut backlog = self.backlog.lock();
+			let Some(backlog) = &mut *backlog else {
+				return Some(Err(errno!(EINVAL)));
+			};
+			if backlog.pending.is_empty() {
+				return nonblock.then_some(Err(errno!(EAGAIN)));
+			}
+			Some(Ok(backlog.pending.remove(0)))
+		})?
+	}
+
+	/// Sends data on the socket.
+	///
+	/// Arguments:
+	/// - `file` is the open file description of the socket.
+	/// - `buf` is the data to send.
+	/// - `files` are the files to pass along with the data.
+	/// - `cred` are the credentials to pass along with the data. If `None`, the ones of the
+	///   current process are used.
+	/// - `dest` is the destination socket. If `None`, the socket's peer is used.
+	/// - `flags` are the `MSG_*` flags.
+	///
+	/// The function returns the number of bytes sent.
+	pub fn send(
+		&self,
+		file: &File,
+		buf: &[u8],
+		files: Vec<Arc<File>>,
+		cred: Option<Ucred>,
+		dest: Option<&Socket>,
+		flags: c_int,
+	) -> EResult<usize> {
+		let nonblock = file.get_flags() & O_NONBLOCK != 0 || flags & MSG_DONTWAIT != 0;
+		if let Some(tcb) = &self.tcp {
+			let mut off = 0;
+			while off < buf.len() {
+				match Tcb::send(tcb, &buf[off..], nonblock) {
+					Ok(len) => off += len,
+					// Report the data sent before the error
+					Err(_) if off > 0 => break,
+					Err(e) => return self.send_error(e, flags),
+				}
+			}
+			return Ok(off);
+		}
+		let cred = cred.unwrap_or_else(Ucred::current);
+		let stream = self.desc.type_.is_stream();
+		let channel = match dest {
+			Some(_) if stream => return Err(errno!(EISCONN)),
+			Some(dest) if dest.desc.type_ != self.desc.type_ => return Err(errno!(EPROTOTYPE)),
+			Some(dest) => dest.rx.clone(),
+			None => self.peer.lock().clone().ok_or_else(|| {
+				if stream {
+					errno!(ENOTCONN)
+				} else {
+					errno!(EDESTADDRREQ)
+				}
+			})?,
+		};
+		if !stream {
+			return channel
+				.send(buf, files, cred, false, nonblock)
+				.or_else(|e| self.send_error(e, flags));
+		}
+		// Files are passed along with the first chunk of data
+		let mut files = Some(files);
+		let mut off = 0;
+		while off < buf.len() {
+			let files = files.take().unwrap_or_default();
+			match channel.send(&buf[off..], files, cred, true, nonblock) {
+				Ok(len) => off += len,
+				// Report the data sent before the error
+				Err(_) if off > 0 => break,
+				Err(e) => return self.send_error(e, flags),
+			}
+		}
+		Ok(off)
+	}
+
+	/// Handles the error `err` that occurred while sending data with the given `flags`.
+	fn send_error(&self, err: errno::Errno, flags: c_int) -> EResult<usize> {
+		if err != errno!(EPIPE) {
+			return Err(err);
+		}
+		// The receiving datagram socket has been closed
+		if !self.desc.type_.is_stream() {
+			return Err(errno!(ECONNREFUSED));
+		}
+		if flags & MSG_NOSIGNAL == 0 {
+			Process::current().lock().kill(Signal::SIGPIPE);
+		}
+		Err(err)
+	}
+
+	/// Receives data from the socket into `buf`.
+	///
+	/// Arguments:
+	/// - `file` is the open file description of the socket.
+	/// - `buf` is the buffer to write the data into.
+	/// - `flags` are the `MSG_*` flags.
+	pub fn recv(&self, file: &File, buf: &mut [u8], flags: c_int) -> EResult<Received> {
+		let nonblock = file.get_flags() & O_NONBLOCK != 0 || flags & MSG_DONTWAIT != 0;
+		if let Some(tcb) = &self.tcp {
+			return Ok(Received {
+				len: Tcb::recv(tcb, buf, flags & MSG_PEEK != 0, nonblock)?,
+				..Default::default()
+			});
+		}
+		let stream = self.desc.type_.is_stream();
+		if stream && self.peer.lock().is_none() && self.rx.is_empty() {
+			return Err(errno!(ENOTCONN));
+		}
+		self.rx.recv(buf, stream, flags & MSG_PEEK != 0, nonblock)
+	}
+
 	/// Shuts down the reception side of the socket.
 	pub fn shutdown_reception(&self) {
-		*self.rx_buff.lock() = None;
+		if let Some(tcb) = &self.tcp {
+			tcb.shutdown_reception();
+			return;
+		}
+		self.rx.close_rx();
 	}
 
 	/// Shuts down the transmit side of the socket.
 	pub fn shutdown_transmit(&self) {
-		*self.tx_buff.lock() = None;
+		if let Some(tcb) = &self.tcp {
+			Tcb::shutdown_transmit(tcb);
+			return;
+		}
+		let mut peer = self.peer.lock();
+		if self.desc.type_.is_stream() {
+			if let Some(peer) = &*peer {
+				peer.close_tx();
+			}
+		} else {
+			// The channel of the peer may be shared with other senders
+			*peer = None;
+		}
+	}
+
+	/// Closes the socket, once it is no longer open.
+	fn close(&self) {
+		if let Some(tcb) = &self.tcp {
+			Tcb::close(tcb);
+			return;
+		}
+		self.shutdown_reception();
+		self.shutdown_transmit();
+		*self.peer.lock() = None;
+		// Close the connections that have not been accepted
+		let backlog = self.backlog.lock().take();
+		for sock in backlog.iter().flat_map(|b| b.pending.iter()) {
+			sock.close();
+		}
+		if let Some(loc) = self.node.lock().take() {
+			buffer::detach_if(&loc, self as *const _ as *const ());
+		}
 	}
 }
 
@@ -168,31 +673,132 @@ impl FileOps for Socket {
 
 	fn release(&self, _file: &File) {
 		let cnt = self.open_count.fetch_sub(1, atomic::Ordering::Release);
-		if cnt == 0 {
-			// TODO close the socket
+		if cnt == 1 {
+			self.close();
 		}
 	}
 
-	fn poll(&self, _file: &File, _mask: u32) -> EResult<u32> {
-		todo!()
+	fn poll(&self, _file: &File, mask: u32) -> EResult<u32> {
+		if let Some(tcb) = &self.tcp {
+			return Ok(tcb.poll(mask));
+		}
+		if let Some(backlog) = &*self.backlog.lock() {
+			let res = if !backlog.pending.is_empty() {
+				POLLIN
+			} else {
+				0
+			};
+			return Ok(res & mask);
+		}
+		let mut res = 0;
+		// Reading returns end-of-file
+		let rx_closed = self.rx.is_closed();
+		if !self.rx.is_empty() || rx_closed {
+			res |= POLLIN;
+		}
+		if rx_closed {
+			res |= POLLRDHUP;
+		}
+		let tx_closed = match &*self.peer.lock() {
+			Some(peer) if peer.is_closed() => true,
+			Some(peer) => {
+				if peer.has_space() {
+					res |= POLLOUT;
+				}
+				false
+			}
+			// An unconnected datagram socket can send with an explicit destination
+			None if !self.desc.type_.is_stream() => {
+				res |= POLLOUT;
+				false
+			}
+			None => true,
+		};
+		if rx_closed && tx_closed {
+			res |= POLLHUP;
+		}
+		Ok(res & mask)
 	}
 
-	fn ioctl(&self, _file: &File, _request: Request, _argp: *const c_void) -> EResult<u32> {
-		todo!()
+	fn poll_wait(&self, _file: &File) -> EResult<bool> {
+		if let Some(tcb) = &self.tcp {
+			tcb.poll_wait()?;
+			return Ok(true);
+		}
+		self.accept_queue.register()?;
+		self.rx.poll_wait()?;
+		if let Some(peer) = &*self.peer.lock() {
+			peer.poll_wait()?;
+		}
+		Ok(true)
 	}
 
-	fn read(&self, _file: &File, _off: u64, _buf: &mut [u8]) -> EResult<usize> {
-		if !self.desc.type_.is_stream() {
-			// TODO error
+	fn ioctl(&self, _file: &File, request: Request, argp: *const c_void) -> EResult<u32> {
+		match request.get_old_format() {
+			ioctl::FIONREAD => {
+				let len = match &self.tcp {
+					Some(tcb) => tcb.get_data_len(),
+					None => self.rx.get_data_len(),
+				};
+				let len = min(len, c_int::MAX as usize);
+				let count_ptr = SyscallPtr::<c_int>::from_syscall_arg(argp as usize);
+				count_ptr.copy_to_user(len as _)?;
+			}
+			_ => return Err(errno!(ENOTTY)),
 		}
-		todo!()
+		Ok(0)
+	}
+
+	fn read(&self, file: &File, _off: u64, buf: &mut [u8]) -> EResult<usize> {
+		// Files passed along with the data are discarded
+		Ok(self.recv(file, buf, 0)?.len)
 	}
 
-	fn write(&self, _file: &File, _off: u64, _buf: &[u8]) -> EResult<usize> {
-		// A destination address is required
-		let Some(_stack) = self.stack.as_ref() else {
-			return Err(errno!(EDESTADDRREQ));
+	fn write(&self, file: &File, _off: u64, buf: &[u8]) -> EResult<usize> {
+		self.send(file, buf, Vec::new(), None, None, 0)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::{
+		file::{pipe::PipeBuffer, O_RDONLY, O_RDWR},
+		net::{SocketDomain, SocketType},
+	};
+
+	#[test_case]
+	fn socket_pair_rights() {
+		let desc = SocketDesc {
+			domain: SocketDomain::AfUnix,
+			type_: SocketType::SockStream,
+			protocol: 0,
 		};
-		todo!()
+		let (sock0, sock1) = Socket::new_pair(desc).unwrap();
+		let sock0 = Arc::new(sock0).unwrap();
+		let sock1 = Arc::new(sock1).unwrap();
+		let file0 = File::open_floating(sock0.clone(), O_RDWR | O_NONBLOCK).unwrap();
+		let file1 = File::open_floating(sock1.clone(), O_RDWR | O_NONBLOCK).unwrap();
+		let pipe = Arc::new(PipeBuffer::new().unwrap()).unwrap();
+		let passed = File::open_floating(pipe, O_RDONLY).unwrap();
+		let files = Vec::try_from([passed]).unwrap();
+		assert_eq!(sock0.send(&file0, b"ab", files, None, None, 0).unwrap(), 2);
+		assert_eq!(
+			sock0
+				.send(&file0, b"cd", Vec::new(), None, None, 0)
+				.unwrap(),
+			2
+		);
+		// Files are received with the beginning of their message only
+		let mut buf = [0u8; 8];
+		let res = sock1.recv(&file1, &mut buf, 0).unwrap();
+		assert_eq!(&buf[..res.len], b"ab");
+		assert_eq!(res.files.len(), 1);
+		let res = sock1.recv(&file1, &mut buf, 0).unwrap();
+		assert_eq!(&buf[..res.len], b"cd");
+		assert!(res.files.is_empty());
+		// Closing the peer gives an end-of-file
+		drop(file0);
+		assert_eq!(sock1.recv(&file1, &mut buf, 0).unwrap().len, 0);
 	}
 }
@@ -0,0 +1,249 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! A timerfd is a timer notifying its expirations through a file descriptor, which allows to wait
+//! for it along with other files using `poll` or `select`.
+//!
+//! Reading from the file returns the number of expirations since the last read, as a `u64`.
+
+use crate::{
+	file::{wait_queue::WaitQueue, File, FileOps, FileType, Stat, O_CLOEXEC, O_NONBLOCK},
+	syscall::{ioctl, poll::POLLIN},
+	time::{
+		clock,
+		clock::{
+			CLOCK_BOOTTIME, CLOCK_BOOTTIME_ALARM, CLOCK_MONOTONIC, CLOCK_REALTIME,
+			CLOCK_REALTIME_ALARM,
+		},
+		namespace,
+		namespace::TimeNamespace,
+		timer::compute_next,
+		unit::{ClockIdT, ITimerspec64, TimeUnit, Timespec, Timespec64},
+	},
+};
+use core::{
+	ffi::{c_int, c_void},
+	mem,
+	mem::size_of,
+	ptr,
+};
+use utils::{
+	collections::vec::Vec,
+	errno,
+	errno::{AllocResult, EResult},
+	lock::IntMutex,
+	ptr::arc::Arc,
+};
+
+/// `timerfd_create` flag: the file descriptor is closed on `execve`.
+pub const TFD_CLOEXEC: c_int = O_CLOEXEC;
+/// `timerfd_create` flag: the file descriptor is non-blocking.
+pub const TFD_NONBLOCK: c_int = O_NONBLOCK;
+
+/// `timerfd_settime` flag: the given value is an absolute timestamp.
+pub const TFD_TIMER_ABSTIME: c_int = 1;
+/// `timerfd_settime` flag: cancel the timer when the real time clock is set.
+pub const TFD_TIMER_CANCEL_ON_SET: c_int = 2;
+
+/// The state of a [`TimerFd`].
+#[derive(Debug, Default)]
+struct TimerFdInner {
+	/// The interval between expirations. If zero, the timer expires only once.
+	interval: Timespec,
+	/// The timestamp of the next expiration. If `None`, the timer is disarmed.
+	next: Option<Timespec>,
+	/// The number of expirations since the last read.
+	expirations: u64,
+}
+
+/// A timer readable through a file descriptor.
+#[derive(Debug)]
+pub struct TimerFd {
+	/// The ID of the clock to use.
+	clockid: ClockIdT,
+	/// The state of the timer.
+	inner: IntMutex<TimerFdInner>,
+	/// The queue of processes waiting for an expiration.
+	rd_queue: WaitQueue,
+}
+
+impl TimerFd {
+	/// Creates a disarmed timer using the clock `clockid`.
+	///
+	/// If the clock cannot be used for a timerfd, the function returns [`errno::EINVAL`].
+	pub fn new(clockid: ClockIdT) -> EResult<Self> {
+		if !matches!(
+			clockid,
+			CLOCK_REALTIME
+				| CLOCK_MONOTONIC
+				| CLOCK_BOOTTIME
+				| CLOCK_REALTIME_ALARM
+				| CLOCK_BOOTTIME_ALARM
+		) {
+			return Err(errno!(EINVAL));
+		}
+		Ok(Self {
+			clockid,
+			inner: Default::default(),
+			rd_queue: WaitQueue::new(),
+		})
+	}
+
+	/// Returns the current state of the timer, relative to the current timestamp `ts`.
+	fn get_time_at(inner: &TimerFdInner, ts: &Timespec) -> ITimerspec64 {
+		let value = inner
+			.next
+			.map(|next| next.to_nano().saturating_sub(ts.to_nano()))
+			.unwrap_or(0);
+		ITimerspec64 {
+			it_interval: Timespec64::from_nano(inner.interval.to_nano()),
+			it_value: Timespec64::from_nano(value),
+		}
+	}
+
+	/// Returns the current state of the timer.
+	pub fn get_time(&self) -> ITimerspec64 {
+		let ts = clock::current_time_struct(self.clockid).unwrap();
+		Self::get_time_at(&self.inner.lock(), &ts)
+	}
+
+	/// Sets the timer's state, then returns the previous one.
+	///
+	/// Arguments:
+	/// - `spec` is the new setting of the timer. If its value is zero, the timer is disarmed.
+	/// - `abs` tells whether the value in `spec` is an absolute timestamp instead of a duration
+	///   relative to the current time.
+	/// - `ns` is the time namespace in which an absolute timestamp is expressed.
+	///
+	/// Expirations that have not been read yet are discarded.
+	pub fn set_time(
+		&self,
+		spec: ITimerspec64,
+		abs: bool,
+		ns: Option<&TimeNamespace>,
+	) -> ITimerspec64 {
+		let ts = clock::current_time_struct(self.clockid).unwrap();
+		let value = if abs {
+			let value = namespace::to_host(ns, self.clockid, spec.it_value.to_nano());
+			Timespec64::from_nano(value)
+		} else {
+			spec.it_value
+		};
+		let mut inner = self.inner.lock();
+		let old = Self::get_time_at(&inner, &ts);
+		inner.interval = Timespec::from_nano(spec.it_interval.to_nano());
+		inner.next = (!spec.it_value.is_zero()).then(|| compute_next(&value, abs, &ts));
+		inner.expirations = 0;
+		old
+	}
+
+	/// Counts the expirations of the timer up to the current timestamp `ts`, then rearms or
+	/// disarms it.
+	///
+	/// If the timer has expired, the function returns `true`.
+	fn expire(&self, ts: &Timespec) -> bool {
+		let mut inner = self.inner.lock();
+		let Some(next) = inner.next else {
+			return false;
+		};
+		if *ts < next {
+			return false;
+		}
+		let interval = inner.interval.to_nano();
+		let count = if interval == 0 {
+			inner.next = None;
+			1
+		} else {
+			// Several periods may have elapsed since the last tick
+			let count = (ts.to_nano() - next.to_nano()) / interval + 1;
+			inner.next = Some(Timespec::from_nano(next.to_nano() + count * interval));
+			count
+		};
+		inner.expirations = inner.expirations.saturating_add(count);
+		true
+	}
+}
+
+impl FileOps for TimerFd {
+	fn get_stat(&self, _file: &File) -> EResult<Stat> {
+		Ok(Stat {
+			mode: FileType::Regular.to_mode() | 0o600,
+			..Default::default()
+		})
+	}
+
+	fn acquire(&self, _file: &File) {}
+
+	fn release(&self, _file: &File) {
+		// Stop ticking the timer
+		TIMERS.lock().retain(|t| !ptr::eq(t.as_ptr(), self));
+	}
+
+	fn poll(&self, _file: &File, mask: u32) -> EResult<u32> {
+		let readable = self.inner.lock().expirations > 0;
+		Ok(if readable { mask & POLLIN } else { 0 })
+	}
+
+	fn poll_wait(&self, _file: &File) -> EResult<bool> {
+		self.rd_queue.register()?;
+		Ok(true)
+	}
+
+	fn ioctl(&self, _file: &File, _request: ioctl::Request, _argp: *const c_void) -> EResult<u32> {
+		Err(errno!(ENOTTY))
+	}
+
+	fn read(&self, file: &File, _off: u64, buf: &mut [u8]) -> EResult<usize> {
+		if buf.len() < size_of::<u64>() {
+			return Err(errno!(EINVAL));
+		}
+		let nonblock = file.get_flags() & O_NONBLOCK != 0;
+		let count = self.rd_queue.wait_until(|| {
+			let mut inner = self.inner.lock();
+			if inner.expirations == 0 {
+				return nonblock.then_some(Err(errno!(EAGAIN)));
+			}
+			Some(Ok(mem::take(&mut inner.expirations)))
+		})??;
+		buf[..size_of::<u64>()].copy_from_slice(&count.to_ne_bytes());
+		Ok(size_of::<u64>())
+	}
+
+	fn write(&self, _file: &File, _off: u64, _buf: &[u8]) -> EResult<usize> {
+		Err(errno!(EINVAL))
+	}
+}
+
+/// The list of existing timers.
+static TIMERS: IntMutex<Vec<Arc<TimerFd>>> = IntMutex::new(Vec::new());
+
+/// Registers the given timer so that it is ticked.
+pub fn register(timer: Arc<TimerFd>) -> AllocResult<()> {
+	TIMERS.lock().push(timer)
+}
+
+/// Ticks timers, waking up processes waiting on those that expired.
+pub(crate) fn tick() {
+	let timers = TIMERS.lock();
+	for timer in timers.iter() {
+		let ts = clock::current_time_struct(timer.clockid).unwrap();
+		if timer.expire(&ts) {
+			timer.rd_queue.wake_all();
+		}
+	}
+}
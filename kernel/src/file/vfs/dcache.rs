@@ -0,0 +1,218 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The directory entries cache (dcache) keeps recently used entries in memory after their last
+//! use, so that resolving the same paths again does not require looking them up on the
+//! filesystem.
+//!
+//! Entries are kept in their parent's list of children, which is indexed by name. The cache only
+//! holds a reference to them, so that they are not released, and evicts the least recently used
+//! one when full.
+//!
+//! Failed lookups are cached as *negative* entries, which have no node. They are invalidated
+//! when a file with the same name is created.
+//!
+//! Lock ordering: the lock of an entry's children may be taken while holding the lock of the
+//! cache, but not the opposite.
+//!
+//! Only entries on filesystems whose files can be cached (see [`Filesystem::use_cache`]) are
+//! kept, since the content of other filesystems may change without going through the VFS.
+//!
+//! [`Filesystem::use_cache`]: crate::file::fs::Filesystem::use_cache
+
+use super::{Entry, EntryChild};
+use crate::file::FileLocation;
+use core::{mem, sync::atomic::Ordering::Relaxed};
+use utils::{
+	collections::{btreemap::BTreeMap, string::String},
+	errno::EResult,
+	lock::{atomic::AtomicU64, Mutex},
+	ptr::arc::Arc,
+};
+
+/// The maximum number of entries kept in cache.
+const CACHE_SIZE: usize = 1024;
+
+/// The cached entries, ordered by use.
+struct Lru {
+	/// The entries, indexed by stamp. The entry with the lowest stamp is the least recently used.
+	entries: BTreeMap<u64, Arc<Entry>>,
+	/// The stamp for the next use.
+	///
+	/// Stamps start at `1`, since `0` is used for entries that are not in cache.
+	next_stamp: u64,
+}
+
+/// The cache.
+static LRU: Mutex<Lru> = Mutex::new(Lru {
+	entries: BTreeMap::new(),
+	next_stamp: 1,
+});
+/// Incremented on each invalidation, so that a lookup concurrent with the creation of a file does
+/// not insert a negative entry for it.
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// Returns the location of the node of `ent`.
+///
+/// Since negative entries are on the same filesystem as their parent, the location of the parent
+/// is returned for them.
+fn location(ent: &Entry) -> &FileLocation {
+	match (&ent.node, &ent.parent) {
+		(Some(node), _) => &node.location,
+		// Negative entries always have a parent
+		(None, parent) => &parent.as_ref().unwrap().node().location,
+	}
+}
+
+/// Tells whether entries of the filesystem containing the file at `loc` can be kept in cache.
+pub(super) fn is_cacheable(loc: &FileLocation) -> bool {
+	loc.get_filesystem().is_some_and(|fs| fs.use_cache())
+}
+
+/// Records a use of `ent`, inserting it in cache if it is not already.
+///
+/// If the cache is full, the least recently used entry is evicted.
+pub(super) fn touch(ent: &Arc<Entry>) -> EResult<()> {
+	// Checking whether the entry can be cached requires locking mountpoints, so it is done only
+	// for entries that are not in cache yet
+	if ent.dcache_stamp.load(Relaxed) == 0 && !is_cacheable(location(ent)) {
+		return Ok(());
+	}
+	let evicted = {
+		let mut lru = LRU.lock();
+		let stamp = lru.next_stamp;
+		lru.next_stamp += 1;
+		// Each stamp is given to a single entry
+		lru.entries.remove(&ent.dcache_stamp.load(Relaxed));
+		// Since the caller holds a reference, the entry does not need to be released on failure
+		if lru.entries.insert(stamp, ent.clone()).is_err() {
+			// Not caching the entry is not an error
+			ent.dcache_stamp.store(0, Relaxed);
+			return Ok(());
+		}
+		ent.dcache_stamp.store(stamp, Relaxed);
+		if lru.entries.len() > CACHE_SIZE {
+			lru.entries.pop_first().map(|(_, ent)| {
+				ent.dcache_stamp.store(0, Relaxed);
+				ent
+			})
+		} else {
+			None
+		}
+	};
+	// Released without holding the lock to keep it short
+	match evicted {
+		Some(ent) => Entry::release(ent),
+		None => Ok(()),
+	}
+}
+
+/// Returns the current generation of the cache, to be passed to [`insert_negative`].
+pub(super) fn generation() -> u64 {
+	GENERATION.load(Relaxed)
+}
+
+/// Inserts a negative entry named `name` in `parent`, remembering the file does not exist.
+///
+/// `generation` is the value returned by [`generation`] before looking up the file. If an
+/// invalidation happened since, the entry is not inserted.
+///
+/// If the entry cannot be kept in cache or if an entry with the same name is already present,
+/// the function does nothing.
+pub(super) fn insert_negative(parent: &Arc<Entry>, name: &[u8], generation: u64) -> EResult<()> {
+	if !is_cacheable(&parent.node().location) {
+		return Ok(());
+	}
+	let ent = Arc::new(Entry {
+		name: String::try_from(name)?,
+		parent: Some(parent.clone()),
+		children: Default::default(),
+		node: None,
+		dcache_stamp: Default::default(),
+	})?;
+	{
+		let mut children = parent.children.lock();
+		if GENERATION.load(Relaxed) != generation || children.get(name).is_some() {
+			return Ok(());
+		}
+		children.insert(EntryChild(ent.clone()))?;
+	}
+	touch(&ent)
+}
+
+/// Removes `ent` from the cache, then releases it.
+///
+/// This function must be called **after** the entry is removed from its parent.
+pub(super) fn forget(ent: Arc<Entry>) -> EResult<()> {
+	let cached = {
+		let mut lru = LRU.lock();
+		let stamp = ent.dcache_stamp.load(Relaxed);
+		ent.dcache_stamp.store(0, Relaxed);
+		lru.entries.remove(&stamp)
+	};
+	// Dropping the reference held by the cache is enough since `ent` is released below
+	drop(cached);
+	Entry::release(ent)
+}
+
+/// Removes the child named `name` from `parent`, if present in memory, and from the cache.
+///
+/// This function must be called when the link named `name` in `parent` is created or removed,
+/// so that the previous entry with the same name, if any, is not used anymore.
+pub(super) fn invalidate(parent: &Entry, name: &[u8]) -> EResult<()> {
+	// Increment before removing, so that a concurrent negative lookup cannot insert an entry
+	// after the removal
+	GENERATION.fetch_add(1, Relaxed);
+	let ent = parent.children.lock().remove(name);
+	match ent {
+		Some(EntryChild(ent)) => forget(ent),
+		None => Ok(()),
+	}
+}
+
+/// Removes the children of the directory `dir` from memory and from the cache.
+///
+/// This function must be called when `dir` is removed. Since it is empty, its children are
+/// negative entries, which would otherwise keep it in memory.
+pub(super) fn purge_children(dir: &Entry) -> EResult<()> {
+	GENERATION.fetch_add(1, Relaxed);
+	let children = mem::take(&mut *dir.children.lock());
+	let mut res = Ok(());
+	for EntryChild(ent) in children {
+		res = res.and(forget(ent));
+	}
+	res
+}
+
+/// Removes every entry on the mountpoint with ID `mountpoint_id` from the cache.
+///
+/// This function must be called when the mountpoint is removed, since the cache would keep its
+/// nodes in use.
+pub(super) fn purge_mountpoint(mountpoint_id: u32) -> EResult<()> {
+	let mut lru = LRU.lock();
+	let mut res = Ok(());
+	// Releasing an entry never locks the cache, so it can be done while holding the lock
+	for (_, ent) in lru
+		.entries
+		.drain_filter(|_, ent| location(ent).mountpoint_id == mountpoint_id)
+	{
+		ent.dcache_stamp.store(0, Relaxed);
+		res = res.and(Entry::release(ent));
+	}
+	res
+}
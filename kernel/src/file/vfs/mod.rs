@@ -21,23 +21,40 @@
 //! To manipulate files, the VFS should be used instead of
 //! calling the filesystems' directly.
 
+mod dcache;
 pub mod mountpoint;
 pub mod node;
+mod orphan;
+pub mod path_cache;
 
 use super::{
-	perm,
-	perm::{AccessProfile, S_ISVTX},
-	File, FileLocation, FileType, Stat,
+	fs::{adjusted_nlink, NodeOps, StatSet, RENAME_EXCHANGE, RENAME_NOREPLACE},
+	notify,
+	notify::{
+		IN_ATTRIB, IN_CREATE, IN_DELETE, IN_DELETE_SELF, IN_ISDIR, IN_MODIFY, IN_MOVED_FROM,
+		IN_MOVED_TO, IN_MOVE_SELF,
+	},
+	page_cache, perm,
+	perm::{AccessProfile, Gid, Uid, S_ISGID, S_ISUID, S_ISVTX, S_IXGRP},
+	File, FileLocation, FileType, INode, Mode, Stat, O_NONBLOCK,
 };
 use crate::{
-	device, device::DeviceID, file::vfs::mountpoint::MountPoint, process::Process,
+	device,
+	device::DeviceID,
+	file::vfs::mountpoint::MountPoint,
+	process::Process,
 	syscall::ioctl::Request,
+	time::{
+		clock::{current_time, CLOCK_REALTIME},
+		unit::TimestampScale,
+	},
 };
 use core::{
 	borrow::Borrow,
-	ffi::c_void,
+	ffi::{c_int, c_void},
 	hash::{Hash, Hasher},
 	intrinsics::unlikely,
+	ptr,
 };
 use node::Node;
 use utils::{
@@ -49,8 +66,8 @@ use utils::{
 	},
 	errno,
 	errno::EResult,
-	limits::{LINK_MAX, PATH_MAX, SYMLOOP_MAX},
-	lock::{once::OnceInit, Mutex},
+	limits::{LINK_MAX, NAME_MAX, PATH_MAX, SYMLOOP_MAX},
+	lock::{atomic::AtomicU64, once::OnceInit, Mutex},
 	ptr::arc::Arc,
 	vec,
 };
@@ -98,6 +115,10 @@ pub struct Entry {
 	///
 	/// If `None`, the file do not actually exist.
 	node: Option<Arc<Node>>,
+	/// The position of the entry in the dcache. If `0`, the entry is not in it.
+	///
+	/// This field is protected by the dcache's lock.
+	dcache_stamp: AtomicU64,
 }
 
 impl Entry {
@@ -108,6 +129,7 @@ impl Entry {
 			parent: None,
 			children: Default::default(),
 			node: Some(node),
+			dcache_stamp: Default::default(),
 		}
 	}
 
@@ -123,6 +145,14 @@ impl Entry {
 		}
 	}
 
+	/// Returns the parent of the entry.
+	///
+	/// If `None`, the entry is the root of the VFS.
+	#[inline]
+	pub fn get_parent(&self) -> Option<&Arc<Entry>> {
+		self.parent.as_ref()
+	}
+
 	/// Returns a reference to the underlying node.
 	///
 	/// If the entry represents a non-existent file, the function panics.
@@ -176,10 +206,7 @@ impl Entry {
 					.ok_or_else(|| errno!(EOVERFLOW))?;
 				buf.resize(new_size, 0)?;
 			}
-			let len =
-				self.node()
-					.ops
-					.read_content(&self.node().location, off as _, &mut buf[off..])?;
+			let len = page_cache::read(self.node(), off as _, &mut buf[off..])?;
 			// Reached EOF, stop here
 			if len == 0 {
 				break;
@@ -191,55 +218,120 @@ impl Entry {
 		Ok(buf)
 	}
 
-	/// Returns the absolute path to reach the entry.
-	pub fn get_path(this: &Arc<Self>) -> EResult<PathBuf> {
-		if this.parent.is_none() {
-			return Ok(PathBuf::root()?);
-		}
+	/// Implementation of [`Self::get_path`] and [`Self::get_path_from`].
+	///
+	/// If `root` is `None`, the path is built up to the root of the VFS.
+	fn get_path_impl(this: &Arc<Self>, root: Option<&Self>) -> EResult<PathBuf> {
 		let mut buf = vec![0u8; PATH_MAX]?;
 		let mut off = PATH_MAX;
 		let mut cur = this;
-		while let Some(parent) = &cur.parent {
+		loop {
+			if let Some(root) = root {
+				if cur.node().location == root.node().location {
+					break;
+				}
+			}
+			let Some(parent) = &cur.parent else {
+				// Reached the root of the VFS
+				if root.is_some() {
+					// `root` is not an ancestor of the entry
+					return Err(errno!(ENOENT));
+				}
+				break;
+			};
 			let len = cur.name.len();
 			off = off
 				.checked_sub(len + 1)
 				.ok_or_else(|| errno!(ENAMETOOLONG))?;
 			buf[off] = b'/';
 			buf[(off + 1)..(off + len + 1)].copy_from_slice(&cur.name);
+			// Mountpoint roots share the name and parent of the directory they are mounted on,
+			// so walking up through parents crosses mountpoints transparently
 			cur = parent;
 		}
+		if off == PATH_MAX {
+			return Ok(PathBuf::root()?);
+		}
 		buf.rotate_left(off);
 		buf.truncate(buf.len() - off);
 		Ok(PathBuf::new_unchecked(String::from(buf)))
 	}
 
+	/// Returns the absolute path to reach the entry.
+	pub fn get_path(this: &Arc<Self>) -> EResult<PathBuf> {
+		Self::get_path_impl(this, None)
+	}
+
+	/// Returns the path to reach the entry, relative to the given `root` directory.
+	///
+	/// The returned path is absolute, `/` representing `root`. This is useful to get paths as
+	/// seen by a process whose root directory has been changed with `chroot`.
+	///
+	/// If `root` is not an ancestor of the entry, the function returns [`errno::ENOENT`].
+	pub fn get_path_from(this: &Arc<Self>, root: &Self) -> EResult<PathBuf> {
+		Self::get_path_impl(this, Some(root))
+	}
+
+	/// Tells whether the entry has been removed from its parent directory by an unlink or a
+	/// rename, while still in use.
+	///
+	/// The root of the VFS is never considered unlinked.
+	pub fn is_unlinked(&self) -> bool {
+		let Some(parent) = &self.parent else {
+			return false;
+		};
+		!parent
+			.children
+			.lock()
+			.get(&*self.name)
+			.is_some_and(|EntryChild(ent)| ptr::eq(ent.as_ptr(), self))
+	}
+
 	/// Releases the entry, removing it the underlying node if no link remain and this was the last
 	/// use of it.
-	pub fn release(this: Arc<Self>) -> EResult<()> {
-		let Some(parent) = &this.parent else {
-			// This is the root of the VFS, stop
-			return Ok(());
-		};
-		{
-			// Lock to avoid a race condition with `strong_count`
-			let mut parent_children = parent.children.lock();
-			// If this is **not** the last reference to the current (the one held by its own
-			// parent
-			// + the one that we hold here)
-			if Arc::strong_count(&this) > 2 {
+	///
+	/// If this was the last use of the entry, its parent is released as well, since the entry
+	/// held a reference to it.
+	pub fn release(mut this: Arc<Self>) -> EResult<()> {
+		loop {
+			let Some(parent) = &this.parent else {
+				// This is either the root of the VFS, which is never released as it is referenced
+				// by its mountpoint, or a detached entry
+				if let Some(node) = Arc::into_inner(this).and_then(|e| e.node) {
+					Node::release(node)?;
+				}
+				return Ok(());
+			};
+			{
+				// Lock to avoid a race condition with `strong_count`
+				let mut parent_children = parent.children.lock();
+				// The entry is not in its parent anymore if it has been unlinked or moved. In that
+				// case, the parent may contain another entry with the same name
+				let cached = parent_children
+					.get(&*this.name)
+					.is_some_and(|EntryChild(ent)| ptr::eq(ent.as_ptr(), this.as_ptr()));
+				// If this is **not** the last reference to the current (the one held by its own
+				// parent, if any, + the one that we hold here)
+				let refs = if cached { 2 } else { 1 };
+				if Arc::strong_count(&this) > refs {
+					return Ok(());
+				}
+				if cached {
+					parent_children.remove(&*this.name);
+				}
+			}
+			let Some(c) = Arc::into_inner(this) else {
+				// The entry was already detached from its parent before: someone else references
+				// it
 				return Ok(());
+			};
+			// Release the inner node if present
+			if let Some(node) = c.node {
+				Node::release(node)?;
 			}
-			parent_children.remove(&*this.name);
-		}
-		let Some(c) = Arc::into_inner(this) else {
-			// The entry was already detached from its parent before: someone else references it
-			return Ok(());
-		};
-		// Release the inner node if present
-		if let Some(node) = c.node {
-			Node::release(node)?;
+			// Cannot fail since the entry has a parent
+			this = c.parent.unwrap();
 		}
-		Ok(())
 	}
 }
 
@@ -300,11 +392,12 @@ impl ResolutionSettings {
 	///
 	/// `follow_links` tells whether symbolic links are followed.
 	pub fn for_process(proc: &Process, follow_links: bool) -> Self {
+		let fs = proc.fs.lock();
 		Self {
-			root: proc.chroot.clone(),
-			cwd: Some(proc.cwd.clone()),
+			root: fs.chroot.clone(),
+			cwd: Some(fs.cwd.clone()),
 
-			access_profile: proc.access_profile,
+			access_profile: proc.cred.get(),
 
 			create: false,
 			follow_link: follow_links,
@@ -329,20 +422,64 @@ pub enum Resolved<'s> {
 	},
 }
 
+/// The maximum total number of symbolic links that can be followed during a single path
+/// resolution.
+///
+/// Contrary to [`SYMLOOP_MAX`], which limits the nesting of symbolic links (a link whose target
+/// contains a link, etc...), this limit counts every link followed, including links in
+/// intermediate components.
+pub const SYMLINK_TRAVERSAL_MAX: usize = 40;
+
+/// The state of a path resolution, shared with the resolution of the symbolic links it follows.
+#[derive(Default)]
+struct Walk {
+	/// The total number of symbolic links followed so far.
+	links: usize,
+	/// If `Some`, every directory searched during the resolution is recorded in it.
+	dirs: Option<Vec<Arc<Entry>>>,
+	/// Tells whether a magic link has been followed.
+	magic: bool,
+}
+
+impl Walk {
+	/// Records that `dir` is searched, if recording is enabled.
+	fn search(&mut self, dir: &Arc<Entry>) -> EResult<()> {
+		if let Some(dirs) = &mut self.dirs {
+			dirs.push(dir.clone())?;
+		}
+		Ok(())
+	}
+}
+
 /// Resolves an entry with the given `name`, in the given `lookup_dir`.
 ///
 /// If the entry does not exist, the function returns `None`.
 fn resolve_entry(lookup_dir: &Arc<Entry>, name: &[u8]) -> EResult<Option<Arc<Entry>>> {
+	let generation = dcache::generation();
+	let Some(ent) = lookup_entry(lookup_dir, name)? else {
+		// Remember the file does not exist
+		dcache::insert_negative(lookup_dir, name, generation)?;
+		return Ok(None);
+	};
+	dcache::touch(&ent)?;
+	if ent.node.is_some() {
+		Ok(Some(ent))
+	} else {
+		Ok(None)
+	}
+}
+
+/// Returns the entry with the given `name` in `lookup_dir`, from memory or the filesystem.
+///
+/// The returned entry may be a negative entry, remembering that the file does not exist. If the
+/// file does not exist and no negative entry is present, the function returns `None`.
+fn lookup_entry(lookup_dir: &Arc<Entry>, name: &[u8]) -> EResult<Option<Arc<Entry>>> {
 	let mut children = lookup_dir.children.lock();
-	// Try to get from cache first
+	// Try to get from memory first
 	if let Some(ent) = children.get(name) {
-		return if ent.0.node.is_some() {
-			Ok(Some(ent.0.clone()))
-		} else {
-			Ok(None)
-		};
+		return Ok(Some(ent.0.clone()));
 	}
-	// Not in cache. Try to get from the filesystem
+	// Not in memory. Try to get from the filesystem
 	let Some((entry, ops)) = lookup_dir
 		.node()
 		.ops
@@ -350,26 +487,53 @@ fn resolve_entry(lookup_dir: &Arc<Entry>, name: &[u8]) -> EResult<Option<Arc<Ent
 	else {
 		return Ok(None);
 	};
-	let node = node::insert(Node {
-		location: FileLocation {
-			// The file is on the same mountpoint as the parent since mountpoint roots are always
-			// in cache
-			mountpoint_id: lookup_dir.node().location.mountpoint_id,
-			inode: entry.inode,
-		},
-		ops,
-	})?;
+	let location = FileLocation {
+		// The file is on the same mountpoint as the parent since mountpoint roots are always
+		// in cache
+		mountpoint_id: lookup_dir.node().location.mountpoint_id,
+		inode: entry.inode,
+	};
+	// The node may already be in use through a hard link
+	let node = node::get_or_insert(location, ops)?;
 	// Create entry and insert in parent
 	let ent = Arc::new(Entry {
 		name: String::try_from(name)?,
 		parent: Some(lookup_dir.clone()),
 		children: Default::default(),
 		node: Some(node),
+		dcache_stamp: Default::default(),
 	})?;
 	children.insert(EntryChild(ent.clone()))?;
 	Ok(Some(ent))
 }
 
+/// Returns the directory designated by `..` in `dir`, without going above `root`.
+///
+/// If `dir` is not a descendant of `root`, which happens when a process's working directory is
+/// outside its root directory, the function returns `root` so that the resolution cannot escape
+/// it.
+///
+/// Since the parent of an entry never changes, a directory moved out of `root` by a concurrent
+/// rename is still seen as a descendant of it, through its previous location.
+fn parent_dir(dir: &Arc<Entry>, root: &Arc<Entry>) -> Arc<Entry> {
+	let root_loc = &root.node().location;
+	if dir.node().location == *root_loc {
+		return root.clone();
+	}
+	// Every entry is a descendant of the root of the VFS
+	if root.parent.is_some() {
+		let mut cur = dir.parent.as_ref();
+		loop {
+			match cur {
+				Some(ent) if ent.node().location == *root_loc => break,
+				Some(ent) => cur = ent.parent.as_ref(),
+				None => return root.clone(),
+			}
+		}
+	}
+	dir.parent.clone().unwrap_or_else(|| dir.clone())
+}
+
 /// Resolves the symbolic link `link` and returns the target.
 ///
 /// Arguments:
@@ -377,6 +541,7 @@ fn resolve_entry(lookup_dir: &Arc<Entry>, name: &[u8]) -> EResult<Option<Arc<Ent
 /// - `lookup_dir` is the directory from which the resolution of the target starts
 /// - `access_profile` is the access profile used for resolution
 /// - `symlink_rec` is the number of recursions so far
+/// - `walk` is the state of the resolution
 ///
 /// Symbolic links are followed recursively, including the last element of the target path.
 fn resolve_link(
@@ -385,11 +550,23 @@ fn resolve_link(
 	lookup_dir: Arc<Entry>,
 	access_profile: AccessProfile,
 	symlink_rec: usize,
+	walk: &mut Walk,
 ) -> EResult<Arc<Entry>> {
 	// If too many recursions occur, error
 	if unlikely(symlink_rec + 1 > SYMLOOP_MAX) {
 		return Err(errno!(ELOOP));
 	}
+	// If too many links have been followed, error
+	walk.links += 1;
+	if unlikely(walk.links > SYMLINK_TRAVERSAL_MAX) {
+		return Err(errno!(ELOOP));
+	}
+	// Magic links point directly to their target
+	let node = link.node();
+	if let Some(target) = node.ops.magic_link(&node.location)? {
+		walk.magic = true;
+		return Ok(target);
+	}
 	// Read link
 	let link_path = PathBuf::try_from(String::from(link.read_all()?))?;
 	// Resolve link
@@ -400,7 +577,7 @@ fn resolve_link(
 		create: false,
 		follow_link: true,
 	};
-	let resolved = resolve_path_impl(&link_path, &rs, symlink_rec + 1)?;
+	let resolved = resolve_path_impl(&link_path, &rs, symlink_rec + 1, walk)?;
 	let Resolved::Found(target) = resolved else {
 		// Because `create` is set to `false`
 		unreachable!();
@@ -410,11 +587,16 @@ fn resolve_link(
 
 /// Implementation of [`resolve_path`].
 ///
-/// `symlink_rec` is the number of recursions due to symbolic links resolution.
+/// Arguments:
+/// - `path` is the path to resolve
+/// - `settings` is the settings for the resolution
+/// - `symlink_rec` is the number of recursions due to symbolic links resolution
+/// - `walk` is the state of the resolution
 fn resolve_path_impl<'p>(
 	path: &'p Path,
 	settings: &ResolutionSettings,
 	symlink_rec: usize,
+	walk: &mut Walk,
 ) -> EResult<Resolved<'p>> {
 	// Get start lookup directory
 	let mut lookup_dir = match (path.is_absolute(), &settings.cwd) {
@@ -435,18 +617,20 @@ fn resolve_path_impl<'p>(
 		{
 			return Err(errno!(EACCES));
 		}
+		walk.search(&lookup_dir)?;
 		// Get the name of the next entry
 		let name = match comp {
 			Component::ParentDir => {
-				if let Some(parent) = &lookup_dir.parent {
-					lookup_dir = parent.clone();
-				}
+				lookup_dir = parent_dir(&lookup_dir, &settings.root);
 				continue;
 			}
 			Component::Normal(name) => name,
 			// Ignore
 			_ => continue,
 		};
+		if unlikely(name.len() > NAME_MAX) {
+			return Err(errno!(ENAMETOOLONG));
+		}
 		// Get entry
 		let entry = resolve_entry(&lookup_dir, name)?.ok_or_else(|| errno!(ENOENT))?;
 		match entry.get_type()? {
@@ -458,7 +642,12 @@ fn resolve_path_impl<'p>(
 					lookup_dir,
 					settings.access_profile,
 					symlink_rec,
+					walk,
 				)?;
+				// The target must be a directory for the resolution to continue through it
+				if lookup_dir.get_type()? != FileType::Directory {
+					return Err(errno!(ENOTDIR));
+				}
 			}
 			_ => return Err(errno!(ENOTDIR)),
 		}
@@ -471,13 +660,13 @@ fn resolve_path_impl<'p>(
 			return Ok(Resolved::Found(lookup_dir));
 		}
 		Component::ParentDir => {
-			if let Some(parent) = &lookup_dir.parent {
-				lookup_dir = parent.clone();
-			}
-			return Ok(Resolved::Found(lookup_dir));
+			return Ok(Resolved::Found(parent_dir(&lookup_dir, &settings.root)));
 		}
 		Component::Normal(name) => name,
 	};
+	if unlikely(name.len() > NAME_MAX) {
+		return Err(errno!(ENAMETOOLONG));
+	}
 	// Check lookup permission
 	let lookup_dir_stat = lookup_dir.stat()?;
 	if !settings
@@ -486,6 +675,7 @@ fn resolve_path_impl<'p>(
 	{
 		return Err(errno!(EACCES));
 	}
+	walk.search(&lookup_dir)?;
 	// Get entry
 	let Some(entry) = resolve_entry(&lookup_dir, name)? else {
 		// The file does not exist
@@ -506,6 +696,7 @@ fn resolve_path_impl<'p>(
 			lookup_dir,
 			settings.access_profile,
 			symlink_rec,
+			walk,
 		)?))
 	} else {
 		Ok(Resolved::Found(entry))
@@ -518,18 +709,60 @@ fn resolve_path_impl<'p>(
 /// - If the path is empty, the function returns [`errno::ENOMEM`].
 /// - If a component of the path cannot be accessed with the provided access profile, the function
 ///   returns [`errno::EACCES`].
-/// - If a component of the path (excluding the last) is not a directory nor a symbolic link, the
-///   function returns [`errno::ENOTDIR`].
+/// - If a component of the path (excluding the last) is not a directory nor a symbolic link to a
+///   directory, the function returns [`errno::ENOTDIR`].
 /// - If a component of the path (excluding the last) is a symbolic link and following them is
 ///   disabled, the function returns [`errno::ENOTDIR`].
+/// - If the path is longer than [`PATH_MAX`], or if a component of the path is longer than
+///   [`NAME_MAX`], the function returns [`errno::ENAMETOOLONG`].
 /// - If the resolution of the path requires more symbolic link indirections than [`SYMLOOP_MAX`],
-///   the function returns [`errno::ELOOP`].
+///   or if more than [`SYMLINK_TRAVERSAL_MAX`] symbolic links are followed in total, the function
+///   returns [`errno::ELOOP`].
 pub fn resolve_path<'p>(path: &'p Path, settings: &ResolutionSettings) -> EResult<Resolved<'p>> {
 	// Required by POSIX
 	if settings.cwd.is_none() && path.is_empty() {
 		return Err(errno!(ENOENT));
 	}
-	resolve_path_impl(path, settings, 0)
+	if unlikely(path.len() > PATH_MAX) {
+		return Err(errno!(ENAMETOOLONG));
+	}
+	resolve_path_impl(path, settings, 0, &mut Walk::default())
+}
+
+/// Like [`resolve_path`], but the file must exist and the function also returns the list of
+/// directories searched during the resolution, including those reached through symbolic links.
+///
+/// If a magic link has been followed, the list of directories is `None`, since the target of such
+/// a link does not depend on the content of these directories.
+#[allow(clippy::type_complexity)]
+fn resolve_path_traced(
+	path: &Path,
+	settings: &ResolutionSettings,
+) -> EResult<(Arc<Entry>, Option<Vec<Arc<Entry>>>)> {
+	if unlikely(path.len() > PATH_MAX) {
+		return Err(errno!(ENAMETOOLONG));
+	}
+	let mut walk = Walk {
+		links: 0,
+		dirs: Some(Vec::new()),
+		magic: false,
+	};
+	let settings = ResolutionSettings {
+		create: false,
+		..settings.clone()
+	};
+	let Resolved::Found(entry) = resolve_path_impl(path, &settings, 0, &mut walk)? else {
+		// Because `create` is set to `false`
+		unreachable!();
+	};
+	let dirs = walk.dirs.unwrap_or_default();
+	if walk.magic {
+		for dir in dirs {
+			Entry::release(dir)?;
+		}
+		return Ok((entry, None));
+	}
+	Ok((entry, Some(dirs)))
 }
 
 /// Like [`get_file_from_path`], but returns `None` is the file does not exist.
@@ -554,6 +787,22 @@ pub fn get_file_from_path(
 	get_file_from_path_opt(path, resolution_settings)?.ok_or_else(|| errno!(ENOENT))
 }
 
+/// Returns an entry for the node with the given `inode` on the mountpoint `mp`.
+///
+/// The returned entry is detached from the VFS tree: it has no name and no parent. This allows
+/// accessing a file without resolving a path, such as from a file handle.
+///
+/// If the node does not exist, the function returns [`errno::ENOENT`].
+pub fn get_detached_entry(mp: &MountPoint, inode: INode) -> EResult<Arc<Entry>> {
+	let location = FileLocation {
+		mountpoint_id: mp.id,
+		inode,
+	};
+	let ops = mp.fs.node_from_inode(inode)?;
+	let node = node::get_or_insert(location, ops)?;
+	Ok(Arc::new(Entry::from_node(node))?)
+}
+
 /// Creates a file, adds it to the VFS, then returns it.
 ///
 /// Arguments:
@@ -568,6 +817,9 @@ pub fn get_file_from_path(
 /// - `uid`
 /// - `gid`
 ///
+/// `nlink` is set to `2` for directories (the entry in `parent` and `.`) and `1` for other files.
+/// The links count of `parent` is incremented if the file is a directory (`..`).
+///
 /// `uid` and `gid` are set according to `ap`.
 ///
 /// The following errors can be returned:
@@ -576,6 +828,7 @@ pub fn get_file_from_path(
 /// - Permissions to create the file are not fulfilled for the given `ap`: [`errno::EACCES`]
 /// - `parent` is not a directory: [`errno::ENOTDIR`]
 /// - The file already exists: [`errno::EEXIST`]
+/// - The file is a directory and the links count of `parent` would overflow: [`errno::EMLINK`]
 ///
 /// Other errors can be returned depending on the underlying filesystem.
 pub fn create_file(
@@ -601,24 +854,39 @@ pub fn create_file(
 		ap.egid
 	};
 	stat.gid = gid;
+	let dir = stat.get_type() == Some(FileType::Directory);
+	stat.nlink = if dir { 2 } else { 1 };
+	// Check the `..` entry of the new directory can be counted before adding it, so that the
+	// entry is not left without its link on failure
+	if dir {
+		adjusted_nlink(parent_stat.nlink, 1)?;
+	}
 	// Add file to filesystem
 	let (inode, ops) = parent
 		.node()
 		.ops
 		.add_file(&parent.node().location, name, stat)?;
+	// The `..` entry of the new directory
+	if dir {
+		parent.node().ops.adjust_nlink(&parent.node().location, 1)?;
+	}
 	let location = FileLocation {
 		mountpoint_id: parent.node().location.mountpoint_id,
 		inode,
 	};
 	let node = node::get_or_insert(location, ops)?;
-	// Create entry and insert it in parent
+	// Create entry and insert it in parent, in place of the negative entry if any
 	let entry = Arc::new(Entry {
 		name: String::try_from(name)?,
 		parent: Some(parent.clone()),
 		children: Default::default(),
 		node: Some(node),
+		dcache_stamp: Default::default(),
 	})?;
+	dcache::invalidate(&parent, name)?;
 	parent.children.lock().insert(EntryChild(entry.clone()))?;
+	notify::notify(&parent.node().location, dir_flag(IN_CREATE, dir), 0, name);
+	path_cache::invalidate_dir(&parent.node().location)?;
 	Ok(entry)
 }
 
@@ -662,7 +930,62 @@ pub fn link(parent: &Entry, name: &[u8], target: &Entry, ap: &AccessProfile) ->
 		.node()
 		.ops
 		.link(&parent.node().location, name, target.node().location.inode)?;
-	Ok(())
+	target.node().ops.adjust_nlink(&target.node().location, 1)?;
+	dcache::invalidate(parent, name)?;
+	notify::notify(&parent.node().location, IN_CREATE, 0, name);
+	notify::notify(&target.node().location, IN_ATTRIB, 0, b"");
+	path_cache::invalidate_dir(&parent.node().location)
+}
+
+/// Updates links counts after the removal of the link to the file at `loc` from `parent`.
+///
+/// Arguments:
+/// - `parent` is the parent directory of the removed link
+/// - `loc` is the location of the file the link pointed to
+/// - `ops` is the handle to perform operations on the file
+/// - `dir` tells whether the file is a directory
+///
+/// On success, the function returns the remaining number of links to the file.
+fn unlink_nlink(parent: &Entry, loc: &FileLocation, ops: &dyn NodeOps, dir: bool) -> EResult<u16> {
+	if dir {
+		// The entry in the parent and `.`
+		let nlink = ops.adjust_nlink(loc, -2)?;
+		// `..`
+		parent
+			.node()
+			.ops
+			.adjust_nlink(&parent.node().location, -1)?;
+		Ok(nlink)
+	} else {
+		ops.adjust_nlink(loc, -1)
+	}
+}
+
+/// Returns `mask` with [`IN_ISDIR`] set if `dir` is `true`.
+fn dir_flag(mask: u32, dir: bool) -> u32 {
+	if dir {
+		mask | IN_ISDIR
+	} else {
+		mask
+	}
+}
+
+/// Reports the removal of the link `name` from `parent` to inotify watches.
+///
+/// Arguments:
+/// - `parent` is the parent directory of the removed link
+/// - `name` is the name of the removed link
+/// - `loc` is the location of the file the link pointed to
+/// - `dir` tells whether the file is a directory
+/// - `nlink` is the remaining number of links to the file
+fn notify_unlink(parent: &Entry, name: &[u8], loc: &FileLocation, dir: bool, nlink: u16) {
+	notify::notify(&parent.node().location, dir_flag(IN_DELETE, dir), 0, name);
+	let mask = if nlink == 0 {
+		IN_DELETE_SELF
+	} else {
+		IN_ATTRIB
+	};
+	notify::notify(loc, dir_flag(mask, dir), 0, b"");
 }
 
 /// Removes a hard link to a file.
@@ -692,6 +1015,8 @@ pub fn unlink(parent: Arc<Entry>, name: &[u8], ap: &AccessProfile) -> EResult<()
 	// Lock now to avoid race conditions
 	let mut children = parent.children.lock();
 	match children.get(name) {
+		// The file is known not to exist
+		Some(EntryChild(entry)) if entry.node.is_none() => Err(errno!(ENOENT)),
 		// The entry is in cache
 		Some(EntryChild(entry)) => {
 			// If the file to remove is a mountpoint, error
@@ -706,10 +1031,22 @@ pub fn unlink(parent: Arc<Entry>, name: &[u8], ap: &AccessProfile) -> EResult<()
 			}
 			// Remove link from filesystem
 			parent.node().ops.unlink(&parent.node().location, name)?;
+			let dir = stat.get_type() == Some(FileType::Directory);
+			let nlink = unlink_nlink(&parent, &entry.node().location, &*entry.node().ops, dir)?;
+			notify_unlink(&parent, name, &entry.node().location, dir, nlink);
+			// If the node is still in use, its removal is deferred until its last use ends
+			if nlink == 0 {
+				orphan::defer(&entry.node().location)?;
+			}
 			// Remove link from cache
 			let EntryChild(ent) = children.remove(name).unwrap();
 			drop(children);
-			Entry::release(ent)
+			// Release cached resolutions first, so that they do not keep the entry alive
+			path_cache::invalidate_dir(&parent.node().location)?;
+			if dir {
+				dcache::purge_children(&ent)?;
+			}
+			dcache::forget(ent)
 		}
 		// The entry is not in cache
 		None => {
@@ -731,11 +1068,508 @@ pub fn unlink(parent: Arc<Entry>, name: &[u8], ap: &AccessProfile) -> EResult<()
 			}
 			// Remove link from filesystem
 			parent.node().ops.unlink(&parent.node().location, name)?;
+			let dir = stat.get_type() == Some(FileType::Directory);
+			let nlink = unlink_nlink(&parent, &loc, &*ops, dir)?;
+			notify_unlink(&parent, name, &loc, dir, nlink);
+			// The node may be in use through another entry that has been unlinked before
+			if nlink == 0 {
+				orphan::defer(&loc)?;
+			}
+			path_cache::invalidate_dir(&parent.node().location)?;
 			node::try_remove(&loc, &*ops)
 		}
 	}
 }
 
+/// Serializes renames between different directories.
+///
+/// Without it, two concurrent renames could each move a directory into the other, creating a cycle
+/// that none of them would detect.
+static RENAME_LOCK: Mutex<()> = Mutex::new(());
+
+/// Tells whether `ent` is `dir` or one of its descendants.
+fn is_descendant(ent: &Arc<Entry>, dir: &Entry) -> bool {
+	let mut cur = Some(ent);
+	while let Some(ent) = cur {
+		if ent.node().location == dir.node().location {
+			return true;
+		}
+		cur = ent.parent.as_ref();
+	}
+	false
+}
+
+/// Moves a file to another location.
+///
+/// Arguments:
+/// - `old` is the file to move
+/// - `new_parent` is the directory in which the file is moved
+/// - `new_name` is the new name of the file
+/// - `flags` is a combination of [`RENAME_NOREPLACE`] and [`RENAME_EXCHANGE`]
+/// - `ap` is the access profile to check permissions
+///
+/// If a file named `new_name` already exists in `new_parent`, it is atomically replaced, or
+/// exchanged with `old` if [`RENAME_EXCHANGE`] is set.
+///
+/// The following errors can be returned:
+/// - The filesystem is read-only: [`errno::EROFS`]
+/// - I/O failed: [`errno::EIO`]
+/// - Permissions to move the file are not fulfilled for the given `ap`: [`errno::EACCES`]
+/// - `old` or the file to replace is the root of a mountpoint: [`errno::EBUSY`]
+/// - `old` and `new_parent` are not on the same mountpoint: [`errno::EXDEV`]
+/// - A directory would be moved into itself or one of its descendants, or both flags are set:
+///   [`errno::EINVAL`]
+/// - A file named `new_name` already exists in `new_parent` and [`RENAME_NOREPLACE`] is set:
+///   [`errno::EEXIST`]
+/// - No file named `new_name` exists in `new_parent` and [`RENAME_EXCHANGE`] is set:
+///   [`errno::ENOENT`]
+/// - A directory would replace a file which is not a directory: [`errno::ENOTDIR`]
+/// - A file which is not a directory would replace a directory: [`errno::EISDIR`]
+/// - The directory to replace is not empty: [`errno::ENOTEMPTY`]
+/// - A directory is moved and the links count of its new parent would overflow: [`errno::EMLINK`]
+///
+/// Other errors can be returned depending on the underlying filesystem.
+pub fn rename(
+	old: Arc<Entry>,
+	new_parent: Arc<Entry>,
+	new_name: &[u8],
+	flags: u32,
+	ap: &AccessProfile,
+) -> EResult<()> {
+	let exchange = flags & RENAME_EXCHANGE != 0;
+	if exchange && flags & RENAME_NOREPLACE != 0 {
+		return Err(errno!(EINVAL));
+	}
+	// The root of a mountpoint cannot be moved
+	if old.get_mountpoint().is_some() {
+		return Err(errno!(EBUSY));
+	}
+	let old_parent = old.parent.clone().ok_or_else(|| errno!(EBUSY))?;
+	let old_parent_stat = old_parent.stat()?;
+	let new_parent_stat = new_parent.stat()?;
+	// Validation
+	if new_parent_stat.get_type() != Some(FileType::Directory) {
+		return Err(errno!(ENOTDIR));
+	}
+	if new_name == b"." || new_name == b".." {
+		return Err(errno!(EINVAL));
+	}
+	if !ap.can_write_directory(&old_parent_stat) || !ap.can_write_directory(&new_parent_stat) {
+		return Err(errno!(EACCES));
+	}
+	// Check the source and destination are both on the same mountpoint
+	if new_parent.node().location.mountpoint_id != old.node().location.mountpoint_id {
+		return Err(errno!(EXDEV));
+	}
+	let stat = old.stat()?;
+	// Check permission
+	let has_sticky_bit = old_parent_stat.mode & S_ISVTX != 0;
+	if has_sticky_bit && ap.euid != stat.uid && ap.euid != old_parent_stat.uid {
+		return Err(errno!(EACCES));
+	}
+	let dir = stat.get_type() == Some(FileType::Directory);
+	let cross_dir = old_parent.node().location != new_parent.node().location;
+	// Held until the end so that the checks against cycles remain valid
+	let _guard = cross_dir.then(|| RENAME_LOCK.lock());
+	// A directory cannot be moved into itself or one of its descendants
+	if dir && is_descendant(&new_parent, &old) {
+		return Err(errno!(EINVAL));
+	}
+	let target = resolve_entry(&new_parent, new_name)?;
+	let mut target_dir = false;
+	match &target {
+		Some(_) if flags & RENAME_NOREPLACE != 0 => return Err(errno!(EEXIST)),
+		// Both names are links to the same file: nothing to do
+		Some(target) if target.node().location == old.node().location => return Ok(()),
+		Some(target) => {
+			// If the file to replace is a mountpoint, error
+			if new_parent.node().location.mountpoint_id != target.node().location.mountpoint_id {
+				return Err(errno!(EBUSY));
+			}
+			let target_stat = target.stat()?;
+			// Check permission
+			let has_sticky_bit = new_parent_stat.mode & S_ISVTX != 0;
+			if has_sticky_bit && ap.euid != target_stat.uid && ap.euid != new_parent_stat.uid {
+				return Err(errno!(EACCES));
+			}
+			target_dir = target_stat.get_type() == Some(FileType::Directory);
+			if exchange {
+				// The target is moved to `old_parent`, with the same restriction as above
+				if target_dir && is_descendant(&old_parent, target) {
+					return Err(errno!(EINVAL));
+				}
+			} else if dir && !target_dir {
+				return Err(errno!(ENOTDIR));
+			} else if !dir && target_dir {
+				return Err(errno!(EISDIR));
+			}
+		}
+		None if exchange => return Err(errno!(ENOENT)),
+		None => {}
+	}
+	// The links counts of parents for the `..` entries of moved and replaced directories.
+	// Deltas are applied at once so that counts never mismatch the directories' content
+	let mut old_parent_delta = 0;
+	let mut new_parent_delta = 0;
+	if cross_dir && dir {
+		old_parent_delta -= 1;
+		new_parent_delta += 1;
+	}
+	if cross_dir && exchange && target_dir {
+		old_parent_delta += 1;
+		new_parent_delta -= 1;
+	}
+	if !exchange && target_dir {
+		new_parent_delta -= 1;
+	}
+	// Check the counts can be updated before moving entries, so that the directories are not
+	// left with mismatching counts on failure
+	for (stat, delta) in [
+		(&old_parent_stat, old_parent_delta),
+		(&new_parent_stat, new_parent_delta),
+	] {
+		if delta > 0 {
+			adjusted_nlink(stat.nlink, delta)?;
+		}
+	}
+	old_parent.node().ops.rename(
+		&old_parent.node().location,
+		&old.name,
+		&new_parent.node().location,
+		new_name,
+		flags,
+	)?;
+	for (parent, delta) in [
+		(&old_parent, old_parent_delta),
+		(&new_parent, new_parent_delta),
+	] {
+		if delta != 0 {
+			parent
+				.node()
+				.ops
+				.adjust_nlink(&parent.node().location, delta)?;
+		}
+	}
+	let cookie = notify::next_cookie();
+	notify::notify(
+		&old_parent.node().location,
+		dir_flag(IN_MOVED_FROM, dir),
+		cookie,
+		&old.name,
+	);
+	notify::notify(
+		&new_parent.node().location,
+		dir_flag(IN_MOVED_TO, dir),
+		cookie,
+		new_name,
+	);
+	notify::notify(&old.node().location, dir_flag(IN_MOVE_SELF, dir), 0, b"");
+	if let Some(target) = &target {
+		let loc = &target.node().location;
+		if exchange {
+			let cookie = notify::next_cookie();
+			notify::notify(
+				&new_parent.node().location,
+				dir_flag(IN_MOVED_FROM, target_dir),
+				cookie,
+				new_name,
+			);
+			notify::notify(
+				&old_parent.node().location,
+				dir_flag(IN_MOVED_TO, target_dir),
+				cookie,
+				&old.name,
+			);
+			notify::notify(loc, dir_flag(IN_MOVE_SELF, target_dir), 0, b"");
+		} else {
+			// The replaced file loses its link, and its `.` entry if it is a directory
+			let delta = if target_dir { -2 } else { -1 };
+			let nlink = target.node().ops.adjust_nlink(loc, delta)?;
+			let mask = if nlink == 0 {
+				IN_DELETE_SELF
+			} else {
+				IN_ATTRIB
+			};
+			notify::notify(loc, dir_flag(mask, target_dir), 0, b"");
+			// If the node is still in use, its removal is deferred until its last use ends
+			if nlink == 0 {
+				orphan::defer(loc)?;
+			}
+		}
+	}
+	path_cache::invalidate_dir(&old_parent.node().location)?;
+	path_cache::invalidate_dir(&new_parent.node().location)?;
+	// Remove both entries from cache. They are looked up again at their new location on next
+	// access
+	drop(target);
+	dcache::invalidate(&new_parent, new_name)?;
+	let ent = old_parent.children.lock().remove(&*old.name);
+	drop(old);
+	if let Some(EntryChild(ent)) = ent {
+		dcache::forget(ent)?;
+	}
+	Ok(())
+}
+
+/// Modifies the status of the file `ent`, then updates its timestamps.
+///
+/// Arguments:
+/// - `ent` is the file to modify
+/// - `set` is the set of attributes to modify
+/// - `content` tells whether the content of the file has been modified
+///
+/// `ctime` is set to the current timestamp, as well as `mtime` if `content` is `true`. Timestamps
+/// that are already specified in `set` are left untouched.
+///
+/// This function is the only place where timestamps should be updated after a modification of a
+/// file. It also reports the modification to inotify watches, with [`IN_MODIFY`] if `content` is
+/// `true`, or [`IN_ATTRIB`] otherwise.
+pub fn update_stat(ent: &Entry, mut set: StatSet, content: bool) -> EResult<()> {
+	let ts = current_time(CLOCK_REALTIME, TimestampScale::Second)?;
+	set.ctime.get_or_insert(ts);
+	if content {
+		set.mtime.get_or_insert(ts);
+	}
+	ent.node().ops.set_stat(&ent.node().location, set)?;
+	let dir = matches!(ent.get_type(), Ok(FileType::Directory));
+	let mask = dir_flag(if content { IN_MODIFY } else { IN_ATTRIB }, dir);
+	notify::notify(&ent.node().location, mask, 0, b"");
+	if let Some(parent) = &ent.parent {
+		if ent.is_unlinked() {
+			notify::notify_unlinked(&parent.node().location, mask, &ent.name);
+		} else {
+			notify::notify(&parent.node().location, mask, 0, &ent.name);
+		}
+	}
+	Ok(())
+}
+
+/// Returns the mode of the file with the given status `stat` after the clearing of the SUID and
+/// SGID bits.
+///
+/// These bits are cleared when the file is modified or when its owner changes, so that it cannot
+/// be used to gain the privileges of the new owner or content. They are left untouched if `ap` is
+/// privileged, or if the file is not a regular file.
+///
+/// The SGID bit is cleared only if the group execute permission is set. Otherwise, it does not
+/// give any privilege.
+///
+/// If no bit is to be cleared, the function returns `None`.
+fn clear_suid(stat: &Stat, ap: &AccessProfile) -> Option<Mode> {
+	if ap.is_privileged() || stat.get_type() != Some(FileType::Regular) {
+		return None;
+	}
+	let mut clear = S_ISUID;
+	if stat.mode & S_IXGRP != 0 {
+		clear |= S_ISGID;
+	}
+	(stat.mode & clear != 0).then_some(stat.mode & 0o7777 & !clear)
+}
+
+/// Changes the permissions of the file `ent`.
+///
+/// Arguments:
+/// - `ent` is the file
+/// - `mode` is the new set of permissions
+/// - `ap` is the access profile to check permissions
+///
+/// If `ap` is not privileged and not a member of the group owning the file, the SGID bit is
+/// cleared.
+///
+/// The following errors can be returned:
+/// - The filesystem is read-only: [`errno::EROFS`]
+/// - `ap` is not allowed to change the permissions of the file: [`errno::EPERM`]
+pub fn set_mode(ent: &Entry, mut mode: Mode, ap: &AccessProfile) -> EResult<()> {
+	let stat = ent.stat()?;
+	if !ap.can_set_file_permissions(&stat) {
+		return Err(errno!(EPERM));
+	}
+	mode &= 0o7777;
+	if !ap.is_privileged() && !ap.is_in_group(stat.gid) {
+		mode &= !S_ISGID;
+	}
+	update_stat(
+		ent,
+		StatSet {
+			mode: Some(mode),
+			..Default::default()
+		},
+		false,
+	)
+}
+
+/// Changes the owner of the file `ent`.
+///
+/// Arguments:
+/// - `ent` is the file
+/// - `uid` is the new owner user ID. If `None`, the owner user is left unchanged
+/// - `gid` is the new owner group ID. If `None`, the owner group is left unchanged
+/// - `ap` is the access profile to check permissions
+///
+/// An unprivileged agent can only change the group of files it owns, to a group it is a member
+/// of. If `ap` is not privileged, the SUID and SGID bits are cleared.
+///
+/// The following errors can be returned:
+/// - The filesystem is read-only: [`errno::EROFS`]
+/// - `ap` is not allowed to change the owner of the file: [`errno::EPERM`]
+pub fn set_owner(
+	ent: &Entry,
+	uid: Option<Uid>,
+	gid: Option<Gid>,
+	ap: &AccessProfile,
+) -> EResult<()> {
+	let stat = ent.stat()?;
+	if !ap.is_privileged() {
+		let uid_ok = uid.map(|uid| uid == stat.uid).unwrap_or(true);
+		let gid_ok = gid
+			.map(|gid| gid == stat.gid || ap.is_in_group(gid))
+			.unwrap_or(true);
+		if ap.euid != stat.uid || !uid_ok || !gid_ok {
+			return Err(errno!(EPERM));
+		}
+	}
+	update_stat(
+		ent,
+		StatSet {
+			mode: clear_suid(&stat, ap),
+			uid,
+			gid,
+			..Default::default()
+		},
+		false,
+	)
+}
+
+/// Updates the status of the file `ent` after a modification of its content.
+///
+/// Arguments:
+/// - `ent` is the file
+/// - `ap` is the access profile of the agent which modified the file
+///
+/// The function updates timestamps and clears the SUID and SGID bits if necessary.
+pub fn content_modified(ent: &Entry, ap: &AccessProfile) -> EResult<()> {
+	let stat = ent.stat()?;
+	update_stat(
+		ent,
+		StatSet {
+			mode: clear_suid(&stat, ap),
+			..Default::default()
+		},
+		true,
+	)
+}
+
+/// Checks whether `ap` can access the extended attribute `name` of the file with status `stat`.
+///
+/// `write` tells whether the attribute is to be modified.
+///
+/// Attributes in the `trusted.` namespace are reserved to privileged agents, as well as
+/// modifications of attributes in the `security.` namespace. Attributes in the `user.` namespace
+/// are subject to the file's permissions, and are available only on regular files and
+/// directories. Other namespaces are left to the filesystem.
+fn check_xattr_access(stat: &Stat, name: &[u8], write: bool, ap: &AccessProfile) -> EResult<()> {
+	// Hide the existence of attributes that cannot be read
+	let denied = || {
+		if write {
+			errno!(EPERM)
+		} else {
+			errno!(ENODATA)
+		}
+	};
+	if name.starts_with(b"trusted.") {
+		if !ap.is_privileged() {
+			return Err(denied());
+		}
+	} else if name.starts_with(b"security.") {
+		if write && !ap.is_privileged() {
+			return Err(errno!(EPERM));
+		}
+	} else if name.starts_with(b"user.") {
+		if !matches!(
+			stat.get_type(),
+			Some(FileType::Regular | FileType::Directory)
+		) {
+			return Err(denied());
+		}
+		let allowed = if write {
+			ap.can_write_file(stat)
+		} else {
+			ap.can_read_file(stat)
+		};
+		if !allowed {
+			return Err(errno!(EACCES));
+		}
+	}
+	Ok(())
+}
+
+/// Returns the value of the extended attribute `name` of the file `ent`.
+///
+/// Arguments:
+/// - `ent` is the file
+/// - `name` is the name of the attribute, including the namespace prefix
+/// - `ap` is the access profile to check permissions
+pub fn get_xattr(ent: &Entry, name: &[u8], ap: &AccessProfile) -> EResult<Vec<u8>> {
+	let stat = ent.stat()?;
+	check_xattr_access(&stat, name, false, ap)?;
+	ent.node().ops.get_xattr(&ent.node().location, name)
+}
+
+/// Returns the names of the extended attributes of the file `ent`, each followed by a nul byte.
+///
+/// Attributes in the `trusted.` namespace are omitted if `ap` is not privileged.
+pub fn list_xattr(ent: &Entry, ap: &AccessProfile) -> EResult<Vec<u8>> {
+	let list = ent.node().ops.list_xattr(&ent.node().location)?;
+	if ap.is_privileged() {
+		return Ok(list);
+	}
+	let mut filtered = Vec::new();
+	for name in list.split(|c| *c == 0) {
+		if name.is_empty() || name.starts_with(b"trusted.") {
+			continue;
+		}
+		filtered.extend_from_slice(name)?;
+		filtered.push(0)?;
+	}
+	Ok(filtered)
+}
+
+/// Sets the value of the extended attribute `name` of the file `ent`.
+///
+/// Arguments:
+/// - `ent` is the file
+/// - `name` is the name of the attribute, including the namespace prefix
+/// - `value` is the new value of the attribute
+/// - `flags` is a combination of [`XATTR_CREATE`] and [`XATTR_REPLACE`]
+/// - `ap` is the access profile to check permissions
+///
+/// [`XATTR_CREATE`]: super::fs::XATTR_CREATE
+/// [`XATTR_REPLACE`]: super::fs::XATTR_REPLACE
+pub fn set_xattr(
+	ent: &Entry,
+	name: &[u8],
+	value: &[u8],
+	flags: c_int,
+	ap: &AccessProfile,
+) -> EResult<()> {
+	let stat = ent.stat()?;
+	check_xattr_access(&stat, name, true, ap)?;
+	ent.node()
+		.ops
+		.set_xattr(&ent.node().location, name, value, flags)?;
+	update_stat(ent, StatSet::default(), false)
+}
+
+/// Removes the extended attribute `name` of the file `ent`.
+///
+/// `ap` is the access profile to check permissions.
+pub fn remove_xattr(ent: &Entry, name: &[u8], ap: &AccessProfile) -> EResult<()> {
+	let stat = ent.stat()?;
+	check_xattr_access(&stat, name, true, ap)?;
+	ent.node().ops.remove_xattr(&ent.node().location, name)?;
+	update_stat(ent, StatSet::default(), false)
+}
+
 /// Helper function to remove a hard link from a given `path`.
 pub fn unlink_from_path(path: &Path, resolution_settings: &ResolutionSettings) -> EResult<()> {
 	let file_name = path.file_name().ok_or_else(|| errno!(ENOENT))?;
@@ -769,10 +1603,28 @@ impl super::FileOps for FileOps {
 			.ok_or_else(|| errno!(ENODEV))?
 			.get_io()
 			.poll(mask),
-			None => todo!(),
+			None => {
+				let node = file.vfs_entry.as_ref().unwrap().node();
+				node.ops.poll(&node.location, mask)
+			}
 		}
 	}
 
+	fn poll_wait(&self, file: &File) -> EResult<bool> {
+		let stat = self.get_stat(file)?;
+		let Some(dev_type) = stat.get_type().and_then(FileType::to_device_type) else {
+			return Ok(false);
+		};
+		device::get(&DeviceID {
+			dev_type,
+			major: stat.dev_major,
+			minor: stat.dev_minor,
+		})
+		.ok_or_else(|| errno!(ENODEV))?
+		.get_io()
+		.poll_wait()
+	}
+
 	fn ioctl(&self, file: &File, request: Request, argp: *const c_void) -> EResult<u32> {
 		let stat = self.get_stat(file)?;
 		let dev_type = stat
@@ -791,29 +1643,35 @@ impl super::FileOps for FileOps {
 
 	fn read(&self, file: &File, off: u64, buf: &mut [u8]) -> EResult<usize> {
 		if unlikely(!file.can_read()) {
-			return Err(errno!(EACCES));
+			return Err(errno!(EBADF));
 		}
 		let stat = self.get_stat(file)?;
 		let dev_type = stat.get_type().and_then(FileType::to_device_type);
 		match dev_type {
-			Some(dev_type) => device::get(&DeviceID {
-				dev_type,
-				major: stat.dev_major,
-				minor: stat.dev_minor,
-			})
-			.ok_or_else(|| errno!(ENODEV))?
-			.get_io()
-			.read_bytes(off, buf),
+			Some(dev_type) => {
+				let dev = device::get(&DeviceID {
+					dev_type,
+					major: stat.dev_major,
+					minor: stat.dev_minor,
+				})
+				.ok_or_else(|| errno!(ENODEV))?;
+				let io = dev.get_io();
+				if file.get_flags() & O_NONBLOCK != 0 {
+					io.read_bytes_nonblock(off, buf)
+				} else {
+					io.read_bytes(off, buf)
+				}
+			}
 			None => {
 				let node = file.vfs_entry.as_ref().unwrap().node();
-				node.ops.read_content(&node.location, off, buf)
+				page_cache::read(node, off, buf)
 			}
 		}
 	}
 
 	fn write(&self, file: &File, off: u64, buf: &[u8]) -> EResult<usize> {
 		if unlikely(!file.can_write()) {
-			return Err(errno!(EACCES));
+			return Err(errno!(EBADF));
 		}
 		let stat = self.get_stat(file)?;
 		let dev_type = stat.get_type().and_then(FileType::to_device_type);
@@ -828,7 +1686,7 @@ impl super::FileOps for FileOps {
 			.write_bytes(off, buf),
 			None => {
 				let node = file.vfs_entry.as_ref().unwrap().node();
-				node.ops.write_content(&node.location, off, buf)
+				page_cache::write(node, off, buf)
 			}
 		}
 	}
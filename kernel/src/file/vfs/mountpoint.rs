@@ -23,21 +23,22 @@ use crate::{
 	device::{DeviceID, DeviceType},
 	file::{
 		fs,
-		fs::{Filesystem, FilesystemType},
-		vfs,
-		vfs::{node, node::Node, EntryChild, ResolutionSettings},
+		fs::{Filesystem, FilesystemType, Statfs},
+		page_cache, vfs,
+		vfs::{dcache, node, node::Node, orphan, path_cache, EntryChild, ResolutionSettings},
 		FileLocation, FileType,
 	},
 };
-use core::fmt;
+use core::{fmt, ptr};
 use utils::{
 	collections::{
 		hashmap::HashMap,
 		path::{Path, PathBuf},
 		string::String,
+		vec::Vec,
 	},
 	errno,
-	errno::{AllocResult, EResult},
+	errno::{AllocResult, CollectResult, EResult},
 	lock::Mutex,
 	ptr::arc::Arc,
 	TryClone,
@@ -82,32 +83,22 @@ pub enum MountSource {
 }
 
 impl MountSource {
-	/// Creates a mount source from a dummy string.
+	/// Creates a mount source from the device file at `path`.
 	///
-	/// `string` might be either a kernfs name or an absolute path.
-	pub fn new(string: &[u8]) -> EResult<Self> {
-		let path = Path::new(string)?;
-		let result = vfs::get_file_from_path(path, &ResolutionSettings::kernel_follow());
-		match result {
-			Ok(file) => {
-				let stat = file.stat()?;
-				match stat.get_type() {
-					Some(FileType::BlockDevice) => Ok(Self::Device(DeviceID {
-						dev_type: DeviceType::Block,
-						major: stat.dev_major,
-						minor: stat.dev_minor,
-					})),
-					Some(FileType::CharDevice) => Ok(Self::Device(DeviceID {
-						dev_type: DeviceType::Char,
-						major: stat.dev_major,
-						minor: stat.dev_minor,
-					})),
-					_ => Err(errno!(EINVAL)),
-				}
-			}
-			Err(err) if err == errno!(ENOENT) => Ok(Self::NoDev(String::try_from(string)?)),
-			Err(err) => Err(err),
+	/// `rs` is the settings used to resolve the path.
+	///
+	/// If the file is not a block device, the function returns [`errno::ENOTBLK`].
+	pub fn from_path(path: &Path, rs: &ResolutionSettings) -> EResult<Self> {
+		let file = vfs::get_file_from_path(path, rs)?;
+		let stat = file.stat()?;
+		if stat.get_type() != Some(FileType::BlockDevice) {
+			return Err(errno!(ENOTBLK));
 		}
+		Ok(Self::Device(DeviceID {
+			dev_type: DeviceType::Block,
+			major: stat.dev_major,
+			minor: stat.dev_minor,
+		}))
 	}
 }
 
@@ -144,11 +135,14 @@ static FILESYSTEMS: Mutex<HashMap<DeviceID, Arc<dyn Filesystem>>> = Mutex::new(H
 /// - `fs_type` is the filesystem type. If `None`, the function tries to detect it automatically.
 /// - `target_path` is the path at which the filesystem is to be mounted.
 /// - `readonly` tells whether the filesystem is mount in readonly.
+/// - `data` is the string of filesystem-specific options. It is ignored if the filesystem is
+///   already loaded.
 fn get_fs(
 	source: &MountSource,
 	fs_type: Option<Arc<dyn FilesystemType>>,
 	target_path: PathBuf,
 	readonly: bool,
+	data: &[u8],
 ) -> EResult<Arc<dyn Filesystem>> {
 	match source {
 		MountSource::Device(dev_id) => {
@@ -163,7 +157,12 @@ fn get_fs(
 				Some(f) => f,
 				None => fs::detect(Arc::as_ref(dev.get_io()))?,
 			};
-			let fs = fs_type.load_filesystem(Some(dev.get_io().clone()), target_path, readonly)?;
+			let fs = fs_type.load_filesystem(
+				Some(dev.get_io().clone()),
+				target_path,
+				readonly,
+				data,
+			)?;
 			// Insert new filesystem into filesystems list
 			filesystems.insert(*dev_id, fs.clone())?;
 			Ok(fs)
@@ -173,11 +172,17 @@ fn get_fs(
 				Some(f) => f,
 				None => fs::get_type(name).ok_or_else(|| errno!(ENODEV))?,
 			};
-			fs_type.load_filesystem(None, target_path, readonly)
+			fs_type.load_filesystem(None, target_path, readonly, data)
 		}
 	}
 }
 
+/// Tells whether a filesystem is loaded from the device with the given ID, meaning the device is
+/// used by at least one mountpoint.
+pub fn is_device_used(dev_id: &DeviceID) -> bool {
+	FILESYSTEMS.lock().contains_key(dev_id)
+}
+
 /// A mount point, allowing to attach a filesystem to a directory on the VFS.
 #[derive(Debug)]
 pub struct MountPoint {
@@ -203,6 +208,37 @@ impl MountPoint {
 			inode: self.fs.get_root_inode(),
 		}
 	}
+
+	/// Returns the ID of the mountpoint on which this mountpoint is mounted.
+	///
+	/// The root mountpoint is its own parent.
+	pub fn get_parent_id(&self) -> u32 {
+		self.root_entry
+			.parent
+			.as_ref()
+			.map(|parent| parent.node().location.mountpoint_id)
+			.unwrap_or(self.id)
+	}
+
+	/// Returns the effective mount flags of the mountpoint.
+	///
+	/// This includes [`FLAG_RDONLY`] if the filesystem itself has become read-only, for example
+	/// after an error.
+	pub fn get_flags(&self) -> u32 {
+		if self.fs.is_readonly() {
+			self.flags | FLAG_RDONLY
+		} else {
+			self.flags
+		}
+	}
+
+	/// Returns the statistics of the mounted filesystem, with `f_flags` reflecting the
+	/// effective mount flags.
+	pub fn get_stat(&self) -> EResult<Statfs> {
+		let mut stat = self.fs.get_stat()?;
+		stat.set_mount_flags(self.get_flags());
+		Ok(stat)
+	}
 }
 
 impl Drop for MountPoint {
@@ -231,16 +267,16 @@ pub static MOUNT_POINTS: Mutex<HashMap<u32, Arc<MountPoint>>> = Mutex::new(HashM
 
 /// Creates the root mountpoint and returns the newly created root entry of the VFS.
 pub(crate) fn create_root(source: MountSource) -> EResult<Arc<vfs::Entry>> {
-	let fs = get_fs(&source, None, PathBuf::root()?, false)?;
+	let fs = get_fs(&source, None, PathBuf::root()?, false, b"")?;
 	// Get filesystem root node
 	let root_inode = fs.get_root_inode();
-	let node = node::insert(Node {
-		location: FileLocation {
+	let node = node::insert(Node::new(
+		FileLocation {
 			mountpoint_id: 0,
 			inode: root_inode,
 		},
-		ops: fs.node_from_inode(root_inode)?,
-	})?;
+		fs.node_from_inode(root_inode)?,
+	))?;
 	// Create an entry for the root of the mountpoint
 	let root_entry = Arc::new(vfs::Entry::from_node(node))?;
 	// Create mountpoint
@@ -265,6 +301,7 @@ pub(crate) fn create_root(source: MountSource) -> EResult<Arc<vfs::Entry>> {
 /// - `source` is the source of the mountpoint
 /// - `fs_type` is the filesystem type. If `None`, the function tries to detect it automatically
 /// - `flags` are the mount flags
+/// - `data` is the string of filesystem-specific options, empty if none
 /// - `target` is the target directory
 ///
 /// The function returns the ID of the newly created mountpoint.
@@ -272,30 +309,38 @@ pub fn create(
 	source: MountSource,
 	fs_type: Option<Arc<dyn FilesystemType>>,
 	flags: u32,
+	data: &[u8],
 	target: Arc<vfs::Entry>,
 ) -> EResult<()> {
 	// Get filesystem
 	let target_path = vfs::Entry::get_path(&target)?;
-	let fs = get_fs(&source, fs_type, target_path, flags & FLAG_RDONLY != 0)?;
+	let fs = get_fs(
+		&source,
+		fs_type,
+		target_path,
+		flags & FLAG_RDONLY != 0,
+		data,
+	)?;
 	let mut mps = MOUNT_POINTS.lock();
 	// Mountpoint ID allocation
 	// TODO improve
 	let id = mps.iter().map(|(i, _)| *i + 1).max().unwrap_or(0);
 	// Get filesystem root node
 	let root_inode = fs.get_root_inode();
-	let node = node::insert(Node {
-		location: FileLocation {
+	let node = node::insert(Node::new(
+		FileLocation {
 			mountpoint_id: id,
 			inode: root_inode,
 		},
-		ops: fs.node_from_inode(root_inode)?,
-	})?;
+		fs.node_from_inode(root_inode)?,
+	))?;
 	// Create an entry for the root of the mountpoint
 	let root_entry = Arc::new(vfs::Entry {
 		name: target.name.try_clone()?,
 		parent: target.parent.clone(),
 		children: Default::default(),
 		node: Some(node),
+		dcache_stamp: Default::default(),
 	})?;
 	// Create mountpoint
 	let mountpoint = Arc::new(MountPoint {
@@ -316,7 +361,73 @@ pub fn create(
 			.lock()
 			.insert(EntryChild(root_entry))?;
 	}
-	Ok(())
+	drop(mps);
+	// Paths crossing the target now lead to the mounted filesystem
+	path_cache::clear()
+}
+
+/// The entries attached by [`pivot_root`], which are kept in memory until they are unmounted.
+static PIVOTED: Mutex<Vec<Arc<vfs::Entry>>> = Mutex::new(Vec::new());
+
+/// Tells whether `ent` is `ancestor` or one of its descendants.
+fn is_under(ent: &vfs::Entry, ancestor: &vfs::Entry) -> bool {
+	let loc = &ancestor.node().location;
+	let mut cur = Some(ent);
+	while let Some(e) = cur {
+		if e.node().location == *loc {
+			return true;
+		}
+		cur = e.parent.as_deref();
+	}
+	false
+}
+
+/// Makes `new_root` the root directory in place of `old_root`, which is attached at `put_old`.
+///
+/// Since the root of the VFS cannot be moved, the kernel keeps using it. The entry at `put_old`
+/// gives access to the same directory, and unmounting it only detaches it. Filesystems mounted
+/// under `old_root` are not visible through `put_old`.
+///
+/// Changing the root and working directories of processes is left to the caller.
+///
+/// The following errors can be returned:
+/// - `new_root` or `old_root` is not the root of a mountpoint: [`errno::EINVAL`]
+/// - `new_root` is not under `old_root`, or `put_old` is not under `new_root`: [`errno::EINVAL`]
+/// - `new_root` is `old_root`, or `put_old` is on the same mountpoint as `old_root`:
+///   [`errno::EBUSY`]
+pub fn pivot_root(
+	old_root: &vfs::Entry,
+	new_root: &vfs::Entry,
+	put_old: &vfs::Entry,
+) -> EResult<()> {
+	let (Some(old_mp), Some(_)) = (old_root.get_mountpoint(), new_root.get_mountpoint()) else {
+		return Err(errno!(EINVAL));
+	};
+	if new_root.node().location == old_root.node().location
+		|| put_old.node().location.mountpoint_id == old_mp.id
+	{
+		return Err(errno!(EBUSY));
+	}
+	if !is_under(new_root, old_root) || !is_under(put_old, new_root) {
+		return Err(errno!(EINVAL));
+	}
+	// `put_old` is under `new_root`, which is under `old_root`, so it has a parent
+	let parent = put_old.parent.as_ref().ok_or_else(|| errno!(EINVAL))?;
+	// Replace `put_old` with the previous root in the tree
+	let ent = Arc::new(vfs::Entry {
+		name: put_old.name.try_clone()?,
+		parent: Some(parent.clone()),
+		children: Default::default(),
+		node: Some(old_root.node().clone()),
+		dcache_stamp: Default::default(),
+	})?;
+	let mut pivoted = PIVOTED.lock();
+	pivoted.reserve(1)?;
+	parent.children.lock().insert(EntryChild(ent.clone()))?;
+	// Cannot fail since memory has been reserved
+	let _ = pivoted.push(ent);
+	drop(pivoted);
+	path_cache::clear()
 }
 
 /// Removes the mountpoint at the given `target` entry.
@@ -332,17 +443,74 @@ pub fn remove(target: Arc<vfs::Entry>) -> EResult<()> {
 	};
 	// TODO Check if another mount point is present in a subdirectory? (EBUSY)
 	// TODO Check if busy (EBUSY)
-	// TODO sync fs
+	// Write back modified files
+	page_cache::sync(Some(mp.id))?;
 	// Detach entry from parent
 	let Some(parent) = &target.parent else {
 		// Cannot unmount root filesystem
 		return Err(errno!(EINVAL));
 	};
-	parent.children.lock().remove(target.name.as_bytes());
+	let ent = parent.children.lock().remove(target.name.as_bytes());
+	if let Some(EntryChild(ent)) = ent {
+		dcache::forget(ent)?;
+	}
+	// Release cached entries on the filesystem
+	path_cache::clear()?;
+	// If `target` is not the root entry of the mountpoint, it is a previous root attached by
+	// `pivot_root`, which the kernel keeps using
+	let is_root = ptr::eq(target.as_ptr(), mp.root_entry.as_ptr());
+	if is_root {
+		dcache::purge_mountpoint(mp.id)?;
+	} else {
+		PIVOTED
+			.lock()
+			.retain(|ent| !ptr::eq(ent.as_ptr(), target.as_ptr()));
+	}
+	// Nodes that were in use when their last link was removed may not be anymore
+	if is_root {
+		if let Err(e) = orphan::reap_mountpoint(&mp) {
+			crate::log!(
+				Vfs,
+				Error,
+				"Could not remove unlinked files on mountpoint {}: {e}",
+				mp.id
+			);
+		}
+	}
 	// If this was the last reference to the mountpoint, remove it
 	let mut mps = MOUNT_POINTS.lock();
-	if Arc::strong_count(&mp) <= 2 {
+	if is_root && Arc::strong_count(&mp) <= 2 {
 		mps.remove(&mp.id);
+		drop(mps);
+		orphan::forget_mountpoint(mp.id);
+		page_cache::invalidate_mountpoint(mp.id);
+	}
+	Ok(())
+}
+
+/// Removes every mountpoint except the root one.
+///
+/// This function is used at shutdown. Since a mountpoint always has a greater ID than the
+/// mountpoint on which it is mounted, mountpoints are removed by decreasing ID so that nested ones
+/// are removed first.
+///
+/// Errors are printed, but do not stop the removal of the other mountpoints.
+pub fn remove_all() -> EResult<()> {
+	let mut mps = MOUNT_POINTS
+		.lock()
+		.iter()
+		.filter(|(id, _)| **id != 0)
+		.map(|(_, mp)| mp.clone())
+		.collect::<CollectResult<Vec<_>>>()
+		.0?;
+	mps.sort_unstable_by(|a, b| b.id.cmp(&a.id));
+	for mp in mps {
+		let (id, root_entry) = (mp.id, mp.root_entry.clone());
+		// Do not keep a reference, which would prevent the removal
+		drop(mp);
+		if let Err(e) = remove(root_entry) {
+			crate::log!(Vfs, Error, "Could not unmount mountpoint {id}: {e}");
+		}
 	}
 	Ok(())
 }
@@ -18,10 +18,11 @@
 
 //! Filesystem node cache, allowing to handle hard links pointing to the same node.
 
-use crate::file::{fs::NodeOps, FileLocation, FileType};
+use crate::file::{fs::NodeOps, vfs::orphan, FileLocation};
 use core::{
 	borrow::Borrow,
 	hash::{Hash, Hasher},
+	sync::atomic::AtomicUsize,
 };
 use utils::{
 	boxed::Box,
@@ -38,9 +39,25 @@ pub struct Node {
 	pub location: FileLocation,
 	/// Handle for node operations.
 	pub ops: Box<dyn NodeOps>,
+
+	/// The number of open file descriptions on the node, excluding those opened with
+	/// [`O_PATH`](crate::file::O_PATH).
+	pub open_count: AtomicUsize,
+	/// The number of open file descriptions on the node that allow writing.
+	pub write_count: AtomicUsize,
 }
 
 impl Node {
+	/// Creates a new node.
+	pub fn new(location: FileLocation, ops: Box<dyn NodeOps>) -> Self {
+		Self {
+			location,
+			ops,
+			open_count: AtomicUsize::new(0),
+			write_count: AtomicUsize::new(0),
+		}
+	}
+
 	/// Releases the node, removing it from the disk if this is the last reference to it.
 	pub fn release(this: Arc<Self>) -> EResult<()> {
 		// Lock to avoid race condition later
@@ -62,15 +79,7 @@ impl Node {
 	/// - `loc` is the location of the node
 	/// - `ops` is the handle to perform operations on the node
 	fn try_remove(loc: &FileLocation, ops: &dyn NodeOps) -> EResult<()> {
-		// If there is no hard link left to the node, remove it
-		let stat = ops.get_stat(loc)?;
-		let dir = stat.get_type() == Some(FileType::Directory);
-		// If the file is a directory, the threshold is `1` because of the `.` entry
-		let remove = (dir && stat.nlink <= 1) || stat.nlink == 0;
-		if remove {
-			ops.remove_node(loc)?;
-		}
-		Ok(())
+		orphan::reap(loc, ops)
 	}
 }
 
@@ -113,10 +122,7 @@ pub(super) fn get_or_insert(location: FileLocation, ops: Box<dyn NodeOps>) -> ER
 		// The node is not in cache. Insert it
 		None => {
 			// Create and insert node
-			let node = Arc::new(Node {
-				location,
-				ops,
-			})?;
+			let node = Arc::new(Node::new(location, ops))?;
 			used_nodes.insert(NodeEntry(node.clone()))?;
 			Ok(node)
 		}
@@ -0,0 +1,116 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Deferred removal of nodes.
+//!
+//! A node whose last link is removed while it is still in use (for example, an open file) cannot
+//! be removed immediately. It is recorded here until its last use ends, at which point it is
+//! reaped.
+//!
+//! If reaping a node fails, it remains recorded so that it is retried when its filesystem is
+//! unmounted.
+//!
+//! Nodes recorded here are lost if the system stops before they are reaped. Filesystems that
+//! keep a persistent list of such nodes (such as ext2 with its orphan inodes list) reclaim them
+//! at mount time.
+
+use crate::file::{
+	buffer,
+	fs::NodeOps,
+	page_cache,
+	vfs::{mountpoint::MountPoint, node},
+	FileLocation,
+};
+use utils::{
+	collections::{hashmap::HashSet, vec::Vec},
+	errno::{AllocResult, CollectResult, EResult},
+	lock::Mutex,
+};
+
+/// The nodes waiting for removal.
+static ORPHANS: Mutex<HashSet<FileLocation>> = Mutex::new(HashSet::new());
+
+/// Records that the node at `loc` has no link left, but is still in use.
+pub(super) fn defer(loc: &FileLocation) -> AllocResult<()> {
+	ORPHANS.lock().insert(loc.clone())?;
+	Ok(())
+}
+
+/// Removes the node at `loc` from its filesystem if it has no link left.
+///
+/// This function must be called only once the node is not in use anymore.
+///
+/// If the removal fails, the node remains recorded so that it can be retried later.
+pub(super) fn reap(loc: &FileLocation, ops: &dyn NodeOps) -> EResult<()> {
+	// Links counts are maintained by the VFS, which also accounts for the `.` entry of
+	// directories, so the threshold is the same for all file types
+	let stat = ops.get_stat(loc)?;
+	if stat.nlink != 0 {
+		// A link has been added back in the meantime
+		ORPHANS.lock().remove(loc);
+		return Ok(());
+	}
+	// The content of the file does not need to be written back
+	page_cache::invalidate(loc);
+	// The location may be reused by another node
+	buffer::detach(loc);
+	let res = ops.remove_node(loc);
+	let mut orphans = ORPHANS.lock();
+	match res {
+		Ok(()) => {
+			orphans.remove(loc);
+		}
+		Err(_) => {
+			orphans.insert(loc.clone())?;
+		}
+	}
+	res
+}
+
+/// Reaps the nodes of the mountpoint `mp` that are not in use anymore.
+///
+/// This function is meant to be called when unmounting.
+pub(super) fn reap_mountpoint(mp: &MountPoint) -> EResult<()> {
+	let locs = ORPHANS
+		.lock()
+		.iter()
+		.filter(|loc| loc.mountpoint_id == mp.id)
+		.cloned()
+		.collect::<CollectResult<Vec<_>>>()
+		.0?;
+	let mut res = Ok(());
+	for loc in locs {
+		let r = mp
+			.fs
+			.node_from_inode(loc.inode)
+			.and_then(|ops| node::try_remove(&loc, &*ops));
+		if let Err(e) = r {
+			res = Err(e);
+		}
+	}
+	res
+}
+
+/// Forgets about the nodes of the mountpoint with the given ID, once it has been removed.
+///
+/// Nodes still in use are left to the filesystem, which records them if it is persistent.
+pub(super) fn forget_mountpoint(mountpoint_id: u32) {
+	ORPHANS
+		.lock()
+		.retain(|loc| loc.mountpoint_id != mountpoint_id);
+}
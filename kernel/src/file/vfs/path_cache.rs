@@ -0,0 +1,207 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Cache of resolved absolute paths.
+//!
+//! Workloads executing many programs, such as shell scripts or builds, resolve the same paths to
+//! programs and interpreters over and over (`/bin/sh`, the dynamic linker, etc...). Each
+//! resolution looks up every component and reads every symbolic link on the way.
+//!
+//! This cache remembers the result of such resolutions, along with the directories searched to
+//! reach it. An entry is invalidated when one of these directories is modified, and the whole
+//! cache is cleared when a filesystem is mounted or unmounted.
+//!
+//! Resolutions crossing a filesystem which does not use the cache (such as procfs) or following a
+//! magic link are not cached, since their result may depend on the calling process (for example,
+//! `/proc/self/cwd`) or change without any directory being modified.
+
+use super::{dcache, resolve_path_traced, Entry, ResolutionSettings};
+use crate::file::FileLocation;
+use core::{iter, sync::atomic::Ordering::Relaxed};
+use utils::{
+	collections::{
+		path::{Path, PathBuf},
+		vec::Vec,
+	},
+	errno::EResult,
+	lock::{atomic::AtomicU64, Mutex},
+	ptr::arc::Arc,
+};
+
+/// The maximum number of cached paths.
+const CACHE_SIZE: usize = 32;
+
+/// A cached path resolution.
+struct CachedPath {
+	/// The location of the root directory the path has been resolved from.
+	root: FileLocation,
+	/// The resolved path.
+	path: PathBuf,
+	/// The directories searched during the resolution.
+	dirs: Vec<Arc<Entry>>,
+	/// The resulting entry.
+	entry: Arc<Entry>,
+}
+
+impl CachedPath {
+	/// Tells whether the resolution depends on the content of the directory at `loc`.
+	fn depends_on(&self, loc: &FileLocation) -> bool {
+		self.dirs.iter().any(|dir| dir.node().location == *loc)
+	}
+
+	/// Releases the entries held by the cached resolution.
+	fn release(self) -> EResult<()> {
+		let mut res = Entry::release(self.entry);
+		for dir in self.dirs {
+			res = res.and(Entry::release(dir));
+		}
+		res
+	}
+}
+
+/// The cached paths, from the least to the most recently used.
+static CACHE: Mutex<Vec<CachedPath>> = Mutex::new(Vec::new());
+/// Incremented on each invalidation, so that a resolution concurrent with a modification is not
+/// inserted in the cache.
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// Looks up `path` in the cache.
+///
+/// The permission to search the directories leading to the file is checked again, so that a
+/// resolution is not reused by a process that could not perform it.
+fn lookup(path: &Path, settings: &ResolutionSettings) -> EResult<Option<Arc<Entry>>> {
+	let root = &settings.root.node().location;
+	let (entry, dirs) = {
+		let mut cache = CACHE.lock();
+		let Some(i) = cache
+			.iter()
+			.position(|c| c.root == *root && *c.path == *path)
+		else {
+			return Ok(None);
+		};
+		let mut dirs = Vec::new();
+		dirs.extend_from_slice(&cache[i].dirs)?;
+		// Move to the most recently used position
+		let cached = cache.remove(i);
+		let entry = cached.entry.clone();
+		// Cannot fail since an element has just been removed
+		let _ = cache.push(cached);
+		(entry, dirs)
+	};
+	for dir in dirs {
+		if !settings.access_profile.can_search_directory(&dir.stat()?) {
+			// Let the full resolution report the error
+			return Ok(None);
+		}
+	}
+	Ok(Some(entry))
+}
+
+/// Returns the file at the given `path`, using the cache if possible.
+///
+/// Only absolute paths are cached. The last component of the path is always followed if it is a
+/// symbolic link. Other paths are resolved as with [`super::get_file_from_path`].
+pub fn resolve(path: &Path, settings: &ResolutionSettings) -> EResult<Arc<Entry>> {
+	if !path.is_absolute() || !settings.follow_link {
+		return super::get_file_from_path(path, settings);
+	}
+	if let Some(entry) = lookup(path, settings)? {
+		return Ok(entry);
+	}
+	let generation = GENERATION.load(Relaxed);
+	let (entry, dirs) = resolve_path_traced(path, settings)?;
+	let Some(dirs) = dirs else {
+		return Ok(entry);
+	};
+	let cacheable = dirs
+		.iter()
+		.chain(iter::once(&entry))
+		.all(|ent| dcache::is_cacheable(&ent.node().location));
+	if !cacheable {
+		for dir in dirs {
+			// Errors are not related to the resolution
+			let _ = Entry::release(dir);
+		}
+		return Ok(entry);
+	}
+	let cached = CachedPath {
+		root: settings.root.node().location.clone(),
+		path: PathBuf::try_from(path)?,
+		dirs,
+		entry: entry.clone(),
+	};
+	let evicted = {
+		let mut cache = CACHE.lock();
+		if GENERATION.load(Relaxed) != generation {
+			// A directory involved may have been modified during the resolution
+			Some(cached)
+		} else if cache.len() >= CACHE_SIZE {
+			let evicted = cache.remove(0);
+			// Cannot fail since an element has just been removed
+			let _ = cache.push(cached);
+			Some(evicted)
+		} else if cache.reserve(1).is_ok() {
+			// Cannot fail since memory has been reserved
+			let _ = cache.push(cached);
+			None
+		} else {
+			Some(cached)
+		}
+	};
+	if let Some(evicted) = evicted {
+		// Errors are not related to the resolution
+		let _ = evicted.release();
+	}
+	Ok(entry)
+}
+
+/// Removes the cached resolutions depending on the content of the directory at `loc`.
+///
+/// This function must be called **after** the directory is modified.
+pub(super) fn invalidate_dir(loc: &FileLocation) -> EResult<()> {
+	let mut res = Ok(());
+	loop {
+		// Entries are released without holding the lock since it requires locking their parent
+		let cached = {
+			let mut cache = CACHE.lock();
+			GENERATION.fetch_add(1, Relaxed);
+			let Some(i) = cache.iter().position(|c| c.depends_on(loc)) else {
+				break;
+			};
+			cache.remove(i)
+		};
+		res = res.and(cached.release());
+	}
+	res
+}
+
+/// Removes every cached resolution.
+///
+/// This function must be called **after** a filesystem is mounted or unmounted.
+pub(super) fn clear() -> EResult<()> {
+	let cache = {
+		let mut cache = CACHE.lock();
+		GENERATION.fetch_add(1, Relaxed);
+		core::mem::take(&mut *cache)
+	};
+	let mut res = Ok(());
+	for cached in cache {
+		res = res.and(cached.release());
+	}
+	res
+}
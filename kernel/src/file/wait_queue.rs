@@ -27,17 +27,28 @@ use core::mem;
 use utils::{
 	collections::vec::Vec,
 	errno,
-	errno::EResult,
+	errno::{AllocResult, EResult},
 	lock::{IntMutex, Mutex},
 };
 
+/// A process waiting on a [`WaitQueue`].
+#[derive(Debug)]
+struct Waiter {
+	/// The PID of the process.
+	pid: Pid,
+	/// If set, the process waits for the resource itself, and only one such process is woken up
+	/// by [`WaitQueue::wake_next`]. Else, the process only watches the resource and is woken up
+	/// by every wake operation.
+	exclusive: bool,
+}
+
 /// A queue of processes waiting on a resource.
 ///
 /// Wait processes shall sleep, and be woken up when the resource is available.
 ///
 /// **Note**: dropping this structure while processes are waiting on it makes them starve.
 #[derive(Debug, Default)]
-pub struct WaitQueue(IntMutex<Vec<Pid>>); // TODO use a VecDeque
+pub struct WaitQueue(IntMutex<Vec<Waiter>>); // TODO use a VecDeque
 
 impl WaitQueue {
 	/// Creates a new empty queue.
@@ -57,7 +68,10 @@ impl WaitQueue {
 			{
 				let proc_mutex = Process::current();
 				let mut proc = proc_mutex.lock();
-				self.0.lock().push(proc.get_pid())?;
+				self.0.lock().push(Waiter {
+					pid: proc.get_pid(),
+					exclusive: true,
+				})?;
 				proc.set_state(process::State::Sleeping);
 			}
 			// Yield
@@ -75,32 +89,47 @@ impl WaitQueue {
 		}
 	}
 
-	/// Wakes the next process in queue.
+	/// Registers the current process on the queue without making it sleep, so that it is woken
+	/// up by the next wake operation.
+	///
+	/// This allows a process to wait on several queues at once, as `poll` does. Since the process
+	/// is not removed from the queue once done waiting, it may be woken up spuriously later.
+	pub fn register(&self) -> AllocResult<()> {
+		let pid = Process::current().lock().get_pid();
+		let mut waiters = self.0.lock();
+		if !waiters.iter().any(|w| w.pid == pid && !w.exclusive) {
+			waiters.push(Waiter {
+				pid,
+				exclusive: false,
+			})?;
+		}
+		Ok(())
+	}
+
+	/// Wakes the next process in queue, along with the processes registered with
+	/// [`Self::register`].
 	pub fn wake_next(&self) {
-		let proc = loop {
-			// TODO: inefficient, must use a linked list
-			let pid = {
-				let mut pids = self.0.lock();
-				if pids.is_empty() {
-					// No process to wake, stop
-					return;
-				}
-				pids.remove(0)
-			};
-			let Some(proc) = Process::get_by_pid(pid) else {
+		let mut woken = false;
+		// TODO: inefficient, must use a linked list
+		self.0.lock().retain(|w| {
+			if w.exclusive && woken {
+				return true;
+			}
+			let Some(proc) = Process::get_by_pid(w.pid) else {
 				// Process does not exist, try next
-				continue;
+				return false;
 			};
-			break proc;
-		};
-		proc.lock().wake();
+			proc.lock().wake();
+			woken |= w.exclusive;
+			false
+		});
 	}
 
 	/// Wakes all processes.
 	pub fn wake_all(&self) {
-		let mut pids = self.0.lock();
-		for pid in mem::take(&mut *pids) {
-			let Some(proc) = Process::get_by_pid(pid) else {
+		let mut waiters = self.0.lock();
+		for w in mem::take(&mut *waiters) {
+			let Some(proc) = Process::get_by_pid(w.pid) else {
 				// Process does not exist, try next
 				continue;
 			};
@@ -79,12 +79,14 @@ pub mod selftest;
 pub mod syscall;
 pub mod time;
 pub mod tty;
+pub mod workqueue;
 
 use crate::{
-	file::{fs::initramfs, vfs, vfs::ResolutionSettings},
+	device::{console, DeviceID, DeviceType},
+	file::{fs::initramfs, page_cache, vfs, vfs::ResolutionSettings},
 	logger::LOGGER,
 	memory::vmem,
-	process::{exec, exec::ExecInfo, Process},
+	process::{exec, exec::ExecInfo, mem_space::ksm, Process},
 	tty::TTY,
 };
 use core::{arch::asm, ffi::c_void};
@@ -190,20 +192,29 @@ fn kernel_main_inner(magic: u32, multiboot_ptr: *const c_void) {
 
 	// Perform kernel self-tests
 	#[cfg(test)]
-	kernel_selftest();
+	{
+		console::init(&[]);
+		kernel_selftest();
+	}
 
 	// Parse bootloader command line arguments
 	let cmdline = boot_info.cmdline.unwrap_or_default();
 	let args_parser = match cmdline::ArgsParser::parse(cmdline) {
 		Ok(p) => p,
 		Err(e) => {
+			console::init(&[]);
 			println!("{e}");
 			power::halt();
 		}
 	};
 	LOGGER.lock().silent = args_parser.is_silent();
+	logger::init().unwrap_or_else(|e| panic!("Failed to initialize logger! ({e})"));
+	logger::init_levels(cmdline);
+	// Initialize consoles, printing logs emitted so far
+	console::init(args_parser.get_consoles());
 
 	println!("Booting Maestro kernel version {VERSION}");
+	panic::init().unwrap_or_else(|e| panic!("Failed to register panic parameters! ({e})"));
 
 	// FIXME
 	//println!("Initializing ACPI...");
@@ -223,6 +234,14 @@ fn kernel_main_inner(magic: u32, multiboot_ptr: *const c_void) {
 		.unwrap_or_else(|_| panic!("Failed to initialize cryptography! (out of memory)"));
 
 	let root = args_parser.get_root_dev();
+	if let Some((major, minor)) = root {
+		// Partition scans are deferred to the workqueue, which is not running yet
+		device::storage::settle(&DeviceID {
+			dev_type: DeviceType::Block,
+			major,
+			minor,
+		});
+	}
 	println!("Initializing files management...");
 	file::init(root).unwrap_or_else(|e| panic!("Failed to initialize files management! ({e})"));
 	if let Some(initramfs) = boot_info.initramfs {
@@ -234,10 +253,16 @@ fn kernel_main_inner(magic: u32, multiboot_ptr: *const c_void) {
 
 	println!("Initializing processes...");
 	process::init().unwrap_or_else(|e| panic!("Failed to init processes! ({e})"));
+	syscall::unimplemented::init()
+		.unwrap_or_else(|e| panic!("Failed to register system call parameters! ({e})"));
 
 	let init_path = args_parser.get_init_path().unwrap_or(INIT_PATH);
 	let init_path = String::try_from(init_path).unwrap();
 	init(init_path).unwrap_or_else(|e| panic!("Cannot execute init process: {e}"));
+	workqueue::init().unwrap_or_else(|e| panic!("Failed to initialize workqueues! ({e})"));
+	page_cache::init().unwrap_or_else(|e| panic!("Failed to start writeback daemon! ({e})"));
+	ksm::init().unwrap_or_else(|e| panic!("Failed to start KSM daemon! ({e})"));
+	console::init_input().unwrap_or_else(|e| panic!("Failed to initialize console input! ({e})"));
 }
 
 /// This is the main function of the Rust source code, responsible for the
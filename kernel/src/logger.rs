@@ -20,18 +20,202 @@
 //!
 //! If the logger is set as silent, logs will not show up on screen, but will be kept in memory
 //! anyway.
+//!
+//! Likewise, logs show up on screen only if their level is lower than the console log level,
+//! which is set with the `console.loglevel` parameter. Levels follow the syslog convention: the
+//! lower the value, the more severe the message.
+//!
+//! Logs are printed on the active consoles. See [`console`].
+//!
+//! Messages emitted through [`crate::log!`] belong to a [`Subsystem`] and have a [`Level`]. Each
+//! subsystem has a maximum level, above which its messages are discarded. This level can be
+//! set:
+//! - at boot, with a `log.<subsystem>=<level>` argument on the kernel's command line
+//! - at runtime, by writing to `/proc/sys/kernel/log/<subsystem>`
 
-use crate::tty::TTY;
+use crate::{
+	cmdline,
+	device::console,
+	module::{param, param::Param},
+	println,
+};
 use core::{
 	cmp::{min, Ordering},
 	fmt,
 	fmt::Write,
+	str,
+	sync::atomic::{
+		AtomicU8,
+		Ordering::{Acquire, Relaxed, Release},
+	},
 };
-use utils::lock::IntMutex;
+use utils::{errno::EResult, lock::IntMutex, DisplayableStr};
 
 /// The size of the kernel logs buffer in bytes.
 const LOGS_SIZE: usize = 1048576;
 
+/// The level of messages which do not specify one.
+const DEFAULT_MESSAGE_LEVEL: u8 = 4;
+/// The default console log level, letting every message show up on screen.
+const DEFAULT_CONSOLE_LEVEL: u8 = 7;
+
+/// The console log level. Messages show up on screen only if their level is lower.
+static CONSOLE_LEVEL: Param<u8> = Param::new(
+	"console",
+	"loglevel",
+	DEFAULT_CONSOLE_LEVEL,
+	true,
+	Some(set_console_level),
+);
+/// The current value of [`CONSOLE_LEVEL`].
+///
+/// It is updated on change, so that the logger does not have to lock the parameter, which would
+/// prevent logging from interrupt handlers.
+static CONSOLE_LEVEL_VAL: AtomicU8 = AtomicU8::new(DEFAULT_CONSOLE_LEVEL);
+
+/// Change notification callback of [`CONSOLE_LEVEL`].
+fn set_console_level(level: u8) {
+	CONSOLE_LEVEL_VAL.store(level, Relaxed);
+}
+
+/// Tells whether messages with the given `level` show up on screen.
+fn is_printed(level: u8) -> bool {
+	level < CONSOLE_LEVEL_VAL.load(Relaxed)
+}
+
+/// Registers the logger's parameters.
+pub(crate) fn init() -> EResult<()> {
+	param::register(&CONSOLE_LEVEL)
+}
+
+/// The severity of a log message.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+#[repr(u8)]
+pub enum Level {
+	/// An operation failed.
+	Error = 0,
+	/// Something unexpected happened, but the operation could proceed.
+	Warn = 1,
+	/// Normal but significant event.
+	Info = 2,
+	/// Detailed information, useful only for debugging.
+	Debug = 3,
+}
+
+impl Level {
+	/// The level of subsystems which have not been configured.
+	pub const DEFAULT: Self = Self::Info;
+
+	/// Returns the level corresponding to the given number.
+	fn from_u8(n: u8) -> Option<Self> {
+		match n {
+			0 => Some(Self::Error),
+			1 => Some(Self::Warn),
+			2 => Some(Self::Info),
+			3 => Some(Self::Debug),
+			_ => None,
+		}
+	}
+
+	/// Parses a level from either its name or its number.
+	///
+	/// If the string is invalid, the function returns `None`.
+	pub fn parse(s: &[u8]) -> Option<Self> {
+		match s {
+			b"error" => Some(Self::Error),
+			b"warn" => Some(Self::Warn),
+			b"info" => Some(Self::Info),
+			b"debug" => Some(Self::Debug),
+			_ => Self::from_u8(str::from_utf8(s).ok()?.parse().ok()?),
+		}
+	}
+
+	/// Returns the name of the level.
+	pub fn name(self) -> &'static str {
+		match self {
+			Self::Error => "error",
+			Self::Warn => "warn",
+			Self::Info => "info",
+			Self::Debug => "debug",
+		}
+	}
+}
+
+/// A part of the kernel whose log level can be configured independently.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(u8)]
+pub enum Subsystem {
+	/// Storage devices and partitions.
+	Storage = 0,
+	/// Virtual filesystem and filesystem implementations.
+	Vfs = 1,
+	/// Network devices and protocols.
+	Net = 2,
+	/// Processes and scheduling.
+	Sched = 3,
+}
+
+impl Subsystem {
+	/// The list of all subsystems.
+	pub const ALL: [Self; 4] = [Self::Storage, Self::Vfs, Self::Net, Self::Sched];
+
+	/// Returns the subsystem with the given name.
+	pub fn from_name(name: &[u8]) -> Option<Self> {
+		Self::ALL.into_iter().find(|s| s.name().as_bytes() == name)
+	}
+
+	/// Returns the name of the subsystem.
+	pub fn name(self) -> &'static str {
+		match self {
+			Self::Storage => "storage",
+			Self::Vfs => "vfs",
+			Self::Net => "net",
+			Self::Sched => "sched",
+		}
+	}
+}
+
+/// The maximum level of each subsystem, indexed by [`Subsystem`].
+///
+/// Atomics are used so that logging does not require locking, which allows it from interrupt
+/// handlers.
+static LEVELS: [AtomicU8; Subsystem::ALL.len()] =
+	[const { AtomicU8::new(Level::DEFAULT as u8) }; Subsystem::ALL.len()];
+
+/// Returns the maximum level of messages printed for the given subsystem.
+pub fn get_level(subsys: Subsystem) -> Level {
+	Level::from_u8(LEVELS[subsys as usize].load(Acquire)).unwrap_or(Level::DEFAULT)
+}
+
+/// Sets the maximum level of messages printed for the given subsystem.
+pub fn set_level(subsys: Subsystem, level: Level) {
+	LEVELS[subsys as usize].store(level as u8, Release);
+}
+
+/// Tells whether a message with the given level for the given subsystem shall be logged.
+pub fn is_enabled(subsys: Subsystem, level: Level) -> bool {
+	level <= get_level(subsys)
+}
+
+/// Applies the log levels specified on the given command line.
+///
+/// Invalid subsystems or levels are ignored and a warning is printed.
+pub fn init_levels(cmdline: &[u8]) {
+	for (module, name, val) in cmdline::params(cmdline) {
+		if module != b"log" {
+			continue;
+		}
+		match (Subsystem::from_name(name), Level::parse(val)) {
+			(Some(subsys), Some(level)) => set_level(subsys, level),
+			_ => println!(
+				"Ignoring invalid log level `log.{}={}`",
+				DisplayableStr(name),
+				DisplayableStr(val)
+			),
+		}
+	}
+}
+
 /// The kernel's logger.
 pub static LOGGER: IntMutex<Logger> = IntMutex::new(Logger::new());
 
@@ -83,6 +267,18 @@ impl Logger {
 		&self.buff
 	}
 
+	/// Returns the logs stored into the logger's buffer, from the oldest to the newest.
+	///
+	/// Since the buffer is a ring, the logs are returned as two slices which must be read one
+	/// after the other.
+	pub fn get_ordered_content(&self) -> (&[u8], &[u8]) {
+		if self.write_head >= self.read_head {
+			(&self.buff[self.read_head..self.write_head], &[])
+		} else {
+			(&self.buff[self.read_head..], &self.buff[..self.write_head])
+		}
+	}
+
 	/// Pushes the given string onto the kernel logs buffer.
 	pub fn push(&mut self, s: &[u8]) {
 		if self.available_space() < s.len() {
@@ -126,9 +322,47 @@ impl Logger {
 impl Write for Logger {
 	fn write_str(&mut self, s: &str) -> fmt::Result {
 		self.push(s.as_bytes());
-		if !self.silent {
-			TTY.display.lock().write(s.as_bytes());
+		if !self.silent && is_printed(DEFAULT_MESSAGE_LEVEL) {
+			console::write(s.as_bytes());
 		}
 		Ok(())
 	}
 }
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::module::param::ParamOps;
+
+	#[test_case]
+	fn console_level() {
+		assert!(is_printed(DEFAULT_MESSAGE_LEVEL));
+		CONSOLE_LEVEL.set_from_str(b"4").unwrap();
+		assert!(!is_printed(DEFAULT_MESSAGE_LEVEL));
+		assert!(is_printed(3));
+		assert!(CONSOLE_LEVEL.set_from_str(b"debug").is_err());
+		assert!(!is_printed(DEFAULT_MESSAGE_LEVEL));
+		CONSOLE_LEVEL.set(DEFAULT_CONSOLE_LEVEL);
+		assert!(is_printed(DEFAULT_MESSAGE_LEVEL));
+	}
+
+	#[test_case]
+	fn log_level_parse() {
+		assert_eq!(Level::parse(b"warn"), Some(Level::Warn));
+		assert_eq!(Level::parse(b"3"), Some(Level::Debug));
+		assert_eq!(Level::parse(b"4"), None);
+		assert_eq!(Level::parse(b"verbose"), None);
+	}
+
+	#[test_case]
+	fn log_level_cmdline() {
+		init_levels(b"-silent log.net=debug log.vfs=0 ide.queue_depth=16");
+		assert_eq!(get_level(Subsystem::Net), Level::Debug);
+		assert_eq!(get_level(Subsystem::Storage), Level::DEFAULT);
+		assert!(is_enabled(Subsystem::Vfs, Level::Error));
+		assert!(!is_enabled(Subsystem::Vfs, Level::Warn));
+		for subsys in Subsystem::ALL {
+			set_level(subsys, Level::DEFAULT);
+		}
+	}
+}
@@ -23,13 +23,14 @@
 //!
 //! The following zones exist:
 //! - Kernel: Memory to be allocated by the kernel, shared across processes. This zone requires
-//!   that every frame of virtual memory are associated with a unique physical frame.
+//!   that every frame of virtual memory are associated with a unique physical frame. It ends
+//!   before the memory that is linearly mapped on the vmalloc range.
 //! - MMIO: Memory used for Memory Mapped I/O. This zones requires only virtual memory, thus it
 //!   overlaps with the user zone which allocates the physical memory.
 //! - User: Memory used for userspace mappings. This zone doesn't require virtual memory to
 //!   correspond with the physical memory, thus it can be located outside the kernelspace.
 
-use crate::memory::{buddy, memmap, KERNELSPACE_SIZE};
+use crate::memory::{buddy, memmap, vmalloc::VMALLOC_BEGIN};
 use core::cmp::min;
 use utils::limits::PAGE_SIZE;
 
@@ -52,8 +53,9 @@ pub(crate) fn init() {
 
 	// The beginning of the kernel's zone
 	let kernel_zone_begin = metadata_end.align_to(PAGE_SIZE);
-	// The maximum number of pages the kernel zone can hold.
-	let kernel_max = (KERNELSPACE_SIZE - metadata_end.0) / PAGE_SIZE;
+	// The maximum number of pages the kernel zone can hold, without reaching the vmalloc range
+	let kernel_end = VMALLOC_BEGIN.kernel_to_physical().unwrap();
+	let kernel_max = kernel_end.0.saturating_sub(kernel_zone_begin.0) / PAGE_SIZE;
 	// The number of frames the kernel zone holds.
 	let kernel_zone_frames = min(available_pages, kernel_max);
 	// The kernel's zone
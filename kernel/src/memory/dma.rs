@@ -0,0 +1,293 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+//! DMA (Direct Memory Access) allows devices to read and write the main memory without going
+//! through the CPU.
+//!
+//! The kernel does not support IOMMUs, thus the address a device uses to access memory (the *bus
+//! address*) is the physical address. A device can only access a contiguous range of physical
+//! memory that is below its DMA mask.
+//!
+//! This module provides two kinds of mappings:
+//! - [`Coherent`] buffers, shared between the CPU and the device for their whole lifetime (for
+//!   example, descriptor rings)
+//! - Streaming mappings, created with [`map`], giving a device access to an existing buffer for a
+//!   single transfer. If the buffer cannot be accessed by the device, the data transparently goes
+//!   through a bounce buffer
+//!
+//! The supported architectures keep caches coherent with DMA, thus synchronizing a mapping only
+//! requires memory barriers, and copies to or from the bounce buffer if any.
+
+use super::{buddy, buddy::FrameOrder, vmalloc::VMALLOC_BEGIN, PhysAddr, VirtAddr};
+use core::{
+	alloc::AllocError,
+	ptr::NonNull,
+	slice,
+	sync::atomic::{fence, Ordering::SeqCst},
+};
+use utils::{errno::AllocResult, limits::PAGE_SIZE};
+
+/// An address of memory, as seen by devices.
+pub type BusAddr = u64;
+
+/// DMA mask for devices that can address the first 16 MiB of memory only (ISA).
+pub const DMA_MASK_24: BusAddr = 0xffffff;
+/// DMA mask for devices that can address the first 4 GiB of memory.
+pub const DMA_MASK_32: BusAddr = 0xffffffff;
+
+/// The direction of a DMA transfer.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Direction {
+	/// The device reads the memory.
+	ToDevice,
+	/// The device writes the memory.
+	FromDevice,
+	/// The device both reads and writes the memory.
+	Bidirectional,
+}
+
+impl Direction {
+	/// Tells whether the device reads the memory.
+	fn device_reads(self) -> bool {
+		matches!(self, Self::ToDevice | Self::Bidirectional)
+	}
+
+	/// Tells whether the device writes the memory.
+	fn device_writes(self) -> bool {
+		matches!(self, Self::FromDevice | Self::Bidirectional)
+	}
+}
+
+/// Tells whether the physical range starting at `addr` with size `len` can be accessed by a
+/// device with the DMA mask `mask`.
+fn is_addressable(addr: PhysAddr, len: usize, mask: BusAddr) -> bool {
+	let end = addr.0 as BusAddr + len.saturating_sub(1) as BusAddr;
+	end <= mask
+}
+
+/// Returns the physical address of the buffer `buf` if it is physically contiguous.
+///
+/// Only buffers located in the linearly mapped part of the kernelspace are known to be
+/// physically contiguous.
+fn contiguous_phys_addr(buf: &[u8]) -> Option<PhysAddr> {
+	let begin = VirtAddr::from(buf.as_ptr());
+	let end = begin.0.checked_add(buf.len())?;
+	if end > VMALLOC_BEGIN.0 {
+		return None;
+	}
+	begin.kernel_to_physical()
+}
+
+/// A physically contiguous buffer, allocated for DMA.
+///
+/// The buffer can be accessed by both the CPU and the device during its whole lifetime. It is
+/// freed when dropped.
+#[derive(Debug)]
+pub struct Coherent {
+	/// The pointer to the beginning of the buffer.
+	ptr: NonNull<u8>,
+	/// The size of the buffer in bytes.
+	len: usize,
+	/// The buddy allocator order of the buffer.
+	order: FrameOrder,
+}
+
+impl Coherent {
+	/// Allocates a zeroed buffer of `len` bytes that can be accessed by a device with the DMA
+	/// mask `mask`.
+	///
+	/// If not enough memory is available, or if the allocated memory is not addressable by the
+	/// device, the function returns an error.
+	pub fn new(len: usize, mask: BusAddr) -> AllocResult<Self> {
+		let order = buddy::get_order(len.div_ceil(PAGE_SIZE));
+		let ptr = buddy::alloc_kernel(order)?;
+		let mut buf = Self {
+			ptr,
+			len,
+			order,
+		};
+		if !is_addressable(buf.phys_addr(), len, mask) {
+			return Err(AllocError);
+		}
+		buf.as_slice_mut().fill(0);
+		Ok(buf)
+	}
+
+	/// Returns the physical address of the buffer.
+	fn phys_addr(&self) -> PhysAddr {
+		VirtAddr::from(self.ptr).kernel_to_physical().unwrap()
+	}
+
+	/// Returns the address the device has to use to access the buffer.
+	pub fn bus_addr(&self) -> BusAddr {
+		self.phys_addr().0 as _
+	}
+
+	/// Returns the size of the buffer in bytes.
+	pub fn len(&self) -> usize {
+		self.len
+	}
+
+	/// Tells whether the buffer is empty.
+	pub fn is_empty(&self) -> bool {
+		self.len == 0
+	}
+
+	/// Returns an immutable slice over the buffer.
+	pub fn as_slice(&self) -> &[u8] {
+		unsafe { slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+	}
+
+	/// Returns a mutable slice over the buffer.
+	pub fn as_slice_mut(&mut self) -> &mut [u8] {
+		unsafe { slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+	}
+}
+
+impl Drop for Coherent {
+	fn drop(&mut self) {
+		unsafe {
+			buddy::free_kernel(self.ptr.as_ptr(), self.order);
+		}
+	}
+}
+
+/// A buffer mapped for a DMA transfer.
+///
+/// While the mapping exists, the CPU must not access the buffer, except between calls to
+/// [`Mapping::sync_for_cpu`] and [`Mapping::sync_for_device`].
+///
+/// The buffer is unmapped when the structure is dropped.
+#[derive(Debug)]
+pub struct Mapping<'b> {
+	/// The mapped buffer.
+	buf: &'b mut [u8],
+	/// The direction of the transfer.
+	dir: Direction,
+	/// The bounce buffer, if the device cannot access `buf` directly.
+	bounce: Option<Coherent>,
+}
+
+/// Maps the buffer `buf` for a DMA transfer in direction `dir` for a device with the DMA mask
+/// `mask`.
+///
+/// If the buffer is not physically contiguous or is not addressable by the device, a bounce
+/// buffer is used. If the allocation of the bounce buffer fails, the function returns an error.
+pub fn map(buf: &mut [u8], dir: Direction, mask: BusAddr) -> AllocResult<Mapping<'_>> {
+	let direct = contiguous_phys_addr(buf)
+		.map(|addr| is_addressable(addr, buf.len(), mask))
+		.unwrap_or(false);
+	let bounce = if direct {
+		None
+	} else {
+		Some(Coherent::new(buf.len(), mask)?)
+	};
+	let mut mapping = Mapping {
+		buf,
+		dir,
+		bounce,
+	};
+	mapping.sync_for_device();
+	Ok(mapping)
+}
+
+impl Mapping<'_> {
+	/// Returns the address the device has to use to access the buffer.
+	pub fn bus_addr(&self) -> BusAddr {
+		match &self.bounce {
+			Some(bounce) => bounce.bus_addr(),
+			None => contiguous_phys_addr(self.buf).unwrap().0 as _,
+		}
+	}
+
+	/// Returns the size of the mapped buffer in bytes.
+	pub fn len(&self) -> usize {
+		self.buf.len()
+	}
+
+	/// Tells whether the mapped buffer is empty.
+	pub fn is_empty(&self) -> bool {
+		self.buf.is_empty()
+	}
+
+	/// Tells whether the transfer goes through a bounce buffer.
+	pub fn is_bounced(&self) -> bool {
+		self.bounce.is_some()
+	}
+
+	/// Gives the ownership of the buffer back to the device, making the modifications of the CPU
+	/// visible to it.
+	pub fn sync_for_device(&mut self) {
+		if let Some(bounce) = &mut self.bounce {
+			if self.dir.device_reads() {
+				bounce.as_slice_mut().copy_from_slice(self.buf);
+			}
+		}
+		fence(SeqCst);
+	}
+
+	/// Gives the ownership of the buffer to the CPU, making the modifications of the device
+	/// visible to it.
+	pub fn sync_for_cpu(&mut self) {
+		fence(SeqCst);
+		if let Some(bounce) = &self.bounce {
+			if self.dir.device_writes() {
+				self.buf.copy_from_slice(bounce.as_slice());
+			}
+		}
+	}
+}
+
+impl Drop for Mapping<'_> {
+	fn drop(&mut self) {
+		self.sync_for_cpu();
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::memory::vmalloc::VMalloc;
+	use core::num::NonZeroUsize;
+
+	#[test_case]
+	fn dma_map_direct() {
+		let mut buf = Coherent::new(PAGE_SIZE, DMA_MASK_32).unwrap();
+		let bus_addr = buf.bus_addr();
+		let mapping = map(buf.as_slice_mut(), Direction::ToDevice, DMA_MASK_32).unwrap();
+		assert!(!mapping.is_bounced());
+		assert_eq!(mapping.bus_addr(), bus_addr);
+	}
+
+	#[test_case]
+	fn dma_map_bounce() {
+		let mut buf = VMalloc::new(NonZeroUsize::new(2).unwrap()).unwrap();
+		buf.as_slice_mut().fill(1);
+		{
+			let mapping = map(buf.as_slice_mut(), Direction::Bidirectional, DMA_MASK_32).unwrap();
+			assert!(mapping.is_bounced());
+			// Simulate a write from the device
+			let addr = PhysAddr(mapping.bus_addr() as _)
+				.kernel_to_virtual()
+				.unwrap();
+			let dev = unsafe { slice::from_raw_parts_mut(addr.as_ptr::<u8>(), mapping.len()) };
+			assert!(dev.iter().all(|b| *b == 1));
+			dev.fill(2);
+		}
+		assert!(buf.as_slice().iter().all(|b| *b == 2));
+	}
+}
@@ -23,10 +23,17 @@
 //! This data is meant to be used by the memory allocators.
 
 use super::{stats, PhysAddr, VirtAddr};
-use crate::{elf::kernel::sections, multiboot, multiboot::BootInfo};
+use crate::{
+	elf::kernel::sections,
+	multiboot,
+	multiboot::{BootInfo, MmapEntry, MEMORY_AVAILABLE},
+};
 use core::{cmp::*, iter, ptr::null};
 use utils::{limits::PAGE_SIZE, lock::once::OnceInit};
 
+/// The end of the physical memory that can be addressed without PAE.
+const ADDRESSABLE_END: u64 = 1 << 32;
+
 /// Physical memory map information.
 #[derive(Debug)]
 pub struct PhysMapInfo {
@@ -64,20 +71,51 @@ pub fn get_info() -> &'static PhysMapInfo {
 	MAP.get()
 }
 
+/// Returns an iterator over the entries of a Multiboot2 memory map.
+///
+/// Arguments:
+/// - `maps` is the pointer to the memory map. If null, the iterator is empty.
+/// - `size` is the size of the memory map in bytes.
+/// - `entry_size` is the size of an entry in bytes.
+fn map_entries(
+	maps: *const MmapEntry,
+	size: usize,
+	entry_size: usize,
+) -> impl Iterator<Item = &'static MmapEntry> {
+	let size = if maps.is_null() || entry_size == 0 {
+		0
+	} else {
+		size
+	};
+	(0..size)
+		.step_by(max(entry_size, 1))
+		// Safe because in range
+		.map(move |off| unsafe { &*maps.byte_add(off) })
+}
+
+/// Returns an iterator over the entries of the physical memory map given by the bootloader.
+pub fn entries() -> impl Iterator<Item = &'static MmapEntry> {
+	let phys_map = get_info();
+	map_entries(
+		phys_map.memory_maps,
+		phys_map.memory_maps_size,
+		phys_map.memory_maps_entry_size,
+	)
+}
+
 /// Prints the physical memory mapping.
 #[cfg(debug_assertions)]
 pub(crate) fn print_entries() {
-	let phys_map = get_info();
-	debug_assert!(!phys_map.memory_maps.is_null());
+	debug_assert!(!get_info().memory_maps.is_null());
 	crate::println!("--- Memory mapping ---");
 	crate::println!("<begin> <end> <type>");
-	for off in (0..phys_map.memory_maps_size).step_by(phys_map.memory_maps_entry_size) {
-		// Safe because in range
-		let entry = unsafe { &*phys_map.memory_maps.byte_add(off) };
-		if entry.is_valid() {
-			let begin = entry.addr;
-			let end = begin + entry.len;
-			let type_ = entry.get_type_string();
+	for entry in entries() {
+		let begin = entry.addr;
+		let end = entry.end();
+		let type_ = entry.get_type_string();
+		if end > ADDRESSABLE_END {
+			crate::println!("- {begin:08x} {end:08x} {type_} (not addressable)");
+		} else {
 			crate::println!("- {begin:08x} {end:08x} {type_}");
 		}
 	}
@@ -117,14 +155,58 @@ fn get_phys_main(boot_info: &BootInfo) -> (PhysAddr, usize) {
 		.into_iter()
 		.max()
 		.unwrap();
-	// TODO Handle 64-bits systems
-	// The size of the physical memory in pages
-	let memory_size = min((1000 + boot_info.mem_upper) / 4, 1024 * 1024) as usize;
+	let entries = || {
+		map_entries(
+			boot_info.memory_maps,
+			boot_info.memory_maps_size,
+			boot_info.memory_maps_entry_size,
+		)
+	};
+	// The end of the available region containing the beginning of allocatable memory
+	let region_end = entries()
+		.filter(|e| e.type_ == MEMORY_AVAILABLE)
+		.find(|e| (e.addr..e.end()).contains(&(begin.0 as u64)))
+		.map(|e| e.end());
+	let end = match region_end {
+		Some(end) => {
+			// Stop before any overlapping region that is not available (ACPI, NVS, MMIO holes,
+			// ...), since firmwares may report overlapping regions
+			entries()
+				.filter(|e| e.type_ != MEMORY_AVAILABLE)
+				.filter(|e| e.addr >= begin.0 as u64)
+				.map(|e| e.addr)
+				.fold(end, min)
+		}
+		// No memory map: assume the upper memory is contiguous
+		None => (1024 + boot_info.mem_upper as u64) * 1024,
+	};
+	// TODO Handle PAE
+	let end = min(end, ADDRESSABLE_END);
 	// The number of physical page available for memory allocation
-	let pages = memory_size - begin.0.div_ceil(PAGE_SIZE);
+	let pages = ((end / PAGE_SIZE as u64) as usize).saturating_sub(begin.0.div_ceil(PAGE_SIZE));
 	(begin, pages)
 }
 
+/// Returns the amount of available memory in the memory map, in KiB.
+///
+/// The function returns a tuple with the memory below and above the addressable limit.
+fn available_memory(boot_info: &BootInfo) -> (u64, u64) {
+	map_entries(
+		boot_info.memory_maps,
+		boot_info.memory_maps_size,
+		boot_info.memory_maps_entry_size,
+	)
+	.filter(|e| e.type_ == MEMORY_AVAILABLE)
+	.fold((0, 0), |(low, high), e| {
+		// The limit between the addressable and non-addressable parts of the region
+		let split = ADDRESSABLE_END.clamp(e.addr, e.end());
+		(
+			low + (split - e.addr) / 1024,
+			high + (e.end() - split) / 1024,
+		)
+	})
+}
+
 /// Fills the memory mapping structure according to Multiboot's information.
 pub(crate) fn init(boot_info: &BootInfo) {
 	// Set memory information
@@ -141,7 +223,15 @@ pub(crate) fn init(boot_info: &BootInfo) {
 		MAP.init(phys_map);
 	}
 	// Update memory stats
+	let (low, high) = available_memory(boot_info);
+	if high > 0 {
+		crate::println!("{high} KiB of memory above 4 GiB cannot be addressed and are ignored");
+	}
 	let mut stats = stats::MEM_INFO.lock();
-	stats.mem_total = min(boot_info.mem_upper, 4194304) as _; // TODO Handle 64-bits systems
+	stats.mem_total = if low > 0 {
+		low as _
+	} else {
+		min(boot_info.mem_upper, 4194304) as _
+	};
 	stats.mem_free = phys_main_pages * 4;
 }
@@ -37,13 +37,16 @@ use core::{
 
 pub mod alloc;
 pub mod buddy;
+pub mod dma;
 pub mod malloc;
 pub mod memmap;
 pub mod mmio;
+pub mod pressure;
 pub mod stack;
 pub mod stats;
 #[cfg(feature = "memtrace")]
 mod trace;
+pub mod vmalloc;
 pub mod vmem;
 
 /// Pointer to the beginning of the allocatable region in the virtual memory.
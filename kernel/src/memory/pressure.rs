@@ -0,0 +1,105 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Memory pressure tracking, allowing userspace to reclaim memory before the OOM killer has to
+//! act.
+//!
+//! The pressure level is computed from the proportion of free physical memory. It is raised to
+//! [`Level::Critical`] when an allocation recently failed, stalling until the OOM killer
+//! freed memory.
+
+use crate::{
+	memory::stats,
+	time::{clock, unit::Timestamp},
+};
+use core::{
+	fmt,
+	fmt::{Display, Formatter},
+	sync::atomic::Ordering::Relaxed,
+};
+use utils::lock::atomic::AtomicU64;
+
+/// Below this percentage of free memory, the pressure is [`Level::Low`].
+const LOW_THRESHOLD: usize = 20;
+/// Below this percentage of free memory, the pressure is [`Level::Medium`].
+const MEDIUM_THRESHOLD: usize = 10;
+/// Below this percentage of free memory, the pressure is [`Level::Critical`].
+const CRITICAL_THRESHOLD: usize = 5;
+/// The duration during which a stall keeps the pressure critical, in nanoseconds.
+const STALL_WINDOW: Timestamp = 10_000_000_000;
+
+/// The number of allocation stalls since boot.
+static STALLS: AtomicU64 = AtomicU64::new(0);
+/// The time of the last allocation stall, in nanoseconds since boot.
+static LAST_STALL: AtomicU64 = AtomicU64::new(0);
+
+/// A memory pressure level.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum Level {
+	/// Enough memory is available.
+	None,
+	/// Memory is getting scarce. Caches may be dropped.
+	Low,
+	/// Memory is scarce. Background processes should release memory.
+	Medium,
+	/// The system is about to run out of memory, or already did.
+	Critical,
+}
+
+impl Display for Level {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		let s = match self {
+			Self::None => "none",
+			Self::Low => "low",
+			Self::Medium => "medium",
+			Self::Critical => "critical",
+		};
+		f.write_str(s)
+	}
+}
+
+/// Records an allocation that failed due to a lack of memory.
+pub fn record_stall() {
+	STALLS.fetch_add(1, Relaxed);
+	LAST_STALL.store(clock::boottime(), Relaxed);
+}
+
+/// Returns the number of allocation stalls since boot.
+pub fn stalls() -> u64 {
+	STALLS.load(Relaxed)
+}
+
+/// Returns the current memory pressure level.
+pub fn level() -> Level {
+	if stalls() > 0 && clock::boottime().saturating_sub(LAST_STALL.load(Relaxed)) < STALL_WINDOW {
+		return Level::Critical;
+	}
+	let (total, free) = {
+		let info = stats::MEM_INFO.lock();
+		(info.mem_total, info.mem_free)
+	};
+	if total == 0 {
+		return Level::None;
+	}
+	match free * 100 / total {
+		p if p < CRITICAL_THRESHOLD => Level::Critical,
+		p if p < MEDIUM_THRESHOLD => Level::Medium,
+		p if p < LOW_THRESHOLD => Level::Low,
+		_ => Level::None,
+	}
+}
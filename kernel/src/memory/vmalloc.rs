@@ -0,0 +1,223 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+//! The vmalloc allocator allows to allocate memory which is contiguous in kernelspace virtual
+//! memory, but backed by physical pages that are not necessarily contiguous.
+//!
+//! This is useful for large allocations, for which finding a physically contiguous chunk with
+//! the buddy allocator is likely to fail because of fragmentation.
+//!
+//! Allocations are placed in a dedicated range of virtual memory at the end of the kernelspace.
+//! The physical memory that is linearly mapped on this range is never given to the kernel zone,
+//! thus remapping the range does not hide memory in use by the kernel.
+
+use super::{buddy, vmem, VirtAddr};
+use crate::process::oom;
+use core::{alloc::AllocError, mem, num::NonZeroUsize, ptr::NonNull, slice};
+use utils::{errno::AllocResult, limits::PAGE_SIZE, lock::Mutex};
+
+/// The beginning of the range of virtual memory used for vmalloc allocations.
+pub const VMALLOC_BEGIN: VirtAddr = VirtAddr(0xf8000000);
+/// The size of the range of virtual memory used for vmalloc allocations, in pages.
+pub const VMALLOC_PAGES: usize = (usize::MAX - VMALLOC_BEGIN.0 + 1) / PAGE_SIZE;
+
+/// Flags for vmalloc mappings in virtual memory.
+const VMALLOC_FLAGS: u32 = vmem::x86::FLAG_WRITE | vmem::x86::FLAG_GLOBAL;
+
+/// Bitmap of the pages of the vmalloc range. A set bit means the page is in use.
+static USED_PAGES: Mutex<[u32; VMALLOC_PAGES / 32]> = Mutex::new([0; VMALLOC_PAGES / 32]);
+
+/// Tells whether the `n`th page of the vmalloc range is used in `bitmap`.
+fn is_used(bitmap: &[u32], n: usize) -> bool {
+	bitmap[n / 32] & (1 << (n % 32)) != 0
+}
+
+/// Sets the state of the pages in range `begin..(begin + pages)` in `bitmap`.
+fn set_used(bitmap: &mut [u32], begin: usize, pages: usize, used: bool) {
+	for n in begin..(begin + pages) {
+		if used {
+			bitmap[n / 32] |= 1 << (n % 32);
+		} else {
+			bitmap[n / 32] &= !(1 << (n % 32));
+		}
+	}
+}
+
+/// Reserves a range of `pages` pages in the vmalloc range and returns the offset of its first
+/// page.
+///
+/// If no free range is large enough, the function returns an error.
+fn reserve_range(pages: usize) -> AllocResult<usize> {
+	let mut bitmap = USED_PAGES.lock();
+	// First fit
+	let mut begin = 0;
+	while begin + pages <= VMALLOC_PAGES {
+		match (begin..(begin + pages)).find(|n| is_used(&*bitmap, *n)) {
+			// Skip after the used page
+			Some(n) => begin = n + 1,
+			None => {
+				set_used(&mut *bitmap, begin, pages, true);
+				return Ok(begin);
+			}
+		}
+	}
+	Err(AllocError)
+}
+
+/// Releases the range of `pages` pages starting at offset `begin` in the vmalloc range.
+fn release_range(begin: usize, pages: usize) {
+	set_used(&mut *USED_PAGES.lock(), begin, pages, false);
+}
+
+/// A chunk of memory that is contiguous in kernelspace virtual memory.
+///
+/// The memory is freed when the structure is dropped.
+#[derive(Debug)]
+pub struct VMalloc {
+	/// The offset of the first page of the chunk in the vmalloc range.
+	off: usize,
+	/// The number of pages in the chunk.
+	pages: NonZeroUsize,
+}
+
+impl VMalloc {
+	/// Allocates a chunk of `pages` pages.
+	///
+	/// The content of the allocated memory is undefined.
+	///
+	/// If not enough physical or virtual memory is available, the function returns an error.
+	pub fn new(pages: NonZeroUsize) -> AllocResult<Self> {
+		let off = reserve_range(pages.get())?;
+		let chunk = Self {
+			off,
+			pages,
+		};
+		let mut vmem = vmem::kernel().lock();
+		let mut transaction = vmem.transaction();
+		let mut mapped = 0;
+		let res = (0..pages.get()).try_for_each(|i| {
+			let physaddr = buddy::alloc(0, buddy::FLAG_ZONE_TYPE_USER)?;
+			let res = transaction.map(physaddr, chunk.page_addr(i), VMALLOC_FLAGS);
+			if res.is_err() {
+				unsafe {
+					buddy::free(physaddr, 0);
+				}
+			} else {
+				mapped += 1;
+			}
+			res
+		});
+		if let Err(e) = res {
+			// Free the pages that have been mapped. The mappings are rolled back when dropping the
+			// transaction
+			for i in 0..mapped {
+				if let Some(physaddr) = transaction.vmem.translate(chunk.page_addr(i)) {
+					unsafe {
+						buddy::free(physaddr, 0);
+					}
+				}
+			}
+			drop(transaction);
+			drop(vmem);
+			release_range(off, pages.get());
+			// The chunk does not own any page
+			mem::forget(chunk);
+			return Err(e);
+		}
+		transaction.commit();
+		Ok(chunk)
+	}
+
+	/// Returns the virtual address of the `n`th page of the chunk.
+	fn page_addr(&self, n: usize) -> VirtAddr {
+		VMALLOC_BEGIN + (self.off + n) * PAGE_SIZE
+	}
+
+	/// Returns the number of pages in the chunk.
+	pub fn pages(&self) -> NonZeroUsize {
+		self.pages
+	}
+
+	/// Returns the pointer to the beginning of the chunk.
+	pub fn as_ptr(&self) -> NonNull<u8> {
+		NonNull::new(self.page_addr(0).as_ptr()).unwrap()
+	}
+
+	/// Returns an immutable slice over the chunk's memory.
+	pub fn as_slice(&self) -> &[u8] {
+		unsafe { slice::from_raw_parts(self.as_ptr().as_ptr(), self.pages.get() * PAGE_SIZE) }
+	}
+
+	/// Returns a mutable slice over the chunk's memory.
+	pub fn as_slice_mut(&mut self) -> &mut [u8] {
+		unsafe { slice::from_raw_parts_mut(self.as_ptr().as_ptr(), self.pages.get() * PAGE_SIZE) }
+	}
+}
+
+impl Drop for VMalloc {
+	fn drop(&mut self) {
+		let mut vmem = vmem::kernel().lock();
+		for i in 0..self.pages.get() {
+			let virtaddr = self.page_addr(i);
+			let Some(physaddr) = vmem.translate(virtaddr) else {
+				continue;
+			};
+			// Restore the linear mapping
+			oom::wrap(|| {
+				let mut transaction = vmem.transaction();
+				transaction.map(
+					virtaddr.kernel_to_physical().unwrap(),
+					virtaddr,
+					VMALLOC_FLAGS,
+				)?;
+				transaction.commit();
+				Ok(())
+			});
+			unsafe {
+				buddy::free(physaddr, 0);
+			}
+		}
+		drop(vmem);
+		release_range(self.off, self.pages.get());
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test_case]
+	fn vmalloc0() {
+		let alloc_pages = buddy::allocated_pages_count();
+		{
+			let mut chunk = VMalloc::new(NonZeroUsize::new(16).unwrap()).unwrap();
+			chunk.as_slice_mut().fill(!0);
+			assert!(chunk.as_slice().iter().all(|b| *b == !0));
+		}
+		assert_eq!(buddy::allocated_pages_count(), alloc_pages);
+	}
+
+	#[test_case]
+	fn vmalloc1() {
+		let a = VMalloc::new(NonZeroUsize::new(3).unwrap()).unwrap();
+		let b = VMalloc::new(NonZeroUsize::new(5).unwrap()).unwrap();
+		// Chunks must not overlap
+		let a_end = a.as_ptr().as_ptr() as usize + a.pages().get() * PAGE_SIZE;
+		let b_end = b.as_ptr().as_ptr() as usize + b.pages().get() * PAGE_SIZE;
+		assert!(a_end <= b.as_ptr().as_ptr() as usize || b_end <= a.as_ptr().as_ptr() as usize);
+	}
+}
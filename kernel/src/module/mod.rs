@@ -28,6 +28,7 @@
 //!
 //! Thus, **Kernel Modules** contain **Modules**.
 
+pub mod param;
 pub mod version;
 
 use crate::{
@@ -0,0 +1,310 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+//! Parameters allow drivers and other subsystems to declare typed configuration values.
+//!
+//! A parameter is identified by the name of the module declaring it and its own name. Its value
+//! can be set:
+//! - at boot, with a `<module>.<name>=<value>` argument on the kernel's command line
+//! - at runtime, by writing to `/sys/module/<module>/parameters/<name>` (if writable)
+//!
+//! Example:
+//! ```rust
+//! static QUEUE_DEPTH: Param<u32> = Param::new("ide", "queue_depth", 65536, true, None);
+//!
+//! param::register(&QUEUE_DEPTH)?;
+//! let depth = QUEUE_DEPTH.get();
+//! ```
+
+use crate::{cmdline, multiboot, println};
+use core::{
+	fmt,
+	fmt::{Debug, Display, Formatter},
+	ptr, str,
+};
+use utils::{collections::vec::Vec, errno, errno::EResult, lock::Mutex, DisplayableStr};
+
+/// A type that can be used as the value of a parameter.
+pub trait ParamType: 'static + Copy + Send + Display {
+	/// Parses a value from the given string.
+	///
+	/// If the string is invalid, the function returns `None`.
+	fn parse(s: &[u8]) -> Option<Self>;
+}
+
+impl ParamType for bool {
+	fn parse(s: &[u8]) -> Option<Self> {
+		match s {
+			b"1" | b"y" | b"Y" | b"true" => Some(true),
+			b"0" | b"n" | b"N" | b"false" => Some(false),
+			_ => None,
+		}
+	}
+}
+
+macro_rules! impl_param_int {
+	($($t:ty),*) => {
+		$(
+			impl ParamType for $t {
+				fn parse(s: &[u8]) -> Option<Self> {
+					str::from_utf8(s).ok()?.parse().ok()
+				}
+			}
+		)*
+	};
+}
+
+impl_param_int!(u8, u16, u32, u64, usize, i8, i16, i32, i64, isize);
+
+/// Type-erased operations on a parameter.
+pub trait ParamOps: Debug + Sync {
+	/// Returns the name of the module declaring the parameter.
+	fn module(&self) -> &'static str;
+	/// Returns the name of the parameter.
+	fn name(&self) -> &'static str;
+	/// Tells whether the parameter can be modified at runtime.
+	fn is_writable(&self) -> bool;
+
+	/// Parses the given string and sets the parameter's value.
+	///
+	/// If the string is invalid, the function returns [`errno::EINVAL`].
+	fn set_from_str(&self, s: &[u8]) -> EResult<()>;
+	/// Writes the current value of the parameter on `f`.
+	fn fmt_value(&self, f: &mut Formatter<'_>) -> fmt::Result;
+}
+
+/// A parameter with a value of type `T`.
+pub struct Param<T: ParamType> {
+	/// The name of the module declaring the parameter.
+	module: &'static str,
+	/// The name of the parameter.
+	name: &'static str,
+	/// Whether the parameter can be modified at runtime.
+	writable: bool,
+	/// Function called each time the value of the parameter changes, with the new value.
+	on_change: Option<fn(T)>,
+
+	/// The current value.
+	value: Mutex<T>,
+}
+
+impl<T: ParamType> Param<T> {
+	/// Creates a new parameter.
+	///
+	/// Arguments:
+	/// - `module` is the name of the module declaring the parameter
+	/// - `name` is the name of the parameter
+	/// - `default` is the value of the parameter if not set on the command line
+	/// - `writable` tells whether the parameter can be modified at runtime, through sysfs
+	/// - `on_change` is a function called each time the value changes, with the new value
+	pub const fn new(
+		module: &'static str,
+		name: &'static str,
+		default: T,
+		writable: bool,
+		on_change: Option<fn(T)>,
+	) -> Self {
+		Self {
+			module,
+			name,
+			writable,
+			on_change,
+
+			value: Mutex::new(default),
+		}
+	}
+
+	/// Returns the current value of the parameter.
+	pub fn get(&self) -> T {
+		*self.value.lock()
+	}
+
+	/// Sets the value of the parameter, then calls the change notification callback, if any.
+	pub fn set(&self, val: T) {
+		*self.value.lock() = val;
+		if let Some(on_change) = self.on_change {
+			on_change(val);
+		}
+	}
+}
+
+impl<T: ParamType> Debug for Param<T> {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		write!(f, "{}.{}={}", self.module, self.name, self.get())
+	}
+}
+
+impl<T: ParamType> ParamOps for Param<T> {
+	fn module(&self) -> &'static str {
+		self.module
+	}
+
+	fn name(&self) -> &'static str {
+		self.name
+	}
+
+	fn is_writable(&self) -> bool {
+		self.writable
+	}
+
+	fn set_from_str(&self, s: &[u8]) -> EResult<()> {
+		let val = T::parse(s).ok_or_else(|| errno!(EINVAL))?;
+		self.set(val);
+		Ok(())
+	}
+
+	fn fmt_value(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		write!(f, "{}", self.get())
+	}
+}
+
+/// The list of registered parameters, in registration order.
+static PARAMS: Mutex<Vec<&'static dyn ParamOps>> = Mutex::new(Vec::new());
+
+/// Registers the given parameter.
+///
+/// If a value for the parameter is present on the kernel's command line, it is applied. If this
+/// value is invalid, it is ignored and a warning is printed.
+///
+/// If a parameter with the same module and name is already registered, the function returns
+/// [`errno::EEXIST`].
+pub fn register(param: &'static dyn ParamOps) -> EResult<()> {
+	let mut params = PARAMS.lock();
+	let exists = params
+		.iter()
+		.any(|p| p.module() == param.module() && p.name() == param.name());
+	if exists {
+		return Err(errno!(EEXIST));
+	}
+	// Apply value from the command line
+	let cmdline = multiboot::get_boot_info().cmdline.unwrap_or_default();
+	let val = cmdline::params(cmdline)
+		.filter(|(module, name, _)| {
+			*module == param.module().as_bytes() && *name == param.name().as_bytes()
+		})
+		.last();
+	if let Some((_, _, val)) = val {
+		if param.set_from_str(val).is_err() {
+			println!(
+				"Ignoring invalid value `{}` for parameter `{}.{}`",
+				DisplayableStr(val),
+				param.module(),
+				param.name()
+			);
+		}
+	}
+	params.push(param)?;
+	Ok(())
+}
+
+/// Unregisters the given parameter.
+///
+/// This must be called before unloading the kernel module that declares the parameter.
+pub fn unregister(param: &'static dyn ParamOps) {
+	PARAMS
+		.lock()
+		.retain(|p| !ptr::addr_eq(*p as *const dyn ParamOps, param as *const _));
+}
+
+/// Returns the parameter with the given module and name.
+pub fn get(module: &[u8], name: &[u8]) -> Option<&'static dyn ParamOps> {
+	PARAMS
+		.lock()
+		.iter()
+		.find(|p| p.module().as_bytes() == module && p.name().as_bytes() == name)
+		.cloned()
+}
+
+/// Returns the name of the first module with registered parameters whose first parameter is
+/// located at index `off` or after, along with the index of this parameter.
+///
+/// This is used to iterate on modules with parameters.
+pub fn next_module(off: usize) -> Option<(&'static str, usize)> {
+	let params = PARAMS.lock();
+	params
+		.iter()
+		.enumerate()
+		.skip(off)
+		.find(|(i, p)| !params[..*i].iter().any(|p2| p2.module() == p.module()))
+		.map(|(i, p)| (p.module(), i))
+}
+
+/// Returns the first parameter of the module `module` located at index `off` or after, along
+/// with its index.
+///
+/// This is used to iterate on the parameters of a module.
+pub fn next_param(module: &[u8], off: usize) -> Option<(&'static dyn ParamOps, usize)> {
+	PARAMS
+		.lock()
+		.iter()
+		.enumerate()
+		.skip(off)
+		.find(|(_, p)| p.module().as_bytes() == module)
+		.map(|(i, p)| (*p, i))
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use core::sync::atomic::{AtomicU32, Ordering::Relaxed};
+
+	/// The last value passed to [`notify`].
+	static NOTIFIED: AtomicU32 = AtomicU32::new(0);
+
+	fn notify(val: u32) {
+		NOTIFIED.store(val, Relaxed);
+	}
+
+	static TEST_PARAM: Param<u32> = Param::new("test", "value", 1, true, Some(notify));
+
+	#[test_case]
+	fn param_parse() {
+		assert_eq!(bool::parse(b"y"), Some(true));
+		assert_eq!(bool::parse(b"0"), Some(false));
+		assert_eq!(bool::parse(b"maybe"), None);
+		assert_eq!(u32::parse(b"16"), Some(16));
+		assert_eq!(u32::parse(b"-1"), None);
+		assert_eq!(i8::parse(b"-1"), Some(-1));
+		assert_eq!(u8::parse(b"256"), None);
+		assert_eq!(u8::parse(b""), None);
+	}
+
+	#[test_case]
+	fn param_set() {
+		assert_eq!(TEST_PARAM.get(), 1);
+		TEST_PARAM.set_from_str(b"16").unwrap();
+		assert_eq!(TEST_PARAM.get(), 16);
+		assert_eq!(NOTIFIED.load(Relaxed), 16);
+		// An invalid value leaves the parameter unchanged
+		assert_eq!(TEST_PARAM.set_from_str(b"x"), Err(errno!(EINVAL)));
+		assert_eq!(TEST_PARAM.get(), 16);
+		TEST_PARAM.set(1);
+		assert_eq!(NOTIFIED.load(Relaxed), 1);
+	}
+
+	#[test_case]
+	fn param_register() {
+		register(&TEST_PARAM).unwrap();
+		assert_eq!(register(&TEST_PARAM), Err(errno!(EEXIST)));
+		let param = get(b"test", b"value").unwrap();
+		assert!(param.is_writable());
+		assert!(get(b"test", b"other").is_none());
+		assert!(next_param(b"test", 0).is_some());
+		unregister(&TEST_PARAM);
+		assert!(get(b"test", b"value").is_none());
+	}
+}
@@ -21,7 +21,7 @@
 //! ELF structure of the kernel.
 
 use crate::memory::PhysAddr;
-use core::{ffi::c_void, ptr::null, slice};
+use core::{ffi::c_void, mem::size_of, ptr::null, slice};
 use utils::lock::once::OnceInit;
 
 /// Multiboot2 magic number.
@@ -113,9 +113,9 @@ struct TagELFSections {
 }
 
 impl MmapEntry {
-	/// Tells if a Multiboot mmap entry is valid.
-	pub fn is_valid(&self) -> bool {
-		(self.addr + self.len) < (1_u64 << (4 * 8))
+	/// Returns the address to the end of the mapping (exclusive).
+	pub fn end(&self) -> u64 {
+		self.addr.saturating_add(self.len)
 	}
 
 	/// Returns the string describing the memory region according to its type.
@@ -237,7 +237,8 @@ fn handle_tag(boot_info: &mut BootInfo, tag: &Tag) {
 		}
 		TAG_TYPE_MMAP => {
 			let t: &TagMmap = unsafe { reinterpret_tag(tag) };
-			boot_info.memory_maps_size = t.size as usize;
+			// The size of the tag includes its header
+			boot_info.memory_maps_size = (t.size as usize).saturating_sub(size_of::<TagMmap>());
 			boot_info.memory_maps_entry_size = t.entry_size as usize;
 			boot_info.memory_maps = t.entries.as_ptr();
 		}
@@ -18,7 +18,7 @@
 
 //! TODO doc
 
-use core::ptr::NonNull;
+use core::{iter, ptr::NonNull};
 
 /// A linked-list of buffers representing a packet being built.
 ///
@@ -63,4 +63,15 @@ impl<'b> BuffList<'b> {
 
 		front
 	}
+
+	/// Returns an iterator over the buffers of the list, from front to back.
+	pub fn iter(&self) -> impl Iterator<Item = &[u8]> {
+		let mut cur = Some(self);
+		iter::from_fn(move || {
+			let buff = cur?;
+			// Safety: the next buffers outlive the current one
+			cur = buff.next.map(|next| unsafe { next.as_ref() });
+			Some(buff.b)
+		})
+	}
 }
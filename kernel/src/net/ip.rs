@@ -18,11 +18,15 @@
 
 //! This module implements the IP protocol.
 
-use super::{buff::BuffList, osi::Layer};
+use super::{buff::BuffList, osi::Layer, tcp};
 use crate::crypto::checksum;
 use core::mem::size_of;
 use macros::AnyRepr;
-use utils::{boxed::Box, bytes::as_bytes, errno::EResult};
+use utils::{
+	boxed::Box,
+	bytes::{as_bytes, from_bytes},
+	errno::EResult,
+};
 
 /// The default TTL value.
 const DEFAULT_TTL: u8 = 128;
@@ -32,6 +36,10 @@ const FLAG_DF: u8 = 0b010;
 /// IPv4 flag: More fragments are to come after this one
 const FLAG_MF: u8 = 0b100;
 
+/// The bits of the IPv4 flags and fragment offset field that are set on fragments: the
+/// `More Fragments` flag and the fragment offset.
+const FRAGMENT_MASK: u16 = 0x3fff;
+
 /// Protocol: TCP
 pub const PROTO_TCP: u8 = 0x06;
 /// Protocol: UDP
@@ -102,6 +110,60 @@ struct IPv6Header {
 	dst_addr: [u8; 16],
 }
 
+/// A received IPv4 packet.
+#[derive(Debug)]
+struct Packet<'p> {
+	/// Source address.
+	src_addr: [u8; 4],
+	/// Destination address.
+	dst_addr: [u8; 4],
+	/// Protocol number.
+	protocol: u8,
+	/// The data carried by the packet.
+	payload: &'p [u8],
+}
+
+/// Parses the IPv4 packet in `buf`.
+///
+/// If the packet is invalid, corrupted, or is a fragment, the function returns `None`.
+fn parse(buf: &[u8]) -> Option<Packet<'_>> {
+	let hdr: &IPv4Header = from_bytes(buf)?;
+	if hdr.version_ihl >> 4 != 4 {
+		return None;
+	}
+	let hdr_len = (hdr.version_ihl & 0xf) as usize * 4;
+	let total_len = u16::from_be(hdr.total_length) as usize;
+	if hdr_len < size_of::<IPv4Header>() || total_len < hdr_len || total_len > buf.len() {
+		return None;
+	}
+	if checksum::compute_rfc1071(&buf[..hdr_len]) != 0 {
+		return None;
+	}
+	// TODO reassemble fragments
+	if u16::from_be(hdr.flags_fragment_offset) & FRAGMENT_MASK != 0 {
+		return None;
+	}
+	Some(Packet {
+		src_addr: hdr.src_addr,
+		dst_addr: hdr.dst_addr,
+		protocol: hdr.protocol,
+		payload: &buf[hdr_len..total_len],
+	})
+}
+
+/// Passes the IPv4 packet in `buf`, received from a network interface, to the transport layer.
+///
+/// Invalid packets and packets of unsupported protocols are dropped.
+pub fn receive(buf: &[u8]) {
+	let Some(packet) = parse(buf) else {
+		return;
+	};
+	// TODO UDP and ICMP
+	if packet.protocol == PROTO_TCP {
+		tcp::receive(packet.src_addr, packet.dst_addr, packet.payload);
+	}
+}
+
 /// The network layer for the IPv4 protocol.
 #[derive(Debug)]
 pub struct IPv4Layer {
@@ -159,3 +221,59 @@ pub fn inet6_build(_sockaddr: &[u8]) -> EResult<Box<dyn Layer>> {
 	// TODO
 	todo!()
 }
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use utils::collections::vec::Vec;
+
+	/// Builds an IPv4 packet carrying `payload`, with the given flags and fragment offset.
+	fn build(flags_fragment_offset: u16, payload: &[u8]) -> Vec<u8> {
+		let mut hdr = IPv4Header {
+			version_ihl: (4 << 4) | 5,
+			type_of_service: 0,
+			total_length: ((size_of::<IPv4Header>() + payload.len()) as u16).to_be(),
+
+			identification: 0,
+			flags_fragment_offset: flags_fragment_offset.to_be(),
+
+			ttl: DEFAULT_TTL,
+			protocol: PROTO_TCP,
+			hdr_checksum: 0,
+
+			src_addr: [10, 0, 2, 2],
+			dst_addr: [10, 0, 2, 15],
+		};
+		hdr.compute_checksum();
+		let mut buf = Vec::new();
+		buf.extend_from_slice(as_bytes(&hdr)).unwrap();
+		buf.extend_from_slice(payload).unwrap();
+		buf
+	}
+
+	#[test_case]
+	fn ipv4_parse() {
+		let mut buf = build(0x4000, b"payload");
+		// Ethernet frames may be padded
+		buf.extend_from_slice(&[0; 4]).unwrap();
+		let packet = parse(&buf).unwrap();
+		assert_eq!(packet.src_addr, [10, 0, 2, 2]);
+		assert_eq!(packet.dst_addr, [10, 0, 2, 15]);
+		assert_eq!(packet.protocol, PROTO_TCP);
+		assert_eq!(packet.payload, b"payload");
+	}
+
+	#[test_case]
+	fn ipv4_parse_invalid() {
+		let mut buf = build(0, b"payload");
+		// Truncated
+		assert!(parse(&buf[..10]).is_none());
+		assert!(parse(&buf[..(buf.len() - 1)]).is_none());
+		// Corrupted header
+		buf[8] ^= 1;
+		assert!(parse(&buf).is_none());
+		// Fragments
+		assert!(parse(&build(0x2000, b"payload")).is_none());
+		assert!(parse(&build(0x0001, b"payload")).is_none());
+	}
+}
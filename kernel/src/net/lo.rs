@@ -19,7 +19,11 @@
 //! This module implements the local loopback.
 
 use super::{buff::BuffList, Address, BindAddress, Interface, MAC};
-use utils::errno::EResult;
+use crate::module::param::Param;
+use utils::{errno, errno::EResult};
+
+/// The Maximum Transmission Unit of the local loopback, in bytes.
+pub static MTU: Param<u32> = Param::new("lo", "mtu", 65536, true, None);
 
 /// Local loopback interfaces allows the system to write data to itself.
 pub struct LocalLoopback {}
@@ -53,13 +57,35 @@ impl Interface for LocalLoopback {
 		]
 	}
 
+	fn get_mtu(&self) -> u32 {
+		MTU.get()
+	}
+
 	fn read(&mut self, _buff: &mut [u8]) -> EResult<u64> {
 		// TODO Write to ring buffer
 		todo!();
 	}
 
-	fn write(&mut self, _buff: &BuffList<'_>) -> EResult<u64> {
+	fn write(&mut self, buff: &BuffList<'_>) -> EResult<u64> {
+		if buff.len() > self.get_mtu() as usize {
+			return Err(errno!(EMSGSIZE));
+		}
 		// TODO Read from ring buffer
 		todo!();
 	}
 }
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test_case]
+	fn lo_mtu() {
+		let lo = LocalLoopback {};
+		let default = MTU.get();
+		assert_eq!(lo.get_mtu(), 65536);
+		MTU.set(1500);
+		assert_eq!(lo.get_mtu(), 1500);
+		MTU.set(default);
+	}
+}
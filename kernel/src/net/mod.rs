@@ -26,13 +26,14 @@ pub mod netlink;
 pub mod osi;
 pub mod sockaddr;
 pub mod tcp;
+pub mod unix;
 
 use crate::{
 	file::perm::AccessProfile,
 	net::sockaddr::{SockAddrIn, SockAddrIn6},
 };
 use buff::BuffList;
-use core::{cmp::Ordering, mem::size_of};
+use core::{cmp::Ordering, ffi::c_short, mem::size_of};
 use utils::{
 	collections::{hashmap::HashMap, string::String, vec::Vec},
 	errno,
@@ -44,6 +45,13 @@ use utils::{
 /// Type representing a Media Access Control (MAC) address.
 pub type MAC = [u8; 6];
 
+/// The size of an Ethernet header.
+const ETH_HDR_SIZE: usize = 14;
+/// The maximum size of an Ethernet frame, without its checksum.
+const ETH_FRAME_MAX: usize = 1518;
+/// The EtherType of IPv4.
+const ETHERTYPE_IPV4: u16 = 0x0800;
+
 // TODO allow implementation of custom protocols
 
 /// An enumeration of network address types.
@@ -105,6 +113,10 @@ pub trait Interface {
 	/// Returns the list of addresses bound to the interface.
 	fn get_addresses(&self) -> &[BindAddress];
 
+	/// Returns the Maximum Transmission Unit of the interface, that is the maximum size of a
+	/// packet it can transmit, in bytes.
+	fn get_mtu(&self) -> u32;
+
 	/// Reads data from the network interface and writes it into `buff`.
 	///
 	/// The function returns the number of bytes read.
@@ -226,6 +238,34 @@ pub fn get_iface_for(addr: Address) -> Option<Arc<Mutex<dyn Interface>>> {
 	get_iface(&route.iface)
 }
 
+/// Reads the frames received by the network interface with the given name, and passes them to
+/// the network layer.
+///
+/// Frames of unsupported protocols are dropped.
+pub fn receive(name: &[u8]) {
+	let Some(iface) = get_iface(name) else {
+		return;
+	};
+	let mut buf = [0u8; ETH_FRAME_MAX];
+	loop {
+		// The interface is not locked while the frame is processed
+		let res = iface.lock().read(&mut buf);
+		// Stop when no frame is left
+		let Ok(len) = res else {
+			break;
+		};
+		let frame = &buf[..len as usize];
+		if frame.len() < ETH_HDR_SIZE {
+			continue;
+		}
+		// TODO IPv6 and ARP
+		let ethertype = u16::from_be_bytes([frame[12], frame[13]]);
+		if ethertype == ETHERTYPE_IPV4 {
+			ip::receive(&frame[ETH_HDR_SIZE..]);
+		}
+	}
+}
+
 /// Enumeration of socket domains.
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 pub enum SocketDomain {
@@ -272,6 +312,7 @@ impl SocketDomain {
 	/// Returns the size of the sockaddr structure for the domain.
 	pub fn get_sockaddr_len(&self) -> usize {
 		match self {
+			Self::AfUnix => size_of::<c_short>() + sockaddr::UNIX_PATH_MAX,
 			Self::AfInet => size_of::<SockAddrIn>(),
 			Self::AfInet6 => size_of::<SockAddrIn6>(),
 			// TODO add others
@@ -347,7 +388,7 @@ impl AccessProfile {
 }
 
 /// Socket network stack descriptor.
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug)]
 pub struct SocketDesc {
 	/// The socket's domain.
 	pub domain: SocketDomain,
@@ -18,7 +18,8 @@
 
 //! The Open Systems Interconnection (OSI) model defines the architecure of a network stack.
 
-use super::{buff::BuffList, ip, SocketDesc, SocketDomain, SocketType};
+use super::{buff::BuffList, ip, lo, SocketDesc, SocketDomain, SocketType};
+use crate::module::param;
 use core::fmt::Debug;
 use utils::{boxed::Box, collections::hashmap::HashMap, errno, errno::EResult, lock::Mutex};
 
@@ -101,8 +102,9 @@ impl Stack {
 	}
 }
 
-/// Registers default domains/types/protocols.
+/// Registers default domains/types/protocols, and the parameters of network interfaces.
 pub(crate) fn init() -> EResult<()> {
+	param::register(&lo::MTU)?;
 	let domains = HashMap::try_from([
 		// TODO unix
 		(
@@ -19,8 +19,12 @@
 //! This module defines sockaddr structures used by system calls to define connection informations
 //! on sockets.
 
-use super::Address;
-use core::ffi::c_short;
+use super::{Address, SocketDomain};
+use core::{ffi::c_short, mem::size_of};
+use utils::{errno, errno::EResult};
+
+/// The maximum length of the path of a Unix socket address, including the terminating null byte.
+pub const UNIX_PATH_MAX: usize = 108;
 
 /// Structure providing connection informations for sockets with IPv4.
 #[repr(C)]
@@ -61,6 +65,58 @@ pub struct SockAddrIn6 {
 	sin6_scope_id: u32,
 }
 
+/// Returns the path in the Unix socket address `addr`, which is a `sockaddr_un` structure.
+///
+/// The path ends at the first null byte, or at the end of the structure.
+///
+/// If the address is invalid or belongs to the abstract namespace, the function returns
+/// [`errno::EINVAL`].
+pub fn unix_path(addr: &[u8]) -> EResult<&[u8]> {
+	let family_len = size_of::<c_short>();
+	if addr.len() <= family_len || addr.len() > family_len + UNIX_PATH_MAX {
+		return Err(errno!(EINVAL));
+	}
+	let family = u16::from_ne_bytes([addr[0], addr[1]]);
+	if family as u32 != SocketDomain::AfUnix.get_id() {
+		return Err(errno!(EINVAL));
+	}
+	let path = &addr[family_len..];
+	let len = path.iter().position(|b| *b == 0).unwrap_or(path.len());
+	// TODO support the abstract namespace
+	if len == 0 {
+		return Err(errno!(EINVAL));
+	}
+	Ok(&path[..len])
+}
+
+/// Returns the address and port in the IPv4 socket address `addr`, which is a `sockaddr_in`
+/// structure. The port is returned in host byte order.
+///
+/// If the address is too short, the function returns [`errno::EINVAL`]. If it is not an IPv4
+/// address, the function returns [`errno::EAFNOSUPPORT`].
+pub fn inet_addr(addr: &[u8]) -> EResult<([u8; 4], u16)> {
+	if addr.len() < size_of::<SockAddrIn>() {
+		return Err(errno!(EINVAL));
+	}
+	let family = u16::from_ne_bytes([addr[0], addr[1]]);
+	if family as u32 != SocketDomain::AfInet.get_id() {
+		return Err(errno!(EAFNOSUPPORT));
+	}
+	let port = u16::from_be_bytes([addr[2], addr[3]]);
+	Ok(([addr[4], addr[5], addr[6], addr[7]], port))
+}
+
+/// Returns the `sockaddr_in` structure for the IPv4 address `addr` and port `port`, in host byte
+/// order.
+pub fn inet_sockaddr(addr: [u8; 4], port: u16) -> [u8; size_of::<SockAddrIn>()] {
+	let mut buf = [0; size_of::<SockAddrIn>()];
+	let family = SocketDomain::AfInet.get_id() as u16;
+	buf[..2].copy_from_slice(&family.to_ne_bytes());
+	buf[2..4].copy_from_slice(&port.to_be_bytes());
+	buf[4..8].copy_from_slice(&addr);
+	buf
+}
+
 /// A unified structure which contains data passed from userspace.
 #[derive(Debug)]
 pub struct SockAddr {
@@ -72,16 +128,10 @@ pub struct SockAddr {
 
 impl From<SockAddrIn> for SockAddr {
 	fn from(val: SockAddrIn) -> Self {
-		let addr: [u8; 4] = [
-			((val.sin_addr >> 24) & 0xff) as u8,
-			((val.sin_addr >> 16) & 0xff) as u8,
-			((val.sin_addr >> 8) & 0xff) as u8,
-			(val.sin_addr & 0xff) as u8,
-		];
-
+		// Both the address and the port are in network byte order
 		Self {
-			port: val.sin_port as _,
-			addr: Address::IPv4(addr),
+			port: u16::from_be(val.sin_port as _),
+			addr: Address::IPv4(val.sin_addr.to_ne_bytes()),
 		}
 	}
 }
@@ -91,7 +141,7 @@ impl From<SockAddrIn6> for SockAddr {
 		let addr = unsafe { val.sin6_addr.__s6_addr };
 
 		Self {
-			port: val.sin6_port as _,
+			port: u16::from_be(val.sin6_port as _),
 			addr: Address::IPv6(addr),
 		}
 	}
@@ -15,16 +15,83 @@
  * You should have received a copy of the GNU General Public License along with
  * Maestro. If not, see <https://www.gnu.org/licenses/>.
  */
-
 //! The Transmission Control Protocol (TCP) is a protocol transmitting sequenced, reliable,
 //! two-way, connection-based byte streams.
+//!
+//! Each connection is described by a Transmission Control Block ([`Tcb`]), implementing the
+//! state machine of RFC 9293.
+//!
+//! Only IPv4 loopback connections are supported for now: segments are not handed to a network
+//! interface, but queued for reception by the local host, then delivered by [`flush`]. Segments
+//! received from a network interface are queued the same way by [`receive`].
 
 use super::{buff::BuffList, osi::Layer};
-use crate::file::socket::Socket;
-use utils::errno::EResult;
+use crate::{
+	crypto::{checksum::compute_rfc1071, rand},
+	file::wait_queue::WaitQueue,
+	syscall::poll::{POLLERR, POLLHUP, POLLIN, POLLOUT, POLLRDHUP},
+	time::unit::Timestamp,
+	workqueue,
+};
+use core::{
+	cmp::min,
+	mem,
+	mem::{offset_of, size_of},
+	ops::RangeInclusive,
+	ptr,
+	sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+use macros::AnyRepr;
+use utils::{
+	bytes,
+	collections::{hashmap::HashMap, ring_buffer::RingBuffer, vec::Vec},
+	errno,
+	errno::{AllocResult, EResult, Errno},
+	lock::Mutex,
+	ptr::arc::Arc,
+	vec,
+};
+
+/// Segment flag: the sender has no more data to send.
+pub const FLAG_FIN: u8 = 0x01;
+/// Segment flag: synchronize sequence numbers.
+pub const FLAG_SYN: u8 = 0x02;
+/// Segment flag: reset the connection.
+pub const FLAG_RST: u8 = 0x04;
+/// Segment flag: push the data to the receiving application.
+pub const FLAG_PSH: u8 = 0x08;
+/// Segment flag: the acknowledgment number is significant.
+pub const FLAG_ACK: u8 = 0x10;
+
+/// The IP protocol number of TCP.
+pub const IPPROTO_TCP: u8 = 6;
+
+/// The IPv4 address accepting connections on any local address.
+pub const INADDR_ANY: [u8; 4] = [0; 4];
+/// The IPv4 address of the local host.
+pub const INADDR_LOOPBACK: [u8; 4] = [127, 0, 0, 1];
+
+/// The size of the send and receive buffers of a connection, in bytes.
+const BUFFER_SIZE: usize = 16384;
+/// The maximum number of bytes of data in a segment.
+const MSS: usize = 4096;
+/// The initial retransmission timeout, in milliseconds.
+const RTO_INIT: Timestamp = 1000;
+/// The maximum retransmission timeout, in milliseconds.
+const RTO_MAX: Timestamp = 60000;
+/// The number of retransmissions after which the connection is dropped.
+const MAX_RETRIES: u32 = 8;
+/// The time spent in the `TIME-WAIT` state, in milliseconds.
+const TIME_WAIT_DELAY: Timestamp = 60000;
+/// The time after which a closed connection still waiting for the peer's FIN is dropped, in
+/// milliseconds.
+const FIN_TIMEOUT: Timestamp = 60000;
+/// The range of ports given to sockets that are not explicitly bound.
+const EPHEMERAL_PORTS: RangeInclusive<u16> = 32768..=60999;
 
 /// The TCP segment header.
 #[repr(C, packed)]
+#[derive(AnyRepr)]
 pub struct TCPHdr {
 	/// Source port.
 	src_port: u16,
@@ -34,7 +101,7 @@ pub struct TCPHdr {
 	/// Sequence number.
 	seq_nbr: u32,
 
-	/// TODO doc
+	/// If the `ACK` flag is set, the next sequence number the sender expects to receive.
 	ack_nbr: u32,
 
 	/// The size of the header in units of 4 bytes.
@@ -43,15 +110,34 @@ pub struct TCPHdr {
 	data_offset: u8,
 	/// The segment's flags.
 	flags: u8,
-	/// TODO doc
+	/// The number of bytes the sender is willing to receive, starting at `ack_nbr`.
 	win_size: u16,
 
-	/// TODO doc
+	/// The checksum of the pseudo-header, header and data.
 	checksum: u16,
-	/// TODO doc
+	/// If the `URG` flag is set, the offset of the end of urgent data.
 	urg_ptr: u16,
 }
 
+/// The pseudo-header covered by the checksum of a segment.
+///
+/// On the loopback, the pseudo-header is queued in front of the segment, in place of the IP
+/// header.
+#[repr(C, packed)]
+#[derive(AnyRepr)]
+struct PseudoHdr {
+	/// Source address.
+	src_addr: [u8; 4],
+	/// Destination address.
+	dst_addr: [u8; 4],
+	/// Reserved, must be zero.
+	zero: u8,
+	/// The IP protocol number.
+	protocol: u8,
+	/// The length of the header and data of the segment.
+	tcp_len: u16,
+}
+
 /// The network layer for the TCP protocol.
 #[derive(Debug)]
 pub struct TCPLayer {}
@@ -66,8 +152,1322 @@ impl Layer for TCPLayer {
 	}
 }
 
-/// Initiates a TCP connection on the given socket `sock`.
-pub fn init_connection(_sock: &mut Socket) -> EResult<()> {
-	// TODO
-	todo!();
+/// An IPv4 address and port, identifying one end of a connection.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct Endpoint {
+	/// The address.
+	pub addr: [u8; 4],
+	/// The port, in host byte order.
+	pub port: u16,
+}
+
+impl Endpoint {
+	/// Tells whether the address belongs to the loopback network `127.0.0.0/8`.
+	pub fn is_loopback(&self) -> bool {
+		self.addr[0] == 127
+	}
+}
+
+/// The state of a connection.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum State {
+	/// No connection.
+	Closed,
+	/// Waiting for connection requests.
+	Listen,
+	/// A connection request has been sent, waiting for the peer's.
+	SynSent,
+	/// A connection request has been received and answered, waiting for its acknowledgment.
+	SynReceived,
+	/// The connection is open, data can be transferred both ways.
+	Established,
+	/// The local end has closed, waiting for the acknowledgment of its FIN or for the peer's FIN.
+	FinWait1,
+	/// The local end has closed and its FIN is acknowledged, waiting for the peer's FIN.
+	FinWait2,
+	/// The peer has closed, waiting for the local end to close.
+	CloseWait,
+	/// Both ends have closed simultaneously, waiting for the acknowledgment of the local FIN.
+	Closing,
+	/// The peer, then the local end, have closed, waiting for the acknowledgment of the local
+	/// FIN.
+	LastAck,
+	/// Both ends have closed, waiting for the peer to receive the acknowledgment of its FIN.
+	TimeWait,
+}
+
+/// A segment, as received.
+struct Segment<'d> {
+	/// The sender.
+	src: Endpoint,
+	/// The receiver.
+	dst: Endpoint,
+	/// The sequence number.
+	seq: u32,
+	/// The acknowledgment number.
+	ack: u32,
+	/// The segment's flags.
+	flags: u8,
+	/// The window of the sender.
+	wnd: u16,
+	/// The data.
+	data: &'d [u8],
+}
+
+impl Segment<'_> {
+	/// Returns the length of the segment in the sequence space.
+	fn len(&self) -> u32 {
+		let syn = (self.flags & FLAG_SYN != 0) as u32;
+		let fin = (self.flags & FLAG_FIN != 0) as u32;
+		self.data.len() as u32 + syn + fin
+	}
+}
+
+/// Tells whether the sequence number `a` is before `b`, modulo `2^32`.
+fn seq_lt(a: u32, b: u32) -> bool {
+	(a.wrapping_sub(b) as i32) < 0
+}
+
+/// Tells whether the sequence number `a` is before or equal to `b`, modulo `2^32`.
+fn seq_le(a: u32, b: u32) -> bool {
+	a == b || seq_lt(a, b)
+}
+
+/// Returns a random initial sequence number.
+fn initial_seq() -> u32 {
+	let mut iss = [0; 4];
+	if let Some(pool) = &mut *rand::ENTROPY_POOL.lock() {
+		pool.read(&mut iss, true);
+	}
+	u32::from_ne_bytes(iss)
+}
+
+/// Listening connections, by port.
+static LISTENERS: Mutex<HashMap<u16, Arc<Tcb>>> = Mutex::new(HashMap::new());
+/// Connections, by local and remote endpoints.
+static CONNECTIONS: Mutex<HashMap<(Endpoint, Endpoint), Arc<Tcb>>> = Mutex::new(HashMap::new());
+/// Reserved local ports.
+static PORTS: Mutex<HashMap<u16, ()>> = Mutex::new(HashMap::new());
+/// The offset in [`EPHEMERAL_PORTS`] of the next ephemeral port to try.
+static NEXT_EPHEMERAL: AtomicUsize = AtomicUsize::new(0);
+
+/// Segments sent on the loopback and waiting to be delivered, each preceded by its
+/// pseudo-header.
+static LOOPBACK: Mutex<Vec<Vec<u8>>> = Mutex::new(Vec::new());
+/// Tells whether segments on the loopback are being delivered.
+static DRAINING: AtomicBool = AtomicBool::new(false);
+
+/// Reserves the local port `port` and returns it.
+///
+/// If `port` is zero, an ephemeral port is chosen.
+///
+/// If the port is already reserved, or if no ephemeral port is left, the function returns
+/// [`errno::EADDRINUSE`].
+fn reserve_port(port: u16) -> EResult<u16> {
+	let mut ports = PORTS.lock();
+	let port = if port != 0 {
+		(!ports.contains_key(&port)).then_some(port)
+	} else {
+		// Cycle through ephemeral ports, so that a port is not reused right after being released
+		let first = *EPHEMERAL_PORTS.start();
+		let count = EPHEMERAL_PORTS.len();
+		let start = NEXT_EPHEMERAL.load(Ordering::Relaxed);
+		let off = (0..count)
+			.map(|i| (start + i) % count)
+			.find(|off| !ports.contains_key(&(first + *off as u16)));
+		if let Some(off) = off {
+			NEXT_EPHEMERAL.store(off + 1, Ordering::Relaxed);
+		}
+		off.map(|off| first + off as u16)
+	};
+	let port = port.ok_or_else(|| errno!(EADDRINUSE))?;
+	ports.insert(port, ())?;
+	Ok(port)
+}
+
+/// Queues a segment on the loopback.
+///
+/// Arguments:
+/// - `src` and `dst` are the sender and receiver of the segment.
+/// - `seq` and `ack` are the sequence and acknowledgment numbers.
+/// - `flags` are the segment's flags.
+/// - `wnd` is the window of the sender.
+/// - `data` is the data of the segment.
+fn transmit(
+	src: Endpoint,
+	dst: Endpoint,
+	seq: u32,
+	ack: u32,
+	flags: u8,
+	wnd: u16,
+	data: &[u8],
+) -> AllocResult<()> {
+	let tcp_len = size_of::<TCPHdr>() + data.len();
+	let pseudo = PseudoHdr {
+		src_addr: src.addr,
+		dst_addr: dst.addr,
+		zero: 0,
+		protocol: IPPROTO_TCP,
+		tcp_len: (tcp_len as u16).to_be(),
+	};
+	let hdr = TCPHdr {
+		src_port: src.port.to_be(),
+		dst_port: dst.port.to_be(),
+		seq_nbr: seq.to_be(),
+		ack_nbr: ack.to_be(),
+		data_offset: ((size_of::<TCPHdr>() / 4) as u8) << 4,
+		flags,
+		win_size: wnd.to_be(),
+		checksum: 0,
+		urg_ptr: 0,
+	};
+	let mut buf = Vec::with_capacity(size_of::<PseudoHdr>() + tcp_len)?;
+	buf.extend_from_slice(bytes::as_bytes(&pseudo))?;
+	buf.extend_from_slice(bytes::as_bytes(&hdr))?;
+	buf.extend_from_slice(data)?;
+	let checksum = compute_rfc1071(&buf);
+	let off = size_of::<PseudoHdr>() + offset_of!(TCPHdr, checksum);
+	buf[off..(off + 2)].copy_from_slice(&checksum.to_le_bytes());
+	LOOPBACK.lock().push(buf)
+}
+
+/// Parses the segment in `buf`, preceded by its pseudo-header.
+///
+/// If the segment is invalid or corrupted, the function returns `None`.
+fn parse(buf: &[u8]) -> Option<Segment<'_>> {
+	if compute_rfc1071(buf) != 0 {
+		return None;
+	}
+	let pseudo: &PseudoHdr = bytes::from_bytes(buf)?;
+	let seg = &buf[size_of::<PseudoHdr>()..];
+	let hdr: &TCPHdr = bytes::from_bytes(seg)?;
+	let data_off = (hdr.data_offset >> 4) as usize * 4;
+	if data_off < size_of::<TCPHdr>() || data_off > seg.len() {
+		return None;
+	}
+	Some(Segment {
+		src: Endpoint {
+			addr: pseudo.src_addr,
+			port: u16::from_be(hdr.src_port),
+		},
+		dst: Endpoint {
+			addr: pseudo.dst_addr,
+			port: u16::from_be(hdr.dst_port),
+		},
+		seq: u32::from_be(hdr.seq_nbr),
+		ack: u32::from_be(hdr.ack_nbr),
+		flags: hdr.flags,
+		wnd: u16::from_be(hdr.win_size),
+		data: &seg[data_off..],
+	})
+}
+
+/// Answers the segment `seg`, which belongs to no connection, with a reset.
+fn reset(seg: &Segment) {
+	if seg.flags & FLAG_RST != 0 {
+		return;
+	}
+	// On failure, the peer retransmits and gets another chance to be reset
+	let _ = if seg.flags & FLAG_ACK != 0 {
+		transmit(seg.dst, seg.src, seg.ack, 0, FLAG_RST, 0, &[])
+	} else {
+		let ack = seg.seq.wrapping_add(seg.len());
+		transmit(seg.dst, seg.src, 0, ack, FLAG_RST | FLAG_ACK, 0, &[])
+	};
+}
+
+/// Queues the segment in `seg`, received from a network interface, then delivers it.
+///
+/// `src` and `dst` are the source and destination addresses of the IPv4 packet carrying the
+/// segment.
+pub fn receive(src: [u8; 4], dst: [u8; 4], seg: &[u8]) {
+	let pseudo = PseudoHdr {
+		src_addr: src,
+		dst_addr: dst,
+		zero: 0,
+		protocol: IPPROTO_TCP,
+		tcp_len: (seg.len() as u16).to_be(),
+	};
+	let res = (|| {
+		let mut buf = Vec::with_capacity(size_of::<PseudoHdr>() + seg.len())?;
+		buf.extend_from_slice(bytes::as_bytes(&pseudo))?;
+		buf.extend_from_slice(seg)?;
+		LOOPBACK.lock().push(buf)
+	})();
+	// On failure, the segment is dropped and the peer retransmits it
+	if res.is_ok() {
+		flush();
+	}
+}
+
+/// Delivers the segments queued on the loopback to their connections.
+///
+/// Segments sent while delivering are delivered too. If segments are already being delivered
+/// by another caller, the function returns immediately and the segments are delivered by that
+/// caller.
+pub fn flush() {
+	loop {
+		if DRAINING.swap(true, Ordering::Acquire) {
+			return;
+		}
+		loop {
+			let buf = {
+				let mut queue = LOOPBACK.lock();
+				if queue.is_empty() {
+					break;
+				}
+				queue.remove(0)
+			};
+			deliver(&buf);
+		}
+		DRAINING.store(false, Ordering::Release);
+		// Segments might have been queued after the last check
+		if LOOPBACK.lock().is_empty() {
+			break;
+		}
+	}
+}
+
+/// Delivers the segment in `buf` to its connection.
+fn deliver(buf: &[u8]) {
+	let Some(seg) = parse(buf) else {
+		return;
+	};
+	let conn = CONNECTIONS.lock().get(&(seg.dst, seg.src)).cloned();
+	if let Some(tcb) = conn {
+		input(&tcb, &seg);
+		return;
+	}
+	let listener = LISTENERS.lock().get(&seg.dst.port).cloned();
+	match listener {
+		Some(tcb) => input_listen(&tcb, &seg),
+		None => reset(&seg),
+	}
+}
+
+/// The state of a listening connection.
+///
+/// Each of the queues holds at most `max` connections. When either queue is full, incoming
+/// connection requests are dropped, and the peers retransmit them later.
+#[derive(Debug)]
+struct Listener {
+	/// The maximum number of connections in each queue, given by `listen`.
+	max: usize,
+	/// The SYN queue: connections whose handshake is in progress.
+	syn_queue: Vec<Arc<Tcb>>,
+	/// The accept queue: established connections waiting to be accepted.
+	pending: Vec<Arc<Tcb>>,
+}
+
+impl Listener {
+	/// Tells whether the accept queue is full.
+	fn is_full(&self) -> bool {
+		self.pending.len() >= self.max
+	}
+}
+
+#[derive(Debug)]
+struct TcbInner {
+	/// The state of the connection.
+	state: State,
+	/// The local end.
+	local: Endpoint,
+	/// The remote end.
+	remote: Endpoint,
+	/// Tells whether the connection is registered in [`CONNECTIONS`].
+	registered: bool,
+	/// Tells whether the connection holds the reservation of its local port.
+	owns_port: bool,
+	/// Tells whether the connection has been established at some point.
+	connected: bool,
+	/// Tells whether the socket of the connection has been closed.
+	orphan: bool,
+
+	/// If listening, the queues of incoming connections.
+	listen: Option<Listener>,
+	/// The listening connection this connection has been created from, until it is established.
+	listener: Option<Arc<Tcb>>,
+
+	/// The oldest sequence number that has not been acknowledged by the peer.
+	snd_una: u32,
+	/// The next sequence number to be sent.
+	snd_nxt: u32,
+	/// The window of the peer.
+	snd_wnd: u32,
+	/// The next sequence number expected from the peer.
+	rcv_nxt: u32,
+
+	/// Data queued for transmission and not acknowledged yet, starting at `snd_una`.
+	tx: Vec<u8>,
+	/// Received data that has not been read yet.
+	rx: RingBuffer<u8, Vec<u8>>,
+
+	/// Tells whether a FIN is to be sent after the queued data.
+	fin_queued: bool,
+	/// Tells whether the FIN has been sent.
+	fin_sent: bool,
+	/// Tells whether the FIN of the peer has been received.
+	fin_received: bool,
+	/// Tells whether the reception side has been shut down.
+	rd_shutdown: bool,
+	/// The error to be reported on the next operation.
+	error: Option<Errno>,
+
+	/// The retransmission timeout, in milliseconds.
+	rto: Timestamp,
+	/// The number of retransmissions since data was last acknowledged.
+	retries: u32,
+	/// Tells whether the retransmission timer is armed.
+	timer: bool,
+}
+
+impl TcbInner {
+	/// Returns the window to advertise to the peer.
+	fn rcv_wnd(&self) -> u16 {
+		min(self.rx.get_available_len(), u16::MAX as usize) as u16
+	}
+
+	/// Sends a segment on the connection.
+	///
+	/// On failure, the segment is lost. Segments occupying sequence numbers are retransmitted
+	/// later.
+	fn send_segment(&self, seq: u32, flags: u8, data: &[u8]) {
+		let ack = if flags & FLAG_ACK != 0 {
+			self.rcv_nxt
+		} else {
+			0
+		};
+		let wnd = self.rcv_wnd();
+		let _ = transmit(self.local, self.remote, seq, ack, flags, wnd, data);
+	}
+
+	/// Moves the connection to the `ESTABLISHED` state.
+	fn establish(&mut self) {
+		self.state = State::Established;
+		self.connected = true;
+		self.retries = 0;
+		self.rto = RTO_INIT;
+	}
+
+	/// Releases the reservation of the local port, if held.
+	fn release_port(&mut self) {
+		if mem::take(&mut self.owns_port) {
+			PORTS.lock().remove(&self.local.port);
+		}
+	}
+
+	/// Moves the connection to the `CLOSED` state, forgetting it.
+	fn set_closed(&mut self) {
+		self.state = State::Closed;
+		self.tx.clear();
+		if mem::take(&mut self.registered) {
+			CONNECTIONS.lock().remove(&(self.local, self.remote));
+		}
+		if self.orphan {
+			self.release_port();
+		}
+	}
+}
+
+/// Arms the retransmission timer of the connection `tcb`, if not armed already.
+fn arm_timer(tcb: &Arc<Tcb>, inner: &mut TcbInner) {
+	if inner.timer {
+		return;
+	}
+	let tcb = tcb.clone();
+	let res = workqueue::queue_delayed_work(
+		move || {
+			timeout(&tcb);
+			flush();
+		},
+		inner.rto,
+	);
+	inner.timer = res.is_ok();
+}
+
+/// Handles the expiry of the retransmission timer of the connection `tcb`, retransmitting
+/// unacknowledged segments.
+fn timeout(tcb: &Arc<Tcb>) {
+	let mut inner = tcb.inner.lock();
+	inner.timer = false;
+	if inner.state == State::Closed || inner.snd_una == inner.snd_nxt {
+		return;
+	}
+	inner.retries += 1;
+	if inner.retries > MAX_RETRIES {
+		inner.error = Some(errno!(ETIMEDOUT));
+		inner.set_closed();
+		let listener = inner.listener.take();
+		drop(inner);
+		tcb.wait.wake_all();
+		if let Some(listener) = listener {
+			hand_over(&listener, tcb, false);
+		}
+		return;
+	}
+	inner.rto = min(inner.rto * 2, RTO_MAX);
+	match inner.state {
+		State::SynSent => inner.send_segment(inner.snd_una, FLAG_SYN, &[]),
+		State::SynReceived => inner.send_segment(inner.snd_una, FLAG_SYN | FLAG_ACK, &[]),
+		_ => {
+			// Go back to the oldest unacknowledged data
+			inner.snd_nxt = inner.snd_una;
+			inner.fin_sent = false;
+			output(tcb, &mut inner);
+		}
+	}
+	arm_timer(tcb, &mut inner);
+}
+
+/// Forgets the connection `tcb` after `delay` milliseconds, if it is still in the
+/// `TIME-WAIT` state, or is closed and in the `FIN-WAIT-2` state.
+fn linger(tcb: &Arc<Tcb>, inner: &mut TcbInner, delay: Timestamp) {
+	let tcb = tcb.clone();
+	let res = workqueue::queue_delayed_work(
+		move || {
+			let mut inner = tcb.inner.lock();
+			let orphan = inner.orphan;
+			if inner.state == State::TimeWait || (inner.state == State::FinWait2 && orphan) {
+				inner.set_closed();
+			}
+		},
+		delay,
+	);
+	if res.is_err() {
+		inner.set_closed();
+	}
+}
+
+/// Sends the queued data and FIN that can be sent on the connection `tcb`.
+///
+/// The function returns `true` if at least one segment has been sent.
+fn output(tcb: &Arc<Tcb>, inner: &mut TcbInner) -> bool {
+	if !matches!(
+		inner.state,
+		State::Established | State::CloseWait | State::FinWait1 | State::Closing | State::LastAck
+	) {
+		return false;
+	}
+	let mut sent = false;
+	while !inner.fin_sent {
+		let off = inner.snd_nxt.wrapping_sub(inner.snd_una) as usize;
+		let wnd = (inner.snd_wnd as usize).saturating_sub(off);
+		let len = min(min(inner.tx.len().saturating_sub(off), wnd), MSS);
+		if len == 0 {
+			break;
+		}
+		let flags = if off + len == inner.tx.len() {
+			FLAG_ACK | FLAG_PSH
+		} else {
+			FLAG_ACK
+		};
+		inner.send_segment(inner.snd_nxt, flags, &inner.tx[off..(off + len)]);
+		inner.snd_nxt = inner.snd_nxt.wrapping_add(len as u32);
+		sent = true;
+	}
+	let off = inner.snd_nxt.wrapping_sub(inner.snd_una) as usize;
+	if inner.fin_queued && !inner.fin_sent && off == inner.tx.len() {
+		inner.send_segment(inner.snd_nxt, FLAG_FIN | FLAG_ACK, &[]);
+		inner.snd_nxt = inner.snd_nxt.wrapping_add(1);
+		inner.fin_sent = true;
+		sent = true;
+		match inner.state {
+			State::Established => inner.state = State::FinWait1,
+			State::CloseWait => inner.state = State::LastAck,
+			_ => {}
+		}
+	}
+	if inner.snd_una != inner.snd_nxt {
+		arm_timer(tcb, inner);
+	}
+	sent
+}
+
+/// Hands the connection `tcb`, whose handshake is over, to its listener, moving it from the
+/// SYN queue to the accept queue.
+///
+/// If `established` is not set, the handshake failed and the listener only forgets the
+/// connection.
+fn hand_over(listener: &Arc<Tcb>, tcb: &Arc<Tcb>, established: bool) {
+	let mut inner = listener.inner.lock();
+	let queued = match &mut inner.listen {
+		Some(listen) => {
+			listen
+				.syn_queue
+				.retain(|conn| !ptr::eq(conn.as_ptr(), tcb.as_ptr()));
+			established && listen.pending.push(tcb.clone()).is_ok()
+		}
+		None => false,
+	};
+	drop(inner);
+	if queued {
+		listener.wait.wake_all();
+	} else if established {
+		// The listener has been closed in the meantime
+		abort(tcb);
+	}
+}
+
+/// Drops the connection `tcb`, resetting it.
+fn abort(tcb: &Arc<Tcb>) {
+	let mut inner = tcb.inner.lock();
+	if inner.state != State::Closed {
+		inner.send_segment(inner.snd_nxt, FLAG_RST | FLAG_ACK, &[]);
+		inner.set_closed();
+	}
+	drop(inner);
+	tcb.wait.wake_all();
+}
+
+/// Processes the segment `seg`, received on the listening connection `listener`.
+fn input_listen(listener: &Arc<Tcb>, seg: &Segment) {
+	let mut inner = listener.inner.lock();
+	let local = inner.local;
+	let Some(listen) = &mut inner.listen else {
+		drop(inner);
+		reset(seg);
+		return;
+	};
+	if local.addr != INADDR_ANY && local.addr != seg.dst.addr {
+		drop(inner);
+		reset(seg);
+		return;
+	}
+	if seg.flags & FLAG_RST != 0 {
+		return;
+	}
+	if seg.flags & FLAG_ACK != 0 {
+		drop(inner);
+		reset(seg);
+		return;
+	}
+	// If either queue is full, drop the request. The peer retransmits it later
+	if seg.flags & FLAG_SYN == 0 || listen.is_full() || listen.syn_queue.len() >= listen.max {
+		return;
+	}
+	let Ok(tcb) = Tcb::new() else {
+		return;
+	};
+	{
+		let mut conn = tcb.inner.lock();
+		let iss = initial_seq();
+		conn.state = State::SynReceived;
+		conn.local = seg.dst;
+		conn.remote = seg.src;
+		conn.registered = true;
+		conn.listener = Some(listener.clone());
+		conn.snd_una = iss;
+		conn.snd_nxt = iss.wrapping_add(1);
+		conn.snd_wnd = seg.wnd as _;
+		conn.rcv_nxt = seg.seq.wrapping_add(1);
+	}
+	if listen.syn_queue.push(tcb.clone()).is_err() {
+		return;
+	}
+	if CONNECTIONS
+		.lock()
+		.insert((seg.dst, seg.src), tcb.clone())
+		.is_err()
+	{
+		listen.syn_queue.pop();
+		return;
+	}
+	drop(inner);
+	let mut conn = tcb.inner.lock();
+	conn.send_segment(conn.snd_una, FLAG_SYN | FLAG_ACK, &[]);
+	arm_timer(&tcb, &mut conn);
+}
+
+/// Tells whether the handshake of the connection `tcb` must not be completed, because the
+/// accept queue of its listener is full.
+fn accept_queue_full(tcb: &Tcb) -> bool {
+	let listener = {
+		let inner = tcb.inner.lock();
+		if inner.state != State::SynReceived {
+			return false;
+		}
+		inner.listener.clone()
+	};
+	// The listener is not locked along with the connection, since it is locked first when
+	// receiving connection requests
+	listener.is_some_and(|listener| {
+		let inner = listener.inner.lock();
+		inner.listen.as_ref().is_some_and(Listener::is_full)
+	})
+}
+
+/// Processes the segment `seg`, received on the connection `tcb`.
+fn input(tcb: &Arc<Tcb>, seg: &Segment) {
+	// If the connection cannot be accepted yet, ignore the acknowledgment ending the handshake.
+	// The handshake is retried when the SYN-ACK is retransmitted
+	if seg.flags & (FLAG_ACK | FLAG_RST) == FLAG_ACK && accept_queue_full(tcb) {
+		return;
+	}
+	let mut inner = tcb.inner.lock();
+	let handshake = match inner.state {
+		State::Closed | State::Listen => None,
+		State::SynSent => {
+			input_syn_sent(tcb, &mut inner, seg);
+			None
+		}
+		_ => input_sync(tcb, &mut inner, seg),
+	};
+	let listener = handshake.and_then(|_| inner.listener.take());
+	drop(inner);
+	tcb.wait.wake_all();
+	if let (Some(listener), Some(established)) = (listener, handshake) {
+		hand_over(&listener, tcb, established);
+	}
+}
+
+/// Processes the segment `seg`, received on the connection `tcb` in the `SYN-SENT` state.
+fn input_syn_sent(tcb: &Arc<Tcb>, inner: &mut TcbInner, seg: &Segment) {
+	let ack = seg.flags & FLAG_ACK != 0;
+	if ack && seg.ack != inner.snd_nxt {
+		reset(seg);
+		return;
+	}
+	if seg.flags & FLAG_RST != 0 {
+		if ack {
+			inner.error = Some(errno!(ECONNREFUSED));
+			inner.set_closed();
+		}
+		return;
+	}
+	if seg.flags & FLAG_SYN == 0 {
+		return;
+	}
+	inner.rcv_nxt = seg.seq.wrapping_add(1);
+	inner.snd_wnd = seg.wnd as _;
+	if ack {
+		inner.snd_una = seg.ack;
+		inner.establish();
+		inner.send_segment(inner.snd_nxt, FLAG_ACK, &[]);
+		output(tcb, inner);
+	} else {
+		// Simultaneous open
+		inner.state = State::SynReceived;
+		inner.send_segment(inner.snd_una, FLAG_SYN | FLAG_ACK, &[]);
+	}
+}
+
+/// Processes the segment `seg`, received on the connection `tcb`, which is synchronized or in
+/// the `SYN-RECEIVED` state.
+///
+/// If the segment ends the handshake, the function returns whether the connection has been
+/// established.
+fn input_sync(tcb: &Arc<Tcb>, inner: &mut TcbInner, seg: &Segment) -> Option<bool> {
+	let syn_received = inner.state == State::SynReceived;
+	if seg.flags & FLAG_RST != 0 {
+		if seg.seq != inner.rcv_nxt {
+			return None;
+		}
+		inner.error = Some(if syn_received {
+			errno!(ECONNREFUSED)
+		} else {
+			errno!(ECONNRESET)
+		});
+		inner.set_closed();
+		return syn_received.then_some(false);
+	}
+	// On simultaneous open, the peer's SYN is acknowledged along with the local SYN
+	let simultaneous = syn_received && seg.flags & FLAG_ACK != 0;
+	if seg.flags & FLAG_SYN != 0 && !simultaneous {
+		// The acknowledgment of the peer's SYN has been lost
+		if syn_received {
+			inner.send_segment(inner.snd_una, FLAG_SYN | FLAG_ACK, &[]);
+		} else {
+			inner.send_segment(inner.snd_nxt, FLAG_ACK, &[]);
+		}
+		return None;
+	}
+	// Skip what has already been received
+	let mut data = seg.data;
+	let mut fin = seg.flags & FLAG_FIN != 0;
+	let mut need_ack = false;
+	if seq_lt(seg.seq, inner.rcv_nxt) {
+		let behind = inner.rcv_nxt.wrapping_sub(seg.seq) as usize;
+		let skip = min(behind, data.len());
+		data = &data[skip..];
+		fin &= behind <= skip;
+		need_ack = seg.len() > 0;
+	} else if seg.seq != inner.rcv_nxt {
+		// The loopback does not reorder segments, the previous ones have been lost
+		inner.send_segment(inner.snd_nxt, FLAG_ACK, &[]);
+		return None;
+	}
+	if seg.flags & FLAG_ACK == 0 {
+		return None;
+	}
+	let mut handshake = None;
+	if syn_received {
+		if seg.ack != inner.snd_nxt {
+			reset(seg);
+			return None;
+		}
+		inner.snd_una = seg.ack;
+		inner.establish();
+		handshake = Some(true);
+	}
+	if seq_lt(inner.snd_una, seg.ack) && seq_le(seg.ack, inner.snd_nxt) {
+		let acked = seg.ack.wrapping_sub(inner.snd_una) as usize;
+		let len = min(acked, inner.tx.len());
+		inner.tx.copy_within(len.., 0);
+		inner.tx.truncate(inner.tx.len() - len);
+		inner.snd_una = seg.ack;
+		inner.retries = 0;
+		inner.rto = RTO_INIT;
+	} else if seq_lt(inner.snd_nxt, seg.ack) {
+		// Acknowledgment of data that has not been sent
+		inner.send_segment(inner.snd_nxt, FLAG_ACK, &[]);
+		return handshake;
+	}
+	inner.snd_wnd = seg.wnd as _;
+	let fin_acked = inner.fin_sent && inner.snd_una == inner.snd_nxt;
+	match inner.state {
+		State::FinWait1 if fin_acked => {
+			inner.state = State::FinWait2;
+			if inner.orphan {
+				linger(tcb, inner, FIN_TIMEOUT);
+			}
+		}
+		State::Closing if fin_acked => {
+			inner.state = State::TimeWait;
+			linger(tcb, inner, TIME_WAIT_DELAY);
+		}
+		State::LastAck if fin_acked => {
+			inner.set_closed();
+			return handshake;
+		}
+		_ => {}
+	}
+	// Nobody is left to read the data
+	if !data.is_empty() && inner.orphan {
+		inner.send_segment(inner.snd_nxt, FLAG_RST | FLAG_ACK, &[]);
+		inner.set_closed();
+		return handshake;
+	}
+	// Receive data
+	if !data.is_empty()
+		&& matches!(
+			inner.state,
+			State::Established | State::FinWait1 | State::FinWait2
+		) {
+		let len = if inner.rd_shutdown {
+			data.len()
+		} else {
+			inner.rx.write(data)
+		};
+		inner.rcv_nxt = inner.rcv_nxt.wrapping_add(len as u32);
+		// The rest is retransmitted once the window reopens
+		fin &= len == data.len();
+		need_ack = true;
+	}
+	if fin {
+		inner.rcv_nxt = inner.rcv_nxt.wrapping_add(1);
+		inner.fin_received = true;
+		need_ack = true;
+		match inner.state {
+			State::Established => inner.state = State::CloseWait,
+			State::FinWait1 => inner.state = State::Closing,
+			State::FinWait2 => {
+				inner.state = State::TimeWait;
+				linger(tcb, inner, TIME_WAIT_DELAY);
+			}
+			_ => {}
+		}
+	}
+	if !output(tcb, inner) && need_ack {
+		inner.send_segment(inner.snd_nxt, FLAG_ACK, &[]);
+	}
+	handshake
+}
+
+/// A Transmission Control Block, holding the state of a TCP connection.
+#[derive(Debug)]
+pub struct Tcb {
+	/// Inner with locking.
+	inner: Mutex<TcbInner>,
+	/// The queue of processes waiting for the state of the connection to change.
+	wait: WaitQueue,
+}
+
+impl Tcb {
+	/// Creates a new instance, in the `CLOSED` state.
+	pub fn new() -> AllocResult<Arc<Self>> {
+		Arc::new(Self {
+			inner: Mutex::new(TcbInner {
+				state: State::Closed,
+				local: Default::default(),
+				remote: Default::default(),
+				registered: false,
+				owns_port: false,
+				connected: false,
+				orphan: false,
+
+				listen: None,
+				listener: None,
+
+				snd_una: 0,
+				snd_nxt: 0,
+				snd_wnd: 0,
+				rcv_nxt: 0,
+
+				tx: Vec::new(),
+				rx: RingBuffer::new(vec![0; BUFFER_SIZE]?),
+
+				fin_queued: false,
+				fin_sent: false,
+				fin_received: false,
+				rd_shutdown: false,
+				error: None,
+
+				rto: RTO_INIT,
+				retries: 0,
+				timer: false,
+			}),
+			wait: WaitQueue::new(),
+		})
+	}
+
+	/// Returns the local end of the connection.
+	pub fn local(&self) -> Endpoint {
+		self.inner.lock().local
+	}
+
+	/// Returns the remote end of the connection.
+	pub fn remote(&self) -> Endpoint {
+		self.inner.lock().remote
+	}
+
+	/// Tells whether the connection has a peer, i.e. whether it has been established, even if it
+	/// is being closed.
+	pub fn is_connected(&self) -> bool {
+		!matches!(
+			self.inner.lock().state,
+			State::Closed | State::Listen | State::SynSent
+		)
+	}
+
+	/// Returns the number of bytes waiting to be read.
+	pub fn get_data_len(&self) -> usize {
+		self.inner.lock().rx.get_data_len()
+	}
+
+	/// Binds the connection to the local end `local`. If the port is zero, an ephemeral port is
+	/// chosen.
+	///
+	/// On success, the function returns the bound local end.
+	///
+	/// Errors:
+	/// - [`errno::EINVAL`]: the connection is already bound
+	/// - [`errno::EADDRNOTAVAIL`]: the address is not local
+	/// - [`errno::EADDRINUSE`]: the port is already in use
+	pub fn bind(&self, local: Endpoint) -> EResult<Endpoint> {
+		let mut inner = self.inner.lock();
+		if inner.owns_port || inner.state != State::Closed {
+			return Err(errno!(EINVAL));
+		}
+		if local.addr != INADDR_ANY && !local.is_loopback() {
+			return Err(errno!(EADDRNOTAVAIL));
+		}
+		inner.local = Endpoint {
+			addr: local.addr,
+			port: reserve_port(local.port)?,
+		};
+		inner.owns_port = true;
+		Ok(inner.local)
+	}
+
+	/// Starts listening for connections, with at most `max` connections waiting to be accepted.
+	///
+	/// If the connection is not bound, it is bound to an ephemeral port on any address.
+	///
+	/// If the connection is not closed, the function returns [`errno::EINVAL`].
+	pub fn listen(this: &Arc<Self>, max: usize) -> EResult<()> {
+		let mut inner = this.inner.lock();
+		let closed = inner.state == State::Closed && !inner.connected;
+		match &mut inner.listen {
+			// Already listening: only update the length of the queue
+			Some(listen) => {
+				listen.max = max;
+				return Ok(());
+			}
+			None if closed => {}
+			None => return Err(errno!(EINVAL)),
+		}
+		if !inner.owns_port {
+			inner.local = Endpoint {
+				addr: INADDR_ANY,
+				port: reserve_port(0)?,
+			};
+			inner.owns_port = true;
+		}
+		LISTENERS.lock().insert(inner.local.port, this.clone())?;
+		inner.listen = Some(Listener {
+			max,
+			syn_queue: Vec::new(),
+			pending: Vec::new(),
+		});
+		inner.state = State::Listen;
+		Ok(())
+	}
+
+	/// Connects to the listening connection at `remote`.
+	///
+	/// If the connection is not bound, it is bound to an ephemeral port.
+	///
+	/// If `nonblock` is set and the connection cannot be established immediately, the function
+	/// returns [`errno::EINPROGRESS`]. Else, it waits until the connection is established.
+	///
+	/// Errors:
+	/// - [`errno::EISCONN`]: the connection is already established, or listening
+	/// - [`errno::EALREADY`]: the connection is being established
+	/// - [`errno::ENETUNREACH`]: the remote address is not local
+	/// - [`errno::ECONNREFUSED`]: nothing is listening at `remote`
+	pub fn connect(this: &Arc<Self>, remote: Endpoint, nonblock: bool) -> EResult<()> {
+		{
+			let mut inner = this.inner.lock();
+			match inner.state {
+				State::Closed if !inner.connected => {}
+				State::SynSent | State::SynReceived => return Err(errno!(EALREADY)),
+				_ => return Err(errno!(EISCONN)),
+			}
+			if !remote.is_loopback() {
+				return Err(errno!(ENETUNREACH));
+			}
+			if !inner.owns_port {
+				inner.local.port = reserve_port(0)?;
+				inner.owns_port = true;
+			}
+			if inner.local.addr == INADDR_ANY {
+				inner.local.addr = INADDR_LOOPBACK;
+			}
+			inner.remote = remote;
+			{
+				let mut conns = CONNECTIONS.lock();
+				if conns.contains_key(&(inner.local, remote)) {
+					return Err(errno!(EADDRNOTAVAIL));
+				}
+				conns.insert((inner.local, remote), this.clone())?;
+			}
+			let iss = initial_seq();
+			inner.registered = true;
+			inner.state = State::SynSent;
+			inner.error = None;
+			inner.snd_una = iss;
+			inner.snd_nxt = iss.wrapping_add(1);
+			inner.send_segment(iss, FLAG_SYN, &[]);
+			arm_timer(this, &mut inner);
+		}
+		flush();
+		this.wait.wait_until(|| {
+			let mut inner = this.inner.lock();
+			match inner.state {
+				State::SynSent | State::SynReceived if nonblock => Some(Err(errno!(EINPROGRESS))),
+				State::SynSent | State::SynReceived => None,
+				_ if inner.connected => Some(Ok(())),
+				_ => {
+					let err = inner.error.take().unwrap_or_else(|| errno!(ECONNREFUSED));
+					Some(Err(err))
+				}
+			}
+		})?
+	}
+
+	/// Accepts an established connection on the listening connection.
+	///
+	/// If no connection is waiting and `nonblock` is set, the function returns
+	/// [`errno::EAGAIN`]. Else, it waits for a connection.
+	///
+	/// If the connection is not listening, the function returns [`errno::EINVAL`].
+	pub fn accept(&self, nonblock: bool) -> EResult<Arc<Tcb>> {
+		self.wait.wait_until(|| {
+			let mut inner = self.inner.lock();
+			let Some(listen) = &mut inner.listen else {
+				return Some(Err(errno!(EINVAL)));
+			};
+			if listen.pending.is_empty() {
+				return nonblock.then_some(Err(errno!(EAGAIN)));
+			}
+			Some(Ok(listen.pending.remove(0)))
+		})?
+	}
+
+	/// Queues data from `buf` for transmission, returning the number of bytes queued.
+	///
+	/// If the send buffer is full and `nonblock` is set, the function returns
+	/// [`errno::EAGAIN`]. Else, it waits for space to be available.
+	///
+	/// If the transmit side of the connection has been shut down, the function returns
+	/// [`errno::EPIPE`].
+	pub fn send(this: &Arc<Self>, buf: &[u8], nonblock: bool) -> EResult<usize> {
+		let res = this.wait.wait_until(|| {
+			let mut inner = this.inner.lock();
+			if let Some(err) = inner.error.take() {
+				return Some(Err(err));
+			}
+			match inner.state {
+				State::SynSent | State::SynReceived => {
+					return nonblock.then_some(Err(errno!(EAGAIN)));
+				}
+				State::Established | State::CloseWait if !inner.fin_queued => {}
+				_ if inner.connected => return Some(Err(errno!(EPIPE))),
+				_ => return Some(Err(errno!(ENOTCONN))),
+			}
+			let len = min(buf.len(), BUFFER_SIZE - inner.tx.len());
+			if len == 0 && !buf.is_empty() {
+				return nonblock.then_some(Err(errno!(EAGAIN)));
+			}
+			if let Err(e) = inner.tx.extend_from_slice(&buf[..len]) {
+				return Some(Err(e.into()));
+			}
+			output(this, &mut inner);
+			Some(Ok(len))
+		});
+		flush();
+		res?
+	}
+
+	/// Receives data into `buf`, returning the number of bytes received.
+	///
+	/// If `peek` is set, the data is not consumed.
+	///
+	/// If no data is available and `nonblock` is set, the function returns [`errno::EAGAIN`].
+	/// Else, it waits for data. Once the peer has closed the connection, the function returns
+	/// zero.
+	pub fn recv(this: &Arc<Self>, buf: &mut [u8], peek: bool, nonblock: bool) -> EResult<usize> {
+		let res = this.wait.wait_until(|| {
+			let mut inner = this.inner.lock();
+			if buf.is_empty() {
+				return Some(Ok(0));
+			}
+			if inner.rx.get_data_len() > 0 {
+				if peek {
+					return Some(Ok(inner.rx.peek(buf)));
+				}
+				let wnd = inner.rcv_wnd() as usize;
+				let len = inner.rx.read(buf);
+				// Tell the peer the window has reopened
+				if wnd < MSS && inner.state != State::Closed {
+					inner.send_segment(inner.snd_nxt, FLAG_ACK, &[]);
+				}
+				return Some(Ok(len));
+			}
+			if let Some(err) = inner.error.take() {
+				return Some(Err(err));
+			}
+			// End-of-file
+			if inner.fin_received || inner.rd_shutdown || inner.state == State::Closed {
+				return Some(if inner.connected {
+					Ok(0)
+				} else {
+					Err(errno!(ENOTCONN))
+				});
+			}
+			if inner.state == State::Listen {
+				return Some(Err(errno!(ENOTCONN)));
+			}
+			nonblock.then_some(Err(errno!(EAGAIN)))
+		});
+		flush();
+		res?
+	}
+
+	/// Shuts down the reception side of the connection, discarding received data.
+	pub fn shutdown_reception(&self) {
+		let mut inner = self.inner.lock();
+		inner.rd_shutdown = true;
+		inner.rx.clear();
+		drop(inner);
+		self.wait.wake_all();
+	}
+
+	/// Shuts down the transmit side of the connection, sending a FIN once the queued data has
+	/// been sent.
+	pub fn shutdown_transmit(this: &Arc<Self>) {
+		let mut inner = this.inner.lock();
+		if matches!(
+			inner.state,
+			State::SynSent | State::SynReceived | State::Established | State::CloseWait
+		) {
+			inner.fin_queued = true;
+			output(this, &mut inner);
+		}
+		drop(inner);
+		this.wait.wake_all();
+		flush();
+	}
+
+	/// Closes the connection, once its socket is closed.
+	///
+	/// Queued data is still sent before the connection ends, unless received data has not been
+	/// read, in which case the connection is reset.
+	pub fn close(this: &Arc<Self>) {
+		let mut inner = this.inner.lock();
+		inner.orphan = true;
+		let mut syn_queue = Vec::new();
+		let mut pending = Vec::new();
+		match inner.state {
+			State::Listen => {
+				LISTENERS.lock().remove(&inner.local.port);
+				if let Some(listen) = inner.listen.take() {
+					syn_queue = listen.syn_queue;
+					pending = listen.pending;
+				}
+				inner.state = State::Closed;
+			}
+			State::SynSent => inner.set_closed(),
+			State::SynReceived | State::Established | State::CloseWait
+				if inner.rx.get_data_len() > 0 =>
+			{
+				inner.send_segment(inner.snd_nxt, FLAG_RST | FLAG_ACK, &[]);
+				inner.set_closed();
+			}
+			State::SynReceived | State::Established | State::CloseWait => {
+				inner.fin_queued = true;
+				output(this, &mut inner);
+			}
+			State::FinWait2 => linger(this, &mut inner, FIN_TIMEOUT),
+			_ => {}
+		}
+		inner.rd_shutdown = true;
+		inner.rx.clear();
+		if inner.state == State::Closed {
+			inner.release_port();
+		}
+		drop(inner);
+		this.wait.wake_all();
+		// Reset connections that have not been accepted
+		for tcb in syn_queue {
+			tcb.inner.lock().listener = None;
+			abort(&tcb);
+		}
+		for tcb in pending {
+			abort(&tcb);
+		}
+		flush();
+	}
+
+	/// Registers the current process to be woken up on the next state change of the connection,
+	/// for polling.
+	pub fn poll_wait(&self) -> AllocResult<()> {
+		self.wait.register()
+	}
+
+	/// Returns the events of the connection that are set in `mask`, as `POLL*` flags.
+	pub fn poll(&self, mask: u32) -> u32 {
+		let inner = self.inner.lock();
+		if let Some(listen) = &inner.listen {
+			let res = if !listen.pending.is_empty() {
+				POLLIN
+			} else {
+				0
+			};
+			return res & mask;
+		}
+		let mut res = 0;
+		let closed = inner.state == State::Closed && (inner.connected || inner.error.is_some());
+		if inner.rx.get_data_len() > 0 || inner.fin_received || inner.rd_shutdown || closed {
+			res |= POLLIN;
+		}
+		if inner.fin_received {
+			res |= POLLRDHUP;
+		}
+		let writable = matches!(inner.state, State::Established | State::CloseWait)
+			&& !inner.fin_queued
+			&& inner.tx.len() < BUFFER_SIZE;
+		if writable {
+			res |= POLLOUT;
+		}
+		if inner.error.is_some() {
+			res |= POLLERR;
+		}
+		if (inner.fin_received && inner.fin_queued) || closed {
+			res |= POLLHUP;
+		}
+		res & mask
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test_case]
+	fn tcp_loopback() {
+		let server = Tcb::new().unwrap();
+		let local = server
+			.bind(Endpoint {
+				addr: INADDR_LOOPBACK,
+				port: 0,
+			})
+			.unwrap();
+		Tcb::listen(&server, 1).unwrap();
+		// The handshake completes immediately on the loopback
+		let client = Tcb::new().unwrap();
+		Tcb::connect(&client, local, true).unwrap();
+		let conn = server.accept(true).unwrap();
+		assert_eq!(conn.remote(), client.local());
+		assert_eq!(server.accept(true).unwrap_err(), errno!(EAGAIN));
+		// Transfer data
+		assert_eq!(Tcb::send(&client, b"hello", true).unwrap(), 5);
+		let mut buf = [0u8; 8];
+		assert_eq!(Tcb::recv(&conn, &mut buf, true, true).unwrap(), 5);
+		assert_eq!(Tcb::recv(&conn, &mut buf, false, true).unwrap(), 5);
+		assert_eq!(&buf[..5], b"hello");
+		assert_eq!(
+			Tcb::recv(&conn, &mut buf, false, true).unwrap_err(),
+			errno!(EAGAIN)
+		);
+		// Closing gives an end-of-file to the peer
+		Tcb::close(&client);
+		assert_eq!(Tcb::recv(&conn, &mut buf, false, true).unwrap(), 0);
+		// Data sent to a closed socket resets the connection
+		assert_eq!(Tcb::send(&conn, b"x", true).unwrap(), 1);
+		assert_eq!(
+			Tcb::send(&conn, b"x", true).unwrap_err(),
+			errno!(ECONNRESET)
+		);
+		assert_eq!(Tcb::send(&conn, b"x", true).unwrap_err(), errno!(EPIPE));
+		Tcb::close(&conn);
+		Tcb::close(&server);
+		// Nothing is listening anymore
+		let client = Tcb::new().unwrap();
+		assert_eq!(
+			Tcb::connect(&client, local, true).unwrap_err(),
+			errno!(ECONNREFUSED)
+		);
+		Tcb::close(&client);
+	}
+
+	#[test_case]
+	fn tcp_backlog() {
+		let server = Tcb::new().unwrap();
+		let local = server
+			.bind(Endpoint {
+				addr: INADDR_LOOPBACK,
+				port: 0,
+			})
+			.unwrap();
+		Tcb::listen(&server, 1).unwrap();
+		assert_eq!(server.poll(POLLIN), 0);
+		let client0 = Tcb::new().unwrap();
+		Tcb::connect(&client0, local, true).unwrap();
+		assert_eq!(server.poll(POLLIN), POLLIN);
+		// The accept queue is full: the connection request is dropped
+		let client1 = Tcb::new().unwrap();
+		assert_eq!(
+			Tcb::connect(&client1, local, true).unwrap_err(),
+			errno!(EINPROGRESS)
+		);
+		let conn = server.accept(true).unwrap();
+		assert_eq!(conn.remote(), client0.local());
+		assert_eq!(server.accept(true).unwrap_err(), errno!(EAGAIN));
+		assert_eq!(server.poll(POLLIN), 0);
+		Tcb::close(&conn);
+		Tcb::close(&client0);
+		Tcb::close(&client1);
+		Tcb::close(&server);
+	}
 }
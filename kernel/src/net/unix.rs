@@ -0,0 +1,413 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Unix domain sockets.
+//!
+//! The data sent through a Unix socket is never handed to a network stack. Instead, it is queued
+//! directly on a [`Channel`] of the receiving socket, along with the files passed as ancillary
+//! data and the credentials of the sender.
+
+use crate::{
+	file::{wait_queue::WaitQueue, File},
+	process::{pid::Pid, Process},
+};
+use core::{cmp::min, ffi::c_int, mem};
+use macros::AnyRepr;
+use utils::{
+	collections::vec::Vec,
+	errno,
+	errno::{AllocResult, EResult},
+	lock::Mutex,
+	ptr::arc::Arc,
+};
+
+/// The maximum number of bytes that can be queued on a channel.
+pub const CHANNEL_SIZE: usize = 65536;
+/// The maximum number of files that can be passed in a single message.
+pub const SCM_MAX_FD: usize = 253;
+
+/// The credentials of a process communicating through a Unix socket, as exposed by
+/// `SO_PEERCRED` and `SCM_CREDENTIALS`.
+///
+/// The PID is the one used by the kernel, and must be translated before being given to
+/// userspace.
+#[repr(C)]
+#[derive(AnyRepr, Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Ucred {
+	/// The ID of the process.
+	pub pid: c_int,
+	/// The effective user ID of the process.
+	pub uid: u32,
+	/// The effective group ID of the process.
+	pub gid: u32,
+}
+
+impl Ucred {
+	/// Returns the credentials of `proc`.
+	pub fn of(proc: &Process) -> Self {
+		let ap = proc.cred.get();
+		Self {
+			pid: proc.get_tgid() as _,
+			uid: ap.euid as _,
+			gid: ap.egid as _,
+		}
+	}
+
+	/// Returns the credentials of the current process.
+	///
+	/// If no process is running, the credentials are the default ones, which are the kernel's.
+	pub fn current() -> Self {
+		Process::current_opt()
+			.map(|proc| Self::of(&proc.lock()))
+			.unwrap_or_default()
+	}
+
+	/// Returns the credentials with the PID as seen from the namespace of `proc`.
+	///
+	/// If `proc` cannot see the process, the PID is zero.
+	pub fn to_local(mut self, proc: &Process) -> Self {
+		self.pid = proc.pid_to_local(self.pid as Pid).unwrap_or(0) as _;
+		self
+	}
+}
+
+/// A message queued on a channel.
+#[derive(Debug)]
+struct Message {
+	/// The content of the message.
+	data: Vec<u8>,
+	/// The offset of the first byte of `data` that has not been received yet.
+	off: usize,
+	/// The files passed along with the message.
+	files: Vec<Arc<File>>,
+	/// The credentials of the sender.
+	cred: Ucred,
+}
+
+/// The result of a reception on a [`Channel`].
+#[derive(Debug, Default)]
+pub struct Received {
+	/// The number of bytes written to the buffer.
+	pub len: usize,
+	/// The files passed along with the received data.
+	pub files: Vec<Arc<File>>,
+	/// The credentials of the sender of the received data, if any.
+	pub cred: Option<Ucred>,
+	/// For datagrams, tells whether the message was larger than the buffer, in which case the
+	/// rest of the message has been discarded.
+	pub truncated: bool,
+}
+
+#[derive(Debug, Default)]
+struct ChannelInner {
+	/// The queued messages, in order.
+	messages: Vec<Message>,
+	/// The number of bytes queued and not received yet.
+	len: usize,
+	/// Tells whether the receiving end of the channel has been closed.
+	rx_closed: bool,
+	/// Tells whether the transmitting end of the channel has been closed.
+	tx_closed: bool,
+}
+
+impl ChannelInner {
+	/// Receives data from the queued messages into `buf`.
+	///
+	/// If `stream` is set, several messages may be received at once, else only the first message
+	/// is received. Messages from different senders are never received at once. If `peek` is
+	/// set, the data is not consumed and files are not received.
+	fn receive(&mut self, buf: &mut [u8], stream: bool, peek: bool) -> Received {
+		let mut res = Received::default();
+		let mut i = 0;
+		while let Some(msg) = self.messages.get_mut(i) {
+			// Files must be received along with the beginning of their message
+			let has_files = !msg.files.is_empty();
+			if res.len > 0 && (has_files || res.cred != Some(msg.cred)) {
+				break;
+			}
+			res.cred = Some(msg.cred);
+			let data = &msg.data[msg.off..];
+			let len = min(data.len(), buf.len() - res.len);
+			buf[res.len..(res.len + len)].copy_from_slice(&data[..len]);
+			res.len += len;
+			if !peek {
+				res.files = mem::take(&mut msg.files);
+			}
+			if !stream {
+				res.truncated = len < data.len();
+				if !peek {
+					self.len -= data.len();
+					self.messages.remove(0);
+				}
+				break;
+			}
+			if peek {
+				i += 1;
+			} else {
+				msg.off += len;
+				self.len -= len;
+				if msg.off >= msg.data.len() {
+					self.messages.remove(0);
+				}
+			}
+			if has_files || res.len >= buf.len() {
+				break;
+			}
+		}
+		res
+	}
+}
+
+/// One direction of the communication between Unix sockets: the queue of data to be received by
+/// a socket.
+#[derive(Debug, Default)]
+pub struct Channel {
+	/// Inner with locking.
+	inner: Mutex<ChannelInner>,
+	/// The queue of processes waiting for data to be available.
+	rx_queue: WaitQueue,
+	/// The queue of processes waiting for space to be available.
+	tx_queue: WaitQueue,
+}
+
+impl Channel {
+	/// Returns the number of bytes waiting to be received.
+	pub fn get_data_len(&self) -> usize {
+		self.inner.lock().len
+	}
+
+	/// Tells whether no message is waiting to be received.
+	pub fn is_empty(&self) -> bool {
+		self.inner.lock().messages.is_empty()
+	}
+
+	/// Tells whether data can be sent without waiting.
+	pub fn has_space(&self) -> bool {
+		self.inner.lock().len < CHANNEL_SIZE
+	}
+
+	/// Tells whether either end of the channel has been closed, in which case no more data can go
+	/// through it.
+	pub fn is_closed(&self) -> bool {
+		let inner = self.inner.lock();
+		inner.rx_closed || inner.tx_closed
+	}
+
+	/// Registers the current process to be woken up on the next event on the channel, for
+	/// polling.
+	pub fn poll_wait(&self) -> AllocResult<()> {
+		self.rx_queue.register()?;
+		self.tx_queue.register()
+	}
+
+	/// Closes the receiving end of the channel, discarding the data waiting to be received.
+	///
+	/// Further transmissions fail with [`errno::EPIPE`].
+	pub fn close_rx(&self) {
+		let messages = {
+			let mut inner = self.inner.lock();
+			inner.rx_closed = true;
+			inner.len = 0;
+			mem::take(&mut inner.messages)
+		};
+		self.rx_queue.wake_all();
+		self.tx_queue.wake_all();
+		// Drop passed files only after unlocking, since closing them may close sockets
+		drop(messages);
+	}
+
+	/// Closes the transmitting end of the channel.
+	///
+	/// Once the remaining data has been received, the receiver gets an end-of-file.
+	pub fn close_tx(&self) {
+		self.inner.lock().tx_closed = true;
+		self.rx_queue.wake_all();
+		self.tx_queue.wake_all();
+	}
+
+	/// Sends `data` on the channel, along with `files` and the credentials `cred` of the sender.
+	///
+	/// If `stream` is set, the function sends as much data as possible, and the return value is
+	/// the number of bytes sent. Else, the data is sent as a single datagram.
+	///
+	/// If no space is available and `nonblock` is set, the function returns [`errno::EAGAIN`].
+	/// Else, it waits for space to be available.
+	///
+	/// If either end of the channel is closed, the function returns [`errno::EPIPE`].
+	pub fn send(
+		&self,
+		data: &[u8],
+		mut files: Vec<Arc<File>>,
+		cred: Ucred,
+		stream: bool,
+		nonblock: bool,
+	) -> EResult<usize> {
+		if !stream && data.len() > CHANNEL_SIZE {
+			return Err(errno!(EMSGSIZE));
+		}
+		self.tx_queue.wait_until(|| {
+			let mut inner = self.inner.lock();
+			if inner.rx_closed || inner.tx_closed {
+				return Some(Err(errno!(EPIPE)));
+			}
+			let available = CHANNEL_SIZE - inner.len;
+			let len = if stream {
+				min(data.len(), available)
+			} else if data.len() <= available {
+				data.len()
+			} else {
+				0
+			};
+			if len == 0 && !data.is_empty() {
+				return nonblock.then_some(Err(errno!(EAGAIN)));
+			}
+			let data = match Vec::try_from(&data[..len]) {
+				Ok(data) => data,
+				Err(e) => return Some(Err(e.into())),
+			};
+			let msg = Message {
+				data,
+				off: 0,
+				files: mem::take(&mut files),
+				cred,
+			};
+			if let Err(e) = inner.messages.push(msg) {
+				return Some(Err(e.into()));
+			}
+			inner.len += len;
+			self.rx_queue.wake_next();
+			Some(Ok(len))
+		})?
+	}
+
+	/// Receives data from the channel into `buf`.
+	///
+	/// If `stream` is set, data from several messages may be received at once. Else, a single
+	/// datagram is received and the part that does not fit in `buf` is discarded.
+	///
+	/// If `peek` is set, the data is left on the channel and files are not received.
+	///
+	/// If no data is available and `nonblock` is set, the function returns [`errno::EAGAIN`].
+	/// Else, it waits for data to be available. If either end of the channel is closed and no
+	/// data remains, the function returns an end-of-file.
+	pub fn recv(
+		&self,
+		buf: &mut [u8],
+		stream: bool,
+		peek: bool,
+		nonblock: bool,
+	) -> EResult<Received> {
+		self.rx_queue.wait_until(|| {
+			let mut inner = self.inner.lock();
+			if inner.messages.is_empty() {
+				if inner.rx_closed || inner.tx_closed {
+					return Some(Ok(Received::default()));
+				}
+				return nonblock.then_some(Err(errno!(EAGAIN)));
+			}
+			let res = inner.receive(buf, stream, peek);
+			if !peek {
+				self.tx_queue.wake_next();
+			}
+			Some(Ok(res))
+		})?
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test_case]
+	fn unix_channel_stream() {
+		let channel = Channel::default();
+		let cred = Ucred::default();
+		assert_eq!(
+			channel.send(b"abc", Vec::new(), cred, true, true).unwrap(),
+			3
+		);
+		assert_eq!(
+			channel.send(b"def", Vec::new(), cred, true, true).unwrap(),
+			3
+		);
+		let mut buf = [0u8; 4];
+		let res = channel.recv(&mut buf, true, true, true).unwrap();
+		assert_eq!(res.len, 4);
+		assert_eq!(channel.get_data_len(), 6);
+		let res = channel.recv(&mut buf, true, false, true).unwrap();
+		assert_eq!(&buf[..res.len], b"abcd");
+		let res = channel.recv(&mut buf, true, false, true).unwrap();
+		assert_eq!(&buf[..res.len], b"ef");
+		assert_eq!(
+			channel.recv(&mut buf, true, false, true).unwrap_err(),
+			errno!(EAGAIN)
+		);
+		// End-of-file
+		channel.close_tx();
+		assert_eq!(channel.recv(&mut buf, true, false, true).unwrap().len, 0);
+		assert_eq!(
+			channel
+				.send(b"abc", Vec::new(), cred, true, true)
+				.unwrap_err(),
+			errno!(EPIPE)
+		);
+	}
+
+	#[test_case]
+	fn unix_channel_dgram() {
+		let channel = Channel::default();
+		let cred = Ucred::default();
+		channel
+			.send(b"abcdef", Vec::new(), cred, false, true)
+			.unwrap();
+		channel.send(b"", Vec::new(), cred, false, true).unwrap();
+		channel.send(b"gh", Vec::new(), cred, false, true).unwrap();
+		let mut buf = [0u8; 4];
+		let res = channel.recv(&mut buf, false, false, true).unwrap();
+		assert_eq!(&buf[..res.len], b"abcd");
+		assert!(res.truncated);
+		assert_eq!(channel.recv(&mut buf, false, false, true).unwrap().len, 0);
+		let res = channel.recv(&mut buf, false, false, true).unwrap();
+		assert_eq!(&buf[..res.len], b"gh");
+		assert!(!res.truncated);
+		assert!(channel.is_empty());
+	}
+
+	#[test_case]
+	fn unix_channel_cred() {
+		let channel = Channel::default();
+		let cred = Ucred {
+			pid: 1,
+			uid: 1000,
+			gid: 1000,
+		};
+		channel.send(b"ab", Vec::new(), cred, true, true).unwrap();
+		channel.send(b"cd", Vec::new(), cred, true, true).unwrap();
+		channel
+			.send(b"ef", Vec::new(), Ucred::default(), true, true)
+			.unwrap();
+		// Data from different senders is not received at once
+		let mut buf = [0u8; 8];
+		let res = channel.recv(&mut buf, true, false, true).unwrap();
+		assert_eq!(&buf[..res.len], b"abcd");
+		assert_eq!(res.cred, Some(cred));
+		let res = channel.recv(&mut buf, true, false, true).unwrap();
+		assert_eq!(&buf[..res.len], b"ef");
+		assert_eq!(res.cred, Some(Ucred::default()));
+	}
+}
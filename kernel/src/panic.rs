@@ -22,15 +22,63 @@
 //! from. This is an undesirable state which requires to reboot the host
 //! machine.
 
-use crate::{logger, memory::VirtAddr, power, register_get};
-use core::panic::PanicInfo;
-use utils::interrupt::cli;
+use crate::{
+	device::console,
+	logger,
+	memory::VirtAddr,
+	module::{param, param::Param},
+	power,
+	process::regs::Regs,
+	register_get,
+	time::hw::rtc,
+};
+use core::{
+	panic::PanicInfo,
+	sync::atomic::{AtomicI32, AtomicUsize, Ordering::Relaxed},
+};
+use utils::{errno::EResult, interrupt::cli};
+
+/// The number of seconds to wait before rebooting after a kernel panic.
+///
+/// If zero, the system halts instead. If negative, it reboots immediately.
+static TIMEOUT: Param<i32> = Param::new(
+	"kernel",
+	"panic",
+	0,
+	true,
+	Some(|secs| TIMEOUT_SECS.store(secs, Relaxed)),
+);
+/// The value of [`TIMEOUT`], readable by the panic handler without locking.
+static TIMEOUT_SECS: AtomicI32 = AtomicI32::new(0);
+
+/// The stack frame of the context that raised the exception causing the panic, if any.
+///
+/// If not zero, the callstack is printed starting from this frame instead of the panic handler's.
+static FAULT_FRAME: AtomicUsize = AtomicUsize::new(0);
+
+/// Makes the kernel panic because of an exception raised in kernelspace.
+///
+/// Arguments:
+/// - `msg` is the description of the exception.
+/// - `code` is the error code associated with the exception.
+/// - `regs` is the state of the registers when the exception was raised.
+pub fn exception(msg: &str, code: u32, regs: &Regs) -> ! {
+	FAULT_FRAME.store(regs.ebp, Relaxed);
+	panic!("{msg}, code: {code:x}\n{regs:?}");
+}
+
+/// Registers the parameters related to kernel panics.
+pub(crate) fn init() -> EResult<()> {
+	param::register(&TIMEOUT)
+}
 
 /// Called on Rust panic.
 #[panic_handler]
 fn panic(panic_info: &PanicInfo) -> ! {
 	cli();
 	logger::LOGGER.lock().silent = false;
+	// If the panic happened before consoles were initialized, use the default ones
+	console::init(&[]);
 
 	#[cfg(test)]
 	{
@@ -65,7 +113,11 @@ fn panic(panic_info: &PanicInfo) -> ! {
 		use core::ptr;
 
 		crate::println!("--- Callstack ---");
-		let ebp = ptr::with_exposed_provenance(register_get!("ebp"));
+		let ebp = match FAULT_FRAME.load(Relaxed) {
+			0 => register_get!("ebp"),
+			frame => frame,
+		};
+		let ebp = ptr::with_exposed_provenance(ebp);
 		let mut callstack: [VirtAddr; 8] = [VirtAddr::default(); 8];
 		unsafe {
 			debug::get_callstack(ebp, &mut callstack);
@@ -73,7 +125,17 @@ fn panic(panic_info: &PanicInfo) -> ! {
 		debug::print_callstack(&callstack);
 	}
 
-	power::halt();
+	// Allow unattended machines to recover
+	match TIMEOUT_SECS.load(Relaxed) {
+		0 => power::halt(),
+		secs => {
+			if secs > 0 {
+				crate::println!("Rebooting in {secs} seconds...");
+				rtc::wait_seconds(secs as _);
+			}
+			power::reboot();
+		}
+	}
 }
 
 // TODO check whether this can be removed since the kernel uses panic=abort
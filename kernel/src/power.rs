@@ -17,10 +17,53 @@
  */
 
 //! This module handles system power.
+//!
+//! Before powering off or rebooting, [`prepare_shutdown`] brings the system to a clean state, so
+//! that no data is lost.
 
-use crate::io;
+use crate::{
+	device::storage,
+	file::{page_cache, vfs::mountpoint},
+	io, println,
+	process::{
+		kthread,
+		pid::{Pid, INIT_PID},
+		scheduler,
+		scheduler::SCHEDULER,
+		signal::Signal,
+		Process, State,
+	},
+	time::{
+		clock,
+		clock::CLOCK_MONOTONIC,
+		unit::{Timestamp, TimestampScale},
+	},
+};
 use core::arch::asm;
-use utils::interrupt::cli;
+use utils::{
+	collections::vec::Vec,
+	errno::{CollectResult, EResult},
+	interrupt::cli,
+	lock::IntMutex,
+	ptr::arc::Arc,
+};
+
+/// The time given to processes to exit after receiving `SIGTERM`, in milliseconds.
+const TERM_TIMEOUT: Timestamp = 5000;
+/// The time given to processes to exit after receiving `SIGKILL`, in milliseconds.
+const KILL_TIMEOUT: Timestamp = 1000;
+
+/// Emulator-specific ports and values powering the machine off, tried in order.
+///
+/// They are used until ACPI is supported.
+const POWEROFF_PORTS: [(u16, u16); 3] = [
+	// QEMU
+	(0x604, 0x2000),
+	// Bochs and older versions of QEMU
+	(0xb004, 0x2000),
+	// VirtualBox
+	(0x4004, 0x3400),
+];
 
 /// Halts the kernel until reboot.
 pub fn halt() -> ! {
@@ -32,10 +75,104 @@ pub fn halt() -> ! {
 	}
 }
 
+/// Returns every userspace process with its PID, except the init process and the current process.
+fn userspace_processes() -> EResult<Vec<(Pid, Arc<IntMutex<Process>>)>> {
+	let cur_pid = Process::current().lock().get_pid();
+	let mut procs = SCHEDULER
+		.get()
+		.lock()
+		.iter_process()
+		.filter(|(pid, _)| **pid != INIT_PID && **pid != cur_pid)
+		.map(|(pid, proc)| (*pid, proc.clone()))
+		.collect::<CollectResult<Vec<_>>>()
+		.0?;
+	// Kernel threads do not receive signals. Check outside the scheduler's lock, since spawning a
+	// kernel thread locks the scheduler while holding the registry of kernel threads
+	procs.retain(|(pid, _)| !kthread::is_kthread(*pid));
+	Ok(procs)
+}
+
+/// Sends the signal `sig` to every process in `procs`, then waits until they all have exited or
+/// `timeout` milliseconds have elapsed.
+///
+/// The function returns `true` if every process has exited.
+fn kill_and_wait(
+	procs: &[(Pid, Arc<IntMutex<Process>>)],
+	sig: Signal,
+	timeout: Timestamp,
+) -> bool {
+	for (_, proc) in procs {
+		proc.lock().kill(sig);
+	}
+	let now = || clock::current_time(CLOCK_MONOTONIC, TimestampScale::Millisecond);
+	let Ok(start) = now() else {
+		return false;
+	};
+	let deadline = start.saturating_add(timeout);
+	loop {
+		let exited = procs
+			.iter()
+			.all(|(_, proc)| proc.lock().get_state() == State::Zombie);
+		if exited {
+			return true;
+		}
+		match now() {
+			Ok(now) if now < deadline => {}
+			_ => return false,
+		}
+		// Let processes handle the signal
+		scheduler::yield_current();
+	}
+}
+
+/// Brings the system to a clean state before powering it off or rebooting it:
+/// - every userspace process, except init and the current process, receives `SIGTERM`, then
+///   `SIGKILL` if it does not exit in time
+/// - modified data is written back to storage
+/// - filesystems are unmounted, except the root filesystem
+/// - storage devices are spun down
+///
+/// This function must be called from process context. Errors are printed, but do not stop the
+/// sequence.
+pub fn prepare_shutdown() {
+	match userspace_processes() {
+		Ok(procs) => {
+			println!("Sending SIGTERM to all processes...");
+			if !kill_and_wait(&procs, Signal::SIGTERM, TERM_TIMEOUT) {
+				println!("Sending SIGKILL to all processes...");
+				kill_and_wait(&procs, Signal::SIGKILL, KILL_TIMEOUT);
+			}
+		}
+		Err(e) => println!("Could not list processes: {e}"),
+	}
+	println!("Syncing filesystems...");
+	if let Err(e) = page_cache::sync(None) {
+		println!("Could not sync filesystems: {e}");
+	}
+	println!("Unmounting filesystems...");
+	if let Err(e) = mountpoint::remove_all() {
+		println!("Could not unmount filesystems: {e}");
+	}
+	// Write back the data of the root filesystem and of filesystems that could not be unmounted
+	if let Err(e) = page_cache::sync(None) {
+		println!("Could not sync filesystems: {e}");
+	}
+	storage::shutdown();
+}
+
 /// Powers the system down.
+///
+/// If the system cannot be powered down, it is halted.
 pub fn shutdown() -> ! {
+	cli();
 	// TODO Use ACPI to power off the system
-	todo!()
+	for (port, value) in POWEROFF_PORTS {
+		unsafe {
+			io::outw(port, value);
+		}
+	}
+	println!("Could not power off, halting");
+	halt();
 }
 
 /// Reboots the system.
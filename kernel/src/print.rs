@@ -23,8 +23,11 @@
 //!
 //! Printing can be silenced at boot using the `-silent` command line argument, but logs remain in
 //! memory.
+//!
+//! Messages from a specific subsystem should be emitted with [`log!`], so that they can be
+//! filtered according to their level. See [`crate::logger`].
 
-use crate::logger::LOGGER;
+use crate::logger::{is_enabled, Level, Subsystem, LOGGER};
 use core::fmt;
 
 /// Prints/logs the given message.
@@ -36,6 +39,16 @@ pub fn _print(args: fmt::Arguments) {
 	fmt::write(&mut *logger, args).ok();
 }
 
+/// Logs the given message if the level of `subsys` allows it.
+///
+/// This function is meant to be used through the [`log!`] macro only.
+#[doc(hidden)]
+pub fn _log(subsys: Subsystem, level: Level, args: fmt::Arguments) {
+	if is_enabled(subsys, level) {
+		_print(args);
+	}
+}
+
 /// Prints the given formatted string with the given values.
 #[allow_internal_unstable(print_internals)]
 #[macro_export]
@@ -54,3 +67,24 @@ macro_rules! println {
 		$crate::print::_print(format_args_nl!($($arg)*));
 	}};
 }
+
+/// Logs a message for a subsystem, with a newline appended at the end.
+///
+/// The first argument is the [`Subsystem`] and the second is the [`Level`], both given by their
+/// variant name. The message is discarded if its level is above the subsystem's.
+///
+/// Example:
+/// ```rust
+/// log!(Storage, Warn, "could not read partitions of {name}");
+/// ```
+#[allow_internal_unstable(format_args_nl)]
+#[macro_export]
+macro_rules! log {
+	($subsys:ident, $level:ident, $($arg:tt)*) => {{
+		$crate::print::_log(
+			$crate::logger::Subsystem::$subsys,
+			$crate::logger::Level::$level,
+			format_args_nl!($($arg)*),
+		);
+	}};
+}
@@ -0,0 +1,88 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The credentials of a process determine what it is allowed to do.
+
+use crate::file::perm::AccessProfile;
+use utils::{errno::EResult, lock::IntMutex};
+
+/// The credentials of a process: its user and group IDs, along with its supplementary groups.
+///
+/// Credentials are read as an [`AccessProfile`] snapshot, which is never modified in place.
+/// Changing them consists in replacing the whole snapshot at once, so that a permission check
+/// always sees a consistent set of IDs, even if another process sharing the credentials changes
+/// them at the same time.
+#[derive(Debug)]
+pub struct Cred(IntMutex<AccessProfile>);
+
+impl Cred {
+	/// Creates credentials from the given access profile.
+	pub fn new(ap: AccessProfile) -> Self {
+		Self(IntMutex::new(ap))
+	}
+
+	/// Returns a snapshot of the current credentials.
+	pub fn get(&self) -> AccessProfile {
+		*self.0.lock()
+	}
+
+	/// Updates the credentials.
+	///
+	/// `f` is called on a copy of the current credentials, which replaces them if it succeeds.
+	/// Otherwise, the credentials are left unchanged and the error is returned.
+	///
+	/// Updates are serialized, so that the checks made by `f` cannot be invalidated by a
+	/// concurrent update before the new credentials are in place.
+	pub fn update<F: FnOnce(&mut AccessProfile) -> EResult<()>>(&self, f: F) -> EResult<()> {
+		let mut ap = self.0.lock();
+		let mut new = *ap;
+		f(&mut new)?;
+		*ap = new;
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use utils::errno;
+
+	#[test_case]
+	fn update() {
+		let cred = Cred::new(AccessProfile::new(1000, 1000));
+		cred.update(|ap| ap.set_euid(0)).unwrap_err();
+		cred.update(|ap| ap.set_groups(&[10])).unwrap_err();
+		// A failing update must not leave partial changes behind
+		cred.update(|ap| {
+			ap.uid = 0;
+			Err(errno!(EPERM))
+		})
+		.unwrap_err();
+		let ap = cred.get();
+		assert_eq!((ap.uid, ap.euid, ap.suid), (1000, 1000, 1000));
+		assert!(ap.groups().is_empty());
+		let cred = Cred::new(AccessProfile::KERNEL);
+		cred.update(|ap| ap.set_groups(&[10, 20])).unwrap();
+		cred.update(|ap| ap.set_uid(1000)).unwrap();
+		let ap = cred.get();
+		assert_eq!((ap.uid, ap.euid, ap.suid), (1000, 1000, 1000));
+		assert_eq!(ap.groups(), &[10, 20]);
+		assert!(ap.is_in_group(20));
+		assert!(!ap.is_in_group(30));
+	}
+}
@@ -50,6 +50,7 @@ use utils::{
 	errno,
 	errno::{CollectResult, EResult},
 	limits::PAGE_SIZE,
+	ptr::arc::Arc,
 	TryClone,
 };
 
@@ -560,7 +561,7 @@ impl<'s> ELFExecutor<'s> {
 			}
 			// Get file
 			let interp_path = Path::new(interp_path)?;
-			let interp_file = vfs::get_file_from_path(interp_path, self.info.path_resolution)?;
+			let interp_file = vfs::path_cache::resolve(interp_path, self.info.path_resolution)?;
 			// Read and parse file
 			let interp_image =
 				read_exec_file(&interp_file, &self.info.path_resolution.access_profile)?;
@@ -634,7 +635,7 @@ impl<'s> Executor for ELFExecutor<'s> {
 	// TODO Ensure there is no way to write in kernel space (check segments position
 	// and relocations)
 	// TODO Handle suid and sgid
-	fn build_image(&self, file: &vfs::Entry) -> EResult<ProgramImage> {
+	fn build_image(&self, file: &Arc<vfs::Entry>) -> EResult<ProgramImage> {
 		// The ELF file image
 		let image = read_exec_file(file, &self.info.path_resolution.access_profile)?;
 		// Parse the ELF file
@@ -696,6 +697,8 @@ impl<'s> Executor for ELFExecutor<'s> {
 			.0?;
 
 		Ok(ProgramImage {
+			file: file.clone(),
+
 			argv: self.info.argv.try_clone()?,
 			envp,
 
@@ -30,7 +30,12 @@ pub mod vdso;
 use crate::{
 	file::{vfs, vfs::ResolutionSettings},
 	memory::VirtAddr,
-	process::{mem_space::MemSpace, regs::Regs, signal::SignalHandler, Process},
+	process::{
+		mem_space::{copy::SyscallPtr, MemSpace},
+		regs::Regs,
+		signal::SignalHandler,
+		Process,
+	},
 };
 use utils::{
 	collections::{string::String, vec::Vec},
@@ -51,6 +56,9 @@ pub struct ExecInfo<'s> {
 
 /// A built program image.
 pub struct ProgramImage {
+	/// The program's file.
+	file: Arc<vfs::Entry>,
+
 	/// The argv of the program.
 	argv: Vec<String>,
 	/// The environment variables of the program.
@@ -69,7 +77,7 @@ pub struct ProgramImage {
 pub trait Executor {
 	/// Builds a program image.
 	/// `file` is the program's file.
-	fn build_image(&self, file: &vfs::Entry) -> EResult<ProgramImage>;
+	fn build_image(&self, file: &Arc<vfs::Entry>) -> EResult<ProgramImage>;
 }
 
 /// Builds a program image from the given executable file.
@@ -80,7 +88,7 @@ pub trait Executor {
 ///
 /// The function returns a memory space containing the program image and the
 /// pointer to the entry point.
-pub fn build_image(file: &vfs::Entry, info: ExecInfo) -> EResult<ProgramImage> {
+pub fn build_image(file: &Arc<vfs::Entry>, info: ExecInfo) -> EResult<ProgramImage> {
 	// TODO Support other formats than ELF (wasm?)
 
 	let exec = elf::ELFExecutor::new(info)?;
@@ -88,10 +96,14 @@ pub fn build_image(file: &vfs::Entry, info: ExecInfo) -> EResult<ProgramImage> {
 }
 
 /// Executes the program image `image` on the process `proc`.
-pub fn exec(proc: &mut Process, image: ProgramImage) -> EResult<()> {
+pub fn exec(proc: &mut Process, mut image: ProgramImage) -> EResult<()> {
+	// The vDSO depends on the time namespace
+	if proc.time_ns.is_some() {
+		vdso::join_timens(&mut image.mem_space)?;
+	}
 	proc.argv = Arc::new(image.argv)?;
 	proc.envp = Arc::new(image.envp)?;
-	// TODO Set exec path
+	proc.exec_file = Some(image.file);
 	// Set the new memory space to the process
 	proc.set_mem_space(Some(Arc::new(IntMutex::new(image.mem_space))?));
 	// Duplicate the file descriptor table
@@ -108,7 +120,9 @@ pub fn exec(proc: &mut Process, image: ProgramImage) -> EResult<()> {
 	proc.signal_handlers.lock().fill(SignalHandler::Default);
 	proc.reset_vfork();
 	proc.tls_entries = Default::default();
-	proc.update_tss();
+	proc.clear_child_tid = SyscallPtr(None);
+	// The process is running, so its context has to be reloaded now
+	proc.load_context();
 	// Set the process's registers
 	proc.regs = Regs {
 		esp: image.user_stack.0,
@@ -18,6 +18,13 @@
 
 //! The vDSO (virtual dynamic shared object) is a small shared library that the kernel
 //! automatically maps into the memory space of all userspace programs.
+//!
+//! The page preceding the image holds a [`VdsoData`] structure, which exposes the coarse clocks
+//! to userspace, allowing to read them without performing a system call.
+//!
+//! Processes in a time namespace other than the root one use a distinct data page, which tells
+//! the vDSO to read monotonic clocks through the system call, so that the namespace's offsets
+//! are applied.
 
 use crate::{
 	elf::parser::ELFParser,
@@ -29,24 +36,107 @@ use crate::{
 			MapConstraint, MemSpace,
 		},
 	},
+	time::{
+		clock,
+		clock::{CLOCK_MONOTONIC_COARSE, CLOCK_REALTIME_COARSE},
+		unit::TimestampScale,
+	},
+};
+use core::{
+	cmp::min,
+	num::NonZeroUsize,
+	ptr,
+	ptr::{addr_of_mut, NonNull},
+	sync::atomic::{fence, AtomicPtr, Ordering},
 };
-use core::{cmp::min, num::NonZeroUsize, ptr::NonNull};
 use utils::{
-	collections::vec::Vec,
-	errno::{AllocResult, CollectResult, EResult},
-	include_bytes_aligned,
-	limits::PAGE_SIZE,
-	lock::Mutex,
+	collections::vec::Vec, errno::EResult, include_bytes_aligned, limits::PAGE_SIZE, lock::Mutex,
 	ptr::arc::Arc,
 };
 
 /// The ELF image of the vDSO.
 static ELF_IMAGE: &[u8] = include_bytes_aligned!(usize, env!("VDSO_PATH"));
 
+/// Data shared with userspace through the page preceding the vDSO image.
+///
+/// Fields are protected by a sequence lock: the writer increments `seq` before and after
+/// updating, so that a reader has to retry if `seq` is odd or has changed while reading.
+///
+/// The layout must match the offsets used by the vDSO's code.
+#[repr(C)]
+struct VdsoData {
+	/// The sequence counter.
+	seq: u32,
+	/// The duration of a tick, in nanoseconds.
+	tick_ns: u32,
+	/// The number of ticks since boot.
+	jiffies: u64,
+	/// The seconds of [`CLOCK_REALTIME_COARSE`].
+	realtime_sec: u64,
+	/// The nanoseconds of [`CLOCK_REALTIME_COARSE`].
+	realtime_nsec: u32,
+	_pad0: u32,
+	/// The seconds of [`CLOCK_MONOTONIC_COARSE`].
+	monotonic_sec: u64,
+	/// The nanoseconds of [`CLOCK_MONOTONIC_COARSE`].
+	monotonic_nsec: u32,
+	/// Non-zero on the data page of processes in a time namespace other than the root one.
+	timens: u32,
+}
+
+/// The data page, or null if not allocated yet.
+///
+/// This is not behind [`VDSO`] since it is updated from the timer's interrupt handler.
+static DATA: AtomicPtr<VdsoData> = AtomicPtr::new(ptr::null_mut());
+/// The data page of processes in a time namespace other than the root one, or null if not
+/// allocated yet.
+static TIMENS_DATA: AtomicPtr<VdsoData> = AtomicPtr::new(ptr::null_mut());
+
+/// Writes the current state of clocks into the data page at `data`.
+///
+/// # Safety
+///
+/// `data` must point to the data page and there must not be any concurrent writer.
+unsafe fn write_data(data: *mut VdsoData) {
+	let realtime =
+		clock::current_time(CLOCK_REALTIME_COARSE, TimestampScale::Nanosecond).unwrap_or(0);
+	let monotonic =
+		clock::current_time(CLOCK_MONOTONIC_COARSE, TimestampScale::Nanosecond).unwrap_or(0);
+	let tick = clock::resolution(CLOCK_MONOTONIC_COARSE).unwrap_or(0);
+	let seq = addr_of_mut!((*data).seq);
+	seq.write_volatile(seq.read_volatile().wrapping_add(1));
+	fence(Ordering::SeqCst);
+	addr_of_mut!((*data).tick_ns).write_volatile(tick as _);
+	addr_of_mut!((*data).jiffies).write_volatile(clock::jiffies());
+	addr_of_mut!((*data).realtime_sec).write_volatile(realtime / 1_000_000_000);
+	addr_of_mut!((*data).realtime_nsec).write_volatile((realtime % 1_000_000_000) as _);
+	addr_of_mut!((*data).monotonic_sec).write_volatile(monotonic / 1_000_000_000);
+	addr_of_mut!((*data).monotonic_nsec).write_volatile((monotonic % 1_000_000_000) as _);
+	fence(Ordering::SeqCst);
+	seq.write_volatile(seq.read_volatile().wrapping_add(1));
+}
+
+/// Publishes the current state of clocks to userspace.
+///
+/// This function is called on each tick. If the vDSO is not loaded yet, it does nothing.
+pub fn update_clocks() {
+	for data in [&DATA, &TIMENS_DATA] {
+		let data = data.load(Ordering::Acquire);
+		if !data.is_null() {
+			// Safety: ticks are not reentrant
+			unsafe {
+				write_data(data);
+			}
+		}
+	}
+}
+
 /// Information on the vDSO ELF image.
 struct Vdso {
-	/// The list of pages on which the image is loaded.
+	/// The data page, followed by the list of pages on which the image is loaded.
 	pages: Arc<Vec<Arc<ResidencePage>>>,
+	/// Same as `pages`, with the data page of time namespaces.
+	timens_pages: Arc<Vec<Arc<ResidencePage>>>,
 	/// The length of the ELF image in bytes.
 	len: usize,
 
@@ -65,58 +155,114 @@ pub struct MappedVDSO {
 /// The info of the vDSO. If `None`, the vDSO is not loaded yet.
 static VDSO: Mutex<Option<Vdso>> = Mutex::new(None);
 
+/// Allocates and fills a data page.
+///
+/// `timens` tells whether the page is the one of time namespaces.
+fn alloc_data_page(timens: bool) -> EResult<(*mut VdsoData, Arc<ResidencePage>)> {
+	let physaddr = buddy::alloc(0, buddy::FLAG_ZONE_TYPE_KERNEL)?;
+	let page = physaddr.kernel_to_virtual().unwrap().as_ptr::<Page>();
+	let data = page as *mut VdsoData;
+	// Fill the data page before publishing it. If a tick occurs in between, the values are late
+	// by one tick until the next one
+	unsafe {
+		(*page).fill(0);
+		addr_of_mut!((*data).timens).write(timens as _);
+		write_data(data);
+	}
+	Ok((data, Arc::new(ResidencePage::new(physaddr))?))
+}
+
 /// Loads the vDSO in memory and returns the image.
 fn load_image() -> EResult<Vdso> {
 	let parser = ELFParser::new(ELF_IMAGE)?;
 	let entry_off = parser.hdr().e_entry as _;
+	let (data, data_page) = alloc_data_page(false)?;
+	let (timens_data, timens_data_page) = alloc_data_page(true)?;
 	// Load image into pages
 	let pages_count = ELF_IMAGE.len().div_ceil(PAGE_SIZE);
-	let pages = (0..pages_count)
-		.map(|i| {
-			let off = i * PAGE_SIZE;
-			let len = min(PAGE_SIZE, ELF_IMAGE.len() - off);
-			// Alloc page
-			let physaddr = buddy::alloc(0, buddy::FLAG_ZONE_TYPE_KERNEL)?;
-			let virtaddr = physaddr.kernel_to_virtual().unwrap();
-			let virtaddr = unsafe { &mut *virtaddr.as_ptr::<Page>() };
-			// Copy data
-			let src = &ELF_IMAGE[off..(off + len)];
-			virtaddr[..src.len()].copy_from_slice(src);
-			virtaddr[src.len()..].fill(0);
-			Arc::new(ResidencePage::new(physaddr))
-		})
-		.collect::<AllocResult<CollectResult<_>>>()?
-		.0?;
+	let mut pages = Vec::with_capacity(pages_count + 1)?;
+	let mut timens_pages = Vec::with_capacity(pages_count + 1)?;
+	pages.push(data_page)?;
+	timens_pages.push(timens_data_page)?;
+	for i in 0..pages_count {
+		let off = i * PAGE_SIZE;
+		let len = min(PAGE_SIZE, ELF_IMAGE.len() - off);
+		// Alloc page
+		let physaddr = buddy::alloc(0, buddy::FLAG_ZONE_TYPE_KERNEL)?;
+		let virtaddr = physaddr.kernel_to_virtual().unwrap();
+		let virtaddr = unsafe { &mut *virtaddr.as_ptr::<Page>() };
+		// Copy data
+		let src = &ELF_IMAGE[off..(off + len)];
+		virtaddr[..src.len()].copy_from_slice(src);
+		virtaddr[src.len()..].fill(0);
+		let page = Arc::new(ResidencePage::new(physaddr))?;
+		timens_pages.push(page.clone())?;
+		pages.push(page)?;
+	}
+	DATA.store(data, Ordering::Release);
+	TIMENS_DATA.store(timens_data, Ordering::Release);
 	Ok(Vdso {
 		pages: Arc::new(pages)?,
+		timens_pages: Arc::new(timens_pages)?,
 		len: ELF_IMAGE.len(),
 
 		entry_off,
 	})
 }
 
+/// Returns the number of pages of the vDSO, including the data page.
+fn pages_count(img: &Vdso) -> NonZeroUsize {
+	// Add one for the data page
+	let vdso_pages = img.len.div_ceil(PAGE_SIZE) + 1;
+	let Some(vdso_pages) = NonZeroUsize::new(vdso_pages) else {
+		panic!("Invalid vDSO image");
+	};
+	vdso_pages
+}
+
 /// Maps the vDSO into the given memory space.
 ///
 /// The function returns the virtual pointer to the mapped vDSO.
 pub fn map(mem_space: &mut MemSpace) -> EResult<MappedVDSO> {
 	let mut elf_image = VDSO.lock();
 	let img = elf_image.get_or_insert_with(|| load_image().expect("Failed to load vDSO"));
-	let vdso_pages = img.len.div_ceil(PAGE_SIZE);
-	let Some(vdso_pages) = NonZeroUsize::new(vdso_pages) else {
-		panic!("Invalid vDSO image");
-	};
 	// TODO ASLR
-	let begin = mem_space.map(
+	let data = mem_space.map(
 		MapConstraint::None,
-		vdso_pages,
+		pages_count(img),
 		mem_space::MAPPING_FLAG_USER,
 		MapResidence::Static {
 			pages: img.pages.clone(),
 		},
 	)?;
+	mem_space.set_vdso(VirtAddr::from(data));
+	let begin = data.wrapping_add(PAGE_SIZE);
 	let entry_ptr = begin.wrapping_add(img.entry_off);
 	Ok(MappedVDSO {
 		begin: begin.into(),
 		entry: NonNull::new(entry_ptr).unwrap(),
 	})
 }
+
+/// Makes the vDSO mapped in `mem_space` use the data page of time namespaces other than the root
+/// one.
+///
+/// If the vDSO is not mapped, the function does nothing.
+pub fn join_timens(mem_space: &mut MemSpace) -> EResult<()> {
+	let Some(addr) = mem_space.get_vdso() else {
+		return Ok(());
+	};
+	let vdso = VDSO.lock();
+	// Cannot fail since the vDSO is mapped
+	let img = vdso.as_ref().unwrap();
+	// Replace the whole mapping
+	mem_space.map(
+		MapConstraint::Fixed(addr),
+		pages_count(img),
+		mem_space::MAPPING_FLAG_USER,
+		MapResidence::Static {
+			pages: img.timens_pages.clone(),
+		},
+	)?;
+	Ok(())
+}
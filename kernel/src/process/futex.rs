@@ -0,0 +1,327 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Fast userspace mutexes (futexes) allow userspace to implement locking primitives, entering the
+//! kernel only when a thread has to wait or to wake up others.
+//!
+//! A futex is identified by the physical address of its 32 bits word, which makes futexes in
+//! shared memory work across processes. Waiters are stored in a table of wait queues, indexed by a
+//! hash of this address.
+
+use crate::{
+	memory::{vmem, PhysAddr, VirtAddr},
+	process,
+	process::{mem_space::copy::SyscallPtr, pid::Pid, scheduler, Process},
+	time::{
+		clock,
+		unit::{ClockIdT, Timestamp, TimestampScale},
+	},
+	workqueue,
+};
+use core::{
+	mem::size_of,
+	sync::atomic::{AtomicBool, AtomicUsize, Ordering::Relaxed},
+};
+use utils::{
+	collections::vec::Vec,
+	errno,
+	errno::EResult,
+	lock::{IntMutex, IntMutexGuard},
+	ptr::arc::Arc,
+};
+
+/// Bitset matching every waiter.
+pub const FUTEX_BITSET_MATCH_ANY: u32 = !0;
+
+/// The number of wait queues in the table.
+const BUCKETS_COUNT: usize = 64;
+/// Value of a waiter's key once it has been woken up.
+///
+/// Since futex words are aligned, this value cannot be a valid key.
+const WOKEN: usize = usize::MAX;
+
+/// A process waiting on a futex.
+#[derive(Clone, Debug)]
+struct Waiter {
+	/// The PID of the waiting process.
+	pid: Pid,
+	/// The bitset given to the wait operation, matched against the one of wake operations.
+	bitset: u32,
+	/// The physical address of the futex the process is waiting on, or [`WOKEN`].
+	///
+	/// The value is shared with the waiting process. It is only modified while holding the lock
+	/// of the wait queue containing the waiter.
+	key: Arc<AtomicUsize>,
+}
+
+/// The table of wait queues.
+static BUCKETS: [IntMutex<Vec<Waiter>>; BUCKETS_COUNT] =
+	[const { IntMutex::new(Vec::new()) }; BUCKETS_COUNT];
+
+/// Returns the index of the wait queue for the futex with the given `key`.
+fn bucket_index(key: usize) -> usize {
+	(key / size_of::<u32>()) % BUCKETS_COUNT
+}
+
+/// Returns the key of the futex at the user address `addr`, which is the physical address of the
+/// futex word.
+///
+/// The page containing the futex is mapped beforehand. If the mapping is writable, a private page
+/// replaces Copy-On-Write and default pages, so that the key does not change on the next write.
+///
+/// If the address is not aligned, the function returns [`errno::EINVAL`]. If it is not mapped,
+/// the function returns [`errno::EFAULT`].
+fn get_key(addr: &SyscallPtr<u32>) -> EResult<usize> {
+	let addr = VirtAddr::from(addr.as_ptr());
+	if addr.is_null() {
+		return Err(errno!(EFAULT));
+	}
+	if !addr.is_aligned_to(size_of::<u32>()) {
+		return Err(errno!(EINVAL));
+	}
+	let mem_space = Process::current().lock().get_mem_space().unwrap().clone();
+	let mut mem_space = mem_space.lock();
+	let mapping = mem_space
+		.get_mapping_for_addr(addr)
+		.ok_or_else(|| errno!(EFAULT))?;
+	let code = if mapping.get_flags() & process::mem_space::MAPPING_FLAG_WRITE != 0 {
+		vmem::x86::PAGE_FAULT_WRITE
+	} else {
+		0
+	};
+	if !mem_space.handle_page_fault(addr, code) {
+		return Err(errno!(EFAULT));
+	}
+	let PhysAddr(key) = mem_space
+		.get_vmem()
+		.translate(addr)
+		.ok_or_else(|| errno!(EFAULT))?;
+	Ok(key)
+}
+
+/// Reads the value of the futex word at `addr`.
+fn read_word(addr: &SyscallPtr<u32>) -> EResult<u32> {
+	addr.copy_from_user()?.ok_or_else(|| errno!(EFAULT))
+}
+
+/// Locks the wait queues at indexes `i` and `j`, in increasing order to avoid deadlocks.
+///
+/// `i` and `j` must be different.
+fn lock_pair(
+	i: usize,
+	j: usize,
+) -> (
+	IntMutexGuard<'static, Vec<Waiter>>,
+	IntMutexGuard<'static, Vec<Waiter>>,
+) {
+	if i < j {
+		let a = BUCKETS[i].lock();
+		let b = BUCKETS[j].lock();
+		(a, b)
+	} else {
+		let b = BUCKETS[j].lock();
+		let a = BUCKETS[i].lock();
+		(a, b)
+	}
+}
+
+/// Wakes at most `count` processes from `waiters` waiting on the futex with the given `key` and
+/// whose bitset matches `bitset`.
+///
+/// The function returns the number of processes that have been woken up.
+fn wake_waiters(waiters: &mut Vec<Waiter>, key: usize, bitset: u32, count: usize) -> usize {
+	let mut woken = 0;
+	waiters.retain(|w| {
+		if woken >= count || w.key.load(Relaxed) != key || w.bitset & bitset == 0 {
+			return true;
+		}
+		w.key.store(WOKEN, Relaxed);
+		if let Some(proc) = Process::get_by_pid(w.pid) {
+			proc.lock().wake();
+		}
+		woken += 1;
+		false
+	});
+	woken
+}
+
+/// Makes the current process wait on the futex at `addr`, if it contains the value `val`.
+///
+/// Arguments:
+/// - `bitset` is matched against the bitset of wake operations. It cannot be zero
+/// - `deadline` is the clock and timestamp in nanoseconds at which waiting stops with
+///   [`errno::ETIMEDOUT`]. If `None`, waiting has no time limit
+///
+/// If the futex does not contain `val`, the function returns [`errno::EAGAIN`].
+///
+/// If waiting is interrupted by a signal handler, the function returns [`errno::EINTR`].
+pub fn wait(
+	addr: &SyscallPtr<u32>,
+	val: u32,
+	bitset: u32,
+	deadline: Option<(ClockIdT, Timestamp)>,
+) -> EResult<()> {
+	if bitset == 0 {
+		return Err(errno!(EINVAL));
+	}
+	let key = get_key(addr)?;
+	let pid = Process::current().lock().get_pid();
+	let state = Arc::new(AtomicUsize::new(key))?;
+	// Checking the value and queueing are atomic with respect to wake operations
+	{
+		let mut waiters = BUCKETS[bucket_index(key)].lock();
+		if read_word(addr)? != val {
+			return Err(errno!(EAGAIN));
+		}
+		waiters.push(Waiter {
+			pid,
+			bitset,
+			key: state.clone(),
+		})?;
+	}
+	// Tells whether a timer waking up the process at the deadline is pending
+	let armed = Arc::new(AtomicBool::new(false))?;
+	loop {
+		// The remaining time before the deadline, in nanoseconds
+		let remaining = deadline.map(|(clk, ts)| {
+			clock::current_time(clk, TimestampScale::Nanosecond)
+				.map_or(0, |cur| ts.saturating_sub(cur))
+		});
+		{
+			let key = state.load(Relaxed);
+			if key == WOKEN {
+				return Ok(());
+			}
+			let mut waiters = BUCKETS[bucket_index(key)].lock();
+			// The waiter might have been woken up or requeued in the meantime
+			if state.load(Relaxed) != key {
+				continue;
+			}
+			let proc_mutex = Process::current();
+			let mut proc = proc_mutex.lock();
+			let interrupted = proc.next_signal(true).is_some();
+			if interrupted || remaining == Some(0) {
+				waiters.retain(|w| w.pid != pid);
+				return Err(if interrupted {
+					errno!(EINTR)
+				} else {
+					errno!(ETIMEDOUT)
+				});
+			}
+			// Make sure the process is woken up at the deadline
+			if let Some(remaining) = remaining {
+				if !armed.swap(true, Relaxed) {
+					let armed = armed.clone();
+					let res = workqueue::queue_delayed_work(
+						move || {
+							armed.store(false, Relaxed);
+							if let Some(proc) = Process::get_by_pid(pid) {
+								proc.lock().wake();
+							}
+						},
+						remaining.div_ceil(1_000_000),
+					);
+					if let Err(e) = res {
+						waiters.retain(|w| w.pid != pid);
+						return Err(e);
+					}
+				}
+			}
+			proc.set_state(process::State::Sleeping);
+		}
+		scheduler::end_tick();
+	}
+}
+
+/// Wakes at most `count` processes waiting on the futex at `addr` with a bitset matching
+/// `bitset`.
+///
+/// The function returns the number of processes that have been woken up.
+pub fn wake(addr: &SyscallPtr<u32>, count: usize, bitset: u32) -> EResult<usize> {
+	if bitset == 0 {
+		return Err(errno!(EINVAL));
+	}
+	let key = get_key(addr)?;
+	let mut waiters = BUCKETS[bucket_index(key)].lock();
+	Ok(wake_waiters(&mut waiters, key, bitset, count))
+}
+
+/// Wakes at most `count` processes waiting on the futex at `addr`, then moves at most
+/// `requeue_count` of the remaining waiters to the futex at `addr2`.
+///
+/// If `cmp` is specified and the futex at `addr` does not contain this value, the function returns
+/// [`errno::EAGAIN`].
+///
+/// The function returns the number of processes that have been woken up and, if `cmp` is
+/// specified, the number of processes that have been requeued.
+pub fn requeue(
+	addr: &SyscallPtr<u32>,
+	count: usize,
+	addr2: &SyscallPtr<u32>,
+	requeue_count: usize,
+	cmp: Option<u32>,
+) -> EResult<usize> {
+	let key = get_key(addr)?;
+	let key2 = get_key(addr2)?;
+	let (i, j) = (bucket_index(key), bucket_index(key2));
+	let check = || match cmp {
+		Some(cmp) if read_word(addr)? != cmp => Err(errno!(EAGAIN)),
+		_ => Ok(()),
+	};
+	let mut requeued = 0;
+	let woken = if i == j {
+		let mut waiters = BUCKETS[i].lock();
+		check()?;
+		let woken = wake_waiters(&mut waiters, key, FUTEX_BITSET_MATCH_ANY, count);
+		// Both futexes share the same queue, so updating keys is enough
+		for w in waiters.iter() {
+			if requeued >= requeue_count {
+				break;
+			}
+			if w.key.load(Relaxed) == key {
+				w.key.store(key2, Relaxed);
+				requeued += 1;
+			}
+		}
+		woken
+	} else {
+		let (mut waiters, mut waiters2) = lock_pair(i, j);
+		check()?;
+		let woken = wake_waiters(&mut waiters, key, FUTEX_BITSET_MATCH_ANY, count);
+		let mut res = Ok(());
+		waiters.retain(|w| {
+			if res.is_err() || requeued >= requeue_count || w.key.load(Relaxed) != key {
+				return true;
+			}
+			res = waiters2.push(w.clone());
+			if res.is_err() {
+				return true;
+			}
+			w.key.store(key2, Relaxed);
+			requeued += 1;
+			false
+		});
+		res?;
+		woken
+	};
+	if cmp.is_some() {
+		Ok(woken + requeued)
+	} else {
+		Ok(woken)
+	}
+}
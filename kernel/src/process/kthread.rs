@@ -0,0 +1,267 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Kernel threads are tasks executing kernel code in the background, scheduled alongside user
+//! processes.
+//!
+//! A kernel thread is spawned with a name, which is shown as its command line, and a function to
+//! execute on its own kernel stack. The thread exits when the function returns.
+//!
+//! Other tasks can ask a kernel thread to park, that is to sleep until it is unparked, or to stop.
+//! The thread is responsible for checking these requests regularly with [`should_park`] and
+//! [`should_stop`], and for calling [`park`] when asked to. A kernel thread doing periodic work
+//! waits between two rounds with [`sleep`], which returns early on such requests.
+
+use crate::{
+	file::wait_queue::WaitQueue,
+	process::{pid::Pid, scheduler, Process},
+	time::unit::Timestamp,
+	workqueue,
+};
+use core::sync::atomic::{
+	AtomicBool,
+	Ordering::{Acquire, Release},
+};
+use utils::{
+	boxed::Box, collections::hashmap::HashMap, errno::EResult, lock::IntMutex, ptr::arc::Arc,
+};
+
+/// Control structure of a kernel thread, shared between the thread and its handles.
+struct KThreadCtl {
+	/// The name of the thread.
+	name: &'static str,
+	/// The function to execute. It is taken by the thread when it starts.
+	entry: IntMutex<Option<Box<dyn FnMut()>>>,
+
+	/// Tells whether the thread has been asked to stop.
+	stop: AtomicBool,
+	/// Tells whether the thread has been asked to park.
+	park: AtomicBool,
+	/// Tells whether the thread is currently parked.
+	parked: AtomicBool,
+	/// Tells whether the thread has exited.
+	exited: AtomicBool,
+
+	/// The queue on which the thread sleeps while parked, and on which other tasks wait for the
+	/// thread to park or exit.
+	queue: WaitQueue,
+}
+
+/// The control structures of running kernel threads, by PID.
+static KTHREADS: IntMutex<HashMap<Pid, Arc<KThreadCtl>>> = IntMutex::new(HashMap::new());
+
+/// Returns the control structure of the current kernel thread.
+///
+/// If the current process is not a kernel thread spawned with [`spawn`], the function returns
+/// `None`.
+fn current() -> Option<Arc<KThreadCtl>> {
+	let pid = scheduler::current_process()?.lock().get_pid();
+	KTHREADS.lock().get(&pid).cloned()
+}
+
+/// The entry point of every kernel thread spawned with [`spawn`].
+fn kthread_entry() -> ! {
+	let proc_mutex = Process::current();
+	let pid = proc_mutex.lock().get_pid();
+	let ctl = current().expect("kernel thread without control structure");
+	let entry = ctl.entry.lock().take();
+	if let Some(mut entry) = entry {
+		(*entry)();
+	}
+	// Exit
+	KTHREADS.lock().remove(&pid);
+	ctl.exited.store(true, Release);
+	ctl.queue.wake_all();
+	proc_mutex.lock().exit(0);
+	drop(proc_mutex);
+	scheduler::end_tick();
+	unreachable!();
+}
+
+/// Spawns a kernel thread with the given `name`, executing `f`.
+///
+/// The thread exits when `f` returns.
+///
+/// This function must not be called before the init process has been created.
+pub fn spawn<F: 'static + FnMut()>(name: &'static str, f: F) -> EResult<KThread> {
+	let ctl = Arc::new(KThreadCtl {
+		name,
+		entry: IntMutex::new(Some(Box::new(f)?)),
+
+		stop: AtomicBool::new(false),
+		park: AtomicBool::new(false),
+		parked: AtomicBool::new(false),
+		exited: AtomicBool::new(false),
+
+		queue: WaitQueue::new(),
+	})?;
+	// Keep the registry locked so that the thread cannot start before being registered
+	let mut kthreads = KTHREADS.lock();
+	let pid = Process::new_kthread(name, kthread_entry)?.lock().get_pid();
+	if let Err(e) = kthreads.insert(pid, ctl.clone()) {
+		// The thread has not started yet, remove it
+		scheduler::SCHEDULER.get().lock().remove_process(pid);
+		return Err(e.into());
+	}
+	Ok(KThread {
+		pid,
+		ctl,
+	})
+}
+
+/// Tells whether the process with PID `pid` is a kernel thread spawned with [`spawn`].
+pub fn is_kthread(pid: Pid) -> bool {
+	KTHREADS.lock().contains_key(&pid)
+}
+
+/// Tells whether the current kernel thread has been asked to stop.
+///
+/// If the current process is not a kernel thread, the function returns `false`.
+pub fn should_stop() -> bool {
+	current().is_some_and(|ctl| ctl.stop.load(Acquire))
+}
+
+/// Tells whether the current kernel thread has been asked to park.
+///
+/// If the current process is not a kernel thread, the function returns `false`.
+pub fn should_park() -> bool {
+	current().is_some_and(|ctl| ctl.park.load(Acquire))
+}
+
+/// Parks the current kernel thread, if asked to, until it is unparked or asked to stop.
+///
+/// If the current process is not a kernel thread, the function does nothing.
+pub fn park() {
+	let Some(ctl) = current() else {
+		return;
+	};
+	if !ctl.park.load(Acquire) {
+		return;
+	}
+	ctl.parked.store(true, Release);
+	ctl.queue.wake_all();
+	// Kernel threads do not receive signals, but retry in case waiting was interrupted
+	while ctl
+		.queue
+		.wait_until(|| (!ctl.park.load(Acquire)).then_some(()))
+		.is_err()
+	{}
+	ctl.parked.store(false, Release);
+}
+
+/// Makes the current kernel thread sleep for `delay` milliseconds, or until it is asked to park
+/// or to stop.
+///
+/// This function must not be called from the worker thread of a workqueue, since the thread is
+/// woken up by a work item.
+///
+/// If the current process is not a kernel thread, the function does nothing.
+pub fn sleep(delay: Timestamp) {
+	let Some(ctl) = current() else {
+		return;
+	};
+	let Ok(expired) = Arc::new(AtomicBool::new(false)) else {
+		return;
+	};
+	let res = workqueue::queue_delayed_work(
+		{
+			let ctl = ctl.clone();
+			let expired = expired.clone();
+			move || {
+				expired.store(true, Release);
+				ctl.queue.wake_all();
+			}
+		},
+		delay,
+	);
+	if res.is_err() {
+		return;
+	}
+	// Kernel threads do not receive signals, but retry in case waiting was interrupted
+	while ctl
+		.queue
+		.wait_until(|| {
+			let done = expired.load(Acquire) || ctl.stop.load(Acquire) || ctl.park.load(Acquire);
+			done.then_some(())
+		})
+		.is_err()
+	{}
+}
+
+/// Handle to a kernel thread.
+///
+/// Dropping the handle does not stop the thread.
+pub struct KThread {
+	/// The PID of the thread.
+	pid: Pid,
+	/// The control structure of the thread.
+	ctl: Arc<KThreadCtl>,
+}
+
+impl KThread {
+	/// Returns the PID of the thread.
+	pub fn get_pid(&self) -> Pid {
+		self.pid
+	}
+
+	/// Returns the name of the thread.
+	pub fn get_name(&self) -> &'static str {
+		self.ctl.name
+	}
+
+	/// Wakes the thread if it is sleeping, so that it notices requests.
+	fn wake(&self) {
+		if self.ctl.exited.load(Acquire) {
+			return;
+		}
+		if let Some(proc) = Process::get_by_pid(self.pid) {
+			proc.lock().wake();
+		}
+		self.ctl.queue.wake_all();
+	}
+
+	/// Asks the thread to park, then waits until it is parked or has exited.
+	///
+	/// If waiting is interrupted by a signal, the function returns [`utils::errno::EINTR`].
+	pub fn park(&self) -> EResult<()> {
+		self.ctl.park.store(true, Release);
+		self.wake();
+		self.ctl.queue.wait_until(|| {
+			let done = self.ctl.parked.load(Acquire) || self.ctl.exited.load(Acquire);
+			done.then_some(())
+		})
+	}
+
+	/// Unparks the thread.
+	pub fn unpark(&self) {
+		self.ctl.park.store(false, Release);
+		self.ctl.queue.wake_all();
+	}
+
+	/// Asks the thread to stop, unparking it if necessary, then waits until it has exited.
+	///
+	/// If waiting is interrupted by a signal, the function returns [`utils::errno::EINTR`].
+	pub fn stop(&self) -> EResult<()> {
+		self.ctl.stop.store(true, Release);
+		self.ctl.park.store(false, Release);
+		self.wake();
+		self.ctl
+			.queue
+			.wait_until(|| self.ctl.exited.load(Acquire).then_some(()))
+	}
+}
@@ -0,0 +1,103 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Load average of the system.
+//!
+//! The load average is an exponentially decaying average of the number of running processes,
+//! sampled at a regular interval. It is computed over 1, 5 and 15 minutes using fixed-point
+//! arithmetic, the same way Linux does.
+
+use crate::time::unit::Timestamp;
+
+/// The number of bits of precision of fixed-point values.
+pub const FSHIFT: u32 = 11;
+/// The fixed-point representation of `1`.
+pub const FIXED_1: u64 = 1 << FSHIFT;
+/// The interval between two samples, in nanoseconds.
+const LOAD_FREQ: Timestamp = 5_000_000_000;
+
+/// Decay factors for each average, in fixed-point: `FIXED_1 / exp(5s / period)`.
+const EXP: [u64; 3] = [
+	// 1 minute
+	1884, // 5 minutes
+	2014, // 15 minutes
+	2037,
+];
+
+/// Returns the new value of the fixed-point average `load` with decay factor `exp`, given the
+/// fixed-point number of `active` processes.
+fn calc_load(load: u64, exp: u64, active: u64) -> u64 {
+	let new = load * exp + active * (FIXED_1 - exp);
+	// Round up when the load is increasing so that it can reach its target
+	let round = if active >= load { FIXED_1 - 1 } else { 0 };
+	(new + round) >> FSHIFT
+}
+
+/// The load average over 1, 5 and 15 minutes.
+#[derive(Debug, Default)]
+pub struct LoadAvg {
+	/// The averages, in fixed-point.
+	avg: [u64; 3],
+	/// The timestamp of the next sample, in nanoseconds since boot.
+	next_sample: Timestamp,
+}
+
+impl LoadAvg {
+	/// Updates the averages with `running` processes running at the time `now`, in nanoseconds
+	/// since boot.
+	///
+	/// Samples which have been missed since the last update, for example because the scheduler
+	/// did not tick, are accounted with the same number of running processes.
+	pub fn update(&mut self, now: Timestamp, running: usize) {
+		let active = running as u64 * FIXED_1;
+		while now >= self.next_sample {
+			for (avg, exp) in self.avg.iter_mut().zip(EXP) {
+				*avg = calc_load(*avg, exp, active);
+			}
+			self.next_sample += LOAD_FREQ;
+		}
+	}
+
+	/// Returns the averages over 1, 5 and 15 minutes, in fixed-point.
+	///
+	/// To get the actual value, divide by [`FIXED_1`].
+	pub fn get(&self) -> [u64; 3] {
+		self.avg
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test_case]
+	fn loadavg_converge() {
+		let mut load = LoadAvg::default();
+		// One process running for 15 minutes
+		load.update(15 * 60 * 1_000_000_000, 1);
+		let [one, five, fifteen] = load.get();
+		assert_eq!(one, FIXED_1);
+		assert!(five > FIXED_1 * 9 / 10 && five <= FIXED_1);
+		assert!(fifteen > FIXED_1 / 2 && fifteen < five);
+		// Idle for 15 minutes
+		load.update(30 * 60 * 1_000_000_000, 0);
+		let [one, five, fifteen] = load.get();
+		assert_eq!(one, 0);
+		assert!(five < fifteen);
+	}
+}
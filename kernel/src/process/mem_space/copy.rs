@@ -30,10 +30,10 @@ use core::{
 	ptr::{null_mut, NonNull},
 };
 use utils::{
-	collections::{string::String, vec::Vec},
+	collections::{path::PathBuf, string::String, vec::Vec},
 	errno,
 	errno::EResult,
-	limits::PAGE_SIZE,
+	limits::{PAGE_SIZE, PATH_MAX},
 };
 
 // TODO optimize copy
@@ -275,10 +275,12 @@ impl SyscallString {
 		self.0.map(NonNull::as_ptr).unwrap_or(null_mut())
 	}
 
-	/// Returns an immutable reference to the string.
+	/// Copies the string from userspace, reading at most `max` bytes, including the terminating
+	/// nul byte.
 	///
-	/// If the string is not accessible, the function returns an error.
-	pub fn copy_from_user(&self) -> EResult<Option<String>> {
+	/// If no nul byte is found within the first `max` bytes, the function returns
+	/// [`errno::ENAMETOOLONG`].
+	fn copy_from_user_impl(&self, max: usize) -> EResult<Option<String>> {
 		let Some(ptr) = self.0 else {
 			return Ok(None);
 		};
@@ -288,11 +290,14 @@ impl SyscallString {
 		let mut buf = Vec::new();
 		loop {
 			let buf_cursor = buf.len();
+			if unlikely(buf_cursor >= max) {
+				return Err(errno!(ENAMETOOLONG));
+			}
 			// May not wrap since the chunk size is obviously lower than the size of the
 			// kernelspace
 			let user_cursor = ptr.as_ptr().wrapping_add(buf_cursor);
 			let page_end = PAGE_SIZE - (user_cursor as usize % PAGE_SIZE);
-			let len = min(page_end, CHUNK_SIZE);
+			let len = min(min(page_end, CHUNK_SIZE), max - buf_cursor);
 			// Read the next chunk
 			buf.reserve(len)?;
 			unsafe {
@@ -310,6 +315,25 @@ impl SyscallString {
 		}
 		Ok(Some(buf.into()))
 	}
+
+	/// Returns an immutable reference to the string.
+	///
+	/// If the string is not accessible, the function returns an error.
+	pub fn copy_from_user(&self) -> EResult<Option<String>> {
+		self.copy_from_user_impl(usize::MAX)
+	}
+
+	/// Copies the string from userspace as a path.
+	///
+	/// If the path, including the terminating nul byte, is longer than [`PATH_MAX`], the function
+	/// returns [`errno::ENAMETOOLONG`] without reading the rest of the string.
+	///
+	/// If the string is not accessible, the function returns an error.
+	pub fn copy_path_from_user(&self) -> EResult<Option<PathBuf>> {
+		self.copy_from_user_impl(PATH_MAX)?
+			.map(PathBuf::try_from)
+			.transpose()
+	}
 }
 
 impl fmt::Debug for SyscallString {
@@ -0,0 +1,96 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Kernel Samepage Merging (KSM) saves memory by merging identical pages of private anonymous
+//! mappings.
+//!
+//! Processes opt in with the `MADV_MERGEABLE` advice of `madvise`. The KSM daemon regularly scans
+//! mergeable mappings, and replaces pages with identical contents by a single page, shared in
+//! Copy-On-Write mode. The first write to a merged page gets a private copy of it again.
+
+use crate::{
+	process::{
+		kthread,
+		mem_space::{residence::ResidencePage, MemSpace},
+		scheduler::SCHEDULER,
+	},
+	time::unit::Timestamp,
+};
+use utils::{
+	collections::{btreemap::BTreeMap, vec::Vec},
+	errno::{AllocResult, CollectResult, EResult},
+	lock::IntMutex,
+	ptr::arc::Arc,
+};
+
+/// The interval between two scans of the KSM daemon, in milliseconds.
+const SCAN_INTERVAL: Timestamp = 1000;
+
+/// Write-protected pages that other pages can be merged with, by hash of their contents.
+pub type StablePages = BTreeMap<u64, Arc<ResidencePage>>;
+
+/// Scans the memory spaces of all processes, merging identical pages of mergeable mappings.
+///
+/// `stable` is the set of pages to merge with, which is kept from one scan to the next.
+fn scan(stable: &mut StablePages) -> AllocResult<()> {
+	// Forget the pages that are not mapped anywhere anymore
+	stable.retain(|_, page| Arc::strong_count(page) > 1);
+	let procs = SCHEDULER
+		.get()
+		.lock()
+		.iter_process()
+		.map(|(_, proc)| proc.clone())
+		.collect::<CollectResult<Vec<_>>>()
+		.0?;
+	let mut mem_spaces: Vec<Arc<IntMutex<MemSpace>>> = Vec::new();
+	for proc in procs {
+		let Some(mem_space) = proc.lock().get_mem_space().cloned() else {
+			continue;
+		};
+		// Threads of the same process share their memory space
+		if !mem_spaces
+			.iter()
+			.any(|m| Arc::as_ptr(m) == Arc::as_ptr(&mem_space))
+		{
+			mem_spaces.push(mem_space)?;
+		}
+	}
+	for mem_space in mem_spaces {
+		mem_space.lock().merge_pages(stable)?;
+	}
+	Ok(())
+}
+
+/// Scans memory periodically, until asked to stop. This function is the body of the KSM daemon.
+fn scan_loop() {
+	let mut stable = StablePages::new();
+	while !kthread::should_stop() {
+		kthread::park();
+		// On failure, the remaining pages are scanned on the next round
+		let _ = scan(&mut stable);
+		kthread::sleep(SCAN_INTERVAL);
+	}
+}
+
+/// Starts the KSM daemon.
+///
+/// This function must be called only once, after the workqueues have been started.
+pub(crate) fn init() -> EResult<()> {
+	kthread::spawn("ksmd", scan_loop)?;
+	Ok(())
+}
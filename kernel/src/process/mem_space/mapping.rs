@@ -23,19 +23,17 @@
 
 use super::gap::MemGap;
 use crate::{
-	memory::{
-		vmem,
-		vmem::{VMem, VMemTransaction},
-		VirtAddr,
-	},
+	file::page_cache,
+	memory::{vmem, vmem::VMemTransaction, VirtAddr},
 	process::mem_space::{
+		ksm::StablePages,
 		residence::{MapResidence, Page, ResidencePage},
 		COPY_BUFFER,
 	},
 };
-use core::{alloc::AllocError, num::NonZeroUsize, ops::Range, slice};
+use core::{alloc::AllocError, num::NonZeroUsize, ops::Range};
 use utils::{
-	collections::vec::Vec,
+	collections::{hashmap, hashmap::hash::FxHasher, vec::Vec},
 	errno::{AllocResult, EResult},
 	limits::PAGE_SIZE,
 	ptr::arc::Arc,
@@ -103,6 +101,11 @@ impl MemMapping {
 		self.flags
 	}
 
+	/// Returns the residence of the mapping.
+	pub fn get_residence(&self) -> &MapResidence {
+		&self.residence
+	}
+
 	/// Tells whether the given `page` is in COW mode.
 	///
 	/// An offset is in COW mode if the mapping is not shared, and the number of references to the
@@ -151,6 +154,11 @@ impl MemMapping {
 		offset: usize,
 		vmem_transaction: &mut VMemTransaction<false>,
 	) -> AllocResult<()> {
+		if self.residence.is_file() {
+			return self
+				.map_file_page(offset, true, vmem_transaction)
+				.map_err(|_| AllocError);
+		}
 		let virtaddr = VirtAddr::from(self.begin) + offset * PAGE_SIZE;
 		// Get previous page
 		let previous = self
@@ -167,26 +175,43 @@ impl MemMapping {
 			_ => {}
 		}
 		// Allocate and map new page
-		let new = self.residence.acquire_page(offset)?;
+		let new = self
+			.residence
+			.acquire_page(offset)
+			.map_err(|_| AllocError)?;
 		// Tells initializing the new page is necessary
-		let init = self.residence.is_normal();
+		if !self.residence.is_normal() {
+			// Map new page
+			let flags = self.get_vmem_flags(true);
+			return vmem_transaction.map(new.get(), virtaddr, flags);
+		}
+		let previous = previous.clone();
+		self.init_page(offset, new, previous.as_deref(), vmem_transaction)
+	}
+
+	/// Initializes the `new` page at offset `offset` and maps it with write access if the mapping
+	/// allows it.
+	///
+	/// If `previous` is not `None`, the content of the page is copied from it. Else, the page is
+	/// zeroed.
+	fn init_page(
+		&mut self,
+		offset: usize,
+		new: Arc<ResidencePage>,
+		previous: Option<&ResidencePage>,
+		vmem_transaction: &mut VMemTransaction<false>,
+	) -> AllocResult<()> {
+		let virtaddr = VirtAddr::from(self.begin) + offset * PAGE_SIZE;
 		// Tells whether a copy from the previous page is necessary
 		let copy = previous.is_some();
-		if init {
-			if let Some(previous) = &previous {
-				// Map previous page for copy
-				vmem_transaction.map(previous.get(), COPY_BUFFER, 0)?;
-			}
+		if let Some(previous) = previous {
+			// Map previous page for copy
+			vmem_transaction.map(previous.get(), COPY_BUFFER, 0)?;
 		}
-		// Map new page
+		// Map new page. Do not allow writing during initialization to avoid concurrency issues
 		let new_physaddr = new.get();
-		// If the page has to be initialized, do not allow writing during initialization to avoid
-		// concurrency issues
-		let flags = self.get_vmem_flags(!init);
+		let flags = self.get_vmem_flags(false);
 		vmem_transaction.map(new_physaddr, virtaddr, flags)?;
-		if !init {
-			return Ok(());
-		}
 		// Initialize the new page
 		unsafe {
 			let dest = self.begin.add(offset * PAGE_SIZE) as *mut Page;
@@ -214,31 +239,174 @@ impl MemMapping {
 		Ok(())
 	}
 
-	/// Applies the mapping to the given `vmem_transaction`.
-	pub fn apply_to(&mut self, vmem_transaction: &mut VMemTransaction<false>) -> AllocResult<()> {
-		let default_page = self.residence.get_default_page();
-		if let Some(default_page) = default_page {
-			for (offset, phys_page) in self.phys_pages.iter().enumerate() {
-				let (physaddr, write) = phys_page
-					.as_ref()
-					.map(|physaddr| {
-						let write = !Self::is_cow(physaddr, self.flags);
-						(physaddr.get(), write)
-					})
-					.unwrap_or((default_page, false));
-				let virtaddr = VirtAddr::from(self.begin) + offset * PAGE_SIZE;
-				let flags = self.get_vmem_flags(write);
-				vmem_transaction.map(physaddr, virtaddr, flags)?;
-				// TODO invalidate cache for this page
+	/// Maps the page at offset `offset` of a file mapping, loading it from the page cache if
+	/// necessary.
+	///
+	/// `write` tells whether the page is accessed for writing:
+	/// - for a shared mapping, the page of the page cache is mapped with write access and marked
+	///   as dirty, so that it gets written back to the file
+	/// - for a private mapping, the page is copied, so that modifications are not visible to other
+	///   mappings nor to the file
+	///
+	/// Otherwise, the page is mapped read-only, so that the first write to it is detected.
+	///
+	/// If the page is past the end of the file, the function returns [`errno::EFAULT`].
+	pub(super) fn map_file_page(
+		&mut self,
+		offset: usize,
+		write: bool,
+		vmem_transaction: &mut VMemTransaction<false>,
+	) -> EResult<()> {
+		let virtaddr = VirtAddr::from(self.begin) + offset * PAGE_SIZE;
+		let page = match self.phys_pages.get(offset).ok_or(AllocError)? {
+			Some(page) => page.clone(),
+			None => {
+				let page = self.residence.acquire_page(offset)?;
+				self.phys_pages[offset] = Some(page.clone());
+				page
 			}
+		};
+		let write = write && self.flags & super::MAPPING_FLAG_WRITE != 0;
+		if self.flags & super::MAPPING_FLAG_SHARED != 0 {
+			if write {
+				if let Some((loc, index)) = self.residence.file_page(offset) {
+					page_cache::mark_dirty(loc, index);
+				}
+			}
+			let flags = self.get_vmem_flags(write);
+			vmem_transaction.map(page.get(), virtaddr, flags)?;
+			return Ok(());
+		}
+		let cow = Self::is_cow(&page, self.flags);
+		if write && cow {
+			// Copy the page so that modifications remain private
+			let new = MapResidence::Normal.acquire_page(0)?;
+			self.init_page(offset, new, Some(&page), vmem_transaction)?;
 		} else {
+			let flags = self.get_vmem_flags(write || !cow);
+			vmem_transaction.map(page.get(), virtaddr, flags)?;
+		}
+		Ok(())
+	}
+
+	/// Maps the page at offset `offset` after an access to it.
+	///
+	/// `write` tells whether the page is accessed for writing. If not, no physical page is
+	/// allocated: the page is either mapped read-only, or in write mode if it is not in
+	/// Copy-On-Write mode.
+	pub(super) fn map_page(
+		&mut self,
+		offset: usize,
+		write: bool,
+		vmem_transaction: &mut VMemTransaction<false>,
+	) -> EResult<()> {
+		if self.residence.is_file() {
+			return self.map_file_page(offset, write, vmem_transaction);
+		}
+		if write {
+			return Ok(self.alloc(offset, vmem_transaction)?);
+		}
+		let virtaddr = VirtAddr::from(self.begin) + offset * PAGE_SIZE;
+		let page = self.phys_pages.get(offset).ok_or(AllocError)?;
+		match (page, self.residence.get_default_page()) {
+			(Some(page), _) => {
+				let flags = self.get_vmem_flags(!Self::is_cow(page, self.flags));
+				vmem_transaction.map(page.get(), virtaddr, flags)?;
+			}
+			(None, Some(default_page)) => {
+				let flags = self.get_vmem_flags(false);
+				vmem_transaction.map(default_page, virtaddr, flags)?;
+			}
+			(None, None) => self.alloc(offset, vmem_transaction)?,
+		}
+		Ok(())
+	}
+
+	/// Applies the mapping to the given `vmem_transaction`.
+	///
+	/// Only the pages that have already been allocated are mapped, pages in Copy-On-Write mode
+	/// being mapped read-only. Other pages are mapped on access by [`Self::map_page`].
+	pub fn apply_to(&mut self, vmem_transaction: &mut VMemTransaction<false>) -> AllocResult<()> {
+		if let MapResidence::Static {
+			..
+		} = self.residence
+		{
 			for i in 0..self.size.get() {
 				self.alloc(i, vmem_transaction)?;
 			}
+			return Ok(());
+		}
+		for (offset, phys_page) in self.phys_pages.iter().enumerate() {
+			let Some(page) = phys_page else {
+				continue;
+			};
+			// Writes to pages of file mappings must be detected
+			let write = !self.residence.is_file() && !Self::is_cow(page, self.flags);
+			let virtaddr = VirtAddr::from(self.begin) + offset * PAGE_SIZE;
+			vmem_transaction.map(page.get(), virtaddr, self.get_vmem_flags(write))?;
+			// TODO invalidate cache for this page
 		}
 		Ok(())
 	}
 
+	/// Merges the pages of the mapping with identical pages of `stable`, using the given
+	/// `vmem_transaction`.
+	///
+	/// Pages that are identical to a page of `stable` are replaced by it, in Copy-On-Write mode.
+	/// Other pages are write-protected, then inserted into `stable` so that pages scanned later
+	/// can be merged with them. Since each page of `stable` is referenced by `stable` itself, the
+	/// first write to it gets a private copy of the page.
+	///
+	/// Only the pages of private anonymous mappings that are not shared yet are considered.
+	///
+	/// The function returns the number of pages that have been merged.
+	pub(super) fn merge_pages(
+		&mut self,
+		stable: &mut StablePages,
+		vmem_transaction: &mut VMemTransaction<false>,
+	) -> AllocResult<usize> {
+		if self.flags & super::MAPPING_FLAG_SHARED != 0 || !self.residence.is_normal() {
+			return Ok(0);
+		}
+		let mut merged = 0;
+		for offset in 0..self.size.get() {
+			let Some(page) = &self.phys_pages[offset] else {
+				continue;
+			};
+			if Arc::strong_count(page) > 1 {
+				continue;
+			}
+			let virtaddr = VirtAddr::from(self.begin) + offset * PAGE_SIZE;
+			let content = virtaddr.as_ptr::<Page>();
+			let hash = unsafe {
+				vmem::switch(vmem_transaction.vmem, || {
+					vmem::smap_disable(|| hashmap::hash::<_, FxHasher>(&*content))
+				})
+			};
+			let flags = self.get_vmem_flags(false);
+			let Some(stable_page) = stable.get(&hash) else {
+				vmem_transaction.map(page.get(), virtaddr, flags)?;
+				stable.insert(hash, page.clone())?;
+				continue;
+			};
+			// Compare contents, in case of a hash collision
+			vmem_transaction.map(stable_page.get(), COPY_BUFFER, 0)?;
+			let same = unsafe {
+				vmem::switch(vmem_transaction.vmem, || {
+					vmem::smap_disable(|| *content == *COPY_BUFFER.as_ptr::<Page>())
+				})
+			};
+			if !same {
+				continue;
+			}
+			vmem_transaction.map(stable_page.get(), virtaddr, flags)?;
+			// Drop the previous page
+			self.phys_pages[offset] = Some(stable_page.clone());
+			merged += 1;
+		}
+		Ok(merged)
+	}
+
 	/// Splits the current mapping, creating up to two new mappings and one gap.
 	///
 	/// Arguments:
@@ -258,16 +426,7 @@ impl MemMapping {
 		size: usize,
 	) -> AllocResult<(Option<Self>, Option<MemGap>, Option<Self>)> {
 		let prev = NonZeroUsize::new(begin)
-			.map(|size| {
-				Ok(MemMapping {
-					begin: self.begin,
-					size,
-					flags: self.flags,
-					residence: self.residence.clone(),
-
-					phys_pages: Vec::try_from(&self.phys_pages[..size.get()])?,
-				})
-			})
+			.map(|size| self.sub_mapping(0, size, self.flags))
 			.transpose()?;
 		let gap = NonZeroUsize::new(size).map(|size| {
 			let begin = VirtAddr::from(self.begin) + begin * PAGE_SIZE;
@@ -280,25 +439,31 @@ impl MemMapping {
 			.get()
 			.checked_sub(end)
 			.and_then(NonZeroUsize::new)
-			.map(|size| {
-				let mut residence = self.residence.clone();
-				residence.offset_add(end);
-				Ok(Self {
-					begin: self.begin.wrapping_add(end * PAGE_SIZE),
-					size,
-					flags: self.flags,
-					residence,
-
-					phys_pages: Vec::try_from(&self.phys_pages[end..])?,
-				})
-			})
+			.map(|size| self.sub_mapping(end, size, self.flags))
 			.transpose()?;
 		Ok((prev, gap, next))
 	}
 
-	/// Synchronizes the data on the memory mapping back to the filesystem.
+	/// Returns a new mapping covering the `size` pages starting at the page offset `begin` of the
+	/// current mapping, with the flags `flags`.
 	///
-	/// `vmem` is the virtual memory context to read from.
+	/// Physical pages are shared with the current mapping.
+	///
+	/// If the range is out of bounds, the function panics.
+	pub fn sub_mapping(&self, begin: usize, size: NonZeroUsize, flags: u8) -> AllocResult<Self> {
+		let mut residence = self.residence.clone();
+		residence.offset_add(begin);
+		Ok(Self {
+			begin: self.begin.wrapping_add(begin * PAGE_SIZE),
+			size,
+			flags,
+			residence,
+
+			phys_pages: Vec::try_from(&self.phys_pages[begin..(begin + size.get())])?,
+		})
+	}
+
+	/// Synchronizes the data on the memory mapping back to the filesystem.
 	///
 	/// The function does nothing if:
 	/// - The mapping is not shared
@@ -306,32 +471,81 @@ impl MemMapping {
 	/// - The associated file has been removed or cannot be accessed
 	///
 	/// If the mapping is lock, the function returns [`crate::errno::EBUSY`].
-	pub fn fs_sync(&self, vmem: &VMem) -> EResult<()> {
+	pub fn fs_sync(&self) -> EResult<()> {
 		if self.flags & super::MAPPING_FLAG_SHARED == 0 {
 			return Ok(());
 		}
 		// TODO if locked, EBUSY
-		// Get file
 		let MapResidence::File {
-			file,
-			off,
+			file, ..
 		} = &self.residence
 		else {
 			return Ok(());
 		};
-		// Sync
-		unsafe {
-			vmem::switch(vmem, || {
-				// TODO Make use of dirty flag if present on the current architecture to update
-				// only pages that have been modified
-				let slice = slice::from_raw_parts(self.begin, self.size.get() * PAGE_SIZE);
-				let mut i = 0;
-				while i < slice.len() {
-					let l = file.ops.write(file, *off, &slice[i..])?;
-					i += l;
+		let Some(ent) = &file.vfs_entry else {
+			return Ok(());
+		};
+		// Pages that have been written since the last synchronization may remain mapped with write
+		// access, so consider them all as modified
+		// TODO Make use of dirty flag if present on the current architecture to update only pages
+		// that have been modified
+		if self.flags & super::MAPPING_FLAG_WRITE != 0 {
+			let pages = self.phys_pages.iter().enumerate();
+			for (offset, _) in pages.filter(|(_, page)| page.is_some()) {
+				if let Some((loc, index)) = self.residence.file_page(offset) {
+					page_cache::mark_dirty(loc, index);
+				}
+			}
+		}
+		let node = ent.node();
+		page_cache::sync_file(&node.location, &*node.ops)
+	}
+
+	/// Tells whether the page at offset `offset` can be released, to be reloaded from the page
+	/// cache on the next access.
+	///
+	/// This is the case for allocated pages of file mappings, unless they are private copies.
+	fn is_releasable(&self, offset: usize) -> bool {
+		let Some(Some(page)) = self.phys_pages.get(offset) else {
+			return false;
+		};
+		self.residence
+			.file_page(offset)
+			.is_some_and(|(loc, index)| page_cache::contains_page(loc, index, page))
+	}
+
+	/// Unmaps the pages in `range` that can be released, using the given `vmem_transaction`.
+	///
+	/// Once the transaction is committed, [`Self::release_pages`] must be called with the same
+	/// range.
+	pub(super) fn unmap_releasable(
+		&self,
+		range: Range<usize>,
+		vmem_transaction: &mut VMemTransaction<false>,
+	) -> AllocResult<()> {
+		for offset in range.filter(|offset| self.is_releasable(*offset)) {
+			vmem_transaction.unmap(VirtAddr::from(self.begin) + offset * PAGE_SIZE)?;
+		}
+		Ok(())
+	}
+
+	/// Releases the pages in `range` that have been unmapped by [`Self::unmap_releasable`].
+	///
+	/// Pages that may have been written through the mapping are marked as dirty, so that they
+	/// get written back.
+	pub(super) fn release_pages(&mut self, range: Range<usize>) {
+		const SHARED_WRITE: u8 = super::MAPPING_FLAG_SHARED | super::MAPPING_FLAG_WRITE;
+		let write = self.flags & SHARED_WRITE == SHARED_WRITE;
+		for offset in range {
+			if !self.is_releasable(offset) {
+				continue;
+			}
+			if write {
+				if let Some((loc, index)) = self.residence.file_page(offset) {
+					page_cache::mark_dirty(loc, index);
 				}
-				Ok(())
-			})
+			}
+			self.phys_pages[offset] = None;
 		}
 	}
 
@@ -350,7 +564,7 @@ impl MemMapping {
 		pages_range: Range<usize>,
 		vmem_transaction: &mut VMemTransaction<false>,
 	) -> EResult<()> {
-		self.fs_sync(vmem_transaction.vmem)?;
+		self.fs_sync()?;
 		let begin = VirtAddr::from(self.begin) + pages_range.start * PAGE_SIZE;
 		let len = pages_range.end - pages_range.start;
 		vmem_transaction.unmap_range(begin, len)?;
@@ -25,12 +25,13 @@
 
 pub mod copy;
 mod gap;
-mod mapping;
+pub mod ksm;
+pub mod mapping;
 pub mod residence;
 mod transaction;
 
 use crate::{
-	file::perm::AccessProfile,
+	file::{page_cache, perm::AccessProfile},
 	memory,
 	memory::{vmem, vmem::VMem, VirtAddr, PROCESS_END},
 };
@@ -42,6 +43,7 @@ use core::{
 	intrinsics::unlikely,
 	mem,
 	num::NonZeroUsize,
+	ops::Range,
 };
 use gap::MemGap;
 use mapping::MemMapping;
@@ -49,6 +51,7 @@ use residence::MapResidence;
 use transaction::MemSpaceTransaction;
 use utils::{
 	collections::{btreemap::BTreeMap, vec::Vec},
+	errno,
 	errno::{AllocResult, CollectResult, EResult},
 	limits::PAGE_SIZE,
 	TryClone,
@@ -66,6 +69,9 @@ pub const MAPPING_FLAG_USER: u8 = 0b00100;
 /// If the mapping is associated with a file, modifications made to the mapping are update to the
 /// file.
 pub const MAPPING_FLAG_SHARED: u8 = 0b1000;
+/// Flag telling that the pages of a memory mapping can be merged with identical pages by Kernel
+/// Samepage Merging. See [`ksm`].
+pub const MAPPING_FLAG_MERGEABLE: u8 = 0b10000;
 
 /// The virtual address of the buffer used to map pages for copy.
 const COPY_BUFFER: VirtAddr = VirtAddr(PROCESS_END.0 - PAGE_SIZE);
@@ -180,6 +186,9 @@ struct MemSpaceState {
 	brk_init: VirtAddr,
 	/// The current pointer of the `[s]brk` system calls.
 	brk_addr: VirtAddr,
+
+	/// The address of the vDSO, including its data page, if mapped.
+	vdso: Option<VirtAddr>,
 }
 
 impl MemSpaceState {
@@ -238,6 +247,31 @@ impl MemSpaceState {
 			Self::addr_search(VirtAddr::from(*key), value.get_size().get(), addr)
 		})
 	}
+
+	/// Calls `f` on each mapping covering the range of `pages` pages starting at `addr`, along
+	/// with the range of pages of the mapping that are in the range.
+	///
+	/// If a part of the range is not mapped, the function returns [`errno::ENOMEM`].
+	fn for_each_mapping<F: FnMut(&mut MemMapping, Range<usize>) -> EResult<()>>(
+		&mut self,
+		addr: VirtAddr,
+		pages: usize,
+		mut f: F,
+	) -> EResult<()> {
+		let mut i = 0;
+		while i < pages {
+			let page_addr = addr + i * PAGE_SIZE;
+			let mapping = self
+				.get_mut_mapping_for_addr(page_addr)
+				.ok_or_else(|| errno!(ENOMEM))?;
+			// The offset in the mapping to the beginning of the pages in the range
+			let inner_off = (page_addr.0 - mapping.get_begin() as usize) / PAGE_SIZE;
+			let count = min(pages - i, mapping.get_size().get() - inner_off);
+			f(mapping, inner_off..(inner_off + count))?;
+			i += count;
+		}
+		Ok(())
+	}
 }
 
 /// A virtual memory space.
@@ -286,6 +320,11 @@ impl MemSpace {
 		self.state.get_mapping_for_addr(addr)
 	}
 
+	/// Returns an iterator over the memory mappings, sorted by address.
+	pub fn iter_mappings(&self) -> impl Iterator<Item = &MemMapping> {
+		self.state.mappings.iter().map(|(_, m)| m)
+	}
+
 	/// Maps a chunk of memory.
 	///
 	/// The function has complexity `O(log n)`.
@@ -479,6 +518,10 @@ impl MemSpace {
 	}
 
 	/// Clones the current memory space for process forking.
+	///
+	/// Physical pages are not copied but shared between both memory spaces, in Copy-On-Write
+	/// mode. The copy of a page happens on the first write to it. Pages that have not been
+	/// allocated yet are not mapped.
 	pub fn fork(&mut self) -> AllocResult<MemSpace> {
 		// Clone gaps
 		let gaps = self.state.gaps.try_clone()?;
@@ -512,6 +555,8 @@ impl MemSpace {
 
 				brk_init: self.state.brk_init,
 				brk_addr: self.state.brk_addr,
+
+				vdso: self.state.vdso,
 			},
 			vmem: new_vmem,
 		})
@@ -550,29 +595,182 @@ impl MemSpace {
 	/// - `prot` is a set of mapping flags
 	/// - `access_profile` is the access profile to check permissions
 	///
+	/// Only the [`MAPPING_FLAG_WRITE`] and [`MAPPING_FLAG_EXEC`] flags are affected. Mappings are
+	/// split if they are not entirely in the range.
+	///
+	/// If a part of the range is not mapped, the function returns [`errno::ENOMEM`].
+	///
 	/// If a mapping to be modified is associated with a file, and the file doesn't have the
-	/// matching permissions, the function returns an error.
+	/// matching permissions, the function returns [`errno::EACCES`].
+	///
+	/// On error, the memory space is left unchanged.
 	pub fn set_prot(
 		&mut self,
-		_addr: *mut c_void,
-		_len: usize,
-		_prot: u8,
-		_access_profile: &AccessProfile,
+		addr: *mut c_void,
+		len: usize,
+		prot: u8,
+		access_profile: &AccessProfile,
+	) -> EResult<()> {
+		const PROT_MASK: u8 = MAPPING_FLAG_WRITE | MAPPING_FLAG_EXEC;
+		self.update_flags(VirtAddr::from(addr), len, |mapping| {
+			let flags = (mapping.get_flags() & !PROT_MASK) | (prot & PROT_MASK);
+			if flags == mapping.get_flags() {
+				return Ok(flags);
+			}
+			// Check permissions on the mapped file
+			if let MapResidence::File {
+				file, ..
+			} = mapping.get_residence()
+			{
+				let stat = file.stat()?;
+				let shared = flags & MAPPING_FLAG_SHARED != 0;
+				if shared && flags & MAPPING_FLAG_WRITE != 0 && !file.can_write() {
+					return Err(errno!(EACCES));
+				}
+				if flags & MAPPING_FLAG_EXEC != 0 && !access_profile.can_execute_file(&stat) {
+					return Err(errno!(EACCES));
+				}
+			}
+			Ok(flags)
+		})
+	}
+
+	/// Sets whether the pages in the given range of memory can be merged by Kernel Samepage
+	/// Merging, with the [`MAPPING_FLAG_MERGEABLE`] flag.
+	///
+	/// Pages that have already been merged remain shared until they are written to.
+	///
+	/// Mappings are split if they are not entirely in the range. If a part of the range is not
+	/// mapped, the function returns [`errno::ENOMEM`] and the memory space is left unchanged.
+	pub fn set_mergeable(&mut self, addr: VirtAddr, len: usize, mergeable: bool) -> EResult<()> {
+		self.update_flags(addr, len, |mapping| {
+			if mergeable {
+				Ok(mapping.get_flags() | MAPPING_FLAG_MERGEABLE)
+			} else {
+				Ok(mapping.get_flags() & !MAPPING_FLAG_MERGEABLE)
+			}
+		})
+	}
+
+	/// Replaces the flags of the mappings in the given range of memory with the ones returned by
+	/// `f`, which is called once for each mapping.
+	///
+	/// Mappings are split if they are not entirely in the range. If a part of the range is not
+	/// mapped, the function returns [`errno::ENOMEM`].
+	///
+	/// On error, the memory space is left unchanged.
+	fn update_flags<F: FnMut(&MemMapping) -> EResult<u8>>(
+		&mut self,
+		addr: VirtAddr,
+		len: usize,
+		mut f: F,
 	) -> EResult<()> {
-		// TODO Iterate on mappings in the range:
-		//		If the mapping is shared and associated to a file, check file permissions match
-		// `prot` (only write)
-		//		Split the mapping if needed
-		//		Set permissions
-		//		Update vmem
+		let pages = len.div_ceil(PAGE_SIZE);
+		let mut transaction = MemSpaceTransaction::new(&mut self.state, &mut self.vmem);
+		let mut i = 0;
+		while i < pages {
+			// The current page's beginning
+			let page_addr = addr + i * PAGE_SIZE;
+			// The mapping containing the page
+			let mapping = transaction
+				.mem_space_state
+				.get_mapping_for_addr(page_addr)
+				.ok_or_else(|| errno!(ENOMEM))?;
+			// The offset in the mapping to the beginning of pages to update
+			let inner_off = (page_addr.0 - mapping.get_begin() as usize) / PAGE_SIZE;
+			// The number of pages to update in the mapping
+			let count = min(pages - i, mapping.get_size().get() - inner_off);
+			i += count;
+			let flags = f(mapping)?;
+			if flags == mapping.get_flags() {
+				continue;
+			}
+			// Split the mapping, and apply the new flags to the affected part
+			let mapping_begin = mapping.get_begin();
+			let (prev, _, next) = mapping.split(inner_off, count)?;
+			let new = mapping.sub_mapping(inner_off, NonZeroUsize::new(count).unwrap(), flags)?;
+			transaction.remove_mapping(mapping_begin)?;
+			if let Some(m) = prev {
+				transaction.insert_mapping(m)?;
+			}
+			transaction.insert_mapping(new)?;
+			if let Some(m) = next {
+				transaction.insert_mapping(m)?;
+			}
+		}
+		transaction.commit();
 		Ok(())
 	}
 
+	/// Releases the pages in the given range that can be reloaded from a file, so that they can
+	/// be reclaimed.
+	///
+	/// Arguments:
+	/// - `addr` is the address to the beginning of the range
+	/// - `len` is the length of the range in bytes
+	/// - `evict` tells whether the released pages are also evicted from the page cache, unless
+	///   they are mapped elsewhere. Modified pages are written back first. If `false`, pages
+	///   remain in the cache, where they become the first candidates for eviction
+	///
+	/// Anonymous pages and private copies of file pages are left untouched, since there is no
+	/// swap to write them to.
+	///
+	/// If a part of the range is not mapped, the function returns [`errno::ENOMEM`] and the
+	/// memory space is left unchanged.
+	pub fn reclaim(&mut self, addr: VirtAddr, len: usize, evict: bool) -> EResult<()> {
+		let pages = len.div_ceil(PAGE_SIZE);
+		// Unmap pages first, so that the memory space is left unchanged on failure
+		let mut transaction = self.vmem.transaction();
+		self.state.for_each_mapping(addr, pages, |mapping, range| {
+			Ok(mapping.unmap_releasable(range, &mut transaction)?)
+		})?;
+		transaction.commit();
+		self.state.for_each_mapping(addr, pages, |mapping, range| {
+			mapping.release_pages(range.clone());
+			if evict {
+				for offset in range {
+					if let Some((loc, index)) = mapping.get_residence().file_page(offset) {
+						page_cache::evict(loc, index)?;
+					}
+				}
+			}
+			Ok(())
+		})
+	}
+
+	/// Merges the pages of mergeable mappings with identical pages of `stable`, for Kernel
+	/// Samepage Merging. See [`MemMapping::merge_pages`].
+	///
+	/// The function returns the number of pages that have been merged.
+	pub fn merge_pages(&mut self, stable: &mut ksm::StablePages) -> AllocResult<usize> {
+		let mut transaction = self.vmem.transaction();
+		let mut merged = 0;
+		let res = self
+			.state
+			.mappings
+			.iter_mut()
+			.map(|(_, mapping)| mapping)
+			.filter(|mapping| mapping.get_flags() & MAPPING_FLAG_MERGEABLE != 0)
+			.try_for_each(|mapping| {
+				merged += mapping.merge_pages(stable, &mut transaction)?;
+				Ok(())
+			});
+		// The mapping of each page is updated along with the page itself, so changes made before
+		// a failure are consistent and must be kept
+		transaction.commit();
+		res.map(|_| merged)
+	}
+
 	/// Returns the address for the `brk` syscall.
 	pub fn get_brk(&self) -> VirtAddr {
 		self.state.brk_addr
 	}
 
+	/// Returns the initial address for the `brk` syscall.
+	pub fn get_brk_init(&self) -> VirtAddr {
+		self.state.brk_init
+	}
+
 	/// Sets the initial pointer for the `brk` syscall.
 	///
 	/// This function MUST be called *only once*, before the program starts.
@@ -584,6 +782,16 @@ impl MemSpace {
 		self.state.brk_addr = addr;
 	}
 
+	/// Returns the address of the vDSO, including its data page, if mapped.
+	pub fn get_vdso(&self) -> Option<VirtAddr> {
+		self.state.vdso
+	}
+
+	/// Sets the address of the vDSO, including its data page.
+	pub fn set_vdso(&mut self, addr: VirtAddr) {
+		self.state.vdso = Some(addr);
+	}
+
 	/// Sets the address for the `brk` syscall.
 	///
 	/// If the memory cannot be allocated, the function returns an error.
@@ -629,7 +837,8 @@ impl MemSpace {
 	/// This function determines whether the process should continue or not.
 	///
 	/// If continuing, the function must resolve the issue before returning.
-	/// A typical situation where is function is useful is for Copy-On-Write allocations.
+	/// A typical situation where is function is useful is for Copy-On-Write allocations, or for
+	/// pages that have not been accessed yet, which are mapped lazily.
 	///
 	/// Arguments:
 	/// - `addr` is the virtual address of the wrong memory access that caused the fault.
@@ -637,9 +846,6 @@ impl MemSpace {
 	///
 	/// If the process should continue, the function returns `true`, else `false`.
 	pub fn handle_page_fault(&mut self, addr: VirtAddr, code: u32) -> bool {
-		if code & vmem::x86::PAGE_FAULT_PRESENT == 0 {
-			return false;
-		}
 		let Some(mapping) = self.state.get_mut_mapping_for_addr(addr) else {
 			return false;
 		};
@@ -658,10 +864,13 @@ impl MemSpace {
 		// Map the accessed page
 		let page_offset = (addr.0 - mapping.get_begin() as usize) / PAGE_SIZE;
 		let mut transaction = self.vmem.transaction();
-		// TODO use OOM killer
-		mapping
-			.alloc(page_offset, &mut transaction)
-			.expect("Out of memory!");
+		match mapping.map_page(page_offset, code_write, &mut transaction) {
+			Ok(()) => {}
+			// TODO use OOM killer
+			Err(e) if e.as_int() == errno::ENOMEM => panic!("Out of memory!"),
+			// Access past the end of a mapped file, or I/O error
+			Err(_) => return false,
+		}
 		transaction.commit();
 		true
 	}
@@ -679,7 +888,7 @@ impl Drop for MemSpace {
 		let mappings = mem::take(&mut self.state.mappings);
 		for (_, m) in mappings {
 			// Ignore I/O errors
-			let _ = m.fs_sync(&self.vmem);
+			let _ = m.fs_sync();
 		}
 	}
 }
@@ -710,4 +919,77 @@ mod test {
 		mem_space.unmap(addr, size, false).unwrap();
 		//assert!(!mem_space.can_access(addr as _, PAGE_SIZE, true, true));
 	}
+
+	#[test_case]
+	fn fork_cow() {
+		let mut mem_space = MemSpace::new().unwrap();
+		let addr = VirtAddr(0x1000);
+		let size = NonZeroUsize::new(2).unwrap();
+		mem_space
+			.map(
+				MapConstraint::Fixed(addr),
+				size,
+				MAPPING_FLAG_WRITE | MAPPING_FLAG_USER,
+				MapResidence::Normal,
+			)
+			.unwrap();
+		// Pages are not mapped before being accessed
+		assert!(mem_space.get_vmem().translate(addr).is_none());
+		mem_space.alloc(addr, PAGE_SIZE).unwrap();
+		let page = mem_space.get_vmem().translate(addr).unwrap();
+		let mut child = mem_space.fork().unwrap();
+		// The physical page is shared, and the page that has not been allocated is not mapped
+		assert_eq!(child.get_vmem().translate(addr), Some(page));
+		assert!(child.get_vmem().translate(addr + PAGE_SIZE).is_none());
+		// Writing copies the page
+		let code = vmem::x86::PAGE_FAULT_PRESENT
+			| vmem::x86::PAGE_FAULT_WRITE
+			| vmem::x86::PAGE_FAULT_USER;
+		assert!(child.handle_page_fault(addr, code));
+		assert_ne!(child.get_vmem().translate(addr), Some(page));
+		assert_eq!(mem_space.get_vmem().translate(addr), Some(page));
+	}
+
+	#[test_case]
+	fn ksm_merge() {
+		let mut mem_space = MemSpace::new().unwrap();
+		let addr = VirtAddr(0x1000);
+		let size = NonZeroUsize::new(4).unwrap();
+		mem_space
+			.map(
+				MapConstraint::Fixed(addr),
+				size,
+				MAPPING_FLAG_WRITE | MAPPING_FLAG_USER,
+				MapResidence::Normal,
+			)
+			.unwrap();
+		assert_eq!(
+			mem_space
+				.set_mergeable(addr, 5 * PAGE_SIZE, true)
+				.unwrap_err()
+				.as_int(),
+			errno::ENOMEM
+		);
+		// All pages are zeroed, but only the first three are mergeable
+		mem_space.alloc(addr, 4 * PAGE_SIZE).unwrap();
+		mem_space.set_mergeable(addr, 3 * PAGE_SIZE, true).unwrap();
+		let mut stable = ksm::StablePages::new();
+		assert_eq!(mem_space.merge_pages(&mut stable).unwrap(), 2);
+		let translate = |off: usize| mem_space.get_vmem().translate(addr + off * PAGE_SIZE);
+		let page = translate(0).unwrap();
+		assert_eq!(translate(1), Some(page));
+		assert_eq!(translate(2), Some(page));
+		assert_ne!(translate(3), Some(page));
+		// Merged pages are not scanned again
+		assert_eq!(mem_space.merge_pages(&mut stable).unwrap(), 0);
+		// Writing gets a private copy
+		let code = vmem::x86::PAGE_FAULT_PRESENT
+			| vmem::x86::PAGE_FAULT_WRITE
+			| vmem::x86::PAGE_FAULT_USER;
+		assert!(mem_space.handle_page_fault(addr + PAGE_SIZE, code));
+		let translate = |off: usize| mem_space.get_vmem().translate(addr + off * PAGE_SIZE);
+		assert_ne!(translate(1), Some(page));
+		assert_eq!(translate(0), Some(page));
+		assert_eq!(translate(2), Some(page));
+	}
 }
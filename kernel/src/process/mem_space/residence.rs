@@ -19,11 +19,10 @@
 //! A map residence provides information about how to populate a memory mapping.
 
 use crate::{
-	file::File,
+	file::{page_cache, File, FileLocation},
 	memory::{buddy, PhysAddr, VirtAddr},
 };
-use core::alloc::AllocError;
-use utils::{collections::vec::Vec, errno::AllocResult, limits::PAGE_SIZE, ptr::arc::Arc};
+use utils::{collections::vec::Vec, errno, errno::EResult, limits::PAGE_SIZE, ptr::arc::Arc};
 
 /// Type representing a memory page.
 pub type Page = [u8; PAGE_SIZE];
@@ -68,7 +67,6 @@ impl Drop for ResidencePage {
 	}
 }
 
-// TODO when reaching the last reference to the open file, close it on unmap
 /// A map residence is the source of the data on a physical page used by a mapping. It is also the
 /// location to which the data is to be synchronized when modified.
 #[derive(Clone, Debug)]
@@ -110,6 +108,25 @@ impl MapResidence {
 		}
 	}
 
+	/// Tells whether the residence is a file.
+	pub fn is_file(&self) -> bool {
+		matches!(self, MapResidence::File { .. })
+	}
+
+	/// If the residence is a file, returns its location and the offset of the page at `offset`
+	/// (in pages, relative to the beginning of the mapping) in the file, in pages.
+	pub fn file_page(&self, offset: usize) -> Option<(&FileLocation, u64)> {
+		let MapResidence::File {
+			file,
+			off,
+		} = self
+		else {
+			return None;
+		};
+		let loc = &file.vfs_entry.as_ref()?.node().location;
+		Some((loc, off / PAGE_SIZE as u64 + offset as u64))
+	}
+
 	/// Adds a value of `pages` pages to the offset of the residence, if applicable.
 	pub fn offset_add(&mut self, pages: usize) {
 		if let Self::File {
@@ -127,21 +144,34 @@ impl MapResidence {
 	///
 	/// The returned page is already populated with the necessary data. It is released when
 	/// [`ResidencePage`] is dropped.
-	pub fn acquire_page(&self, offset: usize) -> AllocResult<Arc<ResidencePage>> {
+	///
+	/// For a file, the page is taken from the page cache and is thus shared with other mappings
+	/// of the same file. If the page is past the end of the file, the function returns
+	/// [`errno::EFAULT`].
+	pub fn acquire_page(&self, offset: usize) -> EResult<Arc<ResidencePage>> {
 		match self {
 			MapResidence::Normal => {
 				let page = buddy::alloc(0, buddy::FLAG_ZONE_TYPE_USER)?;
-				Arc::new(ResidencePage::new(page))
+				Ok(Arc::new(ResidencePage::new(page))?)
 			}
 			MapResidence::Static {
 				pages,
-			} => pages.get(offset).cloned().ok_or(AllocError),
+			} => pages.get(offset).cloned().ok_or_else(|| errno!(EFAULT)),
 			MapResidence::File {
-				file: _,
-				off: _,
+				file,
+				off,
 			} => {
-				// TODO get physical page for this offset
-				todo!();
+				let node = file
+					.vfs_entry
+					.as_ref()
+					.ok_or_else(|| errno!(ENODEV))?
+					.node();
+				let index = off / PAGE_SIZE as u64 + offset as u64;
+				let size = node.ops.get_stat(&node.location)?.size;
+				if index >= size.div_ceil(PAGE_SIZE as u64) {
+					return Err(errno!(EFAULT));
+				}
+				page_cache::get_page(node, index)
 			}
 		}
 	}
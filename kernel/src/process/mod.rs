@@ -25,8 +25,12 @@
 // TODO Do not reallocate a PID of used as a pgid
 // TODO When a process receives a signal or exits, log it if the `strace` feature is enabled
 
+pub mod cred;
 pub mod exec;
+pub mod futex;
 pub mod iovec;
+pub mod kthread;
+pub mod loadavg;
 pub mod mem_space;
 pub mod oom;
 pub mod pid;
@@ -34,6 +38,7 @@ pub mod regs;
 pub mod rusage;
 pub mod scheduler;
 pub mod signal;
+pub mod thread_group;
 #[cfg(target_arch = "x86")]
 pub mod tss;
 pub mod user_desc;
@@ -45,21 +50,23 @@ use crate::{
 	file::{
 		fd::{FileDescriptorTable, NewFDConstraint},
 		perm::AccessProfile,
-		vfs,
+		pidfd, vfs,
 		vfs::ResolutionSettings,
 		File, O_RDWR,
 	},
 	gdt,
-	memory::{buddy, buddy::FrameOrder, VirtAddr},
+	memory::{buddy, buddy::FrameOrder, vmem, VirtAddr},
 	process::{
+		exec::vdso,
 		mem_space::{copy, copy::SyscallPtr},
-		pid::PidHandle,
+		pid::{PidHandle, PidNamespace},
 		scheduler::SCHEDULER,
-		signal::SigSet,
+		signal::{SigSet, SignalInfo},
 	},
 	register_get,
-	syscall::FromSyscallArg,
-	time::timer::TimerManager,
+	syscall::{unimplemented::SyscallSet, FromSyscallArg},
+	time::{clock, namespace::TimeNamespace, timer::TimerManager, unit::Timestamp},
+	workqueue,
 };
 use core::{
 	ffi::c_int,
@@ -68,13 +75,16 @@ use core::{
 	intrinsics::unlikely,
 	mem,
 	mem::{size_of, ManuallyDrop},
+	ptr,
 	ptr::NonNull,
 };
+use cred::Cred;
 use mem_space::MemSpace;
 use pid::Pid;
 use regs::Regs;
-use rusage::RUsage;
+use rusage::{IOUsage, RUsage};
 use signal::{Signal, SignalAction, SignalHandler};
+use thread_group::ThreadGroup;
 #[cfg(target_arch = "x86")]
 use tss::TSS;
 use utils::{
@@ -87,13 +97,120 @@ use utils::{
 	errno::{AllocResult, EResult},
 	lock::{IntMutex, Mutex},
 	ptr::arc::Arc,
+	vec,
 };
 
+/// The number of signals received by the init process that are kept, to be reported if it
+/// terminates.
+const INIT_SIGNALS_HISTORY: usize = 4;
+
+/// The IDs of the last signals received by the init process, the oldest first. Unused slots are
+/// zero.
+static INIT_SIGNALS: IntMutex<[u8; INIT_SIGNALS_HISTORY]> =
+	IntMutex::new([0; INIT_SIGNALS_HISTORY]);
+
+/// Displays the name of the signal with the given ID.
+struct SignalName(u8);
+
+impl fmt::Display for SignalName {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		match Signal::try_from(self.0 as c_int) {
+			Ok(sig) => write!(f, "{sig:?}"),
+			Err(_) => write!(f, "signal {}", self.0),
+		}
+	}
+}
+
+/// Displays the last signals received by the init process.
+struct InitSignals([u8; INIT_SIGNALS_HISTORY]);
+
+impl fmt::Display for InitSignals {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		let mut signals = self.0.iter().filter(|id| **id != 0);
+		let Some(first) = signals.next() else {
+			return write!(f, "none");
+		};
+		write!(f, "{}", SignalName(*first))?;
+		for id in signals {
+			write!(f, ", {}", SignalName(*id))?;
+		}
+		Ok(())
+	}
+}
+
+/// Makes the kernel panic because the init process terminated.
+///
+/// Arguments:
+/// - `status` is the exit status of the process, whose lowest 8 bits are the exit code
+/// - `termsig` is the terminating signal, or `0` if the process exited by itself
+///
+/// The system cannot keep running without init, since orphaned processes would never be reaped.
+fn init_terminated(status: u32, termsig: u8) -> ! {
+	let signals = InitSignals(*INIT_SIGNALS.lock());
+	if termsig != 0 {
+		panic!(
+			"Init process killed by {} (last signals received: {signals})",
+			SignalName(termsig)
+		);
+	}
+	panic!(
+		"Init process exited with code {} (last signals received: {signals})",
+		status & 0xff
+	);
+}
+
+/// Sends `SIGKILL` to every process of the namespace `ns`.
+///
+/// This is done when the init process of the namespace terminates, since no process would be
+/// left to reap orphans.
+fn kill_namespace(ns: &PidNamespace) {
+	let Ok(pids) = ns.get_processes() else {
+		return;
+	};
+	for pid in pids {
+		let Some(proc_mutex) = Process::get_by_pid(pid) else {
+			continue;
+		};
+		let mut proc = proc_mutex.lock();
+		if proc.get_state() != State::Zombie {
+			proc.kill(Signal::SIGKILL);
+		}
+	}
+}
+
+/// Free kernel stacks, ready to be used by new processes.
+static KERNEL_STACKS_POOL: IntMutex<Vec<VirtAddr>> = IntMutex::new(Vec::new());
+
+/// Returns a kernel stack for a new process, taking it from the pool if available.
+///
+/// The function returns the virtual address to the beginning of the stack.
+fn alloc_kernel_stack() -> AllocResult<NonNull<u8>> {
+	let stack = KERNEL_STACKS_POOL.lock().pop();
+	match stack {
+		Some(stack) => Ok(NonNull::new(stack.as_ptr()).unwrap()),
+		None => buddy::alloc_kernel(KERNEL_STACK_ORDER),
+	}
+}
+
+/// Releases the given kernel stack, placing it back in the pool if it is not full.
+///
+/// # Safety
+///
+/// The stack must not be used anymore.
+unsafe fn free_kernel_stack(stack: NonNull<u8>) {
+	let mut pool = KERNEL_STACKS_POOL.lock();
+	if pool.len() < KERNEL_STACKS_POOL_SIZE && pool.push(VirtAddr::from(stack)).is_ok() {
+		return;
+	}
+	drop(pool);
+	buddy::free_kernel(stack.as_ptr(), KERNEL_STACK_ORDER);
+}
+
 /// The opcode of the `hlt` instruction.
 const HLT_INSTRUCTION: u8 = 0xf4;
 
-/// The path to the TTY device file.
-const TTY_DEVICE_PATH: &str = "/dev/tty";
+/// The path to the console device file, used for the standard streams of `init`.
+const CONSOLE_DEVICE_PATH: &str = "/dev/console";
 
 /// The default file creation mask.
 const DEFAULT_UMASK: file::Mode = 0o022;
@@ -104,6 +221,9 @@ const USER_STACK_SIZE: usize = 2048;
 const USER_STACK_FLAGS: u8 = mem_space::MAPPING_FLAG_WRITE | mem_space::MAPPING_FLAG_USER;
 /// The size of the kernelspace stack of a process in number of pages.
 const KERNEL_STACK_ORDER: FrameOrder = 2;
+/// The number of free kernel stacks kept preallocated, to avoid the cost of allocating a stack
+/// for each new process.
+const KERNEL_STACKS_POOL_SIZE: usize = 16;
 
 /// The file descriptor number of the standard input stream.
 const STDIN_FILENO: u32 = 0;
@@ -163,17 +283,31 @@ impl State {
 type ExitStatus = u8;
 
 /// Process forking parameters.
-#[derive(Debug, Default)]
+#[derive(Debug)]
 pub struct ForkOptions {
 	/// If `true`, the parent and child processes both share the same address
 	/// space.
 	pub share_memory: bool,
 	/// If `true`, the parent and child processes both share the same file
 	/// descriptors table.
+	///
+	/// If `false`, the table is duplicated. The file descriptors of both tables still point to
+	/// the same open file descriptions, thus sharing offsets and flags.
 	pub share_fd: bool,
 	/// If `true`, the parent and child processes both share the same signal
 	/// handlers table.
 	pub share_sighand: bool,
+	/// If `true`, the parent and child processes both share the same filesystem information
+	/// (working directory, root directory and umask).
+	///
+	/// If `false`, the information is copied.
+	pub share_fs: bool,
+	/// If `true`, the child is a thread in the parent's thread group, sharing its credentials and
+	/// timers.
+	///
+	/// The parent of the thread is the parent of the calling process. Threads cannot be waited
+	/// for.
+	pub thread: bool,
 
 	/// If `true`, the parent is paused until the child process exits or executes
 	/// a program.
@@ -183,6 +317,40 @@ pub struct ForkOptions {
 	/// This is useful in order to avoid an unnecessary clone of the memory space in case the
 	/// child process executes a program or exits quickly.
 	pub vfork: bool,
+	/// If `true`, the child is the init process of a new PID namespace, child of the parent's.
+	pub new_pid_ns: bool,
+	/// If `true`, the child enters a new time namespace, child of the parent's namespace for
+	/// children. The new namespace also becomes the one of the child's own children.
+	///
+	/// This option cannot be combined with a shared memory space, since the vDSO depends on the
+	/// namespace.
+	pub new_time_ns: bool,
+	/// The PIDs to give to the child, starting from its own PID namespace and going up to the
+	/// root namespace. Namespaces not covered by the list give an unused PID.
+	pub set_tid: Vec<Pid>,
+
+	/// The signal sent to the parent when the child process terminates. If `None`, no signal is
+	/// sent.
+	pub exit_signal: Option<Signal>,
+}
+
+impl Default for ForkOptions {
+	fn default() -> Self {
+		Self {
+			share_memory: false,
+			share_fd: false,
+			share_sighand: false,
+			share_fs: false,
+			thread: false,
+
+			vfork: false,
+			new_pid_ns: false,
+			new_time_ns: false,
+			set_tid: Vec::new(),
+
+			exit_signal: Some(Signal::SIGCHLD),
+		}
+	}
 }
 
 /// The vfork operation is similar to the fork operation except the parent
@@ -204,6 +372,33 @@ enum VForkState {
 	Executing,
 }
 
+/// Filesystem information of a process.
+///
+/// This structure can be shared between several processes (see `CLONE_FS`), in which case a
+/// change of directory or umask in one process is visible to the others.
+#[derive(Clone)]
+pub struct ProcessFs {
+	/// Current working directory
+	///
+	/// The field contains both the path and the directory.
+	pub cwd: Arc<vfs::Entry>,
+	/// Current root path used by the process
+	pub chroot: Arc<vfs::Entry>,
+	/// The process's current umask.
+	pub umask: file::Mode,
+}
+
+impl ProcessFs {
+	/// Creates a new instance with both the working and root directories set to `root_dir`.
+	fn new(root_dir: Arc<vfs::Entry>) -> Self {
+		Self {
+			cwd: root_dir.clone(),
+			chroot: root_dir,
+			umask: DEFAULT_UMASK,
+		}
+	}
+}
+
 /// The **Process Control Block** (PCB). This structure stores all the information
 /// about a process.
 pub struct Process {
@@ -213,18 +408,31 @@ pub struct Process {
 	pub pgid: Pid,
 	/// The thread ID of the process.
 	pub tid: Pid,
+	/// The thread group the process belongs to.
+	pub thread_group: Arc<IntMutex<ThreadGroup>>,
 
 	/// The argv of the process.
 	pub argv: Arc<Vec<String>>,
 	/// The environment variables of the process, separated by `\0`.
 	pub envp: Arc<String>,
-	/// The path to the process's executable.
-	pub exec_path: Arc<PathBuf>,
+	/// The process's executable file.
+	///
+	/// If `None`, the process has not executed any program (for example, kernel threads).
+	pub exec_file: Option<Arc<vfs::Entry>>,
 
-	/// The process's access profile, containing user and group IDs.
-	pub access_profile: AccessProfile,
-	/// The process's current umask.
-	pub umask: file::Mode,
+	/// The process's credentials.
+	pub cred: Arc<Cred>,
+	/// The process's execution domain and its flags, set with the `personality` system call.
+	///
+	/// It is inherited on fork and preserved across program execution.
+	pub personality: u32,
+	/// The time namespace the process belongs to. If `None`, this is the root namespace.
+	pub time_ns: Option<Arc<TimeNamespace>>,
+	/// The time namespace in which children of the process are created.
+	///
+	/// It differs from [`Self::time_ns`] after the process has created a new namespace with
+	/// `unshare`.
+	pub time_ns_for_children: Option<Arc<TimeNamespace>>,
 
 	/// The current state of the process.
 	state: State,
@@ -236,13 +444,17 @@ pub struct Process {
 	pub priority: usize,
 	/// The nice value of the process.
 	pub nice: usize,
+	/// The adjustment of the process's OOM score, in the range `-1000..=1000`.
+	///
+	/// It is inherited on fork and preserved across program execution.
+	pub oom_score_adj: i16,
 	/// The number of quantum run during the cycle.
 	quantum_count: usize,
 
 	/// A pointer to the parent process.
 	parent: Option<Arc<IntMutex<Process>>>,
 	/// The list of children processes.
-	children: Vec<Pid>,
+	children: Vec<Arc<IntMutex<Process>>>,
 	/// The list of processes in the process group.
 	process_group: Vec<Pid>,
 
@@ -264,28 +476,47 @@ pub struct Process {
 	/// A pointer to the kernelspace stack.
 	kernel_stack: NonNull<u8>,
 
-	/// Current working directory
-	///
-	/// The field contains both the path and the directory.
-	pub cwd: Arc<vfs::Entry>,
-	/// Current root path used by the process
-	pub chroot: Arc<vfs::Entry>,
+	/// The process's filesystem information, which may be shared with other processes.
+	pub fs: Arc<Mutex<ProcessFs>>,
 	/// The list of open file descriptors with their respective ID.
 	pub file_descriptors: Option<Arc<Mutex<FileDescriptorTable>>>,
 
 	/// A bitfield storing the set of blocked signals.
 	pub sigmask: SigSet,
+	/// The signal mask to restore before returning to userspace, if the current system call
+	/// temporarily replaced it.
+	saved_sigmask: Option<SigSet>,
 	/// A bitfield storing the set of pending signals.
 	sigpending: SigSet,
+	/// The information attached to each pending signal.
+	sigpending_info: [SignalInfo; signal::SIGNALS_COUNT],
+	/// The set of unimplemented system calls the process has requested.
+	pub unimplemented_syscalls: SyscallSet,
 	/// The list of signal handlers.
 	pub signal_handlers: Arc<Mutex<[SignalHandler; signal::SIGNALS_COUNT]>>,
 
 	/// TLS entries.
 	pub tls_entries: [gdt::Entry; TLS_ENTRIES_COUNT],
+	/// The address which is cleared, and on which futex waiters are woken up, when the process
+	/// exits.
+	///
+	/// It is set by `set_tid_address` or by `clone` with `CLONE_CHILD_CLEARTID`.
+	pub clear_child_tid: SyscallPtr<c_int>,
 
 	/// The process's resources usage.
 	rusage: RUsage,
+	/// The process's I/O accounting.
+	pub io: Arc<IOUsage>,
+	/// The time at which the process started, in nanoseconds since boot.
+	///
+	/// Along with the PID, this allows to identify a process without ambiguity, even if its PID
+	/// has been reused.
+	pub start_time: Timestamp,
 
+	/// The signal sent to the parent when the process terminates. If `None`, no signal is sent.
+	///
+	/// A child whose exit signal is not `SIGCHLD` is called a *clone* child.
+	exit_signal: Option<Signal>,
 	/// The exit status of the process after exiting.
 	exit_status: ExitStatus,
 	/// The terminating signal.
@@ -297,23 +528,39 @@ pub struct Process {
 pub(crate) fn init() -> EResult<()> {
 	TSS::init();
 	scheduler::init()?;
+	// Preallocate kernel stacks
+	{
+		let mut pool = KERNEL_STACKS_POOL.lock();
+		pool.reserve(KERNEL_STACKS_POOL_SIZE)?;
+		for _ in 0..KERNEL_STACKS_POOL_SIZE {
+			let stack = buddy::alloc_kernel(KERNEL_STACK_ORDER)?;
+			pool.push(VirtAddr::from(stack))?;
+		}
+	}
 	// Register interruption callbacks
 	let callback = |id: u32, _code: u32, regs: &Regs, ring: u32| {
 		if ring < 3 {
 			return CallbackResult::Panic;
 		}
-		// Get process
-		let proc_mutex = Process::current();
-		let mut proc = proc_mutex.lock();
-		match id {
+		let (sig, code, addr) = match id {
 			// Divide-by-zero
-			// x87 Floating-Point Exception
-			// SIMD Floating-Point Exception
-			0x00 | 0x10 | 0x13 => proc.kill(Signal::SIGFPE),
+			0x00 => (Signal::SIGFPE, signal::FPE_INTDIV, regs.eip),
+			// Debug
+			0x01 => (Signal::SIGTRAP, signal::TRAP_TRACE, regs.eip),
 			// Breakpoint
-			0x03 => proc.kill(Signal::SIGTRAP),
+			0x03 => (Signal::SIGTRAP, signal::SI_KERNEL, 0),
+			// Overflow
+			// Bound Range Exceeded
+			// Invalid TSS
+			0x04 | 0x05 | 0x0a => (Signal::SIGSEGV, signal::SI_KERNEL, 0),
 			// Invalid Opcode
-			0x06 => proc.kill(Signal::SIGILL),
+			0x06 => (Signal::SIGILL, signal::ILL_ILLOPN, regs.eip),
+			// Device Not Available
+			// Coprocessor Segment Overrun
+			0x07 | 0x09 => (Signal::SIGFPE, signal::SI_KERNEL, 0),
+			// Segment Not Present
+			// Stack-Segment Fault
+			0x0b | 0x0c => (Signal::SIGBUS, signal::SI_KERNEL, 0),
 			// General Protection Fault
 			0x0d => {
 				// Get the instruction opcode
@@ -321,15 +568,32 @@ pub(crate) fn init() -> EResult<()> {
 				let opcode = ptr.copy_from_user();
 				// If the instruction is `hlt`, exit
 				if opcode == Ok(Some(HLT_INSTRUCTION)) {
-					proc.exit(regs.eax as _);
-				} else {
-					proc.kill(Signal::SIGSEGV);
+					Process::current().lock().exit(regs.eax as _);
+					return CallbackResult::Continue;
 				}
+				(Signal::SIGSEGV, signal::SI_KERNEL, 0)
+			}
+			// x87 Floating-Point Exception
+			// SIMD Floating-Point Exception
+			0x10 | 0x13 => {
+				let code = fpu_exception_code(regs, id == 0x13);
+				// Spurious exception
+				if code == 0 {
+					return CallbackResult::Continue;
+				}
+				(Signal::SIGFPE, code, regs.eip)
 			}
 			// Alignment Check
-			0x11 => proc.kill(Signal::SIGBUS),
-			_ => {}
-		}
+			0x11 => (Signal::SIGBUS, signal::BUS_ADRALN, 0),
+			// Double Fault, Machine Check and unexpected exceptions
+			_ => return CallbackResult::Panic,
+		};
+		let info = SignalInfo {
+			code,
+			addr,
+			..Default::default()
+		};
+		Process::current().lock().force_kill(sig, info);
 		CallbackResult::Continue
 	};
 	let page_fault_callback = |_id: u32, code: u32, regs: &Regs, ring: u32| {
@@ -365,22 +629,64 @@ pub(crate) fn init() -> EResult<()> {
 					return CallbackResult::Panic;
 				}
 			} else {
-				curr_proc.kill(Signal::SIGSEGV);
+				let code = if code & vmem::x86::PAGE_FAULT_PRESENT == 0 {
+					signal::SEGV_MAPERR
+				} else {
+					signal::SEGV_ACCERR
+				};
+				let info = SignalInfo {
+					code,
+					addr: accessed_addr.0,
+					..Default::default()
+				};
+				curr_proc.force_kill(Signal::SIGSEGV, info);
 			}
 		}
 		CallbackResult::Continue
 	};
-	let _ = ManuallyDrop::new(event::register_callback(0x00, callback)?);
-	let _ = ManuallyDrop::new(event::register_callback(0x03, callback)?);
-	let _ = ManuallyDrop::new(event::register_callback(0x06, callback)?);
-	let _ = ManuallyDrop::new(event::register_callback(0x0d, callback)?);
+	// Every exception except the Non-maskable Interrupt and the Page Fault, which have their own
+	// handling
+	for id in (0x00..0x20).filter(|id| !matches!(id, 0x02 | 0x0e)) {
+		let _ = ManuallyDrop::new(event::register_callback(id, callback)?);
+	}
 	let _ = ManuallyDrop::new(event::register_callback(0x0e, page_fault_callback)?);
-	let _ = ManuallyDrop::new(event::register_callback(0x10, callback)?);
-	let _ = ManuallyDrop::new(event::register_callback(0x11, callback)?);
-	let _ = ManuallyDrop::new(event::register_callback(0x13, callback)?);
 	Ok(())
 }
 
+/// Returns the `SIGFPE` code corresponding to the floating-point exception that occurred, using
+/// the FPU state saved in `regs`.
+///
+/// If `simd` is `true`, the status of SSE is used. Else, the status of the x87 FPU is used.
+///
+/// If no unmasked exception is pending, the function returns `0`.
+fn fpu_exception_code(regs: &Regs, simd: bool) -> i32 {
+	let fxstate = &regs.fxstate;
+	let err = if simd {
+		let mxcsr = u32::from_le_bytes([fxstate[24], fxstate[25], fxstate[26], fxstate[27]]);
+		// Exception flags with their corresponding mask bits cleared
+		mxcsr & !(mxcsr >> 7) & 0x3f
+	} else {
+		let fcw = u16::from_le_bytes([fxstate[0], fxstate[1]]) as u32;
+		let fsw = u16::from_le_bytes([fxstate[2], fxstate[3]]) as u32;
+		// Exception flags with their corresponding mask bits cleared. The stack fault flag is
+		// kept since it goes along with the invalid operation flag
+		fsw & !(fcw & 0x3f) & 0x7f
+	};
+	if err & 0x41 != 0 {
+		signal::FPE_FLTINV
+	} else if err & 0x04 != 0 {
+		signal::FPE_FLTDIV
+	} else if err & 0x08 != 0 {
+		signal::FPE_FLTOVF
+	} else if err & 0x12 != 0 {
+		signal::FPE_FLTUND
+	} else if err & 0x20 != 0 {
+		signal::FPE_FLTRES
+	} else {
+		0
+	}
+}
+
 impl Process {
 	/// Returns the process with PID `pid`.
 	///
@@ -400,7 +706,7 @@ impl Process {
 	///
 	/// If no process is running, the function returns `None`.
 	pub fn current_opt() -> Option<Arc<IntMutex<Self>>> {
-		SCHEDULER.get().lock().get_current_process()
+		scheduler::current_process()
 	}
 
 	/// Returns the current running process.
@@ -418,7 +724,7 @@ impl Process {
 		// Create the default file descriptors table
 		let file_descriptors = {
 			let mut fds_table = FileDescriptorTable::default();
-			let tty_path = PathBuf::try_from(TTY_DEVICE_PATH.as_bytes())?;
+			let tty_path = PathBuf::try_from(CONSOLE_DEVICE_PATH.as_bytes())?;
 			let tty_file = vfs::get_file_from_path(&tty_path, &rs)?;
 			let tty_file = File::open_entry(tty_file, O_RDWR)?;
 			let (stdin_fd_id, _) = fds_table.create_fd(0, tty_file)?;
@@ -441,19 +747,23 @@ impl Process {
 			pid,
 			pgid: pid::INIT_PID,
 			tid: pid::INIT_PID,
+			thread_group: Arc::new(IntMutex::new(ThreadGroup::new(pid::INIT_PID)?))?,
 
 			argv: Arc::new(Vec::new())?,
 			envp: Arc::new(String::new())?,
-			exec_path: Arc::new(PathBuf::root()?)?,
+			exec_file: None,
 
-			access_profile: rs.access_profile,
-			umask: DEFAULT_UMASK,
+			cred: Arc::new(Cred::new(rs.access_profile))?,
+			personality: 0,
+			time_ns: None,
+			time_ns_for_children: None,
 
 			state: State::Running,
 			vfork_state: VForkState::None,
 
 			priority: 0,
 			nice: 0,
+			oom_score_adj: 0,
 			quantum_count: 0,
 
 			parent: None,
@@ -468,20 +778,116 @@ impl Process {
 			timer_manager: Arc::new(Mutex::new(TimerManager::new(pid::INIT_PID)?))?,
 
 			mem_space: None,
-			kernel_stack: buddy::alloc_kernel(KERNEL_STACK_ORDER)?,
+			kernel_stack: alloc_kernel_stack()?,
 
-			cwd: root_dir.clone(),
-			chroot: root_dir,
+			fs: Arc::new(Mutex::new(ProcessFs::new(root_dir)))?,
 			file_descriptors: Some(Arc::new(Mutex::new(file_descriptors))?),
 
 			sigmask: Default::default(),
+			saved_sigmask: None,
 			sigpending: Default::default(),
+			sigpending_info: Default::default(),
+			unimplemented_syscalls: Default::default(),
 			signal_handlers: Arc::new(Mutex::new(Default::default()))?,
 
 			tls_entries: [gdt::Entry::default(); TLS_ENTRIES_COUNT],
+			clear_child_tid: SyscallPtr(None),
+
+			rusage: RUsage::default(),
+			io: Arc::new(IOUsage::default())?,
+			start_time: clock::boottime(),
+
+			exit_signal: Some(Signal::SIGCHLD),
+			exit_status: 0,
+			termsig: 0,
+		};
+		Ok(SCHEDULER.get().lock().add_process(process)?)
+	}
+
+	/// Creates a kernel thread and places it into the scheduler's queue.
+	///
+	/// A kernel thread runs in kernelspace, on its own kernel stack, with the kernel's access
+	/// profile. It has no parent and no file descriptors table.
+	///
+	/// Arguments:
+	/// - `name` is the name of the thread, used as its command line.
+	/// - `entry` is the function executed by the thread. It must never return.
+	///
+	/// This function must not be called before the init process has been created, otherwise the
+	/// thread would take its PID.
+	///
+	/// Kernel code should use [`kthread::spawn`] instead.
+	pub fn new_kthread(name: &str, entry: fn() -> !) -> EResult<Arc<IntMutex<Self>>> {
+		let root_dir = vfs::root();
+		let pid = PidHandle::unique(None, &[])?;
+		let pid_int = pid.get();
+		let argv = Arc::new(vec![String::try_from(name)?]?)?;
+		let envp = Arc::new(String::new())?;
+		let timer_manager = Arc::new(Mutex::new(TimerManager::new(pid_int)?))?;
+		let mem_space = Arc::new(IntMutex::new(MemSpace::new()?))?;
+		let signal_handlers = Arc::new(Mutex::new(Default::default()))?;
+		let kernel_stack = alloc_kernel_stack()?;
+		let regs = Regs {
+			esp: kernel_stack.as_ptr() as usize + buddy::get_frame_size(KERNEL_STACK_ORDER),
+			eip: entry as usize,
+			..Default::default()
+		};
+		let process = Self {
+			pid,
+			pgid: pid_int,
+			tid: pid_int,
+			thread_group: Arc::new(IntMutex::new(ThreadGroup::new(pid_int)?))?,
+
+			argv,
+			envp,
+			exec_file: None,
+
+			cred: Arc::new(Cred::new(AccessProfile::KERNEL))?,
+			personality: 0,
+			time_ns: None,
+			time_ns_for_children: None,
+
+			state: State::Running,
+			vfork_state: VForkState::None,
+
+			priority: 0,
+			nice: 0,
+			oom_score_adj: 0,
+			quantum_count: 0,
+
+			parent: None,
+			children: Vec::new(),
+			process_group: Vec::new(),
+
+			regs,
+			// The thread is always executing kernel code
+			syscalling: true,
+
+			waitable: false,
+
+			timer_manager,
+
+			mem_space: Some(mem_space),
+			kernel_stack,
+
+			fs: Arc::new(Mutex::new(ProcessFs::new(root_dir)))?,
+			file_descriptors: None,
+
+			sigmask: Default::default(),
+			saved_sigmask: None,
+			sigpending: Default::default(),
+			sigpending_info: Default::default(),
+			unimplemented_syscalls: Default::default(),
+			signal_handlers,
+
+			tls_entries: [gdt::Entry::default(); TLS_ENTRIES_COUNT],
+			clear_child_tid: SyscallPtr(None),
 
 			rusage: RUsage::default(),
+			io: Arc::new(IOUsage::default())?,
+			start_time: clock::boottime(),
 
+			exit_signal: None,
 			exit_status: 0,
 			termsig: 0,
 		};
@@ -493,12 +899,69 @@ impl Process {
 		self.pid.get()
 	}
 
+	/// Returns the process's ID in the PID namespace it belongs to.
+	pub fn get_local_pid(&self) -> Pid {
+		self.pid.get_local()
+	}
+
+	/// Returns the PID namespace the process belongs to. If `None`, this is the root namespace.
+	pub fn get_pid_namespace(&self) -> Option<&Arc<PidNamespace>> {
+		self.pid.get_namespace()
+	}
+
+	/// Translates the PID `pid`, as seen from the process's namespace, into the PID used by the
+	/// kernel.
+	///
+	/// If the process cannot see any process with this PID, the function returns `None`.
+	pub fn pid_to_global(&self, pid: Pid) -> Option<Pid> {
+		pid::to_global(self.get_pid_namespace().map(|ns| &**ns), pid)
+	}
+
+	/// Translates the PID `pid` used by the kernel into the PID seen from the process's
+	/// namespace.
+	///
+	/// If the process cannot see the process with this PID, the function returns `None`.
+	pub fn pid_to_local(&self, pid: Pid) -> Option<Pid> {
+		pid::to_local(self.get_pid_namespace().map(|ns| &**ns), pid)
+	}
+
+	/// Tells whether the process is the init process of a non-root PID namespace.
+	fn is_namespace_init(&self) -> bool {
+		self.get_pid_namespace().is_some() && self.pid.get_local() == pid::INIT_PID
+	}
+
+	/// Returns the process adopting the orphaned children of the process, which is the init
+	/// process of the closest PID namespace whose init is neither the process itself nor
+	/// terminated.
+	fn get_reaper(&self) -> Arc<IntMutex<Process>> {
+		let mut ns = self.get_pid_namespace();
+		while let Some(n) = ns {
+			let reaper = pid::to_global(Some(n), pid::INIT_PID)
+				.filter(|pid| *pid != self.pid.get())
+				.and_then(Process::get_by_pid)
+				.filter(|reaper| reaper.lock().get_state() != State::Zombie);
+			if let Some(reaper) = reaper {
+				return reaper;
+			}
+			ns = n.get_parent();
+		}
+		Process::get_by_pid(pid::INIT_PID).unwrap()
+	}
+
 	/// Tells whether the process is the init process.
 	#[inline(always)]
 	pub fn is_init(&self) -> bool {
 		self.pid.get() == pid::INIT_PID
 	}
 
+	/// Tells whether the process is a kernel thread.
+	///
+	/// Apart from the init process, kernel threads are the only processes without a parent.
+	#[inline(always)]
+	pub fn is_kthread(&self) -> bool {
+		self.parent.is_none() && !self.is_init()
+	}
+
 	/// Tells whether the process is among a group and is not its owner.
 	#[inline(always)]
 	pub fn is_in_group(&self) -> bool {
@@ -581,27 +1044,33 @@ impl Process {
 		}
 		self.state = new_state;
 		if self.state == State::Zombie {
-			if self.is_init() {
-				panic!("Terminated init process!");
-			}
 			// Remove the memory space and file descriptors table to save memory
 			//self.mem_space = None; // TODO Handle the case where the memory space is bound
 			self.file_descriptors = None;
-			// Attach every child to the init process
-			let init_proc_mutex = Process::get_by_pid(pid::INIT_PID).unwrap();
+			// Leave the thread group. Threads other than the leader and kernel threads cannot be
+			// waited for, so they are reaped as soon as they stop running
+			let pid = self.pid.get();
+			self.thread_group.lock().remove(pid);
+			if !self.is_thread_group_leader() || self.is_kthread() {
+				oom::wrap(|| {
+					workqueue::queue_work(move || SCHEDULER.get().lock().remove_process(pid))
+				});
+			}
+			// Wake up the processes polling a pidfd from a work, since the process itself may be
+			// one of them and it is locked
+			oom::wrap(|| workqueue::queue_work(|| pidfd::EXIT_QUEUE.wake_all()));
+			// Attach every child to the init process of the namespace
+			let init_proc_mutex = self.get_reaper();
 			let mut init_proc = init_proc_mutex.lock();
 			let children = mem::take(&mut self.children);
-			for child_pid in children {
-				// Check just in case
-				if child_pid == self.pid.get() {
-					continue;
-				}
-				if let Some(child_mutex) = Process::get_by_pid(child_pid) {
-					child_mutex.lock().parent = Some(init_proc_mutex.clone());
-					oom::wrap(|| init_proc.add_child(child_pid));
-				}
+			for child_mutex in children {
+				let mut child = child_mutex.lock();
+				child.parent = Some(init_proc_mutex.clone());
+				// Make sure init reaps the child
+				child.exit_signal = Some(Signal::SIGCHLD);
+				drop(child);
+				oom::wrap(|| init_proc.add_child(child_mutex.clone()));
 			}
-			self.waitable = true;
 		}
 	}
 
@@ -624,17 +1093,59 @@ impl Process {
 	}
 
 	/// Sets the process waitable with the given signal type.
+	///
+	/// The parent is notified with the process's exit signal if it terminated, or with `SIGCHLD`
+	/// otherwise.
 	pub fn set_waitable(&mut self, sig_type: u8) {
 		self.waitable = true;
 		self.termsig = sig_type;
+		let sig = if self.state == State::Zombie {
+			self.exit_signal
+		} else {
+			Some(Signal::SIGCHLD)
+		};
 		// Wake the parent
 		if let Some(parent) = &self.parent {
 			let mut parent = parent.lock();
-			parent.kill(Signal::SIGCHLD);
+			if let Some(sig) = sig {
+				parent.kill(sig);
+			}
 			parent.wake();
+			// Other threads of the parent's group may be waiting for the process as well. They are
+			// woken up from a work, since the caller may hold one of them locked
+			let group = parent.thread_group.clone();
+			if group.lock().get_threads().len() > 1 {
+				oom::wrap(|| {
+					let group = group.clone();
+					workqueue::queue_work(move || {
+						let group = group.lock();
+						for tid in group.get_threads() {
+							if let Some(thread) = Process::get_by_tid(*tid) {
+								thread.lock().wake();
+							}
+						}
+					})
+				});
+			}
 		}
 	}
 
+	/// Returns the ID of the process's thread group, which is the PID seen by userspace.
+	pub fn get_tgid(&self) -> Pid {
+		self.thread_group.lock().get_tgid()
+	}
+
+	/// Tells whether the process is the leader of its thread group.
+	pub fn is_thread_group_leader(&self) -> bool {
+		self.get_tgid() == self.pid.get()
+	}
+
+	/// Tells whether the process is a *clone* child, that is a child which does not notify its
+	/// parent with `SIGCHLD` when terminating.
+	pub fn is_clone_child(&self) -> bool {
+		self.exit_signal != Some(Signal::SIGCHLD)
+	}
+
 	/// Clears the waitable flag.
 	pub fn clear_waitable(&mut self) {
 		self.waitable = false;
@@ -653,23 +1164,21 @@ impl Process {
 		self.parent.clone()
 	}
 
-	/// Returns an immutable slice of the PIDs of the process's children.
+	/// Returns an immutable slice of the process's children.
 	#[inline(always)]
-	pub fn get_children(&self) -> &[Pid] {
+	pub fn get_children(&self) -> &[Arc<IntMutex<Process>>] {
 		&self.children
 	}
 
-	/// Adds the process with the given PID `pid` as child to the process.
-	pub fn add_child(&mut self, pid: Pid) -> AllocResult<()> {
-		let i = self.children.binary_search(&pid).unwrap_or_else(|i| i);
-		self.children.insert(i, pid)
+	/// Adds the given process as child to the process.
+	pub fn add_child(&mut self, child: Arc<IntMutex<Process>>) -> AllocResult<()> {
+		self.children.push(child)
 	}
 
-	/// Removes the process with the given PID `pid` as child to the process.
-	pub fn remove_child(&mut self, pid: Pid) {
-		if let Ok(i) = self.children.binary_search(&pid) {
-			self.children.remove(i);
-		}
+	/// Removes the given process from the process's children.
+	pub fn remove_child(&mut self, child: &Arc<IntMutex<Process>>) {
+		self.children
+			.retain(|c| !ptr::eq(c.as_ptr(), child.as_ptr()));
 	}
 
 	/// Returns a reference to the process's memory space.
@@ -730,6 +1239,16 @@ impl Process {
 				}
 			}
 		}
+		// Increment the number of ticks the process had
+		self.quantum_count = self.quantum_count.saturating_add(1);
+	}
+
+	/// Loads the context of the process on the current CPU: the TSS, TLS entries and memory
+	/// space.
+	///
+	/// This is required only when switching from another process, since the context remains
+	/// loaded otherwise.
+	pub fn load_context(&self) {
 		// Update the TSS for the process
 		self.update_tss();
 		// Update TLS entries in the GDT
@@ -739,8 +1258,6 @@ impl Process {
 		gdt::flush();
 		// Bind the memory space
 		self.get_mem_space().unwrap().lock().bind();
-		// Increment the number of ticks the process had
-		self.quantum_count = self.quantum_count.saturating_add(1);
 	}
 
 	/// Returns the exit status if the process has ended.
@@ -787,7 +1304,20 @@ impl Process {
 				Arc::new(IntMutex::new(curr_mem_space.lock().fork()?))?
 			}
 		};
-		// Clone file descriptors
+		// The child enters the namespace for children of its parent. Since the vDSO depends on the
+		// namespace, a child sharing the memory space of its parent remains in its namespace
+		let time_ns = if fork_options.new_time_ns {
+			Some(TimeNamespace::new(proc.time_ns_for_children.as_deref())?)
+		} else if fork_options.share_memory || fork_options.vfork {
+			proc.time_ns.clone()
+		} else {
+			proc.time_ns_for_children.clone()
+		};
+		if time_ns.is_some() && proc.time_ns.is_none() {
+			vdso::join_timens(&mut mem_space.lock())?;
+		}
+		// Share or duplicate the file descriptors table. In both cases, open file descriptions
+		// are shared
 		let file_descriptors = if fork_options.share_fd {
 			proc.file_descriptors.clone()
 		} else {
@@ -806,28 +1336,67 @@ impl Process {
 		} else {
 			Arc::new(Mutex::new(proc.signal_handlers.lock().clone()))?
 		};
-		let pid = PidHandle::unique()?;
+		// Share or copy filesystem information
+		let fs = if fork_options.share_fs {
+			proc.fs.clone()
+		} else {
+			Arc::new(Mutex::new(proc.fs.lock().clone()))?
+		};
+		let pid = if fork_options.new_pid_ns {
+			let ns = PidNamespace::new(proc.get_pid_namespace().cloned())?;
+			PidHandle::unique(Some(&ns), &fork_options.set_tid)?
+		} else {
+			PidHandle::unique(proc.get_pid_namespace(), &fork_options.set_tid)?
+		};
 		let pid_int = pid.get();
+		if let Some(ns) = &time_ns {
+			ns.enter();
+		}
+		let time_ns_for_children = if fork_options.new_time_ns {
+			time_ns.clone()
+		} else {
+			proc.time_ns_for_children.clone()
+		};
+		// Share the parent's thread group and related resources, or create new ones
+		let (thread_group, cred, timer_manager, parent) = if fork_options.thread {
+			(
+				proc.thread_group.clone(),
+				proc.cred.clone(),
+				proc.timer_manager.clone(),
+				proc.parent.clone(),
+			)
+		} else {
+			(
+				Arc::new(IntMutex::new(ThreadGroup::new(pid_int)?))?,
+				Arc::new(Cred::new(proc.cred.get()))?,
+				Arc::new(Mutex::new(TimerManager::new(pid_int)?))?,
+				Some(this.clone()),
+			)
+		};
 		let process = Self {
 			pid,
 			pgid: proc.pgid,
 			tid: pid_int,
+			thread_group,
 
 			argv: proc.argv.clone(),
 			envp: proc.envp.clone(),
-			exec_path: proc.exec_path.clone(),
+			exec_file: proc.exec_file.clone(),
 
-			access_profile: proc.access_profile,
-			umask: proc.umask,
+			cred,
+			personality: proc.personality,
+			time_ns,
+			time_ns_for_children,
 
 			state: State::Running,
 			vfork_state,
 
 			priority: proc.priority,
 			nice: proc.nice,
+			oom_score_adj: proc.oom_score_adj,
 			quantum_count: 0,
 
-			parent: Some(this.clone()),
+			parent,
 			children: Vec::new(),
 			process_group: Vec::new(),
 
@@ -836,29 +1405,67 @@ impl Process {
 
 			waitable: false,
 
-			// TODO if creating a thread: timer_manager: proc.timer_manager.clone(),
-			timer_manager: Arc::new(Mutex::new(TimerManager::new(pid_int)?))?,
+			timer_manager,
 
 			mem_space: Some(mem_space),
-			kernel_stack: buddy::alloc_kernel(KERNEL_STACK_ORDER)?,
+			kernel_stack: alloc_kernel_stack()?,
 
-			cwd: proc.cwd.clone(),
-			chroot: proc.chroot.clone(),
+			fs,
 			file_descriptors,
 
 			sigmask: proc.sigmask,
+			saved_sigmask: None,
 			sigpending: Default::default(),
+			sigpending_info: Default::default(),
+			unimplemented_syscalls: Default::default(),
 			signal_handlers,
 
 			tls_entries: proc.tls_entries,
+			clear_child_tid: SyscallPtr(None),
 
 			rusage: RUsage::default(),
+			io: Arc::new(IOUsage::default())?,
+			start_time: clock::boottime(),
 
+			exit_signal: fork_options.exit_signal,
 			exit_status: proc.exit_status,
 			termsig: 0,
 		};
-		proc.add_child(pid_int)?;
-		Ok(SCHEDULER.get().lock().add_process(process)?)
+		if fork_options.thread {
+			let group = process.thread_group.clone();
+			group.lock().add(pid_int)?;
+			return SCHEDULER
+				.get()
+				.lock()
+				.add_process(process)
+				.inspect_err(|_| group.lock().remove(pid_int))
+				.map_err(Into::into);
+		}
+		// Reserve room beforehand so that adding the child cannot fail after it is scheduled
+		proc.children.reserve(1)?;
+		let child = SCHEDULER.get().lock().add_process(process)?;
+		proc.add_child(child.clone())?;
+		Ok(child)
+	}
+
+	/// Terminates and reaps `child`, created by [`Self::fork`] on `this`, without notifying
+	/// `this`.
+	///
+	/// This function is meant to undo a fork when an operation following it fails. `this` must
+	/// not be locked.
+	pub fn cancel_fork(this: &IntMutex<Self>, child: &Arc<IntMutex<Self>>) {
+		let (pid, leader) = {
+			let mut child = child.lock();
+			child.exit_signal = None;
+			child.exit(0);
+			child.clear_waitable();
+			(child.get_pid(), child.is_thread_group_leader())
+		};
+		// Other threads are reaped as soon as they become zombies
+		if leader {
+			this.lock().remove_child(child);
+			SCHEDULER.get().lock().remove_process(pid);
+		}
 	}
 
 	/// Kills the process with the given signal `sig`.
@@ -866,12 +1473,29 @@ impl Process {
 	/// If the process doesn't have a signal handler, the default action for the signal is
 	/// executed.
 	pub fn kill(&mut self, sig: Signal) {
+		self.kill_with_info(sig, SignalInfo::default());
+	}
+
+	/// Same as [`Self::kill`], attaching the information `info` to the signal.
+	///
+	/// If the signal is already pending, the information attached to it is kept.
+	pub fn kill_with_info(&mut self, sig: Signal, info: SignalInfo) {
 		// Cannot kill a zombie process
 		if unlikely(self.state == State::Zombie) {
 			return;
 		}
-		// Ignore blocked signals
+		// Keep track of the signals received by init, to report them if it terminates
+		if self.is_init() {
+			let mut history = INIT_SIGNALS.lock();
+			history.rotate_left(1);
+			history[INIT_SIGNALS_HISTORY - 1] = sig.get_id();
+		}
+		// Blocked signals remain pending until they are unblocked, or dequeued through a
+		// signalfd. Waking the process allows a thread waiting on a signalfd to notice the signal,
+		// other waits go back to sleep since the signal is not deliverable
 		if sig.can_catch() && self.sigmask.is_set(sig.get_id() as _) {
+			self.set_pending(sig, info);
+			self.wake();
 			return;
 		}
 		// Statistics
@@ -900,10 +1524,35 @@ impl Process {
 		{
 			self.set_state(State::Running);
 		}
-		// Set the signal as pending
+		self.set_pending(sig, info);
+	}
+
+	/// Sets the signal `sig` as pending, with the information `info`.
+	///
+	/// If the signal is already pending, the information attached to it is kept.
+	fn set_pending(&mut self, sig: Signal, info: SignalInfo) {
+		if !self.sigpending.is_set(sig.get_id() as _) {
+			self.sigpending_info[sig.get_id() as usize] = info;
+		}
 		self.sigpending.set(sig.get_id() as _);
 	}
 
+	/// Kills the process with the signal `sig`, caused by a fault of the process itself.
+	///
+	/// Since the process cannot continue without handling the signal, the signal cannot be
+	/// blocked or ignored. If it is, it is unblocked and its default action is restored.
+	pub fn force_kill(&mut self, sig: Signal, info: SignalInfo) {
+		let id = sig.get_id() as usize;
+		{
+			let mut handlers = self.signal_handlers.lock();
+			if self.sigmask.is_set(id) || matches!(handlers[id], SignalHandler::Ignore) {
+				handlers[id] = SignalHandler::Default;
+			}
+		}
+		self.sigmask.clear(id);
+		self.kill_with_info(sig, info);
+	}
+
 	/// Kills every process in the process group.
 	pub fn kill_group(&mut self, sig: Signal) {
 		self.process_group
@@ -923,6 +1572,23 @@ impl Process {
 		self.sigmask.is_set(sig.get_id() as _)
 	}
 
+	/// Replaces the signal mask with `mask` until the end of the current system call.
+	///
+	/// The original mask is restored either by [`Self::restore_sigmask`], or before returning to
+	/// userspace. In the latter case, the signal to be handled is selected with the temporary
+	/// mask, so that a signal interrupting the system call is delivered.
+	pub fn set_temporary_sigmask(&mut self, mask: SigSet) {
+		self.saved_sigmask.get_or_insert(self.sigmask);
+		self.sigmask = mask;
+	}
+
+	/// Restores the signal mask replaced by [`Self::set_temporary_sigmask`], if any.
+	pub fn restore_sigmask(&mut self) {
+		if let Some(mask) = self.saved_sigmask.take() {
+			self.sigmask = mask;
+		}
+	}
+
 	/// Returns the ID of the next signal to be handled.
 	///
 	/// If `peek` is `false`, the signal is cleared from the bitfield.
@@ -947,6 +1613,24 @@ impl Process {
 		sig
 	}
 
+	/// Returns the set of pending signals, including blocked ones.
+	pub fn get_pending_signals(&self) -> SigSet {
+		self.sigpending
+	}
+
+	/// Dequeues the pending signal of `mask` with the lowest number, whether it is blocked or
+	/// not, and returns it along with its attached information.
+	///
+	/// If no signal of `mask` is pending, the function returns `None`.
+	pub fn dequeue_signal(&mut self, mask: SigSet) -> Option<(Signal, SignalInfo)> {
+		let sig = (1..signal::SIGNALS_COUNT)
+			.filter(|i| self.sigpending.is_set(*i) && mask.is_set(*i))
+			.find_map(|i| Signal::try_from(i as c_int).ok())?;
+		let id = sig.get_id() as usize;
+		self.sigpending.clear(id);
+		Some((sig, self.sigpending_info[id]))
+	}
+
 	/// Updates the `n`th TLS entry in the GDT.
 	///
 	/// If `n` is out of bounds, the function does nothing.
@@ -980,19 +1664,107 @@ impl Process {
 		}
 	}
 
+	/// Terminates the process with the given exit `status` and terminating signal `termsig`.
+	///
+	/// This function changes the process's status to `Zombie`. Only the leader of the thread
+	/// group notifies its parent, once every thread of the group has terminated.
+	fn terminate(&mut self, status: u32, termsig: u8) {
+		if self.state == State::Zombie {
+			return;
+		}
+		if unlikely(self.is_init()) {
+			init_terminated(status, termsig);
+		}
+		// Processes of a namespace cannot outlive its init
+		if self.is_namespace_init() {
+			if let Some(ns) = self.get_pid_namespace().cloned() {
+				oom::wrap(|| {
+					let ns = ns.clone();
+					workqueue::queue_work(move || kill_namespace(&ns))
+				});
+			}
+		}
+		self.exit_status = status as ExitStatus;
+		self.termsig = termsig;
+		self.set_state(State::Zombie);
+		self.reset_vfork();
+		let (tgid, group_status) = {
+			let group = self.thread_group.lock();
+			if !group.get_threads().is_empty() {
+				return;
+			}
+			(group.get_tgid(), group.get_exit_status())
+		};
+		if tgid == self.pid.get() {
+			self.group_exited(group_status);
+		} else if let Some(leader) = Process::get_by_pid(tgid) {
+			leader.lock().group_exited(group_status);
+		}
+	}
+
+	/// Makes the leader of a thread group waitable, once every thread of the group has
+	/// terminated.
+	///
+	/// `status` is the exit status and terminating signal of the last termination of the whole
+	/// group. If `None`, the leader's own status is reported.
+	fn group_exited(&mut self, status: Option<(ExitStatus, u8)>) {
+		if self.state != State::Zombie {
+			return;
+		}
+		let termsig = match status {
+			Some((status, termsig)) => {
+				self.exit_status = status;
+				termsig
+			}
+			None => self.termsig,
+		};
+		self.set_waitable(termsig);
+	}
+
 	/// Exits the process with the given `status`.
 	///
-	/// This function changes the process's status to `Zombie`.
+	/// Other threads of the thread group are not affected.
 	pub fn exit(&mut self, status: u32) {
 		#[cfg(feature = "strace")]
 		println!(
 			"[strace {pid}] exited with status `{status}`",
 			pid = self.pid.get()
 		);
-		self.exit_status = status as ExitStatus;
-		self.set_state(State::Zombie);
-		self.reset_vfork();
-		self.set_waitable(0);
+		self.terminate(status, 0);
+	}
+
+	/// Terminates every thread of the thread group, including the process itself.
+	///
+	/// Arguments:
+	/// - `status` is the exit status.
+	/// - `termsig` is the terminating signal, or `0` if the group is not terminated by a signal.
+	pub fn exit_group(&mut self, status: u32, termsig: u8) {
+		#[cfg(feature = "strace")]
+		println!(
+			"[strace {pid}] thread group exited with status `{status}`",
+			pid = self.pid.get()
+		);
+		let group = self.thread_group.clone();
+		let pid = self.pid.get();
+		group.lock().set_exiting(status as _, termsig);
+		// Terminate other threads one at a time, without keeping the group locked
+		loop {
+			let tid = group
+				.lock()
+				.get_threads()
+				.iter()
+				.copied()
+				.find(|t| *t != pid);
+			let Some(tid) = tid else {
+				break;
+			};
+			if let Some(thread) = Process::get_by_pid(tid) {
+				thread.lock().terminate(status, termsig);
+			}
+			// Make sure the loop progresses even if the thread was already terminated
+			group.lock().remove(tid);
+		}
+		self.terminate(status, termsig);
 	}
 
 	/// Returns the number of virtual memory pages used by the process.
@@ -1015,7 +1787,7 @@ impl Process {
 		// TODO Take into account userspace-set values (oom may be disabled for this
 		// process, an absolute score or a bonus might be given, etc...)
 		// If the process is owned by the superuser, give it a bonus
-		if self.access_profile.is_privileged() {
+		if self.cred.get().is_privileged() {
 			score = score.saturating_sub(100);
 		}
 		score
@@ -1038,10 +1810,29 @@ impl AccessProfile {
 			return true;
 		}
 		// if sender's `uid` or `euid` equals receiver's `uid` or `suid`
-		self.uid == proc.access_profile.uid
-			|| self.uid == proc.access_profile.suid
-			|| self.euid == proc.access_profile.uid
-			|| self.euid == proc.access_profile.suid
+		let target = proc.cred.get();
+		self.uid == target.uid
+			|| self.uid == target.suid
+			|| self.euid == target.uid
+			|| self.euid == target.suid
+	}
+
+	/// Tells whether the agent can inspect the resources of the process, such as its memory space
+	/// or its open files.
+	///
+	/// This corresponds to Linux's `PTRACE_MODE_READ_REALCREDS` access mode: the real IDs of the
+	/// agent must match all the IDs of the process.
+	pub fn can_inspect(&self, proc: &Process) -> bool {
+		if self.is_privileged() {
+			return true;
+		}
+		let target = proc.cred.get();
+		[target.uid, target.euid, target.suid]
+			.iter()
+			.all(|uid| *uid == self.uid)
+			&& [target.gid, target.egid, target.sgid]
+				.iter()
+				.all(|gid| *gid == self.gid)
 	}
 }
 
@@ -1052,7 +1843,7 @@ impl Drop for Process {
 		}
 		// Free kernel stack
 		unsafe {
-			buddy::free_kernel(self.kernel_stack.as_ptr(), KERNEL_STACK_ORDER);
+			free_kernel_stack(self.kernel_stack);
 		}
 	}
 }
@@ -1064,8 +1855,11 @@ fn yield_current_impl(regs: &mut Regs) -> bool {
 	if proc.state != State::Running {
 		return true;
 	}
+	let sig = proc.next_signal(false);
+	// The signal has been selected, the mask replaced by the system call can be restored
+	proc.restore_sigmask();
 	// If no signal is pending, return
-	let Some(sig) = proc.next_signal(false) else {
+	let Some(sig) = sig else {
 		return false;
 	};
 	// Prepare signal for execution
@@ -24,6 +24,7 @@
 //!
 //! This is an emergency procedure which is not supposed to be used under normal conditions.
 
+use crate::memory::pressure;
 use utils::{errno::AllocResult, lock::Mutex};
 
 /// The maximum number of times the kernel tries to kill a process to retrieve
@@ -48,6 +49,7 @@ pub fn kill() {
 	if !is_killer_enabled() {
 		panic!("Out of memory");
 	}
+	log!(Sched, Warn, "Out of memory, running the OOM killer");
 
 	// TODO Get the process with the highest OOM score (ignore init process)
 }
@@ -63,6 +65,7 @@ pub fn wrap<T, F: FnMut() -> AllocResult<T>>(mut f: F) -> T {
 			return r;
 		}
 
+		pressure::record_stall();
 		kill();
 		// TODO Check if current process has been killed
 	}
@@ -20,15 +20,27 @@
 //!
 //! Each process must have a unique PID, thus they have to be allocated.
 //! A bitfield is used to store the used PIDs.
+//!
+//! PIDs allocated this way belong to the root PID namespace and are the ones used internally by
+//! the kernel. A process created in a child namespace additionally receives a PID in this
+//! namespace and in each of its ancestors, which is the PID visible to processes of the
+//! namespace.
 
-use utils::{collections::id_allocator::IDAllocator, errno::AllocResult, lock::Mutex};
+use core::fmt;
+use utils::{
+	collections::{hashmap::HashMap, id_allocator::IDAllocator, vec::Vec},
+	errno,
+	errno::{AllocResult, EResult},
+	lock::Mutex,
+	ptr::arc::Arc,
+};
 
 /// Type representing a Process ID. This ID is unique for every running
 /// processes.
 pub type Pid = u16;
 
 /// The maximum possible PID.
-const MAX_PID: Pid = 32768;
+pub const MAX_PID: Pid = 32768;
 /// The PID of the init process.
 pub const INIT_PID: Pid = 1;
 
@@ -36,48 +48,306 @@ pub const INIT_PID: Pid = 1;
 static ALLOCATOR: Mutex<Option<IDAllocator>> = Mutex::new(None);
 
 /// Perform an operation with the allocator.
-fn allocator_do<F: Fn(&mut IDAllocator) -> AllocResult<T>, T>(f: F) -> AllocResult<T> {
+fn allocator_do<F: FnOnce(&mut IDAllocator) -> EResult<T>, T>(f: F) -> EResult<T> {
 	let mut allocator = ALLOCATOR.lock();
 	let allocator = match &mut *allocator {
 		Some(a) => a,
-		None => allocator.insert(IDAllocator::new(MAX_PID as _)?),
+		None => {
+			let a = IDAllocator::new(MAX_PID as _).map_err(|_| errno!(ENOMEM))?;
+			allocator.insert(a)
+		}
 	};
 	f(allocator)
 }
 
+/// Allocates a PID with `allocator`.
+///
+/// If `pid` is specified, the function allocates this PID. If the PID is out of bounds, the
+/// function returns [`errno::EINVAL`]. If it is already in use, the function returns
+/// [`errno::EEXIST`].
+fn alloc_pid(allocator: &mut IDAllocator, pid: Option<Pid>) -> EResult<Pid> {
+	let id = match pid {
+		Some(pid) => {
+			if !(1..=MAX_PID).contains(&pid) {
+				return Err(errno!(EINVAL));
+			}
+			allocator
+				.alloc(Some((pid - 1) as _))
+				.map_err(|_| errno!(EEXIST))?
+		}
+		None => allocator.alloc(None)?,
+	};
+	Ok((id + 1) as _)
+}
+
+/// The PIDs of a [`PidNamespace`].
+struct NamespacePids {
+	/// The allocator of PIDs local to the namespace.
+	allocator: IDAllocator,
+	/// Local PIDs to root namespace PIDs.
+	to_global: HashMap<Pid, Pid>,
+	/// Root namespace PIDs to local PIDs.
+	to_local: HashMap<Pid, Pid>,
+}
+
+/// A PID namespace, other than the root one.
+///
+/// Processes of a namespace only see the processes of the namespace and of its descendants.
+/// The first process created in a namespace gets the local PID [`INIT_PID`].
+///
+/// The root namespace is represented by `None` where a namespace is expected.
+pub struct PidNamespace {
+	/// The parent namespace. If `None`, the parent is the root namespace.
+	parent: Option<Arc<PidNamespace>>,
+	/// The PIDs allocated in the namespace.
+	pids: Mutex<NamespacePids>,
+}
+
+impl PidNamespace {
+	/// Creates a new namespace, child of `parent`.
+	pub fn new(parent: Option<Arc<PidNamespace>>) -> AllocResult<Arc<Self>> {
+		Arc::new(Self {
+			parent,
+			pids: Mutex::new(NamespacePids {
+				allocator: IDAllocator::new(MAX_PID as _)?,
+				to_global: HashMap::new(),
+				to_local: HashMap::new(),
+			}),
+		})
+	}
+
+	/// Returns the parent namespace. If `None`, the parent is the root namespace.
+	pub fn get_parent(&self) -> Option<&Arc<PidNamespace>> {
+		self.parent.as_ref()
+	}
+
+	/// Returns the root namespace PIDs of the processes of the namespace, including those of
+	/// descendant namespaces.
+	pub fn get_processes(&self) -> AllocResult<Vec<Pid>> {
+		let pids = self.pids.lock();
+		let mut res = Vec::with_capacity(pids.to_local.len())?;
+		for (global, _) in pids.to_local.iter() {
+			res.push(*global)?;
+		}
+		Ok(res)
+	}
+
+	/// Allocates a local PID for the process with the root namespace PID `global`.
+	///
+	/// If `local` is specified, the function allocates this PID. Errors are the same as for
+	/// [`alloc_pid`].
+	fn alloc(&self, global: Pid, local: Option<Pid>) -> EResult<Pid> {
+		let mut pids = self.pids.lock();
+		pids.to_global.reserve(1)?;
+		pids.to_local.reserve(1)?;
+		let local = alloc_pid(&mut pids.allocator, local)?;
+		// Cannot fail since memory has been reserved
+		let _ = pids.to_global.insert(local, global);
+		let _ = pids.to_local.insert(global, local);
+		Ok(local)
+	}
+
+	/// Frees the local PID `local`.
+	fn free(&self, local: Pid) {
+		let mut pids = self.pids.lock();
+		if let Some(global) = pids.to_global.remove(&local) {
+			pids.to_local.remove(&global);
+		}
+		pids.allocator.free((local - 1) as _);
+	}
+}
+
+impl fmt::Debug for PidNamespace {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("PidNamespace")
+			.field("parent", &self.parent)
+			.field("processes", &self.pids.lock().to_local.len())
+			.finish()
+	}
+}
+
+/// Translates the PID `pid`, as seen from the namespace `ns`, into a root namespace PID.
+///
+/// If no process of the namespace has this PID, the function returns `None`.
+pub fn to_global(ns: Option<&PidNamespace>, pid: Pid) -> Option<Pid> {
+	match ns {
+		Some(ns) => ns.pids.lock().to_global.get(&pid).copied(),
+		None => Some(pid),
+	}
+}
+
+/// Translates the root namespace PID `pid` into the PID seen from the namespace `ns`.
+///
+/// If the process is not visible from the namespace, the function returns `None`.
+pub fn to_local(ns: Option<&PidNamespace>, pid: Pid) -> Option<Pid> {
+	match ns {
+		Some(ns) => ns.pids.lock().to_local.get(&pid).copied(),
+		None => Some(pid),
+	}
+}
+
 /// Wrapper for a PID, freeing it on drop.
+///
+/// This includes the PIDs of the process in non-root namespaces.
 #[derive(Debug)]
-pub struct PidHandle(Pid);
+pub struct PidHandle {
+	/// The PID in the root namespace.
+	pid: Pid,
+	/// The PIDs in other namespaces, from the namespace the process belongs to, up to the child
+	/// of the root namespace.
+	ns_pids: Vec<(Arc<PidNamespace>, Pid)>,
+}
 
 impl PidHandle {
 	/// Returns the init PID.
 	///
 	/// This function **must not** be used outside the creation of the first process.
-	pub(super) fn init() -> AllocResult<Self> {
+	pub(super) fn init() -> EResult<Self> {
 		allocator_do(|a| {
 			a.set_used((INIT_PID - 1) as _);
 			Ok(())
 		})?;
-		Ok(Self(INIT_PID))
+		Ok(Self {
+			pid: INIT_PID,
+			ns_pids: Vec::new(),
+		})
 	}
 
-	/// Returns an unused PID and marks it as used.
-	pub fn unique() -> AllocResult<PidHandle> {
-		allocator_do(|allocator| allocator.alloc(None)).map(|i| PidHandle((i + 1) as _))
+	/// Returns an unused PID in the namespace `ns` and marks it as used.
+	///
+	/// A PID is also allocated in each ancestor of the namespace.
+	///
+	/// `set_tid` is the list of PIDs to allocate, starting from the namespace `ns` and going up
+	/// to the root namespace. Namespaces not covered by the list receive an unused PID. If the
+	/// list is longer than the number of namespaces, the function returns [`errno::EINVAL`]. If
+	/// a requested PID is already used, the function returns [`errno::EEXIST`].
+	pub fn unique(ns: Option<&Arc<PidNamespace>>, set_tid: &[Pid]) -> EResult<PidHandle> {
+		let mut depth = 0;
+		let mut n = ns;
+		while let Some(ns) = n {
+			depth += 1;
+			n = ns.parent.as_ref();
+		}
+		if set_tid.len() > depth + 1 {
+			return Err(errno!(EINVAL));
+		}
+		let pid = allocator_do(|allocator| alloc_pid(allocator, set_tid.get(depth).copied()))?;
+		let mut handle = PidHandle {
+			pid,
+			ns_pids: Vec::new(),
+		};
+		// On failure, dropping the handle frees the PIDs allocated so far
+		let mut ns = ns;
+		while let Some(n) = ns {
+			handle.ns_pids.reserve(1)?;
+			let local = n.alloc(handle.pid, set_tid.get(handle.ns_pids.len()).copied())?;
+			// Cannot fail since memory has been reserved
+			let _ = handle.ns_pids.push((n.clone(), local));
+			ns = n.parent.as_ref();
+		}
+		Ok(handle)
 	}
 
 	/// Returns the actual PID.
 	pub fn get(&self) -> Pid {
-		self.0
+		self.pid
+	}
+
+	/// Returns the namespace the process belongs to. If `None`, this is the root namespace.
+	pub fn get_namespace(&self) -> Option<&Arc<PidNamespace>> {
+		self.ns_pids.first().map(|(ns, _)| ns)
+	}
+
+	/// Returns the PID in the namespace the process belongs to.
+	pub fn get_local(&self) -> Pid {
+		self.ns_pids
+			.first()
+			.map(|(_, pid)| *pid)
+			.unwrap_or(self.pid)
 	}
 }
 
 impl Drop for PidHandle {
 	fn drop(&mut self) {
+		for (ns, pid) in &self.ns_pids {
+			ns.free(*pid);
+		}
 		// Cannot fail
 		let _ = allocator_do(|a| {
-			a.free((self.0 - 1) as _);
+			a.free((self.pid - 1) as _);
 			Ok(())
 		});
 	}
 }
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test_case]
+	fn pid_namespace() {
+		let ns = PidNamespace::new(None).unwrap();
+		let child_ns = PidNamespace::new(Some(ns.clone())).unwrap();
+		let pid = PidHandle::unique(Some(&child_ns), &[]).unwrap();
+		// The first process of a namespace is its init
+		assert_eq!(pid.get_local(), INIT_PID);
+		assert_eq!(to_local(Some(&child_ns), pid.get()), Some(INIT_PID));
+		assert_eq!(to_local(Some(&ns), pid.get()), Some(INIT_PID));
+		assert_eq!(to_global(Some(&child_ns), INIT_PID), Some(pid.get()));
+		assert_eq!(to_local(None, pid.get()), Some(pid.get()));
+		// A process of the parent namespace is not visible from the child
+		let parent_pid = PidHandle::unique(Some(&ns), &[]).unwrap();
+		assert_eq!(to_local(Some(&ns), parent_pid.get()), Some(2));
+		assert_eq!(to_local(Some(&child_ns), parent_pid.get()), None);
+		// PIDs are released on drop
+		let global = pid.get();
+		drop(pid);
+		assert_eq!(to_global(Some(&child_ns), INIT_PID), None);
+		assert_eq!(to_local(Some(&ns), global), None);
+	}
+
+	#[test_case]
+	fn pid_set_tid() {
+		let ns = PidNamespace::new(None).unwrap();
+		let pid = PidHandle::unique(Some(&ns), &[5]).unwrap();
+		assert_eq!(pid.get_local(), 5);
+		assert_eq!(to_global(Some(&ns), 5), Some(pid.get()));
+		// A PID cannot be used twice
+		assert_eq!(
+			PidHandle::unique(Some(&ns), &[5]).unwrap_err(),
+			errno!(EEXIST)
+		);
+		// There are only two levels of namespaces
+		assert_eq!(
+			PidHandle::unique(Some(&ns), &[6, 7, 8]).unwrap_err(),
+			errno!(EINVAL)
+		);
+		assert_eq!(
+			PidHandle::unique(Some(&ns), &[0]).unwrap_err(),
+			errno!(EINVAL)
+		);
+		// A released PID can be requested again
+		drop(pid);
+		let pid = PidHandle::unique(Some(&ns), &[5]).unwrap();
+		assert_eq!(pid.get_local(), 5);
+	}
+
+	#[test_case]
+	fn pid_set_tid_failure() {
+		let ns = PidNamespace::new(None).unwrap();
+		let child_ns = PidNamespace::new(Some(ns.clone())).unwrap();
+		let _parent_pid = PidHandle::unique(Some(&ns), &[4]).unwrap();
+		// The PID is already used in the parent namespace, after allocating the other PIDs
+		assert_eq!(
+			PidHandle::unique(Some(&child_ns), &[7, 4, 30000]).unwrap_err(),
+			errno!(EEXIST)
+		);
+		assert_eq!(to_global(Some(&child_ns), 7), None);
+		assert_eq!(to_local(Some(&ns), 30000), None);
+		// The PIDs allocated before the failure have been released
+		let pid = PidHandle::unique(Some(&child_ns), &[7, 5, 30000]).unwrap();
+		assert_eq!(pid.get(), 30000);
+		assert_eq!(pid.get_local(), 7);
+		assert_eq!(to_local(Some(&ns), 30000), Some(5));
+	}
+}
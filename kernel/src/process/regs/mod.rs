@@ -54,28 +54,41 @@ mod x86 {
 	/// Saves the current x87 FPU, MMX and SSE state to the given buffer.
 	#[no_mangle]
 	pub extern "C" fn save_fxstate(fxstate: &mut [u8; 512]) {
+		// If the buffer is aligned, avoid the copy
+		if fxstate.as_ptr().is_aligned_to(16) {
+			unsafe {
+				asm!("fxsave [{}]", in(reg) fxstate.as_mut_ptr());
+			}
+			return;
+		}
 		let mut buff = FXStateWrapper([0; 512]);
 		unsafe {
 			asm!("fxsave [{}]", in(reg) buff.0.as_mut_ptr());
 		}
-
-		// TODO avoid copy (slow). `fxstate` itself should be aligned
 		fxstate.copy_from_slice(&buff.0);
 	}
 
 	/// Restores the x87 FPU, MMX and SSE state from the given buffer.
 	#[no_mangle]
 	pub extern "C" fn restore_fxstate(fxstate: &[u8; 512]) {
+		// If the buffer is aligned, avoid the copy
+		if fxstate.as_ptr().is_aligned_to(16) {
+			unsafe {
+				asm!("fxrstor [{}]", in(reg) fxstate.as_ptr());
+			}
+			return;
+		}
 		let mut buff = FXStateWrapper([0; 512]);
-		// TODO avoid copy (slow). `fxstate` itself should be aligned
 		buff.0.copy_from_slice(fxstate);
-
 		unsafe {
 			asm!("fxrstor [{}]", in(reg) buff.0.as_ptr());
 		}
 	}
 }
 
+#[cfg(target_arch = "x86")]
+pub use x86::{restore_fxstate, save_fxstate};
+
 /// The register state of an execution context.
 ///
 /// The contents of this structure is architecture-dependent.
@@ -138,6 +151,20 @@ impl Regs {
 	///
 	/// Invalid register values shall result in an undefined behaviour.
 	pub unsafe fn switch(&self, user: bool) -> ! {
+		x86::restore_fxstate(&self.fxstate);
+		self.resume(user)
+	}
+
+	/// Same as [`Self::switch`], except the FPU state is not restored.
+	///
+	/// This is used to avoid the cost of restoring the FPU state when resuming the context that
+	/// was interrupted, since its state is still loaded.
+	///
+	/// # Safety
+	///
+	/// In addition to the requirements of [`Self::switch`], the FPU state of the context must be
+	/// the one currently loaded on the CPU.
+	pub unsafe fn resume(&self, user: bool) -> ! {
 		let pc = self.eip;
 		debug_assert_ne!(pc, 0);
 		if user {
@@ -201,3 +228,29 @@ impl fmt::Debug for Regs {
 			.finish()
 	}
 }
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::selftest;
+
+	#[test_case]
+	fn fxstate_switch() {
+		let regs = Regs::default();
+		let mut original = [0u8; 512];
+		x86::save_fxstate(&mut original);
+		let mut fxstate = [0u8; 512];
+		selftest::bench("fxstate save/restore", 1000, || {
+			x86::save_fxstate(&mut fxstate);
+			x86::restore_fxstate(&regs.fxstate);
+		});
+		// The control words of the context have been loaded
+		x86::save_fxstate(&mut fxstate);
+		assert_eq!(fxstate[..2], regs.fxstate[..2]);
+		assert_eq!(fxstate[24..28], regs.fxstate[24..28]);
+		// Restore the original state
+		x86::restore_fxstate(&original);
+		x86::save_fxstate(&mut fxstate);
+		assert_eq!(fxstate, original);
+	}
+}
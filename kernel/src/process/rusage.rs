@@ -18,7 +18,12 @@
 
 //! Monitoring of the resource usage of processes.
 
-use crate::time::unit::Timeval;
+use crate::{
+	process::scheduler,
+	time::unit::{TimeUnit, Timestamp, Timeval},
+};
+use core::sync::atomic::Ordering::Relaxed;
+use utils::lock::atomic::AtomicU64;
 
 /// Usage of each resource by a process.
 #[derive(Clone, Default, Debug)]
@@ -57,4 +62,71 @@ pub struct RUsage {
 	pub ru_nivcsw: i32,
 }
 
+impl RUsage {
+	/// Accounts `elapsed` nanoseconds of CPU time, spent in userspace if `user` is set, or in
+	/// kernelspace otherwise.
+	pub fn account_cpu(&mut self, user: bool, elapsed: Timestamp) {
+		let time = if user {
+			&mut self.ru_utime
+		} else {
+			&mut self.ru_stime
+		};
+		*time = Timeval::from_nano(time.to_nano().saturating_add(elapsed));
+	}
+}
+
 // TODO Place calls in kernel's code to update usage
+
+/// I/O accounting of a process.
+///
+/// Counters are atomic so that they can be updated without locking the process.
+#[derive(Debug, Default)]
+pub struct IOUsage {
+	/// The number of bytes read through read system calls, whether or not the storage has been
+	/// accessed.
+	pub rchar: AtomicU64,
+	/// The number of bytes written through write system calls, whether or not the storage has
+	/// been accessed.
+	pub wchar: AtomicU64,
+	/// The number of read system calls.
+	pub syscr: AtomicU64,
+	/// The number of write system calls.
+	pub syscw: AtomicU64,
+	/// The number of bytes actually read from storage devices.
+	pub read_bytes: AtomicU64,
+	/// The number of bytes actually written to storage devices.
+	pub write_bytes: AtomicU64,
+}
+
+impl IOUsage {
+	/// Accounts a read system call which read `len` bytes.
+	pub fn account_read(&self, len: usize) {
+		self.rchar.fetch_add(len as _, Relaxed);
+		self.syscr.fetch_add(1, Relaxed);
+	}
+
+	/// Accounts a write system call which wrote `len` bytes.
+	pub fn account_write(&self, len: usize) {
+		self.wchar.fetch_add(len as _, Relaxed);
+		self.syscw.fetch_add(1, Relaxed);
+	}
+}
+
+/// Accounts `len` bytes read from a storage device to the process running on the CPU, if any.
+///
+/// The accounting structure is taken from the context of the CPU, without locking the scheduler
+/// nor the process, so that this function can be called while either is locked.
+pub fn account_block_read(len: usize) {
+	if let Some(io) = scheduler::current_io() {
+		io.read_bytes.fetch_add(len as _, Relaxed);
+	}
+}
+
+/// Accounts `len` bytes written to a storage device to the process running on the CPU, if any.
+///
+/// Like [`account_block_read`], this function does not lock the scheduler nor the process.
+pub fn account_block_write(len: usize) {
+	if let Some(io) = scheduler::current_io() {
+		io.write_bytes.fetch_add(len as _, Relaxed);
+	}
+}
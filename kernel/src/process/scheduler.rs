@@ -30,16 +30,25 @@ use crate::{
 	event::CallbackHook,
 	idt::pic,
 	memory::stack,
-	process::{pid::Pid, regs::Regs, Process, State},
+	process::{loadavg::LoadAvg, pid::Pid, regs::Regs, rusage::IOUsage, Process, State},
 	time,
+	time::{clock, unit::Timestamp},
+};
+use core::{
+	arch::asm,
+	ptr::null_mut,
+	sync::atomic::{
+		AtomicPtr, AtomicUsize,
+		Ordering::{Acquire, Relaxed, Release},
+	},
 };
-use core::arch::asm;
 use utils::{
 	collections::{
 		btreemap::{BTreeMap, MapIterator},
 		vec::Vec,
 	},
 	errno::AllocResult,
+	interrupt,
 	interrupt::cli,
 	limits::PAGE_SIZE,
 	lock::{once::OnceInit, IntMutex},
@@ -56,6 +65,25 @@ const TMP_STACK_SIZE: usize = 16 * PAGE_SIZE;
 /// The process scheduler.
 pub static SCHEDULER: OnceInit<IntMutex<Scheduler>> = unsafe { OnceInit::new() };
 
+/// The process currently running on the CPU, or null if none.
+///
+/// This allows retrieving the current process without locking the scheduler. The process is kept
+/// alive by [`Scheduler::curr_proc`], and the pointer is updated only with interruptions disabled.
+static CURRENT_PROC: AtomicPtr<IntMutex<Process>> = AtomicPtr::new(null_mut());
+/// The I/O accounting structure of the current process, or null if none.
+///
+/// Like [`CURRENT_PROC`], it is kept alive by [`Scheduler::curr_io`].
+static CURRENT_IO: AtomicPtr<IOUsage> = AtomicPtr::new(null_mut());
+/// If the current process yielded, the priority of the process. If not, the value is
+/// [`usize::MAX`].
+///
+/// Processes with the same priority are preferred when selecting the next process to run.
+static YIELD_PRIORITY: AtomicUsize = AtomicUsize::new(usize::MAX);
+/// The current number of processes in running state.
+///
+/// It is modified only with the scheduler locked, but can be read without locking it.
+static RUNNING_PROCS: AtomicUsize = AtomicUsize::new(0);
+
 /// Initializes schedulers.
 pub fn init() -> AllocResult<()> {
 	// TODO handle multicore
@@ -74,6 +102,10 @@ pub struct Scheduler {
 	tick_callback_hook: CallbackHook,
 	/// The total number of ticks since the instantiation of the scheduler.
 	total_ticks: u64,
+	/// The timestamp of the last tick, in nanoseconds since boot.
+	last_tick: Timestamp,
+	/// The load average of the scheduler's processes.
+	load_avg: LoadAvg,
 	/// The scheduler's temporary stacks.
 	tmp_stack: Vec<u8>,
 
@@ -82,8 +114,13 @@ pub struct Scheduler {
 	processes: BTreeMap<Pid, Arc<IntMutex<Process>>>,
 	/// The process currently being executed by the scheduler's core, along with its PID.
 	curr_proc: Option<(Pid, Arc<IntMutex<Process>>)>,
-	/// The current number of processes in running state.
-	running_procs: usize,
+	/// The I/O accounting structure of the current process.
+	///
+	/// This allows accounting I/O without locking the current process, which may already be
+	/// locked by the caller.
+	curr_io: Option<Arc<IOUsage>>,
+	/// The PID of the last process added to the scheduler.
+	last_pid: Pid,
 }
 
 impl Scheduler {
@@ -104,11 +141,14 @@ impl Scheduler {
 		Ok(Self {
 			tick_callback_hook,
 			total_ticks: 0,
+			last_tick: clock::boottime(),
+			load_avg: LoadAvg::default(),
 			tmp_stack,
 
 			processes: BTreeMap::new(),
 			curr_proc: None,
-			running_procs: 0,
+			curr_io: None,
+			last_pid: 0,
 		})
 	}
 
@@ -123,6 +163,31 @@ impl Scheduler {
 		self.total_ticks
 	}
 
+	/// Returns the number of processes registered to the scheduler.
+	pub fn get_process_count(&self) -> usize {
+		self.processes.len()
+	}
+
+	/// Returns the number of processes in running state.
+	pub fn get_running_count(&self) -> usize {
+		RUNNING_PROCS.load(Relaxed)
+	}
+
+	/// Returns the PID of the last process added to the scheduler.
+	pub fn get_last_pid(&self) -> Pid {
+		self.last_pid
+	}
+
+	/// Updates the load average, then returns it.
+	///
+	/// Since the scheduler does not tick when less than two processes are running, the load
+	/// average has to be brought up to date when read.
+	pub fn update_load_avg(&mut self) -> &LoadAvg {
+		self.load_avg
+			.update(clock::boottime(), RUNNING_PROCS.load(Relaxed));
+		&self.load_avg
+	}
+
 	/// Returns an iterator on the scheduler's processes.
 	pub fn iter_process(&self) -> MapIterator<'_, Pid, Arc<IntMutex<Process>>> {
 		self.processes.iter()
@@ -137,10 +202,11 @@ impl Scheduler {
 
 	/// Returns the process with TID `tid`.
 	///
+	/// Since each thread has its own PID, this is equivalent to [`Self::get_by_pid`].
+	///
 	/// If the process doesn't exist, the function returns `None`.
-	pub fn get_by_tid(&self, _tid: Pid) -> Option<Arc<IntMutex<Process>>> {
-		// TODO
-		todo!();
+	pub fn get_by_tid(&self, tid: Pid) -> Option<Arc<IntMutex<Process>>> {
+		self.get_by_pid(tid)
 	}
 
 	/// Returns the current running process.
@@ -171,6 +237,7 @@ impl Scheduler {
 		let priority = process.priority;
 		let ptr = Arc::new(IntMutex::new(process))?;
 		self.processes.insert(pid, ptr.clone())?;
+		self.last_pid = pid;
 		self.update_priority(0, priority);
 		Ok(ptr)
 	}
@@ -190,15 +257,15 @@ impl Scheduler {
 
 	/// Returns the current ticking frequency of the scheduler.
 	pub fn get_ticking_frequency(&self) -> Rational {
-		Rational::from_integer((10 * self.running_procs) as _)
+		Rational::from_integer((10 * RUNNING_PROCS.load(Relaxed)) as _)
 	}
 
 	/// Increments the number of running processes.
 	pub fn increment_running(&mut self) {
-		self.running_procs += 1;
+		let running = RUNNING_PROCS.fetch_add(1, Relaxed) + 1;
 		let mut clocks = time::hw::CLOCKS.lock();
 		let pit = clocks.get_mut(b"pit".as_slice()).unwrap();
-		if self.running_procs > 1 {
+		if running > 1 {
 			pit.set_frequency(self.get_ticking_frequency());
 			pit.set_enabled(true);
 		}
@@ -206,10 +273,10 @@ impl Scheduler {
 
 	/// Decrements the number of running processes.
 	pub fn decrement_running(&mut self) {
-		self.running_procs -= 1;
+		let running = RUNNING_PROCS.fetch_sub(1, Relaxed) - 1;
 		let mut clocks = time::hw::CLOCKS.lock();
 		let pit = clocks.get_mut(b"pit".as_slice()).unwrap();
-		if self.running_procs <= 1 {
+		if running <= 1 {
 			pit.set_enabled(false);
 		} else {
 			pit.set_frequency(self.get_ticking_frequency());
@@ -217,7 +284,10 @@ impl Scheduler {
 	}
 
 	/// Returns the next process to run with its PID.
-	fn get_next_process(&self) -> Option<(Pid, Arc<IntMutex<Process>>)> {
+	///
+	/// If `priority` is specified, a process with this priority other than the current process is
+	/// selected if any is runnable.
+	fn get_next_process(&self, priority: Option<usize>) -> Option<(Pid, Arc<IntMutex<Process>>)> {
 		// Get the current process, or take the first process in the list if no
 		// process is running
 		let curr_pid = self
@@ -225,19 +295,26 @@ impl Scheduler {
 			.as_ref()
 			.map(|(pid, _)| *pid)
 			.or_else(|| self.processes.first_key_value().map(|(pid, _)| *pid))?;
-		let process_filter = |(_, proc_mutex): &(&Pid, &Arc<IntMutex<Process>>)| {
-			let proc = proc_mutex.lock();
-			proc.can_run()
+		let find = |filter: &dyn Fn(&Process) -> bool| {
+			let process_filter = |(_, proc_mutex): &(&Pid, &Arc<IntMutex<Process>>)| {
+				let proc = proc_mutex.lock();
+				proc.can_run() && filter(&proc)
+			};
+			self.processes
+				.range((curr_pid + 1)..)
+				.find(process_filter)
+				.or_else(|| {
+					// If no suitable process is found, go back to the beginning to check
+					// processes located before the previous process (looping)
+					self.processes.range(..=curr_pid).find(process_filter)
+				})
+				.map(|(pid, proc)| (*pid, proc.clone()))
 		};
-		self.processes
-			.range((curr_pid + 1)..)
-			.find(process_filter)
-			.or_else(|| {
-				// If no suitable process is found, go back to the beginning to check processes
-				// located before the previous process (looping)
-				self.processes.range(..=curr_pid).find(process_filter)
+		priority
+			.and_then(|priority| {
+				find(&|proc| proc.get_pid() != curr_pid && proc.priority == priority)
 			})
-			.map(|(pid, proc)| (*pid, proc.clone()))
+			.or_else(|| find(&|_| true))
 	}
 
 	/// Ticking the scheduler.
@@ -247,6 +324,9 @@ impl Scheduler {
 	///
 	/// If no process is ready to run, the scheduler halts the system until a process is runnable.
 	///
+	/// Contrary to [`current_process`] and the fast path of [`end_tick`], switching context still
+	/// locks the scheduler.
+	///
 	/// Arguments:
 	/// - `sched_mutex` is the scheduler's mutex.
 	/// - `regs` is the state of the registers from the paused context.
@@ -259,32 +339,62 @@ impl Scheduler {
 		let (switch_info, tmp_stack) = {
 			let mut sched = sched_mutex.lock();
 			sched.total_ticks = sched.total_ticks.saturating_add(1);
-			// If a process is running, save its registers
-			if let Some(curr_proc) = sched.get_current_process() {
+			let now = clock::boottime();
+			let elapsed = now.saturating_sub(sched.last_tick);
+			sched.last_tick = now;
+			sched.update_load_avg();
+			// If a process is running, save its registers and account the time it ran for
+			let prev = sched.curr_proc.as_ref().map(|(_, proc)| Arc::as_ptr(proc));
+			if let Some((_, curr_proc)) = &sched.curr_proc {
 				let mut curr_proc = curr_proc.lock();
 				curr_proc.regs = regs.clone();
 				curr_proc.syscalling = ring < 3;
+				curr_proc.rusage.account_cpu(ring == 3, elapsed);
 			}
+			let yield_priority = YIELD_PRIORITY.swap(usize::MAX, Relaxed);
+			let yield_priority = (yield_priority != usize::MAX).then_some(yield_priority);
+			// Tells whether a process has been skipped. If so, it may have altered the context
+			let mut skipped = false;
 			// Loop until a runnable process is found
-			let (proc, switch_info) = loop {
-				let Some((pid, proc_mutex)) = sched.get_next_process() else {
+			let (proc, io, switch_info) = loop {
+				let Some((pid, proc_mutex)) = sched.get_next_process(yield_priority) else {
 					// No process to run
-					break (None, None);
+					break (None, None, None);
 				};
 				// Try switching
 				let mut proc = proc_mutex.lock();
 				proc.prepare_switch();
 				// If the process has been killed by a signal, try the next process
 				if !matches!(proc.get_state(), State::Running) {
+					skipped = true;
 					continue;
 				}
+				// If the interrupted process is resumed, its context is still loaded
+				let resumed = !skipped && prev == Some(Arc::as_ptr(&proc_mutex));
+				if !resumed {
+					proc.load_context();
+				}
 				let regs = proc.regs.clone();
 				let syscalling = proc.syscalling;
+				let io = proc.io.clone();
 				drop(proc);
-				break (Some((pid, proc_mutex)), Some((regs, syscalling)));
+				break (
+					Some((pid, proc_mutex)),
+					Some(io),
+					Some((regs, syscalling, resumed)),
+				);
 			};
 			// Set current running process
+			let proc_ptr = proc
+				.as_ref()
+				.map_or(null_mut(), |(_, p)| Arc::as_ptr(p) as *mut _);
+			let io_ptr = io
+				.as_ref()
+				.map_or(null_mut(), |io| Arc::as_ptr(io) as *mut _);
+			CURRENT_PROC.store(proc_ptr, Release);
+			CURRENT_IO.store(io_ptr, Release);
 			sched.curr_proc = proc;
+			sched.curr_io = io;
 			let tmp_stack = sched.get_tmp_stack();
 			(switch_info, tmp_stack)
 		};
@@ -293,8 +403,10 @@ impl Scheduler {
 			event::unlock_callbacks(0x20);
 			pic::end_of_interrupt(0x0);
 			match switch_info {
-				// Runnable process found: resume execution
-				Some((regs, syscalling)) => regs.switch(!syscalling),
+				// The interrupted process is resumed: its FPU state is still loaded
+				Some((regs, syscalling, true)) => regs.resume(!syscalling),
+				// Another process is runnable: switch to it
+				Some((regs, syscalling, false)) => regs.switch(!syscalling),
 				// No runnable process found: idle
 				None => stack::switch(tmp_stack as _, crate::enter_loop),
 			}
@@ -302,13 +414,93 @@ impl Scheduler {
 	}
 }
 
+/// Makes the current process yield, letting other processes run.
+///
+/// The next process to run is preferably another process with the same priority, so that the
+/// current process is placed after them.
+pub fn yield_current() {
+	if let Some(proc_mutex) = current_process() {
+		let mut proc = proc_mutex.lock();
+		proc.rusage.ru_nvcsw = proc.rusage.ru_nvcsw.saturating_add(1);
+		YIELD_PRIORITY.store(proc.priority, Relaxed);
+	}
+	end_tick();
+}
+
+/// Returns a new reference to the object pointed to by `slot`, if not null.
+///
+/// `slot` must be either [`CURRENT_PROC`] or [`CURRENT_IO`].
+fn load_current<T>(slot: &AtomicPtr<T>) -> Option<Arc<T>> {
+	// Prevent the scheduler from switching context, which could drop the object
+	let int = interrupt::is_enabled();
+	cli();
+	let ptr = slot.load(Acquire);
+	// Safety: the object is kept alive by the scheduler while it is current
+	let obj = (!ptr.is_null()).then(|| unsafe { Arc::clone_from_ptr(ptr) });
+	if int {
+		interrupt::sti();
+	}
+	obj
+}
+
+/// Returns the process currently running on the CPU, if any.
+///
+/// Contrary to [`Scheduler::get_current_process`], this function does not lock the scheduler.
+pub fn current_process() -> Option<Arc<IntMutex<Process>>> {
+	load_current(&CURRENT_PROC)
+}
+
+/// Returns the I/O accounting structure of the process currently running on the CPU, if any.
+///
+/// Like [`current_process`], this function does not lock the scheduler.
+pub fn current_io() -> Option<Arc<IOUsage>> {
+	load_current(&CURRENT_IO)
+}
+
 /// Ends the current tick on the current CPU.
 ///
 /// Since this function triggers an interruption, the caller must ensure that no critical mutex is
 /// locked, that could be used in the interruption handler. Otherwise, a deadlock could occur.
+///
+/// If the current process is the only one running, it would be resumed right away. In this case,
+/// the function returns without locking the scheduler nor saving and restoring the FPU state.
 #[inline]
 pub fn end_tick() {
+	if RUNNING_PROCS.load(Relaxed) <= 1 {
+		let running =
+			current_process().is_some_and(|proc| proc.lock().get_state() == State::Running);
+		if running {
+			YIELD_PRIORITY.store(usize::MAX, Relaxed);
+			return;
+		}
+	}
 	unsafe {
 		asm!("int 0x20");
 	}
 }
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::selftest;
+
+	#[test_case]
+	fn current_lookup() {
+		let slot = AtomicPtr::new(null_mut());
+		assert!(load_current::<u32>(&slot).is_none());
+		let obj = Arc::new(42u32).unwrap();
+		slot.store(Arc::as_ptr(&obj) as *mut _, Release);
+		let lockless = selftest::bench("current lookup", 10000, || {
+			let cur = load_current(&slot).unwrap();
+			assert_eq!(*cur, 42);
+		});
+		assert_eq!(Arc::strong_count(&obj), 1);
+		// Compare with a lookup behind a lock, like the scheduler's
+		let locked_slot = IntMutex::new(Some(obj.clone()));
+		let locked = selftest::bench("locked current lookup", 10000, || {
+			let cur = locked_slot.lock().clone().unwrap();
+			assert_eq!(*cur, 42);
+		});
+		assert!(lockless <= locked);
+	}
+}
@@ -25,7 +25,6 @@ use crate::{
 	file::perm::Uid,
 	memory::VirtAddr,
 	process::{pid::Pid, regs::Regs, signal::signal_trampoline::signal_trampoline},
-	time::unit::ClockIdT,
 };
 use core::{
 	ffi::{c_int, c_void},
@@ -51,6 +50,47 @@ pub const SA_RESTART: i32 = 0x10000000;
 /// executed.
 pub const SA_NODEFER: i32 = 0x40000000;
 
+/// Signal code: Sent by a user process, with `kill`.
+pub const SI_USER: i32 = 0;
+/// Signal code: Sent by the kernel.
+pub const SI_KERNEL: i32 = 0x80;
+
+/// `SIGCHLD` code: The child has exited.
+pub const CLD_EXITED: i32 = 1;
+/// `SIGCHLD` code: The child was killed by a signal.
+pub const CLD_KILLED: i32 = 2;
+/// `SIGCHLD` code: The child has been stopped.
+pub const CLD_STOPPED: i32 = 5;
+/// `SIGCHLD` code: The stopped child has been resumed.
+pub const CLD_CONTINUED: i32 = 6;
+
+/// `SIGILL` code: Illegal operand.
+pub const ILL_ILLOPN: i32 = 2;
+
+/// `SIGFPE` code: Integer divide by zero.
+pub const FPE_INTDIV: i32 = 1;
+/// `SIGFPE` code: Floating-point divide by zero.
+pub const FPE_FLTDIV: i32 = 3;
+/// `SIGFPE` code: Floating-point overflow.
+pub const FPE_FLTOVF: i32 = 4;
+/// `SIGFPE` code: Floating-point underflow.
+pub const FPE_FLTUND: i32 = 5;
+/// `SIGFPE` code: Floating-point inexact result.
+pub const FPE_FLTRES: i32 = 6;
+/// `SIGFPE` code: Invalid floating-point operation.
+pub const FPE_FLTINV: i32 = 7;
+
+/// `SIGSEGV` code: Address not mapped to object.
+pub const SEGV_MAPERR: i32 = 1;
+/// `SIGSEGV` code: Invalid permissions for mapped object.
+pub const SEGV_ACCERR: i32 = 2;
+
+/// `SIGBUS` code: Invalid address alignment.
+pub const BUS_ADRALN: i32 = 1;
+
+/// `SIGTRAP` code: Process trace trap.
+pub const TRAP_TRACE: i32 = 2;
+
 /// Notify method: generate a signal
 pub const SIGEV_SIGNAL: c_int = 0;
 /// Notify method: do nothing
@@ -89,8 +129,8 @@ impl SignalAction {
 					pid = process.get_pid(),
 					signal = sig.get_id()
 				);
-				process.set_state(State::Zombie);
-				process.set_waitable(sig.get_id() as _);
+				// A fatal signal terminates the whole thread group
+				process.exit_group(0, sig.get_id());
 			}
 			SignalAction::Ignore => {}
 			SignalAction::Stop => {
@@ -112,81 +152,113 @@ impl SignalAction {
 /// A signal handler value.
 pub type SigVal = usize;
 
-// FIXME: fields are incorrect (check musl source)
-/// Signal information.
+/// Information attached to a pending signal, used to fill the [`SigInfo`] structure passed to the
+/// signal handler.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SignalInfo {
+	/// The signal code, giving the cause of the signal.
+	pub code: i32,
+	/// The ID of the sending process.
+	pub pid: Pid,
+	/// The real user ID of the sending process.
+	pub uid: Uid,
+	/// For faults, the memory location which caused the fault.
+	pub addr: usize,
+}
+
+/// The number of 32 bits words in the signal-specific part of [`SigInfo`].
+const SI_FIELDS_COUNT: usize = 29;
+
+/// Signal information, passed to handlers installed with [`SA_SIGINFO`].
+///
+/// The layout of the fields following `si_code` depends on the signal and on `si_code`.
 #[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
 pub struct SigInfo {
 	/// Signal number.
-	si_signo: i32,
+	pub si_signo: i32,
 	/// An errno value.
-	si_errno: i32,
+	pub si_errno: i32,
 	/// Signal code.
-	si_code: i32,
-	/// Trap number that caused hardware-generated signal.
-	si_trapno: i32,
-	/// Sending process ID.
-	si_pid: Pid,
-	/// Real user ID of sending process.
-	si_uid: Uid,
-	/// Exit value or signal.
-	si_status: i32,
-	/// User time consumed.
-	si_utime: ClockIdT,
-	/// System time consumed.
-	si_stime: ClockIdT,
-	/// Signal value
-	si_value: SigVal,
-	/// POSIX.1b signal.
-	si_int: i32,
-	/// POSIX.1b signal.
-	si_ptr: *mut c_void,
-	/// Timer overrun count.
-	si_overrun: i32,
-	/// Timer ID.
-	si_timerid: i32,
-	/// Memory location which caused fault.
-	si_addr: *mut c_void,
-	/// Band event.
-	si_band: i32, // FIXME long (64bits?)
-	/// File descriptor.
-	si_fd: i32,
-	/// Least significant bit of address.
-	si_addr_lsb: i16,
-	/// Lower bound when address violation.
-	si_lower: *mut c_void,
-	/// Upper bound when address violation.
-	si_upper: *mut c_void,
-	/// Protection key on PTE that caused fault.
-	si_pkey: i32,
-	/// Address of system call instruction.
-	si_call_addr: *mut c_void,
-	/// Number of attempted system call.
-	si_syscall: i32,
-	/// Architecture of attempted system call.
-	si_arch: u32,
+	pub si_code: i32,
+	/// Signal-specific fields.
+	fields: [u32; SI_FIELDS_COUNT],
+}
+
+impl SigInfo {
+	/// Creates the structure for the signal `sig` with the given information.
+	pub fn new(sig: Signal, info: &SignalInfo) -> Self {
+		let mut fields = [0; SI_FIELDS_COUNT];
+		let fault = matches!(
+			sig,
+			Signal::SIGILL | Signal::SIGFPE | Signal::SIGSEGV | Signal::SIGBUS | Signal::SIGTRAP
+		);
+		if fault && info.code > SI_USER && info.code < SI_KERNEL {
+			// `si_addr`
+			fields[0] = info.addr as _;
+		} else {
+			// `si_pid` and `si_uid`
+			fields[0] = info.pid as _;
+			fields[1] = info.uid as _;
+		}
+		Self {
+			si_signo: sig.get_id() as _,
+			si_errno: 0,
+			si_code: info.code,
+			fields,
+		}
+	}
+
+	/// Creates the structure reporting a change of state of a child process.
+	///
+	/// Arguments:
+	/// - `code` is the `CLD_*` code of the change
+	/// - `pid` is the PID of the child
+	/// - `uid` is the real user ID of the child
+	/// - `status` is the exit status of the child, or the signal that caused the change
+	pub fn child(code: i32, pid: Pid, uid: Uid, status: i32) -> Self {
+		let mut fields = [0; SI_FIELDS_COUNT];
+		// `si_pid`, `si_uid` and `si_status`
+		fields[0] = pid as _;
+		fields[1] = uid as _;
+		fields[2] = status as _;
+		Self {
+			si_signo: Signal::SIGCHLD.get_id() as _,
+			si_errno: 0,
+			si_code: code,
+			fields,
+		}
+	}
 }
 
 /// A bits signal mask.
+///
+/// As in userspace, the signal `n` is represented by the bit `n - 1`. Signal `0` is never set.
 #[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
 pub struct SigSet(pub u64);
 
 impl SigSet {
-	/// Tells whether the `n`th bit is set.
+	/// Tells whether the signal `n` is set.
 	pub fn is_set(&self, n: usize) -> bool {
-		self.0 & (1 << n) != 0
+		n > 0 && self.0 & (1 << (n - 1)) != 0
 	}
 
-	/// Sets the `n`th bit.
+	/// Sets the signal `n`.
 	pub fn set(&mut self, n: usize) {
-		self.0 |= (1 << n) as u64;
+		if n > 0 {
+			self.0 |= 1 << (n - 1);
+		}
 	}
 
-	/// Sets the `n`th bit.
+	/// Clears the signal `n`.
 	pub fn clear(&mut self, n: usize) {
-		self.0 &= !((1 << n) as u64);
+		if n > 0 {
+			self.0 &= !(1 << (n - 1));
+		}
 	}
 
-	/// Returns an iterator over the bitset's values
+	/// Returns an iterator telling, for each signal number starting from `0`, whether the signal
+	/// is set.
 	pub fn iter(&self) -> impl Iterator<Item = bool> + '_ {
 		(0..64).map(|n| self.is_set(n))
 	}
@@ -348,13 +420,13 @@ impl SignalHandler {
 		}
 		match self {
 			Self::Ignore => {}
-			// TODO handle SA_SIGINFO
 			Self::Handler(action) if signal.can_catch() => {
 				// Prepare the signal handler stack
 				// TODO Handle the case where an alternate stack is specified (sigaltstack + flag
 				// SA_ONSTACK)
 				let stack_addr = VirtAddr(process.regs.esp) - REDZONE_SIZE;
-				let signal_data_size = size_of::<UContext>() + size_of::<usize>() * 4;
+				let signal_data_size =
+					size_of::<UContext>() + size_of::<SigInfo>() + size_of::<usize>() * 5;
 				let signal_esp = stack_addr - signal_data_size;
 				{
 					let mem_space = process.get_mem_space().unwrap();
@@ -371,13 +443,20 @@ impl SignalHandler {
 					uc_stack: stack_addr.as_ptr(),
 					uc_mcontext: process.regs.clone(),
 				};
+				let info =
+					SigInfo::new(signal, &process.sigpending_info[signal.get_id() as usize]);
 				unsafe {
 					// Write `ctx`
 					let ctx_addr = stack_addr - size_of::<UContext>();
 					ptr::write_volatile(ctx_addr.as_ptr(), ctx);
-					let args = slice::from_raw_parts_mut(signal_esp.as_ptr::<usize>(), 4);
+					// Write `info`
+					let info_addr = ctx_addr - size_of::<SigInfo>();
+					ptr::write_volatile(info_addr.as_ptr(), info);
+					let args = slice::from_raw_parts_mut(signal_esp.as_ptr::<usize>(), 5);
 					// Pointer to  `ctx`
-					args[3] = ctx_addr.0;
+					args[4] = ctx_addr.0;
+					// Pointer to `info`
+					args[3] = info_addr.0;
 					// Signal number
 					args[2] = signal.get_id() as usize;
 					// Pointer to the handler
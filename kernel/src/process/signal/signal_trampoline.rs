@@ -22,8 +22,11 @@
 //!
 //! When the signal handler returns, the process returns directly to execution.
 
-use crate::{process::signal::UContext, syscall::SIGRETURN_ID};
-use core::arch::asm;
+use crate::{
+	process::signal::{SigInfo, UContext},
+	syscall::SIGRETURN_ID,
+};
+use core::{arch::asm, ffi::c_void};
 
 /// The signal handler trampoline.
 ///
@@ -36,15 +39,23 @@ use core::arch::asm;
 /// Arguments:
 /// - `handler` is a pointer to the handler function for the signal.
 /// - `sig` is the signal number.
+/// - `info` is the information about the signal.
 /// - `ctx` is the context to restore after the handler finishes.
+///
+/// The handler is always called with the three arguments of a [`SA_SIGINFO`] handler. Since the
+/// caller is responsible for cleaning up the stack, this is harmless for handlers taking only the
+/// signal number.
+///
+/// [`SA_SIGINFO`]: super::SA_SIGINFO
 #[link_section = ".user"]
 pub unsafe extern "C" fn signal_trampoline(
-	handler: unsafe extern "C" fn(i32),
+	handler: unsafe extern "C" fn(i32, *mut SigInfo, *mut c_void),
 	sig: usize,
+	info: *mut SigInfo,
 	ctx: &mut UContext,
 ) -> ! {
 	// Call the signal handler
-	handler(sig as _);
+	handler(sig as _, info, ctx as *mut UContext as *mut c_void);
 	// Call `sigreturn` to end signal handling
 	asm!(
 		"mov esp, {}",
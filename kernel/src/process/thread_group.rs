@@ -0,0 +1,92 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! A thread group is the set of threads sharing the same process ID, as seen from userspace.
+//!
+//! The ID of a thread group is the ID of the thread which created it, called the *leader*.
+//! When a thread of the group terminates the whole group, for example with `exit_group` or a
+//! fatal signal, every other thread is terminated as well.
+
+use super::ExitStatus;
+use crate::process::pid::Pid;
+use utils::{collections::vec::Vec, errno::AllocResult, vec};
+
+/// A thread group, shared between all the threads it contains.
+#[derive(Debug)]
+pub struct ThreadGroup {
+	/// The ID of the group, which is the ID of its leader.
+	tgid: Pid,
+	/// The IDs of the threads of the group that have not terminated yet.
+	threads: Vec<Pid>,
+	/// If the whole group is being terminated, the exit status and terminating signal of the
+	/// last termination.
+	exit_status: Option<(ExitStatus, u8)>,
+}
+
+impl ThreadGroup {
+	/// Creates a new group with the given leader.
+	pub fn new(leader: Pid) -> AllocResult<Self> {
+		Ok(Self {
+			tgid: leader,
+			threads: vec![leader]?,
+			exit_status: None,
+		})
+	}
+
+	/// Returns the ID of the group.
+	#[inline]
+	pub fn get_tgid(&self) -> Pid {
+		self.tgid
+	}
+
+	/// Returns the IDs of the threads of the group that have not terminated yet.
+	#[inline]
+	pub fn get_threads(&self) -> &[Pid] {
+		&self.threads
+	}
+
+	/// Adds the thread with the given ID to the group.
+	pub fn add(&mut self, tid: Pid) -> AllocResult<()> {
+		self.threads.push(tid)
+	}
+
+	/// Removes the thread with the given ID from the group.
+	pub fn remove(&mut self, tid: Pid) {
+		self.threads.retain(|t| *t != tid);
+	}
+
+	/// Tells whether the whole group is being terminated.
+	#[inline]
+	pub fn is_exiting(&self) -> bool {
+		self.exit_status.is_some()
+	}
+
+	/// Returns the exit status and terminating signal of the last termination of the whole
+	/// group, if any.
+	#[inline]
+	pub fn get_exit_status(&self) -> Option<(ExitStatus, u8)> {
+		self.exit_status
+	}
+
+	/// Marks the whole group as being terminated with the given exit `status` and terminating
+	/// signal `termsig`.
+	#[inline]
+	pub fn set_exiting(&mut self, status: ExitStatus, termsig: u8) {
+		self.exit_status = Some((status, termsig));
+	}
+}
@@ -25,7 +25,7 @@
 //! make them pass even though they should not. Even if this scenario is unlikely, this remains a
 //! concern since the kernel has to be as reliable as possible.
 
-use crate::power;
+use crate::{cpu, power};
 use core::{
 	any::type_name,
 	sync::{atomic, atomic::AtomicBool},
@@ -92,6 +92,21 @@ pub fn runner(tests: &[&dyn Testable]) {
 	power::halt();
 }
 
+/// Runs `f` `iterations` times, then prints and returns the average number of CPU cycles per
+/// iteration.
+///
+/// This is meant to measure the cost of critical paths, such as context switching, so that tests
+/// can compare them against alternatives.
+pub fn bench<F: FnMut()>(name: &str, iterations: u32, mut f: F) -> u64 {
+	let start = cpu::rdtsc();
+	for _ in 0..iterations {
+		f();
+	}
+	let cycles = cpu::rdtsc().wrapping_sub(start) / iterations as u64;
+	crate::print!("({name}: {cycles} cycles) ");
+	cycles
+}
+
 /// Tells whether selftesting is running.
 pub fn is_running() -> bool {
 	RUNNING.load(atomic::Ordering::Relaxed)
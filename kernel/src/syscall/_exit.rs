@@ -20,26 +20,34 @@
 //! status code.
 
 use super::Args;
-use crate::process::{scheduler, Process};
-use core::ffi::c_int;
+use crate::process::{
+	futex, futex::FUTEX_BITSET_MATCH_ANY, mem_space::copy::SyscallPtr, scheduler, Process,
+};
+use core::{ffi::c_int, mem, ptr::NonNull};
 use utils::{errno::EResult, lock::IntMutexGuard};
 
 /// Exits the current process.
 ///
 /// Arguments:
 /// - `status` is the exit status.
-/// - `thread_group`: if `true`, the function exits the whole process group.
-/// - `proc` is the current process.
+/// - `thread_group`: if `true`, the function exits the whole thread group.
 pub fn do_exit(status: u32, thread_group: bool) -> ! {
 	{
 		let proc_mutex = Process::current();
+		// Notify threads waiting for the exit (for example, with `pthread_join`). The process
+		// must not be locked since writing to userspace may trigger a page fault
+		let clear_child_tid =
+			mem::replace(&mut proc_mutex.lock().clear_child_tid, SyscallPtr(None));
+		// Errors are ignored since the process is exiting anyway
+		if clear_child_tid.copy_to_user(0).is_ok() {
+			let addr = SyscallPtr(clear_child_tid.0.map(NonNull::cast));
+			let _ = futex::wake(&addr, 1, FUTEX_BITSET_MATCH_ANY);
+		}
 		let mut proc = proc_mutex.lock();
-		proc.exit(status);
-		let _pid = proc.get_pid();
-		let _tid = proc.tid;
 		if thread_group {
-			// TODO Iterate on every process of thread group `tid`, except the
-			// process with pid `pid`
+			proc.exit_group(status, 0);
+		} else {
+			proc.exit(status);
 		}
 	}
 	scheduler::end_tick();
@@ -38,11 +38,11 @@ use utils::{
 };
 
 /// Sets the offset from the given value.
-const SEEK_SET: u32 = 0;
+pub const SEEK_SET: u32 = 0;
 /// Sets the offset relative to the current offset.
-const SEEK_CUR: u32 = 1;
+pub const SEEK_CUR: u32 = 1;
 /// Sets the offset relative to the end of the file.
-const SEEK_END: u32 = 2;
+pub const SEEK_END: u32 = 2;
 
 pub fn _llseek(
 	Args((fd, offset_high, offset_low, result, whence)): Args<(
@@ -56,6 +56,7 @@ pub fn _llseek(
 ) -> EResult<usize> {
 	let fds = fds_mutex.lock();
 	let file = fds.get_fd(fd as _)?.get_file();
+	file.check_seekable()?;
 	// Compute the offset
 	let off = ((offset_high as u64) << 32) | (offset_low as u64);
 	let base = match whence {
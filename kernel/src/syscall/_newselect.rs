@@ -16,21 +16,15 @@
  * Maestro. If not, see <https://www.gnu.org/licenses/>.
  */
 
-//! `_newselect` is similar to `select`.
+//! `_newselect` is similar to `select`, with its arguments passed directly.
 
-use super::select::{do_select, FDSet};
+use super::select::{do_select_timeout, timeval_valid, FDSet};
 use crate::{
-	file::fd::FileDescriptorTable,
-	process::mem_space::{copy::SyscallPtr, MemSpace},
-	syscall::Args,
-	time::unit::Timeval,
+	file::fd::FileDescriptorTable, process::mem_space::copy::SyscallPtr, syscall::Args,
+	time::unit::Timeval32,
 };
 use core::ffi::c_int;
-use utils::{
-	errno::EResult,
-	lock::{IntMutex, Mutex},
-	ptr::arc::Arc,
-};
+use utils::{errno::EResult, lock::Mutex, ptr::arc::Arc};
 
 #[allow(clippy::type_complexity)]
 pub fn _newselect(
@@ -39,9 +33,18 @@ pub fn _newselect(
 		SyscallPtr<FDSet>,
 		SyscallPtr<FDSet>,
 		SyscallPtr<FDSet>,
-		SyscallPtr<Timeval>,
+		SyscallPtr<Timeval32>,
 	)>,
 	fds: Arc<Mutex<FileDescriptorTable>>,
 ) -> EResult<usize> {
-	do_select(fds, nfds as _, readfds, writefds, exceptfds, timeout, None)
+	do_select_timeout(
+		nfds,
+		readfds,
+		writefds,
+		exceptfds,
+		timeout,
+		timeval_valid,
+		None,
+		&fds,
+	)
 }
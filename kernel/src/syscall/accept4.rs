@@ -0,0 +1,68 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The `accept4` system call accepts a connection on a listening socket.
+
+use crate::{
+	file,
+	file::{
+		fd::{FileDescriptorTable, FD_CLOEXEC},
+		socket::{Socket, SOCK_CLOEXEC, SOCK_NONBLOCK},
+		File, O_NONBLOCK,
+	},
+	process::mem_space::copy::{SyscallPtr, SyscallSlice},
+	syscall::Args,
+};
+use core::{cmp::min, ffi::c_int};
+use utils::{errno, errno::EResult, lock::Mutex, ptr::arc::Arc};
+
+#[allow(clippy::type_complexity)]
+pub fn accept4(
+	Args((sockfd, addr, addrlen, flags)): Args<(
+		c_int,
+		SyscallSlice<u8>,
+		SyscallPtr<isize>,
+		c_int,
+	)>,
+	fds: Arc<Mutex<FileDescriptorTable>>,
+) -> EResult<usize> {
+	if flags & !(SOCK_NONBLOCK | SOCK_CLOEXEC) != 0 {
+		return Err(errno!(EINVAL));
+	}
+	let file = fds.lock().get_fd(sockfd)?.get_file().clone();
+	let sock: &Socket = file.get_buffer().ok_or_else(|| errno!(ENOTSOCK))?;
+	let addrlen_val = addrlen.copy_from_user()?;
+	if matches!(addrlen_val, Some(..0)) {
+		return Err(errno!(EINVAL));
+	}
+	let conn = sock.accept(file.get_flags() & O_NONBLOCK != 0)?;
+	if let Some(addrlen_val) = addrlen_val {
+		let name = conn.get_peername()?;
+		let len = min(name.len(), addrlen_val as usize);
+		addr.copy_to_user(0, &name[..len])?;
+		addrlen.copy_to_user(name.len() as _)?;
+	}
+	let file = File::open_floating(conn, file::O_RDWR | (flags & SOCK_NONBLOCK))?;
+	let fd_flags = if flags & SOCK_CLOEXEC != 0 {
+		FD_CLOEXEC
+	} else {
+		0
+	};
+	let (fd, _) = fds.lock().create_fd(fd_flags, file)?;
+	Ok(fd as _)
+}
@@ -34,7 +34,6 @@ use crate::{
 };
 use core::ffi::c_int;
 use utils::{
-	collections::path::PathBuf,
 	errno,
 	errno::{EResult, Errno},
 	lock::Mutex,
@@ -73,10 +72,7 @@ pub fn do_access(
 	let ap = rs.access_profile;
 	let file = {
 		let fds = fds_mutex.lock();
-		let pathname = pathname
-			.copy_from_user()?
-			.map(PathBuf::try_from)
-			.transpose()?;
+		let pathname = pathname.copy_path_from_user()?;
 		let Resolved::Found(file) = at::get_file(
 			&fds,
 			rs,
@@ -19,12 +19,24 @@
 //! The `bind` system call binds a name to a socket.
 
 use crate::{
-	file::{fd::FileDescriptorTable, socket::Socket},
+	file::{
+		fd::FileDescriptorTable,
+		socket::Socket,
+		vfs,
+		vfs::{ResolutionSettings, Resolved},
+		FileType, Stat,
+	},
+	net::{sockaddr, SocketDomain},
 	process::{mem_space::copy::SyscallSlice, Process},
-	syscall::Args,
+	syscall::{Args, Umask},
+	time::{
+		clock::{current_time, CLOCK_REALTIME},
+		unit::TimestampScale,
+	},
 };
 use core::{any::Any, ffi::c_int};
 use utils::{
+	collections::path::Path,
 	errno,
 	errno::{EResult, Errno},
 	lock::Mutex,
@@ -33,6 +45,8 @@ use utils::{
 
 pub fn bind(
 	Args((sockfd, addr, addrlen)): Args<(c_int, SyscallSlice<u8>, isize)>,
+	umask: Umask,
+	rs: ResolutionSettings,
 	fds: Arc<Mutex<FileDescriptorTable>>,
 ) -> EResult<usize> {
 	// Validation
@@ -45,6 +59,39 @@ pub fn bind(
 	let addr = addr
 		.copy_from_user(..(addrlen as usize))?
 		.ok_or_else(|| errno!(EFAULT))?;
-	sock.bind(&addr)?;
+	if sock.desc().domain != SocketDomain::AfUnix {
+		sock.bind(&addr)?;
+		return Ok(0);
+	}
+	// Create the socket's node
+	let path = Path::new(sockaddr::unix_path(&addr)?)?;
+	sock.bind_node(&file, &addr, || {
+		let rs = ResolutionSettings {
+			create: true,
+			follow_link: false,
+			..rs
+		};
+		let Resolved::Creatable {
+			parent,
+			name,
+		} = vfs::resolve_path(path, &rs)?
+		else {
+			return Err(errno!(EADDRINUSE));
+		};
+		let ts = current_time(CLOCK_REALTIME, TimestampScale::Second)?;
+		let ent = vfs::create_file(
+			parent,
+			name,
+			&rs.access_profile,
+			Stat {
+				mode: FileType::Socket.to_mode() | (0o777 & !umask.0),
+				ctime: ts,
+				mtime: ts,
+				atime: ts,
+				..Default::default()
+			},
+		)?;
+		Ok(ent.node().location.clone())
+	})?;
 	Ok(0)
 }
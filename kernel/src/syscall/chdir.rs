@@ -25,7 +25,6 @@ use crate::{
 	syscall::Args,
 };
 use utils::{
-	collections::path::PathBuf,
 	errno,
 	errno::{EResult, Errno},
 	lock::IntMutex,
@@ -37,8 +36,7 @@ pub fn chdir(
 	proc: Arc<IntMutex<Process>>,
 	rs: ResolutionSettings,
 ) -> EResult<usize> {
-	let path = path.copy_from_user()?.ok_or_else(|| errno!(EFAULT))?;
-	let path = PathBuf::try_from(path)?;
+	let path = path.copy_path_from_user()?.ok_or_else(|| errno!(EFAULT))?;
 	// Get directory
 	let dir = vfs::get_file_from_path(&path, &rs)?;
 	// Validation
@@ -46,10 +44,10 @@ pub fn chdir(
 	if stat.get_type() != Some(FileType::Directory) {
 		return Err(errno!(ENOTDIR));
 	}
-	if !rs.access_profile.can_list_directory(&stat) {
+	if !rs.access_profile.can_search_directory(&stat) {
 		return Err(errno!(EACCES));
 	}
 	// Set new cwd
-	proc.lock().cwd = dir;
+	proc.lock().fs.lock().cwd = dir;
 	Ok(0)
 }
@@ -20,13 +20,12 @@
 
 use crate::{
 	file,
-	file::{fs::StatSet, vfs, vfs::ResolutionSettings},
+	file::{vfs, vfs::ResolutionSettings},
 	process::{mem_space::copy::SyscallString, Process},
 	syscall::Args,
 };
 use core::ffi::c_int;
 use utils::{
-	collections::path::PathBuf,
 	errno,
 	errno::{EResult, Errno},
 };
@@ -35,21 +34,11 @@ pub fn chmod(
 	Args((pathname, mode)): Args<(SyscallString, file::Mode)>,
 	rs: ResolutionSettings,
 ) -> EResult<usize> {
-	let path = pathname.copy_from_user()?.ok_or_else(|| errno!(EFAULT))?;
-	let path = PathBuf::try_from(path)?;
+	let path = pathname
+		.copy_path_from_user()?
+		.ok_or_else(|| errno!(EFAULT))?;
 	// Get file
 	let file = vfs::get_file_from_path(&path, &rs)?;
-	// Check permissions
-	let stat = file.stat()?;
-	if !rs.access_profile.can_set_file_permissions(&stat) {
-		return Err(errno!(EPERM));
-	}
-	file.node().ops.set_stat(
-		&file.node().location,
-		StatSet {
-			mode: Some(mode & 0o777),
-			..Default::default()
-		},
-	)?;
+	vfs::set_mode(&file, mode, &rs.access_profile)?;
 	Ok(0)
 }
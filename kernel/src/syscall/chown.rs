@@ -19,17 +19,50 @@
 //! The `chown` system call changes the owner of a file.
 
 use crate::{
-	file::{fs::StatSet, vfs, vfs::ResolutionSettings},
-	process::{mem_space::copy::SyscallString, Process},
+	file::{
+		perm::{AccessProfile, Uid},
+		vfs,
+		vfs::ResolutionSettings,
+	},
+	process::mem_space::copy::SyscallString,
 	syscall::Args,
 };
 use core::ffi::c_int;
 use utils::{
-	collections::path::PathBuf,
 	errno,
 	errno::{EResult, Errno},
 };
 
+/// Converts an ID passed to the `chown` family of system calls.
+///
+/// If `id` is `-1`, the ID is to be left unchanged and the function returns `None`.
+fn parse_id(id: c_int) -> EResult<Option<Uid>> {
+	match id {
+		-1 => Ok(None),
+		0..=0xffff => Ok(Some(id as _)),
+		_ => Err(errno!(EINVAL)),
+	}
+}
+
+/// Changes the owner of the file `file`.
+///
+/// Arguments:
+/// - `file` is the file
+/// - `owner` is the new owner user ID, or `-1` to leave it unchanged
+/// - `group` is the new owner group ID, or `-1` to leave it unchanged
+/// - `ap` is the access profile to check permissions
+pub fn chown_entry(
+	file: &vfs::Entry,
+	owner: c_int,
+	group: c_int,
+	ap: &AccessProfile,
+) -> EResult<usize> {
+	let owner = parse_id(owner)?;
+	let group = parse_id(group)?;
+	vfs::set_owner(file, owner, group, ap)?;
+	Ok(0)
+}
+
 /// Performs the `chown` syscall.
 pub fn do_chown(
 	pathname: SyscallString,
@@ -37,27 +70,11 @@ pub fn do_chown(
 	group: c_int,
 	rs: ResolutionSettings,
 ) -> EResult<usize> {
-	// Validation
-	if !(-1..=u16::MAX as c_int).contains(&owner) || !(-1..=u16::MAX as c_int).contains(&group) {
-		return Err(errno!(EINVAL));
-	}
-	let path = pathname.copy_from_user()?.ok_or_else(|| errno!(EFAULT))?;
-	let path = PathBuf::try_from(path)?;
-	// Get file
+	let path = pathname
+		.copy_path_from_user()?
+		.ok_or_else(|| errno!(EFAULT))?;
 	let file = vfs::get_file_from_path(&path, &rs)?;
-	// TODO allow changing group to any group whose owner is member
-	if !rs.access_profile.is_privileged() {
-		return Err(errno!(EPERM));
-	}
-	file.node().ops.set_stat(
-		&file.node().location,
-		StatSet {
-			uid: (owner > -1).then_some(owner as _),
-			gid: (group > -1).then_some(group as _),
-			..Default::default()
-		},
-	)?;
-	Ok(0)
+	chown_entry(&file, owner, group, &rs.access_profile)
 }
 
 pub fn chown(
@@ -29,7 +29,6 @@ use crate::{
 	syscall::Args,
 };
 use utils::{
-	collections::path::PathBuf,
 	errno,
 	errno::{EResult, Errno},
 	lock::IntMutex,
@@ -45,8 +44,7 @@ pub fn chroot(
 	if !rs.access_profile.is_privileged() {
 		return Err(errno!(EPERM));
 	}
-	let path = path.copy_from_user()?.ok_or(errno!(EFAULT))?;
-	let path = PathBuf::try_from(path)?;
+	let path = path.copy_path_from_user()?.ok_or(errno!(EFAULT))?;
 	let rs = ResolutionSettings {
 		root: vfs::root(),
 		..rs
@@ -56,6 +54,6 @@ pub fn chroot(
 	if file.get_type()? != FileType::Directory {
 		return Err(errno!(ENOTDIR));
 	}
-	proc.lock().chroot = file;
+	proc.lock().fs.lock().chroot = file;
 	Ok(0)
 }
@@ -0,0 +1,37 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The `clock_getres` syscall returns the resolution of the given clock.
+
+use crate::{
+	process::mem_space::copy::SyscallPtr,
+	syscall::Args,
+	time::{
+		clock,
+		unit::{ClockIdT, TimeUnit, Timespec32},
+	},
+};
+use utils::errno::EResult;
+
+pub fn clock_getres(
+	Args((clockid, res)): Args<(ClockIdT, SyscallPtr<Timespec32>)>,
+) -> EResult<usize> {
+	let resolution = clock::resolution(clockid)?;
+	res.copy_to_user(Timespec32::from_nano(resolution))?;
+	Ok(0)
+}
@@ -22,19 +22,24 @@ use crate::{
 	process::{mem_space::copy::SyscallPtr, Process},
 	syscall::Args,
 	time::{
-		clock,
-		unit::{ClockIdT, Timespec},
+		clock, namespace,
+		unit::{ClockIdT, TimeUnit, Timespec, TimestampScale},
 	},
 };
 use utils::{
 	errno,
 	errno::{EResult, Errno},
+	lock::IntMutex,
+	ptr::arc::Arc,
 };
 
 pub fn clock_gettime64(
 	Args((clockid, tp)): Args<(ClockIdT, SyscallPtr<Timespec>)>,
+	proc: Arc<IntMutex<Process>>,
 ) -> EResult<usize> {
-	let curr_time = clock::current_time_struct::<Timespec>(clockid)?;
-	tp.copy_to_user(curr_time)?;
+	let ns = proc.lock().time_ns.clone();
+	let ts = clock::current_time(clockid, TimestampScale::Nanosecond)?;
+	let ts = namespace::to_local(ns.as_deref(), clockid, ts);
+	tp.copy_to_user(Timespec::from_nano(ts))?;
 	Ok(0)
 }
@@ -19,52 +19,82 @@
 //! The `clone` system call creates a child process.
 
 use crate::{
+	file::{
+		fd::{FileDescriptorTable, FD_CLOEXEC},
+		pidfd::PidFd,
+		File, O_RDWR,
+	},
+	memory::VirtAddr,
 	process::{
-		mem_space::copy::SyscallPtr, regs::Regs, scheduler, user_desc::UserDesc, ForkOptions,
-		Process,
+		mem_space::{copy::SyscallPtr, MAPPING_FLAG_WRITE},
+		pid,
+		pid::Pid,
+		regs::Regs,
+		scheduler,
+		signal::Signal,
+		user_desc::UserDesc,
+		ForkOptions, Process,
 	},
-	syscall::{Args, FromSyscallArg},
+	syscall::{set_thread_area::get_entry, Args, FromSyscallArg},
+};
+use core::{
+	ffi::{c_int, c_ulong, c_void},
+	mem::size_of,
+};
+use utils::{
+	collections::vec::Vec,
+	errno,
+	errno::EResult,
+	lock::{IntMutex, Mutex},
+	ptr::arc::Arc,
 };
-use core::ffi::{c_int, c_ulong, c_void};
-use utils::{errno::EResult, lock::IntMutex, ptr::arc::Arc};
 
+/// Mask of the flags specifying the signal sent to the parent when the child terminates.
+pub const CSIGNAL: c_ulong = 0xff;
 /// TODO doc
 const CLONE_IO: c_ulong = -0x80000000 as _;
+/// If specified, the child is created in a new time namespace.
+///
+/// Since this value overlaps with [`CSIGNAL`], it cannot be used with `clone`.
+pub const CLONE_NEWTIME: c_ulong = 0x80;
 /// If specified, the parent and child processes share the same memory space.
 const CLONE_VM: c_ulong = 0x100;
-/// TODO doc
-const CLONE_FS: c_ulong = 0x200;
+/// If specified, the parent and child processes share the same filesystem information (working
+/// directory, root directory and umask).
+pub const CLONE_FS: c_ulong = 0x200;
 /// If specified, the parent and child processes share the same file descriptors
 /// table.
-const CLONE_FILES: c_ulong = 0x400;
+pub const CLONE_FILES: c_ulong = 0x400;
 /// If specified, the parent and child processes share the same signal handlers
 /// table.
 const CLONE_SIGHAND: c_ulong = 0x800;
-/// TODO doc
-const CLONE_PIDFD: c_ulong = 0x1000;
+/// If specified, a PID file descriptor referring to the child is created in the parent.
+pub const CLONE_PIDFD: c_ulong = 0x1000;
 /// TODO doc
 const CLONE_PTRACE: c_ulong = 0x2000;
-/// TODO doc
+/// If specified, the parent is suspended until the child terminates or executes a program.
 const CLONE_VFORK: c_ulong = 0x4000;
 /// TODO doc
 const CLONE_PARENT: c_ulong = 0x8000;
-/// TODO doc
+/// If specified, the child is placed in the same thread group as the parent.
+///
+/// This flag requires [`CLONE_SIGHAND`], which itself requires [`CLONE_VM`].
 const CLONE_THREAD: c_ulong = 0x10000;
 /// TODO doc
 const CLONE_NEWNS: c_ulong = 0x20000;
 /// TODO doc
 const CLONE_SYSVSEM: c_ulong = 0x40000;
-/// TODO doc
+/// If specified, the TLS descriptor pointed to by the `tls` argument is installed for the child.
 const CLONE_SETTLS: c_ulong = 0x80000;
-/// TODO doc
+/// If specified, the child's thread ID is written at the given address in the parent's memory.
 const CLONE_PARENT_SETTID: c_ulong = 0x100000;
-/// TODO doc
+/// If specified, the child's thread ID is cleared at the given address when the child exits.
 const CLONE_CHILD_CLEARTID: c_ulong = 0x200000;
-/// TODO doc
-const CLONE_DETACHED: c_ulong = 0x400000;
+/// Ignored by `clone` and rejected by `clone3`.
+pub const CLONE_DETACHED: c_ulong = 0x400000;
 /// TODO doc
 const CLONE_UNTRACED: c_ulong = 0x800000;
-/// TODO doc
+/// If specified, the child's thread ID is written at the given address in the child's memory.
 const CLONE_CHILD_SETTID: c_ulong = 0x1000000;
 /// TODO doc
 const CLONE_NEWCGROUP: c_ulong = 0x2000000;
@@ -74,65 +104,203 @@ const CLONE_NEWUTS: c_ulong = 0x4000000;
 const CLONE_NEWIPC: c_ulong = 0x8000000;
 /// TODO doc
 const CLONE_NEWUSER: c_ulong = 0x10000000;
-/// TODO doc
+/// If specified, the child is the init process of a new PID namespace.
+///
+/// This flag cannot be combined with [`CLONE_THREAD`].
 const CLONE_NEWPID: c_ulong = 0x20000000;
 /// TODO doc
 const CLONE_NEWNET: c_ulong = 0x40000000;
 
-#[allow(clippy::type_complexity)]
-pub fn clone(
-	Args((flags, stack, _parent_tid, tls, _child_tid)): Args<(
-		c_ulong,
-		*mut c_void,
-		SyscallPtr<c_int>,
-		c_ulong,
-		SyscallPtr<c_int>,
-	)>,
+/// Writes the thread ID `tid` at `ptr` in the memory space of `proc`, which is not bound.
+///
+/// Pages are allocated beforehand so that no page fault occurs while the memory space is
+/// temporarily bound.
+fn write_child_tid(proc: &Process, ptr: &SyscallPtr<c_int>, tid: c_int) -> EResult<()> {
+	let Some(p) = ptr.0 else {
+		return Ok(());
+	};
+	let addr = VirtAddr::from(p.as_ptr());
+	let mem_space = proc.get_mem_space().unwrap();
+	let mut mem_space = mem_space.lock();
+	// Check the whole value lies on writable mappings
+	let writable = [addr, addr + (size_of::<c_int>() - 1)]
+		.into_iter()
+		.all(|addr| {
+			mem_space
+				.get_mapping_for_addr(addr)
+				.is_some_and(|m| m.get_flags() & MAPPING_FLAG_WRITE != 0)
+		});
+	if !writable {
+		return Err(errno!(EFAULT));
+	}
+	mem_space.alloc(addr, size_of::<c_int>())?;
+	mem_space.bind();
+	let res = ptr.copy_to_user(tid);
+	// Restore the current process's memory space
+	let curr = Process::current();
+	curr.lock().get_mem_space().unwrap().lock().bind();
+	res
+}
+
+/// The parameters of a clone operation, common to `clone` and `clone3`.
+pub(super) struct CloneParams {
+	/// The clone flags, without the exit signal.
+	pub flags: c_ulong,
+	/// The signal sent to the parent when the child terminates.
+	pub exit_signal: Option<Signal>,
+	/// The stack pointer of the child. If `0`, the child uses the same stack pointer as the
+	/// parent.
+	pub stack: usize,
+	/// The pointer to the TLS descriptor, used with [`CLONE_SETTLS`].
+	pub tls: usize,
+	/// The location of the child's thread ID in the parent's memory.
+	pub parent_tid: SyscallPtr<c_int>,
+	/// The location of the child's thread ID in the child's memory.
+	pub child_tid: SyscallPtr<c_int>,
+	/// The location of the PID file descriptor, used with [`CLONE_PIDFD`].
+	pub pidfd: SyscallPtr<c_int>,
+	/// The PIDs to give to the child, starting from its own PID namespace.
+	pub set_tid: Vec<Pid>,
+}
+
+/// Performs a clone operation with the given parameters and returns the thread ID of the child,
+/// as seen by the caller.
+pub(super) fn do_clone(
+	params: CloneParams,
 	regs: &Regs,
 	proc_mutex: Arc<IntMutex<Process>>,
+	fds: Arc<Mutex<FileDescriptorTable>>,
 ) -> EResult<usize> {
-	let new_tid = {
-		if flags & CLONE_PARENT_SETTID != 0 {
-			// TODO
-			todo!();
+	let CloneParams {
+		flags,
+		exit_signal,
+		stack,
+		tls,
+		parent_tid,
+		child_tid,
+		pidfd,
+		set_tid,
+	} = params;
+	// Threads must share signal handlers, which must be shared along with the memory space
+	if flags & CLONE_THREAD != 0 && flags & CLONE_SIGHAND == 0 {
+		return Err(errno!(EINVAL));
+	}
+	if flags & CLONE_SIGHAND != 0 && flags & CLONE_VM == 0 {
+		return Err(errno!(EINVAL));
+	}
+	// A thread cannot belong to another PID namespace than its group
+	if flags & CLONE_NEWPID != 0 {
+		if flags & CLONE_THREAD != 0 {
+			return Err(errno!(EINVAL));
 		}
-		let new_mutex = Process::fork(
-			proc_mutex,
-			ForkOptions {
-				share_memory: flags & CLONE_VM != 0,
-				share_fd: flags & CLONE_FILES != 0,
-				share_sighand: flags & CLONE_SIGHAND != 0,
+		if !proc_mutex.lock().cred.get().is_privileged() {
+			return Err(errno!(EPERM));
+		}
+	}
+	if !set_tid.is_empty() {
+		if !proc_mutex.lock().cred.get().is_privileged() {
+			return Err(errno!(EPERM));
+		}
+		// The first process of a new namespace is its init process
+		if flags & CLONE_NEWPID != 0 && set_tid[0] != pid::INIT_PID {
+			return Err(errno!(EINVAL));
+		}
+	}
+	// The vDSO depends on the time namespace, so the memory space cannot be shared
+	if flags & CLONE_NEWTIME != 0 {
+		if flags & (CLONE_VM | CLONE_VFORK) != 0 {
+			return Err(errno!(EINVAL));
+		}
+		if !proc_mutex.lock().cred.get().is_privileged() {
+			return Err(errno!(EPERM));
+		}
+	}
+	// A PID file descriptor refers to a thread group leader
+	if flags & CLONE_PIDFD != 0 && flags & CLONE_THREAD != 0 {
+		return Err(errno!(EINVAL));
+	}
+	let tls = if flags & CLONE_SETTLS != 0 {
+		let tls = SyscallPtr::<UserDesc>::from_syscall_arg(tls);
+		let info = tls.copy_from_user()?.ok_or(errno!(EFAULT))?;
+		// The entry must be specified since it cannot be reported back
+		if info.get_entry_number() == -1 {
+			return Err(errno!(EINVAL));
+		}
+		// Check the entry exists. The child has the same entries as the parent
+		get_entry(&mut proc_mutex.lock(), info.get_entry_number())?;
+		Some(info)
+	} else {
+		None
+	};
+	let pid_ns = proc_mutex.lock().get_pid_namespace().cloned();
+	let new_mutex = Process::fork(
+		proc_mutex.clone(),
+		ForkOptions {
+			share_memory: flags & CLONE_VM != 0,
+			share_fd: flags & CLONE_FILES != 0,
+			share_sighand: flags & CLONE_SIGHAND != 0,
+			share_fs: flags & CLONE_FS != 0,
+			thread: flags & CLONE_THREAD != 0,
+
+			vfork: flags & CLONE_VFORK != 0,
+			new_pid_ns: flags & CLONE_NEWPID != 0,
+			new_time_ns: flags & CLONE_NEWTIME != 0,
+			set_tid,
 
-				vfork: flags & CLONE_VFORK != 0,
-			},
-		)?;
+			exit_signal,
+		},
+	)?;
+	// The child must not remain if an operation fails after the fork
+	let res = (|| {
 		let mut new_proc = new_mutex.lock();
 		// Set the process's registers
 		let mut new_regs = regs.clone();
 		// Set return value to `0`
 		new_regs.eax = 0;
 		// Set stack
-		new_regs.esp = if stack.is_null() {
-			regs.esp
-		} else {
-			stack as _
-		};
-		// Set TLS
-		if flags & CLONE_SETTLS != 0 {
-			let _tls = SyscallPtr::<UserDesc>::from_syscall_arg(tls as usize);
-			// TODO
-			todo!();
+		new_regs.esp = if stack == 0 { regs.esp } else { stack as _ };
+		// Set TLS. The entry is loaded when switching to the child
+		if let Some(info) = tls {
+			let (_, entry) = get_entry(&mut new_proc, info.get_entry_number())?;
+			*entry = info.to_descriptor();
 		}
 		new_proc.regs = new_regs;
 		if flags & CLONE_CHILD_CLEARTID != 0 {
-			// TODO new_proc.set_clear_child_tid(child_tid);
-			todo!();
+			new_proc.clear_child_tid = SyscallPtr(child_tid.0);
 		}
+		// The child sees its own ID in its namespace
+		let child_view = new_proc.get_local_pid();
 		if flags & CLONE_CHILD_SETTID != 0 {
-			// TODO
-			todo!();
+			if flags & (CLONE_VM | CLONE_VFORK) != 0 {
+				child_tid.copy_to_user(child_view as _)?;
+			} else {
+				write_child_tid(&new_proc, &child_tid, child_view as _)?;
+			}
+		}
+		// The parent sees the child from its own namespace, which is an ancestor of the child's
+		let new_tid = pid::to_local(pid_ns.as_deref(), new_proc.tid).unwrap_or(0);
+		let new_pidfd = PidFd::new(&new_proc);
+		drop(new_proc);
+		if flags & CLONE_PARENT_SETTID != 0 {
+			parent_tid.copy_to_user(new_tid as _)?;
+		}
+		if flags & CLONE_PIDFD != 0 {
+			let file = File::open_floating(Arc::new(new_pidfd)?, O_RDWR)?;
+			let mut fds = fds.lock();
+			let (fd_id, _) = fds.create_fd(FD_CLOEXEC, file)?;
+			if let Err(e) = pidfd.copy_to_user(fd_id as _) {
+				fds.close_fd(fd_id as _)?;
+				return Err(e);
+			}
+		}
+		Ok(new_tid)
+	})();
+	let new_tid = match res {
+		Ok(new_tid) => new_tid,
+		Err(e) => {
+			Process::cancel_fork(&proc_mutex, &new_mutex);
+			return Err(e);
 		}
-		new_proc.tid
 	};
 	if flags & CLONE_VFORK != 0 {
 		// Let another process run instead of the current. Because the current
@@ -141,3 +309,42 @@ pub fn clone(
 	}
 	Ok(new_tid as _)
 }
+
+#[allow(clippy::type_complexity)]
+pub fn clone(
+	Args((flags, stack, parent_tid, tls, child_tid)): Args<(
+		c_ulong,
+		*mut c_void,
+		SyscallPtr<c_int>,
+		c_ulong,
+		SyscallPtr<c_int>,
+	)>,
+	regs: &Regs,
+	proc: Arc<IntMutex<Process>>,
+	fds: Arc<Mutex<FileDescriptorTable>>,
+) -> EResult<usize> {
+	// With `clone`, the PID file descriptor is written at the location of the parent's thread ID
+	if flags & CLONE_PIDFD != 0 && flags & CLONE_PARENT_SETTID != 0 {
+		return Err(errno!(EINVAL));
+	}
+	let exit_signal = match (flags & CSIGNAL) as c_int {
+		0 => None,
+		sig => Some(Signal::try_from(sig)?),
+	};
+	let pidfd = SyscallPtr(parent_tid.0);
+	do_clone(
+		CloneParams {
+			flags: flags & !CSIGNAL,
+			exit_signal,
+			stack: stack as _,
+			tls: tls as _,
+			parent_tid,
+			child_tid,
+			pidfd,
+			set_tid: Vec::new(),
+		},
+		regs,
+		proc,
+		fds,
+	)
+}
@@ -0,0 +1,177 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+//! The `clone3` system call creates a child process, taking its arguments in an extensible
+//! structure.
+
+use super::clone::{do_clone, CloneParams, CLONE_DETACHED, CLONE_NEWTIME, CLONE_PIDFD, CSIGNAL};
+use crate::{
+	file::fd::FileDescriptorTable,
+	process::{
+		mem_space::copy::{SyscallPtr, SyscallSlice},
+		pid::{Pid, MAX_PID},
+		regs::Regs,
+		signal::Signal,
+		Process,
+	},
+	syscall::{Args, FromSyscallArg},
+};
+use core::{
+	cmp::min,
+	ffi::{c_int, c_ulong},
+	mem::size_of,
+};
+use macros::AnyRepr;
+use utils::{
+	bytes,
+	collections::vec::Vec,
+	errno,
+	errno::{EResult, Errno},
+	limits::PAGE_SIZE,
+	lock::{IntMutex, Mutex},
+	ptr::arc::Arc,
+};
+
+/// The size of the first version of [`CloneArgs`], which lacks the `set_tid` and `cgroup` fields.
+const CLONE_ARGS_SIZE_VER0: usize = 64;
+/// The maximum number of nested PID namespaces for which a PID can be requested.
+const MAX_PID_NS_LEVEL: u64 = 32;
+
+/// The arguments of `clone3`.
+///
+/// New fields are appended over time. Userspace gives the size of the structure it knows, so
+/// that older and newer versions remain compatible.
+#[repr(C)]
+#[derive(AnyRepr, Debug, Default)]
+struct CloneArgs {
+	/// The clone flags.
+	flags: u64,
+	/// With [`CLONE_PIDFD`], the location where the PID file descriptor is written.
+	pidfd: u64,
+	/// The location of the child's thread ID in the child's memory.
+	child_tid: u64,
+	/// The location of the child's thread ID in the parent's memory.
+	parent_tid: u64,
+	/// The signal sent to the parent when the child terminates.
+	exit_signal: u64,
+	/// The lowest address of the child's stack.
+	stack: u64,
+	/// The size of the child's stack.
+	stack_size: u64,
+	/// The location of the TLS descriptor.
+	tls: u64,
+	/// The location of the array of PIDs to give to the child.
+	set_tid: u64,
+	/// The number of elements in `set_tid`.
+	set_tid_size: u64,
+	/// The file descriptor of the cgroup in which the child is placed.
+	cgroup: u64,
+}
+
+/// Converts the userspace value `val` into an address, failing with [`errno::EINVAL`] if it does
+/// not fit.
+fn to_addr(val: u64) -> EResult<usize> {
+	val.try_into().map_err(|_| errno!(EINVAL))
+}
+
+/// Reads the arguments of size `size` at `ptr`.
+fn read_args(ptr: SyscallSlice<u8>, size: usize) -> EResult<CloneArgs> {
+	if size < CLONE_ARGS_SIZE_VER0 {
+		return Err(errno!(EINVAL));
+	}
+	if size > PAGE_SIZE {
+		return Err(errno!(E2BIG));
+	}
+	let buf = ptr.copy_from_user(..size)?.ok_or_else(|| errno!(EFAULT))?;
+	// Fields unknown to the kernel must be unused
+	let len = min(size, size_of::<CloneArgs>());
+	if buf[len..].iter().any(|b| *b != 0) {
+		return Err(errno!(E2BIG));
+	}
+	let mut args = CloneArgs::default();
+	bytes::as_bytes_mut(&mut args)[..len].copy_from_slice(&buf[..len]);
+	Ok(args)
+}
+
+/// Reads the list of PIDs requested for the child.
+fn read_set_tid(args: &CloneArgs) -> EResult<Vec<Pid>> {
+	if args.set_tid_size > MAX_PID_NS_LEVEL || (args.set_tid == 0) != (args.set_tid_size == 0) {
+		return Err(errno!(EINVAL));
+	}
+	let ptr = SyscallSlice::<c_int>::from_syscall_arg(to_addr(args.set_tid)?);
+	let len = args.set_tid_size as usize;
+	let Some(tids) = ptr.copy_from_user(..len)? else {
+		return Ok(Vec::new());
+	};
+	let mut set_tid = Vec::with_capacity(len)?;
+	for tid in tids.iter() {
+		if !(1..=MAX_PID as c_int).contains(tid) {
+			return Err(errno!(EINVAL));
+		}
+		set_tid.push(*tid as Pid)?;
+	}
+	Ok(set_tid)
+}
+
+pub fn clone3(
+	Args((cl_args, size)): Args<(SyscallSlice<u8>, usize)>,
+	regs: &Regs,
+	proc: Arc<IntMutex<Process>>,
+	fds: Arc<Mutex<FileDescriptorTable>>,
+) -> EResult<usize> {
+	let args = read_args(cl_args, size)?;
+	// Flags beyond 32 bits are not supported. The exit signal has its own field, which frees the
+	// bits of `CSIGNAL` for `CLONE_NEWTIME`
+	let flags: c_ulong = args.flags.try_into().map_err(|_| errno!(EINVAL))?;
+	if flags & (CLONE_DETACHED | (CSIGNAL & !CLONE_NEWTIME)) != 0 {
+		return Err(errno!(EINVAL));
+	}
+	let exit_signal = match args.exit_signal {
+		0 => None,
+		sig if sig & !(CSIGNAL as u64) != 0 => return Err(errno!(EINVAL)),
+		sig => Some(Signal::try_from(sig as c_int)?),
+	};
+	// The stack grows downwards, from the end of the given area
+	let stack = match (args.stack, args.stack_size) {
+		(0, 0) => 0,
+		(0, _) | (_, 0) => return Err(errno!(EINVAL)),
+		(stack, size) => to_addr(stack)?
+			.checked_add(to_addr(size)?)
+			.ok_or_else(|| errno!(EINVAL))?,
+	};
+	let set_tid = read_set_tid(&args)?;
+	let pidfd = if flags & CLONE_PIDFD != 0 {
+		SyscallPtr::from_syscall_arg(to_addr(args.pidfd)?)
+	} else {
+		SyscallPtr(None)
+	};
+	do_clone(
+		CloneParams {
+			flags,
+			exit_signal,
+			stack,
+			tls: to_addr(args.tls)?,
+			parent_tid: SyscallPtr::from_syscall_arg(to_addr(args.parent_tid)?),
+			child_tid: SyscallPtr::from_syscall_arg(to_addr(args.child_tid)?),
+			pidfd,
+			set_tid,
+		},
+		regs,
+		proc,
+		fds,
+	)
+}
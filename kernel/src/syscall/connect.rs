@@ -19,7 +19,8 @@
 //! The `connect` system call connects a socket to a distant host.
 
 use crate::{
-	file::{fd::FileDescriptorTable, socket::Socket},
+	file::{fd::FileDescriptorTable, socket::Socket, vfs::ResolutionSettings, O_NONBLOCK},
+	net::{sockaddr, SocketDomain},
 	process::{mem_space::copy::SyscallSlice, Process},
 	syscall::Args,
 };
@@ -34,6 +35,7 @@ use utils::{
 /// The implementation of the `connect` syscall.
 pub fn connect(
 	Args((sockfd, addr, addrlen)): Args<(c_int, SyscallSlice<u8>, isize)>,
+	rs: ResolutionSettings,
 	fds: Arc<Mutex<FileDescriptorTable>>,
 ) -> EResult<usize> {
 	// Validation
@@ -42,10 +44,15 @@ pub fn connect(
 	}
 	// Get socket
 	let file = fds.lock().get_fd(sockfd)?.get_file().clone();
-	let _sock: &Socket = file.get_buffer().ok_or_else(|| errno!(ENOTSOCK))?;
-	let _addr = addr
+	let sock: &Socket = file.get_buffer().ok_or_else(|| errno!(ENOTSOCK))?;
+	let addr = addr
 		.copy_from_user(..(addrlen as usize))?
 		.ok_or_else(|| errno!(EFAULT))?;
-	// TODO connect socket
-	todo!();
+	if sock.desc().domain != SocketDomain::AfUnix {
+		sock.connect_inet(&addr, file.get_flags() & O_NONBLOCK != 0)?;
+		return Ok(0);
+	}
+	let path = sockaddr::unix_path(&addr)?;
+	Socket::with_bound(path, &rs, |target| sock.connect(target))?;
+	Ok(0)
 }
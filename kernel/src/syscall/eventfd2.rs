@@ -0,0 +1,66 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The `eventfd2` system call creates an event counter referred to by a file descriptor.
+
+use crate::{
+	file::{
+		eventfd::{EventFd, EFD_CLOEXEC, EFD_NONBLOCK, EFD_SEMAPHORE},
+		fd::{FileDescriptorTable, FD_CLOEXEC},
+		File, O_NONBLOCK, O_RDWR,
+	},
+	syscall::Args,
+};
+use core::ffi::{c_int, c_uint};
+use utils::{
+	errno,
+	errno::{EResult, Errno},
+	lock::Mutex,
+	ptr::arc::Arc,
+};
+
+/// Creates an event counter with the initial value `initval` and the given `flags`, then returns
+/// the file descriptor referring to it.
+pub fn do_eventfd(
+	initval: c_uint,
+	flags: c_int,
+	fds: &Mutex<FileDescriptorTable>,
+) -> EResult<usize> {
+	if flags & !(EFD_SEMAPHORE | EFD_CLOEXEC | EFD_NONBLOCK) != 0 {
+		return Err(errno!(EINVAL));
+	}
+	let counter = EventFd::new(initval as _, flags & EFD_SEMAPHORE != 0);
+	let mut file_flags = O_RDWR;
+	if flags & EFD_NONBLOCK != 0 {
+		file_flags |= O_NONBLOCK;
+	}
+	let file = File::open_floating(Arc::new(counter)?, file_flags)?;
+	let mut fd_flags = 0;
+	if flags & EFD_CLOEXEC != 0 {
+		fd_flags |= FD_CLOEXEC;
+	}
+	let (fd_id, _) = fds.lock().create_fd(fd_flags, file)?;
+	Ok(fd_id as _)
+}
+
+pub fn eventfd2(
+	Args((initval, flags)): Args<(c_uint, c_int)>,
+	fds: Arc<Mutex<FileDescriptorTable>>,
+) -> EResult<usize> {
+	do_eventfd(initval, flags, &fds)
+}
@@ -32,11 +32,7 @@ use crate::{
 	},
 };
 use utils::{
-	collections::{
-		path::{Path, PathBuf},
-		string::String,
-		vec::Vec,
-	},
+	collections::{path::Path, string::String, vec::Vec},
 	errno,
 	errno::{CollectResult, EResult, Errno},
 	interrupt::cli,
@@ -84,7 +80,7 @@ fn get_file<A: Iterator<Item = EResult<String>>>(
 ) -> EResult<(Arc<vfs::Entry>, Vec<String>)> {
 	let mut shebangs: [ShebangBuffer; INTERP_MAX] = Default::default();
 	// Read and parse shebangs
-	let mut file = vfs::get_file_from_path(path, rs)?;
+	let mut file = vfs::path_cache::resolve(path, rs)?;
 	let mut i = 0;
 	loop {
 		let shebang = &mut shebangs[i];
@@ -119,7 +115,7 @@ fn get_file<A: Iterator<Item = EResult<String>>>(
 			.unwrap_or(shebang.end);
 		let interp_path = Path::new(&shebang.buf[2..(2 + interp_end)])?;
 		// Read interpreter
-		file = vfs::get_file_from_path(interp_path, rs)?;
+		file = vfs::path_cache::resolve(interp_path, rs)?;
 	}
 	// Build arguments
 	let final_argv = shebangs[..i]
@@ -144,7 +140,7 @@ fn get_file<A: Iterator<Item = EResult<String>>>(
 
 /// Performs the execution on the current process.
 fn do_exec(
-	file: &vfs::Entry,
+	file: &Arc<vfs::Entry>,
 	rs: &ResolutionSettings,
 	argv: Vec<String>,
 	envp: Vec<String>,
@@ -165,7 +161,7 @@ fn do_exec(
 /// - `argv` is the arguments list
 /// - `envp` is the environment variables list
 fn build_image(
-	file: &vfs::Entry,
+	file: &Arc<vfs::Entry>,
 	path_resolution: &ResolutionSettings,
 	argv: Vec<String>,
 	envp: Vec<String>,
@@ -183,8 +179,9 @@ pub fn execve(
 	rs: ResolutionSettings,
 ) -> EResult<usize> {
 	let (file, argv, envp) = {
-		let path = pathname.copy_from_user()?.ok_or_else(|| errno!(EFAULT))?;
-		let path = PathBuf::try_from(path)?;
+		let path = pathname
+			.copy_path_from_user()?
+			.ok_or_else(|| errno!(EFAULT))?;
 		let argv = argv.iter();
 		let (file, argv) = get_file(&path, &rs, argv)?;
 		let envp = envp.iter().collect::<EResult<CollectResult<Vec<_>>>>()?.0?;
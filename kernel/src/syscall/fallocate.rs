@@ -0,0 +1,72 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The `fallocate` system call manipulates the space allocated for the content of a file.
+//!
+//! Only deallocation with [`FALLOC_FL_PUNCH_HOLE`] is supported. Other modes return
+//! [`errno::EOPNOTSUPP`], which makes the C library fall back to writing zeros for
+//! `posix_fallocate`.
+
+use crate::{
+	file::{fd::FileDescriptorTable, perm::AccessProfile, vfs, FileType},
+	syscall::Args,
+};
+use core::{ffi::c_int, intrinsics::unlikely};
+use utils::{
+	errno,
+	errno::{EResult, Errno},
+	lock::Mutex,
+	ptr::arc::Arc,
+};
+
+/// Flag: the size of the file does not change.
+const FALLOC_FL_KEEP_SIZE: c_int = 0x1;
+/// Flag: deallocates the range, which then reads as zeros. Must be used with
+/// [`FALLOC_FL_KEEP_SIZE`].
+const FALLOC_FL_PUNCH_HOLE: c_int = 0x2;
+
+pub fn fallocate(
+	Args((fd, mode, off_lo, off_hi, len_lo, len_hi)): Args<(c_int, c_int, u32, u32, u32, u32)>,
+	fds: Arc<Mutex<FileDescriptorTable>>,
+	ap: AccessProfile,
+) -> EResult<usize> {
+	// On 32 bits, 64 bits values are split across two arguments
+	let off = ((off_hi as i64) << 32) | off_lo as i64;
+	let len = ((len_hi as i64) << 32) | len_lo as i64;
+	if unlikely(off < 0 || len <= 0) {
+		return Err(errno!(EINVAL));
+	}
+	let file = fds.lock().get_fd(fd)?.get_file().clone();
+	if unlikely(!file.can_write()) {
+		return Err(errno!(EBADF));
+	}
+	match file.get_type()? {
+		FileType::Regular => {}
+		FileType::Directory => return Err(errno!(EISDIR)),
+		FileType::Fifo => return Err(errno!(ESPIPE)),
+		_ => return Err(errno!(ENODEV)),
+	}
+	if mode != FALLOC_FL_PUNCH_HOLE | FALLOC_FL_KEEP_SIZE {
+		return Err(errno!(EOPNOTSUPP));
+	}
+	file.punch_hole(off as _, len as _)?;
+	if let Some(ent) = &file.vfs_entry {
+		vfs::content_modified(ent, &ap)?;
+	}
+	Ok(0)
+}
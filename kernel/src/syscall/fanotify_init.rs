@@ -0,0 +1,76 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The `fanotify_init` system call creates a fanotify notification group.
+
+use crate::{
+	file::{
+		fanotify,
+		fanotify::{Group, FAN_CLASS_CONTENT, FAN_CLASS_PRE_CONTENT, FAN_CLOEXEC, FAN_NONBLOCK},
+		fd::{FileDescriptorTable, FD_CLOEXEC},
+		perm::AccessProfile,
+		File, O_CLOEXEC, O_LARGEFILE, O_NOATIME, O_NONBLOCK, O_RDONLY, O_RDWR,
+	},
+	syscall::Args,
+};
+use core::ffi::c_uint;
+use utils::{
+	errno,
+	errno::{EResult, Errno},
+	lock::Mutex,
+	ptr::arc::Arc,
+};
+
+pub fn fanotify_init(
+	Args((flags, event_f_flags)): Args<(c_uint, c_uint)>,
+	ap: AccessProfile,
+	fds: Arc<Mutex<FileDescriptorTable>>,
+) -> EResult<usize> {
+	if !ap.is_privileged() {
+		return Err(errno!(EPERM));
+	}
+	// Validation
+	const CLASSES: c_uint = FAN_CLASS_CONTENT | FAN_CLASS_PRE_CONTENT;
+	if flags & !(FAN_CLOEXEC | FAN_NONBLOCK | CLASSES) != 0 || flags & CLASSES == CLASSES {
+		return Err(errno!(EINVAL));
+	}
+	let event_flags = event_f_flags as i32;
+	const EVENT_FLAGS: i32 = 0b11 | O_CLOEXEC | O_LARGEFILE | O_NOATIME | O_NONBLOCK;
+	if event_flags & !EVENT_FLAGS != 0 || event_flags & 0b11 > O_RDWR {
+		return Err(errno!(EINVAL));
+	}
+	// Create group
+	let group = Arc::new(Group::new(flags & CLASSES != 0, event_flags))?;
+	let mut file_flags = O_RDONLY;
+	if flags & FAN_NONBLOCK != 0 {
+		file_flags |= O_NONBLOCK;
+	}
+	let file = File::open_floating(group.clone(), file_flags)?;
+	let mut fd_flags = 0;
+	if flags & FAN_CLOEXEC != 0 {
+		fd_flags |= FD_CLOEXEC;
+	}
+	let mut fds = fds.lock();
+	let (fd_id, _) = fds.create_fd(fd_flags, file)?;
+	// Start receiving events
+	if let Err(e) = fanotify::register(group) {
+		fds.close_fd(fd_id as _)?;
+		return Err(e.into());
+	}
+	Ok(fd_id as _)
+}
@@ -0,0 +1,101 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The `fanotify_mark` system call adds, removes or modifies marks of a fanotify notification
+//! group.
+
+use super::util::at;
+use crate::{
+	file::{
+		fanotify::{
+			Group, MarkTarget, FAN_MARK_ADD, FAN_MARK_DONT_FOLLOW, FAN_MARK_FILESYSTEM,
+			FAN_MARK_FLUSH, FAN_MARK_MOUNT, FAN_MARK_ONLYDIR, FAN_MARK_REMOVE,
+		},
+		fd::FileDescriptorTable,
+		vfs::{ResolutionSettings, Resolved},
+		FileType,
+	},
+	process::mem_space::copy::SyscallString,
+	syscall::Args,
+};
+use core::ffi::{c_int, c_uint};
+use utils::{
+	errno,
+	errno::{EResult, Errno},
+	lock::Mutex,
+	ptr::arc::Arc,
+};
+
+pub fn fanotify_mark(
+	Args((fanotify_fd, flags, mask_lo, mask_hi, dirfd, pathname)): Args<(
+		c_int,
+		c_uint,
+		u32,
+		u32,
+		c_int,
+		SyscallString,
+	)>,
+	fds_mutex: Arc<Mutex<FileDescriptorTable>>,
+	rs: ResolutionSettings,
+) -> EResult<usize> {
+	// On 32 bits, the 64 bits mask is split across two arguments
+	let mask = ((mask_hi as u64) << 32) | mask_lo as u64;
+	let group_file = fds_mutex.lock().get_fd(fanotify_fd)?.get_file().clone();
+	let group = group_file
+		.get_buffer::<Group>()
+		.ok_or_else(|| errno!(EINVAL))?;
+	// Validation
+	const ACTIONS: c_uint = FAN_MARK_ADD | FAN_MARK_REMOVE | FAN_MARK_FLUSH;
+	if (flags & ACTIONS).count_ones() != 1 {
+		return Err(errno!(EINVAL));
+	}
+	let mount = flags & (FAN_MARK_MOUNT | FAN_MARK_FILESYSTEM) != 0;
+	if flags & FAN_MARK_FLUSH != 0 {
+		group.flush_marks(mount);
+		return Ok(0);
+	}
+	if mask == 0 {
+		return Err(errno!(EINVAL));
+	}
+	// Get the marked file
+	let pathname = pathname.copy_path_from_user()?;
+	let rs = ResolutionSettings {
+		follow_link: flags & FAN_MARK_DONT_FOLLOW == 0,
+		..rs
+	};
+	let Resolved::Found(file) =
+		at::get_file(&fds_mutex.lock(), rs, dirfd, pathname.as_deref(), 0)?
+	else {
+		return Err(errno!(ENOENT));
+	};
+	if flags & FAN_MARK_ONLYDIR != 0 && file.get_type()? != FileType::Directory {
+		return Err(errno!(ENOTDIR));
+	}
+	let loc = &file.node().location;
+	let target = if mount {
+		MarkTarget::Mount(loc.mountpoint_id)
+	} else {
+		MarkTarget::Inode(loc.clone())
+	};
+	if flags & FAN_MARK_ADD != 0 {
+		group.add_mark(target, mask)?;
+	} else {
+		group.remove_mark(target, mask)?;
+	}
+	Ok(0)
+}
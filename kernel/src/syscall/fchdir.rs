@@ -41,7 +41,7 @@ pub fn fchdir(
 ) -> EResult<usize> {
 	let file = fds
 		.lock()
-		.get_fd(fd)?
+		.get_fd_raw(fd)?
 		.get_file()
 		.vfs_entry
 		.clone()
@@ -51,9 +51,9 @@ pub fn fchdir(
 	if stat.get_type() != Some(FileType::Directory) {
 		return Err(errno!(ENOTDIR));
 	}
-	if !ap.can_list_directory(&stat) {
+	if !ap.can_search_directory(&stat) {
 		return Err(errno!(EACCES));
 	}
-	proc.lock().cwd = file;
+	proc.lock().fs.lock().cwd = file;
 	Ok(0)
 }
@@ -20,7 +20,7 @@
 
 use crate::{
 	file,
-	file::{fd::FileDescriptorTable, fs::StatSet, perm::AccessProfile},
+	file::{fd::FileDescriptorTable, perm::AccessProfile, vfs},
 	process::Process,
 	syscall::Args,
 };
@@ -44,17 +44,6 @@ pub fn fchmod(
 		.vfs_entry
 		.clone()
 		.ok_or_else(|| errno!(EROFS))?;
-	// Check permissions
-	let stat = file.stat()?;
-	if !ap.can_set_file_permissions(&stat) {
-		return Err(errno!(EPERM));
-	}
-	file.node().ops.set_stat(
-		&file.node().location,
-		StatSet {
-			mode: Some(mode & 0o7777),
-			..Default::default()
-		},
-	)?;
+	vfs::set_mode(&file, mode, &ap)?;
 	Ok(0)
 }
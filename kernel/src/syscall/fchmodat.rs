@@ -23,7 +23,7 @@ use crate::{
 	file,
 	file::{
 		fd::FileDescriptorTable,
-		fs::StatSet,
+		vfs,
 		vfs::{ResolutionSettings, Resolved},
 	},
 	process::{mem_space::copy::SyscallString, Process},
@@ -31,7 +31,6 @@ use crate::{
 };
 use core::ffi::c_int;
 use utils::{
-	collections::path::PathBuf,
 	errno,
 	errno::{EResult, Errno},
 	lock::Mutex,
@@ -44,26 +43,14 @@ pub fn fchmodat(
 	rs: ResolutionSettings,
 ) -> EResult<usize> {
 	let pathname = pathname
-		.copy_from_user()?
-		.map(PathBuf::try_from)
-		.transpose()?;
+		.copy_path_from_user()?
+		.ok_or_else(|| errno!(EFAULT))?;
 	// Get file
 	let fds = fds_mutex.lock();
-	let Resolved::Found(file) = at::get_file(&fds, rs.clone(), dirfd, pathname.as_deref(), flags)?
+	let Resolved::Found(file) = at::get_file(&fds, rs.clone(), dirfd, Some(&pathname), flags)?
 	else {
 		return Err(errno!(ENOENT));
 	};
-	// Check permission
-	let stat = file.stat()?;
-	if !rs.access_profile.can_set_file_permissions(&stat) {
-		return Err(errno!(EPERM));
-	}
-	file.node().ops.set_stat(
-		&file.node().location,
-		StatSet {
-			mode: Some(mode & 0o7777),
-			..Default::default()
-		},
-	)?;
+	vfs::set_mode(&file, mode, &rs.access_profile)?;
 	Ok(0)
 }
@@ -0,0 +1,45 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+//! The `fchown` system call changes the owner of a file from a file descriptor.
+
+use crate::{
+	file::{fd::FileDescriptorTable, perm::AccessProfile},
+	syscall::Args,
+};
+use core::ffi::c_int;
+use utils::{
+	errno,
+	errno::{EResult, Errno},
+	lock::Mutex,
+	ptr::arc::Arc,
+};
+
+pub fn fchown(
+	Args((fd, owner, group)): Args<(c_int, c_int, c_int)>,
+	fds: Arc<Mutex<FileDescriptorTable>>,
+	ap: AccessProfile,
+) -> EResult<usize> {
+	let file = fds
+		.lock()
+		.get_fd(fd)?
+		.get_file()
+		.vfs_entry
+		.clone()
+		.ok_or_else(|| errno!(EROFS))?;
+	super::chown::chown_entry(&file, owner, group, &ap)
+}
@@ -0,0 +1,57 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+//! The `fchownat` system call changes the owner of a file relative to a directory.
+
+use super::util::at;
+use crate::{
+	file::{
+		fd::FileDescriptorTable,
+		vfs::{ResolutionSettings, Resolved},
+	},
+	process::mem_space::copy::SyscallString,
+	syscall::Args,
+};
+use core::ffi::c_int;
+use utils::{
+	errno,
+	errno::{EResult, Errno},
+	lock::Mutex,
+	ptr::arc::Arc,
+};
+
+pub fn fchownat(
+	Args((dirfd, pathname, owner, group, flags)): Args<(
+		c_int,
+		SyscallString,
+		c_int,
+		c_int,
+		c_int,
+	)>,
+	fds: Arc<Mutex<FileDescriptorTable>>,
+	rs: ResolutionSettings,
+) -> EResult<usize> {
+	let pathname = pathname
+		.copy_path_from_user()?
+		.ok_or_else(|| errno!(EFAULT))?;
+	let ap = rs.access_profile;
+	let Resolved::Found(file) = at::get_file(&fds.lock(), rs, dirfd, Some(&pathname), flags)?
+	else {
+		return Err(errno!(ENOENT));
+	};
+	super::chown::chown_entry(&file, owner, group, &ap)
+}
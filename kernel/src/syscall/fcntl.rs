@@ -19,11 +19,7 @@
 //! The `fcntl` syscall call allows to manipulate a file descriptor.
 
 use crate::{
-	file::{
-		fd::{FileDescriptorTable, NewFDConstraint},
-		pipe::PipeBuffer,
-		FileType,
-	},
+	file::{fd::NewFDConstraint, lease, lease::LeaseType, pipe::PipeBuffer, FileType},
 	process::Process,
 	syscall::Args,
 };
@@ -34,7 +30,7 @@ use core::{
 use utils::{
 	errno,
 	errno::{EResult, Errno},
-	lock::Mutex,
+	lock::IntMutex,
 	ptr::arc::Arc,
 };
 
@@ -142,21 +138,29 @@ const F_SEAL_WRITE: c_int = 8;
 
 /// Performs the fcntl system call.
 ///
-/// `fcntl64` tells whether this is the `fcntl64` system call.
+/// Arguments:
+/// - `fcntl64` tells whether this is the `fcntl64` system call
+/// - `proc` is the calling process
 pub fn do_fcntl(
 	fd: c_int,
 	cmd: c_int,
 	arg: *mut c_void,
 	_fcntl64: bool,
-	fds: &mut FileDescriptorTable,
+	proc: &IntMutex<Process>,
 ) -> EResult<usize> {
+	let (pid, ap, fds) = {
+		let proc = proc.lock();
+		let fds = proc.file_descriptors.clone().ok_or_else(|| errno!(EBADF))?;
+		(proc.get_pid(), proc.cred.get(), fds)
+	};
+	let mut fds = fds.lock();
 	match cmd {
 		F_DUPFD => {
 			let (id, _) = fds.duplicate_fd(fd as _, NewFDConstraint::Min(arg as _), false)?;
 			Ok(id as _)
 		}
 		F_GETFD => {
-			let fd = fds.get_fd(fd)?;
+			let fd = fds.get_fd_raw(fd)?;
 			Ok(fd.flags as _)
 		}
 		F_SETFD => {
@@ -164,7 +168,7 @@ pub fn do_fcntl(
 			fd.flags = arg as _;
 			Ok(0)
 		}
-		F_GETFL => Ok(fds.get_fd(fd)?.get_file().get_flags() as _),
+		F_GETFL => Ok(fds.get_fd_raw(fd)?.get_file().get_flags() as _),
 		F_SETFL => {
 			fds.get_fd(fd)?.get_file().set_flags(arg as _, true);
 			Ok(0)
@@ -230,12 +234,24 @@ pub fn do_fcntl(
 			todo!();
 		}
 		F_SETLEASE => {
-			// TODO
-			todo!();
+			let lease_type = match arg as c_int {
+				F_RDLCK => Some(LeaseType::Read),
+				F_WRLCK => Some(LeaseType::Write),
+				F_UNLCK => None,
+				_ => return Err(errno!(EINVAL)),
+			};
+			let file = fds.get_fd(fd)?.get_file();
+			lease::set(file, lease_type, pid, &ap)?;
+			Ok(0)
 		}
 		F_GETLEASE => {
-			// TODO
-			todo!();
+			let file = fds.get_fd(fd)?.get_file();
+			let lease_type = match lease::get(file) {
+				Some(LeaseType::Read) => F_RDLCK,
+				Some(LeaseType::Write) => F_WRLCK,
+				None => F_UNLCK,
+			};
+			Ok(lease_type as _)
 		}
 		F_NOTIFY => {
 			// TODO
@@ -286,7 +302,7 @@ pub fn do_fcntl(
 
 pub fn fcntl(
 	Args((fd, cmd, arg)): Args<(c_int, c_int, *mut c_void)>,
-	fds: Arc<Mutex<FileDescriptorTable>>,
+	proc: Arc<IntMutex<Process>>,
 ) -> EResult<usize> {
-	do_fcntl(fd, cmd, arg, false, &mut fds.lock())
+	do_fcntl(fd, cmd, arg, false, &proc)
 }
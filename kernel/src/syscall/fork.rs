@@ -34,6 +34,6 @@ pub fn fork(proc: Arc<IntMutex<Process>>, regs: &Regs) -> EResult<usize> {
 	let mut regs = regs.clone();
 	regs.set_syscall_return(Ok(0));
 	new_proc.regs = regs;
-	// Set parent's return value to the child's PID
-	Ok(new_proc.get_pid() as _)
+	// Set parent's return value to the child's PID, in their namespace
+	Ok(new_proc.get_local_pid() as _)
 }
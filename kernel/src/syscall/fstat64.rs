@@ -20,17 +20,18 @@
 
 use crate::{
 	device::id::makedev,
+	file,
 	file::{
 		fd::FileDescriptorTable,
 		perm::{Gid, Uid},
 		vfs::{mountpoint::MountSource, Entry},
 		INode, Mode,
 	},
-	process::{mem_space::copy::SyscallPtr, Process},
+	process::mem_space::copy::SyscallPtr,
 	syscall::Args,
-	time::unit::{TimeUnit, Timespec, TimestampScale},
+	time::unit::Timespec32,
 };
-use core::ffi::{c_int, c_long};
+use core::ffi::{c_int, c_uint};
 use utils::{
 	errno,
 	errno::{EResult, Errno},
@@ -38,8 +39,7 @@ use utils::{
 	ptr::arc::Arc,
 };
 
-// TODO Check types
-/// A file's stat.
+/// A file's status, as used by the `stat64` family of system calls.
 #[repr(C)]
 #[derive(Debug)]
 pub struct Stat {
@@ -49,8 +49,8 @@ pub struct Stat {
 	/// Padding.
 	__st_dev_padding: c_int,
 
-	/// The inode number.
-	st_ino: INode,
+	/// The inode number, truncated to 32 bits.
+	__st_ino_truncated: u32,
 	/// File's mode.
 	st_mode: Mode,
 	/// Number of hard links to the file.
@@ -66,79 +66,88 @@ pub struct Stat {
 	__st_rdev_padding: c_int,
 
 	/// Size of the file in bytes.
-	st_size: u32,
+	st_size: i64,
 	/// Size of a block on the file's storage medium.
-	st_blksize: c_long,
+	st_blksize: c_uint,
 	/// Size of the file in blocks.
 	st_blocks: u64,
 
 	/// Timestamp of last access.
-	st_atim: Timespec,
+	st_atim: Timespec32,
 	/// Timestamp of last modification of the content.
-	st_mtim: Timespec,
+	st_mtim: Timespec32,
 	/// Timestamp of last modification of the metadata.
-	st_ctim: Timespec,
+	st_ctim: Timespec32,
+
+	/// The inode number.
+	st_ino: INode,
+}
+
+impl Stat {
+	/// Creates the structure from the status `stat` of a file.
+	///
+	/// `ent` is the VFS entry of the file, if any. It is used to retrieve the device containing
+	/// the file and the inode number.
+	pub fn new(ent: Option<&Entry>, stat: &file::Stat) -> EResult<Self> {
+		let (st_dev, st_ino) = match ent {
+			Some(ent) => {
+				let node = ent.node();
+				let mount_source = &node
+					.location
+					.get_mountpoint()
+					.ok_or_else(|| errno!(ENOENT))?
+					.source;
+				let st_dev = match mount_source {
+					MountSource::Device(dev) => dev.get_device_number(),
+					MountSource::NoDev(_) => 0,
+				};
+				(st_dev, node.location.inode)
+			}
+			None => (0, 0),
+		};
+		Ok(Self {
+			st_dev,
+
+			__st_dev_padding: 0,
+
+			__st_ino_truncated: st_ino as _,
+			st_mode: stat.mode,
+			st_nlink: stat.nlink as _,
+			st_uid: stat.uid,
+			st_gid: stat.gid,
+			st_rdev: makedev(stat.dev_major, stat.dev_minor),
+
+			__st_rdev_padding: 0,
+
+			st_size: stat.size as _,
+			st_blksize: 512, // TODO
+			st_blocks: stat.blocks,
+
+			st_atim: Timespec32 {
+				tv_sec: stat.atime as _,
+				tv_nsec: 0,
+			},
+			st_mtim: Timespec32 {
+				tv_sec: stat.mtime as _,
+				tv_nsec: 0,
+			},
+			st_ctim: Timespec32 {
+				tv_sec: stat.ctime as _,
+				tv_nsec: 0,
+			},
+
+			st_ino,
+		})
+	}
 }
 
 pub fn fstat64(
 	Args((fd, statbuf)): Args<(c_int, SyscallPtr<Stat>)>,
 	fds: Arc<Mutex<FileDescriptorTable>>,
 ) -> EResult<usize> {
-	let fds = fds.lock();
-	let file = fds.get_fd(fd)?.get_file();
-	let (st_dev, st_ino) = match &file.vfs_entry {
-		Some(ent) => {
-			let node = ent.node();
-			let mount_source = &node
-				.location
-				.get_mountpoint()
-				.ok_or_else(|| errno!(ENOENT))?
-				.source;
-			let st_dev = match mount_source {
-				MountSource::Device(dev) => dev.get_device_number(),
-				MountSource::NoDev(_) => 0,
-			};
-			let st_ino = node.location.inode;
-			(st_dev, st_ino)
-		}
-		None => (0, 0),
-	};
+	let file = fds.lock().get_fd_raw(fd)?.get_file().clone();
 	let stat = file.stat()?;
-	let rdev = makedev(stat.dev_major, stat.dev_minor);
-	let stat = Stat {
-		st_dev,
-
-		__st_dev_padding: 0,
-
-		st_ino,
-		st_mode: stat.mode,
-		st_nlink: stat.nlink as _,
-		st_uid: stat.uid,
-		st_gid: stat.gid,
-		st_rdev: rdev,
-
-		__st_rdev_padding: 0,
-
-		st_size: stat.size as _,
-		st_blksize: 512, // TODO
-		st_blocks: stat.blocks,
-
-		st_atim: Timespec::from_nano(TimestampScale::convert(
-			stat.atime,
-			TimestampScale::Second,
-			TimestampScale::Nanosecond,
-		)),
-		st_mtim: Timespec::from_nano(TimestampScale::convert(
-			stat.mtime,
-			TimestampScale::Second,
-			TimestampScale::Nanosecond,
-		)),
-		st_ctim: Timespec::from_nano(TimestampScale::convert(
-			stat.ctime,
-			TimestampScale::Second,
-			TimestampScale::Nanosecond,
-		)),
-	};
+	let stat = Stat::new(file.vfs_entry.as_deref(), &stat)?;
 	statbuf.copy_to_user(stat)?;
 	Ok(0)
 }
@@ -0,0 +1,72 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The `fstatat64` system call allows to get the status of a file, relative to a directory.
+
+use super::{fstat64::Stat, util::at};
+use crate::{
+	file::{
+		fd::FileDescriptorTable,
+		vfs::{ResolutionSettings, Resolved},
+	},
+	process::mem_space::copy::{SyscallPtr, SyscallString},
+	syscall::Args,
+};
+use core::ffi::c_int;
+use utils::{
+	errno,
+	errno::{EResult, Errno},
+	lock::Mutex,
+	ptr::arc::Arc,
+};
+
+/// Performs the `fstatat64` system call.
+///
+/// Arguments:
+/// - `dirfd` is the file descriptor of the directory relative to which `pathname` is resolved
+/// - `pathname` is the path to the file
+/// - `statbuf` is the pointer to which the status of the file is written
+/// - `flags` is the set of `AT_*` flags
+/// - `fds` is the file descriptors table
+/// - `rs` is the path resolution settings
+pub fn do_fstatat64(
+	dirfd: c_int,
+	pathname: SyscallString,
+	statbuf: SyscallPtr<Stat>,
+	flags: c_int,
+	fds: &FileDescriptorTable,
+	rs: ResolutionSettings,
+) -> EResult<usize> {
+	let pathname = pathname
+		.copy_path_from_user()?
+		.ok_or_else(|| errno!(EFAULT))?;
+	let Resolved::Found(file) = at::get_file(fds, rs, dirfd, Some(&pathname), flags)? else {
+		return Err(errno!(ENOENT));
+	};
+	let stat = file.stat()?;
+	statbuf.copy_to_user(Stat::new(Some(&file), &stat)?)?;
+	Ok(0)
+}
+
+pub fn fstatat64(
+	Args((dirfd, pathname, statbuf, flags)): Args<(c_int, SyscallString, SyscallPtr<Stat>, c_int)>,
+	fds: Arc<Mutex<FileDescriptorTable>>,
+	rs: ResolutionSettings,
+) -> EResult<usize> {
+	do_fstatat64(dirfd, pathname, statbuf, flags, &fds.lock(), rs)
+}
@@ -19,28 +19,19 @@
 //! The `fstatfs` system call returns information about a mounted file system.
 
 use crate::{
-	file::{fd::FileDescriptorTable, fs::Statfs},
-	process::{mem_space::copy::SyscallPtr, Process},
+	file::{
+		fd::FileDescriptorTable,
+		fs::{Statfs, Statfs32},
+	},
+	process::mem_space::copy::SyscallPtr,
 	syscall::Args,
 };
-use core::{ffi::c_int, intrinsics::size_of};
-use utils::{
-	errno,
-	errno::{EResult, Errno},
-	lock::Mutex,
-	ptr::arc::Arc,
-};
+use core::ffi::c_int;
+use utils::{errno, errno::EResult, lock::Mutex, ptr::arc::Arc};
 
-/// Performs the `fstatfs` system call.
-pub fn do_fstatfs(
-	fd: c_int,
-	_sz: usize,
-	buf: SyscallPtr<Statfs>,
-	fds: &FileDescriptorTable,
-) -> EResult<usize> {
-	// TODO use `sz`
-	let stat = fds
-		.get_fd(fd)?
+/// Returns the statistics of the filesystem on which the file open at `fd` is located.
+pub(super) fn get_fstatfs(fd: c_int, fds: &FileDescriptorTable) -> EResult<Statfs> {
+	fds.get_fd_raw(fd)?
 		.get_file()
 		.vfs_entry
 		.as_ref()
@@ -49,15 +40,14 @@ pub fn do_fstatfs(
 		.location
 		.get_mountpoint()
 		.ok_or_else(|| errno!(ENOSYS))?
-		.fs
-		.get_stat()?;
-	buf.copy_to_user(stat)?;
-	Ok(0)
+		.get_stat()
 }
 
 pub fn fstatfs(
-	Args((fd, buf)): Args<(c_int, SyscallPtr<Statfs>)>,
+	Args((fd, buf)): Args<(c_int, SyscallPtr<Statfs32>)>,
 	fds: Arc<Mutex<FileDescriptorTable>>,
 ) -> EResult<usize> {
-	do_fstatfs(fd, size_of::<Statfs>(), buf, &fds.lock())
+	let stat = get_fstatfs(fd, &fds.lock())?;
+	buf.copy_to_user(Statfs32::try_from(&stat)?)?;
+	Ok(0)
 }
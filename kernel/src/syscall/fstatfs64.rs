@@ -18,22 +18,23 @@
 
 //! The `fstatfs64` system call returns information about a mounted file system.
 
+use super::fstatfs::get_fstatfs;
 use crate::{
 	file::{fd::FileDescriptorTable, fs::Statfs},
-	process::{mem_space::copy::SyscallPtr, Process},
+	process::mem_space::copy::SyscallPtr,
 	syscall::Args,
 };
-use core::ffi::c_int;
-use utils::{
-	errno,
-	errno::{EResult, Errno},
-	lock::Mutex,
-	ptr::arc::Arc,
-};
+use core::{ffi::c_int, mem::size_of};
+use utils::{errno, errno::EResult, lock::Mutex, ptr::arc::Arc};
 
 pub fn fstatfs64(
 	Args((fd, sz, buf)): Args<(c_int, usize, SyscallPtr<Statfs>)>,
 	fds: Arc<Mutex<FileDescriptorTable>>,
 ) -> EResult<usize> {
-	super::fstatfs::do_fstatfs(fd, sz, buf, &fds.lock())
+	if sz != size_of::<Statfs>() {
+		return Err(errno!(EINVAL));
+	}
+	let stat = get_fstatfs(fd, &fds.lock())?;
+	buf.copy_to_user(stat)?;
+	Ok(0)
 }
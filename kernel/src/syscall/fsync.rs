@@ -18,16 +18,23 @@
 
 //! The `fsync` system call synchronizes the state of a file to storage.
 
-use crate::{file::fd::FileDescriptorTable, process::Process, syscall::Args};
+use crate::{
+	file::{fd::FileDescriptorTable, page_cache},
+	syscall::Args,
+};
 use core::ffi::c_int;
 use utils::{
-	errno,
 	errno::{EResult, Errno},
 	lock::Mutex,
 	ptr::arc::Arc,
 };
 
-pub fn fsync(Args(_fd): Args<c_int>, _fds: Arc<Mutex<FileDescriptorTable>>) -> EResult<usize> {
-	// TODO
+pub fn fsync(Args(fd): Args<c_int>, fds: Arc<Mutex<FileDescriptorTable>>) -> EResult<usize> {
+	let file = fds.lock().get_fd(fd)?.get_file().clone();
+	// Files that are not on the VFS have nothing to synchronize
+	if let Some(ent) = &file.vfs_entry {
+		let node = ent.node();
+		page_cache::sync_file(&node.location, &*node.ops)?;
+	}
 	Ok(0)
 }
@@ -0,0 +1,63 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The `ftruncate` syscall allows to truncate a file from a file descriptor.
+
+use crate::{
+	file::{fd::FileDescriptorTable, perm::AccessProfile, vfs},
+	syscall::Args,
+};
+use core::{ffi::c_int, intrinsics::unlikely};
+use utils::{
+	errno,
+	errno::{EResult, Errno},
+	lock::Mutex,
+	ptr::arc::Arc,
+};
+
+/// Truncates the file open at the file descriptor `fd` to `length` bytes.
+///
+/// Arguments:
+/// - `fds` is the file descriptors table
+/// - `ap` is the access profile of the agent truncating the file
+pub fn do_ftruncate(
+	fd: c_int,
+	length: u64,
+	fds: &Mutex<FileDescriptorTable>,
+	ap: &AccessProfile,
+) -> EResult<usize> {
+	let file = fds.lock().get_fd(fd)?.get_file().clone();
+	// The file must be open for writing
+	if unlikely(!file.can_write()) {
+		return Err(errno!(EINVAL));
+	}
+	file.truncate(length)?;
+	if let Some(ent) = &file.vfs_entry {
+		vfs::content_modified(ent, ap)?;
+	}
+	Ok(0)
+}
+
+pub fn ftruncate(
+	Args((fd, length)): Args<(c_int, isize)>,
+	fds: Arc<Mutex<FileDescriptorTable>>,
+	ap: AccessProfile,
+) -> EResult<usize> {
+	let length: u64 = length.try_into().map_err(|_| errno!(EINVAL))?;
+	do_ftruncate(fd, length, &fds, &ap)
+}
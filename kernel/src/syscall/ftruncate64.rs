@@ -0,0 +1,44 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The `ftruncate64` syscall allows to truncate a file from a file descriptor, with a 64 bits
+//! length.
+
+use super::ftruncate::do_ftruncate;
+use crate::{
+	file::{fd::FileDescriptorTable, perm::AccessProfile},
+	syscall::Args,
+};
+use core::ffi::c_int;
+use utils::{
+	errno,
+	errno::{EResult, Errno},
+	lock::Mutex,
+	ptr::arc::Arc,
+};
+
+pub fn ftruncate64(
+	Args((fd, length_lo, length_hi)): Args<(c_int, u32, u32)>,
+	fds: Arc<Mutex<FileDescriptorTable>>,
+	ap: AccessProfile,
+) -> EResult<usize> {
+	// On 32 bits, the 64 bits length is split across two arguments
+	let length = ((length_hi as i64) << 32) | length_lo as i64;
+	let length: u64 = length.try_into().map_err(|_| errno!(EINVAL))?;
+	do_ftruncate(fd, length, &fds, &ap)
+}
@@ -0,0 +1,138 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The `futex` system call allows userspace to wait on and wake up processes waiting on a futex.
+
+use crate::{
+	process::{futex, futex::FUTEX_BITSET_MATCH_ANY, mem_space::copy::SyscallPtr, Process},
+	syscall::{Args, FromSyscallArg},
+	time::{
+		clock,
+		clock::{CLOCK_MONOTONIC, CLOCK_REALTIME},
+		namespace,
+		unit::{ClockIdT, TimeUnit, Timespec32, Timestamp, TimestampScale},
+	},
+};
+use core::ffi::c_int;
+use utils::{errno, errno::EResult};
+
+/// Waits on the futex if it contains the expected value.
+const FUTEX_WAIT: c_int = 0;
+/// Wakes processes waiting on the futex.
+const FUTEX_WAKE: c_int = 1;
+/// Wakes processes waiting on the futex, and moves the remaining ones to another futex.
+const FUTEX_REQUEUE: c_int = 3;
+/// Like [`FUTEX_REQUEUE`], but checks the value of the futex first.
+const FUTEX_CMP_REQUEUE: c_int = 4;
+/// Like [`FUTEX_WAIT`], with a bitset and an absolute timeout.
+const FUTEX_WAIT_BITSET: c_int = 9;
+/// Like [`FUTEX_WAKE`], only waking processes whose bitset matches.
+const FUTEX_WAKE_BITSET: c_int = 10;
+
+/// Tells the futex is used only by the threads of a single process.
+///
+/// Futexes are identified by physical address, which works for both private and shared futexes,
+/// so this flag is ignored.
+const FUTEX_PRIVATE_FLAG: c_int = 128;
+/// Tells the timeout is measured against `CLOCK_REALTIME` instead of `CLOCK_MONOTONIC`.
+const FUTEX_CLOCK_REALTIME: c_int = 256;
+
+/// Reads the timeout at `timeout` and returns the corresponding deadline on the clock `clk`.
+///
+/// If `absolute` is `false`, the timeout is relative to the current time.
+///
+/// If `timeout` is null, the function returns `None`.
+fn get_deadline<T: TimeUnit>(
+	timeout: SyscallPtr<T>,
+	clk: ClockIdT,
+	absolute: bool,
+) -> EResult<Option<(ClockIdT, Timestamp)>> {
+	let Some(timeout) = timeout.copy_from_user()? else {
+		return Ok(None);
+	};
+	let mut ts = timeout.to_nano();
+	if absolute {
+		// The deadline is expressed in the time namespace of the caller
+		let ns = Process::current().lock().time_ns.clone();
+		ts = namespace::to_host(ns.as_deref(), clk, ts);
+	} else {
+		ts = clock::current_time(clk, TimestampScale::Nanosecond)?.saturating_add(ts);
+	}
+	Ok(Some((clk, ts)))
+}
+
+/// Performs the futex operation.
+///
+/// Arguments:
+/// - `uaddr` is the address of the futex word
+/// - `futex_op` is the operation to perform, along with flags
+/// - `val` is the expected value of the futex for wait operations, or the maximum number of
+///   processes to wake up
+/// - `timeout` is a pointer to the timeout for wait operations, or the maximum number of processes
+///   to requeue
+/// - `uaddr2` is the address of the futex to which processes are requeued
+/// - `val3` is the bitset for bitset operations, or the expected value for [`FUTEX_CMP_REQUEUE`]
+pub fn do_futex<T: TimeUnit>(
+	uaddr: SyscallPtr<u32>,
+	futex_op: c_int,
+	val: u32,
+	timeout: usize,
+	uaddr2: SyscallPtr<u32>,
+	val3: u32,
+) -> EResult<usize> {
+	let clk = if futex_op & FUTEX_CLOCK_REALTIME != 0 {
+		CLOCK_REALTIME
+	} else {
+		CLOCK_MONOTONIC
+	};
+	match futex_op & !(FUTEX_PRIVATE_FLAG | FUTEX_CLOCK_REALTIME) {
+		FUTEX_WAIT => {
+			let deadline = get_deadline::<T>(SyscallPtr::from_syscall_arg(timeout), clk, false)?;
+			futex::wait(&uaddr, val, FUTEX_BITSET_MATCH_ANY, deadline)?;
+			Ok(0)
+		}
+		FUTEX_WAIT_BITSET => {
+			let deadline = get_deadline::<T>(SyscallPtr::from_syscall_arg(timeout), clk, true)?;
+			futex::wait(&uaddr, val, val3, deadline)?;
+			Ok(0)
+		}
+		FUTEX_WAKE => futex::wake(&uaddr, val as _, FUTEX_BITSET_MATCH_ANY),
+		FUTEX_WAKE_BITSET => futex::wake(&uaddr, val as _, val3),
+		op @ (FUTEX_REQUEUE | FUTEX_CMP_REQUEUE) => {
+			let requeue_count: usize =
+				(timeout as c_int).try_into().map_err(|_| errno!(EINVAL))?;
+			let cmp = (op == FUTEX_CMP_REQUEUE).then_some(val3);
+			futex::requeue(&uaddr, val as _, &uaddr2, requeue_count, cmp)
+		}
+		_ => Err(errno!(ENOSYS)),
+	}
+}
+
+#[allow(clippy::type_complexity)]
+pub fn futex(
+	Args((uaddr, futex_op, val, timeout, uaddr2, val3)): Args<(
+		SyscallPtr<u32>,
+		c_int,
+		u32,
+		usize,
+		SyscallPtr<u32>,
+		u32,
+	)>,
+) -> EResult<usize> {
+	do_futex::<Timespec32>(uaddr, futex_op, val, timeout, uaddr2, val3)
+}
@@ -0,0 +1,38 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! `futex_time64` is like `futex` but using 64 bits timestamps.
+
+use super::futex::do_futex;
+use crate::{process::mem_space::copy::SyscallPtr, syscall::Args, time::unit::Timespec};
+use core::ffi::c_int;
+use utils::errno::EResult;
+
+#[allow(clippy::type_complexity)]
+pub fn futex_time64(
+	Args((uaddr, futex_op, val, timeout, uaddr2, val3)): Args<(
+		SyscallPtr<u32>,
+		c_int,
+		u32,
+		usize,
+		SyscallPtr<u32>,
+		u32,
+	)>,
+) -> EResult<usize> {
+	do_futex::<Timespec>(uaddr, futex_op, val, timeout, uaddr2, val3)
+}
@@ -0,0 +1,36 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The `getcpu` system call returns the CPU and NUMA node on which the calling thread is running.
+
+use crate::{cpu, process::mem_space::copy::SyscallPtr, syscall::Args};
+use core::ffi::{c_uint, c_void};
+use utils::errno::EResult;
+
+pub fn getcpu(
+	Args((cpu_ptr, node_ptr, _tcache)): Args<(
+		SyscallPtr<c_uint>,
+		SyscallPtr<c_uint>,
+		*mut c_void,
+	)>,
+) -> EResult<usize> {
+	cpu_ptr.copy_to_user(cpu::current_id())?;
+	// NUMA is not supported, thus there is only one node
+	node_ptr.copy_to_user(0)?;
+	Ok(0)
+}
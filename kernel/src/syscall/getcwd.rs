@@ -36,7 +36,13 @@ pub fn getcwd(
 	Args((buf, size)): Args<(SyscallSlice<u8>, usize)>,
 	proc: Arc<IntMutex<Process>>,
 ) -> EResult<usize> {
-	let cwd = vfs::Entry::get_path(&proc.lock().cwd)?;
+	let (cwd, root) = {
+		let fs = proc.lock().fs.clone();
+		let fs = fs.lock();
+		(fs.cwd.clone(), fs.chroot.clone())
+	};
+	// The path is relative to the process's root directory
+	let cwd = vfs::Entry::get_path_from(&cwd, &root)?;
 	if unlikely(size < cwd.len() + 1) {
 		return Err(errno!(ERANGE));
 	}
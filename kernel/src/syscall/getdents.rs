@@ -20,7 +20,7 @@
 //! directory.
 
 use crate::{
-	file::{fd::FileDescriptorTable, FileType, INode},
+	file::{fd::FileDescriptorTable, FileType, INode, DT_UNKNOWN},
 	process::{mem_space::copy::SyscallSlice, Process},
 	syscall::Args,
 };
@@ -57,17 +57,27 @@ pub trait Dirent: Sized {
 	/// - `slice` is the slice to write on.
 	/// - `off` is the offset at which the entry is to be written.
 	/// - `inode` is the inode of the entry.
-	/// - `entry_type` is the type of the entry.
+	/// - `entry_type` is the type of the entry. If `None`, the type is unknown.
 	/// - `name` is the name of the entry.
 	fn write(
 		slice: &SyscallSlice<u8>,
 		off: usize,
 		inode: INode,
-		entry_type: FileType,
+		entry_type: Option<FileType>,
 		name: &[u8],
 	) -> EResult<()>;
 }
 
+/// Returns the value of the type field of a directory entry for the given file type.
+///
+/// If the type is unknown, the function returns [`DT_UNKNOWN`], in which case userspace is
+/// expected to retrieve it with `stat`, if required.
+pub fn dirent_type(entry_type: Option<FileType>) -> u8 {
+	entry_type
+		.map(FileType::to_dirent_type)
+		.unwrap_or(DT_UNKNOWN)
+}
+
 /// Performs the `getdents` system call.
 pub fn do_getdents<E: Dirent>(
 	fd: c_uint,
@@ -90,6 +100,7 @@ pub fn do_getdents<E: Dirent>(
 		};
 		// Skip entries whose inode cannot fit in the structure
 		if entry.inode > E::INODE_MAX {
+			off = next_off;
 			continue;
 		}
 		let len = E::required_length(entry.name.as_ref());
@@ -144,7 +155,7 @@ impl Dirent for LinuxDirent {
 		slice: &SyscallSlice<u8>,
 		off: usize,
 		inode: INode,
-		entry_type: FileType,
+		entry_type: Option<FileType>,
 		name: &[u8],
 	) -> EResult<()> {
 		let len = Self::required_length(name);
@@ -161,7 +172,7 @@ impl Dirent for LinuxDirent {
 		// Write nul byte and entry type
 		slice.copy_to_user(
 			off + offset_of!(Self, d_name) + name.len(),
-			&[b'\0', entry_type.to_dirent_type()],
+			&[b'\0', dirent_type(entry_type)],
 		)?;
 		Ok(())
 	}
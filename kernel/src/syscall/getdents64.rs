@@ -19,7 +19,7 @@
 //! The `getdents64` system call allows to get the list of entries in a given
 //! directory.
 
-use super::getdents::{do_getdents, Dirent};
+use super::getdents::{dirent_type, do_getdents, Dirent};
 use crate::{
 	file::{fd::FileDescriptorTable, FileType, INode},
 	process::mem_space::copy::SyscallSlice,
@@ -66,7 +66,7 @@ impl Dirent for LinuxDirent64 {
 		slice: &SyscallSlice<u8>,
 		off: usize,
 		inode: INode,
-		entry_type: FileType,
+		entry_type: Option<FileType>,
 		name: &[u8],
 	) -> EResult<()> {
 		let len = Self::required_length(name);
@@ -74,7 +74,7 @@ impl Dirent for LinuxDirent64 {
 			d_ino: inode,
 			d_off: (off + len) as _,
 			d_reclen: len as _,
-			d_type: entry_type.to_dirent_type(),
+			d_type: dirent_type(entry_type),
 			d_name: [],
 		};
 		// Write entry
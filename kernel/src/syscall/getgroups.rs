@@ -0,0 +1,58 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! `getgroups` returns the supplementary group IDs of the current process.
+
+use crate::{
+	file::perm::{AccessProfile, Gid},
+	process::mem_space::copy::SyscallSlice,
+	syscall::Args,
+};
+use core::{ffi::c_int, fmt};
+use utils::{collections::vec::Vec, errno, errno::EResult};
+
+/// Performs the `getgroups` operation, writing group IDs to `list` as values of type `T`.
+///
+/// If `size` is zero, the function only returns the number of groups.
+pub fn do_getgroups<T: From<Gid> + fmt::Debug>(
+	size: c_int,
+	list: SyscallSlice<T>,
+	ap: AccessProfile,
+) -> EResult<usize> {
+	let size: usize = size.try_into().map_err(|_| errno!(EINVAL))?;
+	let groups = ap.groups();
+	if size == 0 {
+		return Ok(groups.len());
+	}
+	if size < groups.len() {
+		return Err(errno!(EINVAL));
+	}
+	let mut buf = Vec::with_capacity(groups.len())?;
+	for gid in groups {
+		buf.push(T::from(*gid))?;
+	}
+	list.copy_to_user(0, &buf)?;
+	Ok(groups.len())
+}
+
+pub fn getgroups(
+	Args((size, list)): Args<(c_int, SyscallSlice<Gid>)>,
+	ap: AccessProfile,
+) -> EResult<usize> {
+	do_getgroups(size, list, ap)
+}
@@ -0,0 +1,31 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! `getgroups32` is like `getgroups` but using 32 bits group IDs.
+
+use super::getgroups::do_getgroups;
+use crate::{file::perm::AccessProfile, process::mem_space::copy::SyscallSlice, syscall::Args};
+use core::ffi::c_int;
+use utils::errno::EResult;
+
+pub fn getgroups32(
+	Args((size, list)): Args<(c_int, SyscallSlice<u32>)>,
+	ap: AccessProfile,
+) -> EResult<usize> {
+	do_getgroups(size, list, ap)
+}
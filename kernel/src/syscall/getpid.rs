@@ -26,5 +26,7 @@ use utils::{
 };
 
 pub fn getpid(proc: Arc<IntMutex<Process>>) -> EResult<usize> {
-	Ok(proc.lock().get_pid() as _)
+	let proc = proc.lock();
+	// The thread group leader is in the same namespace
+	Ok(proc.pid_to_local(proc.get_tgid()).unwrap_or(0) as _)
 }
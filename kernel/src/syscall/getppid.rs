@@ -26,5 +26,7 @@ use utils::{
 };
 
 pub fn getppid(proc: Arc<IntMutex<Process>>) -> EResult<usize> {
-	Ok(proc.lock().get_parent_pid() as _)
+	let proc = proc.lock();
+	// The parent of the init process of a namespace is outside of it
+	Ok(proc.pid_to_local(proc.get_parent_pid()).unwrap_or(0) as _)
 }
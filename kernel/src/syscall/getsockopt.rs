@@ -20,7 +20,10 @@
 
 use crate::{
 	file::{fd::FileDescriptorTable, socket::Socket},
-	process::{mem_space::copy::SyscallSlice, Process},
+	process::{
+		mem_space::copy::{SyscallPtr, SyscallSlice},
+		Process,
+	},
 	syscall::Args,
 };
 use core::{any::Any, cmp::min, ffi::c_int};
@@ -31,22 +34,29 @@ use utils::{
 	ptr::arc::Arc,
 };
 
+#[allow(clippy::type_complexity)]
 pub fn getsockopt(
 	Args((sockfd, level, optname, optval, optlen)): Args<(
 		c_int,
 		c_int,
 		c_int,
 		SyscallSlice<u8>,
-		usize,
+		SyscallPtr<isize>,
 	)>,
 	fds: Arc<Mutex<FileDescriptorTable>>,
 ) -> EResult<usize> {
 	// Get socket
 	let file = fds.lock().get_fd(sockfd)?.get_file().clone();
 	let sock: &Socket = file.get_buffer().ok_or_else(|| errno!(ENOTSOCK))?;
+	// Read and check buffer length
+	let optlen_val = optlen.copy_from_user()?.ok_or_else(|| errno!(EFAULT))?;
+	if optlen_val < 0 {
+		return Err(errno!(EINVAL));
+	}
 	let val = sock.get_opt(level, optname)?;
 	// Write back
-	let len = min(val.len(), optlen);
+	let len = min(val.len(), optlen_val as _);
 	optval.copy_to_user(0, &val[..len])?;
-	Ok(len as _)
+	optlen.copy_to_user(len as _)?;
+	Ok(0)
 }
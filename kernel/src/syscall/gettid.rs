@@ -26,5 +26,5 @@ use utils::{
 };
 
 pub fn gettid(proc: Arc<IntMutex<Process>>) -> EResult<usize> {
-	Ok(proc.lock().tid as _)
+	Ok(proc.lock().get_local_pid() as _)
 }
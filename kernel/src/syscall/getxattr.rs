@@ -0,0 +1,111 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The `getxattr` system call returns the value of an extended attribute of a file.
+
+use crate::{
+	file::{perm::AccessProfile, vfs, vfs::ResolutionSettings},
+	process::mem_space::copy::{SyscallSlice, SyscallString},
+	syscall::Args,
+};
+use utils::{
+	collections::string::String,
+	errno,
+	errno::{EResult, Errno},
+};
+
+/// The maximum length of an attribute name.
+const XATTR_NAME_MAX: usize = 255;
+/// The maximum size of an attribute value.
+pub const XATTR_SIZE_MAX: usize = 65536;
+
+/// Copies the name of an attribute from userspace.
+pub fn copy_name(name: SyscallString) -> EResult<String> {
+	let name = name.copy_from_user()?.ok_or_else(|| errno!(EFAULT))?;
+	if name.is_empty() || name.len() > XATTR_NAME_MAX {
+		return Err(errno!(ERANGE));
+	}
+	Ok(name)
+}
+
+/// Copies `data` to the userspace buffer `buf` of size `size`.
+///
+/// If `size` is zero, nothing is copied, which allows userspace to query the size of the buffer
+/// to allocate. In this case, `buf` may be null.
+///
+/// On success, the function returns the size of `data`.
+pub fn copy_result(data: &[u8], buf: SyscallSlice<u8>, size: usize) -> EResult<usize> {
+	if size == 0 {
+		return Ok(data.len());
+	}
+	if data.len() > size {
+		return Err(errno!(ERANGE));
+	}
+	if buf.as_ptr().is_null() && !data.is_empty() {
+		return Err(errno!(EFAULT));
+	}
+	buf.copy_to_user(0, data)?;
+	Ok(data.len())
+}
+
+/// Returns the value of the extended attribute `name` of the file `file`.
+///
+/// Arguments:
+/// - `file` is the file
+/// - `name` is the name of the attribute
+/// - `value` is the buffer to write the value to
+/// - `size` is the size of the buffer
+/// - `ap` is the access profile to check permissions
+pub fn getxattr_entry(
+	file: &vfs::Entry,
+	name: SyscallString,
+	value: SyscallSlice<u8>,
+	size: usize,
+	ap: &AccessProfile,
+) -> EResult<usize> {
+	let name = copy_name(name)?;
+	let data = vfs::get_xattr(file, name.as_bytes(), ap)?;
+	copy_result(&data, value, size)
+}
+
+/// Performs the `getxattr` syscall.
+pub fn do_getxattr(
+	pathname: SyscallString,
+	name: SyscallString,
+	value: SyscallSlice<u8>,
+	size: usize,
+	rs: ResolutionSettings,
+) -> EResult<usize> {
+	let path = pathname
+		.copy_path_from_user()?
+		.ok_or_else(|| errno!(EFAULT))?;
+	let file = vfs::get_file_from_path(&path, &rs)?;
+	getxattr_entry(&file, name, value, size, &rs.access_profile)
+}
+
+pub fn getxattr(
+	Args((pathname, name, value, size)): Args<(
+		SyscallString,
+		SyscallString,
+		SyscallSlice<u8>,
+		usize,
+	)>,
+	rs: ResolutionSettings,
+) -> EResult<usize> {
+	do_getxattr(pathname, name, value, size, rs)
+}
@@ -0,0 +1,81 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The `inotify_add_watch` system call adds a watch to an inotify instance, or modifies an
+//! existing one.
+
+use crate::{
+	file::{
+		fd::FileDescriptorTable,
+		notify::{
+			Instance, IN_ALL_EVENTS, IN_DONT_FOLLOW, IN_EXCL_UNLINK, IN_MASK_ADD, IN_MASK_CREATE,
+			IN_ONESHOT, IN_ONLYDIR,
+		},
+		vfs,
+		vfs::ResolutionSettings,
+		FileType,
+	},
+	process::mem_space::copy::SyscallString,
+	syscall::Args,
+};
+use core::ffi::c_int;
+use utils::{
+	errno,
+	errno::{EResult, Errno},
+	lock::Mutex,
+	ptr::arc::Arc,
+};
+
+pub fn inotify_add_watch(
+	Args((fd, pathname, mask)): Args<(c_int, SyscallString, u32)>,
+	fds: Arc<Mutex<FileDescriptorTable>>,
+	rs: ResolutionSettings,
+) -> EResult<usize> {
+	let instance_file = fds.lock().get_fd(fd)?.get_file().clone();
+	let instance = instance_file
+		.get_buffer::<Instance>()
+		.ok_or_else(|| errno!(EINVAL))?;
+	// Validation
+	const FLAGS: u32 =
+		IN_DONT_FOLLOW | IN_EXCL_UNLINK | IN_MASK_ADD | IN_MASK_CREATE | IN_ONESHOT | IN_ONLYDIR;
+	if mask & !(IN_ALL_EVENTS | FLAGS) != 0 || mask & IN_ALL_EVENTS == 0 {
+		return Err(errno!(EINVAL));
+	}
+	if mask & IN_MASK_ADD != 0 && mask & IN_MASK_CREATE != 0 {
+		return Err(errno!(EINVAL));
+	}
+	// Get the watched file
+	let pathname = pathname
+		.copy_path_from_user()?
+		.ok_or_else(|| errno!(EFAULT))?;
+	let rs = ResolutionSettings {
+		follow_link: mask & IN_DONT_FOLLOW == 0,
+		..rs
+	};
+	let file = vfs::get_file_from_path(&pathname, &rs)?;
+	let stat = file.stat()?;
+	if mask & IN_ONLYDIR != 0 && stat.get_type() != Some(FileType::Directory) {
+		return Err(errno!(ENOTDIR));
+	}
+	if !rs.access_profile.can_read_file(&stat) {
+		return Err(errno!(EACCES));
+	}
+	let flags = IN_EXCL_UNLINK | IN_MASK_ADD | IN_MASK_CREATE | IN_ONESHOT;
+	let wd = instance.add_watch(file.node().location.clone(), mask & (IN_ALL_EVENTS | flags))?;
+	Ok(wd as _)
+}
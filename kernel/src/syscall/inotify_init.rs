@@ -0,0 +1,29 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The `inotify_init` system call creates an inotify instance.
+//!
+//! It is equivalent to `inotify_init1` with no flags.
+
+use super::inotify_init1::inotify_init1;
+use crate::{file::fd::FileDescriptorTable, syscall::Args};
+use utils::{errno::EResult, lock::Mutex, ptr::arc::Arc};
+
+pub fn inotify_init(fds: Arc<Mutex<FileDescriptorTable>>) -> EResult<usize> {
+	inotify_init1(Args(0), fds)
+}
@@ -0,0 +1,63 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The `inotify_init1` system call creates an inotify instance.
+
+use crate::{
+	file::{
+		fd::{FileDescriptorTable, FD_CLOEXEC},
+		notify,
+		notify::{Instance, IN_CLOEXEC, IN_NONBLOCK},
+		File, O_NONBLOCK, O_RDONLY,
+	},
+	syscall::Args,
+};
+use core::ffi::c_int;
+use utils::{
+	errno,
+	errno::{EResult, Errno},
+	lock::Mutex,
+	ptr::arc::Arc,
+};
+
+pub fn inotify_init1(
+	Args(flags): Args<c_int>,
+	fds: Arc<Mutex<FileDescriptorTable>>,
+) -> EResult<usize> {
+	if flags & !(IN_CLOEXEC | IN_NONBLOCK) != 0 {
+		return Err(errno!(EINVAL));
+	}
+	let instance = Arc::new(Instance::default())?;
+	let mut file_flags = O_RDONLY;
+	if flags & IN_NONBLOCK != 0 {
+		file_flags |= O_NONBLOCK;
+	}
+	let file = File::open_floating(instance.clone(), file_flags)?;
+	let mut fd_flags = 0;
+	if flags & IN_CLOEXEC != 0 {
+		fd_flags |= FD_CLOEXEC;
+	}
+	let mut fds = fds.lock();
+	let (fd_id, _) = fds.create_fd(fd_flags, file)?;
+	// Start receiving events
+	if let Err(e) = notify::register(instance) {
+		fds.close_fd(fd_id as _)?;
+		return Err(e.into());
+	}
+	Ok(fd_id as _)
+}
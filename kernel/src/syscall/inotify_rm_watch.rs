@@ -0,0 +1,43 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The `inotify_rm_watch` system call removes a watch from an inotify instance.
+
+use crate::{
+	file::{fd::FileDescriptorTable, notify::Instance},
+	syscall::Args,
+};
+use core::ffi::c_int;
+use utils::{
+	errno,
+	errno::{EResult, Errno},
+	lock::Mutex,
+	ptr::arc::Arc,
+};
+
+pub fn inotify_rm_watch(
+	Args((fd, wd)): Args<(c_int, c_int)>,
+	fds: Arc<Mutex<FileDescriptorTable>>,
+) -> EResult<usize> {
+	let instance_file = fds.lock().get_fd(fd)?.get_file().clone();
+	let instance = instance_file
+		.get_buffer::<Instance>()
+		.ok_or_else(|| errno!(EINVAL))?;
+	instance.rm_watch(wd)?;
+	Ok(0)
+}
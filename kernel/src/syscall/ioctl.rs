@@ -19,8 +19,18 @@
 //! The `ioctl` syscall allows to control a device represented by a file
 //! descriptor.
 
-use crate::{file::fd::FileDescriptorTable, process::Process, syscall::Args};
-use core::ffi::{c_int, c_ulong, c_void};
+use crate::{
+	file::{
+		fd::{FileDescriptorTable, FD_CLOEXEC},
+		File, FileType, O_NONBLOCK,
+	},
+	process::{mem_space::copy::SyscallPtr, Process},
+	syscall::{Args, FromSyscallArg},
+};
+use core::{
+	ffi::{c_int, c_ulong, c_void},
+	sync::atomic,
+};
 use utils::{
 	errno,
 	errno::{EResult, Errno},
@@ -65,6 +75,15 @@ pub const TIOCSWINSZ: u32 = 0x00005414;
 /// ioctl request: Returns the number of bytes available on the file descriptor.
 pub const FIONREAD: u32 = 0x0000541b;
 
+// ioctl requests: generic
+
+/// ioctl request: Enables or disables non-blocking mode on the open file description.
+pub const FIONBIO: u32 = 0x00005421;
+/// ioctl request: Clears the close-on-exec flag of the file descriptor.
+pub const FIONCLEX: u32 = 0x00005450;
+/// ioctl request: Sets the close-on-exec flag of the file descriptor.
+pub const FIOCLEX: u32 = 0x00005451;
+
 /// IO directions for ioctl requests.
 #[derive(Eq, PartialEq)]
 pub enum Direction {
@@ -121,12 +140,56 @@ impl Request {
 	}
 }
 
+/// Returns the number of bytes that can be read from the regular file `file` before reaching its
+/// end.
+///
+/// The result is clamped to [`c_int::MAX`] so that it can be returned to userspace.
+fn regular_available(file: &File) -> EResult<usize> {
+	let size = file.stat()?.size;
+	let off = file.off.load(atomic::Ordering::Acquire);
+	Ok(size.saturating_sub(off).min(c_int::MAX as u64) as usize)
+}
+
 pub(super) fn ioctl(
 	Args((fd, request, argp)): Args<(c_int, c_ulong, *const c_void)>,
 	fds: Arc<Mutex<FileDescriptorTable>>,
 ) -> EResult<usize> {
 	let request = Request::from(request);
-	let fds = fds.lock();
-	let file = fds.get_fd(fd)?.get_file();
-	file.ops.ioctl(file, request, argp).map(|v| v as _)
+	let mut fds = fds.lock();
+	// Generic requests, handled the same way for every file
+	match request.get_old_format() {
+		FIONBIO => {
+			let val_ptr = SyscallPtr::<c_int>::from_syscall_arg(argp as usize);
+			let val = val_ptr.copy_from_user()?.ok_or_else(|| errno!(EFAULT))?;
+			let file = fds.get_fd(fd)?.get_file();
+			let flags = file.get_flags();
+			let flags = if val != 0 {
+				flags | O_NONBLOCK
+			} else {
+				flags & !O_NONBLOCK
+			};
+			file.set_flags(flags, true);
+			return Ok(0);
+		}
+		FIOCLEX => {
+			fds.get_fd_mut(fd)?.flags |= FD_CLOEXEC;
+			return Ok(0);
+		}
+		FIONCLEX => {
+			fds.get_fd_mut(fd)?.flags &= !FD_CLOEXEC;
+			return Ok(0);
+		}
+		// Other files are handled by their driver
+		FIONREAD if fds.get_fd(fd)?.get_file().get_type()? == FileType::Regular => {
+			let len = regular_available(fds.get_fd(fd)?.get_file())?;
+			let count_ptr = SyscallPtr::<c_int>::from_syscall_arg(argp as usize);
+			count_ptr.copy_to_user(len as _)?;
+			return Ok(0);
+		}
+		_ => {}
+	}
+	// Do not hold the table while the driver handles the request, since it may need to access it
+	let file = fds.get_fd(fd)?.get_file().clone();
+	drop(fds);
+	file.ops.ioctl(&file, request, argp).map(|v| v as _)
 }
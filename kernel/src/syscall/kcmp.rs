@@ -0,0 +1,156 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The `kcmp` system call allows to tell whether two processes share a kernel resource.
+
+use crate::{
+	crypto::rand,
+	file::perm::AccessProfile,
+	process::{pid::Pid, Process},
+	syscall::Args,
+};
+use core::{cmp::Ordering, ffi::c_int, mem::size_of};
+use utils::{
+	errno,
+	errno::{EResult, Errno},
+	lock::IntMutex,
+	ptr::arc::Arc,
+};
+
+/// Compares open file descriptions, designated by file descriptors.
+const KCMP_FILE: c_int = 0;
+/// Compares memory spaces.
+const KCMP_VM: c_int = 1;
+/// Compares file descriptor tables.
+const KCMP_FILES: c_int = 2;
+/// Compares filesystem information (root, current directory and umask).
+const KCMP_FS: c_int = 3;
+/// Compares signal handlers tables.
+const KCMP_SIGHAND: c_int = 4;
+/// Compares I/O contexts.
+const KCMP_IO: c_int = 5;
+/// Compares System V semaphore undo lists.
+const KCMP_SYSVSEM: c_int = 6;
+/// Compares files registered in an epoll instance.
+const KCMP_EPOLL_TFD: c_int = 7;
+/// The number of resource types.
+const KCMP_TYPES: usize = 8;
+
+/// Random cookies obfuscating the addresses of resources, by resource type, generated on first
+/// use.
+static COOKIES: IntMutex<Option<[[usize; 2]; KCMP_TYPES]>> = IntMutex::new(None);
+
+/// Returns the key to compare instead of the address `addr` of a resource of type `type_`.
+///
+/// Ordering resources by address would leak the layout of kernel memory to userspace. Keys are
+/// obfuscated with random cookies, so that they keep the equality between addresses, but not
+/// their order.
+fn obfuscate(addr: usize, type_: c_int) -> usize {
+	let [xor, mul] = COOKIES.lock().get_or_insert_with(|| {
+		let mut cookies = [[0; 2]; KCMP_TYPES];
+		if let Some(pool) = &mut *rand::ENTROPY_POOL.lock() {
+			for cookie in cookies.iter_mut().flatten() {
+				let mut buf = [0; size_of::<usize>()];
+				pool.read(&mut buf, true);
+				*cookie = usize::from_ne_bytes(buf);
+			}
+		}
+		cookies
+	})[type_ as usize];
+	// An odd multiplier is invertible, so that different addresses get different keys
+	(addr ^ xor).wrapping_mul(mul | 1)
+}
+
+/// Returns the address identifying the resource of type `type_` of the thread with the given
+/// TID.
+///
+/// `idx` is the file descriptor to use with [`KCMP_FILE`]. `ap` is the access profile of the
+/// caller.
+///
+/// A null address means the thread does not have the resource.
+fn get_resource(tid: Pid, type_: c_int, idx: usize, ap: &AccessProfile) -> EResult<usize> {
+	let proc_mutex = Process::get_by_tid(tid).ok_or_else(|| errno!(ESRCH))?;
+	let proc = proc_mutex.lock();
+	if !ap.can_inspect(&proc) {
+		return Err(errno!(EPERM));
+	}
+	let addr = match type_ {
+		KCMP_FILE => {
+			let fds = proc
+				.file_descriptors
+				.as_ref()
+				.ok_or_else(|| errno!(EBADF))?;
+			let fd = c_int::try_from(idx).map_err(|_| errno!(EBADF))?;
+			let fds = fds.lock();
+			fds.get_fd_raw(fd)?.get_file().as_ptr() as usize
+		}
+		KCMP_VM => proc
+			.get_mem_space()
+			.map(|m| m.as_ptr() as usize)
+			.unwrap_or(0),
+		KCMP_FILES => proc
+			.file_descriptors
+			.as_ref()
+			.map(|fds| fds.as_ptr() as usize)
+			.unwrap_or(0),
+		KCMP_FS => proc.fs.as_ptr() as usize,
+		KCMP_SIGHAND => proc.signal_handlers.as_ptr() as usize,
+		// Those resources do not exist on this kernel. Like on Linux when none of the threads has
+		// them, they compare equal
+		KCMP_IO | KCMP_SYSVSEM => 0,
+		KCMP_EPOLL_TFD => return Err(errno!(EOPNOTSUPP)),
+		_ => return Err(errno!(EINVAL)),
+	};
+	Ok(addr)
+}
+
+pub fn kcmp(
+	Args((pid1, pid2, type_, idx1, idx2)): Args<(Pid, Pid, c_int, usize, usize)>,
+	proc: Arc<IntMutex<Process>>,
+) -> EResult<usize> {
+	let (tid1, tid2, ap) = {
+		let proc = proc.lock();
+		let tid1 = proc.pid_to_global(pid1).ok_or_else(|| errno!(ESRCH))?;
+		let tid2 = proc.pid_to_global(pid2).ok_or_else(|| errno!(ESRCH))?;
+		(tid1, tid2, proc.cred.get())
+	};
+	// Each thread is locked in turn, since they may be the same or the caller itself
+	let res1 = obfuscate(get_resource(tid1, type_, idx1, &ap)?, type_);
+	let res2 = obfuscate(get_resource(tid2, type_, idx2, &ap)?, type_);
+	let ord = match res1.cmp(&res2) {
+		Ordering::Equal => 0,
+		Ordering::Less => 1,
+		Ordering::Greater => 2,
+	};
+	Ok(ord)
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test_case]
+	fn kcmp_obfuscate() {
+		for type_ in [KCMP_FILE, KCMP_VM, KCMP_SIGHAND] {
+			let a = obfuscate(0x1000, type_);
+			assert_eq!(obfuscate(0x1000, type_), a);
+			assert_ne!(obfuscate(0x2000, type_), a);
+			assert_ne!(obfuscate(0x1001, type_), a);
+		}
+	}
+}
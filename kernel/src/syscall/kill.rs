@@ -18,105 +18,141 @@
 
 //! The `kill` system call, which allows to send a signal to a process.
 
-use super::{util, Args};
+use super::Args;
 use crate::{
-	process,
-	process::{pid::Pid, regs::Regs, scheduler::SCHEDULER, signal::Signal, Process, State},
+	file::perm::AccessProfile,
+	process::{
+		pid,
+		pid::{Pid, PidNamespace},
+		scheduler::SCHEDULER,
+		signal::Signal,
+		Process, State,
+	},
 };
 use core::ffi::c_int;
 use utils::{
+	collections::vec::Vec,
 	errno,
-	errno::{EResult, Errno},
-	interrupt::cli,
+	errno::{CollectResult, EResult},
+	lock::IntMutex,
+	ptr::arc::Arc,
 };
 
-/// Tries to kill the process with PID `pid` with the signal `sig`.
+/// Sends the signal `sig` to the process `target`.
 ///
-/// If `sig` is `None`, the function doesn't send a signal, but still checks if
-/// there is a process that could be killed.
-fn try_kill(pid: Pid, sig: Option<Signal>) -> EResult<()> {
-	let proc_mutex = Process::current();
-	let mut proc = proc_mutex.lock();
-	let ap = proc.access_profile;
-	// Closure sending the signal
-	let f = |target: &mut Process| {
-		if matches!(target.get_state(), State::Zombie) {
-			return Ok(());
-		}
-		if !ap.can_kill(target) {
-			return Err(errno!(EPERM));
-		}
-		if let Some(sig) = sig {
-			target.kill(sig);
-		}
-		Ok(())
-	};
-	if pid == proc.get_pid() {
-		f(&mut proc)?;
-	} else {
-		let target_mutex = Process::get_by_pid(pid).ok_or_else(|| errno!(ESRCH))?;
-		let mut target_proc = target_mutex.lock();
-		f(&mut target_proc)?;
+/// If `sig` is `None`, the function doesn't send a signal, but still checks the agent with the
+/// access profile `ap` is allowed to.
+fn kill_proc(target: &mut Process, ap: &AccessProfile, sig: Option<Signal>) -> EResult<()> {
+	if matches!(target.get_state(), State::Zombie) {
+		return Ok(());
+	}
+	if !ap.can_kill(target) {
+		return Err(errno!(EPERM));
+	}
+	if let Some(sig) = sig {
+		target.kill(sig);
 	}
 	Ok(())
 }
 
-/// Tries to kill a process group.
+/// Sends the signal `sig` to every process in `targets`.
 ///
-/// Arguments:
-/// - `pid` is the value that determine which process(es) to kill.
-/// - `sig` is the signal to send.
+/// The function succeeds if the signal could be sent to at least one process. Otherwise, it
+/// returns:
+/// - [`errno::EPERM`] if the agent is not allowed to send the signal to any of the processes
+/// - [`errno::ESRCH`] if `targets` is empty
 ///
-/// If `sig` is `None`, the function doesn't send a signal, but still checks if
-/// there is a process that could be killed.
-fn try_kill_group(pid: i32, sig: Option<Signal>) -> EResult<()> {
-	let pgid = match pid {
-		0 => {
-			let proc_mutex = Process::current();
-			let proc = proc_mutex.lock();
-			proc.pgid
+/// If `sig` is `None`, the function doesn't send a signal, but still checks if there is a
+/// process that could be killed.
+fn kill_all(
+	targets: impl IntoIterator<Item = Arc<IntMutex<Process>>>,
+	ap: &AccessProfile,
+	sig: Option<Signal>,
+) -> EResult<()> {
+	let mut found = false;
+	let mut sent = false;
+	for target in targets {
+		found = true;
+		sent |= kill_proc(&mut target.lock(), ap, sig).is_ok();
+	}
+	match (found, sent) {
+		(_, true) => Ok(()),
+		(true, false) => Err(errno!(EPERM)),
+		(false, false) => Err(errno!(ESRCH)),
+	}
+}
+
+/// Returns the processes of the process group `pgid`.
+///
+/// A group exists as long as one of its members does, even if its leader has terminated. The
+/// list is built while holding the scheduler, so that it reflects the state of the process table
+/// at a single point in time.
+fn get_group(pgid: Pid) -> EResult<Vec<Arc<IntMutex<Process>>>> {
+	let sched = SCHEDULER.get().lock();
+	let mut group = Vec::new();
+	for (_, proc_mutex) in sched.iter_process() {
+		let proc = proc_mutex.lock();
+		// Signals are sent to thread groups through their leader
+		if proc.pgid == pgid && proc.is_thread_group_leader() {
+			group.push(proc_mutex.clone())?;
 		}
-		i if i < 0 => -pid as Pid,
-		_ => pid as Pid,
-	};
-	// Kill process group
-	Process::get_by_pid(pgid)
-		.ok_or_else(|| errno!(ESRCH))?
-		.lock()
-		.get_group_processes()
-		.iter()
-		// Avoid deadlock
-		.filter(|pid| **pid != pgid)
-		.try_for_each(|pid| try_kill(*pid as _, sig))?;
-	// Kill process group owner
-	try_kill(pgid, sig)?;
-	Ok(())
+	}
+	Ok(group)
 }
 
-/// Sends the signal `sig` to the processes according to the given value `pid`.
+/// Returns every process visible from the PID namespace `ns`, except the init process of the
+/// namespace and the process with PID `exclude`.
+///
+/// The list is built while holding the scheduler, so that it reflects the state of the process
+/// table at a single point in time.
+fn get_all(ns: Option<&PidNamespace>, exclude: Pid) -> EResult<Vec<Arc<IntMutex<Process>>>> {
+	let sched = SCHEDULER.get().lock();
+	let procs = sched
+		.iter_process()
+		.filter(|(p, _)| **p != exclude)
+		// Excludes processes outside the namespace, and its init
+		.filter(|(p, _)| !matches!(pid::to_local(ns, **p), None | Some(pid::INIT_PID)))
+		.map(|(_, proc)| proc.clone())
+		.collect::<CollectResult<Vec<_>>>()
+		.0?;
+	Ok(procs)
+}
+
+/// Sends the signal `sig` to the processes according to the given value `pid`, which is
+/// relative to the caller's PID namespace:
+/// - If positive, the signal is sent to the process with this PID
+/// - If `0`, the signal is sent to every process in the caller's process group
+/// - If `-1`, the signal is sent to every process the caller is allowed to, except init and the
+///   caller itself
+/// - If lower than `-1`, the signal is sent to every process in the group `-pid`
 ///
 /// If `sig` is `None`, the function doesn't send a signal, but still checks if
 /// there is a process that could be killed.
 fn send_signal(pid: i32, sig: Option<Signal>) -> EResult<()> {
+	let (cur_pid, pgid, ap, ns) = {
+		let proc_mutex = Process::current();
+		let proc = proc_mutex.lock();
+		let ns = proc.get_pid_namespace().cloned();
+		(proc.get_pid(), proc.pgid, proc.cred.get(), ns)
+	};
+	let ns = ns.as_deref();
+	// Translates a PID from the caller's namespace
+	let to_global = |p: u32| -> EResult<Pid> {
+		let p: Pid = p.try_into().map_err(|_| errno!(ESRCH))?;
+		pid::to_global(ns, p).ok_or_else(|| errno!(ESRCH))
+	};
 	match pid {
-		// Kill the process with the given PID
-		1.. => try_kill(pid as _, sig),
-		// Kill all processes in the current process group
-		0 => try_kill_group(0, sig),
-		// Kill all processes for which the current process has the permission
-		-1 => {
-			let sched = SCHEDULER.get().lock();
-			for (pid, _) in sched.iter_process() {
-				if *pid == process::pid::INIT_PID {
-					continue;
-				}
-				// TODO Check permission
-				try_kill(*pid, sig)?;
-			}
-			Ok(())
+		1.. => {
+			let pid = to_global(pid as u32)?;
+			let target = Process::get_by_pid(pid).ok_or_else(|| errno!(ESRCH))?;
+			kill_all([target], &ap, sig)
+		}
+		0 => kill_all(get_group(pgid)?, &ap, sig),
+		-1 => kill_all(get_all(ns, cur_pid)?, &ap, sig),
+		..-1 => {
+			let pgid = to_global(pid.unsigned_abs())?;
+			kill_all(get_group(pgid)?, &ap, sig)
 		}
-		// Kill the given process group
-		..-1 => try_kill_group(-pid as _, sig),
 	}
 }
 
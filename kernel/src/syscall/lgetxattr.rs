@@ -0,0 +1,48 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The `lgetxattr` system call returns the value of an extended attribute of a file, without
+//! following symbolic links.
+
+use crate::{
+	file::vfs::ResolutionSettings,
+	process::mem_space::copy::{SyscallSlice, SyscallString},
+	syscall::Args,
+};
+use utils::errno::EResult;
+
+pub fn lgetxattr(
+	Args((pathname, name, value, size)): Args<(
+		SyscallString,
+		SyscallString,
+		SyscallSlice<u8>,
+		usize,
+	)>,
+	rs: ResolutionSettings,
+) -> EResult<usize> {
+	super::getxattr::do_getxattr(
+		pathname,
+		name,
+		value,
+		size,
+		ResolutionSettings {
+			follow_link: false,
+			..rs
+		},
+	)
+}
@@ -31,7 +31,6 @@ use crate::{
 };
 use core::ffi::c_int;
 use utils::{
-	collections::path::PathBuf,
 	errno,
 	errno::{EResult, Errno},
 	lock::Mutex,
@@ -50,13 +49,11 @@ pub fn linkat(
 	rs: ResolutionSettings,
 ) -> EResult<usize> {
 	let oldpath = oldpath
-		.copy_from_user()?
-		.map(PathBuf::try_from)
-		.ok_or_else(|| errno!(EFAULT))??;
+		.copy_path_from_user()?
+		.ok_or_else(|| errno!(EFAULT))?;
 	let newpath = newpath
-		.copy_from_user()?
-		.map(PathBuf::try_from)
-		.ok_or_else(|| errno!(EFAULT))??;
+		.copy_path_from_user()?
+		.ok_or_else(|| errno!(EFAULT))?;
 	let fds = fds_mutex.lock();
 	let rs = ResolutionSettings {
 		follow_link: false,
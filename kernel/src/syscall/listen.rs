@@ -0,0 +1,36 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The `listen` system call marks a socket as accepting connections.
+
+use crate::{
+	file::{fd::FileDescriptorTable, socket::Socket},
+	syscall::Args,
+};
+use core::ffi::c_int;
+use utils::{errno, errno::EResult, lock::Mutex, ptr::arc::Arc};
+
+pub fn listen(
+	Args((sockfd, backlog)): Args<(c_int, c_int)>,
+	fds: Arc<Mutex<FileDescriptorTable>>,
+) -> EResult<usize> {
+	let file = fds.lock().get_fd(sockfd)?.get_file().clone();
+	let sock: &Socket = file.get_buffer().ok_or_else(|| errno!(ENOTSOCK))?;
+	sock.listen(backlog)?;
+	Ok(0)
+}
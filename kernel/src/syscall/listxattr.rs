@@ -0,0 +1,67 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The `listxattr` system call returns the names of the extended attributes of a file.
+
+use crate::{
+	file::{perm::AccessProfile, vfs, vfs::ResolutionSettings},
+	process::mem_space::copy::{SyscallSlice, SyscallString},
+	syscall::Args,
+};
+use utils::{
+	errno,
+	errno::{EResult, Errno},
+};
+
+/// Writes the names of the extended attributes of the file `file`, each followed by a nul byte.
+///
+/// Arguments:
+/// - `file` is the file
+/// - `list` is the buffer to write the names to
+/// - `size` is the size of the buffer
+/// - `ap` is the access profile to check permissions
+pub fn listxattr_entry(
+	file: &vfs::Entry,
+	list: SyscallSlice<u8>,
+	size: usize,
+	ap: &AccessProfile,
+) -> EResult<usize> {
+	let names = vfs::list_xattr(file, ap)?;
+	super::getxattr::copy_result(&names, list, size)
+}
+
+/// Performs the `listxattr` syscall.
+pub fn do_listxattr(
+	pathname: SyscallString,
+	list: SyscallSlice<u8>,
+	size: usize,
+	rs: ResolutionSettings,
+) -> EResult<usize> {
+	let path = pathname
+		.copy_path_from_user()?
+		.ok_or_else(|| errno!(EFAULT))?;
+	let file = vfs::get_file_from_path(&path, &rs)?;
+	listxattr_entry(&file, list, size, &rs.access_profile)
+}
+
+pub fn listxattr(
+	Args((pathname, list, size)): Args<(SyscallString, SyscallSlice<u8>, usize)>,
+	rs: ResolutionSettings,
+) -> EResult<usize> {
+	do_listxattr(pathname, list, size, rs)
+}
@@ -0,0 +1,64 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The `lseek` system call repositions the offset of a file descriptor.
+//!
+//! The offset belongs to the open file description, which means it is shared with every file
+//! descriptor pointing to it, including in other processes (after `fork` or `dup`).
+
+use super::_llseek::{SEEK_CUR, SEEK_END, SEEK_SET};
+use crate::{file::fd::FileDescriptorTable, syscall::Args};
+use core::{
+	ffi::{c_int, c_uint},
+	sync::atomic,
+};
+use utils::{
+	errno,
+	errno::{EResult, Errno},
+	lock::Mutex,
+	ptr::arc::Arc,
+};
+
+pub fn lseek(
+	Args((fd, offset, whence)): Args<(c_int, isize, c_uint)>,
+	fds_mutex: Arc<Mutex<FileDescriptorTable>>,
+) -> EResult<usize> {
+	let fds = fds_mutex.lock();
+	let file = fds.get_fd(fd)?.get_file();
+	file.check_seekable()?;
+	// Compute the offset
+	let base = match whence {
+		SEEK_SET => 0,
+		SEEK_CUR => file.off.load(atomic::Ordering::Acquire),
+		SEEK_END => file.stat()?.size,
+		_ => return Err(errno!(EINVAL)),
+	};
+	let off = (base as i64)
+		.checked_add(offset as i64)
+		.ok_or_else(|| errno!(EOVERFLOW))?;
+	if off < 0 {
+		return Err(errno!(EINVAL));
+	}
+	// The resulting offset must be representable in the return value
+	if off > isize::MAX as i64 {
+		return Err(errno!(EOVERFLOW));
+	}
+	// Set the new offset
+	file.off.store(off as u64, atomic::Ordering::Release);
+	Ok(off as _)
+}
@@ -0,0 +1,47 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The `lstat64` system call allows to get the status of a file. Contrary to `stat64`, if the
+//! file is a symbolic link, the status of the link itself is returned.
+
+use super::{
+	fstat64::Stat,
+	fstatat64::do_fstatat64,
+	util::at::{AT_FDCWD, AT_SYMLINK_NOFOLLOW},
+};
+use crate::{
+	file::{fd::FileDescriptorTable, vfs::ResolutionSettings},
+	process::mem_space::copy::{SyscallPtr, SyscallString},
+	syscall::Args,
+};
+use utils::{errno::EResult, lock::Mutex, ptr::arc::Arc};
+
+pub fn lstat64(
+	Args((pathname, statbuf)): Args<(SyscallString, SyscallPtr<Stat>)>,
+	fds: Arc<Mutex<FileDescriptorTable>>,
+	rs: ResolutionSettings,
+) -> EResult<usize> {
+	do_fstatat64(
+		AT_FDCWD,
+		pathname,
+		statbuf,
+		AT_SYMLINK_NOFOLLOW,
+		&fds.lock(),
+		rs,
+	)
+}
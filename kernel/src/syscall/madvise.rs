@@ -19,13 +19,57 @@
 //! The `madvise` system call gives advices to the kernel about the usage of
 //! memory in order to allow optimizations.
 
-use crate::syscall::Args;
+use crate::{memory::VirtAddr, process::mem_space::MemSpace, syscall::Args};
 use core::ffi::{c_int, c_void};
-use utils::errno::{EResult, Errno};
+use utils::{errno, errno::EResult, limits::PAGE_SIZE, lock::IntMutex, ptr::arc::Arc};
+
+/// Advice: the pages may be merged with identical pages by Kernel Samepage Merging.
+pub const MADV_MERGEABLE: c_int = 12;
+/// Advice: undo the effect of [`MADV_MERGEABLE`].
+pub const MADV_UNMERGEABLE: c_int = 13;
+/// Advice: the pages are not expected to be accessed in the near future, they may be reclaimed
+/// first under memory pressure.
+pub const MADV_COLD: c_int = 20;
+/// Advice: reclaim the pages.
+pub const MADV_PAGEOUT: c_int = 21;
+
+/// Applies `advice` on the given range of memory.
+///
+/// Arguments:
+/// - `mem_space` is the memory space containing the range
+/// - `addr` is the address of the beginning of the range, which must be page-aligned
+/// - `len` is the length of the range in bytes
+/// - `advice` is the advice to apply
+pub fn do_madvise(
+	mem_space: &IntMutex<MemSpace>,
+	addr: *mut c_void,
+	len: usize,
+	advice: c_int,
+) -> EResult<()> {
+	if !addr.is_aligned_to(PAGE_SIZE) {
+		return Err(errno!(EINVAL));
+	}
+	if (addr as usize).checked_add(len).is_none() {
+		return Err(errno!(EINVAL));
+	}
+	if len == 0 {
+		return Ok(());
+	}
+	let addr = VirtAddr::from(addr);
+	match advice {
+		MADV_MERGEABLE => mem_space.lock().set_mergeable(addr, len, true),
+		MADV_UNMERGEABLE => mem_space.lock().set_mergeable(addr, len, false),
+		MADV_COLD => mem_space.lock().reclaim(addr, len, false),
+		MADV_PAGEOUT => mem_space.lock().reclaim(addr, len, true),
+		// TODO implement other advices
+		_ => Ok(()),
+	}
+}
 
 pub fn madvise(
-	Args((_addr, _length, _advice)): Args<(*mut c_void, usize, c_int)>,
+	Args((addr, length, advice)): Args<(*mut c_void, usize, c_int)>,
+	mem_space: Arc<IntMutex<MemSpace>>,
 ) -> EResult<usize> {
-	// TODO
+	do_madvise(&mem_space, addr, length, advice)?;
 	Ok(0)
 }
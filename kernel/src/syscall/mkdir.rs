@@ -29,7 +29,7 @@ use crate::{
 	},
 };
 use utils::{
-	collections::path::{Path, PathBuf},
+	collections::path::Path,
 	errno,
 	errno::{EResult, Errno},
 };
@@ -39,8 +39,7 @@ pub fn mkdir(
 	rs: ResolutionSettings,
 	umask: Umask,
 ) -> EResult<usize> {
-	let path = pathname.copy_from_user()?.ok_or(errno!(EFAULT))?;
-	let path = PathBuf::try_from(path)?;
+	let path = pathname.copy_path_from_user()?.ok_or(errno!(EFAULT))?;
 	// If the path is not empty, create
 	if let Some(name) = path.file_name() {
 		// Get parent directory
@@ -18,54 +18,76 @@
 
 //! The `mknod` system call allows to create a new node on a filesystem.
 
+use super::util::at;
 use crate::{
 	device::id,
 	file,
-	file::{vfs, vfs::ResolutionSettings, FileType, Stat},
-	process::{mem_space::copy::SyscallString, Process},
+	file::{
+		fd::FileDescriptorTable,
+		vfs,
+		vfs::{ResolutionSettings, Resolved},
+		FileType, Stat,
+	},
+	process::mem_space::copy::SyscallString,
 	syscall::{Args, Umask},
 	time::{
 		clock::{current_time, CLOCK_REALTIME},
 		unit::TimestampScale,
 	},
 };
-use utils::{
-	collections::path::{Path, PathBuf},
-	errno,
-	errno::{EResult, Errno},
-};
+use core::ffi::c_int;
+use utils::{errno, errno::EResult, lock::Mutex, ptr::arc::Arc};
 
-pub fn mknod(
-	Args((pathname, mode, dev)): Args<(SyscallString, file::Mode, u64)>,
+/// Creates a node at `pathname`, relative to `dirfd`.
+///
+/// Arguments:
+/// - `fds` is the file descriptors table.
+/// - `dirfd` is the file descriptor of the directory from which the path is resolved.
+/// - `pathname` is the path of the node to create.
+/// - `mode` is the type and permissions of the node, before applying the umask.
+/// - `dev` is the device number, used only if the node is a device file.
+/// - `umask` is the umask of the process.
+/// - `rs` is the resolution settings.
+pub(super) fn do_mknod(
+	fds: &FileDescriptorTable,
+	dirfd: c_int,
+	pathname: SyscallString,
+	mode: file::Mode,
+	dev: u64,
 	umask: Umask,
 	rs: ResolutionSettings,
 ) -> EResult<usize> {
-	let path = pathname.copy_from_user()?.ok_or(errno!(EFAULT))?;
-	let path = PathBuf::try_from(path)?;
-	let parent_path = path.parent().unwrap_or(Path::root());
-	// File name
-	let Some(name) = path.file_name() else {
-		return Err(errno!(EEXIST));
-	};
+	let path = pathname.copy_path_from_user()?.ok_or(errno!(EFAULT))?;
 	// Check file type and permissions
 	let mode = mode & !umask.0;
-	let file_type = FileType::from_mode(mode).ok_or(errno!(EPERM))?;
+	let file_type = FileType::from_mode(mode).ok_or(errno!(EINVAL))?;
 	let privileged = rs.access_profile.is_privileged();
 	match (file_type, privileged) {
 		(FileType::Regular | FileType::Fifo | FileType::Socket, _) => {}
 		(FileType::BlockDevice | FileType::CharDevice, true) => {}
-		(_, false) => return Err(errno!(EPERM)),
-		(_, true) => return Err(errno!(EINVAL)),
+		(FileType::BlockDevice | FileType::CharDevice, false) => return Err(errno!(EPERM)),
+		_ => return Err(errno!(EINVAL)),
 	}
+	let rs = ResolutionSettings {
+		create: true,
+		follow_link: false,
+		..rs
+	};
+	let Resolved::Creatable {
+		parent,
+		name,
+	} = at::get_file(fds, rs.clone(), dirfd, Some(&path), 0)?
+	else {
+		return Err(errno!(EEXIST));
+	};
 	// Create file
 	let ts = current_time(CLOCK_REALTIME, TimestampScale::Second)?;
-	let parent = vfs::get_file_from_path(parent_path, &rs)?;
 	vfs::create_file(
 		parent,
 		name,
 		&rs.access_profile,
 		Stat {
-			mode,
+			mode: file_type.to_mode() | (mode & 0o7777),
 			dev_major: id::major(dev),
 			dev_minor: id::minor(dev),
 			ctime: ts,
@@ -76,3 +98,12 @@ pub fn mknod(
 	)?;
 	Ok(0)
 }
+
+pub fn mknod(
+	Args((pathname, mode, dev)): Args<(SyscallString, file::Mode, u64)>,
+	umask: Umask,
+	rs: ResolutionSettings,
+	fds: Arc<Mutex<FileDescriptorTable>>,
+) -> EResult<usize> {
+	do_mknod(&fds.lock(), at::AT_FDCWD, pathname, mode, dev, umask, rs)
+}
@@ -0,0 +1,39 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The `mknodat` system call allows to create a new node on a filesystem, relative to a
+//! directory.
+
+use super::mknod::do_mknod;
+use crate::{
+	file,
+	file::{fd::FileDescriptorTable, vfs::ResolutionSettings},
+	process::mem_space::copy::SyscallString,
+	syscall::{Args, Umask},
+};
+use core::ffi::c_int;
+use utils::{errno::EResult, lock::Mutex, ptr::arc::Arc};
+
+pub fn mknodat(
+	Args((dirfd, pathname, mode, dev)): Args<(c_int, SyscallString, file::Mode, u64)>,
+	umask: Umask,
+	rs: ResolutionSettings,
+	fds: Arc<Mutex<FileDescriptorTable>>,
+) -> EResult<usize> {
+	do_mknod(&fds.lock(), dirfd, pathname, mode, dev, umask, rs)
+}
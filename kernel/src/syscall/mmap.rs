@@ -50,9 +50,15 @@ pub const PROT_WRITE: i32 = 0b010;
 pub const PROT_EXEC: i32 = 0b100;
 
 /// Changes are shared.
-const MAP_SHARED: i32 = 0b001;
+const MAP_SHARED: i32 = 0x01;
+/// Changes are private.
+const MAP_PRIVATE: i32 = 0x02;
+/// Mask of the mapping type.
+const MAP_TYPE: i32 = 0x0f;
 /// Interpret addr exactly.
-const MAP_FIXED: i32 = 0b010;
+const MAP_FIXED: i32 = 0x10;
+/// The mapping is not backed by a file.
+const MAP_ANONYMOUS: i32 = 0x20;
 
 /// Converts mmap's `flags` and `prot` to mem space mapping flags.
 fn get_flags(flags: i32, prot: i32) -> u8 {
@@ -86,14 +92,21 @@ pub fn do_mmap(
 	if !addr.is_aligned_to(PAGE_SIZE) || length == 0 {
 		return Err(errno!(EINVAL));
 	}
+	if !matches!(flags & MAP_TYPE, MAP_SHARED | MAP_PRIVATE) {
+		return Err(errno!(EINVAL));
+	}
 	// The length in number of pages
 	let pages = length.div_ceil(PAGE_SIZE);
 	let Some(pages) = NonZeroUsize::new(pages) else {
 		return Err(errno!(EINVAL));
 	};
 	// Check for overflow
-	if unlikely(addr.0.checked_add(pages.get() * PAGE_SIZE).is_none()) {
-		return Err(errno!(EINVAL));
+	let end = pages
+		.get()
+		.checked_mul(PAGE_SIZE)
+		.and_then(|len| addr.0.checked_add(len));
+	if unlikely(end.is_none()) {
+		return Err(errno!(ENOMEM));
 	}
 	let constraint = {
 		if !addr.is_null() {
@@ -107,7 +120,7 @@ pub fn do_mmap(
 		}
 	};
 	// The file the mapping points to
-	let file_mutex = if fd >= 0 {
+	let file_mutex = if flags & MAP_ANONYMOUS == 0 {
 		// Check the alignment of the offset
 		if offset as usize % PAGE_SIZE != 0 {
 			return Err(errno!(EINVAL));
@@ -117,20 +130,21 @@ pub fn do_mmap(
 	} else {
 		None
 	};
-	// TODO anon flag
 	// Get residence
 	let residence = match file_mutex {
 		Some(file) => {
 			let stat = file.stat()?;
 			// Check the file is suitable
 			if stat.get_type() != Some(FileType::Regular) {
-				return Err(errno!(EACCES));
+				return Err(errno!(ENODEV));
 			}
-			if prot & PROT_READ != 0 && !ap.can_read_file(&stat) {
-				return Err(errno!(EPERM));
+			// Access is checked against the open file description, not the file's permissions
+			if !file.can_read() {
+				return Err(errno!(EACCES));
 			}
-			if prot & PROT_WRITE != 0 && !ap.can_write_file(&stat) {
-				return Err(errno!(EPERM));
+			// Writing to a private mapping does not modify the file
+			if flags & MAP_SHARED != 0 && prot & PROT_WRITE != 0 && !file.can_write() {
+				return Err(errno!(EACCES));
 			}
 			if prot & PROT_EXEC != 0 && !ap.can_execute_file(&stat) {
 				return Err(errno!(EPERM));
@@ -140,10 +154,7 @@ pub fn do_mmap(
 				off: offset,
 			}
 		}
-		None => {
-			// TODO If the mapping requires a fd, return an error
-			MapResidence::Normal
-		}
+		None => MapResidence::Normal,
 	};
 	let flags = get_flags(flags, prot);
 	let mut mem_space = mem_space.lock();
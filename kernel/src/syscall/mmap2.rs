@@ -28,6 +28,7 @@ use crate::{
 use core::ffi::{c_int, c_void};
 use utils::{
 	errno::{EResult, Errno},
+	limits::PAGE_SIZE,
 	lock::{IntMutex, Mutex},
 	ptr::arc::Arc,
 };
@@ -51,7 +52,7 @@ pub fn mmap2(
 		prot,
 		flags,
 		fd,
-		offset * 4096,
+		offset * PAGE_SIZE as u64,
 		fds,
 		ap,
 		mem_space,
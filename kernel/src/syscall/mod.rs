@@ -26,6 +26,7 @@
 mod _exit;
 mod _llseek;
 mod _newselect;
+mod accept4;
 mod access;
 mod arch_prctl;
 mod bind;
@@ -35,37 +36,60 @@ mod chdir;
 mod chmod;
 mod chown;
 mod chroot;
+mod clock_getres;
+mod clock_getres_time64;
 mod clock_gettime;
 mod clock_gettime64;
 mod clone;
+mod clone3;
 mod close;
 mod connect;
 mod creat;
 mod delete_module;
 mod dup;
 mod dup2;
+mod eventfd;
+mod eventfd2;
 mod execve;
 mod exit_group;
 mod faccessat;
 mod faccessat2;
 mod fadvise64_64;
+mod fallocate;
+mod fanotify_init;
+mod fanotify_mark;
 mod fchdir;
 mod fchmod;
 mod fchmodat;
+mod fchown;
+mod fchownat;
 mod fcntl;
 mod fcntl64;
+mod fgetxattr;
 mod finit_module;
+mod flistxattr;
 mod fork;
+mod fremovexattr;
+mod fsetxattr;
 mod fstat64;
+mod fstatat64;
 mod fstatfs;
 mod fstatfs64;
 mod fsync;
+mod ftruncate;
+mod ftruncate64;
+mod futex;
+mod futex_time64;
+mod getcpu;
 mod getcwd;
 mod getdents;
 mod getdents64;
 mod getegid;
 mod geteuid;
 mod getgid;
+mod getgroups;
+mod getgroups32;
+mod getpeername;
 mod getpgid;
 mod getpid;
 mod getppid;
@@ -77,48 +101,83 @@ mod getsockname;
 mod getsockopt;
 mod gettid;
 mod getuid;
+mod getxattr;
 mod init_module;
+mod inotify_add_watch;
+mod inotify_init;
+mod inotify_init1;
+mod inotify_rm_watch;
 pub mod ioctl;
+mod kcmp;
 mod kill;
 mod lchown;
+mod lgetxattr;
 mod link;
 mod linkat;
+mod listen;
+mod listxattr;
+mod llistxattr;
+mod lremovexattr;
+mod lseek;
+mod lsetxattr;
+mod lstat64;
 mod madvise;
 mod mkdir;
 mod mknod;
+mod mknodat;
 mod mmap;
 mod mmap2;
 mod mount;
 mod mprotect;
 mod msync;
 mod munmap;
+mod name_to_handle_at;
 mod nanosleep;
 mod open;
+mod open_by_handle_at;
 mod openat;
+mod personality;
+mod pidfd_open;
+mod pidfd_send_signal;
 mod pipe;
 mod pipe2;
+mod pivot_root;
 pub mod poll;
+mod ppoll;
+mod ppoll_time64;
+mod pread64;
 mod preadv;
 mod preadv2;
 mod prlimit64;
+mod process_madvise;
 mod pselect6;
+mod pselect6_time64;
+mod pwrite64;
 mod pwritev;
 mod pwritev2;
 mod read;
 mod readlink;
 mod readv;
 mod reboot;
+mod recvmsg;
+mod removexattr;
 mod rename;
+mod renameat;
 mod renameat2;
 mod rmdir;
 mod rt_sigaction;
 mod rt_sigprocmask;
 mod sched_yield;
 mod select;
+mod sendfile;
+mod sendfile64;
+mod sendmsg;
 mod sendto;
 mod set_thread_area;
 mod set_tid_address;
 mod setgid;
+mod setgroups;
+mod setgroups32;
 mod sethostname;
 mod setpgid;
 mod setregid;
@@ -127,33 +186,51 @@ mod setresuid;
 mod setreuid;
 mod setsockopt;
 mod setuid;
+mod setxattr;
 mod shutdown;
 mod signal;
+mod signalfd;
+mod signalfd4;
 mod sigreturn;
 mod socket;
 mod socketpair;
+mod stat64;
 mod statfs;
 mod statfs64;
 mod statx;
 mod symlink;
 mod symlinkat;
+mod sync;
 mod syncfs;
 mod time;
 mod timer_create;
 mod timer_delete;
+mod timer_gettime;
+mod timer_gettime64;
 mod timer_settime;
+mod timer_settime64;
+mod timerfd_create;
+mod timerfd_gettime;
+mod timerfd_gettime64;
+mod timerfd_settime;
+mod timerfd_settime64;
 mod tkill;
 mod truncate;
+mod truncate64;
 mod umask;
 mod umount;
+mod umount2;
 mod uname;
+pub mod unimplemented;
 mod unlink;
 mod unlinkat;
+mod unshare;
 mod util;
 mod utimensat;
 mod vfork;
 mod wait;
 mod wait4;
+mod waitid;
 mod waitpid;
 mod write;
 mod writev;
@@ -168,6 +245,7 @@ use crate::{
 use _exit::_exit;
 use _llseek::_llseek;
 use _newselect::_newselect;
+use accept4::accept4;
 use access::access;
 use arch_prctl::arch_prctl;
 use bind::bind;
@@ -176,9 +254,12 @@ use chdir::chdir;
 use chmod::chmod;
 use chown::chown;
 use chroot::chroot;
+use clock_getres::clock_getres;
+use clock_getres_time64::clock_getres_time64;
 use clock_gettime::clock_gettime;
 use clock_gettime64::clock_gettime64;
 use clone::clone;
+use clone3::clone3;
 use close::close;
 use connect::connect;
 use core::{fmt, ptr};
@@ -186,28 +267,48 @@ use creat::creat;
 use delete_module::delete_module;
 use dup::dup;
 use dup2::dup2;
+use eventfd::eventfd;
+use eventfd2::eventfd2;
 use execve::execve;
 use exit_group::exit_group;
 use faccessat::faccessat;
 use faccessat2::faccessat2;
 use fadvise64_64::fadvise64_64;
+use fallocate::fallocate;
+use fanotify_init::fanotify_init;
+use fanotify_mark::fanotify_mark;
 use fchdir::fchdir;
 use fchmod::fchmod;
 use fchmodat::fchmodat;
+use fchown::fchown;
+use fchownat::fchownat;
 use fcntl::fcntl;
 use fcntl64::fcntl64;
+use fgetxattr::fgetxattr;
 use finit_module::finit_module;
+use flistxattr::flistxattr;
 use fork::fork;
+use fremovexattr::fremovexattr;
+use fsetxattr::fsetxattr;
 use fstat64::fstat64;
+use fstatat64::fstatat64;
 use fstatfs::fstatfs;
 use fstatfs64::fstatfs64;
 use fsync::fsync;
+use ftruncate::ftruncate;
+use ftruncate64::ftruncate64;
+use futex::futex;
+use futex_time64::futex_time64;
+use getcpu::getcpu;
 use getcwd::getcwd;
 use getdents::getdents;
 use getdents64::getdents64;
 use getegid::getegid;
 use geteuid::geteuid;
 use getgid::getgid;
+use getgroups::getgroups;
+use getgroups32::getgroups32;
+use getpeername::getpeername;
 use getpgid::getpgid;
 use getpid::getpid;
 use getppid::getppid;
@@ -219,31 +320,58 @@ use getsockname::getsockname;
 use getsockopt::getsockopt;
 use gettid::gettid;
 use getuid::getuid;
+use getxattr::getxattr;
 use init_module::init_module;
+use inotify_add_watch::inotify_add_watch;
+use inotify_init::inotify_init;
+use inotify_init1::inotify_init1;
+use inotify_rm_watch::inotify_rm_watch;
 use ioctl::ioctl;
+use kcmp::kcmp;
 use kill::kill;
 use lchown::lchown;
+use lgetxattr::lgetxattr;
 use link::link;
 use linkat::linkat;
+use listen::listen;
+use listxattr::listxattr;
+use llistxattr::llistxattr;
+use lremovexattr::lremovexattr;
+use lseek::lseek;
+use lsetxattr::lsetxattr;
+use lstat64::lstat64;
 use madvise::madvise;
 use mkdir::mkdir;
 use mknod::mknod;
+use mknodat::mknodat;
 use mmap::mmap;
 use mmap2::mmap2;
 use mount::mount;
 use mprotect::mprotect;
 use msync::msync;
 use munmap::munmap;
+use name_to_handle_at::name_to_handle_at;
 use nanosleep::nanosleep;
 use open::open;
+use open_by_handle_at::open_by_handle_at;
 use openat::openat;
+use personality::personality;
+use pidfd_open::pidfd_open;
+use pidfd_send_signal::pidfd_send_signal;
 use pipe::pipe;
 use pipe2::pipe2;
+use pivot_root::pivot_root;
 use poll::poll;
+use ppoll::ppoll;
+use ppoll_time64::ppoll_time64;
+use pread64::pread64;
 use preadv::preadv;
 use preadv2::preadv2;
 use prlimit64::prlimit64;
+use process_madvise::process_madvise;
 use pselect6::pselect6;
+use pselect6_time64::pselect6_time64;
+use pwrite64::pwrite64;
 use pwritev::pwritev;
 use pwritev2::pwritev2;
 use r#break::r#break;
@@ -251,17 +379,25 @@ use read::read;
 use readlink::readlink;
 use readv::readv;
 use reboot::reboot;
+use recvmsg::recvmsg;
+use removexattr::removexattr;
 use rename::rename;
+use renameat::renameat;
 use renameat2::renameat2;
 use rmdir::rmdir;
 use rt_sigaction::rt_sigaction;
 use rt_sigprocmask::rt_sigprocmask;
 use sched_yield::sched_yield;
 use select::select;
+use sendfile::sendfile;
+use sendfile64::sendfile64;
+use sendmsg::sendmsg;
 use sendto::sendto;
 use set_thread_area::set_thread_area;
 use set_tid_address::set_tid_address;
 use setgid::setgid;
+use setgroups::setgroups;
+use setgroups32::setgroups32;
 use sethostname::sethostname;
 use setpgid::setpgid;
 use setregid::setregid;
@@ -270,29 +406,46 @@ use setresuid::setresuid;
 use setreuid::setreuid;
 use setsockopt::setsockopt;
 use setuid::setuid;
+use setxattr::setxattr;
 use shutdown::shutdown;
 use signal::signal;
+use signalfd::signalfd;
+use signalfd4::signalfd4;
 use sigreturn::sigreturn;
 use socket::socket;
 use socketpair::socketpair;
+use stat64::stat64;
 use statfs::statfs;
 use statfs64::statfs64;
 use statx::statx;
 use symlink::symlink;
 use symlinkat::symlinkat;
+use sync::sync;
 use syncfs::syncfs;
 use time::time;
 use timer_create::timer_create;
 use timer_delete::timer_delete;
+use timer_gettime::timer_gettime;
+use timer_gettime64::timer_gettime64;
 use timer_settime::timer_settime;
+use timer_settime64::timer_settime64;
+use timerfd_create::timerfd_create;
+use timerfd_gettime::timerfd_gettime;
+use timerfd_gettime64::timerfd_gettime64;
+use timerfd_settime::timerfd_settime;
+use timerfd_settime64::timerfd_settime64;
 use tkill::tkill;
 use truncate::truncate;
+use truncate64::truncate64;
 use umask::umask;
 use umount::umount;
+use umount2::umount2;
 use uname::uname;
 use unlink::unlink;
 use unlinkat::unlinkat;
+use unshare::unshare;
 use utils::{
+	errno,
 	errno::EResult,
 	lock::{IntMutex, Mutex},
 	ptr::arc::Arc,
@@ -300,6 +453,7 @@ use utils::{
 use utimensat::utimensat;
 use vfork::vfork;
 use wait4::wait4;
+use waitid::waitid;
 use waitpid::waitpid;
 use write::write;
 use writev::writev;
@@ -385,7 +539,7 @@ impl FromSyscall<'_> for Arc<Mutex<FileDescriptorTable>> {
 
 impl FromSyscall<'_> for AccessProfile {
 	fn from_syscall(_regs: &Regs) -> Self {
-		Process::current().lock().access_profile
+		Process::current().lock().cred.get()
 	}
 }
 
@@ -400,7 +554,7 @@ pub struct Umask(file::Mode);
 
 impl FromSyscall<'_> for Umask {
 	fn from_syscall(_regs: &Regs) -> Self {
-		Self(Process::current().lock().umask)
+		Self(Process::current().lock().fs.lock().umask)
 	}
 }
 
@@ -526,7 +680,7 @@ fn do_syscall(id: usize, regs: &Regs) -> Option<EResult<usize>> {
 		0x010 => Some(syscall!(lchown, regs)),
 		0x011 => Some(syscall!(r#break, regs)),
 		// TODO 0x012 => Some(syscall!(oldstat, regs)),
-		// TODO 0x013 => Some(syscall!(lseek, regs)),
+		0x013 => Some(syscall!(lseek, regs)),
 		0x014 => Some(syscall!(getpid, regs)),
 		0x015 => Some(syscall!(mount, regs)),
 		0x016 => Some(syscall!(umount, regs)),
@@ -543,7 +697,7 @@ fn do_syscall(id: usize, regs: &Regs) -> Option<EResult<usize>> {
 		0x021 => Some(syscall!(access, regs)),
 		// TODO 0x022 => Some(syscall!(nice, regs)),
 		// TODO 0x023 => Some(syscall!(ftime, regs)),
-		// TODO 0x024 => Some(syscall!(sync, regs)),
+		0x024 => Some(syscall!(sync, regs)),
 		0x025 => Some(syscall!(kill, regs)),
 		0x026 => Some(syscall!(rename, regs)),
 		0x027 => Some(syscall!(mkdir, regs)),
@@ -559,7 +713,7 @@ fn do_syscall(id: usize, regs: &Regs) -> Option<EResult<usize>> {
 		0x031 => Some(syscall!(geteuid, regs)),
 		0x032 => Some(syscall!(getegid, regs)),
 		// TODO 0x033 => Some(syscall!(acct, regs)),
-		// TODO 0x034 => Some(syscall!(umount2, regs)),
+		0x034 => Some(syscall!(umount2, regs)),
 		// TODO 0x035 => Some(syscall!(lock, regs)),
 		0x036 => Some(syscall!(ioctl, regs)),
 		0x037 => Some(syscall!(fcntl, regs)),
@@ -587,8 +741,8 @@ fn do_syscall(id: usize, regs: &Regs) -> Option<EResult<usize>> {
 		0x04d => Some(syscall!(getrusage, regs)),
 		// TODO 0x04e => Some(syscall!(gettimeofday, regs)),
 		// TODO 0x04f => Some(syscall!(settimeofday, regs)),
-		// TODO 0x050 => Some(syscall!(getgroups, regs)),
-		// TODO 0x051 => Some(syscall!(setgroups, regs)),
+		0x050 => Some(syscall!(getgroups, regs)),
+		0x051 => Some(syscall!(setgroups, regs)),
 		0x052 => Some(syscall!(select, regs)),
 		0x053 => Some(syscall!(symlink, regs)),
 		// TODO 0x054 => Some(syscall!(oldlstat, regs)),
@@ -600,9 +754,9 @@ fn do_syscall(id: usize, regs: &Regs) -> Option<EResult<usize>> {
 		0x05a => Some(syscall!(mmap, regs)),
 		0x05b => Some(syscall!(munmap, regs)),
 		0x05c => Some(syscall!(truncate, regs)),
-		// TODO 0x05d => Some(syscall!(ftruncate, regs)),
+		0x05d => Some(syscall!(ftruncate, regs)),
 		0x05e => Some(syscall!(fchmod, regs)),
-		// TODO 0x05f => Some(syscall!(fchown, regs)),
+		0x05f => Some(syscall!(fchown, regs)),
 		// TODO 0x060 => Some(syscall!(getpriority, regs)),
 		// TODO 0x061 => Some(syscall!(setpriority, regs)),
 		// TODO 0x062 => Some(syscall!(profil, regs)),
@@ -641,7 +795,7 @@ fn do_syscall(id: usize, regs: &Regs) -> Option<EResult<usize>> {
 		0x085 => Some(syscall!(fchdir, regs)),
 		// TODO 0x086 => Some(syscall!(bdflush, regs)),
 		// TODO 0x087 => Some(syscall!(sysfs, regs)),
-		// TODO 0x088 => Some(syscall!(personality, regs)),
+		0x088 => Some(syscall!(personality, regs)),
 		// TODO 0x089 => Some(syscall!(afs_syscall, regs)),
 		// TODO 0x08a => Some(syscall!(setfsuid, regs)),
 		// TODO 0x08b => Some(syscall!(setfsgid, regs)),
@@ -685,34 +839,34 @@ fn do_syscall(id: usize, regs: &Regs) -> Option<EResult<usize>> {
 		// TODO 0x0b1 => Some(syscall!(rt_sigtimedwait, regs)),
 		// TODO 0x0b2 => Some(syscall!(rt_sigqueueinfo, regs)),
 		// TODO 0x0b3 => Some(syscall!(rt_sigsuspend, regs)),
-		// TODO 0x0b4 => Some(syscall!(pread64, regs)),
-		// TODO 0x0b5 => Some(syscall!(pwrite64, regs)),
+		0x0b4 => Some(syscall!(pread64, regs)),
+		0x0b5 => Some(syscall!(pwrite64, regs)),
 		0x0b6 => Some(syscall!(chown, regs)),
 		0x0b7 => Some(syscall!(getcwd, regs)),
 		// TODO 0x0b8 => Some(syscall!(capget, regs)),
 		// TODO 0x0b9 => Some(syscall!(capset, regs)),
 		// TODO 0x0ba => Some(syscall!(sigaltstack, regs)),
-		// TODO 0x0bb => Some(syscall!(sendfile, regs)),
+		0x0bb => Some(syscall!(sendfile, regs)),
 		// TODO 0x0bc => Some(syscall!(getpmsg, regs)),
 		// TODO 0x0bd => Some(syscall!(putpmsg, regs)),
 		0x0be => Some(syscall!(vfork, regs)),
 		// TODO 0x0bf => Some(syscall!(ugetrlimit, regs)),
 		0x0c0 => Some(syscall!(mmap2, regs)),
-		// TODO 0x0c1 => Some(syscall!(truncate64, regs)),
-		// TODO 0x0c2 => Some(syscall!(ftruncate64, regs)),
-		// TODO 0x0c3 => Some(syscall!(stat64, regs)),
-		// TODO 0x0c4 => Some(syscall!(lstat64, regs)),
+		0x0c1 => Some(syscall!(truncate64, regs)),
+		0x0c2 => Some(syscall!(ftruncate64, regs)),
+		0x0c3 => Some(syscall!(stat64, regs)),
+		0x0c4 => Some(syscall!(lstat64, regs)),
 		0x0c5 => Some(syscall!(fstat64, regs)),
-		// TODO 0x0c6 => Some(syscall!(lchown32, regs)),
+		0x0c6 => Some(syscall!(lchown, regs)),   // lchown32
 		0x0c7 => Some(syscall!(getuid, regs)),   // getuid32
 		0x0c8 => Some(syscall!(getgid, regs)),   // getgid32
 		0x0c9 => Some(syscall!(geteuid, regs)),  // geteuid32
 		0x0ca => Some(syscall!(getegid, regs)),  // getegid32
 		0x0cb => Some(syscall!(setreuid, regs)), // setreuid32
 		0x0cc => Some(syscall!(setregid, regs)), // setregid32
-		// TODO 0x0cd => Some(syscall!(getgroups32, regs)),
-		// TODO 0x0ce => Some(syscall!(setgroups32, regs)),
-		// TODO 0x0cf => Some(syscall!(fchown32, regs)),
+		0x0cd => Some(syscall!(getgroups32, regs)),
+		0x0ce => Some(syscall!(setgroups32, regs)),
+		0x0cf => Some(syscall!(fchown, regs)),    // fchown32
 		0x0d0 => Some(syscall!(setresuid, regs)), // setresuid32
 		0x0d1 => Some(syscall!(getresuid, regs)), // getresuid32
 		0x0d2 => Some(syscall!(setresgid, regs)), // setresgid32
@@ -722,28 +876,28 @@ fn do_syscall(id: usize, regs: &Regs) -> Option<EResult<usize>> {
 		0x0d6 => Some(syscall!(setgid, regs)),    // setgid32
 		// TODO 0x0d7 => Some(syscall!(setfsuid32, regs)),
 		// TODO 0x0d8 => Some(syscall!(setfsgid32, regs)),
-		// TODO 0x0d9 => Some(syscall!(pivot_root, regs)),
+		0x0d9 => Some(syscall!(pivot_root, regs)),
 		// TODO 0x0da => Some(syscall!(mincore, regs)),
 		0x0db => Some(syscall!(madvise, regs)),
 		0x0dc => Some(syscall!(getdents64, regs)),
 		0x0dd => Some(syscall!(fcntl64, regs)),
 		0x0e0 => Some(syscall!(gettid, regs)),
 		// TODO 0x0e1 => Some(syscall!(readahead, regs)),
-		// TODO 0x0e2 => Some(syscall!(setxattr, regs)),
-		// TODO 0x0e3 => Some(syscall!(lsetxattr, regs)),
-		// TODO 0x0e4 => Some(syscall!(fsetxattr, regs)),
-		// TODO 0x0e5 => Some(syscall!(getxattr, regs)),
-		// TODO 0x0e6 => Some(syscall!(lgetxattr, regs)),
-		// TODO 0x0e7 => Some(syscall!(fgetxattr, regs)),
-		// TODO 0x0e8 => Some(syscall!(listxattr, regs)),
-		// TODO 0x0e9 => Some(syscall!(llistxattr, regs)),
-		// TODO 0x0ea => Some(syscall!(flistxattr, regs)),
-		// TODO 0x0eb => Some(syscall!(removexattr, regs)),
-		// TODO 0x0ec => Some(syscall!(lremovexattr, regs)),
-		// TODO 0x0ed => Some(syscall!(fremovexattr, regs)),
+		0x0e2 => Some(syscall!(setxattr, regs)),
+		0x0e3 => Some(syscall!(lsetxattr, regs)),
+		0x0e4 => Some(syscall!(fsetxattr, regs)),
+		0x0e5 => Some(syscall!(getxattr, regs)),
+		0x0e6 => Some(syscall!(lgetxattr, regs)),
+		0x0e7 => Some(syscall!(fgetxattr, regs)),
+		0x0e8 => Some(syscall!(listxattr, regs)),
+		0x0e9 => Some(syscall!(llistxattr, regs)),
+		0x0ea => Some(syscall!(flistxattr, regs)),
+		0x0eb => Some(syscall!(removexattr, regs)),
+		0x0ec => Some(syscall!(lremovexattr, regs)),
+		0x0ed => Some(syscall!(fremovexattr, regs)),
 		0x0ee => Some(syscall!(tkill, regs)),
-		// TODO 0x0ef => Some(syscall!(sendfile64, regs)),
-		// TODO 0x0f0 => Some(syscall!(futex, regs)),
+		0x0ef => Some(syscall!(sendfile64, regs)),
+		0x0f0 => Some(syscall!(futex, regs)),
 		// TODO 0x0f1 => Some(syscall!(sched_setaffinity, regs)),
 		// TODO 0x0f2 => Some(syscall!(sched_getaffinity, regs)),
 		0x0f3 => Some(syscall!(set_thread_area, regs)),
@@ -763,12 +917,12 @@ fn do_syscall(id: usize, regs: &Regs) -> Option<EResult<usize>> {
 		0x102 => Some(syscall!(set_tid_address, regs)),
 		0x103 => Some(syscall!(timer_create, regs)),
 		0x104 => Some(syscall!(timer_settime, regs)),
-		// TODO 0x105 => Some(syscall!(timer_gettime, regs)),
+		0x105 => Some(syscall!(timer_gettime, regs)),
 		// TODO 0x106 => Some(syscall!(timer_getoverrun, regs)),
 		0x107 => Some(syscall!(timer_delete, regs)),
 		// TODO 0x108 => Some(syscall!(clock_settime, regs)),
 		0x109 => Some(syscall!(clock_gettime, regs)),
-		// TODO 0x10a => Some(syscall!(clock_getres, regs)),
+		0x10a => Some(syscall!(clock_getres, regs)),
 		// TODO 0x10b => Some(syscall!(clock_nanosleep, regs)),
 		0x10c => Some(syscall!(statfs64, regs)),
 		0x10d => Some(syscall!(fstatfs64, regs)),
@@ -786,32 +940,32 @@ fn do_syscall(id: usize, regs: &Regs) -> Option<EResult<usize>> {
 		// TODO 0x119 => Some(syscall!(mq_notify, regs)),
 		// TODO 0x11a => Some(syscall!(mq_getsetattr, regs)),
 		// TODO 0x11b => Some(syscall!(kexec_load, regs)),
-		// TODO 0x11c => Some(syscall!(waitid, regs)),
+		0x11c => Some(syscall!(waitid, regs)),
 		// TODO 0x11e => Some(syscall!(add_key, regs)),
 		// TODO 0x11f => Some(syscall!(request_key, regs)),
 		// TODO 0x120 => Some(syscall!(keyctl, regs)),
 		// TODO 0x121 => Some(syscall!(ioprio_set, regs)),
 		// TODO 0x122 => Some(syscall!(ioprio_get, regs)),
-		// TODO 0x123 => Some(syscall!(inotify_init, regs)),
-		// TODO 0x124 => Some(syscall!(inotify_add_watch, regs)),
-		// TODO 0x125 => Some(syscall!(inotify_rm_watch, regs)),
+		0x123 => Some(syscall!(inotify_init, regs)),
+		0x124 => Some(syscall!(inotify_add_watch, regs)),
+		0x125 => Some(syscall!(inotify_rm_watch, regs)),
 		// TODO 0x126 => Some(syscall!(migrate_pages, regs)),
 		0x127 => Some(syscall!(openat, regs)),
 		// TODO 0x128 => Some(syscall!(mkdirat, regs)),
-		// TODO 0x129 => Some(syscall!(mknodat, regs)),
-		// TODO 0x12a => Some(syscall!(fchownat, regs)),
+		0x129 => Some(syscall!(mknodat, regs)),
+		0x12a => Some(syscall!(fchownat, regs)),
 		// TODO 0x12b => Some(syscall!(futimesat, regs)),
-		// TODO 0x12c => Some(syscall!(fstatat64, regs)),
+		0x12c => Some(syscall!(fstatat64, regs)),
 		0x12d => Some(syscall!(unlinkat, regs)),
-		// TODO 0x12e => Some(syscall!(renameat, regs)),
+		0x12e => Some(syscall!(renameat, regs)),
 		0x12f => Some(syscall!(linkat, regs)),
 		0x130 => Some(syscall!(symlinkat, regs)),
 		// TODO 0x131 => Some(syscall!(readlinkat, regs)),
 		0x132 => Some(syscall!(fchmodat, regs)),
 		0x133 => Some(syscall!(faccessat, regs)),
 		0x134 => Some(syscall!(pselect6, regs)),
-		// TODO 0x135 => Some(syscall!(ppoll, regs)),
-		// TODO 0x136 => Some(syscall!(unshare, regs)),
+		0x135 => Some(syscall!(ppoll, regs)),
+		0x136 => Some(syscall!(unshare, regs)),
 		// TODO 0x137 => Some(syscall!(set_robust_list, regs)),
 		// TODO 0x138 => Some(syscall!(get_robust_list, regs)),
 		// TODO 0x139 => Some(syscall!(splice, regs)),
@@ -819,38 +973,38 @@ fn do_syscall(id: usize, regs: &Regs) -> Option<EResult<usize>> {
 		// TODO 0x13b => Some(syscall!(tee, regs)),
 		// TODO 0x13c => Some(syscall!(vmsplice, regs)),
 		// TODO 0x13d => Some(syscall!(move_pages, regs)),
-		// TODO 0x13e => Some(syscall!(getcpu, regs)),
+		0x13e => Some(syscall!(getcpu, regs)),
 		// TODO 0x13f => Some(syscall!(epoll_pwait, regs)),
 		0x140 => Some(syscall!(utimensat, regs)),
-		// TODO 0x141 => Some(syscall!(signalfd, regs)),
-		// TODO 0x142 => Some(syscall!(timerfd_create, regs)),
-		// TODO 0x143 => Some(syscall!(eventfd, regs)),
-		// TODO 0x144 => Some(syscall!(fallocate, regs)),
-		// TODO 0x145 => Some(syscall!(timerfd_settime, regs)),
-		// TODO 0x146 => Some(syscall!(timerfd_gettime, regs)),
-		// TODO 0x147 => Some(syscall!(signalfd4, regs)),
-		// TODO 0x148 => Some(syscall!(eventfd2, regs)),
+		0x141 => Some(syscall!(signalfd, regs)),
+		0x142 => Some(syscall!(timerfd_create, regs)),
+		0x143 => Some(syscall!(eventfd, regs)),
+		0x144 => Some(syscall!(fallocate, regs)),
+		0x145 => Some(syscall!(timerfd_settime, regs)),
+		0x146 => Some(syscall!(timerfd_gettime, regs)),
+		0x147 => Some(syscall!(signalfd4, regs)),
+		0x148 => Some(syscall!(eventfd2, regs)),
 		// TODO 0x149 => Some(syscall!(epoll_create1, regs)),
 		// TODO 0x14a => Some(syscall!(dup3, regs)),
 		0x14b => Some(syscall!(pipe2, regs)),
-		// TODO 0x14c => Some(syscall!(inotify_init1, regs)),
+		0x14c => Some(syscall!(inotify_init1, regs)),
 		0x14d => Some(syscall!(preadv, regs)),
 		0x14e => Some(syscall!(pwritev, regs)),
 		// TODO 0x14f => Some(syscall!(rt_tgsigqueueinfo, regs)),
 		// TODO 0x150 => Some(syscall!(perf_event_open, regs)),
 		// TODO 0x151 => Some(syscall!(recvmmsg, regs)),
-		// TODO 0x152 => Some(syscall!(fanotify_init, regs)),
-		// TODO 0x153 => Some(syscall!(fanotify_mark, regs)),
+		0x152 => Some(syscall!(fanotify_init, regs)),
+		0x153 => Some(syscall!(fanotify_mark, regs)),
 		0x154 => Some(syscall!(prlimit64, regs)),
-		// TODO 0x155 => Some(syscall!(name_to_handle_at, regs)),
-		// TODO 0x156 => Some(syscall!(open_by_handle_at, regs)),
+		0x155 => Some(syscall!(name_to_handle_at, regs)),
+		0x156 => Some(syscall!(open_by_handle_at, regs)),
 		// TODO 0x157 => Some(syscall!(clock_adjtime, regs)),
 		0x158 => Some(syscall!(syncfs, regs)),
 		// TODO 0x159 => Some(syscall!(sendmmsg, regs)),
 		// TODO 0x15a => Some(syscall!(setns, regs)),
 		// TODO 0x15b => Some(syscall!(process_vm_readv, regs)),
 		// TODO 0x15c => Some(syscall!(process_vm_writev, regs)),
-		// TODO 0x15d => Some(syscall!(kcmp, regs)),
+		0x15d => Some(syscall!(kcmp, regs)),
 		0x15e => Some(syscall!(finit_module, regs)),
 		// TODO 0x15f => Some(syscall!(sched_setattr, regs)),
 		// TODO 0x160 => Some(syscall!(sched_getattr, regs)),
@@ -864,16 +1018,16 @@ fn do_syscall(id: usize, regs: &Regs) -> Option<EResult<usize>> {
 		0x168 => Some(syscall!(socketpair, regs)),
 		0x169 => Some(syscall!(bind, regs)),
 		0x16a => Some(syscall!(connect, regs)),
-		// TODO 0x16b => Some(syscall!(listen, regs)),
-		// TODO 0x16c => Some(syscall!(accept4, regs)),
+		0x16b => Some(syscall!(listen, regs)),
+		0x16c => Some(syscall!(accept4, regs)),
 		0x16d => Some(syscall!(getsockopt, regs)),
 		0x16e => Some(syscall!(setsockopt, regs)),
 		0x16f => Some(syscall!(getsockname, regs)),
-		// TODO 0x170 => Some(syscall!(getpeername, regs)),
+		0x170 => Some(syscall!(getpeername, regs)),
 		0x171 => Some(syscall!(sendto, regs)),
-		// TODO 0x172 => Some(syscall!(sendmsg, regs)),
+		0x172 => Some(syscall!(sendmsg, regs)),
 		// TODO 0x173 => Some(syscall!(recvfrom, regs)),
-		// TODO 0x174 => Some(syscall!(recvmsg, regs)),
+		0x174 => Some(syscall!(recvmsg, regs)),
 		0x175 => Some(syscall!(shutdown, regs)),
 		// TODO 0x176 => Some(syscall!(userfaultfd, regs)),
 		// TODO 0x177 => Some(syscall!(membarrier, regs)),
@@ -901,24 +1055,24 @@ fn do_syscall(id: usize, regs: &Regs) -> Option<EResult<usize>> {
 		0x193 => Some(syscall!(clock_gettime64, regs)),
 		// TODO 0x194 => Some(syscall!(clock_settime64, regs)),
 		// TODO 0x195 => Some(syscall!(clock_adjtime64, regs)),
-		// TODO 0x196 => Some(syscall!(clock_getres_time64, regs)),
+		0x196 => Some(syscall!(clock_getres_time64, regs)),
 		// TODO 0x197 => Some(syscall!(clock_nanosleep_time64, regs)),
-		// TODO 0x198 => Some(syscall!(timer_gettime64, regs)),
-		// TODO 0x199 => Some(syscall!(timer_settime64, regs)),
-		// TODO 0x19a => Some(syscall!(timerfd_gettime64, regs)),
-		// TODO 0x19b => Some(syscall!(timerfd_settime64, regs)),
+		0x198 => Some(syscall!(timer_gettime64, regs)),
+		0x199 => Some(syscall!(timer_settime64, regs)),
+		0x19a => Some(syscall!(timerfd_gettime64, regs)),
+		0x19b => Some(syscall!(timerfd_settime64, regs)),
 		// TODO 0x19c => Some(syscall!(utimensat_time64, regs)),
-		// TODO 0x19d => Some(syscall!(pselect6_time64, regs)),
-		// TODO 0x19e => Some(syscall!(ppoll_time64, regs)),
+		0x19d => Some(syscall!(pselect6_time64, regs)),
+		0x19e => Some(syscall!(ppoll_time64, regs)),
 		// TODO 0x1a0 => Some(syscall!(io_pgetevents_time64, regs)),
 		// TODO 0x1a1 => Some(syscall!(recvmmsg_time64, regs)),
 		// TODO 0x1a2 => Some(syscall!(mq_timedsend_time64, regs)),
 		// TODO 0x1a3 => Some(syscall!(mq_timedreceive_time64, regs)),
 		// TODO 0x1a4 => Some(syscall!(semtimedop_time64, regs)),
 		// TODO 0x1a5 => Some(syscall!(rt_sigtimedwait_time64, regs)),
-		// TODO 0x1a6 => Some(syscall!(futex_time64, regs)),
+		0x1a6 => Some(syscall!(futex_time64, regs)),
 		// TODO 0x1a7 => Some(syscall!(sched_rr_get_interval_time64, regs)),
-		// TODO 0x1a8 => Some(syscall!(pidfd_send_signal, regs)),
+		0x1a8 => Some(syscall!(pidfd_send_signal, regs)),
 		// TODO 0x1a9 => Some(syscall!(io_uring_setup, regs)),
 		// TODO 0x1aa => Some(syscall!(io_uring_enter, regs)),
 		// TODO 0x1ab => Some(syscall!(io_uring_register, regs)),
@@ -928,13 +1082,13 @@ fn do_syscall(id: usize, regs: &Regs) -> Option<EResult<usize>> {
 		// TODO 0x1af => Some(syscall!(fsconfig, regs)),
 		// TODO 0x1b0 => Some(syscall!(fsmount, regs)),
 		// TODO 0x1b1 => Some(syscall!(fspick, regs)),
-		// TODO 0x1b2 => Some(syscall!(pidfd_open, regs)),
-		// TODO 0x1b3 => Some(syscall!(clone3, regs)),
+		0x1b2 => Some(syscall!(pidfd_open, regs)),
+		0x1b3 => Some(syscall!(clone3, regs)),
 		// TODO 0x1b4 => Some(syscall!(close_range, regs)),
 		// TODO 0x1b5 => Some(syscall!(openat2, regs)),
 		// TODO 0x1b6 => Some(syscall!(pidfd_getfd, regs)),
 		0x1b7 => Some(syscall!(faccessat2, regs)),
-		// TODO 0x1b8 => Some(syscall!(process_madvise, regs)),
+		0x1b8 => Some(syscall!(process_madvise, regs)),
 		// TODO 0x1b9 => Some(syscall!(epoll_pwait2, regs)),
 		// TODO 0x1ba => Some(syscall!(mount_setattr, regs)),
 		// TODO 0x1bb => Some(syscall!(quotactl_fd, regs)),
@@ -956,7 +1110,8 @@ pub extern "C" fn syscall_handler(regs: &mut Regs) {
 	match do_syscall(id, regs) {
 		// Success: Set the return value
 		Some(res) => regs.set_syscall_return(res),
-		// The system call does not exist: Kill the process with SIGSYS
+		// The system call does not exist: Kill the process with SIGSYS, or fail with ENOSYS in
+		// audit mode
 		None => {
 			let proc_mutex = Process::current();
 			let mut proc = proc_mutex.lock();
@@ -965,8 +1120,14 @@ pub extern "C" fn syscall_handler(regs: &mut Regs) {
 				"[strace PID: {pid}] invalid syscall (ID: 0x{id:x})",
 				pid = proc.get_pid()
 			);
-			// SIGSYS cannot be caught, thus the process will be terminated
-			proc.kill(Signal::SIGSYS);
+			unimplemented::record(&mut proc, id);
+			if unimplemented::AUDIT.get() {
+				drop(proc);
+				regs.set_syscall_return(Err(errno!(ENOSYS)));
+			} else {
+				// SIGSYS cannot be caught, thus the process will be terminated
+				proc.kill(Signal::SIGSYS);
+			}
 		}
 	}
 	// If the process has been killed, handle it
@@ -24,47 +24,102 @@ use crate::{
 		vfs::{mountpoint, mountpoint::MountSource, ResolutionSettings},
 		FileType,
 	},
-	process::{
-		mem_space::copy::{SyscallPtr, SyscallString},
-		Process,
-	},
+	process::{mem_space::copy::SyscallString, Process},
 	syscall::Args,
 };
-use core::ffi::{c_ulong, c_void};
+use core::ffi::c_ulong;
 use utils::{
-	collections::path::PathBuf,
 	errno,
 	errno::{EResult, Errno},
 };
 
+/// Mount the filesystem read-only.
+const MS_RDONLY: c_ulong = 1;
+/// Ignore setuid and setgid bits.
+const MS_NOSUID: c_ulong = 2;
+/// Disallow access to device files.
+const MS_NODEV: c_ulong = 4;
+/// Disallow program execution.
+const MS_NOEXEC: c_ulong = 8;
+/// Make writes synchronous.
+const MS_SYNCHRONOUS: c_ulong = 16;
+/// Allow mandatory locks.
+const MS_MANDLOCK: c_ulong = 64;
+/// Do not update access times.
+const MS_NOATIME: c_ulong = 1024;
+/// Do not update directory access times.
+const MS_NODIRATIME: c_ulong = 2048;
+/// Apply recursively to submounts.
+const MS_REC: c_ulong = 16384;
+/// Suppress certain kernel warnings.
+const MS_SILENT: c_ulong = 32768;
+/// Update access times relative to modification times.
+const MS_RELATIME: c_ulong = 1 << 21;
+/// Always update access times.
+const MS_STRICTATIME: c_ulong = 1 << 24;
+
+/// Converts `MS_*` flags passed to the system call into mountpoint flags.
+///
+/// Unsupported flags are ignored.
+fn mount_flags(mountflags: c_ulong) -> u32 {
+	const MAP: [(c_ulong, u32); 12] = [
+		(MS_RDONLY, mountpoint::FLAG_RDONLY),
+		(MS_NOSUID, mountpoint::FLAG_NOSUID),
+		(MS_NODEV, mountpoint::FLAG_NODEV),
+		(MS_NOEXEC, mountpoint::FLAG_NOEXEC),
+		(MS_SYNCHRONOUS, mountpoint::FLAG_SYNCHRONOUS),
+		(MS_MANDLOCK, mountpoint::FLAG_MANDLOCK),
+		(MS_NOATIME, mountpoint::FLAG_NOATIME),
+		(MS_NODIRATIME, mountpoint::FLAG_NODIRATIME),
+		(MS_REC, mountpoint::FLAG_REC),
+		(MS_SILENT, mountpoint::FLAG_SILENT),
+		(MS_RELATIME, mountpoint::FLAG_RELATIME),
+		(MS_STRICTATIME, mountpoint::FLAG_STRICTATIME),
+	];
+	MAP.iter()
+		.filter(|(ms, _)| mountflags & ms != 0)
+		.fold(0, |res, (_, flag)| res | flag)
+}
+
 pub fn mount(
-	Args((source, target, filesystemtype, mountflags, _data)): Args<(
+	Args((source, target, filesystemtype, mountflags, data)): Args<(
 		SyscallString,
 		SyscallString,
 		SyscallString,
 		c_ulong,
-		SyscallPtr<c_void>,
+		SyscallString,
 	)>,
 	rs: ResolutionSettings,
 ) -> EResult<usize> {
 	if !rs.access_profile.is_privileged() {
 		return Err(errno!(EPERM));
 	}
-	// Read arguments
-	let source_slice = source.copy_from_user()?.ok_or(errno!(EFAULT))?;
-	let mount_source = MountSource::new(&source_slice)?;
-	let target_slice = target.copy_from_user()?.ok_or(errno!(EFAULT))?;
-	let target_path = PathBuf::try_from(target_slice)?;
-	let filesystemtype_slice = filesystemtype.copy_from_user()?.ok_or(errno!(EFAULT))?;
-	let fs_type = fs::get_type(&filesystemtype_slice).ok_or(errno!(ENODEV))?;
 	// Get target file
+	let target_path = target.copy_path_from_user()?.ok_or(errno!(EFAULT))?;
 	let target_file = vfs::get_file_from_path(&target_path, &rs)?;
 	// Check the target is a directory
 	if target_file.get_type()? != FileType::Directory {
 		return Err(errno!(ENOTDIR));
 	}
-	// TODO Use `data`
+	let filesystemtype_slice = filesystemtype.copy_from_user()?.ok_or(errno!(EFAULT))?;
+	let fs_type = fs::get_type(&filesystemtype_slice).ok_or(errno!(ENODEV))?;
+	// The source is a path to a device only if the filesystem requires one. It is resolved the
+	// same way as the target
+	let mount_source = if fs_type.requires_device() {
+		let source_path = source.copy_path_from_user()?.ok_or(errno!(EFAULT))?;
+		MountSource::from_path(&source_path, &rs)?
+	} else {
+		MountSource::NoDev(source.copy_from_user()?.ok_or(errno!(EFAULT))?)
+	};
+	// Filesystem-specific options
+	let data = data.copy_from_user()?.unwrap_or_default();
 	// Create mountpoint
-	mountpoint::create(mount_source, Some(fs_type), mountflags, target_file)?;
+	mountpoint::create(
+		mount_source,
+		Some(fs_type),
+		mount_flags(mountflags),
+		&data,
+		target_file,
+	)?;
 	Ok(0)
 }
@@ -55,6 +55,13 @@ pub fn mprotect(
 	if !addr.is_aligned_to(PAGE_SIZE) || len == 0 {
 		return Err(errno!(EINVAL));
 	}
+	if prot & !(mmap::PROT_READ | mmap::PROT_WRITE | mmap::PROT_EXEC) != 0 {
+		return Err(errno!(EINVAL));
+	}
+	// Check for overflow
+	if (addr as usize).checked_add(len).is_none() {
+		return Err(errno!(ENOMEM));
+	}
 	let flags = prot_to_flags(prot);
 	mem_space.lock().set_prot(addr, len, flags, &ap)?;
 	Ok(0)
@@ -0,0 +1,109 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+//! The `name_to_handle_at` system call returns a handle for a file, allowing to open it later
+//! with `open_by_handle_at` without resolving its path.
+
+use super::util::{
+	at,
+	at::{AT_EMPTY_PATH, AT_SYMLINK_FOLLOW},
+};
+use crate::{
+	file::{
+		fd::FileDescriptorTable,
+		vfs::{ResolutionSettings, Resolved},
+	},
+	process::mem_space::copy::{SyscallPtr, SyscallSlice, SyscallString},
+	syscall::{Args, FromSyscallArg},
+};
+use core::{ffi::c_int, mem::size_of};
+use utils::{
+	errno,
+	errno::{EResult, Errno},
+	lock::Mutex,
+	ptr::arc::Arc,
+};
+
+/// The maximum size of a file handle, in bytes.
+pub const MAX_HANDLE_SZ: usize = 128;
+
+/// Header of a file handle, followed by the content of the handle.
+#[repr(C)]
+#[derive(Debug)]
+pub struct FileHandle {
+	/// The size of the content of the handle, in bytes.
+	pub handle_bytes: u32,
+	/// The type of the handle.
+	pub handle_type: c_int,
+}
+
+impl FileHandle {
+	/// Returns a pointer to the content of the handle whose header is at `handle`.
+	pub fn content(handle: &SyscallPtr<Self>) -> SyscallSlice<u8> {
+		SyscallSlice::from_syscall_arg(handle.as_ptr() as usize + size_of::<Self>())
+	}
+}
+
+#[allow(clippy::type_complexity)]
+pub fn name_to_handle_at(
+	Args((dirfd, pathname, handle, mount_id, flags)): Args<(
+		c_int,
+		SyscallString,
+		SyscallPtr<FileHandle>,
+		SyscallPtr<c_int>,
+		c_int,
+	)>,
+	fds: Arc<Mutex<FileDescriptorTable>>,
+	rs: ResolutionSettings,
+) -> EResult<usize> {
+	// Validation
+	if flags & !(AT_EMPTY_PATH | AT_SYMLINK_FOLLOW) != 0 {
+		return Err(errno!(EINVAL));
+	}
+	let mut header = handle.copy_from_user()?.ok_or_else(|| errno!(EFAULT))?;
+	if header.handle_bytes as usize > MAX_HANDLE_SZ {
+		return Err(errno!(EINVAL));
+	}
+	let pathname = pathname
+		.copy_path_from_user()?
+		.ok_or_else(|| errno!(EFAULT))?;
+	// Symbolic links are followed only if `AT_SYMLINK_FOLLOW` is set
+	let rs = ResolutionSettings {
+		follow_link: false,
+		..rs
+	};
+	let Resolved::Found(file) = at::get_file(&fds.lock(), rs, dirfd, Some(&pathname), flags)?
+	else {
+		return Err(errno!(ENOENT));
+	};
+	let loc = &file.node().location;
+	let fs = loc.get_filesystem().ok_or_else(|| errno!(ENOENT))?;
+	let (handle_type, content) = fs.encode_handle(loc.inode)?;
+	// If the buffer is too small, tell userspace the required size
+	if content.len() > header.handle_bytes as usize {
+		header.handle_bytes = content.len() as _;
+		handle.copy_to_user(header)?;
+		return Err(errno!(EOVERFLOW));
+	}
+	FileHandle::content(&handle).copy_to_user(0, &content)?;
+	handle.copy_to_user(FileHandle {
+		handle_bytes: content.len() as _,
+		handle_type,
+	})?;
+	mount_id.copy_to_user(loc.mountpoint_id as _)?;
+	Ok(0)
+}
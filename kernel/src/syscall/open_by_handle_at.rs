@@ -0,0 +1,87 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+//! The `open_by_handle_at` system call opens a file from a handle returned by
+//! `name_to_handle_at`.
+
+use super::{
+	name_to_handle_at::{FileHandle, MAX_HANDLE_SZ},
+	openat,
+	util::at::AT_FDCWD,
+};
+use crate::{
+	file::{
+		fd::FileDescriptorTable,
+		vfs,
+		vfs::{mountpoint, ResolutionSettings},
+		O_CREAT, O_EXCL,
+	},
+	process::mem_space::copy::SyscallPtr,
+	syscall::Args,
+};
+use core::ffi::c_int;
+use utils::{
+	errno,
+	errno::{EResult, Errno},
+	lock::Mutex,
+	ptr::arc::Arc,
+};
+
+pub fn open_by_handle_at(
+	Args((mount_fd, handle, flags)): Args<(c_int, SyscallPtr<FileHandle>, c_int)>,
+	fds: Arc<Mutex<FileDescriptorTable>>,
+	rs: ResolutionSettings,
+) -> EResult<usize> {
+	// Opening a handle bypasses the permission checks of path resolution
+	if !rs.access_profile.is_privileged() {
+		return Err(errno!(EPERM));
+	}
+	// Files cannot be created from a handle
+	if flags & (O_CREAT | O_EXCL) != 0 {
+		return Err(errno!(EINVAL));
+	}
+	let header = handle.copy_from_user()?.ok_or_else(|| errno!(EFAULT))?;
+	let len = header.handle_bytes as usize;
+	if len == 0 || len > MAX_HANDLE_SZ {
+		return Err(errno!(EINVAL));
+	}
+	let content = FileHandle::content(&handle)
+		.copy_from_user(..len)?
+		.ok_or_else(|| errno!(EFAULT))?;
+	// Get the mountpoint the handle belongs to
+	let mount_ent = if mount_fd == AT_FDCWD {
+		rs.cwd.clone().ok_or_else(|| errno!(ENOENT))?
+	} else {
+		fds.lock()
+			.get_fd(mount_fd)?
+			.get_file()
+			.vfs_entry
+			.clone()
+			.ok_or_else(|| errno!(EBADF))?
+	};
+	let mp = mountpoint::from_id(mount_ent.node().location.mountpoint_id)
+		.ok_or_else(|| errno!(ESTALE))?;
+	let inode = mp.fs.decode_handle(header.handle_type, &content)?;
+	let file = vfs::get_detached_entry(&mp, inode).map_err(|e| {
+		if e.as_int() == errno::ENOENT {
+			errno!(ESTALE)
+		} else {
+			e
+		}
+	})?;
+	openat::open_entry(file, flags, &rs.access_profile, &fds)
+}
@@ -19,14 +19,19 @@
 //! The `openat` syscall allows to open a file.
 
 use crate::{
+	device,
+	device::DeviceID,
 	file,
 	file::{
+		fanotify,
+		fanotify::{FAN_OPEN, FAN_OPEN_PERM},
 		fd::{FileDescriptorTable, FD_CLOEXEC},
+		lease,
 		perm::AccessProfile,
 		vfs,
-		vfs::{ResolutionSettings, Resolved},
+		vfs::{mountpoint::FLAG_RDONLY, ResolutionSettings, Resolved},
 		File, FileType, Stat, O_CLOEXEC, O_CREAT, O_DIRECTORY, O_EXCL, O_NOCTTY, O_NOFOLLOW,
-		O_RDONLY, O_RDWR, O_TRUNC, O_WRONLY,
+		O_NONBLOCK, O_PATH, O_RDONLY, O_RDWR, O_TRUNC, O_WRONLY,
 	},
 	process::{mem_space::copy::SyscallString, Process},
 	syscall::{util::at, Args},
@@ -37,7 +42,7 @@ use crate::{
 };
 use core::ffi::c_int;
 use utils::{
-	collections::path::{Path, PathBuf},
+	collections::path::Path,
 	errno,
 	errno::{EResult, Errno},
 	lock::Mutex,
@@ -52,7 +57,6 @@ use utils::{
 /// Arguments:
 /// - `dirfd` a file descriptor to the directory from which the file will be searched.
 /// - `pathname` the path relative to the directory.
-/// - `flags` is a set of open file flags.
 /// - `mode` is the set of permissions to use if the file needs to be created.
 ///
 /// If the file doesn't exist and the `O_CREAT` flag is set, the file is created,
@@ -65,11 +69,11 @@ fn get_file(
 	fds: &FileDescriptorTable,
 	dirfd: c_int,
 	path: Option<&Path>,
-	flags: c_int,
 	rs: ResolutionSettings,
 	mode: file::Mode,
 ) -> EResult<Arc<vfs::Entry>> {
-	let resolved = at::get_file(fds, rs.clone(), dirfd, path, flags)?;
+	// Open flags are not `AT_*` flags: symbolic links are handled through `rs`
+	let resolved = at::get_file(fds, rs.clone(), dirfd, path, 0)?;
 	match resolved {
 		Resolved::Found(file) => Ok(file),
 		Resolved::Creatable {
@@ -100,6 +104,12 @@ pub fn do_openat(
 	flags: c_int,
 	mode: file::Mode,
 ) -> EResult<usize> {
+	// With `O_PATH`, flags other than these are ignored
+	let flags = if flags & O_PATH != 0 {
+		flags & (O_PATH | O_CLOEXEC | O_DIRECTORY | O_NOFOLLOW)
+	} else {
+		flags
+	};
 	let (rs, pathname, fds_mutex, mode) = {
 		let proc_mutex = Process::current();
 		let proc = proc_mutex.lock();
@@ -109,51 +119,127 @@ pub fn do_openat(
 			..ResolutionSettings::for_process(&proc, follow_link)
 		};
 		let pathname = pathname
-			.copy_from_user()?
-			.map(PathBuf::try_from)
-			.ok_or_else(|| errno!(EFAULT))??;
+			.copy_path_from_user()?
+			.ok_or_else(|| errno!(EFAULT))?;
 		let fds_mutex = proc.file_descriptors.clone().unwrap();
-		let mode = mode & !proc.umask;
+		let mode = mode & !proc.fs.lock().umask;
 		(rs, pathname, fds_mutex, mode)
 	};
 
-	let mut fds = fds_mutex.lock();
-
 	// Get file
-	let file = get_file(&fds, dirfd, Some(&pathname), flags, rs.clone(), mode)?;
-	// Check permissions
+	let file = get_file(&fds_mutex.lock(), dirfd, Some(&pathname), rs.clone(), mode)?;
+	open_entry(file, flags, &rs.access_profile, &fds_mutex)
+}
+
+/// Tells whether the filesystem on which `file` is located, or its mountpoint, is read-only.
+fn is_readonly(file: &vfs::Entry) -> bool {
+	file.node()
+		.location
+		.get_mountpoint()
+		.is_some_and(|mp| mp.get_flags() & FLAG_RDONLY != 0)
+}
+
+/// Opens the file `file` and creates a file descriptor for it.
+///
+/// Arguments:
+/// - `file` is the file to open
+/// - `flags` is the set of open file flags
+/// - `ap` is the access profile to check permissions
+/// - `fds_mutex` is the file descriptors table in which the file descriptor is created
+///
+/// On success, the function returns the ID of the new file descriptor.
+pub fn open_entry(
+	file: Arc<vfs::Entry>,
+	flags: c_int,
+	ap: &AccessProfile,
+	fds_mutex: &Mutex<FileDescriptorTable>,
+) -> EResult<usize> {
+	let path = flags & O_PATH != 0;
+	// Check permissions. A file opened with `O_PATH` is neither readable nor writable
 	let (read, write) = match flags & 0b11 {
+		_ if path => (false, false),
 		O_RDONLY => (true, false),
 		O_WRONLY => (false, true),
 		O_RDWR => (true, true),
 		_ => return Err(errno!(EINVAL)),
 	};
 	let stat = file.stat()?;
-	if read && !rs.access_profile.can_read_file(&stat) {
-		return Err(errno!(EACCES));
-	}
-	if write && !rs.access_profile.can_write_file(&stat) {
-		return Err(errno!(EACCES));
-	}
 	let file_type = stat.get_type();
 	// If `O_DIRECTORY` is set and the file is not a directory, return an error
 	if flags & O_DIRECTORY != 0 && file_type != Some(FileType::Directory) {
 		return Err(errno!(ENOTDIR));
 	}
+	// A directory cannot be written through a file descriptor
+	if file_type == Some(FileType::Directory) && (write || flags & O_CREAT != 0) {
+		return Err(errno!(EISDIR));
+	}
+	// Devices, FIFOs and sockets remain writable on a read-only filesystem
+	let stored = matches!(
+		file_type,
+		Some(FileType::Regular | FileType::Directory | FileType::Link)
+	);
+	if stored && (write || flags & O_TRUNC != 0) && is_readonly(&file) {
+		return Err(errno!(EROFS));
+	}
+	if read && !ap.can_read_file(&stat) {
+		return Err(errno!(EACCES));
+	}
+	if write && !ap.can_write_file(&stat) {
+		return Err(errno!(EACCES));
+	}
+	// A device file is only a name: the device itself may not exist
+	if !path {
+		if let Some(dev_type) = file_type.and_then(FileType::to_device_type) {
+			let id = DeviceID {
+				dev_type,
+				major: stat.dev_major,
+				minor: stat.dev_minor,
+			};
+			if device::get(&id).is_none() {
+				return Err(errno!(ENXIO));
+			}
+		}
+	}
+	// Only `O_PATH` allows referring to a symbolic link itself
+	if !path && file_type == Some(FileType::Link) {
+		return Err(errno!(ELOOP));
+	}
+	// A bound socket is reached through `connect`, not `open`
+	if !path && file_type == Some(FileType::Socket) {
+		return Err(errno!(ENXIO));
+	}
+	// The content of the file is not accessed with `O_PATH`, so listeners are not notified
+	if !path {
+		// Let listeners deny the operation. The file descriptors table must not be locked while
+		// waiting for them
+		fanotify::notify(&file, FAN_OPEN_PERM)?;
+		// Break the leases conflicting with the access. With `O_NONBLOCK`, fail instead of
+		// waiting for their holders to release them
+		lease::break_leases(
+			&file.node().location,
+			write || flags & O_TRUNC != 0,
+			None,
+			flags & O_NONBLOCK != 0,
+		)?;
+	}
 	// Open file
 	const FLAGS_MASK: i32 =
 		!(O_CLOEXEC | O_CREAT | O_DIRECTORY | O_EXCL | O_NOCTTY | O_NOFOLLOW | O_TRUNC);
 	let file = File::open_entry(file, flags & FLAGS_MASK)?;
-	// Truncate if necessary
-	if flags & O_TRUNC != 0 && file_type == Some(FileType::Regular) {
-		file.truncate(0)?;
+	if !path {
+		// Truncate if necessary
+		if flags & O_TRUNC != 0 && file_type == Some(FileType::Regular) {
+			file.truncate(0)?;
+		}
+		// The file has been opened, failing to notify must not fail the system call
+		let _ = fanotify::notify_file(&file, FAN_OPEN);
 	}
 	// Create FD
 	let mut fd_flags = 0;
 	if flags & O_CLOEXEC != 0 {
 		fd_flags |= FD_CLOEXEC;
 	}
-	let (fd_id, _) = fds.create_fd(fd_flags, file)?;
+	let (fd_id, _) = fds_mutex.lock().create_fd(fd_flags, file)?;
 	Ok(fd_id as _)
 }
 
@@ -0,0 +1,47 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The `personality` system call sets the execution domain of the process.
+//!
+//! The execution domain allows to emulate behaviours of other systems or older kernels, which
+//! some legacy programs rely on.
+
+use crate::{process::Process, syscall::Args};
+use core::{ffi::c_uint, mem};
+use utils::{errno::EResult, lock::IntMutex, ptr::arc::Arc};
+
+/// Mask of the execution domain in the personality value. The other bits are flags.
+pub const PER_MASK: u32 = 0xff;
+/// Execution domain: Linux, reporting a 32 bits architecture.
+pub const PER_LINUX32: u32 = 0x0008;
+
+/// Personality flag: report a `2.6.x` kernel release.
+pub const UNAME26: u32 = 0x0020000;
+
+/// Value of the argument to query the current personality without changing it.
+const QUERY: c_uint = 0xffffffff;
+
+pub fn personality(Args(persona): Args<c_uint>, proc: Arc<IntMutex<Process>>) -> EResult<usize> {
+	let mut proc = proc.lock();
+	let prev = if persona == QUERY {
+		proc.personality
+	} else {
+		mem::replace(&mut proc.personality, persona)
+	};
+	Ok(prev as _)
+}
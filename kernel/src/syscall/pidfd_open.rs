@@ -0,0 +1,67 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The `pidfd_open` system call returns a file descriptor referring to a process.
+
+use crate::{
+	file::{
+		fd::{FileDescriptorTable, FD_CLOEXEC},
+		pidfd::{PidFd, PIDFD_NONBLOCK},
+		File, O_RDWR,
+	},
+	process::{pid::Pid, Process},
+	syscall::Args,
+};
+use core::ffi::{c_int, c_uint};
+use utils::{
+	errno,
+	errno::EResult,
+	lock::{IntMutex, Mutex},
+	ptr::arc::Arc,
+};
+
+pub fn pidfd_open(
+	Args((pid, flags)): Args<(c_int, c_uint)>,
+	fds: Arc<Mutex<FileDescriptorTable>>,
+	proc: Arc<IntMutex<Process>>,
+) -> EResult<usize> {
+	let flags = flags as c_int;
+	if flags & !PIDFD_NONBLOCK != 0 {
+		return Err(errno!(EINVAL));
+	}
+	let pid: Pid = pid.try_into().map_err(|_| errno!(EINVAL))?;
+	if pid == 0 {
+		return Err(errno!(EINVAL));
+	}
+	let pid = proc
+		.lock()
+		.pid_to_global(pid)
+		.ok_or_else(|| errno!(ESRCH))?;
+	let target = Process::get_by_pid(pid).ok_or_else(|| errno!(ESRCH))?;
+	let pidfd = {
+		let target = target.lock();
+		// A pidfd refers to a thread group
+		if !target.is_thread_group_leader() {
+			return Err(errno!(EINVAL));
+		}
+		Arc::new(PidFd::new(&target))?
+	};
+	let file = File::open_floating(pidfd, O_RDWR | flags)?;
+	let (fd_id, _) = fds.lock().create_fd(FD_CLOEXEC, file)?;
+	Ok(fd_id as _)
+}
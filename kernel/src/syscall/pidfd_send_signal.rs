@@ -0,0 +1,84 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+//! The `pidfd_send_signal` system call sends a signal to the process referred to by a pidfd.
+
+use crate::{
+	file::{fd::FileDescriptorTable, perm::AccessProfile, pidfd::PidFd},
+	process::{
+		mem_space::copy::SyscallPtr,
+		signal::{SigInfo, Signal, SignalInfo, SI_USER},
+		Process, State,
+	},
+	syscall::Args,
+};
+use core::ffi::{c_int, c_uint};
+use utils::{
+	errno,
+	errno::{EResult, Errno},
+	lock::{IntMutex, Mutex},
+	ptr::arc::Arc,
+};
+
+pub fn pidfd_send_signal(
+	Args((pidfd, sig, info, flags)): Args<(c_int, c_int, SyscallPtr<SigInfo>, c_uint)>,
+	fds: Arc<Mutex<FileDescriptorTable>>,
+	proc: Arc<IntMutex<Process>>,
+	ap: AccessProfile,
+) -> EResult<usize> {
+	if flags != 0 {
+		return Err(errno!(EINVAL));
+	}
+	let sig = (sig != 0).then(|| Signal::try_from(sig)).transpose()?;
+	let file = fds.lock().get_fd(pidfd)?.get_file().clone();
+	let pidfd = file.get_buffer::<PidFd>().ok_or_else(|| errno!(EBADF))?;
+	let target_mutex = pidfd.get_process()?;
+	let (cur_pid, cur_tgid) = {
+		let proc = proc.lock();
+		(proc.get_pid(), proc.get_tgid())
+	};
+	let code = match info.copy_from_user()? {
+		Some(info) => {
+			if sig.map(|s| s.get_id() as c_int) != Some(info.si_signo) {
+				return Err(errno!(EINVAL));
+			}
+			// Only the kernel may impersonate a signal it sends, except to the caller itself
+			if info.si_code >= 0 && pidfd.get_pid() != cur_tgid {
+				return Err(errno!(EPERM));
+			}
+			info.si_code
+		}
+		None => SI_USER,
+	};
+	let mut target = target_mutex.lock();
+	if !ap.can_kill(&target) {
+		return Err(errno!(EPERM));
+	}
+	if let Some(sig) = sig {
+		if target.get_state() != State::Zombie {
+			let info = SignalInfo {
+				code,
+				// The sender as seen from the namespace of the target
+				pid: target.pid_to_local(cur_pid).unwrap_or(0),
+				uid: ap.uid,
+				addr: 0,
+			};
+			target.kill_with_info(sig, info);
+		}
+	}
+	Ok(0)
+}
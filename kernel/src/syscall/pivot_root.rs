@@ -0,0 +1,75 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The `pivot_root` system call changes the root mount, allowing an init system to switch from
+//! an initial root filesystem to the real one.
+
+use crate::{
+	file::{
+		vfs,
+		vfs::{mountpoint, ResolutionSettings},
+		FileType,
+	},
+	process::{mem_space::copy::SyscallString, scheduler::SCHEDULER},
+	syscall::Args,
+};
+use utils::{
+	collections::vec::Vec,
+	errno,
+	errno::{CollectResult, EResult, Errno},
+};
+
+pub fn pivot_root(
+	Args((new_root, put_old)): Args<(SyscallString, SyscallString)>,
+	rs: ResolutionSettings,
+) -> EResult<usize> {
+	// Check permission
+	if !rs.access_profile.is_privileged() {
+		return Err(errno!(EPERM));
+	}
+	let new_root = new_root.copy_path_from_user()?.ok_or(errno!(EFAULT))?;
+	let put_old = put_old.copy_path_from_user()?.ok_or(errno!(EFAULT))?;
+	// Get directories
+	let new_root = vfs::get_file_from_path(&new_root, &rs)?;
+	let put_old = vfs::get_file_from_path(&put_old, &rs)?;
+	if new_root.get_type()? != FileType::Directory || put_old.get_type()? != FileType::Directory {
+		return Err(errno!(ENOTDIR));
+	}
+	let old_root = rs.root;
+	mountpoint::pivot_root(&old_root, &new_root, &put_old)?;
+	// Move processes whose root or working directory is the previous root to the new one
+	let procs = SCHEDULER
+		.get()
+		.lock()
+		.iter_process()
+		.map(|(_, proc)| proc.clone())
+		.collect::<CollectResult<Vec<_>>>()
+		.0?;
+	let old_loc = &old_root.node().location;
+	for proc in procs {
+		let proc = proc.lock();
+		let mut fs = proc.fs.lock();
+		if fs.chroot.node().location == *old_loc {
+			fs.chroot = new_root.clone();
+		}
+		if fs.cwd.node().location == *old_loc {
+			fs.cwd = new_root.clone();
+		}
+	}
+	Ok(0)
+}
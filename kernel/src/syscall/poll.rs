@@ -20,18 +20,27 @@
 //! descriptors.
 
 use crate::{
-	process::{mem_space::copy::SyscallSlice, scheduler, Process},
+	file::fd::FileDescriptorTable,
+	process,
+	process::{mem_space::copy::SyscallSlice, scheduler, signal::SigSet, Process},
 	syscall::Args,
 	time::{
 		clock,
 		clock::CLOCK_MONOTONIC,
 		unit::{Timestamp, TimestampScale},
 	},
+	workqueue,
+};
+use core::{
+	ffi::c_int,
+	sync::atomic::{AtomicBool, Ordering},
 };
-use core::ffi::c_int;
 use utils::{
+	collections::vec::Vec,
 	errno,
 	errno::{EResult, Errno},
+	lock::Mutex,
+	ptr::arc::Arc,
 };
 
 /// Poll event: There is data to read.
@@ -70,65 +79,177 @@ pub struct PollFD {
 	revents: i16,
 }
 
-pub(super) fn poll(
-	Args((fds, nfds, timeout)): Args<(SyscallSlice<PollFD>, usize, c_int)>,
+/// Events that are reported even if they have not been requested.
+const ALWAYS_REPORTED: u32 = POLLERR | POLLHUP | POLLNVAL;
+
+/// Returns the events that occurred on the file descriptor `fd`, among `events`.
+///
+/// Before polling, the current process is registered on the file's wait queues so that it gets
+/// woken up by the next event. If the file cannot wake up processes, `sleep` is cleared, in which
+/// case the caller has to poll again instead of sleeping.
+///
+/// If the file descriptor is invalid, the function returns [`POLLNVAL`]. If polling the file
+/// fails, the error is reported as [`POLLERR`].
+pub(super) fn poll_fd(
+	fds: &Mutex<FileDescriptorTable>,
+	fd: c_int,
+	events: u32,
+	sleep: &mut bool,
+) -> u32 {
+	let Ok(file) = fds.lock().get_fd(fd).map(|fd| fd.get_file().clone()) else {
+		return POLLNVAL;
+	};
+	*sleep &= file.ops.poll_wait(&file).unwrap_or(false);
+	// `POLLRDNORM` and `POLLWRNORM` are equivalent to `POLLIN` and `POLLOUT`
+	let mut mask = events | ALWAYS_REPORTED;
+	if mask & POLLRDNORM != 0 {
+		mask |= POLLIN;
+	}
+	if mask & POLLWRNORM != 0 {
+		mask |= POLLOUT;
+	}
+	let mut revents = file.ops.poll(&file, mask).unwrap_or(POLLERR);
+	if revents & POLLIN != 0 {
+		revents |= POLLRDNORM;
+	}
+	if revents & POLLOUT != 0 {
+		revents |= POLLWRNORM;
+	}
+	revents & (events | ALWAYS_REPORTED)
+}
+
+/// Waits until `check` reports at least one event, the deadline is reached, or a signal is
+/// received.
+///
+/// Arguments:
+/// - `deadline` is the time at which waiting stops, on the monotonic clock, in nanoseconds. If
+///   `None`, the function waits indefinitely
+/// - `sigmask` is the signal mask to apply while waiting. If `None`, the mask is unchanged
+/// - `check` polls the files and returns the number of events. It clears the boolean it is given
+///   if one of the files cannot wake up the process, in which case the files are polled again on
+///   the next tick instead of sleeping
+///
+/// The function returns the number of events, or zero on timeout.
+///
+/// If waiting is interrupted by a signal, the function returns [`errno::EINTR`]. In this case,
+/// the signal mask is restored after the signal has been handled.
+pub(super) fn wait_events<F: FnMut(&mut bool) -> EResult<usize>>(
+	deadline: Option<Timestamp>,
+	sigmask: Option<SigSet>,
+	mut check: F,
 ) -> EResult<usize> {
-	// The timeout. `None` means no timeout
-	let to = (timeout >= 0).then_some(timeout as Timestamp);
-	// The start timestamp
-	let start_ts = clock::current_time(CLOCK_MONOTONIC, TimestampScale::Millisecond)?;
-	loop {
+	let pid = {
+		let proc_mutex = Process::current();
+		let mut proc = proc_mutex.lock();
+		if let Some(mask) = sigmask {
+			proc.set_temporary_sigmask(mask);
+		}
+		proc.get_pid()
+	};
+	// Tells whether a timer waking up the process at the deadline is pending
+	let armed = Arc::new(AtomicBool::new(false))?;
+	let res = loop {
+		let mut sleep = true;
+		let count = match check(&mut sleep) {
+			Ok(count) => count,
+			Err(e) => break Err(e),
+		};
+		if count > 0 {
+			break Ok(count);
+		}
 		// Check whether the system call timed out
-		if let Some(timeout) = to {
-			let now = clock::current_time(CLOCK_MONOTONIC, TimestampScale::Millisecond)?;
-			if now >= start_ts + timeout {
-				return Ok(0);
-			}
+		let now = clock::current_time(CLOCK_MONOTONIC, TimestampScale::Nanosecond)?;
+		let remaining = deadline.map(|deadline| deadline.saturating_sub(now));
+		if remaining == Some(0) {
+			break Ok(0);
 		}
-		{
-			let fds_arr = fds.copy_from_user(..nfds)?.ok_or_else(|| errno!(EFAULT))?;
-			// Check the file descriptors list
-			for fd in &fds_arr {
-				if fd.events as u32 & POLLIN != 0 {
-					// TODO
-					todo!();
-				}
-				if fd.events as u32 & POLLPRI != 0 {
-					// TODO
-					todo!();
-				}
-				if fd.events as u32 & POLLOUT != 0 {
-					// TODO
-					todo!();
-				}
-				if fd.events as u32 & POLLRDNORM != 0 {
-					// TODO
-					todo!();
-				}
-				if fd.events as u32 & POLLRDBAND != 0 {
-					// TODO
-					todo!();
-				}
-				if fd.events as u32 & POLLWRNORM != 0 {
-					// TODO
-					todo!();
-				}
-				if fd.events as u32 & POLLWRBAND != 0 {
-					// TODO
-					todo!();
+		let proc_mutex = Process::current();
+		let mut proc = proc_mutex.lock();
+		// If a signal is pending, stop waiting. The temporary mask is restored after the signal
+		// has been handled
+		if proc.next_signal(true).is_some() {
+			return Err(errno!(EINTR));
+		}
+		if sleep {
+			// Make sure the process is woken up at the deadline
+			if let Some(remaining) = remaining {
+				if !armed.swap(true, Ordering::Relaxed) {
+					let armed = armed.clone();
+					workqueue::queue_delayed_work(
+						move || {
+							armed.store(false, Ordering::Relaxed);
+							if let Some(proc) = Process::get_by_pid(pid) {
+								proc.lock().wake();
+							}
+						},
+						remaining.div_ceil(1_000_000),
+					)?;
 				}
 			}
-			// The number of file descriptor with at least one event
-			let fd_event_count = fds_arr.iter().filter(|fd| fd.revents != 0).count();
-			// If at least on event happened, return the number of file descriptors
-			// concerned
-			if fd_event_count > 0 {
-				fds.copy_to_user(0, &fds_arr)?;
-				return Ok(fd_event_count as _);
-			}
+			proc.set_state(process::State::Sleeping);
 		}
-		// TODO Make process sleep until an event occurs on a file descriptor in
-		// `fds`
+		drop(proc);
 		scheduler::end_tick();
+	};
+	if sigmask.is_some() {
+		Process::current().lock().restore_sigmask();
 	}
+	res
+}
+
+/// Performs the poll operation.
+///
+/// Arguments:
+/// - `fds_arr` is the array of file descriptors to check, along with the events to look for
+/// - `nfds` is the number of elements in `fds_arr`
+/// - `timeout` is the timeout in nanoseconds. If `None`, the function waits indefinitely
+/// - `sigmask` is the signal mask to apply while waiting. If `None`, the mask is unchanged
+/// - `fds` is the process's file descriptors table
+///
+/// On success, the function returns the number of file descriptors on which at least one event
+/// occurred, or zero on timeout.
+///
+/// If waiting is interrupted by a signal, the function returns [`errno::EINTR`].
+pub fn do_poll(
+	fds_arr: SyscallSlice<PollFD>,
+	nfds: usize,
+	timeout: Option<Timestamp>,
+	sigmask: Option<SigSet>,
+	fds: &Mutex<FileDescriptorTable>,
+) -> EResult<usize> {
+	let mut pollfds = match fds_arr.copy_from_user(..nfds)? {
+		Some(pollfds) => pollfds,
+		// With no file descriptor, the system call only waits for the timeout
+		None if nfds == 0 => Vec::new(),
+		None => return Err(errno!(EFAULT)),
+	};
+	// The deadline, on the monotonic clock
+	let start = clock::current_time(CLOCK_MONOTONIC, TimestampScale::Nanosecond)?;
+	let deadline = timeout.map(|timeout| start.saturating_add(timeout));
+	let count = wait_events(deadline, sigmask, |sleep| {
+		let mut count = 0;
+		for pollfd in pollfds.iter_mut() {
+			// Negative file descriptors are ignored
+			pollfd.revents = if pollfd.fd >= 0 {
+				poll_fd(fds, pollfd.fd, pollfd.events as u16 as u32, sleep) as _
+			} else {
+				0
+			};
+			if pollfd.revents != 0 {
+				count += 1;
+			}
+		}
+		Ok(count)
+	})?;
+	fds_arr.copy_to_user(0, &pollfds)?;
+	Ok(count)
+}
+
+pub(super) fn poll(
+	Args((fds_arr, nfds, timeout)): Args<(SyscallSlice<PollFD>, usize, c_int)>,
+	fds: Arc<Mutex<FileDescriptorTable>>,
+) -> EResult<usize> {
+	// A negative timeout means no timeout
+	let timeout = (timeout >= 0).then(|| timeout as Timestamp * 1_000_000);
+	do_poll(fds_arr, nfds, timeout, None, &fds)
 }
@@ -0,0 +1,90 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The `ppoll` system call is similar to `poll`, except it takes a timeout with a nanosecond
+//! precision and allows to atomically replace the signal mask while waiting.
+
+use super::poll::{do_poll, PollFD};
+use crate::{
+	file::fd::FileDescriptorTable,
+	process::{
+		mem_space::copy::{SyscallPtr, SyscallSlice},
+		signal::SigSet,
+	},
+	syscall::Args,
+	time::unit::{TimeUnit, Timespec32},
+};
+use core::{intrinsics::unlikely, mem::size_of};
+use utils::{
+	errno,
+	errno::{EResult, Errno},
+	lock::Mutex,
+	ptr::arc::Arc,
+};
+
+/// Performs the `ppoll` operation with a timeout of type `T`.
+///
+/// `is_valid` tells whether the timeout is valid. If not, the function returns
+/// [`errno::EINVAL`]. The other arguments are the same as the system call's.
+pub(super) fn do_ppoll<T: TimeUnit>(
+	fds_arr: SyscallSlice<PollFD>,
+	nfds: usize,
+	tmo_p: SyscallPtr<T>,
+	is_valid: fn(&T) -> bool,
+	sigmask: SyscallPtr<SigSet>,
+	sigsetsize: usize,
+	fds: &Mutex<FileDescriptorTable>,
+) -> EResult<usize> {
+	let timeout = tmo_p.copy_from_user()?;
+	if unlikely(timeout.as_ref().is_some_and(|timeout| !is_valid(timeout))) {
+		return Err(errno!(EINVAL));
+	}
+	let sigmask = sigmask.copy_from_user()?;
+	if unlikely(sigmask.is_some() && sigsetsize != size_of::<SigSet>()) {
+		return Err(errno!(EINVAL));
+	}
+	do_poll(
+		fds_arr,
+		nfds,
+		timeout.as_ref().map(TimeUnit::to_nano),
+		sigmask,
+		fds,
+	)
+}
+
+#[allow(clippy::type_complexity)]
+pub fn ppoll(
+	Args((fds_arr, nfds, tmo_p, sigmask, sigsetsize)): Args<(
+		SyscallSlice<PollFD>,
+		usize,
+		SyscallPtr<Timespec32>,
+		SyscallPtr<SigSet>,
+		usize,
+	)>,
+	fds: Arc<Mutex<FileDescriptorTable>>,
+) -> EResult<usize> {
+	do_ppoll(
+		fds_arr,
+		nfds,
+		tmo_p,
+		|ts| ts.tv_nsec < 1_000_000_000,
+		sigmask,
+		sigsetsize,
+		&fds,
+	)
+}
@@ -0,0 +1,52 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+//! `ppoll_time64` is the same as `ppoll`, with a 64 bits timeout.
+
+use super::{poll::PollFD, ppoll::do_ppoll, pselect6_time64::timespec_valid};
+use crate::{
+	file::fd::FileDescriptorTable,
+	process::{
+		mem_space::copy::{SyscallPtr, SyscallSlice},
+		signal::SigSet,
+	},
+	syscall::Args,
+	time::unit::Timespec,
+};
+use utils::{errno::EResult, lock::Mutex, ptr::arc::Arc};
+
+#[allow(clippy::type_complexity)]
+pub fn ppoll_time64(
+	Args((fds_arr, nfds, tmo_p, sigmask, sigsetsize)): Args<(
+		SyscallSlice<PollFD>,
+		usize,
+		SyscallPtr<Timespec>,
+		SyscallPtr<SigSet>,
+		usize,
+	)>,
+	fds: Arc<Mutex<FileDescriptorTable>>,
+) -> EResult<usize> {
+	do_ppoll(
+		fds_arr,
+		nfds,
+		tmo_p,
+		timespec_valid,
+		sigmask,
+		sigsetsize,
+		&fds,
+	)
+}
@@ -0,0 +1,68 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The `pread64` system call reads from a file at a given offset, without changing the file's
+//! current offset.
+
+use super::Args;
+use crate::{
+	file::{
+		fanotify,
+		fanotify::{FAN_ACCESS, FAN_ACCESS_PERM},
+		fd::FileDescriptorTable,
+		FileType,
+	},
+	process::{mem_space::copy::SyscallSlice, Process},
+};
+use core::{cmp::min, ffi::c_int};
+use utils::{
+	errno,
+	errno::{EResult, Errno},
+	lock::Mutex,
+	ptr::arc::Arc,
+	vec,
+};
+
+pub fn pread64(
+	Args((fd, buf, count, off_lo, off_hi)): Args<(c_int, SyscallSlice<u8>, usize, u32, u32)>,
+	fds: Arc<Mutex<FileDescriptorTable>>,
+) -> EResult<usize> {
+	// On 32 bits, the 64 bits offset is split across two arguments
+	let off = ((off_hi as i64) << 32) | off_lo as i64;
+	let off: u64 = off.try_into().map_err(|_| errno!(EINVAL))?;
+	let file = fds.lock().get_fd(fd)?.get_file().clone();
+	// Validation
+	match file.get_type()? {
+		FileType::Link => return Err(errno!(EINVAL)),
+		FileType::Directory => return Err(errno!(EISDIR)),
+		_ => file.check_seekable()?,
+	}
+	let len = min(count, i32::MAX as usize);
+	if len == 0 {
+		return Ok(0);
+	}
+	fanotify::notify_file(&file, FAN_ACCESS_PERM)?;
+	// TODO perf: a buffer is not necessarily required
+	let mut buffer = vec![0u8; len]?;
+	let len = file.ops.read(&file, off, &mut buffer)?;
+	buf.copy_to_user(0, &buffer[..len])?;
+	Process::current().lock().io.account_read(len);
+	// The data has been read, failing to notify must not fail the system call
+	let _ = fanotify::notify_file(&file, FAN_ACCESS);
+	Ok(len)
+}
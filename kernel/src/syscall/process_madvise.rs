@@ -0,0 +1,73 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The `process_madvise` system call gives advices about the usage of memory of another process,
+//! allowing for instance a low-memory daemon to reclaim memory from background processes.
+
+use super::madvise::{do_madvise, MADV_COLD, MADV_PAGEOUT};
+use crate::{
+	file::{fd::FileDescriptorTable, perm::AccessProfile, pidfd::PidFd},
+	process::{iovec::IOVec, mem_space::copy::SyscallSlice, Process},
+	syscall::Args,
+};
+use core::ffi::{c_int, c_uint};
+use utils::{errno, errno::EResult, limits::IOV_MAX, lock::Mutex, ptr::arc::Arc};
+
+pub fn process_madvise(
+	Args((pidfd, iov, vlen, advice, flags)): Args<(
+		c_int,
+		SyscallSlice<IOVec>,
+		usize,
+		c_int,
+		c_uint,
+	)>,
+	fds: Arc<Mutex<FileDescriptorTable>>,
+	ap: AccessProfile,
+) -> EResult<usize> {
+	if flags != 0 || vlen > IOV_MAX || !matches!(advice, MADV_COLD | MADV_PAGEOUT) {
+		return Err(errno!(EINVAL));
+	}
+	let file = fds.lock().get_fd(pidfd)?.get_file().clone();
+	let target = file
+		.get_buffer::<PidFd>()
+		.ok_or_else(|| errno!(EBADF))?
+		.get_process()?;
+	let current_pid = Process::current().lock().get_pid();
+	let mem_space = {
+		let target = target.lock();
+		if target.get_pid() != current_pid && !ap.is_privileged() {
+			return Err(errno!(EPERM));
+		}
+		target
+			.get_mem_space()
+			.cloned()
+			.ok_or_else(|| errno!(ESRCH))?
+	};
+	let iov = iov.copy_from_user(..vlen)?.ok_or_else(|| errno!(EFAULT))?;
+	let mut total = 0usize;
+	for i in iov {
+		let res = do_madvise(&mem_space, i.iov_base, i.iov_len, advice);
+		match res {
+			Ok(()) => total = total.saturating_add(i.iov_len),
+			// Report the advised length if at least one range has been processed
+			Err(_) if total > 0 => break,
+			Err(e) => return Err(e),
+		}
+	}
+	Ok(total)
+}
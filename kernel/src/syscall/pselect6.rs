@@ -16,25 +16,53 @@
  * Maestro. If not, see <https://www.gnu.org/licenses/>.
  */
 
-//! `pselect6` is similar to `select`.
+//! `pselect6` is similar to `select`, except it takes a timeout with a nanosecond precision and
+//! allows to atomically replace the signal mask while waiting.
 
-use super::select::{do_select, FDSet};
+use super::select::{do_select_timeout, FDSet};
 use crate::{
 	file::fd::FileDescriptorTable,
-	process::mem_space::{
-		copy::{SyscallPtr, SyscallSlice},
-		MemSpace,
-	},
-	syscall::Args,
-	time::unit::Timespec,
+	process::{mem_space::copy::SyscallPtr, signal::SigSet},
+	syscall::{Args, FromSyscallArg},
+	time::unit::Timespec32,
 };
-use core::ffi::c_int;
+use core::{ffi::c_int, intrinsics::unlikely, mem::size_of};
 use utils::{
-	errno::EResult,
-	lock::{IntMutex, Mutex},
+	errno,
+	errno::{EResult, Errno},
+	lock::Mutex,
 	ptr::arc::Arc,
 };
 
+/// The signal mask argument of `pselect6`.
+#[repr(C)]
+#[derive(Debug)]
+pub struct SigSetArg {
+	/// The signal mask.
+	ss: *const SigSet,
+	/// The size of the signal mask, in bytes.
+	ss_len: usize,
+}
+
+/// Reads the signal mask to apply while waiting, from the argument at `ptr`.
+///
+/// If the size of the mask is invalid, the function returns [`errno::EINVAL`].
+pub(super) fn read_sigmask(ptr: SyscallPtr<SigSetArg>) -> EResult<Option<SigSet>> {
+	let Some(arg) = ptr.copy_from_user()? else {
+		return Ok(None);
+	};
+	let sigmask = SyscallPtr::<SigSet>::from_syscall_arg(arg.ss as usize).copy_from_user()?;
+	if unlikely(sigmask.is_some() && arg.ss_len != size_of::<SigSet>()) {
+		return Err(errno!(EINVAL));
+	}
+	Ok(sigmask)
+}
+
+/// Tells whether the given timeout is valid.
+fn timespec_valid(ts: &Timespec32) -> bool {
+	ts.tv_sec <= i32::MAX as u32 && ts.tv_nsec < 1_000_000_000
+}
+
 #[allow(clippy::type_complexity)]
 pub fn pselect6(
 	Args((nfds, readfds, writefds, exceptfds, timeout, sigmask)): Args<(
@@ -42,18 +70,20 @@ pub fn pselect6(
 		SyscallPtr<FDSet>,
 		SyscallPtr<FDSet>,
 		SyscallPtr<FDSet>,
-		SyscallPtr<Timespec>,
-		SyscallSlice<u8>,
+		SyscallPtr<Timespec32>,
+		SyscallPtr<SigSetArg>,
 	)>,
 	fds: Arc<Mutex<FileDescriptorTable>>,
 ) -> EResult<usize> {
-	do_select(
-		fds,
-		nfds as _,
+	let sigmask = read_sigmask(sigmask)?;
+	do_select_timeout(
+		nfds,
 		readfds,
 		writefds,
 		exceptfds,
 		timeout,
-		Some(sigmask),
+		timespec_valid,
+		sigmask,
+		&fds,
 	)
 }
@@ -0,0 +1,60 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! `pselect6_time64` is the same as `pselect6`, with a 64 bits timeout.
+
+use super::{
+	pselect6::{read_sigmask, SigSetArg},
+	select::{do_select_timeout, FDSet},
+};
+use crate::{
+	file::fd::FileDescriptorTable, process::mem_space::copy::SyscallPtr, syscall::Args,
+	time::unit::Timespec,
+};
+use core::ffi::c_int;
+use utils::{errno::EResult, lock::Mutex, ptr::arc::Arc};
+
+/// Tells whether the given timeout is valid.
+pub(super) fn timespec_valid(ts: &Timespec) -> bool {
+	ts.tv_sec <= i64::MAX as u64 && (0..1_000_000_000).contains(&ts.tv_nsec)
+}
+
+#[allow(clippy::type_complexity)]
+pub fn pselect6_time64(
+	Args((nfds, readfds, writefds, exceptfds, timeout, sigmask)): Args<(
+		c_int,
+		SyscallPtr<FDSet>,
+		SyscallPtr<FDSet>,
+		SyscallPtr<FDSet>,
+		SyscallPtr<Timespec>,
+		SyscallPtr<SigSetArg>,
+	)>,
+	fds: Arc<Mutex<FileDescriptorTable>>,
+) -> EResult<usize> {
+	let sigmask = read_sigmask(sigmask)?;
+	do_select_timeout(
+		nfds,
+		readfds,
+		writefds,
+		exceptfds,
+		timeout,
+		timespec_valid,
+		sigmask,
+		&fds,
+	)
+}
@@ -0,0 +1,67 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The `pwrite64` system call writes to a file at a given offset, without changing the file's
+//! current offset.
+
+use super::Args;
+use crate::{
+	file::{
+		fanotify, fanotify::FAN_MODIFY, fd::FileDescriptorTable, perm::AccessProfile, vfs,
+		FileType,
+	},
+	process::{mem_space::copy::SyscallSlice, Process},
+};
+use core::{cmp::min, ffi::c_int};
+use utils::{
+	errno,
+	errno::{EResult, Errno},
+	lock::Mutex,
+	ptr::arc::Arc,
+};
+
+pub fn pwrite64(
+	Args((fd, buf, count, off_lo, off_hi)): Args<(c_int, SyscallSlice<u8>, usize, u32, u32)>,
+	fds: Arc<Mutex<FileDescriptorTable>>,
+	ap: AccessProfile,
+) -> EResult<usize> {
+	// On 32 bits, the 64 bits offset is split across two arguments
+	let off = ((off_hi as i64) << 32) | off_lo as i64;
+	let off: u64 = off.try_into().map_err(|_| errno!(EINVAL))?;
+	let file = fds.lock().get_fd(fd)?.get_file().clone();
+	// Validation
+	match file.get_type()? {
+		FileType::Link => return Err(errno!(EINVAL)),
+		FileType::Directory => return Err(errno!(EISDIR)),
+		_ => file.check_seekable()?,
+	}
+	let len = min(count, i32::MAX as usize);
+	if len == 0 {
+		return Ok(0);
+	}
+	// TODO find a way to avoid allocating here
+	let buf_slice = buf.copy_from_user(..len)?.ok_or(errno!(EFAULT))?;
+	let len = file.ops.write(&file, off, &buf_slice)?;
+	if let Some(ent) = &file.vfs_entry {
+		vfs::content_modified(ent, &ap)?;
+	}
+	Process::current().lock().io.account_write(len);
+	// The data has been written, failing to notify must not fail the system call
+	let _ = fanotify::notify_file(&file, FAN_MODIFY);
+	Ok(len)
+}
@@ -19,7 +19,7 @@
 //! The `pwritev` system call allows to write sparse data on a file descriptor.
 
 use crate::{
-	file::fd::FileDescriptorTable,
+	file::{fd::FileDescriptorTable, perm::AccessProfile},
 	process::{iovec::IOVec, mem_space::copy::SyscallSlice, Process},
 	syscall::Args,
 };
@@ -33,6 +33,7 @@ use utils::{
 pub fn pwritev(
 	Args((fd, iov, iovcnt, offset)): Args<(c_int, SyscallSlice<IOVec>, c_int, isize)>,
 	fds: Arc<Mutex<FileDescriptorTable>>,
+	ap: AccessProfile,
 ) -> EResult<usize> {
-	super::writev::do_writev(fd, iov, iovcnt, Some(offset), None, fds)
+	super::writev::do_writev(fd, iov, iovcnt, Some(offset), None, fds, ap)
 }
@@ -19,7 +19,7 @@
 //! The `pwritev2` system call allows to write sparse data on a file descriptor.
 
 use crate::{
-	file::fd::FileDescriptorTable,
+	file::{fd::FileDescriptorTable, perm::AccessProfile},
 	process::{iovec::IOVec, mem_space::copy::SyscallSlice, Process},
 	syscall::Args,
 };
@@ -39,6 +39,7 @@ pub fn pwritev2(
 		c_int,
 	)>,
 	fds: Arc<Mutex<FileDescriptorTable>>,
+	ap: AccessProfile,
 ) -> EResult<usize> {
-	super::writev::do_writev(fd, iov, iovcnt, Some(offset), Some(flags), fds)
+	super::writev::do_writev(fd, iov, iovcnt, Some(offset), Some(flags), fds, ap)
 }
@@ -20,7 +20,12 @@
 
 use super::Args;
 use crate::{
-	file::{fd::FileDescriptorTable, FileType},
+	file::{
+		fanotify,
+		fanotify::{FAN_ACCESS, FAN_ACCESS_PERM},
+		fd::FileDescriptorTable,
+		FileType,
+	},
 	process::{mem_space::copy::SyscallSlice, regs::Regs, scheduler, Process},
 };
 use core::{cmp::min, ffi::c_int, sync::atomic};
@@ -47,6 +52,7 @@ pub fn read(
 	if file.get_type()? == FileType::Link {
 		return Err(errno!(EINVAL));
 	}
+	fanotify::notify_file(&file, FAN_ACCESS_PERM)?;
 	// TODO perf: a buffer is not necessarily required
 	let mut buffer = vec![0u8; count]?;
 	let off = file.off.load(atomic::Ordering::Acquire);
@@ -56,5 +62,8 @@ pub fn read(
 	file.off.store(new_off, atomic::Ordering::Release);
 	// Write back
 	buf.copy_to_user(0, &buffer[..len])?;
+	Process::current().lock().io.account_read(len);
+	// The data has been read, failing to notify must not fail the system call
+	let _ = fanotify::notify_file(&file, FAN_ACCESS);
 	Ok(len as _)
 }
@@ -27,7 +27,7 @@ use crate::{
 	syscall::Args,
 };
 use utils::{
-	collections::{path::PathBuf, vec::Vec},
+	collections::vec::Vec,
 	errno,
 	errno::{EResult, Errno},
 	vec,
@@ -42,8 +42,7 @@ pub fn readlink(
 		let proc = proc_mutex.lock();
 
 		// Get file's path
-		let path = pathname.copy_from_user()?.ok_or(errno!(EFAULT))?;
-		let path = PathBuf::try_from(path)?;
+		let path = pathname.copy_path_from_user()?.ok_or(errno!(EFAULT))?;
 
 		let rs = ResolutionSettings::for_process(&proc, false);
 		(path, rs)
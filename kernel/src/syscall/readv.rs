@@ -112,7 +112,11 @@ pub fn do_readv(
 	if file.get_type()? == FileType::Link {
 		return Err(errno!(EINVAL));
 	}
+	if offset.is_some() {
+		file.check_seekable()?;
+	}
 	let len = read(&iov, iovcnt as _, offset, &file)?;
+	Process::current().lock().io.account_read(len);
 	Ok(len as _)
 }
 
@@ -65,14 +65,17 @@ pub fn reboot(
 	}
 	match cmd {
 		CMD_POWEROFF => {
+			power::prepare_shutdown();
 			crate::println!("Power down...");
 			power::shutdown();
 		}
 		CMD_REBOOT => {
+			power::prepare_shutdown();
 			crate::println!("Rebooting...");
 			power::reboot();
 		}
 		CMD_HALT => {
+			power::prepare_shutdown();
 			crate::println!("Halting...");
 			power::halt();
 		}
@@ -0,0 +1,157 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The `recvmsg` system call receives a message from a socket, with ancillary data.
+
+use super::sendmsg::{cmsg_align, CmsgHdr, MsgHdr};
+use crate::{
+	file::{
+		fd::{FileDescriptorTable, FD_CLOEXEC},
+		socket::{
+			Socket, MSG_CMSG_CLOEXEC, MSG_CTRUNC, MSG_TRUNC, SCM_CREDENTIALS, SCM_RIGHTS,
+			SOL_SOCKET,
+		},
+		File,
+	},
+	net::unix::{Ucred, CHANNEL_SIZE},
+	process::{
+		mem_space::copy::{SyscallPtr, SyscallSlice},
+		Process,
+	},
+	syscall::{Args, FromSyscallArg},
+};
+use core::{cmp::min, ffi::c_int, mem::size_of, slice};
+use utils::{
+	bytes, collections::vec::Vec, errno, errno::EResult, lock::Mutex, ptr::arc::Arc, vec,
+};
+
+/// Appends an ancillary data object of type `type_`, containing `data`, to `control`.
+fn put_cmsg(control: &mut Vec<u8>, type_: c_int, data: &[u8]) -> EResult<()> {
+	let hdr_len = size_of::<CmsgHdr>();
+	let hdr = CmsgHdr {
+		cmsg_len: cmsg_align(hdr_len) + data.len(),
+		cmsg_level: SOL_SOCKET,
+		cmsg_type: type_,
+	};
+	let hdr_bytes = unsafe { slice::from_raw_parts(&hdr as *const _ as *const u8, hdr_len) };
+	let off = control.len();
+	control.extend_from_slice(hdr_bytes)?;
+	control.resize(off + cmsg_align(hdr_len), 0)?;
+	control.extend_from_slice(data)?;
+	control.resize(off + cmsg_align(hdr.cmsg_len), 0)?;
+	Ok(())
+}
+
+/// Appends the credentials `cred` received with [`SCM_CREDENTIALS`] to `control`, if the
+/// ancillary data buffer of `msg` has space for them.
+///
+/// If the buffer is too small, [`MSG_CTRUNC`] is set on `msg`.
+fn put_cred(msg: &mut MsgHdr, control: &mut Vec<u8>, cred: Ucred) -> EResult<()> {
+	let len = cmsg_align(size_of::<CmsgHdr>()) + size_of::<Ucred>();
+	if msg.msg_controllen.saturating_sub(control.len()) < len {
+		msg.msg_flags |= MSG_CTRUNC;
+		return Ok(());
+	}
+	let cred = cred.to_local(&Process::current().lock());
+	put_cmsg(control, SCM_CREDENTIALS, bytes::as_bytes(&cred))
+}
+
+/// Installs the `files` received with [`SCM_RIGHTS`] in the file descriptor table `fds`, then
+/// appends the corresponding ancillary data to `control`.
+///
+/// If the ancillary data buffer of `msg` is too small, or if file descriptors cannot be
+/// allocated, the remaining files are discarded and [`MSG_CTRUNC`] is set on `msg`.
+fn put_rights(
+	msg: &mut MsgHdr,
+	control: &mut Vec<u8>,
+	files: Vec<Arc<File>>,
+	flags: c_int,
+	fds: &mut FileDescriptorTable,
+) -> EResult<()> {
+	if files.is_empty() {
+		return Ok(());
+	}
+	let fd_flags = if flags & MSG_CMSG_CLOEXEC != 0 {
+		FD_CLOEXEC
+	} else {
+		0
+	};
+	let available = msg.msg_controllen.saturating_sub(control.len());
+	let max = available.saturating_sub(cmsg_align(size_of::<CmsgHdr>())) / size_of::<c_int>();
+	let count = files.len();
+	let mut data = Vec::new();
+	for file in files.into_iter().take(max) {
+		let Ok((fd, _)) = fds.create_fd(fd_flags, file) else {
+			break;
+		};
+		data.extend_from_slice(&(fd as c_int).to_ne_bytes())?;
+	}
+	if data.len() / size_of::<c_int>() < count {
+		msg.msg_flags |= MSG_CTRUNC;
+	}
+	if data.is_empty() {
+		return Ok(());
+	}
+	put_cmsg(control, SCM_RIGHTS, &data)
+}
+
+pub fn recvmsg(
+	Args((sockfd, msg_ptr, flags)): Args<(c_int, SyscallPtr<MsgHdr>, c_int)>,
+	fds: Arc<Mutex<FileDescriptorTable>>,
+) -> EResult<usize> {
+	let mut msg = msg_ptr.copy_from_user()?.ok_or_else(|| errno!(EFAULT))?;
+	let file = fds.lock().get_fd(sockfd)?.get_file().clone();
+	let sock: &Socket = file.get_buffer().ok_or_else(|| errno!(ENOTSOCK))?;
+	let iov = msg.get_iov()?;
+	// No more data than the size of a channel can be received at once
+	let len = iov
+		.iter()
+		.fold(0usize, |len, iov| len.saturating_add(iov.iov_len));
+	let mut buf = vec![0u8; min(len, CHANNEL_SIZE)]?;
+	let res = sock.recv(&file, &mut buf, flags)?;
+	// Scatter the data
+	let mut off = 0;
+	for iov in &iov {
+		if off >= res.len {
+			break;
+		}
+		let len = min(iov.iov_len, res.len - off);
+		SyscallSlice::<u8>::from_syscall_arg(iov.iov_base as usize)
+			.copy_to_user(0, &buf[off..(off + len)])?;
+		off += len;
+	}
+	// The peer is unnamed
+	msg.msg_namelen = 0;
+	msg.msg_flags = 0;
+	if res.truncated {
+		msg.msg_flags |= MSG_TRUNC;
+	}
+	// Ancillary data
+	let mut control = Vec::new();
+	if let Some(cred) = res.cred.filter(|_| sock.is_passcred()) {
+		put_cred(&mut msg, &mut control, cred)?;
+	}
+	put_rights(&mut msg, &mut control, res.files, flags, &mut fds.lock())?;
+	let len = min(control.len(), msg.msg_controllen);
+	SyscallSlice::<u8>::from_syscall_arg(msg.msg_control as usize)
+		.copy_to_user(0, &control[..len])?;
+	msg.msg_controllen = len;
+	msg_ptr.copy_to_user(msg)?;
+	Process::current().lock().io.account_read(res.len);
+	Ok(res.len)
+}
@@ -0,0 +1,62 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The `removexattr` system call removes an extended attribute of a file.
+
+use crate::{
+	file::{perm::AccessProfile, vfs, vfs::ResolutionSettings},
+	process::mem_space::copy::SyscallString,
+	syscall::Args,
+};
+use utils::{
+	errno,
+	errno::{EResult, Errno},
+};
+
+/// Removes the extended attribute `name` of the file `file`.
+///
+/// `ap` is the access profile to check permissions.
+pub fn removexattr_entry(
+	file: &vfs::Entry,
+	name: SyscallString,
+	ap: &AccessProfile,
+) -> EResult<usize> {
+	let name = super::getxattr::copy_name(name)?;
+	vfs::remove_xattr(file, name.as_bytes(), ap)?;
+	Ok(0)
+}
+
+/// Performs the `removexattr` syscall.
+pub fn do_removexattr(
+	pathname: SyscallString,
+	name: SyscallString,
+	rs: ResolutionSettings,
+) -> EResult<usize> {
+	let path = pathname
+		.copy_path_from_user()?
+		.ok_or_else(|| errno!(EFAULT))?;
+	let file = vfs::get_file_from_path(&path, &rs)?;
+	removexattr_entry(&file, name, &rs.access_profile)
+}
+
+pub fn removexattr(
+	Args((pathname, name)): Args<(SyscallString, SyscallString)>,
+	rs: ResolutionSettings,
+) -> EResult<usize> {
+	do_removexattr(pathname, name, rs)
+}
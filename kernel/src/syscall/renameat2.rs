@@ -22,28 +22,22 @@ use super::util::at;
 use crate::{
 	file::{
 		fd::FileDescriptorTable,
+		fs::{RENAME_EXCHANGE, RENAME_NOREPLACE},
 		vfs,
 		vfs::{ResolutionSettings, Resolved},
-		FileType,
 	},
 	process::{mem_space::copy::SyscallString, Process},
 	syscall::Args,
 };
 use core::ffi::c_int;
 use utils::{
-	collections::path::PathBuf,
+	collections::string::String,
 	errno,
 	errno::{EResult, Errno},
 	lock::Mutex,
 	ptr::arc::Arc,
 };
 
-/// Flag: Don't replace new path if it exists. Return an error instead.
-const RENAME_NOREPLACE: c_int = 1;
-/// Flag: Exchanges old and new paths atomically.
-const RENAME_EXCHANGE: c_int = 2;
-
-// TODO implement flags
 // TODO do not allow rename if the file is in use (example: cwd of a process, listing subfiles,
 // etc...)
 
@@ -52,58 +46,50 @@ pub(super) fn do_renameat2(
 	oldpath: SyscallString,
 	newdirfd: c_int,
 	newpath: SyscallString,
-	_flags: c_int,
+	flags: c_int,
 	fds: Arc<Mutex<FileDescriptorTable>>,
 	rs: ResolutionSettings,
 ) -> EResult<usize> {
+	let flags = flags as u32;
+	if flags & !(RENAME_NOREPLACE | RENAME_EXCHANGE) != 0 {
+		return Err(errno!(EINVAL));
+	}
+	if flags & RENAME_NOREPLACE != 0 && flags & RENAME_EXCHANGE != 0 {
+		return Err(errno!(EINVAL));
+	}
 	let rs = ResolutionSettings {
 		follow_link: false,
 		..rs
 	};
 	// Get old file
 	let oldpath = oldpath
-		.copy_from_user()?
-		.map(PathBuf::try_from)
-		.ok_or_else(|| errno!(EFAULT))??;
-	let old_parent_path = oldpath.parent().ok_or_else(|| errno!(ENOTDIR))?;
-	let old_name = oldpath.file_name().ok_or_else(|| errno!(ENOENT))?;
-	let old_parent = vfs::get_file_from_path(old_parent_path, &rs)?;
+		.copy_path_from_user()?
+		.ok_or_else(|| errno!(EFAULT))?;
 	let Resolved::Found(old) = at::get_file(&fds.lock(), rs.clone(), olddirfd, Some(&oldpath), 0)?
 	else {
 		return Err(errno!(ENOENT));
 	};
 	// Get new file
 	let newpath = newpath
-		.copy_from_user()?
-		.map(PathBuf::try_from)
-		.ok_or_else(|| errno!(EFAULT))??;
+		.copy_path_from_user()?
+		.ok_or_else(|| errno!(EFAULT))?;
 	let rs = ResolutionSettings {
 		create: true,
 		..rs
 	};
-	// TODO RENAME_NOREPLACE
-	let Resolved::Creatable {
-		parent: new_parent,
-		name: new_name,
-	} = at::get_file(&fds.lock(), rs.clone(), newdirfd, Some(&newpath), 0)?
-	else {
-		return Err(errno!(EEXIST));
-	};
-	// Create destination file
-	{
-		// If source and destination are on different mountpoints, error
-		if new_parent.node().location.mountpoint_id != old.node().location.mountpoint_id {
-			return Err(errno!(EXDEV));
-		}
-		// TODO Check permissions if sticky bit is set
-		// Create link at new location
-		// The `..` entry is already updated by the file system since having the same
-		// directory in several locations is not allowed
-		vfs::link(&new_parent, new_name, &old, &rs.access_profile)?;
-	}
-	// Remove source file
-	// TODO on failure, undo previous creation
-	vfs::unlink(old_parent, old_name, &rs.access_profile)?;
+	// If the destination exists, the VFS decides whether it can be replaced, depending on flags
+	let (new_parent, new_name) =
+		match at::get_file(&fds.lock(), rs.clone(), newdirfd, Some(&newpath), 0)? {
+			Resolved::Found(ent) => {
+				let parent = ent.get_parent().cloned().ok_or_else(|| errno!(EBUSY))?;
+				(parent, String::try_from(&*ent.name)?)
+			}
+			Resolved::Creatable {
+				parent,
+				name,
+			} => (parent, String::try_from(name)?),
+		};
+	vfs::rename(old, new_parent, &new_name, flags, &rs.access_profile)?;
 	Ok(0)
 }
 
@@ -26,20 +26,27 @@ use crate::{
 	syscall::Args,
 };
 use utils::{
-	collections::path::PathBuf,
 	errno,
 	errno::{EResult, Errno},
 };
 
 pub fn rmdir(Args(pathname): Args<SyscallString>, rs: ResolutionSettings) -> EResult<usize> {
-	let path = pathname.copy_from_user()?.ok_or(errno!(EFAULT))?;
-	let path = PathBuf::try_from(path)?;
+	let path = pathname.copy_path_from_user()?.ok_or(errno!(EFAULT))?;
 	// Validation
 	{
 		let file = vfs::get_file_from_path(&path, &rs)?;
 		if file.get_type()? != FileType::Directory {
 			return Err(errno!(ENOTDIR));
 		}
+		// The root of a mountpoint cannot be removed
+		if file.get_mountpoint().is_some() {
+			return Err(errno!(EBUSY));
+		}
+	}
+	match path.file_name() {
+		Some(b".") => return Err(errno!(EINVAL)),
+		Some(b"..") => return Err(errno!(ENOTEMPTY)),
+		_ => {}
 	}
 	// Remove
 	vfs::unlink_from_path(&path, &rs)?;
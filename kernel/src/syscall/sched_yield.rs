@@ -17,12 +17,12 @@
  */
 
 //! The `sched_yield` system call ends the current tick of the current process and returns the
-//! control back to the scheduler.
+//! control back to the scheduler, which runs other processes with the same priority first.
 
 use crate::process::scheduler;
 use utils::errno::{EResult, Errno};
 
 pub fn sched_yield() -> EResult<usize> {
-	scheduler::end_tick();
+	scheduler::yield_current();
 	Ok(0)
 }
@@ -19,36 +19,39 @@
 //! `select` waits for a file descriptor in the given sets to be readable,
 //! writable or for an exception to occur.
 
+use super::poll::{poll_fd, wait_events, POLLERR, POLLHUP, POLLIN, POLLNVAL, POLLOUT, POLLPRI};
 use crate::{
 	file::fd::FileDescriptorTable,
-	process::{
-		mem_space::{
-			copy::{SyscallPtr, SyscallSlice},
-			MemSpace,
-		},
-		scheduler, Process,
-	},
-	syscall::{poll, Args},
+	process::{mem_space::copy::SyscallPtr, signal::SigSet},
+	syscall::{Args, FromSyscallArg},
 	time::{
 		clock,
 		clock::CLOCK_MONOTONIC,
-		unit::{TimeUnit, Timeval},
+		unit::{TimeUnit, Timestamp, TimestampScale, Timeval32},
 	},
 };
 use core::{
 	cmp::min,
-	ffi::{c_int, c_long},
+	ffi::{c_int, c_long, c_ulong},
+	intrinsics::unlikely,
 };
 use utils::{
 	errno,
 	errno::{EResult, Errno},
-	lock::{IntMutex, Mutex},
+	lock::Mutex,
 	ptr::arc::Arc,
 };
 
 /// The number of file descriptors in FDSet.
 pub const FD_SETSIZE: usize = 1024;
 
+/// Events making a file descriptor ready for reading.
+const READ_EVENTS: u32 = POLLIN | POLLHUP | POLLERR;
+/// Events making a file descriptor ready for writing.
+const WRITE_EVENTS: u32 = POLLOUT | POLLERR;
+/// Events making a file descriptor have an exceptional condition.
+const EXCEPT_EVENTS: u32 = POLLPRI;
+
 /// Structure representing `fd_set`.
 #[repr(C)]
 #[derive(Debug)]
@@ -58,153 +61,217 @@ pub struct FDSet {
 }
 
 impl FDSet {
+	/// Returns an empty set.
+	fn empty() -> Self {
+		Self {
+			fds_bits: [0; FD_SETSIZE / c_long::BITS as usize],
+		}
+	}
+
 	/// Tells whether the given file descriptor `fd` is set in the list.
 	fn is_set(&self, fd: u32) -> bool {
 		if fd as usize >= FD_SETSIZE {
 			return false;
 		}
-		// TODO Check correctness
 		let i = (fd as usize) / c_long::BITS as usize;
-		self.fds_bits[i] >> (fd % c_long::BITS) != 0
+		(self.fds_bits[i] >> (fd % c_long::BITS)) & 1 != 0
 	}
 
-	/// Sets or clears the bit for file descriptor `fd`.
-	fn set(&mut self, fd: u32, val: bool) {
-		// TODO Check correctness
+	/// Sets the bit for file descriptor `fd`.
+	fn set(&mut self, fd: u32) {
 		let i = (fd as usize) / c_long::BITS as usize;
-		if val {
-			self.fds_bits[i] |= 1 << (fd % c_long::BITS);
-		} else {
-			self.fds_bits[i] &= !(1 << (fd % c_long::BITS));
+		self.fds_bits[i] |= 1 << (fd % c_long::BITS);
+	}
+}
+
+/// A set of file descriptors passed to [`do_select`], along with the events to look for.
+struct SelectSet {
+	/// The location of the set in userspace.
+	ptr: SyscallPtr<FDSet>,
+	/// The set read from userspace, or `None` if the pointer is null.
+	input: Option<FDSet>,
+	/// The set of file descriptors on which an event occurred.
+	output: FDSet,
+	/// The events the set is looking for.
+	events: u32,
+}
+
+impl SelectSet {
+	/// Reads the set at `ptr`, looking for `events`.
+	fn new(ptr: SyscallPtr<FDSet>, events: u32) -> EResult<Self> {
+		Ok(Self {
+			input: ptr.copy_from_user()?,
+			ptr,
+			output: FDSet::empty(),
+			events,
+		})
+	}
+
+	/// Returns the events to look for on `fd`.
+	fn events(&self, fd: u32) -> u32 {
+		match &self.input {
+			Some(set) if set.is_set(fd) => self.events,
+			_ => 0,
+		}
+	}
+}
+
+/// Polls the file descriptors below `nfds` in `sets`, setting the ones on which an event
+/// occurred in the output sets.
+///
+/// The function returns the number of bits set. If a set contains an invalid file descriptor,
+/// the function returns [`errno::EBADF`].
+fn select_fds(
+	sets: &mut [SelectSet; 3],
+	nfds: u32,
+	fds: &Mutex<FileDescriptorTable>,
+	sleep: &mut bool,
+) -> EResult<usize> {
+	let mut count = 0;
+	for fd in 0..nfds {
+		let events = sets.iter().fold(0, |events, set| events | set.events(fd));
+		if events == 0 {
+			continue;
+		}
+		let revents = poll_fd(fds, fd as _, events, sleep);
+		if revents & POLLNVAL != 0 {
+			return Err(errno!(EBADF));
+		}
+		for set in sets.iter_mut() {
+			if revents & set.events(fd) != 0 {
+				set.output.set(fd);
+				count += 1;
+			}
 		}
 	}
+	Ok(count)
 }
 
 /// Performs the select operation.
 ///
 /// Arguments:
-/// - `mem_space` is the process's memory space.
-/// - `fds` is the process's file descriptors table.
 /// - `nfds` is the number of the highest checked fd + 1.
 /// - `readfds` is the bitfield of fds to check for read operations.
 /// - `writefds` is the bitfield of fds to check for write operations.
 /// - `exceptfds` is the bitfield of fds to check for exceptional conditions.
-/// - `timeout` is the timeout after which the syscall returns.
-/// - `sigmask` TODO
-pub fn do_select<T: TimeUnit>(
-	fds: Arc<Mutex<FileDescriptorTable>>,
-	nfds: u32,
+/// - `timeout` is the timeout in nanoseconds. If `None`, the function waits indefinitely. On
+///   return, it is updated with the remaining time.
+/// - `sigmask` is the signal mask to apply while waiting. If `None`, the mask is unchanged.
+/// - `fds` is the process's file descriptors table.
+///
+/// On success, the sets are replaced with the file descriptors on which an event occurred, and
+/// the function returns the total number of bits set, or zero on timeout.
+///
+/// If a set contains an invalid file descriptor, the function returns [`errno::EBADF`]. If
+/// waiting is interrupted by a signal, the function returns [`errno::EINTR`].
+pub fn do_select(
+	nfds: c_int,
 	readfds: SyscallPtr<FDSet>,
 	writefds: SyscallPtr<FDSet>,
 	exceptfds: SyscallPtr<FDSet>,
-	timeout: SyscallPtr<T>,
-	_sigmask: Option<SyscallSlice<u8>>,
+	timeout: &mut Option<Timestamp>,
+	sigmask: Option<SigSet>,
+	fds: &Mutex<FileDescriptorTable>,
 ) -> EResult<usize> {
-	// Get start timestamp
-	let start = clock::current_time_struct::<T>(CLOCK_MONOTONIC)?;
-	// Get timeout
-	let timeout = timeout.copy_from_user()?.unwrap_or_default();
-	// Tells whether the syscall immediately returns
-	let polling = timeout.is_zero();
-	// The end timestamp
-	let end = start + timeout;
-	// Read
-	let mut readfds_set = readfds.copy_from_user()?;
-	let mut writefds_set = writefds.copy_from_user()?;
-	let mut exceptfds_set = exceptfds.copy_from_user()?;
-	let res = loop {
-		let mut events_count = 0;
-		// Set if every bitfields are set to zero
-		let mut all_zeros = true;
-		for fd_id in 0..min(nfds, FD_SETSIZE as u32) {
-			let read = readfds_set
-				.as_ref()
-				.map(|fds| fds.is_set(fd_id))
-				.unwrap_or(false);
-			let write = writefds_set
-				.as_ref()
-				.map(|fds| fds.is_set(fd_id))
-				.unwrap_or(false);
-			let except = exceptfds_set
-				.as_ref()
-				.map(|fds| fds.is_set(fd_id))
-				.unwrap_or(false);
-			// Build event mask
-			let mut mask = 0;
-			if read {
-				mask |= poll::POLLIN;
-			}
-			if write {
-				mask |= poll::POLLOUT;
-			}
-			if except {
-				mask |= poll::POLLPRI;
-			}
-			if mask != 0 {
-				all_zeros = false;
-			}
-			// Poll file
-			let result = {
-				let fds = fds.lock();
-				let Ok(fd) = fds.get_fd(fd_id as _) else {
-					if mask != 0 {
-						return Err(errno!(EBADF));
-					}
-					continue;
-				};
-				let file = fd.get_file();
-				file.ops.poll(file, mask)?
-			};
-			// Set results
-			let read = read && result & poll::POLLIN != 0;
-			let write = write && result & poll::POLLOUT != 0;
-			let except = except && result & poll::POLLPRI != 0;
-			if let Some(fds) = &mut readfds_set {
-				fds.set(fd_id, read);
-			}
-			if let Some(fds) = &mut writefds_set {
-				fds.set(fd_id, write);
-			}
-			if let Some(fds) = &mut exceptfds_set {
-				fds.set(fd_id, except);
-			}
-			events_count += read as usize + write as usize + except as usize;
-		}
-		// If one or more events occurred, return
-		if all_zeros || polling || events_count > 0 {
-			break events_count;
-		}
-		let curr = clock::current_time_struct::<T>(CLOCK_MONOTONIC)?;
-		// On timeout, return 0
-		if curr >= end {
-			break 0;
+	if unlikely(nfds < 0) {
+		return Err(errno!(EINVAL));
+	}
+	let nfds = min(nfds as u32, FD_SETSIZE as u32);
+	let mut sets = [
+		SelectSet::new(readfds, READ_EVENTS)?,
+		SelectSet::new(writefds, WRITE_EVENTS)?,
+		SelectSet::new(exceptfds, EXCEPT_EVENTS)?,
+	];
+	// The deadline, on the monotonic clock
+	let start = clock::current_time(CLOCK_MONOTONIC, TimestampScale::Nanosecond)?;
+	let deadline = timeout.map(|timeout| start.saturating_add(timeout));
+	let res = wait_events(deadline, sigmask, |sleep| {
+		select_fds(&mut sets, nfds, fds, sleep)
+	});
+	// Update the remaining time
+	if let (Some(remaining), Some(deadline)) = (timeout.as_mut(), deadline) {
+		let now = clock::current_time(CLOCK_MONOTONIC, TimestampScale::Nanosecond)?;
+		*remaining = deadline.saturating_sub(now);
+	}
+	let count = res?;
+	for set in sets {
+		if set.input.is_some() {
+			set.ptr.copy_to_user(set.output)?;
 		}
-		// TODO Make the process sleep?
-		scheduler::end_tick();
-	};
-	// Write back
-	if let Some(val) = readfds_set {
-		readfds.copy_to_user(val)?;
 	}
-	if let Some(val) = writefds_set {
-		writefds.copy_to_user(val)?;
+	Ok(count)
+}
+
+/// Performs the select operation with a timeout of type `T`, which is updated with the remaining
+/// time on return.
+///
+/// The other arguments are the same as [`do_select`]. If the timeout is invalid, the function
+/// returns [`errno::EINVAL`].
+#[allow(clippy::too_many_arguments)]
+pub fn do_select_timeout<T: TimeUnit>(
+	nfds: c_int,
+	readfds: SyscallPtr<FDSet>,
+	writefds: SyscallPtr<FDSet>,
+	exceptfds: SyscallPtr<FDSet>,
+	timeout_ptr: SyscallPtr<T>,
+	is_valid: fn(&T) -> bool,
+	sigmask: Option<SigSet>,
+	fds: &Mutex<FileDescriptorTable>,
+) -> EResult<usize> {
+	let timeout = timeout_ptr.copy_from_user()?;
+	if unlikely(timeout.as_ref().is_some_and(|timeout| !is_valid(timeout))) {
+		return Err(errno!(EINVAL));
 	}
-	if let Some(val) = exceptfds_set {
-		exceptfds.copy_to_user(val)?;
+	let mut timeout = timeout.as_ref().map(TimeUnit::to_nano);
+	let res = do_select(
+		nfds,
+		readfds,
+		writefds,
+		exceptfds,
+		&mut timeout,
+		sigmask,
+		fds,
+	);
+	if let Some(timeout) = timeout {
+		timeout_ptr.copy_to_user(T::from_nano(timeout))?;
 	}
-	Ok(res)
+	res
+}
+
+/// The arguments of the legacy `select` system call.
+#[repr(C)]
+#[derive(Debug)]
+pub struct SelArgStruct {
+	/// The number of the highest checked fd + 1.
+	n: c_ulong,
+	/// The bitfield of fds to check for read operations.
+	inp: *mut FDSet,
+	/// The bitfield of fds to check for write operations.
+	outp: *mut FDSet,
+	/// The bitfield of fds to check for exceptional conditions.
+	exp: *mut FDSet,
+	/// The timeout.
+	tvp: *mut Timeval32,
+}
+
+/// Tells whether the given timeout is valid.
+pub(super) fn timeval_valid(tv: &Timeval32) -> bool {
+	tv.tv_sec <= i32::MAX as u32 && tv.tv_usec < 1_000_000
 }
 
-#[allow(clippy::type_complexity)]
 pub fn select(
-	Args((nfds, readfds, writefds, exceptfds, timeout)): Args<(
-		c_int,
-		SyscallPtr<FDSet>,
-		SyscallPtr<FDSet>,
-		SyscallPtr<FDSet>,
-		SyscallPtr<Timeval>,
-	)>,
+	Args(args): Args<SyscallPtr<SelArgStruct>>,
 	fds: Arc<Mutex<FileDescriptorTable>>,
 ) -> EResult<usize> {
-	do_select(fds, nfds as _, readfds, writefds, exceptfds, timeout, None)
+	let args = args.copy_from_user()?.ok_or_else(|| errno!(EFAULT))?;
+	do_select_timeout(
+		args.n as _,
+		SyscallPtr::from_syscall_arg(args.inp as usize),
+		SyscallPtr::from_syscall_arg(args.outp as usize),
+		SyscallPtr::from_syscall_arg(args.exp as usize),
+		SyscallPtr::from_syscall_arg(args.tvp as usize),
+		timeval_valid,
+		None,
+		&fds,
+	)
 }
@@ -0,0 +1,133 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The `sendfile` system call copies data from a file descriptor to another, without going
+//! through userspace.
+
+use crate::{
+	file::{fd::FileDescriptorTable, perm::AccessProfile, vfs, FileType, O_APPEND},
+	process::{mem_space::copy::SyscallPtr, Process},
+	syscall::Args,
+};
+use core::{
+	cmp::min,
+	ffi::{c_int, c_long},
+	intrinsics::unlikely,
+	sync::atomic,
+};
+use utils::{
+	errno,
+	errno::{EResult, Errno},
+	limits::PAGE_SIZE,
+	lock::Mutex,
+	ptr::arc::Arc,
+	vec,
+};
+
+/// Copies data from the file descriptor `in_fd` to `out_fd`.
+///
+/// Arguments:
+/// - `out_fd` is the file descriptor to write to, at its current offset
+/// - `in_fd` is the file descriptor to read from
+/// - `offset` is the offset to read at in `in_fd`. If `None`, the current offset of `in_fd` is
+///   used and updated
+/// - `count` is the maximum number of bytes to copy
+/// - `fds` is the file descriptors table
+/// - `ap` is the access profile of the agent performing the copy
+///
+/// On success, the function returns the number of bytes copied and the offset in `in_fd` right
+/// after the last byte read.
+pub fn do_sendfile(
+	out_fd: c_int,
+	in_fd: c_int,
+	offset: Option<u64>,
+	count: usize,
+	fds: &Mutex<FileDescriptorTable>,
+	ap: &AccessProfile,
+) -> EResult<(usize, u64)> {
+	let (out_file, in_file) = {
+		let fds = fds.lock();
+		let out_file = fds.get_fd(out_fd)?.get_file().clone();
+		let in_file = fds.get_fd(in_fd)?.get_file().clone();
+		(out_file, in_file)
+	};
+	// Validation
+	if unlikely(!in_file.can_read() || !out_file.can_write()) {
+		return Err(errno!(EBADF));
+	}
+	if unlikely(out_file.get_flags() & O_APPEND != 0) {
+		return Err(errno!(EINVAL));
+	}
+	if in_file.get_type()? == FileType::Directory {
+		return Err(errno!(EISDIR));
+	}
+	let count = min(count, i32::MAX as usize);
+	let start = offset.unwrap_or_else(|| in_file.off.load(atomic::Ordering::Acquire));
+	let mut buf = vec![0u8; min(count, PAGE_SIZE)]?;
+	let mut total = 0;
+	while total < count {
+		let len = min(count - total, buf.len());
+		let len = in_file
+			.ops
+			.read(&in_file, start + total as u64, &mut buf[..len])?;
+		if len == 0 {
+			break;
+		}
+		let out_off = out_file.off.load(atomic::Ordering::Acquire);
+		let written = out_file.ops.write(&out_file, out_off, &buf[..len])?;
+		out_file.off.store(
+			out_off.saturating_add(written as u64),
+			atomic::Ordering::Release,
+		);
+		total += written;
+		if written < len {
+			break;
+		}
+	}
+	let end = start + total as u64;
+	if offset.is_none() {
+		in_file.off.store(end, atomic::Ordering::Release);
+	}
+	if total > 0 {
+		if let Some(ent) = &out_file.vfs_entry {
+			vfs::content_modified(ent, ap)?;
+		}
+		let proc = Process::current();
+		let proc = proc.lock();
+		proc.io.account_read(total);
+		proc.io.account_write(total);
+	}
+	Ok((total, end))
+}
+
+pub fn sendfile(
+	Args((out_fd, in_fd, offset, count)): Args<(c_int, c_int, SyscallPtr<c_long>, usize)>,
+	fds: Arc<Mutex<FileDescriptorTable>>,
+	ap: AccessProfile,
+) -> EResult<usize> {
+	let off = offset
+		.copy_from_user()?
+		.map(|off| u64::try_from(off).map_err(|_| errno!(EINVAL)))
+		.transpose()?;
+	let (len, end) = do_sendfile(out_fd, in_fd, off, count, &fds, &ap)?;
+	if off.is_some() {
+		let end = c_long::try_from(end).map_err(|_| errno!(EOVERFLOW))?;
+		offset.copy_to_user(end)?;
+	}
+	Ok(len)
+}
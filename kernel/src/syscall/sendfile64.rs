@@ -0,0 +1,49 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The `sendfile64` system call is similar to `sendfile`, except it takes a 64 bits offset.
+
+use super::sendfile::do_sendfile;
+use crate::{
+	file::{fd::FileDescriptorTable, perm::AccessProfile},
+	process::mem_space::copy::SyscallPtr,
+	syscall::Args,
+};
+use core::ffi::c_int;
+use utils::{
+	errno,
+	errno::{EResult, Errno},
+	lock::Mutex,
+	ptr::arc::Arc,
+};
+
+pub fn sendfile64(
+	Args((out_fd, in_fd, offset, count)): Args<(c_int, c_int, SyscallPtr<i64>, usize)>,
+	fds: Arc<Mutex<FileDescriptorTable>>,
+	ap: AccessProfile,
+) -> EResult<usize> {
+	let off = offset
+		.copy_from_user()?
+		.map(|off| u64::try_from(off).map_err(|_| errno!(EINVAL)))
+		.transpose()?;
+	let (len, end) = do_sendfile(out_fd, in_fd, off, count, &fds, &ap)?;
+	if off.is_some() {
+		offset.copy_to_user(end as _)?;
+	}
+	Ok(len)
+}
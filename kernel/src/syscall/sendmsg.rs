@@ -0,0 +1,209 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The `sendmsg` system call sends a message on a socket, with ancillary data.
+
+use super::sendto::do_send;
+use crate::{
+	file::{
+		fd::FileDescriptorTable,
+		socket::{Socket, SCM_CREDENTIALS, SCM_RIGHTS, SOL_SOCKET},
+		vfs::ResolutionSettings,
+		File,
+	},
+	net::unix::{Ucred, SCM_MAX_FD},
+	process::{
+		iovec::IOVec,
+		mem_space::copy::{SyscallPtr, SyscallSlice},
+		pid::Pid,
+		Process,
+	},
+	syscall::{Args, FromSyscallArg},
+};
+use core::{
+	ffi::{c_int, c_void},
+	mem::size_of,
+	ptr,
+};
+use utils::{
+	collections::vec::Vec, errno, errno::EResult, limits::IOV_MAX, lock::Mutex, ptr::arc::Arc,
+};
+
+/// A message sent or received on a socket.
+#[repr(C)]
+#[derive(Debug)]
+pub(super) struct MsgHdr {
+	/// The address of the peer.
+	pub msg_name: *mut c_void,
+	/// The size of the address, in bytes.
+	pub msg_namelen: u32,
+	/// The buffers of the message.
+	pub msg_iov: *mut IOVec,
+	/// The number of elements in `msg_iov`.
+	pub msg_iovlen: usize,
+	/// The buffer of ancillary data.
+	pub msg_control: *mut c_void,
+	/// The size of the ancillary data buffer, in bytes.
+	pub msg_controllen: usize,
+	/// Flags on the received message.
+	pub msg_flags: c_int,
+}
+
+impl MsgHdr {
+	/// Copies the buffers of the message from userspace.
+	pub fn get_iov(&self) -> EResult<Vec<IOVec>> {
+		if self.msg_iovlen > IOV_MAX {
+			return Err(errno!(EMSGSIZE));
+		}
+		if self.msg_iovlen == 0 {
+			return Ok(Vec::new());
+		}
+		SyscallSlice::<IOVec>::from_syscall_arg(self.msg_iov as usize)
+			.copy_from_user(..self.msg_iovlen)?
+			.ok_or_else(|| errno!(EFAULT))
+	}
+}
+
+/// The header of an ancillary data object, followed by the data itself.
+#[repr(C)]
+#[derive(Debug)]
+pub(super) struct CmsgHdr {
+	/// The size of the object in bytes, including the header.
+	pub cmsg_len: usize,
+	/// The level of the protocol the data belongs to.
+	pub cmsg_level: c_int,
+	/// The type of the data.
+	pub cmsg_type: c_int,
+}
+
+/// Aligns `len` on the boundary required between ancillary data objects.
+pub(super) fn cmsg_align(len: usize) -> usize {
+	len.next_multiple_of(size_of::<usize>())
+}
+
+/// Checks the credentials `cred` passed with [`SCM_CREDENTIALS`] by `proc`, returning them with
+/// the PID used by the kernel.
+///
+/// If the PID is not valid, the function returns [`errno::ESRCH`].
+///
+/// Unless privileged, a process can only pass its own PID, and one of its user and group IDs.
+/// Else, the function returns [`errno::EPERM`].
+fn check_cred(proc: &Process, cred: Ucred) -> EResult<Ucred> {
+	let ap = proc.cred.get();
+	let pid = Pid::try_from(cred.pid)
+		.ok()
+		.filter(|pid| *pid > 0)
+		.and_then(|pid| proc.pid_to_global(pid))
+		.ok_or_else(|| errno!(ESRCH))?;
+	if !ap.is_privileged() {
+		let uid_valid = [ap.uid, ap.euid, ap.suid]
+			.iter()
+			.any(|uid| *uid as u32 == cred.uid);
+		let gid_valid = [ap.gid, ap.egid, ap.sgid]
+			.iter()
+			.any(|gid| *gid as u32 == cred.gid);
+		if pid != proc.get_tgid() || !uid_valid || !gid_valid {
+			return Err(errno!(EPERM));
+		}
+	}
+	Ok(Ucred {
+		pid: pid as _,
+		..cred
+	})
+}
+
+/// Returns the files to pass with [`SCM_RIGHTS`] and the credentials to pass with
+/// [`SCM_CREDENTIALS`], from the ancillary data `control`.
+///
+/// The credentials are not checked, see [`check_cred`].
+///
+/// If the data is invalid or has an unsupported type, the function returns [`errno::EINVAL`].
+fn get_ancillary(
+	control: &[u8],
+	fds: &FileDescriptorTable,
+) -> EResult<(Vec<Arc<File>>, Option<Ucred>)> {
+	let hdr_len = size_of::<CmsgHdr>();
+	let mut files = Vec::new();
+	let mut cred = None;
+	let mut off = 0;
+	while off + hdr_len <= control.len() {
+		let hdr: CmsgHdr = unsafe { ptr::read_unaligned(control[off..].as_ptr() as *const _) };
+		if hdr.cmsg_len < hdr_len || hdr.cmsg_len > control.len() - off {
+			return Err(errno!(EINVAL));
+		}
+		if hdr.cmsg_level != SOL_SOCKET {
+			return Err(errno!(EINVAL));
+		}
+		let data = &control[(off + cmsg_align(hdr_len))..(off + hdr.cmsg_len)];
+		match hdr.cmsg_type {
+			SCM_RIGHTS => {
+				for fd in data.chunks_exact(size_of::<c_int>()) {
+					if files.len() >= SCM_MAX_FD {
+						return Err(errno!(EINVAL));
+					}
+					let fd = c_int::from_ne_bytes(fd.try_into().unwrap());
+					files.push(fds.get_fd_raw(fd)?.get_file().clone())?;
+				}
+			}
+			SCM_CREDENTIALS => {
+				if data.len() != size_of::<Ucred>() {
+					return Err(errno!(EINVAL));
+				}
+				cred = Some(unsafe { ptr::read_unaligned(data.as_ptr() as *const _) });
+			}
+			_ => return Err(errno!(EINVAL)),
+		}
+		off += cmsg_align(hdr.cmsg_len);
+	}
+	Ok((files, cred))
+}
+
+pub fn sendmsg(
+	Args((sockfd, msg, flags)): Args<(c_int, SyscallPtr<MsgHdr>, c_int)>,
+	rs: ResolutionSettings,
+	fds: Arc<Mutex<FileDescriptorTable>>,
+) -> EResult<usize> {
+	let msg = msg.copy_from_user()?.ok_or_else(|| errno!(EFAULT))?;
+	let control = SyscallSlice::<u8>::from_syscall_arg(msg.msg_control as usize)
+		.copy_from_user(..msg.msg_controllen)?
+		.unwrap_or_default();
+	let (file, files, cred) = {
+		let fds = fds.lock();
+		let file = fds.get_fd(sockfd)?.get_file().clone();
+		let (files, cred) = get_ancillary(&control, &fds)?;
+		(file, files, cred)
+	};
+	let cred = cred
+		.map(|cred| check_cred(&Process::current().lock(), cred))
+		.transpose()?;
+	let sock: &Socket = file.get_buffer().ok_or_else(|| errno!(ENOTSOCK))?;
+	// Gather the data
+	let mut buf = Vec::new();
+	for iov in msg.get_iov()? {
+		let data = SyscallSlice::<u8>::from_syscall_arg(iov.iov_base as usize)
+			.copy_from_user(..iov.iov_len)?
+			.ok_or_else(|| errno!(EFAULT))?;
+		buf.extend_from_slice(&data)?;
+	}
+	let name = SyscallSlice::<u8>::from_syscall_arg(msg.msg_name as usize)
+		.copy_from_user(..(msg.msg_namelen as usize))?
+		.filter(|name| !name.is_empty());
+	let len = do_send(sock, &file, &buf, files, cred, name.as_deref(), flags, &rs)?;
+	Process::current().lock().io.account_write(len);
+	Ok(len)
+}
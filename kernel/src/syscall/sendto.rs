@@ -19,22 +19,62 @@
 //! The `sendto` system call sends a message on a socket.
 
 use crate::{
-	file::{fd::FileDescriptorTable, socket::Socket},
+	file::{fd::FileDescriptorTable, socket::Socket, vfs::ResolutionSettings, File},
+	net::{sockaddr, unix::Ucred, SocketDomain},
 	process::{mem_space::copy::SyscallSlice, Process},
 	syscall::Args,
 };
 use core::{any::Any, ffi::c_int};
 use utils::{
+	collections::vec::Vec,
 	errno,
 	errno::{EResult, Errno},
 	lock::Mutex,
 	ptr::arc::Arc,
 };
-// TODO implement flags
+
+/// Sends data on a socket.
+///
+/// Arguments:
+/// - `sock` is the socket, and `file` its open file description.
+/// - `buf` is the data to send.
+/// - `files` are the files to pass along with the data.
+/// - `cred` are the credentials to pass along with the data. If `None`, the ones of the current
+///   process are used.
+/// - `dest_addr` is the destination address. If `None`, the socket's peer is used.
+/// - `flags` are the `MSG_*` flags.
+/// - `rs` is the resolution settings, used to find the destination of Unix sockets.
+#[allow(clippy::too_many_arguments)]
+pub(super) fn do_send(
+	sock: &Socket,
+	file: &File,
+	buf: &[u8],
+	files: Vec<Arc<File>>,
+	cred: Option<Ucred>,
+	dest_addr: Option<&[u8]>,
+	flags: c_int,
+	rs: &ResolutionSettings,
+) -> EResult<usize> {
+	let Some(dest_addr) = dest_addr else {
+		return sock.send(file, buf, files, cred, None, flags);
+	};
+	if sock.desc().domain != SocketDomain::AfUnix {
+		// The destination of a connection-oriented socket is its peer
+		if sock.desc().type_.is_stream() {
+			return sock.send(file, buf, files, cred, None, flags);
+		}
+		// TODO support UDP
+		return Err(errno!(EOPNOTSUPP));
+	}
+	let path = sockaddr::unix_path(dest_addr)?;
+	Socket::with_bound(path, rs, |dest| {
+		sock.send(file, buf, files, cred, Some(dest), flags)
+	})
+}
 
 #[allow(clippy::type_complexity)]
 pub fn sendto(
-	Args((sockfd, buf, len, _flags, dest_addr, addrlen)): Args<(
+	Args((sockfd, buf, len, flags, dest_addr, addrlen)): Args<(
 		c_int,
 		SyscallSlice<u8>,
 		usize,
@@ -42,6 +82,7 @@ pub fn sendto(
 		SyscallSlice<u8>,
 		isize,
 	)>,
+	rs: ResolutionSettings,
 	fds: Arc<Mutex<FileDescriptorTable>>,
 ) -> EResult<usize> {
 	// Validation
@@ -50,12 +91,22 @@ pub fn sendto(
 	}
 	// Get socket
 	let file = fds.lock().get_fd(sockfd)?.get_file().clone();
-	let _sock: &Socket = file.get_buffer().ok_or_else(|| errno!(ENOTSOCK))?;
+	let sock: &Socket = file.get_buffer().ok_or_else(|| errno!(ENOTSOCK))?;
 	// Get slices
-	let _buf_slice = buf.copy_from_user(..len)?.ok_or(errno!(EFAULT))?;
-	let _dest_addr_slice = dest_addr
+	let buf_slice = buf.copy_from_user(..len)?.ok_or(errno!(EFAULT))?;
+	let dest_addr_slice = dest_addr
 		.copy_from_user(..(addrlen as usize))?
-		.ok_or(errno!(EFAULT))?;
-	// TODO
-	todo!()
+		.filter(|addr| !addr.is_empty());
+	let len = do_send(
+		sock,
+		&file,
+		&buf_slice,
+		Vec::new(),
+		None,
+		dest_addr_slice.as_deref(),
+		flags,
+		&rs,
+	)?;
+	Process::current().lock().io.account_write(len);
+	Ok(len)
 }
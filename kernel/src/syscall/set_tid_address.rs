@@ -31,10 +31,10 @@ use utils::{
 };
 
 pub fn set_tid_address(
-	Args(_tidptr): Args<SyscallPtr<c_int>>,
+	Args(tidptr): Args<SyscallPtr<c_int>>,
 	proc: Arc<IntMutex<Process>>,
 ) -> EResult<usize> {
-	let proc = proc.lock();
-	// TODO set process's clear_child_tid
+	let mut proc = proc.lock();
+	proc.clear_child_tid = tidptr;
 	Ok(proc.tid as _)
 }
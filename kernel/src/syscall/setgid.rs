@@ -26,6 +26,7 @@ use utils::{
 };
 
 pub fn setgid(Args(gid): Args<Gid>, proc: Arc<IntMutex<Process>>) -> EResult<usize> {
-	proc.lock().access_profile.set_gid(gid)?;
+	let cred = proc.lock().cred.clone();
+	cred.update(|ap| ap.set_gid(gid))?;
 	Ok(0)
 }
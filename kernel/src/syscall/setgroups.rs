@@ -0,0 +1,53 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! `setgroups` sets the supplementary group IDs of the current process.
+
+use crate::{
+	file::perm::{Gid, NGROUPS_MAX},
+	process::{mem_space::copy::SyscallSlice, Process},
+	syscall::Args,
+};
+use core::fmt;
+use utils::{collections::vec::Vec, errno, errno::EResult, lock::IntMutex, ptr::arc::Arc};
+
+/// Performs the `setgroups` operation, reading `size` group IDs of type `T` from `list`.
+pub fn do_setgroups<T: TryInto<Gid> + fmt::Debug>(
+	size: usize,
+	list: SyscallSlice<T>,
+	proc: Arc<IntMutex<Process>>,
+) -> EResult<usize> {
+	if size > NGROUPS_MAX {
+		return Err(errno!(EINVAL));
+	}
+	let list = list.copy_from_user(..size)?.ok_or_else(|| errno!(EFAULT))?;
+	let mut groups = Vec::with_capacity(size)?;
+	for gid in list {
+		groups.push(gid.try_into().map_err(|_| errno!(EINVAL))?)?;
+	}
+	let cred = proc.lock().cred.clone();
+	cred.update(|ap| ap.set_groups(&groups))?;
+	Ok(0)
+}
+
+pub fn setgroups(
+	Args((size, list)): Args<(usize, SyscallSlice<Gid>)>,
+	proc: Arc<IntMutex<Process>>,
+) -> EResult<usize> {
+	do_setgroups(size, list, proc)
+}
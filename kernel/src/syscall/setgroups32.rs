@@ -0,0 +1,33 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! `setgroups32` is like `setgroups` but using 32 bits group IDs.
+
+use super::setgroups::do_setgroups;
+use crate::{
+	process::{mem_space::copy::SyscallSlice, Process},
+	syscall::Args,
+};
+use utils::{errno::EResult, lock::IntMutex, ptr::arc::Arc};
+
+pub fn setgroups32(
+	Args((size, list)): Args<(usize, SyscallSlice<u32>)>,
+	proc: Arc<IntMutex<Process>>,
+) -> EResult<usize> {
+	do_setgroups(size, list, proc)
+}
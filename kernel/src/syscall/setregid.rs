@@ -18,11 +18,7 @@
 
 //! `setreuid` sets the real and effective group ID of the current process.
 
-use crate::{
-	file::perm::{AccessProfile, Uid},
-	process::Process,
-	syscall::Args,
-};
+use crate::{process::Process, syscall::Args};
 use core::ffi::c_int;
 use utils::{
 	errno,
@@ -33,33 +29,35 @@ use utils::{
 
 pub fn setregid(
 	Args((rgid, egid)): Args<(c_int, c_int)>,
-	ap: AccessProfile,
 	proc: Arc<IntMutex<Process>>,
 ) -> EResult<usize> {
-	// Validation
 	if rgid < -1 || egid < -1 {
 		return Err(errno!(EINVAL));
 	}
-	if !ap.is_privileged()
-		&& (![-1, ap.gid as _, ap.egid as _].contains(&rgid)
-			|| ![-1, ap.gid as _, ap.egid as _, ap.sgid as _].contains(&egid))
-	{
-		return Err(errno!(EPERM));
-	}
-	// Update
-	let mut proc = proc.lock();
-	let new_rgid = match rgid {
-		-1 => ap.gid,
-		i => i as _,
-	};
-	let new_egid = match egid {
-		-1 => ap.egid,
-		i => i as _,
-	};
-	proc.access_profile.gid = new_rgid;
-	proc.access_profile.egid = new_egid;
-	if new_rgid != ap.gid || new_egid != ap.gid {
-		proc.access_profile.sgid = new_egid;
-	}
+	let cred = proc.lock().cred.clone();
+	// Validation and update are done at once, so that a concurrent change cannot invalidate the
+	// checks
+	cred.update(|ap| {
+		if !ap.is_privileged()
+			&& (![-1, ap.gid as _, ap.egid as _].contains(&rgid)
+				|| ![-1, ap.gid as _, ap.egid as _, ap.sgid as _].contains(&egid))
+		{
+			return Err(errno!(EPERM));
+		}
+		let new_rgid = match rgid {
+			-1 => ap.gid,
+			i => i as _,
+		};
+		let new_egid = match egid {
+			-1 => ap.egid,
+			i => i as _,
+		};
+		if new_rgid != ap.gid || new_egid != ap.gid {
+			ap.sgid = new_egid;
+		}
+		ap.gid = new_rgid;
+		ap.egid = new_egid;
+		Ok(())
+	})?;
 	Ok(0)
 }
@@ -18,11 +18,7 @@
 
 //! `setresgid` sets the real, effective and saved group ID of the current process.
 
-use crate::{
-	file::perm::{AccessProfile, Uid},
-	process::Process,
-	syscall::Args,
-};
+use crate::{process::Process, syscall::Args};
 use core::ffi::c_int;
 use utils::{
 	errno,
@@ -33,32 +29,31 @@ use utils::{
 
 pub fn setresgid(
 	Args((rgid, egid, sgid)): Args<(c_int, c_int, c_int)>,
-	ap: AccessProfile,
 	proc: Arc<IntMutex<Process>>,
 ) -> EResult<usize> {
-	// Validation
 	if rgid < -1 || egid < -1 || sgid < -1 {
 		return Err(errno!(EINVAL));
 	}
-	if !ap.is_privileged() {
-		let allowed = [-1, ap.gid as _, ap.egid as _, ap.sgid as _];
-		if !allowed.contains(&rgid) || !allowed.contains(&egid) || !allowed.contains(&sgid) {
-			return Err(errno!(EPERM));
+	let cred = proc.lock().cred.clone();
+	// Validation and update are done at once, so that a concurrent change cannot invalidate the
+	// checks
+	cred.update(|ap| {
+		if !ap.is_privileged() {
+			let allowed = [-1, ap.gid as _, ap.egid as _, ap.sgid as _];
+			if !allowed.contains(&rgid) || !allowed.contains(&egid) || !allowed.contains(&sgid) {
+				return Err(errno!(EPERM));
+			}
 		}
-	}
-	// Update
-	let mut proc = proc.lock();
-	proc.access_profile.gid = match rgid {
-		-1 => ap.gid,
-		i => i as _,
-	};
-	proc.access_profile.egid = match egid {
-		-1 => ap.egid,
-		i => i as _,
-	};
-	proc.access_profile.sgid = match sgid {
-		-1 => ap.sgid,
-		i => i as _,
-	};
+		if rgid != -1 {
+			ap.gid = rgid as _;
+		}
+		if egid != -1 {
+			ap.egid = egid as _;
+		}
+		if sgid != -1 {
+			ap.sgid = sgid as _;
+		}
+		Ok(())
+	})?;
 	Ok(0)
 }
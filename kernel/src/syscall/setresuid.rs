@@ -18,11 +18,7 @@
 
 //! `setresuid` sets the real, effective and saved user ID of the current process.
 
-use crate::{
-	file::perm::{AccessProfile, Uid},
-	process::Process,
-	syscall::Args,
-};
+use crate::{process::Process, syscall::Args};
 use core::ffi::c_int;
 use utils::{
 	errno,
@@ -33,32 +29,31 @@ use utils::{
 
 pub fn setresuid(
 	Args((ruid, euid, suid)): Args<(c_int, c_int, c_int)>,
-	ap: AccessProfile,
 	proc: Arc<IntMutex<Process>>,
 ) -> EResult<usize> {
-	// Validation
 	if ruid < -1 || euid < -1 || suid < -1 {
 		return Err(errno!(EINVAL));
 	}
-	if !ap.is_privileged() {
-		let allowed = [-1, ap.uid as _, ap.euid as _, ap.suid as _];
-		if !allowed.contains(&ruid) || !allowed.contains(&euid) || !allowed.contains(&suid) {
-			return Err(errno!(EPERM));
+	let cred = proc.lock().cred.clone();
+	// Validation and update are done at once, so that a concurrent change cannot invalidate the
+	// checks
+	cred.update(|ap| {
+		if !ap.is_privileged() {
+			let allowed = [-1, ap.uid as _, ap.euid as _, ap.suid as _];
+			if !allowed.contains(&ruid) || !allowed.contains(&euid) || !allowed.contains(&suid) {
+				return Err(errno!(EPERM));
+			}
 		}
-	}
-	// Update
-	let mut proc = proc.lock();
-	proc.access_profile.uid = match ruid {
-		-1 => ap.uid,
-		i => i as _,
-	};
-	proc.access_profile.euid = match euid {
-		-1 => ap.euid,
-		i => i as _,
-	};
-	proc.access_profile.suid = match suid {
-		-1 => ap.suid,
-		i => i as _,
-	};
+		if ruid != -1 {
+			ap.uid = ruid as _;
+		}
+		if euid != -1 {
+			ap.euid = euid as _;
+		}
+		if suid != -1 {
+			ap.suid = suid as _;
+		}
+		Ok(())
+	})?;
 	Ok(0)
 }
@@ -18,11 +18,7 @@
 
 //! `setreuid` sets the real and effective user ID of the current process.
 
-use crate::{
-	file::perm::{AccessProfile, Uid},
-	process::Process,
-	syscall::Args,
-};
+use crate::{process::Process, syscall::Args};
 use core::ffi::c_int;
 use utils::{
 	errno,
@@ -33,32 +29,35 @@ use utils::{
 
 pub fn setreuid(
 	Args((ruid, euid)): Args<(c_int, c_int)>,
-	ap: AccessProfile,
 	proc: Arc<IntMutex<Process>>,
 ) -> EResult<usize> {
-	// Validation
 	if ruid < -1 || euid < -1 {
 		return Err(errno!(EINVAL));
 	}
-	if !ap.is_privileged() && ![-1, ap.uid as _, ap.euid as _].contains(&ruid)
-		|| ![-1, ap.uid as _, ap.euid as _, ap.suid as _].contains(&euid)
-	{
-		return Err(errno!(EPERM));
-	}
-	// Update
-	let mut proc = proc.lock();
-	let new_ruid = match ruid {
-		-1 => ap.uid,
-		i => i as _,
-	};
-	let new_euid = match euid {
-		-1 => ap.euid,
-		i => i as _,
-	};
-	proc.access_profile.uid = new_ruid;
-	proc.access_profile.euid = new_euid;
-	if new_ruid != ap.uid || new_euid != ap.uid {
-		proc.access_profile.suid = new_euid;
-	}
+	let cred = proc.lock().cred.clone();
+	// Validation and update are done at once, so that a concurrent change cannot invalidate the
+	// checks
+	cred.update(|ap| {
+		if !ap.is_privileged()
+			&& (![-1, ap.uid as _, ap.euid as _].contains(&ruid)
+				|| ![-1, ap.uid as _, ap.euid as _, ap.suid as _].contains(&euid))
+		{
+			return Err(errno!(EPERM));
+		}
+		let new_ruid = match ruid {
+			-1 => ap.uid,
+			i => i as _,
+		};
+		let new_euid = match euid {
+			-1 => ap.euid,
+			i => i as _,
+		};
+		if new_ruid != ap.uid || new_euid != ap.uid {
+			ap.suid = new_euid;
+		}
+		ap.uid = new_ruid;
+		ap.euid = new_euid;
+		Ok(())
+	})?;
 	Ok(0)
 }
@@ -26,6 +26,7 @@ use utils::{
 };
 
 pub fn setuid(Args(uid): Args<Uid>, proc: Arc<IntMutex<Process>>) -> EResult<usize> {
-	proc.lock().access_profile.set_uid(uid)?;
+	let cred = proc.lock().cred.clone();
+	cred.update(|ap| ap.set_uid(uid))?;
 	Ok(0)
 }
@@ -0,0 +1,98 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The `setxattr` system call sets the value of an extended attribute of a file.
+
+use crate::{
+	file::{
+		fs::{XATTR_CREATE, XATTR_REPLACE},
+		perm::AccessProfile,
+		vfs,
+		vfs::ResolutionSettings,
+	},
+	process::mem_space::copy::{SyscallSlice, SyscallString},
+	syscall::{getxattr::XATTR_SIZE_MAX, Args},
+};
+use core::ffi::c_int;
+use utils::{
+	collections::vec::Vec,
+	errno,
+	errno::{EResult, Errno},
+};
+
+/// Sets the value of an extended attribute of the file `file`.
+///
+/// Arguments:
+/// - `file` is the file
+/// - `name` is the name of the attribute
+/// - `value` is the new value of the attribute. It may be null if `size` is zero
+/// - `size` is the size of the value
+/// - `flags` is a combination of [`XATTR_CREATE`] and [`XATTR_REPLACE`]
+/// - `ap` is the access profile to check permissions
+pub fn setxattr_entry(
+	file: &vfs::Entry,
+	name: SyscallString,
+	value: SyscallSlice<u8>,
+	size: usize,
+	flags: c_int,
+	ap: &AccessProfile,
+) -> EResult<usize> {
+	if flags & !(XATTR_CREATE | XATTR_REPLACE) != 0 {
+		return Err(errno!(EINVAL));
+	}
+	let name = super::getxattr::copy_name(name)?;
+	if size > XATTR_SIZE_MAX {
+		return Err(errno!(E2BIG));
+	}
+	let value = match value.copy_from_user(..size)? {
+		Some(value) => value,
+		None if size == 0 => Vec::new(),
+		None => return Err(errno!(EFAULT)),
+	};
+	vfs::set_xattr(file, name.as_bytes(), &value, flags, ap)?;
+	Ok(0)
+}
+
+/// Performs the `setxattr` syscall.
+pub fn do_setxattr(
+	pathname: SyscallString,
+	name: SyscallString,
+	value: SyscallSlice<u8>,
+	size: usize,
+	flags: c_int,
+	rs: ResolutionSettings,
+) -> EResult<usize> {
+	let path = pathname
+		.copy_path_from_user()?
+		.ok_or_else(|| errno!(EFAULT))?;
+	let file = vfs::get_file_from_path(&path, &rs)?;
+	setxattr_entry(&file, name, value, size, flags, &rs.access_profile)
+}
+
+pub fn setxattr(
+	Args((pathname, name, value, size, flags)): Args<(
+		SyscallString,
+		SyscallString,
+		SyscallSlice<u8>,
+		usize,
+		c_int,
+	)>,
+	rs: ResolutionSettings,
+) -> EResult<usize> {
+	do_setxattr(pathname, name, value, size, flags, rs)
+}
@@ -0,0 +1,39 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+//! The `signalfd` system call creates a file from which pending signals can be read, without
+//! flags.
+
+use super::signalfd4::do_signalfd;
+use crate::{
+	file::fd::FileDescriptorTable,
+	process::{mem_space::copy::SyscallPtr, signal::SigSet},
+	syscall::Args,
+};
+use core::ffi::c_int;
+use utils::{
+	errno::{EResult, Errno},
+	lock::Mutex,
+	ptr::arc::Arc,
+};
+
+pub fn signalfd(
+	Args((fd, mask, sizemask)): Args<(c_int, SyscallPtr<SigSet>, usize)>,
+	fds: Arc<Mutex<FileDescriptorTable>>,
+) -> EResult<usize> {
+	do_signalfd(fd, mask, sizemask, 0, &fds)
+}
@@ -0,0 +1,85 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+//! The `signalfd4` system call creates a file from which pending signals can be read, or changes
+//! the set of signals read from an existing one.
+
+use crate::{
+	file::{
+		fd::{FileDescriptorTable, FD_CLOEXEC},
+		signalfd::{SignalFd, SFD_CLOEXEC, SFD_NONBLOCK},
+		File, O_NONBLOCK, O_RDONLY,
+	},
+	process::{mem_space::copy::SyscallPtr, signal::SigSet},
+	syscall::Args,
+};
+use core::{ffi::c_int, mem::size_of};
+use utils::{
+	errno,
+	errno::{EResult, Errno},
+	lock::Mutex,
+	ptr::arc::Arc,
+};
+
+/// Creates a signalfd reading the signals of `mask`, or updates the set of the signalfd `fd`.
+///
+/// Arguments:
+/// - `fd` is the signalfd to update. If `-1`, a new one is created
+/// - `mask` is the set of signals to read
+/// - `sizemask` is the size of `mask` in bytes
+/// - `flags` is the set of flags for the new file descriptor
+/// - `fds` is the process's file descriptors table
+///
+/// The function returns the file descriptor of the signalfd.
+pub fn do_signalfd(
+	fd: c_int,
+	mask: SyscallPtr<SigSet>,
+	sizemask: usize,
+	flags: c_int,
+	fds: &Mutex<FileDescriptorTable>,
+) -> EResult<usize> {
+	if flags & !(SFD_CLOEXEC | SFD_NONBLOCK) != 0 || sizemask != size_of::<SigSet>() {
+		return Err(errno!(EINVAL));
+	}
+	let mask = mask.copy_from_user()?.ok_or_else(|| errno!(EFAULT))?;
+	if fd != -1 {
+		let file = fds.lock().get_fd(fd)?.get_file().clone();
+		let signalfd = file
+			.get_buffer::<SignalFd>()
+			.ok_or_else(|| errno!(EINVAL))?;
+		signalfd.set_mask(mask);
+		return Ok(fd as _);
+	}
+	let mut file_flags = O_RDONLY;
+	if flags & SFD_NONBLOCK != 0 {
+		file_flags |= O_NONBLOCK;
+	}
+	let file = File::open_floating(Arc::new(SignalFd::new(mask))?, file_flags)?;
+	let mut fd_flags = 0;
+	if flags & SFD_CLOEXEC != 0 {
+		fd_flags |= FD_CLOEXEC;
+	}
+	let (fd_id, _) = fds.lock().create_fd(fd_flags, file)?;
+	Ok(fd_id as _)
+}
+
+pub fn signalfd4(
+	Args((fd, mask, sizemask, flags)): Args<(c_int, SyscallPtr<SigSet>, usize, c_int)>,
+	fds: Arc<Mutex<FileDescriptorTable>>,
+) -> EResult<usize> {
+	do_signalfd(fd, mask, sizemask, flags, &fds)
+}
@@ -20,7 +20,12 @@
 
 use crate::{
 	file,
-	file::{fd::FileDescriptorTable, perm::AccessProfile, socket::Socket, vfs, File},
+	file::{
+		fd::{FileDescriptorTable, FD_CLOEXEC},
+		perm::AccessProfile,
+		socket::{Socket, SOCK_CLOEXEC, SOCK_NONBLOCK},
+		vfs, File,
+	},
 	net::{SocketDesc, SocketDomain, SocketType},
 	process::Process,
 	syscall::Args,
@@ -40,7 +45,7 @@ pub fn socket(
 	fds: Arc<Mutex<FileDescriptorTable>>,
 ) -> EResult<usize> {
 	let sock_domain = SocketDomain::try_from(domain as u32)?;
-	let sock_type = SocketType::try_from(r#type as u32)?;
+	let sock_type = SocketType::try_from((r#type & !(SOCK_NONBLOCK | SOCK_CLOEXEC)) as u32)?;
 	// Check permissions
 	if !ap.can_use_sock_domain(&sock_domain) || !ap.can_use_sock_type(&sock_type) {
 		return Err(errno!(EACCES));
@@ -52,7 +57,12 @@ pub fn socket(
 	};
 	// Create socket
 	let sock = Arc::new(Socket::new(desc)?)?;
-	let file = File::open_floating(sock, file::O_RDWR)?;
-	let (sock_fd_id, _) = fds.lock().create_fd(0, file)?;
+	let file = File::open_floating(sock, file::O_RDWR | (r#type & SOCK_NONBLOCK))?;
+	let fd_flags = if r#type & SOCK_CLOEXEC != 0 {
+		FD_CLOEXEC
+	} else {
+		0
+	};
+	let (sock_fd_id, _) = fds.lock().create_fd(fd_flags, file)?;
 	Ok(sock_fd_id as _)
 }
@@ -21,7 +21,12 @@
 
 use crate::{
 	file,
-	file::{fd::FileDescriptorTable, perm::AccessProfile, socket::Socket, vfs, File},
+	file::{
+		fd::{FileDescriptorTable, FD_CLOEXEC},
+		perm::AccessProfile,
+		socket::{Socket, SOCK_CLOEXEC, SOCK_NONBLOCK},
+		vfs, File,
+	},
 	net::{SocketDesc, SocketDomain, SocketType},
 	process::{mem_space::copy::SyscallPtr, Process},
 	syscall::Args,
@@ -41,7 +46,10 @@ pub fn socketpair(
 	fds: Arc<Mutex<FileDescriptorTable>>,
 ) -> EResult<usize> {
 	let sock_domain = SocketDomain::try_from(domain as u32)?;
-	let sock_type = SocketType::try_from(r#type as u32)?;
+	let sock_type = SocketType::try_from((r#type & !(SOCK_NONBLOCK | SOCK_CLOEXEC)) as u32)?;
+	if sock_domain != SocketDomain::AfUnix {
+		return Err(errno!(EOPNOTSUPP));
+	}
 	// Check permissions
 	if !ap.can_use_sock_domain(&sock_domain) || !ap.can_use_sock_type(&sock_type) {
 		return Err(errno!(EACCES));
@@ -51,12 +59,21 @@ pub fn socketpair(
 		type_: sock_type,
 		protocol,
 	};
-	// Create socket
-	let sock = Arc::new(Socket::new(desc)?)?;
-	let file0 = File::open_floating(sock.clone(), file::O_RDWR)?;
-	let file1 = File::open_floating(sock, file::O_RDWR)?;
+	// Create sockets
+	let (sock0, sock1) = Socket::new_pair(desc)?;
+	let flags = file::O_RDWR | (r#type & SOCK_NONBLOCK);
+	let file0 = File::open_floating(Arc::new(sock0)?, flags)?;
+	let file1 = File::open_floating(Arc::new(sock1)?, flags)?;
 	// Create file descriptors
-	let (fd0_id, fd1_id) = fds.lock().create_fd_pair(file0, file1)?;
+	let (fd0_id, fd1_id) = {
+		let mut fds = fds.lock();
+		let (fd0_id, fd1_id) = fds.create_fd_pair(file0, file1)?;
+		if r#type & SOCK_CLOEXEC != 0 {
+			fds.get_fd_mut(fd0_id as _)?.flags = FD_CLOEXEC;
+			fds.get_fd_mut(fd1_id as _)?.flags = FD_CLOEXEC;
+		}
+		(fd0_id, fd1_id)
+	};
 	sv.copy_to_user([fd0_id as _, fd1_id as _])?;
 	Ok(0)
 }
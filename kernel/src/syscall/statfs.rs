@@ -19,46 +19,33 @@
 //! The `statfs` system call returns information about a mounted file system.
 
 use crate::{
-	file::{fs::Statfs, vfs, vfs::ResolutionSettings},
-	process::{
-		mem_space::copy::{SyscallPtr, SyscallString},
-		Process,
+	file::{
+		fs::{Statfs, Statfs32},
+		vfs,
+		vfs::ResolutionSettings,
 	},
+	process::mem_space::copy::{SyscallPtr, SyscallString},
 	syscall::Args,
 };
-use utils::{
-	collections::path::PathBuf,
-	errno,
-	errno::{EResult, Errno},
-};
+use utils::{errno, errno::EResult};
 
-pub(super) fn do_statfs(
-	path: SyscallString,
-	buf: SyscallPtr<Statfs>,
-	rs: ResolutionSettings,
-) -> EResult<usize> {
-	let rs = ResolutionSettings {
-		follow_link: false,
-		..rs
-	};
-	let path = path.copy_from_user()?.ok_or_else(|| errno!(EFAULT))?;
-	let path = PathBuf::try_from(path)?;
-	let stat = vfs::get_file_from_path(&path, &rs)?
+/// Returns the statistics of the filesystem on which the file at `path` is located.
+pub(super) fn get_statfs(path: SyscallString, rs: &ResolutionSettings) -> EResult<Statfs> {
+	let path = path.copy_path_from_user()?.ok_or_else(|| errno!(EFAULT))?;
+	vfs::get_file_from_path(&path, rs)?
 		.node()
 		.location
 		.get_mountpoint()
 		// Unwrapping will not fail since the file is accessed from path
 		.unwrap()
-		.fs
-		.get_stat()?;
-	// Write structure to userspace
-	buf.copy_to_user(stat)?;
-	Ok(0)
+		.get_stat()
 }
 
 pub fn statfs(
-	Args((path, buf)): Args<(SyscallString, SyscallPtr<Statfs>)>,
+	Args((path, buf)): Args<(SyscallString, SyscallPtr<Statfs32>)>,
 	rs: ResolutionSettings,
 ) -> EResult<usize> {
-	do_statfs(path, buf, rs)
+	let stat = get_statfs(path, &rs)?;
+	buf.copy_to_user(Statfs32::try_from(&stat)?)?;
+	Ok(0)
 }
@@ -18,19 +18,23 @@
 
 //! The `statfs64` system call returns information about a mounted file system.
 
-use super::statfs::do_statfs;
+use super::statfs::get_statfs;
 use crate::{
 	file::{fs::Statfs, vfs::ResolutionSettings},
 	process::mem_space::copy::{SyscallPtr, SyscallString},
 	syscall::Args,
 };
-use utils::errno::EResult;
+use core::mem::size_of;
+use utils::{errno, errno::EResult};
 
-// TODO Check args types
 pub fn statfs64(
-	Args((path, _sz, buf)): Args<(SyscallString, usize, SyscallPtr<Statfs>)>,
+	Args((path, sz, buf)): Args<(SyscallString, usize, SyscallPtr<Statfs>)>,
 	rs: ResolutionSettings,
 ) -> EResult<usize> {
-	// TODO Use `sz`
-	do_statfs(path, buf, rs)
+	if sz != size_of::<Statfs>() {
+		return Err(errno!(EINVAL));
+	}
+	let stat = get_statfs(path, &rs)?;
+	buf.copy_to_user(stat)?;
+	Ok(0)
 }
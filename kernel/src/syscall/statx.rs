@@ -33,7 +33,6 @@ use crate::{
 };
 use core::ffi::{c_int, c_uint};
 use utils::{
-	collections::path::PathBuf,
 	errno,
 	errno::{EResult, Errno},
 	lock::Mutex,
@@ -125,10 +124,7 @@ pub fn statx(
 	}
 	// TODO Implement all flags
 	// Get the file
-	let pathname = pathname
-		.copy_from_user()?
-		.map(PathBuf::try_from)
-		.transpose()?;
+	let pathname = pathname.copy_path_from_user()?;
 	let Resolved::Found(file) = at::get_file(&fds.lock(), rs, dirfd, pathname.as_deref(), flags)?
 	else {
 		return Err(errno!(ENOENT));
@@ -43,8 +43,9 @@ pub fn symlink(
 		return Err(errno!(ENAMETOOLONG));
 	}
 	let target = PathBuf::try_from(target_slice)?;
-	let linkpath = linkpath.copy_from_user()?.ok_or_else(|| errno!(EFAULT))?;
-	let linkpath = PathBuf::try_from(linkpath)?;
+	let linkpath = linkpath
+		.copy_path_from_user()?
+		.ok_or_else(|| errno!(EFAULT))?;
 	let link_parent = linkpath.parent().unwrap_or(Path::root());
 	let link_name = linkpath.file_name().ok_or_else(|| errno!(ENOENT))?;
 	// Link's parent
@@ -54,11 +54,10 @@ pub fn symlinkat(
 	}
 	let target = PathBuf::try_from(target_slice)?;
 	let linkpath = linkpath
-		.copy_from_user()?
-		.map(PathBuf::try_from)
-		.transpose()?;
+		.copy_path_from_user()?
+		.ok_or_else(|| errno!(EFAULT))?;
 	// Create link
-	let resolved = at::get_file(&fds.lock(), rs.clone(), newdirfd, linkpath.as_deref(), 0)?;
+	let resolved = at::get_file(&fds.lock(), rs.clone(), newdirfd, Some(&linkpath), 0)?;
 	match resolved {
 		Resolved::Creatable {
 			parent,
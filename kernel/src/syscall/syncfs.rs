@@ -19,10 +19,12 @@
 //! The `syncfs` system call allows to synchronize the filesystem containing the
 //! file pointed by the given file descriptor.
 
-use crate::{file::fd::FileDescriptorTable, process::Process, syscall::Args};
+use crate::{
+	file::{fd::FileDescriptorTable, page_cache},
+	syscall::Args,
+};
 use core::ffi::c_int;
 use utils::{
-	errno,
 	errno::{EResult, Errno},
 	lock::Mutex,
 	ptr::arc::Arc,
@@ -34,7 +36,6 @@ pub fn syncfs(Args(fd): Args<c_int>, fds: Arc<Mutex<FileDescriptorTable>>) -> ER
 	let Some(ent) = &file.vfs_entry else {
 		return Ok(0);
 	};
-	let _mountpoint = ent.node().location.get_mountpoint();
-	// TODO Sync all files on mountpoint
+	page_cache::sync(Some(ent.node().location.mountpoint_id))?;
 	Ok(0)
 }
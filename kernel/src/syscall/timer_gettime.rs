@@ -0,0 +1,51 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The `timer_gettime` system call returns the state of a per-process timer.
+
+use crate::{
+	process::{mem_space::copy::SyscallPtr, Process},
+	syscall::Args,
+	time::unit::{ITimerspec32, ITimerspec64, TimerT},
+};
+use utils::{
+	errno,
+	errno::{EResult, Errno},
+	lock::{IntMutex, IntMutexGuard},
+	ptr::arc::Arc,
+};
+
+/// Returns the state of the timer `timerid` of the process `proc`.
+pub(super) fn get_time(proc: &IntMutex<Process>, timerid: TimerT) -> EResult<ITimerspec64> {
+	let proc = proc.lock();
+	let manager_mutex = proc.timer_manager().clone();
+	let mut manager = manager_mutex.lock();
+	let timer = manager
+		.get_timer_mut(timerid)
+		.ok_or_else(|| errno!(EINVAL))?;
+	Ok(timer.get_time())
+}
+
+pub fn timer_gettime(
+	Args((timerid, curr_value)): Args<(TimerT, SyscallPtr<ITimerspec32>)>,
+	proc: Arc<IntMutex<Process>>,
+) -> EResult<usize> {
+	let curr = get_time(&proc, timerid)?;
+	curr_value.copy_to_user(curr.into())?;
+	Ok(0)
+}
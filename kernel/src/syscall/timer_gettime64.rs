@@ -0,0 +1,36 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! `timer_gettime64` is the same as `timer_gettime`, with 64 bits values.
+
+use super::timer_gettime::get_time;
+use crate::{
+	process::{mem_space::copy::SyscallPtr, Process},
+	syscall::Args,
+	time::unit::{ITimerspec64, TimerT},
+};
+use utils::{errno::EResult, lock::IntMutex, ptr::arc::Arc};
+
+pub fn timer_gettime64(
+	Args((timerid, curr_value)): Args<(TimerT, SyscallPtr<ITimerspec64>)>,
+	proc: Arc<IntMutex<Process>>,
+) -> EResult<usize> {
+	let curr = get_time(&proc, timerid)?;
+	curr_value.copy_to_user(curr)?;
+	Ok(0)
+}
@@ -16,12 +16,12 @@
  * Maestro. If not, see <https://www.gnu.org/licenses/>.
  */
 
-//! The `timer_settime` system call creates a per-process timer.
+//! The `timer_settime` system call arms or disarms a per-process timer.
 
 use crate::{
 	process::{mem_space::copy::SyscallPtr, Process},
 	syscall::Args,
-	time::unit::{ITimerspec32, TimerT},
+	time::unit::{ITimerspec32, ITimerspec64, TimerT},
 };
 use core::ffi::c_int;
 use utils::{
@@ -31,18 +31,21 @@ use utils::{
 	ptr::arc::Arc,
 };
 
-/// If set, the specified time is *not* relative to the timer's current counter.
 const TIMER_ABSTIME: c_int = 1;
 
-pub fn timer_settime(
-	Args((timerid, flags, new_value, old_value)): Args<(
-		TimerT,
-		c_int,
-		SyscallPtr<ITimerspec32>,
-		SyscallPtr<ITimerspec32>,
-	)>,
-	proc: Arc<IntMutex<Process>>,
-) -> EResult<usize> {
+/// Sets the timer `timerid` of the process `proc` to `new_value`, returning its previous
+/// state.
+///
+/// The caller is responsible for checking `new_value` is valid.
+pub(super) fn set_time(
+	proc: &IntMutex<Process>,
+	timerid: TimerT,
+	flags: c_int,
+	new_value: ITimerspec64,
+) -> EResult<ITimerspec64> {
+	if flags & !TIMER_ABSTIME != 0 {
+		return Err(errno!(EINVAL));
+	}
 	let proc = proc.lock();
 	// Get timer
 	let manager_mutex = proc.timer_manager().clone();
@@ -50,14 +53,34 @@ pub fn timer_settime(
 	let timer = manager
 		.get_timer_mut(timerid)
 		.ok_or_else(|| errno!(EINVAL))?;
-	// Write old value
 	let old = timer.get_time();
-	old_value.copy_to_user(old)?;
 	// Set new value
-	let mut new_value_val = new_value.copy_from_user()?.ok_or_else(|| errno!(EFAULT))?;
-	if (flags & TIMER_ABSTIME) == 0 {
-		new_value_val.it_value = new_value_val.it_value + old.it_value;
+	let abs = flags & TIMER_ABSTIME != 0;
+	// An absolute value is expressed in the time namespace of the caller
+	timer.set_time(
+		new_value,
+		abs,
+		proc.time_ns.as_deref(),
+		proc.get_pid(),
+		timerid,
+	)?;
+	Ok(old)
+}
+
+pub fn timer_settime(
+	Args((timerid, flags, new_value, old_value)): Args<(
+		TimerT,
+		c_int,
+		SyscallPtr<ITimerspec32>,
+		SyscallPtr<ITimerspec32>,
+	)>,
+	proc: Arc<IntMutex<Process>>,
+) -> EResult<usize> {
+	let new_value = new_value.copy_from_user()?.ok_or_else(|| errno!(EFAULT))?;
+	if !new_value.is_valid() {
+		return Err(errno!(EINVAL));
 	}
-	timer.set_time(new_value_val, proc.get_pid(), timerid)?;
+	let old = set_time(&proc, timerid, flags, new_value.into())?;
+	old_value.copy_to_user(old.into())?;
 	Ok(0)
 }
@@ -0,0 +1,51 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! `timer_settime64` is the same as `timer_settime`, with 64 bits values.
+
+use super::timer_settime::set_time;
+use crate::{
+	process::{mem_space::copy::SyscallPtr, Process},
+	syscall::Args,
+	time::unit::{ITimerspec64, TimerT},
+};
+use core::ffi::c_int;
+use utils::{
+	errno,
+	errno::{EResult, Errno},
+	lock::IntMutex,
+	ptr::arc::Arc,
+};
+
+pub fn timer_settime64(
+	Args((timerid, flags, new_value, old_value)): Args<(
+		TimerT,
+		c_int,
+		SyscallPtr<ITimerspec64>,
+		SyscallPtr<ITimerspec64>,
+	)>,
+	proc: Arc<IntMutex<Process>>,
+) -> EResult<usize> {
+	let new_value = new_value.copy_from_user()?.ok_or_else(|| errno!(EFAULT))?;
+	if !new_value.is_valid() {
+		return Err(errno!(EINVAL));
+	}
+	let old = set_time(&proc, timerid, flags, new_value)?;
+	old_value.copy_to_user(old)?;
+	Ok(0)
+}
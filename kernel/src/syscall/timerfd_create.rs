@@ -0,0 +1,65 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The `timerfd_create` system call creates a timer notifying its expirations through a file
+//! descriptor.
+
+use crate::{
+	file::{
+		fd::{FileDescriptorTable, FD_CLOEXEC},
+		timerfd,
+		timerfd::{TimerFd, TFD_CLOEXEC, TFD_NONBLOCK},
+		File, O_NONBLOCK, O_RDONLY,
+	},
+	syscall::Args,
+	time::unit::ClockIdT,
+};
+use core::ffi::c_int;
+use utils::{
+	errno,
+	errno::{EResult, Errno},
+	lock::Mutex,
+	ptr::arc::Arc,
+};
+
+pub fn timerfd_create(
+	Args((clockid, flags)): Args<(ClockIdT, c_int)>,
+	fds: Arc<Mutex<FileDescriptorTable>>,
+) -> EResult<usize> {
+	if flags & !(TFD_CLOEXEC | TFD_NONBLOCK) != 0 {
+		return Err(errno!(EINVAL));
+	}
+	let timer = Arc::new(TimerFd::new(clockid)?)?;
+	let mut file_flags = O_RDONLY;
+	if flags & TFD_NONBLOCK != 0 {
+		file_flags |= O_NONBLOCK;
+	}
+	let file = File::open_floating(timer.clone(), file_flags)?;
+	let mut fd_flags = 0;
+	if flags & TFD_CLOEXEC != 0 {
+		fd_flags |= FD_CLOEXEC;
+	}
+	let mut fds = fds.lock();
+	let (fd_id, _) = fds.create_fd(fd_flags, file)?;
+	// Start ticking the timer
+	if let Err(e) = timerfd::register(timer) {
+		fds.close_fd(fd_id as _)?;
+		return Err(e.into());
+	}
+	Ok(fd_id as _)
+}
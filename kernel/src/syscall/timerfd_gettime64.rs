@@ -0,0 +1,36 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! `timerfd_gettime64` is the same as `timerfd_gettime`, with 64 bits values.
+
+use super::timerfd_gettime::get_time;
+use crate::{
+	file::fd::FileDescriptorTable, process::mem_space::copy::SyscallPtr, syscall::Args,
+	time::unit::ITimerspec64,
+};
+use core::ffi::c_int;
+use utils::{errno::EResult, lock::Mutex, ptr::arc::Arc};
+
+pub fn timerfd_gettime64(
+	Args((fd, curr_value)): Args<(c_int, SyscallPtr<ITimerspec64>)>,
+	fds: Arc<Mutex<FileDescriptorTable>>,
+) -> EResult<usize> {
+	let curr = get_time(&fds, fd)?;
+	curr_value.copy_to_user(curr)?;
+	Ok(0)
+}
@@ -0,0 +1,75 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The `timerfd_settime` system call arms or disarms a timer referred to by a file descriptor.
+
+use crate::{
+	file::{
+		fd::FileDescriptorTable,
+		timerfd::{TimerFd, TFD_TIMER_ABSTIME, TFD_TIMER_CANCEL_ON_SET},
+	},
+	process::{mem_space::copy::SyscallPtr, Process},
+	syscall::Args,
+	time::unit::{ITimerspec32, ITimerspec64},
+};
+use core::ffi::c_int;
+use utils::{
+	errno,
+	errno::{EResult, Errno},
+	lock::Mutex,
+	ptr::arc::Arc,
+};
+
+/// Sets the timer referred to by the file descriptor `fd` to `new_value`, returning its
+/// previous state.
+///
+/// The caller is responsible for checking `new_value` is valid.
+pub(super) fn set_time(
+	fds: &Mutex<FileDescriptorTable>,
+	fd: c_int,
+	flags: c_int,
+	new_value: ITimerspec64,
+) -> EResult<ITimerspec64> {
+	// TODO implement TFD_TIMER_CANCEL_ON_SET when setting the real time clock is supported
+	if flags & !(TFD_TIMER_ABSTIME | TFD_TIMER_CANCEL_ON_SET) != 0 {
+		return Err(errno!(EINVAL));
+	}
+	let file = fds.lock().get_fd(fd)?.get_file().clone();
+	let timer = file.get_buffer::<TimerFd>().ok_or_else(|| errno!(EINVAL))?;
+	// An absolute value is expressed in the time namespace of the caller
+	let ns = Process::current().lock().time_ns.clone();
+	Ok(timer.set_time(new_value, flags & TFD_TIMER_ABSTIME != 0, ns.as_deref()))
+}
+
+pub fn timerfd_settime(
+	Args((fd, flags, new_value, old_value)): Args<(
+		c_int,
+		c_int,
+		SyscallPtr<ITimerspec32>,
+		SyscallPtr<ITimerspec32>,
+	)>,
+	fds: Arc<Mutex<FileDescriptorTable>>,
+) -> EResult<usize> {
+	let new_value = new_value.copy_from_user()?.ok_or_else(|| errno!(EFAULT))?;
+	if !new_value.is_valid() {
+		return Err(errno!(EINVAL));
+	}
+	let old = set_time(&fds, fd, flags, new_value.into())?;
+	old_value.copy_to_user(old.into())?;
+	Ok(0)
+}
@@ -0,0 +1,50 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! `timerfd_settime64` is the same as `timerfd_settime`, with 64 bits values.
+
+use super::timerfd_settime::set_time;
+use crate::{
+	file::fd::FileDescriptorTable, process::mem_space::copy::SyscallPtr, syscall::Args,
+	time::unit::ITimerspec64,
+};
+use core::ffi::c_int;
+use utils::{
+	errno,
+	errno::{EResult, Errno},
+	lock::Mutex,
+	ptr::arc::Arc,
+};
+
+pub fn timerfd_settime64(
+	Args((fd, flags, new_value, old_value)): Args<(
+		c_int,
+		c_int,
+		SyscallPtr<ITimerspec64>,
+		SyscallPtr<ITimerspec64>,
+	)>,
+	fds: Arc<Mutex<FileDescriptorTable>>,
+) -> EResult<usize> {
+	let new_value = new_value.copy_from_user()?.ok_or_else(|| errno!(EFAULT))?;
+	if !new_value.is_valid() {
+		return Err(errno!(EINVAL));
+	}
+	let old = set_time(&fds, fd, flags, new_value)?;
+	old_value.copy_to_user(old)?;
+	Ok(0)
+}
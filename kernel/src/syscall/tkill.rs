@@ -36,6 +36,7 @@ pub fn tkill(
 ) -> EResult<usize> {
 	let signal = Signal::try_from(sig)?;
 	let mut proc = proc.lock();
+	let tid = proc.pid_to_global(tid).ok_or_else(|| errno!(ESRCH))?;
 	// Check if the thread to kill is the current
 	if proc.tid == tid {
 		proc.kill(signal);
@@ -44,7 +45,7 @@ pub fn tkill(
 		let thread_mutex = Process::get_by_tid(tid).ok_or(errno!(ESRCH))?;
 		let mut thread = thread_mutex.lock();
 		// Check permission
-		if !proc.access_profile.can_kill(&thread) {
+		if !proc.cred.get().can_kill(&thread) {
 			return Err(errno!(EPERM));
 		}
 		thread.kill(signal);
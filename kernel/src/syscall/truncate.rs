@@ -19,34 +19,36 @@
 //! The `truncate` syscall allows to truncate a file.
 
 use crate::{
-	file::{vfs, vfs::ResolutionSettings},
-	process::{mem_space::copy::SyscallString, Process},
+	file::{lease, page_cache, vfs, vfs::ResolutionSettings},
+	process::mem_space::copy::SyscallString,
 	syscall::Args,
 };
 use utils::{
-	collections::path::PathBuf,
 	errno,
 	errno::{EResult, Errno},
 };
 
-pub fn truncate(Args((path, length)): Args<(SyscallString, usize)>) -> EResult<usize> {
-	let proc_mutex = Process::current();
-	let proc = proc_mutex.lock();
-
-	let rs = ResolutionSettings::for_process(&proc, true);
-
-	let path = path.copy_from_user()?.ok_or(errno!(EFAULT))?;
-	let path = PathBuf::try_from(path)?;
-
+/// Truncates the file at `path` to `length` bytes.
+///
+/// `rs` is the path resolution settings.
+pub fn do_truncate(path: SyscallString, length: u64, rs: ResolutionSettings) -> EResult<usize> {
+	let path = path.copy_path_from_user()?.ok_or(errno!(EFAULT))?;
 	let file = vfs::get_file_from_path(&path, &rs)?;
 	// Permission check
 	let stat = file.stat()?;
 	if !rs.access_profile.can_write_file(&stat) {
 		return Err(errno!(EACCES));
 	}
-	file.node()
-		.ops
-		.truncate_content(&file.node().location, length as _)?;
-
+	lease::break_leases(&file.node().location, true, None, false)?;
+	page_cache::truncate(file.node(), length)?;
+	vfs::content_modified(&file, &rs.access_profile)?;
 	Ok(0)
 }
+
+pub fn truncate(
+	Args((path, length)): Args<(SyscallString, isize)>,
+	rs: ResolutionSettings,
+) -> EResult<usize> {
+	let length: u64 = length.try_into().map_err(|_| errno!(EINVAL))?;
+	do_truncate(path, length, rs)
+}
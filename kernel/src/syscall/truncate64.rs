@@ -0,0 +1,38 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The `truncate64` syscall allows to truncate a file, with a 64 bits length.
+
+use super::truncate::do_truncate;
+use crate::{
+	file::vfs::ResolutionSettings, process::mem_space::copy::SyscallString, syscall::Args,
+};
+use utils::{
+	errno,
+	errno::{EResult, Errno},
+};
+
+pub fn truncate64(
+	Args((path, length_lo, length_hi)): Args<(SyscallString, u32, u32)>,
+	rs: ResolutionSettings,
+) -> EResult<usize> {
+	// On 32 bits, the 64 bits length is split across two arguments
+	let length = ((length_hi as i64) << 32) | length_lo as i64;
+	let length: u64 = length.try_into().map_err(|_| errno!(EINVAL))?;
+	do_truncate(path, length, rs)
+}
@@ -27,6 +27,7 @@ use utils::{
 };
 
 pub fn umask(Args(mask): Args<file::Mode>, proc: Arc<IntMutex<Process>>) -> EResult<usize> {
-	let prev = mem::replace(&mut proc.lock().umask, mask & 0o777);
+	let fs = proc.lock().fs.clone();
+	let prev = mem::replace(&mut fs.lock().umask, mask & 0o777);
 	Ok(prev as _)
 }
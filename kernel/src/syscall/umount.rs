@@ -28,21 +28,24 @@ use crate::{
 	syscall::Args,
 };
 use utils::{
-	collections::path::PathBuf,
 	errno,
 	errno::{EResult, Errno},
 };
 
-pub fn umount(Args(target): Args<SyscallString>, rs: ResolutionSettings) -> EResult<usize> {
+/// Unmounts the filesystem mounted on the directory at `target`.
+pub(super) fn do_umount(target: SyscallString, rs: ResolutionSettings) -> EResult<usize> {
 	// Check permission
 	if !rs.access_profile.is_privileged() {
 		return Err(errno!(EPERM));
 	}
 	// Get target directory
-	let target_slice = target.copy_from_user()?.ok_or(errno!(EFAULT))?;
-	let target_path = PathBuf::try_from(target_slice)?;
+	let target_path = target.copy_path_from_user()?.ok_or(errno!(EFAULT))?;
 	let target_file = vfs::get_file_from_path(&target_path, &rs)?;
 	// Remove mountpoint
 	mountpoint::remove(target_file)?;
 	Ok(0)
 }
+
+pub fn umount(Args(target): Args<SyscallString>, rs: ResolutionSettings) -> EResult<usize> {
+	do_umount(target, rs)
+}
@@ -0,0 +1,62 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The `umount2` system call is the same as `umount`, with flags.
+
+use super::umount::do_umount;
+use crate::{
+	file::vfs::ResolutionSettings, process::mem_space::copy::SyscallString, syscall::Args,
+};
+use core::ffi::c_int;
+use utils::{
+	errno,
+	errno::{EResult, Errno},
+};
+
+/// Flag: forces the unmount even if the filesystem is busy.
+const MNT_FORCE: c_int = 1;
+/// Flag: detaches the filesystem, which is unmounted once it is not busy anymore.
+const MNT_DETACH: c_int = 2;
+/// Flag: marks the mountpoint as expired.
+const MNT_EXPIRE: c_int = 4;
+/// Flag: if the last component of the target is a symbolic link, do not follow it.
+const UMOUNT_NOFOLLOW: c_int = 8;
+
+pub fn umount2(
+	Args((target, flags)): Args<(SyscallString, c_int)>,
+	rs: ResolutionSettings,
+) -> EResult<usize> {
+	if flags & !(MNT_FORCE | MNT_DETACH | MNT_EXPIRE | UMOUNT_NOFOLLOW) != 0 {
+		return Err(errno!(EINVAL));
+	}
+	if flags & MNT_EXPIRE != 0 && flags & (MNT_FORCE | MNT_DETACH) != 0 {
+		return Err(errno!(EINVAL));
+	}
+	// TODO support lazy unmount and expiration
+	if flags & (MNT_DETACH | MNT_EXPIRE) != 0 {
+		return Err(errno!(EINVAL));
+	}
+	// Busy filesystems are not detected yet, so `MNT_FORCE` has no effect
+	do_umount(
+		target,
+		ResolutionSettings {
+			follow_link: flags & UMOUNT_NOFOLLOW == 0,
+			..rs
+		},
+	)
+}
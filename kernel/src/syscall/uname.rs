@@ -20,11 +20,17 @@
 
 use crate::{
 	process::{mem_space::copy::SyscallPtr, Process},
-	syscall::Args,
+	syscall::{
+		personality::{PER_LINUX32, PER_MASK, UNAME26},
+		Args,
+	},
 };
 use utils::{
 	errno,
 	errno::{EResult, Errno},
+	format,
+	lock::IntMutex,
+	ptr::arc::Arc,
 };
 
 /// The length of a field of the utsname structure.
@@ -46,7 +52,45 @@ pub struct Utsname {
 	machine: [u8; UTSNAME_LENGTH],
 }
 
-pub fn uname(Args(buf): Args<SyscallPtr<Utsname>>) -> EResult<usize> {
+/// Writes the kernel release into `buf`.
+///
+/// If the [`UNAME26`] personality flag is set, the release is reported as `2.6.x`, where `x` is
+/// derived from the minor version of the kernel.
+fn write_release(personality: u32, buf: &mut [u8]) -> EResult<()> {
+	let release = crate::VERSION.as_bytes();
+	if personality & UNAME26 == 0 {
+		utils::slice_copy(release, buf);
+		return Ok(());
+	}
+	// Split the numeric part (at most three components) from the rest of the release
+	let mut dots = 0;
+	let numeric_len = release
+		.iter()
+		.position(|c| {
+			if *c == b'.' {
+				dots += 1;
+			}
+			dots >= 3 || !(c.is_ascii_digit() || *c == b'.')
+		})
+		.unwrap_or(release.len());
+	let (numeric, rest) = release.split_at(numeric_len);
+	let minor: u32 = numeric
+		.split(|c| *c == b'.')
+		.nth(1)
+		.and_then(|n| core::str::from_utf8(n).ok())
+		.and_then(|n| n.parse().ok())
+		.unwrap_or(0);
+	let rest = core::str::from_utf8(rest).unwrap_or("");
+	let release = format!("2.6.{}{rest}", minor + 60)?;
+	utils::slice_copy(release.as_bytes(), buf);
+	Ok(())
+}
+
+pub fn uname(
+	Args(buf): Args<SyscallPtr<Utsname>>,
+	proc: Arc<IntMutex<Process>>,
+) -> EResult<usize> {
+	let personality = proc.lock().personality;
 	let mut utsname = Utsname {
 		sysname: [0; UTSNAME_LENGTH],
 		nodename: [0; UTSNAME_LENGTH],
@@ -56,9 +100,14 @@ pub fn uname(Args(buf): Args<SyscallPtr<Utsname>>) -> EResult<usize> {
 	};
 	utils::slice_copy(crate::NAME.as_bytes(), &mut utsname.sysname);
 	utils::slice_copy(&crate::HOSTNAME.lock(), &mut utsname.nodename);
-	utils::slice_copy(crate::VERSION.as_bytes(), &mut utsname.release);
+	write_release(personality, &mut utsname.release)?;
 	utils::slice_copy(&[], &mut utsname.version);
-	utils::slice_copy(crate::ARCH.as_bytes(), &mut utsname.machine);
+	let machine = if personality & PER_MASK == PER_LINUX32 {
+		"i686"
+	} else {
+		crate::ARCH
+	};
+	utils::slice_copy(machine.as_bytes(), &mut utsname.machine);
 	buf.copy_to_user(utsname)?;
 	Ok(0)
 }
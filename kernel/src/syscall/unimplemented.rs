@@ -0,0 +1,122 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+//! Tracking of system calls that are requested by userspace but not implemented by the kernel.
+//!
+//! When porting programs, this allows to find which missing system calls matter most in
+//! practice. Records are kept both system-wide and per process, and are exposed through
+//! `/proc/unimplemented_syscalls` and `/proc/<pid>/unimplemented_syscalls`.
+//!
+//! By default, calling an unimplemented system call kills the process with `SIGSYS`. If the
+//! `syscall.audit` parameter is enabled, the system call fails with `ENOSYS` instead, so that a
+//! program can go on and every missing system call it uses gets recorded.
+
+use crate::{
+	module::{param, param::Param},
+	process::Process,
+};
+use core::{
+	fmt,
+	fmt::Formatter,
+	sync::atomic::{AtomicU32, Ordering::Relaxed},
+};
+use utils::errno::EResult;
+
+/// The number of system call IDs that are tracked. Calls to IDs above are not recorded.
+pub const SYSCALLS_COUNT: usize = 0x200;
+
+/// If enabled, unimplemented system calls fail with `ENOSYS` instead of killing the process.
+pub static AUDIT: Param<bool> = Param::new("syscall", "audit", false, true, None);
+
+/// The number of times each unimplemented system call has been requested, system-wide.
+static COUNTS: [AtomicU32; SYSCALLS_COUNT] = [const { AtomicU32::new(0) }; SYSCALLS_COUNT];
+
+/// A set of system call IDs.
+#[derive(Clone, Debug, Default)]
+pub struct SyscallSet([u32; SYSCALLS_COUNT / 32]);
+
+impl SyscallSet {
+	/// Inserts the system call `id` in the set.
+	///
+	/// If `id` is out of range, the function does nothing.
+	pub fn insert(&mut self, id: usize) {
+		if let Some(word) = self.0.get_mut(id / 32) {
+			*word |= 1 << (id % 32);
+		}
+	}
+
+	/// Returns an iterator over the IDs in the set, in ascending order.
+	pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+		(0..SYSCALLS_COUNT).filter(|id| self.0[id / 32] & (1 << (id % 32)) != 0)
+	}
+}
+
+impl fmt::Display for SyscallSet {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		for id in self.iter() {
+			writeln!(f, "{id}")?;
+		}
+		Ok(())
+	}
+}
+
+/// Displays the system-wide records, with one line per system call ID, followed by the number of
+/// times it has been requested.
+pub struct GlobalRecords;
+
+impl fmt::Display for GlobalRecords {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		for (id, count) in COUNTS.iter().enumerate() {
+			let count = count.load(Relaxed);
+			if count > 0 {
+				writeln!(f, "{id} {count}")?;
+			}
+		}
+		Ok(())
+	}
+}
+
+/// Records a call to the unimplemented system call `id` by the process `proc`.
+pub fn record(proc: &mut Process, id: usize) {
+	if let Some(count) = COUNTS.get(id) {
+		let _ = count.fetch_update(Relaxed, Relaxed, |c| c.checked_add(1));
+	}
+	proc.unimplemented_syscalls.insert(id);
+}
+
+/// Registers the parameters related to system calls.
+pub(crate) fn init() -> EResult<()> {
+	param::register(&AUDIT)
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test_case]
+	fn syscall_set() {
+		let mut set = SyscallSet::default();
+		set.insert(3);
+		set.insert(0x1c2);
+		set.insert(3);
+		set.insert(SYSCALLS_COUNT);
+		let mut iter = set.iter();
+		assert_eq!(iter.next(), Some(3));
+		assert_eq!(iter.next(), Some(0x1c2));
+		assert_eq!(iter.next(), None);
+	}
+}
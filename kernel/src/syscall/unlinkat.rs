@@ -32,7 +32,6 @@ use crate::{
 };
 use core::ffi::c_int;
 use utils::{
-	collections::path::PathBuf,
 	errno,
 	errno::{EResult, Errno},
 	lock::Mutex,
@@ -48,9 +47,8 @@ pub fn do_unlinkat(
 	fds: Arc<Mutex<FileDescriptorTable>>,
 ) -> EResult<usize> {
 	let pathname = pathname
-		.copy_from_user()?
-		.map(PathBuf::try_from)
-		.ok_or_else(|| errno!(EFAULT))??;
+		.copy_path_from_user()?
+		.ok_or_else(|| errno!(EFAULT))?;
 	let parent_path = pathname.parent().ok_or_else(|| errno!(ENOENT))?;
 	let rs = ResolutionSettings {
 		follow_link: false,
@@ -0,0 +1,76 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+//! The `unshare` system call allows a process to detach parts of its execution context that are
+//! currently shared with other processes.
+
+use super::clone::{CLONE_FILES, CLONE_FS, CLONE_NEWTIME};
+use crate::{
+	file::fd::FileDescriptorTable, process::Process, syscall::Args, time::namespace::TimeNamespace,
+};
+use core::ffi::c_ulong;
+use utils::{
+	errno,
+	errno::{EResult, Errno},
+	lock::{IntMutex, Mutex},
+	ptr::arc::Arc,
+};
+
+pub fn unshare(
+	Args(flags): Args<c_ulong>,
+	fds: Arc<Mutex<FileDescriptorTable>>,
+	proc: Arc<IntMutex<Process>>,
+) -> EResult<usize> {
+	if flags & !(CLONE_FS | CLONE_FILES | CLONE_NEWTIME) != 0 {
+		return Err(errno!(EINVAL));
+	}
+	// The new time namespace applies to children created afterwards
+	let new_time_ns = if flags & CLONE_NEWTIME != 0 {
+		let proc = proc.lock();
+		if !proc.cred.get().is_privileged() {
+			return Err(errno!(EPERM));
+		}
+		Some(TimeNamespace::new(proc.time_ns_for_children.as_deref())?)
+	} else {
+		None
+	};
+	// Prepare copies before modifying the process so that it is left untouched on failure
+	let new_fs = if flags & CLONE_FS != 0 {
+		let fs = proc.lock().fs.clone();
+		let fs = fs.lock().clone();
+		Some(Arc::new(Mutex::new(fs))?)
+	} else {
+		None
+	};
+	let new_fds = if flags & CLONE_FILES != 0 {
+		let fds = fds.lock().duplicate(false)?;
+		Some(Arc::new(Mutex::new(fds))?)
+	} else {
+		None
+	};
+	let mut proc = proc.lock();
+	if let Some(fs) = new_fs {
+		proc.fs = fs;
+	}
+	if let Some(fds) = new_fds {
+		proc.file_descriptors = Some(fds);
+	}
+	if let Some(ns) = new_time_ns {
+		proc.time_ns_for_children = Some(ns);
+	}
+	Ok(0)
+}
@@ -25,7 +25,7 @@ use crate::file::{
 	fd::FileDescriptorTable,
 	vfs,
 	vfs::{ResolutionSettings, Resolved},
-	File,
+	File, FileType,
 };
 use core::ffi::c_int;
 use utils::{collections::path::Path, errno, errno::EResult, lock::Mutex, ptr::arc::Arc};
@@ -53,6 +53,42 @@ pub const AT_STATX_FORCE_SYNC: c_int = 0x2000;
 /// Flag: Don't synchronize anything, but rather take cached information.
 pub const AT_STATX_DONT_SYNC: c_int = 0x4000;
 
+/// Tells whether symbolic links are followed at the end of the path.
+///
+/// Arguments:
+/// - `default` is the behaviour of the system call when no flag is given
+/// - `flags` is the set of `AT_*` flags
+///
+/// If both [`AT_SYMLINK_NOFOLLOW`] and [`AT_SYMLINK_FOLLOW`] are set, the function returns
+/// [`errno::EINVAL`].
+fn follow_links(default: bool, flags: c_int) -> EResult<bool> {
+	match (
+		flags & AT_SYMLINK_NOFOLLOW != 0,
+		flags & AT_SYMLINK_FOLLOW != 0,
+	) {
+		(false, false) => Ok(default),
+		(true, false) => Ok(false),
+		(false, true) => Ok(true),
+		(true, true) => Err(errno!(EINVAL)),
+	}
+}
+
+/// Returns the VFS entry of the file referred to by the file descriptor `dirfd`.
+///
+/// The file descriptor may have been opened with [`crate::file::O_PATH`].
+///
+/// Errors:
+/// - If the file descriptor is not open, the function returns [`errno::EBADF`]
+/// - If the file is not on the VFS (pipes, sockets, etc...), the function returns
+///   [`errno::ENOTDIR`]
+fn get_dirfd_entry(fds: &FileDescriptorTable, dirfd: c_int) -> EResult<Arc<vfs::Entry>> {
+	fds.get_fd_raw(dirfd)?
+		.get_file()
+		.vfs_entry
+		.clone()
+		.ok_or_else(|| errno!(ENOTDIR))
+}
+
 /// Returns the file for the given path `path`.
 ///
 /// Arguments:
@@ -62,7 +98,21 @@ pub const AT_STATX_DONT_SYNC: c_int = 0x4000;
 /// - `path` is the path relative to the parent directory
 /// - `flags` is the set of `AT_*` flags
 ///
-/// **Note**: the `start` field of [`ResolutionSettings`] must be set as it is used as the current
+/// The following rules apply, in order:
+/// - If `path` is absolute, `dirfd` is ignored
+/// - If `path` is relative and not empty, it is resolved from `dirfd`, or from the current working
+///   directory if `dirfd` is [`AT_FDCWD`]. The file referred to by `dirfd` must be a directory
+/// - If `path` is empty, the function returns the file referred to by `dirfd` (which may be of any
+///   type), or the current working directory. This requires [`AT_EMPTY_PATH`]
+/// - If `path` is `None`, meaning the system call received a null pointer, the function returns
+///   the file referred to by `dirfd`, which cannot be [`AT_FDCWD`]. System calls that do not
+///   accept a null path must check for it beforehand
+///
+/// The `follow_link` field of `rs` gives the default behaviour regarding symbolic links, which is
+/// overridden by [`AT_SYMLINK_NOFOLLOW`] or [`AT_SYMLINK_FOLLOW`]. Other flags are ignored, and it
+/// is the responsibility of the caller to reject flags it does not support.
+///
+/// **Note**: the `cwd` field of [`ResolutionSettings`] must be set as it is used as the current
 /// working directory.
 pub fn get_file<'p>(
 	fds: &FileDescriptorTable,
@@ -71,39 +121,66 @@ pub fn get_file<'p>(
 	path: Option<&'p Path>,
 	flags: c_int,
 ) -> EResult<Resolved<'p>> {
-	// Prepare resolution settings
-	let follow_links = if rs.follow_link {
-		flags & AT_SYMLINK_NOFOLLOW == 0
-	} else {
-		flags & AT_SYMLINK_FOLLOW != 0
-	};
-	rs.follow_link = follow_links;
-	// If not starting from current directory, get location
-	if dirfd != AT_FDCWD {
-		let cwd = fds
-			.get_fd(dirfd)?
-			.get_file()
-			.vfs_entry
-			.clone()
-			.ok_or_else(|| errno!(ENOTDIR))?;
-		rs.cwd = Some(cwd);
-	}
+	rs.follow_link = follow_links(rs.follow_link, flags)?;
 	match path {
-		Some(path) if !path.is_empty() => vfs::resolve_path(path, &rs),
+		Some(path) if path.is_absolute() => vfs::resolve_path(path, &rs),
+		Some(path) if !path.is_empty() => {
+			if dirfd != AT_FDCWD {
+				let dir = get_dirfd_entry(fds, dirfd)?;
+				if dir.get_type()? != FileType::Directory {
+					return Err(errno!(ENOTDIR));
+				}
+				rs.cwd = Some(dir);
+			}
+			vfs::resolve_path(path, &rs)
+		}
 		// Empty path
 		Some(_) => {
-			// Validation
 			if flags & AT_EMPTY_PATH == 0 {
 				return Err(errno!(ENOENT));
 			}
-			Ok(Resolved::Found(rs.cwd.unwrap()))
+			let file = if dirfd == AT_FDCWD {
+				rs.cwd.ok_or_else(|| errno!(ENOENT))?
+			} else {
+				get_dirfd_entry(fds, dirfd)?
+			};
+			Ok(Resolved::Found(file))
 		}
 		None => {
-			// Validation
 			if dirfd == AT_FDCWD {
 				return Err(errno!(EFAULT));
 			}
-			Ok(Resolved::Found(rs.cwd.unwrap()))
+			Ok(Resolved::Found(get_dirfd_entry(fds, dirfd)?))
 		}
 	}
 }
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::file::{pipe::PipeBuffer, O_RDONLY};
+
+	#[test_case]
+	fn at_follow_links() {
+		assert!(follow_links(true, 0).unwrap());
+		assert!(!follow_links(false, 0).unwrap());
+		assert!(!follow_links(true, AT_SYMLINK_NOFOLLOW).unwrap());
+		assert!(follow_links(false, AT_SYMLINK_FOLLOW).unwrap());
+		// Unrelated flags are ignored
+		assert!(follow_links(true, AT_EMPTY_PATH).unwrap());
+		assert!(follow_links(true, AT_SYMLINK_NOFOLLOW | AT_SYMLINK_FOLLOW).is_err());
+	}
+
+	#[test_case]
+	fn at_dirfd() {
+		let mut fds = FileDescriptorTable::default();
+		// Not open
+		assert_eq!(get_dirfd_entry(&fds, 0).unwrap_err(), errno!(EBADF));
+		assert_eq!(get_dirfd_entry(&fds, -1).unwrap_err(), errno!(EBADF));
+		// Not on the VFS
+		let pipe = Arc::new(PipeBuffer::new().unwrap()).unwrap();
+		let file = File::open_floating(pipe, O_RDONLY).unwrap();
+		let (id, _) = fds.create_fd(0, file).unwrap();
+		assert_eq!(get_dirfd_entry(&fds, id as _).unwrap_err(), errno!(ENOTDIR));
+	}
+}
@@ -23,6 +23,7 @@ use crate::{
 	file::{
 		fd::FileDescriptorTable,
 		fs::StatSet,
+		vfs,
 		vfs::{ResolutionSettings, Resolved},
 	},
 	process::{
@@ -40,7 +41,6 @@ use crate::{
 };
 use core::ffi::c_int;
 use utils::{
-	collections::path::PathBuf,
 	errno,
 	errno::{EResult, Errno},
 	lock::Mutex,
@@ -57,10 +57,7 @@ pub fn utimensat(
 	rs: ResolutionSettings,
 	fds: Arc<Mutex<FileDescriptorTable>>,
 ) -> EResult<usize> {
-	let pathname = pathname
-		.copy_from_user()?
-		.map(PathBuf::try_from)
-		.transpose()?;
+	let pathname = pathname.copy_path_from_user()?;
 	let times_val = match times.copy_from_user()? {
 		Some(times) => times,
 		None => {
@@ -76,13 +73,14 @@ pub fn utimensat(
 		return Err(errno!(ENOENT));
 	};
 	// Update timestamps
-	file.node().ops.set_stat(
-		&file.node().location,
+	vfs::update_stat(
+		&file,
 		StatSet {
 			atime: Some(atime.to_nano() / 1000000000),
 			mtime: Some(mtime.to_nano() / 1000000000),
 			..Default::default()
 		},
+		false,
 	)?;
 	Ok(0)
 }
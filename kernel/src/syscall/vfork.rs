@@ -40,7 +40,8 @@ pub fn vfork(proc: Arc<IntMutex<Process>>, regs: &Regs) -> EResult<usize> {
 		let mut regs = regs.clone();
 		regs.set_syscall_return(Ok(0));
 		new_proc.regs = regs;
-		new_proc.get_pid()
+		// The child is in the same namespace as the parent
+		new_proc.get_local_pid()
 	};
 	// Let another process run instead of the current. Because the current
 	// process must now wait for the child process to terminate or execute a program
@@ -0,0 +1,118 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+//! The `waitid` system call waits for a process to change state, designated by a process ID, a
+//! process group ID or a pidfd.
+
+use super::waitpid::{
+	do_wait, pid_to_global, WCONTINUED, WEXITED, WNOHANG, WNOWAIT, WUNTRACED, __WALL, __WCLONE,
+	__WNOTHREAD,
+};
+use crate::{
+	file::{fd::FileDescriptorTable, pidfd::PidFd, O_NONBLOCK},
+	process::{
+		mem_space::copy::SyscallPtr,
+		pid,
+		rusage::RUsage,
+		signal::{SigInfo, Signal, CLD_CONTINUED, CLD_EXITED, CLD_KILLED, CLD_STOPPED},
+		Process, State,
+	},
+	syscall::Args,
+};
+use core::ffi::c_int;
+use utils::{
+	errno,
+	errno::{EResult, Errno},
+	lock::{IntMutex, Mutex},
+	ptr::arc::Arc,
+};
+
+/// Waits for any child.
+const P_ALL: c_int = 0;
+/// Waits for the child with the given PID.
+const P_PID: c_int = 1;
+/// Waits for any child in the given process group.
+const P_PGID: c_int = 2;
+/// Waits for the child referred to by the given pidfd.
+const P_PIDFD: c_int = 3;
+
+/// Wait flag. Returns if a child has stopped.
+const WSTOPPED: c_int = WUNTRACED;
+
+/// Returns the `CLD_*` code and the status to report for the process `proc`.
+fn get_status(proc: &Process) -> (c_int, c_int) {
+	let termsig = proc.get_termsig() as c_int;
+	match proc.get_state() {
+		State::Zombie if termsig == 0 => (CLD_EXITED, proc.get_exit_status().unwrap_or(0) as _),
+		State::Zombie => (CLD_KILLED, termsig),
+		State::Stopped => (CLD_STOPPED, termsig),
+		State::Running | State::Sleeping => (CLD_CONTINUED, Signal::SIGCONT.get_id() as _),
+	}
+}
+
+#[allow(clippy::type_complexity)]
+pub fn waitid(
+	Args((idtype, id, infop, options, rusage)): Args<(
+		c_int,
+		c_int,
+		SyscallPtr<SigInfo>,
+		c_int,
+		SyscallPtr<RUsage>,
+	)>,
+	fds: Arc<Mutex<FileDescriptorTable>>,
+	proc: Arc<IntMutex<Process>>,
+) -> EResult<usize> {
+	const FLAGS: c_int =
+		WNOHANG | WEXITED | WSTOPPED | WCONTINUED | WNOWAIT | __WNOTHREAD | __WALL | __WCLONE;
+	if options & !FLAGS != 0 || options & (WEXITED | WSTOPPED | WCONTINUED) == 0 {
+		return Err(errno!(EINVAL));
+	}
+	// Translate the target into the constraint used by `waitpid`
+	let (pid, nonblock) = match idtype {
+		P_ALL => (-1, false),
+		P_PID if id > 0 => (pid_to_global(&proc.lock(), id)?, false),
+		P_PGID if id >= 0 => (pid_to_global(&proc.lock(), -id)?, false),
+		P_PIDFD => {
+			let file = fds.lock().get_fd(id)?.get_file().clone();
+			let pidfd = file.get_buffer::<PidFd>().ok_or_else(|| errno!(EINVAL))?;
+			// The process might have been waited upon already
+			pidfd.get_process().map_err(|_| errno!(ECHILD))?;
+			(pidfd.get_pid() as _, file.get_flags() & O_NONBLOCK != 0)
+		}
+		_ => return Err(errno!(EINVAL)),
+	};
+	// A non-blocking pidfd makes the call fail instead of waiting
+	let wait_options = if nonblock { options | WNOHANG } else { options };
+	let ns = proc.lock().get_pid_namespace().cloned();
+	let res = do_wait(pid, wait_options, |child| {
+		let (code, status) = get_status(child);
+		// Children are always visible from the namespace of their parent
+		let child_pid = pid::to_local(ns.as_deref(), child.get_pid()).unwrap_or(0);
+		let uid = child.cred.get().uid;
+		infop.copy_to_user(SigInfo::child(code, child_pid, uid, status))?;
+		rusage.copy_to_user(child.get_rusage().clone())?;
+		Ok(())
+	})?;
+	match res {
+		Some(_) => Ok(0),
+		None if nonblock && options & WNOHANG == 0 => Err(errno!(EAGAIN)),
+		None => {
+			infop.copy_to_user(SigInfo::default())?;
+			Ok(0)
+		}
+	}
+}
@@ -19,20 +19,14 @@
 //! The `waitpid` system call allows to wait for an event from a child process.
 
 use crate::{
-	process,
 	process::{
-		mem_space::copy::SyscallPtr, pid::Pid, regs::Regs, rusage::RUsage, scheduler, Process,
-		State,
+		mem_space::copy::SyscallPtr, pid::Pid, rusage::RUsage, scheduler, scheduler::SCHEDULER,
+		Process, State,
 	},
-	syscall::{waitpid::scheduler::SCHEDULER, Args},
-};
-use core::{ffi::c_int, iter};
-use utils::{
-	errno,
-	errno::{EResult, Errno},
-	interrupt::cli,
-	lock::IntMutex,
+	syscall::Args,
 };
+use core::ffi::c_int;
+use utils::{errno, errno::EResult};
 
 /// Wait flag. Returns immediately if no child has exited.
 pub const WNOHANG: i32 = 1;
@@ -46,31 +40,40 @@ pub const WCONTINUED: i32 = 8;
 /// Wait flag. If set, the system call doesn't clear the waitable status of the
 /// child.
 pub const WNOWAIT: i32 = 0x1000000;
+/// Wait flag. Waits only for children of the current thread, not for those of other threads of
+/// the same group.
+///
+/// By default, children of every thread of the group can be waited for.
+pub const __WNOTHREAD: i32 = 0x20000000;
+/// Wait flag. Waits for all children, regardless of whether they are *clone* children.
+pub const __WALL: i32 = 0x40000000;
+/// Wait flag. Waits only for *clone* children.
+///
+/// A clone child is a child which does not send `SIGCHLD` to its parent when terminating.
+pub const __WCLONE: i32 = 0x80000000u32 as _;
 
-/// Returns an iterator over the IDs of the processes to be watched according to the given
-/// constraint.
+/// Tells whether the child process `child` is a target of the call.
 ///
 /// Arguments:
 /// - `curr_proc` is the current process.
+/// - `child` is the child process.
 /// - `pid` is the constraint given to the system call.
-fn iter_targets(curr_proc: &Process, pid: i32) -> impl Iterator<Item = Pid> + '_ {
-	let mut i = 0;
-	iter::from_fn(move || {
-		// FIXME: select only process that are children of `curr_proc`
-		let res = match pid {
-			// FIXME: must wait for any child process whose pgid is equal to -pid
-			..-1 => curr_proc.get_group_processes().get(i).cloned(),
-			-1 => curr_proc.get_children().get(i).cloned(),
-			0 => curr_proc.get_group_processes().get(i).cloned(),
-			_ => (i == 0).then_some(pid as _),
-		};
-		i += 1;
-		res
-	})
+/// - `options` is a set of flags.
+fn is_target(curr_proc: &Process, child: &Process, pid: i32, options: i32) -> bool {
+	let pid_match = match pid {
+		// Any child in the given process group
+		..-1 => pid.unsigned_abs() == child.pgid as u32,
+		-1 => true,
+		// Any child in the same process group
+		0 => child.pgid == curr_proc.pgid,
+		_ => child.get_pid() as i32 == pid,
+	};
+	let clone_match = options & __WALL != 0 || (options & __WCLONE != 0) == child.is_clone_child();
+	pid_match && clone_match
 }
 
 /// Returns the wait status for the given process.
-fn get_wstatus(proc: &Process) -> i32 {
+pub fn get_wstatus(proc: &Process) -> i32 {
 	let status = proc.get_exit_status().unwrap_or(0);
 	let termsig = proc.get_termsig();
 	#[allow(clippy::let_and_return)]
@@ -86,39 +89,66 @@ fn get_wstatus(proc: &Process) -> i32 {
 	wstatus
 }
 
-/// Waits upon a process and returns it. If no process can be waited upon, the function returns
-/// `None`.
+/// Waits upon a process and returns its PID, as seen from the namespace of the current process.
+/// If no process can be waited upon, the function returns `None`.
+///
+/// Unless [`__WNOTHREAD`] is set, the children of the other threads of the current thread group
+/// are searched as well.
+///
+/// The scheduler is locked only if a terminated child has to be removed or if other threads have
+/// to be searched, so that polling for children with [`WNOHANG`] remains cheap.
 ///
 /// Arguments:
 /// - `curr_proc` is the current process.
 /// - `pid` is the constraint given to the system call.
-/// - `wstatus` is the pointer to the wait status.
 /// - `options` is a set of flags.
-/// - `rusage` is the pointer to the resource usage structure.
-fn get_waitable(
+/// - `report` is called with the waited process, to report its state to userspace. If it fails,
+///   the process is not waited upon.
+fn get_waitable<F: FnMut(&Process) -> EResult<()>>(
 	curr_proc: &mut Process,
 	pid: i32,
-	wstatus: &SyscallPtr<i32>,
 	options: i32,
-	rusage: &SyscallPtr<RUsage>,
+	report: &mut F,
 ) -> EResult<Option<Pid>> {
 	let mut empty = true;
-	let mut sched = SCHEDULER.get().lock();
-	// Find a waitable process
-	let proc = iter_targets(curr_proc, pid)
-		.inspect(|_| empty = false)
-		.filter_map(|pid| sched.get_by_pid(pid))
-		// Select a waitable process
-		.find(|proc| {
-			let proc = proc.lock();
-			let state = proc.get_state();
-			let stopped = options & WUNTRACED != 0 && matches!(state, State::Stopped);
-			let exited = options & WEXITED != 0 && matches!(state, State::Zombie);
-			let continued =
-				options & WCONTINUED != 0 && matches!(state, State::Running | State::Sleeping);
-			proc.is_waitable() && (stopped || exited || continued)
-		});
-	let Some(proc) = proc else {
+	// Find a waitable process among the children of `parent`
+	let mut find = |parent: &Process| {
+		parent
+			.get_children()
+			.iter()
+			.find(|child| {
+				let child = child.lock();
+				if !is_target(curr_proc, &child, pid, options) {
+					return false;
+				}
+				empty = false;
+				// Select a waitable process
+				let state = child.get_state();
+				let stopped = options & WUNTRACED != 0 && matches!(state, State::Stopped);
+				let exited = options & WEXITED != 0 && matches!(state, State::Zombie);
+				let continued =
+					options & WCONTINUED != 0 && matches!(state, State::Running | State::Sleeping);
+				child.is_waitable() && (stopped || exited || continued)
+			})
+			.cloned()
+	};
+	// The child, along with the thread it is attached to if it is not the current one
+	let mut child = find(curr_proc).map(|child| (child, None));
+	if child.is_none() && options & __WNOTHREAD == 0 {
+		let tid = curr_proc.get_pid();
+		let group = curr_proc.thread_group.clone();
+		let group = group.lock();
+		child = group
+			.get_threads()
+			.iter()
+			.filter(|t| **t != tid)
+			.filter_map(|t| Process::get_by_tid(*t))
+			.find_map(|thread| {
+				let child = find(&thread.lock())?;
+				Some((child, Some(thread)))
+			});
+	}
+	let Some((child_mutex, thread)) = child else {
 		return if empty {
 			// No target
 			Err(errno!(ECHILD))
@@ -126,31 +156,60 @@ fn get_waitable(
 			Ok(None)
 		};
 	};
-	let mut proc = proc.lock();
+	let mut proc = child_mutex.lock();
 	let pid = proc.get_pid();
-	// Write values back
-	wstatus.copy_to_user(get_wstatus(&proc))?;
-	rusage.copy_to_user(proc.get_rusage().clone())?;
+	// Children are always visible from the namespace of their parent. Translation is done before
+	// removing the child, which releases its PIDs
+	let local_pid = curr_proc.pid_to_local(pid).unwrap_or(0);
+	report(&proc)?;
 	// Clear the waitable flag if requested
 	if options & WNOWAIT == 0 {
 		proc.clear_waitable();
 		// If the process was a zombie, remove it
 		if matches!(proc.get_state(), State::Zombie) {
 			drop(proc);
-			curr_proc.remove_child(pid);
-			sched.remove_process(pid);
+			match thread {
+				Some(thread) => thread.lock().remove_child(&child_mutex),
+				None => curr_proc.remove_child(&child_mutex),
+			}
+			SCHEDULER.get().lock().remove_process(pid);
 		}
 	}
-	Ok(Some(pid))
+	Ok(Some(local_pid))
 }
 
-/// Executes the `waitpid` system call.
-pub fn do_waitpid(
+/// Translates the `pid` constraint given to the system call from the namespace of the process
+/// `proc` into the PIDs used by the kernel.
+///
+/// If `pid` designates a process or process group that is not visible, the function returns
+/// [`errno::ECHILD`].
+pub fn pid_to_global(proc: &Process, pid: i32) -> EResult<i32> {
+	let translate = |p: u32| -> EResult<i32> {
+		let p: Pid = p.try_into().map_err(|_| errno!(ECHILD))?;
+		let p = proc.pid_to_global(p).ok_or_else(|| errno!(ECHILD))?;
+		Ok(p as _)
+	};
+	match pid {
+		..-1 => Ok(-translate(pid.unsigned_abs())?),
+		-1 | 0 => Ok(pid),
+		1.. => translate(pid as _),
+	}
+}
+
+/// Waits for a process to change state.
+///
+/// Arguments:
+/// - `pid` is the constraint given to the system call, using PIDs of the root namespace.
+/// - `options` is a set of flags.
+/// - `report` is called with the waited process, to report its state to userspace.
+///
+/// The function returns the PID of the waited process, as seen from the namespace of the current
+/// process. If [`WNOHANG`] is set and no process is waitable, the function returns `None`.
+pub fn do_wait<F: FnMut(&Process) -> EResult<()>>(
 	pid: i32,
-	wstatus: SyscallPtr<i32>,
 	options: i32,
-	rusage: SyscallPtr<RUsage>,
-) -> EResult<usize> {
+	mut report: F,
+) -> EResult<Option<Pid>> {
 	// Sleep until a target process is waitable
 	loop {
 		{
@@ -159,14 +218,14 @@ pub fn do_waitpid(
 			if proc.next_signal(true).is_some() {
 				return Err(errno!(EINTR));
 			}
-			let result = get_waitable(&mut proc, pid, &wstatus, options, &rusage)?;
+			let result = get_waitable(&mut proc, pid, options, &mut report)?;
 			// On success, return
 			if let Some(p) = result {
-				return Ok(p as _);
+				return Ok(Some(p));
 			}
 			// If the flag is set, do not wait
 			if options & WNOHANG != 0 {
-				return Ok(0);
+				return Ok(None);
 			}
 			// When a child process is paused or resumed by a signal or is terminated, it
 			// changes the state of the current process to wake it up
@@ -176,6 +235,24 @@ pub fn do_waitpid(
 	}
 }
 
+/// Executes the `waitpid` system call.
+///
+/// `pid` is relative to the PID namespace of the current process, as is the returned PID.
+pub fn do_waitpid(
+	pid: i32,
+	wstatus: SyscallPtr<i32>,
+	options: i32,
+	rusage: SyscallPtr<RUsage>,
+) -> EResult<usize> {
+	let pid = pid_to_global(&Process::current().lock(), pid)?;
+	let pid = do_wait(pid, options, |proc| {
+		wstatus.copy_to_user(get_wstatus(proc))?;
+		rusage.copy_to_user(proc.get_rusage().clone())?;
+		Ok(())
+	})?;
+	Ok(pid.unwrap_or(0) as _)
+}
+
 pub fn waitpid(
 	Args((pid, wstatus, options)): Args<(c_int, SyscallPtr<c_int>, c_int)>,
 ) -> EResult<usize> {
@@ -20,7 +20,10 @@
 
 use super::Args;
 use crate::{
-	file::{fd::FileDescriptorTable, FileType},
+	file::{
+		fanotify, fanotify::FAN_MODIFY, fd::FileDescriptorTable, perm::AccessProfile, vfs,
+		FileType,
+	},
 	idt,
 	process::{mem_space::copy::SyscallSlice, regs::Regs, scheduler, Process},
 	syscall::Signal,
@@ -39,6 +42,7 @@ use utils::{
 pub fn write(
 	Args((fd, buf, count)): Args<(c_int, SyscallSlice<u8>, usize)>,
 	fds: Arc<Mutex<FileDescriptorTable>>,
+	ap: AccessProfile,
 ) -> EResult<usize> {
 	// Validation
 	let len = min(count, i32::MAX as usize);
@@ -58,5 +62,11 @@ pub fn write(
 	// Update offset
 	let new_off = off.saturating_add(len as u64);
 	file.off.store(new_off, atomic::Ordering::Release);
+	if let Some(ent) = &file.vfs_entry {
+		vfs::content_modified(ent, &ap)?;
+	}
+	Process::current().lock().io.account_write(len);
+	// The data has been written, failing to notify must not fail the system call
+	let _ = fanotify::notify_file(&file, FAN_MODIFY);
 	Ok(len)
 }
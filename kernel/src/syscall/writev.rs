@@ -19,7 +19,7 @@
 //! The `writev` system call allows to write sparse data on a file descriptor.
 
 use crate::{
-	file::{fd::FileDescriptorTable, File, FileType, O_NONBLOCK},
+	file::{fd::FileDescriptorTable, perm::AccessProfile, vfs, File, FileType, O_NONBLOCK},
 	process::{
 		iovec::IOVec,
 		mem_space::{copy::SyscallSlice, MemSpace},
@@ -85,6 +85,7 @@ fn write(
 /// - `iovcnt` the number of entries in the IO vector
 /// - `offset` is the offset in the file
 /// - `flags` is the set of flags
+/// - `ap` is the access profile of the current process
 pub fn do_writev(
 	fd: i32,
 	iov: SyscallSlice<IOVec>,
@@ -92,6 +93,7 @@ pub fn do_writev(
 	offset: Option<isize>,
 	_flags: Option<i32>,
 	fds: Arc<Mutex<FileDescriptorTable>>,
+	ap: AccessProfile,
 ) -> EResult<usize> {
 	// Validation
 	if iovcnt < 0 || iovcnt as usize > IOV_MAX {
@@ -107,12 +109,21 @@ pub fn do_writev(
 	if file.get_type()? == FileType::Link {
 		return Err(errno!(EINVAL));
 	}
-	write(&iov, iovcnt as _, offset, &file)
+	if offset.is_some() {
+		file.check_seekable()?;
+	}
+	let len = write(&iov, iovcnt as _, offset, &file)?;
+	if let Some(ent) = &file.vfs_entry {
+		vfs::content_modified(ent, &ap)?;
+	}
+	Process::current().lock().io.account_write(len);
+	Ok(len)
 }
 
 pub fn writev(
 	Args((fd, iov, iovcnt)): Args<(c_int, SyscallSlice<IOVec>, c_int)>,
 	fds: Arc<Mutex<FileDescriptorTable>>,
+	ap: AccessProfile,
 ) -> EResult<usize> {
-	do_writev(fd, iov, iovcnt, None, None, fds)
+	do_writev(fd, iov, iovcnt, None, None, fds, ap)
 }
@@ -18,9 +18,12 @@
 
 //! This module implements system clocks.
 
-use crate::time::{
-	unit::{ClockIdT, TimeUnit},
-	Timestamp, TimestampScale,
+use crate::{
+	process::exec::vdso,
+	time::{
+		unit::{ClockIdT, TimeUnit},
+		Timestamp, TimestampScale,
+	},
 };
 use core::{cmp::max, sync::atomic};
 use utils::{errno, errno::EResult, lock::atomic::AtomicU64};
@@ -50,8 +53,6 @@ pub const CLOCK_SGI_CYCLE: ClockIdT = 10;
 /// System clock ID
 pub const CLOCK_TAI: ClockIdT = 11;
 
-// TODO allow accessing clocks through an address shared with userspace (vDSO)
-
 /// The current timestamp of the real time clock, in nanoseconds.
 static REALTIME: AtomicU64 = AtomicU64::new(0);
 /// On time adjustment, this value is updated with the previous value of the real time clock so
@@ -59,12 +60,33 @@ static REALTIME: AtomicU64 = AtomicU64::new(0);
 static MONOTONIC: AtomicU64 = AtomicU64::new(0);
 /// The time elapsed since boot time, in nanoseconds.
 static BOOTTIME: AtomicU64 = AtomicU64::new(0);
+/// The number of ticks since boot.
+static JIFFIES: AtomicU64 = AtomicU64::new(0);
+/// The duration of the last tick, in nanoseconds.
+static TICK: AtomicU64 = AtomicU64::new(0);
 
-/// Updates clocks with the given delta value in nanoseconds.
+/// Updates clocks on a tick, with the given delta value in nanoseconds.
+///
+/// The new values are published to the vDSO.
 pub fn update(delta: Timestamp) {
 	REALTIME.fetch_add(delta as _, atomic::Ordering::Relaxed);
 	MONOTONIC.fetch_add(delta as _, atomic::Ordering::Relaxed);
 	BOOTTIME.fetch_add(delta as _, atomic::Ordering::Relaxed);
+	JIFFIES.fetch_add(1, atomic::Ordering::Relaxed);
+	TICK.store(delta as _, atomic::Ordering::Relaxed);
+	vdso::update_clocks();
+}
+
+/// Returns the number of ticks since boot.
+pub fn jiffies() -> u64 {
+	JIFFIES.load(atomic::Ordering::Relaxed)
+}
+
+/// Returns the time elapsed since boot, in nanoseconds.
+///
+/// This is the value of the [`CLOCK_BOOTTIME`] clock, which cannot fail.
+pub fn boottime() -> Timestamp {
+	BOOTTIME.load(atomic::Ordering::Relaxed)
 }
 
 /// Returns the current timestamp according to the clock with the given ID.
@@ -76,9 +98,13 @@ pub fn update(delta: Timestamp) {
 /// If the clock is invalid, the function returns an error.
 pub fn current_time(clk: ClockIdT, scale: TimestampScale) -> EResult<Timestamp> {
 	// TODO implement all clocks
+	// TODO interpolate non-coarse clocks between ticks. For now, all clocks are only updated
+	// on ticks, so that coarse clocks are the same as their precise counterpart
 	let raw_ts = match clk {
-		CLOCK_REALTIME | CLOCK_REALTIME_ALARM => REALTIME.load(atomic::Ordering::Relaxed),
-		CLOCK_MONOTONIC => {
+		CLOCK_REALTIME | CLOCK_REALTIME_COARSE | CLOCK_REALTIME_ALARM => {
+			REALTIME.load(atomic::Ordering::Relaxed)
+		}
+		CLOCK_MONOTONIC | CLOCK_MONOTONIC_RAW | CLOCK_MONOTONIC_COARSE => {
 			let realtime = REALTIME.load(atomic::Ordering::Relaxed);
 			let monotonic = MONOTONIC.load(atomic::Ordering::Relaxed);
 			max(realtime, monotonic)
@@ -94,6 +120,17 @@ pub fn current_time(clk: ClockIdT, scale: TimestampScale) -> EResult<Timestamp>
 	))
 }
 
+/// Returns the resolution of the clock with the given ID, in nanoseconds.
+///
+/// Since clocks are updated on each tick, this is the duration of a tick.
+///
+/// If the clock is invalid, the function returns an error.
+pub fn resolution(clk: ClockIdT) -> EResult<Timestamp> {
+	// Check the clock is valid
+	current_time(clk, TimestampScale::Nanosecond)?;
+	Ok(max(TICK.load(atomic::Ordering::Relaxed), 1))
+}
+
 /// Returns the current timestamp according to the clock with the given ID.
 ///
 /// Arguments:
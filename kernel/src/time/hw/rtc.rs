@@ -20,6 +20,7 @@
 
 use super::HwClock;
 use crate::{idt, io};
+use core::hint;
 use utils::math::rational::Rational;
 
 /// The ID of the port used to select the CMOS register to read.
@@ -27,6 +28,8 @@ const SELECT_PORT: u16 = 0x70;
 /// The ID of the port to read or write a CMOS port previously selected.
 const VALUE_PORT: u16 = 0x71;
 
+/// The ID of the seconds register.
+const SECONDS_REGISTER: u8 = 0x00;
 /// The ID of the status register A.
 const STATUS_A_REGISTER: u8 = 0x0a;
 /// The ID of the status register B.
@@ -36,6 +39,38 @@ const STATUS_C_REGISTER: u8 = 0x0c;
 
 // FIXME prevent having several instances at the same time
 
+/// Status register A flag: an update of the time registers is in progress.
+const STATUS_A_UPDATE_IN_PROGRESS: u8 = 0x80;
+
+/// Busy-waits for `secs` seconds by polling the clock, without relying on interrupts.
+///
+/// This is meant to be used when interrupts cannot be handled anymore, such as after a kernel
+/// panic. The first second may be shorter, since waiting starts between two ticks.
+pub fn wait_seconds(secs: u32) {
+	let read_seconds = || unsafe {
+		// Wait for the end of the update to read a consistent value
+		loop {
+			io::outb(SELECT_PORT, STATUS_A_REGISTER | 0x80);
+			if io::inb(VALUE_PORT) & STATUS_A_UPDATE_IN_PROGRESS == 0 {
+				break;
+			}
+			hint::spin_loop();
+		}
+		io::outb(SELECT_PORT, SECONDS_REGISTER | 0x80);
+		io::inb(VALUE_PORT)
+	};
+	let mut last = read_seconds();
+	let mut elapsed = 0;
+	while elapsed < secs {
+		let cur = read_seconds();
+		if cur != last {
+			last = cur;
+			elapsed += 1;
+		}
+		hint::spin_loop();
+	}
+}
+
 /// The RTC.
 ///
 /// **Note**: the RTC needs a call to `reset` to allow the next tick to be fired.
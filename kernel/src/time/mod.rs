@@ -26,10 +26,11 @@
 
 pub mod clock;
 pub mod hw;
+pub mod namespace;
 pub mod timer;
 pub mod unit;
 
-use crate::{event, event::CallbackResult};
+use crate::{event, event::CallbackResult, file::timerfd, workqueue};
 use core::mem::ManuallyDrop;
 use unit::{Timestamp, TimestampScale};
 use utils::{boxed::Box, errno::EResult, math::rational::Rational};
@@ -58,6 +59,8 @@ pub(crate) fn init() -> EResult<()> {
 			// FIXME: the value is probably not right
 			clock::update(i64::from(freq * 1_000_000_000) as _);
 			timer::tick();
+			timerfd::tick();
+			workqueue::tick();
 
 			CallbackResult::Continue
 		})?;
@@ -0,0 +1,144 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Time namespaces allow to apply offsets to the monotonic and boot time clocks, as seen by the
+//! processes of the namespace.
+//!
+//! This is used by containers to virtualize these clocks, for example to keep them consistent
+//! when the container is restored on another host, without affecting the rest of the system.
+//!
+//! A process creates a new namespace with `unshare`, which applies to its children only. The
+//! offsets of the namespace may be set through `/proc/[pid]/timens_offsets` until a process
+//! enters it.
+//!
+//! The root namespace is represented by `None` where a namespace is expected. It has no offsets.
+
+use crate::time::{
+	clock,
+	clock::{
+		CLOCK_BOOTTIME, CLOCK_BOOTTIME_ALARM, CLOCK_MONOTONIC, CLOCK_MONOTONIC_COARSE,
+		CLOCK_MONOTONIC_RAW,
+	},
+	unit::{ClockIdT, Timestamp, TimestampScale},
+};
+use utils::{
+	errno,
+	errno::{AllocResult, EResult},
+	lock::Mutex,
+	ptr::arc::Arc,
+};
+
+/// The offsets applied to the clocks of a time namespace, in nanoseconds.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Offsets {
+	/// The offset of [`CLOCK_MONOTONIC`] and its variants.
+	pub monotonic: i64,
+	/// The offset of [`CLOCK_BOOTTIME`] and its variants.
+	pub boottime: i64,
+}
+
+impl Offsets {
+	/// Returns the offset applied to the clock `clk`.
+	///
+	/// Clocks that are not affected by time namespaces have a zero offset.
+	pub fn get(&self, clk: ClockIdT) -> i64 {
+		match clk {
+			CLOCK_MONOTONIC | CLOCK_MONOTONIC_RAW | CLOCK_MONOTONIC_COARSE => self.monotonic,
+			CLOCK_BOOTTIME | CLOCK_BOOTTIME_ALARM => self.boottime,
+			_ => 0,
+		}
+	}
+}
+
+/// The inner state of a [`TimeNamespace`].
+#[derive(Debug)]
+struct Inner {
+	/// The offsets of the namespace's clocks.
+	offsets: Offsets,
+	/// Tells whether a process has entered the namespace, after which offsets cannot change.
+	entered: bool,
+}
+
+/// A time namespace, other than the root one.
+#[derive(Debug)]
+pub struct TimeNamespace(Mutex<Inner>);
+
+impl TimeNamespace {
+	/// Creates a new namespace, with the same offsets as `parent`.
+	pub fn new(parent: Option<&TimeNamespace>) -> AllocResult<Arc<Self>> {
+		Arc::new(Self(Mutex::new(Inner {
+			offsets: offsets(parent),
+			entered: false,
+		})))
+	}
+
+	/// Returns the offsets of the namespace.
+	pub fn get_offsets(&self) -> Offsets {
+		self.0.lock().offsets
+	}
+
+	/// Sets the offset of the clock `clk` to `offset` nanoseconds.
+	///
+	/// Only [`CLOCK_MONOTONIC`] and [`CLOCK_BOOTTIME`] can be set, otherwise the function returns
+	/// [`errno::EINVAL`].
+	///
+	/// If a process has already entered the namespace, the function returns [`errno::EACCES`].
+	///
+	/// If the clock, as seen from the namespace, would be negative, the function returns
+	/// [`errno::ERANGE`].
+	pub fn set_offset(&self, clk: ClockIdT, offset: i64) -> EResult<()> {
+		if !matches!(clk, CLOCK_MONOTONIC | CLOCK_BOOTTIME) {
+			return Err(errno!(EINVAL));
+		}
+		let now = clock::current_time(clk, TimestampScale::Nanosecond)?;
+		if !matches!((now as i64).checked_add(offset), Some(ts) if ts >= 0) {
+			return Err(errno!(ERANGE));
+		}
+		let mut inner = self.0.lock();
+		if inner.entered {
+			return Err(errno!(EACCES));
+		}
+		match clk {
+			CLOCK_MONOTONIC => inner.offsets.monotonic = offset,
+			_ => inner.offsets.boottime = offset,
+		}
+		Ok(())
+	}
+
+	/// Records that a process entered the namespace, freezing its offsets.
+	pub fn enter(&self) {
+		self.0.lock().entered = true;
+	}
+}
+
+/// Returns the offsets of the namespace `ns`.
+pub fn offsets(ns: Option<&TimeNamespace>) -> Offsets {
+	ns.map(TimeNamespace::get_offsets).unwrap_or_default()
+}
+
+/// Converts the timestamp `ts` of the clock `clk` into the same instant, as seen from the
+/// namespace `ns`.
+pub fn to_local(ns: Option<&TimeNamespace>, clk: ClockIdT, ts: Timestamp) -> Timestamp {
+	ts.saturating_add_signed(offsets(ns).get(clk))
+}
+
+/// Converts the timestamp `ts` of the clock `clk`, as seen from the namespace `ns`, into the same
+/// instant as seen by the kernel.
+pub fn to_host(ns: Option<&TimeNamespace>, clk: ClockIdT, ts: Timestamp) -> Timestamp {
+	ts.saturating_add_signed(offsets(ns).get(clk).saturating_neg())
+}
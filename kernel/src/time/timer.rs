@@ -19,17 +19,15 @@
 //! This module implements timers.
 
 use super::{
-	clock,
-	unit::{ClockIdT, ITimerspec32, TimeUnit, TimerT, Timespec, TimestampScale},
+	clock, namespace,
+	namespace::TimeNamespace,
+	unit::{ClockIdT, ITimerspec64, TimeUnit, TimerT, Timespec, Timespec64, TimestampScale},
 };
-use crate::{
-	process::{
-		oom,
-		pid::Pid,
-		signal::{SigEvent, Signal, SIGEV_SIGNAL, SIGEV_THREAD},
-		Process,
-	},
-	time::unit::Timespec32,
+use crate::process::{
+	oom,
+	pid::Pid,
+	signal::{SigEvent, Signal, SIGEV_SIGNAL},
+	Process,
 };
 use utils::{
 	collections::{btreemap::BTreeMap, hashmap::HashMap, id_allocator::IDAllocator},
@@ -50,7 +48,7 @@ pub struct Timer {
 	sevp: SigEvent,
 
 	/// The timer's interval between firing.
-	interval: Timespec32,
+	interval: Timespec,
 	/// The next timestamp at which the timer will expire. If `None`, the timer is unarmed.
 	next: Option<Timespec>,
 }
@@ -99,58 +97,57 @@ impl Timer {
 
 	/// Returns the current state of the timer.
 	#[inline]
-	pub fn get_time(&self) -> ITimerspec32 {
+	pub fn get_time(&self) -> ITimerspec64 {
 		let ts: Timespec = clock::current_time_struct(self.clockid).unwrap();
-		let value = self.next.map(|next| next - ts).unwrap_or_default();
-
-		ITimerspec32 {
-			it_interval: self.interval,
-			it_value: Timespec32 {
-				tv_nsec: value.tv_nsec as _,
-				tv_sec: value.tv_sec as _,
-			},
-		}
-	}
-
-	/// Computes the timestamp at which the timer will fire next.
-	///
-	/// Arguments:
-	/// - `spec` is the timer's setting.
-	/// - `ts` is the current timestamp.
-	fn compute_next(spec: &ITimerspec32, ts: Timespec32) -> Timespec {
-		let time = if spec.it_value.is_zero() {
-			spec.it_interval
-		} else {
-			ts + spec.it_value
-		};
-
-		Timespec {
-			tv_nsec: time.tv_nsec as _,
-			tv_sec: time.tv_sec as _,
+		let value = self
+			.next
+			.map(|next| next.to_nano().saturating_sub(ts.to_nano()))
+			.unwrap_or(0);
+		ITimerspec64 {
+			it_interval: Timespec64::from_nano(self.interval.to_nano()),
+			it_value: Timespec64::from_nano(value),
 		}
 	}
 
 	/// Sets the timer's state.
 	///
 	/// Arguments:
-	/// - `spec` is the new setting of the timer.
+	/// - `spec` is the new setting of the timer. If its value is zero, the timer is disarmed.
+	/// - `abs` tells whether the value in `spec` is an absolute timestamp instead of a duration
+	///   relative to the current time.
+	/// - `ns` is the time namespace in which an absolute timestamp is expressed.
 	/// - `pid` is the PID of the process associated with the timer.
 	/// - `timer_id` is the ID of the timer.
 	///
 	/// On allocation error, the function returns an error.
 	#[inline]
-	pub fn set_time(&mut self, spec: ITimerspec32, pid: Pid, timer_id: TimerT) -> EResult<()> {
+	pub fn set_time(
+		&mut self,
+		spec: ITimerspec64,
+		abs: bool,
+		ns: Option<&TimeNamespace>,
+		pid: Pid,
+		timer_id: TimerT,
+	) -> EResult<()> {
 		let mut queue = TIMERS_QUEUE.lock();
-		if let Some(next) = self.next {
+		if let Some(next) = self.next.take() {
 			queue.remove(&(next, pid, timer_id));
 		}
+		self.interval = Timespec::from_nano(spec.it_interval.to_nano());
+		if spec.it_value.is_zero() {
+			return Ok(());
+		}
 
-		let ts: Timespec32 = clock::current_time_struct(self.clockid).unwrap();
-		let next = Self::compute_next(&spec, ts);
-		self.interval = spec.it_interval;
-		self.next = Some(next);
-
+		let ts: Timespec = clock::current_time_struct(self.clockid).unwrap();
+		let value = if abs {
+			let value = namespace::to_host(ns, self.clockid, spec.it_value.to_nano());
+			Timespec64::from_nano(value)
+		} else {
+			spec.it_value
+		};
+		let next = compute_next(&value, abs, &ts);
 		queue.insert((next, pid, timer_id), ())?;
+		self.next = Some(next);
 		Ok(())
 	}
 
@@ -158,17 +155,15 @@ impl Timer {
 	///
 	/// `proc` is the process to which the timer is fired.
 	pub fn fire(&mut self, proc: &mut Process) {
-		match self.sevp.sigev_notify {
-			SIGEV_SIGNAL => {
-				let Ok(signal) = Signal::try_from(self.sevp.sigev_signo) else {
-					return;
-				};
-				// TODO on sigint_t, set si_code to SI_TIMER
-				proc.kill(signal);
-			}
-			SIGEV_THREAD => todo!(), // TODO
-			_ => {}
+		// `SIGEV_THREAD` is implemented by the C library, which uses a signal under the hood
+		if self.sevp.sigev_notify != SIGEV_SIGNAL {
+			return;
 		}
+		let Ok(signal) = Signal::try_from(self.sevp.sigev_signo) else {
+			return;
+		};
+		// TODO on sigint_t, set si_code to SI_TIMER
+		proc.kill(signal);
 	}
 
 	/// Resets the timer to be fired again.
@@ -187,27 +182,15 @@ impl Timer {
 		pid: Pid,
 		timer_id: TimerT,
 	) -> AllocResult<()> {
-		if let Some(next) = self.next {
+		if let Some(next) = self.next.take() {
 			queue.remove(&(next, pid, timer_id));
 		}
-
 		if self.interval.is_zero() {
-			self.next = None;
 			return Ok(());
 		}
 
-		let next = Self::compute_next(
-			&ITimerspec32 {
-				it_interval: self.interval,
-				it_value: self.interval,
-			},
-			Timespec32 {
-				tv_nsec: ts.tv_nsec as _,
-				tv_sec: ts.tv_sec as _,
-			},
-		);
+		let next = compute_next(&self.interval, false, &ts);
 		queue.insert((next, pid, timer_id), ())?;
-
 		self.next = Some(next);
 
 		Ok(())
@@ -261,13 +244,18 @@ impl TimerManager {
 		self.timers.get_mut(&(id as _))
 	}
 
-	/// Deletes the timer with the given ID.
+	/// Deletes the timer with the given ID, disarming it.
 	///
 	/// If the timer doesn't exist, the function returns an error.
 	pub fn delete_timer(&mut self, id: TimerT) -> EResult<()> {
-		self.timers
+		let timer = self
+			.timers
 			.remove(&(id as _))
 			.ok_or_else(|| errno!(EINVAL))?;
+		if let Some(next) = timer.next {
+			TIMERS_QUEUE.lock().remove(&(next, self.pid, id));
+		}
+		self.id_allocator.free(id as _);
 		Ok(())
 	}
 }
@@ -279,6 +267,21 @@ impl Drop for TimerManager {
 	}
 }
 
+/// Computes the timestamp at which a timer expires.
+///
+/// Arguments:
+/// - `value` is the duration until expiration, or the absolute timestamp of expiration if `abs` is
+///   set.
+/// - `abs` tells whether `value` is absolute.
+/// - `ts` is the current timestamp.
+pub fn compute_next<T: TimeUnit>(value: &T, abs: bool, ts: &Timespec) -> Timespec {
+	if abs {
+		Timespec::from_nano(value.to_nano())
+	} else {
+		Timespec::from_nano(ts.to_nano().saturating_add(value.to_nano()))
+	}
+}
+
 /// The queue of timers to be fired next.
 ///
 /// The key has the following elements:
@@ -305,7 +308,7 @@ pub(super) fn tick() {
 		let Some(proc_mutex) = Process::get_by_pid(pid) else {
 			// invalid timer, remove
 			queue.pop_first();
-			break;
+			continue;
 		};
 		let mut proc = proc_mutex.lock();
 		// Get timer manager
@@ -316,7 +319,7 @@ pub(super) fn tick() {
 		let Some(timer) = timer_manager.get_timer_mut(timer_id) else {
 			// invalid timer, remove
 			queue.pop_first();
-			break;
+			continue;
 		};
 
 		// Get current time
@@ -335,11 +338,7 @@ pub(super) fn tick() {
 		}
 
 		timer.fire(&mut proc);
-
-		if timer.is_oneshot() {
-			queue.pop_first();
-		} else {
-			oom::wrap(|| timer.reset(&mut queue, ts, pid, timer_id));
-		}
+		// Rearm the timer if periodic, or disarm it
+		oom::wrap(|| timer.reset(&mut queue, ts, pid, timer_id));
 	}
 }
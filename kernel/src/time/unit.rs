@@ -158,6 +158,74 @@ impl PartialOrd for Timeval {
 	}
 }
 
+/// Same as `Timeval`, but with 32 bits values.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[repr(C)]
+pub struct Timeval32 {
+	/// Seconds
+	pub tv_sec: u32,
+	/// Microseconds
+	pub tv_usec: u32,
+}
+
+impl TimeUnit for Timeval32 {
+	fn from_nano(timestamp: u64) -> Self {
+		let sec = timestamp / 1000000000;
+		let usec = (timestamp % 1000000000) / 1000;
+
+		Self {
+			tv_sec: sec as _,
+			tv_usec: usec as _,
+		}
+	}
+
+	fn to_nano(&self) -> u64 {
+		(self.tv_sec as u64)
+			.wrapping_mul(1000000000)
+			.wrapping_add((self.tv_usec as u64).wrapping_mul(1000))
+	}
+
+	fn is_zero(&self) -> bool {
+		self.tv_sec == 0 && self.tv_usec == 0
+	}
+}
+
+impl Add<Timeval32> for Timeval32 {
+	type Output = Self;
+
+	fn add(self, rhs: Self) -> Self {
+		Self {
+			tv_sec: self.tv_sec + rhs.tv_sec,
+			tv_usec: self.tv_usec + rhs.tv_usec,
+		}
+	}
+}
+
+impl Sub<Timeval32> for Timeval32 {
+	type Output = Self;
+
+	fn sub(self, rhs: Self) -> Self {
+		Self {
+			tv_sec: self.tv_sec - rhs.tv_sec,
+			tv_usec: self.tv_usec - rhs.tv_usec,
+		}
+	}
+}
+
+impl Ord for Timeval32 {
+	fn cmp(&self, other: &Self) -> Ordering {
+		self.tv_sec
+			.cmp(&other.tv_sec)
+			.then_with(|| self.tv_usec.cmp(&other.tv_usec))
+	}
+}
+
+impl PartialOrd for Timeval32 {
+	fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+		Some(self.cmp(other))
+	}
+}
+
 /// Same as `Timeval`, but with nanosecond precision.
 #[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
 #[repr(C)]
@@ -303,3 +371,118 @@ pub struct ITimerspec32 {
 	/// Start value of the timer.
 	pub it_value: Timespec32,
 }
+
+impl ITimerspec32 {
+	/// Tells whether the structure is valid, i.e. whether both nanosecond fields are in range.
+	pub fn is_valid(&self) -> bool {
+		self.it_interval.tv_nsec < 1_000_000_000 && self.it_value.tv_nsec < 1_000_000_000
+	}
+}
+
+/// Same as `Timespec`, with the layout used by the 64 bits time system calls on 32 bits
+/// architectures.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[repr(C)]
+pub struct Timespec64 {
+	/// Seconds
+	pub tv_sec: i64,
+	/// Nanoseconds
+	pub tv_nsec: i64,
+}
+
+impl Timespec64 {
+	/// Tells whether the structure is valid, i.e. whether both fields are in range.
+	pub fn is_valid(&self) -> bool {
+		self.tv_sec >= 0 && (0..1_000_000_000).contains(&self.tv_nsec)
+	}
+}
+
+impl TimeUnit for Timespec64 {
+	fn from_nano(timestamp: u64) -> Self {
+		Self {
+			tv_sec: (timestamp / 1_000_000_000) as _,
+			tv_nsec: (timestamp % 1_000_000_000) as _,
+		}
+	}
+
+	fn to_nano(&self) -> u64 {
+		(self.tv_sec as u64)
+			.wrapping_mul(1_000_000_000)
+			.wrapping_add(self.tv_nsec as u64)
+	}
+
+	fn is_zero(&self) -> bool {
+		self.tv_sec == 0 && self.tv_nsec == 0
+	}
+}
+
+impl Add<Timespec64> for Timespec64 {
+	type Output = Self;
+
+	fn add(self, rhs: Self) -> Self {
+		Self {
+			tv_sec: self.tv_sec + rhs.tv_sec,
+			tv_nsec: self.tv_nsec + rhs.tv_nsec,
+		}
+	}
+}
+
+impl Sub<Timespec64> for Timespec64 {
+	type Output = Self;
+
+	fn sub(self, rhs: Self) -> Self {
+		Self {
+			tv_sec: self.tv_sec - rhs.tv_sec,
+			tv_nsec: self.tv_nsec - rhs.tv_nsec,
+		}
+	}
+}
+
+impl Ord for Timespec64 {
+	fn cmp(&self, other: &Self) -> Ordering {
+		self.tv_sec
+			.cmp(&other.tv_sec)
+			.then_with(|| self.tv_nsec.cmp(&other.tv_nsec))
+	}
+}
+
+impl PartialOrd for Timespec64 {
+	fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+		Some(self.cmp(other))
+	}
+}
+
+/// Same as [`ITimerspec32`], with 64 bits values.
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C)]
+pub struct ITimerspec64 {
+	/// The interval between each firing of the timer.
+	pub it_interval: Timespec64,
+	/// Start value of the timer.
+	pub it_value: Timespec64,
+}
+
+impl ITimerspec64 {
+	/// Tells whether the structure is valid, i.e. whether all fields are in range.
+	pub fn is_valid(&self) -> bool {
+		self.it_interval.is_valid() && self.it_value.is_valid()
+	}
+}
+
+impl From<ITimerspec32> for ITimerspec64 {
+	fn from(spec: ITimerspec32) -> Self {
+		Self {
+			it_interval: Timespec64::from_nano(spec.it_interval.to_nano()),
+			it_value: Timespec64::from_nano(spec.it_value.to_nano()),
+		}
+	}
+}
+
+impl From<ITimerspec64> for ITimerspec32 {
+	fn from(spec: ITimerspec64) -> Self {
+		Self {
+			it_interval: Timespec32::from_nano(spec.it_interval.to_nano()),
+			it_value: Timespec32::from_nano(spec.it_value.to_nano()),
+		}
+	}
+}
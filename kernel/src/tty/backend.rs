@@ -0,0 +1,55 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! A console backend displays the content of the TTY on a screen.
+//!
+//! The TTY keeps track of the lines that changed since the last update, so that backends only
+//! have to draw those, and scrolls the screen instead of redrawing it when possible. Backends
+//! which need to transmit their content to the display device can batch the changes of an update
+//! in [`ConsoleBackend::flush`].
+//!
+//! Cells are represented in the VGA text mode format, which backends that are not VGA text based
+//! have to convert.
+
+use crate::tty::vga;
+
+/// A screen on which the TTY is displayed.
+///
+/// Positions are expressed in cells, relative to the top-left corner of the screen. The screen is
+/// always [`vga::WIDTH`] by [`vga::HEIGHT`] cells.
+pub trait ConsoleBackend: Sync {
+	/// Draws the character `c`, with its attributes, on the cell at `x`/`y`.
+	fn put_glyph(&self, x: vga::Pos, y: vga::Pos, c: vga::Char);
+
+	/// Scrolls the content of the screen up by `n` lines. If `n` is negative, the content is
+	/// scrolled down.
+	///
+	/// The content of the lines uncovered by the operation is undefined, since they are redrawn
+	/// afterward.
+	fn scroll(&self, n: vga::Pos);
+
+	/// Moves the cursor to the cell at `x`/`y`.
+	///
+	/// If `None`, the cursor is hidden.
+	fn set_cursor(&self, pos: Option<(vga::Pos, vga::Pos)>);
+
+	/// Makes the changes of the current update visible.
+	///
+	/// The default implementation does nothing, for backends which draw directly on the screen.
+	fn flush(&self) {}
+}
@@ -25,21 +25,29 @@
 //! because at the time of creation, memory management isn't initialized yet.
 
 mod ansi;
+pub mod backend;
 pub mod termios;
 pub mod vga;
 
 use crate::{
-	device::serial,
+	device::console,
 	file::wait_queue::WaitQueue,
-	memory::vmem,
 	process::{pid::Pid, signal::Signal, Process},
 	tty::{
 		ansi::ANSIBuffer,
+		backend::ConsoleBackend,
 		termios::{consts::*, Termios},
 	},
 };
-use core::{cmp::min, ptr};
-use utils::{errno::EResult, lock::Mutex};
+use core::{
+	cmp::{max, min},
+	ops::Range,
+};
+use utils::{
+	errno,
+	errno::{AllocResult, EResult},
+	lock::Mutex,
+};
 
 /// The number of history lines for one TTY.
 const HISTORY_LINES: vga::Pos = 128;
@@ -117,8 +125,15 @@ pub struct TTYDisplay {
 	screen_y: vga::Pos,
 	/// The content of the TTY's history
 	history: [vga::Char; HISTORY_SIZE],
-	/// Tells whether TTY updates are enabled or not
-	update: bool,
+
+	/// The backend on which the TTY is displayed.
+	backend: &'static dyn ConsoleBackend,
+	/// The Y position of the screen in the history, as currently displayed by the backend
+	shown_y: vga::Pos,
+	/// The range of lines of the history modified since the last update
+	dirty: Range<vga::Pos>,
+	/// The position of the cursor on screen, as currently displayed by the backend
+	shown_cursor: Option<(vga::Pos, vga::Pos)>,
 
 	/// Terminal I/O settings.
 	termios: Termios,
@@ -137,37 +152,109 @@ pub struct TTYDisplay {
 }
 
 impl TTYDisplay {
+	/// Creates a new instance, displayed on `backend`.
+	const fn new(backend: &'static dyn ConsoleBackend) -> Self {
+		Self {
+			cursor_x: 0,
+			cursor_y: 0,
+
+			screen_y: 0,
+			history: [EMPTY_CHAR; HISTORY_SIZE],
+
+			backend,
+			shown_y: 0,
+			dirty: 0..0,
+			shown_cursor: None,
+
+			termios: Termios::new(),
+			winsize: WinSize {
+				ws_row: vga::HEIGHT as _,
+				ws_col: vga::WIDTH as _,
+				ws_xpixel: vga::PIXEL_WIDTH as _,
+				ws_ypixel: vga::PIXEL_HEIGHT as _,
+			},
+			ansi_buffer: ANSIBuffer::new(),
+
+			pgrp: 0,
+
+			cursor_visible: true,
+			current_color: vga::DEFAULT_COLOR,
+		}
+	}
+
+	/// Marks the lines of the history in the range `begin..end` as modified, so that they are
+	/// redrawn on the next update.
+	fn mark_dirty(&mut self, begin: vga::Pos, end: vga::Pos) {
+		if self.dirty.is_empty() {
+			self.dirty = begin..end;
+		} else {
+			self.dirty = min(self.dirty.start, begin)..max(self.dirty.end, end);
+		}
+	}
+
+	/// Moves the cursor on screen, if it changed since the last update.
+	///
+	/// If `force` is set, the cursor is moved even if it did not change.
+	fn update_cursor(&mut self, force: bool) {
+		let cursor = self
+			.cursor_visible
+			.then_some((self.cursor_x, self.cursor_y - self.screen_y));
+		if force || cursor != self.shown_cursor {
+			self.backend.set_cursor(cursor);
+			self.shown_cursor = cursor;
+		}
+	}
+
 	/// Updates the TTY to the screen.
+	///
+	/// Only the lines modified since the last update are redrawn. If the screen moved in the
+	/// history, its content is scrolled instead of being redrawn when possible.
 	pub fn update(&mut self) {
-		let buff = &self.history[get_history_offset(0, self.screen_y)];
-		unsafe {
-			vmem::write_ro(|| {
-				ptr::copy_nonoverlapping(
-					buff as *const vga::Char,
-					vga::get_buffer_virt() as *mut vga::Char,
-					(vga::WIDTH as usize) * (vga::HEIGHT as usize),
-				);
-			});
+		let screen_end = self.screen_y + vga::HEIGHT;
+		let scroll = self.screen_y - self.shown_y;
+		if scroll.abs() >= vga::HEIGHT {
+			self.mark_dirty(self.screen_y, screen_end);
+		} else if scroll > 0 {
+			self.backend.scroll(scroll);
+			self.mark_dirty(screen_end - scroll, screen_end);
+		} else if scroll < 0 {
+			self.backend.scroll(scroll);
+			self.mark_dirty(self.screen_y, self.screen_y - scroll);
 		}
-
-		let y = self.cursor_y - self.screen_y;
-		vga::move_cursor(self.cursor_x, y);
+		self.shown_y = self.screen_y;
+		// Redraw modified lines that are on screen
+		let begin = max(self.dirty.start, self.screen_y);
+		let end = min(self.dirty.end, screen_end);
+		for y in begin..end {
+			let off = get_history_offset(0, y);
+			let line = &self.history[off..(off + vga::WIDTH as usize)];
+			for (x, c) in line.iter().enumerate() {
+				self.backend.put_glyph(x as _, y - self.screen_y, *c);
+			}
+		}
+		self.dirty = 0..0;
+		self.update_cursor(false);
+		self.backend.flush();
 	}
 
 	/// Shows the TTY on screen.
 	pub fn show(&mut self) {
-		self.set_cursor_visible(self.cursor_visible);
+		self.mark_dirty(self.screen_y, self.screen_y + vga::HEIGHT);
+		self.update_cursor(true);
 		self.update();
 	}
 
+	/// Sets the backend on which the TTY is displayed, then redraws it.
+	pub fn set_backend(&mut self, backend: &'static dyn ConsoleBackend) {
+		self.backend = backend;
+		self.show();
+	}
+
 	/// Hides or shows the cursor on screen.
+	///
+	/// The change takes effect on the next update.
 	pub fn set_cursor_visible(&mut self, visible: bool) {
 		self.cursor_visible = visible;
-		if visible {
-			vga::enable_cursor();
-		} else {
-			vga::disable_cursor();
-		}
 	}
 
 	/// Reinitializes TTY's current attributes.
@@ -221,9 +308,8 @@ impl TTYDisplay {
 		self.cursor_x = 0;
 		self.cursor_y = 0;
 		self.screen_y = 0;
-		for i in 0..self.history.len() {
-			self.history[i] = (vga::DEFAULT_COLOR as vga::Char) << 8;
-		}
+		self.history.fill(EMPTY_CHAR);
+		self.mark_dirty(0, HISTORY_LINES);
 		self.update();
 	}
 
@@ -262,14 +348,18 @@ impl TTYDisplay {
 		}
 
 		if self.screen_y + vga::HEIGHT > HISTORY_LINES {
-			let diff = ((self.screen_y + vga::HEIGHT - HISTORY_LINES) * vga::WIDTH) as usize;
+			// Drop the oldest lines of the history
+			let lines = self.screen_y + vga::HEIGHT - HISTORY_LINES;
+			let diff = (lines * vga::WIDTH) as usize;
+			self.history.copy_within(diff.., 0);
 			let size = self.history.len() - diff;
-			for i in 0..size {
-				self.history[i] = self.history[diff + i];
-			}
-			for i in size..self.history.len() {
-				self.history[i] = (vga::DEFAULT_COLOR as vga::Char) << 8;
+			self.history[size..].fill(EMPTY_CHAR);
+			// Lines already on screen did not change, only their position in the history
+			self.shown_y -= lines;
+			if !self.dirty.is_empty() {
+				self.dirty = max(self.dirty.start - lines, 0)..max(self.dirty.end - lines, 0);
 			}
+			self.mark_dirty(HISTORY_LINES - lines, HISTORY_LINES);
 
 			self.screen_y = HISTORY_LINES - vga::HEIGHT;
 		}
@@ -332,6 +422,7 @@ impl TTYDisplay {
 				let tty_char = (c as vga::Char) | ((self.current_color as vga::Char) << 8);
 				let pos = get_history_offset(self.cursor_x, self.cursor_y);
 				self.history[pos] = tty_char;
+				self.mark_dirty(self.cursor_y, self.cursor_y + 1);
 				self.cursor_forward(1, 0);
 			}
 		}
@@ -339,9 +430,6 @@ impl TTYDisplay {
 
 	/// Writes string `buffer` to TTY.
 	pub fn write(&mut self, buffer: &[u8]) {
-		// TODO Add a compilation and/or runtime option for this
-		serial::PORTS[0].lock().write(buffer);
-
 		let mut i = 0;
 		while i < buffer.len() {
 			let c = buffer[i];
@@ -430,28 +518,7 @@ pub struct TTY {
 
 /// The TTY.
 pub static TTY: TTY = TTY {
-	display: Mutex::new(TTYDisplay {
-		cursor_x: 0,
-		cursor_y: 0,
-
-		screen_y: 0,
-		history: [(vga::DEFAULT_COLOR as vga::Char) << 8; HISTORY_SIZE],
-		update: true,
-
-		termios: Termios::new(),
-		winsize: WinSize {
-			ws_row: vga::HEIGHT as _,
-			ws_col: vga::WIDTH as _,
-			ws_xpixel: vga::PIXEL_WIDTH as _,
-			ws_ypixel: vga::PIXEL_HEIGHT as _,
-		},
-		ansi_buffer: ANSIBuffer::new(),
-
-		pgrp: 0,
-
-		cursor_visible: true,
-		current_color: vga::DEFAULT_COLOR,
-	}),
+	display: Mutex::new(TTYDisplay::new(&vga::VgaText)),
 	input: Mutex::new(TTYInput {
 		buf: [0; INPUT_MAX],
 		input_size: 0,
@@ -464,8 +531,11 @@ impl TTY {
 	// TODO Implement IUTF8
 	/// Reads inputs from the TTY and places it into the buffer `buf`.
 	///
+	/// If not enough data is available, the process sleeps until input is received, unless
+	/// `nonblock` is set, in which case the function returns [`errno::EAGAIN`].
+	///
 	/// The function returns the number of bytes read.
-	pub fn read(&self, buf: &mut [u8]) -> EResult<usize> {
+	pub fn read(&self, buf: &mut [u8], nonblock: bool) -> EResult<usize> {
 		self.rd_queue.wait_until(|| {
 			let termios = self.display.lock().get_termios().clone();
 			let mut input = self.input.lock();
@@ -478,7 +548,7 @@ impl TTY {
 			};
 			// If not enough data is available, wait
 			if input.available_size < min_chars {
-				return None;
+				return nonblock.then(|| Err(errno!(EAGAIN)));
 			}
 			let mut len = min(buf.len(), input.available_size);
 			if canon {
@@ -489,7 +559,7 @@ impl TTY {
 					input.buf.rotate_left(1);
 					input.input_size -= 1;
 					input.available_size -= 1;
-					return Some(0);
+					return Some(Ok(0));
 				}
 				if let Some(eof_off) = eof_off {
 					// Making the next call EOF
@@ -509,8 +579,18 @@ impl TTY {
 			if termios.c_iflag & IMAXBEL != 0 && input.input_size >= buf.len() {
 				ring_bell();
 			}
-			Some(len)
-		})
+			Some(Ok(len))
+		})?
+	}
+
+	/// Returns the number of bytes available to be read from the TTY.
+	pub fn get_available_size(&self) -> usize {
+		self.input.lock().available_size
+	}
+
+	/// Registers the current process to be woken up when input becomes available, for polling.
+	pub fn poll_wait(&self) -> AllocResult<()> {
+		self.rd_queue.register()
 	}
 
 	/// Tells whether the TTY has any data available to be read.
@@ -541,6 +621,7 @@ impl TTY {
 		if termios.c_lflag & ECHO != 0 {
 			// Write onto the TTY
 			self.display.lock().write(buffer);
+			console::echo(buffer);
 		}
 		// TODO If ECHO is disabled but ICANON and ECHONL are set, print newlines
 
@@ -621,7 +702,9 @@ impl TTY {
 					&& termios.c_lflag & ECHOCTL != 0
 					&& *b >= 1 && *b < 32
 				{
-					self.display.lock().write(&[b'^', b + b'A']);
+					let ctl = [b'^', b + b'A'];
+					self.display.lock().write(&ctl);
+					console::echo(&ctl);
 				}
 
 				// TODO Handle every special characters
@@ -655,6 +738,9 @@ impl TTY {
 				disp.cursor_backward(count, 0);
 				let begin = get_history_offset(disp.cursor_x, disp.cursor_y);
 				disp.history[begin..(begin + count)].fill(EMPTY_CHAR);
+				let end = (begin + count).div_ceil(vga::WIDTH as usize) as vga::Pos;
+				let cursor_y = disp.cursor_y;
+				disp.mark_dirty(cursor_y, end);
 				disp.update();
 			}
 
@@ -669,3 +755,78 @@ impl TTY {
 		self.rd_queue.wake_next();
 	}
 }
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use core::sync::atomic::{
+		AtomicI16, AtomicUsize,
+		Ordering::{Relaxed, SeqCst},
+	};
+
+	/// Backend recording the operations performed on it.
+	struct CountingBackend {
+		/// The number of drawn glyphs.
+		glyphs: AtomicUsize,
+		/// The number of lines scrolled.
+		scrolled: AtomicI16,
+		/// The number of cursor updates.
+		cursor: AtomicUsize,
+	}
+
+	impl ConsoleBackend for CountingBackend {
+		fn put_glyph(&self, _x: vga::Pos, _y: vga::Pos, _c: vga::Char) {
+			self.glyphs.fetch_add(1, Relaxed);
+		}
+
+		fn scroll(&self, n: vga::Pos) {
+			self.scrolled.fetch_add(n, Relaxed);
+		}
+
+		fn set_cursor(&self, _pos: Option<(vga::Pos, vga::Pos)>) {
+			self.cursor.fetch_add(1, Relaxed);
+		}
+	}
+
+	impl CountingBackend {
+		/// Returns and resets the counters.
+		fn take(&self) -> (usize, vga::Pos, usize) {
+			(
+				self.glyphs.swap(0, SeqCst),
+				self.scrolled.swap(0, SeqCst),
+				self.cursor.swap(0, SeqCst),
+			)
+		}
+	}
+
+	static BACKEND: CountingBackend = CountingBackend {
+		glyphs: AtomicUsize::new(0),
+		scrolled: AtomicI16::new(0),
+		cursor: AtomicUsize::new(0),
+	};
+	static DISPLAY: Mutex<TTYDisplay> = Mutex::new(TTYDisplay::new(&BACKEND));
+
+	#[test_case]
+	fn tty_dirty_lines() {
+		let screen = vga::WIDTH as usize * vga::HEIGHT as usize;
+		let mut disp = DISPLAY.lock();
+		disp.show();
+		assert_eq!(BACKEND.take(), (screen, 0, 1));
+		// Only the modified line is redrawn
+		disp.write(b"abc");
+		assert_eq!(BACKEND.take(), (vga::WIDTH as usize, 0, 1));
+		// Nothing changed
+		disp.write(b"");
+		assert_eq!(BACKEND.take(), (0, 0, 0));
+		// Moving the screen down by one line scrolls it and draws the new line
+		disp.write(&[b'\n'; vga::HEIGHT as usize]);
+		assert_eq!(BACKEND.take(), (vga::WIDTH as usize, 1, 1));
+		// Moving farther than the size of the screen redraws everything
+		disp.write(&[b'\n'; HISTORY_LINES as usize]);
+		assert_eq!(BACKEND.take(), (screen, 0, 0));
+		// Hiding the cursor
+		disp.set_cursor_visible(false);
+		disp.update();
+		assert_eq!(BACKEND.take(), (0, 0, 1));
+	}
+}
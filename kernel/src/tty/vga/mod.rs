@@ -26,7 +26,9 @@
 use crate::{
 	io,
 	memory::{vmem, PhysAddr},
+	tty::backend::ConsoleBackend,
 };
+use core::ptr;
 
 /// Type representing a VGA text mode character.
 pub type Char = u16;
@@ -183,3 +185,47 @@ pub fn putchar_color(c: char, color: Color, x: Pos, y: Pos) {
 		});
 	}
 }
+
+/// Console backend drawing on the VGA text buffer, using the hardware cursor.
+pub struct VgaText;
+
+impl ConsoleBackend for VgaText {
+	fn put_glyph(&self, x: Pos, y: Pos, c: Char) {
+		let pos = (y as usize) * (WIDTH as usize) + (x as usize);
+		debug_assert!(pos < BUFFER_SIZE as usize);
+		unsafe {
+			vmem::write_ro(|| {
+				*get_buffer_virt().add(pos) = c;
+			});
+		}
+	}
+
+	fn scroll(&self, n: Pos) {
+		let lines = n.unsigned_abs() as usize;
+		if lines == 0 || lines >= HEIGHT as usize {
+			return;
+		}
+		let len = (HEIGHT as usize - lines) * WIDTH as usize;
+		let off = lines * WIDTH as usize;
+		let buf = get_buffer_virt();
+		unsafe {
+			vmem::write_ro(|| {
+				if n > 0 {
+					ptr::copy(buf.add(off), buf, len);
+				} else {
+					ptr::copy(buf, buf.add(off), len);
+				}
+			});
+		}
+	}
+
+	fn set_cursor(&self, pos: Option<(Pos, Pos)>) {
+		match pos {
+			Some((x, y)) => {
+				enable_cursor();
+				move_cursor(x, y);
+			}
+			None => disable_cursor(),
+		}
+	}
+}
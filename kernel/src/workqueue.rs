@@ -0,0 +1,259 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! A workqueue allows to defer work out of interrupt handlers and system calls.
+//!
+//! Work items are executed later by a kernel worker thread, in process context, which means
+//! they are allowed to sleep and to perform heavy processing (partition rescans, writeback,
+//! etc...).
+//!
+//! A work item can also be delayed, in which case it is executed only after the given amount
+//! of time has elapsed.
+
+use crate::{
+	file::wait_queue::WaitQueue,
+	process::kthread,
+	time::{
+		clock,
+		clock::CLOCK_MONOTONIC,
+		unit::{Timestamp, TimestampScale},
+	},
+};
+use core::mem;
+use utils::{
+	boxed::Box,
+	collections::{btreemap::BTreeMap, vec::Vec},
+	errno::{AllocResult, EResult},
+	lock::IntMutex,
+};
+
+/// A unit of deferred work.
+///
+/// A work item is executed once, then dropped. Since [`Box`] cannot move an unsized closure out to
+/// call it by value, work items are created with [`work`], which allows `FnOnce` closures.
+pub type Work = Box<dyn FnMut()>;
+
+/// Creates a work item executing `f`.
+pub fn work<F: 'static + FnOnce()>(f: F) -> AllocResult<Work> {
+	let mut f = Some(f);
+	Ok(Box::new(move || {
+		if let Some(f) = f.take() {
+			f();
+		}
+	})?)
+}
+
+/// A queue of deferred work, executed by a worker thread.
+pub struct Workqueue {
+	/// The name of the queue.
+	name: &'static str,
+
+	/// Work items ready to be executed, in order of submission.
+	pending: IntMutex<Vec<Work>>,
+	/// Delayed work items.
+	///
+	/// The key is the timestamp, in milliseconds, at which the item becomes ready, along with a
+	/// sequence number keeping items with the same timestamp in order of submission.
+	delayed: IntMutex<BTreeMap<(Timestamp, u64), Work>>,
+	/// The next sequence number for delayed work items.
+	seq: IntMutex<u64>,
+
+	/// The queue on which the worker thread sleeps while no work is pending.
+	worker_queue: WaitQueue,
+}
+
+impl Workqueue {
+	/// Creates a new empty queue.
+	///
+	/// No work is executed until a worker thread is started for the queue.
+	pub const fn new(name: &'static str) -> Self {
+		Self {
+			name,
+
+			pending: IntMutex::new(Vec::new()),
+			delayed: IntMutex::new(BTreeMap::new()),
+			seq: IntMutex::new(0),
+
+			worker_queue: WaitQueue::new(),
+		}
+	}
+
+	/// Returns the name of the queue.
+	pub fn get_name(&self) -> &'static str {
+		self.name
+	}
+
+	/// Queues the given work item for execution.
+	///
+	/// This function can be called from interrupt context.
+	pub fn queue(&self, work: Work) -> AllocResult<()> {
+		self.pending.lock().push(work)?;
+		self.worker_queue.wake_all();
+		Ok(())
+	}
+
+	/// Queues the given work item for execution, after `delay` milliseconds have elapsed.
+	///
+	/// This function can be called from interrupt context.
+	pub fn queue_delayed(&self, work: Work, delay: Timestamp) -> EResult<()> {
+		let now = clock::current_time(CLOCK_MONOTONIC, TimestampScale::Millisecond)?;
+		let seq = {
+			let mut seq = self.seq.lock();
+			let s = *seq;
+			*seq = seq.wrapping_add(1);
+			s
+		};
+		self.delayed
+			.lock()
+			.insert((now.saturating_add(delay), seq), work)?;
+		Ok(())
+	}
+
+	/// Moves delayed work items whose delay has expired to the pending list, then wakes the
+	/// worker thread if any work is pending.
+	fn tick(&self, now: Timestamp) {
+		{
+			let mut delayed = self.delayed.lock();
+			let mut pending = self.pending.lock();
+			while let Some((&(ts, seq), _)) = delayed.first_key_value() {
+				if ts > now {
+					break;
+				}
+				// Make sure the item can be inserted before removing it from the delayed list
+				if pending.reserve(1).is_err() {
+					// Retry on next tick
+					break;
+				}
+				if let Some(work) = delayed.remove(&(ts, seq)) {
+					// Cannot fail since memory has been reserved
+					let _ = pending.push(work);
+				}
+			}
+			if pending.is_empty() {
+				return;
+			}
+		}
+		self.worker_queue.wake_all();
+	}
+
+	/// Executes work items until the worker thread is asked to stop. This function is the body
+	/// of the worker thread.
+	///
+	/// When no work is pending, the worker thread sleeps until some is queued.
+	fn worker_loop(&self) {
+		while !kthread::should_stop() {
+			kthread::park();
+			let res = self.worker_queue.wait_until(|| {
+				if kthread::should_stop() || kthread::should_park() {
+					return Some(Vec::new());
+				}
+				let mut pending = self.pending.lock();
+				(!pending.is_empty()).then(|| mem::take(&mut *pending))
+			});
+			// Kernel threads do not receive signals, but retry in case waiting was interrupted
+			let Ok(works) = res else {
+				continue;
+			};
+			for mut work in works {
+				(*work)();
+			}
+		}
+	}
+}
+
+/// The system-wide workqueue, to be used by drivers and filesystems that do not need a
+/// dedicated queue.
+pub static SYSTEM: Workqueue = Workqueue::new("events");
+
+/// Queues the given work item on the system workqueue.
+///
+/// This function can be called from interrupt context.
+pub fn queue_work<F: 'static + FnOnce()>(f: F) -> AllocResult<()> {
+	SYSTEM.queue(work(f)?)
+}
+
+/// Queues the given work item on the system workqueue, to be executed after `delay`
+/// milliseconds have elapsed.
+///
+/// This function can be called from interrupt context.
+pub fn queue_delayed_work<F: 'static + FnOnce()>(f: F, delay: Timestamp) -> EResult<()> {
+	SYSTEM.queue_delayed(work(f)?, delay)
+}
+
+/// Ticks workqueues, making delayed work items whose delay has expired ready for execution.
+///
+/// This function is called from the clock's interrupt handler.
+pub(crate) fn tick() {
+	let Ok(now) = clock::current_time(CLOCK_MONOTONIC, TimestampScale::Millisecond) else {
+		return;
+	};
+	SYSTEM.tick(now);
+}
+
+/// Starts worker threads.
+///
+/// Work queued before this function is called is executed once the worker threads are
+/// started.
+///
+/// This function must be called only once, after the creation of the init process.
+pub(crate) fn init() -> EResult<()> {
+	kthread::spawn(SYSTEM.get_name(), || SYSTEM.worker_loop())?;
+	Ok(())
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use utils::ptr::arc::Arc;
+
+	/// Executes the pending work items of `queue`, like the worker thread does.
+	fn run_pending(queue: &Workqueue) {
+		let works = mem::take(&mut *queue.pending.lock());
+		for mut work in works {
+			(*work)();
+		}
+	}
+
+	#[test_case]
+	fn workqueue_order() {
+		let queue = Workqueue::new("test");
+		let log = Arc::new(IntMutex::new(Vec::new())).unwrap();
+		let record = |id: u32| -> Work {
+			let log = log.clone();
+			work(move || log.lock().push(id).unwrap()).unwrap()
+		};
+		queue.queue_delayed(record(4), 1000).unwrap();
+		queue.queue_delayed(record(2), 100).unwrap();
+		queue.queue_delayed(record(3), 100).unwrap();
+		queue.queue(record(1)).unwrap();
+		let now = clock::current_time(CLOCK_MONOTONIC, TimestampScale::Millisecond).unwrap();
+		// Delayed items are not ready before their delay expires
+		queue.tick(now);
+		run_pending(&queue);
+		assert_eq!(log.lock().as_slice(), &[1]);
+		// Items with the same deadline remain in order of submission
+		queue.tick(now + 100);
+		run_pending(&queue);
+		assert_eq!(log.lock().as_slice(), &[1, 2, 3]);
+		assert_eq!(queue.delayed.lock().len(), 1);
+		queue.tick(now + 1000);
+		run_pending(&queue);
+		assert_eq!(log.lock().as_slice(), &[1, 2, 3, 4]);
+		assert!(queue.delayed.lock().is_empty());
+	}
+}
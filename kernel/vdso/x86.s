@@ -18,17 +18,42 @@
 
 .section .text
 
+# The data page is mapped right before the image, whose text section is located at offset 0x1000
+.set DATA_OFF, 0x2000
+# Offsets of fields in the data page. They must match the kernel's `VdsoData` structure
+.set DATA_SEQ, 0
+.set DATA_REALTIME, 16
+.set DATA_MONOTONIC, 32
+.set DATA_TIMENS, 44
+
+# Clock IDs
+.set CLOCK_REALTIME_COARSE, 5
+.set CLOCK_MONOTONIC_COARSE, 6
+
+.set SYS_CLOCK_GETTIME, 0x109
+
 .global __kernel_vsyscall
 .global __kernel_rt_sigreturn
 .global __kernel_sigreturn
 .global __vdso_clock_gettime
+.global __vdso_getcpu
 .global __vdso_gettimeofday
 .global __vdso_time
 
+.Ltext_start:
+
 __kernel_vsyscall:
 	int $0x80
 	ret
 
+# Returns the address of the data page in %ecx
+get_data:
+	call 1f
+1:
+	pop %ecx
+	lea (.Ltext_start - 1b - DATA_OFF)(%ecx), %ecx
+	ret
+
 __kernel_rt_sigreturn:
 	# TODO
 	ud2
@@ -37,14 +62,112 @@ __kernel_sigreturn:
 	# TODO
 	ud2
 
+# Coarse clocks are read from the data page. Other clocks fall back to the system call, as well
+# as monotonic clocks in time namespaces, since the page does not hold their offsets
 __vdso_clock_gettime:
-	# TODO
-	ud2
+	push %ebx
+	push %esi
+	push %edi
+	call get_data
+	mov 16(%esp), %eax
+	mov $DATA_REALTIME, %edx
+	cmp $CLOCK_REALTIME_COARSE, %eax
+	je 1f
+	mov $DATA_MONOTONIC, %edx
+	cmp $CLOCK_MONOTONIC_COARSE, %eax
+	jne 4f
+	cmpl $0, DATA_TIMENS(%ecx)
+	je 1f
+4:
+	mov %eax, %ebx
+	mov 20(%esp), %ecx
+	mov $SYS_CLOCK_GETTIME, %eax
+	int $0x80
+	jmp 3f
+1:
+	add %ecx, %edx
+2:
+	# Retry while the kernel is updating the page
+	mov DATA_SEQ(%ecx), %eax
+	test $1, %eax
+	jnz 2b
+	mov (%edx), %ebx
+	mov 8(%edx), %esi
+	cmp DATA_SEQ(%ecx), %eax
+	jne 2b
+	mov 20(%esp), %edi
+	mov %ebx, (%edi)
+	mov %esi, 4(%edi)
+	xor %eax, %eax
+3:
+	pop %edi
+	pop %esi
+	pop %ebx
+	ret
+
+# Only one CPU and one NUMA node are supported, so both IDs are always zero, as returned by the
+# `getcpu` system call
+__vdso_getcpu:
+	mov 4(%esp), %eax
+	test %eax, %eax
+	jz 1f
+	movl $0, (%eax)
+1:
+	mov 8(%esp), %eax
+	test %eax, %eax
+	jz 2f
+	movl $0, (%eax)
+2:
+	xor %eax, %eax
+	ret
 
+# Clocks are only updated on ticks, so the coarse clock is as precise as the system call
 __vdso_gettimeofday:
-	# TODO
-	ud2
+	push %ebx
+	push %esi
+	call get_data
+1:
+	mov DATA_SEQ(%ecx), %esi
+	test $1, %esi
+	jnz 1b
+	mov DATA_REALTIME(%ecx), %ebx
+	mov (DATA_REALTIME + 8)(%ecx), %eax
+	cmp DATA_SEQ(%ecx), %esi
+	jne 1b
+	# Convert nanoseconds to microseconds
+	xor %edx, %edx
+	mov $1000, %ecx
+	div %ecx
+	mov 12(%esp), %ecx
+	test %ecx, %ecx
+	jz 2f
+	mov %ebx, (%ecx)
+	mov %eax, 4(%ecx)
+2:
+	# The timezone is always UTC
+	mov 16(%esp), %ecx
+	test %ecx, %ecx
+	jz 3f
+	movl $0, (%ecx)
+	movl $0, 4(%ecx)
+3:
+	xor %eax, %eax
+	pop %esi
+	pop %ebx
+	ret
 
 __vdso_time:
-	# TODO
-	ud2
+	call get_data
+1:
+	mov DATA_SEQ(%ecx), %edx
+	test $1, %edx
+	jnz 1b
+	mov DATA_REALTIME(%ecx), %eax
+	cmp DATA_SEQ(%ecx), %edx
+	jne 1b
+	mov 4(%esp), %ecx
+	test %ecx, %ecx
+	jz 2f
+	mov %eax, (%ecx)
+2:
+	ret
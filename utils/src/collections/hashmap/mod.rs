@@ -32,6 +32,7 @@ use core::{
 	borrow::Borrow,
 	fmt,
 	hash::{Hash, Hasher},
+	iter,
 	iter::{FusedIterator, TrustedLen},
 	marker::PhantomData,
 	mem,
@@ -742,12 +743,31 @@ impl<K: Eq + Hash, H: Default + Hasher> HashSet<K, H> {
 		}
 	}
 
+	/// Returns an iterator over the elements of the hash set, in an arbitrary order.
+	pub fn iter(&self) -> impl Iterator<Item = &K> {
+		self.0.iter().map(|(k, _)| k)
+	}
+
+	/// Retains only the elements for which the given predicate returns `true`.
+	pub fn retain<F: FnMut(&K) -> bool>(&mut self, mut f: F) {
+		self.0.retain(|k, _| f(k));
+	}
+
 	/// Drops all elements from the hash set.
 	pub fn clear(&mut self) {
 		self.0.clear()
 	}
 }
 
+impl<K: Eq + Hash, H: Default + Hasher> IntoIterator for HashSet<K, H> {
+	type IntoIter = iter::Map<IntoIter<K, (), H>, fn((K, ())) -> K>;
+	type Item = K;
+
+	fn into_iter(self) -> Self::IntoIter {
+		self.0.into_iter().map(|(k, _)| k)
+	}
+}
+
 impl<K: Eq + Hash + fmt::Debug, H: Default + Hasher> fmt::Debug for HashSet<K, H> {
 	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
 		fmt::Debug::fmt(&self.0, f)
@@ -773,6 +793,7 @@ impl<K: Eq + Hash + TryClone<Error = E>, H: Default + Hasher, E: From<AllocError
 #[cfg(test)]
 mod test {
 	use super::*;
+	use crate::{collections::vec::Vec, errno::CollectResult};
 
 	#[test]
 	fn hashmap0() {
@@ -825,4 +846,27 @@ mod test {
 		assert_eq!(hm.len(), 500);
 		hm.iter().for_each(|(i, _)| assert_eq!(i % 2, 0));
 	}
+
+	#[test]
+	fn hashset_retain() {
+		let mut hs = HashSet::<u32>::new();
+		for i in 0..100 {
+			hs.insert(i).unwrap();
+		}
+		hs.retain(|i| i % 2 == 0);
+		assert_eq!(hs.len(), 50);
+		assert_eq!(hs.iter().count(), 50);
+		assert!(hs.iter().all(|i| i % 2 == 0));
+	}
+
+	#[test]
+	fn hashset_into_iter() {
+		let mut hs = HashSet::<u32>::new();
+		for i in 0..100 {
+			hs.insert(i).unwrap();
+		}
+		let mut values = hs.into_iter().collect::<CollectResult<Vec<_>>>().0.unwrap();
+		values.sort_unstable();
+		assert!(values.into_iter().eq(0..100));
+	}
 }
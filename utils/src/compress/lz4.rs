@@ -0,0 +1,114 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+//! Decompression of LZ4 blocks.
+//!
+//! This module only implements the block format, not the frame format, since the former is the
+//! one used by filesystems.
+//!
+//! For more information, see the [specifications](https://github.com/lz4/lz4/blob/dev/doc/lz4_Block_format.md).
+
+/// The minimum length of a match.
+const MIN_MATCH: usize = 4;
+
+/// Reads a length extension starting at `*i` in `src` and returns its value.
+fn read_len(src: &[u8], i: &mut usize) -> Option<usize> {
+	let mut len = 0usize;
+	loop {
+		let b = *src.get(*i)?;
+		*i += 1;
+		len = len.checked_add(b as usize)?;
+		if b != u8::MAX {
+			break Some(len);
+		}
+	}
+}
+
+/// Decompresses the LZ4 block `src` into `dst`.
+///
+/// On success, the function returns the number of bytes written to `dst`. If the data is invalid
+/// or if the decompressed data does not fit in `dst`, the function returns `None`.
+pub fn decompress(src: &[u8], dst: &mut [u8]) -> Option<usize> {
+	let mut i = 0;
+	let mut o = 0;
+	loop {
+		let token = *src.get(i)?;
+		i += 1;
+		// Copy literals
+		let mut lit_len = (token >> 4) as usize;
+		if lit_len == 15 {
+			lit_len = lit_len.checked_add(read_len(src, &mut i)?)?;
+		}
+		let lit = src.get(i..i.checked_add(lit_len)?)?;
+		dst.get_mut(o..o + lit_len)?.copy_from_slice(lit);
+		i += lit_len;
+		o += lit_len;
+		// The last sequence has no match
+		if i == src.len() {
+			break Some(o);
+		}
+		// Copy match
+		let off = u16::from_le_bytes([*src.get(i)?, *src.get(i + 1)?]) as usize;
+		i += 2;
+		if off == 0 || off > o {
+			return None;
+		}
+		let mut match_len = (token & 0xf) as usize + MIN_MATCH;
+		if token & 0xf == 15 {
+			match_len = match_len.checked_add(read_len(src, &mut i)?)?;
+		}
+		let end = o.checked_add(match_len)?;
+		if end > dst.len() {
+			return None;
+		}
+		// The match may overlap with the data it produces, hence the copy byte by byte
+		for j in o..end {
+			dst[j] = dst[j - off];
+		}
+		o = end;
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn lz4_literals() {
+		let mut buf = [0u8; 16];
+		let len = decompress(b"\x50hello", &mut buf).unwrap();
+		assert_eq!(&buf[..len], b"hello");
+	}
+
+	#[test]
+	fn lz4_overlapping_match() {
+		let mut buf = [0u8; 16];
+		let len = decompress(b"\x38abc\x03\x00\x00", &mut buf).unwrap();
+		assert_eq!(&buf[..len], b"abcabcabcabcabc");
+	}
+
+	#[test]
+	fn lz4_invalid() {
+		let mut buf = [0u8; 16];
+		// Offset pointing before the beginning of the output
+		assert_eq!(decompress(b"\x38abc\x04\x00\x00", &mut buf), None);
+		// Output too small
+		assert_eq!(decompress(b"\x38abc\x03\x00\x00", &mut buf[..8]), None);
+		// Truncated input
+		assert_eq!(decompress(b"\x50hel", &mut buf), None);
+	}
+}
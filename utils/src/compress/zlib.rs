@@ -0,0 +1,371 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+//! Decompression of zlib streams, which wrap data compressed with the DEFLATE algorithm.
+//!
+//! For more information, see:
+//! - [RFC 1950](https://www.rfc-editor.org/rfc/rfc1950) for the zlib format
+//! - [RFC 1951](https://www.rfc-editor.org/rfc/rfc1951) for the DEFLATE format
+
+/// The maximum length of a Huffman code, in bits.
+const MAX_BITS: usize = 15;
+/// The number of literal/length codes.
+const LITLEN_CODES: usize = 288;
+/// The number of distance codes.
+const DIST_CODES: usize = 30;
+
+/// Base lengths for length codes `257` to `285`.
+const LEN_BASE: [u16; 29] = [
+	3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+	163, 195, 227, 258,
+];
+/// Number of extra bits for length codes `257` to `285`.
+const LEN_EXTRA: [u8; 29] = [
+	0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+/// Base distances for distance codes.
+const DIST_BASE: [u16; DIST_CODES] = [
+	1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+	2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+/// Number of extra bits for distance codes.
+const DIST_EXTRA: [u8; DIST_CODES] = [
+	0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+	13,
+];
+/// The order in which code length code lengths are stored in a dynamic block.
+const CLEN_ORDER: [usize; 19] = [
+	16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+/// Reader for a stream of bits, least significant bit first.
+struct BitReader<'s> {
+	/// The input data.
+	src: &'s [u8],
+	/// The offset of the next byte to read in `src`.
+	pos: usize,
+	/// Bits that have been read from `src` but not consumed yet.
+	buf: u32,
+	/// The number of bits in `buf`.
+	cnt: u32,
+}
+
+impl BitReader<'_> {
+	/// Consumes `n` bits and returns them. `n` must not exceed `16`.
+	fn bits(&mut self, n: u32) -> Option<u32> {
+		while self.cnt < n {
+			let b = *self.src.get(self.pos)?;
+			self.pos += 1;
+			self.buf |= (b as u32) << self.cnt;
+			self.cnt += 8;
+		}
+		let val = self.buf & ((1 << n) - 1);
+		self.buf >>= n;
+		self.cnt -= n;
+		Some(val)
+	}
+
+	/// Discards the remaining bits of the current byte.
+	fn align(&mut self) {
+		self.buf = 0;
+		self.cnt = 0;
+	}
+}
+
+/// A canonical Huffman code, used to decode symbols.
+struct Huffman {
+	/// The number of codes of each length.
+	count: [u16; MAX_BITS + 1],
+	/// The symbols, sorted by code.
+	symbol: [u16; LITLEN_CODES],
+}
+
+impl Huffman {
+	/// Builds the code from the length of the code of each symbol.
+	///
+	/// If the set of lengths is over-subscribed, the function returns `None`. Incomplete codes are
+	/// accepted, the missing codes being invalid when decoding.
+	fn new(lengths: &[u8]) -> Option<Self> {
+		let mut h = Self {
+			count: [0; MAX_BITS + 1],
+			symbol: [0; LITLEN_CODES],
+		};
+		for len in lengths {
+			h.count[*len as usize] += 1;
+		}
+		// Check the code is not over-subscribed
+		let mut left: i32 = 1;
+		for len in 1..=MAX_BITS {
+			left = (left << 1) - h.count[len] as i32;
+			if left < 0 {
+				return None;
+			}
+		}
+		// Offsets of the first symbol of each length in the table
+		let mut offs = [0u16; MAX_BITS + 1];
+		for len in 1..MAX_BITS {
+			offs[len + 1] = offs[len] + h.count[len];
+		}
+		for (sym, len) in lengths.iter().enumerate() {
+			if *len != 0 {
+				h.symbol[offs[*len as usize] as usize] = sym as u16;
+				offs[*len as usize] += 1;
+			}
+		}
+		Some(h)
+	}
+
+	/// Decodes a symbol from `r`.
+	fn decode(&self, r: &mut BitReader) -> Option<u16> {
+		// Codes are stored most significant bit first, so they are read one bit at a time
+		let mut code: i32 = 0;
+		let mut first: i32 = 0;
+		let mut index: i32 = 0;
+		for len in 1..=MAX_BITS {
+			code |= r.bits(1)? as i32;
+			let count = self.count[len] as i32;
+			if code - count < first {
+				return Some(self.symbol[(index + code - first) as usize]);
+			}
+			index += count;
+			first = (first + count) << 1;
+			code <<= 1;
+		}
+		None
+	}
+}
+
+/// Decodes a block compressed with the codes `litlen` and `dist`, appending the data to `dst`
+/// at offset `*o`.
+fn inflate_codes(
+	r: &mut BitReader,
+	dst: &mut [u8],
+	o: &mut usize,
+	litlen: &Huffman,
+	dist: &Huffman,
+) -> Option<()> {
+	loop {
+		let sym = litlen.decode(r)? as usize;
+		match sym {
+			// Literal
+			0..=255 => {
+				*dst.get_mut(*o)? = sym as u8;
+				*o += 1;
+			}
+			// End of block
+			256 => break Some(()),
+			// Length/distance pair
+			257..=285 => {
+				let sym = sym - 257;
+				let len = LEN_BASE[sym] as usize + r.bits(LEN_EXTRA[sym] as _)? as usize;
+				let sym = dist.decode(r)? as usize;
+				if sym >= DIST_CODES {
+					return None;
+				}
+				let d = DIST_BASE[sym] as usize + r.bits(DIST_EXTRA[sym] as _)? as usize;
+				if d > *o || *o + len > dst.len() {
+					return None;
+				}
+				// The data may overlap with the data it produces, hence the copy byte by byte
+				for j in *o..(*o + len) {
+					dst[j] = dst[j - d];
+				}
+				*o += len;
+			}
+			_ => return None,
+		}
+	}
+}
+
+/// Returns the codes used by blocks compressed with fixed Huffman codes.
+fn fixed_codes() -> (Huffman, Huffman) {
+	let mut lengths = [0u8; LITLEN_CODES];
+	lengths[..144].fill(8);
+	lengths[144..256].fill(9);
+	lengths[256..280].fill(7);
+	lengths[280..].fill(8);
+	let litlen = Huffman::new(&lengths).unwrap();
+	let dist = Huffman::new(&[5; DIST_CODES]).unwrap();
+	(litlen, dist)
+}
+
+/// Reads the codes of a block compressed with dynamic Huffman codes.
+fn dynamic_codes(r: &mut BitReader) -> Option<(Huffman, Huffman)> {
+	let nlen = r.bits(5)? as usize + 257;
+	let ndist = r.bits(5)? as usize + 1;
+	let ncode = r.bits(4)? as usize + 4;
+	if nlen > 286 || ndist > DIST_CODES {
+		return None;
+	}
+	// Code lengths code
+	let mut lengths = [0u8; 19];
+	for i in &CLEN_ORDER[..ncode] {
+		lengths[*i] = r.bits(3)? as u8;
+	}
+	let clen = Huffman::new(&lengths)?;
+	// Literal/length and distance code lengths
+	let mut lengths = [0u8; 286 + DIST_CODES];
+	let mut i = 0;
+	while i < nlen + ndist {
+		let sym = clen.decode(r)?;
+		let (len, repeat) = match sym {
+			0..=15 => (sym as u8, 1),
+			16 => (*lengths.get(i.checked_sub(1)?)?, 3 + r.bits(2)?),
+			17 => (0, 3 + r.bits(3)?),
+			18 => (0, 11 + r.bits(7)?),
+			_ => return None,
+		};
+		let end = i + repeat as usize;
+		if end > nlen + ndist {
+			return None;
+		}
+		lengths[i..end].fill(len);
+		i = end;
+	}
+	// The end-of-block code is required
+	if lengths[256] == 0 {
+		return None;
+	}
+	let litlen = Huffman::new(&lengths[..nlen])?;
+	let dist = Huffman::new(&lengths[nlen..(nlen + ndist)])?;
+	Some((litlen, dist))
+}
+
+/// Decompresses the raw DEFLATE stream `src` into `dst`.
+///
+/// On success, the function returns the number of bytes consumed from `src` and the number of
+/// bytes written to `dst`. If the data is invalid or if the decompressed data does not fit in
+/// `dst`, the function returns `None`.
+pub fn inflate(src: &[u8], dst: &mut [u8]) -> Option<(usize, usize)> {
+	let mut r = BitReader {
+		src,
+		pos: 0,
+		buf: 0,
+		cnt: 0,
+	};
+	let mut o = 0;
+	loop {
+		let last = r.bits(1)? != 0;
+		match r.bits(2)? {
+			// Stored block
+			0 => {
+				r.align();
+				let hdr = src.get(r.pos..(r.pos + 4))?;
+				let len = u16::from_le_bytes([hdr[0], hdr[1]]);
+				let nlen = u16::from_le_bytes([hdr[2], hdr[3]]);
+				if len != !nlen {
+					return None;
+				}
+				r.pos += 4;
+				let len = len as usize;
+				let data = src.get(r.pos..(r.pos + len))?;
+				dst.get_mut(o..(o + len))?.copy_from_slice(data);
+				r.pos += len;
+				o += len;
+			}
+			// Fixed Huffman codes
+			1 => {
+				let (litlen, dist) = fixed_codes();
+				inflate_codes(&mut r, dst, &mut o, &litlen, &dist)?;
+			}
+			// Dynamic Huffman codes
+			2 => {
+				let (litlen, dist) = dynamic_codes(&mut r)?;
+				inflate_codes(&mut r, dst, &mut o, &litlen, &dist)?;
+			}
+			_ => return None,
+		}
+		if last {
+			break;
+		}
+	}
+	Some((r.pos, o))
+}
+
+/// Computes the Adler-32 checksum of `data`.
+fn adler32(data: &[u8]) -> u32 {
+	const MOD: u32 = 65521;
+	// The largest number of bytes that can be summed before `b` overflows
+	const CHUNK: usize = 5552;
+	let mut a = 1u32;
+	let mut b = 0u32;
+	for chunk in data.chunks(CHUNK) {
+		for byte in chunk {
+			a += *byte as u32;
+			b += a;
+		}
+		a %= MOD;
+		b %= MOD;
+	}
+	(b << 16) | a
+}
+
+/// Decompresses the zlib stream `src` into `dst`.
+///
+/// On success, the function returns the number of bytes written to `dst`. If the data is invalid,
+/// if its checksum does not match or if the decompressed data does not fit in `dst`, the
+/// function returns `None`.
+pub fn decompress(src: &[u8], dst: &mut [u8]) -> Option<usize> {
+	let [cmf, flg] = *src.first_chunk::<2>()?;
+	// Check method (DEFLATE), window size and header checksum. Preset dictionaries are not
+	// supported
+	let valid = cmf & 0xf == 8
+		&& cmf >> 4 <= 7
+		&& (((cmf as u16) << 8) | flg as u16) % 31 == 0
+		&& flg & 0x20 == 0;
+	if !valid {
+		return None;
+	}
+	let (consumed, len) = inflate(&src[2..], dst)?;
+	let checksum = src.get((2 + consumed)..(2 + consumed + 4))?;
+	let checksum = u32::from_be_bytes(checksum.try_into().unwrap());
+	(checksum == adler32(&dst[..len])).then_some(len)
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn zlib_stored() {
+		let mut buf = [0u8; 16];
+		let src = b"\x78\x01\x01\x05\x00\xfa\xffhello\x06\x2c\x02\x15";
+		let len = decompress(src, &mut buf).unwrap();
+		assert_eq!(&buf[..len], b"hello");
+	}
+
+	#[test]
+	fn zlib_fixed() {
+		let mut buf = [0u8; 64];
+		let src = b"\x78\xda\xcbH\xcd\xc9\xc9W(\xcf/\xcaIQ\xc8\xc0\xce\x06\x00\xef\x93\rU";
+		let len = decompress(src, &mut buf).unwrap();
+		assert_eq!(&buf[..len], b"hello world hello world hello world");
+	}
+
+	#[test]
+	fn zlib_invalid() {
+		let mut buf = [0u8; 64];
+		// Wrong checksum
+		let src = b"\x78\x01\x01\x05\x00\xfa\xffhello\x06\x2c\x02\x16";
+		assert_eq!(decompress(src, &mut buf), None);
+		// Output too small
+		let src = b"\x78\x01\x01\x05\x00\xfa\xffhello\x06\x2c\x02\x15";
+		assert_eq!(decompress(src, &mut buf[..4]), None);
+		// Truncated input
+		assert_eq!(decompress(&src[..8], &mut buf), None);
+	}
+}
@@ -48,6 +48,7 @@ extern crate self as utils;
 pub mod boxed;
 pub mod bytes;
 pub mod collections;
+pub mod compress;
 pub mod cpio;
 pub mod errno;
 pub mod interrupt;
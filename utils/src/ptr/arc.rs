@@ -27,7 +27,7 @@ use core::{
 	hash::{Hash, Hasher},
 	intrinsics::size_of_val,
 	marker::Unsize,
-	mem::ManuallyDrop,
+	mem::{offset_of, ManuallyDrop},
 	ops::{CoerceUnsized, Deref, DispatchFromDyn},
 	ptr,
 	ptr::{drop_in_place, NonNull},
@@ -138,6 +138,21 @@ impl<T> Arc<T> {
 			Some(obj)
 		}
 	}
+
+	/// Returns a new `Arc` from a pointer returned by [`Self::as_ptr`], incrementing the strong
+	/// references counter.
+	///
+	/// # Safety
+	///
+	/// `ptr` must have been returned by [`Self::as_ptr`], and at least one `Arc` pointing to the
+	/// object must remain alive during the call.
+	pub unsafe fn clone_from_ptr(ptr: *const T) -> Self {
+		let inner = ptr.byte_sub(offset_of!(ArcInner<T>, obj)) as *mut ArcInner<T>;
+		let this = ManuallyDrop::new(Self {
+			inner: NonNull::new_unchecked(inner),
+		});
+		(*this).clone()
+	}
 }
 
 impl<T: ?Sized> Arc<T> {